@@ -0,0 +1,665 @@
+//! Package download caching for repeated builds.
+//!
+//! A profile's `apt_cache` section routes the bootstrap's and the provision tasks'
+//! apt traffic through an HTTP caching proxy: either an existing one such as
+//! `apt-cacher-ng` (`proxy:`), or [`CachingProxy`], a minimal built-in proxy that
+//! rsdebstrap runs on the loopback interface for the duration of `apply` (`dir:`).
+//!
+//! The built-in proxy only caches `.deb`/`.udeb`/`.ddeb` files. Their pool paths
+//! embed the package version, so a cached copy never goes stale. Index files
+//! (`Release`, `Packages`, ...) change between runs and are always fetched from the
+//! mirror, so builds still see current package lists. Each suite gets its own
+//! subdirectory under `dir`. Only plain `http://` mirrors can be cached: apt does
+//! not send `https://` requests through an `http_proxy`.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use camino::{Utf8Path, Utf8PathBuf};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+use url::Url;
+
+use crate::error::RsdebstrapError;
+
+/// File extensions of the immutable package files the built-in proxy caches.
+const CACHEABLE_EXTENSIONS: &[&str] = &["deb", "udeb", "ddeb"];
+
+/// Read/write timeout for proxied connections.
+const IO_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Configuration for caching package downloads across builds.
+///
+/// Exactly one of `proxy` and `dir` must be set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct AptCacheConfig {
+    /// URL of an existing caching proxy, e.g. `http://127.0.0.1:3142` for apt-cacher-ng.
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub proxy: Option<String>,
+    /// Cache directory for the built-in caching proxy (relative paths are resolved
+    /// against the profile's directory). Packages are stored per suite in `<dir>/<suite>/`.
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_path",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<crate::schema::Utf8PathSchema>")
+    )]
+    pub dir: Option<Utf8PathBuf>,
+}
+
+impl AptCacheConfig {
+    /// Validates that exactly one cache source is configured and that `proxy` is an
+    /// `http://` URL.
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        match (&self.proxy, &self.dir) {
+            (Some(_), Some(_)) => Err(RsdebstrapError::Validation(
+                "apt_cache: 'proxy' and 'dir' are mutually exclusive".to_string(),
+            )),
+            (None, None) => Err(RsdebstrapError::Validation(
+                "apt_cache: one of 'proxy' or 'dir' must be set".to_string(),
+            )),
+            (Some(proxy), None) => match Url::parse(proxy) {
+                Ok(url) if url.scheme() == "http" && url.host().is_some() => Ok(()),
+                _ => Err(RsdebstrapError::Validation(format!(
+                    "apt_cache.proxy '{}' must be an http:// URL",
+                    proxy
+                ))),
+            },
+            (None, Some(dir)) if dir.as_str().is_empty() => {
+                Err(RsdebstrapError::Validation("apt_cache.dir must not be empty".to_string()))
+            }
+            (None, Some(_)) => Ok(()),
+        }
+    }
+
+    /// Resolves a relative `dir` against the profile's directory.
+    pub(crate) fn resolve_paths(&mut self, profile_dir: &Utf8Path) {
+        if let Some(dir) = self.dir.as_mut()
+            && dir.is_relative()
+        {
+            *dir = profile_dir.join(&*dir);
+        }
+    }
+
+    /// Makes the cache available for a build of `suite`.
+    ///
+    /// Starts the built-in proxy when `dir` is set; an external `proxy` is used as-is.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RsdebstrapError::Io`] if the built-in proxy cannot listen.
+    pub fn start(&self, suite: &str) -> Result<AptCache, RsdebstrapError> {
+        match (&self.proxy, &self.dir) {
+            (Some(proxy), _) => Ok(AptCache::External(proxy.clone())),
+            (None, Some(dir)) => CachingProxy::start(&dir.join(suite)).map(AptCache::BuiltIn),
+            (None, None) => Err(RsdebstrapError::Validation(
+                "apt_cache: one of 'proxy' or 'dir' must be set".to_string(),
+            )),
+        }
+    }
+}
+
+/// A package cache in use by a running build.
+pub enum AptCache {
+    /// An externally managed caching proxy.
+    External(String),
+    /// The built-in proxy, stopped when this value is dropped.
+    BuiltIn(CachingProxy),
+}
+
+impl AptCache {
+    /// Returns the proxy URL apt should use.
+    pub fn url(&self) -> String {
+        match self {
+            AptCache::External(url) => url.clone(),
+            AptCache::BuiltIn(proxy) => proxy.url(),
+        }
+    }
+
+    /// Returns the traffic counters of the built-in proxy (`None` for an external one).
+    pub fn stats(&self) -> Option<Arc<ProxyStats>> {
+        match self {
+            AptCache::External(_) => None,
+            AptCache::BuiltIn(proxy) => Some(proxy.stats()),
+        }
+    }
+}
+
+/// Response body bytes the built-in proxy has relayed.
+#[derive(Debug, Default)]
+pub struct ProxyStats {
+    fetched: AtomicU64,
+    served_from_cache: AtomicU64,
+}
+
+impl ProxyStats {
+    /// Returns the bytes fetched from mirrors.
+    pub fn fetched_bytes(&self) -> u64 {
+        self.fetched.load(Ordering::Relaxed)
+    }
+
+    /// Returns the bytes served from the cache instead of a mirror.
+    pub fn served_from_cache_bytes(&self) -> u64 {
+        self.served_from_cache.load(Ordering::Relaxed)
+    }
+}
+
+/// Minimal caching HTTP forward proxy bound to `127.0.0.1`.
+///
+/// Serves one request per connection on its own thread. `GET` requests for package
+/// files are answered from the cache directory when present, and otherwise fetched
+/// from the mirror and stored as they stream to the client. Everything else is
+/// forwarded unchanged. Dropping the proxy stops the listener; requests already in
+/// flight run to completion.
+pub struct CachingProxy {
+    addr: SocketAddr,
+    stop: Arc<AtomicBool>,
+    stats: Arc<ProxyStats>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl CachingProxy {
+    /// Starts the proxy on an ephemeral loopback port, caching into `cache_dir`.
+    ///
+    /// The cache directory is created on the first stored package, not here.
+    pub fn start(cache_dir: &Utf8Path) -> Result<Self, RsdebstrapError> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).map_err(|e| {
+            RsdebstrapError::io("failed to start caching proxy on 127.0.0.1".to_string(), e)
+        })?;
+        let addr = listener.local_addr().map_err(|e| {
+            RsdebstrapError::io("failed to read caching proxy address".to_string(), e)
+        })?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stats = Arc::new(ProxyStats::default());
+        let cache_dir = cache_dir.to_owned();
+        let handle = thread::Builder::new()
+            .name("apt-cache-proxy".to_string())
+            .spawn({
+                let stop = Arc::clone(&stop);
+                let stats = Arc::clone(&stats);
+                move || accept_loop(listener, cache_dir, stop, stats)
+            })
+            .map_err(|e| {
+                RsdebstrapError::io("failed to spawn caching proxy thread".to_string(), e)
+            })?;
+
+        info!("caching proxy listening on http://{}", addr);
+        Ok(Self {
+            addr,
+            stop,
+            stats,
+            handle: Some(handle),
+        })
+    }
+
+    /// Returns the proxy URL (`http://127.0.0.1:<port>`).
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// Returns the proxy's traffic counters, which stay readable after it stops.
+    pub fn stats(&self) -> Arc<ProxyStats> {
+        Arc::clone(&self.stats)
+    }
+}
+
+impl Drop for CachingProxy {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        // Wake the blocking accept() so the loop sees the stop flag.
+        let _ = TcpStream::connect(self.addr);
+        if let Some(handle) = self.handle.take()
+            && handle.join().is_err()
+        {
+            warn!("caching proxy thread panicked");
+        }
+    }
+}
+
+fn accept_loop(
+    listener: TcpListener,
+    cache_dir: Utf8PathBuf,
+    stop: Arc<AtomicBool>,
+    stats: Arc<ProxyStats>,
+) {
+    for stream in listener.incoming() {
+        if stop.load(Ordering::SeqCst) {
+            break;
+        }
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                debug!("caching proxy accept failed: {}", e);
+                continue;
+            }
+        };
+        let cache_dir = cache_dir.clone();
+        let stats = Arc::clone(&stats);
+        let spawned = thread::Builder::new()
+            .name("apt-cache-conn".to_string())
+            .spawn(move || {
+                if let Err(e) = handle_connection(stream, &cache_dir, &stats) {
+                    debug!("caching proxy connection failed: {}", e);
+                }
+            });
+        if let Err(e) = spawned {
+            warn!("failed to spawn caching proxy connection thread: {}", e);
+        }
+    }
+}
+
+/// Returns the cache file for `url`, or `None` if the resource must not be cached.
+///
+/// Only query-less package files qualify. Every path segment must be a plain name,
+/// so a request cannot address a file outside `cache_dir`.
+fn cache_path(cache_dir: &Utf8Path, url: &Url) -> Option<Utf8PathBuf> {
+    if url.query().is_some() {
+        return None;
+    }
+    let host = url.host_str()?;
+    let segments: Vec<&str> = url.path_segments()?.collect();
+    let file_name = segments.last()?;
+    let extension = Utf8Path::new(file_name).extension()?;
+    if !CACHEABLE_EXTENSIONS.contains(&extension) {
+        return None;
+    }
+    if segments
+        .iter()
+        .any(|s| s.is_empty() || *s == "." || *s == ".." || s.contains('%'))
+    {
+        return None;
+    }
+
+    let mut path = cache_dir.join(match url.port() {
+        Some(port) => format!("{}_{}", host, port),
+        None => host.to_string(),
+    });
+    path.extend(segments);
+    Some(path)
+}
+
+fn handle_connection(
+    client: TcpStream,
+    cache_dir: &Utf8Path,
+    stats: &ProxyStats,
+) -> io::Result<()> {
+    client.set_read_timeout(Some(IO_TIMEOUT))?;
+    client.set_write_timeout(Some(IO_TIMEOUT))?;
+    let mut reader = BufReader::new(client.try_clone()?);
+    let mut client = client;
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return respond_error(&mut client, "400 Bad Request");
+    };
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        let name = line.split(':').next().unwrap_or_default().trim();
+        let hop_by_hop = ["connection", "proxy-connection", "keep-alive", "host"]
+            .iter()
+            .any(|h| name.eq_ignore_ascii_case(h));
+        if !hop_by_hop {
+            headers.push(line.trim_end().to_string());
+        }
+    }
+
+    if method != "GET" && method != "HEAD" {
+        return respond_error(&mut client, "501 Not Implemented");
+    }
+    let url = match Url::parse(target) {
+        Ok(url) if url.scheme() == "http" && url.host_str().is_some() => url,
+        _ => return respond_error(&mut client, "400 Bad Request"),
+    };
+
+    let cached = if method == "GET" {
+        cache_path(cache_dir, &url)
+    } else {
+        None
+    };
+    if let Some(path) = &cached
+        && let Ok(mut file) = std::fs::File::open(path)
+    {
+        let len = file.metadata()?.len();
+        debug!("caching proxy hit: {}", url);
+        write!(
+            client,
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\
+            Content-Type: application/vnd.debian.binary-package\r\nConnection: close\r\n\r\n",
+            len
+        )?;
+        io::copy(&mut file, &mut client)?;
+        stats.served_from_cache.fetch_add(len, Ordering::Relaxed);
+        return client.flush();
+    }
+
+    let fetched = forward(&mut client, method, &url, &headers, cached.as_deref())?;
+    stats.fetched.fetch_add(fetched, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Forwards a request to the origin server and relays the response.
+///
+/// A `200` response for a cacheable URL is also written to a temporary file next to
+/// `cache_path` and moved into place only once the complete body has arrived.
+///
+/// Returns the number of body bytes relayed.
+fn forward(
+    client: &mut TcpStream,
+    method: &str,
+    url: &Url,
+    headers: &[String],
+    cache_path: Option<&Utf8Path>,
+) -> io::Result<u64> {
+    let host = url.host_str().unwrap_or_default();
+    let port = url.port_or_known_default().unwrap_or(80);
+    let upstream = match TcpStream::connect((host, port)) {
+        Ok(stream) => stream,
+        Err(e) => {
+            debug!("caching proxy could not reach {}:{}: {}", host, port, e);
+            return respond_error(client, "502 Bad Gateway").map(|()| 0);
+        }
+    };
+    upstream.set_read_timeout(Some(IO_TIMEOUT))?;
+    upstream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+    let mut path = url.path().to_string();
+    if let Some(query) = url.query() {
+        path.push('?');
+        path.push_str(query);
+    }
+    let host_header = match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    };
+    // HTTP/1.0 with `Connection: close` keeps the response body unchunked and
+    // delimited by EOF, so it can be relayed byte for byte.
+    let mut request = format!("{} {} HTTP/1.0\r\nHost: {}\r\n", method, path, host_header);
+    for header in headers {
+        request.push_str(header);
+        request.push_str("\r\n");
+    }
+    request.push_str("Connection: close\r\n\r\n");
+    (&upstream).write_all(request.as_bytes())?;
+
+    let mut upstream = BufReader::new(upstream);
+    let mut status_line = String::new();
+    upstream.read_line(&mut status_line)?;
+    client.write_all(status_line.as_bytes())?;
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if upstream.read_line(&mut line)? == 0 {
+            break;
+        }
+        client.write_all(line.as_bytes())?;
+        if line.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':')
+            && name.trim().eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse::<u64>().ok();
+        }
+    }
+
+    let ok = status_line.split_whitespace().nth(1) == Some("200");
+    let relayed = match cache_path.filter(|_| ok) {
+        Some(path) => relay_and_store(&mut upstream, client, path, content_length, url),
+        None => io::copy(&mut upstream, client),
+    }?;
+    client.flush()?;
+    Ok(relayed)
+}
+
+/// Streams the response body to the client while storing it at `path`.
+///
+/// Caching is best effort: a failure to write the cache file is logged and the
+/// download continues uncached. Returns the number of body bytes relayed.
+fn relay_and_store(
+    upstream: &mut impl Read,
+    client: &mut TcpStream,
+    path: &Utf8Path,
+    content_length: Option<u64>,
+    url: &Url,
+) -> io::Result<u64> {
+    let mut temp = path
+        .parent()
+        .ok_or_else(|| io::Error::other("cache path has no parent"))
+        .and_then(|parent| {
+            std::fs::create_dir_all(parent)?;
+            tempfile::Builder::new()
+                .prefix(".partial-")
+                .tempfile_in(parent)
+        })
+        .inspect_err(|e| warn!("caching proxy cannot cache {}: {}", url, e))
+        .ok();
+
+    let mut buf = [0u8; 64 * 1024];
+    let mut total = 0u64;
+    loop {
+        let n = upstream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        client.write_all(&buf[..n])?;
+        if let Some(file) = temp.as_mut()
+            && let Err(e) = file.write_all(&buf[..n])
+        {
+            warn!("caching proxy cannot cache {}: {}", url, e);
+            temp = None;
+        }
+        total += n as u64;
+    }
+
+    if let Some(file) = temp {
+        if content_length.is_some_and(|len| len != total) {
+            debug!("caching proxy discarded truncated download: {}", url);
+        } else if let Err(e) = file.persist(path) {
+            warn!("caching proxy cannot cache {}: {}", url, e.error);
+        } else {
+            debug!("caching proxy stored: {}", url);
+        }
+    }
+    Ok(total)
+}
+
+fn respond_error(client: &mut TcpStream, status: &str) -> io::Result<()> {
+    write!(client, "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status)?;
+    client.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(proxy: Option<&str>, dir: Option<&str>) -> AptCacheConfig {
+        AptCacheConfig {
+            proxy: proxy.map(str::to_string),
+            dir: dir.map(Utf8PathBuf::from),
+        }
+    }
+
+    #[test]
+    fn validate_requires_exactly_one_source() {
+        assert!(
+            config(Some("http://127.0.0.1:3142"), None)
+                .validate()
+                .is_ok()
+        );
+        assert!(
+            config(None, Some("/var/cache/rsdebstrap"))
+                .validate()
+                .is_ok()
+        );
+
+        let both = config(Some("http://127.0.0.1:3142"), Some("/var/cache/rsdebstrap"));
+        assert!(
+            both.validate()
+                .unwrap_err()
+                .to_string()
+                .contains("mutually exclusive")
+        );
+        let neither = config(None, None);
+        assert!(
+            neither
+                .validate()
+                .unwrap_err()
+                .to_string()
+                .contains("must be set")
+        );
+    }
+
+    #[test]
+    fn validate_rejects_non_http_proxy() {
+        for proxy in ["https://proxy:3142", "proxy:3142", "socks5://127.0.0.1"] {
+            let err = config(Some(proxy), None).validate().unwrap_err();
+            assert!(err.to_string().contains("must be an http:// URL"), "{proxy}: {err}");
+        }
+    }
+
+    #[test]
+    fn resolve_paths_anchors_relative_dir() {
+        let mut cfg = config(None, Some("cache"));
+        cfg.resolve_paths(Utf8Path::new("/profiles"));
+        assert_eq!(cfg.dir.as_deref(), Some(Utf8Path::new("/profiles/cache")));
+
+        let mut cfg = config(None, Some("/var/cache/rsdebstrap"));
+        cfg.resolve_paths(Utf8Path::new("/profiles"));
+        assert_eq!(cfg.dir.as_deref(), Some(Utf8Path::new("/var/cache/rsdebstrap")));
+    }
+
+    #[test]
+    fn cache_path_only_covers_package_files() {
+        let dir = Utf8Path::new("/cache");
+        let url = |s: &str| Url::parse(s).unwrap();
+
+        assert_eq!(
+            cache_path(dir, &url("http://deb.debian.org/debian/pool/main/v/vim/vim_9.1_amd64.deb")),
+            Some(Utf8PathBuf::from(
+                "/cache/deb.debian.org/debian/pool/main/v/vim/vim_9.1_amd64.deb"
+            ))
+        );
+        assert_eq!(
+            cache_path(dir, &url("http://mirror:8080/d-i/x.udeb")),
+            Some(Utf8PathBuf::from("/cache/mirror_8080/d-i/x.udeb"))
+        );
+        assert_eq!(
+            cache_path(dir, &url("http://deb.debian.org/debian/dists/trixie/Release")),
+            None
+        );
+        assert_eq!(cache_path(dir, &url("http://deb.debian.org/x.deb?nocache=1")), None);
+        assert_eq!(cache_path(dir, &url("http://deb.debian.org/a%2F..%2Fx.deb")), None);
+    }
+
+    /// Serves `body` with status 200 to each of `count` connections, then exits.
+    fn origin(body: &'static [u8], count: usize) -> (SocketAddr, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for stream in listener.incoming().take(count) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                requests.push(line.trim_end().to_string());
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim_end().is_empty() {
+                        break;
+                    }
+                }
+                write!(stream, "HTTP/1.0 200 OK\r\nContent-Length: {}\r\n\r\n", body.len())
+                    .unwrap();
+                stream.write_all(body).unwrap();
+            }
+            requests
+        });
+        (addr, handle)
+    }
+
+    fn proxy_get(proxy: &CachingProxy, url: &str) -> String {
+        let mut stream = TcpStream::connect(proxy.addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: ignored\r\n\r\n", url).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn proxy_serves_second_package_download_from_cache() {
+        let cache = tempfile::tempdir().unwrap();
+        let cache_dir = Utf8Path::from_path(cache.path()).unwrap();
+        let (origin_addr, origin) = origin(b"package-bytes", 1);
+        let proxy = CachingProxy::start(cache_dir).unwrap();
+        let url = format!("http://{}/debian/pool/main/h/hello/hello_1.0_amd64.deb", origin_addr);
+
+        let first = proxy_get(&proxy, &url);
+        // The origin accepts only one connection, so the second response must be cached.
+        let requests = origin.join().unwrap();
+        let second = proxy_get(&proxy, &url);
+
+        assert!(first.starts_with("HTTP/1.0 200 OK"), "{first}");
+        assert!(first.ends_with("package-bytes"), "{first}");
+        assert!(second.starts_with("HTTP/1.1 200 OK"), "{second}");
+        assert!(second.ends_with("package-bytes"), "{second}");
+        assert_eq!(requests, ["GET /debian/pool/main/h/hello/hello_1.0_amd64.deb HTTP/1.0"]);
+        let stats = proxy.stats();
+        assert_eq!(stats.fetched_bytes(), "package-bytes".len() as u64);
+        assert_eq!(stats.served_from_cache_bytes(), "package-bytes".len() as u64);
+    }
+
+    #[test]
+    fn proxy_always_forwards_index_files() {
+        let cache = tempfile::tempdir().unwrap();
+        let cache_dir = Utf8Path::from_path(cache.path()).unwrap();
+        let (origin_addr, origin) = origin(b"Suite: trixie", 2);
+        let proxy = CachingProxy::start(cache_dir).unwrap();
+        let url = format!("http://{}/debian/dists/trixie/Release", origin_addr);
+
+        let first = proxy_get(&proxy, &url);
+        let second = proxy_get(&proxy, &url);
+
+        assert!(first.ends_with("Suite: trixie"), "{first}");
+        assert!(second.ends_with("Suite: trixie"), "{second}");
+        assert_eq!(origin.join().unwrap().len(), 2);
+        assert_eq!(std::fs::read_dir(cache.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn proxy_rejects_connect_requests() {
+        let cache = tempfile::tempdir().unwrap();
+        let proxy = CachingProxy::start(Utf8Path::from_path(cache.path()).unwrap()).unwrap();
+
+        let mut stream = TcpStream::connect(proxy.addr).unwrap();
+        write!(stream, "CONNECT deb.debian.org:443 HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 501"), "{response}");
+    }
+}
@@ -1,5 +1,7 @@
 //! debootstrap backend implementation.
 
+use super::distribution::Distribution;
+use super::version::{self, ToolVersion};
 use super::{BootstrapBackend, CommandArgsBuilder, FlagValueStyle, RootfsOutput};
 use crate::privilege::Privilege;
 use anyhow::Result;
@@ -8,6 +10,15 @@ use camino::Utf8Path;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use strum::Display;
+use tracing::info;
+
+/// First debootstrap releases accepting `--merged-usr` and `--no-merged-usr`. Older
+/// releases never merge `/usr`, so `--no-merged-usr` is left out for them.
+const MERGED_USR_SINCE: &str = "1.0.83";
+const NO_MERGED_USR_SINCE: &str = "1.0.85";
+
+/// Directory holding debootstrap's per-suite scripts; a suite without one fails.
+pub const SCRIPTS_DIR: &str = "/usr/share/debootstrap/scripts";
 
 /// Variant defines the package selection strategy for debootstrap
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Display)]
@@ -42,50 +53,239 @@ pub enum Variant {
 // relies on) and `yaml_serde`. (The well-known serde limitation is that `deny_unknown_fields`
 // is a no-op when placed on the internally-tagged *enum* itself, not — as here — on a
 // variant's struct.)
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
 #[serde(deny_unknown_fields)]
 pub struct DebootstrapConfig {
     /// Debian suite name (e.g., "bookworm", "trixie")
     pub suite: String,
+    /// Distribution of the suite (defaults to Debian); `ubuntu` fills in Ubuntu's
+    /// components, mirror and keyring
+    #[serde(default, skip_serializing_if = "Distribution::is_debian")]
+    pub distribution: Distribution,
+    /// Preset of a Debian derivative (`raspbian`, `devuan`, `kali` or a user preset)
+    /// filling in its mirror, keyring, components and packages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
     /// Target output directory path (relative to profile dir)
     pub target: String,
     /// Package selection variant (defaults to Minbase)
     #[serde(default)]
     pub variant: Variant,
     /// Target architecture (e.g., "amd64", "arm64")
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub arch: Option<String>,
     /// Repository components to enable (e.g., "main", "contrib", "non-free")
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub components: Vec<String>,
     /// Additional packages to include
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub include: Vec<String>,
     /// Packages to exclude
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub exclude: Vec<String>,
     /// APT mirror URL to use as package source
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub mirror: Option<String>,
+    /// Mirror URLs to try in order when `mirror` fails its health check
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallback_mirrors: Vec<String>,
+    /// Keyring path for repository verification
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyring: Option<String>,
     /// Perform two-stage bootstrap (for cross-architecture installations)
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
     pub foreign: bool,
     /// Use merged /usr directory structure
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub merged_usr: Option<bool>,
     /// Don't resolve recommends/suggests
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
     pub no_resolve_deps: bool,
     /// Verbose output
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
     pub verbose: bool,
     /// Print packages to be installed and exit
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
     pub print_debs: bool,
     /// Privilege escalation setting
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Privilege::is_inherit")]
     pub privilege: Privilege,
+    /// Oldest debootstrap release the profile accepts (e.g. "1.0.128")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+    pub min_version: Option<ToolVersion>,
+}
+
+/// Builder for [`DebootstrapConfig`].
+///
+/// Starts from the required `suite` and `target`; every other field keeps its YAML
+/// default unless set.
+///
+/// ```
+/// use rsdebstrap::bootstrap::debootstrap::DebootstrapConfigBuilder;
+///
+/// let config = DebootstrapConfigBuilder::new("trixie", "rootfs")
+///     .arch("arm64")
+///     .mirror("https://deb.debian.org/debian")
+///     .build();
+/// assert_eq!(config.arch.as_deref(), Some("arm64"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct DebootstrapConfigBuilder {
+    config: DebootstrapConfig,
+}
+
+impl DebootstrapConfigBuilder {
+    /// Creates a builder for the given suite and target.
+    pub fn new(suite: impl Into<String>, target: impl Into<String>) -> Self {
+        Self {
+            config: DebootstrapConfig {
+                suite: suite.into(),
+                distribution: Distribution::default(),
+                preset: None,
+                target: target.into(),
+                variant: Variant::default(),
+                arch: None,
+                components: Vec::new(),
+                include: Vec::new(),
+                exclude: Vec::new(),
+                mirror: None,
+                fallback_mirrors: Vec::new(),
+                keyring: None,
+                foreign: false,
+                merged_usr: None,
+                no_resolve_deps: false,
+                verbose: false,
+                print_debs: false,
+                privilege: Privilege::default(),
+                min_version: None,
+            },
+        }
+    }
+
+    /// Sets the distribution of the suite.
+    pub fn distribution(mut self, distribution: Distribution) -> Self {
+        self.config.distribution = distribution;
+        self
+    }
+
+    /// Sets the derivative preset.
+    pub fn preset(mut self, preset: impl Into<String>) -> Self {
+        self.config.preset = Some(preset.into());
+        self
+    }
+
+    /// Sets the package selection variant.
+    pub fn variant(mut self, variant: Variant) -> Self {
+        self.config.variant = variant;
+        self
+    }
+
+    /// Sets the target architecture.
+    pub fn arch(mut self, arch: impl Into<String>) -> Self {
+        self.config.arch = Some(arch.into());
+        self
+    }
+
+    /// Sets the repository components to enable.
+    pub fn components<I, S>(mut self, components: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.components = components.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the additional packages to include.
+    pub fn include<I, S>(mut self, include: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.include = include.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the packages to exclude.
+    pub fn exclude<I, S>(mut self, exclude: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.exclude = exclude.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the APT mirror URL.
+    pub fn mirror(mut self, mirror: impl Into<String>) -> Self {
+        self.config.mirror = Some(mirror.into());
+        self
+    }
+
+    /// Sets the mirror URLs tried when `mirror` fails its health check.
+    pub fn fallback_mirrors<I, S>(mut self, fallback_mirrors: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.fallback_mirrors = fallback_mirrors.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the keyring path for repository verification.
+    pub fn keyring(mut self, keyring: impl Into<String>) -> Self {
+        self.config.keyring = Some(keyring.into());
+        self
+    }
+
+    /// Sets whether to perform a two-stage (foreign) bootstrap.
+    pub fn foreign(mut self, foreign: bool) -> Self {
+        self.config.foreign = foreign;
+        self
+    }
+
+    /// Sets whether to use a merged /usr directory structure.
+    pub fn merged_usr(mut self, merged_usr: bool) -> Self {
+        self.config.merged_usr = Some(merged_usr);
+        self
+    }
+
+    /// Sets whether to skip resolving recommends/suggests.
+    pub fn no_resolve_deps(mut self, no_resolve_deps: bool) -> Self {
+        self.config.no_resolve_deps = no_resolve_deps;
+        self
+    }
+
+    /// Sets verbose output.
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.config.verbose = verbose;
+        self
+    }
+
+    /// Sets whether to print the packages to be installed and exit.
+    pub fn print_debs(mut self, print_debs: bool) -> Self {
+        self.config.print_debs = print_debs;
+        self
+    }
+
+    /// Sets the privilege escalation setting.
+    pub fn privilege(mut self, privilege: Privilege) -> Self {
+        self.config.privilege = privilege;
+        self
+    }
+
+    /// Sets the oldest debootstrap release the profile accepts.
+    pub fn min_version(mut self, min_version: ToolVersion) -> Self {
+        self.config.min_version = Some(min_version);
+        self
+    }
+
+    /// Returns the configured [`DebootstrapConfig`].
+    pub fn build(self) -> DebootstrapConfig {
+        self.config
+    }
 }
 
 impl BootstrapBackend for DebootstrapConfig {
@@ -109,6 +309,10 @@ impl BootstrapBackend for DebootstrapConfig {
         builder.push_comma_joined("--include", &self.include, FlagValueStyle::Equals);
         builder.push_comma_joined("--exclude", &self.exclude, FlagValueStyle::Equals);
 
+        if let Some(ref keyring) = self.keyring {
+            builder.push_flag_value("--keyring", keyring, FlagValueStyle::Equals);
+        }
+
         if self.foreign {
             builder.push_flag("--foreign");
         }
@@ -150,6 +354,20 @@ impl BootstrapBackend for DebootstrapConfig {
         Ok(cmd_args)
     }
 
+    fn adapt_args(&self, mut args: Vec<String>, version: &ToolVersion) -> Result<Vec<String>> {
+        match self.merged_usr {
+            Some(true) => {
+                version::require("debootstrap", version, "merged_usr: true", MERGED_USR_SINCE)?
+            }
+            Some(false) if version::predates(version, NO_MERGED_USR_SINCE) => {
+                info!("debootstrap {} predates --no-merged-usr and never merges /usr", version);
+                args.retain(|arg| arg != "--no-merged-usr");
+            }
+            _ => {}
+        }
+        Ok(args)
+    }
+
     fn rootfs_output(&self, output_dir: &Utf8Path) -> Result<RootfsOutput> {
         Ok(RootfsOutput::Directory(output_dir.join(&self.target)))
     }
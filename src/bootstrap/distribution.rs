@@ -0,0 +1,73 @@
+//! Distribution a bootstrap backend builds.
+//!
+//! Both backends default to Debian. With `distribution: ubuntu`,
+//! [`Profile`](crate::config::Profile) loading fills in what an Ubuntu build otherwise
+//! needs spelled out: the `main universe` components, the archive mirror for the
+//! target architectures (`archive.ubuntu.com` for amd64 and i386, `ports.ubuntu.com`
+//! for the rest) and the Ubuntu archive keyring, which Debian hosts do not trust by
+//! default.
+
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+/// Ubuntu archive for amd64 and i386.
+pub const UBUNTU_ARCHIVE: &str = "https://archive.ubuntu.com/ubuntu";
+
+/// Ubuntu archive for every other architecture.
+pub const UBUNTU_PORTS: &str = "https://ports.ubuntu.com/ubuntu-ports";
+
+/// Keyring of the Ubuntu archive, from the `ubuntu-keyring` package (also packaged
+/// for Debian).
+pub const UBUNTU_KEYRING: &str = "/usr/share/keyrings/ubuntu-archive-keyring.gpg";
+
+/// Components enabled for Ubuntu when none are given.
+pub const UBUNTU_COMPONENTS: &[&str] = &["main", "universe"];
+
+/// Architectures the Ubuntu archives build.
+pub const UBUNTU_ARCHITECTURES: &[&str] = &[
+    "amd64", "arm64", "armhf", "i386", "ppc64el", "riscv64", "s390x",
+];
+
+/// Distribution a bootstrap backend builds.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Display)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Distribution {
+    /// Debian (default)
+    #[default]
+    Debian,
+    /// Ubuntu
+    Ubuntu,
+}
+
+impl Distribution {
+    /// Returns whether this is the default, Debian.
+    pub fn is_debian(&self) -> bool {
+        *self == Self::Debian
+    }
+}
+
+/// Returns the Ubuntu archive serving `arch`: [`UBUNTU_ARCHIVE`] for amd64 and i386,
+/// [`UBUNTU_PORTS`] otherwise.
+pub fn ubuntu_mirror(arch: &str) -> &'static str {
+    match arch {
+        "amd64" | "i386" => UBUNTU_ARCHIVE,
+        _ => UBUNTU_PORTS,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ubuntu_mirror_uses_ports_for_non_x86() {
+        assert_eq!(ubuntu_mirror("amd64"), UBUNTU_ARCHIVE);
+        assert_eq!(ubuntu_mirror("i386"), UBUNTU_ARCHIVE);
+        assert_eq!(ubuntu_mirror("arm64"), UBUNTU_PORTS);
+        assert_eq!(ubuntu_mirror("riscv64"), UBUNTU_PORTS);
+    }
+}
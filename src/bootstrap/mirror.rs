@@ -0,0 +1,250 @@
+//! Mirror failover for bootstrap backends.
+//!
+//! When a backend has `fallback_mirrors`, its primary mirror and the fallbacks are
+//! health-checked in order before the backend runs, and the first mirror that serves
+//! `dists/<suite>/Release` replaces the primary. The check is a `curl --head` run
+//! through the [`CommandExecutor`], so it is logged (and skipped) like any other
+//! command in dry-run mode.
+
+use url::Url;
+
+use super::sanitize_credential;
+use crate::error::RsdebstrapError;
+use crate::executor::{CommandExecutor, CommandSpec};
+
+/// Seconds each health check may take before the mirror is considered down.
+pub const HEALTH_CHECK_TIMEOUT_SECS: u32 = 10;
+
+/// Returns the URL of the `Release` file of `suite` on `mirror`.
+pub fn release_url(mirror: &str, suite: &str) -> String {
+    format!("{}/dists/{}/Release", mirror.trim_end_matches('/'), suite)
+}
+
+/// Builds the health check command for `mirror`.
+pub fn health_check_spec(mirror: &str, suite: &str) -> CommandSpec {
+    CommandSpec::new(
+        "curl",
+        vec![
+            "--head".to_string(),
+            "--silent".to_string(),
+            "--show-error".to_string(),
+            "--fail".to_string(),
+            "--location".to_string(),
+            "--max-time".to_string(),
+            HEALTH_CHECK_TIMEOUT_SECS.to_string(),
+            "--output".to_string(),
+            "/dev/null".to_string(),
+            release_url(mirror, suite),
+        ],
+    )
+}
+
+/// Validates the candidates of a failover list.
+///
+/// `primary` is the mirror the fallbacks stand in for; it is required, and it and
+/// every fallback must be a plain `http://` or `https://` URL (not a sources.list line).
+pub fn validate_candidates(
+    primary: Option<&str>,
+    fallbacks: &[String],
+    primary_field: &str,
+) -> Result<(), RsdebstrapError> {
+    if fallbacks.is_empty() {
+        return Ok(());
+    }
+    let Some(primary) = primary else {
+        return Err(RsdebstrapError::Validation(format!(
+            "fallback_mirrors requires '{}' to be set",
+            primary_field
+        )));
+    };
+    for mirror in std::iter::once(primary).chain(fallbacks.iter().map(String::as_str)) {
+        let valid = Url::parse(mirror)
+            .is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.host().is_some());
+        if !valid {
+            return Err(RsdebstrapError::Validation(format!(
+                "mirror '{}' must be an http:// or https:// URL when fallback_mirrors is set",
+                sanitize_credential(mirror)
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Health-checks `primary` and then `fallbacks` in order, returning the first that
+/// responds.
+///
+/// # Errors
+///
+/// Returns [`RsdebstrapError::Mirror`] listing every failure when no mirror responds,
+/// or [`RsdebstrapError::Interrupted`] as soon as a health check is interrupted.
+pub fn select_mirror(
+    primary: &str,
+    fallbacks: &[String],
+    suite: &str,
+    executor: &dyn CommandExecutor,
+) -> Result<String, RsdebstrapError> {
+    let mut failures = Vec::new();
+    for mirror in std::iter::once(primary).chain(fallbacks.iter().map(String::as_str)) {
+        let shown = sanitize_credential(mirror);
+        let spec = health_check_spec(mirror, suite);
+        match executor.execute_checked(&spec) {
+            Ok(()) => {
+                tracing::info!("using mirror {} for {}", shown, suite);
+                return Ok(mirror.to_string());
+            }
+            // An interrupted check is not the mirror's fault: stop instead of trying the next.
+            Err(e) if crate::error::is_interrupted(&e) => {
+                return Err(e
+                    .downcast::<RsdebstrapError>()
+                    .unwrap_or_else(|_| RsdebstrapError::interrupted(&spec, "interrupted")));
+            }
+            Err(e) => {
+                tracing::warn!("mirror {} failed health check: {:#}", shown, e);
+                failures.push(format!("{}: {:#}", shown, e));
+            }
+        }
+    }
+    Err(RsdebstrapError::Mirror(format!(
+        "no mirror serves {}: {}",
+        release_url("<mirror>", suite),
+        failures.join("; ")
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::ExecutionResult;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+    use std::sync::Mutex;
+
+    /// Fails the health check of every URL containing one of `down`.
+    struct Mirrors {
+        down: Vec<&'static str>,
+        checked: Mutex<Vec<String>>,
+    }
+
+    impl CommandExecutor for Mirrors {
+        fn execute(&self, spec: &CommandSpec) -> anyhow::Result<ExecutionResult> {
+            let url = spec.args.last().unwrap().clone();
+            let code = if self.down.iter().any(|d| url.contains(d)) {
+                22
+            } else {
+                0
+            };
+            self.checked.lock().unwrap().push(url);
+            Ok(ExecutionResult {
+                status: Some(ExitStatus::from_raw(code << 8)),
+                details: None,
+            })
+        }
+    }
+
+    fn mirrors(down: &[&'static str]) -> Mirrors {
+        Mirrors {
+            down: down.to_vec(),
+            checked: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn fallbacks() -> Vec<String> {
+        vec![
+            "http://ftp.jp.debian.org/debian".to_string(),
+            "http://ftp.us.debian.org/debian".to_string(),
+        ]
+    }
+
+    #[test]
+    fn release_url_joins_without_double_slash() {
+        assert_eq!(
+            release_url("http://deb.debian.org/debian/", "trixie"),
+            "http://deb.debian.org/debian/dists/trixie/Release"
+        );
+    }
+
+    #[test]
+    fn health_check_heads_release_file() {
+        let spec = health_check_spec("http://deb.debian.org/debian", "trixie");
+        assert_eq!(spec.command, "curl");
+        assert!(spec.args.contains(&"--head".to_string()));
+        assert!(spec.args.contains(&"--fail".to_string()));
+        assert_eq!(spec.args.last().unwrap(), "http://deb.debian.org/debian/dists/trixie/Release");
+        assert_eq!(spec.privilege, None);
+    }
+
+    #[test]
+    fn healthy_primary_is_kept_without_checking_fallbacks() {
+        let executor = mirrors(&[]);
+        let chosen =
+            select_mirror("http://deb.debian.org/debian", &fallbacks(), "trixie", &executor)
+                .unwrap();
+        assert_eq!(chosen, "http://deb.debian.org/debian");
+        assert_eq!(executor.checked.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn first_healthy_fallback_replaces_failed_primary() {
+        let executor = mirrors(&["deb.debian.org", "ftp.jp"]);
+        let chosen =
+            select_mirror("http://deb.debian.org/debian", &fallbacks(), "trixie", &executor)
+                .unwrap();
+        assert_eq!(chosen, "http://ftp.us.debian.org/debian");
+        assert_eq!(executor.checked.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn all_mirrors_down_is_mirror_error() {
+        let executor = mirrors(&["debian.org"]);
+        let err = select_mirror("http://deb.debian.org/debian", &fallbacks(), "trixie", &executor)
+            .unwrap_err();
+        assert!(matches!(err, RsdebstrapError::Mirror(_)));
+        let message = err.to_string();
+        assert!(message.contains("dists/trixie/Release"), "{message}");
+        for mirror in ["deb.debian.org", "ftp.jp.debian.org", "ftp.us.debian.org"] {
+            assert!(message.contains(mirror), "{message}");
+        }
+    }
+
+    #[test]
+    fn interrupted_health_check_stops_selection() {
+        struct Interrupted(Mutex<usize>);
+        impl CommandExecutor for Interrupted {
+            fn execute(&self, spec: &CommandSpec) -> anyhow::Result<ExecutionResult> {
+                *self.0.lock().unwrap() += 1;
+                Err(RsdebstrapError::interrupted(spec, "interrupted").into())
+            }
+        }
+
+        let executor = Interrupted(Mutex::new(0));
+        let err = select_mirror("http://deb.debian.org/debian", &fallbacks(), "trixie", &executor)
+            .unwrap_err();
+
+        assert!(matches!(err, RsdebstrapError::Interrupted { .. }), "{err}");
+        assert_eq!(*executor.0.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn validate_requires_primary_and_url_candidates() {
+        assert!(validate_candidates(None, &[], "mirror").is_ok());
+        assert!(
+            validate_candidates(Some("http://deb.debian.org/debian"), &fallbacks(), "mirror")
+                .is_ok()
+        );
+
+        let err = validate_candidates(None, &fallbacks(), "mirror").unwrap_err();
+        assert!(err.to_string().contains("requires 'mirror'"), "{err}");
+
+        let err = validate_candidates(
+            Some("deb http://deb.debian.org/debian trixie main"),
+            &fallbacks(),
+            "mirrors",
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("must be an http:// or https:// URL"),
+            "{err}"
+        );
+    }
+}
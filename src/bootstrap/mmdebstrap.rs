@@ -1,7 +1,9 @@
 //! mmdebstrap backend implementation.
 
+use super::distribution::Distribution;
+use super::version::{self, ToolVersion};
 use super::{BootstrapBackend, CommandArgsBuilder, FlagValueStyle, RootfsOutput};
-use crate::privilege::Privilege;
+use crate::privilege::{Privilege, PrivilegeMethod};
 use anyhow::Result;
 use camino::Utf8Path;
 #[cfg(feature = "schema")]
@@ -14,6 +16,19 @@ use strum::Display;
 const KNOWN_ARCHIVE_EXTENSIONS: &[&str] =
     &["tar", "gz", "bz2", "xz", "zst", "squashfs", "ext2", "img"];
 
+/// `target` that makes mmdebstrap write the rootfs archive to standard output.
+pub const STDOUT_TARGET: &str = "-";
+
+/// First mmdebstrap release writing `--format ext2`.
+const EXT2_FORMAT_SINCE: &str = "0.8.0";
+
+/// Special hook commands (`copy-in`, `sync-out`, ...) and the first mmdebstrap
+/// release running them.
+const SPECIAL_HOOKS: &[&str] = &[
+    "copy-in", "copy-out", "tar-in", "tar-out", "upload", "download", "sync-in", "sync-out",
+];
+const SPECIAL_HOOKS_SINCE: &str = "0.6.0";
+
 /// Variant defines the package selection strategy for mmdebstrap
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Display)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
@@ -118,12 +133,20 @@ pub enum Format {
 // relies on) and `yaml_serde`. (The well-known serde limitation is that `deny_unknown_fields`
 // is a no-op when placed on the internally-tagged *enum* itself, not — as here — on a
 // variant's struct.)
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
 #[serde(deny_unknown_fields)]
 pub struct MmdebstrapConfig {
     /// Debian suite name (e.g., "bookworm", "sid")
     pub suite: String,
+    /// Distribution of the suite (defaults to Debian); `ubuntu` fills in Ubuntu's
+    /// components, mirror and keyring
+    #[serde(default, skip_serializing_if = "Distribution::is_debian")]
+    pub distribution: Distribution,
+    /// Preset of a Debian derivative (`raspbian`, `devuan`, `kali` or a user preset)
+    /// filling in its mirror, keyring, components and packages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
     /// Target output path
     pub target: String,
     /// Operation mode (defaults to Auto)
@@ -136,41 +159,306 @@ pub struct MmdebstrapConfig {
     #[serde(default)]
     pub variant: Variant,
     /// Target architectures
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub architectures: Vec<String>,
     /// Repository components to enable (e.g., "main", "contrib", "non-free")
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub components: Vec<String>,
     /// Additional packages to include
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub include: Vec<String>,
     /// Keyring paths for repository verification
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub keyring: Vec<String>,
     /// Additional APT options
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub aptopt: Vec<String>,
     /// Additional dpkg options
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub dpkgopt: Vec<String>,
     /// Setup hook scripts
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub setup_hook: Vec<String>,
     /// Extract hook scripts
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub extract_hook: Vec<String>,
     /// Essential hook scripts
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub essential_hook: Vec<String>,
     /// Customize hook scripts
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub customize_hook: Vec<String>,
     /// APT mirror URLs to use as package sources
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub mirrors: Vec<String>,
+    /// Mirror URLs to try in order when the first entry of `mirrors` fails its
+    /// health check
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fallback_mirrors: Vec<String>,
     /// Privilege escalation setting
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Privilege::is_inherit")]
     pub privilege: Privilege,
+    /// Commands the archive is piped through when `target` is "-", each reading the
+    /// previous one's output and run in `dir` (e.g. `[[zstd, -T0, -o, rootfs.tar.zst]]`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stream_to: Vec<Vec<String>>,
+    /// Oldest mmdebstrap release the profile accepts (e.g. "1.3.0")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+    pub min_version: Option<ToolVersion>,
+}
+
+impl MmdebstrapConfig {
+    /// Returns whether the archive is written to standard output (`target: "-"`).
+    pub fn streams(&self) -> bool {
+        self.target == STDOUT_TARGET
+    }
+
+    /// Returns the mode passed to mmdebstrap.
+    ///
+    /// With the rootless `userns` privilege method an `auto` mode becomes `unshare`,
+    /// so mmdebstrap sets up its own user namespace instead of being wrapped.
+    pub fn effective_mode(&self) -> Mode {
+        // Matched directly rather than via `resolved_method()`, which expects a resolved
+        // setting: backends are also built standalone, where `Inherit` is never resolved.
+        if self.mode == Mode::Auto && self.privilege == Privilege::Method(PrivilegeMethod::Userns) {
+            Mode::Unshare
+        } else {
+            self.mode.clone()
+        }
+    }
+}
+
+/// Builder for [`MmdebstrapConfig`].
+///
+/// Starts from the required `suite` and `target`; every other field keeps its YAML
+/// default unless set.
+///
+/// ```
+/// use rsdebstrap::bootstrap::mmdebstrap::{MmdebstrapConfigBuilder, Variant};
+///
+/// let config = MmdebstrapConfigBuilder::new("trixie", "rootfs")
+///     .variant(Variant::Minbase)
+///     .include(["vim", "curl"])
+///     .build();
+/// assert_eq!(config.include, ["vim", "curl"]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct MmdebstrapConfigBuilder {
+    config: MmdebstrapConfig,
+}
+
+impl MmdebstrapConfigBuilder {
+    /// Creates a builder for the given suite and target.
+    pub fn new(suite: impl Into<String>, target: impl Into<String>) -> Self {
+        Self {
+            config: MmdebstrapConfig {
+                suite: suite.into(),
+                distribution: Distribution::default(),
+                preset: None,
+                target: target.into(),
+                mode: Mode::default(),
+                format: Format::default(),
+                variant: Variant::default(),
+                architectures: Vec::new(),
+                components: Vec::new(),
+                include: Vec::new(),
+                keyring: Vec::new(),
+                aptopt: Vec::new(),
+                dpkgopt: Vec::new(),
+                setup_hook: Vec::new(),
+                extract_hook: Vec::new(),
+                essential_hook: Vec::new(),
+                customize_hook: Vec::new(),
+                mirrors: Vec::new(),
+                fallback_mirrors: Vec::new(),
+                privilege: Privilege::default(),
+                stream_to: Vec::new(),
+                min_version: None,
+            },
+        }
+    }
+
+    /// Sets the operation mode.
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.config.mode = mode;
+        self
+    }
+
+    /// Sets the output format.
+    pub fn format(mut self, format: Format) -> Self {
+        self.config.format = format;
+        self
+    }
+
+    /// Sets the distribution of the suite.
+    pub fn distribution(mut self, distribution: Distribution) -> Self {
+        self.config.distribution = distribution;
+        self
+    }
+
+    /// Sets the derivative preset.
+    pub fn preset(mut self, preset: impl Into<String>) -> Self {
+        self.config.preset = Some(preset.into());
+        self
+    }
+
+    /// Sets the package selection variant.
+    pub fn variant(mut self, variant: Variant) -> Self {
+        self.config.variant = variant;
+        self
+    }
+
+    /// Sets the target architectures.
+    pub fn architectures<I, S>(mut self, architectures: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.architectures = architectures.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the repository components to enable.
+    pub fn components<I, S>(mut self, components: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.components = components.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the additional packages to include.
+    pub fn include<I, S>(mut self, include: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.include = include.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the keyring paths for repository verification.
+    pub fn keyring<I, S>(mut self, keyring: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.keyring = keyring.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the additional APT options.
+    pub fn aptopt<I, S>(mut self, aptopt: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.aptopt = aptopt.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the additional dpkg options.
+    pub fn dpkgopt<I, S>(mut self, dpkgopt: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.dpkgopt = dpkgopt.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the setup hook scripts.
+    pub fn setup_hook<I, S>(mut self, setup_hook: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.setup_hook = setup_hook.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the extract hook scripts.
+    pub fn extract_hook<I, S>(mut self, extract_hook: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.extract_hook = extract_hook.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the essential hook scripts.
+    pub fn essential_hook<I, S>(mut self, essential_hook: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.essential_hook = essential_hook.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the customize hook scripts.
+    pub fn customize_hook<I, S>(mut self, customize_hook: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.customize_hook = customize_hook.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the APT mirror URLs.
+    pub fn mirrors<I, S>(mut self, mirrors: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.mirrors = mirrors.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the mirror URLs tried when the first mirror fails its health check.
+    pub fn fallback_mirrors<I, S>(mut self, fallback_mirrors: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.fallback_mirrors = fallback_mirrors.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the privilege escalation setting.
+    pub fn privilege(mut self, privilege: Privilege) -> Self {
+        self.config.privilege = privilege;
+        self
+    }
+
+    /// Sets the commands the archive is piped through when `target` is "-".
+    pub fn stream_to<I, C, S>(mut self, stream_to: I) -> Self
+    where
+        I: IntoIterator<Item = C>,
+        C: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.stream_to = stream_to
+            .into_iter()
+            .map(|command| command.into_iter().map(Into::into).collect())
+            .collect();
+        self
+    }
+
+    /// Sets the oldest mmdebstrap release the profile accepts.
+    pub fn min_version(mut self, min_version: ToolVersion) -> Self {
+        self.config.min_version = Some(min_version);
+        self
+    }
+
+    /// Returns the configured [`MmdebstrapConfig`].
+    pub fn build(self) -> MmdebstrapConfig {
+        self.config
+    }
 }
 
 impl BootstrapBackend for MmdebstrapConfig {
@@ -183,7 +471,7 @@ impl BootstrapBackend for MmdebstrapConfig {
         let mut builder = CommandArgsBuilder::new();
 
         // Only add flags if they differ from defaults
-        builder.push_if_not_default("--mode", &self.mode, FlagValueStyle::Separate);
+        builder.push_if_not_default("--mode", &self.effective_mode(), FlagValueStyle::Separate);
         builder.push_if_not_default("--format", &self.format, FlagValueStyle::Separate);
         builder.push_if_not_default("--variant", &self.variant, FlagValueStyle::Separate);
 
@@ -210,7 +498,11 @@ impl BootstrapBackend for MmdebstrapConfig {
 
         builder.push_arg(self.suite.clone());
 
-        builder.push_arg(output_dir.join(&self.target).to_string());
+        if self.streams() {
+            builder.push_arg(STDOUT_TARGET);
+        } else {
+            builder.push_arg(output_dir.join(&self.target).to_string());
+        }
 
         let mut cmd_args = builder.into_args();
 
@@ -227,7 +519,37 @@ impl BootstrapBackend for MmdebstrapConfig {
         Ok(cmd_args)
     }
 
+    fn adapt_args(&self, args: Vec<String>, version: &ToolVersion) -> Result<Vec<String>> {
+        if self.format == Format::Ext2 {
+            version::require("mmdebstrap", version, "format ext2", EXT2_FORMAT_SINCE)?;
+        }
+        let hooks = [
+            &self.setup_hook,
+            &self.extract_hook,
+            &self.essential_hook,
+            &self.customize_hook,
+        ];
+        if let Some(hook) = hooks.into_iter().flatten().find(|hook| {
+            hook.split_whitespace()
+                .next()
+                .is_some_and(|command| SPECIAL_HOOKS.contains(&command))
+        }) {
+            version::require(
+                "mmdebstrap",
+                version,
+                &format!("the special hook '{}'", hook),
+                SPECIAL_HOOKS_SINCE,
+            )?;
+        }
+        Ok(args)
+    }
+
     fn rootfs_output(&self, output_dir: &Utf8Path) -> Result<RootfsOutput> {
+        if self.streams() {
+            return Ok(RootfsOutput::NonDirectory {
+                reason: "the archive is streamed to standard output (target: \"-\")".to_string(),
+            });
+        }
         let target_path = output_dir.join(&self.target);
 
         match &self.format {
@@ -0,0 +1,180 @@
+//! Bootstrap presets for Debian derivatives.
+//!
+//! A backend's `preset` names a [`Preset`]: the mirror, keyring, suites, architectures,
+//! components and packages of a derivative such as Devuan. Profile loading fills in the
+//! backend settings the profile leaves unset from it and rejects suites and
+//! architectures the derivative does not have, so building a derivative no longer
+//! means looking all of this up.
+//!
+//! Presets are YAML files. The built-in ones ([`BUILTIN`]) are compiled in from
+//! `src/bootstrap/presets/`; `<name>.yaml` in [`user_dir`] adds a preset or replaces
+//! a built-in one of the same name.
+
+use std::fs;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Deserialize;
+
+use crate::error::RsdebstrapError;
+use crate::yaml_error::edit_distance;
+
+/// The built-in presets, by name.
+pub const BUILTIN: &[(&str, &str)] = &[
+    ("devuan", include_str!("presets/devuan.yaml")),
+    ("kali", include_str!("presets/kali.yaml")),
+    ("raspbian", include_str!("presets/raspbian.yaml")),
+];
+
+/// Settings of a Debian derivative. See the [module documentation](self).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Preset {
+    /// Mirror of the derivative's archive.
+    pub mirror: String,
+    /// Host keyring verifying the archive.
+    #[serde(default)]
+    pub keyring: Option<Utf8PathBuf>,
+    /// Suite names the archive has; empty accepts any.
+    #[serde(default)]
+    pub suites: Vec<String>,
+    /// Architectures the archive builds; empty accepts any. The first is the default
+    /// when the profile lists none and the host's is not among them.
+    #[serde(default)]
+    pub architectures: Vec<String>,
+    /// Components enabled when the profile sets none.
+    #[serde(default)]
+    pub components: Vec<String>,
+    /// Packages always installed, such as the archive keyring.
+    #[serde(default)]
+    pub include: Vec<String>,
+}
+
+impl Preset {
+    /// Checks that the preset has `suite`, suggesting the closest name it has.
+    pub fn check_suite(&self, name: &str, suite: &str) -> Result<(), RsdebstrapError> {
+        if self.suites.is_empty() || self.suites.iter().any(|s| s == suite) {
+            return Ok(());
+        }
+        let closest = self
+            .suites
+            .iter()
+            .min_by_key(|s| edit_distance(suite, s))
+            .filter(|s| edit_distance(suite, s) <= (suite.len().max(s.len()) / 3).max(1));
+        let hint = match closest {
+            Some(closest) => format!("; did you mean '{}'?", closest),
+            None => format!(" (available: {})", self.suites.join(", ")),
+        };
+        Err(RsdebstrapError::Validation(format!(
+            "bootstrap suite '{}' is not a suite of preset {}{}",
+            suite, name, hint
+        )))
+    }
+
+    /// Checks that the preset builds `arch`.
+    pub fn check_architecture(&self, name: &str, arch: &str) -> Result<(), RsdebstrapError> {
+        if self.architectures.is_empty() || self.architectures.iter().any(|a| a == arch) {
+            return Ok(());
+        }
+        Err(RsdebstrapError::Validation(format!(
+            "preset {} is not built for architecture '{}' (available: {})",
+            name,
+            arch,
+            self.architectures.join(", ")
+        )))
+    }
+}
+
+/// Returns the directory of user presets: `presets` in
+/// [`config_dir`](crate::user_config::config_dir).
+pub fn user_dir() -> Option<Utf8PathBuf> {
+    crate::user_config::config_dir().map(|dir| dir.join("presets"))
+}
+
+/// Loads the preset `name`: `<name>.yaml` in [`user_dir`] if it exists, else the
+/// built-in one.
+///
+/// # Errors
+///
+/// Returns [`RsdebstrapError::Validation`] for an unknown or malformed name, and
+/// [`RsdebstrapError::Config`] or [`RsdebstrapError::Io`] for a preset file that
+/// cannot be read or parsed.
+pub fn load(name: &str) -> Result<Preset, RsdebstrapError> {
+    load_from(name, user_dir().as_deref())
+}
+
+fn load_from(name: &str, user_dir: Option<&Utf8Path>) -> Result<Preset, RsdebstrapError> {
+    if name.is_empty()
+        || !name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+    {
+        return Err(RsdebstrapError::Validation(format!(
+            "invalid preset name '{}' (expected lowercase letters, digits and '-')",
+            name
+        )));
+    }
+    if let Some(path) = user_dir.map(|dir| dir.join(format!("{}.yaml", name)))
+        && path.is_file()
+    {
+        let text = fs::read_to_string(&path)
+            .map_err(|e| RsdebstrapError::io(format!("failed to read preset {}", path), e))?;
+        return parse(&text, path.as_str());
+    }
+    match BUILTIN.iter().find(|(builtin, _)| *builtin == name) {
+        Some((_, text)) => parse(text, name),
+        None => Err(RsdebstrapError::Validation(format!(
+            "unknown preset '{}' (built-in: {}; custom presets go in {})",
+            name,
+            BUILTIN
+                .iter()
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", "),
+            user_dir.map_or("$XDG_CONFIG_HOME/rsdebstrap/presets", Utf8Path::as_str)
+        ))),
+    }
+}
+
+fn parse(text: &str, source: &str) -> Result<Preset, RsdebstrapError> {
+    yaml_serde::from_str(text)
+        .map_err(|e| RsdebstrapError::Config(format!("invalid preset {}: {}", source, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_presets_parse() {
+        for (name, _) in BUILTIN {
+            let preset = load_from(name, None).unwrap();
+            assert!(!preset.suites.is_empty(), "{name}");
+            assert!(!preset.include.is_empty(), "{name}");
+        }
+    }
+
+    #[test]
+    fn user_presets_replace_builtin_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        fs::write(dir.join("kali.yaml"), "mirror: http://mirror.example.com/kali\n").unwrap();
+        fs::write(dir.join("pureos.yaml"), "mirror: https://repo.pureos.net/pureos\n").unwrap();
+
+        assert_eq!(load_from("kali", Some(dir)).unwrap().mirror, "http://mirror.example.com/kali");
+        assert!(load_from("pureos", Some(dir)).unwrap().suites.is_empty());
+        assert!(load_from("devuan", Some(dir)).is_ok());
+        let err = load_from("mint", Some(dir)).unwrap_err();
+        assert!(err.to_string().contains("unknown preset 'mint'"), "{err}");
+        assert!(load_from("../kali", Some(dir)).is_err());
+    }
+
+    #[test]
+    fn check_suite_suggests_the_closest_suite() {
+        let preset = load_from("devuan", None).unwrap();
+        assert!(preset.check_suite("devuan", "daedalus").is_ok());
+        let err = preset.check_suite("devuan", "daedelus").unwrap_err();
+        assert!(err.to_string().contains("did you mean 'daedalus'?"), "{err}");
+        let err = preset.check_suite("devuan", "bookworm").unwrap_err();
+        assert!(err.to_string().contains("(available: beowulf, "), "{err}");
+    }
+}
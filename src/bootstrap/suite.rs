@@ -0,0 +1,255 @@
+//! Debian release metadata for bootstrap suites.
+//!
+//! A bundled table ([`RELEASES`], current as of [`TABLE_DATE`]) maps the archive's
+//! aliases (`stable`, `testing`, …) to codenames and records each release's official
+//! architectures and support dates. [`Profile::validate`](crate::config::Profile::validate)
+//! uses it to reject a suite that is a likely typo of a release (`trixy`) and
+//! architectures the Debian archive does not carry for the release, before the backend
+//! spends a minute downloading; [`Release::support`] tells the runner when a suite is
+//! past its security support.
+//!
+//! Suites the table does not know, such as Ubuntu codenames, pass unchecked. The
+//! aliases move at every Debian release, so they resolve as of [`TABLE_DATE`].
+
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::yaml_error::edit_distance;
+
+/// When [`RELEASES`] and [`ALIASES`] were last brought up to date.
+pub const TABLE_DATE: &str = "2026-10";
+
+/// Archive serving releases whose support has ended.
+pub const ARCHIVE_MIRROR: &str = "http://archive.debian.org/debian";
+
+/// A calendar date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl Date {
+    const fn new(year: u16, month: u8, day: u8) -> Self {
+        Self { year, month, day }
+    }
+
+    /// Returns today's date (UTC).
+    pub fn today() -> Self {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Self::from_days((secs / 86_400) as i64)
+    }
+
+    /// Returns the date `days` days after 1970-01-01.
+    fn from_days(days: i64) -> Self {
+        // Howard Hinnant's civil_from_days, for eras of 400 years starting 0000-03-01.
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        Self::new(year as u16, month as u8, day as u8)
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// A Debian release.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Release {
+    pub codename: &'static str,
+    /// Major version, none for testing and unstable.
+    pub version: Option<&'static str>,
+    /// End of regular security support, none while it is not announced.
+    pub security_end: Option<Date>,
+    /// End of LTS support, none while it is not announced.
+    pub lts_end: Option<Date>,
+    /// Architectures the Debian archive builds the release for.
+    pub architectures: &'static [&'static str],
+}
+
+/// Support state of a release on a given day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Support {
+    /// Regular security support, or testing and unstable.
+    Supported,
+    /// Only LTS security support, until the date.
+    Lts(Date),
+    /// No support since the date; the release has moved to [`ARCHIVE_MIRROR`].
+    EndOfLife(Date),
+}
+
+impl Release {
+    /// Returns the release's support state on `today`.
+    pub fn support(&self, today: Date) -> Support {
+        match (self.security_end, self.lts_end) {
+            (_, Some(lts_end)) if today > lts_end => Support::EndOfLife(lts_end),
+            (Some(security_end), Some(lts_end)) if today > security_end => Support::Lts(lts_end),
+            (Some(security_end), None) if today > security_end => Support::EndOfLife(security_end),
+            _ => Support::Supported,
+        }
+    }
+}
+
+const ARCHES_JESSIE: &[&str] = &[
+    "amd64", "arm64", "armel", "armhf", "i386", "mips", "mipsel", "powerpc", "ppc64el", "s390x",
+];
+const ARCHES_STRETCH: &[&str] = &[
+    "amd64", "arm64", "armel", "armhf", "i386", "mips", "mips64el", "mipsel", "ppc64el", "s390x",
+];
+const ARCHES_BULLSEYE: &[&str] = &[
+    "amd64", "arm64", "armel", "armhf", "i386", "mips64el", "mipsel", "ppc64el", "s390x",
+];
+const ARCHES_TRIXIE: &[&str] = &[
+    "amd64", "arm64", "armel", "armhf", "i386", "ppc64el", "riscv64", "s390x",
+];
+const ARCHES_FORKY: &[&str] = &[
+    "amd64", "arm64", "armhf", "i386", "loong64", "ppc64el", "riscv64", "s390x",
+];
+
+/// Debian releases, oldest first.
+pub const RELEASES: &[Release] = &[
+    Release {
+        codename: "jessie",
+        version: Some("8"),
+        security_end: Some(Date::new(2018, 6, 17)),
+        lts_end: Some(Date::new(2020, 6, 30)),
+        architectures: ARCHES_JESSIE,
+    },
+    Release {
+        codename: "stretch",
+        version: Some("9"),
+        security_end: Some(Date::new(2020, 7, 6)),
+        lts_end: Some(Date::new(2022, 6, 30)),
+        architectures: ARCHES_STRETCH,
+    },
+    Release {
+        codename: "buster",
+        version: Some("10"),
+        security_end: Some(Date::new(2022, 9, 10)),
+        lts_end: Some(Date::new(2024, 6, 30)),
+        architectures: ARCHES_STRETCH,
+    },
+    Release {
+        codename: "bullseye",
+        version: Some("11"),
+        security_end: Some(Date::new(2024, 8, 14)),
+        lts_end: Some(Date::new(2026, 8, 31)),
+        architectures: ARCHES_BULLSEYE,
+    },
+    Release {
+        codename: "bookworm",
+        version: Some("12"),
+        security_end: Some(Date::new(2026, 6, 10)),
+        lts_end: Some(Date::new(2028, 6, 30)),
+        architectures: ARCHES_BULLSEYE,
+    },
+    Release {
+        codename: "trixie",
+        version: Some("13"),
+        security_end: Some(Date::new(2028, 8, 9)),
+        lts_end: Some(Date::new(2030, 6, 30)),
+        architectures: ARCHES_TRIXIE,
+    },
+    Release {
+        codename: "forky",
+        version: None,
+        security_end: None,
+        lts_end: None,
+        architectures: ARCHES_FORKY,
+    },
+    Release {
+        codename: "sid",
+        version: None,
+        security_end: None,
+        lts_end: None,
+        architectures: ARCHES_FORKY,
+    },
+];
+
+/// Archive aliases and the codenames they stand for as of [`TABLE_DATE`].
+pub const ALIASES: &[(&str, &str)] = &[
+    ("oldoldstable", "bullseye"),
+    ("oldstable", "bookworm"),
+    ("stable", "trixie"),
+    ("testing", "forky"),
+    ("unstable", "sid"),
+];
+
+/// Returns the release `suite` names, by codename or alias.
+pub fn resolve(suite: &str) -> Option<&'static Release> {
+    let codename = ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == suite)
+        .map_or(suite, |(_, codename)| codename);
+    RELEASES.iter().find(|release| release.codename == codename)
+}
+
+/// Returns the codename or alias closest to `suite`, if `suite` is not a known suite
+/// but close enough to one to be a likely typo.
+pub fn suggest(suite: &str) -> Option<&'static str> {
+    if resolve(suite).is_some() {
+        return None;
+    }
+    RELEASES
+        .iter()
+        .map(|release| release.codename)
+        .chain(ALIASES.iter().map(|(alias, _)| *alias))
+        .map(|name| (edit_distance(suite, name), name))
+        .filter(|&(distance, name)| distance <= (suite.len().max(name.len()) / 3).max(1))
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, name)| name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_maps_aliases_to_codenames() {
+        assert_eq!(resolve("stable").map(|r| r.codename), Some("trixie"));
+        assert_eq!(resolve("unstable").map(|r| r.codename), Some("sid"));
+        assert_eq!(resolve("bookworm").and_then(|r| r.version), Some("12"));
+        assert_eq!(resolve("noble"), None);
+    }
+
+    #[test]
+    fn suggest_names_close_releases_only() {
+        assert_eq!(suggest("trixy"), Some("trixie"));
+        assert_eq!(suggest("bookwrom"), Some("bookworm"));
+        assert_eq!(suggest("stabel"), Some("stable"));
+        assert_eq!(suggest("trixie"), None);
+        assert_eq!(suggest("noble"), None);
+        assert_eq!(suggest("jammy"), None);
+    }
+
+    #[test]
+    fn support_follows_the_release_dates() {
+        let buster = resolve("buster").unwrap();
+        assert_eq!(buster.support(Date::new(2021, 1, 1)), Support::Supported);
+        assert_eq!(buster.support(Date::new(2023, 1, 1)), Support::Lts(Date::new(2024, 6, 30)));
+        assert_eq!(
+            buster.support(Date::new(2024, 7, 1)),
+            Support::EndOfLife(Date::new(2024, 6, 30))
+        );
+        assert_eq!(resolve("sid").unwrap().support(Date::new(2040, 1, 1)), Support::Supported);
+    }
+
+    #[test]
+    fn date_from_days_is_the_civil_date() {
+        assert_eq!(Date::from_days(0), Date::new(1970, 1, 1));
+        assert_eq!(Date::from_days(20_744), Date::new(2026, 10, 18));
+        assert_eq!(Date::from_days(11_016).to_string(), "2000-02-29");
+    }
+}
@@ -0,0 +1,231 @@
+//! Bootstrap tool versions.
+//!
+//! Hosts run very different releases of mmdebstrap and debootstrap, and a flag a
+//! newer release accepts can make an older one abort. Before the bootstrap, `apply`
+//! runs `<backend> --version` ([`detect`]), records the result in the build's
+//! provenance, enforces the profile's optional `min_version` ([`check_min_version`])
+//! and lets the backend adapt its arguments to the release found
+//! ([`BootstrapBackend::adapt_args`](super::BootstrapBackend::adapt_args)).
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::error::RsdebstrapError;
+
+/// A dotted numeric version such as `1.4.3`, compared component by component with
+/// missing components counting as 0 (`1.4` equals `1.4.0`).
+#[derive(Debug, Clone, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ToolVersion(Vec<u64>);
+
+impl ToolVersion {
+    /// Returns the version of the first version-like word of `line`, such as the
+    /// first line of `mmdebstrap --version` (`mmdebstrap 1.4.3`). Packaging suffixes
+    /// (`1.0.134+deb12u1`) are ignored.
+    pub fn parse_output(line: &str) -> Option<Self> {
+        let word = line
+            .split_whitespace()
+            .find(|w| w.starts_with(|c: char| c.is_ascii_digit()))?;
+        let parts: Vec<u64> = word
+            .split(['.', '-', '+', '~'])
+            .map_while(|part| part.parse().ok())
+            .collect();
+        (!parts.is_empty()).then_some(Self(parts))
+    }
+
+    /// Returns the components without trailing zeros, the form versions compare in.
+    fn significant(&self) -> &[u64] {
+        let len = self
+            .0
+            .iter()
+            .rposition(|&part| part != 0)
+            .map_or(0, |i| i + 1);
+        &self.0[..len]
+    }
+}
+
+impl PartialEq for ToolVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.significant() == other.significant()
+    }
+}
+
+impl Ord for ToolVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.significant().cmp(other.significant())
+    }
+}
+
+impl PartialOrd for ToolVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl FromStr for ToolVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim()
+            .split('.')
+            .map(|part| part.parse::<u64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map(Self)
+            .map_err(|_| format!("invalid version '{}': expected numbers such as 1.4.3", s))
+    }
+}
+
+impl TryFrom<String> for ToolVersion {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for ToolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<_> = self.0.iter().map(u64::to_string).collect();
+        f.write_str(&parts.join("."))
+    }
+}
+
+impl From<ToolVersion> for String {
+    fn from(version: ToolVersion) -> Self {
+        version.to_string()
+    }
+}
+
+/// Runs `command --version` and returns the first line it prints, from stdout or,
+/// if that is empty, stderr.
+pub fn version_line(command: &str) -> Option<String> {
+    let output = std::process::Command::new(command)
+        .arg("--version")
+        .stdin(std::process::Stdio::null())
+        .output()
+        .ok()?;
+    // debootstrap prints its version to stdout, some tools to stderr.
+    let text = if output.stdout.is_empty() {
+        output.stderr
+    } else {
+        output.stdout
+    };
+    let text = String::from_utf8_lossy(&text);
+    text.lines().next().map(|line| line.trim().to_string())
+}
+
+/// Returns the installed version of `command`, or `None` if it is not installed or
+/// prints no recognizable version.
+pub fn detect(command: &str) -> Option<ToolVersion> {
+    let found = version_line(command).and_then(|line| ToolVersion::parse_output(&line));
+    match &found {
+        Some(version) => info!("using {} {}", command, version),
+        None => warn!("could not determine the version of {}", command),
+    }
+    found
+}
+
+/// Returns whether `version` is older than the release `since`.
+pub(crate) fn predates(version: &ToolVersion, since: &str) -> bool {
+    *version
+        < since
+            .parse::<ToolVersion>()
+            .expect("feature versions are valid")
+}
+
+/// Returns an error if `version` of `command` predates `since`, the first release
+/// supporting `feature`.
+pub(crate) fn require(
+    command: &str,
+    version: &ToolVersion,
+    feature: &str,
+    since: &str,
+) -> Result<(), RsdebstrapError> {
+    if !predates(version, since) {
+        return Ok(());
+    }
+    Err(RsdebstrapError::BackendVersion(format!(
+        "{} needs {} {} or newer, found {}",
+        feature, command, since, version
+    )))
+}
+
+/// Checks the `found` version of `command` against the profile's `min_version`.
+///
+/// In dry-run mode a failed check is only warned about.
+///
+/// # Errors
+///
+/// Returns [`RsdebstrapError::BackendVersion`] if `found` is older than `min_version`, or
+/// unknown while a `min_version` is set.
+pub fn check_min_version(
+    command: &str,
+    found: Option<&ToolVersion>,
+    min_version: Option<&ToolVersion>,
+    dry_run: bool,
+) -> Result<(), RsdebstrapError> {
+    let Some(min_version) = min_version else {
+        return Ok(());
+    };
+    let message = match found {
+        Some(found) if found >= min_version => return Ok(()),
+        Some(found) => {
+            format!("{} {} is older than the profile's min_version {}", command, found, min_version)
+        }
+        None => format!(
+            "could not determine the version of {} to check the profile's min_version {}",
+            command, min_version
+        ),
+    };
+    if dry_run {
+        warn!("{}", message);
+        return Ok(());
+    }
+    Err(RsdebstrapError::BackendVersion(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(s: &str) -> ToolVersion {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn versions_compare_with_missing_components_as_zero() {
+        assert_eq!(v("1.4"), v("1.4.0"));
+        assert!(v("1.4") < v("1.4.1"));
+        assert!(v("1.10") > v("1.9.9"));
+        assert!(v("0.8.4") < v("1.0"));
+        assert_eq!(v("1.4.0").to_string(), "1.4.0");
+        for invalid in ["", "1.", "v1.2", "1.2-3"] {
+            assert!(invalid.parse::<ToolVersion>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn versions_are_parsed_from_tool_output() {
+        assert_eq!(ToolVersion::parse_output("mmdebstrap 1.4.3"), Some(v("1.4.3")));
+        assert_eq!(ToolVersion::parse_output("debootstrap 1.0.134+deb12u1"), Some(v("1.0.134")));
+        assert_eq!(ToolVersion::parse_output("no version here"), None);
+    }
+
+    #[test]
+    fn min_version_is_enforced_outside_dry_runs() {
+        let min = v("1.0");
+        check_min_version("mmdebstrap", Some(&v("1.4.3")), Some(&min), false).unwrap();
+        check_min_version("mmdebstrap", None, None, false).unwrap();
+        let err =
+            check_min_version("mmdebstrap", Some(&v("0.8.4")), Some(&min), false).unwrap_err();
+        assert!(matches!(err, RsdebstrapError::BackendVersion(_)), "{err}");
+        let err = err.to_string();
+        assert!(err.contains("0.8.4 is older than the profile's min_version 1.0"), "{err}");
+        assert!(check_min_version("mmdebstrap", None, Some(&min), false).is_err());
+        check_min_version("mmdebstrap", Some(&v("0.8.4")), Some(&min), true).unwrap();
+    }
+}
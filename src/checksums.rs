@@ -0,0 +1,435 @@
+//! Checksums and signatures of the build's artifacts.
+//!
+//! A profile's `checksums` section makes `apply` finish by hashing the artifacts it
+//! produced — the bootstrap output when it is an archive or image, or the files
+//! listed in `artifacts` — into `SHA256SUMS`/`SHA512SUMS` in the profile's `dir`,
+//! in the `sha256sum`/`sha512sum` format, so `sha256sum -c SHA256SUMS` run from
+//! `dir` verifies them. The sums files can then be signed with `gpg` or `cosign`.
+//!
+//! This runs after the assemble phase rather than as an assemble task: an archive
+//! output has no pipeline, and assemble tasks only see the rootfs directory.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+
+use anyhow::Result;
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+use tracing::info;
+
+use crate::error::RsdebstrapError;
+use crate::executor::{CommandExecutor, CommandSpec};
+
+/// Hash algorithm of a sums file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumAlgorithm {
+    /// SHA-256, written to `SHA256SUMS`.
+    Sha256,
+    /// SHA-512, written to `SHA512SUMS`.
+    Sha512,
+}
+
+impl ChecksumAlgorithm {
+    /// Returns the name of the sums file for this algorithm.
+    pub fn sums_file(self) -> &'static str {
+        match self {
+            Self::Sha256 => "SHA256SUMS",
+            Self::Sha512 => "SHA512SUMS",
+        }
+    }
+
+    /// Returns the lowercase hex digest of everything `reader` yields.
+    fn digest(self, reader: impl Read) -> io::Result<String> {
+        fn hash<D: Digest + io::Write>(mut hasher: D, mut reader: impl Read) -> io::Result<String> {
+            io::copy(&mut reader, &mut hasher)?;
+            Ok(hasher
+                .finalize()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect())
+        }
+        match self {
+            Self::Sha256 => hash(Sha256::new(), reader),
+            Self::Sha512 => hash(Sha512::new(), reader),
+        }
+    }
+}
+
+impl fmt::Display for ChecksumAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+        })
+    }
+}
+
+fn default_algorithms() -> Vec<ChecksumAlgorithm> {
+    vec![ChecksumAlgorithm::Sha256]
+}
+
+/// Tool that signs the sums files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum SignMethod {
+    /// `gpg --detach-sign --armor`, writing `<sums file>.asc`.
+    Gpg,
+    /// `cosign sign-blob`, writing `<sums file>.sig`.
+    Cosign,
+}
+
+impl fmt::Display for SignMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Gpg => "gpg",
+            Self::Cosign => "cosign",
+        })
+    }
+}
+
+/// How to sign the sums files.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct SignConfig {
+    /// Signing tool.
+    pub method: SignMethod,
+    /// Signing key: a gpg key ID or user ID (default: gpg's default key), or a cosign
+    /// key reference such as a key file or KMS URI (required for cosign).
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub key: Option<String>,
+}
+
+impl SignConfig {
+    /// Returns the signature file written for `sums`.
+    pub fn signature_path(&self, sums: &Utf8Path) -> Utf8PathBuf {
+        let extension = match self.method {
+            SignMethod::Gpg => "asc",
+            SignMethod::Cosign => "sig",
+        };
+        Utf8PathBuf::from(format!("{}.{}", sums, extension))
+    }
+
+    /// Returns the command signing `sums`.
+    fn command(&self, sums: &Utf8Path) -> CommandSpec {
+        let signature = self.signature_path(sums).into_string();
+        let args = match self.method {
+            SignMethod::Gpg => {
+                let mut args = vec!["--batch", "--yes", "--armor", "--detach-sign"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect::<Vec<_>>();
+                if let Some(key) = &self.key {
+                    args.extend(["--local-user".to_string(), key.clone()]);
+                }
+                args.extend(["--output".to_string(), signature, sums.to_string()]);
+                args
+            }
+            SignMethod::Cosign => vec![
+                "sign-blob".to_string(),
+                "--yes".to_string(),
+                "--key".to_string(),
+                self.key.clone().unwrap_or_default(),
+                "--output-signature".to_string(),
+                signature,
+                sums.to_string(),
+            ],
+        };
+        CommandSpec::new(self.method.to_string(), args)
+    }
+}
+
+/// Validates that `artifact`, listed in the profile's `section`, is a plain relative
+/// path inside `dir`.
+pub(crate) fn validate_artifact_path(
+    artifact: &Utf8Path,
+    section: &str,
+) -> Result<(), RsdebstrapError> {
+    let plain = artifact
+        .components()
+        .all(|c| matches!(c, Utf8Component::Normal(_)));
+    if artifact.as_str().is_empty() || !plain || artifact.as_str().contains('\n') {
+        return Err(RsdebstrapError::Validation(format!(
+            "{} artifact {:?} must be a relative path inside dir \
+            (no '..', '.', leading '/' or newline)",
+            section, artifact
+        )));
+    }
+    Ok(())
+}
+
+/// Configuration for checksumming (and optionally signing) the build's artifacts.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct ChecksumConfig {
+    /// Hash algorithms; each writes its own sums file (default: `[sha256]`).
+    #[serde(default = "default_algorithms")]
+    pub algorithms: Vec<ChecksumAlgorithm>,
+    /// Artifact paths relative to the profile's `dir` (default: the bootstrap output,
+    /// which must then be an archive or image rather than a directory).
+    #[serde(
+        default,
+        deserialize_with = "crate::de::path_list",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<String>>"))]
+    pub artifacts: Vec<Utf8PathBuf>,
+    /// Sign the sums files (optional).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sign: Option<SignConfig>,
+}
+
+impl ChecksumConfig {
+    /// Validates the checksum configuration.
+    ///
+    /// `default_artifact` is the bootstrap output relative to `dir` when it is not a
+    /// directory; without it, `artifacts` must be listed.
+    pub fn validate(&self, default_artifact: Option<&Utf8Path>) -> Result<(), RsdebstrapError> {
+        if self.algorithms.is_empty() {
+            return Err(RsdebstrapError::Validation(
+                "checksums.algorithms must not be empty".to_string(),
+            ));
+        }
+        for (index, algorithm) in self.algorithms.iter().enumerate() {
+            if self.algorithms[..index].contains(algorithm) {
+                return Err(RsdebstrapError::Validation(format!(
+                    "checksums.algorithms lists {} more than once",
+                    algorithm
+                )));
+            }
+        }
+        if self.artifacts.is_empty() && default_artifact.is_none() {
+            return Err(RsdebstrapError::Validation(
+                "checksums.artifacts must be set when the bootstrap output is a directory"
+                    .to_string(),
+            ));
+        }
+        for artifact in &self.artifacts {
+            validate_artifact_path(artifact, "checksums")?;
+        }
+        if let Some(sign) = &self.sign {
+            if sign.method == SignMethod::Cosign && sign.key.is_none() {
+                return Err(RsdebstrapError::Validation(
+                    "checksums.sign: cosign requires 'key'".to_string(),
+                ));
+            }
+            if sign.key.as_deref().is_some_and(|k| k.trim().is_empty()) {
+                return Err(RsdebstrapError::Validation(
+                    "checksums.sign.key must not be empty".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the artifacts to hash, relative to `dir`.
+    pub fn artifacts<'a>(&'a self, default_artifact: Option<&'a Utf8Path>) -> Vec<&'a Utf8Path> {
+        if self.artifacts.is_empty() {
+            default_artifact.into_iter().collect()
+        } else {
+            self.artifacts.iter().map(Utf8PathBuf::as_path).collect()
+        }
+    }
+
+    /// Writes a sums file per algorithm into `dir` and signs each one if configured.
+    ///
+    /// Hashing runs in-process; signing goes through `executor` without privilege
+    /// escalation, so the user's own keys are used. In dry-run mode nothing is read
+    /// or written, and the signing commands are only handed to the (dry-run) executor.
+    pub fn write(
+        &self,
+        dir: &Utf8Path,
+        default_artifact: Option<&Utf8Path>,
+        executor: &dyn CommandExecutor,
+        dry_run: bool,
+    ) -> Result<()> {
+        let artifacts = self.artifacts(default_artifact);
+        for algorithm in &self.algorithms {
+            let sums = dir.join(algorithm.sums_file());
+            if dry_run {
+                info!("would write {} for {} artifact(s)", sums, artifacts.len());
+            } else {
+                let mut content = String::new();
+                for artifact in &artifacts {
+                    let path = dir.join(artifact);
+                    let file = File::open(&path).map_err(|e| {
+                        RsdebstrapError::io(format!("failed to open artifact {}", path), e)
+                    })?;
+                    let digest = algorithm.digest(file).map_err(|e| {
+                        RsdebstrapError::io(format!("failed to read artifact {}", path), e)
+                    })?;
+                    content.push_str(&format!("{}  {}\n", digest, artifact));
+                }
+                fs::write(&sums, content)
+                    .map_err(|e| RsdebstrapError::io(format!("failed to write {}", sums), e))?;
+                info!("wrote {} for {} artifact(s)", sums, artifacts.len());
+            }
+            if let Some(sign) = &self.sign {
+                executor.execute_checked(&sign.command(&sums))?;
+                if !dry_run {
+                    info!("signed {} with {}", sums, sign.method);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::ExecutionResult;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct RecordingExecutor {
+        commands: Mutex<Vec<Vec<String>>>,
+    }
+
+    impl CommandExecutor for RecordingExecutor {
+        fn execute(&self, spec: &CommandSpec) -> Result<ExecutionResult> {
+            let mut command = vec![spec.command.clone()];
+            command.extend(spec.args.iter().cloned());
+            self.commands.lock().unwrap().push(command);
+            Ok(ExecutionResult {
+                status: None,
+                details: None,
+            })
+        }
+    }
+
+    fn config(yaml: &str) -> ChecksumConfig {
+        yaml_serde::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn digests_match_known_values() {
+        assert_eq!(
+            ChecksumAlgorithm::Sha256.digest(&b"abc"[..]).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert!(
+            ChecksumAlgorithm::Sha512
+                .digest(&b"abc"[..])
+                .unwrap()
+                .starts_with("ddaf35a193617abacc417349ae204131")
+        );
+    }
+
+    #[test]
+    fn validate_requires_artifacts_for_directory_output() {
+        let err = config("{}").validate(None).unwrap_err();
+        assert!(err.to_string().contains("must be set"), "{err}");
+        config("{}")
+            .validate(Some(Utf8Path::new("rootfs.tar.zst")))
+            .unwrap();
+    }
+
+    #[test]
+    fn validate_rejects_bad_settings() {
+        for (yaml, expected) in [
+            ("{artifacts: [../escape.img]}", "relative path inside dir"),
+            ("{artifacts: [/abs.img]}", "relative path inside dir"),
+            ("{algorithms: [], artifacts: [a.img]}", "must not be empty"),
+            ("{algorithms: [sha256, sha256], artifacts: [a.img]}", "more than once"),
+            ("{artifacts: [a.img], sign: {method: cosign}}", "requires 'key'"),
+            ("{artifacts: [a.img], sign: {method: gpg, key: ' '}}", "must not be empty"),
+        ] {
+            let err = config(yaml).validate(None).unwrap_err();
+            assert!(err.to_string().contains(expected), "{yaml}: {err}");
+        }
+    }
+
+    #[test]
+    fn write_creates_sums_files_and_signs_them() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = Utf8PathBuf::from_path_buf(temp.path().to_path_buf()).unwrap();
+        fs::create_dir(dir.join("out")).unwrap();
+        fs::write(dir.join("rootfs.tar"), "abc").unwrap();
+        fs::write(dir.join("out/disk.img"), "").unwrap();
+        let config = config(
+            "{algorithms: [sha256, sha512], artifacts: [rootfs.tar, out/disk.img], \
+            sign: {method: gpg, key: release@example.org}}",
+        );
+        let executor = RecordingExecutor::default();
+
+        config.write(&dir, None, &executor, false).unwrap();
+
+        let sums = fs::read_to_string(dir.join("SHA256SUMS")).unwrap();
+        assert_eq!(
+            sums,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad  rootfs.tar\n\
+            e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855  out/disk.img\n"
+        );
+        assert!(dir.join("SHA512SUMS").exists());
+        let commands = executor.commands.lock().unwrap();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(
+            commands[0].join(" "),
+            format!(
+                "gpg --batch --yes --armor --detach-sign --local-user release@example.org \
+                --output {dir}/SHA256SUMS.asc {dir}/SHA256SUMS"
+            )
+        );
+    }
+
+    #[test]
+    fn write_defaults_to_the_bootstrap_output() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = Utf8PathBuf::from_path_buf(temp.path().to_path_buf()).unwrap();
+        fs::write(dir.join("rootfs.tar.zst"), "abc").unwrap();
+        let executor = RecordingExecutor::default();
+
+        config("{}")
+            .write(&dir, Some(Utf8Path::new("rootfs.tar.zst")), &executor, false)
+            .unwrap();
+
+        let sums = fs::read_to_string(dir.join("SHA256SUMS")).unwrap();
+        assert!(sums.ends_with("  rootfs.tar.zst\n"), "{sums}");
+        assert!(executor.commands.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn write_dry_run_writes_nothing_but_hands_over_the_signing_command() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = Utf8PathBuf::from_path_buf(temp.path().to_path_buf()).unwrap();
+        let config = config("{artifacts: [missing.img], sign: {method: cosign, key: cosign.key}}");
+        let executor = RecordingExecutor::default();
+
+        config.write(&dir, None, &executor, true).unwrap();
+
+        assert!(!dir.join("SHA256SUMS").exists());
+        let commands = executor.commands.lock().unwrap();
+        assert_eq!(
+            commands[0].join(" "),
+            format!(
+                "cosign sign-blob --yes --key cosign.key --output-signature \
+                {dir}/SHA256SUMS.sig {dir}/SHA256SUMS"
+            )
+        );
+    }
+
+    #[test]
+    fn write_reports_a_missing_artifact() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = Utf8PathBuf::from_path_buf(temp.path().to_path_buf()).unwrap();
+
+        let err = config("{artifacts: [missing.img]}")
+            .write(&dir, None, &RecordingExecutor::default(), false)
+            .unwrap_err();
+
+        assert!(format!("{err:#}").contains("missing.img"), "{err:#}");
+    }
+}
@@ -10,6 +10,10 @@ use camino::Utf8PathBuf;
 use clap::{Args, Parser, Subcommand, ValueEnum, ValueHint};
 use clap_complete::Shell;
 
+use crate::config::{ProfileSet, UnknownFields};
+use crate::metrics::MetricsFormat;
+use crate::pipeline::{PhaseSelection, TagFilter};
+
 /// Top-level CLI structure that serves as the entry point for parsing command-line arguments.
 ///
 /// This struct represents the entire command-line interface for the application.
@@ -38,7 +42,7 @@ pub enum Commands {
     /// This command executes the configured backend (mmdebstrap, debootstrap, etc.).
     /// It reads the YAML profile, converts it to backend-specific arguments, and
     /// executes the command.
-    Apply(ApplyArgs),
+    Apply(Box<ApplyArgs>),
 
     /// Validate the given YAML profile.
     ///
@@ -47,6 +51,64 @@ pub enum Commands {
     /// is valid before attempting to apply it.
     Validate(ValidateArgs),
 
+    /// Report how an existing rootfs has drifted from the YAML profile.
+    ///
+    /// This command inspects the rootfs an earlier `apply` built, without changing it,
+    /// and lists `include` packages that are not installed, an `/etc/resolv.conf` that
+    /// differs from the assemble `resolv_conf` task, missing `prepare.mount` mount
+    /// points, and provision tasks whose script changed since `apply` last ran them.
+    Diff(DiffArgs),
+
+    /// Check the host for the tools and kernel features builds need.
+    ///
+    /// Looks for the bootstrap backends and their versions, a privilege escalation
+    /// tool, `tar`, qemu binfmt handlers when cross-building, user namespaces,
+    /// overlayfs and free disk space, and prints each check as pass, warn or fail with
+    /// an explanation. With `--file`, the checks follow that profile. Exits non-zero if
+    /// any check fails.
+    ///
+    /// ```sh
+    /// rsdebstrap doctor -f profile.yml
+    /// ```
+    Doctor(DoctorArgs),
+
+    /// Generate a starter YAML profile.
+    ///
+    /// Writes a minimal profile for the chosen backend, suite, mirrors, and output
+    /// directory, optionally with an example provision task. Choices come from flags,
+    /// or from questions on the terminal with `--interactive`. The generated profile
+    /// is validated before it is written, and an existing file is never overwritten
+    /// unless `--force` is given.
+    ///
+    /// ```sh
+    /// rsdebstrap init --backend debootstrap --suite bookworm -f bookworm.yml
+    /// ```
+    Init(InitArgs),
+
+    /// Convert a profile written for an older format to the current one.
+    ///
+    /// Rewrites the legacy `pre_processors`/`provisioners`/`post_processors` lists as
+    /// the `prepare`/`provision`/`assemble` phases and declares the current
+    /// `schema_version`, keeping comments where the lists are in block style. The
+    /// result is printed, or replaces the file with `--in-place`.
+    ///
+    /// ```sh
+    /// rsdebstrap migrate -f old.yml --in-place
+    /// ```
+    Migrate(MigrateArgs),
+
+    /// Run a build server with a JSON-RPC API on a Unix socket.
+    ///
+    /// The server accepts JSON-RPC 2.0 requests, one per line: `validate` a profile,
+    /// queue an `apply`, and follow jobs with `status`, `logs` and `cancel`. Jobs start
+    /// in submission order, up to `--max-builds` at once. Profile paths are paths on the
+    /// server.
+    ///
+    /// ```sh
+    /// rsdebstrap serve --socket /run/rsdebstrap.sock
+    /// ```
+    Serve(ServeArgs),
+
     /// Generate shell completion scripts.
     ///
     /// This command generates completion scripts for various shells.
@@ -71,6 +133,17 @@ pub enum Commands {
     /// ```
     Completions(CompletionsArgs),
 
+    /// Generate a man page in roff format.
+    ///
+    /// The page is rendered from the same clap definitions that parse the command
+    /// line, so it always documents the options the binary actually accepts.
+    /// Redirect it to a file to install it:
+    ///
+    /// ```sh
+    /// rsdebstrap man > /usr/local/share/man/man1/rsdebstrap.1
+    /// ```
+    Man,
+
     /// Print the JSON Schema for the YAML profile format.
     ///
     /// The schema is generated directly from the Rust configuration types, so it always
@@ -82,19 +155,36 @@ pub enum Commands {
     /// ```
     #[cfg(feature = "schema")]
     Schema,
+
+    /// Run a command in the `defaults.sandbox` restrictions (internal).
+    ///
+    /// Tasks with `isolation: false` run their commands through this when the profile
+    /// sets `defaults.sandbox`; it is not meant to be run by hand.
+    #[command(name = "sandbox-exec", hide = true)]
+    SandboxExec(SandboxExecArgs),
+
+    /// Run a command with a rootfs as the root of a new mount namespace (internal).
+    ///
+    /// Tasks with `pivot_root` isolation run their commands through this; it is not
+    /// meant to be run by hand.
+    #[command(name = "pivot-exec", hide = true)]
+    PivotExec(PivotExecArgs),
 }
 
 /// Common arguments shared across multiple commands.
 ///
-/// This struct defines arguments that are common to commands like `Apply` and `Validate`,
-/// including the profile file path and log level.
+/// This struct defines arguments that are common to commands like `Apply`, `Validate`,
+/// `Diff` and `Init`, including the profile file path and log level.
 #[derive(Args, Debug)]
 pub struct CommonArgs {
     /// Path to the YAML file defining the profile.
     ///
     /// This file should contain a valid rsdebstrap profile. It is used
-    /// by the `apply` command to configure and execute a bootstrap, and by
-    /// the `validate` command to check for syntax and schema correctness.
+    /// by the `apply` command to configure and execute a bootstrap, by the
+    /// `validate` command to check for syntax and schema correctness, by the
+    /// `diff` command to compare against the built rootfs, by the `migrate`
+    /// command as the profile to convert, and is the output path of the `init`
+    /// command.
     #[arg(short, long, default_value = "profile.yml", value_hint = ValueHint::FilePath)]
     pub file: Utf8PathBuf,
 
@@ -102,8 +192,61 @@ pub struct CommonArgs {
     ///
     /// This determines the amount of information logged during execution.
     /// Options range from `trace` (most verbose) to `error` (least verbose).
-    #[arg(short, long, default_value = "info")]
+    #[arg(short, long, env = "RSDEBSTRAP_LOG_LEVEL", default_value = "info")]
     pub log_level: LogLevel,
+
+    /// Ignore the user configuration file (`~/.config/rsdebstrap/config.yaml`).
+    ///
+    /// Without this flag, its privilege method, mirrors, download cache and log
+    /// format fill in what the profile leaves unset.
+    #[arg(long)]
+    pub no_user_config: bool,
+}
+
+/// The `--strict`/`--no-strict` switch of commands that load a profile.
+#[derive(Args, Debug, Default)]
+pub struct StrictArgs {
+    /// Reject profiles with fields rsdebstrap does not know (the default).
+    #[arg(long, overrides_with = "no_strict")]
+    pub strict: bool,
+
+    /// Warn about fields rsdebstrap does not know and ignore them.
+    ///
+    /// Lets a profile written for a newer rsdebstrap, or with a typo'd key, load
+    /// anyway. A profile whose `schema_version` is newer than supported is still
+    /// rejected.
+    #[arg(long, overrides_with = "strict")]
+    pub no_strict: bool,
+}
+
+impl StrictArgs {
+    /// Returns how the profile loader should treat unknown fields.
+    pub fn unknown_fields(&self) -> UnknownFields {
+        if self.no_strict {
+            UnknownFields::Warn
+        } else {
+            UnknownFields::Deny
+        }
+    }
+}
+
+/// The `--arch` selector and `--set` overrides of commands that load a profile.
+#[derive(Args, Debug, Default)]
+pub struct TargetArgs {
+    /// Use the profile's `targets` entry for this architecture (e.g. `arm64`).
+    ///
+    /// The bootstrap builds that architecture alone, and the entry's `dir`,
+    /// `mirrors`, `include` and `mitamae_binary` replace the shared settings.
+    #[arg(long, value_name = "ARCH")]
+    pub arch: Option<String>,
+
+    /// Set a profile field by dotted path, e.g. `--set bootstrap.suite=trixie`.
+    ///
+    /// The value is YAML (`--set 'bootstrap.include=[vim, curl]'`); list items are
+    /// addressed as `provision[0].content`. Applied in order after the `targets`
+    /// entry and the `RSDEBSTRAP_*` variables, before validation. Repeatable.
+    #[arg(long = "set", value_name = "PATH=VALUE")]
+    pub set: Vec<ProfileSet>,
 }
 
 /// Arguments for the `Apply` command.
@@ -115,6 +258,12 @@ pub struct ApplyArgs {
     #[command(flatten)]
     pub common: CommonArgs,
 
+    #[command(flatten)]
+    pub strictness: StrictArgs,
+
+    #[command(flatten)]
+    pub target: TargetArgs,
+
     /// Do not run the actual bootstrap command, just show what would be done.
     ///
     /// When this flag is enabled, the application will parse the profile and
@@ -122,6 +271,246 @@ pub struct ApplyArgs {
     /// will display the command that would be executed.
     #[arg(long)]
     pub dry_run: bool,
+
+    /// With `--dry-run`, print the ordered execution plan and exit.
+    ///
+    /// The plan lists the bootstrap command (URL credentials masked), the pipeline
+    /// mounts, the resolv.conf handling and each selected task with its resolved
+    /// isolation and privilege.
+    #[arg(long, requires = "dry_run")]
+    pub plan: bool,
+
+    /// Unmount filesystems left mounted under the rootfs by a previous run.
+    ///
+    /// Before mounting anything, the pipeline looks for mounts below the rootfs
+    /// (e.g. after a crashed run). Without this flag they are only reported.
+    #[arg(long)]
+    pub clean_stale_mounts: bool,
+
+    /// Build into an existing, non-empty `dir` not created by rsdebstrap.
+    ///
+    /// Overrides `dir_policy: owned` for this run. The directory is not marked as
+    /// created by rsdebstrap.
+    #[arg(long)]
+    pub allow_existing: bool,
+
+    /// Wait for another build using the same `dir` instead of failing.
+    ///
+    /// `apply` locks `<dir>/.rsdebstrap-dir.lock` for the whole run. By default a
+    /// second `apply` on the same `dir` fails at once; with this flag it waits its turn.
+    #[arg(long)]
+    pub wait_for_lock: bool,
+
+    /// Run only the given phases (repeatable or comma-separated).
+    ///
+    /// `provision` also runs the prepare phase. Without `bootstrap`, the rootfs
+    /// must already exist from an earlier run.
+    #[arg(long, value_delimiter = ',', conflicts_with_all = ["skip", "skip_bootstrap"])]
+    pub only: Vec<ApplyPhase>,
+
+    /// Skip the given phases (repeatable or comma-separated).
+    ///
+    /// Skipping `provision` also skips the prepare phase.
+    #[arg(long, value_delimiter = ',')]
+    pub skip: Vec<ApplyPhase>,
+
+    /// Reuse the existing rootfs instead of bootstrapping it; same as `--skip bootstrap`.
+    #[arg(long)]
+    pub skip_bootstrap: bool,
+
+    /// Run only provision tasks carrying one of these tags (repeatable or comma-separated).
+    ///
+    /// Untagged tasks are skipped. Prepare and assemble are not affected.
+    #[arg(long, value_delimiter = ',')]
+    pub tags: Vec<String>,
+
+    /// Skip provision tasks carrying any of these tags (repeatable or comma-separated).
+    #[arg(long, value_delimiter = ',')]
+    pub skip_tags: Vec<String>,
+
+    /// Start the provision phase at the task with this `name`, skipping those before it.
+    ///
+    /// Combine with `--skip-bootstrap` to resume a run that failed part-way through.
+    #[arg(long, value_name = "NAME")]
+    pub start_at_task: Option<String>,
+
+    /// Git commit of the repository holding the profile, recorded in the
+    /// `assemble.release` provenance file.
+    #[arg(long, value_name = "COMMIT")]
+    pub source_commit: Option<String>,
+
+    /// Run the pipeline against an overlayfs over the rootfs, leaving it unchanged.
+    ///
+    /// The changes land in `<rootfs>.overlay/upper`, which shows what the tasks did;
+    /// remove `<rootfs>.overlay` to discard them. Later overlay runs build on them.
+    #[arg(long)]
+    pub overlay: bool,
+
+    /// Cache each provision task's changes to the rootfs in this directory.
+    ///
+    /// A later run replays the leading tasks whose definitions, and those of every task
+    /// before them, are unchanged from the cache instead of running them.
+    #[arg(long, value_name = "DIR")]
+    pub layer_cache: Option<Utf8PathBuf>,
+
+    /// Write build metrics next to the artifacts in this format; repeat for several.
+    ///
+    /// `prometheus` writes `rsdebstrap.prom` for node_exporter's textfile collector;
+    /// `otel` writes `rsdebstrap-trace.json`, an OpenTelemetry trace in the OTLP JSON
+    /// encoding. They are written whether the build succeeds or fails.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub metrics: Vec<MetricsFormat>,
+
+    /// Also write the end-of-run summary to this file as JSON.
+    ///
+    /// The summary (the time spent in the bootstrap, each phase and each task, the
+    /// rootfs's disk usage and the artifact sizes) is logged at the end of every build;
+    /// the file is written whether the build succeeds or fails.
+    #[arg(long, value_name = "PATH", conflicts_with = "remote", value_hint = ValueHint::FilePath)]
+    pub summary: Option<Utf8PathBuf>,
+
+    /// Write newline-delimited JSON events to this inherited file descriptor.
+    ///
+    /// Every build step, command, warning and error becomes one JSON object, whatever
+    /// the log level, for wrappers that need a stable interface instead of log lines.
+    #[arg(long, value_name = "FD", conflicts_with_all = ["events_file", "remote"])]
+    pub events_fd: Option<u32>,
+
+    /// Write newline-delimited JSON events to this file (see `--events-fd`).
+    #[arg(long, value_name = "PATH", conflicts_with = "remote", value_hint = ValueHint::FilePath)]
+    pub events_file: Option<Utf8PathBuf>,
+
+    /// Build on this `[user@]host` over SSH instead of locally.
+    ///
+    /// The profile's directory is copied to the builder with `rsync`, then
+    /// `rsdebstrap apply` runs there with the same options; the builder needs
+    /// `rsdebstrap` and `rsync` installed.
+    #[arg(long, value_name = "[USER@]HOST")]
+    pub remote: Option<String>,
+
+    /// Directory on the builder the profile is copied to, relative to the remote home;
+    /// `rsync --delete` makes it a copy of the profile's directory.
+    #[arg(
+        long,
+        value_name = "DIR",
+        default_value = "rsdebstrap-remote",
+        requires = "remote"
+    )]
+    pub remote_dir: Utf8PathBuf,
+}
+
+impl ApplyArgs {
+    /// Returns true if `phase` runs under the `--only`/`--skip` filters.
+    pub fn runs(&self, phase: ApplyPhase) -> bool {
+        if !self.only.is_empty() {
+            return self.only.contains(&phase);
+        }
+        !(self.skip.contains(&phase) || (self.skip_bootstrap && phase == ApplyPhase::Bootstrap))
+    }
+
+    /// Returns the pipeline stages selected by the `--only`/`--skip` filters.
+    pub fn pipeline_selection(&self) -> PhaseSelection {
+        PhaseSelection {
+            provision: self.runs(ApplyPhase::Provision),
+            assemble: self.runs(ApplyPhase::Assemble),
+            verify: self.runs(ApplyPhase::Verify),
+        }
+    }
+
+    /// Returns the arguments that run this `apply` on a remote builder for the profile
+    /// `file` there: every option except `--remote` and `--remote-dir`. `--layer-cache`
+    /// becomes [`REMOTE_LAYER_CACHE`](crate::remote::REMOTE_LAYER_CACHE) on the builder.
+    pub fn remote_args(&self, file: &str) -> Vec<String> {
+        let mut args = vec![
+            "apply".to_string(),
+            "--file".to_string(),
+            file.to_string(),
+            "--log-level".to_string(),
+            value_name(self.common.log_level),
+        ];
+        let flags = [
+            (self.dry_run, "--dry-run"),
+            (self.plan, "--plan"),
+            (self.clean_stale_mounts, "--clean-stale-mounts"),
+            (self.allow_existing, "--allow-existing"),
+            (self.wait_for_lock, "--wait-for-lock"),
+            (self.skip_bootstrap, "--skip-bootstrap"),
+            (self.overlay, "--overlay"),
+            (self.strictness.no_strict, "--no-strict"),
+            (self.common.no_user_config, "--no-user-config"),
+        ];
+        args.extend(
+            flags
+                .iter()
+                .filter(|(set, _)| *set)
+                .map(|(_, flag)| flag.to_string()),
+        );
+        for (flag, phases) in [("--only", &self.only), ("--skip", &self.skip)] {
+            for &phase in phases {
+                args.push(flag.to_string());
+                args.push(value_name(phase));
+            }
+        }
+        for (flag, tags) in [("--tags", &self.tags), ("--skip-tags", &self.skip_tags)] {
+            for tag in tags {
+                args.push(format!("{}={}", flag, tag));
+            }
+        }
+        for &format in &self.metrics {
+            args.push(format!("--metrics={}", value_name(format)));
+        }
+        for set in &self.target.set {
+            args.push(format!("--set={}", set));
+        }
+        let options = [
+            ("--arch", self.target.arch.clone()),
+            ("--start-at-task", self.start_at_task.clone()),
+            ("--source-commit", self.source_commit.clone()),
+            // The local cache's path means nothing on the builder.
+            (
+                "--layer-cache",
+                self.layer_cache
+                    .as_ref()
+                    .map(|_| crate::remote::REMOTE_LAYER_CACHE.to_string()),
+            ),
+        ];
+        for (flag, value) in options {
+            if let Some(value) = value {
+                args.push(format!("{}={}", flag, value));
+            }
+        }
+        args
+    }
+
+    /// Returns the provision task filter given by `--tags`/`--skip-tags`.
+    pub fn tag_filter(&self) -> TagFilter {
+        TagFilter {
+            include: self.tags.clone(),
+            exclude: self.skip_tags.clone(),
+        }
+    }
+}
+
+/// Returns the command-line spelling of a `ValueEnum` value.
+fn value_name(value: impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .expect("no value is skipped")
+        .get_name()
+        .to_string()
+}
+
+/// A phase of `apply` that `--only` and `--skip` can select.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ApplyPhase {
+    /// Build the rootfs with the bootstrap backend.
+    Bootstrap,
+    /// Run the prepare and provision pipeline phases.
+    Provision,
+    /// Run the assemble pipeline phase.
+    Assemble,
+    /// Run the verify pipeline phase's checks.
+    Verify,
 }
 
 /// Arguments for the `Validate` command.
@@ -132,6 +521,176 @@ pub struct ApplyArgs {
 pub struct ValidateArgs {
     #[command(flatten)]
     pub common: CommonArgs,
+
+    #[command(flatten)]
+    pub strictness: StrictArgs,
+
+    #[command(flatten)]
+    pub target: TargetArgs,
+
+    /// Print the resolved profile as YAML after validating it.
+    ///
+    /// Relative paths are shown absolute, and each task's privilege, isolation and
+    /// network settings in the form the defaults resolved them to. Credentials such
+    /// as URL passwords are masked.
+    #[arg(long)]
+    pub resolved: bool,
+
+    /// Also print non-fatal warnings about suspicious settings.
+    ///
+    /// Each warning is one line on stdout, `warning[<code>] <location>: <message>`:
+    /// long inline scripts, unnamed tasks, privileged tasks with `isolation: false`,
+    /// mirrors over plain HTTP, and third-party mirrors without a keyring. Warnings
+    /// do not make validation fail.
+    #[arg(long, conflicts_with = "resolved")]
+    pub lint: bool,
+}
+
+/// Arguments for the `Diff` command.
+///
+/// This struct defines all the arguments that can be passed to the `Diff` command.
+/// It includes common options for specifying the profile file and log level.
+#[derive(Args, Debug)]
+pub struct DiffArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    #[command(flatten)]
+    pub strictness: StrictArgs,
+
+    #[command(flatten)]
+    pub target: TargetArgs,
+}
+
+/// Arguments for the `Doctor` command.
+#[derive(Args, Debug)]
+pub struct DoctorArgs {
+    /// Profile whose backend, privilege methods, architectures and output directory
+    /// decide what is checked. Without it, only what every build needs is checked.
+    #[arg(short, long, value_hint = ValueHint::FilePath)]
+    pub file: Option<Utf8PathBuf>,
+
+    #[command(flatten)]
+    pub strictness: StrictArgs,
+
+    /// Check the profile's `targets` entry for this architecture (needs `--file`).
+    #[arg(long, value_name = "ARCH", requires = "file")]
+    pub arch: Option<String>,
+
+    /// Set the log level for controlling verbosity of output.
+    #[arg(
+        short,
+        long,
+        value_enum,
+        env = "RSDEBSTRAP_LOG_LEVEL",
+        default_value = "info"
+    )]
+    pub log_level: LogLevel,
+
+    /// Set a profile field by dotted path, as `apply --set` (needs `--file`).
+    #[arg(long = "set", value_name = "PATH=VALUE", requires = "file")]
+    pub set: Vec<ProfileSet>,
+
+    /// Ignore the user configuration file (`~/.config/rsdebstrap/config.yaml`).
+    #[arg(long)]
+    pub no_user_config: bool,
+}
+
+/// Arguments for the `Serve` command.
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// Path of the Unix socket to listen on (created with mode 0600).
+    #[arg(long, default_value = "rsdebstrap.sock", value_hint = ValueHint::FilePath)]
+    pub socket: Utf8PathBuf,
+
+    /// Number of builds to run at once.
+    ///
+    /// Builds of profiles that share an output directory, or that store layers in the
+    /// same layer cache, still take turns through file locks.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    pub max_builds: u16,
+
+    /// Set the log level for controlling verbosity of output.
+    #[arg(long, value_enum, default_value = "info")]
+    pub log_level: LogLevel,
+}
+
+/// Arguments for the `Migrate` command.
+#[derive(Args, Debug)]
+pub struct MigrateArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    /// Replace the profile file instead of printing the migrated profile.
+    #[arg(long)]
+    pub in_place: bool,
+}
+
+/// Arguments for the `Init` command.
+///
+/// Every choice has a default, so `rsdebstrap init` alone writes a working
+/// mmdebstrap profile for Debian trixie to `profile.yml`.
+#[derive(Args, Debug)]
+pub struct InitArgs {
+    #[command(flatten)]
+    pub common: CommonArgs,
+
+    /// Bootstrap backend to configure.
+    #[arg(long, value_enum, default_value = "mmdebstrap")]
+    pub backend: InitBackend,
+
+    /// Debian suite to bootstrap (e.g. `trixie`, `bookworm`).
+    #[arg(long, default_value = "trixie")]
+    pub suite: String,
+
+    /// APT mirror URL; repeat for several mirrors (mmdebstrap only).
+    ///
+    /// Defaults to `https://deb.debian.org/debian` when not given.
+    #[arg(long, value_hint = ValueHint::Url)]
+    pub mirror: Vec<String>,
+
+    /// Base output directory written as the profile's `dir`.
+    ///
+    /// A relative path is resolved against the profile file's directory.
+    #[arg(long, default_value = "output", value_hint = ValueHint::DirPath)]
+    pub dir: Utf8PathBuf,
+
+    /// Name of the rootfs output under `dir` (`bootstrap.target`).
+    #[arg(long, default_value = "rootfs")]
+    pub target: String,
+
+    /// Configure a default privilege escalation method for the bootstrap.
+    #[arg(long, value_enum)]
+    pub privilege: Option<crate::privilege::PrivilegeMethod>,
+
+    /// Do not include the example shell provision task.
+    #[arg(long)]
+    pub no_example_task: bool,
+
+    /// Ask for each choice on the terminal, using the flag values as defaults.
+    #[arg(short, long)]
+    pub interactive: bool,
+
+    /// Overwrite the output file if it already exists.
+    #[arg(long)]
+    pub force: bool,
+}
+
+/// Bootstrap backend choices for the `Init` command.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+pub enum InitBackend {
+    Mmdebstrap,
+    Debootstrap,
+}
+
+impl InitBackend {
+    /// Returns the backend's `bootstrap.type` name.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Mmdebstrap => "mmdebstrap",
+            Self::Debootstrap => "debootstrap",
+        }
+    }
 }
 
 /// Arguments for the `Completions` command.
@@ -149,6 +708,41 @@ pub struct CompletionsArgs {
     pub shell: Shell,
 }
 
+/// Arguments for the hidden `sandbox-exec` command (see [`crate::sandbox`]).
+#[derive(Args, Debug)]
+pub struct SandboxExecArgs {
+    /// Allow writes beneath this path (repeatable).
+    #[arg(long, value_name = "PATH")]
+    pub write: Vec<Utf8PathBuf>,
+
+    /// Do not restrict writes with Landlock.
+    #[arg(long)]
+    pub no_landlock: bool,
+
+    /// Do not filter syscalls with seccomp.
+    #[arg(long)]
+    pub no_seccomp: bool,
+
+    /// The command to run, after `--`.
+    #[arg(required = true, last = true)]
+    pub command: Vec<String>,
+}
+
+/// Arguments for the hidden `pivot-exec` command (see [`crate::isolation::pivot_root`]).
+#[derive(Args, Debug)]
+pub struct PivotExecArgs {
+    /// Run the command as `user[:group]` of the rootfs.
+    #[arg(long, value_name = "USER[:GROUP]")]
+    pub userspec: Option<String>,
+
+    /// The directory to make the root.
+    pub root: Utf8PathBuf,
+
+    /// The command to run, after `--`.
+    #[arg(required = true, last = true)]
+    pub command: Vec<String>,
+}
+
 /// Represents log levels for controlling the verbosity of logging output.
 ///
 /// This enum maps directly to the log levels used by the `tracing` crate:
@@ -158,7 +752,7 @@ pub struct CompletionsArgs {
 /// - `Warn`: Designates potentially harmful situations.
 /// - `Error`: Designates error events that might still allow the application to continue running.
 ///
-/// The `LogLevel` enum is used in CLI commands (`Apply` and `Validate`) to set the desired
+/// The `LogLevel` enum is used in CLI commands (`Apply`, `Validate`, and `Init`) to set the desired
 /// verbosity level for logging. For example, specifying `--log-level debug` will enable
 /// debug-level logging output.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
@@ -195,7 +789,8 @@ pub enum LogLevel {
 ///         cli::Commands::Validate(opts) => {
 ///             // Process the validate arguments
 ///         }
-///         // Completions, plus Schema when the `schema` feature (default-on) is enabled.
+///         // Completions and Man, plus Schema when the `schema` feature (default-on)
+///         // is enabled.
 ///         _ => {
 ///             // Handle the stdout-only subcommands
 ///         }
@@ -0,0 +1,375 @@
+//! Handlers for the CLI subcommands.
+//!
+//! Each `run_*` function takes the parsed clap arguments for its subcommand. The
+//! build itself lives in [`Runner`], which `run_apply` configures from the `apply`
+//! flags; embedders should use it directly instead of constructing clap structs.
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use camino::Utf8Path;
+use tracing::info;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::{FmtSubscriber, Layer, filter::LevelFilter, fmt};
+
+use crate::config::{EnvOverrides, ProfileLayers, ProfileSet};
+use crate::error::Stage;
+use crate::events::{EventLayer, EventStream};
+use crate::executor::{self, CommandExecutor};
+use crate::redact::{RedactedStdout, redact};
+use crate::remote::RemoteBuild;
+use crate::runner::Runner;
+use crate::serve::{self, JobLogWriter, Server};
+use crate::user_config::{LogFormat, UserConfig};
+use crate::{RsdebstrapError, cli, config, diff, doctor, init, lint, migrate, shellcheck};
+
+fn level_filter(log_level: cli::LogLevel) -> LevelFilter {
+    match log_level {
+        cli::LogLevel::Trace => LevelFilter::TRACE,
+        cli::LogLevel::Debug => LevelFilter::DEBUG,
+        cli::LogLevel::Info => LevelFilter::INFO,
+        cli::LogLevel::Warn => LevelFilter::WARN,
+        cli::LogLevel::Error => LevelFilter::ERROR,
+    }
+}
+
+/// Installs the global tracing subscriber: log lines up to `log_level`, with
+/// credentials [`redact`]ed, plus [`EventLayer`], which forwards every warning and
+/// error to the open event streams.
+pub fn init_logging(log_level: cli::LogLevel) -> Result<()> {
+    init_logging_with(log_level, LogFormat::Text)
+}
+
+/// Like [`init_logging`], writing log lines in `format`.
+pub fn init_logging_with(log_level: cli::LogLevel, format: LogFormat) -> Result<()> {
+    let lines = match format {
+        LogFormat::Text => fmt::layer().with_writer(RedactedStdout).boxed(),
+        LogFormat::Json => fmt::layer().json().with_writer(RedactedStdout).boxed(),
+    };
+    tracing::subscriber::set_global_default(
+        tracing_subscriber::registry()
+            .with(lines.with_filter(level_filter(log_level)))
+            .with(EventLayer.with_filter(LevelFilter::WARN)),
+    )
+    .context("failed to set global default tracing subscriber")
+}
+
+/// Returns what the CLI layers around the profile: the user configuration, unless
+/// `--no-user-config` says to ignore it, the `RSDEBSTRAP_*` overrides and the `--set`
+/// ones in `sets`.
+pub fn load_layers(no_user_config: bool, sets: &[ProfileSet]) -> Result<ProfileLayers> {
+    let user_config = if no_user_config {
+        None
+    } else {
+        UserConfig::load_default().context("failed to load the user configuration")?
+    };
+    Ok(ProfileLayers {
+        user_config,
+        env: EnvOverrides::from_env()?,
+        sets: sets.to_vec(),
+    })
+}
+
+/// Opens the event stream `--events-fd` or `--events-file` asks for, if any.
+fn event_stream(opts: &cli::ApplyArgs) -> Result<Option<Arc<EventStream>>> {
+    Ok(match (opts.events_fd, &opts.events_file) {
+        (Some(fd), _) => Some(EventStream::open_fd(fd)?),
+        (None, Some(path)) => Some(EventStream::create(path)?),
+        (None, None) => None,
+    })
+}
+
+/// Runs the `serve` subcommand: a build [`Server`] on `opts.socket`.
+///
+/// Sets up logging itself, so that the log of each job is also recorded for the `logs`
+/// method. The interrupt handler is installed as for `apply`, so a signal makes a running
+/// build unwind through its cleanup instead of leaving the rootfs mounted.
+pub fn run_serve(opts: &cli::ServeArgs) -> Result<()> {
+    executor::install_interrupt_handler()?;
+    let server = Server::start(None, opts.max_builds.into());
+    tracing::subscriber::set_global_default(
+        FmtSubscriber::builder()
+            .with_max_level(level_filter(opts.log_level))
+            .with_ansi(false)
+            .with_writer(JobLogWriter)
+            .finish(),
+    )
+    .context("failed to set global default tracing subscriber")?;
+    serve::listen(&opts.socket, server)
+}
+
+/// Runs the `apply` subcommand: a [`Runner`] configured from `opts`, using `executor`.
+///
+/// With `--plan`, prints the execution plan instead of running it. With `--remote`, the
+/// build runs on the remote builder instead ([`RemoteBuild`]).
+pub fn run_apply(opts: &cli::ApplyArgs, executor: Arc<dyn CommandExecutor>) -> Result<()> {
+    if let Some(target) = opts.remote.as_deref() {
+        return RemoteBuild::new(target, &opts.remote_dir, executor)?.apply(opts);
+    }
+    let runner = Runner::load_layered(
+        &opts.common.file,
+        opts.strictness.unknown_fields(),
+        opts.target.arch.as_deref(),
+        &load_layers(opts.common.no_user_config, &opts.target.set)?,
+    )?
+    .with_executor(executor)
+    .with_dry_run(opts.dry_run)
+    .with_bootstrap(opts.runs(cli::ApplyPhase::Bootstrap))
+    .with_selection(opts.pipeline_selection())
+    .with_tag_filter(opts.tag_filter())
+    .with_start_at_task(opts.start_at_task.as_deref())
+    .with_source_commit(opts.source_commit.as_deref())
+    .with_clean_stale_mounts(opts.clean_stale_mounts)
+    .with_allow_existing(opts.allow_existing)
+    .with_wait_for_lock(opts.wait_for_lock)
+    .with_overlay(opts.overlay)
+    .with_layer_cache(opts.layer_cache.as_deref())
+    .with_metrics(&opts.metrics)
+    .with_summary(opts.summary.as_deref())
+    .with_events(event_stream(opts)?);
+    if opts.plan {
+        let plan = runner.plan()?;
+        let plan = plan.to_string();
+        return write_stdout(redact(&plan).as_bytes(), "failed to write the execution plan");
+    }
+    runner.run()
+}
+
+/// Prints how the rootfs built from the profile has drifted from it.
+pub fn run_diff(opts: &cli::DiffArgs) -> Result<()> {
+    let profile = config::load_profile_layered(
+        opts.common.file.as_path(),
+        opts.strictness.unknown_fields(),
+        opts.target.arch.as_deref(),
+        &load_layers(opts.common.no_user_config, &opts.target.set)?,
+    )
+    .with_context(|| {
+        Stage::Profile.context(format!("failed to load profile from {}", opts.common.file))
+    })?;
+    profile.validate().context("profile validation failed")?;
+    let report = diff::diff_rootfs(&profile)?;
+    write_stdout(report.to_string().as_bytes(), "failed to write the drift report")
+}
+
+/// Prints the host prerequisite checks for the `doctor` subcommand.
+///
+/// The profile is loaded but not validated, since validation itself fails on some of
+/// the missing prerequisites the report should explain.
+pub fn run_doctor(opts: &cli::DoctorArgs) -> Result<()> {
+    let profile = match &opts.file {
+        Some(path) => Some(
+            config::load_profile_layered(
+                path,
+                opts.strictness.unknown_fields(),
+                opts.arch.as_deref(),
+                &load_layers(opts.no_user_config, &opts.set)?,
+            )
+            .with_context(|| {
+                Stage::Profile.context(format!("failed to load profile from {}", path))
+            })?,
+        ),
+        None => None,
+    };
+    let report = doctor::diagnose(&doctor::SystemHost, profile.as_ref());
+    write_stdout(report.to_string().as_bytes(), "failed to write the doctor report")?;
+    match report.count(doctor::Status::Fail) {
+        0 => Ok(()),
+        failed => {
+            Err(RsdebstrapError::Validation(format!("{} host check(s) failed", failed)).into())
+        }
+    }
+}
+
+pub fn run_validate(opts: &cli::ValidateArgs) -> Result<()> {
+    let profile = config::load_profile_layered(
+        opts.common.file.as_path(),
+        opts.strictness.unknown_fields(),
+        opts.target.arch.as_deref(),
+        &load_layers(opts.common.no_user_config, &opts.target.set)?,
+    )
+    .with_context(|| {
+        Stage::Profile.context(format!("failed to load profile from {}", opts.common.file))
+    })?;
+    profile.validate().context("profile validation failed")?;
+    shellcheck::check_profile(&profile).context("shell task linting failed")?;
+    info!("validation successful:\n{:#?}", profile);
+    if opts.resolved {
+        let yaml = profile.to_yaml()?;
+        write_stdout(redact(&yaml).as_bytes(), "failed to write the resolved profile")?;
+    }
+    if opts.lint {
+        let lints = lint::lint_profile(&profile);
+        info!("{} lint warning(s)", lints.len());
+        let out: String = lints.iter().map(|lint| format!("{}\n", lint)).collect();
+        write_stdout(redact(&out).as_bytes(), "failed to write the lint warnings")?;
+    }
+    Ok(())
+}
+
+/// Writes a starter profile for the `init` subcommand.
+///
+/// The rendered YAML is loaded and validated through the same path as `validate`
+/// from a temporary file next to the destination, and only then moved into place,
+/// so a profile that would not load is never left behind. Without `--force` an
+/// existing file is refused, including one created while the questions were asked.
+pub fn run_init(opts: &cli::InitArgs) -> Result<()> {
+    let path = &opts.common.file;
+    if !opts.force && path.exists() {
+        return Err(RsdebstrapError::Validation(format!(
+            "{} already exists (use --force to overwrite)",
+            path
+        ))
+        .into());
+    }
+
+    let mut options = init::InitOptions::from(opts);
+    if opts.interactive {
+        let stdin = std::io::stdin();
+        options = init::prompt_options(&options, &mut stdin.lock(), &mut std::io::stderr())?;
+    }
+    let yaml = init::render_profile(&options)?;
+
+    let parent = match path.parent() {
+        Some(p) if !p.as_str().is_empty() => p,
+        _ => Utf8Path::new("."),
+    };
+    let mut tmp = tempfile::Builder::new()
+        .prefix(".rsdebstrap-init-")
+        .suffix(".yml")
+        .tempfile_in(parent)
+        .map_err(|e| {
+            RsdebstrapError::io(format!("failed to create temporary file in {}", parent), e)
+        })?;
+    {
+        use std::io::Write;
+        tmp.write_all(yaml.as_bytes())
+            .and_then(|()| tmp.flush())
+            .map_err(|e| RsdebstrapError::io("failed to write generated profile", e))?;
+    }
+
+    let tmp_path =
+        Utf8Path::from_path(tmp.path()).context("temporary profile path is not valid UTF-8")?;
+    let profile = config::load_profile(tmp_path).context("generated profile failed to load")?;
+    profile
+        .validate()
+        .context("generated profile validation failed")?;
+
+    if opts.force {
+        tmp.persist(path)
+            .map_err(|e| RsdebstrapError::io(format!("failed to write {}", path), e.error))?;
+    } else {
+        tmp.persist_noclobber(path).map_err(|e| {
+            RsdebstrapError::io(
+                format!("failed to write {} (use --force to overwrite)", path),
+                e.error,
+            )
+        })?;
+    }
+    info!("wrote profile to {}", path);
+    Ok(())
+}
+
+/// Converts a profile to the current format for the `migrate` subcommand.
+///
+/// Prints the result, or with `--in-place` moves it over the profile (the file a
+/// symlink points to) from a temporary file next to it, so an interrupted write never
+/// leaves a truncated profile. The file keeps its permissions.
+pub fn run_migrate(opts: &cli::MigrateArgs) -> Result<()> {
+    let path = &opts.common.file;
+    let yaml = std::fs::read_to_string(path)
+        .map_err(|e| RsdebstrapError::io(format!("failed to read {}", path), e))?;
+    let migration = migrate::migrate_profile(&yaml)
+        .with_context(|| Stage::Profile.context(format!("failed to migrate {}", path)))?;
+    if migration.is_unchanged() {
+        info!("{} is already in the current format", path);
+    } else {
+        info!("migrated {} from schema_version {}", path, migration.from_version);
+    }
+    if !opts.in_place {
+        return write_stdout(migration.yaml.as_bytes(), "failed to write the migrated profile");
+    }
+    if migration.yaml == yaml {
+        return Ok(());
+    }
+
+    let target = path
+        .canonicalize_utf8()
+        .map_err(|e| RsdebstrapError::io(format!("failed to resolve {}", path), e))?;
+    let permissions = std::fs::metadata(&target)
+        .map_err(|e| RsdebstrapError::io(format!("failed to read {}", target), e))?
+        .permissions();
+    let parent = target.parent().unwrap_or(Utf8Path::new("/"));
+    let mut tmp = tempfile::Builder::new()
+        .permissions(permissions)
+        .prefix(".rsdebstrap-migrate-")
+        .suffix(".yml")
+        .tempfile_in(parent)
+        .map_err(|e| {
+            RsdebstrapError::io(format!("failed to create temporary file in {}", parent), e)
+        })?;
+    {
+        use std::io::Write;
+        tmp.write_all(migration.yaml.as_bytes())
+            .and_then(|()| tmp.flush())
+            .map_err(|e| RsdebstrapError::io("failed to write the migrated profile", e))?;
+    }
+    tmp.persist(&target)
+        .map_err(|e| RsdebstrapError::io(format!("failed to write {}", target), e.error))?;
+    info!("rewrote {}", target);
+    Ok(())
+}
+
+/// Renders the `rsdebstrap(1)` man page (roff) from the clap CLI definitions.
+pub fn render_man_page() -> Result<Vec<u8>> {
+    use clap::CommandFactory;
+
+    let mut buf = Vec::new();
+    clap_mangen::Man::new(cli::Cli::command())
+        .render(&mut buf)
+        .map_err(|e| RsdebstrapError::io("failed to render the man page", e))?;
+    Ok(buf)
+}
+
+/// Prints the `rsdebstrap(1)` man page to stdout.
+///
+/// Like the `schema` subcommand, a closed stdout (`rsdebstrap man | head`) ends the command
+/// successfully instead of failing on `BrokenPipe`.
+pub fn run_man() -> Result<()> {
+    write_stdout(&render_man_page()?, "failed to write the man page")
+}
+
+/// Writes `output` to stdout, treating a closed pipe as success.
+///
+/// A consumer such as `head` closing the pipe early is a normal way to stop reading, so
+/// `BrokenPipe` is not an error, unlike with `print!`, which would panic.
+fn write_stdout(output: &[u8], context: &str) -> Result<()> {
+    use std::io::Write;
+
+    let mut stdout = std::io::stdout().lock();
+    let result = stdout.write_all(output).and_then(|()| stdout.flush());
+    match result {
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        other => other.map_err(|e| RsdebstrapError::io(context, e).into()),
+    }
+}
+
+/// Prints the profile JSON Schema (pretty-printed) to stdout.
+///
+/// A closed stdout (e.g. `rsdebstrap schema | head`) is a normal way for a pipe
+/// consumer to stop reading, so `BrokenPipe` ends the command successfully instead
+/// of panicking the way `println!` would once the schema outgrows the pipe buffer.
+#[cfg(feature = "schema")]
+pub fn run_schema() -> Result<()> {
+    use std::io::Write;
+
+    let mut stdout = std::io::stdout().lock();
+    let result = stdout
+        .write_all(crate::profile_json_schema_pretty().as_bytes())
+        .and_then(|()| stdout.write_all(b"\n"))
+        .and_then(|()| stdout.flush());
+    match result {
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        other => other
+            .map_err(|e| RsdebstrapError::io("failed to write the profile JSON Schema", e).into()),
+    }
+}
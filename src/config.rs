@@ -7,32 +7,325 @@
 //! The configuration is typically loaded from YAML files using the
 //! `load_profile` function.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, Read};
 use std::net::IpAddr;
 
 use camino::{Utf8Path, Utf8PathBuf};
+use clap::ValueEnum;
 #[cfg(feature = "schema")]
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use tracing::debug;
+use tracing::{debug, warn};
 
+use crate::apt_cache::AptCacheConfig;
 use crate::bootstrap::{
-    BootstrapBackend, RootfsOutput, debootstrap::DebootstrapConfig, mmdebstrap::MmdebstrapConfig,
+    BootstrapBackend, RootfsOutput, ToolVersion,
+    debootstrap::{self, DebootstrapConfig},
+    distribution::{self, Distribution},
+    mirror,
+    mmdebstrap::{self, MmdebstrapConfig, Mode},
+    preset, suite,
 };
+use crate::checksums::ChecksumConfig;
+use crate::disk_space::ByteSize;
 use crate::error::RsdebstrapError;
-use crate::executor::CommandSpec;
+use crate::executor::{CommandExecutor, CommandSpec};
+use crate::isolation::staging::Staging;
 use crate::isolation::{ChrootProvider, IsolationProvider};
-use crate::phase::{AssembleConfig, PrepareConfig, ProvisionTask};
-use crate::pipeline::Pipeline;
+use crate::keyring::KeyringSource;
+use crate::migrate::LEGACY_KEYS;
+use crate::output_dir::{self, DirPolicy, FileMode, OutputPermissions};
+use crate::phase::provision::mitamae::{self, MitamaeRelease};
+use crate::phase::{AssembleConfig, PrepareConfig, ProvisionTask, VerifyTask};
+use crate::pipeline::{FailurePolicies, Pipeline};
 use crate::privilege::{Privilege, PrivilegeDefaults, PrivilegeMethod};
+use crate::secrets::{self, SecretConfig, Secrets};
+use crate::shellcheck::ShellcheckConfig;
+use crate::snapshot;
+use crate::upload::UploadConfig;
+use crate::user_config::{LogFormat, UserConfig};
+use crate::yaml_error::{self, Segment};
 
 /// Known pseudo-filesystem source names.
 ///
 /// These are used to determine the correct `mount -t` type argument.
 const PSEUDO_FS_TYPES: &[&str] = &["proc", "sysfs", "devpts", "devtmpfs", "tmpfs"];
 
+/// Where the profile's `context` directory appears inside the rootfs.
+pub const CONTEXT_MOUNT_POINT: &str = "/run/rsdebstrap/context";
+
+/// The newest profile format this build understands (the profile's `schema_version`).
+///
+/// Bump it when a format change needs a migration; profiles declaring a newer version
+/// are rejected with an upgrade hint instead of failing on the fields they add.
+pub const PROFILE_SCHEMA_VERSION: u32 = 1;
+
+/// How [`load_profile_with`] treats fields the profile format does not define.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnknownFields {
+    /// Reject the profile (`--strict`, the default): a typo'd key is an error.
+    #[default]
+    Deny,
+    /// Log a warning for each unknown field and ignore it (`--no-strict`).
+    Warn,
+}
+
+/// Environment variable replacing the profile's `dir`.
+pub const ENV_DIR: &str = "RSDEBSTRAP_DIR";
+
+/// Environment variable replacing the profile's `defaults.privilege` method.
+pub const ENV_PRIVILEGE_METHOD: &str = "RSDEBSTRAP_PRIVILEGE_METHOD";
+
+/// Environment variable replacing the user configuration's `log_format`.
+pub const ENV_LOG_FORMAT: &str = "RSDEBSTRAP_LOG_FORMAT";
+
+/// Profile values set by `RSDEBSTRAP_*` environment variables, for CI systems that
+/// configure builds through the environment rather than by editing files.
+///
+/// They replace what the profile (and the user configuration) sets, and give way to
+/// command-line flags. An empty variable counts as unset. `RSDEBSTRAP_LOG_LEVEL` is
+/// read by the CLI's `--log-level` itself.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnvOverrides {
+    /// [`ENV_DIR`], relative to the current directory like a command-line path.
+    pub dir: Option<Utf8PathBuf>,
+    /// [`ENV_PRIVILEGE_METHOD`].
+    pub privilege_method: Option<PrivilegeMethod>,
+    /// [`ENV_LOG_FORMAT`].
+    pub log_format: Option<LogFormat>,
+}
+
+impl EnvOverrides {
+    /// Reads the overrides from the process environment.
+    ///
+    /// # Errors
+    ///
+    /// As [`EnvOverrides::from_vars`], and `RsdebstrapError::Io` if a relative
+    /// [`ENV_DIR`] needs a current directory that cannot be determined.
+    pub fn from_env() -> Result<Self, RsdebstrapError> {
+        let var = |name: &str| std::env::var(name).ok();
+        let cwd = match var(ENV_DIR) {
+            Some(dir) if Utf8Path::new(&dir).is_relative() => {
+                let cwd = std::env::current_dir().map_err(|e| {
+                    RsdebstrapError::io(format!("failed to resolve {}", ENV_DIR), e)
+                })?;
+                Utf8PathBuf::from_path_buf(cwd).map_err(|cwd| {
+                    RsdebstrapError::Validation(format!(
+                        "current directory is not valid UTF-8: {}",
+                        cwd.display()
+                    ))
+                })?
+            }
+            _ => Utf8PathBuf::new(),
+        };
+        Self::from_vars(var, &cwd)
+    }
+
+    /// Reads the overrides through `var`, resolving a relative [`ENV_DIR`] against
+    /// `cwd`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` for a value the variable does not accept.
+    pub fn from_vars(
+        var: impl Fn(&str) -> Option<String>,
+        cwd: &Utf8Path,
+    ) -> Result<Self, RsdebstrapError> {
+        let var = |name: &str| var(name).filter(|value| !value.is_empty());
+        let value = |name: &str, value: &str, values: String| {
+            RsdebstrapError::Validation(format!(
+                "{}={:?} is not valid (expected one of: {})",
+                name, value, values
+            ))
+        };
+        let privilege_method = match var(ENV_PRIVILEGE_METHOD) {
+            Some(method) => {
+                Some(<PrivilegeMethod as ValueEnum>::from_str(&method, false).map_err(|_| {
+                    value(ENV_PRIVILEGE_METHOD, &method, possible_values::<PrivilegeMethod>())
+                })?)
+            }
+            None => None,
+        };
+        let log_format = match var(ENV_LOG_FORMAT) {
+            Some(format) => Some(
+                <LogFormat as ValueEnum>::from_str(&format, false)
+                    .map_err(|_| value(ENV_LOG_FORMAT, &format, possible_values::<LogFormat>()))?,
+            ),
+            None => None,
+        };
+        Ok(Self {
+            dir: var(ENV_DIR).map(|dir| cwd.join(dir)),
+            privilege_method,
+            log_format,
+        })
+    }
+
+    /// Replaces the profile values the overrides set.
+    pub fn apply(&self, profile: &mut Profile) {
+        if let Some(dir) = &self.dir {
+            profile.dir = dir.clone();
+        }
+        if let Some(method) = self.privilege_method {
+            profile.defaults.privilege = Some(PrivilegeDefaults { method });
+        }
+    }
+}
+
+/// Returns the command-line spelling of each value of `T`, comma-separated.
+fn possible_values<T: ValueEnum>() -> String {
+    T::value_variants()
+        .iter()
+        .filter_map(|value| value.to_possible_value())
+        .map(|value| value.get_name().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A `--set path=value` override of one profile field, for one-off experiments
+/// without a copy of the profile.
+///
+/// The path is dotted with `[index]` for list items (`bootstrap.suite`,
+/// `provision[0].content`); missing mappings along it are created, and an index one
+/// past the end appends. The value is YAML, so `true`, `[vim, curl]` and
+/// `{method: sudo}` work, and a quoted value stays a string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileSet {
+    /// Dotted path of the field.
+    pub path: String,
+    /// YAML text of the value.
+    pub value: String,
+}
+
+impl std::str::FromStr for ProfileSet {
+    type Err = RsdebstrapError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid =
+            |reason: &str| RsdebstrapError::Validation(format!("--set {}: {}", text, reason));
+        let (path, value) = text
+            .split_once('=')
+            .ok_or_else(|| invalid("expected PATH=VALUE"))?;
+        match yaml_error::parse_path(path) {
+            Some(segments) if !segments.is_empty() => {}
+            _ => return Err(invalid("invalid path (expected e.g. bootstrap.suite)")),
+        }
+        yaml_serde::from_str::<yaml_serde::Value>(value)
+            .map_err(|e| invalid(&format!("invalid YAML value: {}", e)))?;
+        Ok(Self {
+            path: path.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+impl std::fmt::Display for ProfileSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.path, self.value)
+    }
+}
+
+impl ProfileSet {
+    /// Sets the field in `profile`, which goes through YAML and back so the new value is
+    /// checked like one in the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if the path runs through a value that is
+    /// not a mapping or list, or the profile no longer deserializes (an unknown field,
+    /// a value of the wrong type).
+    pub fn apply(&self, profile: &mut Profile) -> Result<(), RsdebstrapError> {
+        let failed =
+            |reason: String| RsdebstrapError::Validation(format!("--set {}: {}", self, reason));
+        let segments =
+            yaml_error::parse_path(&self.path).ok_or_else(|| failed("invalid path".to_string()))?;
+        let value = yaml_serde::from_str(&self.value).map_err(|e| failed(e.to_string()))?;
+        let mut document = yaml_serde::to_value(&*profile).map_err(|e| failed(e.to_string()))?;
+        set_at_path(&mut document, &segments, value).map_err(failed)?;
+        *profile = yaml_serde::from_value(document).map_err(|e| failed(e.to_string()))?;
+        Ok(())
+    }
+}
+
+/// Sets the value at `segments` in `document`, creating missing mappings and
+/// appending at an index one past the end of a list.
+fn set_at_path(
+    document: &mut yaml_serde::Value,
+    segments: &[Segment],
+    new: yaml_serde::Value,
+) -> Result<(), String> {
+    let Some((last, parents)) = segments.split_last() else {
+        return Err("empty path".to_string());
+    };
+    let mut value = document;
+    for (depth, segment) in parents.iter().enumerate() {
+        let next = match &segments[depth + 1] {
+            Segment::Key(_) => yaml_serde::Value::Mapping(Default::default()),
+            Segment::Index(_) => yaml_serde::Value::Sequence(Vec::new()),
+        };
+        value = child(value, segment, next)?;
+    }
+    *child(value, last, yaml_serde::Value::Null)? = new;
+    Ok(())
+}
+
+/// Returns the child `segment` of `value`, inserting `missing` if there is none.
+fn child<'a>(
+    value: &'a mut yaml_serde::Value,
+    segment: &Segment,
+    missing: yaml_serde::Value,
+) -> Result<&'a mut yaml_serde::Value, String> {
+    if value.is_null() {
+        *value = match segment {
+            Segment::Key(_) => yaml_serde::Value::Mapping(Default::default()),
+            Segment::Index(_) => yaml_serde::Value::Sequence(Vec::new()),
+        };
+    }
+    match (segment, value) {
+        (Segment::Key(key), yaml_serde::Value::Mapping(mapping)) => Ok(mapping
+            .entry(yaml_serde::Value::String(key.clone()))
+            .or_insert(missing)),
+        (Segment::Index(index), yaml_serde::Value::Sequence(items)) => {
+            if *index == items.len() {
+                items.push(missing);
+            }
+            let len = items.len();
+            items
+                .get_mut(*index)
+                .ok_or_else(|| format!("index {} is past the end of a list of {}", index, len))
+        }
+        (Segment::Key(key), _) => Err(format!("'{}' is set on a value that is not a mapping", key)),
+        (Segment::Index(index), _) => {
+            Err(format!("index {} is set on a value that is not a list", index))
+        }
+    }
+}
+
+/// What the CLI layers around a profile file at load (see [`load_profile_layered`]):
+/// the user configuration beneath it, and the `RSDEBSTRAP_*` and `--set` overrides
+/// above it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProfileLayers {
+    /// Per-user defaults, unless `--no-user-config`.
+    pub user_config: Option<UserConfig>,
+    /// The `RSDEBSTRAP_*` environment variables.
+    pub env: EnvOverrides,
+    /// The `--set` overrides, applied in order after the environment's.
+    pub sets: Vec<ProfileSet>,
+}
+
+impl ProfileLayers {
+    /// Returns the log format: [`ENV_LOG_FORMAT`], else the user configuration's.
+    pub fn log_format(&self) -> LogFormat {
+        self.env.log_format.unwrap_or_else(|| {
+            self.user_config
+                .as_ref()
+                .map_or_else(LogFormat::default, |config| config.log_format)
+        })
+    }
+}
+
 /// Mount preset defining a predefined set of mount entries.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
@@ -96,7 +389,9 @@ impl MountPreset {
 // The profile-facing DNS shapes are `prepare::ResolvConfTask` / `assemble::AssembleResolvConfTask`.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ResolvConfConfig {
-    /// Copy host's /etc/resolv.conf into the chroot (following symlinks).
+    /// Copy host's /etc/resolv.conf into the chroot (following symlinks). A host file that
+    /// only points at the systemd-resolved stub is replaced by
+    /// /run/systemd/resolve/resolv.conf.
     #[serde(default)]
     pub copy: bool,
     /// Nameserver IP addresses to write to resolv.conf.
@@ -244,18 +539,51 @@ impl MountEntry {
         abs_target: &Utf8Path,
         privilege: Option<PrivilegeMethod>,
     ) -> CommandSpec {
-        CommandSpec::new("umount", vec![abs_target.to_string()]).with_privilege(privilege)
+        self.build_umount_spec_with_mode(abs_target, UnmountMode::Normal, privilege)
+    }
+
+    /// Builds a `CommandSpec` for the `umount` command with the flag selected by `mode`
+    /// (`umount [-l|-f] <abs_target>`).
+    pub fn build_umount_spec_with_mode(
+        &self,
+        abs_target: &Utf8Path,
+        mode: UnmountMode,
+        privilege: Option<PrivilegeMethod>,
+    ) -> CommandSpec {
+        let mut args = Vec::new();
+        if let Some(flag) = mode.flag() {
+            args.push(flag.to_string());
+        }
+        args.push(abs_target.to_string());
+        CommandSpec::new("umount", args).with_privilege(privilege)
     }
 
     /// Validates this mount entry's format: source must not be empty, target must
     /// be an absolute path (not `/`) without `..` components, pseudo-filesystem
-    /// and bind mount are mutually exclusive, and bind/regular mount sources must
-    /// be absolute paths.
+    /// and bind mount are mutually exclusive, bind/regular mount sources must
+    /// be absolute paths, and no field may contain a null character.
     pub fn validate(&self) -> Result<(), RsdebstrapError> {
         if self.source.trim().is_empty() {
             return Err(RsdebstrapError::Validation("mount source must not be empty".to_string()));
         }
 
+        // YAML strings can hold "\0", which mount(2) cannot take.
+        let fields = [
+            ("source", self.source.as_str()),
+            ("target", self.target.as_str()),
+        ];
+        let options = self.options.iter().map(|o| ("option", o.as_str()));
+        if let Some((field, value)) = fields
+            .into_iter()
+            .chain(options)
+            .find(|(_, value)| value.contains('\0'))
+        {
+            return Err(RsdebstrapError::Validation(format!(
+                "mount {} {:?} must not contain a null character",
+                field, value
+            )));
+        }
+
         if self.target.as_str() == "/" {
             return Err(RsdebstrapError::Validation(
                 "mount target '/' is not allowed (would mount over rootfs itself)".to_string(),
@@ -308,7 +636,7 @@ impl MountEntry {
 ///
 /// This enum represents the different bootstrap tools that can be used.
 /// The `type` field in YAML determines which variant is used.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum Bootstrap {
@@ -318,6 +646,18 @@ pub enum Bootstrap {
     Debootstrap(DebootstrapConfig),
 }
 
+impl From<MmdebstrapConfig> for Bootstrap {
+    fn from(config: MmdebstrapConfig) -> Self {
+        Self::Mmdebstrap(config)
+    }
+}
+
+impl From<DebootstrapConfig> for Bootstrap {
+    fn from(config: DebootstrapConfig) -> Self {
+        Self::Debootstrap(config)
+    }
+}
+
 impl Bootstrap {
     /// Returns a reference to the underlying backend as a trait object.
     ///
@@ -330,6 +670,72 @@ impl Bootstrap {
         }
     }
 
+    /// Returns the Debian suite being bootstrapped.
+    pub fn suite(&self) -> &str {
+        match self {
+            Bootstrap::Mmdebstrap(cfg) => &cfg.suite,
+            Bootstrap::Debootstrap(cfg) => &cfg.suite,
+        }
+    }
+
+    /// Returns the distribution of the suite.
+    pub fn distribution(&self) -> Distribution {
+        match self {
+            Bootstrap::Mmdebstrap(cfg) => cfg.distribution,
+            Bootstrap::Debootstrap(cfg) => cfg.distribution,
+        }
+    }
+
+    /// Returns the name of the derivative preset, if any.
+    pub fn preset(&self) -> Option<&str> {
+        match self {
+            Bootstrap::Mmdebstrap(cfg) => cfg.preset.as_deref(),
+            Bootstrap::Debootstrap(cfg) => cfg.preset.as_deref(),
+        }
+    }
+
+    /// Returns the additional packages the bootstrap installs (`include`).
+    pub fn include(&self) -> &[String] {
+        match self {
+            Bootstrap::Mmdebstrap(cfg) => &cfg.include,
+            Bootstrap::Debootstrap(cfg) => &cfg.include,
+        }
+    }
+
+    /// Returns the Rust name (`x86_64`, `aarch64`) of the architecture mitamae runs on
+    /// inside the rootfs: the first target architecture, or the host's.
+    pub fn mitamae_arch(&self) -> String {
+        match self.architectures().first() {
+            Some(arch) => crate::doctor::rust_arch(arch).to_string(),
+            None => std::env::consts::ARCH.to_string(),
+        }
+    }
+
+    /// Returns the target architectures the profile lists (mmdebstrap `architectures`,
+    /// debootstrap `arch`); empty means the host's.
+    pub fn architectures(&self) -> Vec<&str> {
+        match self {
+            Bootstrap::Mmdebstrap(cfg) => cfg.architectures.iter().map(String::as_str).collect(),
+            Bootstrap::Debootstrap(cfg) => cfg.arch.iter().map(String::as_str).collect(),
+        }
+    }
+
+    /// Returns the bootstrap output path (`target`), relative to the profile's `dir`.
+    pub fn target(&self) -> &str {
+        match self {
+            Bootstrap::Mmdebstrap(cfg) => &cfg.target,
+            Bootstrap::Debootstrap(cfg) => &cfg.target,
+        }
+    }
+
+    /// Returns the oldest backend release the profile accepts (`min_version`).
+    pub fn min_version(&self) -> Option<&ToolVersion> {
+        match self {
+            Bootstrap::Mmdebstrap(cfg) => cfg.min_version.as_ref(),
+            Bootstrap::Debootstrap(cfg) => cfg.min_version.as_ref(),
+        }
+    }
+
     /// Returns a reference to the privilege setting of the bootstrap backend.
     pub fn privilege(&self) -> &Privilege {
         match self {
@@ -356,153 +762,1637 @@ impl Bootstrap {
     pub fn resolved_privilege_method(&self) -> Option<PrivilegeMethod> {
         self.privilege().resolved_method()
     }
-}
-
-/// Isolation backend configuration.
-///
-/// The `type` key selects the backend used to run commands inside the rootfs; `chroot` is
-/// currently the only backend. `type` is required whenever an `isolation` map is written
-/// out — the chroot default applies only when the surrounding `isolation` key (e.g.
-/// `defaults.isolation`) is omitted entirely.
-// Internally tagged like `Bootstrap` (rather than a plain struct) so each backend keeps its
-// own payload struct as an extension point for backend-specific options (bwrap, nspawn, …).
-// `deny_unknown_fields` would be a serde no-op on the enum itself, so strictness lives on
-// the per-variant payload structs: serde consumes the `type` tag when selecting the variant
-// and hands only the remaining keys to the payload, whose `deny_unknown_fields` then
-// rejects typo'd keys (see the `Bootstrap` note in ARCHITECTURE.md).
-#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
-#[cfg_attr(feature = "schema", derive(JsonSchema))]
-#[serde(tag = "type", rename_all = "lowercase")]
-pub enum IsolationConfig {
-    /// Run commands inside the rootfs via `chroot`.
-    Chroot(ChrootIsolation),
-}
-
-/// Options for the `chroot` isolation backend (currently none).
-// A braced (named-field) empty struct, not a unit struct: internally tagged variants need a
-// map-shaped payload to serialize, and only the braced form gives `deny_unknown_fields` a
-// struct visitor that rejects `{type: chroot, <typo>: ...}`.
-#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq, Eq)]
-#[cfg_attr(feature = "schema", derive(JsonSchema))]
-#[serde(deny_unknown_fields)]
-pub struct ChrootIsolation {}
-
-impl Default for IsolationConfig {
-    /// The backend used when no `isolation` key is configured: chroot.
-    fn default() -> Self {
-        Self::chroot()
-    }
-}
 
-impl IsolationConfig {
-    /// Creates a default chroot config.
-    pub fn chroot() -> Self {
-        Self::Chroot(ChrootIsolation {})
+    /// Returns the privilege method to wrap the backend command with.
+    ///
+    /// Same as [`resolved_privilege_method()`](Self::resolved_privilege_method), except
+    /// that `userns` is handled by mmdebstrap's own `unshare` mode and so wraps nothing.
+    pub fn command_privilege_method(&self) -> Option<PrivilegeMethod> {
+        match self.resolved_privilege_method() {
+            Some(PrivilegeMethod::Userns) => None,
+            other => other,
+        }
     }
 
-    /// Returns a boxed isolation provider instance.
-    ///
-    /// This allows calling `IsolationProvider` methods without matching
-    /// on each variant explicitly.
-    pub fn as_provider(&self) -> Box<dyn IsolationProvider> {
+    /// Returns the mirror the failover list stands in for, the failover list, and the
+    /// primary mirror's field name.
+    fn mirror_failover(&self) -> (Option<&str>, &[String], &'static str) {
         match self {
-            Self::Chroot(_) => Box::new(ChrootProvider),
+            Bootstrap::Mmdebstrap(cfg) => {
+                (cfg.mirrors.first().map(String::as_str), &cfg.fallback_mirrors, "mirrors")
+            }
+            Bootstrap::Debootstrap(cfg) => (cfg.mirror.as_deref(), &cfg.fallback_mirrors, "mirror"),
         }
     }
-}
-
-/// Default settings for mitamae tasks.
-///
-/// Allows specifying architecture-specific binary paths that apply to all
-/// mitamae tasks unless overridden at the task level.
-#[derive(Debug, Deserialize, Clone, Default)]
-#[cfg_attr(feature = "schema", derive(JsonSchema))]
-#[serde(deny_unknown_fields)]
-pub struct MitamaeDefaults {
-    /// Architecture-specific binary paths (key: "x86_64", "aarch64", etc.)
-    #[serde(default, deserialize_with = "crate::de::path_map")]
-    #[cfg_attr(
-        feature = "schema",
-        schemars(
-            with = "Option<std::collections::HashMap<String, crate::schema::Utf8PathSchema>>"
-        )
-    )]
-    pub binary: HashMap<String, Utf8PathBuf>,
-}
 
-/// Default settings that apply across the profile.
-///
-/// Groups configuration defaults like isolation backend.
-/// If omitted in YAML, all fields use their respective defaults.
-#[derive(Debug, Deserialize, Clone, Default)]
-#[cfg_attr(feature = "schema", derive(JsonSchema))]
-#[serde(deny_unknown_fields)]
-pub struct Defaults {
-    /// Isolation backend for running commands in rootfs (default: chroot)
-    #[serde(default)]
-    pub isolation: IsolationConfig,
-    /// Default settings for mitamae tasks
-    #[serde(default, deserialize_with = "crate::de::null_to_default")]
-    #[cfg_attr(feature = "schema", schemars(with = "Option<MitamaeDefaults>"))]
-    pub mitamae: MitamaeDefaults,
-    /// Default privilege escalation settings
-    #[serde(default)]
-    pub privilege: Option<PrivilegeDefaults>,
-}
+    /// Returns the `fallback_mirrors` health-checked before the bootstrap.
+    pub(crate) fn fallback_mirrors(&self) -> &[String] {
+        self.mirror_failover().1
+    }
 
-/// Represents a bootstrap profile configuration.
-///
-/// A profile contains the target directory and bootstrap tool configuration
-/// details needed to create a Debian-based system.
-#[derive(Debug, Deserialize)]
-#[cfg_attr(feature = "schema", derive(JsonSchema))]
-#[serde(deny_unknown_fields)]
-pub struct Profile {
-    /// Target directory path for the bootstrap operation
-    #[serde(deserialize_with = "crate::de::path")]
-    #[cfg_attr(feature = "schema", schemars(with = "crate::schema::Utf8PathSchema"))]
-    pub dir: Utf8PathBuf,
-    /// Default settings (isolation backend, etc.)
-    #[serde(default, deserialize_with = "crate::de::null_to_default")]
-    #[cfg_attr(feature = "schema", schemars(with = "Option<Defaults>"))]
-    pub defaults: Defaults,
-    /// Bootstrap tool configuration
-    pub bootstrap: Bootstrap,
-    /// Prepare tasks to run before provisioning (optional)
-    #[serde(default, deserialize_with = "crate::de::null_to_default")]
-    #[cfg_attr(feature = "schema", schemars(with = "Option<PrepareConfig>"))]
-    pub prepare: PrepareConfig,
-    /// Main provisioning tasks (optional)
-    #[serde(default, deserialize_with = "crate::de::null_to_default")]
-    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<ProvisionTask>>"))]
-    pub provision: Vec<ProvisionTask>,
-    /// Assemble tasks to run after provisioning (optional)
-    #[serde(default, deserialize_with = "crate::de::null_to_default")]
-    #[cfg_attr(feature = "schema", schemars(with = "Option<AssembleConfig>"))]
-    pub assemble: AssembleConfig,
-}
+    /// Validates the `fallback_mirrors` list and its primary mirror.
+    fn validate_mirrors(&self) -> Result<(), RsdebstrapError> {
+        let (primary, fallbacks, field) = self.mirror_failover();
+        mirror::validate_candidates(primary, fallbacks, field)?;
+        if !fallbacks.is_empty() {
+            validate_command_in_path("curl", "mirror health check command")?;
+        }
+        Ok(())
+    }
 
-impl Profile {
-    /// Creates a `Pipeline` from this profile's task phases.
-    pub fn pipeline(&self) -> Pipeline<'_> {
-        Pipeline::new(&self.prepare, &self.provision, &self.assemble)
+    /// Returns the commands the bootstrap output is piped through (mmdebstrap
+    /// `stream_to`).
+    pub fn stream_to(&self) -> &[Vec<String>] {
+        match self {
+            Bootstrap::Mmdebstrap(cfg) => &cfg.stream_to,
+            Bootstrap::Debootstrap(_) => &[],
+        }
     }
 
-    /// Validate configuration semantics beyond basic deserialization.
-    pub fn validate(&self) -> Result<(), RsdebstrapError> {
-        if self.dir.exists() && !self.dir.is_dir() {
+    /// Validates that `target: "-"` and `stream_to` are used together, with an archive
+    /// format and installed commands.
+    fn validate_stream(&self) -> Result<(), RsdebstrapError> {
+        let Bootstrap::Mmdebstrap(cfg) = self else {
+            return Ok(());
+        };
+        match (cfg.streams(), cfg.stream_to.is_empty()) {
+            (true, true) => {
+                return Err(RsdebstrapError::Validation(format!(
+                    "bootstrap target {:?} writes the archive to standard output and needs \
+                    stream_to commands to read it",
+                    mmdebstrap::STDOUT_TARGET
+                )));
+            }
+            (false, false) => {
+                return Err(RsdebstrapError::Validation(format!(
+                    "bootstrap stream_to needs target {:?}, got {:?}",
+                    mmdebstrap::STDOUT_TARGET,
+                    cfg.target
+                )));
+            }
+            (false, true) => return Ok(()),
+            (true, false) => {}
+        }
+        if matches!(cfg.format, mmdebstrap::Format::Directory | mmdebstrap::Format::Null) {
             return Err(RsdebstrapError::Validation(format!(
-                "dir must be a directory: {}",
-                self.dir
+                "bootstrap format {} cannot be streamed to standard output",
+                cfg.format
             )));
         }
+        for (index, command) in cfg.stream_to.iter().enumerate() {
+            let Some(program) = command.first().filter(|p| !p.trim().is_empty()) else {
+                return Err(RsdebstrapError::Validation(format!(
+                    "bootstrap stream_to[{}] must name a command",
+                    index
+                )));
+            };
+            validate_command_in_path(program, "stream_to command")?;
+        }
+        Ok(())
+    }
 
-        // Validate mounts configuration
+    /// Health-checks the primary mirror and `fallback_mirrors`, replacing the primary
+    /// with the first mirror that responds.
+    ///
+    /// Does nothing when no fallbacks are configured. For mmdebstrap only the first
+    /// `mirrors` entry is replaced; further entries are additional sources.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RsdebstrapError::Mirror`] when no mirror responds.
+    pub fn select_mirror(&mut self, executor: &dyn CommandExecutor) -> Result<(), RsdebstrapError> {
+        let (Some(primary), fallbacks, _) = self.mirror_failover() else {
+            return Ok(());
+        };
+        if fallbacks.is_empty() {
+            return Ok(());
+        }
+        let chosen = mirror::select_mirror(primary, fallbacks, self.suite(), executor)?;
+        match self {
+            Bootstrap::Mmdebstrap(cfg) => cfg.mirrors[0] = chosen,
+            Bootstrap::Debootstrap(cfg) => cfg.mirror = Some(chosen),
+        }
+        Ok(())
+    }
+
+    /// Replaces the `${secret:NAME}` placeholders in the mirrors and `fallback_mirrors`
+    /// with the secrets' values.
+    pub fn substitute_secrets(&mut self, secrets: &Secrets) {
+        let (mirrors, fallbacks): (&mut [String], &mut [String]) = match self {
+            Bootstrap::Mmdebstrap(cfg) => (&mut cfg.mirrors, &mut cfg.fallback_mirrors),
+            Bootstrap::Debootstrap(cfg) => (cfg.mirror.as_mut_slice(), &mut cfg.fallback_mirrors),
+        };
+        for mirror in mirrors.iter_mut().chain(fallbacks.iter_mut()) {
+            *mirror = secrets.substitute(mirror);
+        }
+    }
+
+    /// Replaces the Debian archive mirrors with their snapshot at `stamp` (see
+    /// [`snapshot`](crate::snapshot)), or sets the main archive's snapshot when no
+    /// mirror is configured, and turns off apt's `Valid-Until` check for mmdebstrap.
+    ///
+    /// Returns the mirrors left unchanged because they are no Debian archive.
+    pub fn apply_snapshot(&mut self, stamp: &str) -> Vec<String> {
+        let default = || snapshot::archive_url("https", "debian", stamp);
+        let mirrors = match self {
+            Bootstrap::Mmdebstrap(cfg) => {
+                cfg.aptopt.push(snapshot::CHECK_VALID_UNTIL.to_string());
+                if cfg.mirrors.is_empty() {
+                    cfg.mirrors.push(default());
+                }
+                cfg.mirrors.as_mut_slice()
+            }
+            Bootstrap::Debootstrap(cfg) => {
+                cfg.mirror.get_or_insert_with(default);
+                cfg.mirror.as_mut_slice()
+            }
+        };
+        let mut unchanged = Vec::new();
+        for mirror in mirrors.iter_mut() {
+            match snapshot::rewrite_mirror(mirror, stamp) {
+                Some(pinned) => *mirror = pinned,
+                None if mirror.contains(snapshot::SNAPSHOT_HOST) => {}
+                None => unchanged.push(mirror.clone()),
+            }
+        }
+        unchanged
+    }
+
+    /// Returns every configured mirror, followed by the `fallback_mirrors`.
+    pub(crate) fn mirror_urls(&self) -> impl Iterator<Item = &str> {
+        let (mirrors, fallbacks): (&[String], &[String]) = match self {
+            Bootstrap::Mmdebstrap(cfg) => (&cfg.mirrors, &cfg.fallback_mirrors),
+            Bootstrap::Debootstrap(cfg) => (cfg.mirror.as_slice(), &cfg.fallback_mirrors),
+        };
+        mirrors.iter().chain(fallbacks).map(String::as_str)
+    }
+
+    /// Appends the profile's `keyrings` to the backend's own keyring options.
+    ///
+    /// For debootstrap, which takes a single keyring, [`Profile::validate`] ensures
+    /// there is at most one in total.
+    pub fn add_keyrings(&mut self, paths: &[Utf8PathBuf]) {
+        match self {
+            Bootstrap::Mmdebstrap(cfg) => {
+                cfg.keyring.extend(paths.iter().map(Utf8PathBuf::to_string));
+            }
+            Bootstrap::Debootstrap(cfg) => {
+                if let Some(path) = paths.first() {
+                    cfg.keyring = Some(path.to_string());
+                }
+            }
+        }
+    }
+
+    /// Validates the suite against the bundled release table (see
+    /// [`suite`](crate::bootstrap::suite)): a near miss of a known codename or alias is a
+    /// typo, and without mirrors (the Debian archive) each architecture must be one the
+    /// release is built for. Ubuntu suites are checked by
+    /// [`validate_ubuntu`](Self::validate_ubuntu) instead, and a preset's suites by
+    /// [`validate_preset`](Self::validate_preset). debootstrap also needs a script for the
+    /// suite, checked when its scripts directory exists on the host.
+    fn validate_suite(&self) -> Result<(), RsdebstrapError> {
+        let name = self.suite();
+        if let Bootstrap::Debootstrap(_) = self {
+            let scripts = Utf8Path::new(debootstrap::SCRIPTS_DIR);
+            if scripts.is_dir() && !scripts.join(name).exists() {
+                return Err(RsdebstrapError::Validation(format!(
+                    "debootstrap has no script for suite '{}' in {}; upgrade debootstrap \
+                    or use the mmdebstrap backend",
+                    name,
+                    debootstrap::SCRIPTS_DIR
+                )));
+            }
+        }
+        if let Some(name) = self.preset() {
+            return self.validate_preset(name);
+        }
+        if self.distribution() == Distribution::Ubuntu {
+            return self.validate_ubuntu();
+        }
+        if let Some(closest) = suite::suggest(name) {
+            return Err(RsdebstrapError::Validation(format!(
+                "bootstrap suite '{}' is not a Debian release; did you mean '{}'?",
+                name, closest
+            )));
+        }
+        let Some(release) = suite::resolve(name) else {
+            return Ok(());
+        };
+        if self.mirror_urls().next().is_some() {
+            return Ok(());
+        }
+        if let Some(arch) = self
+            .architectures()
+            .into_iter()
+            .find(|arch| !release.architectures.contains(arch))
+        {
+            return Err(RsdebstrapError::Validation(format!(
+                "Debian {} is not built for architecture '{}' (available: {}); set the \
+                bootstrap mirrors to an archive that carries it, such as debian-ports",
+                release.codename,
+                arch,
+                release.architectures.join(", ")
+            )));
+        }
+        Ok(())
+    }
+
+    /// Validates an Ubuntu bootstrap: no Debian suite or mirror, architectures the
+    /// Ubuntu archives build, and an installed Ubuntu keyring when it is the default one.
+    fn validate_ubuntu(&self) -> Result<(), RsdebstrapError> {
+        let name = self.suite();
+        if suite::resolve(name).is_some() {
+            return Err(RsdebstrapError::Validation(format!(
+                "bootstrap suite '{}' is a Debian release, but distribution is ubuntu; \
+                use an Ubuntu codename such as noble",
+                name
+            )));
+        }
+        if let Some(arch) = self
+            .architectures()
+            .into_iter()
+            .find(|arch| !distribution::UBUNTU_ARCHITECTURES.contains(arch))
+        {
+            return Err(RsdebstrapError::Validation(format!(
+                "Ubuntu is not built for architecture '{}' (available: {})",
+                arch,
+                distribution::UBUNTU_ARCHITECTURES.join(", ")
+            )));
+        }
+        if let Some(mirror) = self.mirror_urls().find(|m| m.contains("debian.org")) {
+            return Err(RsdebstrapError::Validation(format!(
+                "bootstrap mirror {} is a Debian archive, but distribution is ubuntu",
+                crate::bootstrap::sanitize_credential(mirror)
+            )));
+        }
+        let keyrings: &[String] = match self {
+            Bootstrap::Mmdebstrap(cfg) => &cfg.keyring,
+            Bootstrap::Debootstrap(cfg) => cfg.keyring.as_slice(),
+        };
+        let keyring = distribution::UBUNTU_KEYRING;
+        if keyrings.iter().any(|k| k == keyring) && !Utf8Path::new(keyring).is_file() {
+            return Err(RsdebstrapError::Validation(format!(
+                "the Ubuntu archive keyring {} is missing; install the ubuntu-keyring \
+                package or set the bootstrap keyring",
+                keyring
+            )));
+        }
+        Ok(())
+    }
+
+    /// Validates a preset bootstrap: a Debian distribution, a suite and architectures
+    /// the preset has (also checked at load), and an installed keyring when it is the
+    /// preset's.
+    fn validate_preset(&self, name: &str) -> Result<(), RsdebstrapError> {
+        if self.distribution() != Distribution::Debian {
+            return Err(RsdebstrapError::Validation(format!(
+                "bootstrap preset {} builds a Debian derivative, but distribution is {}",
+                name,
+                self.distribution()
+            )));
+        }
+        let preset = preset::load(name)?;
+        preset.check_suite(name, self.suite())?;
+        for arch in self.architectures() {
+            preset.check_architecture(name, arch)?;
+        }
+        let keyrings: &[String] = match self {
+            Bootstrap::Mmdebstrap(cfg) => &cfg.keyring,
+            Bootstrap::Debootstrap(cfg) => cfg.keyring.as_slice(),
+        };
+        if let Some(keyring) = &preset.keyring
+            && keyrings.iter().any(|k| k == keyring.as_str())
+            && !keyring.is_file()
+        {
+            return Err(RsdebstrapError::Validation(format!(
+                "the {} archive keyring {} is missing; install it on the host (the \
+                package is usually named after the file) or set the bootstrap keyring",
+                name, keyring
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns warnings about the suite's support on `today`: past its regular security
+    /// support, or past its end of life (with a hint to use [`suite::ARCHIVE_MIRROR`]
+    /// unless a mirror points there already).
+    pub fn suite_warnings(&self, today: suite::Date) -> Vec<String> {
+        if self.preset().is_some() {
+            return Vec::new();
+        }
+        let Some(release) = suite::resolve(self.suite()) else {
+            return Vec::new();
+        };
+        match release.support(today) {
+            suite::Support::Supported => Vec::new(),
+            suite::Support::Lts(until) => vec![format!(
+                "Debian {} only has LTS security support, until {}",
+                release.codename, until
+            )],
+            suite::Support::EndOfLife(since) => {
+                let mut warning =
+                    format!("Debian {} reached its end of life on {}", release.codename, since);
+                if !self.mirror_urls().any(|m| m.contains("archive.debian.org")) {
+                    warning.push_str(&format!(
+                        "; its packages are only on {}, set it as the bootstrap mirror",
+                        suite::ARCHIVE_MIRROR
+                    ));
+                }
+                vec![warning]
+            }
+        }
+    }
+
+    /// Validates that the backend can honor a rootless `userns` privilege method.
+    fn validate_userns(&self) -> Result<(), RsdebstrapError> {
+        if self.resolved_privilege_method() != Some(PrivilegeMethod::Userns) {
+            return Ok(());
+        }
+        match self {
+            Bootstrap::Mmdebstrap(cfg) => {
+                if cfg.effective_mode() != Mode::Unshare {
+                    return Err(RsdebstrapError::Validation(format!(
+                        "bootstrap privilege method userns requires mmdebstrap mode unshare \
+                        (or auto), got {}",
+                        cfg.mode
+                    )));
+                }
+                Ok(())
+            }
+            Bootstrap::Debootstrap(_) => Err(RsdebstrapError::Validation(
+                "bootstrap privilege method userns is not supported by debootstrap; \
+                use the mmdebstrap backend"
+                    .to_string(),
+            )),
+        }
+    }
+}
+
+/// How a filesystem is unmounted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnmountMode {
+    /// A plain unmount, which fails while the filesystem is busy.
+    Normal,
+    /// Detach the filesystem now and clean it up once it is no longer busy (`umount -l`).
+    Lazy,
+    /// Force the unmount (`umount -f`).
+    Force,
+}
+
+impl UnmountMode {
+    /// Returns the `umount` flag selecting this mode, if any.
+    pub fn flag(self) -> Option<&'static str> {
+        match self {
+            Self::Normal => None,
+            Self::Lazy => Some("-l"),
+            Self::Force => Some("-f"),
+        }
+    }
+}
+
+impl std::fmt::Display for UnmountMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Normal => "normal",
+            Self::Lazy => "lazy",
+            Self::Force => "forced",
+        })
+    }
+}
+
+/// Delay between unmount retries when `retry_delay_ms` is not set.
+pub const DEFAULT_UNMOUNT_RETRY_DELAY_MS: u64 = 1000;
+
+/// Escalation applied when unmounting a filesystem fails, typically because a process
+/// started inside the rootfs (gpg-agent, udev, …) still holds it busy.
+///
+/// A failed unmount is retried `retries` times, then tried lazily (`lazy`), then forced
+/// (`force`). The default makes a single plain attempt.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct UnmountPolicy {
+    /// Plain unmount retries before escalating (default: 0).
+    #[serde(default)]
+    pub retries: u32,
+    /// Milliseconds to wait before each retry (default: 1000).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_delay_ms: Option<u64>,
+    /// Then detach the filesystem with a lazy unmount, `umount -l` (default: false).
+    #[serde(default)]
+    pub lazy: bool,
+    /// Then force the unmount, `umount -f` (default: false).
+    #[serde(default)]
+    pub force: bool,
+}
+
+impl UnmountPolicy {
+    /// Returns the delay before each retry.
+    pub fn retry_delay(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(
+            self.retry_delay_ms
+                .unwrap_or(DEFAULT_UNMOUNT_RETRY_DELAY_MS),
+        )
+    }
+
+    /// Returns the escalation steps tried after the retries, in order.
+    pub fn escalation(&self) -> impl Iterator<Item = UnmountMode> {
+        [
+            (self.lazy, UnmountMode::Lazy),
+            (self.force, UnmountMode::Force),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, mode)| enabled.then_some(mode))
+    }
+}
+
+/// Isolation backend configuration.
+///
+/// The `type` key selects the backend used to run commands inside the rootfs: `chroot`, or
+/// `pivot_root`, which takes the same options. `type` is required whenever an `isolation` map is written
+/// out — the chroot default applies only when the surrounding `isolation` key (e.g.
+/// `defaults.isolation`) is omitted entirely.
+// Internally tagged like `Bootstrap` (rather than a plain struct) so each backend keeps its
+// own payload struct as an extension point for backend-specific options (bwrap, nspawn, …).
+// `deny_unknown_fields` would be a serde no-op on the enum itself, so strictness lives on
+// the per-variant payload structs: serde consumes the `type` tag when selecting the variant
+// and hands only the remaining keys to the payload, whose `deny_unknown_fields` then
+// rejects typo'd keys (see the `Bootstrap` note in ARCHITECTURE.md).
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum IsolationConfig {
+    /// Run commands inside the rootfs via `chroot`.
+    Chroot(ChrootIsolation),
+    /// Run commands in a private mount namespace whose root is the rootfs, via
+    /// `pivot_root`, so they can neither escape to the host's root nor change its mount
+    /// table.
+    #[serde(rename = "pivot_root")]
+    PivotRoot(ChrootIsolation),
+}
+
+/// `PATH` inside the chroot when `clean_env` is set without an explicit `path`: the
+/// default `PATH` of Debian's root account.
+pub const CHROOT_DEFAULT_PATH: &str =
+    "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+
+/// Options for the `chroot` isolation backend.
+// A braced (named-field) struct, not a unit struct: internally tagged variants need a
+// map-shaped payload to serialize, and only the braced form gives `deny_unknown_fields` a
+// struct visitor that rejects `{type: chroot, <typo>: ...}`.
+#[derive(Debug, Default, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct ChrootIsolation {
+    /// Allow network access (default: true). `false` runs each command in a new,
+    /// empty network namespace.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub network: Option<bool>,
+    /// Working directory inside the chroot for task commands (absolute path; default: `/`).
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_path",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<crate::schema::Utf8PathSchema>")
+    )]
+    pub workdir: Option<Utf8PathBuf>,
+    /// User to run task commands as, written `user[:group]` with names or numeric ids
+    /// (default: root). Passed to `chroot --userspec`.
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub user: Option<String>,
+    /// Run task commands with an empty environment (`env -i`) instead of inheriting the
+    /// host's, keeping only `PATH`, `HOME` (when running as root) and `env` (default:
+    /// false).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub clean_env: bool,
+    /// `PATH` inside the chroot, as `:`-separated absolute directories (default: the
+    /// inherited `PATH`, or [`CHROOT_DEFAULT_PATH`] with `clean_env`).
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub path: Option<String>,
+    /// Extra environment variables set inside the chroot.
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<BTreeMap<String, String>>")
+    )]
+    pub env: BTreeMap<String, String>,
+    /// Extra bind mounts set up for the duration of each task.
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<ChrootBind>>"))]
+    pub binds: Vec<ChrootBind>,
+    /// Escalation for unmounts that fail because the filesystem is busy (default: a
+    /// single plain attempt). On `defaults.isolation` it also covers the `prepare.mount`
+    /// and `context` mounts; a task's own isolation covers its `binds` and `mounts`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unmount_policy: Option<UnmountPolicy>,
+    /// Host name-resolution files copied into the rootfs for the prepare and provision
+    /// phases and restored before assemble, like a `prepare.resolv_conf` copy. Only
+    /// read from `defaults.isolation`.
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<NetworkFile>>"))]
+    pub network_files: Vec<NetworkFile>,
+    /// Keep package maintainer scripts from starting daemons during the prepare and
+    /// provision phases by installing a `policy-rc.d` that exits 101 and diverting
+    /// `start-stop-daemon`, both undone before assemble (default: false). Only read from
+    /// `defaults.isolation`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub block_services: bool,
+    /// Run the pipeline in a private mount namespace, so its mounts never show up in the
+    /// host's mount table and vanish with rsdebstrap even if it is killed (default:
+    /// true). Takes effect only when rsdebstrap runs as root; otherwise the default
+    /// warns and an explicit `true` fails the build. Only read from
+    /// `defaults.isolation`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mount_namespace: Option<bool>,
+}
+
+impl ChrootIsolation {
+    /// Returns the `/usr/bin/env` arguments (after `env` itself) that apply `workdir`,
+    /// `clean_env`, `path` and `env`, or an empty list when none is set.
+    ///
+    /// `home` is the `user`'s home directory in the rootfs, if known; it becomes `HOME`.
+    /// Otherwise `clean_env` sets `HOME=/root` when running as root.
+    pub fn env_args(&self, home: Option<&str>) -> Vec<String> {
+        let mut args = Vec::new();
+        if self.clean_env {
+            args.push("-i".to_string());
+        }
+        if let Some(workdir) = &self.workdir {
+            args.push(format!("--chdir={}", workdir));
+        }
+        let path = self
+            .path
+            .as_deref()
+            .or(self.clean_env.then_some(CHROOT_DEFAULT_PATH));
+        if let Some(path) = path {
+            args.push(format!("PATH={}", path));
+        }
+        if let Some(home) = home {
+            args.push(format!("HOME={}", home));
+        } else if self.clean_env && self.user.is_none() {
+            args.push("HOME=/root".to_string());
+        }
+        args.extend(
+            self.env
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value)),
+        );
+        args
+    }
+
+    /// Validates the chroot options: `workdir` is absolute without `..`, `user` is a
+    /// well-formed `user[:group]`, `path` lists absolute directories, `env` names are
+    /// valid variable names other than `PATH`, `network_files` has no duplicates, and
+    /// every bind is a valid bind mount whose host source exists.
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        if let Some(workdir) = &self.workdir {
+            if !workdir.starts_with("/") {
+                return Err(RsdebstrapError::Validation(format!(
+                    "chroot workdir '{}' must be an absolute path",
+                    workdir
+                )));
+            }
+            crate::phase::validate_no_parent_dirs(workdir, "chroot workdir")?;
+        }
+
+        if let Some(user) = &self.user {
+            let valid_name = |name: &str| {
+                !name.is_empty()
+                    && !name.starts_with('-')
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+            };
+            let valid = match user.split_once(':') {
+                Some((name, group)) => valid_name(name) && valid_name(group),
+                None => valid_name(user),
+            };
+            if !valid {
+                return Err(RsdebstrapError::Validation(format!(
+                    "chroot user '{}' must be 'user' or 'user:group' (names or numeric ids)",
+                    user
+                )));
+            }
+        }
+
+        if let Some(path) = &self.path
+            && (path.split(':').any(|dir| !dir.starts_with('/'))
+                || path.contains(['\0', '\n', '\r']))
+        {
+            return Err(RsdebstrapError::Validation(format!(
+                "chroot path {:?} must be ':'-separated absolute directories",
+                path
+            )));
+        }
+
+        for (name, value) in &self.env {
+            let valid_name = name
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid_name {
+                return Err(RsdebstrapError::Validation(format!(
+                    "chroot env name {:?} must be letters, digits and '_', not starting with \
+                    a digit",
+                    name
+                )));
+            }
+            if name == "PATH" {
+                return Err(RsdebstrapError::Validation(
+                    "chroot env must not set PATH; use 'path' instead".to_string(),
+                ));
+            }
+            if value.contains('\0') {
+                return Err(RsdebstrapError::Validation(format!(
+                    "chroot env {} must not contain a null character",
+                    name
+                )));
+            }
+        }
+
+        for (index, file) in self.network_files.iter().enumerate() {
+            if self.network_files[..index].contains(file) {
+                return Err(RsdebstrapError::Validation(format!(
+                    "network_files lists {} more than once",
+                    file
+                )));
+            }
+        }
+
+        for bind in &self.binds {
+            bind.to_mount_entry().validate()?;
+            if !bind.source.exists() {
+                return Err(RsdebstrapError::Validation(format!(
+                    "chroot bind source not found: {}",
+                    bind.source
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A host file that `network_files` copies into the rootfs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkFile {
+    /// `/etc/hosts`
+    Hosts,
+    /// `/etc/nsswitch.conf`
+    Nsswitch,
+}
+
+impl NetworkFile {
+    /// Returns the file's absolute path, on the host and inside the rootfs.
+    pub fn path(self) -> &'static str {
+        match self {
+            Self::Hosts => "/etc/hosts",
+            Self::Nsswitch => "/etc/nsswitch.conf",
+        }
+    }
+}
+
+impl std::fmt::Display for NetworkFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.path())
+    }
+}
+
+/// A host path bind-mounted into the rootfs while a task runs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct ChrootBind {
+    /// Host path to bind (absolute path).
+    #[serde(deserialize_with = "crate::de::path")]
+    #[cfg_attr(feature = "schema", schemars(with = "crate::schema::Utf8PathSchema"))]
+    pub source: Utf8PathBuf,
+    /// Mount point inside the rootfs (absolute path).
+    #[serde(deserialize_with = "crate::de::path")]
+    #[cfg_attr(feature = "schema", schemars(with = "crate::schema::Utf8PathSchema"))]
+    pub target: Utf8PathBuf,
+    /// Mount read-only (default: false).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ro: bool,
+}
+
+impl ChrootBind {
+    /// Returns the equivalent `bind` (and, with `ro`, read-only) mount entry.
+    pub fn to_mount_entry(&self) -> MountEntry {
+        let mut options = vec!["bind".to_string()];
+        if self.ro {
+            options.push("ro".to_string());
+        }
+        MountEntry {
+            source: self.source.to_string(),
+            target: self.target.clone(),
+            options,
+        }
+    }
+}
+
+impl Default for IsolationConfig {
+    /// The backend used when no `isolation` key is configured: chroot.
+    fn default() -> Self {
+        Self::chroot()
+    }
+}
+
+impl IsolationConfig {
+    /// Creates a default chroot config.
+    pub fn chroot() -> Self {
+        Self::Chroot(ChrootIsolation::default())
+    }
+
+    /// Returns the backend's name, as written in `type`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Chroot(_) => "chroot",
+            Self::PivotRoot(_) => "pivot_root",
+        }
+    }
+
+    /// Returns the backend's options, which every backend shares.
+    pub fn options(&self) -> &ChrootIsolation {
+        match self {
+            Self::Chroot(cfg) | Self::PivotRoot(cfg) => cfg,
+        }
+    }
+
+    /// Returns the backend's options for modification.
+    pub fn options_mut(&mut self) -> &mut ChrootIsolation {
+        match self {
+            Self::Chroot(cfg) | Self::PivotRoot(cfg) => cfg,
+        }
+    }
+
+    /// Returns the explicitly configured network setting, if any.
+    pub fn network(&self) -> Option<bool> {
+        self.options().network
+    }
+
+    /// Validates the backend-specific options.
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        self.options().validate()
+    }
+
+    /// Returns the unmount escalation policy (the default when not configured).
+    pub fn unmount_policy(&self) -> UnmountPolicy {
+        self.options().unmount_policy.unwrap_or_default()
+    }
+
+    /// Returns the host files copied into the rootfs for the pipeline.
+    pub fn network_files(&self) -> &[NetworkFile] {
+        &self.options().network_files
+    }
+
+    /// Returns whether service starts are blocked for the pipeline.
+    pub fn block_services(&self) -> bool {
+        self.options().block_services
+    }
+
+    /// Returns whether the pipeline runs in a private mount namespace.
+    pub fn mount_namespace(&self) -> bool {
+        self.options().mount_namespace.unwrap_or(true)
+    }
+
+    /// Returns whether `mount_namespace: true` is set explicitly, making a build that
+    /// cannot enter the namespace fail instead of warning.
+    pub fn requires_mount_namespace(&self) -> bool {
+        self.options().mount_namespace == Some(true)
+    }
+
+    /// Returns whether the backend mounts anything for each task.
+    pub fn has_binds(&self) -> bool {
+        !self.options().binds.is_empty()
+    }
+
+    /// Returns a boxed isolation provider instance.
+    ///
+    /// This allows calling `IsolationProvider` methods without matching
+    /// on each variant explicitly. `privilege` is the task's privilege method,
+    /// used for the backend's own setup (e.g. chroot bind mounts).
+    pub fn as_provider(&self, privilege: Option<PrivilegeMethod>) -> Box<dyn IsolationProvider> {
+        match self {
+            Self::Chroot(cfg) => Box::new(ChrootProvider::new(cfg.clone(), privilege)),
+            Self::PivotRoot(cfg) => Box::new(ChrootProvider::pivot_root(cfg.clone(), privilege)),
+        }
+    }
+}
+
+/// Default settings for mitamae tasks.
+///
+/// Allows specifying architecture-specific binary paths that apply to all
+/// mitamae tasks unless overridden at the task level.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct MitamaeDefaults {
+    /// Architecture-specific binary paths (key: "x86_64", "aarch64", etc.)
+    #[serde(
+        default,
+        deserialize_with = "crate::de::path_map",
+        serialize_with = "serialize_sorted"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(
+            with = "Option<std::collections::HashMap<String, crate::schema::Utf8PathSchema>>"
+        )
+    )]
+    pub binary: HashMap<String, Utf8PathBuf>,
+    /// Official mitamae release (e.g. `"1.14.1"`) downloaded for the target
+    /// architecture when `binary` has no entry for it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// SHA-256 digests of the release tarballs, by architecture (same keys as `binary`)
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        serialize_with = "serialize_sorted",
+        skip_serializing_if = "HashMap::is_empty"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<std::collections::HashMap<String, String>>")
+    )]
+    pub sha256: HashMap<String, String>,
+}
+
+impl MitamaeDefaults {
+    /// Returns true if no mitamae defaults are configured.
+    pub fn is_empty(&self) -> bool {
+        self.binary.is_empty() && self.version.is_none() && self.sha256.is_empty()
+    }
+
+    /// Returns the release to download for `arch` (a Rust architecture name).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RsdebstrapError::Validation`] if no `version` is set, mitamae has no
+    /// release for `arch`, or `sha256` has no digest for it.
+    pub fn release(&self, arch: &str) -> Result<MitamaeRelease, RsdebstrapError> {
+        let Some(version) = &self.version else {
+            return Err(RsdebstrapError::Validation(format!(
+                "no mitamae binary for target architecture '{}': set 'binary' or 'url' on \
+                the task, 'defaults.mitamae.binary.{}', or 'defaults.mitamae.version' to \
+                download the release",
+                arch, arch
+            )));
+        };
+        let Some(release_arch) = mitamae::release_arch(arch) else {
+            return Err(RsdebstrapError::Validation(format!(
+                "mitamae publishes no Linux release for target architecture '{}'; set \
+                'defaults.mitamae.binary.{}' instead",
+                arch, arch
+            )));
+        };
+        let Some(sha256) = self.sha256.get(arch) else {
+            return Err(RsdebstrapError::Validation(format!(
+                "defaults.mitamae.sha256.{} is required to download mitamae {} for the \
+                target architecture",
+                arch, version
+            )));
+        };
+        Ok(MitamaeRelease::new(version, release_arch, sha256))
+    }
+
+    /// Validates that `version` is a release number and `sha256` holds digests for it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RsdebstrapError::Validation`] otherwise.
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        if let Some(version) = &self.version {
+            let parts: Vec<&str> = version.split('.').collect();
+            if parts.len() != 3
+                || !parts
+                    .iter()
+                    .all(|p| !p.is_empty() && p.chars().all(|c| c.is_ascii_digit()))
+            {
+                return Err(RsdebstrapError::Validation(format!(
+                    "defaults.mitamae.version '{}' must be a release number such as 1.14.1; \
+                    sha256 pins a single release",
+                    version
+                )));
+            }
+        } else if !self.sha256.is_empty() {
+            return Err(RsdebstrapError::Validation(
+                "defaults.mitamae.sha256 needs defaults.mitamae.version".to_string(),
+            ));
+        }
+        let mut archs: Vec<&String> = self.sha256.keys().collect();
+        archs.sort();
+        for arch in archs {
+            crate::download::validate_sha256(
+                &self.sha256[arch],
+                &format!("defaults.mitamae.sha256.{}", arch),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Restrictions on the commands of tasks run with `isolation: false`
+/// (`defaults.sandbox`).
+///
+/// Such commands run on the host; with a sandbox they run through
+/// `rsdebstrap sandbox-exec`, which applies the restrictions after privilege
+/// escalation and then executes the command (see [`crate::sandbox`]).
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct SandboxConfig {
+    /// Restrict writes to the rootfs, the run directory, `/dev/null` and `writable`
+    /// with Landlock (default: true).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub landlock: Option<bool>,
+    /// Block syscalls that change the host (mounts, module loading, `kexec`, reboot,
+    /// `ptrace`, `bpf`, ...) with a seccomp filter (default: true).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seccomp: Option<bool>,
+    /// Further host paths the commands may write to (absolute paths).
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<Vec<crate::schema::Utf8PathSchema>>")
+    )]
+    pub writable: Vec<Utf8PathBuf>,
+}
+
+impl SandboxConfig {
+    /// Returns true if writes are restricted with Landlock.
+    pub fn landlock(&self) -> bool {
+        self.landlock.unwrap_or(true)
+    }
+
+    /// Returns true if syscalls are filtered with seccomp.
+    pub fn seccomp(&self) -> bool {
+        self.seccomp.unwrap_or(true)
+    }
+
+    /// Validates that the sandbox restricts something and `writable` holds absolute
+    /// paths.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RsdebstrapError::Validation`] otherwise.
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        if !self.landlock() && !self.seccomp() {
+            return Err(RsdebstrapError::Validation(
+                "defaults.sandbox disables both landlock and seccomp; remove it instead"
+                    .to_string(),
+            ));
+        }
+        if let Some(path) = self.writable.iter().find(|p| !p.is_absolute()) {
+            return Err(RsdebstrapError::Validation(format!(
+                "defaults.sandbox.writable: '{}' must be an absolute path",
+                path
+            )));
+        }
+        if !self.writable.is_empty() && !self.landlock() {
+            return Err(RsdebstrapError::Validation(
+                "defaults.sandbox.writable needs landlock".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Per-architecture settings of a profile `targets` entry.
+///
+/// `--arch <name>` selects the entry keyed by the Debian architecture `<name>` when
+/// the profile is loaded (see [`Profile::select_target`]); each field that is set
+/// replaces the shared setting.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct TargetConfig {
+    /// Output directory replacing `dir` (relative paths resolve against the profile),
+    /// so the architectures do not build into one another's tree.
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_path",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<crate::schema::Utf8PathSchema>")
+    )]
+    pub dir: Option<Utf8PathBuf>,
+    /// Mirrors replacing the bootstrap's (`mirrors`, or debootstrap's single `mirror`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirrors: Option<Vec<String>>,
+    /// Packages replacing the bootstrap's `include` list.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub include: Option<Vec<String>>,
+    /// mitamae binary for the mitamae tasks that set neither `binary` nor `url`
+    /// (relative paths resolve against the profile).
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_path",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<crate::schema::Utf8PathSchema>")
+    )]
+    pub mitamae_binary: Option<Utf8PathBuf>,
+}
+
+/// Returns whether `arch` looks like a Debian architecture name (`amd64`, `arm64`,
+/// `musl-linux-riscv64`).
+fn is_architecture_name(arch: &str) -> bool {
+    arch.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && arch
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+/// Serializes a map in key order, so the output does not depend on hash order.
+fn serialize_sorted<S, T>(map: &HashMap<String, T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+    T: Serialize,
+{
+    map.iter()
+        .collect::<std::collections::BTreeMap<_, _>>()
+        .serialize(serializer)
+}
+
+/// Default settings that apply across the profile.
+///
+/// Groups configuration defaults like isolation backend.
+/// If omitted in YAML, all fields use their respective defaults.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct Defaults {
+    /// Isolation backend for running commands in rootfs (default: chroot)
+    #[serde(default)]
+    pub isolation: IsolationConfig,
+    /// Default settings for mitamae tasks
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "MitamaeDefaults::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<MitamaeDefaults>"))]
+    pub mitamae: MitamaeDefaults,
+    /// Default privilege escalation settings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub privilege: Option<PrivilegeDefaults>,
+    /// Where provision tasks stage their scripts and binaries inside the rootfs
+    /// (default: `tmp`)
+    #[serde(default)]
+    pub staging: Staging,
+    /// Umask of the build, e.g. `"0077"` (optional; default: the caller's).
+    ///
+    /// Set for rsdebstrap and every command it runs, and applied to the scripts,
+    /// recipes and binaries tasks stage in the rootfs. It must leave the owner's
+    /// permissions alone.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<crate::schema::FileModeSchema>")
+    )]
+    pub umask: Option<FileMode>,
+    /// Landlock and seccomp restrictions on the commands of tasks run with
+    /// `isolation: false` (optional; none by default).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<SandboxConfig>,
+    /// Lint shell task scripts with `shellcheck` (or a built-in scanner) before the
+    /// build (optional; not linted by default).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shellcheck: Option<ShellcheckConfig>,
+}
+
+/// Represents a bootstrap profile configuration.
+///
+/// A profile contains the target directory and bootstrap tool configuration
+/// details needed to create a Debian-based system.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Profile format version (optional, default: 1).
+    ///
+    /// A profile declaring a version newer than this rsdebstrap supports is rejected
+    /// with an upgrade hint.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u32>,
+    /// Target directory path for the bootstrap operation
+    #[serde(deserialize_with = "crate::de::path")]
+    #[cfg_attr(feature = "schema", schemars(with = "crate::schema::Utf8PathSchema"))]
+    pub dir: Utf8PathBuf,
+    /// Space the build needs in `dir`, e.g. `8G` (optional).
+    ///
+    /// Checked against the free space before the bootstrap starts. Without it the
+    /// space is estimated from the bootstrap variant and `include` list; `0` skips
+    /// the check.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<crate::schema::ByteSizeSchema>")
+    )]
+    pub estimated_size: Option<ByteSize>,
+    /// Which existing `dir` `apply` may build into (default: any).
+    ///
+    /// With `owned`, an existing non-empty `dir` must carry the marker rsdebstrap
+    /// writes into directories it creates; `--allow-existing` overrides it.
+    #[serde(default, skip_serializing_if = "DirPolicy::is_default")]
+    pub dir_policy: DirPolicy,
+    /// Default settings (isolation backend, etc.)
+    #[serde(default, deserialize_with = "crate::de::null_to_default")]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Defaults>"))]
+    pub defaults: Defaults,
+    /// Bootstrap tool configuration
+    pub bootstrap: Bootstrap,
+    /// Per-architecture overrides keyed by Debian architecture (optional).
+    ///
+    /// `apply --arch <name>` (and `validate`, `diff`, `doctor`) builds the profile for
+    /// that target; without `--arch` the shared settings are used as written.
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<BTreeMap<String, TargetConfig>>")
+    )]
+    pub targets: BTreeMap<String, TargetConfig>,
+    /// Prepare tasks to run before provisioning (optional)
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "PrepareConfig::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<PrepareConfig>"))]
+    pub prepare: PrepareConfig,
+    /// Main provisioning tasks (optional)
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<ProvisionTask>>"))]
+    pub provision: Vec<ProvisionTask>,
+    /// Assemble tasks to run after provisioning (optional)
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "AssembleConfig::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<AssembleConfig>"))]
+    pub assemble: AssembleConfig,
+    /// Checks of the finished rootfs, run after the assemble phase (optional)
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<VerifyTask>>"))]
+    pub verify: Vec<VerifyTask>,
+    /// What the provision and assemble phases do when a task fails (default:
+    /// `fail_fast` for both).
+    #[serde(default, skip_serializing_if = "FailurePolicies::is_default")]
+    pub failure_policy: FailurePolicies,
+    /// Forbid network access after the bootstrap (default: false).
+    ///
+    /// The bootstrap itself still downloads packages; afterwards each provision task
+    /// runs in a new, empty network namespace, and a task or isolation config that
+    /// explicitly sets `network: true` is rejected. So are `prepare.download`,
+    /// `upload` and task binaries downloaded from a URL (a mitamae `url` or
+    /// `defaults.mitamae.version`).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub offline: bool,
+    /// Route apt downloads through a caching proxy (optional).
+    ///
+    /// Applies to the bootstrap and to apt inside the rootfs during provisioning.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub apt_cache: Option<AptCacheConfig>,
+    /// Build from snapshot.debian.org as of this UTC time (optional), e.g.
+    /// `2024-06-01T00:00:00Z` or `2024-06-01`.
+    ///
+    /// The bootstrap's Debian mirrors are replaced by their snapshots, which apt inside
+    /// the rootfs then uses too.
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub snapshot: Option<String>,
+    /// Keyrings the bootstrap backend should trust for repository verification
+    /// (optional).
+    ///
+    /// Each entry is a local `path` or a `url` pinned with `sha256`; the keyrings are
+    /// passed to the backend as `--keyring` options.
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<KeyringSource>>"))]
+    pub keyrings: Vec<KeyringSource>,
+    /// Host directory shared read-only with every task (optional).
+    ///
+    /// Bind-mounted at `/run/rsdebstrap/context` in the rootfs for the whole
+    /// pipeline, so scripts and recipes can read shared assets from there.
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_path",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<crate::schema::Utf8PathSchema>")
+    )]
+    pub context: Option<Utf8PathBuf>,
+    /// Write checksums of the build's artifacts and optionally sign them (optional).
+    ///
+    /// Runs after the assemble phase; the sums files are written to `dir`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksums: Option<ChecksumConfig>,
+    /// Upload the build's artifacts to S3-compatible object storage (optional).
+    ///
+    /// Runs after `checksums`; credentials are read from the environment.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub upload: Option<UploadConfig>,
+    /// Owner and mode to give `dir` and the rootfs at the end of the build (optional).
+    ///
+    /// Runs last, with `defaults.privilege`, also after a failed build, e.g. to hand a
+    /// sudo-built tree back to the CI user who has to remove it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_permissions: Option<OutputPermissions>,
+    /// Secrets read from the environment, files or commands at build time (optional).
+    ///
+    /// Provision tasks receive them as environment variables or files, bootstrap
+    /// mirrors refer to them as `${secret:NAME}`, and their values are masked in logs.
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<BTreeMap<String, SecretConfig>>")
+    )]
+    pub secrets: BTreeMap<String, SecretConfig>,
+}
+
+impl Profile {
+    /// Serializes the profile to YAML.
+    ///
+    /// A profile returned by [`load_profile`] or [`ProfileBuilder::build`] round-trips:
+    /// its paths are already resolved, and privilege, isolation and network settings are
+    /// written in their resolved form, so loading the output yields an equal profile.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Config` if the profile cannot be serialized.
+    pub fn to_yaml(&self) -> Result<String, RsdebstrapError> {
+        yaml_serde::to_string(self)
+            .map_err(|e| RsdebstrapError::Config(format!("failed to serialize profile: {}", e)))
+    }
+
+    /// Applies the `targets` entry for `arch` and drops the `targets` map.
+    ///
+    /// The bootstrap then builds `arch` alone (mmdebstrap `architectures`, debootstrap
+    /// `arch`), the entry's `dir`, `mirrors` and `include` replace the shared ones, and
+    /// its `mitamae_binary` becomes the binary of every mitamae task that sets neither
+    /// `binary` nor `url`. Call it before the profile's paths are resolved.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if the profile has no target `arch`, or if
+    /// the target gives debootstrap more than one mirror.
+    pub fn select_target(&mut self, arch: &str) -> Result<(), RsdebstrapError> {
+        let Some(target) = self.targets.remove(arch) else {
+            let available: Vec<&str> = self.targets.keys().map(String::as_str).collect();
+            return Err(RsdebstrapError::Validation(if available.is_empty() {
+                format!("--arch {}: the profile defines no targets", arch)
+            } else {
+                format!(
+                    "--arch {}: the profile has no such target (available: {})",
+                    arch,
+                    available.join(", ")
+                )
+            }));
+        };
+        self.targets.clear();
+
+        if let Some(dir) = target.dir {
+            self.dir = dir;
+        }
+        match &mut self.bootstrap {
+            Bootstrap::Mmdebstrap(cfg) => {
+                cfg.architectures = vec![arch.to_string()];
+                if let Some(mirrors) = target.mirrors {
+                    cfg.mirrors = mirrors;
+                }
+                if let Some(include) = target.include {
+                    cfg.include = include;
+                }
+            }
+            Bootstrap::Debootstrap(cfg) => {
+                cfg.arch = Some(arch.to_string());
+                if let Some(mirrors) = target.mirrors {
+                    if mirrors.len() > 1 {
+                        return Err(RsdebstrapError::Validation(format!(
+                            "targets.{}.mirrors: debootstrap takes a single mirror, got {}",
+                            arch,
+                            mirrors.len()
+                        )));
+                    }
+                    cfg.mirror = mirrors.into_iter().next();
+                }
+                if let Some(include) = target.include {
+                    cfg.include = include;
+                }
+            }
+        }
+        if let Some(binary) = &target.mitamae_binary {
+            for task in &mut self.provision {
+                if let ProvisionTask::Mitamae(mitamae_task) = task {
+                    mitamae_task.set_binary_if_absent(binary);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates a `Pipeline` from this profile's task phases.
+    pub fn pipeline(&self) -> Pipeline<'_> {
+        Pipeline::new(&self.prepare, &self.provision, &self.assemble)
+            .with_verify(&self.verify, self.defaults.privilege.as_ref().map(|d| d.method))
+            .with_failure_policy(self.failure_policy)
+    }
+
+    /// Returns the mounts that bracket the whole pipeline: the resolved `prepare.mount`
+    /// entries, followed by the read-only `context` bind mount and the staging tmpfs if
+    /// configured.
+    pub fn pipeline_mounts(&self) -> Vec<MountEntry> {
+        let mut mounts = self
+            .prepare
+            .mount
+            .as_ref()
+            .map(|m| m.resolved_mounts())
+            .unwrap_or_default();
+        if let Some(context) = &self.context {
+            mounts.push(MountEntry {
+                source: context.to_string(),
+                target: Utf8PathBuf::from(CONTEXT_MOUNT_POINT),
+                options: vec!["bind".to_string(), "ro".to_string()],
+            });
+        }
+        mounts.extend(self.defaults.staging.tmpfs_mount());
+        mounts
+    }
+
+    /// Returns the artifact `checksums` hashes when it lists none: the bootstrap output,
+    /// relative to `dir`, if it is an archive or image rather than a directory. A
+    /// streamed output has no file of its own.
+    pub fn default_checksum_artifact(&self) -> Option<&Utf8Path> {
+        if !self.bootstrap.stream_to().is_empty() {
+            return None;
+        }
+        match self.bootstrap.as_backend().rootfs_output(&self.dir) {
+            Ok(RootfsOutput::NonDirectory { .. }) => Some(Utf8Path::new(self.bootstrap.target())),
+            _ => None,
+        }
+    }
+
+    /// Returns the distinct privilege methods the build will use, in first-use order.
+    ///
+    /// Covers the bootstrap backend, the prepare-phase mounts, resolv.conf and apt
+    /// proxy setup, the verify checks and `output_permissions` (which use
+    /// `defaults.privilege`), the prepare download task, and every provision and
+    /// assemble task.
+    /// Should only be called on a profile returned by [`load_profile`], whose
+    /// privilege settings are already resolved.
+    pub fn privilege_methods(&self) -> Vec<PrivilegeMethod> {
+        let prepare_uses_privilege = self.prepare.mount.as_ref().is_some_and(|m| m.has_mounts())
+            || self.prepare.resolv_conf.is_some()
+            || ((self.apt_cache.is_some()
+                || self.context.is_some()
+                || self.defaults.staging == Staging::Tmpfs)
+                && !self.pipeline().is_empty());
+        let prepare_method = if prepare_uses_privilege {
+            self.defaults.privilege.as_ref().map(|d| d.method)
+        } else {
+            None
+        };
+
+        let candidates = std::iter::once(self.bootstrap.resolved_privilege_method())
+            .chain(std::iter::once(prepare_method))
+            .chain(
+                self.prepare
+                    .download
+                    .iter()
+                    .map(|t| t.resolved_privilege_method()),
+            )
+            .chain(self.provision.iter().map(|t| t.resolved_privilege_method()))
+            .chain(
+                self.assemble
+                    .extract
+                    .iter()
+                    .map(|t| t.resolved_privilege_method()),
+            )
+            .chain(
+                self.assemble
+                    .resolv_conf
+                    .iter()
+                    .map(|t| t.resolved_privilege_method()),
+            )
+            .chain(
+                self.assemble
+                    .sanitize
+                    .iter()
+                    .map(|t| t.resolved_privilege_method()),
+            )
+            .chain(
+                self.assemble
+                    .clean
+                    .iter()
+                    .map(|t| t.resolved_privilege_method()),
+            )
+            .chain(
+                self.assemble
+                    .release
+                    .iter()
+                    .map(|t| t.resolved_privilege_method()),
+            )
+            .chain(
+                self.assemble
+                    .relabel
+                    .iter()
+                    .map(|t| t.resolved_privilege_method()),
+            )
+            .chain(
+                (!self.verify.is_empty() || self.output_permissions.is_some())
+                    .then(|| self.defaults.privilege.as_ref().map(|d| d.method)),
+            );
+
+        let mut methods = Vec::new();
+        for method in candidates.flatten() {
+            if !methods.contains(&method) {
+                methods.push(method);
+            }
+        }
+        methods
+    }
+
+    /// Validate configuration semantics beyond basic deserialization.
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        output_dir::validate_dir(&self.dir)?;
+        if self.dir.exists() && !self.dir.is_dir() {
+            return Err(RsdebstrapError::Validation(format!(
+                "dir must be a directory: {}",
+                self.dir
+            )));
+        }
+
+        // Validate the bootstrap backend can run rootless
+        self.bootstrap.validate_userns()?;
+
+        // Validate the suite and architectures against the release table
+        self.bootstrap.validate_suite()?;
+
+        // Validate the mirror failover list
+        self.bootstrap.validate_mirrors()?;
+
+        // Validate streaming the bootstrap archive into other commands
+        self.bootstrap.validate_stream()?;
+
+        // Validate mounts configuration
         self.validate_mounts()?;
 
+        // Validate the shared context directory
+        self.validate_context()?;
+
+        // Validate the task staging mode
+        self.validate_staging()?;
+
+        // Validate the build umask
+        self.validate_umask()?;
+
+        // Validate that an offline build downloads and uploads nothing
+        self.validate_offline()?;
+
+        // Validate the mitamae release pins
+        self.defaults.mitamae.validate()?;
+
+        // Validate the direct-execution sandbox
+        if let Some(sandbox) = &self.defaults.sandbox {
+            sandbox.validate()?;
+        }
+
+        // Validate the shell task linting thresholds
+        if let Some(shellcheck) = &self.defaults.shellcheck {
+            shellcheck.validate()?;
+        }
+
+        // Validate the per-architecture targets
+        self.validate_targets()?;
+
         // Validate resolv_conf configuration
         self.validate_resolv_conf()?;
 
+        if let Some(apt_cache) = &self.apt_cache {
+            apt_cache.validate()?;
+        }
+
+        // Validate the snapshot.debian.org timestamp
+        self.validate_snapshot()?;
+
+        // Validate keyrings configuration
+        self.validate_keyrings()?;
+
+        // Validate secrets and the mirrors referring to them
+        self.validate_secrets()?;
+
+        // Validate artifact checksums and the signing tool
+        if let Some(checksums) = &self.checksums {
+            checksums.validate(self.default_checksum_artifact())?;
+            if let Some(sign) = &checksums.sign {
+                validate_command_in_path(&sign.method.to_string(), "checksum signing command")?;
+            }
+        }
+
+        // Validate the artifact upload and its request command
+        if let Some(upload) = &self.upload {
+            upload.validate(self.default_checksum_artifact())?;
+            validate_command_in_path("curl", "upload command")?;
+        }
+
+        // Validate per-task isolation options
+        self.validate_task_isolation()?;
+
+        // Validate per-task mounts against privilege and the prepare-phase mounts
+        self.validate_task_mounts()?;
+
+        // mitamae tasks need a binary for the target architecture
+        self.validate_mitamae_binaries()?;
+
+        // Task binaries given as a URL are downloaded with curl
+        if self.provision.iter().any(|t| t.binary_url().is_some()) {
+            validate_command_in_path("curl", "task binary download command")?;
+        }
+        if self
+            .provision
+            .iter()
+            .any(|t| matches!(t, ProvisionTask::Mitamae(m) if m.release().is_some()))
+        {
+            validate_command_in_path("tar", "mitamae release extraction command")?;
+        }
+        if self.prepare.download.is_some() {
+            validate_command_in_path("curl", "prepare download command")?;
+        }
+        if self
+            .provision
+            .iter()
+            .any(|t| matches!(t, ProvisionTask::Rsync(_)))
+        {
+            validate_command_in_path("rsync", "rsync task command")?;
+        }
+        for check in &self.verify {
+            if let VerifyTask::Boot(boot) = check {
+                validate_command_in_path(&boot.qemu(), "boot check command")?;
+            }
+        }
+
         // Validate all tasks across phases
         let pipeline = self.pipeline();
         pipeline.validate()?;
@@ -528,6 +2418,172 @@ impl Profile {
         Ok(())
     }
 
+    /// Validates that `snapshot` is a timestamp, without mirror failover.
+    fn validate_snapshot(&self) -> Result<(), RsdebstrapError> {
+        let Some(snapshot) = &self.snapshot else {
+            return Ok(());
+        };
+        snapshot::stamp(snapshot)?;
+        if let Some(preset) = self.bootstrap.preset() {
+            return Err(RsdebstrapError::Validation(format!(
+                "snapshot pins Debian mirrors, but bootstrap preset {} builds a derivative",
+                preset
+            )));
+        }
+        if self.bootstrap.distribution() != Distribution::Debian {
+            return Err(RsdebstrapError::Validation(format!(
+                "snapshot pins Debian mirrors, but distribution is {}",
+                self.bootstrap.distribution()
+            )));
+        }
+        if !self.bootstrap.fallback_mirrors().is_empty() {
+            return Err(RsdebstrapError::Validation(
+                "snapshot and bootstrap fallback_mirrors cannot be combined: every Debian \
+                mirror is replaced by snapshot.debian.org"
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Validates keyring-related configuration.
+    fn validate_keyrings(&self) -> Result<(), RsdebstrapError> {
+        for (index, keyring) in self.keyrings.iter().enumerate() {
+            keyring.validate().map_err(|e| match e {
+                RsdebstrapError::Validation(msg) => {
+                    RsdebstrapError::Validation(format!("keyrings[{}]: {}", index, msg))
+                }
+                other => other,
+            })?;
+        }
+
+        if let Bootstrap::Debootstrap(cfg) = &self.bootstrap {
+            let total = self.keyrings.len() + usize::from(cfg.keyring.is_some());
+            if total > 1 {
+                return Err(RsdebstrapError::Validation(format!(
+                    "debootstrap accepts a single keyring, but {} are configured across \
+                    bootstrap.keyring and keyrings",
+                    total
+                )));
+            }
+        }
+
+        if self.keyrings.iter().any(KeyringSource::is_remote) {
+            validate_command_in_path("curl", "keyring download command")?;
+        }
+        Ok(())
+    }
+
+    /// Validates the `secrets` and checks that every `${secret:NAME}` in the bootstrap
+    /// mirrors names one of them.
+    fn validate_secrets(&self) -> Result<(), RsdebstrapError> {
+        let task_names: Vec<&str> = self
+            .provision
+            .iter()
+            .filter_map(|t| t.configured_name())
+            .collect();
+        for (name, secret) in &self.secrets {
+            secret.validate(name, &task_names)?;
+        }
+        for mirror in self.bootstrap.mirror_urls() {
+            for name in secrets::placeholder_names(mirror) {
+                if !self.secrets.contains_key(name) {
+                    return Err(RsdebstrapError::Validation(format!(
+                        "mirror refers to undefined secret '{}'",
+                        name
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates the isolation options of `defaults.isolation` and every provision task.
+    ///
+    /// Chroot binds are mounted with the task's own privilege method, so a task with
+    /// binds needs one, and it must not be `userns`.
+    fn validate_task_isolation(&self) -> Result<(), RsdebstrapError> {
+        self.defaults.isolation.validate().map_err(|e| match e {
+            RsdebstrapError::Validation(msg) => {
+                RsdebstrapError::Validation(format!("defaults.isolation: {}", msg))
+            }
+            other => other,
+        })?;
+
+        for (index, task) in self.provision.iter().enumerate() {
+            let Some(config) = task.resolved_isolation_config() else {
+                continue;
+            };
+            let prefixed = |msg: String| {
+                RsdebstrapError::Validation(format!("provision {} isolation: {}", index + 1, msg))
+            };
+            config.validate().map_err(|e| match e {
+                RsdebstrapError::Validation(msg) => prefixed(msg),
+                other => other,
+            })?;
+            let chroot = config.options();
+            if chroot.binds.is_empty() {
+                continue;
+            }
+            let privilege = task.resolved_privilege_method();
+            validate_task_mount_privilege(privilege, "binds").map_err(prefixed)?;
+            let entries: Vec<MountEntry> = chroot
+                .binds
+                .iter()
+                .map(ChrootBind::to_mount_entry)
+                .collect();
+            validate_mount_commands(&entries, privilege)?;
+        }
+        Ok(())
+    }
+
+    /// Validates that every mitamae task has a binary for the target architecture:
+    /// its own `binary` or `url`, a `defaults.mitamae.binary` entry, or the
+    /// `defaults.mitamae.version` release.
+    fn validate_mitamae_binaries(&self) -> Result<(), RsdebstrapError> {
+        let missing = self.provision.iter().any(|task| {
+            matches!(task, ProvisionTask::Mitamae(m)
+                if m.binary().is_none() && m.url().is_none() && m.release().is_none())
+        });
+        if missing {
+            self.defaults
+                .mitamae
+                .release(&self.bootstrap.mitamae_arch())?;
+        }
+        Ok(())
+    }
+
+    /// Validates the `mounts` of every provision task.
+    ///
+    /// Task mounts are made with the task's own privilege method and go on top of
+    /// the `prepare.mount` mounts, so they must not replace or cover one of those.
+    /// Entry format and order within a task are checked by the task's `validate()`.
+    fn validate_task_mounts(&self) -> Result<(), RsdebstrapError> {
+        let global = self.pipeline_mounts();
+
+        for (index, task) in self.provision.iter().enumerate() {
+            if task.mounts().is_empty() {
+                continue;
+            }
+            let prefixed = |msg: String| {
+                RsdebstrapError::Validation(format!("provision {} mounts: {}", index + 1, msg))
+            };
+            let privilege = task.resolved_privilege_method();
+            validate_task_mount_privilege(privilege, "mounts").map_err(prefixed)?;
+            validate_mount_commands(task.mounts(), privilege)?;
+
+            for entry in task.mounts() {
+                if let Some(covered) = global.iter().find(|g| g.target.starts_with(&entry.target)) {
+                    return Err(prefixed(format!(
+                        "'{}' would mount over pipeline mount target '{}'",
+                        entry.target, covered.target
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Validates mount-related configuration.
     fn validate_mounts(&self) -> Result<(), RsdebstrapError> {
         // The named-field `prepare.mount` guarantees at most one mount task.
@@ -536,10 +2592,10 @@ impl Profile {
             _ => return Ok(()),
         };
 
-        // No isolation guard: `IsolationConfig` has a single `Chroot` variant, so
-        // `defaults.isolation` is always chroot and mounts (which assume a chroot rootfs)
-        // can never observe a non-chroot backend. Reintroduce a guard next to a second
-        // backend if one is ever added, where it would be reachable and testable.
+        // No isolation guard: both `IsolationConfig` backends see the rootfs's mounts
+        // (`pivot_root` bind-mounts the rootfs recursively before pivoting into it), so
+        // mounts work under either. Reintroduce a guard next to a backend that does not,
+        // where it would be reachable and testable.
 
         // mounts require privilege to be configured
         if self.defaults.privilege.is_none() {
@@ -550,9 +2606,21 @@ impl Profile {
             ));
         }
 
-        // Validate mount/umount commands exist in PATH
-        validate_command_in_path("mount", "mount command")?;
-        validate_command_in_path("umount", "umount command")?;
+        // Each privileged command runs in its own user namespace, so a mount made
+        // there would be gone before the next task runs.
+        if self.defaults.privilege.as_ref().map(|d| d.method) == Some(PrivilegeMethod::Userns) {
+            return Err(RsdebstrapError::Validation(
+                "mounts are not supported with privilege method userns \
+                (each command runs in its own user namespace, so mounts would not persist)"
+                    .to_string(),
+            ));
+        }
+
+        // Validate mount/umount commands exist in PATH, unless mount(2) covers every entry
+        validate_mount_commands(
+            &self.pipeline_mounts(),
+            self.defaults.privilege.as_ref().map(|d| d.method),
+        )?;
 
         // Mount entry validation and mount order are handled by MountTask::validate()
         // which is called by the pipeline validation path.
@@ -561,11 +2629,142 @@ impl Profile {
         Ok(())
     }
 
+    /// Validates the `context` directory and its bind mount's requirements.
+    ///
+    /// The directory is bind-mounted with `defaults.privilege`, like the
+    /// `prepare.mount` mounts.
+    fn validate_context(&self) -> Result<(), RsdebstrapError> {
+        let Some(context) = &self.context else {
+            return Ok(());
+        };
+        if !context.is_dir() {
+            return Err(RsdebstrapError::Validation(format!(
+                "context must be an existing directory: {}",
+                context
+            )));
+        }
+        match self.defaults.privilege.as_ref().map(|d| d.method) {
+            None => {
+                return Err(RsdebstrapError::Validation(
+                    "defaults.privilege must be configured when context is specified \
+                    (mount/umount require privilege escalation)"
+                        .to_string(),
+                ));
+            }
+            Some(PrivilegeMethod::Userns) => {
+                return Err(RsdebstrapError::Validation(
+                    "context is not supported with privilege method userns \
+                    (each command runs in its own user namespace, so mounts would not persist)"
+                        .to_string(),
+                ));
+            }
+            Some(_) => {}
+        }
+        validate_mount_commands(
+            &self.pipeline_mounts(),
+            self.defaults.privilege.as_ref().map(|d| d.method),
+        )?;
+        Ok(())
+    }
+
+    /// Validates that `defaults.umask` only masks group and other permissions.
+    fn validate_umask(&self) -> Result<(), RsdebstrapError> {
+        match self.defaults.umask {
+            Some(umask) if umask.0 & !0o077 != 0 => Err(RsdebstrapError::Validation(format!(
+                "defaults.umask {} must not mask the owner's permissions (staged scripts \
+                and binaries must stay readable and executable); use at most 0077",
+                umask
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Validates that an `offline` profile reaches the network only in the bootstrap.
+    fn validate_offline(&self) -> Result<(), RsdebstrapError> {
+        if !self.offline {
+            return Ok(());
+        }
+        let offending = |what: String| {
+            Err(RsdebstrapError::Validation(format!(
+                "{} is not allowed when the profile sets offline: true",
+                what
+            )))
+        };
+        if self.prepare.download.is_some() {
+            return offending("prepare.download".to_string());
+        }
+        for (index, task) in self.provision.iter().enumerate() {
+            if let Some(url) = task.binary_url() {
+                return offending(format!(
+                    "provision {} downloading its binary from {}",
+                    index + 1,
+                    url
+                ));
+            }
+        }
+        if self.upload.is_some() {
+            return offending("upload".to_string());
+        }
+        Ok(())
+    }
+
+    /// Validates that `targets` is keyed by architecture names and sets no empty `dir`.
+    fn validate_targets(&self) -> Result<(), RsdebstrapError> {
+        for (arch, target) in &self.targets {
+            if !is_architecture_name(arch) {
+                return Err(RsdebstrapError::Validation(format!(
+                    "targets: '{}' is not a Debian architecture name (e.g. amd64, arm64)",
+                    arch
+                )));
+            }
+            if target
+                .dir
+                .as_ref()
+                .is_some_and(|dir| dir.as_str().is_empty())
+            {
+                return Err(RsdebstrapError::Validation(format!(
+                    "targets.{}.dir must not be empty",
+                    arch
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates `defaults.staging`: a staging tmpfs is mounted for the whole pipeline
+    /// like `prepare.mount`, so it needs `defaults.privilege` (not `userns`).
+    fn validate_staging(&self) -> Result<(), RsdebstrapError> {
+        if self.defaults.staging != Staging::Tmpfs {
+            return Ok(());
+        }
+        match self.defaults.privilege.as_ref().map(|d| d.method) {
+            None => {
+                return Err(RsdebstrapError::Validation(
+                    "defaults.privilege must be configured when defaults.staging is tmpfs \
+                    (mount/umount require privilege escalation)"
+                        .to_string(),
+                ));
+            }
+            Some(PrivilegeMethod::Userns) => {
+                return Err(RsdebstrapError::Validation(
+                    "defaults.staging tmpfs is not supported with privilege method userns \
+                    (each command runs in its own user namespace, so mounts would not persist)"
+                        .to_string(),
+                ));
+            }
+            Some(_) => {}
+        }
+        validate_mount_commands(
+            &self.pipeline_mounts(),
+            self.defaults.privilege.as_ref().map(|d| d.method),
+        )
+    }
+
     /// Validates resolv_conf-related configuration.
     fn validate_resolv_conf(&self) -> Result<(), RsdebstrapError> {
         // The named-field `prepare.resolv_conf` guarantees at most one task.
-        // No isolation guard here for the same reason as `validate_mounts`: the sole
-        // `IsolationConfig::Chroot` variant makes `defaults.isolation` always chroot.
+        // No isolation guard here for the same reason as `validate_mounts`: every
+        // `IsolationConfig` variant runs commands with the rootfs as their root.
         if let Some(task) = &self.prepare.resolv_conf {
             task.config().validate()?;
         }
@@ -574,6 +2773,44 @@ impl Profile {
     }
 }
 
+/// Checks that a task's privilege method can make its own mounts (`what` names
+/// the setting in the error).
+///
+/// Returns the error message rather than an error, for the caller to prefix.
+fn validate_task_mount_privilege(
+    method: Option<PrivilegeMethod>,
+    what: &str,
+) -> Result<(), String> {
+    match method {
+        None => Err(format!(
+            "{} require a privilege method (mount/umount require privilege escalation)",
+            what
+        )),
+        Some(PrivilegeMethod::Userns) => Err(format!(
+            "{} are not supported with privilege method userns \
+            (each command runs in its own user namespace, so mounts would not persist)",
+            what
+        )),
+        Some(_) => Ok(()),
+    }
+}
+
+/// Validates that `mount`/`umount` are in PATH, unless every entry will be mounted with
+/// `mount(2)` (rsdebstrap runs as root and the entry's options allow it).
+fn validate_mount_commands(
+    entries: &[MountEntry],
+    privilege: Option<PrivilegeMethod>,
+) -> Result<(), RsdebstrapError> {
+    if entries
+        .iter()
+        .all(|entry| crate::executor::mounts_natively(entry, privilege))
+    {
+        return Ok(());
+    }
+    validate_command_in_path("mount", "mount command")?;
+    validate_command_in_path("umount", "umount command")
+}
+
 /// Validates that a command exists in PATH.
 fn validate_command_in_path(command: &str, label: &str) -> Result<(), RsdebstrapError> {
     if which::which(command).is_err() {
@@ -600,62 +2837,343 @@ pub(crate) fn validate_mount_order(mounts: &[MountEntry]) -> Result<(), Rsdebstr
     Ok(())
 }
 
-fn format_yaml_parse_error(err: yaml_serde::Error, file_path: &Utf8Path) -> RsdebstrapError {
-    // yaml_serde sometimes embeds the location in its Display output
-    // ("... at line X column Y") and sometimes exposes it only via location()
-    // (e.g. "missing field `dir`"). We render the message as-is and append the
-    // location from location() only when the message does not already mention
-    // that line. The check keys off the numeric line value (stable data), not
-    // yaml_serde's exact wording, so a future change to its phrasing degrades to
-    // a harmless duplicate location rather than silently dropping it.
+fn read_profile_file(path: &Utf8Path) -> Result<(BufReader<File>, Utf8PathBuf), RsdebstrapError> {
+    // Resolve symlinks so we operate on the real file path.
+    let canonical_path = path
+        .canonicalize_utf8()
+        .map_err(|e| RsdebstrapError::io(path.to_string(), e))?;
+
+    // On Linux, File::open on a directory succeeds silently, so we must
+    // check explicitly before attempting to open.
+    if canonical_path.is_dir() {
+        return Err(RsdebstrapError::Validation(format!(
+            "expected a file, not a directory: {}",
+            canonical_path
+        )));
+    }
+
+    let file = File::open(&canonical_path)
+        .map_err(|e| RsdebstrapError::io(canonical_path.to_string(), e))?;
+    Ok((BufReader::new(file), canonical_path))
+}
+
+fn parse_profile_yaml(
+    mut reader: BufReader<File>,
+    file_path: &Utf8Path,
+    unknown_fields: UnknownFields,
+) -> Result<Profile, RsdebstrapError> {
+    let mut text = String::new();
+    reader
+        .read_to_string(&mut text)
+        .map_err(|e| RsdebstrapError::io(file_path.to_string(), e))?;
+    // A document that is not even valid YAML fails below with the usual parse error.
+    let Ok(mut value) = yaml_serde::from_str::<yaml_serde::Value>(&text) else {
+        return yaml_serde::from_str(&text)
+            .map_err(|e| yaml_error::profile_parse_error(&e, &text, None, file_path));
+    };
+    check_schema_version(&value, file_path)?;
+
+    // Errors are located in the file as written, not in the text re-serialized after
+    // dropping unknown fields; the paths of the remaining keys are the same in both.
+    let original = text.clone();
+    loop {
+        let err = match yaml_serde::from_str(&text) {
+            Ok(profile) => return Ok(profile),
+            Err(err) => err,
+        };
+        let unknown = remove_unknown_field(&mut value, &err);
+        if unknown_fields == UnknownFields::Warn
+            && let Some((path, field)) = unknown
+        {
+            let field = if path == "." {
+                field
+            } else {
+                format!("{}.{}", path, field)
+            };
+            warn!("{}: ignoring unknown field `{}`", file_path, field);
+            text = yaml_serde::to_string(&value).map_err(|e| {
+                RsdebstrapError::Config(format!(
+                    "{}: failed to re-serialize profile: {}",
+                    file_path, e
+                ))
+            })?;
+            continue;
+        }
+        return Err(yaml_error::profile_parse_error(&err, &original, unknown, file_path));
+    }
+}
+
+/// Rejects a `schema_version` this build does not understand, and the legacy layout
+/// [`crate::migrate`] converts, whose phase lists would otherwise be unknown fields.
+///
+/// A wrongly typed `schema_version` is left to the deserializer to report.
+fn check_schema_version(
+    value: &yaml_serde::Value,
+    file_path: &Utf8Path,
+) -> Result<(), RsdebstrapError> {
+    if let Some(key) = LEGACY_KEYS.iter().find(|key| value.get(**key).is_some()) {
+        return Err(RsdebstrapError::Config(format!(
+            "{}: `{}` belongs to the legacy profile layout; convert the profile with \
+            `rsdebstrap migrate -f {}`",
+            file_path, key, file_path
+        )));
+    }
+    let Some(version) = value
+        .get("schema_version")
+        .and_then(yaml_serde::Value::as_u64)
+    else {
+        return Ok(());
+    };
+    if version == 0 {
+        return Err(RsdebstrapError::Config(format!(
+            "{}: schema_version must be at least 1",
+            file_path
+        )));
+    }
+    if version > u64::from(PROFILE_SCHEMA_VERSION) {
+        return Err(RsdebstrapError::Config(format!(
+            "{}: profile schema_version {} is newer than this rsdebstrap supports ({}); \
+            upgrade rsdebstrap to use it",
+            file_path, version, PROFILE_SCHEMA_VERSION
+        )));
+    }
+    Ok(())
+}
+
+/// Removes the field an "unknown field" error names from `value`, returning where it
+/// was (`provision[1].isolation`, or `.` for the top level) and its name.
+///
+/// The error's path leads to the mapping holding the field, or, inside an internally
+/// tagged enum (whose content serde buffers), to the enum's mapping; the field is then
+/// the first one by that name, breadth first, in a mapping whose other keys the error
+/// lists as expected. Returns `None` for any other error, or if no such mapping exists.
+fn remove_unknown_field(
+    value: &mut yaml_serde::Value,
+    err: &yaml_serde::Error,
+) -> Option<(String, String)> {
     let msg = err.to_string();
-    let suffix = match err.location() {
-        Some(loc) if !msg.contains(&format!("line {}", loc.line())) => {
-            format!(" (line {}, column {})", loc.line(), loc.column())
+    let (prefix, rest) = msg.split_once("unknown field `")?;
+    let (field, rest) = rest.split_once('`')?;
+    let expected: Vec<&str> = rest
+        .split(" at line ")
+        .next()?
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .collect();
+    let path = prefix.strip_suffix(": ").unwrap_or(".");
+
+    let mut queue =
+        std::collections::VecDeque::from([(path.to_string(), value_at_path(value, path)?)]);
+    while let Some((at, node)) = queue.pop_front() {
+        match node {
+            yaml_serde::Value::Mapping(map) => {
+                let fits = map.keys().all(|key| {
+                    key.as_str()
+                        .is_some_and(|key| key == field || key == "type" || expected.contains(&key))
+                });
+                if fits && map.remove(field).is_some() {
+                    return Some((at, field.to_string()));
+                }
+                for (key, child) in map.iter_mut() {
+                    let key = key.as_str().unwrap_or("?");
+                    let child_path = if at == "." {
+                        key.to_string()
+                    } else {
+                        format!("{}.{}", at, key)
+                    };
+                    queue.push_back((child_path, child));
+                }
+            }
+            yaml_serde::Value::Sequence(items) => {
+                for (index, child) in items.iter_mut().enumerate() {
+                    queue.push_back((format!("{}[{}]", at, index), child));
+                }
+            }
+            _ => {}
         }
-        _ => String::new(),
-    };
-    RsdebstrapError::Config(format!("{}: YAML parse error: {}{}", file_path, msg, suffix))
+    }
+    None
 }
 
-fn read_profile_file(path: &Utf8Path) -> Result<(BufReader<File>, Utf8PathBuf), RsdebstrapError> {
-    // Resolve symlinks so we operate on the real file path.
-    let canonical_path = path
-        .canonicalize_utf8()
-        .map_err(|e| RsdebstrapError::io(path.to_string(), e))?;
-
-    // On Linux, File::open on a directory succeeds silently, so we must
-    // check explicitly before attempting to open.
-    if canonical_path.is_dir() {
-        return Err(RsdebstrapError::Validation(format!(
-            "expected a file, not a directory: {}",
-            canonical_path
-        )));
+/// Follows a yaml_serde error path (`provision[1].isolation`, `.` for the root).
+fn value_at_path<'a>(
+    mut value: &'a mut yaml_serde::Value,
+    path: &str,
+) -> Option<&'a mut yaml_serde::Value> {
+    for segment in yaml_error::parse_path(path)? {
+        value = match segment {
+            Segment::Key(key) => value.get_mut(key)?,
+            Segment::Index(index) => value.get_mut(index)?,
+        };
     }
+    Some(value)
+}
 
-    let file = File::open(&canonical_path)
-        .map_err(|e| RsdebstrapError::io(canonical_path.to_string(), e))?;
-    Ok((BufReader::new(file), canonical_path))
+/// Fills in a preset bootstrap's mirror, components and keyring where the profile sets
+/// none, adds the preset's packages to `include`, and picks the preset's first
+/// architecture when the profile lists none and the host's is not one the preset has
+/// (see [`preset`](crate::bootstrap::preset)). The keyring is left out when the profile
+/// has `keyrings` of its own.
+///
+/// # Errors
+///
+/// Returns an error when the preset cannot be loaded, or does not have the suite or an
+/// architecture the profile lists.
+fn apply_preset(profile: &mut Profile) -> Result<(), RsdebstrapError> {
+    let Some(name) = profile.bootstrap.preset().map(str::to_string) else {
+        return Ok(());
+    };
+    if profile.bootstrap.distribution() != Distribution::Debian {
+        return Ok(());
+    }
+    let preset = preset::load(&name)?;
+    preset.check_suite(&name, profile.bootstrap.suite())?;
+    for arch in profile.bootstrap.architectures() {
+        preset.check_architecture(&name, arch)?;
+    }
+    let host = crate::doctor::debian_arch(std::env::consts::ARCH);
+    let arch = (profile.bootstrap.architectures().is_empty()
+        && preset.check_architecture(&name, host).is_err())
+    .then(|| preset.architectures.first().cloned())
+    .flatten();
+    let mirror = profile
+        .bootstrap
+        .mirror_urls()
+        .next()
+        .is_none()
+        .then(|| preset.mirror.clone());
+    let keyring = preset
+        .keyring
+        .as_ref()
+        .filter(|_| profile.keyrings.is_empty())
+        .map(Utf8PathBuf::to_string);
+    let add_include = |include: &mut Vec<String>| {
+        for package in &preset.include {
+            if !include.contains(package) {
+                include.push(package.clone());
+            }
+        }
+    };
+    match &mut profile.bootstrap {
+        Bootstrap::Mmdebstrap(cfg) => {
+            if cfg.components.is_empty() {
+                cfg.components = preset.components.clone();
+            }
+            cfg.mirrors.extend(mirror);
+            if cfg.keyring.is_empty() {
+                cfg.keyring.extend(keyring);
+            }
+            cfg.architectures.extend(arch);
+            add_include(&mut cfg.include);
+        }
+        Bootstrap::Debootstrap(cfg) => {
+            if cfg.components.is_empty() {
+                cfg.components = preset.components.clone();
+            }
+            if cfg.mirror.is_none() {
+                cfg.mirror = mirror;
+            }
+            if cfg.keyring.is_none() {
+                cfg.keyring = keyring;
+            }
+            if cfg.arch.is_none() {
+                cfg.arch = arch;
+            }
+            add_include(&mut cfg.include);
+        }
+    }
+    Ok(())
 }
 
-fn parse_profile_yaml(
-    reader: BufReader<File>,
-    file_path: &Utf8Path,
-) -> Result<Profile, RsdebstrapError> {
-    yaml_serde::from_reader(reader).map_err(|e| format_yaml_parse_error(e, file_path))
+/// Fills in an Ubuntu bootstrap's components, mirror and keyring where the profile sets
+/// none (see [`distribution`](crate::bootstrap::distribution)). The keyring is left out
+/// when the profile has `keyrings` of its own.
+///
+/// # Errors
+///
+/// Returns `RsdebstrapError::Validation` when the default mirror would have to serve
+/// architectures split between the Ubuntu archive and ports.
+fn apply_distribution_defaults(profile: &mut Profile) -> Result<(), RsdebstrapError> {
+    if profile.bootstrap.distribution() != Distribution::Ubuntu {
+        return Ok(());
+    }
+    let host = [crate::doctor::debian_arch(std::env::consts::ARCH)];
+    let architectures = profile.bootstrap.architectures();
+    let architectures = if architectures.is_empty() {
+        &host[..]
+    } else {
+        &architectures[..]
+    };
+    let mirrors: BTreeSet<&str> = architectures
+        .iter()
+        .map(|arch| distribution::ubuntu_mirror(arch))
+        .collect();
+    let mirror = match (mirrors.len(), profile.bootstrap.mirror_urls().next()) {
+        (_, Some(_)) => None,
+        (1, None) => mirrors.first().map(|m| m.to_string()),
+        _ => {
+            return Err(RsdebstrapError::Validation(format!(
+                "bootstrap architectures {} are served by different Ubuntu archives ({}); \
+                set the mirrors",
+                architectures.join(", "),
+                mirrors.into_iter().collect::<Vec<_>>().join(", ")
+            )));
+        }
+    };
+    let keyring = profile
+        .keyrings
+        .is_empty()
+        .then(|| distribution::UBUNTU_KEYRING.to_string());
+    let components = || {
+        distribution::UBUNTU_COMPONENTS
+            .iter()
+            .map(|c| c.to_string())
+            .collect()
+    };
+    match &mut profile.bootstrap {
+        Bootstrap::Mmdebstrap(cfg) => {
+            if cfg.components.is_empty() {
+                cfg.components = components();
+            }
+            cfg.mirrors.extend(mirror);
+            if cfg.keyring.is_empty() {
+                cfg.keyring.extend(keyring);
+            }
+        }
+        Bootstrap::Debootstrap(cfg) => {
+            if cfg.components.is_empty() {
+                cfg.components = components();
+            }
+            if cfg.mirror.is_none() {
+                cfg.mirror = mirror;
+            }
+            if cfg.keyring.is_none() {
+                cfg.keyring = keyring;
+            }
+        }
+    }
+    Ok(())
 }
 
 fn apply_defaults_to_tasks(profile: &mut Profile) -> Result<(), RsdebstrapError> {
-    let arch = std::env::consts::ARCH;
-    let default_binary = profile.defaults.mitamae.binary.get(arch);
+    // mitamae runs inside the rootfs, so its binary must match the target architecture.
+    let arch = profile.bootstrap.mitamae_arch();
+    let default_binary = profile.defaults.mitamae.binary.get(&arch);
+    // Reported by validation when a task needs it.
+    let release = if default_binary.is_none() {
+        profile.defaults.mitamae.release(&arch).ok()
+    } else {
+        None
+    };
     let privilege_defaults = profile.defaults.privilege.as_ref();
     let isolation_defaults = profile.defaults.isolation.clone();
 
     if default_binary.is_none() && !profile.defaults.mitamae.binary.is_empty() {
         let available: Vec<&String> = profile.defaults.mitamae.binary.keys().collect();
         tracing::warn!(
-            "defaults.mitamae.binary has entries for {:?} but current architecture is '{}'; \
-            no default binary will be applied",
+            "defaults.mitamae.binary has entries for {:?} but the target architecture is \
+            '{}'; no default binary will be applied",
             available,
             arch,
         );
@@ -665,19 +3183,42 @@ fn apply_defaults_to_tasks(profile: &mut Profile) -> Result<(), RsdebstrapError>
     profile.bootstrap.resolve_privilege(privilege_defaults)?;
 
     for task in profile.provision.iter_mut() {
-        if let ProvisionTask::Mitamae(mitamae_task) = task
-            && let Some(binary) = default_binary
-        {
-            mitamae_task.set_binary_if_absent(binary);
+        if let ProvisionTask::Mitamae(mitamae_task) = task {
+            if let Some(binary) = default_binary {
+                mitamae_task.set_binary_if_absent(binary);
+            } else if let Some(release) = &release {
+                mitamae_task.set_release_if_absent(release);
+            }
         }
         task.resolve_privilege(privilege_defaults)?;
         task.resolve_isolation(&isolation_defaults);
+        task.resolve_user()?;
+        task.resolve_network(profile.offline)?;
+    }
+
+    if let Some(task) = profile.prepare.download.as_mut() {
+        task.resolve_privilege(privilege_defaults)?;
     }
 
     // Resolve privilege for assemble tasks
+    if let Some(task) = profile.assemble.extract.as_mut() {
+        task.resolve_privilege(privilege_defaults)?;
+    }
     if let Some(task) = profile.assemble.resolv_conf.as_mut() {
         task.resolve_privilege(privilege_defaults)?;
     }
+    if let Some(task) = profile.assemble.sanitize.as_mut() {
+        task.resolve_privilege(privilege_defaults)?;
+    }
+    if let Some(task) = profile.assemble.clean.as_mut() {
+        task.resolve_privilege(privilege_defaults)?;
+    }
+    if let Some(task) = profile.assemble.release.as_mut() {
+        task.resolve_privilege(privilege_defaults)?;
+    }
+    if let Some(task) = profile.assemble.relabel.as_mut() {
+        task.resolve_privilege(privilege_defaults)?;
+    }
 
     Ok(())
 }
@@ -697,6 +3238,243 @@ fn resolve_profile_paths(profile: &mut Profile, profile_dir: &Utf8Path) {
     for task in profile.provision.iter_mut() {
         task.resolve_paths(profile_dir);
     }
+
+    if let Some(download) = profile.prepare.download.as_mut() {
+        download.resolve_paths(profile_dir);
+    }
+
+    if let Some(extract) = profile.assemble.extract.as_mut() {
+        extract.resolve_paths(profile_dir);
+    }
+
+    for check in profile.verify.iter_mut() {
+        check.resolve_paths(profile_dir, &profile.dir);
+    }
+
+    if let Some(apt_cache) = profile.apt_cache.as_mut() {
+        apt_cache.resolve_paths(profile_dir);
+    }
+
+    for keyring in profile.keyrings.iter_mut() {
+        keyring.resolve_paths(profile_dir);
+    }
+
+    if let Some(context) = profile.context.as_mut()
+        && context.is_relative()
+    {
+        *context = profile_dir.join(&*context);
+    }
+
+    for secret in profile.secrets.values_mut() {
+        secret.resolve_paths(profile_dir);
+    }
+}
+
+/// Builds a [`Profile`] in Rust code instead of loading it from YAML.
+///
+/// [`build()`](Self::build) applies the same post-processing as [`load_profile`]:
+/// relative paths are resolved against [`base_dir`](Self::base_dir) (if set) and
+/// the profile defaults are applied to every task. Like `load_profile`, it does not
+/// validate the profile; call [`Profile::validate`] before running it.
+///
+/// # Examples
+///
+/// ```
+/// use rsdebstrap::bootstrap::mmdebstrap::MmdebstrapConfigBuilder;
+/// use rsdebstrap::config::ProfileBuilder;
+/// use rsdebstrap::phase::{ScriptSource, ShellTask};
+/// use rsdebstrap::privilege::PrivilegeMethod;
+///
+/// let profile = ProfileBuilder::new(
+///     "/var/tmp/build",
+///     MmdebstrapConfigBuilder::new("trixie", "rootfs").include(["vim"]).build(),
+/// )
+/// .privilege(PrivilegeMethod::Sudo)
+/// .provision(ShellTask::new(ScriptSource::Content("apt-get clean".into())).with_name("clean"))
+/// .build()
+/// .unwrap();
+/// assert!(profile.to_yaml().unwrap().contains("name: clean"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct ProfileBuilder {
+    profile: Profile,
+    base_dir: Option<Utf8PathBuf>,
+}
+
+impl ProfileBuilder {
+    /// Creates a builder for a profile with the given target directory and backend.
+    pub fn new(dir: impl Into<Utf8PathBuf>, bootstrap: impl Into<Bootstrap>) -> Self {
+        Self {
+            profile: Profile {
+                schema_version: None,
+                dir: dir.into(),
+                estimated_size: None,
+                dir_policy: DirPolicy::default(),
+                defaults: Defaults::default(),
+                bootstrap: bootstrap.into(),
+                targets: BTreeMap::new(),
+                prepare: PrepareConfig::default(),
+                provision: Vec::new(),
+                assemble: AssembleConfig::default(),
+                verify: Vec::new(),
+                failure_policy: FailurePolicies::default(),
+                offline: false,
+                apt_cache: None,
+                snapshot: None,
+                keyrings: Vec::new(),
+                context: None,
+                checksums: None,
+                upload: None,
+                output_permissions: None,
+                secrets: BTreeMap::new(),
+            },
+            base_dir: None,
+        }
+    }
+
+    /// Sets the space the build needs in `dir` (`estimated_size`).
+    pub fn estimated_size(mut self, size: ByteSize) -> Self {
+        self.profile.estimated_size = Some(size);
+        self
+    }
+
+    /// Sets which existing `dir` `apply` may build into (`dir_policy`).
+    pub fn dir_policy(mut self, policy: DirPolicy) -> Self {
+        self.profile.dir_policy = policy;
+        self
+    }
+
+    /// Sets the profile defaults.
+    pub fn defaults(mut self, defaults: Defaults) -> Self {
+        self.profile.defaults = defaults;
+        self
+    }
+
+    /// Sets the default privilege escalation method (`defaults.privilege.method`).
+    pub fn privilege(mut self, method: PrivilegeMethod) -> Self {
+        self.profile.defaults.privilege = Some(PrivilegeDefaults { method });
+        self
+    }
+
+    /// Sets the prepare phase tasks.
+    pub fn prepare(mut self, prepare: PrepareConfig) -> Self {
+        self.profile.prepare = prepare;
+        self
+    }
+
+    /// Appends a provision task.
+    pub fn provision(mut self, task: impl Into<ProvisionTask>) -> Self {
+        self.profile.provision.push(task.into());
+        self
+    }
+
+    /// Sets the assemble phase tasks.
+    pub fn assemble(mut self, assemble: AssembleConfig) -> Self {
+        self.profile.assemble = assemble;
+        self
+    }
+
+    /// Appends a verify phase check.
+    pub fn verify(mut self, task: VerifyTask) -> Self {
+        self.profile.verify.push(task);
+        self
+    }
+
+    /// Sets what the provision and assemble phases do when a task fails.
+    pub fn failure_policy(mut self, policy: FailurePolicies) -> Self {
+        self.profile.failure_policy = policy;
+        self
+    }
+
+    /// Forbids network access for every provision task.
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.profile.offline = offline;
+        self
+    }
+
+    /// Routes apt downloads through a caching proxy.
+    pub fn apt_cache(mut self, apt_cache: AptCacheConfig) -> Self {
+        self.profile.apt_cache = Some(apt_cache);
+        self
+    }
+
+    /// Builds from snapshot.debian.org as of `snapshot` (`snapshot`).
+    pub fn snapshot(mut self, snapshot: impl Into<String>) -> Self {
+        self.profile.snapshot = Some(snapshot.into());
+        self
+    }
+
+    /// Appends a keyring the bootstrap backend should trust.
+    pub fn keyring(mut self, keyring: KeyringSource) -> Self {
+        self.profile.keyrings.push(keyring);
+        self
+    }
+
+    /// Sets the host directory shared read-only with every task.
+    pub fn context(mut self, context: impl Into<Utf8PathBuf>) -> Self {
+        self.profile.context = Some(context.into());
+        self
+    }
+
+    /// Writes checksums of the build's artifacts after the assemble phase.
+    pub fn checksums(mut self, checksums: ChecksumConfig) -> Self {
+        self.profile.checksums = Some(checksums);
+        self
+    }
+
+    /// Uploads the build's artifacts to object storage after the checksums.
+    pub fn upload(mut self, upload: UploadConfig) -> Self {
+        self.profile.upload = Some(upload);
+        self
+    }
+
+    /// Sets the owner and mode of `dir` and the rootfs at the end of the build.
+    pub fn output_permissions(mut self, permissions: OutputPermissions) -> Self {
+        self.profile.output_permissions = Some(permissions);
+        self
+    }
+
+    /// Adds the `targets` entry for the architecture `arch`.
+    pub fn target(mut self, arch: impl Into<String>, target: TargetConfig) -> Self {
+        self.profile.targets.insert(arch.into(), target);
+        self
+    }
+
+    /// Adds the secret `name`.
+    pub fn secret(mut self, name: impl Into<String>, secret: SecretConfig) -> Self {
+        self.profile.secrets.insert(name.into(), secret);
+        self
+    }
+
+    /// Sets the directory relative paths are resolved against, as `load_profile`
+    /// resolves them against the profile file's directory.
+    ///
+    /// Without it, relative paths are left as given (relative to the working
+    /// directory).
+    pub fn base_dir(mut self, base_dir: impl Into<Utf8PathBuf>) -> Self {
+        self.base_dir = Some(base_dir.into());
+        self
+    }
+
+    /// Returns the profile with its paths resolved and defaults applied.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `dir` is empty or a task's
+    /// privilege or network setting conflicts with the profile defaults.
+    pub fn build(self) -> Result<Profile, RsdebstrapError> {
+        let mut profile = self.profile;
+        if profile.dir.as_str().is_empty() {
+            return Err(RsdebstrapError::Validation("dir must not be empty".to_string()));
+        }
+        if let Some(base_dir) = &self.base_dir {
+            resolve_profile_paths(&mut profile, base_dir);
+        }
+        apply_preset(&mut profile)?;
+        apply_distribution_defaults(&mut profile)?;
+        apply_defaults_to_tasks(&mut profile)?;
+        Ok(profile)
+    }
 }
 
 /// Loads a bootstrap profile from a YAML file.
@@ -723,8 +3501,61 @@ fn resolve_profile_paths(profile: &mut Profile, profile_dir: &Utf8Path) {
 /// ```
 #[tracing::instrument]
 pub fn load_profile(path: &Utf8Path) -> Result<Profile, RsdebstrapError> {
+    load_profile_with(path, UnknownFields::Deny)
+}
+
+/// Loads a profile like [`load_profile`], treating unknown fields as `unknown_fields`
+/// says.
+///
+/// # Errors
+///
+/// As [`load_profile`]; with [`UnknownFields::Warn`] an unknown field is logged and
+/// skipped instead of failing the load.
+pub fn load_profile_with(
+    path: &Utf8Path,
+    unknown_fields: UnknownFields,
+) -> Result<Profile, RsdebstrapError> {
+    load_profile_for(path, unknown_fields, None)
+}
+
+/// Loads a profile like [`load_profile_with`] for the `targets` entry `arch` (`--arch`),
+/// selected with [`Profile::select_target`] before anything else is resolved.
+///
+/// # Errors
+///
+/// As [`load_profile_with`], and `RsdebstrapError::Validation` if the profile has no
+/// target `arch`.
+pub fn load_profile_for(
+    path: &Utf8Path,
+    unknown_fields: UnknownFields,
+    arch: Option<&str>,
+) -> Result<Profile, RsdebstrapError> {
+    load_profile_layered(path, unknown_fields, arch, &ProfileLayers::default())
+}
+
+/// Loads a profile like [`load_profile_for`] between the [`ProfileLayers`]: the
+/// `RSDEBSTRAP_*` overrides and then the `--set` ones replace the values they set
+/// (after the `targets` entry is selected), then the user configuration fills in what
+/// is still unset (see [`UserConfig::apply`]), before any defaults are applied.
+///
+/// # Errors
+///
+/// As [`load_profile_for`].
+pub fn load_profile_layered(
+    path: &Utf8Path,
+    unknown_fields: UnknownFields,
+    arch: Option<&str>,
+    layers: &ProfileLayers,
+) -> Result<Profile, RsdebstrapError> {
     let (reader, canonical_path) = read_profile_file(path)?;
-    let mut profile = parse_profile_yaml(reader, &canonical_path)?;
+    let mut profile = parse_profile_yaml(reader, &canonical_path, unknown_fields)?;
+    if let Some(arch) = arch {
+        profile.select_target(arch)?;
+    }
+    layers.env.apply(&mut profile);
+    for set in &layers.sets {
+        set.apply(&mut profile)?;
+    }
 
     // Checked before path resolution: joining an empty `dir` onto the profile's
     // directory would silently target that directory itself.
@@ -739,6 +3570,11 @@ pub fn load_profile(path: &Utf8Path) -> Result<Profile, RsdebstrapError> {
         ))
     })?;
     resolve_profile_paths(&mut profile, profile_dir);
+    if let Some(user_config) = &layers.user_config {
+        user_config.apply(&mut profile);
+    }
+    apply_preset(&mut profile)?;
+    apply_distribution_defaults(&mut profile)?;
     apply_defaults_to_tasks(&mut profile)?;
     debug!("loaded profile:\n{:#?}", profile);
     Ok(profile)
@@ -751,55 +3587,176 @@ mod tests {
     use tempfile::NamedTempFile;
 
     // =========================================================================
-    // format_yaml_parse_error tests
+    // environment override tests
     // =========================================================================
 
-    /// Generates a yaml_serde::Error by attempting to parse invalid YAML.
-    fn make_yaml_error(yaml: &str) -> yaml_serde::Error {
-        yaml_serde::from_str::<Profile>(yaml).unwrap_err()
+    fn env_overrides(vars: &[(&str, &str)]) -> Result<EnvOverrides, RsdebstrapError> {
+        EnvOverrides::from_vars(
+            |name| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            },
+            Utf8Path::new("/work"),
+        )
     }
 
     #[test]
-    fn test_format_yaml_parse_error_with_location() {
-        // A type error embeds the location directly in yaml_serde's Display output.
-        let err = make_yaml_error("dir: [invalid, list]");
-        let result = format_yaml_parse_error(err, Utf8Path::new("/test/profile.yml"));
-        let msg = result.to_string();
-        assert!(msg.contains("/test/profile.yml"), "should contain file path: {}", msg);
-        assert!(msg.contains("YAML parse error"), "should contain 'YAML parse error': {}", msg);
-        assert!(msg.contains("line "), "should contain line info: {}", msg);
-        assert!(msg.contains("column "), "should contain column info: {}", msg);
+    fn env_overrides_parse_the_variables() {
+        let env = env_overrides(&[
+            (ENV_DIR, "out"),
+            (ENV_PRIVILEGE_METHOD, "userns"),
+            (ENV_LOG_FORMAT, "json"),
+        ])
+        .unwrap();
+        assert_eq!(env.dir.as_deref(), Some(Utf8Path::new("/work/out")));
+        assert_eq!(env.privilege_method, Some(PrivilegeMethod::Userns));
+        assert_eq!(env.log_format, Some(LogFormat::Json));
+
+        let env = env_overrides(&[(ENV_DIR, "/srv/rootfs"), (ENV_PRIVILEGE_METHOD, "")]).unwrap();
+        assert_eq!(env.dir.as_deref(), Some(Utf8Path::new("/srv/rootfs")));
+        assert_eq!(env.privilege_method, None);
+        assert_eq!(env_overrides(&[]).unwrap(), EnvOverrides::default());
     }
 
     #[test]
-    fn test_format_yaml_parse_error_no_duplicate_location() {
-        // yaml_serde already embeds the location in this error's message, so we must
-        // not append a second copy from location().
-        let err = make_yaml_error("dir: [invalid, list]");
-        let result = format_yaml_parse_error(err, Utf8Path::new("/test/profile.yml"));
-        let msg = result.to_string();
-        assert_eq!(msg.matches("line ").count(), 1, "location must not be duplicated: {}", msg);
+    fn env_overrides_reject_unknown_values() {
+        let err = env_overrides(&[(ENV_PRIVILEGE_METHOD, "su")]).unwrap_err();
         assert!(
-            !msg.contains(" (line "),
-            "our appended suffix must be absent when yaml_serde embeds the location: {}",
-            msg
+            err.to_string().contains(
+                "RSDEBSTRAP_PRIVILEGE_METHOD=\"su\" is not valid (expected one of: sudo, doas"
+            ),
+            "{err}"
         );
+        let err = env_overrides(&[(ENV_LOG_FORMAT, "JSON")]).unwrap_err();
+        assert!(err.to_string().contains("expected one of: text, json"), "{err}");
     }
 
     #[test]
-    fn test_format_yaml_parse_error_appends_location_when_absent() {
-        // A "missing field" error carries a location() but omits it from Display,
-        // so we append it ourselves rather than dropping it.
-        let err = make_yaml_error("");
-        let result = format_yaml_parse_error(err, Utf8Path::new("/test/empty.yml"));
-        let msg = result.to_string();
-        assert!(msg.contains("/test/empty.yml"), "should contain file path: {}", msg);
-        assert!(msg.contains("YAML parse error"), "should contain 'YAML parse error': {}", msg);
-        assert!(
-            msg.contains(" (line "),
-            "location from location() should be appended when the message lacks it: {}",
-            msg
-        );
+    fn profile_layers_log_format_prefers_the_environment() {
+        let mut layers = ProfileLayers {
+            user_config: Some(UserConfig {
+                log_format: LogFormat::Json,
+                ..UserConfig::default()
+            }),
+            ..ProfileLayers::default()
+        };
+        assert_eq!(layers.log_format(), LogFormat::Json);
+        layers.env.log_format = Some(LogFormat::Text);
+        assert_eq!(layers.log_format(), LogFormat::Text);
+        assert_eq!(ProfileLayers::default().log_format(), LogFormat::Text);
+    }
+
+    #[test]
+    fn profile_set_parses_path_and_yaml_value() {
+        let set: ProfileSet = "provision[0].content=echo a=b".parse().unwrap();
+        assert_eq!(set.path, "provision[0].content");
+        assert_eq!(set.value, "echo a=b");
+        assert_eq!(set.to_string(), "provision[0].content=echo a=b");
+
+        for (text, expected) in [
+            ("bootstrap.suite", "expected PATH=VALUE"),
+            ("=trixie", "invalid path"),
+            ("bootstrap suite=trixie", "invalid path"),
+            ("bootstrap.include=[vim", "invalid YAML value"),
+        ] {
+            let err = text.parse::<ProfileSet>().unwrap_err();
+            assert!(err.to_string().contains(expected), "{text:?}: {err}");
+        }
+    }
+
+    #[test]
+    fn set_at_path_creates_mappings_and_appends_to_lists() {
+        let mut document: yaml_serde::Value =
+            yaml_serde::from_str("dir: /out\nprovision: [{content: a}]\n").unwrap();
+        let set = |document: &mut yaml_serde::Value, path: &str, value: &str| {
+            set_at_path(
+                document,
+                &yaml_error::parse_path(path).unwrap(),
+                yaml_serde::from_str(value).unwrap(),
+            )
+        };
+        set(&mut document, "defaults.privilege.method", "doas").unwrap();
+        set(&mut document, "provision[0].content", "b").unwrap();
+        set(&mut document, "provision[1].content", "c").unwrap();
+        let expected: yaml_serde::Value = yaml_serde::from_str(
+            "dir: /out\nprovision: [{content: b}, {content: c}]\n\
+            defaults: {privilege: {method: doas}}\n",
+        )
+        .unwrap();
+        assert_eq!(document, expected);
+
+        let err = set(&mut document, "provision[3].content", "d").unwrap_err();
+        assert_eq!(err, "index 3 is past the end of a list of 2");
+        let err = set(&mut document, "dir.name", "x").unwrap_err();
+        assert_eq!(err, "'name' is set on a value that is not a mapping");
+    }
+
+    // =========================================================================
+    // profile parse error tests
+    // =========================================================================
+
+    /// Parses `yaml` as a profile file and returns the `ConfigParse` error's fields
+    /// (line, column, path, message, suggestion).
+    fn parse_error_fields(
+        yaml: &str,
+    ) -> (Option<u32>, Option<u32>, Option<String>, String, Option<String>) {
+        match parse_yaml(yaml, UnknownFields::Deny).unwrap_err() {
+            RsdebstrapError::ConfigParse {
+                line,
+                column,
+                path,
+                message,
+                suggestion,
+                ..
+            } => (line, column, path, message, suggestion),
+            other => panic!("Expected ConfigParse error, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_error_with_location() {
+        let err = parse_yaml("dir: [invalid, list]", UnknownFields::Deny).unwrap_err();
+        let msg = err.to_string();
+        assert!(msg.contains("YAML parse error at /dir: invalid type"), "{}", msg);
+        assert!(msg.ends_with(" (line 1, column 1)"), "points at the key: {}", msg);
+        assert_eq!(msg.matches("line ").count(), 1, "location must not be duplicated: {}", msg);
+        assert_eq!(err.code(), "RSD0013");
+        assert_eq!(err.exit_code(), crate::error::exit_codes::CONFIG);
+    }
+
+    #[test]
+    fn test_parse_error_keeps_location_without_path() {
+        // A "missing field" error has a location but no path.
+        let (line, column, path, message, suggestion) = parse_error_fields("");
+        assert_eq!((line, column), (Some(1), Some(1)));
+        assert_eq!(path, None);
+        assert_eq!(message, "missing field `dir`");
+        assert_eq!(suggestion, None);
+    }
+
+    #[test]
+    fn test_parse_error_locates_unknown_fields_in_tagged_enums() {
+        // yaml_serde reports these at the buffered enum and without the sequence index.
+        let (line, column, path, message, suggestion) = parse_error_fields(TYPO_YAML);
+        assert_eq!((line, column), (Some(2), Some(1)));
+        assert_eq!(path.as_deref(), Some("/colour"));
+        assert!(message.starts_with("unknown field `colour`, expected one of"), "{}", message);
+        assert_eq!(suggestion, None);
+
+        let yaml = TYPO_YAML.replace("colour: blue\n", "");
+        let (line, column, path, _, suggestion) = parse_error_fields(&yaml);
+        assert_eq!((line, column), (Some(6), Some(3)));
+        assert_eq!(path.as_deref(), Some("/bootstrap/mirors"));
+        assert_eq!(suggestion.as_deref(), Some("mirrors"));
+        let err = parse_yaml(&yaml, UnknownFields::Deny).unwrap_err();
+        assert_eq!(err.hint().as_deref(), Some("did you mean `mirrors`?"));
+
+        let yaml = yaml.replace("  mirors: [https://deb.debian.org/debian]\n", "");
+        let (line, column, path, _, suggestion) = parse_error_fields(&yaml);
+        assert_eq!((line, column), (Some(12), Some(5)));
+        assert_eq!(path.as_deref(), Some("/provision/0/isolation/name"));
+        assert_eq!(suggestion, None);
     }
 
     // =========================================================================
@@ -872,7 +3829,7 @@ mod tests {
         let reader = BufReader::new(file);
         let file_path = Utf8Path::from_path(tmpfile.path()).unwrap();
 
-        let result = parse_profile_yaml(reader, file_path);
+        let result = parse_profile_yaml(reader, file_path, UnknownFields::Deny);
         assert!(result.is_ok(), "Expected Ok, got: {:?}", result.unwrap_err());
 
         let profile = result.unwrap();
@@ -889,15 +3846,105 @@ mod tests {
         let reader = BufReader::new(file);
         let file_path = Utf8Path::from_path(tmpfile.path()).unwrap();
 
-        let result = parse_profile_yaml(reader, file_path);
+        let result = parse_profile_yaml(reader, file_path, UnknownFields::Deny);
         let err = result.unwrap_err();
         assert!(
-            matches!(&err, RsdebstrapError::Config(msg) if msg.contains("YAML parse error")),
-            "Expected Config error with YAML parse error, got: {:?}",
+            matches!(&err, RsdebstrapError::ConfigParse { .. })
+                && err.to_string().contains("YAML parse error"),
+            "Expected ConfigParse error with YAML parse error, got: {:?}",
             err
         );
     }
 
+    fn parse_yaml(yaml: &str, unknown_fields: UnknownFields) -> Result<Profile, RsdebstrapError> {
+        let mut tmpfile = NamedTempFile::new().unwrap();
+        tmpfile.write_all(yaml.as_bytes()).unwrap();
+        tmpfile.flush().unwrap();
+        let reader = BufReader::new(File::open(tmpfile.path()).unwrap());
+        parse_profile_yaml(reader, Utf8Path::from_path(tmpfile.path()).unwrap(), unknown_fields)
+    }
+
+    const TYPO_YAML: &str = "\
+dir: /tmp/rootfs
+colour: blue
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+  mirors: [https://deb.debian.org/debian]
+provision:
+- type: shell
+  name: setup
+  content: echo hi
+  isolation:
+    type: chroot
+    name: typo
+";
+
+    #[test]
+    fn test_parse_profile_yaml_strict_rejects_unknown_fields() {
+        let err = parse_yaml(TYPO_YAML, UnknownFields::Deny).unwrap_err();
+        assert!(err.to_string().contains("unknown field `colour`"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_profile_yaml_permissive_skips_unknown_fields() {
+        let profile = parse_yaml(TYPO_YAML, UnknownFields::Warn).unwrap();
+        let expected = parse_yaml(
+            &TYPO_YAML
+                .replace("colour: blue\n", "")
+                .replace("  mirors: [https://deb.debian.org/debian]\n", "")
+                .replace("    name: typo\n", ""),
+            UnknownFields::Deny,
+        )
+        .unwrap();
+        assert_eq!(profile, expected);
+        assert_eq!(profile.provision[0].configured_name(), Some("setup"));
+    }
+
+    #[test]
+    fn test_remove_unknown_field_reports_the_path() {
+        let mut value: yaml_serde::Value = yaml_serde::from_str(TYPO_YAML).unwrap();
+        let mut removed = Vec::new();
+        while let Err(err) =
+            yaml_serde::from_str::<Profile>(&yaml_serde::to_string(&value).unwrap())
+        {
+            removed.push(remove_unknown_field(&mut value, &err).expect("an unknown field"));
+        }
+        assert_eq!(
+            removed,
+            [
+                (".".to_string(), "colour".to_string()),
+                ("provision[0].isolation".to_string(), "name".to_string()),
+                ("bootstrap".to_string(), "mirors".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_profile_yaml_checks_schema_version() {
+        let yaml = |version: &str| {
+            format!(
+                "schema_version: {}\ndir: /tmp/rootfs\nbootstrap:\n  type: mmdebstrap\n  \
+                suite: trixie\n  target: rootfs\n",
+                version
+            )
+        };
+        let profile = parse_yaml(&yaml("1"), UnknownFields::Deny).unwrap();
+        assert_eq!(profile.schema_version, Some(1));
+
+        let err = parse_yaml(&yaml("2"), UnknownFields::Warn).unwrap_err();
+        assert!(err.to_string().contains("schema_version 2 is newer"), "{}", err);
+        let err = parse_yaml(&yaml("0"), UnknownFields::Deny).unwrap_err();
+        assert!(err.to_string().contains("at least 1"), "{}", err);
+        let err = parse_yaml(&yaml("one"), UnknownFields::Deny).unwrap_err();
+        assert!(err.to_string().contains("schema_version"), "{}", err);
+
+        let legacy = yaml("1").replace("schema_version: 1\n", "provisioners: []\n");
+        let err = parse_yaml(&legacy, UnknownFields::Warn).unwrap_err();
+        assert!(err.to_string().contains("rsdebstrap migrate"), "{}", err);
+    }
+
     // =========================================================================
     // MountEntry tests
     // =========================================================================
@@ -994,6 +4041,29 @@ mod tests {
         assert_eq!(spec.args, vec!["/rootfs/proc"]);
     }
 
+    #[test]
+    fn test_mount_entry_build_umount_spec_with_mode() {
+        let entry = MountEntry {
+            source: "proc".to_string(),
+            target: "/proc".into(),
+            options: vec![],
+        };
+        for (mode, expected) in [
+            (UnmountMode::Normal, vec!["/rootfs/proc"]),
+            (UnmountMode::Lazy, vec!["-l", "/rootfs/proc"]),
+            (UnmountMode::Force, vec!["-f", "/rootfs/proc"]),
+        ] {
+            let spec = entry.build_umount_spec_with_mode(
+                Utf8Path::new("/rootfs/proc"),
+                mode,
+                Some(PrivilegeMethod::Sudo),
+            );
+            assert_eq!(spec.command, "umount");
+            assert_eq!(spec.args, expected, "{mode}");
+            assert_eq!(spec.privilege, Some(PrivilegeMethod::Sudo));
+        }
+    }
+
     #[test]
     fn test_mount_entry_build_mount_spec_with_path_privilege() {
         let entry = MountEntry {
@@ -1032,6 +4102,19 @@ mod tests {
         assert!(entry.validate().is_ok());
     }
 
+    #[test]
+    fn test_mount_entry_validate_rejects_null_characters() {
+        let entry = MountEntry {
+            source: "tmpfs".to_string(),
+            target: "/mnt".into(),
+            options: vec!["size=1M\0,exec".to_string()],
+        };
+        let err = entry.validate().unwrap_err();
+        assert!(matches!(err, RsdebstrapError::Validation(_)));
+        assert!(err.to_string().contains("mount option"), "{err}");
+        assert!(err.to_string().contains("null character"), "{err}");
+    }
+
     #[test]
     fn test_mount_entry_validate_rejects_relative_target() {
         let entry = MountEntry {
@@ -1537,9 +4620,8 @@ mod tests {
     // =========================================================================
     // Profile::validate_mounts / validate_resolv_conf tests
     //
-    // `IsolationConfig` has a single `Chroot` variant, so `defaults.isolation` is
-    // always chroot; the former "require chroot isolation" guards were removed as
-    // unreachable dead code (e0fd092). These tests cover the two private validators
+    // Every `IsolationConfig` backend sees the rootfs's mounts, so the former "require
+    // chroot isolation" guards were removed as unreachable dead code (e0fd092). These tests cover the two private validators
     // directly, complementing the integration-level `test_profile_validation_*`
     // tests in tests/config_test.rs.
     // =========================================================================
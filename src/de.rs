@@ -15,7 +15,7 @@
 //! `yaml_serde` text deserializer and `serde_json` values, which keeps the parser and
 //! the generated schema in agreement by construction.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 
 use camino::Utf8PathBuf;
@@ -53,6 +53,66 @@ pub(crate) fn string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Strin
     deserializer.deserialize_any(StrictStringVisitor)
 }
 
+/// Visitor accepting a string, boolean or number, kept as its text.
+struct ScalarTextVisitor;
+
+impl Visitor<'_> for ScalarTextVisitor {
+    type Value = String;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a string, boolean or number")
+    }
+
+    fn visit_str<E: Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(v.to_owned())
+    }
+
+    fn visit_bool<E: Error>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_i64<E: Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_u64<E: Error>(self, v: u64) -> Result<Self::Value, E> {
+        Ok(v.to_string())
+    }
+
+    fn visit_f64<E: Error>(self, v: f64) -> Result<Self::Value, E> {
+        Ok(v.to_string())
+    }
+}
+
+/// Deserializes a `String` field that also takes a boolean or number as its text
+/// (`true`, `8080`), for values handed on verbatim to another tool. `null` and other
+/// non-scalars are still rejected; the schema types such fields as
+/// `["string", "boolean", "number"]`.
+pub(crate) fn scalar_text<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    deserializer.deserialize_any(ScalarTextVisitor)
+}
+
+/// A `String` that also takes a boolean or number as its text (used for map values).
+struct ScalarText(String);
+
+impl<'de> Deserialize<'de> for ScalarText {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer
+            .deserialize_any(ScalarTextVisitor)
+            .map(ScalarText)
+    }
+}
+
+/// Deserializes a `BTreeMap<String, String>` field: `null` means empty, values are
+/// [`scalar_text`].
+pub(crate) fn scalar_text_map<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<String, String>, D::Error> {
+    Ok(Option::<BTreeMap<String, ScalarText>>::deserialize(deserializer)?
+        .map(|map| map.into_iter().map(|(key, value)| (key, value.0)).collect())
+        .unwrap_or_default())
+}
+
 /// Deserializes a `Utf8PathBuf` field, rejecting non-string scalars.
 pub(crate) fn path<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Utf8PathBuf, D::Error> {
     deserializer
@@ -70,7 +130,16 @@ pub(crate) fn opt_string<'de, D: Deserializer<'de>>(
     Option::<StrictString>::deserialize(deserializer).map(|opt| opt.map(|s| s.0))
 }
 
-/// A `Utf8PathBuf` that deserializes strictly (used for map values).
+/// Deserializes an `Option<Utf8PathBuf>` field, rejecting non-string scalars.
+///
+/// `null` (and an empty value) still deserializes to `None`.
+pub(crate) fn opt_path<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Utf8PathBuf>, D::Error> {
+    Option::<StrictPath>::deserialize(deserializer).map(|opt| opt.map(|p| p.0))
+}
+
+/// A `Utf8PathBuf` that deserializes strictly (used for `Option` and map values).
 struct StrictPath(Utf8PathBuf);
 
 impl<'de> Deserialize<'de> for StrictPath {
@@ -106,6 +175,15 @@ pub(crate) fn string_list<'de, D: Deserializer<'de>>(
         .unwrap_or_default())
 }
 
+/// Deserializes a `Vec<Utf8PathBuf>` field: `null` means empty, elements are strict paths.
+pub(crate) fn path_list<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Utf8PathBuf>, D::Error> {
+    Ok(Option::<Vec<StrictPath>>::deserialize(deserializer)?
+        .map(|items| items.into_iter().map(|p| p.0).collect())
+        .unwrap_or_default())
+}
+
 /// Deserializes a `HashMap<String, Utf8PathBuf>` field: `null` means empty, values are
 /// strict paths.
 pub(crate) fn path_map<'de, D: Deserializer<'de>>(
@@ -0,0 +1,297 @@
+//! Drift between a profile and an existing rootfs, for the `diff` subcommand.
+//!
+//! [`diff_rootfs`] inspects the rootfs an earlier `apply` built and reports what no
+//! longer matches the profile: `include` packages that are not installed, an
+//! `/etc/resolv.conf` that differs from the assemble `resolv_conf` task, missing
+//! `prepare.mount` mount points, and provision tasks whose script changed since the
+//! last recorded run (see [`crate::task_record`]). It only reads the rootfs.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use tracing::warn;
+
+use crate::bootstrap::RootfsOutput;
+use crate::config::{Profile, ResolvConfConfig};
+use crate::error::RsdebstrapError;
+use crate::isolation::resolv_conf::generate_resolv_conf;
+use crate::phase::AssembleResolvConfTask;
+use crate::pipeline::task_label;
+use crate::task_record::{TaskRecord, task_digest};
+
+/// One difference between the profile and the rootfs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Drift {
+    /// A package from `include` is not installed.
+    MissingPackage(String),
+    /// `/etc/resolv.conf` differs from the assemble `resolv_conf` task.
+    ResolvConf(String),
+    /// A `prepare.mount` mount point directory is missing.
+    MissingMountPoint(Utf8PathBuf),
+    /// A provision task's script changed since the last recorded run.
+    TaskChanged(String),
+    /// A provision task has no recorded run.
+    TaskNotRecorded(String),
+    /// A recorded task is no longer in the profile.
+    TaskRemoved(String),
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingPackage(package) => write!(f, "package {} is not installed", package),
+            Self::ResolvConf(reason) => write!(f, "/etc/resolv.conf {}", reason),
+            Self::MissingMountPoint(target) => write!(f, "mount point {} is missing", target),
+            Self::TaskChanged(label) => {
+                write!(f, "{}: script changed since the last recorded run", label)
+            }
+            Self::TaskNotRecorded(label) => write!(f, "{}: no recorded run", label),
+            Self::TaskRemoved(name) => {
+                write!(f, "{}: recorded but no longer in the profile", name)
+            }
+        }
+    }
+}
+
+/// The differences found by [`diff_rootfs`]. Displays as a list.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DriftReport {
+    /// Differences in check order: packages, resolv.conf, mount points, tasks.
+    pub drifts: Vec<Drift>,
+}
+
+impl DriftReport {
+    /// Returns true if the rootfs matches the profile.
+    pub fn is_empty(&self) -> bool {
+        self.drifts.is_empty()
+    }
+}
+
+impl fmt::Display for DriftReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.drifts.is_empty() {
+            return writeln!(f, "No drift: the rootfs matches the profile.");
+        }
+        writeln!(f, "Drift ({} difference(s)):", self.drifts.len())?;
+        for drift in &self.drifts {
+            writeln!(f, "  - {}", drift)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares the rootfs `profile` builds against the profile.
+///
+/// # Errors
+///
+/// Returns [`RsdebstrapError::Validation`] if the profile does not build a directory
+/// rootfs or the rootfs does not exist, and an I/O error if it cannot be read.
+pub fn diff_rootfs(profile: &Profile) -> Result<DriftReport> {
+    let RootfsOutput::Directory(rootfs) =
+        profile.bootstrap.as_backend().rootfs_output(&profile.dir)?
+    else {
+        return Err(RsdebstrapError::Validation(
+            "diff needs a profile with directory output".to_string(),
+        )
+        .into());
+    };
+    if !rootfs.is_dir() {
+        return Err(RsdebstrapError::Validation(format!(
+            "rootfs {} does not exist; run apply first",
+            rootfs
+        ))
+        .into());
+    }
+
+    let mut report = DriftReport::default();
+    missing_packages(&mut report, profile, &rootfs)?;
+    if let Some(task) = &profile.assemble.resolv_conf {
+        resolv_conf_drift(&mut report, task, &rootfs)?;
+    }
+    missing_mount_points(&mut report, profile, &rootfs);
+    task_drift(&mut report, profile, &rootfs)?;
+    Ok(report)
+}
+
+fn missing_packages(report: &mut DriftReport, profile: &Profile, rootfs: &Utf8Path) -> Result<()> {
+    let entries = profile
+        .bootstrap
+        .include()
+        .iter()
+        .flat_map(|e| e.split(|c: char| c == ',' || c.is_whitespace()))
+        .filter(|e| !e.is_empty())
+        .collect::<Vec<_>>();
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let status_path = rootfs.join("var/lib/dpkg/status");
+    let status = fs::read_to_string(&status_path)
+        .map_err(|e| RsdebstrapError::io(format!("failed to read {}", status_path), e))?;
+    let installed = installed_packages(&status);
+    for entry in entries {
+        match package_name(entry) {
+            Some(package) if !installed.contains(package) => {
+                report
+                    .drifts
+                    .push(Drift::MissingPackage(package.to_string()));
+            }
+            Some(_) => {}
+            None => warn!("cannot check include entry {:?} against the installed packages", entry),
+        }
+    }
+    Ok(())
+}
+
+/// Returns the names of the packages dpkg's `status` file lists as installed.
+fn installed_packages(status: &str) -> HashSet<&str> {
+    status
+        .split("\n\n")
+        .filter_map(|stanza| {
+            let field = |name: &str| {
+                stanza
+                    .lines()
+                    .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+                    .map(str::trim)
+            };
+            let installed = field("Status").is_some_and(|s| s.ends_with(" installed"));
+            installed.then(|| field("Package")).flatten()
+        })
+        .collect()
+}
+
+/// Returns the package name of an `include` entry, without a version, release or
+/// architecture qualifier. Apt patterns and local `.deb` files return `None`.
+fn package_name(entry: &str) -> Option<&str> {
+    if entry.starts_with(['?', '~', '.', '/']) || entry.ends_with(".deb") {
+        return None;
+    }
+    let name = entry.split(['=', '/', ':']).next()?;
+    (!name.is_empty()).then_some(name)
+}
+
+fn resolv_conf_drift(
+    report: &mut DriftReport,
+    task: &AssembleResolvConfTask,
+    rootfs: &Utf8Path,
+) -> Result<()> {
+    let path = rootfs.join("etc/resolv.conf");
+    let metadata = match fs::symlink_metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            report
+                .drifts
+                .push(Drift::ResolvConf("is missing".to_string()));
+            return Ok(());
+        }
+        Err(e) => return Err(RsdebstrapError::io(format!("failed to inspect {}", path), e).into()),
+    };
+    let is_symlink = metadata.file_type().is_symlink();
+    let reason = match &task.link {
+        Some(expected) if is_symlink => {
+            let target =
+                fs::read_link(&path).with_context(|| format!("failed to read {}", path))?;
+            (target.as_os_str() != expected.as_str())
+                .then(|| format!("points at {} instead of {}", target.display(), expected))
+        }
+        Some(expected) => Some(format!("is not a symlink to {}", expected)),
+        None if is_symlink => Some("is a symlink instead of the generated file".to_string()),
+        None => {
+            let expected = generate_resolv_conf(&ResolvConfConfig {
+                copy: false,
+                name_servers: task.name_servers.clone(),
+                search: task.search.clone(),
+            });
+            let content =
+                fs::read_to_string(&path).with_context(|| format!("failed to read {}", path))?;
+            (content != expected).then(|| "differs from the configured name servers".to_string())
+        }
+    };
+    if let Some(reason) = reason {
+        report.drifts.push(Drift::ResolvConf(reason));
+    }
+    Ok(())
+}
+
+fn missing_mount_points(report: &mut DriftReport, profile: &Profile, rootfs: &Utf8Path) {
+    let Some(mount) = &profile.prepare.mount else {
+        return;
+    };
+    for entry in mount.resolved_mounts() {
+        let path = rootfs.join(entry.target.as_str().trim_start_matches('/'));
+        if !fs::symlink_metadata(&path).is_ok_and(|m| m.is_dir()) {
+            report.drifts.push(Drift::MissingMountPoint(entry.target));
+        }
+    }
+}
+
+fn task_drift(report: &mut DriftReport, profile: &Profile, rootfs: &Utf8Path) -> Result<()> {
+    let record = TaskRecord::load(rootfs)?;
+    for (index, task) in profile.provision.iter().enumerate() {
+        let number = index + 1;
+        let label = task_label("provision", number, task);
+        match record.tasks.get(&number) {
+            None => report.drifts.push(Drift::TaskNotRecorded(label)),
+            Some(recorded) if recorded.digest != task_digest(task)? => {
+                report.drifts.push(Drift::TaskChanged(label));
+            }
+            Some(_) => {}
+        }
+    }
+    for (number, recorded) in record.tasks.range(profile.provision.len() + 1..) {
+        report
+            .drifts
+            .push(Drift::TaskRemoved(format!("provision {} ({})", number, recorded.name)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn installed_packages_skips_removed_ones() {
+        let status = "Package: vim\nStatus: install ok installed\nVersion: 2\n\n\
+                      Package: nano\nStatus: deinstall ok config-files\n\n\
+                      Status: install ok installed\nPackage: curl\n";
+
+        let installed = installed_packages(status);
+
+        assert_eq!(installed, HashSet::from(["vim", "curl"]));
+    }
+
+    #[test]
+    fn package_name_strips_qualifiers() {
+        assert_eq!(package_name("vim"), Some("vim"));
+        assert_eq!(package_name("vim=2:9.1"), Some("vim"));
+        assert_eq!(package_name("libc6:amd64"), Some("libc6"));
+        assert_eq!(package_name("systemd/trixie-backports"), Some("systemd"));
+        assert_eq!(package_name("?name(vim)"), None);
+        assert_eq!(package_name("./local.deb"), None);
+    }
+
+    #[test]
+    fn report_displays_a_list() {
+        let report = DriftReport {
+            drifts: vec![
+                Drift::MissingPackage("vim".to_string()),
+                Drift::TaskChanged("provision 2 (setup)".to_string()),
+            ],
+        };
+
+        assert_eq!(
+            report.to_string(),
+            "Drift (2 difference(s)):\n  \
+             - package vim is not installed\n  \
+             - provision 2 (setup): script changed since the last recorded run\n"
+        );
+        assert_eq!(
+            DriftReport::default().to_string(),
+            "No drift: the rootfs matches the profile.\n"
+        );
+    }
+}
@@ -0,0 +1,307 @@
+//! Free space pre-check for the output directory.
+//!
+//! A bootstrap that runs out of space dies somewhere in the middle of unpacking with
+//! `ENOSPC`, leaving a half-written rootfs behind. Before the bootstrap starts,
+//! [`check_free_space`] compares the space available on the filesystem of the profile's
+//! `dir` with the space the build needs: the profile's `estimated_size` if set,
+//! otherwise an [`estimate`] from the bootstrap variant and `include` list.
+//!
+//! The estimate is deliberately generous (downloaded packages plus the unpacked
+//! system); profiles that install much more in provision tasks should set
+//! `estimated_size`, and `estimated_size: 0` turns the check off.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::str::FromStr;
+
+use camino::Utf8Path;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::bootstrap::{debootstrap, mmdebstrap};
+use crate::config::{Bootstrap, Profile};
+use crate::error::RsdebstrapError;
+
+const KIB: u64 = 1 << 10;
+const MIB: u64 = 1 << 20;
+const GIB: u64 = 1 << 30;
+
+/// Space added to the estimate for each `include` package.
+pub const PER_PACKAGE: u64 = 32 * MIB;
+
+/// A size in bytes, written in YAML as a number of bytes or with a binary unit
+/// suffix: `K`, `M`, `G` or `T` (also `KiB`, `MiB`, `GiB`, `TiB`), e.g. `2G` or
+/// `1.5G`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(try_from = "SizeWire", into = "String")]
+pub struct ByteSize(pub u64);
+
+/// The accepted YAML shapes of a [`ByteSize`].
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SizeWire {
+    Bytes(u64),
+    Text(String),
+}
+
+impl TryFrom<SizeWire> for ByteSize {
+    type Error = String;
+
+    fn try_from(wire: SizeWire) -> Result<Self, Self::Error> {
+        match wire {
+            SizeWire::Bytes(bytes) => Ok(Self(bytes)),
+            SizeWire::Text(text) => text.parse(),
+        }
+    }
+}
+
+impl FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid size '{}': expected bytes or a number with K/M/G/T", s);
+        let trimmed = s.trim();
+        let split = trimmed
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(trimmed.len());
+        let (number, unit) = trimmed.split_at(split);
+        let unit = match unit.trim() {
+            "" | "B" => 1,
+            "K" | "KiB" => KIB,
+            "M" | "MiB" => MIB,
+            "G" | "GiB" => GIB,
+            "T" | "TiB" => GIB * KIB,
+            _ => return Err(invalid()),
+        };
+        if number.contains('.') {
+            let value: f64 = number.parse().map_err(|_| invalid())?;
+            let bytes = value * unit as f64;
+            if !bytes.is_finite() || bytes >= u64::MAX as f64 {
+                return Err(invalid());
+            }
+            return Ok(Self(bytes.round() as u64));
+        }
+        let value: u64 = number.parse().map_err(|_| invalid())?;
+        value.checked_mul(unit).map(Self).ok_or_else(invalid)
+    }
+}
+
+impl fmt::Display for ByteSize {
+    /// Writes the size in the largest unit that divides it exactly, so it parses back to
+    /// the same value.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (unit, suffix) in [(GIB * KIB, "T"), (GIB, "G"), (MIB, "M"), (KIB, "K")] {
+            if self.0 >= unit && self.0.is_multiple_of(unit) {
+                return write!(f, "{}{}", self.0 / unit, suffix);
+            }
+        }
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<ByteSize> for String {
+    fn from(size: ByteSize) -> Self {
+        size.to_string()
+    }
+}
+
+/// Formats `bytes` for messages, e.g. `1.4 GiB`.
+pub(crate) fn human(bytes: u64) -> String {
+    if bytes >= GIB {
+        format!("{:.1} GiB", bytes as f64 / GIB as f64)
+    } else {
+        format!("{} MiB", bytes.div_ceil(MIB))
+    }
+}
+
+/// Formats a byte count with a binary unit (e.g. `12.3 MiB`).
+pub(crate) fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Returns the disk space used by the files under `root`, without following
+/// symlinks or crossing into other filesystems (such as `prepare.mount` mounts).
+/// Hard-linked files are counted once; unreadable entries are skipped.
+pub(crate) fn disk_usage(root: &Utf8Path) -> u64 {
+    let Ok(meta) = fs::symlink_metadata(root) else {
+        return 0;
+    };
+    let device = meta.dev();
+    let mut seen = HashSet::new();
+    let mut total = meta.blocks() * 512;
+    let mut pending = vec![root.as_std_path().to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+            if meta.dev() != device || (meta.nlink() > 1 && !seen.insert(meta.ino())) {
+                continue;
+            }
+            total += meta.blocks() * 512;
+            if meta.is_dir() {
+                pending.push(entry.path());
+            }
+        }
+    }
+    total
+}
+
+/// Estimates the space a bootstrap takes in the output directory: the downloaded
+/// packages and the unpacked system for the variant, plus [`PER_PACKAGE`] per
+/// `include` entry.
+pub fn estimate(bootstrap: &Bootstrap) -> u64 {
+    let (base, includes) = match bootstrap {
+        Bootstrap::Mmdebstrap(cfg) => {
+            let base = match cfg.variant {
+                mmdebstrap::Variant::Extract | mmdebstrap::Variant::Custom => 64 * MIB,
+                mmdebstrap::Variant::Essential => 200 * MIB,
+                mmdebstrap::Variant::Apt => 300 * MIB,
+                mmdebstrap::Variant::Required | mmdebstrap::Variant::Minbase => 350 * MIB,
+                mmdebstrap::Variant::Debootstrap | mmdebstrap::Variant::Important => 500 * MIB,
+                mmdebstrap::Variant::Buildd => 700 * MIB,
+                mmdebstrap::Variant::Standard => 900 * MIB,
+            };
+            (base, cfg.include.len())
+        }
+        Bootstrap::Debootstrap(cfg) => {
+            let base = match cfg.variant {
+                debootstrap::Variant::Buildd => 700 * MIB,
+                debootstrap::Variant::Minbase
+                | debootstrap::Variant::Fakechroot
+                | debootstrap::Variant::Scratchbox => 350 * MIB,
+            };
+            (base, cfg.include.len())
+        }
+    };
+    base + includes as u64 * PER_PACKAGE
+}
+
+/// Returns the space the build needs in `dir`: `estimated_size` if set, otherwise
+/// the [`estimate`]. `None` if `estimated_size` is 0, which disables the check.
+pub fn required_space(profile: &Profile) -> Option<u64> {
+    match profile.estimated_size {
+        Some(ByteSize(0)) => None,
+        Some(ByteSize(size)) => Some(size),
+        None => Some(estimate(&profile.bootstrap)),
+    }
+}
+
+/// Returns the bytes available to unprivileged users on the filesystem `path` is (or
+/// will be created) on, measured at its nearest existing ancestor.
+pub fn available_space(path: &Utf8Path) -> Result<u64, RsdebstrapError> {
+    let existing = path
+        .ancestors()
+        .find(|p| !p.as_str().is_empty() && p.exists())
+        .unwrap_or(Utf8Path::new("."));
+    let stat = rustix::fs::statvfs(existing.as_std_path()).map_err(|e| {
+        RsdebstrapError::io(format!("failed to read the free space of {}", existing), e.into())
+    })?;
+    Ok(stat.f_bavail.saturating_mul(stat.f_frsize))
+}
+
+/// Checks that the filesystem of the profile's `dir` has the [`required_space`] free.
+///
+/// In dry-run mode a shortage is only warned about.
+///
+/// # Errors
+///
+/// Returns [`RsdebstrapError::Validation`] if less space is available than needed,
+/// or [`RsdebstrapError::Io`] if the free space cannot be read.
+pub fn check_free_space(profile: &Profile, dry_run: bool) -> Result<(), RsdebstrapError> {
+    let Some(required) = required_space(profile) else {
+        info!("skipping the free space check (estimated_size: 0)");
+        return Ok(());
+    };
+    let available = available_space(&profile.dir)?;
+    if available >= required {
+        info!("{} free in {}, about {} needed", human(available), profile.dir, human(required));
+        return Ok(());
+    }
+    let source = if profile.estimated_size.is_some() {
+        "estimated_size"
+    } else {
+        "estimated from the variant and include list"
+    };
+    let message = format!(
+        "not enough free space in {}: {} available, about {} needed ({})",
+        profile.dir,
+        human(available),
+        human(required),
+        source
+    );
+    if dry_run {
+        warn!("{}", message);
+        return Ok(());
+    }
+    Err(RsdebstrapError::Validation(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use camino::Utf8PathBuf;
+
+    #[test]
+    fn sizes_parse_with_binary_units() {
+        assert_eq!("4096".parse(), Ok(ByteSize(4096)));
+        assert_eq!("512M".parse(), Ok(ByteSize(512 * MIB)));
+        assert_eq!("2 GiB".parse(), Ok(ByteSize(2 * GIB)));
+        assert_eq!("1.5G".parse(), Ok(ByteSize(3 * GIB / 2)));
+        assert_eq!("1T".parse(), Ok(ByteSize(GIB * KIB)));
+        for invalid in ["", "G", "2X", "-1G", "1.2.3M", "99999999999T"] {
+            assert!(invalid.parse::<ByteSize>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn sizes_display_in_the_largest_exact_unit() {
+        assert_eq!(ByteSize(2 * GIB).to_string(), "2G");
+        assert_eq!(ByteSize(1536 * MIB).to_string(), "1536M");
+        assert_eq!(ByteSize(1000).to_string(), "1000");
+        assert_eq!(ByteSize(0).to_string(), "0");
+        let size = ByteSize(3 * GIB / 2);
+        assert_eq!(size.to_string().parse(), Ok(size));
+    }
+
+    #[test]
+    fn human_sizes_round_up_mebibytes() {
+        assert_eq!(human(GIB + GIB / 2), "1.5 GiB");
+        assert_eq!(human(MIB + 1), "2 MiB");
+    }
+
+    #[test]
+    fn format_bytes_picks_a_binary_unit() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+
+    #[test]
+    fn disk_usage_counts_hard_links_once() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::from_path_buf(temp.path().to_path_buf()).unwrap();
+        fs::create_dir(root.join("dir")).unwrap();
+        fs::write(root.join("dir/file"), vec![1u8; 64 * 1024]).unwrap();
+        let single = disk_usage(&root);
+        assert!(single >= 64 * 1024, "{single}");
+
+        fs::hard_link(root.join("dir/file"), root.join("link")).unwrap();
+        assert_eq!(disk_usage(&root), single);
+    }
+}
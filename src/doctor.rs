@@ -0,0 +1,492 @@
+//! Host prerequisite checks for the `doctor` subcommand.
+//!
+//! Most first builds that fail do so because the host lacks something rsdebstrap
+//! relies on rather than because of the profile. [`diagnose`] looks for those
+//! prerequisites without changing anything: the bootstrap backends and their versions,
+//! a privilege escalation tool, `tar`, qemu binfmt handlers when cross-building, user
+//! namespaces and overlayfs support in the kernel, and free space below the output
+//! directory. Each [`Check`] is a pass, a warning (something only some profiles need)
+//! or a failure (something the build will trip over), with an explanation.
+//!
+//! Given a profile, the checks follow it: the backend it uses is required, the
+//! privilege methods it uses must be installed, and its architectures and `dir`
+//! decide the cross-build and disk space checks. The host is read through the
+//! [`Host`] trait so the checks can be tested against a fake one.
+
+use std::fmt;
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::bootstrap::{ToolVersion, version};
+use crate::config::{Bootstrap, Profile};
+use crate::disk_space;
+use crate::privilege::PrivilegeMethod;
+
+/// Free space below the output directory that always fails, even when the estimated
+/// size of the build is smaller.
+pub const MIN_FREE_BYTES: u64 = 1 << 30;
+
+/// Free space below the output directory under which a warning is shown.
+///
+/// A minimal rootfs takes a few hundred MiB; images with a desktop or the apt cache
+/// take several GiB.
+pub const LOW_FREE_BYTES: u64 = 4 << 30;
+
+/// Oldest mmdebstrap release the generated command lines are tested with.
+const MIN_MMDEBSTRAP_VERSION: &str = "1.0.0";
+
+/// The outcome of a [`Check`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    /// The prerequisite is met.
+    Pass,
+    /// The prerequisite is missing but only some builds need it.
+    Warn,
+    /// The build will fail without the prerequisite.
+    Fail,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Pass => "pass",
+            Self::Warn => "warn",
+            Self::Fail => "fail",
+        })
+    }
+}
+
+/// One host prerequisite and what was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    /// What was checked (e.g. `mmdebstrap`, `user namespaces`).
+    pub name: String,
+    /// Whether the prerequisite is met.
+    pub status: Status,
+    /// What was found, and for a warning or failure how to fix it.
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: impl Into<String>, status: Status, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}: {}", self.status, self.name, self.detail)
+    }
+}
+
+/// The checks run by [`diagnose`]. Displays as a list with a summary line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DoctorReport {
+    /// The checks in the order they ran.
+    pub checks: Vec<Check>,
+}
+
+impl DoctorReport {
+    /// Returns the number of checks with `status`.
+    pub fn count(&self, status: Status) -> usize {
+        self.checks.iter().filter(|c| c.status == status).count()
+    }
+
+    /// Returns the check named `name`, if it ran.
+    pub fn get(&self, name: &str) -> Option<&Check> {
+        self.checks.iter().find(|c| c.name == name)
+    }
+}
+
+impl fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            writeln!(f, "{}", check)?;
+        }
+        writeln!(
+            f,
+            "{} passed, {} warning(s), {} failed",
+            self.count(Status::Pass),
+            self.count(Status::Warn),
+            self.count(Status::Fail)
+        )
+    }
+}
+
+/// The parts of the host [`diagnose`] looks at.
+pub trait Host {
+    /// Returns the path of `name` in PATH.
+    fn find_command(&self, name: &str) -> Option<Utf8PathBuf>;
+    /// Returns the first line `command --version` prints.
+    fn command_version(&self, command: &Utf8Path) -> Option<String>;
+    /// Returns the contents of a (usually `/proc`) file.
+    fn read_file(&self, path: &Utf8Path) -> Option<String>;
+    /// Returns whether `path` exists.
+    fn exists(&self, path: &Utf8Path) -> bool;
+    /// Returns the bytes available to unprivileged users on the filesystem of `path`.
+    fn available_space(&self, path: &Utf8Path) -> Option<u64>;
+    /// Returns whether rsdebstrap runs as root.
+    fn is_root(&self) -> bool;
+    /// Returns the Debian architecture of the host (e.g. `amd64`).
+    fn arch(&self) -> String;
+}
+
+/// The [`Host`] rsdebstrap runs on.
+pub struct SystemHost;
+
+impl Host for SystemHost {
+    fn find_command(&self, name: &str) -> Option<Utf8PathBuf> {
+        let path = which::which(name).ok()?;
+        Utf8PathBuf::from_path_buf(path).ok()
+    }
+
+    fn command_version(&self, command: &Utf8Path) -> Option<String> {
+        version::version_line(command.as_str())
+    }
+
+    fn read_file(&self, path: &Utf8Path) -> Option<String> {
+        std::fs::read_to_string(path).ok()
+    }
+
+    fn exists(&self, path: &Utf8Path) -> bool {
+        path.exists()
+    }
+
+    fn available_space(&self, path: &Utf8Path) -> Option<u64> {
+        disk_space::available_space(path).ok()
+    }
+
+    fn is_root(&self) -> bool {
+        rustix::process::geteuid().is_root()
+    }
+
+    fn arch(&self) -> String {
+        debian_arch(std::env::consts::ARCH).to_string()
+    }
+}
+
+/// Maps a Rust target architecture to the Debian one.
+pub(crate) fn debian_arch(arch: &str) -> &str {
+    match arch {
+        "x86_64" => "amd64",
+        "x86" => "i386",
+        "aarch64" => "arm64",
+        "arm" => "armhf",
+        "powerpc64" => "ppc64el",
+        "loongarch64" => "loong64",
+        other => other,
+    }
+}
+
+/// Maps a Debian architecture to the Rust one, undoing [`debian_arch`].
+pub(crate) fn rust_arch(arch: &str) -> &str {
+    match arch {
+        "amd64" => "x86_64",
+        "i386" => "x86",
+        "arm64" => "aarch64",
+        "armhf" => "arm",
+        "ppc64el" => "powerpc64",
+        "loong64" => "loongarch64",
+        other => other,
+    }
+}
+
+/// Maps a Debian architecture to the name of its qemu binfmt handler.
+fn qemu_arch(arch: &str) -> &str {
+    match arch {
+        "amd64" => "x86_64",
+        "arm64" => "aarch64",
+        "armhf" | "armel" => "arm",
+        "ppc64el" => "ppc64le",
+        "loong64" => "loongarch64",
+        other => other,
+    }
+}
+
+/// Runs every check against `host`, following `profile` if one is given.
+///
+/// `profile` should come from [`crate::config::load_profile`], whose privilege
+/// settings are resolved; it does not have to pass validation.
+pub fn diagnose(host: &dyn Host, profile: Option<&Profile>) -> DoctorReport {
+    let mut checks = Vec::new();
+    check_backends(host, profile, &mut checks);
+    check_privilege(host, profile, &mut checks);
+    checks.push(match host.find_command("tar") {
+        Some(path) => Check::new("tar", Status::Pass, path.as_str()),
+        None => Check::new(
+            "tar",
+            Status::Warn,
+            "not installed; needed for tar outputs, overlays, `--remote` and the layer cache \
+            (`apt install tar`)",
+        ),
+    });
+    if let Some(profile) = profile {
+        check_cross_build(host, &profile.bootstrap, &mut checks);
+    }
+    checks.push(check_user_namespaces(host, profile));
+    checks.push(check_overlayfs(host));
+    if let Some(profile) = profile {
+        checks.push(check_disk_space(host, profile));
+    }
+    DoctorReport { checks }
+}
+
+/// Checks that the bootstrap backends are installed and recent enough.
+///
+/// The profile's backend is required. Without a profile, one of the two is.
+fn check_backends(host: &dyn Host, profile: Option<&Profile>, checks: &mut Vec<Check>) {
+    let required = profile.map(|p| p.bootstrap.as_backend().command_name());
+    let found: Vec<_> = ["mmdebstrap", "debootstrap"]
+        .into_iter()
+        .map(|name| (name, host.find_command(name)))
+        .collect();
+    let any_found = found.iter().any(|(_, path)| path.is_some());
+    for (name, path) in found {
+        let Some(path) = path else {
+            let (status, why) = match required {
+                Some(required) if required == name => {
+                    (Status::Fail, "the profile's bootstrap uses it".to_string())
+                }
+                None if !any_found => (Status::Fail, "no bootstrap backend is installed".into()),
+                _ => (Status::Warn, format!("only profiles with `type: {}` need it", name)),
+            };
+            checks.push(Check::new(
+                name,
+                status,
+                format!("not installed; {} (`apt install {}`)", why, name),
+            ));
+            continue;
+        };
+        let line = host.command_version(&path).unwrap_or_default();
+        let version = ToolVersion::parse_output(&line);
+        let detail = match &version {
+            Some(v) => format!("{} at {}", v, path),
+            None => format!("{} (version unknown)", path),
+        };
+        let min_version = profile
+            .filter(|_| required == Some(name))
+            .and_then(|p| p.bootstrap.min_version());
+        if let Some(min_version) = min_version
+            && version.as_ref().is_none_or(|v| v < min_version)
+        {
+            checks.push(Check::new(
+                name,
+                Status::Fail,
+                format!("{}; the profile needs {} or newer (min_version)", detail, min_version),
+            ));
+            continue;
+        }
+        let too_old = name == "mmdebstrap"
+            && version
+                .as_ref()
+                .is_some_and(|v| version::predates(v, MIN_MMDEBSTRAP_VERSION));
+        checks.push(if too_old {
+            Check::new(
+                name,
+                Status::Warn,
+                format!(
+                    "{}; older than {}, some options rsdebstrap passes may be rejected",
+                    detail, MIN_MMDEBSTRAP_VERSION
+                ),
+            )
+        } else {
+            Check::new(name, Status::Pass, detail)
+        });
+    }
+
+    if let Some(Bootstrap::Debootstrap(cfg)) = profile.map(|p| &p.bootstrap) {
+        let script = Utf8Path::new("/usr/share/debootstrap/scripts").join(&cfg.suite);
+        if host.find_command("debootstrap").is_some() && !host.exists(&script) {
+            checks.push(Check::new(
+                format!("debootstrap suite {}", cfg.suite),
+                Status::Fail,
+                format!("{} is missing; upgrade debootstrap to one that knows the suite", script),
+            ));
+        }
+    }
+}
+
+/// Checks that privilege escalation is possible.
+///
+/// With a profile, every method it uses must be installed. Without one, root or an
+/// installed `sudo`/`doas` passes. Nothing is run, so no password is asked for; `apply`
+/// runs `<method> true` before the build for that.
+fn check_privilege(host: &dyn Host, profile: Option<&Profile>, checks: &mut Vec<Check>) {
+    let Some(profile) = profile else {
+        checks.push(if host.is_root() {
+            Check::new("privilege", Status::Pass, "running as root")
+        } else if let Some(path) = ["sudo", "doas"]
+            .into_iter()
+            .find_map(|name| host.find_command(name))
+        {
+            Check::new("privilege", Status::Pass, path.as_str())
+        } else {
+            Check::new(
+                "privilege",
+                Status::Warn,
+                "not root and neither sudo nor doas is installed; only rootless profiles \
+                (`privilege: { method: userns }`) can be built",
+            )
+        });
+        return;
+    };
+    for method in profile.privilege_methods() {
+        let name = format!("privilege {}", method);
+        let command = method.command_name();
+        checks.push(match host.find_command(command) {
+            Some(path) => Check::new(name, Status::Pass, path.as_str()),
+            None => Check::new(
+                name,
+                Status::Fail,
+                format!("`{}` is not installed but the profile uses it", command),
+            ),
+        });
+    }
+}
+
+/// Checks that foreign architectures have a qemu binfmt handler registered.
+fn check_cross_build(host: &dyn Host, bootstrap: &Bootstrap, checks: &mut Vec<Check>) {
+    let host_arch = host.arch();
+    for arch in bootstrap
+        .architectures()
+        .into_iter()
+        .filter(|a| *a != host_arch)
+    {
+        let qemu = qemu_arch(arch);
+        let handler = Utf8PathBuf::from(format!("/proc/sys/fs/binfmt_misc/qemu-{}", qemu));
+        let name = format!("qemu {}", arch);
+        checks.push(match host.read_file(&handler) {
+            Some(state) if state.starts_with("enabled") => {
+                Check::new(name, Status::Pass, format!("{} is enabled", handler))
+            }
+            _ => Check::new(
+                name,
+                Status::Fail,
+                format!(
+                    "building {} on {} needs the qemu-{} binfmt handler; install \
+                    qemu-user-static and binfmt-support",
+                    arch, host_arch, qemu
+                ),
+            ),
+        });
+    }
+}
+
+/// Checks that unprivileged user namespaces are allowed.
+///
+/// They are required by `privilege: { method: userns }`, and optional otherwise.
+fn check_user_namespaces(host: &dyn Host, profile: Option<&Profile>) -> Check {
+    let disabled = [
+        ("/proc/sys/user/max_user_namespaces", "0"),
+        ("/proc/sys/kernel/unprivileged_userns_clone", "0"),
+        ("/proc/sys/kernel/apparmor_restrict_unprivileged_userns", "1"),
+    ]
+    .into_iter()
+    .find(|(path, value)| {
+        host.read_file(Utf8Path::new(path))
+            .is_some_and(|content| content.trim() == *value)
+    });
+    let Some((path, value)) = disabled else {
+        return Check::new(
+            "user namespaces",
+            Status::Pass,
+            "unprivileged user namespaces are allowed",
+        );
+    };
+    let needed = profile.is_some_and(|p| p.privilege_methods().contains(&PrivilegeMethod::Userns));
+    Check::new(
+        "user namespaces",
+        if needed { Status::Fail } else { Status::Warn },
+        format!(
+            "{} is {}, so rootless builds (`privilege: {{ method: userns }}`) cannot run; \
+            change it with sysctl",
+            path, value
+        ),
+    )
+}
+
+/// Checks that the kernel supports overlayfs, which `apply --overlay` mounts.
+fn check_overlayfs(host: &dyn Host) -> Check {
+    let supported = host
+        .read_file(Utf8Path::new("/proc/filesystems"))
+        .is_some_and(|list| {
+            list.lines()
+                .any(|line| line.split_whitespace().last() == Some("overlay"))
+        });
+    if supported {
+        Check::new("overlayfs", Status::Pass, "supported by the kernel")
+    } else {
+        Check::new(
+            "overlayfs",
+            Status::Warn,
+            "not listed in /proc/filesystems; `apply --overlay` needs it (`modprobe overlay`)",
+        )
+    }
+}
+
+/// Checks the free space on the filesystem the profile's `dir` is (or will be) on.
+///
+/// Less than the space `apply` requires before the bootstrap
+/// ([`disk_space::required_space`], at least [`MIN_FREE_BYTES`]) fails.
+fn check_disk_space(host: &dyn Host, profile: &Profile) -> Check {
+    let dir = profile.dir.as_path();
+    let required =
+        disk_space::required_space(profile).map_or(MIN_FREE_BYTES, |r| r.max(MIN_FREE_BYTES));
+    let existing = dir.ancestors().find(|p| host.exists(p)).unwrap_or(dir);
+    let Some(available) = host.available_space(existing) else {
+        return Check::new(
+            "disk space",
+            Status::Warn,
+            format!("could not read the free space of {}", existing),
+        );
+    };
+    let status = if available < required {
+        Status::Fail
+    } else if available < LOW_FREE_BYTES {
+        Status::Warn
+    } else {
+        Status::Pass
+    };
+    let mut detail = format!("{} free at {}", disk_space::human(available), existing);
+    match status {
+        Status::Fail => detail.push_str(&format!(
+            "; the build needs about {} (set `estimated_size` if that is wrong)",
+            disk_space::human(required)
+        )),
+        Status::Warn => detail.push_str(&format!(
+            "; larger images usually need {} GiB or more",
+            LOW_FREE_BYTES >> 30
+        )),
+        Status::Pass => {}
+    }
+    Check::new("disk space", status, detail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_are_parsed_from_the_first_numeric_word() {
+        let parse = ToolVersion::parse_output;
+        assert_eq!(parse("mmdebstrap 1.4.3"), "1.4.3".parse().ok());
+        assert_eq!(parse("debootstrap 1.0.134+deb12u1"), "1.0.134".parse().ok());
+        assert_eq!(parse("no version here"), None);
+        assert!(version::predates(&parse("mmdebstrap 0.8.4").unwrap(), MIN_MMDEBSTRAP_VERSION));
+    }
+
+    #[test]
+    fn architectures_map_between_rust_debian_and_qemu() {
+        assert_eq!(debian_arch("x86_64"), "amd64");
+        assert_eq!(debian_arch("riscv64"), "riscv64");
+        for arch in ["x86_64", "x86", "aarch64", "arm", "powerpc64", "riscv64"] {
+            assert_eq!(rust_arch(debian_arch(arch)), arch);
+        }
+        assert_eq!(qemu_arch("arm64"), "aarch64");
+        assert_eq!(qemu_arch("ppc64el"), "ppc64le");
+    }
+}
@@ -0,0 +1,372 @@
+//! Downloading and checksum verification of host-side input files.
+//!
+//! Profiles can reference files that are fetched at `apply` time (keyrings,
+//! task binaries) instead of being pre-placed on the host. Downloads go through
+//! `curl` via the [`CommandExecutor`], like every other external command, so they
+//! are logged and skipped in dry-run mode. A downloaded file must match the
+//! SHA-256 pinned in the profile before it is used.
+//!
+//! [`fetch_cached`] keeps verified downloads in a cache directory named by their
+//! digest, so a rebuild (or another profile pinning the same file) reuses them.
+
+use std::fs::{self, File};
+use std::io;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::error::RsdebstrapError;
+use crate::executor::{CommandExecutor, CommandSpec};
+
+/// Validates that `value` is a SHA-256 digest written as 64 hexadecimal digits.
+pub fn validate_sha256(value: &str, label: &str) -> Result<(), RsdebstrapError> {
+    if value.len() != 64 || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(RsdebstrapError::Validation(format!(
+            "{} sha256 must be 64 hexadecimal digits, got '{}'",
+            label, value
+        )));
+    }
+    Ok(())
+}
+
+/// Validates that `url` is an `http://` or `https://` URL.
+pub fn validate_url(url: &str, label: &str) -> Result<(), RsdebstrapError> {
+    let valid =
+        Url::parse(url).is_ok_and(|u| matches!(u.scheme(), "http" | "https") && u.host().is_some());
+    if !valid {
+        return Err(RsdebstrapError::Validation(format!(
+            "{} url '{}' must be an http:// or https:// URL",
+            label, url
+        )));
+    }
+    Ok(())
+}
+
+/// Returns the lowercase hexadecimal SHA-256 digest of the file at `path`.
+pub fn sha256_file(path: &Utf8Path) -> Result<String, RsdebstrapError> {
+    let mut file = File::open(path).map_err(|e| RsdebstrapError::io(path.to_string(), e))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)
+        .map_err(|e| RsdebstrapError::io(format!("failed to read {}", path), e))?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Verifies that the file at `path` has the SHA-256 digest `expected`.
+///
+/// The comparison ignores the case of the hexadecimal digits.
+///
+/// # Errors
+///
+/// Returns [`RsdebstrapError::ChecksumMismatch`] if the digests differ, or
+/// [`RsdebstrapError::Io`] if the file cannot be read.
+pub fn verify_sha256(path: &Utf8Path, expected: &str) -> Result<(), RsdebstrapError> {
+    let actual = sha256_file(path)?;
+    if !actual.eq_ignore_ascii_case(expected) {
+        return Err(RsdebstrapError::ChecksumMismatch {
+            path: path.to_string(),
+            expected: expected.to_ascii_lowercase(),
+            actual,
+        });
+    }
+    Ok(())
+}
+
+/// Builds the `curl` command that downloads `url` to `dest`.
+pub fn download_spec(url: &str, dest: &Utf8Path) -> CommandSpec {
+    CommandSpec::new(
+        "curl",
+        vec![
+            "--fail".to_string(),
+            "--silent".to_string(),
+            "--show-error".to_string(),
+            "--location".to_string(),
+            "--output".to_string(),
+            dest.to_string(),
+            url.to_string(),
+        ],
+    )
+}
+
+/// Downloads `url` to `dest` and verifies it against `sha256`.
+///
+/// In dry-run mode the executor does not run `curl`, so nothing is downloaded
+/// and verification is skipped.
+///
+/// # Errors
+///
+/// Returns an error if `curl` fails or the downloaded file does not match.
+pub fn download_verified(
+    url: &str,
+    dest: &Utf8Path,
+    sha256: &str,
+    executor: &dyn CommandExecutor,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    executor.execute_checked(&download_spec(url, dest))?;
+    if dry_run {
+        tracing::info!("would verify sha256 of {} against {}", dest, sha256);
+        return Ok(());
+    }
+    verify_sha256(dest, sha256)?;
+    tracing::info!("downloaded and verified {}", url);
+    Ok(())
+}
+
+/// Returns the default download cache: `$XDG_CACHE_HOME/rsdebstrap/downloads`, or
+/// `~/.cache/rsdebstrap/downloads` without it.
+///
+/// # Errors
+///
+/// Returns [`RsdebstrapError::Validation`] if neither `XDG_CACHE_HOME` nor `HOME` is
+/// set.
+pub fn default_cache_dir() -> Result<Utf8PathBuf, RsdebstrapError> {
+    let var = |name| std::env::var(name).ok().filter(|v| !v.is_empty());
+    let base = match (var("XDG_CACHE_HOME"), var("HOME")) {
+        (Some(cache), _) => Utf8PathBuf::from(cache),
+        (None, Some(home)) => Utf8PathBuf::from(home).join(".cache"),
+        (None, None) => {
+            return Err(RsdebstrapError::Validation(
+                "no download cache directory: set XDG_CACHE_HOME or HOME, or a cache_dir"
+                    .to_string(),
+            ));
+        }
+    };
+    Ok(base.join("rsdebstrap/downloads"))
+}
+
+/// Returns the file `url` is cached as in `cache_dir`, downloading and verifying it
+/// against `sha256` unless a verified copy is already there.
+///
+/// Entries are named by their digest. A download is written to a partial file of
+/// its own and renamed into place once verified, so concurrent builds sharing
+/// the cache never see a partial or unverified file. A cached copy that no longer
+/// matches is downloaded again. In dry-run mode nothing is downloaded.
+///
+/// # Errors
+///
+/// Returns an error if the cache cannot be written, `curl` fails or the download
+/// does not match.
+pub fn fetch_cached(
+    url: &str,
+    sha256: &str,
+    cache_dir: &Utf8Path,
+    executor: &dyn CommandExecutor,
+    dry_run: bool,
+) -> anyhow::Result<Utf8PathBuf> {
+    let sha256 = sha256.to_ascii_lowercase();
+    let cached = cache_dir.join(&sha256);
+    if cached.is_file() {
+        match verify_sha256(&cached, &sha256) {
+            Ok(()) => {
+                tracing::info!("using cached download of {}", url);
+                return Ok(cached);
+            }
+            Err(RsdebstrapError::ChecksumMismatch { .. }) => {
+                tracing::warn!("cached {} does not match, downloading it again", cached);
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    if dry_run {
+        executor.execute_checked(&download_spec(url, &cached))?;
+        return Ok(cached);
+    }
+    fs::create_dir_all(cache_dir)
+        .map_err(|e| RsdebstrapError::io(format!("failed to create {}", cache_dir), e))?;
+    let partial = tempfile::Builder::new()
+        .prefix(&format!("{}.", sha256))
+        .suffix(".part")
+        .tempfile_in(cache_dir)
+        .map_err(|e| RsdebstrapError::io(format!("failed to create a file in {}", cache_dir), e))?;
+    let partial_path = Utf8Path::from_path(partial.path()).ok_or_else(|| {
+        RsdebstrapError::Validation(format!("{} is not valid UTF-8", partial.path().display()))
+    })?;
+    download_verified(url, partial_path, &sha256, executor, false)?;
+    partial
+        .persist(&cached)
+        .map_err(|e| RsdebstrapError::io(format!("failed to store {}", cached), e.error))?;
+    Ok(cached)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use camino::Utf8PathBuf;
+
+    /// SHA-256 of the bytes `hello\n`.
+    const HELLO_SHA256: &str = "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03";
+
+    fn write_temp(content: &[u8]) -> (tempfile::TempDir, Utf8PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::from_path_buf(dir.path().join("file")).unwrap();
+        std::fs::write(&path, content).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn sha256_file_hashes_contents() {
+        let (_dir, path) = write_temp(b"hello\n");
+        assert_eq!(sha256_file(&path).unwrap(), HELLO_SHA256);
+    }
+
+    #[test]
+    fn verify_accepts_uppercase_digest() {
+        let (_dir, path) = write_temp(b"hello\n");
+        verify_sha256(&path, &HELLO_SHA256.to_ascii_uppercase()).unwrap();
+    }
+
+    #[test]
+    fn verify_mismatch_reports_both_digests() {
+        let (_dir, path) = write_temp(b"tampered\n");
+        let err = verify_sha256(&path, HELLO_SHA256).unwrap_err();
+        match err {
+            RsdebstrapError::ChecksumMismatch {
+                expected, actual, ..
+            } => {
+                assert_eq!(expected, HELLO_SHA256);
+                assert_ne!(actual, HELLO_SHA256);
+            }
+            other => panic!("expected ChecksumMismatch, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn verify_missing_file_is_io_error() {
+        let err =
+            verify_sha256(Utf8Path::new("/nonexistent/keyring.gpg"), HELLO_SHA256).unwrap_err();
+        assert!(matches!(err, RsdebstrapError::Io { .. }));
+    }
+
+    #[test]
+    fn validate_sha256_rejects_malformed_digests() {
+        validate_sha256(HELLO_SHA256, "keyring").unwrap();
+        for bad in [
+            "",
+            "abc",
+            &HELLO_SHA256[1..],
+            &HELLO_SHA256.replace('5', "g"),
+        ] {
+            let err = validate_sha256(bad, "keyring").unwrap_err();
+            assert!(err.to_string().contains("64 hexadecimal digits"), "{err}");
+        }
+    }
+
+    #[test]
+    fn validate_url_requires_http_scheme() {
+        validate_url("https://example.org/key.gpg", "keyring").unwrap();
+        for bad in [
+            "ftp://example.org/key.gpg",
+            "/srv/key.gpg",
+            "example.org/key.gpg",
+        ] {
+            assert!(validate_url(bad, "keyring").is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn download_spec_writes_to_destination() {
+        let spec = download_spec("https://example.org/key.gpg", Utf8Path::new("/tmp/key.gpg"));
+        assert_eq!(spec.command, "curl");
+        assert_eq!(
+            spec.args,
+            [
+                "--fail",
+                "--silent",
+                "--show-error",
+                "--location",
+                "--output",
+                "/tmp/key.gpg",
+                "https://example.org/key.gpg"
+            ]
+        );
+    }
+
+    /// Fake `curl` writing fixed content to `--output`.
+    struct FakeCurl {
+        content: &'static [u8],
+        calls: std::sync::Mutex<usize>,
+    }
+
+    impl CommandExecutor for FakeCurl {
+        fn execute(&self, spec: &CommandSpec) -> anyhow::Result<crate::executor::ExecutionResult> {
+            *self.calls.lock().unwrap() += 1;
+            let index = spec.args.iter().position(|a| a == "--output").unwrap();
+            std::fs::write(&spec.args[index + 1], self.content)?;
+            Ok(crate::executor::ExecutionResult {
+                status: None,
+                details: None,
+            })
+        }
+    }
+
+    #[test]
+    fn fetch_cached_reuses_verified_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Utf8PathBuf::from_path_buf(dir.path().join("cache")).unwrap();
+        let curl = FakeCurl {
+            content: b"hello\n",
+            calls: Default::default(),
+        };
+        let url = "https://example.org/hello";
+        let cached = fetch_cached(url, HELLO_SHA256, &cache, &curl, false).unwrap();
+        assert_eq!(cached, cache.join(HELLO_SHA256));
+        fetch_cached(url, &HELLO_SHA256.to_ascii_uppercase(), &cache, &curl, false).unwrap();
+        assert_eq!(*curl.calls.lock().unwrap(), 1);
+
+        // A corrupted entry is downloaded again.
+        std::fs::write(&cached, b"tampered\n").unwrap();
+        fetch_cached(url, HELLO_SHA256, &cache, &curl, false).unwrap();
+        assert_eq!(*curl.calls.lock().unwrap(), 2);
+        assert_eq!(std::fs::read(&cached).unwrap(), b"hello\n");
+    }
+
+    #[test]
+    fn fetch_cached_downloads_to_a_partial_file_of_its_own() {
+        struct Outputs(std::sync::Mutex<Vec<String>>);
+        impl CommandExecutor for Outputs {
+            fn execute(
+                &self,
+                spec: &CommandSpec,
+            ) -> anyhow::Result<crate::executor::ExecutionResult> {
+                let index = spec.args.iter().position(|a| a == "--output").unwrap();
+                self.0.lock().unwrap().push(spec.args[index + 1].clone());
+                std::fs::write(&spec.args[index + 1], b"hello\n")?;
+                Ok(crate::executor::ExecutionResult {
+                    status: None,
+                    details: None,
+                })
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap();
+        let curl = Outputs(Default::default());
+        for _ in 0..2 {
+            fetch_cached("https://example.org/hello", HELLO_SHA256, &cache, &curl, false).unwrap();
+            std::fs::remove_file(cache.join(HELLO_SHA256)).unwrap();
+        }
+
+        let outputs = curl.0.lock().unwrap();
+        assert_ne!(outputs[0], outputs[1]);
+        assert!(outputs.iter().all(|o| o.ends_with(".part")), "{outputs:?}");
+        assert_eq!(std::fs::read_dir(&cache).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn fetch_cached_discards_mismatched_downloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap();
+        let curl = FakeCurl {
+            content: b"tampered\n",
+            calls: Default::default(),
+        };
+        let err = fetch_cached("https://example.org/hello", HELLO_SHA256, &cache, &curl, false)
+            .unwrap_err();
+        assert!(
+            err.downcast_ref::<RsdebstrapError>()
+                .is_some_and(|e| matches!(e, RsdebstrapError::ChecksumMismatch { .. }))
+        );
+        assert_eq!(std::fs::read_dir(&cache).unwrap().count(), 0);
+    }
+}
@@ -8,10 +8,22 @@
 //! `RsdebstrapError` implements `std::error::Error` (via `thiserror`), which
 //! allows automatic conversion into `anyhow::Error` via the `?` operator
 //! at trait boundaries that return `anyhow::Result`.
+//!
+//! The process exit code of a failed run is chosen by [`exit_code()`]: a typed
+//! error with its own category (configuration, validation, privilege) decides it,
+//! otherwise the [`Stage`] recorded with a [`StageContext`] on the way up does.
+//!
+//! Each variant also has a stable error code ([`RsdebstrapError::code()`], e.g.
+//! `RSD0005`) that support requests and scripts can refer to, and many errors carry a
+//! remediation [`hint()`] that `main` prints below the error.
 
+use std::fmt;
 use std::io;
+use std::time::Duration;
 
 use crate::executor::format_command_args;
+use crate::privilege::PrivilegeMethod;
+use crate::redact;
 
 /// Formats an IO error kind into a human-readable message.
 ///
@@ -32,6 +44,248 @@ pub(crate) fn io_error_kind_message(err: &io::Error) -> String {
     }
 }
 
+/// Process exit codes of `rsdebstrap`, one per failure category.
+pub mod exit_codes {
+    /// Any failure without a more specific category.
+    pub const FAILURE: i32 = 1;
+    /// The profile could not be loaded or parsed. Also used by clap for usage errors.
+    pub const CONFIG: i32 = 2;
+    /// The profile or one of its inputs failed validation (including checksums).
+    pub const VALIDATION: i32 = 3;
+    /// Preparing or running the bootstrap backend failed.
+    pub const BOOTSTRAP: i32 = 4;
+    /// Setting up or running a pipeline phase failed.
+    pub const PIPELINE: i32 = 5;
+    /// Cleaning up after the pipeline (restores, unmounts) failed.
+    pub const TEARDOWN: i32 = 6;
+    /// Privilege escalation is unavailable or was refused.
+    pub const PRIVILEGE: i32 = 7;
+}
+
+/// Stage of an `apply` run that an error came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Loading the profile.
+    Profile,
+    /// Preparing inputs for and running the bootstrap backend.
+    Bootstrap,
+    /// Setting up and running the prepare, provision, assemble and verify phases.
+    Pipeline,
+    /// Restoring and unmounting after the pipeline.
+    Teardown,
+}
+
+impl Stage {
+    /// Returns the exit code of a failure in this stage.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Profile => exit_codes::CONFIG,
+            Self::Bootstrap => exit_codes::BOOTSTRAP,
+            Self::Pipeline => exit_codes::PIPELINE,
+            Self::Teardown => exit_codes::TEARDOWN,
+        }
+    }
+
+    /// Creates an error context that records this stage.
+    pub fn context(self, message: impl Into<String>) -> StageContext {
+        StageContext {
+            stage: self,
+            message: message.into(),
+        }
+    }
+}
+
+/// Error context carrying the [`Stage`] it was attached in.
+///
+/// Displays only its message, so tagging a context does not change the error text.
+#[derive(Debug)]
+pub struct StageContext {
+    stage: Stage,
+    message: String,
+}
+
+impl StageContext {
+    /// Returns the stage recorded with this context.
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+}
+
+impl fmt::Display for StageContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Returns the process exit code for a failed run.
+///
+/// The first [`RsdebstrapError`] in the chain decides if its variant has a category
+/// of its own ([`RsdebstrapError::exit_code()`]); otherwise the outermost
+/// [`StageContext`] does, and [`exit_codes::FAILURE`] is the fallback.
+pub fn exit_code(err: &anyhow::Error) -> i32 {
+    let typed = err
+        .chain()
+        .find_map(|e| e.downcast_ref::<RsdebstrapError>())
+        .map(RsdebstrapError::exit_code)
+        .filter(|&code| code != exit_codes::FAILURE);
+    typed
+        .or_else(|| {
+            err.downcast_ref::<StageContext>()
+                .map(|c| c.stage().exit_code())
+        })
+        .unwrap_or(exit_codes::FAILURE)
+}
+
+/// Returns the code of the first [`RsdebstrapError`] in the chain, if any.
+pub fn error_code(err: &anyhow::Error) -> Option<&'static str> {
+    err.chain()
+        .find_map(|e| e.downcast_ref::<RsdebstrapError>())
+        .map(RsdebstrapError::code)
+}
+
+/// Returns the remediation hint of the first [`RsdebstrapError`] in the chain that has
+/// one.
+pub fn hint(err: &anyhow::Error) -> Option<String> {
+    err.chain()
+        .filter_map(|e| e.downcast_ref::<RsdebstrapError>())
+        .find_map(RsdebstrapError::hint)
+}
+
+/// Returns whether `err` comes from a command interrupted by a signal or a `serve`
+/// cancel ([`RsdebstrapError::Interrupted`]), anywhere in its chain or among the
+/// errors it collects.
+pub fn is_interrupted(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|e| e.downcast_ref::<RsdebstrapError>())
+        .any(|e| match e {
+            RsdebstrapError::Interrupted { .. } => true,
+            RsdebstrapError::Multiple { errors, .. } => errors.iter().any(is_interrupted),
+            _ => false,
+        })
+}
+
+/// Formats `spec` as `"[privilege] command arg1 arg2 ..."` for execution errors.
+fn spec_command(spec: &crate::executor::CommandSpec) -> String {
+    if let Some(method) = &spec.privilege {
+        if spec.args.is_empty() {
+            format!("{} {}", method.command_name(), spec.command)
+        } else {
+            format!(
+                "{} {} {}",
+                method.command_name(),
+                spec.command,
+                format_command_args(&spec.args)
+            )
+        }
+    } else if spec.args.is_empty() {
+        spec.command.clone()
+    } else {
+        format!("{} {}", spec.command, format_command_args(&spec.args))
+    }
+}
+
+/// Hint for an operation that needs root, such as mounting.
+const PRIVILEGE_HINT: &str = "mounting needs root; configure privilege escalation, e.g. \
+    `defaults: { privilege: { method: sudo } }` (or `privilege: { method: sudo }` on the task)";
+
+/// Returns the package that provides `command` on Debian, if it differs from the name.
+fn package_for(command: &str) -> &str {
+    match command {
+        "mount" | "umount" | "unshare" => "util-linux",
+        "chroot" | "env" => "coreutils",
+        "gpg" => "gnupg",
+        "run0" => "systemd",
+        "pkexec" => "polkitd",
+        other => other,
+    }
+}
+
+/// Number of trailing standard error lines kept in [`ExecutionDetails::stderr_tail`].
+pub const STDERR_TAIL_LINES: usize = 20;
+
+/// How a command ran, attached to an [`RsdebstrapError::Execution`] error.
+///
+/// Recorded by executors that run real processes. The command vector and
+/// environment are redacted when recorded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecutionDetails {
+    /// The program and arguments as run, privilege escalation command included.
+    pub argv: Vec<String>,
+    /// The working directory, if the command did not inherit rsdebstrap's.
+    pub cwd: Option<String>,
+    /// Variables set for the command on top of the inherited environment, as `KEY=VALUE`.
+    pub env: Vec<String>,
+    /// Time from starting the command until it exited or was killed.
+    pub elapsed: Option<Duration>,
+    /// The last lines (at most [`STDERR_TAIL_LINES`]) the command wrote to standard error.
+    pub stderr_tail: Vec<String>,
+}
+
+impl ExecutionDetails {
+    /// Records how `spec` ran, with its command vector and environment redacted.
+    pub(crate) fn new(
+        spec: &crate::executor::CommandSpec,
+        elapsed: Duration,
+        stderr_tail: Vec<String>,
+    ) -> Self {
+        let argv = spec
+            .privilege
+            .iter()
+            .map(|method| method.command_name())
+            .chain(std::iter::once(spec.command.as_str()))
+            .chain(spec.args.iter().map(String::as_str))
+            .map(|arg| redact::redact(arg).into_owned())
+            .collect();
+        let env = spec
+            .env
+            .iter()
+            .map(|(key, value)| redact::redact(&format!("{}={}", key, value)).into_owned())
+            .collect();
+        Self {
+            argv,
+            cwd: spec.cwd.as_ref().map(ToString::to_string),
+            env,
+            elapsed: Some(elapsed),
+            stderr_tail,
+        }
+    }
+}
+
+impl fmt::Display for ExecutionDetails {
+    /// Formats as ` (cwd: ..., env: ..., after 1.2s)`, followed by the stderr lines
+    /// indented on lines of their own.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut facts = Vec::new();
+        if let Some(cwd) = &self.cwd {
+            facts.push(format!("cwd: {}", cwd));
+        }
+        if !self.env.is_empty() {
+            facts.push(format!("env: {}", self.env.join(" ")));
+        }
+        if let Some(elapsed) = self.elapsed {
+            facts.push(format!("after {:.1?}", elapsed));
+        }
+        if !facts.is_empty() {
+            write!(f, " ({})", facts.join(", "))?;
+        }
+        if !self.stderr_tail.is_empty() {
+            write!(f, "\nlast {} line(s) of stderr:", self.stderr_tail.len())?;
+            for line in &self.stderr_tail {
+                write!(f, "\n  {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Formats the optional details of an `Execution` error (empty without them).
+fn format_details(details: &Option<Box<ExecutionDetails>>) -> String {
+    details
+        .as_ref()
+        .map(ToString::to_string)
+        .unwrap_or_default()
+}
+
 /// Domain-specific error type for rsdebstrap.
 ///
 /// Provides typed variants for common failure modes, enabling callers
@@ -45,13 +299,21 @@ pub enum RsdebstrapError {
     Validation(String),
 
     /// A command execution failed (non-zero exit, spawn failure, wait failure, thread panic, etc.).
-    #[error("command execution failed: {command}: {status}")]
+    ///
+    /// When the command actually ran, `details` records where and how, and what it last
+    /// wrote to standard error, so the failure can be understood without a re-run.
+    #[error(
+        "command execution failed: {command}: {status}{}",
+        format_details(details)
+    )]
     Execution {
         /// The command that was executed.
         command: String,
         /// Human-readable reason for the failure: exit code, signal information,
         /// or a description of the internal error (e.g., thread spawn failure).
         status: String,
+        /// How the command ran, if an executor recorded it.
+        details: Option<Box<ExecutionDetails>>,
     },
 
     /// An isolation backend operation failed.
@@ -72,6 +334,33 @@ pub enum RsdebstrapError {
         label: String,
     },
 
+    /// Privilege escalation is unavailable or was refused.
+    ///
+    /// Raised by the `apply` pre-flight check before any bootstrap work starts,
+    /// so a misconfigured or password-prompting escalation tool fails fast.
+    #[error("privilege escalation via {method} failed: {message}")]
+    Privilege {
+        /// The escalation method that was checked.
+        method: PrivilegeMethod,
+        /// Human-readable reason for the failure.
+        message: String,
+    },
+
+    /// No configured mirror passed the pre-bootstrap health check.
+    #[error("mirror health check failed: {0}")]
+    Mirror(String),
+
+    /// A downloaded or local input file does not match its pinned SHA-256 digest.
+    #[error("checksum mismatch for {path}: expected sha256 {expected}, got {actual}")]
+    ChecksumMismatch {
+        /// The file that was checked.
+        path: String,
+        /// The digest pinned in the profile.
+        expected: String,
+        /// The digest of the file's contents.
+        actual: String,
+    },
+
     /// An I/O operation failed with contextual information.
     ///
     /// The `Display` implementation formats as `"{context}: {io_error_kind_message}"`,
@@ -90,9 +379,233 @@ pub enum RsdebstrapError {
         #[source]
         source: std::io::Error,
     },
+
+    /// A resource is locked by another build and the caller chose not to wait.
+    #[error("{what} is in use by another build (lock file {path})")]
+    Locked {
+        /// The lock file.
+        path: String,
+        /// The locked resource (e.g., "the output directory").
+        what: String,
+    },
+
+    /// One or more verify phase checks failed.
+    ///
+    /// Raised after every check has run, so it names all the failures at once.
+    #[error("verification failed: {} of {total} check(s) failed: {}", failed.len(), failed.join(", "))]
+    Verification {
+        /// Display names of the failed checks, in phase order.
+        failed: Vec<String>,
+        /// Number of checks that ran.
+        total: usize,
+    },
+
+    /// Several independent operations failed.
+    ///
+    /// Raised by steps that carry on after a failure and report them all at the end
+    /// (unmounting, a phase with the `run_all_then_fail` failure policy). Each error is
+    /// kept whole, so its typed variant, code and hint stay reachable.
+    #[error("{message}: {}", join_errors(errors))]
+    Multiple {
+        /// Summary of what failed (e.g. "failed to unmount 2 filesystem(s)").
+        message: String,
+        /// The individual errors, in the order they occurred.
+        errors: Vec<anyhow::Error>,
+    },
+
+    /// A profile could not be deserialized.
+    ///
+    /// Carries where the problem is, so a typo deep in a long profile can be found
+    /// without bisecting it.
+    #[error(
+        "configuration error: {file}: YAML parse error{}: {message}{}",
+        path.as_ref().map(|path| format!(" at {}", path)).unwrap_or_default(),
+        line.zip(*column)
+            .map(|(line, column)| format!(" (line {}, column {})", line, column))
+            .unwrap_or_default()
+    )]
+    ConfigParse {
+        /// The profile file.
+        file: String,
+        /// 1-based line of the offending key or value, if known.
+        line: Option<u32>,
+        /// 1-based column of the offending key or value, if known.
+        column: Option<u32>,
+        /// JSON-pointer-like path to the offending key (e.g. `/provision/0/isolaton`).
+        path: Option<String>,
+        /// The deserializer's description of the problem.
+        message: String,
+        /// The expected field or variant name closest to a misspelled one.
+        suggestion: Option<String>,
+    },
+
+    /// A command was stopped by SIGINT/SIGTERM or a `serve` cancel.
+    ///
+    /// Kept apart from `Execution` so the pipeline stops on it, whatever its failure
+    /// policy or a task's `ignore_errors` says.
+    #[error(
+        "command execution failed: {command}: {status}{}",
+        format_details(details)
+    )]
+    Interrupted {
+        /// The command that was interrupted.
+        command: String,
+        /// When it was interrupted ("interrupted" or "interrupted before it started").
+        status: String,
+        /// How the command ran, if it was started.
+        details: Option<Box<ExecutionDetails>>,
+    },
+
+    /// The installed bootstrap backend is older than the profile's `min_version`, or
+    /// than the release an option it uses needs.
+    #[error("unsupported backend version: {0}")]
+    BackendVersion(String),
+}
+
+/// Formats each error with its context chain, separated by `; `.
+fn join_errors(errors: &[anyhow::Error]) -> String {
+    errors
+        .iter()
+        .map(|e| format!("{:#}", e))
+        .collect::<Vec<_>>()
+        .join("; ")
 }
 
 impl RsdebstrapError {
+    /// Returns the stable code of this error's variant.
+    ///
+    /// Codes are assigned in declaration order and never reused; a new variant gets
+    /// the next free number.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Validation(_) => "RSD0001",
+            Self::Execution { .. } => "RSD0002",
+            Self::Isolation(_) => "RSD0003",
+            Self::Config(_) => "RSD0004",
+            Self::CommandNotFound { .. } => "RSD0005",
+            Self::Privilege { .. } => "RSD0006",
+            Self::Mirror(_) => "RSD0007",
+            Self::ChecksumMismatch { .. } => "RSD0008",
+            Self::Io { .. } => "RSD0009",
+            Self::Locked { .. } => "RSD0010",
+            Self::Verification { .. } => "RSD0011",
+            Self::Multiple { .. } => "RSD0012",
+            Self::ConfigParse { .. } => "RSD0013",
+            Self::Interrupted { .. } => "RSD0014",
+            Self::BackendVersion(_) => "RSD0015",
+        }
+    }
+
+    /// Returns a suggestion for fixing this error, if there is a likely one.
+    pub fn hint(&self) -> Option<String> {
+        match self {
+            Self::Validation(message) if message.contains("require directory output") => Some(
+                "provision tasks run inside the rootfs directory; set the bootstrap `format: \
+                directory` (mmdebstrap) or a `target` without an archive extension, and pack \
+                the image in an assemble task instead"
+                    .to_string(),
+            ),
+            Self::Validation(message) if message.contains("not enough free space") => Some(
+                "free up space, point `dir` at a larger filesystem, or set `estimated_size` \
+                if the estimate is too high (`estimated_size: 0` skips the check)"
+                    .to_string(),
+            ),
+            Self::BackendVersion(_) => Some(
+                "install a newer mmdebstrap or debootstrap (e.g. from backports), or run \
+                `rsdebstrap doctor` to see the installed versions"
+                    .to_string(),
+            ),
+            Self::Execution { command, .. }
+                if command.starts_with("mount ") || command.starts_with("umount ") =>
+            {
+                Some(PRIVILEGE_HINT.to_string())
+            }
+            Self::Io { context, source } if source.kind() == io::ErrorKind::PermissionDenied => {
+                Some(if context.contains("mount") {
+                    PRIVILEGE_HINT.to_string()
+                } else {
+                    "check the file's ownership and permissions, or run the step with \
+                    privilege escalation (`privilege: { method: sudo }`)"
+                        .to_string()
+                })
+            }
+            Self::Config(message) if message.contains("unknown field") => Some(
+                "check the key's spelling against `rsdebstrap schema`, or pass `--no-strict` \
+                to only warn about unknown fields"
+                    .to_string(),
+            ),
+            Self::CommandNotFound { command, .. } => Some(format!(
+                "install it (e.g. `apt install {}`) or add its directory to PATH",
+                package_for(command)
+            )),
+            Self::Privilege { method, .. } => Some(match method {
+                PrivilegeMethod::Sudo => {
+                    "run `sudo -v` before `apply`, or allow this user to run sudo".to_string()
+                }
+                PrivilegeMethod::Doas => {
+                    "add a `permit` rule for this user to /etc/doas.conf".to_string()
+                }
+                PrivilegeMethod::Userns => {
+                    "add ranges for this user to /etc/subuid and /etc/subgid".to_string()
+                }
+                _ => format!("check the polkit rules that let this user run `{}`", method),
+            }),
+            Self::Mirror(_) => Some(
+                "check network access and proxy settings, or list more `fallback_mirrors`"
+                    .to_string(),
+            ),
+            Self::Locked { .. } => Some(
+                "wait for the other build to finish (the lock is released when it exits), or \
+                pass `--wait-for-lock` to queue behind it"
+                    .to_string(),
+            ),
+            Self::ChecksumMismatch { .. } => {
+                Some("if the file changed on purpose, pin its new digest in `sha256`".to_string())
+            }
+            Self::Verification { .. } => Some(
+                "see the verify report above for each failure; after fixing the profile, \
+                `apply --only verify` re-checks the existing rootfs"
+                    .to_string(),
+            ),
+            Self::Multiple { errors, .. } => errors.iter().find_map(hint),
+            Self::ConfigParse {
+                suggestion: Some(suggestion),
+                ..
+            } => Some(format!("did you mean `{}`?", suggestion)),
+            _ => None,
+        }
+    }
+
+    /// Returns the exit code of this error's category.
+    ///
+    /// Errors that can occur in any stage (command, isolation and I/O failures)
+    /// return [`exit_codes::FAILURE`]; [`exit_code()`] then uses the stage instead.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Config(_) | Self::ConfigParse { .. } => exit_codes::CONFIG,
+            Self::Validation(_) | Self::ChecksumMismatch { .. } | Self::BackendVersion(_) => {
+                exit_codes::VALIDATION
+            }
+            Self::Privilege { .. } => exit_codes::PRIVILEGE,
+            Self::Mirror(_) => exit_codes::BOOTSTRAP,
+            Self::Execution { .. }
+            | Self::Isolation(_)
+            | Self::CommandNotFound { .. }
+            | Self::Io { .. }
+            | Self::Locked { .. }
+            | Self::Verification { .. }
+            | Self::Interrupted { .. } => exit_codes::FAILURE,
+            Self::Multiple { errors, .. } => {
+                // Only a category every error shares is meaningful for the whole.
+                let mut codes = errors.iter().map(exit_code);
+                match codes.next() {
+                    Some(first) if codes.all(|code| code == first) => first,
+                    _ => exit_codes::FAILURE,
+                }
+            }
+        }
+    }
+
     /// Creates an `Io` variant from a context string and an I/O error.
     ///
     /// This is the preferred way to construct `Io` errors.
@@ -111,6 +624,14 @@ impl RsdebstrapError {
         }
     }
 
+    /// Creates a `Multiple` variant from a summary and the collected errors.
+    pub(crate) fn multiple(message: impl Into<String>, errors: Vec<anyhow::Error>) -> Self {
+        Self::Multiple {
+            message: message.into(),
+            errors,
+        }
+    }
+
     /// Converts an `anyhow::Error` into a `RsdebstrapError`, preserving the typed
     /// variant if the error is already a `RsdebstrapError`, or wrapping it as
     /// `Validation` otherwise.
@@ -130,25 +651,22 @@ impl RsdebstrapError {
         spec: &crate::executor::CommandSpec,
         status: impl Into<String>,
     ) -> Self {
-        let command = if let Some(method) = &spec.privilege {
-            if spec.args.is_empty() {
-                format!("{} {}", method.command_name(), spec.command)
-            } else {
-                format!(
-                    "{} {} {}",
-                    method.command_name(),
-                    spec.command,
-                    format_command_args(&spec.args)
-                )
-            }
-        } else if spec.args.is_empty() {
-            spec.command.clone()
-        } else {
-            format!("{} {}", spec.command, format_command_args(&spec.args))
-        };
         Self::Execution {
-            command,
+            command: spec_command(spec),
+            status: status.into(),
+            details: None,
+        }
+    }
+
+    /// Creates an `Interrupted` variant for `spec`, formatted like [`Self::execution`].
+    pub(crate) fn interrupted(
+        spec: &crate::executor::CommandSpec,
+        status: impl Into<String>,
+    ) -> Self {
+        Self::Interrupted {
+            command: spec_command(spec),
             status: status.into(),
+            details: None,
         }
     }
 
@@ -165,13 +683,206 @@ impl RsdebstrapError {
         Self::Execution {
             command: format!("{} (isolation: {})", format_command_args(command), isolation_name),
             status: status.into(),
+            details: None,
+        }
+    }
+
+    /// Attaches `details` to an `Execution` error; other variants are returned unchanged.
+    pub(crate) fn with_details(mut self, details: Option<Box<ExecutionDetails>>) -> Self {
+        if let Self::Execution { details: slot, .. } | Self::Interrupted { details: slot, .. } =
+            &mut self
+        {
+            *slot = details;
         }
+        self
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use anyhow::Context;
+
+    fn execution_error() -> RsdebstrapError {
+        RsdebstrapError::Execution {
+            command: "mmdebstrap".to_string(),
+            status: "exit status: 1".to_string(),
+            details: None,
+        }
+    }
+
+    #[test]
+    fn exit_code_uses_stage_for_uncategorized_errors() {
+        let err = Err::<(), _>(execution_error())
+            .context(Stage::Bootstrap.context("failed to execute mmdebstrap"))
+            .unwrap_err();
+        assert_eq!(exit_code(&err), exit_codes::BOOTSTRAP);
+        assert_eq!(err.to_string(), "failed to execute mmdebstrap");
+
+        let err = Err::<(), _>(anyhow::anyhow!("umount failed"))
+            .context(Stage::Teardown.context("failed to unmount filesystems"))
+            .unwrap_err();
+        assert_eq!(exit_code(&err), exit_codes::TEARDOWN);
+    }
+
+    #[test]
+    fn exit_code_prefers_typed_category_over_stage() {
+        let err = Err::<(), _>(RsdebstrapError::Validation("bad".into()))
+            .context(Stage::Pipeline.context("failed to run provision 1"))
+            .unwrap_err();
+        assert_eq!(exit_code(&err), exit_codes::VALIDATION);
+
+        let err = anyhow::Error::from(RsdebstrapError::Config("unparsable".into()));
+        assert_eq!(exit_code(&err), exit_codes::CONFIG);
+    }
+
+    #[test]
+    fn exit_code_falls_back_to_failure() {
+        assert_eq!(exit_code(&anyhow::Error::from(execution_error())), exit_codes::FAILURE);
+        assert_eq!(exit_code(&anyhow::anyhow!("untyped")), exit_codes::FAILURE);
+    }
+
+    #[test]
+    fn exit_code_uses_outermost_stage() {
+        let err = Err::<(), _>(execution_error())
+            .context(Stage::Pipeline.context("failed to run provision 1"))
+            .context(Stage::Teardown.context("failed to unmount filesystems"))
+            .unwrap_err();
+        assert_eq!(exit_code(&err), exit_codes::TEARDOWN);
+    }
+
+    #[test]
+    fn multiple_keeps_each_error_reachable() {
+        let validation = || {
+            Err::<(), _>(RsdebstrapError::Validation("bad".into()))
+                .context(Stage::Pipeline.context("failed to run provision 1"))
+                .unwrap_err()
+        };
+        let err = anyhow::Error::from(RsdebstrapError::multiple(
+            "provision phase failed",
+            vec![validation(), validation()],
+        ));
+        assert_eq!(exit_code(&err), exit_codes::VALIDATION);
+
+        let locked = RsdebstrapError::Locked {
+            path: "/out.lock".into(),
+            what: "the output directory".into(),
+        };
+        let err = RsdebstrapError::multiple(
+            "failed to unmount 2 filesystem(s)",
+            vec![anyhow::anyhow!("untyped"), locked.into()],
+        );
+        assert_eq!(err.exit_code(), exit_codes::FAILURE, "the categories differ");
+        assert!(err.hint().unwrap().contains("--wait-for-lock"), "{:?}", err.hint());
+        let RsdebstrapError::Multiple { errors, .. } = &err else {
+            unreachable!();
+        };
+        assert!(errors[1].downcast_ref::<RsdebstrapError>().is_some());
+    }
+
+    #[test]
+    fn is_interrupted_looks_through_context_and_collected_errors() {
+        let spec = crate::executor::CommandSpec::new("sleep", vec!["30".into()]);
+        let interrupted = RsdebstrapError::interrupted(&spec, "interrupted");
+        assert_eq!(interrupted.code(), "RSD0014");
+        assert_eq!(interrupted.to_string(), "command execution failed: sleep \"30\": interrupted");
+
+        let err = anyhow::Error::from(interrupted).context("failed to run provision 1");
+        assert!(is_interrupted(&err));
+        let collected =
+            RsdebstrapError::multiple("failed to unmount 1 filesystem(s)", vec![err]).into();
+        assert!(is_interrupted(&collected));
+        assert!(!is_interrupted(&execution_error().into()));
+    }
+
+    #[test]
+    fn test_codes_are_stable() {
+        let errors = [
+            RsdebstrapError::Validation(String::new()),
+            RsdebstrapError::Isolation(String::new()),
+            RsdebstrapError::command_not_found("mmdebstrap", "bootstrap backend"),
+            RsdebstrapError::io("reading x", io::Error::other("boom")),
+            RsdebstrapError::Verification {
+                failed: vec!["service_enabled:ssh".into()],
+                total: 3,
+            },
+            RsdebstrapError::multiple(
+                "assemble phase failed: 2 of 3 task(s) failed",
+                vec![
+                    anyhow::anyhow!("boom").context("failed to run assemble 1"),
+                    anyhow::anyhow!("bang").context("failed to run assemble 3 (trim)"),
+                ],
+            ),
+            RsdebstrapError::ConfigParse {
+                file: "profile.yml".into(),
+                line: Some(6),
+                column: Some(3),
+                path: Some("/bootstrap/mirors".into()),
+                message: "unknown field `mirors`".into(),
+                suggestion: Some("mirrors".into()),
+            },
+            RsdebstrapError::BackendVersion(String::new()),
+        ];
+        let codes: Vec<_> = errors.iter().map(RsdebstrapError::code).collect();
+        assert_eq!(
+            codes,
+            [
+                "RSD0001", "RSD0003", "RSD0005", "RSD0009", "RSD0011", "RSD0012", "RSD0013",
+                "RSD0015"
+            ]
+        );
+        assert_eq!(
+            errors[4].to_string(),
+            "verification failed: 1 of 3 check(s) failed: service_enabled:ssh"
+        );
+        assert_eq!(
+            errors[5].to_string(),
+            "assemble phase failed: 2 of 3 task(s) failed: failed to run assemble 1: boom; \
+            failed to run assemble 3 (trim): bang"
+        );
+        assert_eq!(
+            errors[6].to_string(),
+            "configuration error: profile.yml: YAML parse error at /bootstrap/mirors: \
+            unknown field `mirors` (line 6, column 3)"
+        );
+        assert_eq!(errors[6].hint().as_deref(), Some("did you mean `mirrors`?"));
+
+        let err = anyhow::Error::new(RsdebstrapError::Config("bad".into())).context("loading");
+        assert_eq!(error_code(&err), Some("RSD0004"));
+        assert_eq!(error_code(&anyhow::anyhow!("untyped")), None);
+    }
+
+    #[test]
+    fn test_hints() {
+        // mount(2) without `CAP_SYS_ADMIN` fails with EPERM.
+        let eperm = io::Error::from_raw_os_error(rustix::io::Errno::PERM.raw_os_error());
+        let err = RsdebstrapError::io("failed to mount proc on /r/proc", eperm);
+        assert!(err.hint().unwrap().contains("privilege: { method: sudo }"));
+
+        let err = RsdebstrapError::Validation(
+            "pipeline tasks require directory output but got: archive format detected based \
+            on extension: rootfs.tar.zst."
+                .to_string(),
+        );
+        assert!(err.hint().unwrap().contains("format: directory"));
+
+        let err = RsdebstrapError::command_not_found("unshare", "isolation backend");
+        assert!(err.hint().unwrap().contains("apt install util-linux"));
+
+        assert_eq!(RsdebstrapError::Validation("empty name".into()).hint(), None);
+        let err = RsdebstrapError::BackendVersion(
+            "mmdebstrap 0.8.4 is older than the profile's min_version 1.0".into(),
+        );
+        assert!(err.hint().unwrap().contains("install a newer mmdebstrap"));
+        // A typo in `bootstrap` lists `min_version` among the expected fields.
+        let err = RsdebstrapError::Validation(
+            "unknown field `suiet`, expected one of `suite`, `min_version`".into(),
+        );
+        assert_eq!(err.hint(), None);
+
+        let err = anyhow::Error::new(RsdebstrapError::Mirror("down".into())).context("checking");
+        assert!(hint(&err).unwrap().contains("fallback_mirrors"));
+    }
 
     #[test]
     fn test_validation_display() {
@@ -179,11 +890,46 @@ mod tests {
         assert_eq!(err.to_string(), "validation error: shell path must not be empty");
     }
 
+    #[test]
+    fn test_privilege_display() {
+        let err = RsdebstrapError::Privilege {
+            method: PrivilegeMethod::Sudo,
+            message: "`sudo true` exited with exit status: 1".to_string(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "privilege escalation via sudo failed: `sudo true` exited with exit status: 1"
+        );
+    }
+
+    #[test]
+    fn test_mirror_display() {
+        let err = RsdebstrapError::Mirror("no mirror serves <mirror>/dists/trixie/Release".into());
+        assert_eq!(
+            err.to_string(),
+            "mirror health check failed: no mirror serves <mirror>/dists/trixie/Release"
+        );
+    }
+
+    #[test]
+    fn test_checksum_mismatch_display() {
+        let err = RsdebstrapError::ChecksumMismatch {
+            path: "/tmp/key.gpg".into(),
+            expected: "aa".into(),
+            actual: "bb".into(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "checksum mismatch for /tmp/key.gpg: expected sha256 aa, got bb"
+        );
+    }
+
     #[test]
     fn test_execution_display() {
         let err = RsdebstrapError::Execution {
             command: "mmdebstrap".to_string(),
             status: "exit status: 1".to_string(),
+            details: None,
         };
         assert_eq!(err.to_string(), "command execution failed: mmdebstrap: exit status: 1");
     }
@@ -193,6 +939,7 @@ mod tests {
         let err = RsdebstrapError::Execution {
             command: "mmdebstrap --variant=debootstrap".to_string(),
             status: "failed to spawn stdout reader thread: resource exhausted".to_string(),
+            details: None,
         };
         let display = err.to_string();
         assert!(display.contains("command execution failed:"));
@@ -379,6 +1126,7 @@ mod tests {
         let err = RsdebstrapError::Execution {
             command: "test".to_string(),
             status: "failed".to_string(),
+            details: None,
         };
         let anyhow_err: anyhow::Error = err.into();
         let downcast = anyhow_err.downcast_ref::<RsdebstrapError>();
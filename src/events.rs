@@ -0,0 +1,490 @@
+//! Machine-readable event stream (`apply --events-fd` / `--events-file`).
+//!
+//! Wrapper UIs should not parse log lines, whose wording changes and which depend on
+//! the log level. An [`EventStream`] writes one JSON object per line instead, for
+//! every build step, every command and every warning, whatever the log level. Each
+//! object has `version` (the format version, [`EVENTS_VERSION`]), `time_ms` (Unix
+//! time in milliseconds) and `event`, the event type; the other fields depend on the
+//! type (see [`Event`]). Fields may be added within a version, never renamed or
+//! removed, so readers must ignore fields they do not know.
+//!
+//! Build steps and commands reach the stream through
+//! [`Runner::with_events`](crate::Runner::with_events). Warnings and errors reach it through [`EventLayer`],
+//! the tracing layer `init_logging` installs, which forwards them to every open stream.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use camino::Utf8Path;
+use serde::Serialize;
+use tracing::Level;
+use tracing::field::{Field, Visit};
+use tracing_subscriber::Layer;
+use tracing_subscriber::layer::Context;
+
+use crate::config::{MountEntry, UnmountMode};
+use crate::error::RsdebstrapError;
+use crate::executor::{CommandExecutor, CommandSpec, ExecutionResult, pipeline_spec};
+use crate::privilege::PrivilegeMethod;
+use crate::progress::{Progress, ProgressEvent};
+
+/// Version of the event format, the `version` field of every event.
+pub const EVENTS_VERSION: u32 = 1;
+
+/// An event of the stream; `event` holds the variant name in snake_case.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Event {
+    /// The run started.
+    RunStarted {
+        /// ID of the run (see [`RunId`](crate::run_dir::RunId)).
+        run_id: String,
+        /// Whether commands are only logged.
+        dry_run: bool,
+    },
+    /// The run ended.
+    RunFinished {
+        /// Whether the build succeeded.
+        success: bool,
+        /// The error that ended a failed build.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    /// The bootstrap backend is about to run.
+    BootstrapStarted {
+        /// Backend command name (e.g. `mmdebstrap`).
+        command: String,
+    },
+    /// The bootstrap backend finished.
+    BootstrapFinished,
+    /// A pipeline phase is about to run its tasks.
+    PhaseStarted {
+        /// `prepare`, `provision`, `assemble` or `verify`.
+        phase: String,
+        /// Number of tasks the phase runs.
+        tasks: usize,
+    },
+    /// A pipeline phase ran all its tasks.
+    PhaseFinished {
+        /// `prepare`, `provision`, `assemble` or `verify`.
+        phase: String,
+    },
+    /// A pipeline task is about to run.
+    TaskStarted {
+        /// `prepare`, `provision`, `assemble` or `verify`.
+        phase: String,
+        /// 1-based position of the task in its phase.
+        number: usize,
+        /// Display name of the task.
+        name: String,
+    },
+    /// A pipeline task finished.
+    TaskFinished {
+        /// `prepare`, `provision`, `assemble` or `verify`.
+        phase: String,
+        /// 1-based position of the task in its phase.
+        number: usize,
+        /// Display name of the task.
+        name: String,
+    },
+    /// A command ran (or, in dry-run mode, would have run).
+    Command {
+        /// Program name.
+        command: String,
+        /// Arguments.
+        args: Vec<String>,
+        /// Exit code; absent in dry-run mode, when the command could not start, or
+        /// when a signal killed it.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        exit_code: Option<i32>,
+        /// Whether the command succeeded.
+        success: bool,
+        /// Wall-clock duration in milliseconds.
+        duration_ms: u64,
+    },
+    /// A warning was logged.
+    Warning {
+        /// The log message.
+        message: String,
+    },
+    /// An error was logged.
+    Error {
+        /// The log message.
+        message: String,
+    },
+}
+
+impl Event {
+    fn from_progress(event: &ProgressEvent) -> Self {
+        match event {
+            ProgressEvent::BootstrapStarted { command } => Self::BootstrapStarted {
+                command: command.clone(),
+            },
+            ProgressEvent::BootstrapFinished => Self::BootstrapFinished,
+            ProgressEvent::PhaseStarted { phase, tasks } => Self::PhaseStarted {
+                phase: phase.to_string(),
+                tasks: *tasks,
+            },
+            ProgressEvent::PhaseFinished { phase } => Self::PhaseFinished {
+                phase: phase.to_string(),
+            },
+            ProgressEvent::TaskStarted {
+                phase,
+                number,
+                name,
+            } => Self::TaskStarted {
+                phase: phase.to_string(),
+                number: *number,
+                name: name.clone(),
+            },
+            ProgressEvent::TaskFinished {
+                phase,
+                number,
+                name,
+            } => Self::TaskFinished {
+                phase: phase.to_string(),
+                number: *number,
+                name: name.clone(),
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    version: u32,
+    time_ms: u128,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// Streams open for warnings, see [`EventLayer`].
+static OPEN_STREAMS: Mutex<Vec<Weak<EventStream>>> = Mutex::new(Vec::new());
+
+/// Newline-delimited JSON output of [`Event`]s.
+///
+/// Each event is written and flushed as one line. If writing fails (say, the reader
+/// went away), the stream stops writing and the build carries on.
+pub struct EventStream {
+    out: Mutex<Box<dyn Write + Send>>,
+    broken: AtomicBool,
+}
+
+impl EventStream {
+    /// Creates a stream writing to `out`.
+    pub fn new(out: impl Write + Send + 'static) -> Arc<Self> {
+        let stream = Arc::new(Self {
+            out: Mutex::new(Box::new(out)),
+            broken: AtomicBool::new(false),
+        });
+        let mut open = OPEN_STREAMS.lock().unwrap_or_else(PoisonError::into_inner);
+        open.retain(|s| s.strong_count() > 0);
+        open.push(Arc::downgrade(&stream));
+        stream
+    }
+
+    /// Creates a stream writing to the file at `path`, replacing its contents.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RsdebstrapError::Io`] if the file cannot be created.
+    pub fn create(path: &Utf8Path) -> Result<Arc<Self>, RsdebstrapError> {
+        let file = File::create(path)
+            .map_err(|e| RsdebstrapError::io(format!("failed to create {}", path), e))?;
+        Ok(Self::new(file))
+    }
+
+    /// Creates a stream writing to the inherited file descriptor `fd`, e.g. a pipe the
+    /// wrapper passed in.
+    ///
+    /// The descriptor is reopened through `/dev/fd` in append mode, so the events
+    /// follow anything already written to it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RsdebstrapError::Io`] if `fd` is not open for writing.
+    pub fn open_fd(fd: u32) -> Result<Arc<Self>, RsdebstrapError> {
+        let path = format!("/dev/fd/{}", fd);
+        let file = OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .map_err(|e| RsdebstrapError::io(format!("failed to open --events-fd {}", fd), e))?;
+        Ok(Self::new(file))
+    }
+
+    /// Writes `event` as one line.
+    pub fn emit(&self, event: &Event) {
+        if self.broken.load(Ordering::Relaxed) {
+            return;
+        }
+        let time_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let line = Line {
+            version: EVENTS_VERSION,
+            time_ms,
+            event,
+        };
+        let Ok(mut json) = serde_json::to_vec(&line) else {
+            return;
+        };
+        json.push(b'\n');
+        let written = {
+            let mut out = self.out.lock().unwrap_or_else(PoisonError::into_inner);
+            out.write_all(&json).and_then(|()| out.flush())
+        };
+        // Logged after the lock is released: the warning comes back through
+        // `EventLayer`, which finds this stream broken and skips it.
+        if let Err(e) = written
+            && !self.broken.swap(true, Ordering::Relaxed)
+        {
+            tracing::warn!("event stream closed, no longer writing events: {}", e);
+        }
+    }
+}
+
+impl Progress for EventStream {
+    fn report(&self, event: &ProgressEvent) {
+        self.emit(&Event::from_progress(event));
+    }
+}
+
+/// Command executor that emits a [`Event::Command`] for each command it runs.
+pub struct EventExecutor {
+    inner: Arc<dyn CommandExecutor>,
+    events: Arc<EventStream>,
+}
+
+impl EventExecutor {
+    /// Wraps `inner`, emitting to `events`.
+    pub fn new(inner: Arc<dyn CommandExecutor>, events: Arc<EventStream>) -> Self {
+        Self { inner, events }
+    }
+}
+
+impl CommandExecutor for EventExecutor {
+    fn execute(&self, spec: &CommandSpec) -> Result<ExecutionResult> {
+        let start = Instant::now();
+        let result = self.inner.execute(spec);
+        let status = result.as_ref().ok().and_then(|r| r.status);
+        self.events.emit(&Event::Command {
+            command: spec.command.clone(),
+            args: spec.args.clone(),
+            exit_code: status.and_then(|s| s.code()),
+            success: result.as_ref().is_ok_and(ExecutionResult::success),
+            duration_ms: u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX),
+        });
+        result
+    }
+
+    /// Emits one [`Event::Command`] for the whole pipeline, in its shell form.
+    fn execute_pipeline(&self, stages: &[CommandSpec]) -> Result<()> {
+        let start = Instant::now();
+        let result = self.inner.execute_pipeline(stages);
+        let spec = pipeline_spec(stages);
+        self.events.emit(&Event::Command {
+            command: spec.command,
+            args: spec.args,
+            exit_code: None,
+            success: result.is_ok(),
+            duration_ms: u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX),
+        });
+        result
+    }
+
+    // Mounts go straight to the wrapped executor, which may make them without running
+    // a command at all.
+    fn mount(
+        &self,
+        entry: &MountEntry,
+        target: &Utf8Path,
+        privilege: Option<PrivilegeMethod>,
+    ) -> Result<()> {
+        self.inner.mount(entry, target, privilege)
+    }
+
+    fn unmount(
+        &self,
+        entry: &MountEntry,
+        target: &Utf8Path,
+        mode: UnmountMode,
+        privilege: Option<PrivilegeMethod>,
+    ) -> Result<()> {
+        self.inner.unmount(entry, target, mode, privilege)
+    }
+}
+
+/// Tracing layer that emits warnings and errors to every open [`EventStream`],
+/// whatever the log level.
+#[derive(Debug, Default)]
+pub struct EventLayer;
+
+impl<S: tracing::Subscriber> Layer<S> for EventLayer {
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        let level = *event.metadata().level();
+        if level > Level::WARN {
+            return;
+        }
+        let streams: Vec<Arc<EventStream>> = OPEN_STREAMS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect();
+        if streams.is_empty() {
+            return;
+        }
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let message = crate::redact::redact(&visitor.into_text()).into_owned();
+        let event = if level == Level::ERROR {
+            Event::Error { message }
+        } else {
+            Event::Warning { message }
+        };
+        for stream in streams {
+            stream.emit(&event);
+        }
+    }
+}
+
+/// Collects an event's message and its other fields as `name=value`.
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: Vec<String>,
+}
+
+impl MessageVisitor {
+    /// Returns the message followed by the other fields.
+    fn into_text(self) -> String {
+        std::iter::once(self.message)
+            .chain(self.fields)
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.fields.push(format!("{}={:?}", field.name(), value));
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.record_debug(field, &value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    /// A `Write` into a shared buffer.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Buffer {
+        fn events(&self) -> Vec<Value> {
+            String::from_utf8(self.0.lock().unwrap().clone())
+                .unwrap()
+                .lines()
+                .map(|l| serde_json::from_str(l).unwrap())
+                .collect()
+        }
+    }
+
+    /// Succeeds for `true` and fails with exit code 3 otherwise.
+    struct ExitExecutor;
+
+    impl CommandExecutor for ExitExecutor {
+        fn execute(&self, spec: &CommandSpec) -> Result<ExecutionResult> {
+            use std::os::unix::process::ExitStatusExt;
+            let code = if spec.command == "true" { 0 } else { 3 };
+            Ok(ExecutionResult {
+                status: Some(std::process::ExitStatus::from_raw(code << 8)),
+                details: None,
+            })
+        }
+    }
+
+    #[test]
+    fn events_are_versioned_json_lines() {
+        let buffer = Buffer::default();
+        let stream = EventStream::new(buffer.clone());
+        stream.report(&ProgressEvent::TaskStarted {
+            phase: "provision",
+            number: 2,
+            name: "shell:setup.sh".to_string(),
+        });
+        let executor = EventExecutor::new(Arc::new(ExitExecutor), Arc::clone(&stream));
+        executor
+            .execute_checked(&CommandSpec::new("true", vec![]))
+            .unwrap();
+        executor
+            .execute_checked(&CommandSpec::new("false", vec!["-x".to_string()]))
+            .unwrap_err();
+
+        let mut events = buffer.events();
+        // Warnings logged by tests running at the same time reach every open stream.
+        events.retain(|e| e["event"] != "warning" && e["event"] != "error");
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0]["version"], EVENTS_VERSION);
+        assert!(events[0]["time_ms"].as_u64().unwrap() > 0);
+        assert_eq!(events[0]["event"], "task_started");
+        assert_eq!(events[0]["phase"], "provision");
+        assert_eq!(events[0]["number"], 2);
+        assert_eq!(events[1]["event"], "command");
+        assert_eq!(events[1]["success"], true);
+        assert_eq!(events[2]["command"], "false");
+        assert_eq!(events[2]["args"], serde_json::json!(["-x"]));
+        assert_eq!(events[2]["exit_code"], 3);
+        assert_eq!(events[2]["success"], false);
+    }
+
+    #[test]
+    fn layer_forwards_warnings_whatever_the_log_level() {
+        let buffer = Buffer::default();
+        let _stream = EventStream::new(buffer.clone());
+        let subscriber = tracing_subscriber::registry().with(EventLayer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("not an event");
+            tracing::warn!(path = "/srv", "stale mount {}", 1);
+            tracing::error!("failed");
+        });
+
+        let events = buffer.events();
+        assert_eq!(events.len(), 2, "{events:?}");
+        assert_eq!(events[0]["event"], "warning");
+        assert_eq!(events[0]["message"], "stale mount 1 path=\"/srv\"");
+        assert_eq!(events[1]["event"], "error");
+        assert_eq!(events[1]["message"], "failed");
+    }
+}
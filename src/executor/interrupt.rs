@@ -0,0 +1,125 @@
+//! SIGINT/SIGTERM handling for command execution.
+//!
+//! Without a handler, Ctrl-C kills rsdebstrap on the spot and leaves the rootfs
+//! mounted. [`install_interrupt_handler`] turns the first SIGINT or SIGTERM into a
+//! request: [`RealCommandExecutor`](super::RealCommandExecutor) kills the running
+//! command (or refuses to start the next one) and returns an error, so the pipeline
+//! unwinds through its RAII guards and unmounts, restores resolv.conf and removes
+//! staged files. A second signal terminates the process as usual, even during that
+//! cleanup. [`received_signal`] lets `main` exit with `128 + signal` afterwards.
+
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, OnceLock};
+use std::time::{Duration, Instant};
+
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::flag;
+
+use crate::error::RsdebstrapError;
+
+/// Set by a signal and cleared when a command takes it.
+static PENDING: LazyLock<Arc<AtomicBool>> = LazyLock::new(|| Arc::new(AtomicBool::new(false)));
+/// Set by the first signal and never cleared; a later signal then terminates.
+static SEEN: LazyLock<Arc<AtomicBool>> = LazyLock::new(|| Arc::new(AtomicBool::new(false)));
+/// Number of the last signal received, 0 if none.
+static SIGNAL: LazyLock<Arc<AtomicUsize>> = LazyLock::new(|| Arc::new(AtomicUsize::new(0)));
+static INSTALLED: OnceLock<()> = OnceLock::new();
+
+/// Installs the SIGINT and SIGTERM handlers. Calling it more than once has no
+/// further effect.
+///
+/// # Errors
+///
+/// Returns [`RsdebstrapError::Io`] if a handler cannot be registered.
+pub fn install_interrupt_handler() -> Result<(), RsdebstrapError> {
+    if INSTALLED.get().is_some() {
+        return Ok(());
+    }
+    for signal in [SIGINT, SIGTERM] {
+        // Handlers run in registration order: the conditional default sees `SEEN`
+        // as it was before this signal.
+        flag::register_conditional_default(signal, Arc::clone(&SEEN))
+            .and_then(|_| flag::register(signal, Arc::clone(&PENDING)))
+            .and_then(|_| flag::register(signal, Arc::clone(&SEEN)))
+            .and_then(|_| flag::register_usize(signal, Arc::clone(&SIGNAL), signal as usize))
+            .map_err(|e| RsdebstrapError::io("failed to install the signal handlers", e))?;
+    }
+    let _ = INSTALLED.set(());
+    Ok(())
+}
+
+thread_local! {
+    /// Cancellation flag of the build running on this thread (see [`watch_cancel`]).
+    static CANCEL: RefCell<Option<Arc<AtomicBool>>> = const { RefCell::new(None) };
+    /// Set on helper threads that run commands beside a build (see [`run_in_background`]).
+    static BACKGROUND: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Returns whether a signal arrived, or this thread's build was cancelled, since the
+/// last call, clearing it.
+///
+/// Each signal or cancellation cancels exactly one command, so the cleanup that
+/// follows can still run its own commands. On a background thread, a pending signal
+/// is only reported and left for the build's own command to take.
+pub(crate) fn take_interrupt() -> bool {
+    if BACKGROUND.get() {
+        return PENDING.load(Ordering::SeqCst);
+    }
+    let cancelled = CANCEL.with_borrow(|flag| {
+        flag.as_ref()
+            .is_some_and(|flag| flag.swap(false, Ordering::SeqCst))
+    });
+    let pending = PENDING.swap(false, Ordering::SeqCst) || cancelled;
+    if pending {
+        tracing::warn!("interrupted, cleaning up (interrupt again to quit immediately)");
+    }
+    pending
+}
+
+/// Makes commands run on this thread also watch `flag` (until replaced; `None` stops
+/// watching): setting it cancels the running command (or the next one) like a signal
+/// would, but only for this thread's build.
+///
+/// Used by `rsdebstrap serve` to cancel one of several jobs running at once.
+pub(crate) fn watch_cancel(flag: Option<Arc<AtomicBool>>) {
+    CANCEL.set(flag);
+}
+
+/// Returns whether a signal, or this thread's cancellation, is pending, without
+/// taking it: the next command started on this thread takes it and is refused.
+pub(crate) fn interrupt_pending() -> bool {
+    PENDING.load(Ordering::SeqCst)
+        || CANCEL.with_borrow(|flag| {
+            flag.as_ref()
+                .is_some_and(|flag| flag.load(Ordering::SeqCst))
+        })
+}
+
+/// Sleeps for `duration`, waking early once [`interrupt_pending`].
+pub(crate) fn sleep_unless_interrupted(duration: Duration) {
+    const POLL: Duration = Duration::from_millis(100);
+    let deadline = Instant::now() + duration;
+    while !interrupt_pending() {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return;
+        }
+        std::thread::sleep(left.min(POLL));
+    }
+}
+
+/// Marks this thread as running commands beside a build, such as the `sudo`
+/// credential keepalive: its commands see a pending signal but never take it, so
+/// the signal still cancels the build's command.
+pub(crate) fn run_in_background() {
+    BACKGROUND.set(true);
+}
+
+/// Returns the signal that interrupted this run, if any.
+pub fn received_signal() -> Option<i32> {
+    match SIGNAL.load(Ordering::SeqCst) {
+        0 => None,
+        signal => i32::try_from(signal).ok(),
+    }
+}
@@ -5,18 +5,36 @@
 //! - [`ExecutionResult`]: Result of command execution
 //! - [`CommandExecutor`]: Trait for command execution strategies
 //! - [`RealCommandExecutor`]: Production implementation using `std::process::Command`
+//! - [`OfflineExecutor`]: Wrapper running every command in a new network namespace
+//! - [`OutputPrefix`]: Guard labelling the streamed output of commands
+//! - [`pipeline_spec`]: The shell form of a pipeline of commands
+//! - [`install_interrupt_handler`]: SIGINT/SIGTERM handling that cancels the running command
 
+mod interrupt;
+mod native_mount;
+mod offline;
 mod pipe;
 mod real;
 
 use std::process::ExitStatus;
+use std::time::Duration;
 
 use anyhow::Result;
-use camino::Utf8PathBuf;
+use camino::{Utf8Path, Utf8PathBuf};
 
 use crate::RsdebstrapError;
+use crate::config::{MountEntry, UnmountMode};
+use crate::error::ExecutionDetails;
+use crate::phase::sh_quote;
 use crate::privilege::PrivilegeMethod;
 
+pub use interrupt::{install_interrupt_handler, received_signal};
+pub(crate) use interrupt::{
+    run_in_background, sleep_unless_interrupted, take_interrupt, watch_cancel,
+};
+pub(crate) use native_mount::mounts_natively;
+pub use offline::OfflineExecutor;
+pub use pipe::OutputPrefix;
 pub use real::RealCommandExecutor;
 
 /// Formats string arguments into a space-separated, debug-quoted string.
@@ -30,6 +48,69 @@ pub(crate) fn format_command_args(args: &[String]) -> String {
         .join(" ")
 }
 
+/// Returns the shell command line that runs `spec`: its privilege escalation, then
+/// `env -C <cwd> KEY=VALUE...` when needed, then the command, every word quoted.
+pub(crate) fn shell_command_line(spec: &CommandSpec) -> String {
+    let mut words: Vec<String> = Vec::new();
+    if let Some(method) = spec.privilege {
+        words.push(method.command_name().to_string());
+        if method == PrivilegeMethod::Userns {
+            words.push("--map-root-user".to_string());
+            words.push("--map-auto".to_string());
+        }
+    }
+    // `env` runs after the escalation, so every method keeps the variables and the
+    // working directory.
+    if spec.cwd.is_some() || !spec.env.is_empty() {
+        words.push("env".to_string());
+        if let Some(cwd) = &spec.cwd {
+            words.push("-C".to_string());
+            words.push(cwd.to_string());
+        }
+        for (key, value) in &spec.env {
+            words.push(format!("{}={}", key, value));
+        }
+    }
+    words.push(spec.command.clone());
+    words.extend(spec.args.iter().cloned());
+    words
+        .iter()
+        .map(|w| sh_quote(w))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Returns a single command that runs `stages` as a shell pipeline:
+/// `bash -o pipefail -c '<stage> | <stage> ...'`, each stage escalating itself.
+///
+/// The shortest stage timeout applies to the whole pipeline, and the first stage's
+/// standard input is fed to it.
+pub fn pipeline_spec(stages: &[CommandSpec]) -> CommandSpec {
+    let line = stages
+        .iter()
+        .map(shell_command_line)
+        .collect::<Vec<_>>()
+        .join(" | ");
+    let mut spec =
+        CommandSpec::new("bash", vec!["-o".into(), "pipefail".into(), "-c".into(), line]);
+    spec.timeout = stages.iter().filter_map(|s| s.timeout).min();
+    spec.stdin = stages.first().and_then(|s| s.stdin.clone());
+    spec
+}
+
+/// Returns the path of the running rsdebstrap executable, for commands that run one of
+/// its hidden helper subcommands (`sandbox-exec`, `pivot-exec`).
+pub(crate) fn current_exe() -> Result<Utf8PathBuf, RsdebstrapError> {
+    let exe = std::env::current_exe()
+        .map_err(|e| RsdebstrapError::io("failed to locate the rsdebstrap executable", e))?;
+    Utf8PathBuf::from_path_buf(exe).map_err(|exe| {
+        RsdebstrapError::Isolation(format!(
+            "rsdebstrap executable path is not valid UTF-8: {}",
+            exe.display()
+        ))
+    })
+}
+
 /// Specification for a command to be executed
 #[derive(Debug, Clone)]
 pub struct CommandSpec {
@@ -43,6 +124,10 @@ pub struct CommandSpec {
     pub env: Vec<(String, String)>,
     /// Privilege escalation method to wrap the command
     pub privilege: Option<PrivilegeMethod>,
+    /// Time after which the command is killed and fails (optional, no limit by default)
+    pub timeout: Option<Duration>,
+    /// Bytes written to the command's standard input (optional, inherited by default)
+    pub stdin: Option<Vec<u8>>,
 }
 
 impl CommandSpec {
@@ -55,6 +140,8 @@ impl CommandSpec {
             cwd: None,
             env: Vec::new(),
             privilege: None,
+            timeout: None,
+            stdin: None,
         }
     }
 
@@ -72,6 +159,20 @@ impl CommandSpec {
         self
     }
 
+    /// Sets the time after which the command is killed
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the bytes written to the command's standard input
+    #[must_use]
+    pub fn with_stdin(mut self, stdin: impl Into<Vec<u8>>) -> Self {
+        self.stdin = Some(stdin.into());
+        self
+    }
+
     /// Adds an environment variable
     #[must_use]
     pub fn with_env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
@@ -101,6 +202,8 @@ impl CommandSpec {
 pub struct ExecutionResult {
     /// Exit status of the command (None in dry-run mode)
     pub status: Option<ExitStatus>,
+    /// How the command ran (None in dry-run mode and for executors that do not record it)
+    pub details: Option<Box<ExecutionDetails>>,
 }
 
 impl ExecutionResult {
@@ -134,8 +237,48 @@ pub trait CommandExecutor: Send + Sync {
         let result = self.execute(spec)?;
         match result.status {
             Some(status) if status.success() => Ok(()),
-            Some(status) => Err(RsdebstrapError::execution(spec, status.to_string()).into()),
+            Some(status) => Err(RsdebstrapError::execution(spec, status.to_string())
+                .with_details(result.details)
+                .into()),
             None => Ok(()),
         }
     }
+
+    /// Runs `stages` as a pipeline: each command's standard output feeds the next
+    /// one's standard input, so data flows between them without touching disk. Only
+    /// the last command's output is logged, and [`CommandSpec::stdin`] applies to the
+    /// first. Fails if any stage fails.
+    ///
+    /// The default runs the shell form from [`pipeline_spec`] through
+    /// [`execute_checked`](Self::execute_checked).
+    fn execute_pipeline(&self, stages: &[CommandSpec]) -> Result<()> {
+        self.execute_checked(&pipeline_spec(stages))
+    }
+
+    /// Mounts `entry` on `target`, an already verified absolute path in the rootfs.
+    ///
+    /// The default runs the `mount` command from
+    /// [`MountEntry::build_mount_spec_with_path`].
+    fn mount(
+        &self,
+        entry: &MountEntry,
+        target: &Utf8Path,
+        privilege: Option<PrivilegeMethod>,
+    ) -> Result<()> {
+        self.execute_checked(&entry.build_mount_spec_with_path(target, privilege))
+    }
+
+    /// Unmounts `entry` from `target`, the path it was mounted on, in the given `mode`.
+    ///
+    /// The default runs the `umount` command from
+    /// [`MountEntry::build_umount_spec_with_mode`].
+    fn unmount(
+        &self,
+        entry: &MountEntry,
+        target: &Utf8Path,
+        mode: UnmountMode,
+        privilege: Option<PrivilegeMethod>,
+    ) -> Result<()> {
+        self.execute_checked(&entry.build_umount_spec_with_mode(target, mode, privilege))
+    }
 }
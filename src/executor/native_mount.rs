@@ -0,0 +1,208 @@
+//! Native `mount(2)`/`umount(2)` for mount entries.
+//!
+//! When rsdebstrap itself runs as root, [`RealCommandExecutor`](super::RealCommandExecutor)
+//! mounts entries with direct syscalls instead of running `mount`/`umount`, so a
+//! minimal build environment does not need util-linux. Entries whose options the
+//! syscall path cannot express (e.g. a block device that needs filesystem
+//! detection) fall back to the commands.
+
+use std::ffi::CString;
+
+use anyhow::Result;
+use camino::Utf8Path;
+use rustix::mount::{self as rmount, MountFlags, UnmountFlags};
+
+use crate::config::{MountEntry, UnmountMode};
+use crate::error::RsdebstrapError;
+use crate::privilege::PrivilegeMethod;
+
+/// A mount entry translated to `mount(2)` arguments.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct NativeMount {
+    /// `bind` or `rbind` (with `recursive`).
+    bind: bool,
+    recursive: bool,
+    /// Flags applied on mount, or by a remount after a bind mount.
+    flags: MountFlags,
+    /// Filesystem-specific `key=value` options, comma-joined.
+    data: Option<String>,
+}
+
+impl NativeMount {
+    /// Translates `entry`, or returns `None` if it has to go through the `mount` command.
+    ///
+    /// Pseudo-filesystems and bind mounts are supported. Generic options map to mount
+    /// flags and `key=value` options are passed to the filesystem; any other option,
+    /// and a device source, fall back to the command.
+    pub(crate) fn from_entry(entry: &MountEntry) -> Option<Self> {
+        let mut native = Self {
+            bind: false,
+            recursive: false,
+            flags: MountFlags::empty(),
+            data: None,
+        };
+        let mut data = Vec::new();
+        for option in &entry.options {
+            match option.as_str() {
+                "bind" => native.bind = true,
+                "rbind" => {
+                    native.bind = true;
+                    native.recursive = true;
+                }
+                "defaults" | "rw" => {}
+                "ro" => native.flags |= MountFlags::RDONLY,
+                "nosuid" => native.flags |= MountFlags::NOSUID,
+                "nodev" => native.flags |= MountFlags::NODEV,
+                "noexec" => native.flags |= MountFlags::NOEXEC,
+                "noatime" => native.flags |= MountFlags::NOATIME,
+                "nodiratime" => native.flags |= MountFlags::NODIRATIME,
+                "relatime" => native.flags |= MountFlags::RELATIME,
+                "strictatime" => native.flags |= MountFlags::STRICTATIME,
+                "sync" => native.flags |= MountFlags::SYNCHRONOUS,
+                "dirsync" => native.flags |= MountFlags::DIRSYNC,
+                other if other.contains('=') => data.push(other),
+                _ => return None,
+            }
+        }
+
+        if native.bind {
+            // Bind mounts take no filesystem options.
+            if !data.is_empty() {
+                return None;
+            }
+        } else {
+            if !entry.is_pseudo_fs() {
+                return None;
+            }
+            if !data.is_empty() {
+                native.data = Some(data.join(","));
+            }
+        }
+        Some(native)
+    }
+
+    /// Mounts `source` on `target`.
+    ///
+    /// A bind mount ignores flags, so they are applied by a bind remount afterwards.
+    fn mount(&self, source: &str, target: &Utf8Path) -> rustix::io::Result<()> {
+        if self.bind {
+            if self.recursive {
+                rmount::mount_bind_recursive(source, target.as_str())?;
+            } else {
+                rmount::mount_bind(source, target.as_str())?;
+            }
+            if !self.flags.is_empty() {
+                rmount::mount_remount(target.as_str(), MountFlags::BIND | self.flags, "")?;
+            }
+            return Ok(());
+        }
+
+        // Validation rejects NUL in options; an entry that skipped it fails like mount(2).
+        let data = self
+            .data
+            .as_deref()
+            .map(CString::new)
+            .transpose()
+            .map_err(|_| rustix::io::Errno::INVAL)?;
+        rmount::mount(source, target.as_str(), source, self.flags, data.as_deref())
+    }
+}
+
+/// Returns whether mounts made with `privilege` can use the syscalls: rsdebstrap runs
+/// as root, and the mount is not meant for a user namespace.
+pub(crate) fn native_mounts_available(privilege: Option<PrivilegeMethod>) -> bool {
+    privilege != Some(PrivilegeMethod::Userns) && rustix::process::geteuid().is_root()
+}
+
+/// Returns whether `entry`, mounted with `privilege`, will use the syscalls instead of
+/// the `mount`/`umount` commands.
+pub(crate) fn mounts_natively(entry: &MountEntry, privilege: Option<PrivilegeMethod>) -> bool {
+    native_mounts_available(privilege) && NativeMount::from_entry(entry).is_some()
+}
+
+/// Mounts `entry` on the verified absolute path `target` with `mount(2)`.
+///
+/// Returns `Ok(false)` without doing anything if the entry needs the `mount` command.
+pub(crate) fn mount(entry: &MountEntry, target: &Utf8Path) -> Result<bool> {
+    let Some(native) = NativeMount::from_entry(entry) else {
+        return Ok(false);
+    };
+    tracing::debug!("mount(2) {} on {}: {:?}", entry.source, target, native);
+    native.mount(&entry.source, target).map_err(|e| {
+        RsdebstrapError::io(
+            format!("failed to mount {} on {}", entry.source, target),
+            std::io::Error::from(e),
+        )
+    })?;
+    Ok(true)
+}
+
+/// Unmounts the verified absolute path `target` with `umount2(2)`, passing the flag
+/// selected by `mode`.
+pub(crate) fn unmount(target: &Utf8Path, mode: UnmountMode) -> Result<()> {
+    let flags = match mode {
+        UnmountMode::Normal => UnmountFlags::empty(),
+        UnmountMode::Lazy => UnmountFlags::DETACH,
+        UnmountMode::Force => UnmountFlags::FORCE,
+    };
+    tracing::debug!("umount2(2) {} ({})", target, mode);
+    rmount::unmount(target.as_str(), flags).map_err(|e| {
+        RsdebstrapError::io(format!("failed to unmount {}", target), std::io::Error::from(e)).into()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(source: &str, options: &[&str]) -> MountEntry {
+        MountEntry {
+            source: source.to_string(),
+            target: "/mnt".into(),
+            options: options.iter().map(|o| o.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn pseudo_fs_options_split_into_flags_and_data() {
+        let native =
+            NativeMount::from_entry(&entry("devpts", &["nosuid", "gid=5", "mode=620"])).unwrap();
+        assert!(!native.bind);
+        assert_eq!(native.flags, MountFlags::NOSUID);
+        assert_eq!(native.data.as_deref(), Some("gid=5,mode=620"));
+    }
+
+    #[test]
+    fn pseudo_fs_without_options_has_no_data() {
+        let native = NativeMount::from_entry(&entry("proc", &[])).unwrap();
+        assert_eq!(native.flags, MountFlags::empty());
+        assert_eq!(native.data, None);
+    }
+
+    #[test]
+    fn read_only_bind_keeps_flags_for_remount() {
+        let native = NativeMount::from_entry(&entry("/srv/data", &["bind", "ro"])).unwrap();
+        assert!(native.bind);
+        assert!(!native.recursive);
+        assert_eq!(native.flags, MountFlags::RDONLY);
+
+        let native = NativeMount::from_entry(&entry("/srv/data", &["rbind"])).unwrap();
+        assert!(native.bind && native.recursive);
+    }
+
+    #[test]
+    fn unsupported_entries_fall_back_to_command() {
+        // A device needs filesystem detection.
+        assert_eq!(NativeMount::from_entry(&entry("/dev/sdb1", &[])), None);
+        // Options the syscall path does not know.
+        assert_eq!(NativeMount::from_entry(&entry("tmpfs", &["user_xattr"])), None);
+        assert_eq!(NativeMount::from_entry(&entry("proc", &["remount"])), None);
+        // Filesystem options on a bind mount.
+        assert_eq!(NativeMount::from_entry(&entry("/srv/data", &["bind", "mode=755"])), None);
+    }
+
+    #[test]
+    fn userns_privilege_never_mounts_natively() {
+        assert!(!native_mounts_available(Some(PrivilegeMethod::Userns)));
+    }
+}
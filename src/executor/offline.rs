@@ -0,0 +1,120 @@
+//! Network-isolating executor wrapper.
+//!
+//! This module provides [`OfflineExecutor`], which runs every command it is given
+//! inside a new network namespace via `unshare --net`. The namespace contains only
+//! a down loopback interface, so the command cannot reach any network.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use camino::Utf8Path;
+
+use super::{CommandExecutor, CommandSpec, ExecutionResult};
+use crate::config::{MountEntry, UnmountMode};
+use crate::privilege::PrivilegeMethod;
+
+/// Command executor that denies network access to every command.
+///
+/// Each spec is rewritten to `unshare --net -- <command> <args>`, keeping its
+/// privilege method, working directory, and environment, and handed to the
+/// wrapped executor. Creating a network namespace needs root, so a spec without
+/// a privilege method additionally maps the caller to itself in a new user
+/// namespace (`--map-current-user`), which requires unprivileged user namespaces.
+pub struct OfflineExecutor {
+    inner: Arc<dyn CommandExecutor>,
+}
+
+impl OfflineExecutor {
+    /// Wraps `inner` so every command runs without network access.
+    #[must_use]
+    pub fn new(inner: Arc<dyn CommandExecutor>) -> Self {
+        Self { inner }
+    }
+
+    /// Returns the spec that `execute` hands to the wrapped executor.
+    pub fn wrap(spec: &CommandSpec) -> CommandSpec {
+        let mut args: Vec<String> = Vec::with_capacity(spec.args.len() + 4);
+        args.push("--net".to_string());
+        if spec.privilege.is_none() {
+            args.push("--map-current-user".to_string());
+        }
+        args.push("--".to_string());
+        args.push(spec.command.clone());
+        args.extend(spec.args.iter().cloned());
+
+        CommandSpec {
+            command: "unshare".to_string(),
+            args,
+            cwd: spec.cwd.clone(),
+            env: spec.env.clone(),
+            privilege: spec.privilege,
+            timeout: spec.timeout,
+            stdin: spec.stdin.clone(),
+        }
+    }
+}
+
+impl CommandExecutor for OfflineExecutor {
+    fn execute(&self, spec: &CommandSpec) -> Result<ExecutionResult> {
+        self.inner.execute(&Self::wrap(spec))
+    }
+
+    fn execute_pipeline(&self, stages: &[CommandSpec]) -> Result<()> {
+        let wrapped: Vec<_> = stages.iter().map(Self::wrap).collect();
+        self.inner.execute_pipeline(&wrapped)
+    }
+
+    // Mounts change no network state, so they go straight to the wrapped executor,
+    // which may make them without running a command at all.
+    fn mount(
+        &self,
+        entry: &MountEntry,
+        target: &Utf8Path,
+        privilege: Option<PrivilegeMethod>,
+    ) -> Result<()> {
+        self.inner.mount(entry, target, privilege)
+    }
+
+    fn unmount(
+        &self,
+        entry: &MountEntry,
+        target: &Utf8Path,
+        mode: UnmountMode,
+        privilege: Option<PrivilegeMethod>,
+    ) -> Result<()> {
+        self.inner.unmount(entry, target, mode, privilege)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::privilege::PrivilegeMethod;
+    use camino::Utf8PathBuf;
+
+    #[test]
+    fn wrap_prefixes_unshare_net_and_keeps_privilege() {
+        let spec = CommandSpec::new("chroot", vec!["/rootfs".into(), "/bin/sh".into()])
+            .with_privilege(Some(PrivilegeMethod::Sudo))
+            .with_cwd(Utf8PathBuf::from("/work"))
+            .with_env("LANG", "C");
+
+        let wrapped = OfflineExecutor::wrap(&spec);
+
+        assert_eq!(wrapped.command, "unshare");
+        assert_eq!(wrapped.args, ["--net", "--", "chroot", "/rootfs", "/bin/sh"]);
+        assert_eq!(wrapped.privilege, Some(PrivilegeMethod::Sudo));
+        assert_eq!(wrapped.cwd, spec.cwd);
+        assert_eq!(wrapped.env, spec.env);
+    }
+
+    #[test]
+    fn wrap_without_privilege_maps_current_user() {
+        let spec = CommandSpec::new("true", vec![]);
+
+        let wrapped = OfflineExecutor::wrap(&spec);
+
+        assert_eq!(wrapped.args, ["--net", "--map-current-user", "--", "true"]);
+        assert_eq!(wrapped.privilege, None);
+    }
+}
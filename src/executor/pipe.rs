@@ -1,9 +1,47 @@
 //! Internal utilities for streaming command output to logs.
 //!
 //! This module handles reading from stdout/stderr pipes and logging
-//! the output in real-time during command execution.
+//! the output in real-time during command execution. Each line carries the
+//! [`OutputPrefix`] active on the thread that ran the command, so the output of
+//! a pipeline task can be told apart from the next one.
 
-use std::io::{BufRead, BufReader, Read};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+
+thread_local! {
+    static OUTPUT_PREFIX: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Guard that prefixes the logged output of commands run on this thread.
+///
+/// While the guard is alive, every line a command writes is logged as
+/// `[<prefix>] <line>`. Guards nest: dropping one restores the previous prefix.
+#[must_use = "the prefix is removed when the guard is dropped"]
+pub struct OutputPrefix {
+    previous: Option<String>,
+}
+
+impl OutputPrefix {
+    /// Sets `prefix` for the output of commands run on the current thread.
+    pub fn enter(prefix: impl Into<String>) -> Self {
+        let previous = OUTPUT_PREFIX.with(|p| p.replace(Some(prefix.into())));
+        Self { previous }
+    }
+
+    /// Returns the prefix active on the current thread, if any.
+    pub(super) fn current() -> Option<String> {
+        OUTPUT_PREFIX.with(|p| p.borrow().clone())
+    }
+}
+
+impl Drop for OutputPrefix {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        OUTPUT_PREFIX.with(|p| *p.borrow_mut() = previous);
+    }
+}
 
 /// Type of output stream for logging purposes.
 #[derive(Clone, Copy)]
@@ -32,8 +70,14 @@ pub(super) fn panic_message(err: &(dyn std::any::Any + Send)) -> &str {
         .unwrap_or("unknown panic")
 }
 
-/// Reads from a pipe and logs each line in real-time.
+/// Reads from a pipe and logs each line in real-time, returning the last `tail` lines.
 ///
+/// Runs as a task on the executor's runtime, concurrently with the wait for the
+/// child to exit. The returned lines (formatted without the prefix) go into the
+/// [`ExecutionDetails`](crate::error::ExecutionDetails) of a failed command.
+///
+/// - Each line is prefixed with `prefix` (the caller's [`OutputPrefix`]), since the
+///   task is not tied to the caller's thread.
 /// - stdout is logged at INFO level, stderr at WARN level.
 ///   INFO/WARN levels are chosen so users can see mmdebstrap/debootstrap
 ///   progress output in real-time during bootstrap operations.
@@ -41,13 +85,19 @@ pub(super) fn panic_message(err: &(dyn std::any::Any + Send)) -> &str {
 /// - I/O errors stop reading but don't fail command execution
 ///   (output streaming is best-effort; command success is determined by exit status)
 /// - `None` pipe logs an error and returns (unexpected if `Stdio::piped()` was set)
-pub(super) fn read_pipe_to_log<R: Read>(pipe: Option<R>, stream_type: StreamType) {
+pub(super) async fn read_pipe_to_log<R: AsyncRead + Unpin>(
+    pipe: Option<R>,
+    stream_type: StreamType,
+    prefix: Option<String>,
+    tail: usize,
+) -> Vec<String> {
+    let mut last_lines = VecDeque::with_capacity(tail);
     let Some(pipe) = pipe else {
         tracing::error!(
             stream = %stream_type,
             "pipe was None (unexpected: Stdio::piped() was set), no output will be captured"
         );
-        return;
+        return Vec::new();
     };
 
     let mut reader = BufReader::new(pipe);
@@ -55,12 +105,18 @@ pub(super) fn read_pipe_to_log<R: Read>(pipe: Option<R>, stream_type: StreamType
 
     loop {
         line_buf.clear();
-        match reader.read_until(b'\n', &mut line_buf) {
+        match reader.read_until(b'\n', &mut line_buf).await {
             Ok(0) => break, // EOF
             Ok(_) => {
                 // Log output (excluding newline)
                 let log_content = line_buf.strip_suffix(b"\n").unwrap_or(&line_buf);
-                log_line(log_content, stream_type);
+                log_line(log_content, stream_type, prefix.as_deref());
+                if tail > 0 {
+                    if last_lines.len() == tail {
+                        last_lines.pop_front();
+                    }
+                    last_lines.push_back(format_line(log_content, None));
+                }
             }
             Err(e) => {
                 tracing::error!(stream = %stream_type, error = %e, "I/O error, stopping read");
@@ -68,16 +124,72 @@ pub(super) fn read_pipe_to_log<R: Read>(pipe: Option<R>, stream_type: StreamType
             }
         }
     }
+    last_lines.into()
 }
 
 /// Logs a complete line at the appropriate level.
+fn log_line(line: &[u8], stream_type: StreamType, prefix: Option<&str>) {
+    let text = format_line(line, prefix);
+    match stream_type {
+        StreamType::Stdout => tracing::info!(stream = %stream_type, "{}", text),
+        StreamType::Stderr => tracing::warn!(stream = %stream_type, "{}", text),
+    }
+}
+
+/// Formats a line for logging, prepending `[prefix] ` if given.
 ///
 /// Trailing CR is trimmed to handle CRLF line endings.
-fn log_line(line: &[u8], stream_type: StreamType) {
+fn format_line(line: &[u8], prefix: Option<&str>) -> String {
     let text = String::from_utf8_lossy(line);
     let trimmed = text.trim_end_matches('\r');
-    match stream_type {
-        StreamType::Stdout => tracing::info!(stream = %stream_type, "{}", trimmed),
-        StreamType::Stderr => tracing::warn!(stream = %stream_type, "{}", trimmed),
+    match prefix {
+        Some(prefix) => format!("[{}] {}", prefix, trimmed),
+        None => trimmed.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_line_prepends_prefix_and_trims_cr() {
+        assert_eq!(format_line(b"Setting up vim\r", None), "Setting up vim");
+        assert_eq!(
+            format_line(b"Setting up vim", Some("provision/shell:setup.sh")),
+            "[provision/shell:setup.sh] Setting up vim"
+        );
+        assert_eq!(format_line(b"\xffok", Some("p/t")), "[p/t] \u{fffd}ok");
+    }
+
+    #[test]
+    fn output_prefix_nests_and_restores() {
+        assert_eq!(OutputPrefix::current(), None);
+        {
+            let _outer = OutputPrefix::enter("provision/outer");
+            {
+                let _inner = OutputPrefix::enter("provision/inner");
+                assert_eq!(OutputPrefix::current().as_deref(), Some("provision/inner"));
+            }
+            assert_eq!(OutputPrefix::current().as_deref(), Some("provision/outer"));
+        }
+        assert_eq!(OutputPrefix::current(), None);
+    }
+
+    #[test]
+    fn read_pipe_to_log_reads_to_eof() {
+        // Reading must consume every line, including a last one without newline.
+        let mut input: &[u8] = b"one\ntwo\r\nthree";
+        let tail = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(read_pipe_to_log(
+                Some(&mut input),
+                StreamType::Stdout,
+                Some("p/t".into()),
+                2,
+            ));
+        assert!(input.is_empty());
+        assert_eq!(tail, ["two", "three"]);
     }
 }
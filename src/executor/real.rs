@@ -1,92 +1,548 @@
 //! Real command executor implementation.
 //!
 //! This module provides [`RealCommandExecutor`], which executes commands
-//! using `std::process::Command` with real-time output streaming.
+//! using `tokio::process::Command` with real-time output streaming.
+//! Output is never inherited from the terminal: both streams are piped and
+//! logged line by line, so it is attributed to the running task. Standard input
+//! is inherited unless the spec gives [`CommandSpec::stdin`] bytes to feed it.
+//!
+//! Each command runs on a small single-threaded tokio runtime owned by the call:
+//! the output readers, the wait for the child, the [`CommandSpec::timeout`] and
+//! Ctrl-C cancellation are futures raced on that runtime instead of a thread per
+//! pipe. A pipeline ([`CommandExecutor::execute_pipeline`]) runs every stage on one
+//! runtime, each stage's standard output connected directly to the next stage's
+//! standard input.
 
-use std::process::{Child, Command, Stdio};
-use std::thread;
-use std::thread::JoinHandle;
+use std::process::{ExitStatus, Stdio};
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
+use camino::Utf8Path;
+use tokio::io::AsyncWriteExt;
+use tokio::process::{Child, Command};
+use tokio::task::JoinHandle;
 use which::which;
 
-use super::pipe::{StreamType, panic_message, read_pipe_to_log};
+use super::interrupt;
+use super::native_mount;
+use super::pipe::{OutputPrefix, StreamType, panic_message, read_pipe_to_log};
 use super::{CommandExecutor, CommandSpec, ExecutionResult};
+use crate::config::{MountEntry, UnmountMode};
+use crate::error::{ExecutionDetails, RsdebstrapError, STDERR_TAIL_LINES};
+use crate::privilege::PrivilegeMethod;
 
-/// Cleans up a child process and its associated reader threads.
-///
-/// This function kills the child process, waits for it to terminate,
-/// and joins all reader threads to prevent resource leaks.
-///
-/// Called from error paths in [`RealCommandExecutor::execute()`] to ensure
-/// proper cleanup when thread spawning or process waiting fails.
-fn cleanup_child_process<I>(child: &mut Child, handles: I)
-where
-    I: IntoIterator<Item = JoinHandle<()>>,
-{
-    let pid = child.id();
-    if let Err(e) = child.kill() {
-        tracing::debug!(pid = pid, "kill returned error (process may have already exited): {}", e);
+/// How often a running command checks for a pending Ctrl-C.
+const INTERRUPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long a cancelled command may take to exit after SIGTERM before it gets SIGKILL.
+const TERMINATE_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// How long output is still read after a cancelled command was killed. A process
+/// the command started in the background can keep the pipes open indefinitely.
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How the wait for child processes ended.
+#[derive(Debug)]
+enum WaitOutcome<T = ExitStatus> {
+    Exited(T),
+    TimedOut,
+    Interrupted,
+}
+
+/// Waits for `child` to exit, its `timeout` to pass, or `interrupted` to report a
+/// Ctrl-C, whichever comes first. The child is left running in the last two cases.
+async fn wait_for_child(
+    child: &mut Child,
+    timeout: Option<Duration>,
+    interrupted: impl Fn() -> bool,
+) -> std::io::Result<WaitOutcome> {
+    wait_or_cancel(child.wait(), timeout, interrupted).await
+}
+
+/// Races `wait` against `timeout` and `interrupted`, like [`wait_for_child`].
+async fn wait_or_cancel<T>(
+    wait: impl Future<Output = std::io::Result<T>>,
+    timeout: Option<Duration>,
+    interrupted: impl Fn() -> bool,
+) -> std::io::Result<WaitOutcome<T>> {
+    let deadline = async {
+        match timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+    let interrupt = async {
+        let mut poll = tokio::time::interval(INTERRUPT_POLL_INTERVAL);
+        loop {
+            poll.tick().await;
+            if interrupted() {
+                return;
+            }
+        }
+    };
+    tokio::select! {
+        status = wait => status.map(WaitOutcome::Exited),
+        () = deadline => Ok(WaitOutcome::TimedOut),
+        () = interrupt => Ok(WaitOutcome::Interrupted),
     }
-    if let Err(e) = child.wait() {
-        tracing::warn!(pid = pid, "failed to wait for child process after kill: {}", e);
+}
+
+/// Kills a cancelled child process and reaps it (see [`kill_children`]).
+async fn kill_child(child: &mut Child) {
+    kill_children(std::slice::from_mut(child), TERMINATE_GRACE_PERIOD).await;
+}
+
+/// Stops cancelled child processes and reaps them.
+///
+/// Each first gets SIGTERM, which sudo, doas, run0 and pkexec relay to the command
+/// they run; SIGKILL would stop only the wrapper and leave that command running in
+/// the rootfs, keeping its mounts busy. A child still running after `grace` is killed.
+async fn kill_children(children: &mut [Child], grace: Duration) {
+    for child in children.iter() {
+        let Some(pid) = child
+            .id()
+            .and_then(|pid| rustix::process::Pid::from_raw(pid as i32))
+        else {
+            continue;
+        };
+        if let Err(e) = rustix::process::kill_process(pid, rustix::process::Signal::TERM) {
+            tracing::debug!(pid = pid.as_raw_nonzero().get(), "SIGTERM returned error: {}", e);
+        }
     }
-    for handle in handles {
-        if let Err(e) = handle.join() {
-            tracing::warn!("reader thread panicked during cleanup: {}", panic_message(&*e));
+    let deadline = tokio::time::Instant::now() + grace;
+    for child in children {
+        let pid = child.id();
+        let status = match tokio::time::timeout_at(deadline, child.wait()).await {
+            Ok(status) => status,
+            Err(_) => {
+                tracing::debug!(pid = pid, "still running {:?} after SIGTERM, killing it", grace);
+                if let Err(e) = child.start_kill() {
+                    tracing::debug!(
+                        pid = pid,
+                        "kill returned error (process may have already exited): {}",
+                        e
+                    );
+                }
+                child.wait().await
+            }
+        };
+        if let Err(e) = status {
+            tracing::warn!(pid = pid, "failed to wait for child process after kill: {}", e);
         }
     }
 }
 
-/// Spawns stdout and stderr reader threads for a child process.
+/// An output reader task and the name of the stream it reads.
+type Reader = (&'static str, JoinHandle<Vec<String>>);
+
+/// Spawns a runtime task logging standard error of `child` and keeping its last
+/// [`STDERR_TAIL_LINES`] lines.
+fn spawn_stderr_reader(child: &mut Child, prefix: Option<String>) -> Reader {
+    (
+        "stderr",
+        tokio::spawn(read_pipe_to_log(
+            child.stderr.take(),
+            StreamType::Stderr,
+            prefix,
+            STDERR_TAIL_LINES,
+        )),
+    )
+}
+
+/// Spawns the stdout and stderr readers of a child process as runtime tasks.
 ///
-/// Takes the pipes from the child process and spawns a thread for each.
-/// On failure, cleans up the child process and any already-spawned threads
-/// before returning the error.
-fn spawn_reader_threads(
-    child: &mut Child,
+/// Both log with the calling thread's [`OutputPrefix`].
+fn spawn_readers(child: &mut Child) -> [Reader; 2] {
+    let prefix = OutputPrefix::current();
+    [
+        (
+            "stdout",
+            tokio::spawn(read_pipe_to_log(
+                child.stdout.take(),
+                StreamType::Stdout,
+                prefix.clone(),
+                0,
+            )),
+        ),
+        spawn_stderr_reader(child, prefix),
+    ]
+}
+
+/// Spawns a runtime task that writes [`CommandSpec::stdin`] to the child and then
+/// closes its standard input, so the command sees end-of-file.
+///
+/// A command that exits without reading all of its input makes the write fail
+/// with a broken pipe; that is left to the command's exit status to report.
+fn spawn_stdin_writer(child: &mut Child, spec: &CommandSpec) -> Option<JoinHandle<()>> {
+    let input = spec.stdin.clone()?;
+    let mut stdin = child.stdin.take()?;
+    let command = spec.command.clone();
+    Some(tokio::spawn(async move {
+        if let Err(e) = stdin.write_all(&input).await {
+            tracing::debug!("stopped writing stdin of {}: {}", command, e);
+        }
+    }))
+}
+
+/// Runs `command` to completion on the current runtime.
+///
+/// The output readers and the stdin writer run alongside the wait. When the command times out or is
+/// interrupted, it is killed, the remaining output is read for at most
+/// [`OUTPUT_DRAIN_TIMEOUT`], and an [`RsdebstrapError::Execution`] (or
+/// [`RsdebstrapError::Interrupted`]) is returned. Both the result and that error carry
+/// the [`ExecutionDetails`] of the run.
+async fn run_child(
+    mut command: Command,
     spec: &CommandSpec,
-) -> Result<(JoinHandle<()>, JoinHandle<()>)> {
-    let stdout_pipe = child.stdout.take();
-    let stderr_pipe = child.stderr.take();
+    interrupted: impl Fn() -> bool,
+) -> Result<ExecutionResult> {
+    if interrupted() {
+        return Err(RsdebstrapError::interrupted(spec, "interrupted before it started").into());
+    }
 
-    let stdout_handle = match thread::Builder::new()
-        .name("stdout-reader".to_string())
-        .spawn(move || read_pipe_to_log(stdout_pipe, StreamType::Stdout))
-    {
-        Ok(handle) => handle,
+    let mut child = command
+        .spawn()
+        .map_err(|e| RsdebstrapError::execution(spec, format!("failed to spawn command: {}", e)))?;
+    tracing::trace!("spawned command: {}: pid={:?}", spec.command, child.id());
+    let started = Instant::now();
+
+    let readers = spawn_readers(&mut child);
+    // Aborted once the command ends: a process it started in the background may
+    // hold the pipe open without ever reading it.
+    let writer = spawn_stdin_writer(&mut child, spec);
+
+    let outcome = wait_for_child(&mut child, spec.timeout, interrupted).await;
+    if let Some(writer) = writer {
+        writer.abort();
+    }
+    let outcome = match outcome {
+        Ok(outcome) => outcome,
         Err(e) => {
-            cleanup_child_process(child, []);
-            return Err(crate::error::RsdebstrapError::execution(
+            // If waiting fails, the process might still be running.
+            kill_child(&mut child).await;
+            return Err(RsdebstrapError::execution(
                 spec,
-                format!("failed to spawn stdout reader thread: {}", e),
+                format!("failed to wait for command: {}", e),
             )
             .into());
         }
     };
 
-    let stderr_handle = match thread::Builder::new()
-        .name("stderr-reader".to_string())
-        .spawn(move || read_pipe_to_log(stderr_pipe, StreamType::Stderr))
-    {
-        Ok(handle) => handle,
+    let (status, elapsed, tails) = match outcome {
+        WaitOutcome::Exited(status) => {
+            let elapsed = started.elapsed();
+            (status, elapsed, join_readers(readers, spec).await?)
+        }
+        WaitOutcome::TimedOut | WaitOutcome::Interrupted => {
+            kill_child(&mut child).await;
+            let elapsed = started.elapsed();
+            let tails = tokio::time::timeout(OUTPUT_DRAIN_TIMEOUT, join_readers(readers, spec))
+                .await
+                .unwrap_or_else(|_| {
+                    tracing::debug!(
+                        "stopped reading output of {} after it was killed",
+                        spec.command
+                    );
+                    Ok(Vec::new())
+                })
+                .unwrap_or_default();
+            let error = match (outcome, spec.timeout) {
+                (WaitOutcome::TimedOut, Some(timeout)) => {
+                    RsdebstrapError::execution(spec, format!("timed out after {:?}", timeout))
+                }
+                _ => RsdebstrapError::interrupted(spec, "interrupted"),
+            };
+            let details = ExecutionDetails::new(spec, elapsed, tails.concat());
+            return Err(error.with_details(Some(Box::new(details))).into());
+        }
+    };
+
+    tracing::trace!("executed command: {}: success={}", spec.command, status.success());
+
+    Ok(ExecutionResult {
+        status: Some(status),
+        details: Some(Box::new(ExecutionDetails::new(spec, elapsed, tails.concat()))),
+    })
+}
+
+/// Runs `commands`, built from `stages`, as a pipeline on the current runtime.
+///
+/// Each stage's standard output is handed to the next stage as its standard input;
+/// the first stage gets [`CommandSpec::stdin`], and the last stage's output is logged.
+/// The shortest stage timeout applies to the whole pipeline. When it passes or the
+/// pipeline is interrupted, every stage is killed. Otherwise the first stage that
+/// failed is reported.
+async fn run_pipeline(
+    commands: Vec<Command>,
+    stages: &[CommandSpec],
+    interrupted: impl Fn() -> bool,
+) -> Result<()> {
+    let first = &stages[0];
+    if interrupted() {
+        return Err(RsdebstrapError::interrupted(first, "interrupted before it started").into());
+    }
+
+    let prefix = OutputPrefix::current();
+    let mut children: Vec<Child> = Vec::with_capacity(stages.len());
+    let mut readers: Vec<Reader> = Vec::with_capacity(stages.len() + 1);
+    // Position in `readers` of each stage's stderr reader.
+    let mut stderr_readers = Vec::with_capacity(stages.len());
+    let mut writer = None;
+    let mut previous_stdout = None;
+    for (index, (mut command, spec)) in commands.into_iter().zip(stages).enumerate() {
+        match previous_stdout.take() {
+            Some(stdout) => {
+                let stdin: Stdio = tokio::process::ChildStdout::try_into(stdout).map_err(|e| {
+                    RsdebstrapError::execution(spec, format!("failed to connect stdin: {}", e))
+                })?;
+                command.stdin(stdin);
+            }
+            None if spec.stdin.is_some() => {
+                command.stdin(Stdio::piped());
+            }
+            None => {}
+        }
+        command.stdout(Stdio::piped());
+        // Stages already started are killed when `children` is dropped.
+        let mut child = command.spawn().map_err(|e| {
+            RsdebstrapError::execution(spec, format!("failed to spawn command: {}", e))
+        })?;
+        tracing::trace!("spawned pipeline stage: {}: pid={:?}", spec.command, child.id());
+        if index == 0 {
+            writer = spawn_stdin_writer(&mut child, spec);
+        }
+        if index + 1 == stages.len() {
+            readers.push((
+                "stdout",
+                tokio::spawn(read_pipe_to_log(
+                    child.stdout.take(),
+                    StreamType::Stdout,
+                    prefix.clone(),
+                    0,
+                )),
+            ));
+        } else {
+            previous_stdout = child.stdout.take();
+        }
+        stderr_readers.push(readers.len());
+        readers.push(spawn_stderr_reader(&mut child, prefix.clone()));
+        children.push(child);
+    }
+    let started = Instant::now();
+
+    let timeout = stages.iter().filter_map(|s| s.timeout).min();
+    let wait_all = async {
+        let mut statuses = Vec::with_capacity(children.len());
+        for child in children.iter_mut() {
+            statuses.push(child.wait().await?);
+        }
+        Ok(statuses)
+    };
+    let outcome = wait_or_cancel(wait_all, timeout, interrupted).await;
+    if let Some(writer) = writer {
+        writer.abort();
+    }
+    let statuses = match outcome {
+        Ok(WaitOutcome::Exited(statuses)) => statuses,
+        Ok(outcome) => {
+            kill_children(&mut children, TERMINATE_GRACE_PERIOD).await;
+            let error = match (outcome, timeout) {
+                (WaitOutcome::TimedOut, Some(timeout)) => {
+                    RsdebstrapError::execution(first, format!("timed out after {:?}", timeout))
+                }
+                _ => RsdebstrapError::interrupted(first, "interrupted"),
+            };
+            return Err(error.into());
+        }
         Err(e) => {
-            cleanup_child_process(child, [stdout_handle]);
-            return Err(crate::error::RsdebstrapError::execution(
-                spec,
-                format!("failed to spawn stderr reader thread: {}", e),
+            kill_children(&mut children, TERMINATE_GRACE_PERIOD).await;
+            return Err(RsdebstrapError::execution(
+                first,
+                format!("failed to wait for command: {}", e),
             )
             .into());
         }
     };
+    let elapsed = started.elapsed();
+    let mut tails = join_readers(readers, first).await?;
+    match stages
+        .iter()
+        .zip(&statuses)
+        .zip(stderr_readers)
+        .find(|((_, status), _)| !status.success())
+    {
+        Some(((spec, status), reader)) => {
+            let details = ExecutionDetails::new(spec, elapsed, std::mem::take(&mut tails[reader]));
+            Err(RsdebstrapError::execution(spec, status.to_string())
+                .with_details(Some(Box::new(details)))
+                .into())
+        }
+        None => Ok(()),
+    }
+}
+
+/// Builds the single-threaded runtime a command (or pipeline, starting with `spec`)
+/// runs on.
+fn runtime(spec: &CommandSpec) -> Result<tokio::runtime::Runtime> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .enable_time()
+        .build()
+        .map_err(|e| {
+            RsdebstrapError::execution(spec, format!("failed to start async runtime: {}", e)).into()
+        })
+}
+
+/// Waits for the output readers to finish (with error propagation on panic).
+///
+/// Returns the lines each reader kept, in the order of `readers`.
+async fn join_readers(
+    readers: impl IntoIterator<Item = Reader>,
+    spec: &CommandSpec,
+) -> Result<Vec<Vec<String>>> {
+    let mut panicked_streams = Vec::new();
+    let mut tails = Vec::new();
+    for (name, handle) in readers {
+        match handle.await {
+            Ok(tail) => tails.push(tail),
+            Err(e) => {
+                tails.push(Vec::new());
+                if e.is_panic() {
+                    let payload = e.into_panic();
+                    let msg = panic_message(&*payload);
+                    tracing::error!(stream = name, panic = msg, "reader task panicked");
+                    panicked_streams.push(format!("{}: {}", name, msg));
+                }
+            }
+        }
+    }
 
-    Ok((stdout_handle, stderr_handle))
+    if !panicked_streams.is_empty() {
+        return Err(RsdebstrapError::execution(
+            spec,
+            format!(
+                "reader task(s) panicked during command execution: {}",
+                panicked_streams.join(", ")
+            ),
+        )
+        .into());
+    }
+    Ok(tails)
+}
+
+/// Returns whether `spec` needs an `env` shim to reach the command under `method`.
+///
+/// Only `pkexec` needs one: it has no options for setting variables or the working
+/// directory, and replaces the caller's environment with a minimal one.
+fn needs_env_shim(method: PrivilegeMethod, spec: &CommandSpec) -> bool {
+    method == PrivilegeMethod::Pkexec && (!spec.env.is_empty() || spec.cwd.is_some())
+}
+
+/// Builds the arguments passed to the privilege escalation command.
+///
+/// `program` is the resolved path of `spec.command`. `sudo` and `doas` take it as-is,
+/// since they keep the caller's working directory and environment; so does `unshare`
+/// for `userns`, which only adds the root and subordinate ID mappings. `run0` gets
+/// `spec.env`/`spec.cwd` as `--setenv=`/`--chdir=` options. `pkexec` runs the command
+/// through `env_program` (`env -C <cwd> KEY=VALUE...`) when there is anything to
+/// forward. No method is made non-interactive: `pkexec` has no equivalent of
+/// `sudo -n`, so password prompts are left to the escalation tool in every case.
+fn privileged_args(
+    method: PrivilegeMethod,
+    program: String,
+    env_program: Option<String>,
+    spec: &CommandSpec,
+) -> Vec<String> {
+    let mut args: Vec<String> = Vec::with_capacity(spec.args.len() + spec.env.len() + 4);
+    match method {
+        PrivilegeMethod::Sudo | PrivilegeMethod::Doas => {}
+        PrivilegeMethod::Userns => {
+            args.push("--map-root-user".to_string());
+            args.push("--map-auto".to_string());
+        }
+        PrivilegeMethod::Run0 => {
+            if let Some(ref cwd) = spec.cwd {
+                args.push(format!("--chdir={}", cwd));
+            }
+            for (key, value) in &spec.env {
+                args.push(format!("--setenv={}={}", key, value));
+            }
+        }
+        PrivilegeMethod::Pkexec => {
+            if let Some(env_program) = env_program {
+                args.push(env_program);
+                if let Some(ref cwd) = spec.cwd {
+                    args.push("-C".to_string());
+                    args.push(cwd.to_string());
+                }
+                for (key, value) in &spec.env {
+                    args.push(format!("{}={}", key, value));
+                }
+            }
+        }
+    }
+    args.push(program);
+    args.extend(spec.args.iter().cloned());
+    args
+}
+
+/// Resolves `spec`'s command, wrapped in its privilege escalation, into a [`Command`]
+/// with the working directory and environment set and standard error piped. Standard
+/// input and output are left to the caller.
+fn build_command(spec: &CommandSpec) -> Result<Command> {
+    let find_command = |cmd_name: &str, label: &str| -> Result<std::path::PathBuf> {
+        which(cmd_name).map_err(|e| {
+            tracing::debug!("command lookup failed for '{}': {}", cmd_name, e);
+            RsdebstrapError::command_not_found(cmd_name, label).into()
+        })
+    };
+
+    // Resolve the actual command to execute, wrapping with privilege if needed
+    let (resolved_program, resolved_args) = if let Some(method) = &spec.privilege {
+        let privilege_cmd = find_command(method.command_name(), "privilege escalation command")?;
+        let actual_cmd = find_command(&spec.command, "command")?;
+
+        tracing::trace!("privilege escalation: {} {}", method.command_name(), actual_cmd.display());
+
+        let env_program = if needs_env_shim(*method, spec) {
+            Some(find_command("env", "command")?.display().to_string())
+        } else {
+            None
+        };
+        let args = privileged_args(*method, actual_cmd.display().to_string(), env_program, spec);
+
+        (privilege_cmd, args)
+    } else {
+        let cmd = find_command(&spec.command, "command")?;
+        tracing::trace!("command found: {}: {}", spec.command, cmd.display());
+        (cmd, spec.args.clone())
+    };
+
+    let mut command = Command::new(&resolved_program);
+    command.args(&resolved_args);
+
+    if let Some(ref cwd) = spec.cwd {
+        command.current_dir(cwd.as_std_path());
+    }
+
+    for (key, value) in &spec.env {
+        command.env(key, value);
+    }
+
+    command.stderr(Stdio::piped());
+    // Never leave a command running if the future is dropped early.
+    command.kill_on_drop(true);
+    Ok(command)
 }
 
 /// Command executor that runs actual system commands.
 ///
 /// When `dry_run` is true, commands are logged but not executed,
-/// and `execute()` returns `Ok(ExecutionResult { status: None })`.
+/// and `execute()` returns `Ok(ExecutionResult { status: None, details: None })`.
+///
+/// When rsdebstrap runs as root, mount entries are mounted and unmounted with
+/// `mount(2)`/`umount(2)` instead of the `mount`/`umount` commands, where the
+/// entry allows it.
 pub struct RealCommandExecutor {
     pub dry_run: bool,
 }
@@ -112,109 +568,245 @@ impl CommandExecutor for RealCommandExecutor {
             if let Some(ref cwd) = spec.cwd {
                 tracing::info!("dry run cwd: {}", cwd);
             }
-            return Ok(ExecutionResult { status: None });
+            if let Some(ref stdin) = spec.stdin {
+                tracing::info!("dry run stdin: {} bytes", stdin.len());
+            }
+            return Ok(ExecutionResult {
+                status: None,
+                details: None,
+            });
         }
 
-        let find_command = |cmd_name: &str, label: &str| -> Result<std::path::PathBuf> {
-            which(cmd_name).map_err(|e| {
-                tracing::debug!("command lookup failed for '{}': {}", cmd_name, e);
-                crate::error::RsdebstrapError::command_not_found(cmd_name, label).into()
-            })
+        let mut command = build_command(spec)?;
+        if spec.stdin.is_some() {
+            command.stdin(Stdio::piped());
+        }
+        command.stdout(Stdio::piped());
+
+        runtime(spec)?.block_on(run_child(command, spec, interrupt::take_interrupt))
+    }
+
+    fn execute_pipeline(&self, stages: &[CommandSpec]) -> Result<()> {
+        let Some(first) = stages.first() else {
+            return Ok(());
         };
+        if self.dry_run {
+            let line = stages
+                .iter()
+                .map(super::shell_command_line)
+                .collect::<Vec<_>>()
+                .join(" | ");
+            tracing::info!("dry run: {}", line);
+            return Ok(());
+        }
+        let commands = stages
+            .iter()
+            .map(build_command)
+            .collect::<Result<Vec<_>>>()?;
+        runtime(first)?.block_on(run_pipeline(commands, stages, interrupt::take_interrupt))
+    }
 
-        // Resolve the actual command to execute, wrapping with privilege if needed
-        let (resolved_program, resolved_args) = if let Some(method) = &spec.privilege {
-            let privilege_cmd =
-                find_command(method.command_name(), "privilege escalation command")?;
-            let actual_cmd = find_command(&spec.command, "command")?;
+    fn mount(
+        &self,
+        entry: &MountEntry,
+        target: &Utf8Path,
+        privilege: Option<PrivilegeMethod>,
+    ) -> Result<()> {
+        if !self.dry_run
+            && native_mount::native_mounts_available(privilege)
+            && native_mount::mount(entry, target)?
+        {
+            return Ok(());
+        }
+        self.execute_checked(&entry.build_mount_spec_with_path(target, privilege))
+    }
 
-            tracing::trace!(
-                "privilege escalation: {} {}",
-                method.command_name(),
-                actual_cmd.display()
-            );
+    fn unmount(
+        &self,
+        entry: &MountEntry,
+        target: &Utf8Path,
+        mode: UnmountMode,
+        privilege: Option<PrivilegeMethod>,
+    ) -> Result<()> {
+        // Mirrors `mount`: an entry mounted with the command is unmounted with it.
+        if !self.dry_run && native_mount::mounts_natively(entry, privilege) {
+            return native_mount::unmount(target, mode);
+        }
+        self.execute_checked(&entry.build_umount_spec_with_mode(target, mode, privilege))
+    }
+}
 
-            let mut args: Vec<String> = Vec::with_capacity(spec.args.len() + 1);
-            args.push(actual_cmd.display().to_string());
-            args.extend(spec.args.iter().cloned());
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use camino::Utf8PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Instant;
 
-            (privilege_cmd, args)
-        } else {
-            let cmd = find_command(&spec.command, "command")?;
-            tracing::trace!("command found: {}: {}", spec.command, cmd.display());
-            (cmd, spec.args.clone())
-        };
+    fn run(command: Command, spec: &CommandSpec, interrupted: impl Fn() -> bool) -> Result<()> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime
+            .block_on(run_child(command, spec, interrupted))
+            .map(|_| ())
+    }
 
-        let mut command = Command::new(&resolved_program);
-        command.args(&resolved_args);
+    fn piped(program: &str, args: &[&str]) -> Command {
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        command
+    }
 
-        if let Some(ref cwd) = spec.cwd {
-            command.current_dir(cwd.as_std_path());
-        }
+    #[test]
+    fn interrupt_kills_running_command() {
+        let spec = CommandSpec::new("sleep", vec!["30".into()]);
+        // No interrupt when starting, one as soon as the command runs.
+        let polls = AtomicUsize::new(0);
+        let started = Instant::now();
+        let err = run(piped("sleep", &["30"]), &spec, || polls.fetch_add(1, Ordering::SeqCst) > 0)
+            .unwrap_err();
+        assert!(err.to_string().contains("interrupted"), "{err:#}");
+        assert!(crate::error::is_interrupted(&err), "{err:#}");
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
 
-        for (key, value) in &spec.env {
-            command.env(key, value);
-        }
+    /// Starts `script`, gives it time to set its traps, then stops it with
+    /// [`kill_children`] and returns how it exited.
+    fn stop_script(script: &str, grace: Duration) -> ExitStatus {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let mut children = [piped("sh", &["-c", script]).spawn().unwrap()];
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            kill_children(&mut children, grace).await;
+            children[0].wait().await.unwrap()
+        })
+    }
 
-        command.stdout(Stdio::piped());
-        command.stderr(Stdio::piped());
+    #[test]
+    fn cancelled_command_gets_sigterm_first() {
+        use std::os::unix::process::ExitStatusExt;
+        let started = Instant::now();
+        let status =
+            stop_script("trap 'exit 3' TERM; while :; do sleep 0.1; done", Duration::from_secs(30));
+        assert_eq!(status.code(), Some(3), "{status:?}");
+        assert_eq!(status.signal(), None);
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
 
-        let mut child = match command.spawn() {
-            Ok(child) => child,
-            Err(e) => {
-                return Err(crate::error::RsdebstrapError::execution(
-                    spec,
-                    format!("failed to spawn command: {}", e),
-                )
-                .into());
-            }
-        };
+    #[test]
+    fn command_ignoring_sigterm_is_killed_after_grace_period() {
+        use std::os::unix::process::ExitStatusExt;
+        let status =
+            stop_script("trap '' TERM; while :; do sleep 0.1; done", Duration::from_millis(300));
+        assert_eq!(status.signal(), Some(libc::SIGKILL), "{status:?}");
+    }
 
-        tracing::trace!("spawned command: {}: pid={}", spec.command, child.id());
+    #[test]
+    fn stdin_is_written_and_closed() {
+        let script = r#"read -r line && test "$line" = hello && test -z "$(cat)""#;
+        let spec = CommandSpec::new("sh", vec!["-c".into(), script.into()]).with_stdin("hello\n");
+        let mut command = piped("sh", &["-c", script]);
+        command.stdin(Stdio::piped());
+        let result = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(run_child(command, &spec, || false))
+            .unwrap();
+        assert!(result.success());
+    }
 
-        let (stdout_handle, stderr_handle) = spawn_reader_threads(&mut child, spec)?;
+    #[test]
+    fn unread_stdin_does_not_fail_command() {
+        let input = vec![b'x'; 1 << 20];
+        let spec = CommandSpec::new("true", Vec::new()).with_stdin(input);
+        let mut command = piped("true", &[]);
+        command.stdin(Stdio::piped());
+        run(command, &spec, || false).expect("an unread stdin should not fail the command");
+    }
 
-        // Wait for the child process to complete
-        let status = match child.wait() {
-            Ok(s) => s,
-            Err(e) => {
-                // If waiting fails, the process might still be running.
-                // Kill it and clean up threads to prevent resource leaks.
-                cleanup_child_process(&mut child, [stdout_handle, stderr_handle]);
-                return Err(crate::error::RsdebstrapError::execution(
-                    spec,
-                    format!("failed to wait for command: {}", e),
-                )
-                .into());
-            }
-        };
+    #[test]
+    fn pending_interrupt_prevents_spawn() {
+        let spec = CommandSpec::new("true", Vec::new());
+        let err = run(piped("/nonexistent/true", &[]), &spec, || true).unwrap_err();
+        assert!(err.to_string().contains("interrupted before it started"), "{err:#}");
+    }
 
-        // Wait for reader threads to complete (with error propagation on panic)
-        let mut panicked_streams = Vec::new();
-        let handles = [("stdout", stdout_handle), ("stderr", stderr_handle)];
-        for (name, handle) in handles {
-            if let Err(e) = handle.join() {
-                let msg = panic_message(&*e);
-                tracing::error!(stream = name, panic = msg, "reader thread panicked");
-                panicked_streams.push(format!("{}: {}", name, msg));
-            }
-        }
+    fn spec_with_context() -> CommandSpec {
+        CommandSpec::new("sh", vec!["-c".into(), "true".into()])
+            .with_cwd(Utf8PathBuf::from("/work"))
+            .with_env("LANG", "C")
+    }
 
-        if !panicked_streams.is_empty() {
-            return Err(crate::error::RsdebstrapError::execution(
-                spec,
-                format!(
-                    "reader thread(s) panicked during command execution: {}",
-                    panicked_streams.join(", ")
-                ),
-            )
-            .into());
+    #[test]
+    fn sudo_and_doas_pass_command_directly() {
+        for method in [PrivilegeMethod::Sudo, PrivilegeMethod::Doas] {
+            let args = privileged_args(method, "/bin/sh".into(), None, &spec_with_context());
+            assert_eq!(args, ["/bin/sh", "-c", "true"], "{method}");
         }
+    }
 
-        tracing::trace!("executed command: {}: success={}", spec.command, status.success());
+    #[test]
+    fn run0_forwards_cwd_and_env_as_options() {
+        let args =
+            privileged_args(PrivilegeMethod::Run0, "/bin/sh".into(), None, &spec_with_context());
+        assert_eq!(args, ["--chdir=/work", "--setenv=LANG=C", "/bin/sh", "-c", "true"]);
+    }
 
-        Ok(ExecutionResult {
-            status: Some(status),
-        })
+    #[test]
+    fn pkexec_forwards_cwd_and_env_through_env_shim() {
+        let spec = spec_with_context();
+        assert!(needs_env_shim(PrivilegeMethod::Pkexec, &spec));
+        let args = privileged_args(
+            PrivilegeMethod::Pkexec,
+            "/bin/sh".into(),
+            Some("/usr/bin/env".into()),
+            &spec,
+        );
+        assert_eq!(
+            args,
+            [
+                "/usr/bin/env",
+                "-C",
+                "/work",
+                "LANG=C",
+                "/bin/sh",
+                "-c",
+                "true"
+            ]
+        );
+    }
+
+    #[test]
+    fn pkexec_without_context_passes_command_directly() {
+        let spec = CommandSpec::new("sh", vec!["-c".into(), "true".into()]);
+        assert!(!needs_env_shim(PrivilegeMethod::Pkexec, &spec));
+        let args = privileged_args(PrivilegeMethod::Pkexec, "/bin/sh".into(), None, &spec);
+        assert_eq!(args, ["/bin/sh", "-c", "true"]);
+    }
+
+    #[test]
+    fn userns_runs_command_under_unshare_mappings() {
+        let args =
+            privileged_args(PrivilegeMethod::Userns, "/bin/sh".into(), None, &spec_with_context());
+        assert_eq!(args, ["--map-root-user", "--map-auto", "/bin/sh", "-c", "true"]);
+    }
+
+    #[test]
+    fn only_pkexec_needs_env_shim() {
+        let spec = spec_with_context();
+        assert!(!needs_env_shim(PrivilegeMethod::Sudo, &spec));
+        assert!(!needs_env_shim(PrivilegeMethod::Doas, &spec));
+        assert!(!needs_env_shim(PrivilegeMethod::Run0, &spec));
+        assert!(!needs_env_shim(PrivilegeMethod::Userns, &spec));
     }
 }
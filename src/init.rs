@@ -0,0 +1,423 @@
+//! Starter profile scaffolding for `rsdebstrap init`.
+//!
+//! This module renders a minimal, commented YAML profile from a handful of
+//! choices (backend, suite, mirrors, output directory, privilege method, and
+//! whether to include an example provision task). The choices come either from
+//! CLI flags or from an interactive prompt session; [`prompt_options`] reads
+//! answers from any `BufRead` so the dialogue is testable without a terminal.
+//!
+//! The rendered YAML is not trusted on its own: `run_init()` loads it through
+//! [`load_profile`](crate::config::load_profile) and
+//! [`Profile::validate`](crate::config::Profile::validate) before the file is
+//! written to its final location.
+
+use std::fmt::Write as _;
+use std::io::{BufRead, Write};
+
+use anyhow::{Context, Result};
+use camino::Utf8PathBuf;
+
+use crate::cli::{InitArgs, InitBackend};
+use crate::config::PROFILE_SCHEMA_VERSION;
+use crate::error::RsdebstrapError;
+use crate::privilege::PrivilegeMethod;
+
+/// Default mirror written when none is given.
+pub const DEFAULT_MIRROR: &str = "https://deb.debian.org/debian";
+
+/// Choices used to render a starter profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InitOptions {
+    /// Bootstrap backend to configure.
+    pub backend: InitBackend,
+    /// Debian suite name.
+    pub suite: String,
+    /// APT mirror URLs (debootstrap accepts at most one).
+    pub mirrors: Vec<String>,
+    /// Base output directory (`dir`), relative to the profile file if relative.
+    pub dir: Utf8PathBuf,
+    /// Output name under `dir` (`bootstrap.target`).
+    pub target: String,
+    /// Default privilege escalation method, if any.
+    pub privilege: Option<PrivilegeMethod>,
+    /// Whether to include an example inline shell provision task.
+    pub example_task: bool,
+}
+
+impl From<&InitArgs> for InitOptions {
+    fn from(args: &InitArgs) -> Self {
+        Self {
+            backend: args.backend,
+            suite: args.suite.clone(),
+            mirrors: if args.mirror.is_empty() {
+                vec![DEFAULT_MIRROR.to_string()]
+            } else {
+                args.mirror.clone()
+            },
+            dir: args.dir.clone(),
+            target: args.target.clone(),
+            privilege: args.privilege,
+            example_task: !args.no_example_task,
+        }
+    }
+}
+
+impl InitOptions {
+    /// Validates the choices before rendering.
+    ///
+    /// Catches the mistakes the rendered profile would otherwise surface only as a
+    /// less specific load/validate error (empty names, several mirrors for debootstrap).
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        if self.suite.trim().is_empty() {
+            return Err(RsdebstrapError::Validation("init: suite must not be empty".to_string()));
+        }
+        if self.target.trim().is_empty() {
+            return Err(RsdebstrapError::Validation("init: target must not be empty".to_string()));
+        }
+        if self.dir.as_str().trim().is_empty() {
+            return Err(RsdebstrapError::Validation("init: dir must not be empty".to_string()));
+        }
+        if self.mirrors.iter().any(|m| m.trim().is_empty()) {
+            return Err(RsdebstrapError::Validation(
+                "init: mirror URLs must not be empty".to_string(),
+            ));
+        }
+        if self.backend == InitBackend::Debootstrap && self.mirrors.len() > 1 {
+            return Err(RsdebstrapError::Validation(format!(
+                "init: debootstrap accepts a single mirror (got {})",
+                self.mirrors.len()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Renders a scalar as a YAML value, quoting it when a plain scalar would be
+/// read back as something other than the same string (e.g. `"13"`, `"true"`).
+fn yaml_scalar(value: &str) -> Result<String> {
+    let rendered = yaml_serde::to_string(value).context("failed to render YAML scalar")?;
+    Ok(rendered.trim_end().to_string())
+}
+
+/// Renders the starter profile YAML for the given choices.
+pub fn render_profile(options: &InitOptions) -> Result<String> {
+    options.validate()?;
+
+    let mut out = String::new();
+    out.push_str("---\n");
+    out.push_str("# rsdebstrap profile generated by `rsdebstrap init`.\n");
+    out.push_str("#\n");
+    out.push_str("# Check it with `rsdebstrap validate -f <this file>`, preview the build with\n");
+    out.push_str("# `rsdebstrap apply -f <this file> --dry-run`, and see\n");
+    out.push_str("# examples/debian_trixie_mmdebstrap.yml for every available option.\n\n");
+
+    writeln!(out, "schema_version: {}", PROFILE_SCHEMA_VERSION)?;
+    writeln!(out, "dir: {}", yaml_scalar(options.dir.as_str())?)?;
+
+    if let Some(method) = options.privilege {
+        out.push_str("\ndefaults:\n");
+        out.push_str("  privilege:\n");
+        writeln!(out, "    method: {}", method)?;
+    }
+
+    out.push_str("\nbootstrap:\n");
+    writeln!(out, "  type: {}", options.backend.as_str())?;
+    writeln!(out, "  suite: {}", yaml_scalar(&options.suite)?)?;
+    writeln!(out, "  target: {}", yaml_scalar(&options.target)?)?;
+    match options.backend {
+        InitBackend::Mmdebstrap => {
+            if !options.mirrors.is_empty() {
+                out.push_str("  mirrors:\n");
+                for mirror in &options.mirrors {
+                    writeln!(out, "  - {}", yaml_scalar(mirror)?)?;
+                }
+            }
+        }
+        InitBackend::Debootstrap => {
+            if let Some(mirror) = options.mirrors.first() {
+                writeln!(out, "  mirror: {}", yaml_scalar(mirror)?)?;
+            }
+        }
+    }
+    if options.privilege.is_some() {
+        out.push_str("  privilege: true # Use defaults.privilege method\n");
+    }
+
+    if options.example_task {
+        out.push_str("\nprovision:\n");
+        out.push_str("- type: shell\n");
+        out.push_str("  content: |-\n");
+        out.push_str("    #!/bin/sh\n");
+        out.push_str("    set -e\n");
+        out.push_str("    echo \"provisioning $(cat /etc/debian_version)\"\n");
+    }
+
+    Ok(out)
+}
+
+/// Asks one question, returning the trimmed answer or `default` on an empty line.
+fn ask<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    question: &str,
+    default: &str,
+) -> Result<String> {
+    if default.is_empty() {
+        write!(output, "{}: ", question)?;
+    } else {
+        write!(output, "{} [{}]: ", question, default)?;
+    }
+    output.flush()?;
+
+    let mut line = String::new();
+    let read = input
+        .read_line(&mut line)
+        .context("failed to read answer")?;
+    if read == 0 {
+        return Err(RsdebstrapError::Validation(
+            "init: input ended before all questions were answered".to_string(),
+        )
+        .into());
+    }
+    let answer = line.trim();
+    Ok(if answer.is_empty() {
+        default.to_string()
+    } else {
+        answer.to_string()
+    })
+}
+
+/// Asks a question until the answer is one of `choices` (case-insensitive).
+fn ask_choice<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    question: &str,
+    choices: &[&str],
+    default: &str,
+) -> Result<String> {
+    let prompt = format!("{} ({})", question, choices.join("/"));
+    loop {
+        let answer = ask(input, output, &prompt, default)?.to_ascii_lowercase();
+        if choices.contains(&answer.as_str()) {
+            return Ok(answer);
+        }
+        writeln!(output, "please answer one of: {}", choices.join(", "))?;
+    }
+}
+
+/// Runs the interactive question session, using `defaults` for empty answers.
+///
+/// Questions are written to `output` (stderr in the CLI, so stdout stays clean)
+/// and answers read line by line from `input`.
+pub fn prompt_options<R: BufRead, W: Write>(
+    defaults: &InitOptions,
+    input: &mut R,
+    output: &mut W,
+) -> Result<InitOptions> {
+    let backend = match ask_choice(
+        input,
+        output,
+        "Bootstrap backend",
+        &["mmdebstrap", "debootstrap"],
+        defaults.backend.as_str(),
+    )?
+    .as_str()
+    {
+        "debootstrap" => InitBackend::Debootstrap,
+        _ => InitBackend::Mmdebstrap,
+    };
+
+    let suite = ask(input, output, "Debian suite", &defaults.suite)?;
+
+    let mirror_question = match backend {
+        InitBackend::Mmdebstrap => "Mirror URLs (comma-separated)",
+        InitBackend::Debootstrap => "Mirror URL",
+    };
+    let mirrors = ask(input, output, mirror_question, &defaults.mirrors.join(","))?
+        .split(',')
+        .map(str::trim)
+        .filter(|m| !m.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    let dir = ask(input, output, "Output directory", defaults.dir.as_str())?;
+    let target = ask(input, output, "Rootfs target name", &defaults.target)?;
+
+    let privilege = match ask_choice(
+        input,
+        output,
+        "Privilege escalation",
+        &["none", "sudo", "doas", "run0", "pkexec", "userns"],
+        defaults.privilege.map_or("none", |m| m.as_str()),
+    )?
+    .as_str()
+    {
+        "sudo" => Some(PrivilegeMethod::Sudo),
+        "doas" => Some(PrivilegeMethod::Doas),
+        "run0" => Some(PrivilegeMethod::Run0),
+        "pkexec" => Some(PrivilegeMethod::Pkexec),
+        "userns" => Some(PrivilegeMethod::Userns),
+        _ => None,
+    };
+
+    let example_task = ask_choice(
+        input,
+        output,
+        "Include an example provision task",
+        &["y", "n"],
+        if defaults.example_task { "y" } else { "n" },
+    )? == "y";
+
+    let options = InitOptions {
+        backend,
+        suite,
+        mirrors,
+        dir: dir.into(),
+        target,
+        privilege,
+        example_task,
+    };
+    options.validate()?;
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> InitOptions {
+        InitOptions {
+            backend: InitBackend::Mmdebstrap,
+            suite: "trixie".to_string(),
+            mirrors: vec![DEFAULT_MIRROR.to_string()],
+            dir: "output".into(),
+            target: "rootfs".to_string(),
+            privilege: None,
+            example_task: true,
+        }
+    }
+
+    #[test]
+    fn render_mmdebstrap_profile() {
+        let yaml = render_profile(&options()).unwrap();
+        assert!(yaml.contains("schema_version: 1\ndir: output\n"));
+        assert!(yaml.contains("  type: mmdebstrap\n"));
+        assert!(yaml.contains("  mirrors:\n  - https://deb.debian.org/debian\n"));
+        assert!(yaml.contains("provision:\n- type: shell\n"));
+        assert!(!yaml.contains("defaults:"));
+    }
+
+    #[test]
+    fn render_debootstrap_profile_uses_single_mirror() {
+        let yaml = render_profile(&InitOptions {
+            backend: InitBackend::Debootstrap,
+            ..options()
+        })
+        .unwrap();
+        assert!(yaml.contains("  type: debootstrap\n"));
+        assert!(yaml.contains("  mirror: https://deb.debian.org/debian\n"));
+        assert!(!yaml.contains("mirrors:"));
+    }
+
+    #[test]
+    fn render_with_privilege_adds_defaults() {
+        let yaml = render_profile(&InitOptions {
+            privilege: Some(PrivilegeMethod::Doas),
+            ..options()
+        })
+        .unwrap();
+        assert!(yaml.contains("defaults:\n  privilege:\n    method: doas\n"));
+        assert!(yaml.contains("  privilege: true"));
+    }
+
+    #[test]
+    fn render_quotes_numeric_suite() {
+        let yaml = render_profile(&InitOptions {
+            suite: "13".to_string(),
+            ..options()
+        })
+        .unwrap();
+        assert!(yaml.contains("  suite: '13'\n"), "suite must be quoted: {yaml}");
+    }
+
+    #[test]
+    fn render_without_example_task() {
+        let yaml = render_profile(&InitOptions {
+            example_task: false,
+            ..options()
+        })
+        .unwrap();
+        assert!(!yaml.contains("provision:"));
+    }
+
+    #[test]
+    fn debootstrap_rejects_multiple_mirrors() {
+        let err = InitOptions {
+            backend: InitBackend::Debootstrap,
+            mirrors: vec!["http://a/debian".to_string(), "http://b/debian".to_string()],
+            ..options()
+        }
+        .validate()
+        .unwrap_err();
+        assert!(err.to_string().contains("single mirror"));
+    }
+
+    #[test]
+    fn empty_suite_is_rejected() {
+        let err = InitOptions {
+            suite: " ".to_string(),
+            ..options()
+        }
+        .validate()
+        .unwrap_err();
+        assert!(matches!(err, RsdebstrapError::Validation(_)));
+    }
+
+    #[test]
+    fn prompt_accepts_defaults_on_empty_answers() {
+        let mut input = "\n\n\n\n\n\n\n".as_bytes();
+        let mut output = Vec::new();
+        let answered = prompt_options(&options(), &mut input, &mut output).unwrap();
+        assert_eq!(answered, options());
+        let transcript = String::from_utf8(output).unwrap();
+        assert!(transcript.contains("Debian suite [trixie]: "));
+    }
+
+    #[test]
+    fn prompt_reads_answers() {
+        let mut input =
+            "debootstrap\nbookworm\nhttp://mirror/debian\n/srv/out\nroot\nsudo\nn\n".as_bytes();
+        let mut output = Vec::new();
+        let answered = prompt_options(&options(), &mut input, &mut output).unwrap();
+        assert_eq!(
+            answered,
+            InitOptions {
+                backend: InitBackend::Debootstrap,
+                suite: "bookworm".to_string(),
+                mirrors: vec!["http://mirror/debian".to_string()],
+                dir: "/srv/out".into(),
+                target: "root".to_string(),
+                privilege: Some(PrivilegeMethod::Sudo),
+                example_task: false,
+            }
+        );
+    }
+
+    #[test]
+    fn prompt_repeats_invalid_choice() {
+        let mut input = "bwrap\nmmdebstrap\n\n\n\n\n\n\n".as_bytes();
+        let mut output = Vec::new();
+        let answered = prompt_options(&options(), &mut input, &mut output).unwrap();
+        assert_eq!(answered.backend, InitBackend::Mmdebstrap);
+        let transcript = String::from_utf8(output).unwrap();
+        assert!(transcript.contains("please answer one of: mmdebstrap, debootstrap"));
+    }
+
+    #[test]
+    fn prompt_errors_on_eof() {
+        let mut input = "mmdebstrap\n".as_bytes();
+        let mut output = Vec::new();
+        let err = prompt_options(&options(), &mut input, &mut output).unwrap_err();
+        assert!(err.to_string().contains("input ended"));
+    }
+}
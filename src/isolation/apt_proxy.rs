@@ -0,0 +1,369 @@
+//! apt proxy configuration lifecycle for rootfs isolation.
+//!
+//! This module provides [`RootfsAptProxy`], an RAII guard that points apt inside
+//! the rootfs at the build's caching proxy (see [`crate::apt_cache`]) while the
+//! provision tasks run, and removes the setting again before the assemble phase
+//! so it never ships in the final image.
+
+use std::fs;
+use std::os::fd::OwnedFd;
+use std::sync::Arc;
+
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+use rustix::fs::{self as rfs, CWD, Mode, OFlags};
+use tracing::info;
+
+use crate::error::RsdebstrapError;
+use crate::executor::{CommandExecutor, CommandSpec};
+use crate::privilege::PrivilegeMethod;
+
+/// apt configuration snippet written inside the rootfs, relative to its root.
+const APT_CONF_SNIPPET: &str = "etc/apt/apt.conf.d/00rsdebstrap-proxy";
+
+/// Generates the apt configuration that routes HTTP downloads through `proxy_url`.
+pub(crate) fn generate_apt_proxy_conf(proxy_url: &str) -> String {
+    format!("// Generated by rsdebstrap\nAcquire::http::Proxy \"{}\";\n", proxy_url)
+}
+
+/// RAII guard for the apt proxy snippet within a rootfs.
+///
+/// Writes `/etc/apt/apt.conf.d/00rsdebstrap-proxy` on setup and removes it on
+/// teardown. The `Drop` implementation ensures cleanup even on error paths.
+/// A rootfs without `/etc/apt/apt.conf.d` (e.g. one bootstrapped without apt)
+/// is left untouched.
+pub struct RootfsAptProxy {
+    rootfs: Utf8PathBuf,
+    proxy_url: Option<String>,
+    executor: Arc<dyn CommandExecutor>,
+    privilege: Option<PrivilegeMethod>,
+    active: bool,
+    dry_run: bool,
+    torn_down: bool,
+}
+
+impl RootfsAptProxy {
+    /// Creates a new `RootfsAptProxy` instance.
+    ///
+    /// If `proxy_url` is `None`, setup and teardown are no-ops.
+    pub fn new(
+        rootfs: &Utf8Path,
+        proxy_url: Option<String>,
+        executor: Arc<dyn CommandExecutor>,
+        privilege: Option<PrivilegeMethod>,
+        dry_run: bool,
+    ) -> Self {
+        Self {
+            rootfs: rootfs.to_owned(),
+            proxy_url,
+            executor,
+            privilege,
+            active: false,
+            dry_run,
+            torn_down: false,
+        }
+    }
+
+    /// Path to the apt configuration snippet in the rootfs.
+    fn snippet_path(&self) -> Utf8PathBuf {
+        self.rootfs.join(APT_CONF_SNIPPET)
+    }
+
+    /// Returns whether `<rootfs>/etc/apt/apt.conf.d` exists.
+    ///
+    /// Each component is opened without following symlinks, so a rootfs cannot
+    /// redirect the write to the host.
+    fn has_apt_conf_dir(&self) -> Result<bool> {
+        let open = |dirfd: Option<&OwnedFd>, path: &str| {
+            let flags = OFlags::NOFOLLOW | OFlags::DIRECTORY | OFlags::RDONLY | OFlags::CLOEXEC;
+            match dirfd {
+                Some(fd) => rfs::openat(fd, path, flags, Mode::empty()),
+                None => rfs::openat(CWD, path, flags, Mode::empty()),
+            }
+        };
+        let etc = self.rootfs.join("etc");
+        let result = open(None, etc.as_str())
+            .and_then(|etc_fd| open(Some(&etc_fd), "apt"))
+            .and_then(|apt_fd| open(Some(&apt_fd), "apt.conf.d"));
+        match result {
+            Ok(_) => Ok(true),
+            Err(rustix::io::Errno::NOENT) => Ok(false),
+            Err(rustix::io::Errno::LOOP | rustix::io::Errno::NOTDIR) => {
+                Err(RsdebstrapError::Isolation(format!(
+                    "{}/apt/apt.conf.d has a symlink or non-directory component, refusing to \
+                    configure the apt proxy (possible symlink attack)",
+                    etc
+                ))
+                .into())
+            }
+            Err(e) => Err(RsdebstrapError::io(
+                format!("failed to open {}/apt/apt.conf.d", etc),
+                std::io::Error::from(e),
+            )
+            .into()),
+        }
+    }
+
+    /// Writes the apt proxy snippet into the rootfs with mode 0o644.
+    pub fn setup(&mut self) -> Result<()> {
+        let Some(proxy_url) = &self.proxy_url else {
+            return Ok(());
+        };
+
+        if self.dry_run {
+            info!("would configure apt proxy {} in {}", proxy_url, self.rootfs);
+            return Ok(());
+        }
+
+        if !self.has_apt_conf_dir()? {
+            info!("{} has no /etc/apt/apt.conf.d, not configuring the apt proxy", self.rootfs);
+            return Ok(());
+        }
+
+        // A snippet the rootfs ships is removed first, so `cp` never writes through it.
+        let snippet = self.snippet_path();
+        match snippet.symlink_metadata() {
+            Ok(metadata) if !metadata.is_file() => {
+                return Err(RsdebstrapError::Isolation(format!(
+                    "{} exists and is not a regular file, refusing to configure the apt proxy \
+                    (possible symlink attack)",
+                    snippet
+                ))
+                .into());
+            }
+            Ok(_) => {
+                let rm_spec = CommandSpec::new("rm", vec!["-f".to_string(), snippet.to_string()])
+                    .with_privilege(self.privilege);
+                self.executor.execute_checked(&rm_spec)?;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(RsdebstrapError::io(format!("failed to inspect {}", snippet), e).into());
+            }
+        }
+
+        let temp = tempfile::NamedTempFile::new().map_err(|e| {
+            RsdebstrapError::io("failed to create temporary file for apt proxy".to_string(), e)
+        })?;
+        fs::write(temp.path(), generate_apt_proxy_conf(proxy_url)).map_err(|e| {
+            RsdebstrapError::io(
+                format!("failed to write temporary apt proxy config: {}", temp.path().display()),
+                e,
+            )
+        })?;
+        let temp_path = temp.path().to_string_lossy().to_string();
+        let spec = CommandSpec::new("cp", vec![temp_path, snippet.to_string()])
+            .with_privilege(self.privilege);
+        self.executor.execute_checked(&spec)?;
+        self.active = true;
+
+        let chmod_spec = CommandSpec::new("chmod", vec!["644".to_string(), snippet.to_string()])
+            .with_privilege(self.privilege);
+        if let Err(e) = self.executor.execute_checked(&chmod_spec) {
+            tracing::warn!("failed to set permissions on {}: {}", snippet, e);
+        }
+
+        info!("configured apt proxy {} in {}", proxy_url, self.rootfs);
+        Ok(())
+    }
+
+    /// Removes the apt proxy snippet.
+    ///
+    /// This method is idempotent after a successful teardown.
+    pub fn teardown(&mut self) -> Result<()> {
+        if !self.active || self.torn_down {
+            return Ok(());
+        }
+
+        let rm_spec =
+            CommandSpec::new("rm", vec!["-f".to_string(), self.snippet_path().to_string()])
+                .with_privilege(self.privilege);
+        self.executor.execute_checked(&rm_spec)?;
+
+        info!("removed apt proxy configuration from {}", self.rootfs);
+        self.torn_down = true;
+        Ok(())
+    }
+}
+
+impl Drop for RootfsAptProxy {
+    fn drop(&mut self) {
+        if self.active
+            && !self.torn_down
+            && let Err(e) = self.teardown()
+        {
+            tracing::error!("failed to remove apt proxy configuration during cleanup: {:#}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::ExecutionResult;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct RecordingExecutor {
+        calls: Mutex<Vec<CommandSpec>>,
+    }
+
+    impl CommandExecutor for RecordingExecutor {
+        fn execute(&self, spec: &CommandSpec) -> anyhow::Result<ExecutionResult> {
+            self.calls.lock().unwrap().push(spec.clone());
+            Ok(ExecutionResult {
+                status: Some(ExitStatus::from_raw(0)),
+                details: None,
+            })
+        }
+    }
+
+    fn rootfs_with_apt() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("etc/apt/apt.conf.d")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn generate_points_http_downloads_at_proxy() {
+        assert_eq!(
+            generate_apt_proxy_conf("http://127.0.0.1:3142"),
+            "// Generated by rsdebstrap\nAcquire::http::Proxy \"http://127.0.0.1:3142\";\n"
+        );
+    }
+
+    #[test]
+    fn setup_and_teardown_write_and_remove_snippet() {
+        let rootfs = rootfs_with_apt();
+        let rootfs_path = Utf8Path::from_path(rootfs.path()).unwrap();
+        let executor = Arc::new(RecordingExecutor::default());
+        let mut guard = RootfsAptProxy::new(
+            rootfs_path,
+            Some("http://127.0.0.1:3142".to_string()),
+            executor.clone(),
+            Some(PrivilegeMethod::Sudo),
+            false,
+        );
+
+        guard.setup().unwrap();
+        guard.teardown().unwrap();
+        guard.teardown().unwrap();
+
+        let calls = executor.calls.lock().unwrap();
+        let snippet = rootfs_path.join(APT_CONF_SNIPPET).to_string();
+        let commands: Vec<&str> = calls.iter().map(|c| c.command.as_str()).collect();
+        assert_eq!(commands, ["cp", "chmod", "rm"]);
+        assert_eq!(calls[0].args[1], snippet);
+        assert_eq!(calls[2].args, ["-f", snippet.as_str()]);
+        assert!(
+            calls
+                .iter()
+                .all(|c| c.privilege == Some(PrivilegeMethod::Sudo))
+        );
+    }
+
+    #[test]
+    fn setup_skips_rootfs_without_apt() {
+        let rootfs = tempfile::tempdir().unwrap();
+        fs::create_dir(rootfs.path().join("etc")).unwrap();
+        let executor = Arc::new(RecordingExecutor::default());
+        let mut guard = RootfsAptProxy::new(
+            Utf8Path::from_path(rootfs.path()).unwrap(),
+            Some("http://127.0.0.1:3142".to_string()),
+            executor.clone(),
+            None,
+            false,
+        );
+
+        guard.setup().unwrap();
+        drop(guard);
+
+        assert!(executor.calls.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn setup_rejects_symlinked_apt_dir() {
+        let rootfs = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        fs::create_dir_all(outside.path().join("apt.conf.d")).unwrap();
+        fs::create_dir(rootfs.path().join("etc")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), rootfs.path().join("etc/apt")).unwrap();
+        let executor = Arc::new(RecordingExecutor::default());
+        let mut guard = RootfsAptProxy::new(
+            Utf8Path::from_path(rootfs.path()).unwrap(),
+            Some("http://127.0.0.1:3142".to_string()),
+            executor.clone(),
+            None,
+            false,
+        );
+
+        let err = guard.setup().unwrap_err();
+
+        assert!(format!("{err:#}").contains("possible symlink attack"), "{err:#}");
+        assert!(executor.calls.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn setup_rejects_symlinked_snippet() {
+        let rootfs = rootfs_with_apt();
+        let outside = tempfile::NamedTempFile::new().unwrap();
+        std::os::unix::fs::symlink(outside.path(), rootfs.path().join(APT_CONF_SNIPPET)).unwrap();
+        let executor = Arc::new(RecordingExecutor::default());
+        let mut guard = RootfsAptProxy::new(
+            Utf8Path::from_path(rootfs.path()).unwrap(),
+            Some("http://127.0.0.1:3142".to_string()),
+            executor.clone(),
+            None,
+            false,
+        );
+
+        let err = guard.setup().unwrap_err();
+
+        assert!(format!("{err:#}").contains("not a regular file"), "{err:#}");
+        assert!(executor.calls.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn setup_removes_existing_snippet_before_copying() {
+        let rootfs = rootfs_with_apt();
+        fs::write(rootfs.path().join(APT_CONF_SNIPPET), "stale").unwrap();
+        let rootfs_path = Utf8Path::from_path(rootfs.path()).unwrap();
+        let executor = Arc::new(RecordingExecutor::default());
+        let mut guard = RootfsAptProxy::new(
+            rootfs_path,
+            Some("http://127.0.0.1:3142".to_string()),
+            executor.clone(),
+            None,
+            false,
+        );
+
+        guard.setup().unwrap();
+
+        let calls = executor.calls.lock().unwrap();
+        let snippet = rootfs_path.join(APT_CONF_SNIPPET).to_string();
+        assert_eq!(calls[0].command, "rm");
+        assert_eq!(calls[0].args, ["-f", snippet.as_str()]);
+        assert_eq!(calls[1].command, "cp");
+    }
+
+    #[test]
+    fn drop_removes_snippet_left_active() {
+        let rootfs = rootfs_with_apt();
+        let executor = Arc::new(RecordingExecutor::default());
+        let mut guard = RootfsAptProxy::new(
+            Utf8Path::from_path(rootfs.path()).unwrap(),
+            Some("http://127.0.0.1:3142".to_string()),
+            executor.clone(),
+            None,
+            false,
+        );
+
+        guard.setup().unwrap();
+        drop(guard);
+
+        let calls = executor.calls.lock().unwrap();
+        assert_eq!(calls.last().unwrap().command, "rm");
+    }
+}
@@ -1,9 +1,16 @@
 //! Chroot isolation implementation.
+//!
+//! Also runs the `pivot_root` backend, which shares the chroot options and differs only
+//! in the command each context runs (see [`super::pivot_root`]).
 
+use super::mount::RootfsMounts;
+use super::pivot_root::pivot_exec_args;
 use super::{IsolationContext, IsolationProvider};
-use crate::executor::{CommandExecutor, CommandSpec, ExecutionResult};
+use crate::config::{ChrootBind, ChrootIsolation};
+use crate::error::RsdebstrapError;
+use crate::executor::{CommandExecutor, CommandSpec, ExecutionResult, current_exe};
 use crate::privilege::PrivilegeMethod;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use camino::{Utf8Path, Utf8PathBuf};
 use std::sync::Arc;
 
@@ -12,14 +19,47 @@ use std::sync::Arc;
 /// This is the simplest isolation mechanism, using the standard `chroot` command
 /// to change the root directory before executing commands.
 ///
-/// Chroot doesn't require any special setup or teardown operations,
-/// making it a lightweight option for pipeline task execution.
+/// Without `binds`, chroot doesn't require any special setup or teardown
+/// operations, making it a lightweight option for pipeline task execution.
+/// `binds` are mounted on setup and unmounted on teardown, using the task's
+/// privilege method.
 #[derive(Debug, Default, Clone)]
-pub struct ChrootProvider;
+pub struct ChrootProvider {
+    options: ChrootIsolation,
+    privilege: Option<PrivilegeMethod>,
+    /// Run commands through `pivot-exec` rather than `chroot`.
+    pivot_root: bool,
+}
+
+impl ChrootProvider {
+    /// Creates a provider applying the chroot `options` to each context.
+    ///
+    /// `privilege` is the task's privilege method, used for the bind mounts.
+    pub fn new(options: ChrootIsolation, privilege: Option<PrivilegeMethod>) -> Self {
+        Self {
+            options,
+            privilege,
+            pivot_root: false,
+        }
+    }
+
+    /// Creates a provider for the `pivot_root` backend, whose contexts run each command
+    /// in a private mount namespace rooted at the rootfs.
+    pub fn pivot_root(options: ChrootIsolation, privilege: Option<PrivilegeMethod>) -> Self {
+        Self {
+            pivot_root: true,
+            ..Self::new(options, privilege)
+        }
+    }
+}
 
 impl IsolationProvider for ChrootProvider {
     fn name(&self) -> &'static str {
-        "chroot"
+        if self.pivot_root {
+            "pivot_root"
+        } else {
+            "chroot"
+        }
     }
 
     fn setup(
@@ -28,29 +68,176 @@ impl IsolationProvider for ChrootProvider {
         executor: Arc<dyn CommandExecutor>,
         dry_run: bool,
     ) -> Result<Box<dyn IsolationContext>> {
+        let mounts = if self.options.binds.is_empty() {
+            None
+        } else {
+            let entries = self
+                .options
+                .binds
+                .iter()
+                .map(ChrootBind::to_mount_entry)
+                .collect();
+            let mut mounts =
+                RootfsMounts::new(rootfs, entries, executor.clone(), self.privilege, dry_run)
+                    .with_unmount_policy(self.options.unmount_policy.unwrap_or_default());
+            mounts
+                .mount()
+                .context("failed to set up chroot bind mounts")?;
+            Some(mounts)
+        };
+        // Nothing may exist yet in a dry run, so the account is only checked for real.
+        let home = match &self.options.user {
+            Some(user) if !dry_run => lookup_account(rootfs, user)?,
+            _ => None,
+        };
+
         Ok(Box::new(ChrootContext {
             rootfs: rootfs.to_owned(),
             executor,
+            options: self.options.clone(),
+            home,
+            mounts,
+            pivot_root: self.pivot_root,
             dry_run,
             torn_down: false,
         }))
     }
 }
 
+/// Checks that the `user[:group]` spec names accounts in the rootfs's `/etc/passwd` and
+/// `/etc/group`, and returns the user's home directory.
+///
+/// Numeric ids need no entry; a numeric user without one has no known home.
+fn lookup_account(rootfs: &Utf8Path, spec: &str) -> Result<Option<String>> {
+    let (user, group) = match spec.split_once(':') {
+        Some((user, group)) => (user, Some(group)),
+        None => (spec, None),
+    };
+    let numeric = |id: &str| id.bytes().all(|b| b.is_ascii_digit());
+    let read = |name: &str| -> Result<String> {
+        let path = rootfs.join("etc").join(name);
+        // Refuse a symlink so a planted link cannot point the lookup at host files.
+        match path.symlink_metadata() {
+            Ok(meta) if meta.is_file() => {}
+            Ok(_) => {
+                return Err(RsdebstrapError::Isolation(format!(
+                    "{} is not a regular file, refusing to read it",
+                    path
+                ))
+                .into());
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(String::new()),
+            Err(e) => return Err(RsdebstrapError::io(format!("failed to stat {}", path), e).into()),
+        }
+        std::fs::read_to_string(&path)
+            .map_err(|e| RsdebstrapError::io(format!("failed to read {}", path), e).into())
+    };
+
+    let passwd = read("passwd")?;
+    // name:password:uid:gid:gecos:home:shell
+    let entry = passwd
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|f| {
+            f.len() >= 7
+                && if numeric(user) {
+                    f[2] == user
+                } else {
+                    f[0] == user
+                }
+        });
+    let home = match entry {
+        Some(fields) => Some(fields[5].to_string()),
+        None if numeric(user) => None,
+        None => {
+            return Err(RsdebstrapError::Isolation(format!(
+                "chroot user '{}' does not exist in {}/etc/passwd",
+                user, rootfs
+            ))
+            .into());
+        }
+    };
+
+    if let Some(group) = group.filter(|g| !numeric(g)) {
+        let groups = read("group")?;
+        if !groups
+            .lines()
+            .any(|line| line.split(':').next() == Some(group))
+        {
+            return Err(RsdebstrapError::Isolation(format!(
+                "chroot group '{}' does not exist in {}/etc/group",
+                group, rootfs
+            ))
+            .into());
+        }
+    }
+    Ok(home)
+}
+
 /// Active chroot isolation context.
 ///
-/// Holds the state for an active chroot session. For chroot, this is minimal
-/// since chroot doesn't require any persistent state between commands.
+/// Holds the state for an active chroot session: the chroot options applied to
+/// every command and the task's bind mounts, if any.
 pub struct ChrootContext {
     rootfs: Utf8PathBuf,
     executor: Arc<dyn CommandExecutor>,
+    options: ChrootIsolation,
+    home: Option<String>,
+    mounts: Option<RootfsMounts>,
+    pivot_root: bool,
     dry_run: bool,
     torn_down: bool,
 }
 
+impl ChrootContext {
+    /// Builds the host command that runs `command` in this context.
+    fn command_spec(
+        &self,
+        command: &[String],
+        privilege: Option<PrivilegeMethod>,
+    ) -> Result<CommandSpec> {
+        if self.torn_down {
+            return Err(crate::error::RsdebstrapError::Isolation(format!(
+                "cannot execute command: {} context has already been torn down",
+                self.name()
+            ))
+            .into());
+        }
+
+        let mut args: Vec<String> = Vec::with_capacity(command.len() + 3);
+        if self.pivot_root {
+            args.extend(pivot_exec_args(&self.rootfs, self.options.user.as_deref()));
+        } else {
+            if let Some(user) = &self.options.user {
+                args.push(format!("--userspec={}", user));
+            }
+            args.push(self.rootfs.to_string());
+        }
+        // chroot has no working directory or environment options; env applies them
+        // inside the rootfs.
+        let env_args = self.options.env_args(self.home.as_deref());
+        if !env_args.is_empty() {
+            args.push("/usr/bin/env".to_string());
+            args.extend(env_args);
+        }
+        args.extend(command.iter().cloned());
+
+        let program = if self.pivot_root {
+            current_exe()?.into_string()
+        } else {
+            "chroot".to_string()
+        };
+        Ok(CommandSpec::new(program, args).with_privilege(privilege))
+    }
+}
+
 impl IsolationContext for ChrootContext {
     fn name(&self) -> &'static str {
-        "chroot"
+        if self.pivot_root {
+            "pivot_root"
+        } else {
+            "chroot"
+        }
     }
 
     fn rootfs(&self) -> &Utf8Path {
@@ -70,23 +257,45 @@ impl IsolationContext for ChrootContext {
         command: &[String],
         privilege: Option<PrivilegeMethod>,
     ) -> Result<ExecutionResult> {
-        if self.torn_down {
-            return Err(crate::error::RsdebstrapError::Isolation(
-                "cannot execute command: chroot context has already been torn down".to_string(),
-            )
-            .into());
-        }
+        self.executor
+            .execute(&self.command_spec(command, privilege)?)
+    }
 
-        let mut args: Vec<String> = Vec::with_capacity(command.len() + 1);
-        args.push(self.rootfs.to_string());
-        args.extend(command.iter().cloned());
+    fn execute_with_stdin(
+        &self,
+        command: &[String],
+        privilege: Option<PrivilegeMethod>,
+        stdin: &[u8],
+    ) -> Result<ExecutionResult> {
+        self.executor
+            .execute(&self.command_spec(command, privilege)?.with_stdin(stdin))
+    }
+
+    fn hand_over(&self, paths: &[String], privilege: Option<PrivilegeMethod>) -> Result<()> {
+        let Some(user) = &self.options.user else {
+            return Ok(());
+        };
+        if paths.is_empty() {
+            return Ok(());
+        }
 
+        // chown runs inside the chroot so user and group names resolve against the
+        // rootfs's own account database.
+        let mut args = vec![self.rootfs.to_string(), "chown".to_string(), user.clone()];
+        args.extend(paths.iter().cloned());
         let spec = CommandSpec::new("chroot", args).with_privilege(privilege);
-        self.executor.execute(&spec)
+        self.executor.execute_checked(&spec)
     }
 
     fn teardown(&mut self) -> Result<()> {
-        // Chroot doesn't need any cleanup
+        if self.torn_down {
+            return Ok(());
+        }
+        if let Some(mounts) = self.mounts.as_mut() {
+            mounts
+                .unmount()
+                .context("failed to unmount chroot bind mounts")?;
+        }
         self.torn_down = true;
         Ok(())
     }
@@ -2,11 +2,13 @@
 //!
 //! This module provides a "no-op" isolation backend that executes commands
 //! directly on the host filesystem, translating absolute paths to be relative
-//! to the rootfs directory. Used when a task has `isolation: false`.
+//! to the rootfs directory. Used when a task has `isolation: false`; with
+//! `defaults.sandbox` the commands run in a [`Sandbox`].
 
 use super::{IsolationContext, IsolationProvider};
 use crate::executor::{CommandExecutor, CommandSpec, ExecutionResult};
 use crate::privilege::PrivilegeMethod;
+use crate::sandbox::Sandbox;
 use anyhow::Result;
 use camino::{Utf8Path, Utf8PathBuf};
 use std::sync::Arc;
@@ -16,7 +18,16 @@ use std::sync::Arc;
 /// Creates contexts that execute commands directly on the host filesystem,
 /// translating absolute paths to be prefixed with the rootfs directory.
 #[derive(Debug, Default, Clone)]
-pub struct DirectProvider;
+pub struct DirectProvider {
+    sandbox: Option<Sandbox>,
+}
+
+impl DirectProvider {
+    /// Creates a provider whose contexts run their commands in `sandbox`, if given.
+    pub fn new(sandbox: Option<Sandbox>) -> Self {
+        Self { sandbox }
+    }
+}
 
 impl IsolationProvider for DirectProvider {
     fn name(&self) -> &'static str {
@@ -33,6 +44,7 @@ impl IsolationProvider for DirectProvider {
             rootfs: rootfs.to_owned(),
             executor,
             dry_run,
+            sandbox: self.sandbox.clone(),
             torn_down: false,
         }))
     }
@@ -41,43 +53,23 @@ impl IsolationProvider for DirectProvider {
 /// Active direct execution context (no isolation).
 ///
 /// Translates absolute command paths to be relative to the rootfs directory.
-/// For example, `/bin/sh` becomes `<rootfs>/bin/sh`.
+/// For example, `/bin/sh` becomes `<rootfs>/bin/sh`. With a sandbox, the translated
+/// command runs through `rsdebstrap sandbox-exec`.
 pub struct DirectContext {
     rootfs: Utf8PathBuf,
     executor: Arc<dyn CommandExecutor>,
     dry_run: bool,
+    sandbox: Option<Sandbox>,
     torn_down: bool,
 }
 
-impl IsolationContext for DirectContext {
-    fn name(&self) -> &'static str {
-        "direct"
-    }
-
-    fn rootfs(&self) -> &Utf8Path {
-        &self.rootfs
-    }
-
-    fn dry_run(&self) -> bool {
-        self.dry_run
-    }
-
-    fn executor(&self) -> &dyn CommandExecutor {
-        &*self.executor
-    }
-
-    /// Executes a command directly on the host filesystem.
-    ///
-    /// All arguments that start with '/' are translated to rootfs-prefixed paths.
-    /// For example, `/bin/sh` becomes `<rootfs>/bin/sh` and `/tmp/task.sh` becomes
-    /// `<rootfs>/tmp/task.sh`. This matches the current usage pattern where tasks
-    /// pass isolation-relative absolute paths (e.g., shell path, script path) as
-    /// arguments to the isolation context.
-    fn execute(
+impl DirectContext {
+    /// Builds the host command that runs `command` in this context.
+    fn command_spec(
         &self,
         command: &[String],
         privilege: Option<PrivilegeMethod>,
-    ) -> Result<ExecutionResult> {
+    ) -> Result<CommandSpec> {
         if self.torn_down {
             return Err(crate::error::RsdebstrapError::Isolation(
                 "cannot execute command: direct context has already been torn down".to_string(),
@@ -107,10 +99,57 @@ impl IsolationContext for DirectContext {
                 }
             })
             .collect();
+        let translated = match &self.sandbox {
+            Some(sandbox) => sandbox.wrap(&translated)?,
+            None => translated,
+        };
+
+        Ok(CommandSpec::new(translated[0].clone(), translated[1..].to_vec())
+            .with_privilege(privilege))
+    }
+}
+
+impl IsolationContext for DirectContext {
+    fn name(&self) -> &'static str {
+        "direct"
+    }
+
+    fn rootfs(&self) -> &Utf8Path {
+        &self.rootfs
+    }
+
+    fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    fn executor(&self) -> &dyn CommandExecutor {
+        &*self.executor
+    }
+
+    /// Executes a command directly on the host filesystem.
+    ///
+    /// All arguments that start with '/' are translated to rootfs-prefixed paths.
+    /// For example, `/bin/sh` becomes `<rootfs>/bin/sh` and `/tmp/task.sh` becomes
+    /// `<rootfs>/tmp/task.sh`. This matches the current usage pattern where tasks
+    /// pass isolation-relative absolute paths (e.g., shell path, script path) as
+    /// arguments to the isolation context.
+    fn execute(
+        &self,
+        command: &[String],
+        privilege: Option<PrivilegeMethod>,
+    ) -> Result<ExecutionResult> {
+        self.executor
+            .execute(&self.command_spec(command, privilege)?)
+    }
 
-        let spec = CommandSpec::new(translated[0].clone(), translated[1..].to_vec())
-            .with_privilege(privilege);
-        self.executor.execute(&spec)
+    fn execute_with_stdin(
+        &self,
+        command: &[String],
+        privilege: Option<PrivilegeMethod>,
+        stdin: &[u8],
+    ) -> Result<ExecutionResult> {
+        self.executor
+            .execute(&self.command_spec(command, privilege)?.with_stdin(stdin))
     }
 
     fn teardown(&mut self) -> Result<()> {
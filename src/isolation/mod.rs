@@ -33,10 +33,17 @@ use crate::privilege::PrivilegeMethod;
 static DEFAULT_ISOLATION_CONFIG: LazyLock<IsolationConfig> =
     LazyLock::new(IsolationConfig::default);
 
+pub mod apt_proxy;
 pub mod chroot;
 pub mod direct;
 pub mod mount;
+pub mod namespace;
+pub mod network_files;
+pub mod overlay;
+pub mod pivot_root;
 pub mod resolv_conf;
+pub mod services;
+pub mod staging;
 
 pub use chroot::{ChrootContext, ChrootProvider};
 pub use direct::{DirectContext, DirectProvider};
@@ -91,6 +98,27 @@ pub trait IsolationContext: Send {
     /// semantics at its own level.
     fn dry_run(&self) -> bool;
 
+    /// Returns the directory, as seen inside the isolation, where tasks stage their
+    /// scripts and binaries.
+    ///
+    /// The default is `/tmp`; the pipeline wraps contexts in a
+    /// [`StagedContext`](staging::StagedContext) when the profile selects another
+    /// [`Staging`](staging::Staging) mode.
+    fn staging_dir(&self) -> &Utf8Path {
+        Utf8Path::new(staging::TMP_STAGING_DIR)
+    }
+
+    /// Returns the name of a file (or directory) of `kind` (`task`, `recipe`, ...)
+    /// the task stages in [`staging_dir()`](Self::staging_dir), unique among those
+    /// of the same kind.
+    ///
+    /// The default appends a random UUID; inside the pipeline the name is
+    /// `<kind>-<run-id>-<phase>-<number>` instead (see
+    /// [`StagedContext`](staging::StagedContext)), naming the run and the task.
+    fn staging_name(&self, kind: &str) -> String {
+        format!("{}-{}", kind, uuid::Uuid::new_v4())
+    }
+
     /// Executes a command within the isolated environment.
     ///
     /// # Arguments
@@ -105,6 +133,35 @@ pub trait IsolationContext: Send {
         privilege: Option<PrivilegeMethod>,
     ) -> Result<ExecutionResult>;
 
+    /// Executes a command within the isolated environment, writing `stdin` to its
+    /// standard input.
+    ///
+    /// Otherwise behaves like [`execute()`](Self::execute). The default rejects the
+    /// command, for contexts that cannot feed input to it.
+    fn execute_with_stdin(
+        &self,
+        command: &[String],
+        privilege: Option<PrivilegeMethod>,
+        stdin: &[u8],
+    ) -> Result<ExecutionResult> {
+        let _ = (command, privilege, stdin);
+        Err(crate::error::RsdebstrapError::Isolation(format!(
+            "{} isolation does not support stdin",
+            self.name()
+        ))
+        .into())
+    }
+
+    /// Hands files staged in the rootfs over to the user that runs task commands.
+    ///
+    /// Tasks call this after copying their script or binary into the rootfs, so a
+    /// context that runs commands as a non-root user can still read and execute
+    /// them. `paths` are as seen inside the isolation. The default does nothing.
+    fn hand_over(&self, paths: &[String], privilege: Option<PrivilegeMethod>) -> Result<()> {
+        let _ = (paths, privilege);
+        Ok(())
+    }
+
     /// Returns a reference to the underlying command executor.
     ///
     /// This allows tasks to execute commands directly via the executor
@@ -145,6 +202,11 @@ pub enum TaskIsolation {
 }
 
 impl TaskIsolation {
+    /// Returns true if the setting is `Inherit` (the field was not specified).
+    pub fn is_inherit(&self) -> bool {
+        matches!(self, Self::Inherit)
+    }
+
     /// Returns the resolved isolation config.
     ///
     /// Should only be called after [`resolve_in_place()`](Self::resolve_in_place).
@@ -13,9 +13,9 @@ use std::sync::Arc;
 use anyhow::Result;
 use camino::{Utf8Path, Utf8PathBuf};
 use rustix::fs::{self as rfs, CWD, Mode, OFlags};
-use tracing::info;
+use tracing::{info, warn};
 
-use crate::config::MountEntry;
+use crate::config::{MountEntry, UnmountMode, UnmountPolicy};
 use crate::error::RsdebstrapError;
 use crate::executor::CommandExecutor;
 use crate::privilege::PrivilegeMethod;
@@ -109,6 +109,77 @@ pub fn safe_create_mount_point(rootfs: &Utf8Path, target: &Utf8Path) -> Result<U
     Ok(current_path)
 }
 
+/// A filesystem found mounted under a rootfs before rsdebstrap mounted anything,
+/// typically left behind by a crashed previous run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaleMount {
+    /// Absolute mount point on the host.
+    pub mount_point: Utf8PathBuf,
+    /// Mount point inside the rootfs (absolute path).
+    pub target: Utf8PathBuf,
+    /// Filesystem type (e.g. `proc`, `ext4`).
+    pub fs_type: String,
+}
+
+/// Decodes the octal escapes (`\040` for a space, …) the kernel uses in mountinfo fields.
+fn unescape_mountinfo(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\\'
+            && let Some(octal) = field.get(i + 1..i + 4)
+            && let Ok(byte) = u8::from_str_radix(octal, 8)
+        {
+            out.push(byte);
+            i += 4;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Returns the mounts listed in `mountinfo` (the format of `/proc/self/mountinfo`) that
+/// lie strictly below `rootfs`, in mount order.
+///
+/// `rootfs` itself is not reported: it may be a mount the user set up on purpose.
+pub fn parse_stale_mounts(mountinfo: &str, rootfs: &Utf8Path) -> Vec<StaleMount> {
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(' ').collect();
+            let mount_point = Utf8PathBuf::from(unescape_mountinfo(fields.get(4)?));
+            // Optional fields end with a lone "-", followed by the filesystem type.
+            let separator = fields.iter().skip(6).position(|f| *f == "-")? + 6;
+            let fs_type = unescape_mountinfo(fields.get(separator + 1)?);
+            let relative = mount_point.strip_prefix(rootfs).ok()?;
+            if relative.as_str().is_empty() {
+                return None;
+            }
+            Some(StaleMount {
+                target: Utf8Path::new("/").join(relative),
+                mount_point,
+                fs_type,
+            })
+        })
+        .collect()
+}
+
+/// Scans `/proc/self/mountinfo` for mounts below `rootfs`.
+///
+/// A rootfs that does not exist yet (e.g. in dry-run mode) has no stale mounts.
+pub fn find_stale_mounts(rootfs: &Utf8Path) -> Result<Vec<StaleMount>> {
+    // mountinfo lists resolved paths, so compare against the canonical rootfs.
+    let Ok(rootfs) = rootfs.canonicalize_utf8() else {
+        return Ok(Vec::new());
+    };
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo")
+        .map_err(|e| RsdebstrapError::io("failed to read /proc/self/mountinfo".to_string(), e))?;
+    Ok(parse_stale_mounts(&mountinfo, &rootfs))
+}
+
 /// RAII guard for filesystem mounts within a rootfs.
 ///
 /// Mounts are established in order and torn down in reverse order.
@@ -118,6 +189,9 @@ pub fn safe_create_mount_point(rootfs: &Utf8Path, target: &Utf8Path) -> Result<U
 /// with `O_NOFOLLOW` to prevent TOCTOU races. Verified absolute paths are
 /// stored and reused for `umount` commands, avoiding re-traversal of
 /// potentially-tampered paths.
+///
+/// A failed unmount is retried and escalated according to the
+/// [`UnmountPolicy`] set with [`with_unmount_policy()`](Self::with_unmount_policy).
 pub struct RootfsMounts {
     rootfs: Utf8PathBuf,
     entries: Vec<MountEntry>,
@@ -125,6 +199,7 @@ pub struct RootfsMounts {
     mounted_paths: Vec<Option<Utf8PathBuf>>,
     executor: Arc<dyn CommandExecutor>,
     privilege: Option<PrivilegeMethod>,
+    unmount_policy: UnmountPolicy,
     dry_run: bool,
     torn_down: bool,
 }
@@ -147,11 +222,42 @@ impl RootfsMounts {
             mounted_paths,
             executor,
             privilege,
+            unmount_policy: UnmountPolicy::default(),
             dry_run,
             torn_down: false,
         }
     }
 
+    /// Sets how failed unmounts are retried and escalated (default: a single plain attempt).
+    pub fn with_unmount_policy(mut self, policy: UnmountPolicy) -> Self {
+        self.unmount_policy = policy;
+        self
+    }
+
+    /// Creates an instance owning `stale` mounts that are already in place, so that
+    /// [`unmount()`](Self::unmount) removes them (in reverse order, with the unmount
+    /// policy) like mounts this instance made.
+    pub fn adopt_stale(
+        rootfs: &Utf8Path,
+        stale: &[StaleMount],
+        executor: Arc<dyn CommandExecutor>,
+        privilege: Option<PrivilegeMethod>,
+        dry_run: bool,
+    ) -> Self {
+        let entries = stale
+            .iter()
+            .map(|m| MountEntry {
+                // The filesystem type lets pseudo-filesystems use `umount(2)` directly.
+                source: m.fs_type.clone(),
+                target: m.target.clone(),
+                options: vec![],
+            })
+            .collect();
+        let mut mounts = Self::new(rootfs, entries, executor, privilege, dry_run);
+        mounts.mounted_paths = stale.iter().map(|m| Some(m.mount_point.clone())).collect();
+        mounts
+    }
+
     /// Returns the number of currently mounted entries.
     fn mounted_count(&self) -> usize {
         self.mounted_paths.iter().filter(|p| p.is_some()).count()
@@ -196,20 +302,10 @@ impl RootfsMounts {
             };
 
             info!("mounting {} on {}", entry.source, entry.target);
-            let spec = entry.build_mount_spec_with_path(&abs_target, self.privilege);
-            match self.executor.execute(&spec) {
-                Ok(result) if result.success() => {
+            match self.executor.mount(entry, &abs_target, self.privilege) {
+                Ok(()) => {
                     self.mounted_paths[i] = Some(abs_target);
                 }
-                Ok(result) => {
-                    let status = result
-                        .status
-                        .map(|s| s.to_string())
-                        .unwrap_or_else(|| "unknown".to_string());
-                    return Err(
-                        self.cleanup_after_error(RsdebstrapError::execution(&spec, status).into())
-                    );
-                }
                 Err(e) => {
                     return Err(self.cleanup_after_error(e));
                 }
@@ -264,20 +360,12 @@ impl RootfsMounts {
             };
             let entry = &self.entries[i];
             info!("unmounting {}", entry.target);
-            let spec = entry.build_umount_spec_with_path(abs_target, self.privilege);
-            match self.executor.execute(&spec) {
-                Ok(result) if result.success() => {
+            match self.unmount_entry(entry, abs_target) {
+                Ok(()) => {
                     self.mounted_paths[i] = None;
                 }
-                Ok(result) => {
-                    let status = result
-                        .status
-                        .map(|s| s.to_string())
-                        .unwrap_or_else(|| "unknown".to_string());
-                    errors.push(format!("umount {} failed: {}", abs_target, status));
-                }
                 Err(e) => {
-                    errors.push(format!("umount {} failed: {}", abs_target, e));
+                    errors.push(e.context(format!("umount {} failed", abs_target)));
                 }
             }
         }
@@ -285,13 +373,60 @@ impl RootfsMounts {
         if errors.is_empty() {
             Ok(())
         } else {
-            Err(RsdebstrapError::Isolation(format!(
-                "failed to unmount {} filesystem(s): {}",
-                errors.len(),
-                errors.join("; ")
-            ))
-            .into())
+            let message = format!("failed to unmount {} filesystem(s)", errors.len());
+            Err(RsdebstrapError::multiple(message, errors).into())
+        }
+    }
+
+    /// Unmounts one entry, retrying and then escalating per the unmount policy.
+    ///
+    /// Returns the error of the last attempt if every step fails.
+    fn unmount_entry(&self, entry: &MountEntry, abs_target: &Utf8Path) -> Result<()> {
+        let policy = &self.unmount_policy;
+        let mut last_err =
+            match self
+                .executor
+                .unmount(entry, abs_target, UnmountMode::Normal, self.privilege)
+            {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+
+        for attempt in 1..=policy.retries {
+            warn!(
+                "umount {} failed: {:#}; retrying ({}/{})",
+                abs_target, last_err, attempt, policy.retries
+            );
+            if !self.dry_run {
+                std::thread::sleep(policy.retry_delay());
+            }
+            match self
+                .executor
+                .unmount(entry, abs_target, UnmountMode::Normal, self.privilege)
+            {
+                Ok(()) => {
+                    info!("unmounted {} on retry {}", abs_target, attempt);
+                    return Ok(());
+                }
+                Err(e) => last_err = e,
+            }
+        }
+
+        for mode in policy.escalation() {
+            warn!("umount {} failed: {:#}; escalating to a {} unmount", abs_target, last_err, mode);
+            match self
+                .executor
+                .unmount(entry, abs_target, mode, self.privilege)
+            {
+                Ok(()) => {
+                    warn!("unmounted {} with a {} unmount", abs_target, mode);
+                    return Ok(());
+                }
+                Err(e) => last_err = e,
+            }
         }
+
+        Err(last_err)
     }
 }
 
@@ -382,10 +517,12 @@ mod tests {
             if self.fail_on_call == Some(index) || self.fail_umount_on_calls.contains(&index) {
                 Ok(ExecutionResult {
                     status: Some(ExitStatus::from_raw(1 << 8)),
+                    details: None,
                 })
             } else {
                 Ok(ExecutionResult {
                     status: Some(ExitStatus::from_raw(0)),
+                    details: None,
                 })
             }
         }
@@ -554,6 +691,13 @@ mod tests {
             msg
         );
         assert!(msg.contains("1"), "error should contain failure count: {}", msg);
+        match err.downcast_ref::<RsdebstrapError>() {
+            Some(RsdebstrapError::Multiple { errors, .. }) => {
+                assert_eq!(errors.len(), 1);
+                assert!(errors[0].to_string().starts_with("umount "), "{:#}", errors[0]);
+            }
+            other => panic!("Expected RsdebstrapError::Multiple, got: {:?}", other),
+        }
 
         let calls = executor.calls();
         // 2 mounts + 2 umount attempts (both attempted even though first fails)
@@ -691,6 +835,144 @@ mod tests {
         assert!(calls[4][1].contains("sys"), "retry should target /sys only");
     }
 
+    fn retrying_policy(retries: u32, lazy: bool, force: bool) -> UnmountPolicy {
+        UnmountPolicy {
+            retries,
+            retry_delay_ms: Some(0),
+            lazy,
+            force,
+        }
+    }
+
+    #[test]
+    fn unmount_policy_retries_busy_mount() {
+        // 2 mounts succeed (calls 0, 1), /sys is busy once (call 2), its retry succeeds
+        let executor = Arc::new(MockMountExecutor::failing_umount_on(vec![2]));
+        let temp_dir = tempfile::tempdir().unwrap();
+        let rootfs = Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap();
+
+        let mut mounts = RootfsMounts::new(&rootfs, test_entries(), executor.clone(), None, false)
+            .with_unmount_policy(retrying_policy(2, false, false));
+        mounts.mount().unwrap();
+        mounts.unmount().unwrap();
+
+        let calls = executor.calls();
+        // 2 mounts + umount /sys twice + umount /proc
+        assert_eq!(calls.len(), 5);
+        assert_eq!(calls[2], calls[3], "retry should repeat the plain umount");
+        assert_eq!(calls[3], ["umount", rootfs.join("sys").as_str()]);
+        assert_eq!(calls[4], ["umount", rootfs.join("proc").as_str()]);
+    }
+
+    #[test]
+    fn unmount_policy_escalates_to_lazy_then_force() {
+        let executor = Arc::new(MockMountExecutor::failing_umount_on(vec![1, 2, 3]));
+        let temp_dir = tempfile::tempdir().unwrap();
+        let rootfs = Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap();
+        let entries = test_entries()[..1].to_vec();
+
+        let mut mounts = RootfsMounts::new(&rootfs, entries, executor.clone(), None, false)
+            .with_unmount_policy(retrying_policy(1, true, true));
+        mounts.mount().unwrap();
+        mounts.unmount().unwrap();
+
+        let target = rootfs.join("proc");
+        let calls = executor.calls();
+        // mount, umount, retry, umount -l (fails), umount -f (succeeds)
+        assert_eq!(calls.len(), 5);
+        assert_eq!(calls[1], ["umount", target.as_str()]);
+        assert_eq!(calls[2], ["umount", target.as_str()]);
+        assert_eq!(calls[3], ["umount", "-l", target.as_str()]);
+        assert_eq!(calls[4], ["umount", "-f", target.as_str()]);
+        assert!(mounts.torn_down);
+    }
+
+    #[test]
+    fn unmount_policy_failure_keeps_entry_mounted() {
+        let executor = Arc::new(MockMountExecutor::failing_umount_on(vec![1, 2]));
+        let temp_dir = tempfile::tempdir().unwrap();
+        let rootfs = Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).unwrap();
+        let entries = test_entries()[..1].to_vec();
+
+        let mut mounts = RootfsMounts::new(&rootfs, entries, executor.clone(), None, false)
+            .with_unmount_policy(retrying_policy(0, true, false));
+        mounts.mount().unwrap();
+        let err = mounts.unmount().unwrap_err();
+
+        assert!(err.to_string().contains("1 filesystem"), "{err}");
+        let calls = executor.calls();
+        // mount, umount (fails), umount -l (fails); force is not enabled
+        assert_eq!(calls.len(), 3);
+        assert_eq!(calls[2][1], "-l");
+        assert!(mounts.mounted_paths[0].is_some());
+        assert!(!mounts.torn_down);
+    }
+
+    // editorconfig-checker-disable
+    const MOUNTINFO: &str = "\
+22 1 8:1 / / rw,relatime shared:1 - ext4 /dev/sda1 rw
+30 22 0:5 / /srv/rootfs rw,relatime shared:2 - tmpfs tmpfs rw
+31 30 0:21 / /srv/rootfs/proc rw,nosuid,nodev,noexec,relatime shared:3 - proc proc rw
+32 30 0:22 / /srv/rootfs/dev/pts rw,nosuid,noexec,relatime shared:4 master:7 - devpts devpts rw,gid=5
+33 30 8:1 /srv/my\\040data /srv/rootfs/mnt/my\\040data rw,relatime shared:1 - ext4 /dev/sda1 rw
+34 22 0:23 / /srv/rootfs2/proc rw shared:5 - proc proc rw
+";
+    // editorconfig-checker-enable
+
+    #[test]
+    fn parse_stale_mounts_lists_mounts_below_rootfs() {
+        let stale = parse_stale_mounts(MOUNTINFO, Utf8Path::new("/srv/rootfs"));
+
+        let points: Vec<&str> = stale.iter().map(|m| m.mount_point.as_str()).collect();
+        // The rootfs mount itself and a sibling directory sharing its prefix are skipped.
+        assert_eq!(
+            points,
+            [
+                "/srv/rootfs/proc",
+                "/srv/rootfs/dev/pts",
+                "/srv/rootfs/mnt/my data"
+            ]
+        );
+        assert_eq!(stale[1].target, "/dev/pts");
+        // Optional fields (`master:7`) before the separator are skipped.
+        assert_eq!(stale[1].fs_type, "devpts");
+        assert_eq!(stale[2].fs_type, "ext4");
+    }
+
+    #[test]
+    fn parse_stale_mounts_ignores_malformed_lines() {
+        let stale =
+            parse_stale_mounts("garbage\n31 30 0:21 / /srv/rootfs/proc rw\n", "/srv/rootfs".into());
+        assert!(stale.is_empty());
+    }
+
+    #[test]
+    fn find_stale_mounts_skips_missing_rootfs() {
+        let stale = find_stale_mounts(Utf8Path::new("/nonexistent/rsdebstrap/rootfs")).unwrap();
+        assert!(stale.is_empty());
+    }
+
+    #[test]
+    fn adopted_stale_mounts_unmount_in_reverse_order() {
+        let executor = Arc::new(MockMountExecutor::new());
+        let rootfs = Utf8Path::new("/srv/rootfs");
+        let stale = parse_stale_mounts(MOUNTINFO, rootfs);
+
+        let mut mounts = RootfsMounts::adopt_stale(rootfs, &stale, executor.clone(), None, false);
+        mounts.unmount().unwrap();
+        mounts.unmount().unwrap();
+
+        let calls = executor.calls();
+        assert_eq!(
+            calls,
+            [
+                ["umount", "/srv/rootfs/mnt/my data"],
+                ["umount", "/srv/rootfs/dev/pts"],
+                ["umount", "/srv/rootfs/proc"],
+            ]
+        );
+    }
+
     #[test]
     fn mount_rejects_symlink_in_target_path() {
         let executor = Arc::new(MockMountExecutor::new());
@@ -0,0 +1,197 @@
+//! Private mount namespace for the pipeline.
+//!
+//! This module provides [`PipelineMountNamespace`], an RAII guard that runs the
+//! pipeline phase in a new mount namespace (as selected by
+//! `defaults.isolation.mount_namespace`). Every mount in it is private, so the
+//! `proc`/`sys`/`dev` mounts and binds the pipeline sets up never appear in the host's
+//! mount table, and the kernel tears them all down with the namespace if
+//! rsdebstrap dies without unmounting them. Leaving switches back to the host's
+//! namespace with `setns(2)`. The `--overlay` mount and the cleanup of stale mounts
+//! left by earlier runs happen in the host's namespace, before it is entered.
+//!
+//! The namespace belongs to the thread running the pipeline (a `serve` worker gets one
+//! of its own), and the commands and threads it starts inherit it, including those
+//! escalated through `sudo` or `doas`. Creating it needs `CAP_SYS_ADMIN`, so it is only
+//! entered when rsdebstrap runs as root. A build that runs as another user and mounts
+//! through `sudo`, `doas`, `run0` or `pkexec` gets no namespace: each escalated command
+//! is a separate process, so its mounts land in the host's namespace. That is warned
+//! about, or refused when `mount_namespace: true` is set explicitly.
+
+use std::fs::File;
+use std::io;
+use std::os::fd::AsRawFd;
+
+use rustix::mount::MountPropagationFlags;
+use tracing::{info, warn};
+
+use crate::error::RsdebstrapError;
+use crate::privilege::PrivilegeMethod;
+
+/// The calling thread's mount namespace.
+const SELF_MOUNT_NAMESPACE: &str = "/proc/thread-self/ns/mnt";
+
+/// RAII guard keeping the pipeline's thread in a private mount namespace.
+///
+/// The `Drop` implementation returns to the host's namespace on error paths.
+pub struct PipelineMountNamespace {
+    /// The host's mount namespace, to return to; `None` once left.
+    host: Option<File>,
+}
+
+impl PipelineMountNamespace {
+    /// Moves the calling thread into a new mount namespace with every mount private.
+    ///
+    /// Returns `None`, leaving it in the host's namespace, in a dry run, when
+    /// rsdebstrap does not run as root, or when the namespace cannot be created. The
+    /// last two are warned about when the pipeline mounts through `privilege`, unless
+    /// `required` (an explicit `mount_namespace: true`) makes them errors.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the host's namespace cannot be opened, if the mounts of the
+    /// new namespace cannot be made private (rsdebstrap is back in the host's namespace
+    /// by then), or if `required` and no namespace can be entered.
+    pub fn enter(
+        privilege: Option<PrivilegeMethod>,
+        required: bool,
+        dry_run: bool,
+    ) -> Result<Option<Self>, RsdebstrapError> {
+        if dry_run {
+            info!("would run the pipeline in a private mount namespace");
+            return Ok(None);
+        }
+        if !rustix::process::geteuid().is_root() {
+            let without = "the pipeline's mounts are made in the host's mount namespace, \
+                where they are visible and outlive a killed build";
+            match privilege {
+                // The rootless method mounts in user namespaces of its own.
+                Some(PrivilegeMethod::Userns) => {}
+                _ if required => {
+                    return Err(RsdebstrapError::Validation(format!(
+                        "defaults.isolation.mount_namespace: true needs rsdebstrap to run \
+                        as root; through {} {}",
+                        privilege.map_or("no escalation".to_string(), |m| m.to_string()),
+                        without
+                    )));
+                }
+                Some(method) => warn!(
+                    "not running as root: {} through {} (run rsdebstrap as root for a \
+                    private mount namespace, or set defaults.isolation.mount_namespace: \
+                    false)",
+                    without, method
+                ),
+                None => info!("not running as root: {}", without),
+            }
+            return Ok(None);
+        }
+        let host = File::open(SELF_MOUNT_NAMESPACE).map_err(|e| {
+            RsdebstrapError::io(format!("failed to open {}", SELF_MOUNT_NAMESPACE), e)
+        })?;
+        // SAFETY: unshare takes only flags. The filesystem attributes CLONE_NEWNS implies
+        // are copied for this thread, leaving any other threads where they are.
+        if unsafe { libc::unshare(libc::CLONE_NEWNS) } != 0 {
+            let error = io::Error::last_os_error();
+            if required {
+                return Err(RsdebstrapError::io(
+                    "failed to create a private mount namespace",
+                    error,
+                ));
+            }
+            warn!(
+                "could not create a private mount namespace ({}): the pipeline's mounts \
+                are made in the host's mount namespace",
+                error
+            );
+            return Ok(None);
+        }
+        let namespace = Self { host: Some(host) };
+        // Without this, mounts under shared mount points would still propagate to the
+        // host. The guard returns to the host's namespace if it fails.
+        rustix::mount::mount_change(
+            "/",
+            MountPropagationFlags::PRIVATE | MountPropagationFlags::REC,
+        )
+        .map_err(|e| {
+            RsdebstrapError::io("failed to make the pipeline's mounts private", e.into())
+        })?;
+        info!("running the pipeline in a private mount namespace");
+        Ok(Some(namespace))
+    }
+
+    /// Returns to the host's mount namespace. Mounts still in the private namespace
+    /// go away with it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the thread cannot switch back to the host's namespace, as
+    /// when threads it started (which share its filesystem attributes) still run.
+    pub fn leave(&mut self) -> Result<(), RsdebstrapError> {
+        let Some(host) = self.host.take() else {
+            return Ok(());
+        };
+        // SAFETY: `host` is an open namespace file descriptor for the duration of the
+        // call.
+        if unsafe { libc::setns(host.as_raw_fd(), libc::CLONE_NEWNS) } != 0 {
+            return Err(RsdebstrapError::io(
+                "failed to return to the host's mount namespace",
+                io::Error::last_os_error(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for PipelineMountNamespace {
+    fn drop(&mut self) {
+        if let Err(e) = self.leave() {
+            // Harmless: the private namespace sees the same files, and its mounts still
+            // go away when the last thread in it exits.
+            warn!("{}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dry_run_stays_in_the_host_namespace() {
+        let before = std::fs::read_link(SELF_MOUNT_NAMESPACE).unwrap();
+        assert!(
+            PipelineMountNamespace::enter(None, true, true)
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(std::fs::read_link(SELF_MOUNT_NAMESPACE).unwrap(), before);
+    }
+
+    #[test]
+    fn leaving_returns_to_the_host_namespace() {
+        // Only root enters a namespace; either way the thread ends up where it started.
+        let before = std::fs::read_link(SELF_MOUNT_NAMESPACE).unwrap();
+        if let Some(mut namespace) = PipelineMountNamespace::enter(None, false, false).unwrap() {
+            assert_ne!(std::fs::read_link(SELF_MOUNT_NAMESPACE).unwrap(), before);
+            namespace.leave().unwrap();
+        }
+        assert_eq!(std::fs::read_link(SELF_MOUNT_NAMESPACE).unwrap(), before);
+    }
+
+    #[test]
+    fn required_namespace_fails_without_root() {
+        if rustix::process::geteuid().is_root() {
+            return;
+        }
+        let err = PipelineMountNamespace::enter(Some(PrivilegeMethod::Sudo), true, false)
+            .err()
+            .unwrap();
+        assert!(matches!(err, RsdebstrapError::Validation(_)), "{err:?}");
+        assert!(err.to_string().contains("through sudo"), "{err}");
+        // Only warned about when not asked for explicitly.
+        assert!(
+            PipelineMountNamespace::enter(Some(PrivilegeMethod::Sudo), false, false)
+                .unwrap()
+                .is_none()
+        );
+    }
+}
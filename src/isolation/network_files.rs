@@ -0,0 +1,383 @@
+//! Host name-resolution file lifecycle for rootfs isolation.
+//!
+//! This module provides [`RootfsNetworkFiles`], an RAII guard that copies the
+//! host's `/etc/hosts` and `/etc/nsswitch.conf` (as selected by
+//! `defaults.isolation.network_files`) into the rootfs for the prepare and
+//! provision phases. Each file the rootfs already had is backed up first and
+//! restored on teardown, so the final image keeps its own copies.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+use rustix::fs::{self as rfs, CWD, Mode, OFlags};
+use tracing::{info, warn};
+
+use crate::config::NetworkFile;
+use crate::error::RsdebstrapError;
+use crate::executor::{CommandExecutor, CommandSpec};
+use crate::privilege::PrivilegeMethod;
+
+/// Backup suffix appended to an original file during setup.
+const BACKUP_SUFFIX: &str = ".rsdebstrap-orig";
+
+/// RAII guard for host network files within a rootfs.
+///
+/// Backs up each existing file before copying the host's over it, and restores
+/// the originals on teardown. The `Drop` implementation ensures cleanup even on
+/// error paths. A file the host does not have is skipped with a warning.
+///
+/// The `host_root` parameter allows injecting a test-specific directory in
+/// place of the host's `/`.
+pub struct RootfsNetworkFiles {
+    rootfs: Utf8PathBuf,
+    files: Vec<NetworkFile>,
+    host_root: Utf8PathBuf,
+    executor: Arc<dyn CommandExecutor>,
+    privilege: Option<PrivilegeMethod>,
+    dry_run: bool,
+    /// Files copied into the rootfs and not yet restored, in setup order.
+    installed: Vec<NetworkFile>,
+}
+
+impl RootfsNetworkFiles {
+    /// Creates a new `RootfsNetworkFiles` instance.
+    ///
+    /// If `files` is empty, setup and teardown are no-ops.
+    pub fn new(
+        rootfs: &Utf8Path,
+        files: Vec<NetworkFile>,
+        host_root: &Utf8Path,
+        executor: Arc<dyn CommandExecutor>,
+        privilege: Option<PrivilegeMethod>,
+        dry_run: bool,
+    ) -> Self {
+        Self {
+            rootfs: rootfs.to_owned(),
+            files,
+            host_root: host_root.to_owned(),
+            executor,
+            privilege,
+            dry_run,
+            installed: Vec::new(),
+        }
+    }
+
+    /// Path of `file` inside the rootfs.
+    fn rootfs_path(&self, file: NetworkFile) -> Utf8PathBuf {
+        self.rootfs.join(file.path().trim_start_matches('/'))
+    }
+
+    /// Path of `file` on the host.
+    fn host_path(&self, file: NetworkFile) -> Utf8PathBuf {
+        self.host_root.join(file.path().trim_start_matches('/'))
+    }
+
+    /// Path of the backup of the rootfs's original `file`.
+    fn backup_path(&self, file: NetworkFile) -> Utf8PathBuf {
+        let mut path = self.rootfs_path(file).into_string();
+        path.push_str(BACKUP_SUFFIX);
+        Utf8PathBuf::from(path)
+    }
+
+    fn run(&self, command: &str, args: Vec<String>) -> Result<()> {
+        let spec = CommandSpec::new(command, args).with_privilege(self.privilege);
+        self.executor.execute_checked(&spec)?;
+        Ok(())
+    }
+
+    /// Copies the configured host files into the rootfs.
+    ///
+    /// 1. Validates that `<rootfs>/etc` exists and is not a symlink
+    /// 2. For each file the host has: backs up the rootfs's copy, copies the
+    ///    host's in and sets mode 0o644
+    ///
+    /// On a copy failure, the file's backup is rolled back and the files already
+    /// copied are restored.
+    pub fn setup(&mut self) -> Result<()> {
+        if self.files.is_empty() {
+            return Ok(());
+        }
+
+        if self.dry_run {
+            let names = self.files.iter().map(|f| f.path()).collect::<Vec<_>>();
+            info!("would copy host {} into {}", names.join(", "), self.rootfs);
+            return Ok(());
+        }
+
+        // Validate /etc exists and is not a symlink (fd-based, like the resolv.conf guard).
+        let etc = self.rootfs.join("etc");
+        rfs::openat(
+            CWD,
+            etc.as_str(),
+            OFlags::NOFOLLOW | OFlags::DIRECTORY | OFlags::RDONLY | OFlags::CLOEXEC,
+            Mode::empty(),
+        )
+        .map_err(|e| match e {
+            rustix::io::Errno::LOOP | rustix::io::Errno::NOTDIR => {
+                RsdebstrapError::Isolation(format!(
+                    "{} is a symlink or not a directory, refusing to copy network files \
+                    (possible symlink attack)",
+                    etc
+                ))
+            }
+            _ => RsdebstrapError::io(format!("failed to open {}", etc), std::io::Error::from(e)),
+        })?;
+
+        for file in self.files.clone() {
+            if let Err(e) = self.install(file) {
+                if let Err(restore_err) = self.teardown() {
+                    tracing::error!(
+                        "failed to restore network files after setup failure: {:#}",
+                        restore_err
+                    );
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    fn install(&mut self, file: NetworkFile) -> Result<()> {
+        let host_path = self.host_path(file);
+        if !host_path.is_file() {
+            warn!("host has no {}, not copying it into {}", host_path, self.rootfs);
+            return Ok(());
+        }
+
+        let path = self.rootfs_path(file);
+        let backup_path = self.backup_path(file);
+        if backup_path.symlink_metadata().is_ok() {
+            return Err(RsdebstrapError::Isolation(format!(
+                "backup file {} already exists (possible leftover from a previous crash; \
+                please restore or remove it manually)",
+                backup_path
+            ))
+            .into());
+        }
+
+        let had_original = path.symlink_metadata().is_ok();
+        if had_original {
+            self.run("mv", vec![path.to_string(), backup_path.to_string()])?;
+        }
+        if let Err(e) = self.run("cp", vec![host_path.to_string(), path.to_string()]) {
+            if had_original
+                && let Err(rollback_err) =
+                    self.run("mv", vec![backup_path.to_string(), path.to_string()])
+            {
+                tracing::error!(
+                    "failed to roll back {} backup after copy failure: {:#}",
+                    path,
+                    rollback_err
+                );
+            }
+            return Err(e);
+        }
+        self.installed.push(file);
+
+        if let Err(e) = self.run("chmod", vec!["644".to_string(), path.to_string()]) {
+            warn!("failed to set permissions on {}: {}", path, e);
+        }
+        info!("copied host {} into {}", file, self.rootfs);
+        Ok(())
+    }
+
+    /// Removes the copied files, restoring the rootfs's originals.
+    ///
+    /// Files are restored in reverse setup order; on failure, the rest stay
+    /// pending for a later call or the `Drop` backstop. This method is idempotent
+    /// after a successful teardown.
+    pub fn teardown(&mut self) -> Result<()> {
+        while let Some(&file) = self.installed.last() {
+            let path = self.rootfs_path(file);
+            let backup_path = self.backup_path(file);
+            self.run("rm", vec!["-f".to_string(), path.to_string()])?;
+            // try_exists() surfaces stat errors so a failed check does not strand the backup.
+            let have_backup = backup_path.try_exists().map_err(|e| {
+                RsdebstrapError::io(format!("failed to check for backup {}", backup_path), e)
+            })?;
+            if have_backup {
+                self.run("mv", vec![backup_path.to_string(), path.to_string()])?;
+            }
+            self.installed.pop();
+            info!("restored {} in {}", file, self.rootfs);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for RootfsNetworkFiles {
+    fn drop(&mut self) {
+        if !self.installed.is_empty()
+            && let Err(e) = self.teardown()
+        {
+            tracing::error!(
+                "failed to restore network files during cleanup: {:#}. \
+                Manual cleanup may be required: check {}/etc for *{} backups",
+                e,
+                self.rootfs,
+                BACKUP_SUFFIX
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::ExecutionResult;
+    use std::fs;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::{Command, ExitStatus};
+    use std::sync::Mutex;
+
+    /// Runs `mv`, `cp`, `rm` and `chmod` for real and records each call, failing the
+    /// call at `fail_on` (0-based) if set.
+    #[derive(Default)]
+    struct RunningExecutor {
+        calls: Mutex<Vec<Vec<String>>>,
+        fail_on: Option<usize>,
+    }
+
+    impl CommandExecutor for RunningExecutor {
+        fn execute(&self, spec: &CommandSpec) -> anyhow::Result<ExecutionResult> {
+            let mut calls = self.calls.lock().unwrap();
+            let mut call = vec![spec.command.clone()];
+            call.extend(spec.args.iter().cloned());
+            let index = calls.len();
+            calls.push(call);
+            if self.fail_on == Some(index) {
+                return Ok(ExecutionResult {
+                    status: Some(ExitStatus::from_raw(1 << 8)),
+                    details: None,
+                });
+            }
+            let status = Command::new(&spec.command).args(&spec.args).status()?;
+            Ok(ExecutionResult {
+                status: Some(status),
+                details: None,
+            })
+        }
+    }
+
+    struct Fixture {
+        _dir: tempfile::TempDir,
+        rootfs: Utf8PathBuf,
+        host: Utf8PathBuf,
+    }
+
+    fn fixture() -> Fixture {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8Path::from_path(dir.path()).unwrap().to_owned();
+        let rootfs = root.join("rootfs");
+        let host = root.join("host");
+        fs::create_dir_all(rootfs.join("etc")).unwrap();
+        fs::create_dir_all(host.join("etc")).unwrap();
+        fs::write(host.join("etc/hosts"), "127.0.0.1 host\n").unwrap();
+        fs::write(host.join("etc/nsswitch.conf"), "hosts: files dns\n").unwrap();
+        fs::write(rootfs.join("etc/hosts"), "127.0.0.1 localhost\n").unwrap();
+        Fixture {
+            _dir: dir,
+            rootfs,
+            host,
+        }
+    }
+
+    fn guard(f: &Fixture, executor: Arc<RunningExecutor>) -> RootfsNetworkFiles {
+        RootfsNetworkFiles::new(
+            &f.rootfs,
+            vec![NetworkFile::Hosts, NetworkFile::Nsswitch],
+            &f.host,
+            executor,
+            None,
+            false,
+        )
+    }
+
+    #[test]
+    fn setup_copies_and_teardown_restores() {
+        let f = fixture();
+        let mut files = guard(&f, Arc::new(RunningExecutor::default()));
+
+        files.setup().unwrap();
+        assert_eq!(fs::read_to_string(f.rootfs.join("etc/hosts")).unwrap(), "127.0.0.1 host\n");
+        assert_eq!(
+            fs::read_to_string(f.rootfs.join("etc/nsswitch.conf")).unwrap(),
+            "hosts: files dns\n"
+        );
+
+        files.teardown().unwrap();
+        assert_eq!(
+            fs::read_to_string(f.rootfs.join("etc/hosts")).unwrap(),
+            "127.0.0.1 localhost\n"
+        );
+        assert!(!f.rootfs.join("etc/nsswitch.conf").exists());
+        assert!(!f.rootfs.join("etc/hosts.rsdebstrap-orig").exists());
+    }
+
+    #[test]
+    fn setup_skips_files_the_host_lacks() {
+        let f = fixture();
+        fs::remove_file(f.host.join("etc/nsswitch.conf")).unwrap();
+        let mut files = guard(&f, Arc::new(RunningExecutor::default()));
+
+        files.setup().unwrap();
+
+        assert_eq!(files.installed, [NetworkFile::Hosts]);
+        assert!(!f.rootfs.join("etc/nsswitch.conf").exists());
+    }
+
+    #[test]
+    fn copy_failure_restores_earlier_files() {
+        let f = fixture();
+        // Calls: mv hosts, cp hosts, chmod hosts, cp nsswitch (fails).
+        let executor = Arc::new(RunningExecutor {
+            fail_on: Some(3),
+            ..Default::default()
+        });
+        let mut files = guard(&f, executor);
+
+        assert!(files.setup().is_err());
+
+        assert!(files.installed.is_empty());
+        assert_eq!(
+            fs::read_to_string(f.rootfs.join("etc/hosts")).unwrap(),
+            "127.0.0.1 localhost\n"
+        );
+        assert!(!f.rootfs.join("etc/nsswitch.conf").exists());
+    }
+
+    #[test]
+    fn leftover_backup_is_refused() {
+        let f = fixture();
+        fs::write(f.rootfs.join("etc/hosts.rsdebstrap-orig"), "old\n").unwrap();
+        let mut files = guard(&f, Arc::new(RunningExecutor::default()));
+
+        let err = files.setup().unwrap_err();
+
+        assert!(err.to_string().contains("already exists"), "{err:#}");
+        assert_eq!(
+            fs::read_to_string(f.rootfs.join("etc/hosts")).unwrap(),
+            "127.0.0.1 localhost\n"
+        );
+    }
+
+    #[test]
+    fn dry_run_touches_nothing() {
+        let f = fixture();
+        let executor = Arc::new(RunningExecutor::default());
+        let mut files = RootfsNetworkFiles::new(
+            &f.rootfs,
+            vec![NetworkFile::Hosts],
+            &f.host,
+            executor.clone(),
+            None,
+            true,
+        );
+
+        files.setup().unwrap();
+        files.teardown().unwrap();
+
+        assert!(executor.calls.lock().unwrap().is_empty());
+    }
+}
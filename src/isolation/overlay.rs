@@ -0,0 +1,324 @@
+//! Overlay lifecycle for experiment runs (`apply --overlay`).
+//!
+//! This module provides [`RootfsOverlay`], an RAII guard that mounts an overlayfs whose
+//! lower layer is the bootstrapped rootfs, so the pipeline writes only to a scratch upper
+//! layer. The rootfs itself is never modified: deleting the overlay directory discards
+//! the experiment, and the upper layer lists exactly what the tasks changed.
+//!
+//! The overlay lives next to the rootfs, in `<rootfs>.overlay/`:
+//! - `upper/` — the changed and added files (deletions appear as whiteouts)
+//! - `work/` — overlayfs's scratch directory
+//! - `merged/` — the mount point the pipeline runs against
+//!
+//! An existing upper layer is reused, so successive overlay runs build on each other.
+
+use std::os::unix::fs::MetadataExt;
+use std::sync::Arc;
+
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+use tracing::info;
+
+use crate::error::RsdebstrapError;
+use crate::executor::{CommandExecutor, CommandSpec};
+use crate::privilege::PrivilegeMethod;
+
+/// Suffix appended to the rootfs path to name the overlay directory.
+const OVERLAY_SUFFIX: &str = ".overlay";
+
+/// Returns the overlay directory used for `rootfs`: `<rootfs>.overlay`.
+pub fn overlay_dir(rootfs: &Utf8Path) -> Utf8PathBuf {
+    let mut dir = rootfs.as_str().trim_end_matches('/').to_string();
+    dir.push_str(OVERLAY_SUFFIX);
+    Utf8PathBuf::from(dir)
+}
+
+/// RAII guard for an overlayfs mounted over a rootfs.
+///
+/// [`mount()`](Self::mount) creates the layer directories (with the executor's
+/// privilege, so they are root-owned like the rootfs) and mounts the overlay on
+/// [`merged()`](Self::merged); [`unmount()`](Self::unmount) unmounts it and leaves the
+/// layers in place. The `Drop` implementation ensures the unmount even on error paths.
+pub struct RootfsOverlay {
+    lower: Utf8PathBuf,
+    dir: Utf8PathBuf,
+    executor: Arc<dyn CommandExecutor>,
+    privilege: Option<PrivilegeMethod>,
+    dry_run: bool,
+    mounted: bool,
+}
+
+impl RootfsOverlay {
+    /// Creates a new `RootfsOverlay` over `lower`, in [`overlay_dir(lower)`](overlay_dir).
+    ///
+    /// Nothing is mounted until [`mount()`](Self::mount) is called.
+    pub fn new(
+        lower: &Utf8Path,
+        executor: Arc<dyn CommandExecutor>,
+        privilege: Option<PrivilegeMethod>,
+        dry_run: bool,
+    ) -> Self {
+        Self {
+            lower: lower.to_owned(),
+            dir: overlay_dir(lower),
+            executor,
+            privilege,
+            dry_run,
+            mounted: false,
+        }
+    }
+
+    /// Returns the upper layer holding the changes.
+    pub fn upper(&self) -> Utf8PathBuf {
+        self.dir.join("upper")
+    }
+
+    /// Returns overlayfs's work directory.
+    pub fn work(&self) -> Utf8PathBuf {
+        self.dir.join("work")
+    }
+
+    /// Returns the mount point the pipeline runs against.
+    pub fn merged(&self) -> Utf8PathBuf {
+        self.dir.join("merged")
+    }
+
+    /// Returns the `mount -o` options naming the three layers.
+    fn options(&self) -> String {
+        format!("lowerdir={},upperdir={},workdir={}", self.lower, self.upper(), self.work())
+    }
+
+    fn run(&self, command: &str, args: Vec<String>) -> Result<()> {
+        let spec = CommandSpec::new(command, args).with_privilege(self.privilege);
+        self.executor.execute_checked(&spec)?;
+        Ok(())
+    }
+
+    /// Mounts the overlay.
+    ///
+    /// 1. Checks that no layer path contains `,`, `:` or `\` (they would be read as
+    ///    option separators), that the rootfs is a directory, and that `merged` is not
+    ///    still mounted from an interrupted run
+    /// 2. Creates `upper`, `work` and `merged` with `mkdir -p`
+    /// 3. Runs `mount -t overlay -o lowerdir=...,upperdir=...,workdir=... overlay <merged>`
+    pub fn mount(&mut self) -> Result<()> {
+        for path in [&self.lower, &self.dir] {
+            if path.as_str().contains([',', ':', '\\']) {
+                return Err(RsdebstrapError::Validation(format!(
+                    "--overlay cannot be used with {}: overlayfs layer paths must not \
+                    contain ',', ':' or '\\'",
+                    path
+                ))
+                .into());
+            }
+        }
+
+        if self.dry_run {
+            info!("would mount overlay of {} on {}", self.lower, self.merged());
+            return Ok(());
+        }
+
+        if !self.lower.is_dir() {
+            return Err(RsdebstrapError::Validation(format!(
+                "rootfs {} does not exist; the overlay needs a bootstrapped rootfs",
+                self.lower
+            ))
+            .into());
+        }
+        let merged = self.merged();
+        if let (Ok(dir), Ok(mount_point)) = (self.dir.metadata(), merged.metadata())
+            && dir.dev() != mount_point.dev()
+        {
+            return Err(RsdebstrapError::Isolation(format!(
+                "{} is still mounted (possibly from an interrupted run); unmount it first",
+                merged
+            ))
+            .into());
+        }
+
+        self.run(
+            "mkdir",
+            vec![
+                "-p".to_string(),
+                self.upper().to_string(),
+                self.work().to_string(),
+                merged.to_string(),
+            ],
+        )?;
+        self.run(
+            "mount",
+            vec![
+                "-t".to_string(),
+                "overlay".to_string(),
+                "-o".to_string(),
+                self.options(),
+                "overlay".to_string(),
+                merged.to_string(),
+            ],
+        )?;
+        self.mounted = true;
+        info!(
+            "mounted overlay of {} on {} (changes go to {})",
+            self.lower,
+            merged,
+            self.upper()
+        );
+        Ok(())
+    }
+
+    /// Unmounts the overlay, keeping its layers.
+    ///
+    /// This method is idempotent.
+    pub fn unmount(&mut self) -> Result<()> {
+        if !self.mounted {
+            return Ok(());
+        }
+        self.run("umount", vec![self.merged().to_string()])?;
+        self.mounted = false;
+        info!(
+            "unmounted overlay; changes are in {} (remove {} to discard them)",
+            self.upper(),
+            self.dir
+        );
+        Ok(())
+    }
+}
+
+impl Drop for RootfsOverlay {
+    fn drop(&mut self) {
+        if let Err(e) = self.unmount() {
+            tracing::error!(
+                "failed to unmount overlay during cleanup: {:#}. Manual cleanup may be \
+                required: umount {}",
+                e,
+                self.merged()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::ExecutionResult;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+    use std::sync::Mutex;
+
+    /// Records each call and succeeds, failing the call at `fail_on` (0-based) if set.
+    #[derive(Default)]
+    struct RecordingExecutor {
+        calls: Mutex<Vec<Vec<String>>>,
+        fail_on: Option<usize>,
+    }
+
+    impl CommandExecutor for RecordingExecutor {
+        fn execute(&self, spec: &CommandSpec) -> anyhow::Result<ExecutionResult> {
+            let mut calls = self.calls.lock().unwrap();
+            let mut call = vec![spec.command.clone()];
+            call.extend(spec.args.iter().cloned());
+            let code = if self.fail_on == Some(calls.len()) {
+                1
+            } else {
+                0
+            };
+            calls.push(call);
+            Ok(ExecutionResult {
+                status: Some(ExitStatus::from_raw(code << 8)),
+                details: None,
+            })
+        }
+    }
+
+    fn rootfs() -> (tempfile::TempDir, Utf8PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let rootfs = Utf8Path::from_path(dir.path()).unwrap().join("rootfs");
+        std::fs::create_dir(&rootfs).unwrap();
+        (dir, rootfs)
+    }
+
+    #[test]
+    fn overlay_dir_is_next_to_rootfs() {
+        assert_eq!(overlay_dir(Utf8Path::new("/srv/rootfs")), "/srv/rootfs.overlay");
+        assert_eq!(overlay_dir(Utf8Path::new("/srv/rootfs/")), "/srv/rootfs.overlay");
+    }
+
+    #[test]
+    fn mount_and_unmount_overlay() {
+        let (_dir, rootfs) = rootfs();
+        let executor = Arc::new(RecordingExecutor::default());
+        let mut overlay = RootfsOverlay::new(&rootfs, executor.clone(), None, false);
+
+        overlay.mount().unwrap();
+        overlay.unmount().unwrap();
+        overlay.unmount().unwrap();
+
+        let base = overlay_dir(&rootfs);
+        let calls = executor.calls.lock().unwrap();
+        assert_eq!(calls.len(), 3);
+        assert_eq!(
+            calls[0],
+            [
+                "mkdir".to_string(),
+                "-p".to_string(),
+                base.join("upper").to_string(),
+                base.join("work").to_string(),
+                base.join("merged").to_string(),
+            ]
+        );
+        assert_eq!(
+            calls[1],
+            [
+                "mount".to_string(),
+                "-t".to_string(),
+                "overlay".to_string(),
+                "-o".to_string(),
+                format!("lowerdir={},upperdir={}/upper,workdir={}/work", rootfs, base, base),
+                "overlay".to_string(),
+                base.join("merged").to_string(),
+            ]
+        );
+        assert_eq!(calls[2], ["umount".to_string(), base.join("merged").to_string()]);
+    }
+
+    #[test]
+    fn failed_mount_is_not_unmounted() {
+        let (_dir, rootfs) = rootfs();
+        let executor = Arc::new(RecordingExecutor {
+            fail_on: Some(1),
+            ..Default::default()
+        });
+        let mut overlay = RootfsOverlay::new(&rootfs, executor.clone(), None, false);
+
+        assert!(overlay.mount().is_err());
+        drop(overlay);
+
+        assert_eq!(executor.calls.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn mount_rejects_missing_rootfs_and_separator_paths() {
+        let (dir, _) = rootfs();
+        let base = Utf8Path::from_path(dir.path()).unwrap();
+        let executor = Arc::new(RecordingExecutor::default());
+
+        let mut missing = RootfsOverlay::new(&base.join("missing"), executor.clone(), None, false);
+        assert!(missing.mount().is_err());
+        let mut comma = RootfsOverlay::new(&base.join("a,b"), executor.clone(), None, true);
+        assert!(comma.mount().is_err());
+
+        assert!(executor.calls.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn dry_run_mounts_nothing() {
+        let executor = Arc::new(RecordingExecutor::default());
+        let mut overlay =
+            RootfsOverlay::new(Utf8Path::new("/nonexistent/rootfs"), executor.clone(), None, true);
+
+        overlay.mount().unwrap();
+        overlay.unmount().unwrap();
+
+        assert!(executor.calls.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,133 @@
+//! `pivot_root` isolation.
+//!
+//! `isolation: { type: pivot_root }` takes the chroot options, but
+//! [`ChrootContext`](super::ChrootContext) runs each command through the hidden
+//! `rsdebstrap pivot-exec` subcommand instead of `chroot`. Running with the task's
+//! privileges, [`exec`] moves itself into a new mount namespace, makes every mount in it
+//! private, bind-mounts the rootfs onto itself (with the mounts beneath it), makes that
+//! the root with `pivot_root(2)` and detaches the host's root before executing the
+//! command. No path leads back to the host's filesystem, so the classic chroot breakouts
+//! (a nested `chroot` with a directory descriptor kept open, `..` past the root) have
+//! nothing to reach, and whatever the command mounts stays in its namespace and is gone
+//! when it exits.
+//!
+//! With `user`, the command runs as `chroot --userspec=<user> / <command>` with the
+//! rootfs's own `chroot`, so names resolve against its account database.
+
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+use anyhow::Result;
+use camino::Utf8Path;
+use rustix::mount::{MountPropagationFlags, UnmountFlags};
+
+use crate::cli::PivotExecArgs;
+use crate::error::RsdebstrapError;
+
+/// Name of the hidden subcommand that runs a command with the rootfs as its root.
+pub const PIVOT_EXEC: &str = "pivot-exec";
+
+/// Returns the `pivot-exec` arguments (after the executable) that run a command in
+/// `rootfs` as `user`, up to the `--` the command follows.
+pub(crate) fn pivot_exec_args(rootfs: &Utf8Path, user: Option<&str>) -> Vec<String> {
+    let mut args = vec![PIVOT_EXEC.to_string()];
+    if let Some(user) = user {
+        args.push(format!("--userspec={}", user));
+    }
+    args.push(rootfs.to_string());
+    args.push("--".to_string());
+    args
+}
+
+/// Runs the `pivot-exec` subcommand: makes `args.root` the root of a new mount
+/// namespace and executes the command in place of rsdebstrap. Returns only on failure.
+///
+/// # Errors
+///
+/// Returns an error if the namespace cannot be set up or the command cannot be
+/// executed.
+pub fn exec(args: &PivotExecArgs) -> Result<()> {
+    let Some((program, rest)) = args.command.split_first() else {
+        return Err(RsdebstrapError::Validation(format!("{}: no command given", PIVOT_EXEC)).into());
+    };
+    enter(&args.root)?;
+    let mut command = match &args.userspec {
+        Some(user) => {
+            let mut command = Command::new("/usr/sbin/chroot");
+            command
+                .arg(format!("--userspec={}", user))
+                .arg("/")
+                .args(&args.command);
+            command
+        }
+        None => {
+            let mut command = Command::new(program);
+            command.args(rest);
+            command
+        }
+    };
+    let error = command.exec();
+    Err(RsdebstrapError::io(format!("failed to execute {}", program), error).into())
+}
+
+/// Moves the process into a new mount namespace whose root is `root`.
+fn enter(root: &Utf8Path) -> Result<(), RsdebstrapError> {
+    let step = |what: String, result: rustix::io::Result<()>| {
+        result.map_err(|e| {
+            RsdebstrapError::io(format!("pivot_root isolation: failed to {}", what), e.into())
+        })
+    };
+    // SAFETY: unshare takes only flags. The helper has not started any threads, which
+    // CLONE_NEWNS (implying CLONE_FS) requires.
+    if unsafe { libc::unshare(libc::CLONE_NEWNS) } != 0 {
+        return Err(RsdebstrapError::io(
+            "pivot_root isolation: failed to create a mount namespace",
+            io::Error::last_os_error(),
+        ));
+    }
+    // Nothing mounted or unmounted from here on reaches the host's mount table.
+    step(
+        "make the mounts private".to_string(),
+        rustix::mount::mount_change(
+            "/",
+            MountPropagationFlags::PRIVATE | MountPropagationFlags::REC,
+        ),
+    )?;
+    // pivot_root needs the new root to be a mount point.
+    step(
+        format!("bind-mount {}", root),
+        rustix::mount::mount_bind_recursive(root.as_str(), root.as_str()),
+    )?;
+    step(format!("enter {}", root), rustix::process::chdir(root.as_str()))?;
+    // Stacks the old root on top of the new one, so it can be detached without a
+    // directory for it in the rootfs.
+    step(format!("pivot into {}", root), rustix::process::pivot_root(".", "."))?;
+    step(
+        "detach the host's root".to_string(),
+        rustix::mount::unmount(".", UnmountFlags::DETACH),
+    )?;
+    step("enter the new root".to_string(), rustix::process::chdir("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pivot_exec_args_end_before_the_command() {
+        assert_eq!(
+            pivot_exec_args(Utf8Path::new("/srv/rootfs"), Some("builder:staff")),
+            [
+                "pivot-exec",
+                "--userspec=builder:staff",
+                "/srv/rootfs",
+                "--"
+            ]
+        );
+        assert_eq!(
+            pivot_exec_args(Utf8Path::new("/srv/rootfs"), None),
+            ["pivot-exec", "/srv/rootfs", "--"]
+        );
+    }
+}
@@ -4,14 +4,19 @@
 //! the resolv.conf file within a rootfs directory. It backs up the existing
 //! resolv.conf before setup and restores it on teardown, ensuring DNS
 //! resolution works inside chroot environments.
+//!
+//! In `copy` mode a host `/etc/resolv.conf` that only lists the systemd-resolved stub
+//! listener is replaced by the upstream list systemd-resolved maintains, since the stub
+//! address is useless to a resolver that cannot reach the host's resolved.
 
 use std::fs;
+use std::net::{IpAddr, Ipv4Addr};
 use std::sync::Arc;
 
 use anyhow::Result;
 use camino::{Utf8Path, Utf8PathBuf};
 use rustix::fs::{self as rfs, CWD, Mode, OFlags};
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::config::ResolvConfConfig;
 use crate::error::RsdebstrapError;
@@ -21,6 +26,33 @@ use crate::privilege::PrivilegeMethod;
 /// Backup suffix appended to the original resolv.conf during setup.
 const BACKUP_SUFFIX: &str = ".rsdebstrap-orig";
 
+/// Addresses of systemd-resolved's local stub listeners.
+const SYSTEMD_RESOLVED_STUBS: [IpAddr; 2] = [
+    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 53)),
+    IpAddr::V4(Ipv4Addr::new(127, 0, 0, 54)),
+];
+
+/// resolv.conf listing systemd-resolved's upstream nameservers.
+pub const SYSTEMD_RESOLVED_UPSTREAM: &str = "/run/systemd/resolve/resolv.conf";
+
+/// Parses the `nameserver` entries of resolv.conf content, skipping unparsable ones.
+fn nameservers(content: &str) -> impl Iterator<Item = IpAddr> + '_ {
+    content.lines().filter_map(|line| {
+        let mut fields = line.split_whitespace();
+        match (fields.next(), fields.next()) {
+            (Some("nameserver"), Some(addr)) => addr.parse().ok(),
+            _ => None,
+        }
+    })
+}
+
+/// Returns `true` if resolv.conf content lists nameservers and all of them are
+/// systemd-resolved stub listeners.
+fn only_resolved_stubs(content: &str) -> bool {
+    let mut servers = nameservers(content).peekable();
+    servers.peek().is_some() && servers.all(|ns| SYSTEMD_RESOLVED_STUBS.contains(&ns))
+}
+
 /// Generates resolv.conf content from explicit configuration.
 pub(crate) fn generate_resolv_conf(config: &ResolvConfConfig) -> String {
     let mut lines = Vec::new();
@@ -41,11 +73,14 @@ pub(crate) fn generate_resolv_conf(config: &ResolvConfConfig) -> String {
 /// on error paths.
 ///
 /// The `host_resolv_conf` parameter allows injecting a test-specific path
-/// instead of using the real `/etc/resolv.conf`.
+/// instead of using the real `/etc/resolv.conf`; likewise
+/// [`with_host_upstream_resolv_conf`](Self::with_host_upstream_resolv_conf) for
+/// [`SYSTEMD_RESOLVED_UPSTREAM`].
 pub struct RootfsResolvConf {
     rootfs: Utf8PathBuf,
     config: Option<ResolvConfConfig>,
     host_resolv_conf: Utf8PathBuf,
+    host_upstream_resolv_conf: Utf8PathBuf,
     executor: Arc<dyn CommandExecutor>,
     privilege: Option<PrivilegeMethod>,
     active: bool,
@@ -69,6 +104,7 @@ impl RootfsResolvConf {
             rootfs: rootfs.to_owned(),
             config,
             host_resolv_conf: host_resolv_conf.to_owned(),
+            host_upstream_resolv_conf: Utf8PathBuf::from(SYSTEMD_RESOLVED_UPSTREAM),
             executor,
             privilege,
             active: false,
@@ -77,6 +113,48 @@ impl RootfsResolvConf {
         }
     }
 
+    /// Overrides the systemd-resolved upstream resolv.conf consulted in `copy` mode.
+    pub fn with_host_upstream_resolv_conf(mut self, path: &Utf8Path) -> Self {
+        self.host_upstream_resolv_conf = path.to_owned();
+        self
+    }
+
+    /// Picks the host file copied in `copy` mode.
+    ///
+    /// This is the host resolv.conf unless it only lists systemd-resolved stub
+    /// listeners, in which case the upstream resolv.conf is used if it names any other
+    /// nameserver. Unreadable files fall back to the host resolv.conf, so `cp` reports
+    /// the error.
+    fn copy_source(&self) -> &Utf8Path {
+        let host = &self.host_resolv_conf;
+        let Ok(content) = fs::read_to_string(host) else {
+            return host;
+        };
+        if !only_resolved_stubs(&content) {
+            return host;
+        }
+
+        let upstream = &self.host_upstream_resolv_conf;
+        let usable = fs::read_to_string(upstream).is_ok_and(|content| {
+            nameservers(&content).any(|ns| !SYSTEMD_RESOLVED_STUBS.contains(&ns))
+        });
+        if usable {
+            warn!(
+                "{} only points at the systemd-resolved stub; copying {} instead",
+                host, upstream
+            );
+            upstream
+        } else {
+            warn!(
+                "{} only points at the systemd-resolved stub and {} lists no upstream \
+                nameservers; DNS inside the rootfs may not work (set resolv_conf.name_servers \
+                instead of copy)",
+                host, upstream
+            );
+            host
+        }
+    }
+
     /// Path to the rootfs resolv.conf.
     fn resolv_conf_path(&self) -> Utf8PathBuf {
         self.rootfs.join("etc/resolv.conf")
@@ -97,7 +175,8 @@ impl RootfsResolvConf {
     /// Sets up resolv.conf in the rootfs.
     ///
     /// 1. Validates that `<rootfs>/etc` exists and is not a symlink
-    /// 2. Determines content (copy from host or generate)
+    /// 2. Determines content (copy from host, bypassing a systemd-resolved stub, or
+    ///    generate)
     /// 3. Backs up existing resolv.conf
     /// 4. Writes new resolv.conf with mode 0o644
     ///
@@ -161,7 +240,7 @@ impl RootfsResolvConf {
         let write_result = if config.copy {
             let spec = CommandSpec::new(
                 "cp",
-                vec![self.host_resolv_conf.to_string(), resolv_path.to_string()],
+                vec![self.copy_source().to_string(), resolv_path.to_string()],
             )
             .with_privilege(self.privilege);
             self.executor.execute_checked(&spec)
@@ -325,10 +404,12 @@ mod tests {
             if self.fail_on_call == Some(index) {
                 Ok(ExecutionResult {
                     status: Some(ExitStatus::from_raw(1 << 8)),
+                    details: None,
                 })
             } else {
                 Ok(ExecutionResult {
                     status: Some(ExitStatus::from_raw(0)),
+                    details: None,
                 })
             }
         }
@@ -471,6 +552,67 @@ mod tests {
         assert_eq!(calls[1].args[2], rootfs.join("etc/resolv.conf").as_str());
     }
 
+    /// Runs copy-mode setup against a host resolv.conf and optional upstream
+    /// resolv.conf, returning the `cp` source and the expected paths.
+    fn copy_mode_source(host: &str, upstream: Option<&str>) -> (String, Utf8PathBuf, Utf8PathBuf) {
+        let temp = tempfile::tempdir().unwrap();
+        let rootfs = create_rootfs_with_etc(temp.path());
+        let host_path = rootfs.join("host_resolv.conf");
+        fs::write(&host_path, host).unwrap();
+        let upstream_path = rootfs.join("upstream_resolv.conf");
+        if let Some(upstream) = upstream {
+            fs::write(&upstream_path, upstream).unwrap();
+        }
+
+        let config = ResolvConfConfig {
+            copy: true,
+            name_servers: vec![],
+            search: vec![],
+        };
+        let executor = mock_executor();
+        let mut rc =
+            RootfsResolvConf::new(&rootfs, Some(config), &host_path, executor.clone(), None, false)
+                .with_host_upstream_resolv_conf(&upstream_path);
+        rc.setup().unwrap();
+
+        let calls = executor.calls();
+        assert_eq!(calls[0].args[0], "cp");
+        (calls[0].args[1].clone(), host_path, upstream_path)
+    }
+
+    #[test]
+    fn only_resolved_stubs_detects_stub_listeners() {
+        assert!(only_resolved_stubs("nameserver 127.0.0.53\noptions edns0 trust-ad\n"));
+        assert!(only_resolved_stubs("nameserver 127.0.0.53\nnameserver 127.0.0.54\n"));
+        assert!(!only_resolved_stubs("nameserver 127.0.0.53\nnameserver 192.0.2.1\n"));
+        assert!(!only_resolved_stubs("nameserver 127.0.0.1\n"));
+        assert!(!only_resolved_stubs("# no nameservers\n"));
+    }
+
+    #[test]
+    fn setup_copy_mode_replaces_resolved_stub_with_upstream() {
+        let (source, _, upstream) =
+            copy_mode_source("nameserver 127.0.0.53\n", Some("nameserver 192.0.2.1\n"));
+        assert_eq!(source, upstream.as_str());
+    }
+
+    #[test]
+    fn setup_copy_mode_keeps_stub_without_usable_upstream() {
+        let (source, host, _) = copy_mode_source("nameserver 127.0.0.53\n", None);
+        assert_eq!(source, host.as_str());
+
+        let (source, host, _) =
+            copy_mode_source("nameserver 127.0.0.53\n", Some("nameserver 127.0.0.53\n"));
+        assert_eq!(source, host.as_str());
+    }
+
+    #[test]
+    fn setup_copy_mode_keeps_regular_host_resolv_conf() {
+        let (source, host, _) =
+            copy_mode_source("nameserver 192.0.2.53\n", Some("nameserver 192.0.2.1\n"));
+        assert_eq!(source, host.as_str());
+    }
+
     #[test]
     fn setup_generate_mode_issues_correct_commands() {
         let temp = tempfile::tempdir().unwrap();
@@ -0,0 +1,485 @@
+//! Service start blocking lifecycle for rootfs isolation.
+//!
+//! This module provides [`RootfsServiceBlock`], an RAII guard that keeps package
+//! maintainer scripts from starting daemons while the prepare and provision phases
+//! run (as selected by `defaults.isolation.block_services`). It installs a
+//! `/usr/sbin/policy-rc.d` that denies every action and diverts
+//! `start-stop-daemon` to a no-op, and undoes both before the assemble phase so the
+//! final image starts its services normally.
+
+use std::fs;
+use std::sync::Arc;
+
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+use rustix::fs::{self as rfs, CWD, Mode, OFlags};
+use tracing::info;
+
+use crate::error::RsdebstrapError;
+use crate::executor::{CommandExecutor, CommandSpec};
+use crate::privilege::PrivilegeMethod;
+
+/// `policy-rc.d` inside the rootfs, relative to its root.
+const POLICY_RC_D: &str = "usr/sbin/policy-rc.d";
+
+/// `policy-rc.d` that denies every `invoke-rc.d` action (exit status 101).
+const POLICY_RC_D_SCRIPT: &str = "#!/bin/sh\n# Generated by rsdebstrap\nexit 101\n";
+
+/// `dpkg-divert` inside the rootfs, relative to its root; without it nothing is diverted.
+const DPKG_DIVERT: &str = "usr/bin/dpkg-divert";
+
+/// Locations of `start-stop-daemon` inside the rootfs, in the order they are tried.
+const START_STOP_DAEMON_PATHS: [&str; 2] =
+    ["/usr/sbin/start-stop-daemon", "/sbin/start-stop-daemon"];
+
+/// Replacement `start-stop-daemon` that starts nothing, as debootstrap installs.
+const FAKE_START_STOP_DAEMON: &str = "#!/bin/sh\n# Generated by rsdebstrap\n\
+    echo \"Warning: Fake start-stop-daemon called, doing nothing\" >&2\nexit 0\n";
+
+/// Backup suffix appended to a `policy-rc.d` the rootfs already had.
+const BACKUP_SUFFIX: &str = ".rsdebstrap-orig";
+
+/// RAII guard blocking service starts within a rootfs.
+///
+/// Installs `/usr/sbin/policy-rc.d` (backing up an existing one) and, when the rootfs
+/// has `dpkg-divert` and `start-stop-daemon`, diverts the latter with
+/// `dpkg-divert --local --rename` inside a chroot and puts a no-op in its place.
+/// Teardown reverses both; the `Drop` implementation ensures cleanup even on error
+/// paths. A rootfs without `/usr/sbin` is left untouched.
+pub struct RootfsServiceBlock {
+    rootfs: Utf8PathBuf,
+    enabled: bool,
+    executor: Arc<dyn CommandExecutor>,
+    privilege: Option<PrivilegeMethod>,
+    dry_run: bool,
+    /// Whether our `policy-rc.d` is in place and not yet removed.
+    policy_installed: bool,
+    /// Whether the rootfs's own `policy-rc.d` was moved to its backup.
+    policy_backed_up: bool,
+    /// `start-stop-daemon` path (inside the rootfs) diverted and not yet restored.
+    diverted: Option<&'static str>,
+}
+
+impl RootfsServiceBlock {
+    /// Creates a new `RootfsServiceBlock` instance.
+    ///
+    /// If `enabled` is false, setup and teardown are no-ops.
+    pub fn new(
+        rootfs: &Utf8Path,
+        enabled: bool,
+        executor: Arc<dyn CommandExecutor>,
+        privilege: Option<PrivilegeMethod>,
+        dry_run: bool,
+    ) -> Self {
+        Self {
+            rootfs: rootfs.to_owned(),
+            enabled,
+            executor,
+            privilege,
+            dry_run,
+            policy_installed: false,
+            policy_backed_up: false,
+            diverted: None,
+        }
+    }
+
+    /// Path of `path` (relative or absolute inside the rootfs) on the host.
+    fn host_path(&self, path: &str) -> Utf8PathBuf {
+        self.rootfs.join(path.trim_start_matches('/'))
+    }
+
+    /// Path of the backup of the rootfs's original `policy-rc.d`.
+    fn backup_path(&self) -> Utf8PathBuf {
+        let mut path = self.host_path(POLICY_RC_D).into_string();
+        path.push_str(BACKUP_SUFFIX);
+        Utf8PathBuf::from(path)
+    }
+
+    fn run(&self, command: &str, args: Vec<String>) -> Result<()> {
+        let spec = CommandSpec::new(command, args).with_privilege(self.privilege);
+        self.executor.execute_checked(&spec)?;
+        Ok(())
+    }
+
+    /// Runs `dpkg-divert --local --rename <action> <path>` inside the rootfs.
+    fn dpkg_divert(&self, action: &str, path: &str) -> Result<()> {
+        let args = [
+            self.rootfs.as_str(),
+            "dpkg-divert",
+            "--local",
+            "--rename",
+            action,
+            path,
+        ];
+        self.run("chroot", args.iter().map(|a| a.to_string()).collect())
+    }
+
+    /// Writes `content` to `path` in the rootfs with mode 0o755, through a host
+    /// temporary file copied with the executor's privilege.
+    fn write_executable(&self, path: &Utf8Path, content: &str, label: &str) -> Result<()> {
+        let temp = tempfile::NamedTempFile::new().map_err(|e| {
+            RsdebstrapError::io(format!("failed to create temporary file for {}", label), e)
+        })?;
+        fs::write(temp.path(), content).map_err(|e| {
+            RsdebstrapError::io(
+                format!("failed to write temporary {}: {}", label, temp.path().display()),
+                e,
+            )
+        })?;
+        let temp_path = temp.path().to_string_lossy().to_string();
+        self.run("cp", vec![temp_path, path.to_string()])?;
+        self.run("chmod", vec!["755".to_string(), path.to_string()])
+    }
+
+    /// Returns whether `dir` (relative to the rootfs) is a real directory, opening
+    /// each component without following symlinks.
+    ///
+    /// A missing directory is `false`; a symlink or non-directory component is an
+    /// error only when `strict`, since `/sbin` is a symlink in merged-`/usr` systems.
+    fn is_real_dir(&self, dir: &str, strict: bool) -> Result<bool> {
+        let flags = OFlags::NOFOLLOW | OFlags::DIRECTORY | OFlags::RDONLY | OFlags::CLOEXEC;
+        let mut fd = rfs::openat(CWD, self.rootfs.as_str(), flags, Mode::empty()).map_err(|e| {
+            RsdebstrapError::io(format!("failed to open {}", self.rootfs), e.into())
+        })?;
+        for component in dir.split('/') {
+            fd = match rfs::openat(&fd, component, flags, Mode::empty()) {
+                Ok(fd) => fd,
+                Err(rustix::io::Errno::NOENT) => return Ok(false),
+                Err(rustix::io::Errno::LOOP | rustix::io::Errno::NOTDIR) if !strict => {
+                    return Ok(false);
+                }
+                Err(rustix::io::Errno::LOOP | rustix::io::Errno::NOTDIR) => {
+                    return Err(RsdebstrapError::Isolation(format!(
+                        "{}/{} has a symlink or non-directory component, refusing to block \
+                        services (possible symlink attack)",
+                        self.rootfs, dir
+                    ))
+                    .into());
+                }
+                Err(e) => {
+                    return Err(RsdebstrapError::io(
+                        format!("failed to open {}/{}", self.rootfs, dir),
+                        e.into(),
+                    )
+                    .into());
+                }
+            };
+        }
+        Ok(true)
+    }
+
+    /// Installs `policy-rc.d` and diverts `start-stop-daemon`.
+    ///
+    /// 1. Validates that `<rootfs>/usr/sbin` is a real directory (skipping the rootfs
+    ///    if it has none)
+    /// 2. Backs up an existing `policy-rc.d` and writes one that exits 101
+    /// 3. If the rootfs has `dpkg-divert` and a `start-stop-daemon`, diverts it and
+    ///    writes a no-op in its place
+    ///
+    /// On failure, whatever was already changed is undone.
+    pub fn setup(&mut self) -> Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        if self.dry_run {
+            info!("would block service starts in {}", self.rootfs);
+            return Ok(());
+        }
+
+        if !self.is_real_dir("usr/sbin", true)? {
+            info!("{} has no /usr/sbin, not blocking service starts", self.rootfs);
+            return Ok(());
+        }
+
+        if let Err(e) = self.install() {
+            if let Err(restore_err) = self.teardown() {
+                tracing::error!(
+                    "failed to unblock services after setup failure: {:#}",
+                    restore_err
+                );
+            }
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn install(&mut self) -> Result<()> {
+        let policy = self.host_path(POLICY_RC_D);
+        let backup = self.backup_path();
+        if backup.symlink_metadata().is_ok() {
+            return Err(RsdebstrapError::Isolation(format!(
+                "backup file {} already exists (possible leftover from a previous crash; \
+                please restore or remove it manually)",
+                backup
+            ))
+            .into());
+        }
+        if policy.symlink_metadata().is_ok() {
+            self.run("mv", vec![policy.to_string(), backup.to_string()])?;
+            self.policy_backed_up = true;
+        }
+        self.policy_installed = true;
+        self.write_executable(&policy, POLICY_RC_D_SCRIPT, "policy-rc.d")?;
+        info!("installed policy-rc.d denying service starts in {}", self.rootfs);
+
+        let has_dpkg_divert = self.is_real_dir("usr/bin", false)?
+            && self
+                .host_path(DPKG_DIVERT)
+                .symlink_metadata()
+                .is_ok_and(|m| m.is_file());
+        if !has_dpkg_divert {
+            info!("{} has no dpkg-divert, not diverting start-stop-daemon", self.rootfs);
+            return Ok(());
+        }
+        let mut found = None;
+        for path in START_STOP_DAEMON_PATHS {
+            let dir = path.rsplit_once('/').map_or("", |(dir, _)| dir);
+            if self.is_real_dir(dir.trim_start_matches('/'), false)?
+                && self
+                    .host_path(path)
+                    .symlink_metadata()
+                    .is_ok_and(|m| m.is_file())
+            {
+                found = Some(path);
+                break;
+            }
+        }
+        let Some(path) = found else {
+            info!("{} has no start-stop-daemon, not diverting it", self.rootfs);
+            return Ok(());
+        };
+
+        self.dpkg_divert("--add", path)?;
+        self.diverted = Some(path);
+        self.write_executable(&self.host_path(path), FAKE_START_STOP_DAEMON, "start-stop-daemon")?;
+        info!("diverted {} in {}", path, self.rootfs);
+        Ok(())
+    }
+
+    /// Restores `start-stop-daemon` and removes `policy-rc.d`, restoring the
+    /// rootfs's original if it had one.
+    ///
+    /// On failure, the remaining steps stay pending for a later call or the `Drop`
+    /// backstop. This method is idempotent after a successful teardown.
+    pub fn teardown(&mut self) -> Result<()> {
+        if let Some(path) = self.diverted {
+            self.run("rm", vec!["-f".to_string(), self.host_path(path).to_string()])?;
+            self.dpkg_divert("--remove", path)?;
+            self.diverted = None;
+            info!("restored {} in {}", path, self.rootfs);
+        }
+        if self.policy_installed {
+            self.run("rm", vec!["-f".to_string(), self.host_path(POLICY_RC_D).to_string()])?;
+            self.policy_installed = false;
+        }
+        if self.policy_backed_up {
+            let policy = self.host_path(POLICY_RC_D);
+            self.run("mv", vec![self.backup_path().to_string(), policy.to_string()])?;
+            self.policy_backed_up = false;
+            info!("restored the original policy-rc.d in {}", self.rootfs);
+        }
+        Ok(())
+    }
+
+    /// Returns whether anything is still left to undo.
+    fn is_active(&self) -> bool {
+        self.policy_installed || self.policy_backed_up || self.diverted.is_some()
+    }
+}
+
+impl Drop for RootfsServiceBlock {
+    fn drop(&mut self) {
+        if self.is_active()
+            && let Err(e) = self.teardown()
+        {
+            tracing::error!(
+                "failed to unblock services during cleanup: {:#}. Manual cleanup may be \
+                required: check {} and `dpkg-divert --list` in the rootfs",
+                e,
+                self.host_path(POLICY_RC_D)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::ExecutionResult;
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::{Command, ExitStatus};
+    use std::sync::Mutex;
+
+    /// Runs `mv`, `cp`, `rm` and `chmod` for real, emulates `chroot <rootfs>
+    /// dpkg-divert --rename` with a rename, and records each call, failing the call at
+    /// `fail_on` (0-based) if set.
+    #[derive(Default)]
+    struct RunningExecutor {
+        calls: Mutex<Vec<Vec<String>>>,
+        fail_on: Option<usize>,
+    }
+
+    impl CommandExecutor for RunningExecutor {
+        fn execute(&self, spec: &CommandSpec) -> anyhow::Result<ExecutionResult> {
+            let mut calls = self.calls.lock().unwrap();
+            let mut call = vec![spec.command.clone()];
+            call.extend(spec.args.iter().cloned());
+            let index = calls.len();
+            calls.push(call);
+            if self.fail_on == Some(index) {
+                return Ok(ExecutionResult {
+                    status: Some(ExitStatus::from_raw(1 << 8)),
+                    details: None,
+                });
+            }
+            if spec.command == "chroot" {
+                let path = Utf8Path::new(&spec.args[0]).join(spec.args[5].trim_start_matches('/'));
+                let distrib = format!("{}.distrib", path);
+                match spec.args[4].as_str() {
+                    "--add" => fs::rename(&path, &distrib)?,
+                    _ => fs::rename(&distrib, &path)?,
+                }
+                return Ok(ExecutionResult {
+                    status: Some(ExitStatus::from_raw(0)),
+                    details: None,
+                });
+            }
+            let status = Command::new(&spec.command).args(&spec.args).status()?;
+            Ok(ExecutionResult {
+                status: Some(status),
+                details: None,
+            })
+        }
+    }
+
+    /// Creates a rootfs with `/usr/sbin`, `dpkg-divert` and `start-stop-daemon`.
+    fn rootfs() -> (tempfile::TempDir, Utf8PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let rootfs = Utf8Path::from_path(dir.path()).unwrap().to_owned();
+        fs::create_dir_all(rootfs.join("usr/sbin")).unwrap();
+        fs::create_dir_all(rootfs.join("usr/bin")).unwrap();
+        fs::write(rootfs.join(DPKG_DIVERT), "").unwrap();
+        fs::write(rootfs.join("usr/sbin/start-stop-daemon"), "real\n").unwrap();
+        (dir, rootfs)
+    }
+
+    fn guard(rootfs: &Utf8Path, executor: Arc<RunningExecutor>) -> RootfsServiceBlock {
+        RootfsServiceBlock::new(rootfs, true, executor, None, false)
+    }
+
+    #[test]
+    fn setup_blocks_and_teardown_restores() {
+        let (_dir, rootfs) = rootfs();
+        let executor = Arc::new(RunningExecutor::default());
+        let mut block = guard(&rootfs, executor.clone());
+
+        block.setup().unwrap();
+        let policy = rootfs.join(POLICY_RC_D);
+        assert_eq!(fs::read_to_string(&policy).unwrap(), POLICY_RC_D_SCRIPT);
+        assert_eq!(fs::metadata(&policy).unwrap().permissions().mode() & 0o777, 0o755);
+        let daemon = rootfs.join("usr/sbin/start-stop-daemon");
+        assert_eq!(fs::read_to_string(&daemon).unwrap(), FAKE_START_STOP_DAEMON);
+        let divert = executor.calls.lock().unwrap()[2].clone();
+        assert_eq!(
+            divert[..6],
+            [
+                "chroot",
+                rootfs.as_str(),
+                "dpkg-divert",
+                "--local",
+                "--rename",
+                "--add"
+            ]
+        );
+
+        block.teardown().unwrap();
+        block.teardown().unwrap();
+        assert!(!policy.exists());
+        assert_eq!(fs::read_to_string(&daemon).unwrap(), "real\n");
+        assert!(!rootfs.join("usr/sbin/start-stop-daemon.distrib").exists());
+    }
+
+    #[test]
+    fn existing_policy_is_backed_up_and_restored() {
+        let (_dir, rootfs) = rootfs();
+        fs::write(rootfs.join(POLICY_RC_D), "#!/bin/sh\nexit 0\n").unwrap();
+        let mut block = guard(&rootfs, Arc::new(RunningExecutor::default()));
+
+        block.setup().unwrap();
+        assert_eq!(fs::read_to_string(rootfs.join(POLICY_RC_D)).unwrap(), POLICY_RC_D_SCRIPT);
+        drop(block);
+
+        assert_eq!(fs::read_to_string(rootfs.join(POLICY_RC_D)).unwrap(), "#!/bin/sh\nexit 0\n");
+        assert!(!rootfs.join("usr/sbin/policy-rc.d.rsdebstrap-orig").exists());
+    }
+
+    #[test]
+    fn setup_skips_divert_without_dpkg() {
+        let (_dir, rootfs) = rootfs();
+        fs::remove_file(rootfs.join(DPKG_DIVERT)).unwrap();
+        let executor = Arc::new(RunningExecutor::default());
+        let mut block = guard(&rootfs, executor.clone());
+
+        block.setup().unwrap();
+
+        assert!(rootfs.join(POLICY_RC_D).exists());
+        assert!(
+            executor
+                .calls
+                .lock()
+                .unwrap()
+                .iter()
+                .all(|c| c[0] != "chroot")
+        );
+        assert_eq!(
+            fs::read_to_string(rootfs.join("usr/sbin/start-stop-daemon")).unwrap(),
+            "real\n"
+        );
+    }
+
+    #[test]
+    fn divert_failure_removes_policy() {
+        let (_dir, rootfs) = rootfs();
+        let executor = Arc::new(RunningExecutor {
+            fail_on: Some(2),
+            ..Default::default()
+        });
+        let mut block = guard(&rootfs, executor);
+
+        assert!(block.setup().is_err());
+        assert!(!rootfs.join(POLICY_RC_D).exists());
+        assert!(!block.is_active());
+    }
+
+    #[test]
+    fn setup_rejects_symlinked_usr_sbin() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let rootfs = Utf8Path::from_path(dir.path()).unwrap();
+        fs::create_dir(rootfs.join("usr")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), rootfs.join("usr/sbin")).unwrap();
+        let executor = Arc::new(RunningExecutor::default());
+        let mut block = guard(rootfs, executor.clone());
+
+        let err = block.setup().unwrap_err();
+
+        assert!(format!("{err:#}").contains("possible symlink attack"), "{err:#}");
+        assert!(executor.calls.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn disabled_and_dry_run_do_nothing() {
+        let (_dir, rootfs) = rootfs();
+        let executor = Arc::new(RunningExecutor::default());
+        let mut disabled = RootfsServiceBlock::new(&rootfs, false, executor.clone(), None, false);
+        let mut dry_run = RootfsServiceBlock::new(&rootfs, true, executor.clone(), None, true);
+
+        disabled.setup().unwrap();
+        dry_run.setup().unwrap();
+
+        assert!(executor.calls.lock().unwrap().is_empty());
+        assert!(!rootfs.join(POLICY_RC_D).exists());
+    }
+}
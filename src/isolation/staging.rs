@@ -0,0 +1,409 @@
+//! Staging area for task payloads inside the rootfs.
+//!
+//! Shell scripts, mitamae binaries and recipes are copied into the rootfs before a
+//! task runs. By default they go straight into `/tmp`, next to whatever the image
+//! itself keeps there. [`Staging`] selects a dedicated place instead: a per-run
+//! directory that only rsdebstrap can list, or a tmpfs mounted for the pipeline.
+//!
+//! [`RootfsStaging`] is the RAII guard that creates (and removes) the per-run
+//! directory, and [`StagedContext`] hands the chosen directory to each task through
+//! [`IsolationContext::staging_dir()`].
+
+use std::fs;
+use std::os::fd::OwnedFd;
+
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+use rustix::fs::{self as rfs, CWD, Mode, OFlags};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use super::IsolationContext;
+use crate::config::MountEntry;
+use crate::error::RsdebstrapError;
+use crate::executor::{CommandExecutor, ExecutionResult};
+use crate::privilege::PrivilegeMethod;
+use crate::run_dir::RunId;
+
+/// Staging directory used when `staging` is `tmp`.
+pub const TMP_STAGING_DIR: &str = "/tmp";
+
+/// Mount point of the staging tmpfs inside the rootfs.
+pub const TMPFS_STAGING_MOUNT_POINT: &str = "/run/rsdebstrap/staging";
+
+/// Permissions of the staging directory: other users may reach a staged file by its
+/// (random) name, e.g. a chroot `user`, but cannot list the directory.
+const STAGING_DIR_MODE: u32 = 0o711;
+
+/// Where task payloads are staged inside the rootfs.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Staging {
+    /// Directly in the rootfs's `/tmp` (default).
+    #[default]
+    Tmp,
+    /// In a per-run `/tmp/rsdebstrap-<uuid>` directory with mode 0711, removed when
+    /// the pipeline finishes.
+    Private,
+    /// On a tmpfs mounted at `/run/rsdebstrap/staging` for the whole pipeline.
+    Tmpfs,
+}
+
+impl Staging {
+    /// Returns the tmpfs mount entry for [`Staging::Tmpfs`].
+    ///
+    /// The tmpfs is owned by the user running rsdebstrap, which writes the staged
+    /// files itself, even though it is mounted with privilege escalation.
+    pub fn tmpfs_mount(&self) -> Option<MountEntry> {
+        if *self != Self::Tmpfs {
+            return None;
+        }
+        Some(MountEntry {
+            source: "tmpfs".to_string(),
+            target: Utf8PathBuf::from(TMPFS_STAGING_MOUNT_POINT),
+            options: vec![
+                "nosuid".to_string(),
+                "nodev".to_string(),
+                format!("mode={:04o}", STAGING_DIR_MODE),
+                format!("uid={}", rustix::process::geteuid().as_raw()),
+                format!("gid={}", rustix::process::getegid().as_raw()),
+            ],
+        })
+    }
+}
+
+/// RAII guard for the staging directory of a pipeline run.
+///
+/// For [`Staging::Private`], [`setup()`](Self::setup) creates the per-run directory
+/// and [`teardown()`](Self::teardown) removes it again; the `Drop` implementation
+/// ensures cleanup even on error paths. The other modes need no setup here (the
+/// tmpfs is one of the pipeline mounts).
+pub struct RootfsStaging {
+    rootfs: Utf8PathBuf,
+    staging: Staging,
+    /// Staging directory as seen inside the rootfs.
+    dir: Utf8PathBuf,
+    dry_run: bool,
+    created: bool,
+}
+
+impl RootfsStaging {
+    /// Creates a new `RootfsStaging` instance; nothing is created until
+    /// [`setup()`](Self::setup) is called.
+    pub fn new(rootfs: &Utf8Path, staging: Staging, dry_run: bool) -> Self {
+        let dir = match staging {
+            Staging::Tmp => Utf8PathBuf::from(TMP_STAGING_DIR),
+            Staging::Private => {
+                Utf8Path::new(TMP_STAGING_DIR).join(format!("rsdebstrap-{}", uuid::Uuid::new_v4()))
+            }
+            Staging::Tmpfs => Utf8PathBuf::from(TMPFS_STAGING_MOUNT_POINT),
+        };
+        Self {
+            rootfs: rootfs.to_owned(),
+            staging,
+            dir,
+            dry_run,
+            created: false,
+        }
+    }
+
+    /// Names the [`Staging::Private`] directory `rsdebstrap-<run-id>` rather than after
+    /// a random UUID.
+    pub fn with_run_id(mut self, run_id: Option<&RunId>) -> Self {
+        if let (Staging::Private, Some(run_id)) = (self.staging, run_id) {
+            self.dir = Utf8Path::new(TMP_STAGING_DIR).join(format!("rsdebstrap-{}", run_id));
+        }
+        self
+    }
+
+    /// Returns the staging directory as seen inside the rootfs.
+    pub fn dir(&self) -> &Utf8Path {
+        &self.dir
+    }
+
+    /// Creates the per-run directory for [`Staging::Private`].
+    ///
+    /// `/tmp` is opened without following symlinks and the directory is created
+    /// with `mkdirat`, so a rootfs cannot redirect it to the host.
+    pub fn setup(&mut self) -> Result<()> {
+        if self.staging != Staging::Private {
+            return Ok(());
+        }
+        if self.dry_run {
+            info!("would create staging directory {} in {}", self.dir, self.rootfs);
+            return Ok(());
+        }
+
+        let tmp = self.rootfs.join("tmp");
+        let name = self.dir.file_name().expect("staging directory has a name");
+        let tmp_fd = rfs::openat(
+            CWD,
+            tmp.as_str(),
+            OFlags::NOFOLLOW | OFlags::DIRECTORY | OFlags::RDONLY | OFlags::CLOEXEC,
+            Mode::empty(),
+        )
+        .map_err(|e| map_staging_error(e, &tmp))?;
+        rfs::mkdirat(&tmp_fd, name, Mode::from_raw_mode(STAGING_DIR_MODE))
+            .map_err(|e| map_staging_error(e, &self.host_dir()))?;
+        self.created = true;
+
+        // mkdirat applies the umask; set the mode explicitly.
+        let dir_fd: OwnedFd = rfs::openat(
+            &tmp_fd,
+            name,
+            OFlags::NOFOLLOW | OFlags::DIRECTORY | OFlags::RDONLY | OFlags::CLOEXEC,
+            Mode::empty(),
+        )
+        .map_err(|e| map_staging_error(e, &self.host_dir()))?;
+        rfs::fchmod(&dir_fd, Mode::from_raw_mode(STAGING_DIR_MODE))
+            .map_err(|e| map_staging_error(e, &self.host_dir()))?;
+
+        info!("created staging directory {} in {}", self.dir, self.rootfs);
+        Ok(())
+    }
+
+    /// Removes the per-run directory.
+    ///
+    /// Tasks remove their own staged files, so the directory is expected to be
+    /// empty. This method is idempotent after a successful teardown.
+    pub fn teardown(&mut self) -> Result<()> {
+        if !self.created {
+            return Ok(());
+        }
+        let host_dir = self.host_dir();
+        fs::remove_dir(&host_dir).map_err(|e| {
+            RsdebstrapError::io(format!("failed to remove staging directory {}", host_dir), e)
+        })?;
+        self.created = false;
+        info!("removed staging directory {} from {}", self.dir, self.rootfs);
+        Ok(())
+    }
+
+    /// Path of the staging directory on the host.
+    fn host_dir(&self) -> Utf8PathBuf {
+        self.rootfs
+            .join(self.dir.strip_prefix("/").unwrap_or(&self.dir))
+    }
+}
+
+impl Drop for RootfsStaging {
+    fn drop(&mut self) {
+        if self.created
+            && let Err(e) = self.teardown()
+        {
+            tracing::error!("failed to remove staging directory during cleanup: {:#}", e);
+        }
+    }
+}
+
+/// Maps an `openat`/`mkdirat` error on the staging directory to a typed error.
+fn map_staging_error(err: rustix::io::Errno, path: &Utf8Path) -> anyhow::Error {
+    match err {
+        rustix::io::Errno::LOOP | rustix::io::Errno::NOTDIR => RsdebstrapError::Isolation(format!(
+            "symlink or non-directory at {} while creating the staging directory",
+            path
+        ))
+        .into(),
+        _ => RsdebstrapError::io(
+            format!("failed to create staging directory {}", path),
+            std::io::Error::from(err),
+        )
+        .into(),
+    }
+}
+
+/// Isolation context wrapper that stages task payloads in `staging_dir`, named after
+/// the task.
+///
+/// Providers know nothing about staging; the pipeline wraps each context it sets
+/// up, and every other call goes to the wrapped context.
+pub struct StagedContext {
+    inner: Box<dyn IsolationContext>,
+    staging_dir: Utf8PathBuf,
+    /// Suffix of the staged names, `<run-id>-<phase>-<number>`; random without one.
+    name_suffix: Option<String>,
+}
+
+impl StagedContext {
+    /// Wraps `inner` so that tasks stage their files in `staging_dir`.
+    pub fn new(inner: Box<dyn IsolationContext>, staging_dir: &Utf8Path) -> Self {
+        Self {
+            inner,
+            staging_dir: staging_dir.to_owned(),
+            name_suffix: None,
+        }
+    }
+
+    /// Names staged files `<kind>-<suffix>` rather than after a random UUID.
+    pub fn with_name_suffix(mut self, suffix: Option<String>) -> Self {
+        self.name_suffix = suffix;
+        self
+    }
+}
+
+impl IsolationContext for StagedContext {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn rootfs(&self) -> &Utf8Path {
+        self.inner.rootfs()
+    }
+
+    fn dry_run(&self) -> bool {
+        self.inner.dry_run()
+    }
+
+    fn staging_dir(&self) -> &Utf8Path {
+        &self.staging_dir
+    }
+
+    fn staging_name(&self, kind: &str) -> String {
+        match &self.name_suffix {
+            Some(suffix) => format!("{}-{}", kind, suffix),
+            None => self.inner.staging_name(kind),
+        }
+    }
+
+    fn execute(
+        &self,
+        command: &[String],
+        privilege: Option<PrivilegeMethod>,
+    ) -> Result<ExecutionResult> {
+        self.inner.execute(command, privilege)
+    }
+
+    fn execute_with_stdin(
+        &self,
+        command: &[String],
+        privilege: Option<PrivilegeMethod>,
+        stdin: &[u8],
+    ) -> Result<ExecutionResult> {
+        self.inner.execute_with_stdin(command, privilege, stdin)
+    }
+
+    fn hand_over(&self, paths: &[String], privilege: Option<PrivilegeMethod>) -> Result<()> {
+        self.inner.hand_over(paths, privilege)
+    }
+
+    fn executor(&self) -> &dyn CommandExecutor {
+        self.inner.executor()
+    }
+
+    fn teardown(&mut self) -> Result<()> {
+        self.inner.teardown()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn rootfs_with_tmp() -> (tempfile::TempDir, Utf8PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let rootfs = Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap();
+        fs::create_dir(rootfs.join("tmp")).unwrap();
+        (dir, rootfs)
+    }
+
+    #[test]
+    fn staging_dirs_per_mode() {
+        let rootfs = Utf8Path::new("/rootfs");
+        assert_eq!(RootfsStaging::new(rootfs, Staging::Tmp, true).dir(), "/tmp");
+        assert_eq!(
+            RootfsStaging::new(rootfs, Staging::Tmpfs, true).dir(),
+            TMPFS_STAGING_MOUNT_POINT
+        );
+        let private = RootfsStaging::new(rootfs, Staging::Private, true);
+        assert!(private.dir().as_str().starts_with("/tmp/rsdebstrap-"), "{}", private.dir());
+        let run_id = RunId::generate();
+        let private = private.with_run_id(Some(&run_id));
+        assert_eq!(private.dir(), format!("/tmp/rsdebstrap-{}", run_id));
+        let tmp = RootfsStaging::new(rootfs, Staging::Tmp, true).with_run_id(Some(&run_id));
+        assert_eq!(tmp.dir(), "/tmp");
+    }
+
+    #[test]
+    fn staged_context_names_files_after_the_suffix() {
+        use crate::executor::RealCommandExecutor;
+        use crate::isolation::{DirectProvider, IsolationProvider};
+        use std::sync::Arc;
+
+        let executor = Arc::new(RealCommandExecutor { dry_run: true });
+        let inner = DirectProvider::default()
+            .setup(Utf8Path::new("/rootfs"), executor, true)
+            .unwrap();
+        let random = inner.staging_name("task");
+        assert!(random.starts_with("task-") && random.len() > "task-".len(), "{random}");
+
+        let context = StagedContext::new(inner, Utf8Path::new("/tmp"))
+            .with_name_suffix(Some("20260102T030405Z-3f9c2a1b-provision-2".to_string()));
+        assert_eq!(context.staging_name("task"), "task-20260102T030405Z-3f9c2a1b-provision-2");
+        assert_eq!(context.staging_name("node"), "node-20260102T030405Z-3f9c2a1b-provision-2");
+    }
+
+    #[test]
+    fn tmpfs_mount_only_for_tmpfs_staging() {
+        assert_eq!(Staging::Tmp.tmpfs_mount(), None);
+        assert_eq!(Staging::Private.tmpfs_mount(), None);
+
+        let entry = Staging::Tmpfs.tmpfs_mount().unwrap();
+        assert_eq!(entry.source, "tmpfs");
+        assert_eq!(entry.target, TMPFS_STAGING_MOUNT_POINT);
+        assert!(entry.options.contains(&"mode=0711".to_string()));
+        assert!(entry.options.iter().any(|o| o.starts_with("uid=")));
+        entry.validate().unwrap();
+    }
+
+    #[test]
+    fn private_staging_creates_and_removes_directory() {
+        let (_dir, rootfs) = rootfs_with_tmp();
+        let mut staging = RootfsStaging::new(&rootfs, Staging::Private, false);
+
+        staging.setup().unwrap();
+        let host_dir = rootfs.join(staging.dir().strip_prefix("/").unwrap());
+        let mode = fs::metadata(&host_dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o711);
+
+        staging.teardown().unwrap();
+        staging.teardown().unwrap();
+        assert!(!host_dir.exists());
+    }
+
+    #[test]
+    fn private_staging_removed_on_drop() {
+        let (_dir, rootfs) = rootfs_with_tmp();
+        let host_dir = {
+            let mut staging = RootfsStaging::new(&rootfs, Staging::Private, false);
+            staging.setup().unwrap();
+            rootfs.join(staging.dir().strip_prefix("/").unwrap())
+        };
+        assert!(!host_dir.exists());
+    }
+
+    #[test]
+    fn private_staging_rejects_symlinked_tmp() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let rootfs = Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap();
+        std::os::unix::fs::symlink(outside.path(), rootfs.join("tmp")).unwrap();
+
+        let mut staging = RootfsStaging::new(&rootfs, Staging::Private, false);
+        let err = staging.setup().unwrap_err();
+
+        assert!(err.to_string().contains("symlink"), "{err:#}");
+        assert_eq!(fs::read_dir(outside.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn dry_run_creates_nothing() {
+        let mut staging =
+            RootfsStaging::new(Utf8Path::new("/nonexistent/rootfs"), Staging::Private, true);
+        staging.setup().unwrap();
+        staging.teardown().unwrap();
+    }
+}
@@ -0,0 +1,399 @@
+//! Repository keyring management.
+//!
+//! The profile's `keyrings` section lists OpenPGP keyring files that the bootstrap
+//! backend should trust, typically the archive keys of a Debian derivative. Each
+//! keyring is either a local file or a URL pinned with a SHA-256 digest. Local
+//! files are checked by [`KeyringSource::validate`]; downloads happen in
+//! [`prepare_keyrings`] right before the bootstrap, into a temporary directory
+//! that lives until the bootstrap has finished. The resulting paths are passed to
+//! the backend as `--keyring` options.
+
+use std::fs::File;
+use std::io::Read;
+
+use camino::{Utf8Path, Utf8PathBuf};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tempfile::TempDir;
+
+use crate::download;
+use crate::error::RsdebstrapError;
+use crate::executor::CommandExecutor;
+
+/// Header of an ASCII-armored OpenPGP public key block.
+const ARMOR_HEADER: &[u8] = b"-----BEGIN PGP PUBLIC KEY BLOCK-----";
+
+/// A keyring file for repository signature verification.
+///
+/// Exactly one of `path` and `url` must be set. A `url` keyring must pin its
+/// `sha256`; for a `path` keyring it is optional.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct KeyringSource {
+    /// Local keyring file (relative paths are resolved against the profile's directory).
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_path",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<crate::schema::Utf8PathSchema>")
+    )]
+    pub path: Option<Utf8PathBuf>,
+    /// URL to download the keyring from (`http://` or `https://`; requires `sha256`).
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub url: Option<String>,
+    /// Expected SHA-256 digest of the keyring file, as 64 hexadecimal digits.
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub sha256: Option<String>,
+}
+
+impl KeyringSource {
+    /// Returns whether the keyring has to be downloaded.
+    pub fn is_remote(&self) -> bool {
+        self.url.is_some()
+    }
+
+    /// Resolves a relative `path` against the profile's directory.
+    pub(crate) fn resolve_paths(&mut self, profile_dir: &Utf8Path) {
+        if let Some(path) = self.path.as_mut()
+            && path.is_relative()
+        {
+            *path = profile_dir.join(&*path);
+        }
+    }
+
+    /// Validates the keyring source.
+    ///
+    /// A local keyring must exist, look like an OpenPGP keyring, and match its
+    /// `sha256` if one is given. A remote keyring is only checked for a well-formed
+    /// URL and digest here; its content is verified after the download.
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        if let Some(sha256) = &self.sha256 {
+            download::validate_sha256(sha256, "keyring")?;
+        }
+        match (&self.path, &self.url) {
+            (Some(_), Some(_)) => Err(RsdebstrapError::Validation(
+                "keyring: 'path' and 'url' are mutually exclusive".to_string(),
+            )),
+            (None, None) => Err(RsdebstrapError::Validation(
+                "keyring: one of 'path' or 'url' must be set".to_string(),
+            )),
+            (None, Some(url)) => {
+                download::validate_url(url, "keyring")?;
+                if self.sha256.is_none() {
+                    return Err(RsdebstrapError::Validation(format!(
+                        "keyring url '{}' requires 'sha256'",
+                        url
+                    )));
+                }
+                Ok(())
+            }
+            (Some(path), None) => {
+                if !path.is_file() {
+                    return Err(RsdebstrapError::Validation(format!(
+                        "keyring file not found: {}",
+                        path
+                    )));
+                }
+                check_keyring_format(path)?;
+                if let Some(sha256) = &self.sha256 {
+                    download::verify_sha256(path, sha256)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Checks that `path` holds an OpenPGP keyring, binary or ASCII-armored.
+///
+/// Only the first bytes are inspected: a binary keyring starts with an OpenPGP
+/// packet header (high bit set), an armored one with the public key block header.
+/// This catches HTML error pages and other wrong files, not corrupt keys.
+pub fn check_keyring_format(path: &Utf8Path) -> Result<(), RsdebstrapError> {
+    let mut head = [0u8; ARMOR_HEADER.len()];
+    let mut file = File::open(path).map_err(|e| RsdebstrapError::io(path.to_string(), e))?;
+    let mut len = 0;
+    while len < head.len() {
+        match file.read(&mut head[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) => return Err(RsdebstrapError::io(format!("failed to read {}", path), e)),
+        }
+    }
+    let head = &head[..len];
+    if head.starts_with(ARMOR_HEADER) || head.first().is_some_and(|b| b & 0x80 != 0) {
+        Ok(())
+    } else {
+        Err(RsdebstrapError::Validation(format!(
+            "keyring {} is not an OpenPGP keyring (expected a binary .gpg or an armored .asc file)",
+            path
+        )))
+    }
+}
+
+/// Keyring files ready to be passed to the bootstrap backend.
+///
+/// Holds the temporary directory of downloaded keyrings, which is removed when
+/// this value is dropped.
+#[derive(Debug, Default)]
+pub struct PreparedKeyrings {
+    _staging: Option<TempDir>,
+    /// Paths of all keyrings, in profile order.
+    pub paths: Vec<Utf8PathBuf>,
+}
+
+/// Makes every keyring available as a local file, downloading remote ones.
+///
+/// Should only be called with sources that passed [`KeyringSource::validate`].
+///
+/// # Errors
+///
+/// Returns an error if a download fails or a downloaded keyring does not match its
+/// `sha256` or is not an OpenPGP keyring.
+pub fn prepare_keyrings(
+    sources: &[KeyringSource],
+    executor: &dyn CommandExecutor,
+    dry_run: bool,
+) -> anyhow::Result<PreparedKeyrings> {
+    let staging = if sources.iter().any(KeyringSource::is_remote) {
+        let dir = tempfile::Builder::new()
+            .prefix("rsdebstrap-keyrings-")
+            .tempdir()
+            .map_err(|e| RsdebstrapError::io("failed to create keyring download directory", e))?;
+        Some(dir)
+    } else {
+        None
+    };
+    let staging_path = staging
+        .as_ref()
+        .map(|dir| {
+            Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).map_err(|p| {
+                RsdebstrapError::Validation(format!(
+                    "keyring download directory is not valid UTF-8: {}",
+                    p.display()
+                ))
+            })
+        })
+        .transpose()?;
+
+    let mut paths = Vec::with_capacity(sources.len());
+    for (index, source) in sources.iter().enumerate() {
+        match (&source.path, &source.url, &source.sha256, &staging_path) {
+            (Some(path), _, _, _) => paths.push(path.clone()),
+            (None, Some(url), Some(sha256), Some(staging)) => {
+                // Prefix with the index so two URLs ending in the same file name cannot collide.
+                let dest = staging.join(format!("{}-{}", index, keyring_file_name(url)));
+                download::download_verified(url, &dest, sha256, executor, dry_run)?;
+                if !dry_run {
+                    check_keyring_format(&dest)?;
+                }
+                paths.push(dest);
+            }
+            _ => {}
+        }
+    }
+    Ok(PreparedKeyrings {
+        _staging: staging,
+        paths,
+    })
+}
+
+/// Returns the file name to store a downloaded keyring under.
+///
+/// apt recognizes keyrings by extension, so the URL's last path segment is kept
+/// when it has one of the keyring extensions.
+fn keyring_file_name(url: &str) -> String {
+    url::Url::parse(url)
+        .ok()
+        .and_then(|u| {
+            u.path_segments()
+                .and_then(|mut segments| segments.next_back().map(str::to_string))
+        })
+        .filter(|name| {
+            let ext = Utf8Path::new(name).extension();
+            matches!(ext, Some("gpg" | "asc" | "pgp"))
+                && !name.starts_with('.')
+                && !name.contains('%')
+        })
+        .unwrap_or_else(|| "keyring.gpg".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::{CommandSpec, ExecutionResult};
+    use std::sync::Mutex;
+
+    /// `curl` stand-in that writes `content` to the `--output` path.
+    struct FakeCurl {
+        content: &'static [u8],
+        calls: Mutex<Vec<CommandSpec>>,
+    }
+
+    impl CommandExecutor for FakeCurl {
+        fn execute(&self, spec: &CommandSpec) -> anyhow::Result<ExecutionResult> {
+            let output = spec.args.iter().position(|a| a == "--output").unwrap() + 1;
+            std::fs::write(&spec.args[output], self.content)?;
+            self.calls.lock().unwrap().push(spec.clone());
+            Ok(ExecutionResult {
+                status: Some(std::os::unix::process::ExitStatusExt::from_raw(0)),
+                details: None,
+            })
+        }
+    }
+
+    /// A one-byte binary "keyring" (an OpenPGP old-format packet tag) and its digest.
+    const BINARY_KEYRING: &[u8] = b"\x99";
+    const BINARY_KEYRING_SHA256: &str =
+        "fd9528b920d6d3956e9e16114523e1889c751e8c1e040182116d4c906b43f558";
+
+    fn write_temp(name: &str, content: &[u8]) -> (tempfile::TempDir, Utf8PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::from_path_buf(dir.path().join(name)).unwrap();
+        std::fs::write(&path, content).unwrap();
+        (dir, path)
+    }
+
+    fn source(path: Option<&Utf8Path>, url: Option<&str>, sha256: Option<&str>) -> KeyringSource {
+        KeyringSource {
+            path: path.map(Utf8Path::to_path_buf),
+            url: url.map(str::to_string),
+            sha256: sha256.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn format_check_accepts_binary_and_armored_keyrings() {
+        let (_d1, binary) = write_temp("a.gpg", BINARY_KEYRING);
+        check_keyring_format(&binary).unwrap();
+        let (_d2, armored) = write_temp("a.asc", b"-----BEGIN PGP PUBLIC KEY BLOCK-----\n\nmQ\n");
+        check_keyring_format(&armored).unwrap();
+    }
+
+    #[test]
+    fn format_check_rejects_other_files() {
+        for content in [&b""[..], b"<html>404</html>", b"not a key"] {
+            let (_dir, path) = write_temp("k.gpg", content);
+            let err = check_keyring_format(&path).unwrap_err();
+            assert!(err.to_string().contains("not an OpenPGP keyring"), "{err}");
+        }
+    }
+
+    #[test]
+    fn validate_requires_exactly_one_source() {
+        let (_dir, path) = write_temp("k.gpg", BINARY_KEYRING);
+        let both = source(Some(&path), Some("https://example.org/k.gpg"), None);
+        assert!(
+            both.validate()
+                .unwrap_err()
+                .to_string()
+                .contains("mutually exclusive")
+        );
+        let neither = source(None, None, None);
+        assert!(
+            neither
+                .validate()
+                .unwrap_err()
+                .to_string()
+                .contains("must be set")
+        );
+    }
+
+    #[test]
+    fn validate_url_keyring_requires_sha256() {
+        let err = source(None, Some("https://example.org/k.gpg"), None)
+            .validate()
+            .unwrap_err();
+        assert!(err.to_string().contains("requires 'sha256'"), "{err}");
+        source(None, Some("https://example.org/k.gpg"), Some(BINARY_KEYRING_SHA256))
+            .validate()
+            .unwrap();
+    }
+
+    #[test]
+    fn validate_path_keyring_checks_existence_and_digest() {
+        let missing = source(Some(Utf8Path::new("/nonexistent/k.gpg")), None, None);
+        assert!(
+            missing
+                .validate()
+                .unwrap_err()
+                .to_string()
+                .contains("not found")
+        );
+
+        let (_dir, path) = write_temp("k.gpg", BINARY_KEYRING);
+        source(Some(&path), None, None).validate().unwrap();
+        let wrong = source(Some(&path), None, Some(&"0".repeat(64)));
+        assert!(matches!(wrong.validate(), Err(RsdebstrapError::ChecksumMismatch { .. })));
+    }
+
+    #[test]
+    fn prepare_downloads_remote_and_passes_local_through() {
+        let (_dir, local) = write_temp("local.gpg", BINARY_KEYRING);
+        let sha256 = download::sha256_file(&local).unwrap();
+        let sources = [
+            source(Some(&local), None, None),
+            source(None, Some("https://example.org/keys/derivative.gpg"), Some(&sha256)),
+        ];
+        let curl = FakeCurl {
+            content: BINARY_KEYRING,
+            calls: Mutex::new(Vec::new()),
+        };
+
+        let prepared = prepare_keyrings(&sources, &curl, false).unwrap();
+
+        assert_eq!(prepared.paths.len(), 2);
+        assert_eq!(prepared.paths[0], local);
+        assert!(prepared.paths[1].as_str().ends_with("/1-derivative.gpg"));
+        assert!(prepared.paths[1].is_file());
+        assert_eq!(curl.calls.lock().unwrap().len(), 1);
+
+        let staged = prepared.paths[1].clone();
+        drop(prepared);
+        assert!(!staged.exists(), "staging directory must be removed on drop");
+    }
+
+    #[test]
+    fn prepare_rejects_tampered_download() {
+        let sources = [source(
+            None,
+            Some("https://example.org/k.gpg"),
+            Some(&"0".repeat(64)),
+        )];
+        let curl = FakeCurl {
+            content: BINARY_KEYRING,
+            calls: Mutex::new(Vec::new()),
+        };
+
+        let err = prepare_keyrings(&sources, &curl, false).unwrap_err();
+
+        assert!(
+            matches!(
+                err.downcast_ref::<RsdebstrapError>(),
+                Some(RsdebstrapError::ChecksumMismatch { .. })
+            ),
+            "{err:#}"
+        );
+    }
+
+    #[test]
+    fn keyring_file_name_keeps_keyring_extensions_only() {
+        assert_eq!(keyring_file_name("https://example.org/a/archive.gpg"), "archive.gpg");
+        assert_eq!(keyring_file_name("https://example.org/a/archive.asc"), "archive.asc");
+        assert_eq!(keyring_file_name("https://example.org/a/download?id=1"), "keyring.gpg");
+        assert_eq!(keyring_file_name("https://example.org/"), "keyring.gpg");
+    }
+}
@@ -0,0 +1,476 @@
+//! Content-addressed cache of the filesystem changes provision tasks make
+//! (`apply --layer-cache`).
+//!
+//! Each provision task that runs with the cache enabled has its changes to the rootfs
+//! captured as a layer, like a Docker image layer: a tar of the paths it added or changed
+//! and a list of the paths it removed. A layer is keyed by the key of the layer below it
+//! and the task's definition, so it identifies the task together with everything that
+//! ran before it; the first task's parent is the profile without its provision and
+//! assemble tasks, which covers the bootstrap.
+//!
+//! On a later run, leading tasks whose layers are cached are replayed from the cache
+//! instead of running; from the first task without a cached layer on, tasks run and
+//! their layers are captured. Layers are stored in the cache directory as
+//! `<key>.tar` and `<key>.deleted`.
+//!
+//! Changes are found by listing the rootfs before and after each task with
+//! `find -xdev` (so mounted filesystems are skipped) and comparing inode change times,
+//! so the listing runs with the profile's default privilege. Changes under the task
+//! staging directory are not captured.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+use crate::config::Profile;
+use crate::error::RsdebstrapError;
+use crate::executor::{CommandExecutor, CommandSpec};
+use crate::lock::FileLock;
+use crate::phase::ProvisionTask;
+use crate::privilege::PrivilegeMethod;
+use crate::task_record::task_digest;
+
+/// Versions the key derivation, so layers from an incompatible format never match.
+const KEY_VERSION: &str = "rsdebstrap-layer-v1";
+
+/// How far the change-time stamp is set back before each task, so changes made within
+/// the filesystem's timestamp granularity of the stamp are still seen as newer.
+const STAMP_SLACK: Duration = Duration::from_secs(1);
+
+fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Returns the key every layer chain starts from: a digest of `profile` without its
+/// provision and assemble tasks.
+pub fn base_key(profile: &Profile) -> Result<String, RsdebstrapError> {
+    let mut base = profile.clone();
+    base.provision.clear();
+    base.assemble = Default::default();
+    Ok(sha256_hex(format!("{}\n{}", KEY_VERSION, base.to_yaml()?).as_bytes()))
+}
+
+/// Returns the digest identifying `task`: its definition and the digest of its script
+/// or recipe (see [`task_digest`]).
+pub fn task_key(task: &ProvisionTask) -> Result<String, RsdebstrapError> {
+    let definition = yaml_serde::to_string(task)
+        .map_err(|e| RsdebstrapError::Config(format!("failed to serialize task: {}", e)))?;
+    Ok(sha256_hex(format!("{}\n{}", definition, task_digest(task)?).as_bytes()))
+}
+
+/// Returns the key of the layer `task_key` adds on top of the layer keyed `parent`.
+pub fn layer_key(parent: &str, task_key: &str) -> String {
+    sha256_hex(format!("{}\n{}", parent, task_key).as_bytes())
+}
+
+/// The layer cache directory, with the keys of a profile's provision tasks.
+///
+/// The keys are computed by [`open()`](Self::open), before the run adjusts the profile
+/// (e.g. replacing a downloaded binary's `url` with a temporary path).
+pub struct LayerCache {
+    dir: Utf8PathBuf,
+    base_key: String,
+    task_keys: Vec<String>,
+    executor: Arc<dyn CommandExecutor>,
+    privilege: Option<PrivilegeMethod>,
+    work: tempfile::TempDir,
+}
+
+impl LayerCache {
+    /// Opens the cache in `dir`, creating it if needed, for `profile`'s provision tasks.
+    ///
+    /// Commands that read or write the rootfs run through `executor` with `privilege`.
+    pub fn open(
+        dir: &Utf8Path,
+        profile: &Profile,
+        executor: Arc<dyn CommandExecutor>,
+        privilege: Option<PrivilegeMethod>,
+    ) -> Result<Self, RsdebstrapError> {
+        fs::create_dir_all(dir).map_err(|e| {
+            RsdebstrapError::io(format!("failed to create layer cache directory {}", dir), e)
+        })?;
+        let task_keys = profile
+            .provision
+            .iter()
+            .map(task_key)
+            .collect::<Result<_, _>>()?;
+        let work = tempfile::Builder::new()
+            .prefix("rsdebstrap-layers-")
+            .tempdir()
+            .map_err(|e| RsdebstrapError::io("failed to create layer cache work directory", e))?;
+        Ok(Self {
+            dir: dir.to_owned(),
+            base_key: base_key(profile)?,
+            task_keys,
+            executor,
+            privilege,
+            work,
+        })
+    }
+
+    /// Returns the cache directory.
+    pub fn dir(&self) -> &Utf8Path {
+        &self.dir
+    }
+
+    fn tar_path(&self, key: &str) -> Utf8PathBuf {
+        self.dir.join(format!("{}.tar", key))
+    }
+
+    fn deleted_path(&self, key: &str) -> Utf8PathBuf {
+        self.dir.join(format!("{}.deleted", key))
+    }
+
+    fn work_path(&self, name: &str) -> Utf8PathBuf {
+        // The tempdir path is created from the UTF-8 temp directory and a UTF-8 prefix.
+        Utf8Path::from_path(self.work.path())
+            .expect("temporary directory path should be valid UTF-8")
+            .join(name)
+    }
+
+    /// Returns true if the layer keyed `key` is cached.
+    ///
+    /// The deleted-paths list is written last, so it marks a complete layer.
+    pub fn contains(&self, key: &str) -> bool {
+        self.tar_path(key).is_file() && self.deleted_path(key).is_file()
+    }
+
+    /// Starts a chain of layers from the base key, for one provision phase.
+    pub fn layers(&self) -> Layers<'_> {
+        Layers {
+            cache: self,
+            key: self.base_key.clone(),
+            snapshot: None,
+        }
+    }
+
+    fn run(&self, command: &str, args: Vec<String>) -> Result<()> {
+        let spec = CommandSpec::new(command, args).with_privilege(self.privilege);
+        self.executor.execute_checked(&spec)?;
+        Ok(())
+    }
+
+    /// Runs `script` with `sh -c`, passing `args` as its positional parameters.
+    fn run_sh(&self, script: &str, args: &[&str]) -> Result<()> {
+        let mut argv = vec!["-c".to_string(), script.to_string(), "sh".to_string()];
+        argv.extend(args.iter().map(|a| a.to_string()));
+        self.run("sh", argv)
+    }
+
+    /// Creates (or resets) the change-time stamp, backdated by [`STAMP_SLACK`].
+    fn touch_stamp(&self) -> Result<()> {
+        let stamp = self.work_path("stamp");
+        let file = fs::File::create(&stamp)
+            .map_err(|e| RsdebstrapError::io(format!("failed to create {}", stamp), e))?;
+        file.set_modified(SystemTime::now() - STAMP_SLACK)
+            .map_err(|e| RsdebstrapError::io(format!("failed to set time of {}", stamp), e))?;
+        Ok(())
+    }
+
+    /// Lists every path on the rootfs filesystem and resets the change-time stamp.
+    fn snapshot(&self, rootfs: &Utf8Path, staging_dir: &Utf8Path) -> Result<Snapshot> {
+        let list = self.work_path("paths");
+        self.run_sh(
+            "cd \"$1\" && find . -xdev -print0 > \"$2\"",
+            &[rootfs.as_str(), list.as_str()],
+        )?;
+        let paths = read_paths(&list, staging_dir)?;
+        self.touch_stamp()?;
+        Ok(Snapshot { paths })
+    }
+
+    /// Captures the changes since `before` as the layer keyed `key`, returning the
+    /// snapshot the next task starts from.
+    fn capture(
+        &self,
+        key: &str,
+        rootfs: &Utf8Path,
+        staging_dir: &Utf8Path,
+        before: Snapshot,
+    ) -> Result<Snapshot> {
+        let list = self.work_path("paths");
+        let changed_list = self.work_path("changed");
+        let stamp = self.work_path("stamp");
+        self.run_sh(
+            "cd \"$1\" && find . -xdev -print0 > \"$2\" && find . -xdev -cnewer \"$3\" -print0 > \"$4\"",
+            &[rootfs.as_str(), list.as_str(), stamp.as_str(), changed_list.as_str()],
+        )?;
+        let after = read_paths(&list, staging_dir)?;
+        let changed = read_paths(&changed_list, staging_dir)?;
+        let deleted: Vec<&String> = before.paths.difference(&after).collect();
+
+        // The filtered list of changed paths is what goes into the layer.
+        write_paths(&changed_list, changed.iter())?;
+        // Builds sharing the cache may store the same layer at once; the `.partial`
+        // names are per key, so take turns.
+        let _lock = FileLock::exclusive(&self.dir.join(".lock"), "the layer cache")?;
+        let partial = self.dir.join(format!("{}.tar.partial", key));
+        self.run(
+            "tar",
+            vec![
+                "--create".to_string(),
+                "--file".to_string(),
+                partial.to_string(),
+                "--directory".to_string(),
+                rootfs.to_string(),
+                "--numeric-owner".to_string(),
+                "--xattrs".to_string(),
+                "--xattrs-include=*".to_string(),
+                "--no-recursion".to_string(),
+                "--null".to_string(),
+                "--files-from".to_string(),
+                changed_list.to_string(),
+            ],
+        )?;
+        let tar = self.tar_path(key);
+        fs::rename(&partial, &tar)
+            .map_err(|e| RsdebstrapError::io(format!("failed to rename {}", partial), e))?;
+        let deleted_path = self.deleted_path(key);
+        let deleted_partial = self.dir.join(format!("{}.deleted.partial", key));
+        write_paths(&deleted_partial, deleted.iter().copied())?;
+        fs::rename(&deleted_partial, &deleted_path)
+            .map_err(|e| RsdebstrapError::io(format!("failed to rename {}", deleted_partial), e))?;
+        info!(
+            "cached layer {} ({} changed, {} removed path(s))",
+            short_key(key),
+            changed.len(),
+            deleted.len()
+        );
+
+        self.touch_stamp()?;
+        Ok(Snapshot { paths: after })
+    }
+
+    /// Replays the layer keyed `key` onto `rootfs`: removes its deleted paths, then
+    /// extracts its tar.
+    fn replay(&self, key: &str, rootfs: &Utf8Path) -> Result<()> {
+        let deleted = self.deleted_path(key);
+        let content = fs::read(&deleted)
+            .map_err(|e| RsdebstrapError::io(format!("failed to read {}", deleted), e))?;
+        for path in content.split(|&b| b == 0).filter(|p| !p.is_empty()) {
+            let path = String::from_utf8_lossy(path);
+            if !is_layer_path(&path) {
+                return Err(RsdebstrapError::Validation(format!(
+                    "layer {} removes {:?}, which is not a path inside the rootfs",
+                    deleted, path
+                ))
+                .into());
+            }
+        }
+        self.run_sh(
+            "cd \"$1\" && xargs -0 -r rm -rf -- < \"$2\"",
+            &[rootfs.as_str(), deleted.as_str()],
+        )?;
+        self.run(
+            "tar",
+            vec![
+                "--extract".to_string(),
+                "--file".to_string(),
+                self.tar_path(key).to_string(),
+                "--directory".to_string(),
+                rootfs.to_string(),
+                "--numeric-owner".to_string(),
+                "--xattrs".to_string(),
+                "--xattrs-include=*".to_string(),
+            ],
+        )
+    }
+}
+
+/// The paths on the rootfs filesystem before a task runs, relative to the rootfs.
+struct Snapshot {
+    paths: BTreeSet<String>,
+}
+
+/// A chain of layers being replayed or captured, one provision task at a time.
+pub struct Layers<'a> {
+    cache: &'a LayerCache,
+    key: String,
+    /// The rootfs listing once a task has missed the cache; every later task runs too.
+    snapshot: Option<Snapshot>,
+}
+
+impl Layers<'_> {
+    /// Brings `rootfs` to the state after provision task `number` (1-based, in the
+    /// profile): replays its layer if it and every layer before it in this chain are
+    /// cached, otherwise runs it with `run` and captures its layer.
+    ///
+    /// Changes under `staging_dir` (as seen inside the rootfs) are not captured.
+    pub fn apply(
+        &mut self,
+        number: usize,
+        rootfs: &Utf8Path,
+        staging_dir: &Utf8Path,
+        run: impl FnOnce() -> Result<()>,
+    ) -> Result<()> {
+        let task_key = &self.cache.task_keys[number - 1];
+        self.key = layer_key(&self.key, task_key);
+        if self.snapshot.is_none() && self.cache.contains(&self.key) {
+            info!("replaying cached layer {}", short_key(&self.key));
+            return self
+                .cache
+                .replay(&self.key, rootfs)
+                .context("failed to replay the cached layer");
+        }
+        let before = match self.snapshot.take() {
+            Some(snapshot) => snapshot,
+            None => self
+                .cache
+                .snapshot(rootfs, staging_dir)
+                .context("failed to list the rootfs for the layer cache")?,
+        };
+        run()?;
+        self.snapshot = Some(
+            self.cache
+                .capture(&self.key, rootfs, staging_dir, before)
+                .context("failed to capture the task's layer")?,
+        );
+        Ok(())
+    }
+}
+
+fn short_key(key: &str) -> &str {
+    &key[..12.min(key.len())]
+}
+
+/// Returns true if `path` is a rootfs-relative path as `find .` prints it: `.` or
+/// `./`-prefixed, without `..` components.
+fn is_layer_path(path: &str) -> bool {
+    (path == "." || path.starts_with("./")) && !path.split('/').any(|c| c == "..")
+}
+
+/// Reads a NUL-separated `find` listing, leaving out `staging_dir` and everything
+/// below it.
+fn read_paths(list: &Utf8Path, staging_dir: &Utf8Path) -> Result<BTreeSet<String>> {
+    let content =
+        fs::read(list).map_err(|e| RsdebstrapError::io(format!("failed to read {}", list), e))?;
+    let staging = format!(".{}", staging_dir.as_str().trim_end_matches('/'));
+    Ok(content
+        .split(|&b| b == 0)
+        .filter(|p| !p.is_empty())
+        .map(|p| String::from_utf8_lossy(p).into_owned())
+        .filter(|p| {
+            !(p == &staging || p.strip_prefix(&staging).is_some_and(|r| r.starts_with('/')))
+        })
+        .collect())
+}
+
+fn write_paths<'p>(path: &Utf8Path, paths: impl Iterator<Item = &'p String>) -> Result<()> {
+    let mut content = Vec::new();
+    for p in paths {
+        content.extend_from_slice(p.as_bytes());
+        content.push(0);
+    }
+    fs::write(path, content)
+        .map_err(|e| RsdebstrapError::io(format!("failed to write {}", path), e).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config;
+    use crate::executor::RealCommandExecutor;
+    use std::cell::Cell;
+    use std::io::Write;
+
+    fn profile(dir: &Utf8Path, content: &str) -> Profile {
+        let yaml = format!(
+            "dir: {dir}\nbootstrap:\n  type: mmdebstrap\n  suite: trixie\n  target: rootfs\n\
+             provision:\n  - type: shell\n    content: \"{content}\"\n"
+        );
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(yaml.as_bytes()).unwrap();
+        config::load_profile(Utf8Path::from_path(file.path()).unwrap()).unwrap()
+    }
+
+    fn seed_rootfs(rootfs: &Utf8Path) {
+        fs::create_dir_all(rootfs.join("etc")).unwrap();
+        fs::create_dir_all(rootfs.join("tmp")).unwrap();
+        fs::write(rootfs.join("etc/keep"), "keep\n").unwrap();
+        fs::write(rootfs.join("etc/gone"), "gone\n").unwrap();
+    }
+
+    #[test]
+    fn captured_layer_replays_onto_fresh_rootfs() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = Utf8Path::from_path(dir.path()).unwrap();
+        let executor: Arc<dyn CommandExecutor> = Arc::new(RealCommandExecutor { dry_run: false });
+        let cache_dir = base.join("cache");
+        let cache =
+            LayerCache::open(&cache_dir, &profile(base, "true"), executor.clone(), None).unwrap();
+
+        let first = base.join("first");
+        seed_rootfs(&first);
+        cache
+            .layers()
+            .apply(1, &first, Utf8Path::new("/tmp"), || {
+                fs::write(first.join("etc/new"), "new\n")?;
+                fs::remove_file(first.join("etc/gone"))?;
+                fs::write(first.join("tmp/scratch"), "")?;
+                Ok(())
+            })
+            .unwrap();
+
+        let second = base.join("second");
+        seed_rootfs(&second);
+        cache
+            .layers()
+            .apply(1, &second, Utf8Path::new("/tmp"), || panic!("the task should be replayed"))
+            .unwrap();
+        assert_eq!(fs::read_to_string(second.join("etc/new")).unwrap(), "new\n");
+        assert_eq!(fs::read_to_string(second.join("etc/keep")).unwrap(), "keep\n");
+        assert!(!second.join("etc/gone").exists());
+        assert!(!second.join("tmp/scratch").exists());
+
+        // A changed task misses the cache and runs.
+        let changed =
+            LayerCache::open(&cache_dir, &profile(base, "false"), executor, None).unwrap();
+        let ran = Cell::new(false);
+        changed
+            .layers()
+            .apply(1, &second, Utf8Path::new("/tmp"), || {
+                ran.set(true);
+                Ok(())
+            })
+            .unwrap();
+        assert!(ran.get());
+    }
+
+    #[test]
+    fn layer_keys_chain() {
+        let a = layer_key("base", "task-a");
+        assert_eq!(a.len(), 64);
+        assert_eq!(a, layer_key("base", "task-a"));
+        assert_ne!(a, layer_key("other", "task-a"));
+        assert_ne!(layer_key(&a, "task-b"), layer_key(&layer_key("base", "task-c"), "task-b"));
+    }
+
+    #[test]
+    fn read_paths_skips_staging_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let list = Utf8Path::from_path(dir.path()).unwrap().join("list");
+        fs::write(
+            &list,
+            b".\0./tmp\0./tmp/rsdebstrap-1\0./tmp/rsdebstrap-1/x\0./tmp/rsdebstrap-10\0",
+        )
+        .unwrap();
+
+        let paths = read_paths(&list, Utf8Path::new("/tmp/rsdebstrap-1")).unwrap();
+        assert_eq!(paths.into_iter().collect::<Vec<_>>(), [".", "./tmp", "./tmp/rsdebstrap-10"]);
+    }
+
+    #[test]
+    fn layer_paths_stay_inside_rootfs() {
+        assert!(is_layer_path("."));
+        assert!(is_layer_path("./etc/..hidden"));
+        assert!(!is_layer_path("/etc/passwd"));
+        assert!(!is_layer_path("./etc/../../host"));
+        assert!(!is_layer_path("etc"));
+    }
+}
@@ -0,0 +1,352 @@
+//! Non-fatal diagnostics for profiles, for `validate --lint`.
+//!
+//! [`lint_profile`] looks for patterns that are valid but usually a mistake: long
+//! inline scripts, unnamed tasks, privileged tasks running without isolation, mirrors
+//! over plain HTTP, third-party mirrors with no keyring to verify them, and suites past
+//! their security support. Each
+//! [`Lint`] carries a stable [`LintCode`] so scripts can filter or count them; unlike
+//! [`Profile::validate`] errors, lints never stop a build.
+
+use std::fmt;
+
+use serde::Serialize;
+use url::{Host, Url};
+
+use crate::bootstrap::sanitize_credential;
+use crate::bootstrap::suite::Date;
+use crate::config::{Bootstrap, Profile};
+use crate::phase::{ProvisionTask, ScriptSource};
+use crate::pipeline::task_label;
+
+/// Inline scripts longer than this many lines are reported as [`LintCode::LongInlineScript`].
+pub const MAX_INLINE_SCRIPT_LINES: usize = 50;
+
+/// Archive hosts whose repositories the distribution keyrings already verify.
+const DISTRIBUTION_DOMAINS: &[&str] = &["debian.org", "ubuntu.com"];
+
+/// What a [`Lint`] is about. The kebab-case name ([`LintCode::as_str`]) is stable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum LintCode {
+    /// An inline `content` script is longer than [`MAX_INLINE_SCRIPT_LINES`].
+    LongInlineScript,
+    /// A provision task has no `name`, so logs and `--start-at-task` refer to it by number.
+    UnnamedTask,
+    /// A task escalates privileges with `isolation: false`, running as root on the host.
+    UnisolatedPrivilegedTask,
+    /// A mirror is fetched over plain `http://`.
+    InsecureMirror,
+    /// A mirror is not a Debian or Ubuntu archive, and no keyring is configured.
+    MirrorWithoutKeyring,
+    /// The suite is a Debian release past its regular security support.
+    UnsupportedSuite,
+}
+
+impl LintCode {
+    /// Returns the code's stable kebab-case name.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::LongInlineScript => "long-inline-script",
+            Self::UnnamedTask => "unnamed-task",
+            Self::UnisolatedPrivilegedTask => "unisolated-privileged-task",
+            Self::InsecureMirror => "insecure-mirror",
+            Self::MirrorWithoutKeyring => "mirror-without-keyring",
+            Self::UnsupportedSuite => "unsupported-suite",
+        }
+    }
+}
+
+impl fmt::Display for LintCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// One warning found by [`lint_profile`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Lint {
+    /// What kind of problem this is.
+    pub code: LintCode,
+    /// Where in the profile: a task label (`provision 2 (setup)`) or a field path
+    /// (`bootstrap.mirrors[0]`).
+    pub location: String,
+    /// What is suspicious and how to fix it.
+    pub message: String,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "warning[{}] {}: {}", self.code, self.location, self.message)
+    }
+}
+
+/// Lints a loaded profile, in profile order: bootstrap suite and mirrors, then provision tasks.
+///
+/// Expects a profile from [`crate::config::load_profile`] or
+/// [`crate::config::ProfileBuilder::build`], whose task privilege and isolation are
+/// resolved against the defaults.
+pub fn lint_profile(profile: &Profile) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    for message in profile.bootstrap.suite_warnings(Date::today()) {
+        lints.push(Lint {
+            code: LintCode::UnsupportedSuite,
+            location: "bootstrap.suite".to_string(),
+            message,
+        });
+    }
+    lint_mirrors(&mut lints, profile);
+    for (index, task) in profile.provision.iter().enumerate() {
+        lint_task(&mut lints, &task_label("provision", index + 1, task), task);
+    }
+    lints
+}
+
+fn lint_task(lints: &mut Vec<Lint>, location: &str, task: &ProvisionTask) {
+    let mut push = |code, message: String| {
+        lints.push(Lint {
+            code,
+            location: location.to_string(),
+            message,
+        })
+    };
+    if task.configured_name().is_none() {
+        push(
+            LintCode::UnnamedTask,
+            format!("task {} has no name; set `name` to label it in logs", task.name()),
+        );
+    }
+    if let Some(ScriptSource::Content(content)) = task.source() {
+        let lines = content.lines().count();
+        if lines > MAX_INLINE_SCRIPT_LINES {
+            push(
+                LintCode::LongInlineScript,
+                format!(
+                    "inline script has {} lines (more than {}); move it to a `script` file",
+                    lines, MAX_INLINE_SCRIPT_LINES
+                ),
+            );
+        }
+    }
+    if let Some(method) = task.resolved_privilege_method()
+        && task.resolved_isolation_config().is_none()
+    {
+        push(
+            LintCode::UnisolatedPrivilegedTask,
+            format!(
+                "runs with {} privilege and `isolation: false`, so directly on the host",
+                method
+            ),
+        );
+    }
+}
+
+fn lint_mirrors(lints: &mut Vec<Lint>, profile: &Profile) {
+    let (mut entries, keyring_configured): (Vec<(String, &String)>, bool) = match &profile.bootstrap
+    {
+        Bootstrap::Mmdebstrap(cfg) => (
+            cfg.mirrors
+                .iter()
+                .enumerate()
+                .map(|(i, entry)| (format!("bootstrap.mirrors[{}]", i), entry))
+                .collect(),
+            !cfg.keyring.is_empty(),
+        ),
+        Bootstrap::Debootstrap(cfg) => (
+            cfg.mirror
+                .iter()
+                .map(|entry| ("bootstrap.mirror".to_string(), entry))
+                .collect(),
+            cfg.keyring.is_some(),
+        ),
+    };
+    let keyring_configured = keyring_configured || !profile.keyrings.is_empty();
+    entries.extend(
+        profile
+            .bootstrap
+            .fallback_mirrors()
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (format!("bootstrap.fallback_mirrors[{}]", i), entry)),
+    );
+    for (location, entry) in entries {
+        // An mmdebstrap entry may be a whole sources.list line.
+        let Some(url) = entry
+            .split_whitespace()
+            .find(|word| word.contains("://"))
+            .and_then(|word| Url::parse(word).ok())
+        else {
+            continue;
+        };
+        let Some(host) = url.host() else {
+            continue;
+        };
+        if is_loopback(&host) {
+            continue;
+        }
+        let shown = sanitize_credential(url.as_str());
+        if url.scheme() == "http" {
+            lints.push(Lint {
+                code: LintCode::InsecureMirror,
+                location: location.clone(),
+                message: format!(
+                    "{} uses plain HTTP; use https:// so the download cannot be tampered with",
+                    shown
+                ),
+            });
+        }
+        let signed_by = entry.contains("signed-by=");
+        if !keyring_configured && !signed_by && !is_distribution_host(&host) {
+            lints.push(Lint {
+                code: LintCode::MirrorWithoutKeyring,
+                location,
+                message: format!(
+                    "{} is not a Debian or Ubuntu archive and no keyring is configured; \
+                    add its signing key to `keyrings`",
+                    shown
+                ),
+            });
+        }
+    }
+}
+
+fn is_loopback(host: &Host<&str>) -> bool {
+    match host {
+        Host::Domain(domain) => *domain == "localhost",
+        Host::Ipv4(ip) => ip.is_loopback(),
+        Host::Ipv6(ip) => ip.is_loopback(),
+    }
+}
+
+fn is_distribution_host(host: &Host<&str>) -> bool {
+    let Host::Domain(domain) = host else {
+        return false;
+    };
+    DISTRIBUTION_DOMAINS.iter().any(|suffix| {
+        domain == suffix
+            || domain
+                .strip_suffix(suffix)
+                .is_some_and(|rest| rest.ends_with('.'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bootstrap::debootstrap::DebootstrapConfigBuilder;
+    use crate::bootstrap::mmdebstrap::MmdebstrapConfigBuilder;
+    use crate::config::ProfileBuilder;
+    use crate::isolation::TaskIsolation;
+    use crate::phase::ShellTask;
+    use crate::privilege::{Privilege, PrivilegeMethod};
+
+    fn codes(lints: &[Lint]) -> Vec<(LintCode, &str)> {
+        lints
+            .iter()
+            .map(|lint| (lint.code, lint.location.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn clean_profile_has_no_lints() {
+        let bootstrap = MmdebstrapConfigBuilder::new("trixie", "rootfs")
+            .mirrors(["https://deb.debian.org/debian"])
+            .build();
+        let profile = ProfileBuilder::new("/tmp/lint", bootstrap)
+            .provision(
+                ShellTask::new(ScriptSource::Content("apt-get install -y vim".to_string()))
+                    .with_name("tools"),
+            )
+            .build()
+            .unwrap();
+        assert_eq!(lint_profile(&profile), []);
+    }
+
+    #[test]
+    fn reports_suspicious_tasks() {
+        let bootstrap = MmdebstrapConfigBuilder::new("trixie", "rootfs")
+            .mirrors(["https://deb.debian.org/debian"])
+            .build();
+        let long = "true\n".repeat(MAX_INLINE_SCRIPT_LINES + 1);
+        let profile = ProfileBuilder::new("/tmp/lint", bootstrap)
+            .provision(ShellTask::new(ScriptSource::Content(long)).with_name("long"))
+            .provision(
+                ShellTask::new(ScriptSource::Content("echo host".to_string()))
+                    .with_privilege(Privilege::Method(PrivilegeMethod::Sudo))
+                    .with_isolation(TaskIsolation::Disabled),
+            )
+            .build()
+            .unwrap();
+
+        let lints = lint_profile(&profile);
+        assert_eq!(
+            codes(&lints),
+            [
+                (LintCode::LongInlineScript, "provision 1 (long)"),
+                (LintCode::UnnamedTask, "provision 2"),
+                (LintCode::UnisolatedPrivilegedTask, "provision 2"),
+            ]
+        );
+        assert_eq!(
+            lints[1].to_string(),
+            "warning[unnamed-task] provision 2: task shell:<inline> has no name; \
+            set `name` to label it in logs"
+        );
+    }
+
+    #[test]
+    fn reports_insecure_and_unverified_mirrors() {
+        let bootstrap = MmdebstrapConfigBuilder::new("trixie", "rootfs")
+            .mirrors([
+                "http://deb.debian.org/debian",
+                "deb https://apt.example.com/repo trixie main",
+                "deb [signed-by=/etc/apt/keyrings/x.gpg] https://x.example.com/repo trixie main",
+                "http://127.0.0.1:3142/debian",
+            ])
+            .build();
+        let profile = ProfileBuilder::new("/tmp/lint", bootstrap).build().unwrap();
+        assert_eq!(
+            codes(&lint_profile(&profile)),
+            [
+                (LintCode::InsecureMirror, "bootstrap.mirrors[0]"),
+                (LintCode::MirrorWithoutKeyring, "bootstrap.mirrors[1]"),
+            ]
+        );
+
+        let bootstrap = DebootstrapConfigBuilder::new("trixie", "rootfs")
+            .mirror("http://mirror.example.com/debian")
+            .keyring("/usr/share/keyrings/example.gpg")
+            .build();
+        let profile = ProfileBuilder::new("/tmp/lint", bootstrap).build().unwrap();
+        assert_eq!(
+            codes(&lint_profile(&profile)),
+            [(LintCode::InsecureMirror, "bootstrap.mirror")]
+        );
+    }
+
+    #[test]
+    fn reports_suites_past_security_support() {
+        let bootstrap = MmdebstrapConfigBuilder::new("buster", "rootfs")
+            .mirrors(["https://deb.debian.org/debian"])
+            .build();
+        let profile = ProfileBuilder::new("/tmp/lint", bootstrap).build().unwrap();
+        let lints = lint_profile(&profile);
+        assert_eq!(codes(&lints), [(LintCode::UnsupportedSuite, "bootstrap.suite")]);
+        assert!(
+            lints[0]
+                .message
+                .contains("http://archive.debian.org/debian"),
+            "{}",
+            lints[0]
+        );
+    }
+
+    #[test]
+    fn distribution_hosts_match_whole_labels() {
+        let host = |s: &'static str| Host::Domain(s);
+        assert!(is_distribution_host(&host("deb.debian.org")));
+        assert!(is_distribution_host(&host("archive.ubuntu.com")));
+        assert!(!is_distribution_host(&host("notdebian.org")));
+        assert!(!is_distribution_host(&host("debian.org.example.com")));
+    }
+}
@@ -0,0 +1,142 @@
+//! Advisory file locks between concurrent builds.
+//!
+//! Builds running at the same time (`rsdebstrap serve --max-builds`, or several
+//! `rsdebstrap apply` processes) may share an output directory or a layer cache.
+//! [`FileLock`] serializes them with `flock(2)` on a lock file: the lock belongs to
+//! the open file, so it is released when the guard drops or the process dies, and a
+//! lock file left behind is harmless. `apply` takes the output directory's lock with
+//! [`FileLock::try_exclusive`] and fails fast unless told to wait. Lock files are
+//! never removed, since removing one while another build waits on it would let a
+//! third build lock a new file.
+
+use std::fs::{File, OpenOptions};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use rustix::fs::{FlockOperation, flock};
+use rustix::io::Errno;
+use tracing::info;
+
+use crate::error::RsdebstrapError;
+
+/// Name of the lock file inside a locked directory.
+pub const DIR_LOCK: &str = ".rsdebstrap-dir.lock";
+
+/// Returns the lock file that guards the directory `dir`: [`DIR_LOCK`] inside it, so
+/// locking needs no more access than writing to the directory does.
+pub fn dir_lock_path(dir: &Utf8Path) -> Utf8PathBuf {
+    dir.join(DIR_LOCK)
+}
+
+/// An exclusive `flock(2)` lock, held until dropped.
+#[derive(Debug)]
+pub struct FileLock {
+    _file: File,
+}
+
+impl FileLock {
+    /// Locks `path` (created if missing), waiting for any other holder to release it.
+    ///
+    /// `what` names the locked resource in the message logged while waiting.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RsdebstrapError::Io`] if the lock file cannot be opened or locked.
+    pub fn exclusive(path: &Utf8Path, what: &str) -> Result<Self, RsdebstrapError> {
+        let file = open_lock_file(path)?;
+        let locked = |op| {
+            flock(&file, op)
+                .map_err(|e| RsdebstrapError::io(format!("failed to lock {}", path), e.into()))
+        };
+        match flock(&file, FlockOperation::NonBlockingLockExclusive) {
+            Ok(()) => {}
+            Err(Errno::WOULDBLOCK) => {
+                info!("waiting for {} (locked by another build: {})", what, path);
+                locked(FlockOperation::LockExclusive)?;
+            }
+            Err(e) => {
+                return Err(RsdebstrapError::io(format!("failed to lock {}", path), e.into()));
+            }
+        }
+        Ok(Self { _file: file })
+    }
+
+    /// Locks `path` (created if missing) like [`exclusive`](Self::exclusive), but
+    /// fails instead of waiting if another build holds the lock.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RsdebstrapError::Locked`] if the lock is held, or
+    /// [`RsdebstrapError::Io`] if the lock file cannot be opened or locked.
+    pub fn try_exclusive(path: &Utf8Path, what: &str) -> Result<Self, RsdebstrapError> {
+        let file = open_lock_file(path)?;
+        match flock(&file, FlockOperation::NonBlockingLockExclusive) {
+            Ok(()) => Ok(Self { _file: file }),
+            Err(Errno::WOULDBLOCK) => Err(RsdebstrapError::Locked {
+                path: path.to_string(),
+                what: what.to_string(),
+            }),
+            Err(e) => Err(RsdebstrapError::io(format!("failed to lock {}", path), e.into())),
+        }
+    }
+}
+
+/// Opens (creating if missing) the lock file at `path`.
+fn open_lock_file(path: &Utf8Path) -> Result<File, RsdebstrapError> {
+    OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .map_err(|e| RsdebstrapError::io(format!("failed to open lock file {}", path), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn dir_lock_is_inside_dir() {
+        assert_eq!(dir_lock_path(Utf8Path::new("/srv/out")), "/srv/out/.rsdebstrap-dir.lock");
+        assert_eq!(dir_lock_path(Utf8Path::new("/srv/out/")), "/srv/out/.rsdebstrap-dir.lock");
+    }
+
+    #[test]
+    fn second_lock_waits_for_the_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8Path::from_path(dir.path()).unwrap().join("out.lock");
+        let first = FileLock::exclusive(&path, "the output directory").unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let waiter = {
+            let path = path.clone();
+            thread::spawn(move || {
+                let _second = FileLock::exclusive(&path, "the output directory").unwrap();
+                tx.send(()).unwrap();
+            })
+        };
+        assert!(rx.recv_timeout(Duration::from_millis(200)).is_err());
+        drop(first);
+        rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        waiter.join().unwrap();
+        assert!(path.exists());
+    }
+
+    #[test]
+    fn try_lock_fails_while_held() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8Path::from_path(dir.path()).unwrap().join("out.lock");
+        let first = FileLock::try_exclusive(&path, "the output directory").unwrap();
+
+        let err = FileLock::try_exclusive(&path, "the output directory").unwrap_err();
+        assert!(matches!(err, RsdebstrapError::Locked { .. }), "{err}");
+        assert_eq!(
+            err.to_string(),
+            format!("the output directory is in use by another build (lock file {})", path)
+        );
+        drop(first);
+        FileLock::try_exclusive(&path, "the output directory").unwrap();
+    }
+}
@@ -6,9 +6,31 @@ use std::sync::Arc;
 
 #[cfg(feature = "schema")]
 use rsdebstrap::run_schema;
-use rsdebstrap::{cli, executor, init_logging, run_apply, run_validate};
+use rsdebstrap::{
+    cli, error, executor, init_logging_with, isolation, load_layers, redact, run_apply, run_diff,
+    run_doctor, run_init, run_man, run_migrate, run_serve, run_validate, sandbox,
+};
 
-fn main() -> Result<()> {
+fn main() {
+    if let Err(err) = run() {
+        let message = redact::redact(&format!("{:?}", err)).into_owned();
+        match error::error_code(&err) {
+            Some(code) => eprintln!("Error [{}]: {}", code, message),
+            None => eprintln!("Error: {}", message),
+        }
+        if let Some(hint) = error::hint(&err) {
+            eprintln!("Hint: {}", redact::redact(&hint));
+        }
+        // An interrupted build has already cleaned up while unwinding; exit like a
+        // process killed by the signal would. Otherwise the code names the failure
+        // category (see `error::exit_codes`).
+        let code = executor::received_signal()
+            .map_or_else(|| error::exit_code(&err), |signal| 128 + signal);
+        std::process::exit(code);
+    }
+}
+
+fn run() -> Result<()> {
     let args = cli::parse_args()?;
 
     // Handle stdout-only subcommands before setting up logging
@@ -19,23 +41,42 @@ fn main() -> Result<()> {
             generate(opts.shell, &mut cmd, "rsdebstrap", &mut io::stdout());
             return Ok(());
         }
+        cli::Commands::Man => return run_man(),
+        // The server sets up its own logging, which also records each job's log.
+        cli::Commands::Serve(opts) => return run_serve(opts),
         #[cfg(feature = "schema")]
         cli::Commands::Schema => return run_schema(),
+        // Replaced by the command it runs; it must not write anything of its own.
+        cli::Commands::SandboxExec(opts) => return sandbox::exec(opts),
+        cli::Commands::PivotExec(opts) => return isolation::pivot_root::exec(opts),
         _ => {}
     }
 
-    let log_level = match &args.command {
-        cli::Commands::Apply(opts) => opts.common.log_level,
-        cli::Commands::Validate(opts) => opts.common.log_level,
-        cli::Commands::Completions(_) => unreachable!("stdout-only subcommands handled above"),
+    let (log_level, no_user_config) = match &args.command {
+        cli::Commands::Apply(opts) => (opts.common.log_level, opts.common.no_user_config),
+        cli::Commands::Validate(opts) => (opts.common.log_level, opts.common.no_user_config),
+        cli::Commands::Diff(opts) => (opts.common.log_level, opts.common.no_user_config),
+        cli::Commands::Doctor(opts) => (opts.log_level, opts.no_user_config),
+        cli::Commands::Init(opts) => (opts.common.log_level, opts.common.no_user_config),
+        cli::Commands::Migrate(opts) => (opts.common.log_level, opts.common.no_user_config),
+        cli::Commands::Completions(_)
+        | cli::Commands::Man
+        | cli::Commands::Serve(_)
+        | cli::Commands::SandboxExec(_)
+        | cli::Commands::PivotExec(_) => {
+            unreachable!("stdout-only subcommands handled above")
+        }
         #[cfg(feature = "schema")]
         cli::Commands::Schema => unreachable!("stdout-only subcommands handled above"),
     };
 
-    init_logging(log_level)?;
+    init_logging_with(log_level, load_layers(no_user_config, &[])?.log_format())?;
 
     match &args.command {
         cli::Commands::Apply(opts) => {
+            if !opts.dry_run {
+                executor::install_interrupt_handler()?;
+            }
             let executor = Arc::new(executor::RealCommandExecutor {
                 dry_run: opts.dry_run,
             });
@@ -43,7 +84,17 @@ fn main() -> Result<()> {
             run_apply(opts, executor)?;
         }
         cli::Commands::Validate(opts) => run_validate(opts)?,
-        cli::Commands::Completions(_) => unreachable!("stdout-only subcommands handled earlier"),
+        cli::Commands::Diff(opts) => run_diff(opts)?,
+        cli::Commands::Doctor(opts) => run_doctor(opts)?,
+        cli::Commands::Init(opts) => run_init(opts)?,
+        cli::Commands::Migrate(opts) => run_migrate(opts)?,
+        cli::Commands::Completions(_)
+        | cli::Commands::Man
+        | cli::Commands::Serve(_)
+        | cli::Commands::SandboxExec(_)
+        | cli::Commands::PivotExec(_) => {
+            unreachable!("stdout-only subcommands handled earlier")
+        }
         #[cfg(feature = "schema")]
         cli::Commands::Schema => unreachable!("stdout-only subcommands handled earlier"),
     }
@@ -0,0 +1,785 @@
+//! Build metrics (`apply --metrics`).
+//!
+//! [`BuildMetrics`] records one build: the bootstrap's and each pipeline task's start
+//! and end (as a [`Progress`] observer), the commands run and how many failed (through
+//! [`MeteredExecutor`]), the bytes of host-side downloads and, when the built-in apt
+//! caching proxy runs, the package bytes it fetched. When the build ends, successfully
+//! or not, [`BuildMetrics::write`] saves them in the profile's `dir` next to the
+//! artifacts, in each [`MetricsFormat`] asked for:
+//!
+//! - `prometheus`: `rsdebstrap.prom`, a Prometheus text exposition file for
+//!   node_exporter's textfile collector
+//! - `otel`: `rsdebstrap-trace.json`, the build as an OpenTelemetry trace in the OTLP
+//!   JSON encoding (one span for the build, one per phase and one per task), which the
+//!   OpenTelemetry Collector's `otlpjsonfile` receiver can forward
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io::Write as _;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::apt_cache::ProxyStats;
+use crate::config::{MountEntry, UnmountMode};
+use crate::error::RsdebstrapError;
+use crate::executor::{CommandExecutor, CommandSpec, ExecutionResult};
+use crate::privilege::PrivilegeMethod;
+use crate::progress::{Progress, ProgressEvent};
+use crate::summary::{BuildSummary, PhaseTiming, TaskTiming};
+
+/// Output format of [`BuildMetrics::write`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricsFormat {
+    /// Prometheus text exposition format (`rsdebstrap.prom`).
+    Prometheus,
+    /// OpenTelemetry trace in the OTLP JSON encoding (`rsdebstrap-trace.json`).
+    Otel,
+}
+
+impl MetricsFormat {
+    /// Returns the name of the file written in this format.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Self::Prometheus => "rsdebstrap.prom",
+            Self::Otel => "rsdebstrap-trace.json",
+        }
+    }
+}
+
+/// One timed step of the build: the bootstrap or a pipeline task.
+#[derive(Debug, Clone)]
+struct Step {
+    /// `bootstrap`, `prepare`, `provision`, `assemble` or `verify`.
+    phase: &'static str,
+    /// 1-based position in the phase (0 for the bootstrap).
+    number: usize,
+    name: String,
+    start: SystemTime,
+    end: Option<SystemTime>,
+    /// False if the build failed while the step ran.
+    ok: bool,
+}
+
+#[derive(Debug)]
+struct State {
+    start: SystemTime,
+    end: Option<SystemTime>,
+    success: Option<bool>,
+    steps: Vec<Step>,
+    apt: Option<Arc<ProxyStats>>,
+}
+
+/// Metrics of one build. See the [module documentation](self).
+#[derive(Debug)]
+pub struct BuildMetrics {
+    state: Mutex<State>,
+    commands: AtomicU64,
+    failed_commands: AtomicU64,
+    downloaded_bytes: AtomicU64,
+}
+
+impl Default for BuildMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BuildMetrics {
+    /// Starts recording a build that starts now.
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(State {
+                start: SystemTime::now(),
+                end: None,
+                success: None,
+                steps: Vec::new(),
+                apt: None,
+            }),
+            commands: AtomicU64::new(0),
+            failed_commands: AtomicU64::new(0),
+            downloaded_bytes: AtomicU64::new(0),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Counts the package bytes the built-in apt caching proxy fetches.
+    pub fn watch_apt_cache(&self, stats: Arc<ProxyStats>) {
+        self.state().apt = Some(stats);
+    }
+
+    /// Ends the build now. Steps still running are ended as failed.
+    pub fn finish(&self, success: bool) {
+        let now = SystemTime::now();
+        let mut state = self.state();
+        state.end = Some(now);
+        state.success = Some(success);
+        for step in state.steps.iter_mut().filter(|s| s.end.is_none()) {
+            step.end = Some(now);
+            step.ok = false;
+        }
+    }
+
+    /// Writes the metrics in each of `formats` to `dir`, replacing earlier files
+    /// atomically. Returns the paths written.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RsdebstrapError::Io`] if a file cannot be written.
+    pub fn write(
+        &self,
+        dir: &Utf8Path,
+        formats: &[MetricsFormat],
+    ) -> Result<Vec<Utf8PathBuf>, RsdebstrapError> {
+        formats
+            .iter()
+            .map(|&format| {
+                let content = match format {
+                    MetricsFormat::Prometheus => self.prometheus(),
+                    MetricsFormat::Otel => format!("{}\n", self.otlp_trace()),
+                };
+                let path = dir.join(format.file_name());
+                write_atomically(&path, content.as_bytes())?;
+                Ok(path)
+            })
+            .collect()
+    }
+
+    /// Returns the build's timings as a [`BuildSummary`], without the rootfs usage or
+    /// the artifacts, which the caller measures.
+    pub fn summary(&self) -> BuildSummary {
+        let state = self.state();
+        let end = state.end.unwrap_or_else(SystemTime::now);
+        BuildSummary {
+            success: state.success == Some(true),
+            duration_secs: seconds_between(state.start, end),
+            phases: phase_spans(&state.steps)
+                .into_iter()
+                .map(|(phase, start, phase_end, success)| PhaseTiming {
+                    phase,
+                    duration_secs: seconds_between(start, phase_end),
+                    success,
+                })
+                .collect(),
+            tasks: state
+                .steps
+                .iter()
+                .map(|step| TaskTiming {
+                    phase: step.phase,
+                    number: step.number,
+                    name: step.name.clone(),
+                    duration_secs: seconds_between(step.start, step.end.unwrap_or(end)),
+                    success: step.ok,
+                })
+                .collect(),
+            rootfs: None,
+            artifacts: Vec::new(),
+        }
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn prometheus(&self) -> String {
+        let state = self.state();
+        let end = state.end.unwrap_or_else(SystemTime::now);
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, f64)]| {
+            let _ = writeln!(out, "# HELP rsdebstrap_{} {}", name, help);
+            let _ = writeln!(out, "# TYPE rsdebstrap_{} {}", name, kind);
+            for (labels, value) in samples {
+                let _ = writeln!(out, "rsdebstrap_{}{} {}", name, labels, value);
+            }
+        };
+
+        metric(
+            "build_success",
+            "gauge",
+            "1 if the build succeeded, 0 if it failed.",
+            &[(String::new(), f64::from(u8::from(state.success == Some(true))))],
+        );
+        metric(
+            "build_start_timestamp_seconds",
+            "gauge",
+            "Unix time the build started.",
+            &[(String::new(), unix_seconds(state.start))],
+        );
+        metric(
+            "build_duration_seconds",
+            "gauge",
+            "Duration of the build.",
+            &[(String::new(), seconds_between(state.start, end))],
+        );
+        let phases: Vec<_> = phase_spans(&state.steps)
+            .into_iter()
+            .map(|(phase, start, end, _)| {
+                (labels(&[("phase", phase)]), seconds_between(start, end))
+            })
+            .collect();
+        metric("phase_duration_seconds", "gauge", "Duration of each phase that ran.", &phases);
+        let steps: Vec<_> = state
+            .steps
+            .iter()
+            .map(|step| (step_labels(step), step))
+            .collect();
+        metric(
+            "task_duration_seconds",
+            "gauge",
+            "Duration of the bootstrap and of each pipeline task that ran.",
+            &steps
+                .iter()
+                .map(|(labels, step)| {
+                    let end = step.end.unwrap_or(end);
+                    (labels.clone(), seconds_between(step.start, end))
+                })
+                .collect::<Vec<_>>(),
+        );
+        metric(
+            "task_success",
+            "gauge",
+            "1 if the step succeeded, 0 if the build failed while it ran.",
+            &steps
+                .iter()
+                .map(|(labels, step)| (labels.clone(), f64::from(u8::from(step.ok))))
+                .collect::<Vec<_>>(),
+        );
+        metric(
+            "commands_total",
+            "counter",
+            "Commands run.",
+            &[(String::new(), self.commands.load(Ordering::Relaxed) as f64)],
+        );
+        metric(
+            "command_failures_total",
+            "counter",
+            "Commands that failed to start or exited unsuccessfully.",
+            &[(String::new(), self.failed_commands.load(Ordering::Relaxed) as f64)],
+        );
+        let mut downloaded = vec![(
+            labels(&[("source", "files")]),
+            self.downloaded_bytes.load(Ordering::Relaxed) as f64,
+        )];
+        if let Some(apt) = &state.apt {
+            downloaded.push((labels(&[("source", "apt")]), apt.fetched_bytes() as f64));
+        }
+        metric(
+            "downloaded_bytes_total",
+            "counter",
+            "Bytes downloaded: host-side files (keyrings, task binaries) and, with the \
+             built-in apt cache, packages fetched from mirrors.",
+            &downloaded,
+        );
+        if let Some(apt) = &state.apt {
+            metric(
+                "apt_cache_hit_bytes_total",
+                "counter",
+                "Package bytes the built-in apt cache served instead of a mirror.",
+                &[(String::new(), apt.served_from_cache_bytes() as f64)],
+            );
+        }
+        out
+    }
+
+    /// Renders the build as an OpenTelemetry trace in the OTLP JSON encoding.
+    pub fn otlp_trace(&self) -> Value {
+        let state = self.state();
+        let end = state.end.unwrap_or_else(SystemTime::now);
+        let trace_id = uuid::Uuid::new_v4().simple().to_string();
+        let new_span_id = || uuid::Uuid::new_v4().simple().to_string()[..16].to_string();
+
+        let root_id = new_span_id();
+        let mut apt_attributes = Vec::new();
+        if let Some(apt) = &state.apt {
+            apt_attributes.push(attribute("rsdebstrap.apt.fetched_bytes", apt.fetched_bytes()));
+            apt_attributes
+                .push(attribute("rsdebstrap.apt.cache_hit_bytes", apt.served_from_cache_bytes()));
+        }
+        let mut spans = vec![span(
+            &trace_id,
+            &root_id,
+            None,
+            "rsdebstrap apply",
+            (state.start, end),
+            state.success == Some(true),
+            [
+                vec![
+                    attribute("rsdebstrap.commands", self.commands.load(Ordering::Relaxed)),
+                    attribute(
+                        "rsdebstrap.command_failures",
+                        self.failed_commands.load(Ordering::Relaxed),
+                    ),
+                    attribute(
+                        "rsdebstrap.downloaded_bytes",
+                        self.downloaded_bytes.load(Ordering::Relaxed),
+                    ),
+                ],
+                apt_attributes,
+            ]
+            .concat(),
+        )];
+        for (phase, start, phase_end, ok) in phase_spans(&state.steps) {
+            let phase_id = new_span_id();
+            spans.push(span(
+                &trace_id,
+                &phase_id,
+                Some(&root_id),
+                phase,
+                (start, phase_end),
+                ok,
+                vec![],
+            ));
+            for step in state.steps.iter().filter(|s| s.phase == phase) {
+                spans.push(span(
+                    &trace_id,
+                    &new_span_id(),
+                    Some(&phase_id),
+                    &step.name,
+                    (step.start, step.end.unwrap_or(end)),
+                    step.ok,
+                    vec![
+                        attribute("rsdebstrap.phase", phase),
+                        attribute("rsdebstrap.task.number", step.number as u64),
+                    ],
+                ));
+            }
+        }
+
+        json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [attribute("service.name", "rsdebstrap")],
+                },
+                "scopeSpans": [{
+                    "scope": { "name": "rsdebstrap", "version": env!("CARGO_PKG_VERSION") },
+                    "spans": spans,
+                }],
+            }],
+        })
+    }
+}
+
+impl Progress for BuildMetrics {
+    fn report(&self, event: &ProgressEvent) {
+        let now = SystemTime::now();
+        let mut state = self.state();
+        let (phase, number, name) = match event {
+            ProgressEvent::BootstrapStarted { command } => ("bootstrap", 0, command.clone()),
+            ProgressEvent::TaskStarted {
+                phase,
+                number,
+                name,
+            } => (*phase, *number, name.clone()),
+            ProgressEvent::BootstrapFinished => {
+                finish_step(&mut state.steps, "bootstrap", 0, now);
+                return;
+            }
+            ProgressEvent::TaskFinished { phase, number, .. } => {
+                finish_step(&mut state.steps, phase, *number, now);
+                return;
+            }
+            // Phase spans are derived from their tasks.
+            ProgressEvent::PhaseStarted { .. } | ProgressEvent::PhaseFinished { .. } => return,
+        };
+        state.steps.push(Step {
+            phase,
+            number,
+            name,
+            start: now,
+            end: None,
+            ok: true,
+        });
+    }
+}
+
+fn finish_step(steps: &mut [Step], phase: &str, number: usize, now: SystemTime) {
+    if let Some(step) = steps
+        .iter_mut()
+        .rev()
+        .find(|s| s.phase == phase && s.number == number && s.end.is_none())
+    {
+        step.end = Some(now);
+    }
+}
+
+/// Returns each phase that ran, in order, with the start of its first step, the end of
+/// its last one and whether all its steps succeeded.
+fn phase_spans(steps: &[Step]) -> Vec<(&'static str, SystemTime, SystemTime, bool)> {
+    let mut phases: Vec<(&'static str, SystemTime, SystemTime, bool)> = Vec::new();
+    for step in steps {
+        let end = step.end.unwrap_or(step.start);
+        match phases.iter_mut().find(|(phase, ..)| *phase == step.phase) {
+            Some((_, _, phase_end, ok)) => {
+                *phase_end = (*phase_end).max(end);
+                *ok &= step.ok;
+            }
+            None => phases.push((step.phase, step.start, end, step.ok)),
+        }
+    }
+    phases
+}
+
+fn step_labels(step: &Step) -> String {
+    labels(&[
+        ("phase", step.phase),
+        ("number", &step.number.to_string()),
+        ("task", &step.name),
+    ])
+}
+
+/// Formats Prometheus labels, escaping the values.
+fn labels(pairs: &[(&str, &str)]) -> String {
+    let pairs: Vec<String> = pairs
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", name, value)
+        })
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
+
+fn unix_seconds(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+fn seconds_between(start: SystemTime, end: SystemTime) -> f64 {
+    end.duration_since(start)
+        .unwrap_or(Duration::ZERO)
+        .as_secs_f64()
+}
+
+/// Returns an OTLP JSON key-value attribute.
+fn attribute(key: &str, value: impl Into<AttributeValue>) -> Value {
+    let value = match value.into() {
+        AttributeValue::String(s) => json!({ "stringValue": s }),
+        // OTLP JSON encodes 64-bit integers as strings.
+        AttributeValue::Int(i) => json!({ "intValue": i.to_string() }),
+    };
+    json!({ "key": key, "value": value })
+}
+
+enum AttributeValue {
+    String(String),
+    Int(u64),
+}
+
+impl From<&str> for AttributeValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+impl From<u64> for AttributeValue {
+    fn from(value: u64) -> Self {
+        Self::Int(value)
+    }
+}
+
+/// Returns an OTLP JSON span (kind internal; status OK or ERROR).
+fn span(
+    trace_id: &str,
+    span_id: &str,
+    parent: Option<&str>,
+    name: &str,
+    (start, end): (SystemTime, SystemTime),
+    ok: bool,
+    attributes: Vec<Value>,
+) -> Value {
+    let mut span = json!({
+        "traceId": trace_id,
+        "spanId": span_id,
+        "name": name,
+        "kind": 1,
+        "startTimeUnixNano": unix_nanos(start),
+        "endTimeUnixNano": unix_nanos(end),
+        "attributes": attributes,
+        "status": { "code": if ok { 1 } else { 2 } },
+    });
+    if let Some(parent) = parent {
+        span["parentSpanId"] = json!(parent);
+    }
+    span
+}
+
+fn write_atomically(path: &Utf8Path, content: &[u8]) -> Result<(), RsdebstrapError> {
+    let dir = path.parent().unwrap_or(Utf8Path::new("."));
+    let mut file = tempfile::Builder::new()
+        .prefix(".metrics-")
+        .tempfile_in(dir)
+        .map_err(|e| RsdebstrapError::io(format!("failed to create a file in {}", dir), e))?;
+    file.write_all(content)
+        .map_err(|e| RsdebstrapError::io(format!("failed to write {}", path), e))?;
+    file.persist(path)
+        .map_err(|e| RsdebstrapError::io(format!("failed to write {}", path), e.error))?;
+    Ok(())
+}
+
+/// Command executor that counts the commands run through it, and the bytes of the
+/// files `curl` downloads (see [`crate::download::download_spec`]), into a
+/// [`BuildMetrics`].
+pub struct MeteredExecutor {
+    inner: Arc<dyn CommandExecutor>,
+    metrics: Arc<BuildMetrics>,
+}
+
+impl MeteredExecutor {
+    /// Wraps `inner`, counting into `metrics`.
+    pub fn new(inner: Arc<dyn CommandExecutor>, metrics: Arc<BuildMetrics>) -> Self {
+        Self { inner, metrics }
+    }
+}
+
+impl CommandExecutor for MeteredExecutor {
+    fn execute(&self, spec: &CommandSpec) -> Result<ExecutionResult> {
+        self.metrics.commands.fetch_add(1, Ordering::Relaxed);
+        let result = self.inner.execute(spec);
+        match &result {
+            Ok(result) if result.success() => {
+                // Dry runs (no status) download nothing.
+                if spec.command == "curl"
+                    && result.status.is_some()
+                    && let Some(dest) = spec
+                        .args
+                        .iter()
+                        .position(|a| a == "--output")
+                        .and_then(|i| spec.args.get(i + 1))
+                    && let Ok(metadata) = fs::metadata(dest)
+                {
+                    self.metrics
+                        .downloaded_bytes
+                        .fetch_add(metadata.len(), Ordering::Relaxed);
+                }
+            }
+            _ => {
+                self.metrics.failed_commands.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        result
+    }
+
+    /// Counts each stage as a command, and a failed pipeline as one failed command.
+    fn execute_pipeline(&self, stages: &[CommandSpec]) -> Result<()> {
+        self.metrics
+            .commands
+            .fetch_add(stages.len() as u64, Ordering::Relaxed);
+        let result = self.inner.execute_pipeline(stages);
+        if result.is_err() {
+            self.metrics.failed_commands.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    // Mounts go straight to the wrapped executor, which may make them without running
+    // a command at all; they are not counted.
+    fn mount(
+        &self,
+        entry: &MountEntry,
+        target: &Utf8Path,
+        privilege: Option<PrivilegeMethod>,
+    ) -> Result<()> {
+        self.inner.mount(entry, target, privilege)
+    }
+
+    fn unmount(
+        &self,
+        entry: &MountEntry,
+        target: &Utf8Path,
+        mode: UnmountMode,
+        privilege: Option<PrivilegeMethod>,
+    ) -> Result<()> {
+        self.inner.unmount(entry, target, mode, privilege)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    /// A task name that needs escaping as a Prometheus label value.
+    const SETUP: &str = "shell:\"setup\".sh";
+
+    /// Succeeds for every command except `false`; `curl` writes `hello\n` to its
+    /// `--output` file.
+    struct FakeExecutor;
+
+    impl CommandExecutor for FakeExecutor {
+        fn execute(&self, spec: &CommandSpec) -> Result<ExecutionResult> {
+            if spec.command == "curl" {
+                let dest = &spec.args[spec.args.iter().position(|a| a == "--output").unwrap() + 1];
+                fs::write(dest, "hello\n").unwrap();
+            }
+            let code = if spec.command == "false" { 1 } else { 0 };
+            Ok(ExecutionResult {
+                status: Some(ExitStatus::from_raw(code << 8)),
+                details: None,
+            })
+        }
+    }
+
+    fn started(phase: &'static str, number: usize, name: String) -> ProgressEvent {
+        ProgressEvent::TaskStarted {
+            phase,
+            number,
+            name,
+        }
+    }
+
+    fn finished(phase: &'static str, number: usize, name: String) -> ProgressEvent {
+        ProgressEvent::TaskFinished {
+            phase,
+            number,
+            name,
+        }
+    }
+
+    /// Records a build that bootstraps, runs one provision task, runs `true`, `false`
+    /// and a 6-byte download, then fails in its second provision task.
+    fn failed_build(dir: &Utf8Path) -> Arc<BuildMetrics> {
+        let metrics = Arc::new(BuildMetrics::new());
+        let executor = MeteredExecutor::new(Arc::new(FakeExecutor), Arc::clone(&metrics));
+        metrics.report(&ProgressEvent::BootstrapStarted {
+            command: "mmdebstrap".to_string(),
+        });
+        metrics.report(&ProgressEvent::BootstrapFinished);
+        metrics.report(&started("provision", 1, SETUP.to_string()));
+        executor
+            .execute_checked(&CommandSpec::new("true", vec![]))
+            .unwrap();
+        executor
+            .execute_checked(&CommandSpec::new("false", vec![]))
+            .unwrap_err();
+        let dest = dir.join("keyring.gpg");
+        executor
+            .execute_checked(&crate::download::download_spec("https://example.org/k", &dest))
+            .unwrap();
+        metrics.report(&finished("provision", 1, SETUP.to_string()));
+        metrics.report(&started("provision", 2, "mitamae".to_string()));
+        metrics.finish(false);
+        metrics
+    }
+
+    #[test]
+    fn prometheus_reports_steps_commands_and_downloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        let text = failed_build(dir).prometheus();
+
+        for line in [
+            "# TYPE rsdebstrap_build_success gauge",
+            "rsdebstrap_build_success 0",
+            "rsdebstrap_commands_total 3",
+            "rsdebstrap_command_failures_total 1",
+            "rsdebstrap_downloaded_bytes_total{source=\"files\"} 6",
+            "rsdebstrap_task_success{phase=\"bootstrap\",number=\"0\",task=\"mmdebstrap\"} 1",
+            "rsdebstrap_task_success{phase=\"provision\",number=\"1\",task=\"shell:\\\"setup\\\".sh\"} 1",
+            "rsdebstrap_task_success{phase=\"provision\",number=\"2\",task=\"mitamae\"} 0",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {line:?} in\n{text}");
+        }
+        assert!(text.contains("rsdebstrap_phase_duration_seconds{phase=\"provision\"} "));
+        assert!(!text.contains("apt_cache_hit"));
+    }
+
+    #[test]
+    fn otlp_trace_nests_tasks_under_phases() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        let trace = failed_build(dir).otlp_trace();
+        let spans = trace["resourceSpans"][0]["scopeSpans"][0]["spans"]
+            .as_array()
+            .unwrap();
+        let names: Vec<&str> = spans.iter().map(|s| s["name"].as_str().unwrap()).collect();
+        assert_eq!(
+            names,
+            [
+                "rsdebstrap apply",
+                "bootstrap",
+                "mmdebstrap",
+                "provision",
+                "shell:\"setup\".sh",
+                "mitamae"
+            ]
+        );
+        let root = &spans[0];
+        assert_eq!(root["status"]["code"], 2);
+        assert!(root.get("parentSpanId").is_none());
+        assert_eq!(spans[3]["parentSpanId"], root["spanId"]);
+        assert_eq!(spans[4]["parentSpanId"], spans[3]["spanId"]);
+        assert_eq!(spans[4]["status"]["code"], 1);
+        assert_eq!(spans[5]["status"]["code"], 2);
+        for span in spans {
+            assert_eq!(span["traceId"], root["traceId"]);
+            assert_eq!(span["traceId"].as_str().unwrap().len(), 32);
+            assert_eq!(span["spanId"].as_str().unwrap().len(), 16);
+        }
+    }
+
+    #[test]
+    fn summary_times_phases_and_tasks() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        let summary = failed_build(dir).summary();
+
+        assert!(!summary.success);
+        let phases: Vec<_> = summary
+            .phases
+            .iter()
+            .map(|p| (p.phase, p.success))
+            .collect();
+        assert_eq!(phases, [("bootstrap", true), ("provision", false)]);
+        let tasks: Vec<_> = summary
+            .tasks
+            .iter()
+            .map(|t| (t.phase, t.number, t.success))
+            .collect();
+        assert_eq!(
+            tasks,
+            [
+                ("bootstrap", 0, true),
+                ("provision", 1, true),
+                ("provision", 2, false)
+            ]
+        );
+        assert!(summary.duration_secs >= summary.phases[0].duration_secs);
+    }
+
+    #[test]
+    fn write_creates_one_file_per_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        let metrics = failed_build(dir);
+        let paths = metrics
+            .write(dir, &[MetricsFormat::Prometheus, MetricsFormat::Otel])
+            .unwrap();
+        assert_eq!(
+            paths,
+            [
+                dir.join("rsdebstrap.prom"),
+                dir.join("rsdebstrap-trace.json")
+            ]
+        );
+        let trace: Value = serde_json::from_str(&fs::read_to_string(&paths[1]).unwrap()).unwrap();
+        assert!(trace["resourceSpans"].is_array());
+    }
+}
@@ -0,0 +1,455 @@
+//! Profile format migration, for the `migrate` subcommand.
+//!
+//! [`migrate_profile`] rewrites a profile written for an older format into the current
+//! one ([`PROFILE_SCHEMA_VERSION`]). The only older format is the legacy layout
+//! (schema version 0) whose phases were lists:
+//!
+//! - `pre_processors: [{type: mount, ...}, {type: resolv_conf, ...}]` becomes the
+//!   named-field `prepare: {mount: {...}, resolv_conf: {...}}`
+//! - `provisioners: [...]` becomes `provision: [...]` (the tasks are unchanged)
+//! - `post_processors: [{type: resolv_conf, ...}, ...]` becomes `assemble: {...}`
+//!
+//! and declares `schema_version: 1`. The rewrite works on the text line by line, so
+//! comments and formatting survive; a phase list that is not in plain block style is
+//! converted through a parsed YAML value instead, which drops the comments.
+
+use tracing::warn;
+
+use crate::config::{PROFILE_SCHEMA_VERSION, Profile};
+use crate::error::RsdebstrapError;
+
+/// Top-level keys of the legacy layout.
+pub(crate) const LEGACY_KEYS: &[&str] = &["pre_processors", "provisioners", "post_processors"];
+
+/// The legacy phase lists and what replaced them: the new key, and the task types the
+/// new named-field phase accepts (`None` for a list that stays a list).
+const PHASES: &[(&str, &str, Option<&[&str]>)] = &[
+    ("pre_processors", "prepare", Some(&["mount", "resolv_conf"])),
+    ("provisioners", "provision", None),
+    (
+        "post_processors",
+        "assemble",
+        Some(&["resolv_conf", "sanitize", "clean", "release"]),
+    ),
+];
+
+/// A profile rewritten by [`migrate_profile`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    /// The schema version the profile was written for.
+    pub from_version: u32,
+    /// The profile in the current format.
+    pub yaml: String,
+    /// False if a phase had to be converted through a parsed value, losing comments.
+    pub comments_preserved: bool,
+}
+
+impl Migration {
+    /// Returns true if the profile already was in the current format.
+    pub fn is_unchanged(&self) -> bool {
+        self.from_version == PROFILE_SCHEMA_VERSION
+    }
+}
+
+/// Rewrites `yaml` into the current profile format.
+///
+/// A profile already in the current format comes back unchanged, with
+/// `schema_version` added if it did not declare one.
+///
+/// # Errors
+///
+/// Returns [`RsdebstrapError::Config`] if `yaml` is not a YAML mapping, declares a
+/// `schema_version` newer than supported, mixes a legacy key with its replacement, or
+/// has a legacy phase list the new format cannot express (an entry without `type`, an
+/// unknown type, or a type given twice), or if the result does not parse as a profile.
+pub fn migrate_profile(yaml: &str) -> Result<Migration, RsdebstrapError> {
+    let value: yaml_serde::Value = yaml_serde::from_str(yaml)
+        .map_err(|e| RsdebstrapError::Config(format!("YAML parse error: {}", e)))?;
+    let Some(root) = value.as_mapping() else {
+        return Err(RsdebstrapError::Config("a profile must be a YAML mapping".to_string()));
+    };
+    let declared = match root.get("schema_version") {
+        None => None,
+        Some(v) => Some(
+            v.as_u64()
+                .and_then(|v| u32::try_from(v).ok())
+                .ok_or_else(|| {
+                    RsdebstrapError::Config(
+                        "schema_version must be a non-negative integer".to_string(),
+                    )
+                })?,
+        ),
+    };
+    if let Some(version) = declared
+        && version > PROFILE_SCHEMA_VERSION
+    {
+        return Err(RsdebstrapError::Config(format!(
+            "profile schema_version {} is newer than this rsdebstrap supports ({})",
+            version, PROFILE_SCHEMA_VERSION
+        )));
+    }
+    let legacy = LEGACY_KEYS.iter().any(|key| root.contains_key(*key));
+    let from_version = if legacy {
+        0
+    } else {
+        declared.unwrap_or(PROFILE_SCHEMA_VERSION)
+    };
+
+    let mut migration = Migration {
+        from_version,
+        yaml: yaml.to_string(),
+        comments_preserved: true,
+    };
+    if legacy {
+        for &(old, new, types) in PHASES {
+            if root.contains_key(old) && root.contains_key(new) {
+                return Err(RsdebstrapError::Config(format!(
+                    "profile has both `{}` and its replacement `{}`; merge them by hand",
+                    old, new
+                )));
+            }
+            if let Some(types) = types
+                && let Some(list) = root.get(old)
+            {
+                check_phase_list(old, list, types)?;
+            }
+        }
+        match rewrite_text(&migration.yaml) {
+            Some(text) => migration.yaml = text,
+            None => {
+                warn!("a legacy phase list is not in block style; comments are not preserved");
+                migration.yaml = rewrite_value(root)?;
+                migration.comments_preserved = false;
+            }
+        }
+    }
+    if declared != Some(PROFILE_SCHEMA_VERSION) {
+        migration.yaml = set_schema_version(&migration.yaml);
+    }
+
+    yaml_serde::from_str::<Profile>(&migration.yaml).map_err(|e| {
+        RsdebstrapError::Config(format!("the migrated profile does not parse: {}", e))
+    })?;
+    Ok(migration)
+}
+
+/// Checks that a legacy phase list maps onto a named-field phase.
+fn check_phase_list(
+    key: &str,
+    list: &yaml_serde::Value,
+    types: &[&str],
+) -> Result<(), RsdebstrapError> {
+    let Some(items) = list.as_sequence() else {
+        return Err(RsdebstrapError::Config(format!("`{}` must be a list", key)));
+    };
+    let mut seen = Vec::new();
+    for (index, item) in items.iter().enumerate() {
+        let Some(task_type) = item.get("type").and_then(yaml_serde::Value::as_str) else {
+            return Err(RsdebstrapError::Config(format!("{}[{}] has no `type`", key, index)));
+        };
+        if !types.contains(&task_type) {
+            return Err(RsdebstrapError::Config(format!(
+                "{}[{}] has type `{}`, which the new phase does not have (expected one of: {})",
+                key,
+                index,
+                task_type,
+                types.join(", ")
+            )));
+        }
+        if seen.contains(&task_type) {
+            return Err(RsdebstrapError::Config(format!(
+                "{} has more than one `{}` entry; the new phase takes one of each",
+                key, task_type
+            )));
+        }
+        seen.push(task_type);
+    }
+    Ok(())
+}
+
+/// Rewrites the legacy phases in the text, keeping comments. Returns `None` if a
+/// phase list is not a plain block sequence of block mappings.
+fn rewrite_text(yaml: &str) -> Option<String> {
+    let lines: Vec<&str> = yaml.lines().collect();
+    let mut out = Vec::with_capacity(lines.len() + 1);
+    let mut i = 0;
+    while i < lines.len() {
+        let line = lines[i];
+        let Some(&(old, new, types)) = PHASES
+            .iter()
+            .find(|(old, _, _)| top_level_key(line) == Some(*old))
+        else {
+            out.push(line.to_string());
+            i += 1;
+            continue;
+        };
+        let rest = &line[old.len() + 1..];
+        out.push(format!("{}:{}", new, rest));
+        i += 1;
+        let end = (i..lines.len())
+            .find(|&j| top_level_key(lines[j]).is_some() || lines[j] == "---")
+            .unwrap_or(lines.len());
+        if types.is_none() {
+            out.extend(lines[i..end].iter().map(|l| l.to_string()));
+        } else {
+            if !is_blank_or_comment(rest) {
+                return None;
+            }
+            out.extend(rewrite_phase_list(&lines[i..end])?);
+        }
+        i = end;
+    }
+    let mut text = out.join("\n");
+    if yaml.ends_with('\n') {
+        text.push('\n');
+    }
+    Some(text)
+}
+
+/// Turns the lines of a block sequence of `type:`-tagged mappings into a mapping
+/// keyed by type.
+fn rewrite_phase_list(lines: &[&str]) -> Option<Vec<String>> {
+    let dash_indent = lines
+        .iter()
+        .find(|l| !is_blank_or_comment(l))
+        .map(|l| indent(l))?;
+    let item_start = |l: &str| indent(l) == dash_indent && l[dash_indent..].starts_with("- ");
+
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        if !item_start(lines[i]) {
+            if !is_blank_or_comment(lines[i]) {
+                return None;
+            }
+            out.push(lines[i].to_string());
+            i += 1;
+            continue;
+        }
+        let end = (i + 1..lines.len())
+            .find(|&j| {
+                item_start(lines[j])
+                    || (!is_blank_or_comment(lines[j]) && indent(lines[j]) <= dash_indent)
+            })
+            .unwrap_or(lines.len());
+        if end < lines.len() && !item_start(lines[end]) {
+            return None;
+        }
+        // The item as a block mapping at `content`: the dash becomes indentation.
+        let content = dash_indent + 2;
+        let mut item: Vec<String> = lines[i..end].iter().map(|l| l.to_string()).collect();
+        item[0] = format!("{}{}", " ".repeat(content), &lines[i][content..]);
+        let type_line = item
+            .iter()
+            .position(|l| indent(l) == content && l[content..].starts_with("type:"))?;
+        let task_type = item.remove(type_line)[content + "type:".len()..]
+            .split('#')
+            .next()?
+            .trim()
+            .trim_matches(|c| c == '"' || c == '\'')
+            .to_string();
+        if task_type.is_empty() {
+            return None;
+        }
+        let fields = item.iter().any(|l| !is_blank_or_comment(l));
+        out.push(format!(
+            "{}{}:{}",
+            " ".repeat(content),
+            task_type,
+            if fields { "" } else { " {}" }
+        ));
+        out.extend(item.into_iter().map(|l| {
+            if l.trim().is_empty() {
+                l
+            } else {
+                format!("  {}", l)
+            }
+        }));
+        i = end;
+    }
+    Some(out)
+}
+
+/// Rewrites the legacy phases through a parsed value (comments are lost).
+fn rewrite_value(root: &yaml_serde::Mapping) -> Result<String, RsdebstrapError> {
+    let mut migrated = yaml_serde::Mapping::new();
+    for (key, value) in root {
+        let phase = key
+            .as_str()
+            .and_then(|key| PHASES.iter().find(|(old, _, _)| *old == key));
+        match phase {
+            Some(&(_, new, None)) => {
+                migrated.insert(new.into(), value.clone());
+            }
+            Some(&(_, new, Some(_))) => {
+                let mut phase = yaml_serde::Mapping::new();
+                for item in value.as_sequence().into_iter().flatten() {
+                    let mut fields = item.as_mapping().cloned().unwrap_or_default();
+                    if let Some(task_type) = fields.remove("type") {
+                        phase.insert(task_type, fields.into());
+                    }
+                }
+                migrated.insert(new.into(), phase.into());
+            }
+            None => {
+                migrated.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    yaml_serde::to_string(&migrated)
+        .map_err(|e| RsdebstrapError::Config(format!("failed to render the profile: {}", e)))
+}
+
+/// Sets the top-level `schema_version` to the current version, adding it before the
+/// first top-level key if missing.
+fn set_schema_version(yaml: &str) -> String {
+    let line = format!("schema_version: {}", PROFILE_SCHEMA_VERSION);
+    let mut lines: Vec<String> = yaml.lines().map(str::to_string).collect();
+    if let Some(existing) = lines
+        .iter_mut()
+        .find(|l| top_level_key(l) == Some("schema_version"))
+    {
+        *existing = line;
+    } else {
+        let at = lines
+            .iter()
+            .position(|l| top_level_key(l).is_some())
+            .unwrap_or(lines.len());
+        lines.insert(at, line);
+    }
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+/// Returns the key a top-level `key:` line starts, if it is one.
+fn top_level_key(line: &str) -> Option<&str> {
+    let (key, _) = line.split_once(':')?;
+    let plain = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        && !key.starts_with('-');
+    plain.then_some(key)
+}
+
+fn is_blank_or_comment(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.is_empty() || trimmed.starts_with('#')
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // editorconfig-checker-disable
+    const LEGACY: &str = "\
+---
+# Web server image
+dir: /tmp/web
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+pre_processors:
+# Mount the usual pseudo filesystems
+- type: mount
+  preset: recommends
+- type: resolv_conf
+  copy: true # use the host's resolvers
+provisioners:
+- type: shell
+  name: setup
+  content: echo setup
+post_processors:
+  - type: resolv_conf
+    name_servers: [1.1.1.1]
+  - type: clean
+";
+
+    const CURRENT: &str = "\
+---
+# Web server image
+schema_version: 1
+dir: /tmp/web
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+prepare:
+# Mount the usual pseudo filesystems
+  mount:
+    preset: recommends
+  resolv_conf:
+    copy: true # use the host's resolvers
+provision:
+- type: shell
+  name: setup
+  content: echo setup
+assemble:
+    resolv_conf:
+      name_servers: [1.1.1.1]
+    clean: {}
+";
+    // editorconfig-checker-enable
+
+    #[test]
+    fn rewrites_legacy_phases_keeping_comments() {
+        let migration = migrate_profile(LEGACY).unwrap();
+        assert_eq!(migration.from_version, 0);
+        assert!(migration.comments_preserved);
+        assert_eq!(migration.yaml, CURRENT);
+    }
+
+    #[test]
+    fn flow_style_lists_are_converted_without_comments() {
+        let legacy = "dir: /tmp/web\n\
+            bootstrap: {type: mmdebstrap, suite: trixie, target: rootfs}\n\
+            pre_processors: [{type: mount, preset: recommends}] # pseudo filesystems\n";
+        let migration = migrate_profile(legacy).unwrap();
+        assert!(!migration.comments_preserved);
+        let profile: Profile = yaml_serde::from_str(&migration.yaml).unwrap();
+        let expected: Profile = yaml_serde::from_str(
+            "dir: /tmp/web\nbootstrap: {type: mmdebstrap, suite: trixie, target: rootfs}\n\
+            prepare: {mount: {preset: recommends}}\n",
+        )
+        .unwrap();
+        assert_eq!(
+            Profile {
+                schema_version: None,
+                ..profile
+            },
+            expected
+        );
+    }
+
+    #[test]
+    fn current_profiles_only_gain_schema_version() {
+        let current =
+            "dir: /tmp/web\nbootstrap: {type: mmdebstrap, suite: trixie, target: rootfs}\n";
+        let migration = migrate_profile(current).unwrap();
+        assert!(migration.is_unchanged());
+        assert_eq!(migration.yaml, format!("schema_version: 1\n{}", current));
+
+        let migration = migrate_profile(&migration.yaml).unwrap();
+        assert_eq!(migration.yaml, format!("schema_version: 1\n{}", current));
+    }
+
+    #[test]
+    fn rejects_what_the_new_format_cannot_express() {
+        let base = "dir: /tmp/web\nbootstrap: {type: mmdebstrap, suite: trixie, target: rootfs}\n";
+        for (extra, expected) in [
+            ("pre_processors:\n- type: shell\n", "does not have"),
+            ("post_processors:\n- type: clean\n- type: clean\n", "more than one `clean`"),
+            ("provisioners: []\nprovision: []\n", "merge them by hand"),
+            ("schema_version: 2\n", "newer"),
+        ] {
+            let err = migrate_profile(&format!("{}{}", base, extra)).unwrap_err();
+            assert!(err.to_string().contains(expected), "{}: {}", extra, err);
+        }
+    }
+}
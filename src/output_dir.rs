@@ -0,0 +1,498 @@
+//! Safety checks for the profile's output directory.
+//!
+//! Privileged steps write to and clean up below `dir`, so a typo there (`dir: /`, or a
+//! relative path that resolves to the filesystem root) could damage the host.
+//! [`validate_dir`] rejects the root, the top-level system directories, and any path
+//! that is the host's root filesystem under another name (a bind mount or symlink of
+//! `/`), whether or not `dir` exists yet.
+//!
+//! The profile's `dir_policy` can additionally require the directory to belong to
+//! rsdebstrap: `apply` marks a directory it creates, or finds empty, with a
+//! [`MARKER`] file, and with `dir_policy: owned` refuses an existing non-empty
+//! directory without one unless `--allow-existing` is given ([`check_policy`]).
+//!
+//! A build run with privilege leaves `dir` and the rootfs owned by root, which a CI
+//! user cannot remove. The profile's `output_permissions` hands them over at the end
+//! of the build ([`OutputPermissions::apply`]): it changes the owner of everything in
+//! `dir` and/or in the rootfs, and the mode of the two directories themselves.
+
+use std::fmt;
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::str::FromStr;
+
+use anyhow::Result;
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+
+use crate::error::RsdebstrapError;
+use crate::executor::{CommandExecutor, CommandSpec};
+use crate::isolation::mount::find_stale_mounts;
+use crate::lock::DIR_LOCK;
+use crate::privilege::PrivilegeMethod;
+
+/// File in `dir` marking it as created by rsdebstrap.
+pub const MARKER: &str = ".rsdebstrap-dir";
+
+/// Top-level host directories `dir` must never be.
+const SYSTEM_DIRS: &[&str] = &[
+    "/bin", "/boot", "/dev", "/etc", "/home", "/lib", "/lib32", "/lib64", "/opt", "/proc", "/root",
+    "/run", "/sbin", "/srv", "/sys", "/usr", "/var",
+];
+
+/// Which existing output directories `apply` may build into.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum DirPolicy {
+    /// Any directory (default).
+    #[default]
+    Any,
+    /// Only a missing or empty directory, or one marked as created by rsdebstrap.
+    Owned,
+}
+
+impl DirPolicy {
+    /// Returns true for the default policy, so it is left out of serialized profiles.
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Final owner and mode of `dir` and the rootfs (`output_permissions`).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct OutputPermissions {
+    /// Owner and mode of `dir` and everything in it except the rootfs (optional).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathPermissions>,
+    /// Owner and mode of the rootfs directory (optional).
+    ///
+    /// An `owner` here replaces the owner of every file in the rootfs, so only set it
+    /// for a rootfs that is not used as a system afterwards (e.g. once packed into an
+    /// image). Ignored when the bootstrap writes an archive or image.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rootfs: Option<PathPermissions>,
+}
+
+/// Owner and mode of a directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct PathPermissions {
+    /// Owner of the directory and everything below it (optional): `invoking` for the
+    /// user who ran rsdebstrap (`SUDO_UID`/`SUDO_GID` under sudo), or `USER[:GROUP]`
+    /// with names or numeric ids.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+    pub owner: Option<Owner>,
+    /// Mode of the directory itself, in octal, e.g. `"0755"` (optional).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<crate::schema::FileModeSchema>")
+    )]
+    pub mode: Option<FileMode>,
+}
+
+/// Owner given to files, as `chown` takes it.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Owner {
+    /// The user who ran rsdebstrap, and their primary group.
+    Invoking,
+    /// `USER[:GROUP]`, with names or numeric ids.
+    Named(String),
+}
+
+impl Owner {
+    /// Returns the `chown` argument for this owner. [`Owner::Invoking`] is the user
+    /// sudo ran rsdebstrap for, or else the current user.
+    pub fn resolve(&self) -> String {
+        match self {
+            Self::Named(owner) => owner.clone(),
+            Self::Invoking => {
+                let sudo = |name| std::env::var(name).ok().filter(|id| !id.is_empty());
+                match (sudo("SUDO_UID"), sudo("SUDO_GID")) {
+                    (Some(uid), Some(gid)) => format!("{}:{}", uid, gid),
+                    _ => format!(
+                        "{}:{}",
+                        rustix::process::getuid().as_raw(),
+                        rustix::process::getgid().as_raw()
+                    ),
+                }
+            }
+        }
+    }
+}
+
+impl TryFrom<String> for Owner {
+    type Error = String;
+
+    fn try_from(owner: String) -> Result<Self, Self::Error> {
+        if owner == "invoking" {
+            return Ok(Self::Invoking);
+        }
+        let valid_name = |name: &str| {
+            !name.is_empty()
+                && !name.starts_with('-')
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        };
+        let (user, group) = match owner.split_once(':') {
+            Some((user, group)) => (user, Some(group)),
+            None => (owner.as_str(), None),
+        };
+        if !valid_name(user) || group.is_some_and(|g| !valid_name(g)) {
+            return Err(format!("invalid owner '{}': expected 'invoking' or USER[:GROUP]", owner));
+        }
+        Ok(Self::Named(owner))
+    }
+}
+
+impl From<Owner> for String {
+    fn from(owner: Owner) -> Self {
+        match owner {
+            Owner::Invoking => "invoking".to_string(),
+            Owner::Named(owner) => owner,
+        }
+    }
+}
+
+/// A file mode, written in YAML as an octal string (`"0755"`) or as a number whose
+/// digits are read as octal (`755`, so an unquoted `0755` also works).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "ModeWire", into = "String")]
+pub struct FileMode(pub u32);
+
+/// The accepted YAML shapes of a [`FileMode`].
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ModeWire {
+    Digits(u32),
+    Text(String),
+}
+
+impl TryFrom<ModeWire> for FileMode {
+    type Error = String;
+
+    fn try_from(wire: ModeWire) -> Result<Self, Self::Error> {
+        match wire {
+            ModeWire::Digits(digits) => digits.to_string().parse(),
+            ModeWire::Text(text) => text.parse(),
+        }
+    }
+}
+
+impl FromStr for FileMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s.strip_prefix("0o").unwrap_or(s);
+        match u32::from_str_radix(digits, 8) {
+            Ok(mode) if !digits.is_empty() && mode <= 0o7777 => Ok(Self(mode)),
+            _ => Err(format!("invalid mode '{}': expected octal digits, e.g. \"0755\"", s)),
+        }
+    }
+}
+
+impl fmt::Display for FileMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04o}", self.0)
+    }
+}
+
+impl From<FileMode> for String {
+    fn from(mode: FileMode) -> Self {
+        mode.to_string()
+    }
+}
+
+impl OutputPermissions {
+    /// Returns the commands setting the permissions, in order: the rootfs's owner and
+    /// mode, then those of `dir`. `rootfs` is the rootfs directory, if the bootstrap
+    /// writes one; the owner of `dir` is applied around it.
+    ///
+    /// Owners are changed with `find -xdev`, which neither follows symlinks nor enters
+    /// other filesystems.
+    pub fn commands(
+        &self,
+        dir: &Utf8Path,
+        rootfs: Option<&Utf8Path>,
+    ) -> Vec<(String, Vec<String>)> {
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        let mut commands = Vec::new();
+        if let (Some(rootfs), Some(permissions)) = (rootfs, &self.rootfs) {
+            if let Some(owner) = &permissions.owner {
+                let owner = owner.resolve();
+                commands.push((
+                    "find".to_string(),
+                    args(&[
+                        rootfs.as_str(),
+                        "-xdev",
+                        "-exec",
+                        "chown",
+                        "-h",
+                        &owner,
+                        "{}",
+                        "+",
+                    ]),
+                ));
+            }
+            if let Some(mode) = permissions.mode {
+                commands.push(("chmod".to_string(), args(&[&mode.to_string(), rootfs.as_str()])));
+            }
+        }
+        if let Some(permissions) = &self.dir {
+            if let Some(owner) = &permissions.owner {
+                let owner = owner.resolve();
+                let mut find = args(&[dir.as_str(), "-xdev"]);
+                if let Some(rootfs) = rootfs {
+                    find.extend(args(&["-path", rootfs.as_str(), "-prune", "-o"]));
+                }
+                find.extend(args(&["-exec", "chown", "-h", &owner, "{}", "+"]));
+                commands.push(("find".to_string(), find));
+            }
+            if let Some(mode) = permissions.mode {
+                commands.push(("chmod".to_string(), args(&[&mode.to_string(), dir.as_str()])));
+            }
+        }
+        commands
+    }
+
+    /// Sets the permissions of `dir` and the `rootfs` directory through `executor` with
+    /// `privilege`; in dry-run mode the executor only logs the commands.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a filesystem is still mounted below the rootfs (its files
+    /// would be changed too), or if a command fails.
+    pub fn apply(
+        &self,
+        dir: &Utf8Path,
+        rootfs: Option<&Utf8Path>,
+        executor: &dyn CommandExecutor,
+        privilege: Option<PrivilegeMethod>,
+        dry_run: bool,
+    ) -> Result<()> {
+        if !dry_run && !dir.is_dir() {
+            info!("not setting output permissions: {} does not exist", dir);
+            return Ok(());
+        }
+        let rootfs = rootfs.filter(|rootfs| dry_run || rootfs.is_dir());
+        if let Some(rootfs) = rootfs {
+            let stale = find_stale_mounts(rootfs)?;
+            if !stale.is_empty() {
+                return Err(RsdebstrapError::Validation(format!(
+                    "{} filesystem(s) are still mounted under {}; not changing its permissions",
+                    stale.len(),
+                    rootfs
+                ))
+                .into());
+            }
+        }
+        for (command, args) in self.commands(dir, rootfs) {
+            let spec = CommandSpec::new(command, args).with_privilege(privilege);
+            executor.execute_checked(&spec)?;
+        }
+        Ok(())
+    }
+}
+
+/// Normalizes `path` lexically: drops `.` components and resolves `..` against the
+/// preceding component, never above the root.
+fn normalize(path: &Utf8Path) -> Utf8PathBuf {
+    let mut normalized = Utf8PathBuf::new();
+    for component in path.components() {
+        match component {
+            Utf8Component::CurDir => {}
+            Utf8Component::ParentDir => {
+                if !normalized.pop() && !normalized.has_root() {
+                    normalized.push("..");
+                }
+            }
+            other => normalized.push(other.as_str()),
+        }
+    }
+    normalized
+}
+
+/// Rejects `path` if it names the root or a top-level system directory.
+fn check_path(dir: &Utf8Path, path: &Utf8Path) -> Result<(), RsdebstrapError> {
+    if path == "/" {
+        return Err(RsdebstrapError::Validation(format!(
+            "dir must not be the filesystem root: {}",
+            dir
+        )));
+    }
+    if SYSTEM_DIRS.contains(&path.as_str()) {
+        return Err(RsdebstrapError::Validation(format!(
+            "dir must not be the host system directory {}: {}",
+            path, dir
+        )));
+    }
+    Ok(())
+}
+
+/// Checks that `dir` is safe to build into.
+///
+/// # Errors
+///
+/// Returns [`RsdebstrapError::Validation`] if `dir` is, as written or with symlinks
+/// resolved, `/` or a top-level system directory such as `/usr`, or if it is the host's
+/// root filesystem mounted elsewhere.
+pub fn validate_dir(dir: &Utf8Path) -> Result<(), RsdebstrapError> {
+    check_path(dir, &normalize(dir))?;
+    let Ok(canonical) = dir.canonicalize_utf8() else {
+        // Not created yet; nothing else can alias the root.
+        return Ok(());
+    };
+    check_path(dir, &canonical)?;
+    if let (Ok(meta), Ok(root)) = (fs::metadata(&canonical), fs::metadata("/"))
+        && (meta.dev(), meta.ino()) == (root.dev(), root.ino())
+    {
+        return Err(RsdebstrapError::Validation(format!(
+            "dir is the host root filesystem mounted at another path: {}",
+            dir
+        )));
+    }
+    Ok(())
+}
+
+/// Checks `dir` against `policy` and returns whether rsdebstrap may claim it by
+/// writing the [`MARKER`]: true if it is missing or empty (but for its lock file),
+/// or already marked.
+///
+/// `allow_existing` (`--allow-existing`) accepts a non-empty unmarked directory under
+/// any policy, without claiming it.
+///
+/// # Errors
+///
+/// Returns [`RsdebstrapError::Validation`] if `policy` is [`DirPolicy::Owned`] and
+/// `dir` is a non-empty directory without the marker, or [`RsdebstrapError::Io`] if it
+/// cannot be read.
+pub fn check_policy(
+    dir: &Utf8Path,
+    policy: DirPolicy,
+    allow_existing: bool,
+) -> Result<bool, RsdebstrapError> {
+    let mut entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(true),
+        Err(e) => return Err(RsdebstrapError::io(format!("failed to read {}", dir), e)),
+    };
+    // The build's own lock file, taken before this check, does not count.
+    let empty = !entries.any(|entry| entry.map_or(true, |e| e.file_name() != DIR_LOCK));
+    if empty || dir.join(MARKER).is_file() {
+        return Ok(true);
+    }
+    if policy == DirPolicy::Owned && !allow_existing {
+        return Err(RsdebstrapError::Validation(format!(
+            "{} already exists, is not empty and was not created by rsdebstrap \
+            (dir_policy: owned); check `dir`, or pass --allow-existing to build into it",
+            dir
+        )));
+    }
+    Ok(false)
+}
+
+/// Marks `dir` as created by rsdebstrap.
+pub fn write_marker(dir: &Utf8Path) -> Result<(), RsdebstrapError> {
+    let path = dir.join(MARKER);
+    fs::write(&path, "This directory was created by rsdebstrap.\n")
+        .map_err(|e| RsdebstrapError::io(format!("failed to write {}", path), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_resolves_dot_components() {
+        assert_eq!(normalize(Utf8Path::new("/tmp/../")), "/");
+        assert_eq!(normalize(Utf8Path::new("/./usr/local/..")), "/usr");
+        assert_eq!(normalize(Utf8Path::new("/../..")), "/");
+        assert_eq!(normalize(Utf8Path::new("../out/./rootfs")), "../out/rootfs");
+    }
+
+    #[test]
+    fn root_and_system_dirs_are_rejected() {
+        for dir in ["/", "//", "/tmp/..", "/usr", "/etc/", "/var/lib/.."] {
+            let err = validate_dir(Utf8Path::new(dir)).unwrap_err();
+            assert!(matches!(err, RsdebstrapError::Validation(_)), "{dir}: {err}");
+        }
+        for dir in [
+            "/tmp/rsdebstrap-out",
+            "/var/tmp/build",
+            "/home/user/images",
+            "out",
+        ] {
+            validate_dir(Utf8Path::new(dir)).unwrap();
+        }
+    }
+
+    #[test]
+    fn owner_accepts_invoking_and_user_group() {
+        assert_eq!(Owner::try_from("invoking".to_string()), Ok(Owner::Invoking));
+        assert_eq!(Owner::try_from("ci:1000".to_string()), Ok(Owner::Named("ci:1000".to_string())));
+        for owner in ["", "-R", "ci:", "ci user", "ci:a:b"] {
+            assert!(Owner::try_from(owner.to_string()).is_err(), "{owner:?}");
+        }
+    }
+
+    #[test]
+    fn mode_parses_octal_strings_and_numbers() {
+        let mode: PathPermissions = yaml_serde::from_str("mode: 0755").unwrap();
+        assert_eq!(mode.mode, Some(FileMode(0o755)));
+        let mode: PathPermissions = yaml_serde::from_str("mode: \"2775\"").unwrap();
+        assert_eq!(mode.mode, Some(FileMode(0o2775)));
+        assert!(yaml_serde::from_str::<PathPermissions>("mode: 0789").is_err());
+        assert!(yaml_serde::from_str::<PathPermissions>("mode: 17777").is_err());
+        assert_eq!(FileMode(0o755).to_string(), "0755");
+    }
+
+    #[test]
+    fn commands_change_the_rootfs_then_the_rest_of_dir() {
+        let permissions = OutputPermissions {
+            dir: Some(PathPermissions {
+                owner: Some(Owner::Named("ci:ci".to_string())),
+                mode: Some(FileMode(0o755)),
+            }),
+            rootfs: Some(PathPermissions {
+                owner: None,
+                mode: Some(FileMode(0o700)),
+            }),
+        };
+        let commands: Vec<String> = permissions
+            .commands(Utf8Path::new("/out"), Some(Utf8Path::new("/out/rootfs")))
+            .into_iter()
+            .map(|(command, args)| format!("{} {}", command, args.join(" ")))
+            .collect();
+        assert_eq!(
+            commands,
+            [
+                "chmod 0700 /out/rootfs",
+                "find /out -xdev -path /out/rootfs -prune -o -exec chown -h ci:ci {} +",
+                "chmod 0755 /out",
+            ]
+        );
+        assert!(permissions.commands(Utf8Path::new("/out"), None).len() == 2);
+    }
+
+    #[test]
+    fn check_policy_ignores_the_lock_file() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(temp.path()).unwrap();
+        fs::write(dir.join(DIR_LOCK), "").unwrap();
+        assert!(check_policy(dir, DirPolicy::Owned, false).unwrap());
+
+        fs::write(dir.join("precious.txt"), "keep me").unwrap();
+        assert!(check_policy(dir, DirPolicy::Owned, false).is_err());
+    }
+}
@@ -0,0 +1,440 @@
+//! clean task implementation for the assemble phase.
+//!
+//! This module provides the `AssembleCleanTask`, which shrinks the final image by
+//! removing downloaded packages, apt package lists and log contents, and optionally
+//! by running `apt-get autoremove`/`apt-get clean`. Every command runs inside a plain
+//! chroot (as root, without the `defaults.isolation` options), so paths resolve
+//! inside the rootfs and a symlink planted there cannot point a removal at the host.
+
+use std::borrow::Cow;
+
+use anyhow::Result;
+use camino::Utf8Path;
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::config::IsolationConfig;
+use crate::disk_space::{disk_usage, format_bytes};
+use crate::error::RsdebstrapError;
+use crate::isolation::IsolationContext;
+use crate::phase::assemble::dir_exists;
+use crate::phase::{PhaseItem, check_execution_result, execute_in_context};
+use crate::privilege::{Privilege, PrivilegeDefaults, PrivilegeMethod};
+
+/// Downloaded `.deb` files (`lock` and the `partial` directory itself are kept).
+const APT_ARCHIVES: &str = "/var/cache/apt/archives";
+
+/// Package lists fetched by `apt-get update` (`lock` and `partial` are kept).
+const APT_LISTS: &str = "/var/lib/apt/lists";
+
+/// Log directory whose files are truncated (rotated logs are removed).
+const LOG_DIR: &str = "/var/log";
+
+/// Returns the `find` command emptying `dir` but keeping its `lock` file and the
+/// `partial` directory (whose contents are removed).
+fn clear_dir_command(dir: &str) -> Vec<String> {
+    let partial = format!("{}/partial", dir);
+    [
+        "find",
+        dir,
+        "-xdev",
+        "-mindepth",
+        "1",
+        "!",
+        "-name",
+        "lock",
+        "!",
+        "-path",
+        &partial,
+        "-delete",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+/// Returns the `find` commands removing rotated logs and truncating the others.
+fn clean_logs_commands() -> [Vec<String>; 2] {
+    let rotated = [
+        "find", LOG_DIR, "-xdev", "-type", "f", "(", "-name", "*.gz", "-o", "-name", "*.xz", "-o",
+        "-name", "*.old", "-o", "-name", "*.[0-9]", ")", "-delete",
+    ];
+    let truncate = [
+        "find", LOG_DIR, "-xdev", "-type", "f", "-exec", "truncate", "-s", "0", "{}", "+",
+    ];
+    [
+        rotated.map(String::from).to_vec(),
+        truncate.map(String::from).to_vec(),
+    ]
+}
+
+/// Assemble phase clean task reducing the size of the final rootfs.
+///
+/// `apt_archives`, `apt_lists` and `logs` are enabled by default, so `clean: {}`
+/// handles all three; `apt_clean` and `autoremove` are opt-in. At most one
+/// `AssembleCleanTask` may appear in the assemble phase.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct AssembleCleanTask {
+    /// Privilege escalation setting (resolved during defaults application).
+    #[serde(default, skip_serializing_if = "Privilege::is_inherit")]
+    pub privilege: Privilege,
+    /// Log a failure of the task and keep going instead of failing the phase.
+    #[serde(default, skip_serializing_if = "crate::phase::is_false")]
+    pub ignore_errors: bool,
+    /// Remove downloaded packages from `/var/cache/apt/archives`.
+    #[serde(
+        default = "crate::phase::default_true",
+        skip_serializing_if = "crate::phase::is_true"
+    )]
+    pub apt_archives: bool,
+    /// Remove package lists from `/var/lib/apt/lists` (run `apt-get update` before
+    /// installing anything in the image).
+    #[serde(
+        default = "crate::phase::default_true",
+        skip_serializing_if = "crate::phase::is_true"
+    )]
+    pub apt_lists: bool,
+    /// Truncate the files under `/var/log` and remove rotated logs.
+    #[serde(
+        default = "crate::phase::default_true",
+        skip_serializing_if = "crate::phase::is_true"
+    )]
+    pub logs: bool,
+    /// Run `apt-get clean` inside the rootfs.
+    #[serde(default, skip_serializing_if = "crate::phase::is_false")]
+    pub apt_clean: bool,
+    /// Run `apt-get -y autoremove --purge` inside the rootfs (before the other steps).
+    #[serde(default, skip_serializing_if = "crate::phase::is_false")]
+    pub autoremove: bool,
+    /// Chroot the commands run in, without the `defaults.isolation` options.
+    #[serde(skip)]
+    isolation: IsolationConfig,
+}
+
+impl Default for AssembleCleanTask {
+    fn default() -> Self {
+        Self {
+            privilege: Privilege::default(),
+            ignore_errors: false,
+            apt_archives: true,
+            apt_lists: true,
+            logs: true,
+            apt_clean: false,
+            autoremove: false,
+            isolation: IsolationConfig::default(),
+        }
+    }
+}
+
+impl AssembleCleanTask {
+    /// Returns the names of the enabled steps, in execution order.
+    pub fn items(&self) -> Vec<&'static str> {
+        [
+            (self.autoremove, "autoremove"),
+            (self.apt_clean, "apt-clean"),
+            (self.apt_archives, "apt-archives"),
+            (self.apt_lists, "apt-lists"),
+            (self.logs, "logs"),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, name)| enabled.then_some(name))
+        .collect()
+    }
+
+    /// Resolves the privilege setting against profile defaults.
+    pub fn resolve_privilege(
+        &mut self,
+        defaults: Option<&PrivilegeDefaults>,
+    ) -> Result<(), RsdebstrapError> {
+        self.privilege.resolve_in_place(defaults)
+    }
+
+    /// Returns the resolved privilege method.
+    ///
+    /// Should only be called after `resolve_privilege()`.
+    pub fn resolved_privilege_method(&self) -> Option<PrivilegeMethod> {
+        self.privilege.resolved_method()
+    }
+
+    /// Validates the assemble clean task configuration.
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        if self.items().is_empty() {
+            return Err(RsdebstrapError::Validation(
+                "assemble clean: nothing to clean (every step is disabled)".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the commands to run inside the rootfs, in order. Directory steps are
+    /// skipped when the directory is missing (never in dry-run mode, where the rootfs
+    /// may not exist yet).
+    fn commands(&self, rootfs: &Utf8Path, dry_run: bool) -> Result<Vec<Vec<String>>> {
+        let present = |dir: &str| -> Result<bool, RsdebstrapError> {
+            Ok(dry_run || dir_exists(rootfs, Utf8Path::new(dir.trim_start_matches('/')))?)
+        };
+        let mut commands = Vec::new();
+        if self.autoremove {
+            commands.push(
+                ["apt-get", "-y", "autoremove", "--purge"]
+                    .map(String::from)
+                    .to_vec(),
+            );
+        }
+        if self.apt_clean {
+            commands.push(["apt-get", "clean"].map(String::from).to_vec());
+        }
+        if self.apt_archives && present(APT_ARCHIVES)? {
+            commands.push(clear_dir_command(APT_ARCHIVES));
+        }
+        if self.apt_lists && present(APT_LISTS)? {
+            commands.push(clear_dir_command(APT_LISTS));
+        }
+        if self.logs && present(LOG_DIR)? {
+            commands.extend(clean_logs_commands());
+        }
+        Ok(commands)
+    }
+
+    /// Executes the assemble clean task.
+    ///
+    /// Runs each step's command inside the chroot and logs the disk space reclaimed
+    /// across the rootfs filesystem.
+    pub fn execute(&self, ctx: &dyn IsolationContext) -> Result<()> {
+        let rootfs = ctx.rootfs();
+        let dry_run = ctx.dry_run();
+        let privilege = self.resolved_privilege_method();
+
+        let before = (!dry_run).then(|| disk_usage(rootfs));
+        for command in self.commands(rootfs, dry_run)? {
+            let result = execute_in_context(ctx, &command, "clean", privilege)?;
+            check_execution_result(&result, &command, ctx.name(), dry_run)?;
+        }
+
+        match before {
+            Some(before) => info!(
+                "cleaned {} in {}: reclaimed {}",
+                self.items().join(", "),
+                rootfs,
+                format_bytes(before.saturating_sub(disk_usage(rootfs)))
+            ),
+            None => info!("would clean {} in {}", self.items().join(", "), rootfs),
+        }
+        Ok(())
+    }
+}
+
+impl PhaseItem for AssembleCleanTask {
+    fn name(&self) -> Cow<'_, str> {
+        Cow::Owned(format!("clean:{}", self.items().join(",")))
+    }
+
+    fn validate(&self) -> Result<(), RsdebstrapError> {
+        AssembleCleanTask::validate(self)
+    }
+
+    fn execute(&self, ctx: &dyn IsolationContext) -> Result<()> {
+        AssembleCleanTask::execute(self, ctx)
+    }
+
+    fn resolved_isolation_config(&self) -> Option<&IsolationConfig> {
+        Some(&self.isolation)
+    }
+
+    fn isolation_privilege(&self) -> Option<PrivilegeMethod> {
+        self.resolved_privilege_method()
+    }
+
+    fn ignore_errors(&self) -> bool {
+        self.ignore_errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::{CommandExecutor, ExecutionResult};
+    use camino::Utf8PathBuf;
+    use std::fs;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+    use std::sync::Mutex;
+
+    /// Records the commands run inside the isolation and reports success.
+    struct RecordingContext {
+        rootfs: Utf8PathBuf,
+        dry_run: bool,
+        commands: Mutex<Vec<(Vec<String>, Option<PrivilegeMethod>)>>,
+    }
+
+    impl RecordingContext {
+        fn new(rootfs: &Utf8Path, dry_run: bool) -> Self {
+            Self {
+                rootfs: rootfs.to_owned(),
+                dry_run,
+                commands: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn programs(&self) -> Vec<String> {
+            self.commands
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(command, _)| command[..2].join(" "))
+                .collect()
+        }
+    }
+
+    impl IsolationContext for RecordingContext {
+        fn name(&self) -> &'static str {
+            "recording"
+        }
+
+        fn rootfs(&self) -> &Utf8Path {
+            &self.rootfs
+        }
+
+        fn dry_run(&self) -> bool {
+            self.dry_run
+        }
+
+        fn executor(&self) -> &dyn CommandExecutor {
+            unimplemented!("not used by assemble clean tests")
+        }
+
+        fn execute(
+            &self,
+            command: &[String],
+            privilege: Option<PrivilegeMethod>,
+        ) -> Result<ExecutionResult> {
+            self.commands
+                .lock()
+                .unwrap()
+                .push((command.to_vec(), privilege));
+            Ok(ExecutionResult {
+                status: Some(ExitStatus::from_raw(0)),
+                details: None,
+            })
+        }
+
+        fn teardown(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn resolved_task() -> AssembleCleanTask {
+        AssembleCleanTask {
+            privilege: Privilege::Disabled,
+            ignore_errors: false,
+            ..AssembleCleanTask::default()
+        }
+    }
+
+    fn every_step() -> AssembleCleanTask {
+        AssembleCleanTask {
+            privilege: Privilege::Method(PrivilegeMethod::Sudo),
+            ignore_errors: false,
+            apt_clean: true,
+            autoremove: true,
+            ..AssembleCleanTask::default()
+        }
+    }
+
+    #[test]
+    fn clear_dir_command_keeps_lock_and_partial() {
+        assert_eq!(
+            clear_dir_command(APT_LISTS).join(" "),
+            "find /var/lib/apt/lists -xdev -mindepth 1 ! -name lock \
+            ! -path /var/lib/apt/lists/partial -delete"
+        );
+    }
+
+    #[test]
+    fn deserialize_empty_map_enables_default_steps() {
+        let task: AssembleCleanTask = yaml_serde::from_str("{}").unwrap();
+        assert_eq!(task, AssembleCleanTask::default());
+        assert_eq!(task.items(), ["apt-archives", "apt-lists", "logs"]);
+
+        let task: AssembleCleanTask =
+            yaml_serde::from_str("apt_lists: false\nautoremove: true\n").unwrap();
+        assert_eq!(task.items(), ["autoremove", "apt-archives", "logs"]);
+        let yaml = yaml_serde::to_string(&task).unwrap();
+        assert_eq!(yaml, "apt_lists: false\nautoremove: true\n");
+    }
+
+    #[test]
+    fn validate_rejects_nothing_to_do() {
+        let task = AssembleCleanTask {
+            apt_archives: false,
+            apt_lists: false,
+            logs: false,
+            ..AssembleCleanTask::default()
+        };
+        let err = task.validate().unwrap_err();
+        assert!(err.to_string().contains("nothing to clean"), "{err}");
+    }
+
+    #[test]
+    fn execute_dry_run_issues_every_step_in_order() {
+        let task = every_step();
+        let ctx = RecordingContext::new(Utf8Path::new("/nonexistent/rootfs"), true);
+
+        task.execute(&ctx).unwrap();
+
+        assert_eq!(
+            ctx.programs(),
+            [
+                "apt-get -y",
+                "apt-get clean",
+                "find /var/cache/apt/archives",
+                "find /var/lib/apt/lists",
+                "find /var/log",
+                "find /var/log",
+            ]
+        );
+        assert!(
+            ctx.commands
+                .lock()
+                .unwrap()
+                .iter()
+                .all(|(_, privilege)| *privilege == Some(PrivilegeMethod::Sudo))
+        );
+    }
+
+    #[test]
+    fn execute_skips_missing_directories() {
+        let temp = tempfile::tempdir().unwrap();
+        let rootfs = Utf8PathBuf::from_path_buf(temp.path().to_path_buf()).unwrap();
+        fs::create_dir_all(rootfs.join("var/lib/apt/lists")).unwrap();
+
+        let ctx = RecordingContext::new(&rootfs, false);
+        resolved_task().execute(&ctx).unwrap();
+
+        assert_eq!(ctx.programs(), ["find /var/lib/apt/lists"]);
+    }
+
+    #[test]
+    fn execute_refuses_symlinked_directory() {
+        let temp = tempfile::tempdir().unwrap();
+        let rootfs = Utf8PathBuf::from_path_buf(temp.path().to_path_buf()).unwrap();
+        fs::create_dir_all(rootfs.join("srv")).unwrap();
+        std::os::unix::fs::symlink(rootfs.join("srv"), rootfs.join("var")).unwrap();
+
+        let ctx = RecordingContext::new(&rootfs, false);
+        let err = resolved_task().execute(&ctx).unwrap_err();
+
+        assert!(err.to_string().contains("symlink attack"), "{err}");
+        assert!(ctx.programs().is_empty());
+    }
+
+    #[test]
+    fn runs_in_a_plain_chroot_with_its_privilege() {
+        let task = every_step();
+        assert_eq!(PhaseItem::resolved_isolation_config(&task), Some(&IsolationConfig::default()));
+        assert_eq!(task.isolation_privilege(), Some(PrivilegeMethod::Sudo));
+    }
+}
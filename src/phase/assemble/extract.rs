@@ -0,0 +1,563 @@
+//! extract task implementation for the assemble phase.
+//!
+//! This module provides the `AssembleExtractTask`, which copies files out of the
+//! final rootfs into a host directory: build logs, package manifests or binaries
+//! compiled inside the chroot. The paths are shell-style glob patterns matched one
+//! component at a time without following symlinks, so a pattern can never reach
+//! outside the rootfs.
+
+use std::borrow::Cow;
+use std::fs;
+
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::config::IsolationConfig;
+use crate::error::RsdebstrapError;
+use crate::executor::CommandSpec;
+use crate::isolation::IsolationContext;
+use crate::phase::assemble::{dir_exists, parent_dir};
+use crate::phase::{PhaseItem, validate_no_parent_dirs};
+use crate::privilege::{Privilege, PrivilegeDefaults, PrivilegeMethod};
+
+/// Returns `true` if `s` contains a glob metacharacter.
+fn has_glob(s: &str) -> bool {
+    s.contains(['*', '?', '['])
+}
+
+/// Matches a single path component against a shell-style glob `pattern`.
+///
+/// Supports `*`, `?` and bracket expressions (`[abc]`, `[a-z]`, `[!a]`); a `[` without
+/// a closing `]` matches itself. A leading `.` in `name` is only matched by a literal
+/// `.`, as in the shell.
+fn glob_match(pattern: &str, name: &str) -> bool {
+    if name.starts_with('.') && !pattern.starts_with('.') {
+        return false;
+    }
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    // Iterative matcher with single-star backtracking.
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match match_bracket(&pattern[p..], name[n]) {
+                Some((true, len)) => Some(len),
+                Some((false, _)) => None,
+                None => (name[n] == '[').then_some(1),
+            },
+            Some(&c) => (c == name[n]).then_some(1),
+            None => None,
+        };
+        match (step, star) {
+            (Some(len), _) => {
+                p += len;
+                n += 1;
+            }
+            (None, Some((star_p, star_n))) => {
+                p = star_p + 1;
+                n = star_n + 1;
+                star = Some((star_p, star_n + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Matches `c` against the bracket expression starting at `pattern[0] == '['`.
+///
+/// Returns whether it matched and the expression's length, or `None` if the bracket
+/// is not closed.
+fn match_bracket(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let &start = pattern.get(i)?;
+        if start == ']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+        first = false;
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|&e| e != ']') {
+            matched |= (start..=pattern[i + 2]).contains(&c);
+            i += 3;
+        } else {
+            matched |= start == c;
+            i += 1;
+        }
+    }
+}
+
+/// Assemble phase extract task copying files from the rootfs to a host directory.
+///
+/// At most one `AssembleExtractTask` may appear in the assemble phase; it lists every
+/// path. It runs before the other assemble tasks, so it sees the logs and caches that
+/// `clean` and `sanitize` would remove.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct AssembleExtractTask {
+    /// Privilege escalation setting (resolved during defaults application).
+    #[serde(default, skip_serializing_if = "Privilege::is_inherit")]
+    pub privilege: Privilege,
+    /// Log a failure of the task and keep going instead of failing the phase.
+    #[serde(default, skip_serializing_if = "crate::phase::is_false")]
+    pub ignore_errors: bool,
+    /// Host directory receiving the files (relative paths are resolved against the
+    /// profile's directory). Each file keeps its rootfs path below it.
+    #[serde(deserialize_with = "crate::de::path")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub output: Utf8PathBuf,
+    /// Absolute paths inside the rootfs to copy, as glob patterns (`*`, `?` and
+    /// `[...]` within one path component). Every pattern must match something.
+    #[serde(deserialize_with = "crate::de::string_list")]
+    pub paths: Vec<String>,
+    /// Copy extended attributes: ACLs, file capabilities (e.g. `cap_net_raw` on
+    /// `ping`) and `user.*` attributes. Capabilities can only be set with privilege.
+    #[serde(
+        default = "crate::phase::default_true",
+        skip_serializing_if = "crate::phase::is_true"
+    )]
+    pub xattrs: bool,
+}
+
+impl AssembleExtractTask {
+    /// Creates a task copying `paths` into `output`.
+    pub fn new(
+        output: impl Into<Utf8PathBuf>,
+        paths: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        Self {
+            privilege: Privilege::default(),
+            ignore_errors: false,
+            output: output.into(),
+            paths: paths.into_iter().map(Into::into).collect(),
+            xattrs: true,
+        }
+    }
+
+    /// Resolves the privilege setting against profile defaults.
+    pub fn resolve_privilege(
+        &mut self,
+        defaults: Option<&PrivilegeDefaults>,
+    ) -> Result<(), RsdebstrapError> {
+        self.privilege.resolve_in_place(defaults)
+    }
+
+    /// Returns the resolved privilege method.
+    ///
+    /// Should only be called after `resolve_privilege()`.
+    pub fn resolved_privilege_method(&self) -> Option<PrivilegeMethod> {
+        self.privilege.resolved_method()
+    }
+
+    /// Resolves a relative `output` against the profile's directory.
+    pub(crate) fn resolve_paths(&mut self, profile_dir: &Utf8Path) {
+        if self.output.is_relative() {
+            self.output = profile_dir.join(&self.output);
+        }
+    }
+
+    /// Validates the assemble extract task configuration.
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        if self.output.as_str().is_empty() {
+            return Err(RsdebstrapError::Validation(
+                "assemble extract: output must not be empty".to_string(),
+            ));
+        }
+        if self.paths.is_empty() {
+            return Err(RsdebstrapError::Validation(
+                "assemble extract: paths must not be empty".to_string(),
+            ));
+        }
+        for pattern in &self.paths {
+            let path = Utf8Path::new(pattern);
+            if !path.is_absolute() {
+                return Err(RsdebstrapError::Validation(format!(
+                    "assemble extract: path '{}' must be absolute",
+                    pattern
+                )));
+            }
+            if !path
+                .components()
+                .any(|c| matches!(c, Utf8Component::Normal(_)))
+            {
+                return Err(RsdebstrapError::Validation(format!(
+                    "assemble extract: path '{}' must not be the rootfs root",
+                    pattern
+                )));
+            }
+            if pattern.contains(['\0', '\n', '\r']) {
+                return Err(RsdebstrapError::Validation(format!(
+                    "assemble extract: path {:?} must not contain null or newline characters",
+                    pattern
+                )));
+            }
+            if pattern.contains("**") {
+                return Err(RsdebstrapError::Validation(format!(
+                    "assemble extract: path '{}' uses '**', which is not supported",
+                    pattern
+                )));
+            }
+            validate_no_parent_dirs(path, "assemble extract")?;
+        }
+        Ok(())
+    }
+
+    /// Expands the glob patterns against `rootfs` and returns the matched
+    /// rootfs-relative paths, sorted and without duplicates.
+    ///
+    /// Every entry is inspected with `symlink_metadata`, so symlinks are never
+    /// followed: a literal directory component that is a symlink or not a directory
+    /// is an error (possible symlink attack), while wildcard components skip
+    /// symlinked directories. The final component may match any file type; a
+    /// symlink is copied as a symlink.
+    pub fn expand(&self, rootfs: &Utf8Path) -> Result<Vec<Utf8PathBuf>, RsdebstrapError> {
+        let mut matches = Vec::new();
+        for pattern in &self.paths {
+            let components = Utf8Path::new(pattern)
+                .components()
+                .filter_map(|c| match c {
+                    Utf8Component::Normal(name) => Some(name),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            let mut current = vec![Utf8PathBuf::new()];
+            for (index, component) in components.iter().enumerate() {
+                let last = index + 1 == components.len();
+                let mut next = Vec::new();
+                for dir in &current {
+                    if has_glob(component) {
+                        let host_dir = rootfs.join(dir);
+                        let entries = fs::read_dir(&host_dir).map_err(|e| {
+                            RsdebstrapError::io(format!("failed to read {}", host_dir), e)
+                        })?;
+                        for entry in entries {
+                            let entry = entry.map_err(|e| {
+                                RsdebstrapError::io(format!("failed to read {}", host_dir), e)
+                            })?;
+                            let Some(name) = entry.file_name().to_str().map(str::to_owned) else {
+                                continue;
+                            };
+                            if !glob_match(component, &name) {
+                                continue;
+                            }
+                            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+                            if last || is_dir {
+                                next.push(dir.join(name));
+                            }
+                        }
+                    } else {
+                        let path = dir.join(component);
+                        let host_path = rootfs.join(&path);
+                        match host_path.symlink_metadata() {
+                            Ok(meta) if last || meta.is_dir() => next.push(path),
+                            Ok(_) => {
+                                return Err(RsdebstrapError::Isolation(format!(
+                                    "{} is a symlink or not a directory, refusing to copy \
+                                    files below it (possible symlink attack)",
+                                    host_path
+                                )));
+                            }
+                            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                            Err(e) => {
+                                return Err(RsdebstrapError::io(
+                                    format!("failed to stat {}", host_path),
+                                    e,
+                                ));
+                            }
+                        }
+                    }
+                }
+                current = next;
+            }
+            if current.is_empty() {
+                return Err(RsdebstrapError::Validation(format!(
+                    "assemble extract: path '{}' matched nothing in {}",
+                    pattern, rootfs
+                )));
+            }
+            matches.extend(current);
+        }
+        matches.sort();
+        matches.dedup();
+        Ok(matches)
+    }
+
+    /// Executes the assemble extract task.
+    ///
+    /// Expands the patterns (see [`Self::expand`]) and copies each match to the same
+    /// path below `output` with `cp -R -P`, preserving modes, timestamps and (with
+    /// `xattrs`) extended attributes, but not following symlinks. The directories leading to each match are re-opened with
+    /// `O_NOFOLLOW` just before the copy. Commands run with privilege escalation when
+    /// configured, so the copies are then owned by the escalated user.
+    pub fn execute(&self, ctx: &dyn IsolationContext) -> anyhow::Result<()> {
+        let rootfs = ctx.rootfs();
+
+        if ctx.dry_run() {
+            info!("would extract {} from {} to {}", self.paths.join(", "), rootfs, self.output);
+            return Ok(());
+        }
+
+        let executor = ctx.executor();
+        let privilege = self.resolved_privilege_method();
+        let matches = self.expand(rootfs)?;
+        let preserve = if self.xattrs {
+            "--preserve=mode,timestamps,xattr"
+        } else {
+            "--preserve=mode,timestamps"
+        };
+        for relative in &matches {
+            if !dir_exists(rootfs, parent_dir(relative))? {
+                return Err(RsdebstrapError::Isolation(format!(
+                    "{} disappeared while extracting",
+                    rootfs.join(relative)
+                ))
+                .into());
+            }
+            let target = self.output.join(relative);
+            let spec =
+                CommandSpec::new("mkdir", vec!["-p".to_string(), parent_dir(&target).to_string()])
+                    .with_privilege(privilege);
+            executor.execute_checked(&spec)?;
+            let spec = CommandSpec::new(
+                "cp",
+                vec![
+                    "-R".to_string(),
+                    "-P".to_string(),
+                    preserve.to_string(),
+                    "--no-target-directory".to_string(),
+                    rootfs.join(relative).to_string(),
+                    target.to_string(),
+                ],
+            )
+            .with_privilege(privilege);
+            executor.execute_checked(&spec)?;
+        }
+
+        info!("extracted {} path(s) from {} to {}", matches.len(), rootfs, self.output);
+        Ok(())
+    }
+}
+
+impl PhaseItem for AssembleExtractTask {
+    fn name(&self) -> Cow<'_, str> {
+        Cow::Owned(format!("extract:{}", self.paths.join(",")))
+    }
+
+    fn validate(&self) -> Result<(), RsdebstrapError> {
+        AssembleExtractTask::validate(self)
+    }
+
+    fn execute(&self, ctx: &dyn IsolationContext) -> anyhow::Result<()> {
+        // Assemble extract reads the final rootfs filesystem directly.
+        AssembleExtractTask::execute(self, ctx)
+    }
+
+    fn resolved_isolation_config(&self) -> Option<&IsolationConfig> {
+        None
+    }
+
+    fn ignore_errors(&self) -> bool {
+        self.ignore_errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phase::assemble::test_support::MockAssembleContext;
+    use rustix::fs::XattrFlags;
+
+    /// Creates a rootfs holding build logs, a manifest and a compiled binary.
+    fn populated_rootfs(dir: &std::path::Path) -> Utf8PathBuf {
+        let rootfs = Utf8PathBuf::from_path_buf(dir.join("rootfs")).unwrap();
+        for sub in ["var/log/build", "usr/local/bin", "srv"] {
+            fs::create_dir_all(rootfs.join(sub)).unwrap();
+        }
+        fs::write(rootfs.join("var/log/build/make.log"), "make\n").unwrap();
+        fs::write(rootfs.join("var/log/build/test.log"), "test\n").unwrap();
+        fs::write(rootfs.join("var/log/build/.hidden.log"), "hidden\n").unwrap();
+        fs::write(rootfs.join("var/log/dpkg.log"), "dpkg\n").unwrap();
+        fs::write(rootfs.join("usr/local/bin/tool"), "#!/bin/sh\n").unwrap();
+        rootfs
+    }
+
+    fn resolved_task(output: &Utf8Path, paths: &[&str]) -> AssembleExtractTask {
+        AssembleExtractTask {
+            privilege: Privilege::Disabled,
+            ignore_errors: false,
+            ..AssembleExtractTask::new(output, paths.iter().copied())
+        }
+    }
+
+    #[test]
+    fn glob_match_follows_shell_rules() {
+        assert!(glob_match("*.log", "make.log"));
+        assert!(!glob_match("*.log", ".hidden.log"));
+        assert!(glob_match(".*.log", ".hidden.log"));
+        assert!(glob_match("t?st", "test"));
+        assert!(glob_match("a*b*c", "aXbYbc"));
+        assert!(!glob_match("a*b", "abc"));
+        assert!(glob_match("[mt]*", "make.log"));
+        assert!(!glob_match("[!mt]*", "make.log"));
+        assert!(glob_match("v[0-9]", "v7"));
+        assert!(glob_match("[]]", "]"));
+        assert!(glob_match("a[b", "a[b"));
+        assert!(glob_match("*", "x"));
+    }
+
+    #[test]
+    fn validate_rejects_bad_paths() {
+        for (paths, expected) in [
+            (vec![], "must not be empty"),
+            (vec!["var/log"], "must be absolute"),
+            (vec!["/"], "rootfs root"),
+            (vec!["/var/../etc"], "'..'"),
+            (vec!["/var/log\n"], "newline"),
+            (vec!["/var/**/log"], "'**'"),
+        ] {
+            let task = AssembleExtractTask::new("out", paths.clone());
+            let err = task.validate().unwrap_err();
+            assert!(err.to_string().contains(expected), "{paths:?}: {err}");
+        }
+        let err = AssembleExtractTask::new("", ["/a"]).validate().unwrap_err();
+        assert!(err.to_string().contains("output must not be empty"), "{err}");
+        AssembleExtractTask::new("out", ["/var/log/*.log"])
+            .validate()
+            .unwrap();
+    }
+
+    #[test]
+    fn deserialize_and_resolve_output() {
+        let mut task: AssembleExtractTask =
+            yaml_serde::from_str("output: artifacts\npaths: [/var/log/build/*.log]\n").unwrap();
+        assert_eq!(PhaseItem::name(&task), "extract:/var/log/build/*.log");
+        let yaml = yaml_serde::to_string(&task).unwrap();
+        assert_eq!(yaml_serde::from_str::<AssembleExtractTask>(&yaml).unwrap(), task);
+        task.resolve_paths(Utf8Path::new("/profiles"));
+        assert_eq!(task.output, "/profiles/artifacts");
+
+        assert!(yaml_serde::from_str::<AssembleExtractTask>("output: a\n").is_err());
+    }
+
+    #[test]
+    fn expand_matches_globs_without_following_symlinks() {
+        let temp = tempfile::tempdir().unwrap();
+        let rootfs = populated_rootfs(temp.path());
+        std::os::unix::fs::symlink("/etc", rootfs.join("var/log/etc")).unwrap();
+
+        let task = AssembleExtractTask::new(
+            "out",
+            [
+                "/var/log/*/*.log",
+                "/var/log/build/make.log",
+                "/usr/local/bin/tool",
+            ],
+        );
+        assert_eq!(
+            task.expand(&rootfs).unwrap(),
+            [
+                "usr/local/bin/tool",
+                "var/log/build/make.log",
+                "var/log/build/test.log"
+            ]
+            .map(Utf8PathBuf::from)
+        );
+
+        let task = AssembleExtractTask::new("out", ["/var/log/etc"]);
+        assert_eq!(task.expand(&rootfs).unwrap(), [Utf8PathBuf::from("var/log/etc")]);
+
+        let task = AssembleExtractTask::new("out", ["/var/log/etc/passwd"]);
+        let err = task.expand(&rootfs).unwrap_err();
+        assert!(err.to_string().contains("symlink attack"), "{err}");
+
+        let task = AssembleExtractTask::new("out", ["/var/log/build/*.gz"]);
+        let err = task.expand(&rootfs).unwrap_err();
+        assert!(err.to_string().contains("matched nothing"), "{err}");
+    }
+
+    #[test]
+    fn execute_copies_matches_below_output() {
+        let temp = tempfile::tempdir().unwrap();
+        let rootfs = populated_rootfs(temp.path());
+        let output = Utf8PathBuf::from_path_buf(temp.path().join("artifacts")).unwrap();
+        let task = resolved_task(&output, &["/var/log/build", "/usr/local/bin/*"]);
+
+        let ctx = MockAssembleContext::new(&rootfs, false);
+        task.execute(&ctx).unwrap();
+
+        assert_eq!(fs::read_to_string(output.join("var/log/build/make.log")).unwrap(), "make\n");
+        assert!(output.join("var/log/build/.hidden.log").exists());
+        assert!(output.join("usr/local/bin/tool").exists());
+        assert!(!output.join("var/log/dpkg.log").exists());
+        assert_eq!(ctx.commands().len(), 4);
+
+        // A second run refreshes the copies rather than nesting them.
+        let ctx = MockAssembleContext::new(&rootfs, false);
+        task.execute(&ctx).unwrap();
+        assert!(!output.join("var/log/build/build").exists());
+    }
+
+    #[test]
+    fn execute_keeps_extended_attributes_unless_disabled() {
+        let temp = tempfile::tempdir().unwrap();
+        let rootfs = populated_rootfs(temp.path());
+        let tool = rootfs.join("usr/local/bin/tool");
+        if rustix::fs::lsetxattr(tool.as_str(), "user.origin", b"build", XattrFlags::empty())
+            .is_err()
+        {
+            eprintln!("skipping: {} does not support user xattrs", temp.path().display());
+            return;
+        }
+        let read = |path: &Utf8Path| {
+            let mut value = [0; 16];
+            rustix::fs::lgetxattr(path.as_str(), "user.origin", &mut value)
+                .map(|len| value[..len].to_vec())
+                .ok()
+        };
+
+        for xattrs in [true, false] {
+            let output =
+                Utf8PathBuf::from_path_buf(temp.path().join(format!("out-{xattrs}"))).unwrap();
+            let task = AssembleExtractTask {
+                xattrs,
+                ..resolved_task(&output, &["/usr/local/bin/tool"])
+            };
+            task.execute(&MockAssembleContext::new(&rootfs, false))
+                .unwrap();
+
+            let copied = read(&output.join("usr/local/bin/tool"));
+            assert_eq!(copied.is_some(), xattrs, "xattrs: {xattrs}");
+        }
+    }
+
+    #[test]
+    fn execute_dry_run_copies_nothing() {
+        let temp = tempfile::tempdir().unwrap();
+        let rootfs = populated_rootfs(temp.path());
+        let output = Utf8PathBuf::from_path_buf(temp.path().join("artifacts")).unwrap();
+
+        let ctx = MockAssembleContext::new(&rootfs, true);
+        resolved_task(&output, &["/var/log/*.log"])
+            .execute(&ctx)
+            .unwrap();
+
+        assert!(ctx.commands().is_empty());
+        assert!(!output.exists());
+    }
+}
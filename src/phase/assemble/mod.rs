@@ -1,56 +1,167 @@
 //! Assemble phase module for post-provisioning tasks.
 //!
 //! This module provides the [`AssembleConfig`] named-field struct describing the
-//! tasks that run after the main provisioning phase. Currently the only role is:
+//! tasks that run after the main provisioning phase, in this order:
+//! - [`extract`](AssembleConfig::extract) — copies files out of the rootfs to the host
 //! - [`resolv_conf`](AssembleConfig::resolv_conf) — writes a permanent `/etc/resolv.conf`
+//! - [`sanitize`](AssembleConfig::sanitize) — removes per-instance identifiers
+//! - [`clean`](AssembleConfig::clean) — removes apt caches and log contents
+//! - [`release`](AssembleConfig::release) — writes a build provenance file
+//! - [`relabel`](AssembleConfig::relabel) — applies the rootfs's SELinux file labels
 //!
-//! The named-field shape makes "at most one resolv_conf" structural rather than
+//! The named-field shape makes "at most one of each" structural rather than
 //! validated after the fact.
 
+pub mod clean;
+pub mod extract;
+pub mod relabel;
+pub mod release;
 pub mod resolv_conf;
+pub mod sanitize;
+#[cfg(test)]
+mod test_support;
+
+use std::os::fd::{AsFd, OwnedFd};
 
+use camino::{Utf8Component, Utf8Path};
+use rustix::fs::{self as rfs, CWD, Mode, OFlags};
 #[cfg(feature = "schema")]
 use schemars::JsonSchema;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
+pub use clean::AssembleCleanTask;
+pub use extract::AssembleExtractTask;
+pub use relabel::AssembleRelabelTask;
+pub use release::AssembleReleaseTask;
 pub use resolv_conf::AssembleResolvConfTask;
+pub use sanitize::AssembleSanitizeTask;
 
+use crate::error::RsdebstrapError;
 use crate::phase::PhaseItem;
 
 /// Assemble phase configuration (named-field, schema-first).
 ///
-/// The single field is an optional singleton; a duplicate YAML key is rejected
+/// Each field is an optional singleton; a duplicate YAML key is rejected
 /// by `yaml_serde` at parse time and an unknown key by `deny_unknown_fields`.
-#[derive(Debug, Deserialize, Default, Clone, PartialEq, Eq)]
+#[derive(Debug, Deserialize, Serialize, Default, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
 #[serde(deny_unknown_fields)]
 pub struct AssembleConfig {
+    /// extract task copying files (build logs, manifests, binaries) from the rootfs to
+    /// a host directory before the other assemble tasks change it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extract: Option<AssembleExtractTask>,
     /// resolv_conf task writing a permanent `/etc/resolv.conf` into the final rootfs.
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub resolv_conf: Option<AssembleResolvConfTask>,
+    /// sanitize task removing per-instance identifiers (machine ID, random seed, SSH host
+    /// keys) from the final rootfs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sanitize: Option<AssembleSanitizeTask>,
+    /// clean task removing apt caches and log contents to shrink the final rootfs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clean: Option<AssembleCleanTask>,
+    /// release task writing a build provenance file (`/etc/rsdebstrap-release`) into
+    /// the final rootfs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release: Option<AssembleReleaseTask>,
+    /// relabel task running `setfiles` with the rootfs's own SELinux policy, so files
+    /// carry the image's labels rather than the host's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relabel: Option<AssembleRelabelTask>,
 }
 
 impl AssembleConfig {
     /// Returns the present phase items in execution order.
     pub(crate) fn items(&self) -> Vec<&dyn PhaseItem> {
         let mut items: Vec<&dyn PhaseItem> = Vec::new();
+        if let Some(extract) = &self.extract {
+            items.push(extract);
+        }
         if let Some(resolv_conf) = &self.resolv_conf {
             items.push(resolv_conf);
         }
+        if let Some(sanitize) = &self.sanitize {
+            items.push(sanitize);
+        }
+        if let Some(clean) = &self.clean {
+            items.push(clean);
+        }
+        if let Some(release) = &self.release {
+            items.push(release);
+        }
+        if let Some(relabel) = &self.relabel {
+            items.push(relabel);
+        }
         items
     }
 
     /// Returns true if no assemble tasks are configured.
     pub fn is_empty(&self) -> bool {
-        self.resolv_conf.is_none()
+        self.extract.is_none()
+            && self.resolv_conf.is_none()
+            && self.sanitize.is_none()
+            && self.clean.is_none()
+            && self.release.is_none()
+            && self.relabel.is_none()
     }
 
     /// Returns the number of configured assemble tasks.
     pub fn len(&self) -> usize {
-        usize::from(self.resolv_conf.is_some())
+        usize::from(self.extract.is_some())
+            + usize::from(self.resolv_conf.is_some())
+            + usize::from(self.sanitize.is_some())
+            + usize::from(self.clean.is_some())
+            + usize::from(self.release.is_some())
+            + usize::from(self.relabel.is_some())
     }
 }
 
+/// Returns whether the directory `relative` exists under `rootfs`, opening each
+/// directory on the way with `O_NOFOLLOW`.
+///
+/// A missing directory returns `false`; a symlink or non-directory is an error
+/// (possible symlink attack). A TOCTOU window remains between this check and the
+/// external `rm`/`truncate` commands, which operate on path strings.
+pub(crate) fn dir_exists(rootfs: &Utf8Path, relative: &Utf8Path) -> Result<bool, RsdebstrapError> {
+    fn open(dirfd: impl AsFd, name: &str) -> rustix::io::Result<OwnedFd> {
+        rfs::openat(
+            dirfd,
+            name,
+            OFlags::NOFOLLOW | OFlags::DIRECTORY | OFlags::RDONLY | OFlags::CLOEXEC,
+            Mode::empty(),
+        )
+    }
+    let to_error = |e: rustix::io::Errno, shown: &Utf8Path| match e {
+        rustix::io::Errno::LOOP | rustix::io::Errno::NOTDIR => RsdebstrapError::Isolation(format!(
+            "{} is a symlink or not a directory, refusing to modify files below it \
+            (possible symlink attack)",
+            shown
+        )),
+        _ => RsdebstrapError::io(format!("failed to open {}", shown), std::io::Error::from(e)),
+    };
+
+    let mut dir = open(CWD, rootfs.as_str()).map_err(|e| to_error(e, rootfs))?;
+    let mut shown = rootfs.to_owned();
+    for component in relative.components() {
+        let Utf8Component::Normal(name) = component else {
+            continue;
+        };
+        shown.push(name);
+        dir = match open(&dir, name) {
+            Ok(fd) => fd,
+            Err(rustix::io::Errno::NOENT) => return Ok(false),
+            Err(e) => return Err(to_error(e, &shown)),
+        };
+    }
+    Ok(true)
+}
+
+/// Returns the directory containing the rootfs-relative `path`.
+pub(crate) fn parent_dir(path: &Utf8Path) -> &Utf8Path {
+    path.parent().unwrap_or(Utf8Path::new(""))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -64,6 +175,39 @@ mod tests {
         assert!(!config.is_empty());
     }
 
+    #[test]
+    fn deserialize_sanitize_defaults_to_every_item() {
+        let yaml = "resolv_conf:\n  link: ../run/x\nsanitize: {}\n";
+        let config: AssembleConfig = yaml_serde::from_str(yaml).unwrap();
+        assert_eq!(config.sanitize, Some(AssembleSanitizeTask::default()));
+        assert_eq!(config.len(), 2);
+        let names = config
+            .items()
+            .iter()
+            .map(|i| i.name().into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "resolv_conf:link",
+                "sanitize:machine-id,random-seed,ssh-host-keys"
+            ]
+        );
+    }
+
+    #[test]
+    fn relabel_runs_after_every_other_task() {
+        let yaml = "relabel:\n  policy: mls\nrelease: {}\nclean: {}\n";
+        let config: AssembleConfig = yaml_serde::from_str(yaml).unwrap();
+        let names = config
+            .items()
+            .iter()
+            .map(|i| i.name().into_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(names.last().map(String::as_str), Some("relabel:mls"));
+        assert_eq!(config.len(), 3);
+    }
+
     #[test]
     fn deserialize_absent_defaults_to_empty() {
         let config: AssembleConfig = yaml_serde::from_str("{}").unwrap();
@@ -0,0 +1,332 @@
+//! relabel task implementation for the assemble phase.
+//!
+//! This module provides the `AssembleRelabelTask`, which gives every file in the final
+//! rootfs the SELinux label the rootfs's own policy assigns to it, by running that
+//! policy's `setfiles` inside a plain chroot. Files created during the build carry the
+//! labels of the host (or none), which is wrong for the image whenever the host runs a
+//! different policy, e.g. a Debian image built on a Fedora host.
+//!
+//! The rootfs must contain `setfiles` (package `policycoreutils`) and the policy
+//! (e.g. `selinux-policy-default`), installed by a provision task. AppArmor confines
+//! by path and needs no relabeling.
+
+use std::borrow::Cow;
+use std::fs;
+
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::config::IsolationConfig;
+use crate::error::RsdebstrapError;
+use crate::isolation::IsolationContext;
+use crate::phase::assemble::dir_exists;
+use crate::phase::{PhaseItem, check_execution_result, execute_in_context};
+use crate::privilege::{Privilege, PrivilegeDefaults, PrivilegeMethod};
+
+/// Directory holding the SELinux policies of a system.
+const SELINUX_DIR: &str = "/etc/selinux";
+
+/// Policy used when neither the task nor the rootfs's `/etc/selinux/config` names one.
+const DEFAULT_POLICY: &str = "default";
+
+/// Host file reporting whether SELinux is enforcing (`1`).
+const HOST_ENFORCE: &str = "/sys/fs/selinux/enforce";
+
+/// Returns whether the host enforces SELinux.
+pub fn host_selinux_enforcing() -> bool {
+    fs::read_to_string(HOST_ENFORCE).is_ok_and(|value| value.trim() == "1")
+}
+
+/// Assemble phase relabel task applying the rootfs's SELinux file contexts.
+///
+/// At most one `AssembleRelabelTask` may appear in the assemble phase; it runs last,
+/// so the files the other assemble tasks write are labeled too.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct AssembleRelabelTask {
+    /// Privilege escalation setting (resolved during defaults application).
+    #[serde(default, skip_serializing_if = "Privilege::is_inherit")]
+    pub privilege: Privilege,
+    /// Log a failure of the task and keep going instead of failing the phase.
+    #[serde(default, skip_serializing_if = "crate::phase::is_false")]
+    pub ignore_errors: bool,
+    /// Policy directory under `/etc/selinux` in the rootfs (default: `SELINUXTYPE`
+    /// from the rootfs's `/etc/selinux/config`, else `default`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<String>,
+    /// Chroot `setfiles` runs in, without the `defaults.isolation` options.
+    #[serde(skip)]
+    isolation: IsolationConfig,
+}
+
+impl AssembleRelabelTask {
+    /// Resolves the privilege setting against profile defaults.
+    pub fn resolve_privilege(
+        &mut self,
+        defaults: Option<&PrivilegeDefaults>,
+    ) -> Result<(), RsdebstrapError> {
+        self.privilege.resolve_in_place(defaults)
+    }
+
+    /// Returns the resolved privilege method.
+    ///
+    /// Should only be called after `resolve_privilege()`.
+    pub fn resolved_privilege_method(&self) -> Option<PrivilegeMethod> {
+        self.privilege.resolved_method()
+    }
+
+    /// Validates the assemble relabel task configuration.
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        if let Some(policy) = &self.policy
+            && !is_policy_name(policy)
+        {
+            return Err(RsdebstrapError::Validation(format!(
+                "assemble relabel: invalid policy '{}' (expected a directory name under {})",
+                policy, SELINUX_DIR
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns the policy to apply: the configured one, else `SELINUXTYPE` from the
+    /// rootfs's `/etc/selinux/config` (never read in dry-run mode), else
+    /// [`DEFAULT_POLICY`].
+    fn policy(&self, rootfs: &Utf8Path, dry_run: bool) -> Result<String> {
+        if let Some(policy) = &self.policy {
+            return Ok(policy.clone());
+        }
+        if dry_run || !dir_exists(rootfs, Utf8Path::new("etc/selinux"))? {
+            return Ok(DEFAULT_POLICY.to_string());
+        }
+        let config = rootfs.join("etc/selinux/config");
+        match fs::symlink_metadata(&config) {
+            Ok(meta) if meta.is_file() => {}
+            _ => return Ok(DEFAULT_POLICY.to_string()),
+        }
+        let text = fs::read_to_string(&config)
+            .map_err(|e| RsdebstrapError::io(format!("failed to read {}", config), e))?;
+        Ok(selinux_type(&text)
+            .filter(|policy| is_policy_name(policy))
+            .unwrap_or(DEFAULT_POLICY)
+            .to_string())
+    }
+
+    /// Returns the `setfiles` command labeling the whole rootfs with `policy`.
+    fn command(policy: &str) -> Vec<String> {
+        let file_contexts = file_contexts(policy);
+        [
+            "setfiles",
+            "-F",
+            "-e",
+            "/proc",
+            "-e",
+            "/sys",
+            "-e",
+            "/dev",
+            file_contexts.as_str(),
+            "/",
+        ]
+        .map(String::from)
+        .to_vec()
+    }
+
+    /// Executes the assemble relabel task.
+    ///
+    /// Checks that the rootfs holds the policy's file contexts, then runs `setfiles`
+    /// inside the chroot.
+    pub fn execute(&self, ctx: &dyn IsolationContext) -> Result<()> {
+        let rootfs = ctx.rootfs();
+        let dry_run = ctx.dry_run();
+        let policy = self.policy(rootfs, dry_run)?;
+
+        if !dry_run {
+            let contexts = file_contexts(&policy);
+            let relative = contexts.strip_prefix("/").unwrap_or(&contexts);
+            let parent = relative.parent().unwrap_or(Utf8Path::new(""));
+            if !dir_exists(rootfs, parent)? || !rootfs.join(relative).is_file() {
+                return Err(RsdebstrapError::Validation(format!(
+                    "assemble relabel: the rootfs has no SELinux policy '{}' ({} is missing); \
+                    install the policy and policycoreutils in a provision task",
+                    policy, contexts
+                ))
+                .into());
+            }
+        }
+
+        info!("relabeling {} with the SELinux policy '{}'", rootfs, policy);
+        let command = Self::command(&policy);
+        let result =
+            execute_in_context(ctx, &command, "relabel", self.resolved_privilege_method())?;
+        check_execution_result(&result, &command, ctx.name(), dry_run)?;
+        Ok(())
+    }
+}
+
+impl PhaseItem for AssembleRelabelTask {
+    fn name(&self) -> Cow<'_, str> {
+        match &self.policy {
+            Some(policy) => Cow::Owned(format!("relabel:{}", policy)),
+            None => Cow::Borrowed("relabel"),
+        }
+    }
+
+    fn validate(&self) -> Result<(), RsdebstrapError> {
+        AssembleRelabelTask::validate(self)
+    }
+
+    fn execute(&self, ctx: &dyn IsolationContext) -> Result<()> {
+        AssembleRelabelTask::execute(self, ctx)
+    }
+
+    fn resolved_isolation_config(&self) -> Option<&IsolationConfig> {
+        Some(&self.isolation)
+    }
+
+    fn isolation_privilege(&self) -> Option<PrivilegeMethod> {
+        self.resolved_privilege_method()
+    }
+
+    fn ignore_errors(&self) -> bool {
+        self.ignore_errors
+    }
+}
+
+/// Returns the path, inside the rootfs, of `policy`'s file contexts.
+fn file_contexts(policy: &str) -> Utf8PathBuf {
+    Utf8Path::new(SELINUX_DIR)
+        .join(policy)
+        .join("contexts/files/file_contexts")
+}
+
+/// Returns whether `policy` is a plain directory name.
+fn is_policy_name(policy: &str) -> bool {
+    !policy.is_empty()
+        && policy != "."
+        && policy != ".."
+        && policy
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
+/// Returns the `SELINUXTYPE` set in an `/etc/selinux/config` file.
+fn selinux_type(config: &str) -> Option<&str> {
+    config.lines().find_map(|line| {
+        let value = line.trim().strip_prefix("SELINUXTYPE=")?;
+        Some(value.trim().trim_matches('"'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::{CommandExecutor, ExecutionResult};
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+    use std::sync::Mutex;
+
+    /// Records the commands run inside the isolation and reports success.
+    struct RecordingContext {
+        rootfs: Utf8PathBuf,
+        dry_run: bool,
+        commands: Mutex<Vec<Vec<String>>>,
+    }
+
+    impl IsolationContext for RecordingContext {
+        fn name(&self) -> &'static str {
+            "recording"
+        }
+
+        fn rootfs(&self) -> &Utf8Path {
+            &self.rootfs
+        }
+
+        fn dry_run(&self) -> bool {
+            self.dry_run
+        }
+
+        fn executor(&self) -> &dyn CommandExecutor {
+            unimplemented!("not used by assemble relabel tests")
+        }
+
+        fn execute(
+            &self,
+            command: &[String],
+            _privilege: Option<PrivilegeMethod>,
+        ) -> Result<ExecutionResult> {
+            self.commands.lock().unwrap().push(command.to_vec());
+            Ok(ExecutionResult {
+                status: Some(ExitStatus::from_raw(0)),
+                details: None,
+            })
+        }
+
+        fn teardown(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn context(rootfs: &Utf8Path, dry_run: bool) -> RecordingContext {
+        RecordingContext {
+            rootfs: rootfs.to_owned(),
+            dry_run,
+            commands: Mutex::new(Vec::new()),
+        }
+    }
+
+    #[test]
+    fn selinux_type_reads_the_config() {
+        let config = "# comment\nSELINUX=permissive\nSELINUXTYPE=\"mls\"\n";
+        assert_eq!(selinux_type(config), Some("mls"));
+        assert_eq!(selinux_type("SELINUX=disabled\n"), None);
+    }
+
+    #[test]
+    fn validate_rejects_paths_as_policy() {
+        for policy in ["", "..", "../etc", "a/b"] {
+            let task = AssembleRelabelTask {
+                policy: Some(policy.to_string()),
+                ..AssembleRelabelTask::default()
+            };
+            assert!(task.validate().is_err(), "{policy:?}");
+        }
+    }
+
+    #[test]
+    fn execute_uses_the_policy_from_the_rootfs_config() {
+        let temp = tempfile::tempdir().unwrap();
+        let rootfs = Utf8PathBuf::from_path_buf(temp.path().to_path_buf()).unwrap();
+        fs::create_dir_all(rootfs.join("etc/selinux/mls/contexts/files")).unwrap();
+        fs::write(rootfs.join("etc/selinux/config"), "SELINUXTYPE=mls\n").unwrap();
+        fs::write(rootfs.join("etc/selinux/mls/contexts/files/file_contexts"), "").unwrap();
+
+        let ctx = context(&rootfs, false);
+        let task = AssembleRelabelTask {
+            privilege: Privilege::Disabled,
+            ..AssembleRelabelTask::default()
+        };
+        task.execute(&ctx).unwrap();
+
+        assert_eq!(
+            ctx.commands.lock().unwrap()[0].join(" "),
+            "setfiles -F -e /proc -e /sys -e /dev \
+            /etc/selinux/mls/contexts/files/file_contexts /"
+        );
+    }
+
+    #[test]
+    fn execute_requires_the_policy_in_the_rootfs() {
+        let temp = tempfile::tempdir().unwrap();
+        let rootfs = Utf8PathBuf::from_path_buf(temp.path().to_path_buf()).unwrap();
+
+        let ctx = context(&rootfs, false);
+        let err = AssembleRelabelTask::default().execute(&ctx).unwrap_err();
+
+        assert!(err.to_string().contains("no SELinux policy 'default'"), "{err}");
+        assert!(ctx.commands.lock().unwrap().is_empty());
+    }
+}
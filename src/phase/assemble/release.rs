@@ -0,0 +1,510 @@
+//! release task implementation for the assemble phase.
+//!
+//! This module provides the `AssembleReleaseTask`, which writes a build provenance
+//! file (`/etc/rsdebstrap-release` by default) into the final rootfs so a deployed
+//! image can be traced back to the exact build inputs: the hash of the resolved
+//! profile, the rsdebstrap version, the bootstrap backend, its version and the suite,
+//! the build time,
+//! and the profile repository's commit when `apply --source-commit` gives one.
+
+use std::borrow::Cow;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use camino::{Utf8Component, Utf8PathBuf};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+use crate::bootstrap::ToolVersion;
+use crate::config::{IsolationConfig, Profile};
+use crate::error::RsdebstrapError;
+use crate::executor::CommandSpec;
+use crate::isolation::IsolationContext;
+use crate::phase::assemble::{dir_exists, parent_dir};
+use crate::phase::{PhaseItem, validate_no_parent_dirs};
+use crate::privilege::{Privilege, PrivilegeDefaults, PrivilegeMethod};
+
+/// Default location of the provenance file inside the rootfs.
+const DEFAULT_PATH: &str = "/etc/rsdebstrap-release";
+
+/// Suffix for the staging entry used to atomically replace the provenance file.
+const STAGING_SUFFIX: &str = ".rsdebstrap-tmp";
+
+fn default_path() -> Utf8PathBuf {
+    Utf8PathBuf::from(DEFAULT_PATH)
+}
+
+fn is_default_path(path: &Utf8PathBuf) -> bool {
+    path == DEFAULT_PATH
+}
+
+/// Returns `true` if `commit` looks like an abbreviated or full git object name.
+pub fn is_git_commit(commit: &str) -> bool {
+    (4..=64).contains(&commit.len()) && commit.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Returns the SHA-256 digest of `profile` serialized as `validate --resolved` prints
+/// it, the profile hash recorded in provenance files and upload metadata.
+///
+/// # Errors
+///
+/// Returns `RsdebstrapError::Config` if the profile cannot be serialized.
+pub fn profile_sha256(profile: &Profile) -> Result<String, RsdebstrapError> {
+    let digest = Sha256::digest(profile.to_yaml()?.as_bytes());
+    Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Formats seconds since the Unix epoch as an RFC 3339 UTC timestamp.
+pub(crate) fn format_utc(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+/// Quotes `value` for a shell-sourceable `KEY="value"` line, as in `os-release(5)`.
+fn quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The build inputs recorded in the provenance file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildInfo {
+    /// SHA-256 digest of the resolved profile, as `validate --resolved` prints it.
+    pub profile_sha256: String,
+    /// Version of rsdebstrap running the build.
+    pub version: String,
+    /// Bootstrap backend command (`mmdebstrap` or `debootstrap`).
+    pub backend: String,
+    /// Version of the bootstrap backend, if it could be determined.
+    pub backend_version: Option<String>,
+    /// Debian suite being bootstrapped.
+    pub suite: String,
+    /// Build time as an RFC 3339 UTC timestamp.
+    pub build_date: String,
+    /// Commit of the repository holding the profile (optional).
+    pub source_commit: Option<String>,
+}
+
+impl BuildInfo {
+    /// Collects the build information for `profile`.
+    ///
+    /// The build time honours `SOURCE_DATE_EPOCH`, so reproducible builds record a
+    /// fixed date.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `SOURCE_DATE_EPOCH` is not a number of
+    /// seconds, or `RsdebstrapError::Config` if the profile cannot be serialized.
+    pub fn for_profile(
+        profile: &Profile,
+        backend_version: Option<&ToolVersion>,
+        source_commit: Option<&str>,
+    ) -> Result<Self, RsdebstrapError> {
+        let secs = match std::env::var("SOURCE_DATE_EPOCH") {
+            Ok(value) => value.trim().parse::<u64>().map_err(|_| {
+                RsdebstrapError::Validation(format!(
+                    "SOURCE_DATE_EPOCH must be a number of seconds, got {:?}",
+                    value
+                ))
+            })?,
+            Err(_) => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default(),
+        };
+        Ok(Self {
+            profile_sha256: profile_sha256(profile)?,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            backend: profile.bootstrap.as_backend().command_name().to_string(),
+            backend_version: backend_version.map(ToolVersion::to_string),
+            suite: profile.bootstrap.suite().to_string(),
+            build_date: format_utc(secs),
+            source_commit: source_commit.map(str::to_string),
+        })
+    }
+
+    /// Renders the provenance file: one shell-sourceable `KEY="value"` line per field.
+    pub fn render(&self) -> String {
+        let mut fields = vec![
+            ("RSDEBSTRAP_VERSION", self.version.as_str()),
+            ("RSDEBSTRAP_PROFILE_SHA256", self.profile_sha256.as_str()),
+            ("RSDEBSTRAP_BACKEND", self.backend.as_str()),
+        ];
+        if let Some(version) = &self.backend_version {
+            fields.push(("RSDEBSTRAP_BACKEND_VERSION", version));
+        }
+        fields.push(("RSDEBSTRAP_SUITE", self.suite.as_str()));
+        fields.push(("RSDEBSTRAP_BUILD_DATE", self.build_date.as_str()));
+        if let Some(commit) = &self.source_commit {
+            fields.push(("RSDEBSTRAP_SOURCE_COMMIT", commit));
+        }
+        fields
+            .into_iter()
+            .map(|(key, value)| format!("{}={}\n", key, quote(value)))
+            .collect()
+    }
+}
+
+/// Assemble phase release task writing a build provenance file into the rootfs.
+///
+/// The file's content comes from the [`BuildInfo`] the runner sets in `build`
+/// before the pipeline starts. At most one `AssembleReleaseTask` may appear in the
+/// assemble phase.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct AssembleReleaseTask {
+    /// Privilege escalation setting (resolved during defaults application).
+    #[serde(default, skip_serializing_if = "Privilege::is_inherit")]
+    pub privilege: Privilege,
+    /// Log a failure of the task and keep going instead of failing the phase.
+    #[serde(default, skip_serializing_if = "crate::phase::is_false")]
+    pub ignore_errors: bool,
+    /// Absolute path of the provenance file inside the rootfs
+    /// (default: `/etc/rsdebstrap-release`).
+    #[serde(
+        default = "default_path",
+        deserialize_with = "crate::de::path",
+        skip_serializing_if = "is_default_path"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+    pub path: Utf8PathBuf,
+    /// Build information to record, set by the runner at run time.
+    #[serde(skip)]
+    pub build: Option<BuildInfo>,
+}
+
+impl Default for AssembleReleaseTask {
+    fn default() -> Self {
+        Self {
+            privilege: Privilege::default(),
+            ignore_errors: false,
+            path: default_path(),
+            build: None,
+        }
+    }
+}
+
+impl AssembleReleaseTask {
+    /// Resolves the privilege setting against profile defaults.
+    pub fn resolve_privilege(
+        &mut self,
+        defaults: Option<&PrivilegeDefaults>,
+    ) -> Result<(), RsdebstrapError> {
+        self.privilege.resolve_in_place(defaults)
+    }
+
+    /// Returns the resolved privilege method.
+    ///
+    /// Should only be called after `resolve_privilege()`.
+    pub fn resolved_privilege_method(&self) -> Option<PrivilegeMethod> {
+        self.privilege.resolved_method()
+    }
+
+    /// Validates the assemble release task configuration.
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        if !self.path.is_absolute() {
+            return Err(RsdebstrapError::Validation(format!(
+                "assemble release: path '{}' must be absolute",
+                self.path
+            )));
+        }
+        if !self
+            .path
+            .components()
+            .any(|c| matches!(c, Utf8Component::Normal(_)))
+        {
+            return Err(RsdebstrapError::Validation(format!(
+                "assemble release: path '{}' must name a file",
+                self.path
+            )));
+        }
+        if self.path.as_str().contains(['\0', '\n', '\r']) {
+            return Err(RsdebstrapError::Validation(format!(
+                "assemble release: path {:?} must not contain null or newline characters",
+                self.path
+            )));
+        }
+        validate_no_parent_dirs(&self.path, "assemble release")
+    }
+
+    /// Executes the assemble release task.
+    ///
+    /// Operates directly on the rootfs with privilege escalation when configured. The
+    /// file is staged at a sibling path and renamed into place, after the directories
+    /// leading to it are opened with `O_NOFOLLOW` so a symlink planted in the rootfs
+    /// cannot redirect the write to the host.
+    pub fn execute(&self, ctx: &dyn IsolationContext) -> anyhow::Result<()> {
+        let build = self.build.as_ref().ok_or_else(|| {
+            RsdebstrapError::Config(
+                "assemble release: no build information was set for this run".to_string(),
+            )
+        })?;
+        let rootfs = ctx.rootfs();
+        let relative = self.path.strip_prefix("/").unwrap_or(&self.path);
+        let target = rootfs.join(relative);
+
+        if ctx.dry_run() {
+            info!("would write build provenance to {}", target);
+            return Ok(());
+        }
+
+        if !dir_exists(rootfs, parent_dir(relative))? {
+            return Err(RsdebstrapError::Isolation(format!(
+                "assemble release: directory of {} does not exist in the rootfs",
+                self.path
+            ))
+            .into());
+        }
+        if target.symlink_metadata().is_ok_and(|m| m.is_dir()) {
+            return Err(RsdebstrapError::Isolation(format!(
+                "{} is a directory, refusing to replace it",
+                target
+            ))
+            .into());
+        }
+
+        let temp_file = tempfile::NamedTempFile::new()
+            .map_err(|e| RsdebstrapError::io("failed to create temporary file".to_string(), e))?;
+        std::fs::write(temp_file.path(), build.render()).map_err(|e| {
+            RsdebstrapError::io(
+                format!("failed to write temporary file {}", temp_file.path().display()),
+                e,
+            )
+        })?;
+        let temp_path = temp_file.path().to_string_lossy().to_string();
+
+        let executor = ctx.executor();
+        let privilege = self.resolved_privilege_method();
+        let staging = Utf8PathBuf::from(format!("{}{}", target, STAGING_SUFFIX));
+        // Remove a stale staging entry first so `cp` cannot write through a symlink.
+        for (command, args) in [
+            ("rm", vec!["-f".to_string(), staging.to_string()]),
+            ("cp", vec![temp_path, staging.to_string()]),
+            ("chmod", vec!["644".to_string(), staging.to_string()]),
+            ("mv", vec![staging.to_string(), target.to_string()]),
+        ] {
+            let spec = CommandSpec::new(command, args).with_privilege(privilege);
+            executor.execute_checked(&spec)?;
+        }
+
+        info!("wrote build provenance to {}", target);
+        Ok(())
+    }
+}
+
+impl PhaseItem for AssembleReleaseTask {
+    fn name(&self) -> Cow<'_, str> {
+        Cow::Owned(format!("release:{}", self.path))
+    }
+
+    fn validate(&self) -> Result<(), RsdebstrapError> {
+        AssembleReleaseTask::validate(self)
+    }
+
+    fn execute(&self, ctx: &dyn IsolationContext) -> anyhow::Result<()> {
+        // Assemble release operates directly on the final rootfs filesystem.
+        AssembleReleaseTask::execute(self, ctx)
+    }
+
+    fn resolved_isolation_config(&self) -> Option<&IsolationConfig> {
+        None
+    }
+
+    fn ignore_errors(&self) -> bool {
+        self.ignore_errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phase::assemble::test_support::MockAssembleContext;
+    use camino::Utf8Path;
+    use std::fs;
+
+    fn context(dir: &std::path::Path, dry_run: bool) -> MockAssembleContext {
+        MockAssembleContext::new(Utf8Path::from_path(dir).unwrap(), dry_run)
+    }
+
+    fn build_info(source_commit: Option<&str>) -> BuildInfo {
+        BuildInfo {
+            profile_sha256: "ab".repeat(32),
+            version: "1.2.3".to_string(),
+            backend: "mmdebstrap".to_string(),
+            backend_version: None,
+            suite: "trixie".to_string(),
+            build_date: "2026-01-02T03:04:05Z".to_string(),
+            source_commit: source_commit.map(str::to_string),
+        }
+    }
+
+    fn resolved_task(path: &str) -> AssembleReleaseTask {
+        AssembleReleaseTask {
+            privilege: Privilege::Disabled,
+            ignore_errors: false,
+            path: Utf8PathBuf::from(path),
+            build: Some(build_info(Some("0123abcd"))),
+        }
+    }
+
+    #[test]
+    fn format_utc_converts_epoch_seconds() {
+        assert_eq!(format_utc(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_utc(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_utc(1_767_323_045), "2026-01-02T03:04:05Z");
+    }
+
+    #[test]
+    fn is_git_commit_accepts_hex_object_names_only() {
+        assert!(is_git_commit("0123abcd"));
+        assert!(is_git_commit(&"f".repeat(40)));
+        assert!(!is_git_commit("abc"));
+        assert!(!is_git_commit("main"));
+        assert!(!is_git_commit(&"f".repeat(65)));
+    }
+
+    #[test]
+    fn render_writes_shell_sourceable_lines() {
+        assert_eq!(
+            build_info(Some("0123abcd")).render(),
+            format!(
+                "RSDEBSTRAP_VERSION=\"1.2.3\"\n\
+                RSDEBSTRAP_PROFILE_SHA256=\"{}\"\n\
+                RSDEBSTRAP_BACKEND=\"mmdebstrap\"\n\
+                RSDEBSTRAP_SUITE=\"trixie\"\n\
+                RSDEBSTRAP_BUILD_DATE=\"2026-01-02T03:04:05Z\"\n\
+                RSDEBSTRAP_SOURCE_COMMIT=\"0123abcd\"\n",
+                "ab".repeat(32)
+            )
+        );
+        let without_commit = build_info(None).render();
+        assert!(!without_commit.contains("SOURCE_COMMIT"), "{without_commit}");
+        assert!(!without_commit.contains("BACKEND_VERSION"), "{without_commit}");
+        let with_version = BuildInfo {
+            backend_version: Some("1.4.3".to_string()),
+            ..build_info(None)
+        }
+        .render();
+        assert!(
+            with_version.contains(
+                "RSDEBSTRAP_BACKEND=\"mmdebstrap\"\nRSDEBSTRAP_BACKEND_VERSION=\"1.4.3\"\n"
+            ),
+            "{with_version}"
+        );
+        assert_eq!(quote("a\"$`\\b"), "\"a\\\"\\$\\`\\\\b\"");
+    }
+
+    #[test]
+    fn validate_rejects_bad_paths() {
+        for (path, expected) in [
+            ("etc/release", "must be absolute"),
+            ("/", "must name a file"),
+            ("/etc/../release", ".."),
+            ("/etc/rel\nease", "newline"),
+        ] {
+            let err = resolved_task(path).validate().unwrap_err();
+            assert!(err.to_string().contains(expected), "{path}: {err}");
+        }
+        resolved_task(DEFAULT_PATH).validate().unwrap();
+    }
+
+    #[test]
+    fn default_path_is_omitted_when_serialized() {
+        let task: AssembleReleaseTask = yaml_serde::from_str("{}").unwrap();
+        assert_eq!(task, AssembleReleaseTask::default());
+        assert_eq!(yaml_serde::to_string(&task).unwrap().trim(), "{}");
+        assert_eq!(task.name(), "release:/etc/rsdebstrap-release");
+    }
+
+    #[test]
+    fn execute_writes_the_file() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::create_dir(temp.path().join("etc")).unwrap();
+        fs::write(temp.path().join("etc/rsdebstrap-release"), "stale").unwrap();
+        let ctx = context(temp.path(), false);
+
+        resolved_task(DEFAULT_PATH).execute(&ctx).unwrap();
+
+        let target = temp.path().join("etc/rsdebstrap-release");
+        assert_eq!(fs::read_to_string(&target).unwrap(), build_info(Some("0123abcd")).render());
+        assert!(
+            !temp
+                .path()
+                .join("etc/rsdebstrap-release.rsdebstrap-tmp")
+                .exists()
+        );
+        let commands: Vec<_> = ctx.commands().into_iter().map(|c| c[0].clone()).collect();
+        assert_eq!(commands, ["rm", "cp", "chmod", "mv"]);
+    }
+
+    #[test]
+    fn execute_refuses_a_symlinked_directory() {
+        let temp = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink(outside.path(), temp.path().join("etc")).unwrap();
+        let ctx = context(temp.path(), false);
+
+        let err = resolved_task(DEFAULT_PATH).execute(&ctx).unwrap_err();
+
+        assert!(err.to_string().contains("possible symlink attack"), "{err}");
+        assert!(fs::read_dir(outside.path()).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn execute_rejects_a_missing_directory() {
+        let temp = tempfile::tempdir().unwrap();
+        let ctx = context(temp.path(), false);
+
+        let err = resolved_task("/usr/share/rsdebstrap/release")
+            .execute(&ctx)
+            .unwrap_err();
+
+        assert!(err.to_string().contains("does not exist"), "{err}");
+    }
+
+    #[test]
+    fn execute_dry_run_and_missing_build_info() {
+        let temp = tempfile::tempdir().unwrap();
+        let ctx = context(temp.path(), true);
+
+        resolved_task(DEFAULT_PATH).execute(&ctx).unwrap();
+        assert!(ctx.commands().is_empty());
+
+        let task = AssembleReleaseTask {
+            build: None,
+            ..resolved_task(DEFAULT_PATH)
+        };
+        let err = task.execute(&ctx).unwrap_err();
+        assert!(err.to_string().contains("no build information"), "{err}");
+    }
+}
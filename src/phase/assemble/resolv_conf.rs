@@ -23,11 +23,6 @@ use crate::isolation::resolv_conf::generate_resolv_conf;
 use crate::phase::PhaseItem;
 use crate::privilege::{Privilege, PrivilegeDefaults, PrivilegeMethod};
 
-/// Returns true if the privilege setting is the default (`Inherit`).
-fn privilege_is_default(p: &Privilege) -> bool {
-    matches!(p, Privilege::Inherit)
-}
-
 /// Suffix for the staging entry used to atomically replace `/etc/resolv.conf`.
 ///
 /// Mirrors the prepare guard's `.rsdebstrap-orig` naming: the suffix is
@@ -56,8 +51,11 @@ fn staging_path(resolv_conf_path: &Utf8Path) -> Utf8PathBuf {
 #[serde(deny_unknown_fields)]
 pub struct AssembleResolvConfTask {
     /// Privilege escalation setting (resolved during defaults application).
-    #[serde(default, skip_serializing_if = "privilege_is_default")]
+    #[serde(default, skip_serializing_if = "Privilege::is_inherit")]
     pub privilege: Privilege,
+    /// Log a failure of the task and keep going instead of failing the phase.
+    #[serde(default, skip_serializing_if = "crate::phase::is_false")]
+    pub ignore_errors: bool,
     /// Symlink target path (mutually exclusive with `name_servers`/`search`).
     #[serde(
         default,
@@ -312,6 +310,10 @@ impl PhaseItem for AssembleResolvConfTask {
     fn resolved_isolation_config(&self) -> Option<&IsolationConfig> {
         None
     }
+
+    fn ignore_errors(&self) -> bool {
+        self.ignore_errors
+    }
 }
 
 #[cfg(test)]
@@ -364,6 +366,7 @@ mod tests {
     fn validate_rejects_mutual_exclusion() {
         let task = AssembleResolvConfTask {
             privilege: Privilege::Disabled,
+            ignore_errors: false,
             link: Some("/run/systemd/resolve/stub-resolv.conf".to_string()),
             name_servers: vec!["8.8.8.8".parse().unwrap()],
             search: vec![],
@@ -377,6 +380,7 @@ mod tests {
     fn validate_rejects_empty_config() {
         let task = AssembleResolvConfTask {
             privilege: Privilege::Disabled,
+            ignore_errors: false,
             link: None,
             name_servers: vec![],
             search: vec![],
@@ -390,6 +394,7 @@ mod tests {
     fn validate_rejects_empty_link() {
         let task = AssembleResolvConfTask {
             privilege: Privilege::Disabled,
+            ignore_errors: false,
             link: Some("".to_string()),
             name_servers: vec![],
             search: vec![],
@@ -403,6 +408,7 @@ mod tests {
     fn validate_rejects_link_with_newline() {
         let task = AssembleResolvConfTask {
             privilege: Privilege::Disabled,
+            ignore_errors: false,
             link: Some("foo\nbar".to_string()),
             name_servers: vec![],
             search: vec![],
@@ -416,6 +422,7 @@ mod tests {
     fn validate_rejects_link_with_carriage_return() {
         let task = AssembleResolvConfTask {
             privilege: Privilege::Disabled,
+            ignore_errors: false,
             link: Some("foo\rbar".to_string()),
             name_servers: vec![],
             search: vec![],
@@ -429,6 +436,7 @@ mod tests {
     fn validate_rejects_link_with_null() {
         let task = AssembleResolvConfTask {
             privilege: Privilege::Disabled,
+            ignore_errors: false,
             link: Some("foo\0bar".to_string()),
             name_servers: vec![],
             search: vec![],
@@ -442,6 +450,7 @@ mod tests {
     fn validate_delegates_nameserver_limits() {
         let task = AssembleResolvConfTask {
             privilege: Privilege::Disabled,
+            ignore_errors: false,
             link: None,
             name_servers: vec![
                 "8.8.8.8".parse().unwrap(),
@@ -460,6 +469,7 @@ mod tests {
     fn validate_link_and_search_mutual_exclusion() {
         let task = AssembleResolvConfTask {
             privilege: Privilege::Disabled,
+            ignore_errors: false,
             link: Some("/run/systemd/resolve/stub-resolv.conf".to_string()),
             name_servers: vec![],
             search: vec!["example.com".to_string()],
@@ -560,6 +570,7 @@ mod tests {
     fn serialize_skips_empty_fields() {
         let task = AssembleResolvConfTask {
             privilege: Privilege::Inherit,
+            ignore_errors: false,
             link: None,
             name_servers: vec![],
             search: vec![],
@@ -596,6 +607,7 @@ mod tests {
     fn resolve_privilege_disabled() {
         let mut task = AssembleResolvConfTask {
             privilege: Privilege::Disabled,
+            ignore_errors: false,
             link: None,
             name_servers: vec!["8.8.8.8".parse().unwrap()],
             search: vec![],
@@ -785,6 +797,7 @@ mod tests {
 
         let task = AssembleResolvConfTask {
             privilege: Privilege::Method(PrivilegeMethod::Sudo),
+            ignore_errors: false,
             link: None,
             name_servers: vec!["8.8.8.8".parse().unwrap()],
             search: vec![],
@@ -807,6 +820,7 @@ mod tests {
 
         let task = AssembleResolvConfTask {
             privilege: Privilege::Method(PrivilegeMethod::Doas),
+            ignore_errors: false,
             link: Some("/run/systemd/resolve/stub-resolv.conf".to_string()),
             name_servers: vec![],
             search: vec![],
@@ -983,6 +997,7 @@ mod tests {
     fn make_task_link(target: &str) -> AssembleResolvConfTask {
         AssembleResolvConfTask {
             privilege: Privilege::Inherit,
+            ignore_errors: false,
             link: Some(target.to_string()),
             name_servers: vec![],
             search: vec![],
@@ -992,6 +1007,7 @@ mod tests {
     fn make_task_link_resolved(target: &str) -> AssembleResolvConfTask {
         AssembleResolvConfTask {
             privilege: Privilege::Disabled,
+            ignore_errors: false,
             link: Some(target.to_string()),
             name_servers: vec![],
             search: vec![],
@@ -1001,6 +1017,7 @@ mod tests {
     fn make_task_generate(ns: Vec<&str>, search: Vec<&str>) -> AssembleResolvConfTask {
         AssembleResolvConfTask {
             privilege: Privilege::Inherit,
+            ignore_errors: false,
             link: None,
             name_servers: ns.into_iter().map(|s| s.parse().unwrap()).collect(),
             search: search.into_iter().map(|s| s.to_string()).collect(),
@@ -1010,6 +1027,7 @@ mod tests {
     fn make_task_generate_resolved(ns: Vec<&str>, search: Vec<&str>) -> AssembleResolvConfTask {
         AssembleResolvConfTask {
             privilege: Privilege::Disabled,
+            ignore_errors: false,
             link: None,
             name_servers: ns.into_iter().map(|s| s.parse().unwrap()).collect(),
             search: search.into_iter().map(|s| s.to_string()).collect(),
@@ -1058,6 +1076,7 @@ mod tests {
                 ));
                 return Ok(ExecutionResult {
                     status: Some(ExitStatus::from_raw(1 << 8)),
+                    details: None,
                 });
             }
 
@@ -1080,6 +1099,7 @@ mod tests {
 
             Ok(ExecutionResult {
                 status: Some(status),
+                details: None,
             })
         }
     }
@@ -0,0 +1,477 @@
+//! sanitize task implementation for the assemble phase.
+//!
+//! This module provides the `AssembleSanitizeTask`, which strips per-instance
+//! identifiers from the final rootfs so every machine cloned from the image
+//! generates its own: the machine ID, the saved random seed and the SSH host keys,
+//! plus any extra paths the profile lists.
+
+use std::borrow::Cow;
+use std::fs;
+
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::config::IsolationConfig;
+use crate::error::RsdebstrapError;
+use crate::executor::CommandSpec;
+use crate::isolation::IsolationContext;
+use crate::phase::assemble::{dir_exists, parent_dir};
+use crate::phase::{PhaseItem, validate_no_parent_dirs};
+use crate::privilege::{Privilege, PrivilegeDefaults, PrivilegeMethod};
+
+/// Machine ID, truncated (not removed) so systemd treats the next boot as the first.
+const MACHINE_ID: &str = "etc/machine-id";
+
+/// D-Bus machine ID, removed when it is a copy rather than a symlink to [`MACHINE_ID`].
+const DBUS_MACHINE_ID: &str = "var/lib/dbus/machine-id";
+
+/// Saved random seeds of systemd and of the sysvinit `urandom` script.
+const RANDOM_SEEDS: [&str; 2] = ["var/lib/systemd/random-seed", "var/lib/urandom/random-seed"];
+
+/// Directory holding the SSH host keys (`ssh_host_*_key` and `ssh_host_*_key.pub`).
+const SSH_DIR: &str = "etc/ssh";
+
+/// Returns `true` if `name` is an OpenSSH host key or host public key file name.
+fn is_ssh_host_key(name: &str) -> bool {
+    name.starts_with("ssh_host_") && (name.ends_with("_key") || name.ends_with("_key.pub"))
+}
+
+/// Assemble phase sanitize task removing per-instance identifiers from the rootfs.
+///
+/// Each built-in item is enabled by default, so `sanitize: {}` handles all of them.
+/// At most one `AssembleSanitizeTask` may appear in the assemble phase.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct AssembleSanitizeTask {
+    /// Privilege escalation setting (resolved during defaults application).
+    #[serde(default, skip_serializing_if = "Privilege::is_inherit")]
+    pub privilege: Privilege,
+    /// Log a failure of the task and keep going instead of failing the phase.
+    #[serde(default, skip_serializing_if = "crate::phase::is_false")]
+    pub ignore_errors: bool,
+    /// Truncate `/etc/machine-id` and remove a copied `/var/lib/dbus/machine-id`.
+    #[serde(
+        default = "crate::phase::default_true",
+        skip_serializing_if = "crate::phase::is_true"
+    )]
+    pub machine_id: bool,
+    /// Remove the saved random seed (`/var/lib/systemd/random-seed`,
+    /// `/var/lib/urandom/random-seed`).
+    #[serde(
+        default = "crate::phase::default_true",
+        skip_serializing_if = "crate::phase::is_true"
+    )]
+    pub random_seed: bool,
+    /// Remove the SSH host keys (`/etc/ssh/ssh_host_*_key` and their `.pub` files).
+    #[serde(
+        default = "crate::phase::default_true",
+        skip_serializing_if = "crate::phase::is_true"
+    )]
+    pub ssh_host_keys: bool,
+    /// Additional absolute paths inside the rootfs to remove (recursively).
+    #[serde(
+        default,
+        deserialize_with = "crate::de::path_list",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<String>>"))]
+    pub paths: Vec<Utf8PathBuf>,
+}
+
+impl Default for AssembleSanitizeTask {
+    fn default() -> Self {
+        Self {
+            privilege: Privilege::default(),
+            ignore_errors: false,
+            machine_id: true,
+            random_seed: true,
+            ssh_host_keys: true,
+            paths: Vec::new(),
+        }
+    }
+}
+
+impl AssembleSanitizeTask {
+    /// Returns the names of the enabled items, in execution order.
+    pub fn items(&self) -> Vec<&'static str> {
+        let mut items = Vec::new();
+        if self.machine_id {
+            items.push("machine-id");
+        }
+        if self.random_seed {
+            items.push("random-seed");
+        }
+        if self.ssh_host_keys {
+            items.push("ssh-host-keys");
+        }
+        if !self.paths.is_empty() {
+            items.push("paths");
+        }
+        items
+    }
+
+    /// Resolves the privilege setting against profile defaults.
+    pub fn resolve_privilege(
+        &mut self,
+        defaults: Option<&PrivilegeDefaults>,
+    ) -> Result<(), RsdebstrapError> {
+        self.privilege.resolve_in_place(defaults)
+    }
+
+    /// Returns the resolved privilege method.
+    ///
+    /// Should only be called after `resolve_privilege()`.
+    pub fn resolved_privilege_method(&self) -> Option<PrivilegeMethod> {
+        self.privilege.resolved_method()
+    }
+
+    /// Validates the assemble sanitize task configuration.
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        if self.items().is_empty() {
+            return Err(RsdebstrapError::Validation(
+                "assemble sanitize: nothing to sanitize (every item is disabled and 'paths' \
+                is empty)"
+                    .to_string(),
+            ));
+        }
+        for path in &self.paths {
+            if !path.is_absolute() {
+                return Err(RsdebstrapError::Validation(format!(
+                    "assemble sanitize: path '{}' must be absolute",
+                    path
+                )));
+            }
+            if !path
+                .components()
+                .any(|c| matches!(c, Utf8Component::Normal(_)))
+            {
+                return Err(RsdebstrapError::Validation(format!(
+                    "assemble sanitize: path '{}' must not be the rootfs root",
+                    path
+                )));
+            }
+            if path.as_str().contains(['\0', '\n', '\r']) {
+                return Err(RsdebstrapError::Validation(format!(
+                    "assemble sanitize: path {:?} must not contain null or newline characters",
+                    path
+                )));
+            }
+            validate_no_parent_dirs(path, "assemble sanitize")?;
+        }
+        Ok(())
+    }
+
+    /// Executes the assemble sanitize task.
+    ///
+    /// Operates directly on the rootfs with privilege escalation when configured.
+    /// Every directory leading to a target is opened with `O_NOFOLLOW`, so a symlink
+    /// planted in the rootfs cannot redirect the removal to the host; a target whose
+    /// directory does not exist is skipped. The targets themselves are removed with
+    /// `rm`, which never follows a final symlink.
+    pub fn execute(&self, ctx: &dyn IsolationContext) -> anyhow::Result<()> {
+        let rootfs = ctx.rootfs();
+
+        if ctx.dry_run() {
+            info!("would sanitize {} in {}", self.items().join(", "), rootfs);
+            return Ok(());
+        }
+
+        let executor = ctx.executor();
+        let privilege = self.resolved_privilege_method();
+        let mut files = Vec::new();
+
+        if self.machine_id {
+            let machine_id = rootfs.join(MACHINE_ID);
+            if dir_exists(rootfs, parent_dir(Utf8Path::new(MACHINE_ID)))? {
+                match machine_id.symlink_metadata() {
+                    Ok(meta) if meta.is_file() => {
+                        let spec = CommandSpec::new(
+                            "truncate",
+                            vec!["-s".to_string(), "0".to_string(), machine_id.to_string()],
+                        )
+                        .with_privilege(privilege);
+                        executor.execute_checked(&spec)?;
+                    }
+                    Ok(_) => {
+                        return Err(RsdebstrapError::Isolation(format!(
+                            "{} is not a regular file, refusing to truncate it",
+                            machine_id
+                        ))
+                        .into());
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(e) => {
+                        return Err(RsdebstrapError::io(
+                            format!("failed to stat {}", machine_id),
+                            e,
+                        )
+                        .into());
+                    }
+                }
+            }
+            // Debian links the D-Bus machine ID to /etc/machine-id; only a copy goes stale.
+            let dbus = rootfs.join(DBUS_MACHINE_ID);
+            if dir_exists(rootfs, parent_dir(Utf8Path::new(DBUS_MACHINE_ID)))?
+                && dbus.symlink_metadata().is_ok_and(|m| m.is_file())
+            {
+                files.push(dbus);
+            }
+        }
+
+        if self.random_seed {
+            for seed in RANDOM_SEEDS {
+                if dir_exists(rootfs, parent_dir(Utf8Path::new(seed)))? {
+                    files.push(rootfs.join(seed));
+                }
+            }
+        }
+
+        if self.ssh_host_keys && dir_exists(rootfs, Utf8Path::new(SSH_DIR))? {
+            let ssh_dir = rootfs.join(SSH_DIR);
+            let entries = fs::read_dir(&ssh_dir)
+                .map_err(|e| RsdebstrapError::io(format!("failed to read {}", ssh_dir), e))?;
+            let mut keys = Vec::new();
+            for entry in entries {
+                let entry = entry
+                    .map_err(|e| RsdebstrapError::io(format!("failed to read {}", ssh_dir), e))?;
+                if let Some(name) = entry.file_name().to_str()
+                    && is_ssh_host_key(name)
+                {
+                    keys.push(ssh_dir.join(name));
+                }
+            }
+            keys.sort();
+            files.extend(keys);
+        }
+
+        if !files.is_empty() {
+            let mut args = vec!["-f".to_string()];
+            args.extend(files.iter().map(ToString::to_string));
+            let spec = CommandSpec::new("rm", args).with_privilege(privilege);
+            executor.execute_checked(&spec)?;
+        }
+
+        let mut paths = Vec::new();
+        for path in &self.paths {
+            let relative = path.strip_prefix("/").unwrap_or(path);
+            if dir_exists(rootfs, parent_dir(relative))? {
+                paths.push(rootfs.join(relative).to_string());
+            }
+        }
+        if !paths.is_empty() {
+            let mut args = vec!["-rf".to_string(), "--one-file-system".to_string()];
+            args.extend(paths);
+            let spec = CommandSpec::new("rm", args).with_privilege(privilege);
+            executor.execute_checked(&spec)?;
+        }
+
+        info!("sanitized {} in {}", self.items().join(", "), rootfs);
+        Ok(())
+    }
+}
+
+impl PhaseItem for AssembleSanitizeTask {
+    fn name(&self) -> Cow<'_, str> {
+        Cow::Owned(format!("sanitize:{}", self.items().join(",")))
+    }
+
+    fn validate(&self) -> Result<(), RsdebstrapError> {
+        AssembleSanitizeTask::validate(self)
+    }
+
+    fn execute(&self, ctx: &dyn IsolationContext) -> anyhow::Result<()> {
+        // Assemble sanitize operates directly on the final rootfs filesystem.
+        AssembleSanitizeTask::execute(self, ctx)
+    }
+
+    fn resolved_isolation_config(&self) -> Option<&IsolationConfig> {
+        None
+    }
+
+    fn ignore_errors(&self) -> bool {
+        self.ignore_errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phase::assemble::test_support::MockAssembleContext;
+
+    /// Creates a rootfs holding every identifier the task removes.
+    fn populated_rootfs(dir: &std::path::Path) -> Utf8PathBuf {
+        let rootfs = Utf8PathBuf::from_path_buf(dir.to_path_buf()).unwrap();
+        for sub in [
+            "etc/ssh",
+            "var/lib/dbus",
+            "var/lib/systemd",
+            "var/log/installer",
+        ] {
+            fs::create_dir_all(rootfs.join(sub)).unwrap();
+        }
+        fs::write(rootfs.join(MACHINE_ID), "0123456789abcdef\n").unwrap();
+        fs::write(rootfs.join(DBUS_MACHINE_ID), "0123456789abcdef\n").unwrap();
+        fs::write(rootfs.join("var/lib/systemd/random-seed"), "seed").unwrap();
+        for name in [
+            "ssh_host_ed25519_key",
+            "ssh_host_ed25519_key.pub",
+            "sshd_config",
+        ] {
+            fs::write(rootfs.join(SSH_DIR).join(name), name).unwrap();
+        }
+        fs::write(rootfs.join("var/log/installer/syslog"), "log").unwrap();
+        rootfs
+    }
+
+    fn resolved_task() -> AssembleSanitizeTask {
+        AssembleSanitizeTask {
+            privilege: Privilege::Disabled,
+            ignore_errors: false,
+            ..AssembleSanitizeTask::default()
+        }
+    }
+
+    #[test]
+    fn is_ssh_host_key_matches_keys_only() {
+        assert!(is_ssh_host_key("ssh_host_rsa_key"));
+        assert!(is_ssh_host_key("ssh_host_ecdsa_key.pub"));
+        assert!(!is_ssh_host_key("sshd_config"));
+        assert!(!is_ssh_host_key("ssh_host_rsa_key-cert.pub"));
+    }
+
+    #[test]
+    fn validate_rejects_nothing_to_do() {
+        let task = AssembleSanitizeTask {
+            machine_id: false,
+            random_seed: false,
+            ssh_host_keys: false,
+            ..AssembleSanitizeTask::default()
+        };
+        let err = task.validate().unwrap_err();
+        assert!(err.to_string().contains("nothing to sanitize"), "{err}");
+    }
+
+    #[test]
+    fn validate_rejects_bad_paths() {
+        for (path, expected) in [
+            ("var/log", "must be absolute"),
+            ("/", "rootfs root"),
+            ("/var/../etc", "'..'"),
+            ("/var/log\n", "newline"),
+        ] {
+            let task = AssembleSanitizeTask {
+                paths: vec![path.into()],
+                ..AssembleSanitizeTask::default()
+            };
+            let err = task.validate().unwrap_err();
+            assert!(err.to_string().contains(expected), "{path:?}: {err}");
+        }
+    }
+
+    #[test]
+    fn deserialize_empty_map_enables_every_item() {
+        let task: AssembleSanitizeTask = yaml_serde::from_str("{}").unwrap();
+        assert_eq!(task, AssembleSanitizeTask::default());
+        assert_eq!(task.items(), ["machine-id", "random-seed", "ssh-host-keys"]);
+
+        let task: AssembleSanitizeTask =
+            yaml_serde::from_str("ssh_host_keys: false\npaths: [/var/log/installer]\n").unwrap();
+        assert_eq!(task.items(), ["machine-id", "random-seed", "paths"]);
+        let yaml = yaml_serde::to_string(&task).unwrap();
+        assert_eq!(yaml_serde::from_str::<AssembleSanitizeTask>(&yaml).unwrap(), task);
+        assert!(!yaml.contains("machine_id"), "{yaml}");
+    }
+
+    #[test]
+    fn execute_removes_instance_identifiers() {
+        let temp = tempfile::tempdir().unwrap();
+        let rootfs = populated_rootfs(temp.path());
+        let task = AssembleSanitizeTask {
+            paths: vec!["/var/log/installer".into(), "/opt/missing/dir".into()],
+            ..resolved_task()
+        };
+
+        let ctx = MockAssembleContext::new(&rootfs, false);
+        task.execute(&ctx).unwrap();
+
+        assert_eq!(fs::read_to_string(rootfs.join(MACHINE_ID)).unwrap(), "");
+        assert!(!rootfs.join(DBUS_MACHINE_ID).exists());
+        assert!(!rootfs.join("var/lib/systemd/random-seed").exists());
+        assert!(!rootfs.join("etc/ssh/ssh_host_ed25519_key").exists());
+        assert!(!rootfs.join("etc/ssh/ssh_host_ed25519_key.pub").exists());
+        assert!(rootfs.join("etc/ssh/sshd_config").exists());
+        assert!(!rootfs.join("var/log/installer").exists());
+        assert!(rootfs.join("var/log").exists());
+        assert_eq!(ctx.commands().len(), 3);
+    }
+
+    #[test]
+    fn execute_keeps_dbus_machine_id_symlink() {
+        let temp = tempfile::tempdir().unwrap();
+        let rootfs = populated_rootfs(temp.path());
+        fs::remove_file(rootfs.join(DBUS_MACHINE_ID)).unwrap();
+        std::os::unix::fs::symlink("/etc/machine-id", rootfs.join(DBUS_MACHINE_ID)).unwrap();
+
+        let ctx = MockAssembleContext::new(&rootfs, false);
+        resolved_task().execute(&ctx).unwrap();
+
+        assert!(rootfs.join(DBUS_MACHINE_ID).symlink_metadata().is_ok());
+    }
+
+    #[test]
+    fn execute_skips_missing_directories() {
+        let temp = tempfile::tempdir().unwrap();
+        let rootfs = Utf8PathBuf::from_path_buf(temp.path().to_path_buf()).unwrap();
+
+        let ctx = MockAssembleContext::new(&rootfs, false);
+        resolved_task().execute(&ctx).unwrap();
+
+        assert!(ctx.commands().is_empty());
+    }
+
+    #[test]
+    fn execute_refuses_symlinked_directory() {
+        let temp = tempfile::tempdir().unwrap();
+        let rootfs = populated_rootfs(temp.path());
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("ssh_host_rsa_key"), "host key").unwrap();
+        fs::remove_dir_all(rootfs.join(SSH_DIR)).unwrap();
+        std::os::unix::fs::symlink(outside.path(), rootfs.join(SSH_DIR)).unwrap();
+
+        let ctx = MockAssembleContext::new(&rootfs, false);
+        let err = resolved_task().execute(&ctx).unwrap_err();
+
+        assert!(err.to_string().contains("symlink attack"), "{err}");
+        assert!(outside.path().join("ssh_host_rsa_key").exists());
+    }
+
+    #[test]
+    fn execute_refuses_symlinked_machine_id() {
+        let temp = tempfile::tempdir().unwrap();
+        let rootfs = populated_rootfs(temp.path());
+        fs::remove_file(rootfs.join(MACHINE_ID)).unwrap();
+        std::os::unix::fs::symlink("/etc/hostname", rootfs.join(MACHINE_ID)).unwrap();
+
+        let ctx = MockAssembleContext::new(&rootfs, false);
+        let err = resolved_task().execute(&ctx).unwrap_err();
+
+        assert!(err.to_string().contains("not a regular file"), "{err}");
+        assert!(ctx.commands().is_empty());
+    }
+
+    #[test]
+    fn execute_dry_run_changes_nothing() {
+        let temp = tempfile::tempdir().unwrap();
+        let rootfs = populated_rootfs(temp.path());
+
+        let ctx = MockAssembleContext::new(&rootfs, true);
+        resolved_task().execute(&ctx).unwrap();
+
+        assert!(ctx.commands().is_empty());
+        assert!(rootfs.join("etc/ssh/ssh_host_ed25519_key").exists());
+    }
+}
@@ -0,0 +1,83 @@
+//! Test doubles shared by the assemble task tests.
+
+use std::sync::{Arc, Mutex};
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::executor::{CommandExecutor, CommandSpec, ExecutionResult};
+use crate::isolation::IsolationContext;
+use crate::privilege::PrivilegeMethod;
+
+/// Runs commands for real (so tests see the file effects) and records them.
+#[derive(Default)]
+pub(super) struct RunningExecutor {
+    commands: Mutex<Vec<Vec<String>>>,
+}
+
+impl CommandExecutor for RunningExecutor {
+    fn execute(&self, spec: &CommandSpec) -> anyhow::Result<ExecutionResult> {
+        let status = std::process::Command::new(&spec.command)
+            .args(&spec.args)
+            .status()?;
+        let mut command = vec![spec.command.clone()];
+        command.extend(spec.args.iter().cloned());
+        self.commands.lock().unwrap().push(command);
+        Ok(ExecutionResult {
+            status: Some(status),
+            details: None,
+        })
+    }
+}
+
+/// Isolation context of an assemble task: its commands run on the host through a
+/// [`RunningExecutor`].
+pub(super) struct MockAssembleContext {
+    rootfs: Utf8PathBuf,
+    dry_run: bool,
+    executor: Arc<RunningExecutor>,
+}
+
+impl MockAssembleContext {
+    pub(super) fn new(rootfs: &Utf8Path, dry_run: bool) -> Self {
+        Self {
+            rootfs: rootfs.to_owned(),
+            dry_run,
+            executor: Arc::new(RunningExecutor::default()),
+        }
+    }
+
+    /// Returns the commands run so far, each with its arguments.
+    pub(super) fn commands(&self) -> Vec<Vec<String>> {
+        self.executor.commands.lock().unwrap().clone()
+    }
+}
+
+impl IsolationContext for MockAssembleContext {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn rootfs(&self) -> &Utf8Path {
+        &self.rootfs
+    }
+
+    fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    fn executor(&self) -> &dyn CommandExecutor {
+        &*self.executor
+    }
+
+    fn execute(
+        &self,
+        _command: &[String],
+        _privilege: Option<PrivilegeMethod>,
+    ) -> anyhow::Result<ExecutionResult> {
+        unimplemented!("not used by assemble task tests")
+    }
+
+    fn teardown(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
@@ -7,9 +7,10 @@
 //!
 //! - [`prepare`] — Preparation tasks before main provisioning (named-field
 //!   [`PrepareConfig`]: `mount`, `resolv_conf`)
-//! - [`provision`] — Main provisioning tasks (Shell, Mitamae), an ordered `Vec`
+//! - [`provision`] — Main provisioning tasks (Shell, Mitamae, ..., Plugin), an ordered `Vec`
 //! - [`assemble`] — Finalization tasks after provisioning (named-field
-//!   [`AssembleConfig`]: `resolv_conf`)
+//!   [`AssembleConfig`]: `extract`, `resolv_conf`, `sanitize`, `clean`, `release`)
+//! - [`verify`] — Assertions about the finished rootfs ([`VerifyTask`]), an ordered `Vec`
 //!
 //! Adding a new task to a named-field phase requires:
 //! 1. Adding an `Option<...>` field to the phase config struct
@@ -19,6 +20,7 @@
 pub mod assemble;
 pub mod prepare;
 pub mod provision;
+pub mod verify;
 
 use std::borrow::Cow;
 use std::fs;
@@ -27,19 +29,33 @@ use anyhow::{Context, Result};
 use camino::{Utf8Path, Utf8PathBuf};
 use tracing::info;
 
+pub use assemble::AssembleCleanTask;
 pub use assemble::AssembleConfig;
+pub use assemble::AssembleExtractTask;
+pub use assemble::AssembleReleaseTask;
 pub use assemble::AssembleResolvConfTask;
+pub use assemble::AssembleSanitizeTask;
 pub use prepare::MountTask;
 pub use prepare::PrepareConfig;
 pub use prepare::ResolvConfTask;
+pub use provision::CookbookTask;
+pub use provision::DebconfTask;
+pub use provision::KernelTask;
 pub use provision::MitamaeTask;
+pub use provision::OverlayTask;
+pub use provision::PluginTask;
 pub use provision::ProvisionTask;
+pub use provision::PuppetTask;
+pub use provision::RsyncTask;
 pub use provision::ShellTask;
+pub use provision::SshTask;
+pub use provision::WasmTask;
+pub use verify::VerifyTask;
 
-use crate::config::IsolationConfig;
+use crate::config::{IsolationConfig, MountEntry};
 use crate::error::RsdebstrapError;
 use crate::executor::ExecutionResult;
-use crate::isolation::IsolationContext;
+use crate::isolation::{IsolationContext, TaskIsolation};
 use crate::privilege::PrivilegeMethod;
 
 /// Script source for task execution.
@@ -111,9 +127,141 @@ impl ScriptSource {
 /// This is not an extension point, but for internal convenience only.
 pub(crate) trait PhaseItem: std::fmt::Debug {
     fn name(&self) -> Cow<'_, str>;
+    /// The user-given `name`, used to label the task in errors. Built-in
+    /// prepare/assemble tasks are identified by their key instead.
+    fn configured_name(&self) -> Option<&str> {
+        None
+    }
     fn validate(&self) -> Result<(), RsdebstrapError>;
     fn execute(&self, ctx: &dyn IsolationContext) -> Result<()>;
     fn resolved_isolation_config(&self) -> Option<&IsolationConfig>;
+    /// Whether the task may use the network. Built-in prepare/assemble tasks only
+    /// touch files, so they keep the host's network namespace.
+    fn network_enabled(&self) -> bool {
+        true
+    }
+    /// Privilege method for the isolation backend's own setup (e.g. chroot bind
+    /// mounts) and the task's own mounts. Built-in prepare/assemble tasks don't use
+    /// per-task isolation.
+    fn isolation_privilege(&self) -> Option<PrivilegeMethod> {
+        None
+    }
+    /// Filesystems mounted into the rootfs for this task only, on top of the
+    /// `prepare.mount` mounts.
+    fn task_mounts(&self) -> &[MountEntry] {
+        &[]
+    }
+    /// Whether a failure of the task is logged and the phase goes on, as if it had
+    /// succeeded (`ignore_errors: true`). Prepare tasks never ignore errors.
+    fn ignore_errors(&self) -> bool {
+        false
+    }
+}
+
+/// Serde default for boolean task options that are enabled unless turned off.
+pub(crate) fn default_true() -> bool {
+    true
+}
+
+/// Serde `skip_serializing_if` for options defaulting to [`default_true`].
+pub(crate) fn is_true(value: &bool) -> bool {
+    *value
+}
+
+/// Serde `skip_serializing_if` for options that are off by default.
+pub(crate) fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Validates a task's own `mounts`: each entry's format and their order (parent
+/// before child).
+pub(crate) fn validate_task_mounts(mounts: &[MountEntry]) -> Result<(), RsdebstrapError> {
+    for entry in mounts {
+        entry.validate()?;
+    }
+    crate::config::validate_mount_order(mounts)
+}
+
+/// Validates a task's `name`: if set, it must not be blank.
+pub(crate) fn validate_task_name(name: Option<&str>) -> Result<(), RsdebstrapError> {
+    if name.is_some_and(|n| n.trim().is_empty()) {
+        return Err(RsdebstrapError::Validation("task name must not be blank".to_string()));
+    }
+    Ok(())
+}
+
+/// Validates a task's `tags`: each must be non-empty and free of whitespace and
+/// commas, so it can be named in a comma-separated `--tags` list.
+pub(crate) fn validate_task_tags(tags: &[String]) -> Result<(), RsdebstrapError> {
+    for tag in tags {
+        if tag.is_empty() || tag.contains(|c: char| c.is_whitespace() || c == ',') {
+            return Err(RsdebstrapError::Validation(format!(
+                "invalid tag {:?}: tags must be non-empty without whitespace or commas",
+                tag
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Resolves a task's `network` setting against its isolation config and the
+/// profile-level `offline` flag.
+///
+/// The task's own setting wins over the isolation config's. With `offline`, the
+/// result is always `Some(false)`, and an explicit `true` at either level is a
+/// validation error rather than being silently overridden.
+pub(crate) fn resolve_network(
+    task_network: Option<bool>,
+    isolation: Option<&IsolationConfig>,
+    offline: bool,
+) -> Result<Option<bool>, RsdebstrapError> {
+    let network = task_network.or_else(|| isolation.and_then(IsolationConfig::network));
+    if !offline {
+        return Ok(network);
+    }
+    if network == Some(true) {
+        return Err(RsdebstrapError::Validation(
+            "network: true is not allowed when the profile sets offline: true".to_string(),
+        ));
+    }
+    Ok(Some(false))
+}
+
+/// Merges a provision task's `user`/`group` into its resolved chroot isolation as
+/// `user[:group]`, so it reaches `chroot --userspec` like an isolation-level `user`.
+///
+/// Re-resolving is a no-op when the isolation already names the same user, which keeps
+/// a serialized profile loadable. Call after the isolation is resolved.
+pub(crate) fn resolve_task_user(
+    isolation: &mut TaskIsolation,
+    user: Option<&str>,
+    group: Option<&str>,
+) -> Result<(), RsdebstrapError> {
+    let spec = match (user, group) {
+        (None, None) => return Ok(()),
+        (None, Some(_)) => {
+            return Err(RsdebstrapError::Validation("task 'group' requires 'user'".to_string()));
+        }
+        (Some(user), None) => user.to_string(),
+        (Some(user), Some(group)) => format!("{}:{}", user, group),
+    };
+    let TaskIsolation::Config(config) = isolation else {
+        return Err(RsdebstrapError::Validation(format!(
+            "task user '{}' requires chroot isolation (isolation: false runs on the host)",
+            spec
+        )));
+    };
+    let chroot = config.options_mut();
+    match &chroot.user {
+        Some(existing) if *existing != spec => Err(RsdebstrapError::Validation(format!(
+            "task user '{}' conflicts with isolation user '{}'",
+            spec, existing
+        ))),
+        _ => {
+            chroot.user = Some(spec);
+            Ok(())
+        }
+    }
 }
 
 /// Validates that a path contains no `..` components.
@@ -176,6 +324,16 @@ pub(crate) fn resolve_script_source<E: serde::de::Error>(
     }
 }
 
+/// Splits a [`ScriptSource`] back into its `script`/`content` fields.
+///
+/// The inverse of [`resolve_script_source`], used by task `Serialize` impls.
+pub(crate) fn split_script_source(source: &ScriptSource) -> (Option<Utf8PathBuf>, Option<String>) {
+    match source {
+        ScriptSource::Script(path) => (Some(path.clone()), None),
+        ScriptSource::Content(content) => (None, Some(content.clone())),
+    }
+}
+
 /// RAII guard to ensure temporary file cleanup even on error.
 pub(crate) struct TempFileGuard {
     path: Utf8PathBuf,
@@ -209,19 +367,160 @@ impl Drop for TempFileGuard {
     }
 }
 
-/// Sets Unix file permissions on the given path.
+/// RAII guard to ensure a staged directory tree is removed even on error.
+pub(crate) struct TempDirGuard {
+    path: Utf8PathBuf,
+    dry_run: bool,
+}
+
+impl TempDirGuard {
+    pub(crate) fn new(path: Utf8PathBuf, dry_run: bool) -> Self {
+        Self { path, dry_run }
+    }
+}
+
+impl Drop for TempDirGuard {
+    fn drop(&mut self) {
+        if !self.dry_run {
+            // `remove_dir_all` does not follow symlinks, so a link created by the task
+            // inside the tree cannot redirect the removal.
+            match fs::remove_dir_all(&self.path) {
+                Ok(()) => tracing::debug!("cleaned up temp directory: {}", self.path),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    tracing::debug!("temp directory already removed: {}", self.path);
+                }
+                Err(e) => {
+                    tracing::error!(
+                        path = %self.path,
+                        error_kind = ?e.kind(),
+                        "failed to cleanup temp directory: {}",
+                        e,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Sets Unix file permissions on the given path, masked with the profile's
+/// `defaults.umask` if it sets one.
 #[cfg(unix)]
 pub(crate) fn set_file_mode(path: &Utf8Path, mode: u32) -> Result<()> {
     use std::os::unix::fs::PermissionsExt;
     let mut perms = fs::metadata(path)
         .with_context(|| format!("failed to read metadata for {}", path))?
         .permissions();
-    perms.set_mode(mode);
+    perms.set_mode(crate::umask::mask(mode));
     fs::set_permissions(path, perms)
         .with_context(|| format!("failed to set permissions on {}", path))?;
     Ok(())
 }
 
+/// Returns the entries below `dir` as relative paths, parents before children and
+/// sorted within each directory.
+///
+/// `dir` must be a real directory; symlinks and special files anywhere in the tree
+/// are rejected, since the copy is later handed over with `chown`, which follows links.
+pub(crate) fn walk_host_tree(
+    dir: &Utf8Path,
+    label: &str,
+) -> Result<Vec<Utf8PathBuf>, RsdebstrapError> {
+    let metadata = fs::symlink_metadata(dir).map_err(|e| {
+        RsdebstrapError::io(format!("failed to read {} directory metadata: {}", label, dir), e)
+    })?;
+    if !metadata.is_dir() {
+        return Err(RsdebstrapError::Validation(format!(
+            "{} path '{}' is not a directory",
+            label, dir
+        )));
+    }
+
+    let mut entries = Vec::new();
+    let mut pending = vec![Utf8PathBuf::new()];
+    while let Some(relative) = pending.pop() {
+        let current = dir.join(&relative);
+        let read = fs::read_dir(&current)
+            .map_err(|e| RsdebstrapError::io(format!("failed to read {}", current), e))?;
+        let mut names = Vec::new();
+        for entry in read {
+            let entry =
+                entry.map_err(|e| RsdebstrapError::io(format!("failed to read {}", current), e))?;
+            let name = entry.file_name().into_string().map_err(|name| {
+                RsdebstrapError::Validation(format!(
+                    "{} entry {:?} in {} is not valid UTF-8",
+                    label, name, current
+                ))
+            })?;
+            names.push(name);
+        }
+        names.sort();
+        let mut subdirs = Vec::new();
+        for name in names {
+            let child = relative.join(&name);
+            let path = dir.join(&child);
+            let metadata = fs::symlink_metadata(&path)
+                .map_err(|e| RsdebstrapError::io(format!("failed to read {}", path), e))?;
+            if metadata.is_dir() {
+                subdirs.push(child.clone());
+            } else if !metadata.is_file() {
+                return Err(RsdebstrapError::Validation(format!(
+                    "{} entry '{}' is not a regular file or directory \
+                    (symlinks are not allowed for security reasons)",
+                    label, path
+                )));
+            }
+            entries.push(child);
+        }
+        pending.extend(subdirs.into_iter().rev());
+    }
+    Ok(entries)
+}
+
+/// Copies the host directory `source` to the new directory `target` in the rootfs.
+///
+/// `target` is created with 0o700 and must not exist yet; entries keep their
+/// permission bits. Returns the copied paths as seen inside the isolation (with
+/// `target` at `in_isolation`), ready for [`IsolationContext::hand_over`].
+pub(crate) fn copy_host_tree(
+    source: &Utf8Path,
+    target: &Utf8Path,
+    in_isolation: &str,
+    label: &str,
+) -> Result<Vec<String>> {
+    info!("copying {} from {} to rootfs", label, source);
+    fs::create_dir(target).with_context(|| format!("failed to create {}", target))?;
+    set_file_mode(target, 0o700)?;
+    let mut copied = vec![in_isolation.to_string()];
+    for relative in walk_host_tree(source, label)? {
+        copy_tree_entry(&source.join(&relative), &target.join(&relative), label)?;
+        copied.push(format!("{}/{}", in_isolation, relative));
+    }
+    Ok(copied)
+}
+
+/// Copies one entry of a walked tree, keeping its permission bits.
+fn copy_tree_entry(source: &Utf8Path, target: &Utf8Path, label: &str) -> Result<()> {
+    let metadata = fs::symlink_metadata(source)
+        .with_context(|| format!("failed to read metadata for {}", source))?;
+    // Directories stay writable by their owner so their children can be copied in.
+    let mut mode = std::os::unix::fs::PermissionsExt::mode(&metadata.permissions()) & 0o7777;
+    if metadata.is_dir() {
+        mode |= 0o700;
+        fs::create_dir(target).with_context(|| format!("failed to create {}", target))?;
+    } else if metadata.is_file() {
+        fs::copy(source, target)
+            .with_context(|| format!("failed to copy {} to {}", source, target))?;
+    } else {
+        return Err(RsdebstrapError::Validation(format!(
+            "{} entry '{}' changed to a non-regular file during the copy",
+            label, source
+        ))
+        .into());
+    }
+    set_file_mode(target, mode)?;
+    Ok(())
+}
+
 /// Copies or writes a script source to the target path and sets permissions.
 ///
 /// On Unix systems, sets the file mode to the specified `mode`.
@@ -249,52 +548,70 @@ pub(crate) fn prepare_source_file(
     Ok(())
 }
 
-/// Validates that /tmp exists as a real directory (not a symlink).
+/// Validates that the staging directory `dir` (e.g. `/tmp`) and each of its parents
+/// exist in the rootfs as real directories (not symlinks).
 ///
 /// This is a security-critical check to prevent attackers from using symlinks
 /// to write files outside the chroot.
-pub(crate) fn validate_tmp_directory(rootfs: &Utf8Path) -> Result<()> {
-    let tmp_dir = rootfs.join("tmp");
-    let metadata = match std::fs::symlink_metadata(&tmp_dir) {
-        Ok(metadata) => metadata,
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+pub(crate) fn validate_staging_directory(rootfs: &Utf8Path, dir: &Utf8Path) -> Result<()> {
+    let mut in_rootfs = Utf8PathBuf::from("/");
+    for component in dir.strip_prefix("/").unwrap_or(dir).components() {
+        in_rootfs.push(component);
+        let host_path = rootfs.join(in_rootfs.strip_prefix("/").unwrap_or(&in_rootfs));
+        let metadata = match std::fs::symlink_metadata(&host_path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(RsdebstrapError::Validation(format!(
+                    "{} directory not found in rootfs at {}. \
+                    The rootfs may not be properly bootstrapped.",
+                    in_rootfs, host_path
+                ))
+                .into());
+            }
+            Err(e) => {
+                return Err(RsdebstrapError::io(
+                    format!("failed to read {} metadata at {}", in_rootfs, host_path),
+                    e,
+                )
+                .into());
+            }
+        };
+
+        if metadata.file_type().is_symlink() {
             return Err(RsdebstrapError::Validation(format!(
-                "/tmp directory not found in rootfs at {}. \
-                The rootfs may not be properly bootstrapped.",
-                tmp_dir
+                "{} in rootfs is a symlink, which is not allowed for security reasons. \
+                An attacker could use this to write files outside the chroot.",
+                in_rootfs
             ))
             .into());
         }
-        Err(e) => {
-            return Err(RsdebstrapError::io(
-                format!("failed to read /tmp metadata at {}", tmp_dir),
-                e,
-            )
+
+        if !metadata.file_type().is_dir() {
+            return Err(RsdebstrapError::Validation(format!(
+                "{} in rootfs is not a directory: {}. \
+                The rootfs may not be properly bootstrapped.",
+                in_rootfs, host_path
+            ))
             .into());
         }
-    };
-
-    if metadata.file_type().is_symlink() {
-        return Err(RsdebstrapError::Validation(
-            "/tmp in rootfs is a symlink, which is not allowed for security reasons. \
-            An attacker could use this to write files outside the chroot."
-                .to_string(),
-        )
-        .into());
-    }
-
-    if !metadata.file_type().is_dir() {
-        return Err(RsdebstrapError::Validation(format!(
-            "/tmp in rootfs is not a directory: {}. \
-            The rootfs may not be properly bootstrapped.",
-            tmp_dir
-        ))
-        .into());
     }
 
     Ok(())
 }
 
+/// Returns where a task file named `name` is staged for `context`: its path on the
+/// host (for [`prepare_source_file`]) and its path inside the isolation.
+pub(crate) fn staged_file_paths(
+    context: &dyn IsolationContext,
+    name: &str,
+) -> (Utf8PathBuf, String) {
+    let in_isolation = context.staging_dir().join(name);
+    let host = context
+        .rootfs()
+        .join(in_isolation.strip_prefix("/").unwrap_or(&in_isolation));
+    (host, in_isolation.into_string())
+}
+
 /// Executes a command within an isolation context, preserving `RsdebstrapError` variants.
 ///
 /// If the context returns an `anyhow::Error` that wraps a `RsdebstrapError`, the typed
@@ -312,18 +629,33 @@ pub(crate) fn execute_in_context(
     task_label: &str,
     privilege: Option<PrivilegeMethod>,
 ) -> Result<ExecutionResult> {
-    context
-        .execute(command, privilege)
-        .map_err(|e| match e.downcast::<RsdebstrapError>() {
-            Ok(typed) => typed.into(),
-            Err(e) => e.context(format!("failed to execute {}", task_label)),
-        })
+    execute_in_context_with_stdin(context, command, task_label, privilege, None)
+}
+
+/// Like [`execute_in_context`], but writes `stdin`, if any, to the command's
+/// standard input.
+pub(crate) fn execute_in_context_with_stdin(
+    context: &dyn IsolationContext,
+    command: &[String],
+    task_label: &str,
+    privilege: Option<PrivilegeMethod>,
+    stdin: Option<&[u8]>,
+) -> Result<ExecutionResult> {
+    match stdin {
+        Some(stdin) => context.execute_with_stdin(command, privilege, stdin),
+        None => context.execute(command, privilege),
+    }
+    .map_err(|e| match e.downcast::<RsdebstrapError>() {
+        Ok(typed) => typed.into(),
+        Err(e) => e.context(format!("failed to execute {}", task_label)),
+    })
 }
 
 /// Checks the execution result and returns an error if the command failed.
 ///
 /// Handles three cases:
-/// - Non-zero exit status: returns `Execution` error with the status code
+/// - Non-zero exit status: returns `Execution` error with the status code and the
+///   executor's `ExecutionDetails`
 /// - No exit status in non-dry-run mode: returns `Execution` error (e.g., killed by signal)
 /// - Success or dry-run with no status: returns `Ok(())`
 pub(crate) fn check_execution_result(
@@ -336,6 +668,7 @@ pub(crate) fn check_execution_result(
         Some(status) if !status.success() => {
             Err(
                 RsdebstrapError::execution_in_isolation(command, context_name, status.to_string())
+                    .with_details(result.details.clone())
                     .into(),
             )
         }
@@ -349,17 +682,26 @@ pub(crate) fn check_execution_result(
     }
 }
 
-/// Re-validates `/tmp` (TOCTOU mitigation) and runs the file preparation closure.
+/// Quotes `value` as a single-quoted POSIX shell word, for scripts generated by tasks.
+pub(crate) fn sh_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Re-validates the staging directory (TOCTOU mitigation) and runs the file
+/// preparation closure.
 ///
 /// In dry-run mode, skips both validation and file preparation entirely.
 pub(crate) fn prepare_files_with_toctou_check(
-    rootfs: &Utf8Path,
-    dry_run: bool,
+    context: &dyn IsolationContext,
     prepare_fn: impl FnOnce() -> Result<()>,
 ) -> Result<()> {
-    if !dry_run {
-        validate_tmp_directory(rootfs)
-            .context("TOCTOU check: /tmp validation failed before writing files")?;
+    if !context.dry_run() {
+        validate_staging_directory(context.rootfs(), context.staging_dir()).with_context(|| {
+            format!(
+                "TOCTOU check: {} validation failed before writing files",
+                context.staging_dir()
+            )
+        })?;
         prepare_fn()?;
     }
     Ok(())
@@ -381,6 +723,7 @@ mod tests {
         fn success_returns_ok() {
             let result = ExecutionResult {
                 status: Some(ExitStatus::from_raw(0)),
+                details: None,
             };
             let command: Vec<String> = vec!["/bin/sh".to_string(), "/tmp/test.sh".to_string()];
             assert!(check_execution_result(&result, &command, "chroot", false).is_ok());
@@ -390,6 +733,7 @@ mod tests {
         fn nonzero_exit_returns_execution_error() {
             let result = ExecutionResult {
                 status: Some(ExitStatus::from_raw(1 << 8)),
+                details: None,
             };
             let command: Vec<String> = vec!["/bin/sh".to_string(), "/tmp/test.sh".to_string()];
             let err = check_execution_result(&result, &command, "chroot", false).unwrap_err();
@@ -403,7 +747,10 @@ mod tests {
 
         #[test]
         fn no_status_in_non_dry_run_returns_error() {
-            let result = ExecutionResult { status: None };
+            let result = ExecutionResult {
+                status: None,
+                details: None,
+            };
             let command: Vec<String> = vec!["/bin/sh".to_string(), "/tmp/test.sh".to_string()];
             let err = check_execution_result(&result, &command, "chroot", false).unwrap_err();
             let typed = err.downcast_ref::<RsdebstrapError>().unwrap();
@@ -417,7 +764,10 @@ mod tests {
 
         #[test]
         fn no_status_in_dry_run_returns_ok() {
-            let result = ExecutionResult { status: None };
+            let result = ExecutionResult {
+                status: None,
+                details: None,
+            };
             let command: Vec<String> = vec!["/bin/sh".to_string(), "/tmp/test.sh".to_string()];
             assert!(check_execution_result(&result, &command, "chroot", true).is_ok());
         }
@@ -0,0 +1,294 @@
+//! download task implementation for the prepare phase.
+//!
+//! This module provides the `DownloadTask`, which fetches files from URLs (tool
+//! binaries, tar overlays, CA certificates) and places them at fixed paths in the
+//! rootfs, where the provision tasks after it find them. Each file is verified
+//! against its pinned SHA-256 and kept in a host cache directory named by digest
+//! ([`download::fetch_cached`]), so rebuilds do not download it again.
+//!
+//! The runner fetches the files before the bootstrap, so a bad URL or digest fails
+//! fast; the task itself then only copies them from the cache.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+use anyhow::Result;
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::config::IsolationConfig;
+use crate::download;
+use crate::error::RsdebstrapError;
+use crate::executor::{CommandExecutor, CommandSpec};
+use crate::isolation::IsolationContext;
+use crate::phase::assemble::{dir_exists, parent_dir};
+use crate::phase::{PhaseItem, validate_no_parent_dirs};
+use crate::privilege::{Privilege, PrivilegeDefaults, PrivilegeMethod};
+
+/// Suffix for the staging entry used to atomically replace a downloaded file.
+const STAGING_SUFFIX: &str = ".rsdebstrap-tmp";
+
+/// A file fetched into the rootfs.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct DownloadFile {
+    /// `http://` or `https://` URL to fetch.
+    #[serde(deserialize_with = "crate::de::string")]
+    pub url: String,
+    /// SHA-256 digest the download must match (64 hexadecimal digits).
+    #[serde(deserialize_with = "crate::de::string")]
+    pub sha256: String,
+    /// Absolute path of the file inside the rootfs.
+    #[serde(deserialize_with = "crate::de::path")]
+    #[cfg_attr(feature = "schema", schemars(with = "String"))]
+    pub path: Utf8PathBuf,
+    /// Make the file executable (mode 0755 instead of 0644).
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub executable: bool,
+}
+
+/// Prepare phase download task fetching files into the rootfs.
+///
+/// At most one `DownloadTask` may appear in the prepare phase; it lists every file.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct DownloadTask {
+    /// Privilege escalation setting (resolved during defaults application).
+    #[serde(default, skip_serializing_if = "Privilege::is_inherit")]
+    pub privilege: Privilege,
+    /// Host directory caching the downloads (relative paths are resolved against the
+    /// profile's directory; default: `$XDG_CACHE_HOME/rsdebstrap/downloads`).
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_path",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<crate::schema::Utf8PathSchema>")
+    )]
+    pub cache_dir: Option<Utf8PathBuf>,
+    /// Files to fetch, in order.
+    pub files: Vec<DownloadFile>,
+}
+
+impl DownloadTask {
+    /// Resolves the privilege setting against profile defaults.
+    pub fn resolve_privilege(
+        &mut self,
+        defaults: Option<&PrivilegeDefaults>,
+    ) -> Result<(), RsdebstrapError> {
+        self.privilege.resolve_in_place(defaults)
+    }
+
+    /// Returns the resolved privilege method.
+    ///
+    /// Should only be called after `resolve_privilege()`.
+    pub fn resolved_privilege_method(&self) -> Option<PrivilegeMethod> {
+        self.privilege.resolved_method()
+    }
+
+    /// Resolves a relative `cache_dir` against the profile's directory.
+    pub(crate) fn resolve_paths(&mut self, profile_dir: &Utf8Path) {
+        if let Some(dir) = self.cache_dir.as_mut()
+            && dir.is_relative()
+        {
+            *dir = profile_dir.join(&*dir);
+        }
+    }
+
+    /// Returns the host directory the downloads are cached in.
+    pub fn cache_dir(&self) -> Result<Utf8PathBuf, RsdebstrapError> {
+        match &self.cache_dir {
+            Some(dir) => Ok(dir.clone()),
+            None => download::default_cache_dir(),
+        }
+    }
+
+    /// Validates the prepare download task configuration.
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        if self.files.is_empty() {
+            return Err(RsdebstrapError::Validation(
+                "prepare download: files must not be empty".to_string(),
+            ));
+        }
+        if self
+            .cache_dir
+            .as_ref()
+            .is_some_and(|d| d.as_str().is_empty())
+        {
+            return Err(RsdebstrapError::Validation(
+                "prepare download: cache_dir must not be empty".to_string(),
+            ));
+        }
+        let mut paths = HashSet::new();
+        for file in &self.files {
+            download::validate_url(&file.url, "prepare download")?;
+            download::validate_sha256(&file.sha256, "prepare download")?;
+            if !file.path.is_absolute()
+                || !file
+                    .path
+                    .components()
+                    .any(|c| matches!(c, Utf8Component::Normal(_)))
+                || file.path.as_str().contains(['\0', '\n', '\r'])
+            {
+                return Err(RsdebstrapError::Validation(format!(
+                    "prepare download: path {:?} must be an absolute file path \
+                    without null or newline characters",
+                    file.path
+                )));
+            }
+            validate_no_parent_dirs(&file.path, "prepare download")?;
+            if !paths.insert(&file.path) {
+                return Err(RsdebstrapError::Validation(format!(
+                    "prepare download: path '{}' is listed more than once",
+                    file.path
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fetches every file into the cache (see [`download::fetch_cached`]) and returns
+    /// the cached copies in order.
+    pub fn fetch(&self, executor: &dyn CommandExecutor, dry_run: bool) -> Result<Vec<Utf8PathBuf>> {
+        let cache_dir = self.cache_dir()?;
+        self.files
+            .iter()
+            .map(|file| {
+                download::fetch_cached(&file.url, &file.sha256, &cache_dir, executor, dry_run)
+            })
+            .collect()
+    }
+
+    /// Executes the prepare download task.
+    ///
+    /// Fetches the files (normally from the cache) and copies each into the rootfs
+    /// with privilege escalation when configured, staged at a sibling path and renamed
+    /// into place. Missing parent directories are created once the existing ones have
+    /// been opened with `O_NOFOLLOW`, so a symlink planted in the rootfs cannot
+    /// redirect the write to the host.
+    pub fn execute(&self, ctx: &dyn IsolationContext) -> Result<()> {
+        let executor = ctx.executor();
+        let cached = self.fetch(executor, ctx.dry_run())?;
+        let rootfs = ctx.rootfs();
+        let privilege = self.resolved_privilege_method();
+        let run = |command: &str, args: Vec<String>| {
+            executor.execute_checked(&CommandSpec::new(command, args).with_privilege(privilege))
+        };
+        for (file, source) in self.files.iter().zip(cached) {
+            let relative = file.path.strip_prefix("/").unwrap_or(&file.path);
+            let target = rootfs.join(relative);
+            if ctx.dry_run() {
+                info!("would install {} to {}", file.url, target);
+                continue;
+            }
+
+            let parent = parent_dir(relative);
+            if !dir_exists(rootfs, parent)? {
+                run("mkdir", vec!["-p".to_string(), rootfs.join(parent).to_string()])?;
+            }
+            if target.symlink_metadata().is_ok_and(|m| m.is_dir()) {
+                return Err(RsdebstrapError::Isolation(format!(
+                    "{} is a directory, refusing to replace it",
+                    target
+                ))
+                .into());
+            }
+            let staging = format!("{}{}", target, STAGING_SUFFIX);
+            let mode = if file.executable { "755" } else { "644" };
+            // Remove a stale staging entry first so `cp` cannot write through a symlink.
+            run("rm", vec!["-f".to_string(), staging.clone()])?;
+            run("cp", vec![source.to_string(), staging.clone()])?;
+            run("chmod", vec![mode.to_string(), staging.clone()])?;
+            run("mv", vec![staging, target.to_string()])?;
+            info!("installed {} to {}", file.url, file.path);
+        }
+        Ok(())
+    }
+}
+
+impl PhaseItem for DownloadTask {
+    fn name(&self) -> Cow<'_, str> {
+        let paths = self
+            .files
+            .iter()
+            .map(|f| f.path.as_str())
+            .collect::<Vec<_>>();
+        Cow::Owned(format!("download:{}", paths.join(",")))
+    }
+
+    fn validate(&self) -> Result<(), RsdebstrapError> {
+        DownloadTask::validate(self)
+    }
+
+    fn execute(&self, ctx: &dyn IsolationContext) -> Result<()> {
+        // The download task operates directly on the rootfs filesystem.
+        DownloadTask::execute(self, ctx)
+    }
+
+    fn resolved_isolation_config(&self) -> Option<&IsolationConfig> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA: &str = "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03";
+
+    fn task(yaml: &str) -> DownloadTask {
+        yaml_serde::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn validate_rejects_bad_files() {
+        for (yaml, expected) in [
+            ("files: []", "must not be empty"),
+            (
+                "files: [{url: 'ftp://x/a', sha256: 5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03, path: /a}]",
+                "http:// or https://",
+            ),
+            ("files: [{url: 'https://x/a', sha256: abc, path: /a}]", "64 hexadecimal"),
+            (&format!("files: [{{url: 'https://x/a', sha256: {SHA}, path: a}}]"), "absolute"),
+            (&format!("files: [{{url: 'https://x/a', sha256: {SHA}, path: /}}]"), "absolute"),
+            (
+                &format!("files: [{{url: 'https://x/a', sha256: {SHA}, path: /usr/../etc/a}}]"),
+                "..",
+            ),
+            (
+                &format!(
+                    "files: [{{url: 'https://x/a', sha256: {SHA}, path: /a}}, \
+                    {{url: 'https://x/b', sha256: {SHA}, path: /a}}]"
+                ),
+                "more than once",
+            ),
+        ] {
+            let err = task(yaml).validate().unwrap_err();
+            assert!(err.to_string().contains(expected), "{yaml}: {err}");
+        }
+        task(&format!(
+            "files: [{{url: 'https://x/tool', sha256: {SHA}, path: /usr/local/bin/tool, \
+            executable: true}}]"
+        ))
+        .validate()
+        .unwrap();
+    }
+
+    #[test]
+    fn name_lists_the_paths_and_cache_dir_is_resolved() {
+        let mut task = task(&format!(
+            "cache_dir: cache\nfiles:\n- {{url: 'https://x/a', sha256: {SHA}, path: /a}}\n\
+            - {{url: 'https://x/b', sha256: {SHA}, path: /etc/b}}\n"
+        ));
+        assert_eq!(PhaseItem::name(&task), "download:/a,/etc/b");
+        task.resolve_paths(Utf8Path::new("/profiles"));
+        assert_eq!(task.cache_dir().unwrap(), "/profiles/cache");
+    }
+}
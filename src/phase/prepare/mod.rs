@@ -5,18 +5,21 @@
 //! optional singleton field:
 //! - [`mount`](PrepareConfig::mount) — declares filesystem mounts for the rootfs
 //! - [`resolv_conf`](PrepareConfig::resolv_conf) — declares resolv.conf setup for DNS resolution
+//! - [`download`](PrepareConfig::download) — fetches files into the rootfs
 //!
 //! The named-field shape makes "at most one mount", "at most one resolv_conf",
-//! and the fixed `mount → resolv_conf` execution order structural rather than
-//! validated after the fact.
+//! and the fixed `mount → resolv_conf → download` execution order structural
+//! rather than validated after the fact.
 
+pub mod download;
 pub mod mount;
 pub mod resolv_conf;
 
 #[cfg(feature = "schema")]
 use schemars::JsonSchema;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
+pub use download::{DownloadFile, DownloadTask};
 pub use mount::MountTask;
 pub use resolv_conf::ResolvConfTask;
 
@@ -24,25 +27,29 @@ use crate::phase::PhaseItem;
 
 /// Prepare phase configuration (named-field, schema-first).
 ///
-/// Both fields are optional singletons. A duplicate YAML key (e.g. two `mount`
+/// Every field is an optional singleton. A duplicate YAML key (e.g. two `mount`
 /// entries) is rejected by `yaml_serde` at parse time, and an unknown key is
 /// rejected by `deny_unknown_fields` — so the "at most one" invariants hold
 /// structurally instead of being validated after parsing.
-#[derive(Debug, Deserialize, Default, Clone, PartialEq, Eq)]
+#[derive(Debug, Deserialize, Serialize, Default, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
 #[serde(deny_unknown_fields)]
 pub struct PrepareConfig {
     /// Mount task declaring filesystem mounts for the rootfs.
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub mount: Option<MountTask>,
     /// resolv_conf task declaring DNS configuration for the chroot.
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub resolv_conf: Option<ResolvConfTask>,
+    /// download task fetching files (verified against their SHA-256) into the rootfs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub download: Option<DownloadTask>,
 }
 
 impl PrepareConfig {
-    /// Returns the present phase items in fixed execution order: `mount` then
-    /// `resolv_conf`. The order is structural, independent of YAML key order.
+    /// Returns the present phase items in fixed execution order: `mount`,
+    /// `resolv_conf`, then `download`. The order is structural, independent of YAML
+    /// key order.
     pub(crate) fn items(&self) -> Vec<&dyn PhaseItem> {
         let mut items: Vec<&dyn PhaseItem> = Vec::new();
         if let Some(mount) = &self.mount {
@@ -51,17 +58,22 @@ impl PrepareConfig {
         if let Some(resolv_conf) = &self.resolv_conf {
             items.push(resolv_conf);
         }
+        if let Some(download) = &self.download {
+            items.push(download);
+        }
         items
     }
 
     /// Returns true if no prepare tasks are configured.
     pub fn is_empty(&self) -> bool {
-        self.mount.is_none() && self.resolv_conf.is_none()
+        self.mount.is_none() && self.resolv_conf.is_none() && self.download.is_none()
     }
 
     /// Returns the number of configured prepare tasks.
     pub fn len(&self) -> usize {
-        usize::from(self.mount.is_some()) + usize::from(self.resolv_conf.is_some())
+        usize::from(self.mount.is_some())
+            + usize::from(self.resolv_conf.is_some())
+            + usize::from(self.download.is_some())
     }
 }
 
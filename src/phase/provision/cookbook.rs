@@ -0,0 +1,559 @@
+//! Cookbook task implementation.
+//!
+//! This module provides the `CookbookTask` data structure and execution logic
+//! for running existing Ruby configuration code — Itamae recipes or Chef cookbooks
+//! via Cinc — within an isolation context. It handles:
+//! - Copying the cookbook directory into the staging directory (rootfs /tmp by default)
+//! - Optional Bundler handling (`bundle install`, then `bundle exec`)
+//! - Security validation (path traversal, symlinks and special files in the tree)
+//! - RAII cleanup of the staged directory
+
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::config::{IsolationConfig, MountEntry};
+use crate::error::RsdebstrapError;
+use crate::isolation::{IsolationContext, TaskIsolation};
+use crate::phase::TempDirGuard;
+use crate::privilege::{Privilege, PrivilegeDefaults, PrivilegeMethod};
+
+/// Tool that applies the cookbook inside the rootfs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum CookbookRunner {
+    /// `itamae local <recipe>...`; `run_list` names recipe files within `dir`
+    Itamae,
+    /// `cinc-solo` with `dir/cookbooks` as the cookbook path; `run_list` holds
+    /// run-list items such as `recipe[base]`
+    Cinc,
+}
+
+impl CookbookRunner {
+    /// Returns the command run inside the rootfs.
+    pub fn command(self) -> &'static str {
+        match self {
+            Self::Itamae => "itamae",
+            Self::Cinc => "cinc-solo",
+        }
+    }
+}
+
+/// Bundler handling for a cookbook directory with a `Gemfile`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct BundlerConfig {
+    /// Run `bundle install` before the runner (default: true)
+    #[serde(
+        default = "crate::phase::default_true",
+        skip_serializing_if = "crate::phase::is_true"
+    )]
+    pub install: bool,
+    /// Gem groups skipped by `bundle install` (`BUNDLE_WITHOUT`)
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<String>>"))]
+    pub without: Vec<String>,
+}
+
+impl Default for BundlerConfig {
+    fn default() -> Self {
+        Self {
+            install: true,
+            without: Vec::new(),
+        }
+    }
+}
+
+/// Cookbook task data and execution logic.
+///
+/// Copies a host cookbook directory into the rootfs staging directory and runs
+/// `itamae local` or `cinc-solo` against it, optionally through Bundler. The
+/// runner (and `bundle`) must already be installed in the rootfs, e.g. by an
+/// earlier shell task.
+///
+/// ## Lifecycle
+///
+/// The typical lifecycle when loaded from a YAML profile is:
+/// 1. **Deserialize** — construct from YAML via `serde`
+///    (or [`new()`](Self::new) for programmatic use)
+/// 2. [`resolve_paths()`](Self::resolve_paths) — resolve a relative `dir`
+/// 3. [`validate()`](Self::validate) — check the directory tree and `run_list`
+/// 4. [`execute()`](Self::execute) — run within an isolation context
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct CookbookTask {
+    /// Tool that applies the cookbook
+    runner: CookbookRunner,
+
+    /// Host directory copied into the rootfs (relative paths resolve against the profile)
+    #[cfg_attr(feature = "schema", schemars(with = "crate::schema::Utf8PathSchema"))]
+    dir: Utf8PathBuf,
+
+    /// Recipe files within `dir` (itamae) or run-list items (cinc), applied in order
+    run_list: Vec<String>,
+
+    /// Bundler handling; requires a `Gemfile` in `dir`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bundler: Option<BundlerConfig>,
+
+    /// Privilege escalation setting (resolved during defaults application)
+    #[serde(default, skip_serializing_if = "Privilege::is_inherit")]
+    privilege: Privilege,
+
+    /// Isolation setting (resolved during defaults application)
+    #[serde(default, skip_serializing_if = "TaskIsolation::is_inherit")]
+    isolation: TaskIsolation,
+
+    /// Network access (`None` inherits from isolation; resolved during defaults application)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    network: Option<bool>,
+
+    /// User to run the task as inside the chroot (merged into the isolation's `user`)
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    user: Option<String>,
+
+    /// Group to run the task as, together with `user`
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    group: Option<String>,
+
+    /// Filesystems mounted into the rootfs for this task only
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<MountEntry>>"))]
+    mounts: Vec<MountEntry>,
+
+    /// Labels matched by `apply --tags`/`--skip-tags`
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<String>>"))]
+    tags: Vec<String>,
+
+    /// Log a failure of the task and keep going instead of failing the phase
+    #[serde(default, skip_serializing_if = "crate::phase::is_false")]
+    ignore_errors: bool,
+
+    /// User-given name shown in logs and errors, and matched by `apply --start-at-task`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+impl CookbookTask {
+    /// Creates a new CookbookTask applying `run_list` from `dir` with `runner`.
+    pub fn new<I, S>(runner: CookbookRunner, dir: impl Into<Utf8PathBuf>, run_list: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            runner,
+            dir: dir.into(),
+            run_list: run_list.into_iter().map(Into::into).collect(),
+            bundler: None,
+            privilege: Privilege::default(),
+            isolation: TaskIsolation::default(),
+            network: None,
+            user: None,
+            group: None,
+            mounts: Vec::new(),
+            tags: Vec::new(),
+            ignore_errors: false,
+            name: None,
+        }
+    }
+
+    /// Sets the Bundler handling.
+    pub fn with_bundler(mut self, bundler: BundlerConfig) -> Self {
+        self.bundler = Some(bundler);
+        self
+    }
+
+    /// Sets the user-given task name.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets whether a failure of the task is logged instead of failing the phase.
+    pub fn with_ignore_errors(mut self, ignore_errors: bool) -> Self {
+        self.ignore_errors = ignore_errors;
+        self
+    }
+
+    /// Sets the labels matched by `apply --tags`/`--skip-tags`.
+    pub fn with_tags<I, S>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the privilege escalation setting.
+    pub fn with_privilege(mut self, privilege: Privilege) -> Self {
+        self.privilege = privilege;
+        self
+    }
+
+    /// Sets the isolation setting.
+    pub fn with_isolation(mut self, isolation: TaskIsolation) -> Self {
+        self.isolation = isolation;
+        self
+    }
+
+    /// Sets network access (by default it is inherited from the isolation config).
+    pub fn with_network(mut self, network: bool) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Sets the user (and optionally group) to run the task as inside the chroot.
+    pub fn with_user(mut self, user: impl Into<String>, group: Option<String>) -> Self {
+        self.user = Some(user.into());
+        self.group = group;
+        self
+    }
+
+    /// Sets the filesystems mounted into the rootfs for this task only.
+    pub fn with_mounts(mut self, mounts: Vec<MountEntry>) -> Self {
+        self.mounts = mounts;
+        self
+    }
+
+    /// Returns the tool that applies the cookbook.
+    pub fn runner(&self) -> CookbookRunner {
+        self.runner
+    }
+
+    /// Returns the host cookbook directory.
+    pub fn dir(&self) -> &Utf8Path {
+        &self.dir
+    }
+
+    /// Returns the recipe files (itamae) or run-list items (cinc).
+    pub fn run_list(&self) -> &[String] {
+        &self.run_list
+    }
+
+    /// Returns the Bundler handling, if any.
+    pub fn bundler(&self) -> Option<&BundlerConfig> {
+        self.bundler.as_ref()
+    }
+
+    /// Returns a human-readable name for this task (without type prefix).
+    pub fn name(&self) -> &str {
+        self.dir.as_str()
+    }
+
+    /// Resolves a relative `dir` relative to the given base directory.
+    pub fn resolve_paths(&mut self, base_dir: &Utf8Path) {
+        if self.dir.is_relative() {
+            self.dir = base_dir.join(&self.dir);
+        }
+    }
+
+    /// Resolves the privilege setting against profile defaults.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `privilege: true` is specified
+    /// but no `defaults.privilege.method` is configured in the profile.
+    pub fn resolve_privilege(
+        &mut self,
+        defaults: Option<&PrivilegeDefaults>,
+    ) -> Result<(), RsdebstrapError> {
+        self.privilege.resolve_in_place(defaults)
+    }
+
+    /// Returns the resolved privilege method.
+    ///
+    /// Should only be called after [`resolve_privilege()`](Self::resolve_privilege).
+    pub fn resolved_privilege_method(&self) -> Option<PrivilegeMethod> {
+        self.privilege.resolved_method()
+    }
+
+    /// Returns a reference to the task's isolation setting.
+    pub fn task_isolation(&self) -> &TaskIsolation {
+        &self.isolation
+    }
+
+    /// Resolves the isolation setting against profile defaults.
+    pub fn resolve_isolation(&mut self, defaults: &IsolationConfig) {
+        self.isolation.resolve_in_place(defaults);
+    }
+
+    /// Returns the resolved isolation config.
+    ///
+    /// Should only be called after [`resolve_isolation()`](Self::resolve_isolation).
+    pub fn resolved_isolation_config(&self) -> Option<&IsolationConfig> {
+        self.isolation.resolved_config()
+    }
+
+    /// Resolves the network setting against the isolation config and `offline`.
+    ///
+    /// Should be called after [`resolve_isolation()`](Self::resolve_isolation).
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `offline` is set and the task or its
+    /// isolation config explicitly enables the network.
+    pub fn resolve_network(&mut self, offline: bool) -> Result<(), RsdebstrapError> {
+        self.network =
+            crate::phase::resolve_network(self.network, self.isolation.resolved_config(), offline)?;
+        Ok(())
+    }
+
+    /// Merges `user`/`group` into the resolved chroot isolation's `user`.
+    ///
+    /// Should be called after [`resolve_isolation()`](Self::resolve_isolation).
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `group` is set without `user`, the task
+    /// runs without isolation, or the isolation config names a different user.
+    pub fn resolve_user(&mut self) -> Result<(), RsdebstrapError> {
+        crate::phase::resolve_task_user(
+            &mut self.isolation,
+            self.user.as_deref(),
+            self.group.as_deref(),
+        )
+    }
+
+    /// Returns whether the task may use the network (default: true).
+    pub fn network_enabled(&self) -> bool {
+        self.network.unwrap_or(true)
+    }
+
+    /// Returns the filesystems mounted into the rootfs for this task only.
+    pub fn mounts(&self) -> &[MountEntry] {
+        &self.mounts
+    }
+
+    /// Returns the task's tags.
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Returns whether a failure of the task is logged instead of failing the phase.
+    pub fn ignore_errors(&self) -> bool {
+        self.ignore_errors
+    }
+
+    /// Returns the user-given `name`, if any.
+    pub fn configured_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Validates the task configuration.
+    ///
+    /// Checks:
+    /// - `dir` has no `..` components and is a directory (not a symlink) holding only
+    ///   directories and regular files
+    /// - `run_list` is non-empty; itamae entries are relative recipe files within `dir`,
+    ///   cinc entries are free of whitespace and commas and `dir/cookbooks` exists
+    /// - `bundler`: `dir/Gemfile` exists and `without` groups are non-empty words
+    /// - `name`, `tags` and `mounts` as for other provision tasks
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        crate::phase::validate_task_name(self.name.as_deref())?;
+        crate::phase::validate_task_tags(&self.tags)?;
+        crate::phase::validate_task_mounts(&self.mounts)?;
+
+        crate::phase::validate_no_parent_dirs(&self.dir, "cookbook")?;
+        crate::phase::walk_host_tree(&self.dir, "cookbook")?;
+
+        if self.run_list.is_empty() {
+            return Err(RsdebstrapError::Validation(format!(
+                "cookbook {} requires a non-empty 'run_list'",
+                self.dir
+            )));
+        }
+        for entry in &self.run_list {
+            match self.runner {
+                CookbookRunner::Itamae => {
+                    let recipe = Utf8Path::new(entry);
+                    if entry.is_empty() || recipe.is_absolute() {
+                        return Err(RsdebstrapError::Validation(format!(
+                            "itamae run_list entry '{}' must be a recipe path relative to {}",
+                            entry, self.dir
+                        )));
+                    }
+                    crate::phase::validate_no_parent_dirs(recipe, "itamae recipe")?;
+                    crate::phase::validate_host_file_exists(
+                        &self.dir.join(recipe),
+                        "itamae recipe",
+                    )?;
+                }
+                CookbookRunner::Cinc => {
+                    if entry.is_empty() || entry.contains(|c: char| c.is_whitespace() || c == ',') {
+                        return Err(RsdebstrapError::Validation(format!(
+                            "invalid cinc run_list entry {:?}: entries must be non-empty \
+                            without whitespace or commas",
+                            entry
+                        )));
+                    }
+                }
+            }
+        }
+        if self.runner == CookbookRunner::Cinc && !self.dir.join("cookbooks").is_dir() {
+            return Err(RsdebstrapError::Validation(format!(
+                "cinc cookbook directory {} has no 'cookbooks' directory",
+                self.dir
+            )));
+        }
+
+        if let Some(bundler) = &self.bundler {
+            crate::phase::validate_host_file_exists(&self.dir.join("Gemfile"), "Gemfile")?;
+            for group in &bundler.without {
+                if group.is_empty()
+                    || !group
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                {
+                    return Err(RsdebstrapError::Validation(format!(
+                        "invalid bundler group {:?}: groups must be non-empty words",
+                        group
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the runner command for a cookbook staged at `staged` (as seen inside
+    /// the isolation), wrapped in `bundle exec` when Bundler is configured.
+    pub fn command(&self, staged: &str) -> Vec<String> {
+        let mut command = self.bundle_command(staged);
+        if !command.is_empty() {
+            command.push("exec".to_string());
+        }
+        command.push(self.runner.command().to_string());
+        match self.runner {
+            CookbookRunner::Itamae => {
+                command.push("local".to_string());
+                command.extend(self.run_list.iter().map(|r| format!("{}/{}", staged, r)));
+            }
+            CookbookRunner::Cinc => {
+                command.push("--config-option".to_string());
+                command.push(format!("cookbook_path={}/cookbooks", staged));
+                command.push("--override-runlist".to_string());
+                command.push(self.run_list.join(","));
+            }
+        }
+        command
+    }
+
+    /// Builds the `bundle install` command, or `None` without Bundler or with
+    /// `install: false`.
+    pub fn install_command(&self, staged: &str) -> Option<Vec<String>> {
+        if !self.bundler.as_ref().is_some_and(|b| b.install) {
+            return None;
+        }
+        let mut command = self.bundle_command(staged);
+        command.push("install".to_string());
+        Some(command)
+    }
+
+    /// Returns `env BUNDLE_GEMFILE=... [BUNDLE_WITHOUT=...] bundle`, or an empty
+    /// command without Bundler.
+    fn bundle_command(&self, staged: &str) -> Vec<String> {
+        let Some(bundler) = &self.bundler else {
+            return Vec::new();
+        };
+        let mut command = vec![
+            "env".to_string(),
+            format!("BUNDLE_GEMFILE={}/Gemfile", staged),
+        ];
+        if !bundler.without.is_empty() {
+            command.push(format!("BUNDLE_WITHOUT={}", bundler.without.join(":")));
+        }
+        command.push("bundle".to_string());
+        command
+    }
+
+    /// Executes the cookbook using the provided isolation context.
+    ///
+    /// This method:
+    /// 1. Validates the staging directory (`/tmp` by default) in rootfs (unless dry_run)
+    /// 2. Sets up an RAII guard removing the staged directory
+    /// 3. Re-validates the staging directory to mitigate TOCTOU race conditions,
+    ///    then copies `dir` into a fresh 0o700 directory there (unless dry_run)
+    /// 4. Hands the copied tree over to the isolation's task user, if any
+    /// 5. Runs `bundle install` if Bundler is configured with `install`
+    /// 6. Runs the runner (through `bundle exec` with Bundler)
+    /// 7. Returns an error if a process fails or exits without status
+    pub fn execute(&self, context: &dyn IsolationContext) -> Result<()> {
+        let rootfs = context.rootfs();
+        let dry_run = context.dry_run();
+
+        if !dry_run {
+            crate::phase::validate_staging_directory(rootfs, context.staging_dir())
+                .context("rootfs validation failed")?;
+        }
+
+        info!(
+            "running {} cookbook: {} (isolation: {})",
+            self.runner.command(),
+            self.name(),
+            context.name()
+        );
+        debug!("rootfs: {}, dry_run: {}", rootfs, dry_run);
+
+        let dir_name = context.staging_name("cookbook");
+        let (target_dir, dir_in_isolation) = crate::phase::staged_file_paths(context, &dir_name);
+        let _guard = TempDirGuard::new(target_dir.clone(), dry_run);
+
+        let mut staged = vec![dir_in_isolation.clone()];
+        crate::phase::prepare_files_with_toctou_check(context, || {
+            staged = crate::phase::copy_host_tree(
+                &self.dir,
+                &target_dir,
+                &dir_in_isolation,
+                "cookbook",
+            )?;
+            Ok(())
+        })?;
+
+        context.hand_over(&staged, self.privilege.resolved_method())?;
+
+        let commands = self
+            .install_command(&dir_in_isolation)
+            .into_iter()
+            .chain([self.command(&dir_in_isolation)]);
+        for command in commands {
+            let result = crate::phase::execute_in_context(
+                context,
+                &command,
+                "cookbook",
+                self.privilege.resolved_method(),
+            )?;
+            crate::phase::check_execution_result(&result, &command, context.name(), dry_run)?;
+        }
+
+        info!("cookbook completed successfully");
+        Ok(())
+    }
+}
@@ -0,0 +1,487 @@
+//! Debconf task implementation.
+//!
+//! This module provides the `DebconfTask` data structure and execution logic
+//! for preseeding the rootfs debconf database with `debconf-set-selections`.
+//! It handles:
+//! - Selections given as a map of questions or as a host preseed file
+//! - Validation of the selection lines before anything runs
+//! - Piping the selections into `debconf-set-selections` inside the isolation
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::config::IsolationConfig;
+use crate::error::RsdebstrapError;
+use crate::isolation::{IsolationContext, TaskIsolation};
+use crate::privilege::{Privilege, PrivilegeDefaults, PrivilegeMethod};
+
+/// Command that reads the selections from its standard input.
+const DEBCONF_SET_SELECTIONS: &str = "debconf-set-selections";
+
+/// Type of a debconf question, as written in the third field of a selection line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum DebconfType {
+    Boolean,
+    Error,
+    Multiselect,
+    Note,
+    Password,
+    Select,
+    String,
+    Text,
+    Title,
+}
+
+impl DebconfType {
+    /// Returns the name debconf uses for this type.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Boolean => "boolean",
+            Self::Error => "error",
+            Self::Multiselect => "multiselect",
+            Self::Note => "note",
+            Self::Password => "password",
+            Self::Select => "select",
+            Self::String => "string",
+            Self::Text => "text",
+            Self::Title => "title",
+        }
+    }
+}
+
+impl fmt::Display for DebconfType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Answer to one debconf question.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct DebconfSelection {
+    /// Package owning the question (default: the question name up to its first `/`)
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub owner: Option<String>,
+
+    /// Question type (boolean, select, string, ...)
+    #[serde(rename = "type")]
+    pub kind: DebconfType,
+
+    /// Answer; booleans and numbers are written as given (`true`, `8080`)
+    #[serde(default, deserialize_with = "crate::de::scalar_text")]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(extend("type" = ["string", "boolean", "number"]))
+    )]
+    pub value: String,
+}
+
+impl DebconfSelection {
+    /// Creates a selection answering a question of type `kind` with `value`.
+    pub fn new(kind: DebconfType, value: impl Into<String>) -> Self {
+        Self {
+            owner: None,
+            kind,
+            value: value.into(),
+        }
+    }
+}
+
+/// Debconf task data and execution logic.
+///
+/// Feeds debconf selections to `debconf-set-selections` inside the rootfs, so later
+/// tasks install packages such as tzdata or keyboard-configuration without prompting.
+/// The selections come from a host preseed `file`, the `selections` map, or both (the
+/// map is applied after the file).
+///
+/// ## Lifecycle
+///
+/// The typical lifecycle when loaded from a YAML profile is:
+/// 1. **Deserialize** — construct from YAML via `serde`
+///    (or [`new()`](Self::new) for programmatic use)
+/// 2. [`resolve_paths()`](Self::resolve_paths) — resolve a relative preseed `file`
+/// 3. [`validate()`](Self::validate) — check the selections and the preseed file
+/// 4. [`execute()`](Self::execute) — run within an isolation context
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct DebconfTask {
+    /// Host preseed file in `debconf-set-selections` format (relative paths resolve
+    /// against the profile)
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_path",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<crate::schema::Utf8PathSchema>")
+    )]
+    file: Option<Utf8PathBuf>,
+
+    /// Answers keyed by question name (e.g. `tzdata/Areas`)
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<BTreeMap<String, DebconfSelection>>")
+    )]
+    selections: BTreeMap<String, DebconfSelection>,
+
+    /// Privilege escalation setting (resolved during defaults application)
+    #[serde(default, skip_serializing_if = "Privilege::is_inherit")]
+    privilege: Privilege,
+
+    /// Isolation setting (resolved during defaults application); must not be disabled
+    #[serde(default, skip_serializing_if = "TaskIsolation::is_inherit")]
+    isolation: TaskIsolation,
+
+    /// Network access (`None` inherits from isolation; resolved during defaults application)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    network: Option<bool>,
+
+    /// Labels matched by `apply --tags`/`--skip-tags`
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<String>>"))]
+    tags: Vec<String>,
+
+    /// Log a failure of the task and keep going instead of failing the phase
+    #[serde(default, skip_serializing_if = "crate::phase::is_false")]
+    ignore_errors: bool,
+
+    /// User-given name shown in logs and errors, and matched by `apply --start-at-task`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+impl DebconfTask {
+    /// Creates a new DebconfTask with no selections.
+    ///
+    /// Note: add a preseed file or selections and call [`validate()`](Self::validate)
+    /// before executing it.
+    pub fn new() -> Self {
+        Self {
+            file: None,
+            selections: BTreeMap::new(),
+            privilege: Privilege::default(),
+            isolation: TaskIsolation::default(),
+            network: None,
+            tags: Vec::new(),
+            ignore_errors: false,
+            name: None,
+        }
+    }
+
+    /// Sets the host preseed file.
+    pub fn with_file(mut self, file: impl Into<Utf8PathBuf>) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+
+    /// Adds the answer to `question`.
+    pub fn with_selection(
+        mut self,
+        question: impl Into<String>,
+        selection: DebconfSelection,
+    ) -> Self {
+        self.selections.insert(question.into(), selection);
+        self
+    }
+
+    /// Sets the user-given task name.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets whether a failure of the task is logged instead of failing the phase.
+    pub fn with_ignore_errors(mut self, ignore_errors: bool) -> Self {
+        self.ignore_errors = ignore_errors;
+        self
+    }
+
+    /// Sets the labels matched by `apply --tags`/`--skip-tags`.
+    pub fn with_tags<I, S>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the privilege escalation setting.
+    pub fn with_privilege(mut self, privilege: Privilege) -> Self {
+        self.privilege = privilege;
+        self
+    }
+
+    /// Sets the isolation setting.
+    pub fn with_isolation(mut self, isolation: TaskIsolation) -> Self {
+        self.isolation = isolation;
+        self
+    }
+
+    /// Sets network access (by default it is inherited from the isolation config).
+    pub fn with_network(mut self, network: bool) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Returns the host preseed file, if any.
+    pub fn file(&self) -> Option<&Utf8Path> {
+        self.file.as_deref()
+    }
+
+    /// Returns the answers keyed by question name.
+    pub fn selections(&self) -> &BTreeMap<String, DebconfSelection> {
+        &self.selections
+    }
+
+    /// Returns a human-readable name for this task (without type prefix): the preseed
+    /// file, or the number of selections.
+    pub fn name(&self) -> String {
+        match &self.file {
+            Some(file) => file.to_string(),
+            None => format!("<{} selections>", self.selections.len()),
+        }
+    }
+
+    /// Resolves a relative preseed `file` relative to the given base directory.
+    pub fn resolve_paths(&mut self, base_dir: &Utf8Path) {
+        if let Some(file) = &mut self.file
+            && file.is_relative()
+        {
+            *file = base_dir.join(&*file);
+        }
+    }
+
+    /// Resolves the privilege setting against profile defaults.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `privilege: true` is specified
+    /// but no `defaults.privilege.method` is configured in the profile.
+    pub fn resolve_privilege(
+        &mut self,
+        defaults: Option<&PrivilegeDefaults>,
+    ) -> Result<(), RsdebstrapError> {
+        self.privilege.resolve_in_place(defaults)
+    }
+
+    /// Returns the resolved privilege method.
+    ///
+    /// Should only be called after [`resolve_privilege()`](Self::resolve_privilege).
+    pub fn resolved_privilege_method(&self) -> Option<PrivilegeMethod> {
+        self.privilege.resolved_method()
+    }
+
+    /// Returns a reference to the task's isolation setting.
+    pub fn task_isolation(&self) -> &TaskIsolation {
+        &self.isolation
+    }
+
+    /// Resolves the isolation setting against profile defaults.
+    pub fn resolve_isolation(&mut self, defaults: &IsolationConfig) {
+        self.isolation.resolve_in_place(defaults);
+    }
+
+    /// Returns the resolved isolation config.
+    ///
+    /// Should only be called after [`resolve_isolation()`](Self::resolve_isolation).
+    pub fn resolved_isolation_config(&self) -> Option<&IsolationConfig> {
+        self.isolation.resolved_config()
+    }
+
+    /// Resolves the network setting against the isolation config and `offline`.
+    ///
+    /// Should be called after [`resolve_isolation()`](Self::resolve_isolation).
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `offline` is set and the task or its
+    /// isolation config explicitly enables the network.
+    pub fn resolve_network(&mut self, offline: bool) -> Result<(), RsdebstrapError> {
+        self.network =
+            crate::phase::resolve_network(self.network, self.isolation.resolved_config(), offline)?;
+        Ok(())
+    }
+
+    /// Returns whether the task may use the network (default: true).
+    pub fn network_enabled(&self) -> bool {
+        self.network.unwrap_or(true)
+    }
+
+    /// Returns the task's tags.
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Returns whether a failure of the task is logged instead of failing the phase.
+    pub fn ignore_errors(&self) -> bool {
+        self.ignore_errors
+    }
+
+    /// Returns the user-given `name`, if any.
+    pub fn configured_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Validates the task configuration.
+    ///
+    /// Checks:
+    /// - at least one of `file` and `selections` is given, and isolation is not disabled
+    ///   (`debconf-set-selections` would otherwise change the host's database)
+    /// - `file` has no `..` components and is a regular file (not a symlink)
+    /// - each question name and `owner` is non-empty without whitespace, the owner can
+    ///   be derived from the question when omitted, and no value spans several lines
+    /// - `name` and `tags` as for other provision tasks
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        crate::phase::validate_task_name(self.name.as_deref())?;
+        crate::phase::validate_task_tags(&self.tags)?;
+
+        if self.file.is_none() && self.selections.is_empty() {
+            return Err(RsdebstrapError::Validation(
+                "debconf task requires 'file' or non-empty 'selections'".to_string(),
+            ));
+        }
+        if self.isolation == TaskIsolation::Disabled {
+            return Err(RsdebstrapError::Validation(
+                "debconf task requires isolation (isolation: false would preseed the host)"
+                    .to_string(),
+            ));
+        }
+        if let Some(file) = &self.file {
+            crate::phase::validate_no_parent_dirs(file, "debconf preseed file")?;
+            crate::phase::validate_host_file_exists(file, "debconf preseed file")?;
+        }
+        for (question, selection) in &self.selections {
+            Self::selection_line(question, selection)?;
+        }
+        Ok(())
+    }
+
+    /// Formats one `owner question type value` line for `debconf-set-selections`.
+    fn selection_line(
+        question: &str,
+        selection: &DebconfSelection,
+    ) -> Result<String, RsdebstrapError> {
+        let is_word = |s: &str| !s.is_empty() && !s.contains(char::is_whitespace);
+        if !is_word(question) {
+            return Err(RsdebstrapError::Validation(format!(
+                "debconf question {:?} must be non-empty without whitespace",
+                question
+            )));
+        }
+        let owner = match (&selection.owner, question.split_once('/')) {
+            (Some(owner), _) => owner.as_str(),
+            (None, Some((owner, _))) => owner,
+            (None, None) => {
+                return Err(RsdebstrapError::Validation(format!(
+                    "debconf question '{}' needs an 'owner' (no package prefix before '/')",
+                    question
+                )));
+            }
+        };
+        if !is_word(owner) {
+            return Err(RsdebstrapError::Validation(format!(
+                "debconf owner {:?} of '{}' must be non-empty without whitespace",
+                owner, question
+            )));
+        }
+        if selection.value.contains(['\n', '\r']) {
+            return Err(RsdebstrapError::Validation(format!(
+                "debconf value of '{}' must be a single line",
+                question
+            )));
+        }
+        Ok(format!("{} {} {} {}", owner, question, selection.kind, selection.value))
+    }
+
+    /// Returns the text piped into `debconf-set-selections`: the preseed file, then one
+    /// line per entry of `selections`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Io` if the preseed file cannot be read, or
+    /// `RsdebstrapError::Validation` for an invalid selection.
+    pub fn input(&self) -> Result<String, RsdebstrapError> {
+        let mut input = match &self.file {
+            Some(file) => std::fs::read_to_string(file).map_err(|e| {
+                RsdebstrapError::io(format!("failed to read debconf preseed file: {}", file), e)
+            })?,
+            None => String::new(),
+        };
+        if !input.is_empty() && !input.ends_with('\n') {
+            input.push('\n');
+        }
+        for (question, selection) in &self.selections {
+            input.push_str(&Self::selection_line(question, selection)?);
+            input.push('\n');
+        }
+        Ok(input)
+    }
+
+    /// Executes `debconf-set-selections` using the provided isolation context.
+    ///
+    /// This method:
+    /// 1. Reads the preseed file and formats the selections
+    /// 2. Runs `debconf-set-selections` via the isolation context, with the selections
+    ///    on its standard input
+    /// 3. Returns an error if the process fails or exits without status
+    ///
+    /// Nothing is written to the rootfs by rsdebstrap itself, so dry-run only skips
+    /// the command, as the executor does for every task.
+    pub fn execute(&self, context: &dyn IsolationContext) -> Result<()> {
+        let dry_run = context.dry_run();
+
+        info!("preseeding debconf: {} (isolation: {})", self.name(), context.name());
+        let input = self.input()?;
+        debug!(
+            "rootfs: {}, selections: {} lines, dry_run: {}",
+            context.rootfs(),
+            input.lines().count(),
+            dry_run
+        );
+
+        let command = vec![DEBCONF_SET_SELECTIONS.to_string()];
+        let result = crate::phase::execute_in_context_with_stdin(
+            context,
+            &command,
+            "debconf-set-selections",
+            self.privilege.resolved_method(),
+            Some(input.as_bytes()),
+        )?;
+        crate::phase::check_execution_result(&result, &command, context.name(), dry_run)?;
+
+        info!("debconf selections set successfully");
+        Ok(())
+    }
+}
+
+impl Default for DebconfTask {
+    fn default() -> Self {
+        Self::new()
+    }
+}
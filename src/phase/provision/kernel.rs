@@ -0,0 +1,482 @@
+//! Kernel task implementation.
+//!
+//! This module provides the `KernelTask` data structure and execution logic for
+//! declaring the kernel modules loaded at boot (`/etc/modules-load.d`) and the
+//! sysctl settings applied at boot (`/etc/sysctl.d`) in the rootfs. It handles:
+//! - Validation of module names and sysctl key syntax before anything runs
+//! - Writing both files through a generated POSIX shell script piped into
+//!   `/bin/sh` inside the isolation
+//!
+//! Nothing is applied to the running kernel: the files take effect when the image
+//! boots.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use camino::Utf8Path;
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::config::IsolationConfig;
+use crate::error::RsdebstrapError;
+use crate::isolation::{IsolationContext, TaskIsolation};
+use crate::phase::sh_quote;
+use crate::privilege::{Privilege, PrivilegeDefaults, PrivilegeMethod};
+
+/// Shell reading the generated script from its standard input.
+const SHELL: &str = "/bin/sh";
+
+/// Directory inside the rootfs listing the modules loaded at boot.
+const MODULES_LOAD_DIR: &str = "/etc/modules-load.d";
+
+/// Directory inside the rootfs holding the sysctl settings applied at boot.
+const SYSCTL_DIR: &str = "/etc/sysctl.d";
+
+/// File name (without `.conf`) used when `file_name` is not given.
+const DEFAULT_FILE_NAME: &str = "90-rsdebstrap";
+
+/// First line of every generated file.
+const HEADER: &str = "# Generated by rsdebstrap";
+
+fn default_file_name() -> String {
+    DEFAULT_FILE_NAME.to_string()
+}
+
+fn is_default_file_name(name: &String) -> bool {
+    name == DEFAULT_FILE_NAME
+}
+
+/// Returns `true` if `key` is a sysctl key as `sysctl.d(5)` accepts it: an optional
+/// leading `-` (ignore a missing key), then non-empty segments of letters, digits, `_`,
+/// `-`, `@`, `:` and `*` separated by `.` or `/`.
+pub fn is_sysctl_key(key: &str) -> bool {
+    let key = key.strip_prefix('-').unwrap_or(key);
+    !key.is_empty()
+        && key.split(['.', '/']).all(|segment| {
+            !segment.is_empty()
+                && segment
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '@' | ':' | '*'))
+        })
+}
+
+/// Kernel task data and execution logic.
+///
+/// Writes `modules` to `/etc/modules-load.d/<file_name>.conf` and `sysctl` to
+/// `/etc/sysctl.d/<file_name>.conf` (one `key = value` line each, sorted by key), both
+/// mode 0644. Each file is replaced as a whole and only written when its list is
+/// non-empty. `file_name` orders the sysctl file against the others in `/etc/sysctl.d`:
+/// later files override earlier ones.
+///
+/// ## Lifecycle
+///
+/// The typical lifecycle when loaded from a YAML profile is:
+/// 1. **Deserialize** — construct from YAML via `serde`
+///    (or [`new()`](Self::new) for programmatic use)
+/// 2. [`validate()`](Self::validate) — check module names and sysctl keys
+/// 3. [`execute()`](Self::execute) — run within an isolation context
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct KernelTask {
+    /// Kernel modules loaded at boot
+    #[serde(
+        default,
+        deserialize_with = "crate::de::string_list",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<String>>"))]
+    modules: Vec<String>,
+
+    /// sysctl settings applied at boot; booleans and numbers are written as given
+    #[serde(
+        default,
+        deserialize_with = "crate::de::scalar_text_map",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(
+            with = "Option<BTreeMap<String, String>>",
+            extend("additionalProperties" = { "type": ["string", "boolean", "number"] })
+        )
+    )]
+    sysctl: BTreeMap<String, String>,
+
+    /// Name of the generated files, without `.conf` (default: `90-rsdebstrap`)
+    #[serde(
+        default = "default_file_name",
+        deserialize_with = "crate::de::string",
+        skip_serializing_if = "is_default_file_name"
+    )]
+    file_name: String,
+
+    /// Privilege escalation setting (resolved during defaults application)
+    #[serde(default, skip_serializing_if = "Privilege::is_inherit")]
+    privilege: Privilege,
+
+    /// Isolation setting (resolved during defaults application); must not be disabled
+    #[serde(default, skip_serializing_if = "TaskIsolation::is_inherit")]
+    isolation: TaskIsolation,
+
+    /// Network access (`None` inherits from isolation; resolved during defaults application)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    network: Option<bool>,
+
+    /// Labels matched by `apply --tags`/`--skip-tags`
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<String>>"))]
+    tags: Vec<String>,
+
+    /// Log a failure of the task and keep going instead of failing the phase
+    #[serde(default, skip_serializing_if = "crate::phase::is_false")]
+    ignore_errors: bool,
+
+    /// User-given name shown in logs and errors, and matched by `apply --start-at-task`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+impl KernelTask {
+    /// Creates a new KernelTask with no modules or settings.
+    ///
+    /// Note: add modules or sysctl settings and call [`validate()`](Self::validate)
+    /// before executing it.
+    pub fn new() -> Self {
+        Self {
+            modules: Vec::new(),
+            sysctl: BTreeMap::new(),
+            file_name: default_file_name(),
+            privilege: Privilege::default(),
+            isolation: TaskIsolation::default(),
+            network: None,
+            tags: Vec::new(),
+            ignore_errors: false,
+            name: None,
+        }
+    }
+
+    /// Sets the kernel modules loaded at boot.
+    pub fn with_modules<I, S>(mut self, modules: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.modules = modules.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Adds the sysctl setting `key = value`.
+    pub fn with_sysctl(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.sysctl.insert(key.into(), value.into());
+        self
+    }
+
+    /// Sets the name of the generated files, without `.conf`.
+    pub fn with_file_name(mut self, file_name: impl Into<String>) -> Self {
+        self.file_name = file_name.into();
+        self
+    }
+
+    /// Sets the user-given task name.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets whether a failure of the task is logged instead of failing the phase.
+    pub fn with_ignore_errors(mut self, ignore_errors: bool) -> Self {
+        self.ignore_errors = ignore_errors;
+        self
+    }
+
+    /// Sets the labels matched by `apply --tags`/`--skip-tags`.
+    pub fn with_tags<I, S>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the privilege escalation setting.
+    pub fn with_privilege(mut self, privilege: Privilege) -> Self {
+        self.privilege = privilege;
+        self
+    }
+
+    /// Sets the isolation setting.
+    pub fn with_isolation(mut self, isolation: TaskIsolation) -> Self {
+        self.isolation = isolation;
+        self
+    }
+
+    /// Sets network access (by default it is inherited from the isolation config).
+    pub fn with_network(mut self, network: bool) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Returns the kernel modules loaded at boot.
+    pub fn modules(&self) -> &[String] {
+        &self.modules
+    }
+
+    /// Returns the sysctl settings keyed by sysctl key.
+    pub fn sysctl(&self) -> &BTreeMap<String, String> {
+        &self.sysctl
+    }
+
+    /// Returns the name of the generated files, without `.conf`.
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    /// Returns a human-readable name for this task (without type prefix): the generated
+    /// file name.
+    pub fn name(&self) -> String {
+        format!("{}.conf", self.file_name)
+    }
+
+    /// Resolves relative paths; a kernel task has none, so this does nothing.
+    pub fn resolve_paths(&mut self, _base_dir: &Utf8Path) {}
+
+    /// Resolves the privilege setting against profile defaults.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `privilege: true` is specified
+    /// but no `defaults.privilege.method` is configured in the profile.
+    pub fn resolve_privilege(
+        &mut self,
+        defaults: Option<&PrivilegeDefaults>,
+    ) -> Result<(), RsdebstrapError> {
+        self.privilege.resolve_in_place(defaults)
+    }
+
+    /// Returns the resolved privilege method.
+    ///
+    /// Should only be called after [`resolve_privilege()`](Self::resolve_privilege).
+    pub fn resolved_privilege_method(&self) -> Option<PrivilegeMethod> {
+        self.privilege.resolved_method()
+    }
+
+    /// Returns a reference to the task's isolation setting.
+    pub fn task_isolation(&self) -> &TaskIsolation {
+        &self.isolation
+    }
+
+    /// Resolves the isolation setting against profile defaults.
+    pub fn resolve_isolation(&mut self, defaults: &IsolationConfig) {
+        self.isolation.resolve_in_place(defaults);
+    }
+
+    /// Returns the resolved isolation config.
+    ///
+    /// Should only be called after [`resolve_isolation()`](Self::resolve_isolation).
+    pub fn resolved_isolation_config(&self) -> Option<&IsolationConfig> {
+        self.isolation.resolved_config()
+    }
+
+    /// Resolves the network setting against the isolation config and `offline`.
+    ///
+    /// Should be called after [`resolve_isolation()`](Self::resolve_isolation).
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `offline` is set and the task or its
+    /// isolation config explicitly enables the network.
+    pub fn resolve_network(&mut self, offline: bool) -> Result<(), RsdebstrapError> {
+        self.network =
+            crate::phase::resolve_network(self.network, self.isolation.resolved_config(), offline)?;
+        Ok(())
+    }
+
+    /// Returns whether the task may use the network (default: true).
+    pub fn network_enabled(&self) -> bool {
+        self.network.unwrap_or(true)
+    }
+
+    /// Returns the task's tags.
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Returns whether a failure of the task is logged instead of failing the phase.
+    pub fn ignore_errors(&self) -> bool {
+        self.ignore_errors
+    }
+
+    /// Returns the user-given `name`, if any.
+    pub fn configured_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Validates the task configuration.
+    ///
+    /// Checks:
+    /// - at least one of `modules` and `sysctl` is given, and isolation is not disabled
+    ///   (the host's `/etc` would be changed)
+    /// - `file_name` consists of letters, digits, `.`, `_` and `-` and does not start
+    ///   with `.` or `-`
+    /// - module names consist of letters, digits, `_` and `-`, without duplicates
+    /// - sysctl keys are well-formed (see [`is_sysctl_key`]) and values are non-empty
+    ///   single lines
+    /// - `name` and `tags` as for other provision tasks
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        crate::phase::validate_task_name(self.name.as_deref())?;
+        crate::phase::validate_task_tags(&self.tags)?;
+
+        if self.modules.is_empty() && self.sysctl.is_empty() {
+            return Err(RsdebstrapError::Validation(
+                "kernel task requires non-empty 'modules' or 'sysctl'".to_string(),
+            ));
+        }
+        if self.isolation == TaskIsolation::Disabled {
+            return Err(RsdebstrapError::Validation(
+                "kernel task requires isolation (isolation: false would configure the host)"
+                    .to_string(),
+            ));
+        }
+        let valid_file_name = !self.file_name.is_empty()
+            && !self.file_name.starts_with(['.', '-'])
+            && self
+                .file_name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        if !valid_file_name {
+            return Err(RsdebstrapError::Validation(format!(
+                "kernel task file_name {:?} must consist of letters, digits, '.', '_' and '-' \
+                and not start with '.' or '-'",
+                self.file_name
+            )));
+        }
+        for (index, module) in self.modules.iter().enumerate() {
+            if module.is_empty()
+                || !module
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'))
+            {
+                return Err(RsdebstrapError::Validation(format!(
+                    "kernel module name {:?} must consist of letters, digits, '_' and '-'",
+                    module
+                )));
+            }
+            if self.modules[..index].contains(module) {
+                return Err(RsdebstrapError::Validation(format!(
+                    "kernel modules list '{}' more than once",
+                    module
+                )));
+            }
+        }
+        for (key, value) in &self.sysctl {
+            if !is_sysctl_key(key) {
+                return Err(RsdebstrapError::Validation(format!(
+                    "sysctl key {:?} must be '.'- or '/'-separated segments of letters, \
+                    digits, '_', '-', '@', ':' and '*' (e.g. net.ipv4.ip_forward)",
+                    key
+                )));
+            }
+            if value.trim().is_empty() || value.contains(['\n', '\r', '\0']) {
+                return Err(RsdebstrapError::Validation(format!(
+                    "sysctl value of '{}' must be a non-empty single line",
+                    key
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the content of the modules-load.d file, or `None` without modules.
+    pub fn modules_load_conf(&self) -> Option<String> {
+        if self.modules.is_empty() {
+            return None;
+        }
+        let mut content = format!("{}\n", HEADER);
+        for module in &self.modules {
+            content.push_str(module);
+            content.push('\n');
+        }
+        Some(content)
+    }
+
+    /// Returns the content of the sysctl.d file, or `None` without settings.
+    pub fn sysctl_conf(&self) -> Option<String> {
+        if self.sysctl.is_empty() {
+            return None;
+        }
+        let mut content = format!("{}\n", HEADER);
+        for (key, value) in &self.sysctl {
+            content.push_str(&format!("{} = {}\n", key, value));
+        }
+        Some(content)
+    }
+
+    /// Returns the shell script piped into `/bin/sh` inside the rootfs.
+    pub fn script(&self) -> String {
+        let mut script = String::from("set -eu\numask 022\n");
+        let files = [
+            (MODULES_LOAD_DIR, self.modules_load_conf()),
+            (SYSCTL_DIR, self.sysctl_conf()),
+        ];
+        for (dir, content) in files {
+            let Some(content) = content else {
+                continue;
+            };
+            let path = sh_quote(&format!("{}/{}.conf", dir, self.file_name));
+            script.push_str(&format!("mkdir -p {}\n", dir));
+            script.push_str(&format!("printf '%s' {} > {}\n", sh_quote(&content), path));
+            script.push_str(&format!("chmod 644 {}\n", path));
+        }
+        script
+    }
+
+    /// Executes the generated script using the provided isolation context.
+    ///
+    /// This method:
+    /// 1. Generates the script writing the modules-load.d and sysctl.d files
+    /// 2. Runs `/bin/sh` via the isolation context, with the script on its standard input
+    /// 3. Returns an error if the process fails or exits without status
+    ///
+    /// Nothing is written to the rootfs by rsdebstrap itself, so dry-run only skips
+    /// the command, as the executor does for every task.
+    pub fn execute(&self, context: &dyn IsolationContext) -> Result<()> {
+        let dry_run = context.dry_run();
+
+        info!("configuring kernel: {} (isolation: {})", self.name(), context.name());
+        let script = self.script();
+        debug!(
+            "rootfs: {}, modules: {}, sysctl settings: {}, dry_run: {}",
+            context.rootfs(),
+            self.modules.len(),
+            self.sysctl.len(),
+            dry_run
+        );
+
+        let command = vec![SHELL.to_string(), "-s".to_string()];
+        let result = crate::phase::execute_in_context_with_stdin(
+            context,
+            &command,
+            "kernel configuration",
+            self.privilege.resolved_method(),
+            Some(script.as_bytes()),
+        )?;
+        crate::phase::check_execution_result(&result, &command, context.name(), dry_run)?;
+
+        info!("kernel configured successfully");
+        Ok(())
+    }
+}
+
+impl Default for KernelTask {
+    fn default() -> Self {
+        Self::new()
+    }
+}
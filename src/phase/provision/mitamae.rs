@@ -2,8 +2,11 @@
 //!
 //! This module provides the `MitamaeTask` data structure and execution logic
 //! for running mitamae recipes within an isolation context. It handles:
-//! - Recipe source management (external files or inline content)
-//! - Binary copying to rootfs /tmp with 0o700 permissions
+//! - Recipe source management (external files, inline content, or a list of recipes)
+//! - Node attributes written to a JSON file passed via `--node-json`
+//! - Binary download from a URL with SHA-256 verification
+//! - Download of the official release for the target architecture (`defaults.mitamae.version`)
+//! - Binary copying to the staging directory (rootfs /tmp by default) with 0o700 permissions
 //! - Security validation (path traversal, file existence)
 //! - RAII cleanup of both binary and recipe temp files
 
@@ -11,23 +14,134 @@ use anyhow::{Context, Result};
 use camino::{Utf8Path, Utf8PathBuf};
 #[cfg(feature = "schema")]
 use schemars::{JsonSchema, Schema, SchemaGenerator};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 #[cfg(feature = "schema")]
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::fs;
 use tracing::{debug, info};
 
-use crate::config::IsolationConfig;
+use crate::config::{IsolationConfig, MountEntry};
 use crate::error::RsdebstrapError;
+use crate::executor::{CommandExecutor, CommandSpec};
 use crate::isolation::{IsolationContext, TaskIsolation};
 use crate::phase::{ScriptSource, TempFileGuard};
-use crate::privilege::{Privilege, PrivilegeDefaults};
+use crate::privilege::{Privilege, PrivilegeDefaults, PrivilegeMethod};
+
+/// Base URL of the official mitamae releases.
+const RELEASE_BASE_URL: &str = "https://github.com/itamae-kitchen/mitamae/releases/download";
+
+/// Returns the name mitamae's Linux releases use for `arch`, a Rust architecture name
+/// (`std::env::consts::ARCH`), or `None` if there is no release for it.
+pub fn release_arch(arch: &str) -> Option<&'static str> {
+    match arch {
+        "x86_64" => Some("x86_64"),
+        "aarch64" => Some("aarch64"),
+        "arm" => Some("armhf"),
+        "x86" => Some("i386"),
+        _ => None,
+    }
+}
+
+/// Official mitamae release for one architecture, pinned by the SHA-256 digest of
+/// its tarball (`defaults.mitamae.version` and `sha256`).
+///
+/// [`fetch()`](Self::fetch) downloads `mitamae-<arch>-linux.tar.gz` into the download
+/// cache and extracts the binary next to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MitamaeRelease {
+    version: String,
+    arch: &'static str,
+    sha256: String,
+    url: String,
+}
+
+impl MitamaeRelease {
+    /// Creates the release `version` for `arch` (a name from [`release_arch`]) whose
+    /// tarball has the digest `sha256`.
+    pub fn new(version: impl Into<String>, arch: &'static str, sha256: impl Into<String>) -> Self {
+        let version = version.into();
+        let url = format!("{}/v{}/mitamae-{}-linux.tar.gz", RELEASE_BASE_URL, version, arch);
+        Self {
+            version,
+            arch,
+            sha256: sha256.into(),
+            url,
+        }
+    }
+
+    /// Returns the URL of the release tarball.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Returns the name of the binary inside the tarball.
+    fn binary_name(&self) -> String {
+        format!("mitamae-{}-linux", self.arch)
+    }
+
+    /// Downloads the tarball into `cache_dir` unless a verified copy is there (see
+    /// [`crate::download::fetch_cached`]), extracts the binary and returns its path.
+    ///
+    /// The binary is extracted into a private directory and renamed into place as
+    /// `<sha256>-mitamae-<arch>-linux`, so builds sharing the cache never run a
+    /// partial file. In dry-run mode nothing is downloaded or extracted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the download fails or does not match, or the tarball
+    /// does not hold the binary.
+    pub fn fetch(
+        &self,
+        cache_dir: &Utf8Path,
+        executor: &dyn CommandExecutor,
+        dry_run: bool,
+    ) -> Result<Utf8PathBuf> {
+        let tarball =
+            crate::download::fetch_cached(&self.url, &self.sha256, cache_dir, executor, dry_run)
+                .with_context(|| {
+                    format!("failed to download mitamae {} for {}", self.version, self.arch)
+                })?;
+        let name = self.binary_name();
+        let binary = cache_dir.join(format!("{}-{}", self.sha256.to_ascii_lowercase(), name));
+        if dry_run {
+            info!("would extract {} from {} to {}", name, tarball, binary);
+            return Ok(binary);
+        }
+        let dir = tempfile::Builder::new()
+            .prefix(".mitamae-")
+            .tempdir_in(cache_dir)
+            .map_err(|e| {
+                RsdebstrapError::io(format!("failed to create a directory in {}", cache_dir), e)
+            })?;
+        let dir_path = Utf8Path::from_path(dir.path())
+            .ok_or_else(|| RsdebstrapError::Validation(format!("{} is not UTF-8", cache_dir)))?;
+        executor
+            .execute_checked(&CommandSpec::new(
+                "tar",
+                vec![
+                    "-xzf".to_string(),
+                    tarball.to_string(),
+                    "-C".to_string(),
+                    dir_path.to_string(),
+                    name.clone(),
+                ],
+            ))
+            .with_context(|| format!("failed to extract {} from {}", name, tarball))?;
+        fs::rename(dir_path.join(&name), &binary)
+            .map_err(|e| RsdebstrapError::io(format!("failed to store {}", binary), e))?;
+        info!("using mitamae {} for {} from {}", self.version, self.arch, binary);
+        Ok(binary)
+    }
+}
 
 /// Mitamae task data and execution logic.
 ///
 /// Represents a mitamae recipe to be executed within an isolation context.
 /// The mitamae binary is copied from the host into the rootfs /tmp directory
-/// before execution, and cleaned up afterwards via RAII guards.
+/// before execution, and cleaned up afterwards via RAII guards. Instead of a
+/// host path, the binary can be given as a `url` pinned with `sha256`; it is then
+/// downloaded by [`download_binary()`](Self::download_binary) before the build.
 ///
 /// ## Lifecycle
 ///
@@ -36,53 +150,148 @@ use crate::privilege::{Privilege, PrivilegeDefaults};
 ///    (or [`new()`](Self::new) for programmatic use)
 /// 2. [`resolve_paths()`](Self::resolve_paths) — resolve relative paths
 /// 3. [`validate()`](Self::validate) — check binary and recipe existence
-/// 4. [`execute()`](Self::execute) — run within an isolation context
+/// 4. [`download_binary()`](Self::download_binary) — fetch a `url` binary (apply only)
+/// 5. [`execute()`](Self::execute) — run within an isolation context
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MitamaeTask {
     /// Recipe source: either an external file path or inline content
     source: ScriptSource,
-    /// Host-side mitamae binary path (None when relying on defaults)
+    /// Further recipe files applied after `source`, in order (from `recipes:`)
+    recipes: Vec<Utf8PathBuf>,
+    /// Node attributes passed to mitamae as JSON via `--node-json`
+    attributes: BTreeMap<String, serde_json::Value>,
+    /// Host-side mitamae binary path (None when relying on defaults or `url`)
     binary: Option<Utf8PathBuf>,
+    /// URL to download the mitamae binary from (mutually exclusive with `binary`)
+    url: Option<String>,
+    /// Expected SHA-256 digest of the mitamae binary
+    sha256: Option<String>,
+    /// Release downloaded for the target architecture when neither `binary` nor `url`
+    /// is set (from `defaults.mitamae.version`)
+    release: Option<MitamaeRelease>,
     /// Privilege escalation setting (resolved during defaults application)
     privilege: Privilege,
     /// Isolation setting (resolved during defaults application)
     isolation: TaskIsolation,
+
+    /// Network access (`None` inherits from isolation; resolved during defaults application)
+    network: Option<bool>,
+
+    /// User to run the task as inside the chroot (merged into the isolation's `user`)
+    user: Option<String>,
+
+    /// Group to run the task as, together with `user`
+    group: Option<String>,
+
+    /// Filesystems mounted into the rootfs for this task only
+    mounts: Vec<MountEntry>,
+
+    /// Labels matched by `apply --tags`/`--skip-tags`
+    tags: Vec<String>,
+
+    /// Log a failure of the task and keep going instead of failing the phase
+    ignore_errors: bool,
+
+    /// User-given name shown in logs and errors, and matched by `apply --start-at-task`
+    name: Option<String>,
 }
 
 // Wire shape of a mitamae task.
 //
-// Single source of truth for the YAML shape, shared by both deserialization (via
-// `MitamaeTask`'s `Deserialize`) and schema generation (via `MitamaeTask`'s `JsonSchema`).
-// `deny_unknown_fields` keeps typo'd keys rejected. The `script`/`content` mutual-exclusion is
-// enforced at runtime by `resolve_script_source`, and mirrored in the schema by the `oneOf`
-// below (exactly one of `script`/`content` must be set). Each branch also constrains the field
-// to a string, not just presence: serde treats an explicit `null` on an `Option` field as
+// Single source of truth for the YAML shape, shared by deserialization (via `MitamaeTask`'s
+// `Deserialize`), serialization (via its `Serialize`) and schema generation (via its
+// `JsonSchema`).
+// `deny_unknown_fields` keeps typo'd keys rejected. The `script`/`content`/`recipes`
+// mutual-exclusion is enforced at deserialization (`recipes` first, then
+// `resolve_script_source`), and mirrored in the schema by the `oneOf` below (exactly one of
+// them must be set). Each branch also constrains the field to its type, not just presence: serde treats an explicit `null` on an `Option` field as
 // absent (`None`), so a bare `required` would diverge from deserialization for e.g.
-// `{ script: null, content: hi }`. Plain `//` (not `///`) so the note does not leak into the
+// `{ script: null, content: hi }`. `binary`/`url` exclusion is likewise enforced at
+// deserialization, but not mirrored in the schema since both are optional. Plain `//` (not `///`) so the note does not leak into the
 // schema's `description`.
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
 #[serde(deny_unknown_fields)]
 #[cfg_attr(feature = "schema", schemars(extend("oneOf" = serde_json::json!([
     { "required": ["script"], "properties": { "script": { "type": "string" } } },
     { "required": ["content"], "properties": { "content": { "type": "string" } } },
+    { "required": ["recipes"], "properties": { "recipes": { "type": "array", "minItems": 1 } } },
 ]))))]
 struct RawMitamaeTask {
     #[cfg_attr(
         feature = "schema",
         schemars(with = "Option<crate::schema::Utf8PathSchema>")
     )]
+    #[serde(skip_serializing_if = "Option::is_none")]
     script: Option<Utf8PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     content: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<Vec<crate::schema::Utf8PathSchema>>")
+    )]
+    recipes: Vec<Utf8PathBuf>,
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<BTreeMap<String, serde_json::Value>>")
+    )]
+    attributes: BTreeMap<String, serde_json::Value>,
     #[cfg_attr(
         feature = "schema",
         schemars(with = "Option<crate::schema::Utf8PathSchema>")
     )]
+    #[serde(skip_serializing_if = "Option::is_none")]
     binary: Option<Utf8PathBuf>,
-    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "Privilege::is_inherit")]
     privilege: Privilege,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "TaskIsolation::is_inherit")]
     isolation: TaskIsolation,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    network: Option<bool>,
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    user: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    group: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<MountEntry>>"))]
+    mounts: Vec<MountEntry>,
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<String>>"))]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "crate::phase::is_false")]
+    ignore_errors: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
 }
 
 impl<'de> Deserialize<'de> for MitamaeTask {
@@ -91,16 +300,85 @@ impl<'de> Deserialize<'de> for MitamaeTask {
         D: serde::Deserializer<'de>,
     {
         let raw = RawMitamaeTask::deserialize(deserializer)?;
-        let source = crate::phase::resolve_script_source::<D::Error>(raw.script, raw.content)?;
+        let (source, recipes) = if raw.recipes.is_empty() {
+            if raw.script.is_none() && raw.content.is_none() {
+                return Err(serde::de::Error::custom(
+                    "either 'script', 'content' or 'recipes' must be specified",
+                ));
+            }
+            let source = crate::phase::resolve_script_source::<D::Error>(raw.script, raw.content)?;
+            (source, Vec::new())
+        } else if raw.script.is_some() || raw.content.is_some() {
+            return Err(serde::de::Error::custom(
+                "'recipes' is mutually exclusive with 'script' and 'content'",
+            ));
+        } else {
+            let mut recipes = raw.recipes.into_iter();
+            let first = recipes.next().expect("recipes is non-empty");
+            (ScriptSource::Script(first), recipes.collect())
+        };
+        if raw.binary.is_some() && raw.url.is_some() {
+            return Err(serde::de::Error::custom("'binary' and 'url' are mutually exclusive"));
+        }
         Ok(MitamaeTask {
             source,
+            recipes,
+            attributes: raw.attributes,
             binary: raw.binary,
+            url: raw.url,
+            sha256: raw.sha256,
+            release: None,
             privilege: raw.privilege,
             isolation: raw.isolation,
+            network: raw.network,
+            user: raw.user,
+            group: raw.group,
+            mounts: raw.mounts,
+            tags: raw.tags,
+            ignore_errors: raw.ignore_errors,
+            name: raw.name,
         })
     }
 }
 
+impl Serialize for MitamaeTask {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let (script, content) = crate::phase::split_script_source(&self.source);
+        // A task with further recipes is written back as a single `recipes:` list.
+        let (script, recipes) = match script {
+            Some(first) if !self.recipes.is_empty() => (
+                None,
+                std::iter::once(first)
+                    .chain(self.recipes.iter().cloned())
+                    .collect(),
+            ),
+            script => (script, Vec::new()),
+        };
+        RawMitamaeTask {
+            script,
+            content,
+            recipes,
+            attributes: self.attributes.clone(),
+            binary: self.binary.clone(),
+            url: self.url.clone(),
+            sha256: self.sha256.clone(),
+            privilege: self.privilege.clone(),
+            isolation: self.isolation.clone(),
+            network: self.network,
+            user: self.user.clone(),
+            group: self.group.clone(),
+            mounts: self.mounts.clone(),
+            tags: self.tags.clone(),
+            ignore_errors: self.ignore_errors,
+            name: self.name.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
 #[cfg(feature = "schema")]
 impl JsonSchema for MitamaeTask {
     fn schema_name() -> Cow<'static, str> {
@@ -117,9 +395,21 @@ impl MitamaeTask {
     pub fn new(source: ScriptSource, binary: Utf8PathBuf) -> Self {
         Self {
             source,
+            recipes: Vec::new(),
+            attributes: BTreeMap::new(),
             binary: Some(binary),
+            url: None,
+            sha256: None,
+            release: None,
             privilege: Privilege::default(),
             isolation: TaskIsolation::default(),
+            network: None,
+            user: None,
+            group: None,
+            mounts: Vec::new(),
+            tags: Vec::new(),
+            ignore_errors: false,
+            name: None,
         }
     }
 
@@ -127,26 +417,187 @@ impl MitamaeTask {
     pub fn new_without_binary(source: ScriptSource) -> Self {
         Self {
             source,
+            recipes: Vec::new(),
+            attributes: BTreeMap::new(),
             binary: None,
+            url: None,
+            sha256: None,
+            release: None,
             privilege: Privilege::default(),
             isolation: TaskIsolation::default(),
+            network: None,
+            user: None,
+            group: None,
+            mounts: Vec::new(),
+            tags: Vec::new(),
+            ignore_errors: false,
+            name: None,
         }
     }
 
+    /// Creates a new MitamaeTask whose binary is downloaded from `url` and verified
+    /// against `sha256`.
+    pub fn with_url(
+        source: ScriptSource,
+        url: impl Into<String>,
+        sha256: impl Into<String>,
+    ) -> Self {
+        Self {
+            source,
+            recipes: Vec::new(),
+            attributes: BTreeMap::new(),
+            binary: None,
+            url: Some(url.into()),
+            sha256: Some(sha256.into()),
+            release: None,
+            privilege: Privilege::default(),
+            isolation: TaskIsolation::default(),
+            network: None,
+            user: None,
+            group: None,
+            mounts: Vec::new(),
+            tags: Vec::new(),
+            ignore_errors: false,
+            name: None,
+        }
+    }
+
+    /// Sets the user-given task name.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets whether a failure of the task is logged instead of failing the phase.
+    pub fn with_ignore_errors(mut self, ignore_errors: bool) -> Self {
+        self.ignore_errors = ignore_errors;
+        self
+    }
+
+    /// Sets the labels matched by `apply --tags`/`--skip-tags`.
+    pub fn with_tags<I, S>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the privilege escalation setting.
+    pub fn with_privilege(mut self, privilege: Privilege) -> Self {
+        self.privilege = privilege;
+        self
+    }
+
+    /// Sets the isolation setting.
+    pub fn with_isolation(mut self, isolation: TaskIsolation) -> Self {
+        self.isolation = isolation;
+        self
+    }
+
+    /// Sets network access (by default it is inherited from the isolation config).
+    pub fn with_network(mut self, network: bool) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Sets the user (and optionally group) to run the task as inside the chroot.
+    pub fn with_user(mut self, user: impl Into<String>, group: Option<String>) -> Self {
+        self.user = Some(user.into());
+        self.group = group;
+        self
+    }
+
+    /// Sets further recipe files applied after the main recipe, in order.
+    ///
+    /// Only meaningful when the main recipe is a `script` file; inline `content`
+    /// cannot be combined with further recipes.
+    pub fn with_recipes<I, P>(mut self, recipes: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<Utf8PathBuf>,
+    {
+        self.recipes = recipes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the node attributes passed to mitamae via `--node-json`.
+    pub fn with_attributes(mut self, attributes: BTreeMap<String, serde_json::Value>) -> Self {
+        self.attributes = attributes;
+        self
+    }
+
+    /// Sets the filesystems mounted into the rootfs for this task only.
+    pub fn with_mounts(mut self, mounts: Vec<MountEntry>) -> Self {
+        self.mounts = mounts;
+        self
+    }
+
     /// Returns a reference to the recipe source.
     pub fn source(&self) -> &ScriptSource {
         &self.source
     }
 
+    /// Returns the recipe files applied after the main recipe, in order.
+    pub fn recipes(&self) -> &[Utf8PathBuf] {
+        &self.recipes
+    }
+
+    /// Returns the node attributes passed to mitamae via `--node-json`.
+    pub fn attributes(&self) -> &BTreeMap<String, serde_json::Value> {
+        &self.attributes
+    }
+
+    /// Renders the node attributes as the JSON document given to `--node-json`.
+    pub fn node_json(&self) -> String {
+        let mut json =
+            serde_json::to_string_pretty(&self.attributes).expect("JSON values always serialize");
+        json.push('\n');
+        json
+    }
+
     /// Returns the mitamae binary path, if set.
     pub fn binary(&self) -> Option<&Utf8Path> {
         self.binary.as_deref()
     }
 
+    /// Returns the URL the mitamae binary is downloaded from, if set.
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    /// Returns the expected SHA-256 digest of the mitamae binary, if set.
+    pub fn sha256(&self) -> Option<&str> {
+        self.sha256.as_deref()
+    }
+
+    /// Returns the release the binary is downloaded from, if it comes from
+    /// `defaults.mitamae.version`.
+    pub fn release(&self) -> Option<&MitamaeRelease> {
+        self.release.as_ref()
+    }
+
+    /// Returns the URL the binary is downloaded from: `url`, or the release's.
+    pub fn download_url(&self) -> Option<&str> {
+        self.url
+            .as_deref()
+            .or_else(|| self.release.as_ref().map(MitamaeRelease::url))
+    }
+
+    /// Downloads the binary from `release` if neither `binary` nor `url` is set (used
+    /// for applying defaults).
+    pub fn set_release_if_absent(&mut self, release: &MitamaeRelease) {
+        if self.binary.is_none() && self.url.is_none() {
+            self.release = Some(release.clone());
+        }
+    }
+
     /// Sets the mitamae binary path if not already set (used for applying defaults).
-    /// Does nothing if binary is already set (task-level takes precedence).
+    /// Does nothing if binary is already set (task-level takes precedence) or the
+    /// binary is downloaded from `url`.
     pub fn set_binary_if_absent(&mut self, binary: &Utf8Path) {
-        if self.binary.is_none() {
+        if self.binary.is_none() && self.url.is_none() {
             self.binary = Some(binary.to_path_buf());
         }
     }
@@ -169,6 +620,11 @@ impl MitamaeTask {
             *binary = base_dir.join(&*binary);
         }
         self.source.resolve_paths(base_dir);
+        for recipe in &mut self.recipes {
+            if recipe.is_relative() {
+                *recipe = base_dir.join(&*recipe);
+            }
+        }
     }
 
     /// Resolves the privilege setting against profile defaults.
@@ -184,6 +640,13 @@ impl MitamaeTask {
         self.privilege.resolve_in_place(defaults)
     }
 
+    /// Returns the resolved privilege method.
+    ///
+    /// Should only be called after [`resolve_privilege()`](Self::resolve_privilege).
+    pub fn resolved_privilege_method(&self) -> Option<PrivilegeMethod> {
+        self.privilege.resolved_method()
+    }
+
     /// Returns a reference to the task's isolation setting.
     pub fn task_isolation(&self) -> &TaskIsolation {
         &self.isolation
@@ -201,15 +664,97 @@ impl MitamaeTask {
         self.isolation.resolved_config()
     }
 
+    /// Resolves the network setting against the isolation config and `offline`.
+    ///
+    /// Should be called after [`resolve_isolation()`](Self::resolve_isolation).
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `offline` is set and the task or its
+    /// isolation config explicitly enables the network.
+    pub fn resolve_network(&mut self, offline: bool) -> Result<(), RsdebstrapError> {
+        self.network =
+            crate::phase::resolve_network(self.network, self.isolation.resolved_config(), offline)?;
+        Ok(())
+    }
+
+    /// Merges `user`/`group` into the resolved chroot isolation's `user`.
+    ///
+    /// Should be called after [`resolve_isolation()`](Self::resolve_isolation).
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `group` is set without `user`, the task
+    /// runs without isolation, or the isolation config names a different user.
+    pub fn resolve_user(&mut self) -> Result<(), RsdebstrapError> {
+        crate::phase::resolve_task_user(
+            &mut self.isolation,
+            self.user.as_deref(),
+            self.group.as_deref(),
+        )
+    }
+
+    /// Returns whether the task may use the network (default: true).
+    pub fn network_enabled(&self) -> bool {
+        self.network.unwrap_or(true)
+    }
+
+    /// Returns the filesystems mounted into the rootfs for this task only.
+    pub fn mounts(&self) -> &[MountEntry] {
+        &self.mounts
+    }
+
+    /// Returns the task's tags.
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Returns whether a failure of the task is logged instead of failing the phase.
+    pub fn ignore_errors(&self) -> bool {
+        self.ignore_errors
+    }
+
+    /// Returns the user-given `name`, if any.
+    pub fn configured_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
     /// Validates the task configuration.
     ///
     /// Checks:
-    /// - Binary path is set and non-empty with no `..` components
-    /// - Binary file exists and is a regular file
+    /// - `sha256`, if set, is 64 hexadecimal digits
+    /// - With `url`: it is an `http://`/`https://` URL and `sha256` is set
+    /// - Otherwise: binary path is set and non-empty with no `..` components, exists,
+    ///   is a regular file, and matches `sha256` if set
     /// - Recipe: Script → no path traversal, exists, is a regular file; Content → non-empty
+    /// - `recipes`: only follow a `script` recipe; each like a Script recipe
+    /// - `name`, if set, is not blank
+    /// - `tags`: each is non-empty without whitespace or commas
+    /// - `mounts`: each entry is well-formed and parents come before children
     pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        crate::phase::validate_task_name(self.name.as_deref())?;
+        crate::phase::validate_task_tags(&self.tags)?;
+        crate::phase::validate_task_mounts(&self.mounts)?;
+
+        if let Some(sha256) = &self.sha256 {
+            crate::download::validate_sha256(sha256, "mitamae binary")?;
+        }
+
+        if let Some(url) = &self.url {
+            crate::download::validate_url(url, "mitamae binary")?;
+            if self.sha256.is_none() {
+                return Err(RsdebstrapError::Validation(format!(
+                    "mitamae binary url '{}' requires 'sha256'",
+                    url
+                )));
+            }
+            return self.validate_recipes();
+        }
+
         let binary = match &self.binary {
             Some(b) => b,
+            // Checked (digest and all) when it is downloaded.
+            None if self.release.is_some() => return self.validate_recipes(),
             None => {
                 return Err(RsdebstrapError::Validation(format!(
                     "mitamae binary path is not specified and no default is configured \
@@ -229,63 +774,162 @@ impl MitamaeTask {
 
         crate::phase::validate_no_parent_dirs(binary, "mitamae binary")?;
         crate::phase::validate_host_file_exists(binary, "mitamae binary")?;
+        if let Some(sha256) = &self.sha256 {
+            crate::download::verify_sha256(binary, sha256)?;
+        }
 
-        // Validate recipe source
-        self.source.validate("mitamae recipe")
+        self.validate_recipes()
+    }
+
+    /// Validates the main recipe source and any further recipes.
+    fn validate_recipes(&self) -> Result<(), RsdebstrapError> {
+        self.source.validate("mitamae recipe")?;
+        if !self.recipes.is_empty() && self.source.script_path().is_none() {
+            return Err(RsdebstrapError::Validation(
+                "mitamae 'recipes' cannot follow inline recipe content".to_string(),
+            ));
+        }
+        for recipe in &self.recipes {
+            crate::phase::validate_no_parent_dirs(recipe, "mitamae recipe")?;
+            crate::phase::validate_host_file_exists(recipe, "mitamae recipe")?;
+        }
+        Ok(())
+    }
+
+    /// Downloads the binary from `url` to `dest` and uses it as the task's binary.
+    ///
+    /// A binary from the `defaults.mitamae.version` release goes to the download
+    /// cache instead of `dest` (see [`MitamaeRelease::fetch`]). Does nothing for a
+    /// task with neither. In dry-run mode the download is only logged.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the download fails or the file does not match `sha256`.
+    pub fn download_binary(
+        &mut self,
+        dest: &Utf8Path,
+        executor: &dyn CommandExecutor,
+        dry_run: bool,
+    ) -> Result<()> {
+        if let Some(release) = &self.release
+            && self.binary.is_none()
+        {
+            let cache_dir = crate::download::default_cache_dir()?;
+            self.binary = Some(release.fetch(&cache_dir, executor, dry_run)?);
+            return Ok(());
+        }
+        let (Some(url), Some(sha256)) = (&self.url, &self.sha256) else {
+            return Ok(());
+        };
+        crate::download::download_verified(url, dest, sha256, executor, dry_run)
+            .with_context(|| format!("failed to download mitamae binary from {}", url))?;
+        self.binary = Some(dest.to_path_buf());
+        Ok(())
     }
 
     /// Executes the mitamae recipe using the provided isolation context.
     ///
     /// This method:
-    /// 1. Validates /tmp in rootfs (unless dry_run)
+    /// 1. Validates the staging directory (`/tmp` by default) in rootfs (unless dry_run)
     /// 2. Sets up RAII guards for cleanup of temp files
-    /// 3. Re-validates /tmp to mitigate TOCTOU race conditions (unless dry_run)
-    /// 4. Copies mitamae binary to rootfs /tmp with 0o700 permissions
-    /// 5. Copies or writes the recipe to rootfs /tmp with 0o600 permissions
-    /// 6. Executes `mitamae local <recipe>` via the isolation context
-    /// 7. Returns an error if the process fails or exits without status
+    /// 3. Re-validates the staging directory to mitigate TOCTOU race conditions
+    ///    (unless dry_run)
+    /// 4. Verifies the binary against `sha256` if set, then copies it to the staging
+    ///    directory with 0o700 permissions
+    /// 5. Copies or writes the recipes, and the `attributes` JSON if any, to the staging
+    ///    directory with 0o600 permissions
+    /// 6. Hands the files over to the isolation's task user, if any
+    /// 7. Executes `mitamae local [--node-json <node.json>] <recipe>...` via the isolation
+    ///    context
+    /// 8. Returns an error if the process fails or exits without status
     pub fn execute(&self, context: &dyn IsolationContext) -> Result<()> {
         let rootfs = context.rootfs();
         let dry_run = context.dry_run();
 
-        let binary = self.binary.as_ref().unwrap();
+        let binary = self.binary.as_ref().ok_or_else(|| {
+            RsdebstrapError::Validation(format!(
+                "mitamae binary from {} has not been downloaded",
+                self.download_url().unwrap_or("<unset>")
+            ))
+        })?;
 
         // Unlike ShellTask, no validate_rootfs() is needed here because the mitamae
         // binary is copied from the host side — there is no rootfs-resident binary
-        // to verify. Only /tmp validation is required for the copy destination.
+        // to verify. Only staging directory validation is required for the copy destination.
         if !dry_run {
-            crate::phase::validate_tmp_directory(rootfs).context("rootfs validation failed")?;
+            crate::phase::validate_staging_directory(rootfs, context.staging_dir())
+                .context("rootfs validation failed")?;
         }
 
         info!("running mitamae recipe: {} (isolation: {})", self.name(), context.name());
         debug!("rootfs: {}, binary: {}, dry_run: {}", rootfs, binary, dry_run);
 
-        let uuid = uuid::Uuid::new_v4();
-        let binary_name = format!("mitamae-{}", uuid);
-        let recipe_name = format!("recipe-{}.rb", uuid);
-        let target_binary = rootfs.join("tmp").join(&binary_name);
-        let target_recipe = rootfs.join("tmp").join(&recipe_name);
+        let binary_name = context.staging_name("mitamae");
+        let (target_binary, binary_path_in_isolation) =
+            crate::phase::staged_file_paths(context, &binary_name);
+        // The first recipe keeps the single-recipe name; further ones are numbered.
+        let recipe_sources: Vec<ScriptSource> = std::iter::once(self.source.clone())
+            .chain(self.recipes.iter().cloned().map(ScriptSource::Script))
+            .collect();
+        let recipe_name = context.staging_name("recipe");
+        let staged_recipes: Vec<(Utf8PathBuf, String)> = (0..recipe_sources.len())
+            .map(|index| {
+                let name = match index {
+                    0 => format!("{}.rb", recipe_name),
+                    n => format!("{}-{}.rb", recipe_name, n),
+                };
+                crate::phase::staged_file_paths(context, &name)
+            })
+            .collect();
+        let staged_node = (!self.attributes.is_empty()).then(|| {
+            let name = format!("{}.json", context.staging_name("node"));
+            crate::phase::staged_file_paths(context, &name)
+        });
 
         let _binary_guard = TempFileGuard::new(target_binary.clone(), dry_run);
-        let _recipe_guard = TempFileGuard::new(target_recipe.clone(), dry_run);
-
-        crate::phase::prepare_files_with_toctou_check(rootfs, dry_run, || {
+        let _recipe_guards: Vec<TempFileGuard> = staged_recipes
+            .iter()
+            .map(|(target, _)| TempFileGuard::new(target.clone(), dry_run))
+            .collect();
+        let _node_guard = staged_node
+            .as_ref()
+            .map(|(target, _)| TempFileGuard::new(target.clone(), dry_run));
+
+        crate::phase::prepare_files_with_toctou_check(context, || {
+            if let Some(sha256) = &self.sha256 {
+                crate::download::verify_sha256(binary, sha256)?;
+            }
             info!("copying mitamae binary from {} to rootfs", binary);
             fs::copy(binary, &target_binary).with_context(|| {
                 format!("failed to copy mitamae binary {} to {}", binary, target_binary)
             })?;
             #[cfg(unix)]
             crate::phase::set_file_mode(&target_binary, 0o700)?;
-            crate::phase::prepare_source_file(&self.source, &target_recipe, 0o600, "recipe")
+            for (source, (target, _)) in recipe_sources.iter().zip(&staged_recipes) {
+                crate::phase::prepare_source_file(source, target, 0o600, "recipe")?;
+            }
+            if let Some((target, _)) = &staged_node {
+                info!("writing mitamae node attributes to rootfs");
+                fs::write(target, self.node_json()).with_context(|| {
+                    format!("failed to write mitamae node attributes to {}", target)
+                })?;
+                #[cfg(unix)]
+                crate::phase::set_file_mode(target, 0o600)?;
+            }
+            Ok(())
         })?;
 
-        let binary_path_in_isolation = format!("/tmp/{}", binary_name);
-        let recipe_path_in_isolation = format!("/tmp/{}", recipe_name);
-        let command: Vec<String> = vec![
-            binary_path_in_isolation,
-            "local".to_string(),
-            recipe_path_in_isolation,
-        ];
+        let mut staged: Vec<String> = vec![binary_path_in_isolation.clone()];
+        staged.extend(staged_recipes.iter().map(|(_, path)| path.clone()));
+        staged.extend(staged_node.iter().map(|(_, path)| path.clone()));
+        context.hand_over(&staged, self.privilege.resolved_method())?;
+
+        let mut command: Vec<String> = vec![binary_path_in_isolation, "local".to_string()];
+        if let Some((_, node_path)) = staged_node {
+            command.push("--node-json".to_string());
+            command.push(node_path);
+        }
+        command.extend(staged_recipes.into_iter().map(|(_, path)| path));
 
         let result = crate::phase::execute_in_context(
             context,
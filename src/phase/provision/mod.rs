@@ -12,25 +12,46 @@
 //!    `resolve_privilege`, `resolve_isolation`, `resolved_isolation_config`)
 //!
 //! The compiler enforces exhaustiveness, ensuring all task types are handled.
+//! Task types maintained outside rsdebstrap use the `Plugin` variant instead (see
+//! [`plugin`]), or `Wasm` for sandboxed logic (see [`wasm`]).
 
+pub mod cookbook;
+pub mod debconf;
+pub mod kernel;
 pub mod mitamae;
+pub mod overlay;
+pub mod plugin;
+pub mod puppet;
+pub mod rsync;
 pub mod shell;
+pub mod ssh;
+pub mod wasm;
 
 use std::borrow::Cow;
 
 use camino::Utf8Path;
 #[cfg(feature = "schema")]
 use schemars::JsonSchema;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
+pub use cookbook::{BundlerConfig, CookbookRunner, CookbookTask};
+pub use debconf::{DebconfSelection, DebconfTask, DebconfType};
+pub use kernel::KernelTask;
 pub use mitamae::MitamaeTask;
+pub use overlay::OverlayTask;
+pub use plugin::{PluginPlan, PluginTask};
+pub use puppet::PuppetTask;
+pub use rsync::{RsyncDirection, RsyncTask};
 pub use shell::ShellTask;
+pub use ssh::{SshHostKeys, SshTask};
+pub use wasm::WasmTask;
 
-use crate::config::IsolationConfig;
+use crate::config::{IsolationConfig, MountEntry};
 use crate::error::RsdebstrapError;
+use crate::executor::CommandExecutor;
 use crate::isolation::TaskIsolation;
-use crate::phase::PhaseItem;
-use crate::privilege::PrivilegeDefaults;
+use crate::phase::{PhaseItem, ScriptSource};
+use crate::privilege::{PrivilegeDefaults, PrivilegeMethod};
 
 /// Declarative task definition for provision pipeline steps.
 ///
@@ -38,7 +59,7 @@ use crate::privilege::PrivilegeDefaults;
 /// type of task. The enum dispatch pattern provides compile-time exhaustive
 /// matching — adding a new variant causes compilation errors at every
 /// unhandled match site, preventing missed implementations.
-#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum ProvisionTask {
@@ -46,6 +67,90 @@ pub enum ProvisionTask {
     Shell(ShellTask),
     /// Mitamae recipe execution task
     Mitamae(MitamaeTask),
+    /// Itamae or Cinc cookbook execution task
+    Cookbook(CookbookTask),
+    /// `puppet apply` execution task
+    Puppet(PuppetTask),
+    /// `debconf-set-selections` preseeding task
+    Debconf(DebconfTask),
+    /// sshd drop-in configuration, `authorized_keys` and host key task
+    Ssh(SshTask),
+    /// modules-load.d and sysctl.d configuration task
+    Kernel(KernelTask),
+    /// Host directory or tarball laid over the rootfs
+    Overlay(OverlayTask),
+    /// `rsync` between a host directory and the rootfs, run on the host
+    Rsync(RsyncTask),
+    /// Task type provided by an external `rsdebstrap-plugin-<name>` executable
+    Plugin(PluginTask),
+    /// Task logic from a sandboxed WebAssembly module (`wasm` feature)
+    Wasm(WasmTask),
+}
+
+impl From<ShellTask> for ProvisionTask {
+    fn from(task: ShellTask) -> Self {
+        Self::Shell(task)
+    }
+}
+
+impl From<MitamaeTask> for ProvisionTask {
+    fn from(task: MitamaeTask) -> Self {
+        Self::Mitamae(task)
+    }
+}
+
+impl From<CookbookTask> for ProvisionTask {
+    fn from(task: CookbookTask) -> Self {
+        Self::Cookbook(task)
+    }
+}
+
+impl From<PuppetTask> for ProvisionTask {
+    fn from(task: PuppetTask) -> Self {
+        Self::Puppet(task)
+    }
+}
+
+impl From<DebconfTask> for ProvisionTask {
+    fn from(task: DebconfTask) -> Self {
+        Self::Debconf(task)
+    }
+}
+
+impl From<SshTask> for ProvisionTask {
+    fn from(task: SshTask) -> Self {
+        Self::Ssh(task)
+    }
+}
+
+impl From<KernelTask> for ProvisionTask {
+    fn from(task: KernelTask) -> Self {
+        Self::Kernel(task)
+    }
+}
+
+impl From<OverlayTask> for ProvisionTask {
+    fn from(task: OverlayTask) -> Self {
+        Self::Overlay(task)
+    }
+}
+
+impl From<RsyncTask> for ProvisionTask {
+    fn from(task: RsyncTask) -> Self {
+        Self::Rsync(task)
+    }
+}
+
+impl From<PluginTask> for ProvisionTask {
+    fn from(task: PluginTask) -> Self {
+        Self::Plugin(task)
+    }
+}
+
+impl From<WasmTask> for ProvisionTask {
+    fn from(task: WasmTask) -> Self {
+        Self::Wasm(task)
+    }
 }
 
 impl PhaseItem for ProvisionTask {
@@ -53,10 +158,23 @@ impl PhaseItem for ProvisionTask {
         ProvisionTask::name(self)
     }
 
+    fn configured_name(&self) -> Option<&str> {
+        ProvisionTask::configured_name(self)
+    }
+
     fn validate(&self) -> Result<(), RsdebstrapError> {
         match self {
             Self::Shell(task) => task.validate(),
             Self::Mitamae(task) => task.validate(),
+            Self::Cookbook(task) => task.validate(),
+            Self::Puppet(task) => task.validate(),
+            Self::Debconf(task) => task.validate(),
+            Self::Ssh(task) => task.validate(),
+            Self::Kernel(task) => task.validate(),
+            Self::Overlay(task) => task.validate(),
+            Self::Rsync(task) => task.validate(),
+            Self::Plugin(task) => task.validate(),
+            Self::Wasm(task) => task.validate(),
         }
     }
 
@@ -64,20 +182,58 @@ impl PhaseItem for ProvisionTask {
         match self {
             Self::Shell(task) => task.execute(ctx),
             Self::Mitamae(task) => task.execute(ctx),
+            Self::Cookbook(task) => task.execute(ctx),
+            Self::Puppet(task) => task.execute(ctx),
+            Self::Debconf(task) => task.execute(ctx),
+            Self::Ssh(task) => task.execute(ctx),
+            Self::Kernel(task) => task.execute(ctx),
+            Self::Overlay(task) => task.execute(ctx),
+            Self::Rsync(task) => task.execute(ctx),
+            Self::Plugin(task) => task.execute(ctx),
+            Self::Wasm(task) => task.execute(ctx),
         }
     }
 
     fn resolved_isolation_config(&self) -> Option<&IsolationConfig> {
         ProvisionTask::resolved_isolation_config(self)
     }
+
+    fn network_enabled(&self) -> bool {
+        ProvisionTask::network_enabled(self)
+    }
+
+    fn isolation_privilege(&self) -> Option<PrivilegeMethod> {
+        self.resolved_privilege_method()
+    }
+
+    fn task_mounts(&self) -> &[MountEntry] {
+        self.mounts()
+    }
+
+    fn ignore_errors(&self) -> bool {
+        ProvisionTask::ignore_errors(self)
+    }
 }
 
 impl ProvisionTask {
-    /// Returns the display name of this task (e.g., `shell:<inline>`, `mitamae:recipe.rb`).
+    /// Returns the display name of this task: its `name` if given, otherwise derived
+    /// from its source (e.g., `shell:<inline>`, `mitamae:recipe.rb`).
     pub fn name(&self) -> Cow<'_, str> {
+        if let Some(name) = self.configured_name() {
+            return Cow::Borrowed(name);
+        }
         match self {
             Self::Shell(task) => Cow::Owned(format!("shell:{}", task.name())),
             Self::Mitamae(task) => Cow::Owned(format!("mitamae:{}", task.name())),
+            Self::Cookbook(task) => Cow::Owned(format!("cookbook:{}", task.name())),
+            Self::Puppet(task) => Cow::Owned(format!("puppet:{}", task.name())),
+            Self::Debconf(task) => Cow::Owned(format!("debconf:{}", task.name())),
+            Self::Ssh(task) => Cow::Owned(format!("ssh:{}", task.name())),
+            Self::Kernel(task) => Cow::Owned(format!("kernel:{}", task.name())),
+            Self::Overlay(task) => Cow::Owned(format!("overlay:{}", task.name())),
+            Self::Rsync(task) => Cow::Owned(format!("rsync:{}", task.name())),
+            Self::Plugin(task) => Cow::Owned(format!("plugin:{}", task.name())),
+            Self::Wasm(task) => Cow::Owned(format!("wasm:{}", task.name())),
         }
     }
 
@@ -86,6 +242,33 @@ impl ProvisionTask {
         match self {
             Self::Shell(task) => task.resolved_isolation_config(),
             Self::Mitamae(task) => task.resolved_isolation_config(),
+            Self::Cookbook(task) => task.resolved_isolation_config(),
+            Self::Puppet(task) => task.resolved_isolation_config(),
+            Self::Debconf(task) => task.resolved_isolation_config(),
+            Self::Ssh(task) => task.resolved_isolation_config(),
+            Self::Kernel(task) => task.resolved_isolation_config(),
+            Self::Overlay(task) => task.resolved_isolation_config(),
+            Self::Rsync(task) => task.resolved_isolation_config(),
+            Self::Plugin(task) => task.resolved_isolation_config(),
+            Self::Wasm(task) => task.resolved_isolation_config(),
+        }
+    }
+
+    /// Returns the task's script (shell) or recipe (mitamae) source; cookbook, puppet,
+    /// debconf, ssh, kernel, overlay and rsync tasks have none.
+    pub fn source(&self) -> Option<&ScriptSource> {
+        match self {
+            Self::Shell(task) => Some(task.source()),
+            Self::Mitamae(task) => Some(task.source()),
+            Self::Cookbook(_)
+            | Self::Puppet(_)
+            | Self::Debconf(_)
+            | Self::Ssh(_)
+            | Self::Kernel(_)
+            | Self::Overlay(_)
+            | Self::Rsync(_)
+            | Self::Plugin(_)
+            | Self::Wasm(_) => None,
         }
     }
 
@@ -94,6 +277,15 @@ impl ProvisionTask {
         match self {
             Self::Shell(task) => task.script_path(),
             Self::Mitamae(task) => task.script_path(),
+            Self::Cookbook(_) => None,
+            Self::Puppet(_) => None,
+            Self::Debconf(_) => None,
+            Self::Ssh(_) => None,
+            Self::Kernel(_) => None,
+            Self::Overlay(_) => None,
+            Self::Rsync(_) => None,
+            Self::Plugin(_) => None,
+            Self::Wasm(_) => None,
         }
     }
 
@@ -102,6 +294,15 @@ impl ProvisionTask {
         match self {
             Self::Shell(task) => task.resolve_paths(base_dir),
             Self::Mitamae(task) => task.resolve_paths(base_dir),
+            Self::Cookbook(task) => task.resolve_paths(base_dir),
+            Self::Puppet(task) => task.resolve_paths(base_dir),
+            Self::Debconf(task) => task.resolve_paths(base_dir),
+            Self::Ssh(task) => task.resolve_paths(base_dir),
+            Self::Kernel(task) => task.resolve_paths(base_dir),
+            Self::Overlay(task) => task.resolve_paths(base_dir),
+            Self::Rsync(task) => task.resolve_paths(base_dir),
+            Self::Plugin(task) => task.resolve_paths(base_dir),
+            Self::Wasm(task) => task.resolve_paths(base_dir),
         }
     }
 
@@ -110,6 +311,54 @@ impl ProvisionTask {
         match self {
             Self::Shell(_) => None,
             Self::Mitamae(task) => task.binary(),
+            Self::Cookbook(_) => None,
+            Self::Puppet(_) => None,
+            Self::Debconf(_) => None,
+            Self::Ssh(_) => None,
+            Self::Kernel(_) => None,
+            Self::Overlay(_) => None,
+            Self::Rsync(_) => None,
+            Self::Plugin(_) => None,
+            Self::Wasm(_) => None,
+        }
+    }
+
+    /// Returns the URL the task's binary is downloaded from, if any.
+    pub fn binary_url(&self) -> Option<&str> {
+        match self {
+            Self::Shell(_) => None,
+            Self::Mitamae(task) => task.download_url(),
+            Self::Cookbook(_) => None,
+            Self::Puppet(_) => None,
+            Self::Debconf(_) => None,
+            Self::Ssh(_) => None,
+            Self::Kernel(_) => None,
+            Self::Overlay(_) => None,
+            Self::Rsync(_) => None,
+            Self::Plugin(_) => None,
+            Self::Wasm(_) => None,
+        }
+    }
+
+    /// Downloads the task's binary to `dest` if it is given as a URL.
+    pub fn download_binary(
+        &mut self,
+        dest: &Utf8Path,
+        executor: &dyn CommandExecutor,
+        dry_run: bool,
+    ) -> anyhow::Result<()> {
+        match self {
+            Self::Shell(_) => Ok(()),
+            Self::Mitamae(task) => task.download_binary(dest, executor, dry_run),
+            Self::Cookbook(_) => Ok(()),
+            Self::Puppet(_) => Ok(()),
+            Self::Debconf(_) => Ok(()),
+            Self::Ssh(_) => Ok(()),
+            Self::Kernel(_) => Ok(()),
+            Self::Overlay(_) => Ok(()),
+            Self::Rsync(_) => Ok(()),
+            Self::Plugin(_) => Ok(()),
+            Self::Wasm(_) => Ok(()),
         }
     }
 
@@ -121,6 +370,32 @@ impl ProvisionTask {
         match self {
             Self::Shell(task) => task.resolve_privilege(defaults),
             Self::Mitamae(task) => task.resolve_privilege(defaults),
+            Self::Cookbook(task) => task.resolve_privilege(defaults),
+            Self::Puppet(task) => task.resolve_privilege(defaults),
+            Self::Debconf(task) => task.resolve_privilege(defaults),
+            Self::Ssh(task) => task.resolve_privilege(defaults),
+            Self::Kernel(task) => task.resolve_privilege(defaults),
+            Self::Overlay(task) => task.resolve_privilege(defaults),
+            Self::Rsync(task) => task.resolve_privilege(defaults),
+            Self::Plugin(task) => task.resolve_privilege(defaults),
+            Self::Wasm(task) => task.resolve_privilege(defaults),
+        }
+    }
+
+    /// Returns the resolved privilege method after `resolve_privilege()` has been called.
+    pub fn resolved_privilege_method(&self) -> Option<PrivilegeMethod> {
+        match self {
+            Self::Shell(task) => task.resolved_privilege_method(),
+            Self::Mitamae(task) => task.resolved_privilege_method(),
+            Self::Cookbook(task) => task.resolved_privilege_method(),
+            Self::Puppet(task) => task.resolved_privilege_method(),
+            Self::Debconf(task) => task.resolved_privilege_method(),
+            Self::Ssh(task) => task.resolved_privilege_method(),
+            Self::Kernel(task) => task.resolved_privilege_method(),
+            Self::Overlay(task) => task.resolved_privilege_method(),
+            Self::Rsync(task) => task.resolved_privilege_method(),
+            Self::Plugin(task) => task.resolved_privilege_method(),
+            Self::Wasm(task) => task.resolved_privilege_method(),
         }
     }
 
@@ -129,6 +404,15 @@ impl ProvisionTask {
         match self {
             Self::Shell(task) => task.task_isolation(),
             Self::Mitamae(task) => task.task_isolation(),
+            Self::Cookbook(task) => task.task_isolation(),
+            Self::Puppet(task) => task.task_isolation(),
+            Self::Debconf(task) => task.task_isolation(),
+            Self::Ssh(task) => task.task_isolation(),
+            Self::Kernel(task) => task.task_isolation(),
+            Self::Overlay(task) => task.task_isolation(),
+            Self::Rsync(task) => task.task_isolation(),
+            Self::Plugin(task) => task.task_isolation(),
+            Self::Wasm(task) => task.task_isolation(),
         }
     }
 
@@ -137,6 +421,136 @@ impl ProvisionTask {
         match self {
             Self::Shell(task) => task.resolve_isolation(defaults),
             Self::Mitamae(task) => task.resolve_isolation(defaults),
+            Self::Cookbook(task) => task.resolve_isolation(defaults),
+            Self::Puppet(task) => task.resolve_isolation(defaults),
+            Self::Debconf(task) => task.resolve_isolation(defaults),
+            Self::Ssh(task) => task.resolve_isolation(defaults),
+            Self::Kernel(task) => task.resolve_isolation(defaults),
+            Self::Overlay(task) => task.resolve_isolation(defaults),
+            Self::Rsync(_) => {}
+            Self::Plugin(task) => task.resolve_isolation(defaults),
+            Self::Wasm(task) => task.resolve_isolation(defaults),
+        }
+    }
+
+    /// Resolves the network setting against the isolation config and the
+    /// profile-level `offline` flag. Call after `resolve_isolation()`.
+    pub fn resolve_network(&mut self, offline: bool) -> Result<(), RsdebstrapError> {
+        match self {
+            Self::Shell(task) => task.resolve_network(offline),
+            Self::Mitamae(task) => task.resolve_network(offline),
+            Self::Cookbook(task) => task.resolve_network(offline),
+            Self::Puppet(task) => task.resolve_network(offline),
+            Self::Debconf(task) => task.resolve_network(offline),
+            Self::Ssh(task) => task.resolve_network(offline),
+            Self::Kernel(task) => task.resolve_network(offline),
+            Self::Overlay(task) => task.resolve_network(offline),
+            Self::Rsync(_) => Ok(()),
+            Self::Plugin(task) => task.resolve_network(offline),
+            Self::Wasm(task) => task.resolve_network(offline),
+        }
+    }
+
+    /// Merges the task's `user`/`group` into its chroot isolation. Call after
+    /// `resolve_isolation()`.
+    pub fn resolve_user(&mut self) -> Result<(), RsdebstrapError> {
+        match self {
+            Self::Shell(task) => task.resolve_user(),
+            Self::Mitamae(task) => task.resolve_user(),
+            Self::Cookbook(task) => task.resolve_user(),
+            Self::Puppet(task) => task.resolve_user(),
+            Self::Debconf(_) => Ok(()),
+            Self::Ssh(_) => Ok(()),
+            Self::Kernel(_) => Ok(()),
+            Self::Overlay(_) => Ok(()),
+            Self::Rsync(_) => Ok(()),
+            Self::Plugin(_) => Ok(()),
+            Self::Wasm(_) => Ok(()),
+        }
+    }
+
+    /// Returns whether the task may use the network.
+    pub fn network_enabled(&self) -> bool {
+        match self {
+            Self::Shell(task) => task.network_enabled(),
+            Self::Mitamae(task) => task.network_enabled(),
+            Self::Cookbook(task) => task.network_enabled(),
+            Self::Puppet(task) => task.network_enabled(),
+            Self::Debconf(task) => task.network_enabled(),
+            Self::Ssh(task) => task.network_enabled(),
+            Self::Kernel(task) => task.network_enabled(),
+            Self::Overlay(task) => task.network_enabled(),
+            Self::Rsync(_) => true,
+            Self::Plugin(task) => task.network_enabled(),
+            Self::Wasm(task) => task.network_enabled(),
+        }
+    }
+
+    /// Returns the filesystems mounted into the rootfs for this task only.
+    pub fn mounts(&self) -> &[MountEntry] {
+        match self {
+            Self::Shell(task) => task.mounts(),
+            Self::Mitamae(task) => task.mounts(),
+            Self::Cookbook(task) => task.mounts(),
+            Self::Puppet(task) => task.mounts(),
+            Self::Debconf(_) => &[],
+            Self::Ssh(_) => &[],
+            Self::Kernel(_) => &[],
+            Self::Overlay(_) => &[],
+            Self::Rsync(_) => &[],
+            Self::Plugin(_) => &[],
+            Self::Wasm(_) => &[],
+        }
+    }
+
+    /// Returns the user-given `name`, if any.
+    pub fn configured_name(&self) -> Option<&str> {
+        match self {
+            Self::Shell(task) => task.configured_name(),
+            Self::Mitamae(task) => task.configured_name(),
+            Self::Cookbook(task) => task.configured_name(),
+            Self::Puppet(task) => task.configured_name(),
+            Self::Debconf(task) => task.configured_name(),
+            Self::Ssh(task) => task.configured_name(),
+            Self::Kernel(task) => task.configured_name(),
+            Self::Overlay(task) => task.configured_name(),
+            Self::Rsync(task) => task.configured_name(),
+            Self::Plugin(task) => task.configured_name(),
+            Self::Wasm(task) => task.configured_name(),
+        }
+    }
+
+    /// Returns the task's tags.
+    pub fn tags(&self) -> &[String] {
+        match self {
+            Self::Shell(task) => task.tags(),
+            Self::Mitamae(task) => task.tags(),
+            Self::Cookbook(task) => task.tags(),
+            Self::Puppet(task) => task.tags(),
+            Self::Debconf(task) => task.tags(),
+            Self::Ssh(task) => task.tags(),
+            Self::Kernel(task) => task.tags(),
+            Self::Overlay(task) => task.tags(),
+            Self::Rsync(task) => task.tags(),
+            Self::Plugin(task) => task.tags(),
+            Self::Wasm(task) => task.tags(),
+        }
+    }
+
+    /// Returns whether a failure of the task is logged instead of failing the phase.
+    pub fn ignore_errors(&self) -> bool {
+        match self {
+            Self::Shell(task) => task.ignore_errors(),
+            Self::Mitamae(task) => task.ignore_errors(),
+            Self::Cookbook(task) => task.ignore_errors(),
+            Self::Puppet(task) => task.ignore_errors(),
+            Self::Debconf(task) => task.ignore_errors(),
+            Self::Ssh(task) => task.ignore_errors(),
+            Self::Kernel(task) => task.ignore_errors(),
+            Self::Overlay(task) => task.ignore_errors(),
+            Self::Rsync(task) => task.ignore_errors(),
+            Self::Plugin(task) => task.ignore_errors(),
+            Self::Wasm(task) => task.ignore_errors(),
         }
     }
 }
@@ -0,0 +1,694 @@
+//! Overlay task implementation.
+//!
+//! This module provides the `OverlayTask` data structure and execution logic for
+//! laying a host directory tree or tarball over the rootfs, such as an `/etc`
+//! skeleton. It handles:
+//! - Listing the entries the overlay writes and checking them against the optional
+//!   `allow` list before anything is written
+//! - Staging the directory or tarball in the rootfs staging directory (rootfs /tmp by
+//!   default), with RAII cleanup
+//! - Copying or extracting it inside the isolation, so symlinks in the rootfs or the
+//!   archive resolve within the rootfs and never lead to the host
+//! - Keeping extended attributes (ACLs, file capabilities) unless `xattrs` is off: a
+//!   directory is then staged as a tarball the host's `tar` packs, since the staging
+//!   copy runs unprivileged and could not set capabilities
+
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::config::IsolationConfig;
+use crate::error::RsdebstrapError;
+use crate::isolation::{IsolationContext, TaskIsolation};
+use crate::phase::{ScriptSource, TempDirGuard, TempFileGuard, sh_quote};
+use crate::privilege::{Privilege, PrivilegeDefaults, PrivilegeMethod};
+
+/// Shell reading the generated script from its standard input.
+const SHELL: &str = "/bin/sh";
+
+fn default_destination() -> Utf8PathBuf {
+    Utf8PathBuf::from("/")
+}
+
+fn is_default_destination(path: &Utf8PathBuf) -> bool {
+    path == "/"
+}
+
+/// An entry written by an overlay, relative to its destination.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OverlayEntry {
+    /// Path relative to the destination, without a leading `./` or trailing `/`
+    pub path: Utf8PathBuf,
+    /// Whether the entry is a directory
+    pub is_dir: bool,
+}
+
+/// Returns the entries of the tarball `archive`, as listed by the host's `tar`.
+///
+/// # Errors
+///
+/// Returns [`RsdebstrapError::Validation`] if `tar` cannot list the archive, or if an
+/// entry is absolute or contains `..`.
+pub fn archive_entries(archive: &Utf8Path) -> Result<Vec<OverlayEntry>, RsdebstrapError> {
+    let output = Command::new("tar")
+        .args(["--list", "--file", archive.as_str()])
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| RsdebstrapError::io(format!("failed to run tar to list {}", archive), e))?;
+    if !output.status.success() {
+        return Err(RsdebstrapError::Validation(format!(
+            "failed to list overlay archive {}: {}",
+            archive,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let mut entries = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let mut name = line;
+        while let Some(rest) = name.strip_prefix("./") {
+            name = rest;
+        }
+        let is_dir = name.ends_with('/');
+        let name = name.trim_end_matches('/');
+        if name.is_empty() || name == "." {
+            continue;
+        }
+        let path = Utf8PathBuf::from(name);
+        if path.is_absolute() || path.components().any(|c| c.as_str() == "..") {
+            return Err(RsdebstrapError::Validation(format!(
+                "overlay archive {} entry '{}' would be written outside the destination",
+                archive, line
+            )));
+        }
+        entries.push(OverlayEntry { path, is_dir });
+    }
+    Ok(entries)
+}
+
+/// Packs the directory `source` into the tarball `archive` with the host's `tar`,
+/// keeping extended attributes and ACLs, and makes it private (`0600`).
+///
+/// Only the top-level entries are named, so the directory itself is not in the
+/// archive and extracting it leaves the destination's own mode and owner alone.
+fn pack_directory(source: &Utf8Path, archive: &Utf8Path) -> Result<()> {
+    info!("packing overlay {} into the rootfs", source);
+    let top_level: Vec<Utf8PathBuf> = crate::phase::walk_host_tree(source, "overlay")?
+        .into_iter()
+        .filter(|path| path.components().count() == 1)
+        .collect();
+    let mut command = Command::new("tar");
+    command
+        .args(["--create", "--format=posix", "--file", archive.as_str()])
+        .args(["--directory", source.as_str()])
+        .args(["--xattrs", "--xattrs-include=*", "--acls"]);
+    if top_level.is_empty() {
+        command.args(["--files-from", "/dev/null"]);
+    } else {
+        command.arg("--").args(&top_level);
+    }
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| RsdebstrapError::io(format!("failed to run tar to pack {}", source), e))?;
+    if !output.status.success() {
+        return Err(RsdebstrapError::Validation(format!(
+            "failed to pack overlay {}: {}",
+            source,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+        .into());
+    }
+    crate::phase::set_file_mode(archive, 0o600)
+}
+
+/// Overlay task data and execution logic.
+///
+/// Lays the host directory or tarball `source` over `destination` in the rootfs (like
+/// debos' `overlay` action), replacing existing files. A tarball is extracted with the
+/// rootfs' `tar` (any compression it detects), a directory is packed by the host's `tar`
+/// and extracted the same way; both keep permission bits, timestamps and, unless
+/// `xattrs` is off, extended attributes such as ACLs and file capabilities. Without
+/// `xattrs` a directory is copied with `cp` instead. Entries are owned by root unless
+/// `preserve_ownership` is set, which keeps the owners recorded in the tarball (by name
+/// as the rootfs resolves it) or the numeric owners of the directory's files.
+///
+/// ## Lifecycle
+///
+/// The typical lifecycle when loaded from a YAML profile is:
+/// 1. **Deserialize** — construct from YAML via `serde`
+///    (or [`new()`](Self::new) for programmatic use)
+/// 2. [`resolve_paths()`](Self::resolve_paths) — resolve a relative `source`
+/// 3. [`validate()`](Self::validate) — check the source, destination and `allow` list
+/// 4. [`execute()`](Self::execute) — run within an isolation context
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct OverlayTask {
+    /// Host directory or tarball laid over the rootfs (relative paths resolve against
+    /// the profile)
+    #[serde(deserialize_with = "crate::de::path")]
+    #[cfg_attr(feature = "schema", schemars(with = "crate::schema::Utf8PathSchema"))]
+    source: Utf8PathBuf,
+
+    /// Directory in the rootfs the overlay is laid over (default: `/`)
+    #[serde(
+        default = "default_destination",
+        deserialize_with = "crate::de::path",
+        skip_serializing_if = "is_default_destination"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<crate::schema::Utf8PathSchema>")
+    )]
+    destination: Utf8PathBuf,
+
+    /// Keep the owners of the source entries instead of making root own them
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    preserve_ownership: bool,
+
+    /// Keep extended attributes: ACLs, file capabilities and `user.*` attributes
+    #[serde(
+        default = "crate::phase::default_true",
+        skip_serializing_if = "crate::phase::is_true"
+    )]
+    xattrs: bool,
+
+    /// Rootfs paths the overlay may write below; empty allows any path under
+    /// `destination`
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<String>>"))]
+    allow: Vec<Utf8PathBuf>,
+
+    /// Privilege escalation setting (resolved during defaults application)
+    #[serde(default, skip_serializing_if = "Privilege::is_inherit")]
+    privilege: Privilege,
+
+    /// Isolation setting (resolved during defaults application); must not be disabled
+    #[serde(default, skip_serializing_if = "TaskIsolation::is_inherit")]
+    isolation: TaskIsolation,
+
+    /// Network access (`None` inherits from isolation; resolved during defaults application)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    network: Option<bool>,
+
+    /// Labels matched by `apply --tags`/`--skip-tags`
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<String>>"))]
+    tags: Vec<String>,
+
+    /// Log a failure of the task and keep going instead of failing the phase
+    #[serde(default, skip_serializing_if = "crate::phase::is_false")]
+    ignore_errors: bool,
+
+    /// User-given name shown in logs and errors, and matched by `apply --start-at-task`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+impl OverlayTask {
+    /// Creates a new OverlayTask laying `source` over the rootfs root.
+    pub fn new(source: impl Into<Utf8PathBuf>) -> Self {
+        Self {
+            source: source.into(),
+            destination: default_destination(),
+            preserve_ownership: false,
+            xattrs: true,
+            allow: Vec::new(),
+            privilege: Privilege::default(),
+            isolation: TaskIsolation::default(),
+            network: None,
+            tags: Vec::new(),
+            ignore_errors: false,
+            name: None,
+        }
+    }
+
+    /// Sets the directory in the rootfs the overlay is laid over.
+    pub fn with_destination(mut self, destination: impl Into<Utf8PathBuf>) -> Self {
+        self.destination = destination.into();
+        self
+    }
+
+    /// Keeps the owners of the source entries.
+    pub fn with_preserve_ownership(mut self, preserve_ownership: bool) -> Self {
+        self.preserve_ownership = preserve_ownership;
+        self
+    }
+
+    /// Sets whether extended attributes are kept.
+    pub fn with_xattrs(mut self, xattrs: bool) -> Self {
+        self.xattrs = xattrs;
+        self
+    }
+
+    /// Sets the rootfs paths the overlay may write below.
+    pub fn with_allow<I, P>(mut self, allow: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<Utf8PathBuf>,
+    {
+        self.allow = allow.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the user-given task name.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets whether a failure of the task is logged instead of failing the phase.
+    pub fn with_ignore_errors(mut self, ignore_errors: bool) -> Self {
+        self.ignore_errors = ignore_errors;
+        self
+    }
+
+    /// Sets the labels matched by `apply --tags`/`--skip-tags`.
+    pub fn with_tags<I, S>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the privilege escalation setting.
+    pub fn with_privilege(mut self, privilege: Privilege) -> Self {
+        self.privilege = privilege;
+        self
+    }
+
+    /// Sets the isolation setting.
+    pub fn with_isolation(mut self, isolation: TaskIsolation) -> Self {
+        self.isolation = isolation;
+        self
+    }
+
+    /// Sets network access (by default it is inherited from the isolation config).
+    pub fn with_network(mut self, network: bool) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Returns the host directory or tarball.
+    pub fn source(&self) -> &Utf8Path {
+        &self.source
+    }
+
+    /// Returns the directory in the rootfs the overlay is laid over.
+    pub fn destination(&self) -> &Utf8Path {
+        &self.destination
+    }
+
+    /// Returns whether the owners of the source entries are kept.
+    pub fn preserve_ownership(&self) -> bool {
+        self.preserve_ownership
+    }
+
+    /// Returns whether extended attributes are kept.
+    pub fn xattrs(&self) -> bool {
+        self.xattrs
+    }
+
+    /// Returns the rootfs paths the overlay may write below.
+    pub fn allow(&self) -> &[Utf8PathBuf] {
+        &self.allow
+    }
+
+    /// Returns whether `source` is a tarball rather than a directory.
+    pub fn is_archive(&self) -> bool {
+        !self.source.is_dir()
+    }
+
+    /// Returns whether the overlay is staged as a tarball: an archive `source`, or a
+    /// directory packed to keep its extended attributes.
+    fn stages_archive(&self) -> bool {
+        self.xattrs || self.is_archive()
+    }
+
+    /// Returns a human-readable name for this task (without type prefix).
+    pub fn name(&self) -> &str {
+        self.source.as_str()
+    }
+
+    /// Resolves a relative `source` relative to the given base directory.
+    pub fn resolve_paths(&mut self, base_dir: &Utf8Path) {
+        if self.source.is_relative() {
+            self.source = base_dir.join(&self.source);
+        }
+    }
+
+    /// Resolves the privilege setting against profile defaults.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `privilege: true` is specified
+    /// but no `defaults.privilege.method` is configured in the profile.
+    pub fn resolve_privilege(
+        &mut self,
+        defaults: Option<&PrivilegeDefaults>,
+    ) -> Result<(), RsdebstrapError> {
+        self.privilege.resolve_in_place(defaults)
+    }
+
+    /// Returns the resolved privilege method.
+    ///
+    /// Should only be called after [`resolve_privilege()`](Self::resolve_privilege).
+    pub fn resolved_privilege_method(&self) -> Option<PrivilegeMethod> {
+        self.privilege.resolved_method()
+    }
+
+    /// Returns a reference to the task's isolation setting.
+    pub fn task_isolation(&self) -> &TaskIsolation {
+        &self.isolation
+    }
+
+    /// Resolves the isolation setting against profile defaults.
+    pub fn resolve_isolation(&mut self, defaults: &IsolationConfig) {
+        self.isolation.resolve_in_place(defaults);
+    }
+
+    /// Returns the resolved isolation config.
+    ///
+    /// Should only be called after [`resolve_isolation()`](Self::resolve_isolation).
+    pub fn resolved_isolation_config(&self) -> Option<&IsolationConfig> {
+        self.isolation.resolved_config()
+    }
+
+    /// Resolves the network setting against the isolation config and `offline`.
+    ///
+    /// Should be called after [`resolve_isolation()`](Self::resolve_isolation).
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `offline` is set and the task or its
+    /// isolation config explicitly enables the network.
+    pub fn resolve_network(&mut self, offline: bool) -> Result<(), RsdebstrapError> {
+        self.network =
+            crate::phase::resolve_network(self.network, self.isolation.resolved_config(), offline)?;
+        Ok(())
+    }
+
+    /// Returns whether the task may use the network (default: true).
+    pub fn network_enabled(&self) -> bool {
+        self.network.unwrap_or(true)
+    }
+
+    /// Returns the task's tags.
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Returns whether a failure of the task is logged instead of failing the phase.
+    pub fn ignore_errors(&self) -> bool {
+        self.ignore_errors
+    }
+
+    /// Returns the user-given `name`, if any.
+    pub fn configured_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the entries the overlay at `copy` (`source` or its staged copy) writes,
+    /// relative to `destination`: its directory tree, or the members of the tarball.
+    pub fn entries(&self, copy: &Utf8Path) -> Result<Vec<OverlayEntry>, RsdebstrapError> {
+        if !copy.is_dir() {
+            return archive_entries(copy);
+        }
+        Ok(crate::phase::walk_host_tree(copy, "overlay")?
+            .into_iter()
+            .map(|path| {
+                let is_dir = copy.join(&path).is_dir();
+                OverlayEntry { path, is_dir }
+            })
+            .collect())
+    }
+
+    /// Checks every entry against the `allow` list.
+    ///
+    /// An entry is allowed if its rootfs path is an allowed path or lies below one; a
+    /// directory is also allowed if an allowed path lies below it, since it has to be
+    /// created on the way there.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RsdebstrapError::Validation`] naming the first entry outside the list.
+    pub fn check_allowed(&self, entries: &[OverlayEntry]) -> Result<(), RsdebstrapError> {
+        if self.allow.is_empty() {
+            return Ok(());
+        }
+        for entry in entries {
+            let target = self.destination.join(&entry.path);
+            let allowed = self.allow.iter().any(|allowed| {
+                target.starts_with(allowed) || (entry.is_dir && allowed.starts_with(&target))
+            });
+            if !allowed {
+                return Err(RsdebstrapError::Validation(format!(
+                    "overlay {} writes {}, which is not below any path in 'allow'",
+                    self.source, target
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates the task configuration.
+    ///
+    /// Checks:
+    /// - isolation is not disabled (the host would be overwritten)
+    /// - `source` contains no `..` and is a tarball or a directory without symlinks or
+    ///   special files
+    /// - `destination` and the `allow` paths are absolute, without `..`
+    /// - every entry the overlay writes is allowed (see [`check_allowed`](Self::check_allowed))
+    /// - `name` and `tags` as for other provision tasks
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        crate::phase::validate_task_name(self.name.as_deref())?;
+        crate::phase::validate_task_tags(&self.tags)?;
+
+        if self.isolation == TaskIsolation::Disabled {
+            return Err(RsdebstrapError::Validation(
+                "overlay task requires isolation (isolation: false would write to the host)"
+                    .to_string(),
+            ));
+        }
+        crate::phase::validate_no_parent_dirs(&self.source, "overlay source")?;
+        if self.is_archive() {
+            crate::phase::validate_host_file_exists(&self.source, "overlay source")?;
+        }
+        for (label, path) in std::iter::once(("overlay destination", &self.destination))
+            .chain(self.allow.iter().map(|path| ("overlay allow", path)))
+        {
+            if !path.is_absolute() || path.as_str().contains(['\0', '\n', '\r']) {
+                return Err(RsdebstrapError::Validation(format!(
+                    "{} path {:?} must be absolute, without null or newline characters",
+                    label, path
+                )));
+            }
+            crate::phase::validate_no_parent_dirs(path, label)?;
+        }
+        self.check_allowed(&self.entries(&self.source)?)
+    }
+
+    /// Returns the shell script piped into `/bin/sh` inside the rootfs, which lays the
+    /// overlay staged at `staged` (a path inside the isolation) over `destination`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory `source` cannot be read.
+    pub fn script(&self, staged: &str) -> Result<String, RsdebstrapError> {
+        let destination = sh_quote(self.destination.as_str());
+        let mut script = format!("set -eu\nmkdir -p {}\n", destination);
+        if self.stages_archive() {
+            let mut options = vec![if self.preserve_ownership {
+                "--same-owner"
+            } else {
+                "--no-same-owner"
+            }];
+            if !self.is_archive() {
+                // The host packed the directory: its owners are the host's numeric IDs.
+                options.push("--numeric-owner");
+            }
+            if self.xattrs {
+                options.extend(["--xattrs", "--xattrs-include='*'", "--acls"]);
+            }
+            // --keep-directory-symlink keeps merged-/usr links such as /lib intact.
+            script.push_str(&format!(
+                "tar --extract --file {} --directory {} --preserve-permissions {} \
+                --keep-directory-symlink\n",
+                sh_quote(staged),
+                destination,
+                options.join(" ")
+            ));
+            return Ok(script);
+        }
+
+        let entries = crate::phase::walk_host_tree(&self.source, "overlay")?;
+        let top_level: Vec<String> = entries
+            .iter()
+            .filter(|path| path.components().count() == 1)
+            .map(|path| sh_quote(&format!("{}/{}", staged, path)))
+            .collect();
+        if top_level.is_empty() {
+            return Ok(script);
+        }
+        script.push_str(&format!(
+            "cp -R --preserve=mode,timestamps {} {}/\n",
+            top_level.join(" "),
+            destination
+        ));
+        if self.preserve_ownership {
+            for path in &entries {
+                let host = self.source.join(path);
+                let metadata = fs::symlink_metadata(&host).map_err(|e| {
+                    RsdebstrapError::io(format!("failed to read metadata for {}", host), e)
+                })?;
+                script.push_str(&format!(
+                    "chown -h {}:{} {}\n",
+                    metadata.uid(),
+                    metadata.gid(),
+                    sh_quote(self.destination.join(path).as_str())
+                ));
+            }
+        }
+        Ok(script)
+    }
+
+    /// Executes the overlay using the provided isolation context.
+    ///
+    /// This method:
+    /// 1. Stages the tarball, the directory packed as a tarball (with `xattrs`) or the
+    ///    directory tree in the rootfs staging directory
+    /// 2. Checks the staged entries against `allow` again, so a source changed since
+    ///    validation cannot slip past it
+    /// 3. Runs `/bin/sh` via the isolation context with the generated script on its
+    ///    standard input, which extracts or copies the overlay into place
+    /// 4. Removes the staged copy, even on error
+    pub fn execute(&self, context: &dyn IsolationContext) -> Result<()> {
+        let rootfs = context.rootfs();
+        let dry_run = context.dry_run();
+
+        if !dry_run {
+            crate::phase::validate_staging_directory(rootfs, context.staging_dir())
+                .context("rootfs validation failed")?;
+        }
+
+        info!(
+            "laying overlay {} over {} (isolation: {})",
+            self.name(),
+            self.destination,
+            context.name()
+        );
+        debug!("rootfs: {}, dry_run: {}", rootfs, dry_run);
+
+        let staged_name = context.staging_name("overlay");
+        let (target, in_isolation) = crate::phase::staged_file_paths(context, &staged_name);
+        let mut staged = vec![in_isolation.clone()];
+        let _file_guard;
+        let _dir_guard;
+        if self.is_archive() {
+            _file_guard = TempFileGuard::new(target.clone(), dry_run);
+            crate::phase::prepare_files_with_toctou_check(context, || {
+                let source = ScriptSource::Script(self.source.clone());
+                crate::phase::prepare_source_file(&source, &target, 0o600, "overlay archive")
+            })?;
+        } else if self.xattrs {
+            _file_guard = TempFileGuard::new(target.clone(), dry_run);
+            crate::phase::prepare_files_with_toctou_check(context, || {
+                pack_directory(&self.source, &target)
+            })?;
+        } else {
+            _dir_guard = TempDirGuard::new(target.clone(), dry_run);
+            crate::phase::prepare_files_with_toctou_check(context, || {
+                staged =
+                    crate::phase::copy_host_tree(&self.source, &target, &in_isolation, "overlay")?;
+                Ok(())
+            })?;
+        }
+        let copy = if dry_run {
+            self.source.as_path()
+        } else {
+            &target
+        };
+        self.check_allowed(&self.entries(copy)?)?;
+
+        context.hand_over(&staged, self.privilege.resolved_method())?;
+
+        let script = self.script(&in_isolation)?;
+        let command = vec![SHELL.to_string(), "-s".to_string()];
+        let result = crate::phase::execute_in_context_with_stdin(
+            context,
+            &command,
+            "overlay",
+            self.privilege.resolved_method(),
+            Some(script.as_bytes()),
+        )?;
+        crate::phase::check_execution_result(&result, &command, context.name(), dry_run)?;
+
+        info!("overlay laid successfully");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustix::fs::XattrFlags;
+
+    #[test]
+    fn pack_directory_keeps_extended_attributes() {
+        let temp = tempfile::tempdir().unwrap();
+        let base = Utf8Path::from_path(temp.path()).unwrap();
+        let source = base.join("overlay");
+        fs::create_dir_all(source.join("usr/bin")).unwrap();
+        let ping = source.join("usr/bin/ping");
+        fs::write(&ping, "#!/bin/sh\n").unwrap();
+        if rustix::fs::lsetxattr(ping.as_str(), "user.origin", b"overlay", XattrFlags::empty())
+            .is_err()
+        {
+            eprintln!("skipping: {} does not support user xattrs", base);
+            return;
+        }
+
+        let archive = base.join("staged");
+        pack_directory(&source, &archive).unwrap();
+        assert_eq!(fs::metadata(&archive).unwrap().mode() & 0o777, 0o600);
+        let entries = archive_entries(&archive).unwrap();
+        assert_eq!(entries[0].path, "usr");
+
+        let target = base.join("target");
+        fs::create_dir(&target).unwrap();
+        let status = Command::new("tar")
+            .args([
+                "--extract",
+                "--file",
+                archive.as_str(),
+                "--directory",
+                target.as_str(),
+            ])
+            .args(["--xattrs", "--xattrs-include=*"])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let mut value = [0; 16];
+        let len =
+            rustix::fs::lgetxattr(target.join("usr/bin/ping").as_str(), "user.origin", &mut value)
+                .expect("the extracted file should keep its xattr");
+        assert_eq!(&value[..len], b"overlay");
+    }
+}
@@ -0,0 +1,524 @@
+//! Plugin task implementation.
+//!
+//! This module provides the `PluginTask` data structure and the host side of the
+//! plugin protocol, which lets a task type live outside rsdebstrap. A plugin is an
+//! executable named `rsdebstrap-plugin-<name>` (looked up in `PATH`, or given as
+//! `binary`) that speaks a small JSON protocol:
+//!
+//! - rsdebstrap runs `<plugin> <action>` on the host, writes one JSON request to its
+//!   standard input and reads one JSON response from its standard output; standard
+//!   error is passed through to the terminal
+//! - `validate` checks the task's `config` and answers `{"errors": [...]}` (an empty
+//!   or missing list means valid)
+//! - `plan` answers `{"command": [...], "stdin": "..."}`: the command rsdebstrap runs
+//!   inside the isolation, with the task's privilege, and the optional text piped into
+//!   it
+//!
+//! Both actions must be free of side effects: the plugin only describes the work, and
+//! rsdebstrap runs it like any other task (including dry-run, isolation and privilege
+//! handling). A plugin exiting non-zero, or printing anything but the response on
+//! standard output, fails the task.
+
+use std::fmt;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::config::IsolationConfig;
+use crate::error::RsdebstrapError;
+use crate::isolation::{IsolationContext, TaskIsolation};
+use crate::privilege::{Privilege, PrivilegeDefaults, PrivilegeMethod};
+
+/// Version of the JSON protocol sent in every request as `protocol`.
+pub const PLUGIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Prefix of the executable name a plugin is looked up by in `PATH`.
+pub const PLUGIN_EXECUTABLE_PREFIX: &str = "rsdebstrap-plugin-";
+
+/// A request of the plugin protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PluginAction {
+    /// Check the task's `config`.
+    Validate,
+    /// Describe the command to run inside the isolation.
+    Plan,
+}
+
+impl PluginAction {
+    /// Returns the action name passed as the plugin's first argument.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Validate => "validate",
+            Self::Plan => "plan",
+        }
+    }
+}
+
+impl fmt::Display for PluginAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// JSON request written to the plugin's standard input.
+#[derive(Debug, Serialize)]
+struct PluginRequest<'a> {
+    protocol: u32,
+    action: &'static str,
+    /// Display name of the task, for the plugin's messages.
+    task: &'a str,
+    config: &'a serde_json::Map<String, serde_json::Value>,
+    /// Directory of the profile, against which relative paths in `config` resolve.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    base_dir: Option<&'a Utf8Path>,
+    /// Rootfs the planned command will run against (`plan` only).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rootfs: Option<&'a Utf8Path>,
+    dry_run: bool,
+}
+
+/// Response to [`PluginAction::Validate`].
+#[derive(Debug, Deserialize)]
+struct ValidateResponse {
+    #[serde(default)]
+    errors: Vec<String>,
+}
+
+/// Response to [`PluginAction::Plan`]: what to run inside the isolation.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct PluginPlan {
+    /// Command and arguments, run inside the isolation.
+    pub command: Vec<String>,
+    /// Text piped into the command's standard input, if any.
+    #[serde(default)]
+    pub stdin: Option<String>,
+}
+
+/// Plugin task data and execution logic.
+///
+/// Hands its `config` to the plugin executable, which validates it and plans the
+/// command to run; rsdebstrap then runs that command inside the isolation like the
+/// built-in tasks do.
+///
+/// ## Lifecycle
+///
+/// The typical lifecycle when loaded from a YAML profile is:
+/// 1. **Deserialize** — construct from YAML via `serde`
+///    (or [`new()`](Self::new) for programmatic use)
+/// 2. [`resolve_paths()`](Self::resolve_paths) — record the profile directory and
+///    resolve a relative `binary`
+/// 3. [`validate()`](Self::validate) — find the plugin and let it check `config`
+/// 4. [`execute()`](Self::execute) — plan with the plugin and run within an
+///    isolation context
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct PluginTask {
+    /// Plugin name; the executable is `rsdebstrap-plugin-<plugin>` in `PATH`
+    #[serde(deserialize_with = "crate::de::string")]
+    plugin: String,
+
+    /// Host-side plugin executable, instead of looking it up in `PATH`
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_path",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<crate::schema::Utf8PathSchema>")
+    )]
+    binary: Option<Utf8PathBuf>,
+
+    /// Settings passed to the plugin as they are
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "serde_json::Map::is_empty"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<std::collections::BTreeMap<String, serde_json::Value>>")
+    )]
+    config: serde_json::Map<String, serde_json::Value>,
+
+    /// Privilege escalation setting (resolved during defaults application)
+    #[serde(default, skip_serializing_if = "Privilege::is_inherit")]
+    privilege: Privilege,
+
+    /// Isolation setting (resolved during defaults application)
+    #[serde(default, skip_serializing_if = "TaskIsolation::is_inherit")]
+    isolation: TaskIsolation,
+
+    /// Network access (`None` inherits from isolation; resolved during defaults application)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    network: Option<bool>,
+
+    /// Labels matched by `apply --tags`/`--skip-tags`
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<String>>"))]
+    tags: Vec<String>,
+
+    /// Log a failure of the task and keep going instead of failing the phase
+    #[serde(default, skip_serializing_if = "crate::phase::is_false")]
+    ignore_errors: bool,
+
+    /// User-given name shown in logs and errors, and matched by `apply --start-at-task`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+
+    /// Profile directory recorded by `resolve_paths()` and sent to the plugin.
+    #[serde(skip)]
+    base_dir: Option<Utf8PathBuf>,
+}
+
+impl PluginTask {
+    /// Creates a new PluginTask running the plugin `plugin` with an empty config.
+    pub fn new(plugin: impl Into<String>) -> Self {
+        Self {
+            plugin: plugin.into(),
+            binary: None,
+            config: serde_json::Map::new(),
+            privilege: Privilege::default(),
+            isolation: TaskIsolation::default(),
+            network: None,
+            tags: Vec::new(),
+            ignore_errors: false,
+            name: None,
+            base_dir: None,
+        }
+    }
+
+    /// Sets the host-side plugin executable.
+    pub fn with_binary(mut self, binary: impl Into<Utf8PathBuf>) -> Self {
+        self.binary = Some(binary.into());
+        self
+    }
+
+    /// Sets the config setting `key` passed to the plugin.
+    pub fn with_config(mut self, key: impl Into<String>, value: serde_json::Value) -> Self {
+        self.config.insert(key.into(), value);
+        self
+    }
+
+    /// Sets the user-given task name.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets whether a failure of the task is logged instead of failing the phase.
+    pub fn with_ignore_errors(mut self, ignore_errors: bool) -> Self {
+        self.ignore_errors = ignore_errors;
+        self
+    }
+
+    /// Sets the labels matched by `apply --tags`/`--skip-tags`.
+    pub fn with_tags<I, S>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the privilege escalation setting.
+    pub fn with_privilege(mut self, privilege: Privilege) -> Self {
+        self.privilege = privilege;
+        self
+    }
+
+    /// Sets the isolation setting.
+    pub fn with_isolation(mut self, isolation: TaskIsolation) -> Self {
+        self.isolation = isolation;
+        self
+    }
+
+    /// Sets network access (by default it is inherited from the isolation config).
+    pub fn with_network(mut self, network: bool) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Returns the plugin name.
+    pub fn plugin(&self) -> &str {
+        &self.plugin
+    }
+
+    /// Returns the configured plugin executable, if any.
+    pub fn binary(&self) -> Option<&Utf8Path> {
+        self.binary.as_deref()
+    }
+
+    /// Returns the settings passed to the plugin.
+    pub fn config(&self) -> &serde_json::Map<String, serde_json::Value> {
+        &self.config
+    }
+
+    /// Returns a human-readable name for this task (without type prefix): the plugin
+    /// name.
+    pub fn name(&self) -> &str {
+        &self.plugin
+    }
+
+    /// Records `base_dir` for the plugin and resolves a relative `binary` against it.
+    pub fn resolve_paths(&mut self, base_dir: &Utf8Path) {
+        if let Some(ref mut binary) = self.binary
+            && binary.is_relative()
+        {
+            *binary = base_dir.join(&*binary);
+        }
+        self.base_dir = Some(base_dir.to_path_buf());
+    }
+
+    /// Resolves the privilege setting against profile defaults.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `privilege: true` is specified
+    /// but no `defaults.privilege.method` is configured in the profile.
+    pub fn resolve_privilege(
+        &mut self,
+        defaults: Option<&PrivilegeDefaults>,
+    ) -> Result<(), RsdebstrapError> {
+        self.privilege.resolve_in_place(defaults)
+    }
+
+    /// Returns the resolved privilege method.
+    ///
+    /// Should only be called after [`resolve_privilege()`](Self::resolve_privilege).
+    pub fn resolved_privilege_method(&self) -> Option<PrivilegeMethod> {
+        self.privilege.resolved_method()
+    }
+
+    /// Returns a reference to the task's isolation setting.
+    pub fn task_isolation(&self) -> &TaskIsolation {
+        &self.isolation
+    }
+
+    /// Resolves the isolation setting against profile defaults.
+    pub fn resolve_isolation(&mut self, defaults: &IsolationConfig) {
+        self.isolation.resolve_in_place(defaults);
+    }
+
+    /// Returns the resolved isolation config.
+    ///
+    /// Should only be called after [`resolve_isolation()`](Self::resolve_isolation).
+    pub fn resolved_isolation_config(&self) -> Option<&IsolationConfig> {
+        self.isolation.resolved_config()
+    }
+
+    /// Resolves the network setting against the isolation config and `offline`.
+    ///
+    /// Should be called after [`resolve_isolation()`](Self::resolve_isolation).
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `offline` is set and the task or its
+    /// isolation config explicitly enables the network.
+    pub fn resolve_network(&mut self, offline: bool) -> Result<(), RsdebstrapError> {
+        self.network =
+            crate::phase::resolve_network(self.network, self.isolation.resolved_config(), offline)?;
+        Ok(())
+    }
+
+    /// Returns whether the task may use the network (default: true).
+    pub fn network_enabled(&self) -> bool {
+        self.network.unwrap_or(true)
+    }
+
+    /// Returns the task's tags.
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Returns whether a failure of the task is logged instead of failing the phase.
+    pub fn ignore_errors(&self) -> bool {
+        self.ignore_errors
+    }
+
+    /// Returns the user-given `name`, if any.
+    pub fn configured_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the plugin executable: `binary` if given, otherwise
+    /// `rsdebstrap-plugin-<plugin>` found in `PATH`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::CommandNotFound` if the plugin is not in `PATH`.
+    pub fn executable(&self) -> Result<Utf8PathBuf, RsdebstrapError> {
+        if let Some(binary) = &self.binary {
+            return Ok(binary.clone());
+        }
+        let command = format!("{}{}", PLUGIN_EXECUTABLE_PREFIX, self.plugin);
+        which::which(&command)
+            .ok()
+            .and_then(|path| Utf8PathBuf::from_path_buf(path).ok())
+            .ok_or_else(|| RsdebstrapError::command_not_found(command, "task plugin"))
+    }
+
+    /// Validates the task configuration.
+    ///
+    /// Checks:
+    /// - `plugin` consists of lowercase letters, digits, `_` and `-`
+    /// - the plugin executable exists (a `binary` must be a regular file)
+    /// - the plugin accepts `config` (its `validate` action reports no errors)
+    /// - `name` and `tags` as for other provision tasks
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        crate::phase::validate_task_name(self.name.as_deref())?;
+        crate::phase::validate_task_tags(&self.tags)?;
+
+        let valid_plugin = !self.plugin.is_empty()
+            && self
+                .plugin
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '-'));
+        if !valid_plugin {
+            return Err(RsdebstrapError::Validation(format!(
+                "plugin name {:?} must consist of lowercase letters, digits, '_' and '-'",
+                self.plugin
+            )));
+        }
+        if let Some(binary) = &self.binary {
+            crate::phase::validate_host_file_exists(binary, "plugin binary")?;
+        }
+
+        let response: ValidateResponse = self.call(PluginAction::Validate, None, false)?;
+        if !response.errors.is_empty() {
+            return Err(RsdebstrapError::Validation(format!(
+                "plugin '{}' rejected its config: {}",
+                self.plugin,
+                response.errors.join("; ")
+            )));
+        }
+        Ok(())
+    }
+
+    /// Asks the plugin for the command to run against `rootfs`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the plugin fails, answers something other than a plan, or
+    /// plans an empty command.
+    pub fn plan(&self, rootfs: &Utf8Path, dry_run: bool) -> Result<PluginPlan, RsdebstrapError> {
+        let plan: PluginPlan = self.call(PluginAction::Plan, Some(rootfs), dry_run)?;
+        if plan
+            .command
+            .first()
+            .is_none_or(|program| program.is_empty())
+        {
+            return Err(RsdebstrapError::Validation(format!(
+                "plugin '{}' planned an empty command",
+                self.plugin
+            )));
+        }
+        Ok(plan)
+    }
+
+    /// Runs the plugin's `action` and parses its JSON response.
+    fn call<T: serde::de::DeserializeOwned>(
+        &self,
+        action: PluginAction,
+        rootfs: Option<&Utf8Path>,
+        dry_run: bool,
+    ) -> Result<T, RsdebstrapError> {
+        let executable = self.executable()?;
+        let name = self.name.as_deref().unwrap_or(&self.plugin);
+        let request = serde_json::to_vec(&PluginRequest {
+            protocol: PLUGIN_PROTOCOL_VERSION,
+            action: action.as_str(),
+            task: name,
+            config: &self.config,
+            base_dir: self.base_dir.as_deref(),
+            rootfs,
+            dry_run,
+        })
+        .map_err(|e| {
+            RsdebstrapError::Config(format!("failed to serialize plugin request: {}", e))
+        })?;
+        let label = format!("{} {}", executable, action);
+        debug!("running plugin: {}", label);
+
+        let mut child = Command::new(executable.as_std_path())
+            .arg(action.as_str())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| RsdebstrapError::io(format!("failed to run plugin: {}", label), e))?;
+        let mut stdin = child.stdin.take().expect("plugin stdin is piped");
+        // Write on another thread so a plugin answering before reading cannot block us.
+        let writer = std::thread::spawn(move || stdin.write_all(&request));
+        let output = child
+            .wait_with_output()
+            .map_err(|e| RsdebstrapError::io(format!("failed to wait for plugin: {}", label), e))?;
+        let written = writer.join().expect("plugin stdin writer panicked");
+
+        if !output.status.success() {
+            return Err(RsdebstrapError::Execution {
+                command: label,
+                status: output.status.to_string(),
+                details: None,
+            });
+        }
+        // A plugin may exit without reading a request it does not need.
+        if let Err(e) = written
+            && e.kind() != std::io::ErrorKind::BrokenPipe
+        {
+            return Err(RsdebstrapError::io(
+                format!("failed to write plugin request: {}", label),
+                e,
+            ));
+        }
+        serde_json::from_slice(&output.stdout).map_err(|e| {
+            RsdebstrapError::Validation(format!(
+                "plugin '{}' answered {} with invalid JSON: {}",
+                self.plugin, action, e
+            ))
+        })
+    }
+
+    /// Plans the command with the plugin and runs it using the provided isolation
+    /// context.
+    ///
+    /// This method:
+    /// 1. Runs the plugin's `plan` action on the host
+    /// 2. Runs the planned command via the isolation context, with the planned
+    ///    `stdin` if any
+    /// 3. Returns an error if the process fails or exits without status
+    ///
+    /// The plugin is asked to plan in dry-run as well, so its answer is checked; only
+    /// the planned command is skipped, as the executor does for every task.
+    pub fn execute(&self, context: &dyn IsolationContext) -> Result<()> {
+        let dry_run = context.dry_run();
+
+        info!("running plugin task: {} (isolation: {})", self.plugin, context.name());
+        let plan = self.plan(context.rootfs(), dry_run)?;
+        debug!("rootfs: {}, plan: {:?}, dry_run: {}", context.rootfs(), plan.command, dry_run);
+
+        let result = crate::phase::execute_in_context_with_stdin(
+            context,
+            &plan.command,
+            "plugin task",
+            self.privilege.resolved_method(),
+            plan.stdin.as_deref().map(str::as_bytes),
+        )?;
+        crate::phase::check_execution_result(&result, &plan.command, context.name(), dry_run)?;
+
+        info!("plugin task completed successfully");
+        Ok(())
+    }
+}
@@ -0,0 +1,502 @@
+//! Puppet task implementation.
+//!
+//! This module provides the `PuppetTask` data structure and execution logic
+//! for running `puppet apply` within an isolation context. It handles:
+//! - Copying the manifest/module tree into the staging directory (rootfs /tmp by default)
+//! - Module path and Hiera configuration options
+//! - Security validation (path traversal, symlinks and special files in the tree)
+//! - RAII cleanup of the staged directory
+
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::config::{IsolationConfig, MountEntry};
+use crate::error::RsdebstrapError;
+use crate::isolation::{IsolationContext, TaskIsolation};
+use crate::phase::TempDirGuard;
+use crate::privilege::{Privilege, PrivilegeDefaults, PrivilegeMethod};
+
+fn default_manifest() -> Utf8PathBuf {
+    Utf8PathBuf::from("manifests/site.pp")
+}
+
+fn is_default_manifest(manifest: &Utf8PathBuf) -> bool {
+    *manifest == default_manifest()
+}
+
+fn default_puppet() -> String {
+    "puppet".to_string()
+}
+
+fn is_default_puppet(puppet: &String) -> bool {
+    *puppet == default_puppet()
+}
+
+/// Puppet task data and execution logic.
+///
+/// Copies a host Puppet tree (manifests, modules and Hiera data) into the rootfs
+/// staging directory and runs `puppet apply` against it. Puppet must already be
+/// installed in the rootfs, e.g. by an earlier shell task.
+///
+/// ## Lifecycle
+///
+/// The typical lifecycle when loaded from a YAML profile is:
+/// 1. **Deserialize** — construct from YAML via `serde`
+///    (or [`new()`](Self::new) for programmatic use)
+/// 2. [`resolve_paths()`](Self::resolve_paths) — resolve a relative `dir`
+/// 3. [`validate()`](Self::validate) — check the tree and the paths within it
+/// 4. [`execute()`](Self::execute) — run within an isolation context
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct PuppetTask {
+    /// Host directory copied into the rootfs (relative paths resolve against the profile)
+    #[cfg_attr(feature = "schema", schemars(with = "crate::schema::Utf8PathSchema"))]
+    dir: Utf8PathBuf,
+
+    /// Manifest file or directory within `dir` (default: manifests/site.pp)
+    #[serde(
+        default = "default_manifest",
+        skip_serializing_if = "is_default_manifest"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "crate::schema::Utf8PathSchema"))]
+    manifest: Utf8PathBuf,
+
+    /// Module directories within `dir`, passed as `--modulepath`
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<Vec<crate::schema::Utf8PathSchema>>")
+    )]
+    modulepath: Vec<Utf8PathBuf>,
+
+    /// Hiera configuration file within `dir`, passed as `--hiera_config`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<crate::schema::Utf8PathSchema>")
+    )]
+    hiera_config: Option<Utf8PathBuf>,
+
+    /// Puppet executable inside the rootfs (default: puppet, looked up on `PATH`)
+    #[serde(default = "default_puppet", skip_serializing_if = "is_default_puppet")]
+    puppet: String,
+
+    /// Privilege escalation setting (resolved during defaults application)
+    #[serde(default, skip_serializing_if = "Privilege::is_inherit")]
+    privilege: Privilege,
+
+    /// Isolation setting (resolved during defaults application)
+    #[serde(default, skip_serializing_if = "TaskIsolation::is_inherit")]
+    isolation: TaskIsolation,
+
+    /// Network access (`None` inherits from isolation; resolved during defaults application)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    network: Option<bool>,
+
+    /// User to run the task as inside the chroot (merged into the isolation's `user`)
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    user: Option<String>,
+
+    /// Group to run the task as, together with `user`
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    group: Option<String>,
+
+    /// Filesystems mounted into the rootfs for this task only
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<MountEntry>>"))]
+    mounts: Vec<MountEntry>,
+
+    /// Labels matched by `apply --tags`/`--skip-tags`
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<String>>"))]
+    tags: Vec<String>,
+
+    /// Log a failure of the task and keep going instead of failing the phase
+    #[serde(default, skip_serializing_if = "crate::phase::is_false")]
+    ignore_errors: bool,
+
+    /// User-given name shown in logs and errors, and matched by `apply --start-at-task`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+impl PuppetTask {
+    /// Creates a new PuppetTask applying `dir/manifests/site.pp`.
+    pub fn new(dir: impl Into<Utf8PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            manifest: default_manifest(),
+            modulepath: Vec::new(),
+            hiera_config: None,
+            puppet: default_puppet(),
+            privilege: Privilege::default(),
+            isolation: TaskIsolation::default(),
+            network: None,
+            user: None,
+            group: None,
+            mounts: Vec::new(),
+            tags: Vec::new(),
+            ignore_errors: false,
+            name: None,
+        }
+    }
+
+    /// Sets the manifest file or directory within `dir`.
+    pub fn with_manifest(mut self, manifest: impl Into<Utf8PathBuf>) -> Self {
+        self.manifest = manifest.into();
+        self
+    }
+
+    /// Sets the module directories within `dir`.
+    pub fn with_modulepath<I, P>(mut self, modulepath: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<Utf8PathBuf>,
+    {
+        self.modulepath = modulepath.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the Hiera configuration file within `dir`.
+    pub fn with_hiera_config(mut self, hiera_config: impl Into<Utf8PathBuf>) -> Self {
+        self.hiera_config = Some(hiera_config.into());
+        self
+    }
+
+    /// Sets the Puppet executable inside the rootfs.
+    pub fn with_puppet(mut self, puppet: impl Into<String>) -> Self {
+        self.puppet = puppet.into();
+        self
+    }
+
+    /// Sets the user-given task name.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets whether a failure of the task is logged instead of failing the phase.
+    pub fn with_ignore_errors(mut self, ignore_errors: bool) -> Self {
+        self.ignore_errors = ignore_errors;
+        self
+    }
+
+    /// Sets the labels matched by `apply --tags`/`--skip-tags`.
+    pub fn with_tags<I, S>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the privilege escalation setting.
+    pub fn with_privilege(mut self, privilege: Privilege) -> Self {
+        self.privilege = privilege;
+        self
+    }
+
+    /// Sets the isolation setting.
+    pub fn with_isolation(mut self, isolation: TaskIsolation) -> Self {
+        self.isolation = isolation;
+        self
+    }
+
+    /// Sets network access (by default it is inherited from the isolation config).
+    pub fn with_network(mut self, network: bool) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Sets the user (and optionally group) to run the task as inside the chroot.
+    pub fn with_user(mut self, user: impl Into<String>, group: Option<String>) -> Self {
+        self.user = Some(user.into());
+        self.group = group;
+        self
+    }
+
+    /// Sets the filesystems mounted into the rootfs for this task only.
+    pub fn with_mounts(mut self, mounts: Vec<MountEntry>) -> Self {
+        self.mounts = mounts;
+        self
+    }
+
+    /// Returns the host Puppet tree directory.
+    pub fn dir(&self) -> &Utf8Path {
+        &self.dir
+    }
+
+    /// Returns the manifest file or directory within `dir`.
+    pub fn manifest(&self) -> &Utf8Path {
+        &self.manifest
+    }
+
+    /// Returns the module directories within `dir`.
+    pub fn modulepath(&self) -> &[Utf8PathBuf] {
+        &self.modulepath
+    }
+
+    /// Returns the Hiera configuration file within `dir`, if any.
+    pub fn hiera_config(&self) -> Option<&Utf8Path> {
+        self.hiera_config.as_deref()
+    }
+
+    /// Returns the Puppet executable inside the rootfs.
+    pub fn puppet(&self) -> &str {
+        &self.puppet
+    }
+
+    /// Returns a human-readable name for this task (without type prefix).
+    pub fn name(&self) -> &str {
+        self.dir.as_str()
+    }
+
+    /// Resolves a relative `dir` relative to the given base directory.
+    pub fn resolve_paths(&mut self, base_dir: &Utf8Path) {
+        if self.dir.is_relative() {
+            self.dir = base_dir.join(&self.dir);
+        }
+    }
+
+    /// Resolves the privilege setting against profile defaults.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `privilege: true` is specified
+    /// but no `defaults.privilege.method` is configured in the profile.
+    pub fn resolve_privilege(
+        &mut self,
+        defaults: Option<&PrivilegeDefaults>,
+    ) -> Result<(), RsdebstrapError> {
+        self.privilege.resolve_in_place(defaults)
+    }
+
+    /// Returns the resolved privilege method.
+    ///
+    /// Should only be called after [`resolve_privilege()`](Self::resolve_privilege).
+    pub fn resolved_privilege_method(&self) -> Option<PrivilegeMethod> {
+        self.privilege.resolved_method()
+    }
+
+    /// Returns a reference to the task's isolation setting.
+    pub fn task_isolation(&self) -> &TaskIsolation {
+        &self.isolation
+    }
+
+    /// Resolves the isolation setting against profile defaults.
+    pub fn resolve_isolation(&mut self, defaults: &IsolationConfig) {
+        self.isolation.resolve_in_place(defaults);
+    }
+
+    /// Returns the resolved isolation config.
+    ///
+    /// Should only be called after [`resolve_isolation()`](Self::resolve_isolation).
+    pub fn resolved_isolation_config(&self) -> Option<&IsolationConfig> {
+        self.isolation.resolved_config()
+    }
+
+    /// Resolves the network setting against the isolation config and `offline`.
+    ///
+    /// Should be called after [`resolve_isolation()`](Self::resolve_isolation).
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `offline` is set and the task or its
+    /// isolation config explicitly enables the network.
+    pub fn resolve_network(&mut self, offline: bool) -> Result<(), RsdebstrapError> {
+        self.network =
+            crate::phase::resolve_network(self.network, self.isolation.resolved_config(), offline)?;
+        Ok(())
+    }
+
+    /// Merges `user`/`group` into the resolved chroot isolation's `user`.
+    ///
+    /// Should be called after [`resolve_isolation()`](Self::resolve_isolation).
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `group` is set without `user`, the task
+    /// runs without isolation, or the isolation config names a different user.
+    pub fn resolve_user(&mut self) -> Result<(), RsdebstrapError> {
+        crate::phase::resolve_task_user(
+            &mut self.isolation,
+            self.user.as_deref(),
+            self.group.as_deref(),
+        )
+    }
+
+    /// Returns whether the task may use the network (default: true).
+    pub fn network_enabled(&self) -> bool {
+        self.network.unwrap_or(true)
+    }
+
+    /// Returns the filesystems mounted into the rootfs for this task only.
+    pub fn mounts(&self) -> &[MountEntry] {
+        &self.mounts
+    }
+
+    /// Returns the task's tags.
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Returns whether a failure of the task is logged instead of failing the phase.
+    pub fn ignore_errors(&self) -> bool {
+        self.ignore_errors
+    }
+
+    /// Returns the user-given `name`, if any.
+    pub fn configured_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Validates the task configuration.
+    ///
+    /// Checks:
+    /// - `dir` has no `..` components and is a directory (not a symlink) holding only
+    ///   directories and regular files
+    /// - `manifest` is a file or directory, `modulepath` entries are directories and
+    ///   `hiera_config` is a file, each given relative to `dir` without `..`
+    /// - `puppet` is non-empty without whitespace
+    /// - `name`, `tags` and `mounts` as for other provision tasks
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        crate::phase::validate_task_name(self.name.as_deref())?;
+        crate::phase::validate_task_tags(&self.tags)?;
+        crate::phase::validate_task_mounts(&self.mounts)?;
+
+        crate::phase::validate_no_parent_dirs(&self.dir, "puppet")?;
+        crate::phase::walk_host_tree(&self.dir, "puppet")?;
+
+        self.validate_tree_path(&self.manifest, "manifest", |p| p.exists())?;
+        for module_dir in &self.modulepath {
+            self.validate_tree_path(module_dir, "modulepath entry", Utf8Path::is_dir)?;
+        }
+        if let Some(hiera_config) = &self.hiera_config {
+            self.validate_tree_path(hiera_config, "hiera_config", Utf8Path::is_file)?;
+        }
+
+        if self.puppet.is_empty() || self.puppet.contains(char::is_whitespace) {
+            return Err(RsdebstrapError::Validation(format!(
+                "puppet executable {:?} must be non-empty without whitespace",
+                self.puppet
+            )));
+        }
+        Ok(())
+    }
+
+    /// Validates that `path` is relative, free of `..`, and passes `check` within `dir`.
+    fn validate_tree_path(
+        &self,
+        path: &Utf8Path,
+        label: &str,
+        check: impl Fn(&Utf8Path) -> bool,
+    ) -> Result<(), RsdebstrapError> {
+        if path.as_str().is_empty() || path.is_absolute() {
+            return Err(RsdebstrapError::Validation(format!(
+                "puppet {} '{}' must be a path relative to {}",
+                label, path, self.dir
+            )));
+        }
+        crate::phase::validate_no_parent_dirs(path, &format!("puppet {}", label))?;
+        if !check(&self.dir.join(path)) {
+            return Err(RsdebstrapError::Validation(format!(
+                "puppet {} '{}' does not exist in {}",
+                label, path, self.dir
+            )));
+        }
+        Ok(())
+    }
+
+    /// Builds the `puppet apply` command for a tree staged at `staged` (as seen inside
+    /// the isolation).
+    pub fn command(&self, staged: &str) -> Vec<String> {
+        let mut command = vec![self.puppet.clone(), "apply".to_string()];
+        if !self.modulepath.is_empty() {
+            let dirs: Vec<String> = self
+                .modulepath
+                .iter()
+                .map(|d| format!("{}/{}", staged, d))
+                .collect();
+            command.push(format!("--modulepath={}", dirs.join(":")));
+        }
+        if let Some(hiera_config) = &self.hiera_config {
+            command.push(format!("--hiera_config={}/{}", staged, hiera_config));
+        }
+        command.push(format!("{}/{}", staged, self.manifest));
+        command
+    }
+
+    /// Executes `puppet apply` using the provided isolation context.
+    ///
+    /// This method:
+    /// 1. Validates the staging directory (`/tmp` by default) in rootfs (unless dry_run)
+    /// 2. Sets up an RAII guard removing the staged directory
+    /// 3. Re-validates the staging directory to mitigate TOCTOU race conditions,
+    ///    then copies `dir` into a fresh 0o700 directory there (unless dry_run)
+    /// 4. Hands the copied tree over to the isolation's task user, if any
+    /// 5. Runs `puppet apply` via the isolation context
+    /// 6. Returns an error if the process fails or exits without status
+    pub fn execute(&self, context: &dyn IsolationContext) -> Result<()> {
+        let rootfs = context.rootfs();
+        let dry_run = context.dry_run();
+
+        if !dry_run {
+            crate::phase::validate_staging_directory(rootfs, context.staging_dir())
+                .context("rootfs validation failed")?;
+        }
+
+        info!("running puppet manifest: {} (isolation: {})", self.name(), context.name());
+        debug!("rootfs: {}, manifest: {}, dry_run: {}", rootfs, self.manifest, dry_run);
+
+        let dir_name = context.staging_name("puppet");
+        let (target_dir, dir_in_isolation) = crate::phase::staged_file_paths(context, &dir_name);
+        let _guard = TempDirGuard::new(target_dir.clone(), dry_run);
+
+        let mut staged = vec![dir_in_isolation.clone()];
+        crate::phase::prepare_files_with_toctou_check(context, || {
+            staged =
+                crate::phase::copy_host_tree(&self.dir, &target_dir, &dir_in_isolation, "puppet")?;
+            Ok(())
+        })?;
+
+        context.hand_over(&staged, self.privilege.resolved_method())?;
+
+        let command = self.command(&dir_in_isolation);
+        let result = crate::phase::execute_in_context(
+            context,
+            &command,
+            "puppet",
+            self.privilege.resolved_method(),
+        )?;
+        crate::phase::check_execution_result(&result, &command, context.name(), dry_run)?;
+
+        info!("puppet manifest applied successfully");
+        Ok(())
+    }
+}
@@ -0,0 +1,535 @@
+//! Rsync task implementation.
+//!
+//! This module provides the `RsyncTask` data structure and execution logic for
+//! synchronizing a host directory into the rootfs, or a rootfs directory out to the
+//! host (to extract build artifacts), with `rsync`. Only changed files are copied,
+//! so large asset trees that rarely change cost little per build. It handles:
+//! - Validation of the paths and `exclude` patterns before anything runs
+//! - Checking the rootfs directory with `O_NOFOLLOW` so a symlink planted in the
+//!   rootfs cannot redirect the sync to the host
+//! - Running the host's `rsync` through the command executor, with privilege
+//!   escalation when configured
+//!
+//! The task runs on the host, outside any isolation: the rootfs may not have
+//! `rsync`, and the sync crosses the rootfs boundary by design.
+
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::config::IsolationConfig;
+use crate::error::RsdebstrapError;
+use crate::executor::CommandSpec;
+use crate::isolation::{IsolationContext, TaskIsolation};
+use crate::phase::assemble::dir_exists;
+use crate::privilege::{Privilege, PrivilegeDefaults, PrivilegeMethod};
+
+/// The isolation of every rsync task: it always runs on the host.
+static NO_ISOLATION: TaskIsolation = TaskIsolation::Disabled;
+
+/// Which way an rsync task copies.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum RsyncDirection {
+    /// From the host directory into the rootfs (default)
+    #[default]
+    In,
+    /// From the rootfs out to the host directory
+    Out,
+}
+
+impl RsyncDirection {
+    /// Returns true for the default direction, so it is left out of serialized profiles.
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Rsync task data and execution logic.
+///
+/// Runs `rsync --archive` from `host` to the rootfs directory `path` (or the other
+/// way with `direction: out`), creating the target directory if needed. `delete`
+/// removes target files missing from the source, and `exclude` patterns are passed
+/// to rsync as given. Files synced out of the rootfs belong to the user running
+/// rsync, and the sync stays on the rootfs filesystem (`--one-file-system`), so
+/// mounts such as `/proc` are skipped.
+///
+/// ## Lifecycle
+///
+/// The typical lifecycle when loaded from a YAML profile is:
+/// 1. **Deserialize** — construct from YAML via `serde`
+///    (or [`new()`](Self::new) for programmatic use)
+/// 2. [`resolve_paths()`](Self::resolve_paths) — resolve a relative `host` directory
+/// 3. [`validate()`](Self::validate) — check the paths and `exclude` patterns
+/// 4. [`execute()`](Self::execute) — run `rsync` on the host
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct RsyncTask {
+    /// Host directory (relative paths resolve against the profile)
+    #[serde(deserialize_with = "crate::de::path")]
+    #[cfg_attr(feature = "schema", schemars(with = "crate::schema::Utf8PathSchema"))]
+    host: Utf8PathBuf,
+
+    /// Absolute directory in the rootfs
+    #[serde(deserialize_with = "crate::de::path")]
+    #[cfg_attr(feature = "schema", schemars(with = "crate::schema::Utf8PathSchema"))]
+    path: Utf8PathBuf,
+
+    /// Copy into the rootfs (`in`, default) or out of it (`out`)
+    #[serde(default, skip_serializing_if = "RsyncDirection::is_default")]
+    direction: RsyncDirection,
+
+    /// Remove files in the target that are missing from the source
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    delete: bool,
+
+    /// rsync `--exclude` patterns, relative to the source directory
+    #[serde(
+        default,
+        deserialize_with = "crate::de::string_list",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<String>>"))]
+    exclude: Vec<String>,
+
+    /// Privilege escalation setting (resolved during defaults application)
+    #[serde(default, skip_serializing_if = "Privilege::is_inherit")]
+    privilege: Privilege,
+
+    /// Labels matched by `apply --tags`/`--skip-tags`
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<String>>"))]
+    tags: Vec<String>,
+
+    /// Log a failure of the task and keep going instead of failing the phase
+    #[serde(default, skip_serializing_if = "crate::phase::is_false")]
+    ignore_errors: bool,
+
+    /// User-given name shown in logs and errors, and matched by `apply --start-at-task`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+impl RsyncTask {
+    /// Creates a new RsyncTask copying the host directory `host` into the rootfs
+    /// directory `path`.
+    pub fn new(host: impl Into<Utf8PathBuf>, path: impl Into<Utf8PathBuf>) -> Self {
+        Self {
+            host: host.into(),
+            path: path.into(),
+            direction: RsyncDirection::default(),
+            delete: false,
+            exclude: Vec::new(),
+            privilege: Privilege::default(),
+            tags: Vec::new(),
+            ignore_errors: false,
+            name: None,
+        }
+    }
+
+    /// Sets which way the task copies.
+    pub fn with_direction(mut self, direction: RsyncDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Removes target files missing from the source.
+    pub fn with_delete(mut self, delete: bool) -> Self {
+        self.delete = delete;
+        self
+    }
+
+    /// Sets the rsync `--exclude` patterns.
+    pub fn with_exclude<I, S>(mut self, exclude: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.exclude = exclude.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the user-given task name.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets whether a failure of the task is logged instead of failing the phase.
+    pub fn with_ignore_errors(mut self, ignore_errors: bool) -> Self {
+        self.ignore_errors = ignore_errors;
+        self
+    }
+
+    /// Sets the labels matched by `apply --tags`/`--skip-tags`.
+    pub fn with_tags<I, S>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the privilege escalation setting.
+    pub fn with_privilege(mut self, privilege: Privilege) -> Self {
+        self.privilege = privilege;
+        self
+    }
+
+    /// Returns the host directory.
+    pub fn host(&self) -> &Utf8Path {
+        &self.host
+    }
+
+    /// Returns the directory in the rootfs.
+    pub fn path(&self) -> &Utf8Path {
+        &self.path
+    }
+
+    /// Returns which way the task copies.
+    pub fn direction(&self) -> RsyncDirection {
+        self.direction
+    }
+
+    /// Returns whether target files missing from the source are removed.
+    pub fn delete(&self) -> bool {
+        self.delete
+    }
+
+    /// Returns the rsync `--exclude` patterns.
+    pub fn exclude(&self) -> &[String] {
+        &self.exclude
+    }
+
+    /// Returns a human-readable name for this task (without type prefix), e.g.
+    /// `assets -> /srv/assets`.
+    pub fn name(&self) -> String {
+        match self.direction {
+            RsyncDirection::In => format!("{} -> {}", self.host, self.path),
+            RsyncDirection::Out => format!("{} -> {}", self.path, self.host),
+        }
+    }
+
+    /// Resolves a relative `host` directory relative to the given base directory.
+    pub fn resolve_paths(&mut self, base_dir: &Utf8Path) {
+        if self.host.is_relative() {
+            self.host = base_dir.join(&self.host);
+        }
+    }
+
+    /// Resolves the privilege setting against profile defaults.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `privilege: true` is specified
+    /// but no `defaults.privilege.method` is configured in the profile.
+    pub fn resolve_privilege(
+        &mut self,
+        defaults: Option<&PrivilegeDefaults>,
+    ) -> Result<(), RsdebstrapError> {
+        self.privilege.resolve_in_place(defaults)
+    }
+
+    /// Returns the resolved privilege method.
+    ///
+    /// Should only be called after [`resolve_privilege()`](Self::resolve_privilege).
+    pub fn resolved_privilege_method(&self) -> Option<PrivilegeMethod> {
+        self.privilege.resolved_method()
+    }
+
+    /// Returns the task's isolation setting, which is always disabled: the task runs
+    /// on the host.
+    pub fn task_isolation(&self) -> &TaskIsolation {
+        &NO_ISOLATION
+    }
+
+    /// Returns the resolved isolation config: none, as the task runs on the host.
+    pub fn resolved_isolation_config(&self) -> Option<&IsolationConfig> {
+        None
+    }
+
+    /// Returns the task's tags.
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Returns whether a failure of the task is logged instead of failing the phase.
+    pub fn ignore_errors(&self) -> bool {
+        self.ignore_errors
+    }
+
+    /// Returns the user-given `name`, if any.
+    pub fn configured_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Validates the task configuration.
+    ///
+    /// Checks:
+    /// - `host` contains no `..` and, when copying in, is an existing directory
+    /// - `path` is an absolute rootfs directory without `..`
+    /// - `exclude` patterns are non-empty single lines
+    /// - `name` and `tags` as for other provision tasks
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        crate::phase::validate_task_name(self.name.as_deref())?;
+        crate::phase::validate_task_tags(&self.tags)?;
+
+        if self.host.as_str().is_empty() {
+            return Err(RsdebstrapError::Validation(
+                "rsync task host directory must not be empty".to_string(),
+            ));
+        }
+        crate::phase::validate_no_parent_dirs(&self.host, "rsync host")?;
+        if self.direction == RsyncDirection::In && !self.host.is_dir() {
+            return Err(RsdebstrapError::Validation(format!(
+                "rsync task host directory {} does not exist or is not a directory",
+                self.host
+            )));
+        }
+        if !self.path.is_absolute() || self.path.as_str().contains(['\0', '\n', '\r']) {
+            return Err(RsdebstrapError::Validation(format!(
+                "rsync task path {:?} must be an absolute rootfs directory \
+                without null or newline characters",
+                self.path
+            )));
+        }
+        crate::phase::validate_no_parent_dirs(&self.path, "rsync")?;
+        for pattern in &self.exclude {
+            if pattern.trim().is_empty() || pattern.contains(['\0', '\n', '\r']) {
+                return Err(RsdebstrapError::Validation(format!(
+                    "rsync exclude pattern {:?} must be a non-empty single line",
+                    pattern
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the `rsync` arguments copying between `rootfs_dir` (the task's `path`
+    /// on the host) and the host directory.
+    pub fn args(&self, rootfs_dir: &Utf8Path) -> Vec<String> {
+        let mut args = vec!["--archive".to_string()];
+        if self.direction == RsyncDirection::Out {
+            args.extend(["--one-file-system", "--no-owner", "--no-group"].map(String::from));
+        }
+        if self.delete {
+            args.push("--delete".to_string());
+        }
+        for pattern in &self.exclude {
+            args.push(format!("--exclude={}", pattern));
+        }
+        // Trailing slashes copy the directories' contents rather than the directories.
+        let (source, target) = match self.direction {
+            RsyncDirection::In => (self.host.as_path(), rootfs_dir),
+            RsyncDirection::Out => (rootfs_dir, self.host.as_path()),
+        };
+        args.push("--".to_string());
+        args.push(format!("{}/", source.as_str().trim_end_matches('/')));
+        args.push(format!("{}/", target.as_str().trim_end_matches('/')));
+        args
+    }
+
+    /// Executes the sync with the context's command executor, on the host.
+    ///
+    /// This method:
+    /// 1. Opens the rootfs directory with `O_NOFOLLOW` on every component; copying in
+    ///    creates it with `mkdir -p` if missing, copying out requires it
+    /// 2. Creates the target directory if needed and runs `rsync`, with privilege
+    ///    escalation when configured
+    pub fn execute(&self, context: &dyn IsolationContext) -> Result<()> {
+        let rootfs = context.rootfs();
+        let executor = context.executor();
+        let privilege = self.privilege.resolved_method();
+        let relative = self.path.strip_prefix("/").unwrap_or(&self.path);
+        let rootfs_dir = rootfs.join(relative);
+
+        info!("syncing {}", self.name());
+        let exists = dir_exists(rootfs, relative)?;
+        let target = match self.direction {
+            RsyncDirection::In if exists => None,
+            RsyncDirection::In => Some(rootfs_dir.clone()),
+            RsyncDirection::Out if exists || context.dry_run() => Some(self.host.clone()),
+            RsyncDirection::Out => {
+                return Err(RsdebstrapError::Validation(format!(
+                    "rsync task path {} does not exist in the rootfs",
+                    self.path
+                ))
+                .into());
+            }
+        };
+        if let Some(target) = target {
+            executor.execute_checked(
+                &CommandSpec::new("mkdir", vec!["-p".to_string(), target.to_string()])
+                    .with_privilege(privilege),
+            )?;
+        }
+        executor.execute_checked(
+            &CommandSpec::new("rsync", self.args(&rootfs_dir)).with_privilege(privilege),
+        )?;
+
+        info!("rsync completed successfully");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::{CommandExecutor, ExecutionResult};
+    use std::sync::Mutex;
+
+    /// Records the commands without running them.
+    #[derive(Default)]
+    struct RecordingExecutor {
+        specs: Mutex<Vec<CommandSpec>>,
+    }
+
+    impl CommandExecutor for RecordingExecutor {
+        fn execute(&self, spec: &CommandSpec) -> anyhow::Result<ExecutionResult> {
+            self.specs.lock().unwrap().push(spec.clone());
+            Ok(ExecutionResult {
+                status: None,
+                details: None,
+            })
+        }
+    }
+
+    struct HostContext {
+        rootfs: Utf8PathBuf,
+        executor: RecordingExecutor,
+    }
+
+    impl IsolationContext for HostContext {
+        fn name(&self) -> &'static str {
+            "direct"
+        }
+
+        fn rootfs(&self) -> &Utf8Path {
+            &self.rootfs
+        }
+
+        fn dry_run(&self) -> bool {
+            false
+        }
+
+        fn executor(&self) -> &dyn CommandExecutor {
+            &self.executor
+        }
+
+        fn execute(
+            &self,
+            _command: &[String],
+            _privilege: Option<PrivilegeMethod>,
+        ) -> anyhow::Result<ExecutionResult> {
+            unimplemented!("rsync tasks run through the executor")
+        }
+
+        fn teardown(&mut self) -> anyhow::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn context(dir: &tempfile::TempDir) -> HostContext {
+        HostContext {
+            rootfs: Utf8PathBuf::from_path_buf(dir.path().to_path_buf()).unwrap(),
+            executor: RecordingExecutor::default(),
+        }
+    }
+
+    fn resolved(mut task: RsyncTask) -> RsyncTask {
+        task.resolve_privilege(None).unwrap();
+        task
+    }
+
+    fn commands(ctx: &HostContext) -> Vec<Vec<String>> {
+        ctx.executor
+            .specs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|spec| [vec![spec.command.clone()], spec.args.clone()].concat())
+            .collect()
+    }
+
+    #[test]
+    fn args_copy_directory_contents_in_either_direction() {
+        let task = RsyncTask::new("/assets/", "/srv/assets")
+            .with_delete(true)
+            .with_exclude(["*.tmp", "cache/"]);
+        assert_eq!(
+            task.args(Utf8Path::new("/rootfs/srv/assets")),
+            [
+                "--archive",
+                "--delete",
+                "--exclude=*.tmp",
+                "--exclude=cache/",
+                "--",
+                "/assets/",
+                "/rootfs/srv/assets/"
+            ]
+        );
+        let task = RsyncTask::new("/out", "/boot").with_direction(RsyncDirection::Out);
+        assert_eq!(
+            task.args(Utf8Path::new("/rootfs/boot")),
+            [
+                "--archive",
+                "--one-file-system",
+                "--no-owner",
+                "--no-group",
+                "--",
+                "/rootfs/boot/",
+                "/out/"
+            ]
+        );
+        assert_eq!(task.name(), "/boot -> /out");
+    }
+
+    #[test]
+    fn execute_creates_a_missing_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = context(&dir);
+        let task = resolved(RsyncTask::new("/assets", "/srv/assets"));
+        task.execute(&ctx).unwrap();
+        let target = ctx.rootfs.join("srv/assets");
+        assert_eq!(
+            commands(&ctx),
+            [
+                vec!["mkdir".to_string(), "-p".to_string(), target.to_string()],
+                [vec!["rsync".to_string()], task.args(&target)].concat(),
+            ]
+        );
+
+        std::fs::create_dir_all(&target).unwrap();
+        let ctx = context(&dir);
+        task.execute(&ctx).unwrap();
+        assert_eq!(commands(&ctx).len(), 1);
+        assert_eq!(commands(&ctx)[0][0], "rsync");
+    }
+
+    #[test]
+    fn execute_refuses_symlinks_and_missing_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let ctx = context(&dir);
+        std::os::unix::fs::symlink("/etc", ctx.rootfs.join("srv")).unwrap();
+        let err = resolved(RsyncTask::new("/assets", "/srv/assets"))
+            .execute(&ctx)
+            .unwrap_err();
+        assert!(err.to_string().contains("symlink"), "{err}");
+
+        let err = resolved(RsyncTask::new("/out", "/boot").with_direction(RsyncDirection::Out))
+            .execute(&ctx)
+            .unwrap_err();
+        assert!(err.to_string().contains("does not exist in the rootfs"), "{err}");
+        assert!(commands(&ctx).is_empty());
+    }
+}
@@ -10,17 +10,17 @@ use anyhow::{Context, Result};
 use camino::{Utf8Path, Utf8PathBuf};
 #[cfg(feature = "schema")]
 use schemars::{JsonSchema, Schema, SchemaGenerator};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 #[cfg(feature = "schema")]
 use std::borrow::Cow;
 use std::fs;
 use tracing::{debug, info};
 
-use crate::config::IsolationConfig;
+use crate::config::{IsolationConfig, MountEntry};
 use crate::error::RsdebstrapError;
 use crate::isolation::{IsolationContext, TaskIsolation};
 use crate::phase::{ScriptSource, TempFileGuard};
-use crate::privilege::{Privilege, PrivilegeDefaults};
+use crate::privilege::{Privilege, PrivilegeDefaults, PrivilegeMethod};
 
 /// Shell task data and execution logic.
 ///
@@ -47,11 +47,41 @@ pub struct ShellTask {
     /// Shell interpreter to use (default: /bin/sh)
     shell: String,
 
+    /// Arguments passed to the interpreter before the script (e.g. `-e`, `-o pipefail`)
+    shell_args: Vec<String>,
+
+    /// Arguments passed to the script
+    args: Vec<String>,
+
+    /// Content written to the script's standard input
+    stdin: Option<String>,
+
     /// Privilege escalation setting (resolved during defaults application)
     privilege: Privilege,
 
     /// Isolation setting (resolved during defaults application)
     isolation: TaskIsolation,
+
+    /// Network access (`None` inherits from isolation; resolved during defaults application)
+    network: Option<bool>,
+
+    /// User to run the task as inside the chroot (merged into the isolation's `user`)
+    user: Option<String>,
+
+    /// Group to run the task as, together with `user`
+    group: Option<String>,
+
+    /// Filesystems mounted into the rootfs for this task only
+    mounts: Vec<MountEntry>,
+
+    /// Labels matched by `apply --tags`/`--skip-tags`
+    tags: Vec<String>,
+
+    /// Log a failure of the task and keep going instead of failing the phase
+    ignore_errors: bool,
+
+    /// User-given name shown in logs and errors, and matched by `apply --start-at-task`
+    name: Option<String>,
 }
 
 fn default_shell() -> String {
@@ -60,8 +90,9 @@ fn default_shell() -> String {
 
 // Wire shape of a shell task.
 //
-// Single source of truth for the YAML shape, shared by both deserialization (via
-// `ShellTask`'s `Deserialize`) and schema generation (via `ShellTask`'s `JsonSchema`).
+// Single source of truth for the YAML shape, shared by deserialization (via `ShellTask`'s
+// `Deserialize`), serialization (via its `Serialize`) and schema generation (via its
+// `JsonSchema`).
 // `deny_unknown_fields` keeps typo'd keys rejected. The `script`/`content` mutual-exclusion is
 // enforced at runtime by `resolve_script_source`, and mirrored in the schema by the `oneOf`
 // below (exactly one of `script`/`content` must be set). Each branch also constrains the field
@@ -69,7 +100,7 @@ fn default_shell() -> String {
 // absent (`None`), so a bare `required` would diverge from deserialization for e.g.
 // `{ script: null, content: hi }`. Plain `//` (not `///`) so the note does not leak into the
 // schema's `description`.
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
 #[serde(deny_unknown_fields)]
 #[cfg_attr(feature = "schema", schemars(extend("oneOf" = serde_json::json!([
@@ -81,14 +112,68 @@ struct RawShellTask {
         feature = "schema",
         schemars(with = "Option<crate::schema::Utf8PathSchema>")
     )]
+    #[serde(skip_serializing_if = "Option::is_none")]
     script: Option<Utf8PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     content: Option<String>,
     #[serde(default = "default_shell")]
     shell: String,
-    #[serde(default)]
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<String>>"))]
+    shell_args: Vec<String>,
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<String>>"))]
+    args: Vec<String>,
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    stdin: Option<String>,
+    #[serde(default, skip_serializing_if = "Privilege::is_inherit")]
     privilege: Privilege,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "TaskIsolation::is_inherit")]
     isolation: TaskIsolation,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    network: Option<bool>,
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    user: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    group: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<MountEntry>>"))]
+    mounts: Vec<MountEntry>,
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<String>>"))]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "crate::phase::is_false")]
+    ignore_errors: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
 }
 
 impl<'de> Deserialize<'de> for ShellTask {
@@ -101,12 +186,49 @@ impl<'de> Deserialize<'de> for ShellTask {
         Ok(ShellTask {
             source,
             shell: raw.shell,
+            shell_args: raw.shell_args,
+            args: raw.args,
+            stdin: raw.stdin,
             privilege: raw.privilege,
             isolation: raw.isolation,
+            network: raw.network,
+            user: raw.user,
+            group: raw.group,
+            mounts: raw.mounts,
+            tags: raw.tags,
+            ignore_errors: raw.ignore_errors,
+            name: raw.name,
         })
     }
 }
 
+impl Serialize for ShellTask {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let (script, content) = crate::phase::split_script_source(&self.source);
+        RawShellTask {
+            script,
+            content,
+            shell: self.shell.clone(),
+            shell_args: self.shell_args.clone(),
+            args: self.args.clone(),
+            stdin: self.stdin.clone(),
+            privilege: self.privilege.clone(),
+            isolation: self.isolation.clone(),
+            network: self.network,
+            user: self.user.clone(),
+            group: self.group.clone(),
+            mounts: self.mounts.clone(),
+            tags: self.tags.clone(),
+            ignore_errors: self.ignore_errors,
+            name: self.name.clone(),
+        }
+        .serialize(serializer)
+    }
+}
+
 #[cfg(feature = "schema")]
 impl JsonSchema for ShellTask {
     fn schema_name() -> Cow<'static, str> {
@@ -127,8 +249,18 @@ impl ShellTask {
         Self {
             source,
             shell: default_shell(),
+            shell_args: Vec::new(),
+            args: Vec::new(),
+            stdin: None,
             privilege: Privilege::default(),
             isolation: TaskIsolation::default(),
+            network: None,
+            user: None,
+            group: None,
+            mounts: Vec::new(),
+            tags: Vec::new(),
+            ignore_errors: false,
+            name: None,
         }
     }
 
@@ -140,11 +272,100 @@ impl ShellTask {
         Self {
             source,
             shell: shell.into(),
+            shell_args: Vec::new(),
+            args: Vec::new(),
+            stdin: None,
             privilege: Privilege::default(),
             isolation: TaskIsolation::default(),
+            network: None,
+            user: None,
+            group: None,
+            mounts: Vec::new(),
+            tags: Vec::new(),
+            ignore_errors: false,
+            name: None,
         }
     }
 
+    /// Sets the arguments passed to the interpreter before the script.
+    pub fn with_shell_args<I, S>(mut self, shell_args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.shell_args = shell_args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the arguments passed to the script.
+    pub fn with_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args = args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the content written to the script's standard input.
+    pub fn with_stdin(mut self, stdin: impl Into<String>) -> Self {
+        self.stdin = Some(stdin.into());
+        self
+    }
+
+    /// Sets the user-given task name.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets whether a failure of the task is logged instead of failing the phase.
+    pub fn with_ignore_errors(mut self, ignore_errors: bool) -> Self {
+        self.ignore_errors = ignore_errors;
+        self
+    }
+
+    /// Sets the labels matched by `apply --tags`/`--skip-tags`.
+    pub fn with_tags<I, S>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the privilege escalation setting.
+    pub fn with_privilege(mut self, privilege: Privilege) -> Self {
+        self.privilege = privilege;
+        self
+    }
+
+    /// Sets the isolation setting.
+    pub fn with_isolation(mut self, isolation: TaskIsolation) -> Self {
+        self.isolation = isolation;
+        self
+    }
+
+    /// Sets network access (by default it is inherited from the isolation config).
+    pub fn with_network(mut self, network: bool) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Sets the user (and optionally group) to run the task as inside the chroot.
+    pub fn with_user(mut self, user: impl Into<String>, group: Option<String>) -> Self {
+        self.user = Some(user.into());
+        self.group = group;
+        self
+    }
+
+    /// Sets the filesystems mounted into the rootfs for this task only.
+    pub fn with_mounts(mut self, mounts: Vec<MountEntry>) -> Self {
+        self.mounts = mounts;
+        self
+    }
+
     /// Returns a reference to the script source.
     pub fn source(&self) -> &ScriptSource {
         &self.source
@@ -155,6 +376,21 @@ impl ShellTask {
         &self.shell
     }
 
+    /// Returns the arguments passed to the interpreter before the script.
+    pub fn shell_args(&self) -> &[String] {
+        &self.shell_args
+    }
+
+    /// Returns the arguments passed to the script.
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Returns the content written to the script's standard input, if any.
+    pub fn stdin(&self) -> Option<&str> {
+        self.stdin.as_deref()
+    }
+
     /// Returns a human-readable name for this task (without type prefix).
     pub fn name(&self) -> &str {
         self.source.name()
@@ -183,6 +419,13 @@ impl ShellTask {
         self.privilege.resolve_in_place(defaults)
     }
 
+    /// Returns the resolved privilege method.
+    ///
+    /// Should only be called after [`resolve_privilege()`](Self::resolve_privilege).
+    pub fn resolved_privilege_method(&self) -> Option<PrivilegeMethod> {
+        self.privilege.resolved_method()
+    }
+
     /// Returns a reference to the task's isolation setting.
     pub fn task_isolation(&self) -> &TaskIsolation {
         &self.isolation
@@ -200,6 +443,61 @@ impl ShellTask {
         self.isolation.resolved_config()
     }
 
+    /// Resolves the network setting against the isolation config and `offline`.
+    ///
+    /// Should be called after [`resolve_isolation()`](Self::resolve_isolation).
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `offline` is set and the task or its
+    /// isolation config explicitly enables the network.
+    pub fn resolve_network(&mut self, offline: bool) -> Result<(), RsdebstrapError> {
+        self.network =
+            crate::phase::resolve_network(self.network, self.isolation.resolved_config(), offline)?;
+        Ok(())
+    }
+
+    /// Merges `user`/`group` into the resolved chroot isolation's `user`.
+    ///
+    /// Should be called after [`resolve_isolation()`](Self::resolve_isolation).
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `group` is set without `user`, the task
+    /// runs without isolation, or the isolation config names a different user.
+    pub fn resolve_user(&mut self) -> Result<(), RsdebstrapError> {
+        crate::phase::resolve_task_user(
+            &mut self.isolation,
+            self.user.as_deref(),
+            self.group.as_deref(),
+        )
+    }
+
+    /// Returns whether the task may use the network (default: true).
+    pub fn network_enabled(&self) -> bool {
+        self.network.unwrap_or(true)
+    }
+
+    /// Returns the filesystems mounted into the rootfs for this task only.
+    pub fn mounts(&self) -> &[MountEntry] {
+        &self.mounts
+    }
+
+    /// Returns the task's tags.
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Returns whether a failure of the task is logged instead of failing the phase.
+    pub fn ignore_errors(&self) -> bool {
+        self.ignore_errors
+    }
+
+    /// Returns the user-given `name`, if any.
+    pub fn configured_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
     /// Validates the task configuration.
     ///
     /// Checks that the shell path is non-empty and absolute, then validates
@@ -208,11 +506,14 @@ impl ShellTask {
     ///   validates that the file exists and is a regular file.
     /// - For inline content: validates that the content is not empty or whitespace-only.
     ///
+    /// Then checks that `shell_args` and `args` contain no NUL bytes, and finally
+    /// the task's `name` and `tags`, then each of its `mounts` and their order.
+    ///
     /// # Errors
     ///
     /// Returns `RsdebstrapError::Validation` for constraint violations (empty shell,
     /// relative shell path, path traversal, non-file script, empty or whitespace-only
-    /// content) or `RsdebstrapError::Io` if the script file cannot be accessed.
+    /// content, NUL byte in an argument, blank name, invalid tag or mount entry) or `RsdebstrapError::Io` if the script file cannot be accessed.
     pub fn validate(&self) -> Result<(), RsdebstrapError> {
         if self.shell.is_empty() {
             return Err(RsdebstrapError::Validation("shell path must not be empty".to_string()));
@@ -224,7 +525,18 @@ impl ShellTask {
             )));
         }
 
-        self.source.validate("shell script")
+        self.source.validate("shell script")?;
+        for (label, args) in [("shell_args", &self.shell_args), ("args", &self.args)] {
+            if let Some(arg) = args.iter().find(|arg| arg.contains('\0')) {
+                return Err(RsdebstrapError::Validation(format!(
+                    "shell task {} entry {:?} must not contain NUL bytes",
+                    label, arg
+                )));
+            }
+        }
+        crate::phase::validate_task_name(self.name.as_deref())?;
+        crate::phase::validate_task_tags(&self.tags)?;
+        crate::phase::validate_task_mounts(&self.mounts)
     }
 
     /// Executes the shell script using the provided isolation context.
@@ -235,10 +547,13 @@ impl ShellTask {
     /// This method:
     /// 1. Validates the rootfs (unless dry_run)
     /// 2. Sets up an RAII guard for cleanup of the temp script file
-    /// 3. Re-validates /tmp to mitigate TOCTOU race conditions (unless dry_run)
-    /// 4. Copies or writes the script to rootfs /tmp
-    /// 5. Executes the script via the isolation context
-    /// 6. Returns an error if the process fails or exits without status
+    /// 3. Re-validates the staging directory to mitigate TOCTOU race conditions
+    ///    (unless dry_run)
+    /// 4. Copies or writes the script to the context's staging directory (`/tmp`
+    ///    by default)
+    /// 5. Hands the script over to the isolation's task user, if any
+    /// 6. Executes the script via the isolation context, feeding it `stdin` if set
+    /// 7. Returns an error if the process fails or exits without status
     ///
     /// In dry-run mode, skips file I/O (rootfs validation, script copy/write,
     /// permission changes, cleanup) while still constructing and delegating
@@ -248,29 +563,37 @@ impl ShellTask {
         let dry_run = context.dry_run();
 
         if !dry_run {
-            self.validate_rootfs(rootfs)
+            self.validate_rootfs(rootfs, context.staging_dir())
                 .context("rootfs validation failed")?;
         }
 
         info!("running shell script: {} (isolation: {})", self.name(), context.name());
         debug!("rootfs: {}, shell: {}, dry_run: {}", rootfs, self.shell, dry_run);
 
-        let script_name = format!("task-{}.sh", uuid::Uuid::new_v4());
-        let target_script = rootfs.join("tmp").join(&script_name);
+        let script_name = format!("{}.sh", context.staging_name("task"));
+        let (target_script, script_path_in_isolation) =
+            crate::phase::staged_file_paths(context, &script_name);
         let _guard = TempFileGuard::new(target_script.clone(), dry_run);
 
-        crate::phase::prepare_files_with_toctou_check(rootfs, dry_run, || {
+        crate::phase::prepare_files_with_toctou_check(context, || {
             crate::phase::prepare_source_file(&self.source, &target_script, 0o700, "script")
         })?;
 
-        let script_path_in_isolation = format!("/tmp/{}", script_name);
-        let command: Vec<String> = vec![self.shell.clone(), script_path_in_isolation];
+        context.hand_over(
+            std::slice::from_ref(&script_path_in_isolation),
+            self.privilege.resolved_method(),
+        )?;
+        let mut command: Vec<String> = vec![self.shell.clone()];
+        command.extend(self.shell_args.iter().cloned());
+        command.push(script_path_in_isolation);
+        command.extend(self.args.iter().cloned());
 
-        let result = crate::phase::execute_in_context(
+        let result = crate::phase::execute_in_context_with_stdin(
             context,
             &command,
             "script",
             self.privilege.resolved_method(),
+            self.stdin.as_deref().map(str::as_bytes),
         )?;
         crate::phase::check_execution_result(&result, &command, context.name(), dry_run)?;
 
@@ -279,8 +602,8 @@ impl ShellTask {
     }
 
     /// Validates that the rootfs is ready for isolated command execution.
-    fn validate_rootfs(&self, rootfs: &Utf8Path) -> Result<()> {
-        crate::phase::validate_tmp_directory(rootfs)?;
+    fn validate_rootfs(&self, rootfs: &Utf8Path, staging_dir: &Utf8Path) -> Result<()> {
+        crate::phase::validate_staging_directory(rootfs, staging_dir)?;
 
         // Validate shell path to prevent path traversal attacks
         let shell_path = self.shell.trim_start_matches('/');
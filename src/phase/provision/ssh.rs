@@ -0,0 +1,507 @@
+//! SSH task implementation.
+//!
+//! This module provides the `SshTask` data structure and execution logic for
+//! configuring the OpenSSH server of the rootfs declaratively. It handles:
+//! - Drop-in `sshd_config.d` snippets
+//! - `authorized_keys` for rootfs users
+//! - Keeping, regenerating or removing the host keys (e.g. for cloned images)
+//!
+//! The changes are made by a generated POSIX shell script piped into `/bin/sh`
+//! inside the isolation, so user names and home directories resolve against the
+//! rootfs's own `/etc/passwd`.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use camino::Utf8Path;
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::config::IsolationConfig;
+use crate::error::RsdebstrapError;
+use crate::isolation::{IsolationContext, TaskIsolation};
+use crate::phase::sh_quote;
+use crate::privilege::{Privilege, PrivilegeDefaults, PrivilegeMethod};
+
+/// Shell reading the generated script from its standard input.
+const SHELL: &str = "/bin/sh";
+
+/// Directory inside the rootfs holding the drop-in `sshd_config` snippets.
+const SSHD_CONFIG_DIR: &str = "/etc/ssh/sshd_config.d";
+
+/// Key type prefixes accepted in an `authorized_keys` entry.
+const KEY_TYPE_PREFIXES: [&str; 3] = ["ssh-", "ecdsa-sha2-", "sk-"];
+
+/// What to do with the rootfs's SSH host keys.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum SshHostKeys {
+    /// Leave the host keys generated by the openssh-server package untouched.
+    #[default]
+    Keep,
+    /// Replace the host keys with freshly generated ones (`ssh-keygen -A`).
+    Regenerate,
+    /// Delete the host keys so every clone of the image generates its own.
+    Remove,
+}
+
+impl SshHostKeys {
+    fn is_keep(&self) -> bool {
+        *self == Self::Keep
+    }
+}
+
+/// Returns the file name of the drop-in snippet `name`: `name` with `.conf` appended
+/// unless it already ends with it (sshd only includes `*.conf`).
+fn snippet_file(name: &str) -> String {
+    if name.ends_with(".conf") {
+        name.to_string()
+    } else {
+        format!("{}.conf", name)
+    }
+}
+
+/// SSH task data and execution logic.
+///
+/// Writes drop-in snippets to `/etc/ssh/sshd_config.d` (as `<name>.conf`, mode 0644),
+/// replaces the `~/.ssh/authorized_keys` of each listed user with the given keys
+/// (`~/.ssh` mode 0700 and the file mode 0600, both owned by the user), and then keeps,
+/// regenerates or removes the host keys.
+///
+/// ## Lifecycle
+///
+/// The typical lifecycle when loaded from a YAML profile is:
+/// 1. **Deserialize** — construct from YAML via `serde`
+///    (or [`new()`](Self::new) for programmatic use)
+/// 2. [`validate()`](Self::validate) — check snippet names, users and keys
+/// 3. [`execute()`](Self::execute) — run within an isolation context
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct SshTask {
+    /// Drop-in `sshd_config` snippets keyed by name (written as `<name>.conf`)
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<BTreeMap<String, String>>")
+    )]
+    config: BTreeMap<String, String>,
+
+    /// Public keys keyed by rootfs user; each user's `authorized_keys` is replaced
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<BTreeMap<String, Vec<String>>>")
+    )]
+    authorized_keys: BTreeMap<String, Vec<String>>,
+
+    /// What to do with the host keys: keep (default), regenerate or remove
+    #[serde(default, skip_serializing_if = "SshHostKeys::is_keep")]
+    host_keys: SshHostKeys,
+
+    /// Privilege escalation setting (resolved during defaults application)
+    #[serde(default, skip_serializing_if = "Privilege::is_inherit")]
+    privilege: Privilege,
+
+    /// Isolation setting (resolved during defaults application); must not be disabled
+    #[serde(default, skip_serializing_if = "TaskIsolation::is_inherit")]
+    isolation: TaskIsolation,
+
+    /// Network access (`None` inherits from isolation; resolved during defaults application)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    network: Option<bool>,
+
+    /// Labels matched by `apply --tags`/`--skip-tags`
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<String>>"))]
+    tags: Vec<String>,
+
+    /// Log a failure of the task and keep going instead of failing the phase
+    #[serde(default, skip_serializing_if = "crate::phase::is_false")]
+    ignore_errors: bool,
+
+    /// User-given name shown in logs and errors, and matched by `apply --start-at-task`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+impl SshTask {
+    /// Creates a new SshTask that changes nothing.
+    ///
+    /// Note: add snippets, keys or a host key action and call
+    /// [`validate()`](Self::validate) before executing it.
+    pub fn new() -> Self {
+        Self {
+            config: BTreeMap::new(),
+            authorized_keys: BTreeMap::new(),
+            host_keys: SshHostKeys::default(),
+            privilege: Privilege::default(),
+            isolation: TaskIsolation::default(),
+            network: None,
+            tags: Vec::new(),
+            ignore_errors: false,
+            name: None,
+        }
+    }
+
+    /// Adds the drop-in snippet `name` with `content`.
+    pub fn with_config(mut self, name: impl Into<String>, content: impl Into<String>) -> Self {
+        self.config.insert(name.into(), content.into());
+        self
+    }
+
+    /// Sets the public keys authorized for `user`.
+    pub fn with_authorized_keys<I, S>(mut self, user: impl Into<String>, keys: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.authorized_keys
+            .insert(user.into(), keys.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Sets what to do with the host keys.
+    pub fn with_host_keys(mut self, host_keys: SshHostKeys) -> Self {
+        self.host_keys = host_keys;
+        self
+    }
+
+    /// Sets the user-given task name.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets whether a failure of the task is logged instead of failing the phase.
+    pub fn with_ignore_errors(mut self, ignore_errors: bool) -> Self {
+        self.ignore_errors = ignore_errors;
+        self
+    }
+
+    /// Sets the labels matched by `apply --tags`/`--skip-tags`.
+    pub fn with_tags<I, S>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the privilege escalation setting.
+    pub fn with_privilege(mut self, privilege: Privilege) -> Self {
+        self.privilege = privilege;
+        self
+    }
+
+    /// Sets the isolation setting.
+    pub fn with_isolation(mut self, isolation: TaskIsolation) -> Self {
+        self.isolation = isolation;
+        self
+    }
+
+    /// Sets network access (by default it is inherited from the isolation config).
+    pub fn with_network(mut self, network: bool) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Returns the drop-in snippets keyed by name.
+    pub fn config(&self) -> &BTreeMap<String, String> {
+        &self.config
+    }
+
+    /// Returns the public keys keyed by user.
+    pub fn authorized_keys(&self) -> &BTreeMap<String, Vec<String>> {
+        &self.authorized_keys
+    }
+
+    /// Returns what to do with the host keys.
+    pub fn host_keys(&self) -> SshHostKeys {
+        self.host_keys
+    }
+
+    /// Returns a human-readable name for this task (without type prefix): the snippet
+    /// names, the users given keys and the host key action, as applicable.
+    pub fn name(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.config.is_empty() {
+            let names: Vec<&str> = self.config.keys().map(String::as_str).collect();
+            parts.push(format!("config {}", names.join(",")));
+        }
+        if !self.authorized_keys.is_empty() {
+            let users: Vec<&str> = self.authorized_keys.keys().map(String::as_str).collect();
+            parts.push(format!("keys {}", users.join(",")));
+        }
+        match self.host_keys {
+            SshHostKeys::Keep => {}
+            SshHostKeys::Regenerate => parts.push("regenerate host keys".to_string()),
+            SshHostKeys::Remove => parts.push("remove host keys".to_string()),
+        }
+        format!("<{}>", parts.join("; "))
+    }
+
+    /// Resolves relative paths; an SSH task has none, so this does nothing.
+    pub fn resolve_paths(&mut self, _base_dir: &Utf8Path) {}
+
+    /// Resolves the privilege setting against profile defaults.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `privilege: true` is specified
+    /// but no `defaults.privilege.method` is configured in the profile.
+    pub fn resolve_privilege(
+        &mut self,
+        defaults: Option<&PrivilegeDefaults>,
+    ) -> Result<(), RsdebstrapError> {
+        self.privilege.resolve_in_place(defaults)
+    }
+
+    /// Returns the resolved privilege method.
+    ///
+    /// Should only be called after [`resolve_privilege()`](Self::resolve_privilege).
+    pub fn resolved_privilege_method(&self) -> Option<PrivilegeMethod> {
+        self.privilege.resolved_method()
+    }
+
+    /// Returns a reference to the task's isolation setting.
+    pub fn task_isolation(&self) -> &TaskIsolation {
+        &self.isolation
+    }
+
+    /// Resolves the isolation setting against profile defaults.
+    pub fn resolve_isolation(&mut self, defaults: &IsolationConfig) {
+        self.isolation.resolve_in_place(defaults);
+    }
+
+    /// Returns the resolved isolation config.
+    ///
+    /// Should only be called after [`resolve_isolation()`](Self::resolve_isolation).
+    pub fn resolved_isolation_config(&self) -> Option<&IsolationConfig> {
+        self.isolation.resolved_config()
+    }
+
+    /// Resolves the network setting against the isolation config and `offline`.
+    ///
+    /// Should be called after [`resolve_isolation()`](Self::resolve_isolation).
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `offline` is set and the task or its
+    /// isolation config explicitly enables the network.
+    pub fn resolve_network(&mut self, offline: bool) -> Result<(), RsdebstrapError> {
+        self.network =
+            crate::phase::resolve_network(self.network, self.isolation.resolved_config(), offline)?;
+        Ok(())
+    }
+
+    /// Returns whether the task may use the network (default: true).
+    pub fn network_enabled(&self) -> bool {
+        self.network.unwrap_or(true)
+    }
+
+    /// Returns the task's tags.
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Returns whether a failure of the task is logged instead of failing the phase.
+    pub fn ignore_errors(&self) -> bool {
+        self.ignore_errors
+    }
+
+    /// Returns the user-given `name`, if any.
+    pub fn configured_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Validates the task configuration.
+    ///
+    /// Checks:
+    /// - at least one of `config`, `authorized_keys` and a `host_keys` action other than
+    ///   `keep` is given, and isolation is not disabled (the host's sshd would be changed)
+    /// - snippet names are file names of letters, digits, `.`, `_` and `-` not starting
+    ///   with `.` or `-`, and snippets contain no NUL byte
+    /// - users are names or numeric ids not starting with `-`, each with at least one
+    ///   key, and every key is a single line naming an OpenSSH key type
+    /// - `name` and `tags` as for other provision tasks
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        crate::phase::validate_task_name(self.name.as_deref())?;
+        crate::phase::validate_task_tags(&self.tags)?;
+
+        if self.config.is_empty() && self.authorized_keys.is_empty() && self.host_keys.is_keep() {
+            return Err(RsdebstrapError::Validation(
+                "ssh task requires 'config', 'authorized_keys' or a 'host_keys' action".to_string(),
+            ));
+        }
+        if self.isolation == TaskIsolation::Disabled {
+            return Err(RsdebstrapError::Validation(
+                "ssh task requires isolation (isolation: false would configure the host)"
+                    .to_string(),
+            ));
+        }
+
+        let valid_name = |name: &str| {
+            !name.is_empty()
+                && !name.starts_with(['.', '-'])
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+        };
+        for (name, content) in &self.config {
+            if !valid_name(name) {
+                return Err(RsdebstrapError::Validation(format!(
+                    "ssh config snippet name {:?} must consist of letters, digits, '.', '_' \
+                    and '-' and not start with '.' or '-'",
+                    name
+                )));
+            }
+            if let Some(stem) = name.strip_suffix(".conf")
+                && self.config.contains_key(stem)
+            {
+                return Err(RsdebstrapError::Validation(format!(
+                    "ssh config snippets '{}' and '{}' would both be written to {}",
+                    stem, name, name
+                )));
+            }
+            if content.contains('\0') {
+                return Err(RsdebstrapError::Validation(format!(
+                    "ssh config snippet '{}' must not contain NUL bytes",
+                    name
+                )));
+            }
+        }
+        for (user, keys) in &self.authorized_keys {
+            if !valid_name(user) {
+                return Err(RsdebstrapError::Validation(format!(
+                    "ssh authorized_keys user {:?} must be a user name or numeric id",
+                    user
+                )));
+            }
+            if keys.is_empty() {
+                return Err(RsdebstrapError::Validation(format!(
+                    "ssh authorized_keys for '{}' must list at least one key",
+                    user
+                )));
+            }
+            for key in keys {
+                let names_type = key.split_whitespace().any(|field| {
+                    KEY_TYPE_PREFIXES
+                        .iter()
+                        .any(|prefix| field.starts_with(prefix))
+                });
+                if key.contains(['\n', '\r', '\0']) || !names_type {
+                    return Err(RsdebstrapError::Validation(format!(
+                        "ssh authorized key {:?} for '{}' must be a single \
+                        '[options] type base64 [comment]' line",
+                        key, user
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the shell script piped into `/bin/sh` inside the rootfs.
+    pub fn script(&self) -> String {
+        let mut script = String::from("set -eu\numask 022\n");
+
+        if !self.config.is_empty() {
+            script.push_str(&format!("mkdir -p {}\n", SSHD_CONFIG_DIR));
+        }
+        for (name, content) in &self.config {
+            let path = sh_quote(&format!("{}/{}", SSHD_CONFIG_DIR, snippet_file(name)));
+            let mut content = content.clone();
+            if !content.is_empty() && !content.ends_with('\n') {
+                content.push('\n');
+            }
+            script.push_str(&format!("printf '%s' {} > {}\n", sh_quote(&content), path));
+            script.push_str(&format!("chmod 644 {}\n", path));
+        }
+
+        for (user, keys) in &self.authorized_keys {
+            let quoted = sh_quote(user);
+            let missing = sh_quote(&format!("rsdebstrap: user '{}' not found in the rootfs", user));
+            script.push_str(&format!(
+                "home=$(getent passwd {quoted} | cut -d: -f6)\n\
+                 if [ -z \"$home\" ]; then echo {missing} >&2; exit 1; fi\n\
+                 group=$(id -gn {quoted})\n\
+                 mkdir -p \"$home/.ssh\"\n\
+                 chown {quoted}:\"$group\" \"$home/.ssh\"\n\
+                 chmod 700 \"$home/.ssh\"\n"
+            ));
+            let keys: Vec<String> = keys.iter().map(|key| sh_quote(key)).collect();
+            script.push_str(&format!(
+                "printf '%s\\n' {} > \"$home/.ssh/authorized_keys\"\n\
+                 chown {quoted}:\"$group\" \"$home/.ssh/authorized_keys\"\n\
+                 chmod 600 \"$home/.ssh/authorized_keys\"\n",
+                keys.join(" ")
+            ));
+        }
+
+        if !self.host_keys.is_keep() {
+            script.push_str("rm -f /etc/ssh/ssh_host_*_key /etc/ssh/ssh_host_*_key.pub\n");
+        }
+        if self.host_keys == SshHostKeys::Regenerate {
+            script.push_str("ssh-keygen -A\n");
+        }
+        script
+    }
+
+    /// Executes the generated script using the provided isolation context.
+    ///
+    /// This method:
+    /// 1. Generates the script writing the snippets and keys and handling the host keys
+    /// 2. Runs `/bin/sh` via the isolation context, with the script on its standard input
+    /// 3. Returns an error if the process fails or exits without status
+    ///
+    /// Nothing is written to the rootfs by rsdebstrap itself, so dry-run only skips
+    /// the command, as the executor does for every task.
+    pub fn execute(&self, context: &dyn IsolationContext) -> Result<()> {
+        let dry_run = context.dry_run();
+
+        info!("configuring ssh: {} (isolation: {})", self.name(), context.name());
+        let script = self.script();
+        debug!(
+            "rootfs: {}, script: {} lines, dry_run: {}",
+            context.rootfs(),
+            script.lines().count(),
+            dry_run
+        );
+
+        let command = vec![SHELL.to_string(), "-s".to_string()];
+        let result = crate::phase::execute_in_context_with_stdin(
+            context,
+            &command,
+            "ssh configuration",
+            self.privilege.resolved_method(),
+            Some(script.as_bytes()),
+        )?;
+        crate::phase::check_execution_result(&result, &command, context.name(), dry_run)?;
+
+        info!("ssh configured successfully");
+        Ok(())
+    }
+}
+
+impl Default for SshTask {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,618 @@
+//! WASM task implementation.
+//!
+//! This module provides the `WasmTask` data structure, which runs task logic from a
+//! WebAssembly module in an embedded wasmtime sandbox (the `wasm` cargo feature). Unlike
+//! a [`plugin`](super::plugin) executable, the module gets no host access at all: no
+//! WASI, so no files, network, environment or clock. It sees only the narrow host API
+//! below, and changes the rootfs solely by asking rsdebstrap to run commands inside
+//! the task's isolation.
+//!
+//! ## Host API
+//!
+//! The module imports these functions from `rsdebstrap` (pointers and lengths are
+//! `i32` offsets into its exported `memory`):
+//!
+//! - `input_len() -> i32` / `input_read(ptr)` — the JSON request: `protocol`,
+//!   `action` (`validate` or `run`), `task`, `config`, `rootfs` and `dry_run`
+//! - `execute(ptr, len) -> i32` — run `{"command": [...], "stdin": "..."}` inside the
+//!   isolation with the task's privilege; returns the exit code (0 in dry-run), or -1
+//!   if it could not run. Only allowed during `run`
+//! - `error(ptr, len)` — report an error message (a rejected config or a failed run)
+//! - `log(ptr, len)` — log a message at info level
+//!
+//! It exports `memory`, `run() -> i32` and optionally `validate() -> i32`; a non-zero
+//! result fails the task. Execution is bounded by [`WASM_FUEL`] and linear memory by
+//! [`WASM_MEMORY_LIMIT`].
+
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+use crate::config::IsolationConfig;
+use crate::error::RsdebstrapError;
+use crate::isolation::{IsolationContext, TaskIsolation};
+use crate::privilege::{Privilege, PrivilegeDefaults, PrivilegeMethod};
+
+/// Version of the host API sent in every request as `protocol`.
+pub const WASM_PROTOCOL_VERSION: u32 = 1;
+
+/// Fuel (roughly, WebAssembly instructions) a module may use per call.
+pub const WASM_FUEL: u64 = 10_000_000_000;
+
+/// Maximum size in bytes of a module's linear memory.
+pub const WASM_MEMORY_LIMIT: usize = 256 * 1024 * 1024;
+
+/// WASM task data and execution logic.
+///
+/// Loads `module` (a `.wasm` binary, or `.wat` text) and calls its exports with
+/// `config`; every command the module asks for runs inside the isolation like the
+/// built-in tasks' commands do.
+///
+/// ## Lifecycle
+///
+/// The typical lifecycle when loaded from a YAML profile is:
+/// 1. **Deserialize** — construct from YAML via `serde`
+///    (or [`new()`](Self::new) for programmatic use)
+/// 2. [`resolve_paths()`](Self::resolve_paths) — resolve a relative `module`
+/// 3. [`validate()`](Self::validate) — compile the module and let it check `config`
+/// 4. [`execute()`](Self::execute) — call its `run` export within an isolation context
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct WasmTask {
+    /// Host-side WebAssembly module (`.wasm`, or `.wat` text)
+    #[serde(deserialize_with = "crate::de::path")]
+    #[cfg_attr(feature = "schema", schemars(with = "crate::schema::Utf8PathSchema"))]
+    module: Utf8PathBuf,
+
+    /// Settings passed to the module as they are
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "serde_json::Map::is_empty"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<std::collections::BTreeMap<String, serde_json::Value>>")
+    )]
+    config: serde_json::Map<String, serde_json::Value>,
+
+    /// Privilege escalation setting (resolved during defaults application)
+    #[serde(default, skip_serializing_if = "Privilege::is_inherit")]
+    privilege: Privilege,
+
+    /// Isolation setting (resolved during defaults application)
+    #[serde(default, skip_serializing_if = "TaskIsolation::is_inherit")]
+    isolation: TaskIsolation,
+
+    /// Network access (`None` inherits from isolation; resolved during defaults application)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    network: Option<bool>,
+
+    /// Labels matched by `apply --tags`/`--skip-tags`
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<String>>"))]
+    tags: Vec<String>,
+
+    /// Log a failure of the task and keep going instead of failing the phase
+    #[serde(default, skip_serializing_if = "crate::phase::is_false")]
+    ignore_errors: bool,
+
+    /// User-given name shown in logs and errors, and matched by `apply --start-at-task`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+impl WasmTask {
+    /// Creates a new WasmTask running `module` with an empty config.
+    pub fn new(module: impl Into<Utf8PathBuf>) -> Self {
+        Self {
+            module: module.into(),
+            config: serde_json::Map::new(),
+            privilege: Privilege::default(),
+            isolation: TaskIsolation::default(),
+            network: None,
+            tags: Vec::new(),
+            ignore_errors: false,
+            name: None,
+        }
+    }
+
+    /// Sets the config setting `key` passed to the module.
+    pub fn with_config(mut self, key: impl Into<String>, value: serde_json::Value) -> Self {
+        self.config.insert(key.into(), value);
+        self
+    }
+
+    /// Sets the user-given task name.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Sets whether a failure of the task is logged instead of failing the phase.
+    pub fn with_ignore_errors(mut self, ignore_errors: bool) -> Self {
+        self.ignore_errors = ignore_errors;
+        self
+    }
+
+    /// Sets the labels matched by `apply --tags`/`--skip-tags`.
+    pub fn with_tags<I, S>(mut self, tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.tags = tags.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the privilege escalation setting.
+    pub fn with_privilege(mut self, privilege: Privilege) -> Self {
+        self.privilege = privilege;
+        self
+    }
+
+    /// Sets the isolation setting.
+    pub fn with_isolation(mut self, isolation: TaskIsolation) -> Self {
+        self.isolation = isolation;
+        self
+    }
+
+    /// Sets network access (by default it is inherited from the isolation config).
+    pub fn with_network(mut self, network: bool) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// Returns the module path.
+    pub fn module(&self) -> &Utf8Path {
+        &self.module
+    }
+
+    /// Returns the settings passed to the module.
+    pub fn config(&self) -> &serde_json::Map<String, serde_json::Value> {
+        &self.config
+    }
+
+    /// Returns a human-readable name for this task (without type prefix): the module
+    /// file name.
+    pub fn name(&self) -> &str {
+        self.module.file_name().unwrap_or(self.module.as_str())
+    }
+
+    /// Resolves a relative `module` path against `base_dir`.
+    pub fn resolve_paths(&mut self, base_dir: &Utf8Path) {
+        if self.module.is_relative() {
+            self.module = base_dir.join(&self.module);
+        }
+    }
+
+    /// Resolves the privilege setting against profile defaults.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `privilege: true` is specified
+    /// but no `defaults.privilege.method` is configured in the profile.
+    pub fn resolve_privilege(
+        &mut self,
+        defaults: Option<&PrivilegeDefaults>,
+    ) -> Result<(), RsdebstrapError> {
+        self.privilege.resolve_in_place(defaults)
+    }
+
+    /// Returns the resolved privilege method.
+    ///
+    /// Should only be called after [`resolve_privilege()`](Self::resolve_privilege).
+    pub fn resolved_privilege_method(&self) -> Option<PrivilegeMethod> {
+        self.privilege.resolved_method()
+    }
+
+    /// Returns a reference to the task's isolation setting.
+    pub fn task_isolation(&self) -> &TaskIsolation {
+        &self.isolation
+    }
+
+    /// Resolves the isolation setting against profile defaults.
+    pub fn resolve_isolation(&mut self, defaults: &IsolationConfig) {
+        self.isolation.resolve_in_place(defaults);
+    }
+
+    /// Returns the resolved isolation config.
+    ///
+    /// Should only be called after [`resolve_isolation()`](Self::resolve_isolation).
+    pub fn resolved_isolation_config(&self) -> Option<&IsolationConfig> {
+        self.isolation.resolved_config()
+    }
+
+    /// Resolves the network setting against the isolation config and `offline`.
+    ///
+    /// Should be called after [`resolve_isolation()`](Self::resolve_isolation).
+    ///
+    /// # Errors
+    ///
+    /// Returns `RsdebstrapError::Validation` if `offline` is set and the task or its
+    /// isolation config explicitly enables the network.
+    pub fn resolve_network(&mut self, offline: bool) -> Result<(), RsdebstrapError> {
+        self.network =
+            crate::phase::resolve_network(self.network, self.isolation.resolved_config(), offline)?;
+        Ok(())
+    }
+
+    /// Returns whether the task may use the network (default: true).
+    pub fn network_enabled(&self) -> bool {
+        self.network.unwrap_or(true)
+    }
+
+    /// Returns the task's tags.
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Returns whether a failure of the task is logged instead of failing the phase.
+    pub fn ignore_errors(&self) -> bool {
+        self.ignore_errors
+    }
+
+    /// Returns the user-given `name`, if any.
+    pub fn configured_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the JSON request the module reads through `input_read`.
+    #[cfg(feature = "wasm")]
+    fn request(&self, action: &str, rootfs: Option<&Utf8Path>, dry_run: bool) -> Vec<u8> {
+        serde_json::json!({
+            "protocol": WASM_PROTOCOL_VERSION,
+            "action": action,
+            "task": self.name.as_deref().unwrap_or(self.name()),
+            "config": self.config,
+            "rootfs": rootfs,
+            "dry_run": dry_run,
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    /// Validates the task configuration.
+    ///
+    /// Checks:
+    /// - rsdebstrap was built with the `wasm` feature
+    /// - `module` is a regular file that compiles, exporting `memory` and `run`
+    /// - the module accepts `config` (its optional `validate` export returns 0)
+    /// - `name` and `tags` as for other provision tasks
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        crate::phase::validate_task_name(self.name.as_deref())?;
+        crate::phase::validate_task_tags(&self.tags)?;
+        crate::phase::validate_host_file_exists(&self.module, "wasm module")?;
+
+        #[cfg(feature = "wasm")]
+        {
+            let request = self.request("validate", None, false);
+            runtime::validate(&self.module, request)
+        }
+        #[cfg(not(feature = "wasm"))]
+        Err(RsdebstrapError::Validation(format!(
+            "wasm task {} requires rsdebstrap built with the `wasm` feature",
+            self.module
+        )))
+    }
+
+    /// Calls the module's `run` export, running the commands it requests via the
+    /// provided isolation context.
+    ///
+    /// This method:
+    /// 1. Compiles the module and instantiates it without any WASI imports
+    /// 2. Calls `run` on a worker thread; each `execute` call is handed back to this
+    ///    thread, which runs it via the isolation context with the task's privilege
+    /// 3. Returns an error if `run` traps, runs out of fuel or returns non-zero
+    ///
+    /// The module also runs in dry-run; its commands are skipped by the executor as
+    /// for every task and report exit code 0.
+    pub fn execute(&self, context: &dyn IsolationContext) -> Result<()> {
+        let dry_run = context.dry_run();
+
+        info!("running wasm task: {} (isolation: {})", self.name(), context.name());
+        debug!("rootfs: {}, module: {}, dry_run: {}", context.rootfs(), self.module, dry_run);
+
+        #[cfg(feature = "wasm")]
+        {
+            let request = self.request("run", Some(context.rootfs()), dry_run);
+            runtime::run(&self.module, request, |command, stdin| {
+                let result = crate::phase::execute_in_context_with_stdin(
+                    context,
+                    command,
+                    "wasm task command",
+                    self.privilege.resolved_method(),
+                    stdin,
+                )?;
+                Ok(match result.status {
+                    Some(status) => status.code().unwrap_or(-1),
+                    None if dry_run => 0,
+                    None => -1,
+                })
+            })?;
+            info!("wasm task completed successfully");
+            Ok(())
+        }
+        #[cfg(not(feature = "wasm"))]
+        Err(RsdebstrapError::Validation(format!(
+            "wasm task {} requires rsdebstrap built with the `wasm` feature",
+            self.module
+        ))
+        .into())
+    }
+}
+
+/// The wasmtime embedding behind [`WasmTask`].
+#[cfg(feature = "wasm")]
+mod runtime {
+    use std::sync::mpsc;
+
+    use anyhow::{Context, Result, anyhow, bail};
+    use camino::Utf8Path;
+    use serde::Deserialize;
+    use tracing::info;
+    use wasmtime::{
+        Caller, Config, Engine, Extern, Linker, Memory, Module, Store, StoreLimits,
+        StoreLimitsBuilder,
+    };
+
+    use super::{WASM_FUEL, WASM_MEMORY_LIMIT};
+    use crate::error::RsdebstrapError;
+
+    /// A command requested through `execute`.
+    #[derive(Debug, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct CommandRequest {
+        command: Vec<String>,
+        #[serde(default)]
+        stdin: Option<String>,
+    }
+
+    /// Channel pair handing `execute` calls to the thread holding the isolation context.
+    struct Executor {
+        requests: mpsc::Sender<CommandRequest>,
+        results: mpsc::Receiver<i32>,
+    }
+
+    /// Data a module's host calls operate on.
+    struct HostState {
+        input: Vec<u8>,
+        executor: Option<Executor>,
+        errors: Vec<String>,
+        limits: StoreLimits,
+    }
+
+    /// Reads `len` bytes at `ptr` from the caller's exported memory.
+    fn read_guest(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32) -> Result<Vec<u8>> {
+        let memory = guest_memory(caller)?;
+        let start = usize::try_from(ptr).context("negative pointer")?;
+        let len = usize::try_from(len).context("negative length")?;
+        let end = start.checked_add(len).context("range overflows")?;
+        memory
+            .data(&caller)
+            .get(start..end)
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| anyhow!("range {}..{} is outside memory", start, end))
+    }
+
+    fn read_guest_string(caller: &mut Caller<'_, HostState>, ptr: i32, len: i32) -> Result<String> {
+        String::from_utf8(read_guest(caller, ptr, len)?).context("string is not UTF-8")
+    }
+
+    fn guest_memory(caller: &mut Caller<'_, HostState>) -> Result<Memory> {
+        match caller.get_export("memory") {
+            Some(Extern::Memory(memory)) => Ok(memory),
+            _ => bail!("module does not export `memory`"),
+        }
+    }
+
+    /// Links the `rsdebstrap` host API.
+    fn linker(engine: &Engine) -> Result<Linker<HostState>> {
+        let mut linker = Linker::new(engine);
+        linker.func_wrap("rsdebstrap", "input_len", |caller: Caller<'_, HostState>| {
+            i32::try_from(caller.data().input.len()).context("request is too large")
+        })?;
+        linker.func_wrap(
+            "rsdebstrap",
+            "input_read",
+            |mut caller: Caller<'_, HostState>, ptr: i32| -> Result<()> {
+                let memory = guest_memory(&mut caller)?;
+                let start = usize::try_from(ptr).context("negative pointer")?;
+                let (data, state) = memory.data_and_store_mut(&mut caller);
+                let end = start + state.input.len();
+                data.get_mut(start..end)
+                    .ok_or_else(|| anyhow!("range {}..{} is outside memory", start, end))?
+                    .copy_from_slice(&state.input);
+                Ok(())
+            },
+        )?;
+        linker.func_wrap(
+            "rsdebstrap",
+            "execute",
+            |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> Result<i32> {
+                let request: CommandRequest =
+                    serde_json::from_slice(&read_guest(&mut caller, ptr, len)?)
+                        .context("invalid execute request")?;
+                if request
+                    .command
+                    .first()
+                    .is_none_or(|program| program.is_empty())
+                {
+                    bail!("execute requested an empty command");
+                }
+                let executor = caller
+                    .data()
+                    .executor
+                    .as_ref()
+                    .context("execute is only allowed during `run`")?;
+                executor
+                    .requests
+                    .send(request)
+                    .map_err(|_| anyhow!("executor is gone"))?;
+                executor.results.recv().context("executor is gone")
+            },
+        )?;
+        linker.func_wrap(
+            "rsdebstrap",
+            "error",
+            |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> Result<()> {
+                let message = read_guest_string(&mut caller, ptr, len)?;
+                caller.data_mut().errors.push(message);
+                Ok(())
+            },
+        )?;
+        linker.func_wrap(
+            "rsdebstrap",
+            "log",
+            |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> Result<()> {
+                info!("wasm: {}", read_guest_string(&mut caller, ptr, len)?);
+                Ok(())
+            },
+        )?;
+        Ok(linker)
+    }
+
+    /// Compiles the module at `path` for a fuel-metered engine.
+    fn load(path: &Utf8Path) -> Result<(Engine, Module), RsdebstrapError> {
+        let fail = |e: anyhow::Error| {
+            RsdebstrapError::Validation(format!("wasm module {}: {:#}", path, e))
+        };
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(fail)?;
+        let module = Module::from_file(&engine, path.as_std_path()).map_err(fail)?;
+        Ok((engine, module))
+    }
+
+    /// Instantiates `module` and calls its export `export` (if `required` or present),
+    /// returning the export's result and the reported errors.
+    fn call(
+        path: &Utf8Path,
+        (engine, module): &(Engine, Module),
+        input: Vec<u8>,
+        executor: Option<Executor>,
+        export: &str,
+        required: bool,
+    ) -> Result<(i32, Vec<String>), RsdebstrapError> {
+        let fail = |e: anyhow::Error| {
+            RsdebstrapError::Validation(format!("wasm module {}: {:#}", path, e))
+        };
+        let state = HostState {
+            input,
+            executor,
+            errors: Vec::new(),
+            limits: StoreLimitsBuilder::new()
+                .memory_size(WASM_MEMORY_LIMIT)
+                .build(),
+        };
+        let mut store = Store::new(engine, state);
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(WASM_FUEL).map_err(fail)?;
+        let instance = linker(engine)
+            .and_then(|linker| linker.instantiate(&mut store, module))
+            .map_err(fail)?;
+        if instance.get_memory(&mut store, "memory").is_none() {
+            return Err(fail(anyhow!("module does not export `memory`")));
+        }
+        let status = match instance.get_typed_func::<(), i32>(&mut store, export) {
+            Ok(func) => func
+                .call(&mut store, ())
+                .map_err(|e| RsdebstrapError::Execution {
+                    command: format!("{} {}", path, export),
+                    status: format!("{:#}", e),
+                    details: None,
+                })?,
+            Err(_) if !required => 0,
+            Err(e) => return Err(fail(e.context(format!("no `{}` export", export)))),
+        };
+        Ok((status, std::mem::take(&mut store.data_mut().errors)))
+    }
+
+    /// Joins the module's error messages, or names its status if it reported none.
+    fn reason(export: &str, status: i32, errors: &[String]) -> String {
+        if errors.is_empty() {
+            format!("{} returned {}", export, status)
+        } else {
+            errors.join("; ")
+        }
+    }
+
+    /// Compiles the module, checks it exports `run`, and calls its `validate` export.
+    pub(super) fn validate(path: &Utf8Path, input: Vec<u8>) -> Result<(), RsdebstrapError> {
+        let loaded = load(path)?;
+        if !loaded.1.exports().any(|export| export.name() == "run") {
+            return Err(RsdebstrapError::Validation(format!(
+                "wasm module {} does not export `run`",
+                path
+            )));
+        }
+        let (status, errors) = call(path, &loaded, input, None, "validate", false)?;
+        if status != 0 || !errors.is_empty() {
+            return Err(RsdebstrapError::Validation(format!(
+                "wasm module {} rejected its config: {}",
+                path,
+                reason("validate", status, &errors)
+            )));
+        }
+        Ok(())
+    }
+
+    /// Calls the module's `run` export on a worker thread, running each command it
+    /// requests with `execute` on the calling thread.
+    pub(super) fn run(
+        path: &Utf8Path,
+        input: Vec<u8>,
+        mut execute: impl FnMut(&[String], Option<&[u8]>) -> Result<i32>,
+    ) -> Result<()> {
+        let loaded = load(path)?;
+        let (request_tx, request_rx) = mpsc::channel::<CommandRequest>();
+        let (result_tx, result_rx) = mpsc::channel();
+        let executor = Executor {
+            requests: request_tx,
+            results: result_rx,
+        };
+        std::thread::scope(|scope| {
+            let worker = scope.spawn(|| call(path, &loaded, input, Some(executor), "run", true));
+            // Ends when the worker drops its sender, i.e. when `run` returns or traps. A
+            // failed command drops `result_tx`, which makes the pending `execute` trap.
+            let mut failure = None;
+            for request in request_rx {
+                let stdin = request.stdin.as_deref().map(str::as_bytes);
+                match execute(&request.command, stdin) {
+                    Ok(code) => {
+                        if result_tx.send(code).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        failure = Some(e);
+                        break;
+                    }
+                }
+            }
+            drop(result_tx);
+            let outcome = worker.join().expect("wasm worker panicked");
+            if let Some(e) = failure {
+                return Err(e);
+            }
+            let (status, errors) = outcome?;
+            if status != 0 {
+                return Err(RsdebstrapError::Execution {
+                    command: format!("{} run", path),
+                    status: reason("run", status, &errors),
+                    details: None,
+                }
+                .into());
+            }
+            Ok(())
+        })
+    }
+}
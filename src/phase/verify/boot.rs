@@ -0,0 +1,770 @@
+//! `boot` verify check: a headless qemu boot smoke test of a disk image.
+//!
+//! The image is booted on the host with `-snapshot`, so the test leaves it unchanged,
+//! and the check waits until the guest prints a string on its serial console or
+//! answers with an SSH banner on a forwarded port. On success the guest is asked to
+//! power off (and killed if it does not); when the wait times out, or qemu exits
+//! first, the check fails. A signal or a `serve` cancel kills qemu and interrupts
+//! the check. The serial console is kept next to the image as
+//! `<image>.boot.log` for debugging a failed boot.
+
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::disk_space::ByteSize;
+use crate::error::RsdebstrapError;
+use crate::executor::{self, format_command_args};
+
+/// How often the console log or the SSH port is polled while the guest boots.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long a guest may take to power off before qemu is killed.
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(30);
+
+fn default_timeout_secs() -> u64 {
+    300
+}
+
+fn is_default_timeout_secs(timeout_secs: &u64) -> bool {
+    *timeout_secs == default_timeout_secs()
+}
+
+fn default_memory() -> ByteSize {
+    ByteSize(1 << 30)
+}
+
+fn is_default_memory(memory: &ByteSize) -> bool {
+    *memory == default_memory()
+}
+
+/// Disk image format passed to qemu's `-drive format=`.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    /// A raw disk image.
+    Raw,
+    /// A qcow2 disk image.
+    Qcow2,
+}
+
+impl ImageFormat {
+    /// Returns the format of `image` by its extension: `qcow2` for `.qcow2`,
+    /// otherwise `raw`.
+    pub fn detect(image: &Utf8Path) -> Self {
+        match image.extension() {
+            Some("qcow2") => Self::Qcow2,
+            _ => Self::Raw,
+        }
+    }
+
+    /// Returns the qemu name of the format.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Raw => "raw",
+            Self::Qcow2 => "qcow2",
+        }
+    }
+}
+
+/// What the boot check waits for to call a boot successful, written in YAML as a map
+/// with exactly one of `console` or `ssh`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(try_from = "BootWaitWire", into = "BootWaitWire")]
+pub enum BootWait {
+    /// A string printed on the serial console (`ttyS0`), e.g. `login:`.
+    Console(String),
+    /// An SSH banner on this guest port, forwarded to a free port on the host.
+    Ssh(u16),
+}
+
+/// The YAML shape of a [`BootWait`].
+#[derive(Debug, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub(crate) struct BootWaitWire {
+    /// String printed on the serial console
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    console: Option<String>,
+    /// Guest port answering with an SSH banner
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ssh: Option<u16>,
+}
+
+impl TryFrom<BootWaitWire> for BootWait {
+    type Error = String;
+
+    fn try_from(wire: BootWaitWire) -> Result<Self, Self::Error> {
+        match (wire.console, wire.ssh) {
+            (Some(text), None) => Ok(Self::Console(text)),
+            (None, Some(port)) => Ok(Self::Ssh(port)),
+            _ => Err("wait_for needs exactly one of 'console' or 'ssh'".to_string()),
+        }
+    }
+}
+
+impl From<BootWait> for BootWaitWire {
+    fn from(wait: BootWait) -> Self {
+        match wait {
+            BootWait::Console(text) => Self {
+                console: Some(text),
+                ssh: None,
+            },
+            BootWait::Ssh(port) => Self {
+                console: None,
+                ssh: Some(port),
+            },
+        }
+    }
+}
+
+impl Default for BootWait {
+    fn default() -> Self {
+        Self::Console("login:".to_string())
+    }
+}
+
+impl BootWait {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Self::Console(text) => format!("print {:?} on the serial console", text),
+            Self::Ssh(port) => format!("answer SSH on port {}", port),
+        }
+    }
+}
+
+/// Boot smoke test data and execution logic.
+///
+/// Boots `image`, a disk image produced by an earlier step (for example copied out
+/// of the rootfs by the assemble `extract` task), in a headless qemu and waits for
+/// [`BootWait`]. The check runs on the host; qemu uses KVM when available and falls
+/// back to emulation otherwise.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct BootCheck {
+    /// Disk image to boot (relative paths resolve against `dir`)
+    #[serde(deserialize_with = "crate::de::path")]
+    #[cfg_attr(feature = "schema", schemars(with = "crate::schema::Utf8PathSchema"))]
+    image: Utf8PathBuf,
+
+    /// Image format (default: `qcow2` for a `.qcow2` file, otherwise `raw`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    format: Option<ImageFormat>,
+
+    /// What to wait for (default: `console: "login:"`)
+    #[serde(default, skip_serializing_if = "BootWait::is_default")]
+    #[cfg_attr(feature = "schema", schemars(with = "BootWaitWire"))]
+    wait_for: BootWait,
+
+    /// Seconds the guest has to boot (default: 300)
+    #[serde(
+        default = "default_timeout_secs",
+        skip_serializing_if = "is_default_timeout_secs"
+    )]
+    timeout_secs: u64,
+
+    /// Guest memory (default: 1G)
+    #[serde(default = "default_memory", skip_serializing_if = "is_default_memory")]
+    #[cfg_attr(feature = "schema", schemars(with = "crate::schema::ByteSizeSchema"))]
+    memory: ByteSize,
+
+    /// qemu executable (default: `qemu-system-<host architecture>`)
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    qemu: Option<String>,
+
+    /// Firmware passed as `-bios`, e.g. OVMF for UEFI images (relative paths resolve
+    /// against the profile)
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_path",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<crate::schema::Utf8PathSchema>")
+    )]
+    firmware: Option<Utf8PathBuf>,
+
+    /// Extra qemu arguments, e.g. `[-machine, virt, -cpu, max]`
+    #[serde(
+        default,
+        deserialize_with = "crate::de::string_list",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<String>>"))]
+    qemu_args: Vec<String>,
+}
+
+impl BootCheck {
+    /// Creates a new BootCheck booting `image` with the default settings.
+    pub fn new(image: impl Into<Utf8PathBuf>) -> Self {
+        Self {
+            image: image.into(),
+            format: None,
+            wait_for: BootWait::default(),
+            timeout_secs: default_timeout_secs(),
+            memory: default_memory(),
+            qemu: None,
+            firmware: None,
+            qemu_args: Vec::new(),
+        }
+    }
+
+    /// Sets the image format instead of detecting it from the extension.
+    pub fn with_format(mut self, format: ImageFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Sets what the check waits for.
+    pub fn with_wait_for(mut self, wait_for: BootWait) -> Self {
+        self.wait_for = wait_for;
+        self
+    }
+
+    /// Sets the seconds the guest has to boot.
+    pub fn with_timeout_secs(mut self, timeout_secs: u64) -> Self {
+        self.timeout_secs = timeout_secs;
+        self
+    }
+
+    /// Sets the qemu executable.
+    pub fn with_qemu(mut self, qemu: impl Into<String>) -> Self {
+        self.qemu = Some(qemu.into());
+        self
+    }
+
+    /// Sets the firmware passed as `-bios`.
+    pub fn with_firmware(mut self, firmware: impl Into<Utf8PathBuf>) -> Self {
+        self.firmware = Some(firmware.into());
+        self
+    }
+
+    /// Sets extra qemu arguments.
+    pub fn with_qemu_args<I, S>(mut self, qemu_args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.qemu_args = qemu_args.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Returns the disk image.
+    pub fn image(&self) -> &Utf8Path {
+        &self.image
+    }
+
+    /// Returns what the check waits for.
+    pub fn wait_for(&self) -> &BootWait {
+        &self.wait_for
+    }
+
+    /// Returns the qemu executable.
+    pub fn qemu(&self) -> String {
+        self.qemu
+            .clone()
+            .unwrap_or_else(|| format!("qemu-system-{}", std::env::consts::ARCH))
+    }
+
+    /// Returns where the serial console is logged: `<image>.boot.log`.
+    pub fn console_log(&self) -> Utf8PathBuf {
+        format!("{}.boot.log", self.image).into()
+    }
+
+    /// Returns a human-readable name for this check (without type prefix): the
+    /// image's file name.
+    pub fn name(&self) -> &str {
+        self.image.file_name().unwrap_or(self.image.as_str())
+    }
+
+    /// Resolves a relative `image` against the output directory `dir`, and a relative
+    /// `firmware` against the profile file's directory.
+    pub(crate) fn resolve_paths(&mut self, profile_dir: &Utf8Path, dir: &Utf8Path) {
+        if self.image.is_relative() {
+            self.image = dir.join(&self.image);
+        }
+        if let Some(firmware) = self.firmware.as_mut()
+            && firmware.is_relative()
+        {
+            *firmware = profile_dir.join(&*firmware);
+        }
+    }
+
+    /// Validates the boot check configuration.
+    ///
+    /// The image itself is not checked: it is usually produced during the build.
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        if self.image.file_name().is_none() {
+            return Err(RsdebstrapError::Validation(format!(
+                "verify boot: image {:?} must name a file",
+                self.image
+            )));
+        }
+        crate::phase::validate_no_parent_dirs(&self.image, "verify boot image")?;
+        if self.timeout_secs == 0 {
+            return Err(RsdebstrapError::Validation(
+                "verify boot: timeout_secs must be greater than 0".to_string(),
+            ));
+        }
+        if self.memory.0 < 1 << 20 {
+            return Err(RsdebstrapError::Validation(format!(
+                "verify boot: memory {} is below 1M",
+                self.memory
+            )));
+        }
+        match &self.wait_for {
+            BootWait::Console(text) if text.is_empty() || text.contains(['\n', '\r']) => {
+                return Err(RsdebstrapError::Validation(
+                    "verify boot: wait_for console must be a non-empty single line".to_string(),
+                ));
+            }
+            BootWait::Ssh(0) => {
+                return Err(RsdebstrapError::Validation(
+                    "verify boot: wait_for ssh port must not be 0".to_string(),
+                ));
+            }
+            _ => {}
+        }
+        if self
+            .qemu
+            .as_deref()
+            .is_some_and(|qemu| qemu.trim().is_empty())
+        {
+            return Err(RsdebstrapError::Validation(
+                "verify boot: qemu must not be empty".to_string(),
+            ));
+        }
+        if let Some(firmware) = &self.firmware {
+            crate::phase::validate_host_file_exists(firmware, "verify boot firmware")?;
+        }
+        if self.qemu_args.iter().any(|arg| arg.contains('\0')) {
+            return Err(RsdebstrapError::Validation(
+                "verify boot: qemu_args must not contain null characters".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the qemu arguments booting the image, with the guest's SSH port
+    /// forwarded to `host_port` when waiting for SSH.
+    pub fn qemu_command(&self, host_port: Option<u16>) -> Vec<String> {
+        let format = self
+            .format
+            .unwrap_or_else(|| ImageFormat::detect(&self.image));
+        let mut args: Vec<String> = [
+            "-accel",
+            "kvm",
+            "-accel",
+            "tcg",
+            "-m",
+            &format!("{}M", self.memory.0.div_ceil(1 << 20)),
+            "-display",
+            "none",
+            "-serial",
+            &format!("file:{}", self.console_log()),
+            "-monitor",
+            "stdio",
+            "-no-reboot",
+            "-snapshot",
+            "-drive",
+            &format!(
+                "file={},format={},if=virtio",
+                self.image.as_str().replace(',', ",,"),
+                format.as_str()
+            ),
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+        if let Some(firmware) = &self.firmware {
+            args.extend(["-bios".to_string(), firmware.to_string()]);
+        }
+        match (&self.wait_for, host_port) {
+            (BootWait::Ssh(guest_port), Some(host_port)) => args.extend([
+                "-netdev".to_string(),
+                format!("user,id=net0,hostfwd=tcp:127.0.0.1:{}-:{}", host_port, guest_port),
+                "-device".to_string(),
+                "virtio-net-pci,netdev=net0".to_string(),
+            ]),
+            _ => args.extend(["-nic".to_string(), "none".to_string()]),
+        }
+        args.extend(self.qemu_args.iter().cloned());
+        args
+    }
+
+    /// Boots the image and waits for the guest; `dry_run` only logs the command.
+    pub fn execute(&self, dry_run: bool) -> Result<()> {
+        let qemu = self.qemu();
+        if dry_run {
+            info!(
+                "dry run: would boot {} with {} {}",
+                self.image,
+                qemu,
+                format_command_args(&self.qemu_command(None))
+            );
+            return Ok(());
+        }
+        if !self.image.is_file() {
+            return Err(RsdebstrapError::Validation(format!(
+                "verify boot: image {} does not exist; it must be produced before the \
+                verify phase runs",
+                self.image
+            ))
+            .into());
+        }
+
+        let host_port = match self.wait_for {
+            BootWait::Ssh(_) => Some(free_local_port()?),
+            BootWait::Console(_) => None,
+        };
+        let args = self.qemu_command(host_port);
+        let label = format!("{} {}", qemu, format_command_args(&args));
+        let failed = |status: String| RsdebstrapError::Execution {
+            command: label.clone(),
+            status,
+            details: None,
+        };
+        let log = self.console_log();
+        // qemu appends to an existing file, so a stale log could match.
+        match fs::remove_file(&log) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(RsdebstrapError::io(format!("failed to remove {}", log), e).into());
+            }
+            _ => {}
+        }
+
+        info!("booting {} to {}", self.image, self.wait_for.describe());
+        debug!("running: {}", label);
+        let child = Command::new(&qemu)
+            .args(&args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| RsdebstrapError::io(format!("failed to run {}", qemu), e))?;
+        let mut guest = Guest(child);
+
+        let started = Instant::now();
+        let deadline = started + Duration::from_secs(self.timeout_secs);
+        loop {
+            // Checked first: Ctrl-C reaches qemu as well and makes it exit.
+            if executor::take_interrupt() {
+                return Err(RsdebstrapError::Interrupted {
+                    command: label.clone(),
+                    status: "interrupted".to_string(),
+                    details: None,
+                }
+                .into());
+            }
+            if let Some(status) = guest.0.try_wait()? {
+                return Err(failed(format!(
+                    "qemu exited with {} before the guest booted; console log: {}",
+                    status, log
+                ))
+                .into());
+            }
+            let booted = match (&self.wait_for, host_port) {
+                (BootWait::Ssh(_), Some(port)) => ssh_banner(port),
+                (BootWait::Console(text), _) => console_contains(&log, text)?,
+                (BootWait::Ssh(_), None) => unreachable!("a port is picked when waiting for SSH"),
+            };
+            if booted {
+                break;
+            }
+            if Instant::now() >= deadline {
+                return Err(failed(format!(
+                    "guest did not {} within {}s; console log: {}",
+                    self.wait_for.describe(),
+                    self.timeout_secs,
+                    log
+                ))
+                .into());
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+
+        info!("{} booted in {}s", self.image, started.elapsed().as_secs());
+        guest.power_off();
+        Ok(())
+    }
+}
+
+/// A running qemu, killed when dropped.
+struct Guest(Child);
+
+impl Guest {
+    /// Asks the guest to power off through the qemu monitor, and waits for qemu to
+    /// exit; it is killed on drop if it is still running after [`SHUTDOWN_GRACE`].
+    fn power_off(&mut self) {
+        if let Some(stdin) = self.0.stdin.as_mut()
+            && let Err(e) = stdin.write_all(b"system_powerdown\n")
+        {
+            debug!("failed to send system_powerdown to qemu: {}", e);
+            return;
+        }
+        let deadline = Instant::now() + SHUTDOWN_GRACE;
+        while Instant::now() < deadline {
+            match self.0.try_wait() {
+                Ok(Some(_)) => return,
+                Ok(None) => std::thread::sleep(POLL_INTERVAL),
+                Err(e) => {
+                    debug!("failed to wait for qemu: {}", e);
+                    return;
+                }
+            }
+        }
+        warn!("guest did not power off within {}s; stopping qemu", SHUTDOWN_GRACE.as_secs());
+    }
+}
+
+impl Drop for Guest {
+    fn drop(&mut self) {
+        if let Ok(None) = self.0.try_wait() {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+}
+
+/// Returns a TCP port on the loopback interface that is free right now.
+fn free_local_port() -> Result<u16, RsdebstrapError> {
+    TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .and_then(|listener| listener.local_addr())
+        .map(|addr| addr.port())
+        .map_err(|e| RsdebstrapError::io("failed to pick a port for the SSH forward", e))
+}
+
+/// Returns `true` if `127.0.0.1:port` answers with an SSH banner.
+///
+/// qemu accepts forwarded connections before the guest listens, and closes them
+/// when nothing answers, so a successful connect alone is not enough.
+pub(crate) fn ssh_banner(port: u16) -> bool {
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let Ok(mut stream) = TcpStream::connect_timeout(&addr, Duration::from_secs(1)) else {
+        return false;
+    };
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(2)));
+    let mut banner = [0u8; 4];
+    stream.read_exact(&mut banner).is_ok() && &banner == b"SSH-"
+}
+
+/// Returns `true` if the console log at `log` contains `text`.
+fn console_contains(log: &Utf8Path, text: &str) -> Result<bool, RsdebstrapError> {
+    match fs::read(log) {
+        Ok(content) => Ok(String::from_utf8_lossy(&content).contains(text)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(RsdebstrapError::io(format!("failed to read console log {}", log), e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_defaults_and_options() {
+        let check: BootCheck = yaml_serde::from_str("image: disk.img\n").unwrap();
+        assert_eq!(check, BootCheck::new("disk.img"));
+        assert_eq!(yaml_serde::to_string(&check).unwrap(), "image: disk.img\n");
+
+        let yaml = "image: disk.qcow2\nwait_for:\n  ssh: 22\ntimeout_secs: 60\nmemory: 2G\n\
+                    qemu: qemu-system-aarch64\nqemu_args:\n- -machine\n- virt\n";
+        let check: BootCheck = yaml_serde::from_str(yaml).unwrap();
+        assert_eq!(check.wait_for(), &BootWait::Ssh(22));
+        assert_eq!(check.memory, ByteSize(2 << 30));
+        assert_eq!(yaml_serde::to_string(&check).unwrap(), yaml);
+
+        for yaml in [
+            "image: a.img\nwait_for: login\n",
+            "image: a.img\nformat: vmdk\n",
+            "image: a.img\nwait_for:\n  ssh: 22\n  console: x\n",
+            "image: a.img\nwait_for: {}\n",
+        ] {
+            assert!(yaml_serde::from_str::<BootCheck>(yaml).is_err(), "{yaml}");
+        }
+    }
+
+    #[test]
+    fn validate_rejects_bad_settings() {
+        for check in [
+            BootCheck::new("/"),
+            BootCheck::new("/out/../disk.img"),
+            BootCheck::new("/out/disk.img").with_timeout_secs(0),
+            BootCheck::new("/out/disk.img").with_wait_for(BootWait::Console(String::new())),
+            BootCheck::new("/out/disk.img").with_wait_for(BootWait::Ssh(0)),
+            BootCheck::new("/out/disk.img").with_qemu(" "),
+            BootCheck::new("/out/disk.img").with_firmware("/nonexistent/OVMF.fd"),
+        ] {
+            assert!(check.validate().is_err(), "{check:?}");
+        }
+        BootCheck::new("/out/disk.img")
+            .with_wait_for(BootWait::Ssh(22))
+            .validate()
+            .unwrap();
+    }
+
+    #[test]
+    fn qemu_command_boots_a_snapshot() {
+        let mut check = BootCheck::new("out,1/disk.qcow2").with_firmware("OVMF.fd");
+        check.resolve_paths(Utf8Path::new("/profiles"), Utf8Path::new("/build"));
+        assert_eq!(
+            check.qemu_command(None),
+            [
+                "-accel",
+                "kvm",
+                "-accel",
+                "tcg",
+                "-m",
+                "1024M",
+                "-display",
+                "none",
+                "-serial",
+                "file:/build/out,1/disk.qcow2.boot.log",
+                "-monitor",
+                "stdio",
+                "-no-reboot",
+                "-snapshot",
+                "-drive",
+                "file=/build/out,,1/disk.qcow2,format=qcow2,if=virtio",
+                "-bios",
+                "/profiles/OVMF.fd",
+                "-nic",
+                "none",
+            ]
+        );
+
+        let check = BootCheck::new("/b/disk.img")
+            .with_format(ImageFormat::Qcow2)
+            .with_wait_for(BootWait::Ssh(2222))
+            .with_qemu_args(["-smp", "2"]);
+        let args = check.qemu_command(Some(40022));
+        assert!(args.contains(&"file=/b/disk.img,format=qcow2,if=virtio".to_string()));
+        assert!(
+            args.contains(&"user,id=net0,hostfwd=tcp:127.0.0.1:40022-:2222".to_string()),
+            "{args:?}"
+        );
+        assert_eq!(args[args.len() - 2..], ["-smp", "2"]);
+    }
+
+    #[test]
+    fn ssh_banner_requires_the_ssh_prefix() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            for banner in [&b"HTTP/1.1 400"[..], b"SSH-2.0-OpenSSH_9.2\r\n"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                stream.write_all(banner).unwrap();
+            }
+        });
+        assert!(!ssh_banner(port));
+        assert!(ssh_banner(port));
+        server.join().unwrap();
+        assert!(!ssh_banner(port), "nothing listens any more");
+    }
+
+    #[test]
+    fn execute_waits_for_the_console_and_powers_off() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        let image = dir.join("disk.img");
+        fs::write(&image, "").unwrap();
+        fs::write(format!("{}.boot.log", image), "login: (stale)\n").unwrap();
+        // A fake qemu writing its `-name` to the `-serial file:` log and exiting on
+        // the monitor's `system_powerdown`.
+        let qemu = dir.join("qemu");
+        fs::write(
+            &qemu,
+            "#!/bin/sh\nwhile [ $# -gt 0 ]; do\n  case $1 in\n    \
+             -serial) log=${2#file:} ;;\n    -name) name=$2 ;;\n  esac\n  shift\ndone\n\
+             printf '%s\\n' \"$name\" >>\"$log\"\nread -r command\n\
+             [ \"$command\" = system_powerdown ] && exit 0\nexit 1\n",
+        )
+        .unwrap();
+        fs::set_permissions(&qemu, fs::Permissions::from_mode(0o755)).unwrap();
+        let check = |print: &str| {
+            BootCheck::new(&image)
+                .with_qemu(qemu.as_str())
+                .with_timeout_secs(2)
+                .with_qemu_args(["-name", print])
+        };
+
+        check("Debian GNU/Linux 13 host ttyS0\nhost login:")
+            .execute(false)
+            .expect("the guest prints the login prompt");
+        let err = check("Kernel panic - not syncing")
+            .execute(false)
+            .unwrap_err();
+        assert!(err.to_string().contains("did not print \"login:\""), "{err}");
+        assert!(
+            fs::read_to_string(format!("{}.boot.log", image))
+                .unwrap()
+                .starts_with("Kernel panic"),
+            "the stale log is replaced"
+        );
+
+        let err = BootCheck::new(dir.join("missing.img"))
+            .with_qemu(qemu.as_str())
+            .execute(false)
+            .unwrap_err();
+        assert!(err.to_string().contains("does not exist"), "{err}");
+        BootCheck::new(dir.join("missing.img"))
+            .execute(true)
+            .expect("a dry run only logs the command");
+    }
+
+    #[test]
+    fn execute_stops_on_a_cancel() {
+        use std::os::unix::fs::PermissionsExt;
+        use std::sync::Arc;
+        use std::sync::atomic::AtomicBool;
+
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        let image = dir.join("disk.img");
+        fs::write(&image, "").unwrap();
+        // A fake qemu whose guest never boots.
+        let qemu = dir.join("qemu");
+        fs::write(
+            &qemu,
+            "#!/bin/sh
+exec sleep 60
+",
+        )
+        .unwrap();
+        fs::set_permissions(&qemu, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let started = Instant::now();
+        executor::watch_cancel(Some(Arc::new(AtomicBool::new(true))));
+        let result = BootCheck::new(&image)
+            .with_qemu(qemu.as_str())
+            .with_timeout_secs(60)
+            .execute(false);
+        executor::watch_cancel(None);
+
+        let err = result.unwrap_err();
+        assert!(crate::error::is_interrupted(&err), "{err:#}");
+        assert!(started.elapsed() < Duration::from_secs(30));
+    }
+}
@@ -0,0 +1,699 @@
+//! Verify phase module for assertions about the finished rootfs.
+//!
+//! This module provides the [`VerifyTask`] enum: checks that run after the assemble
+//! phase, each as one query inside a plain chroot:
+//! - `file_exists` — a path exists in the rootfs (`test -e`)
+//! - `command_succeeds` — a shell command exits with status 0
+//! - `package_installed` — dpkg reports a package as installed
+//! - `service_enabled` — `systemctl is-enabled` accepts a unit
+//! - `test_suite` — a host executable, staged into the rootfs and run there, exits
+//!   with status 0 and writes a JUnit XML report without failures
+//! - `boot` — a disk image boots in qemu (see [`boot`]); this check runs on the host
+//!
+//! Unlike the other phases, a failed check does not stop the phase: every check runs,
+//! the pipeline logs a pass/fail report, and the build then fails with
+//! [`RsdebstrapError::Verification`] if any check failed.
+//!
+//! Nothing keeps a check from changing the rootfs: the chroot is the finished,
+//! writable rootfs, the checks run as root, a `command_succeeds` command can write
+//! anywhere in it, and `test_suite` stages its suite and report there (both removed
+//! afterwards), so a suite can change the rootfs too.
+
+pub mod boot;
+
+use std::borrow::Cow;
+use std::fs;
+use std::io::Read;
+
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::config::IsolationConfig;
+use crate::error::RsdebstrapError;
+use crate::isolation::IsolationContext;
+use crate::phase::{
+    PhaseItem, ScriptSource, TempFileGuard, check_execution_result, execute_in_context,
+    validate_host_file_exists, validate_no_parent_dirs,
+};
+use crate::privilege::PrivilegeMethod;
+
+pub use boot::{BootCheck, BootWait, ImageFormat};
+
+/// Environment variable holding the path, inside the rootfs, that a `test_suite`
+/// check writes its JUnit XML report to.
+pub const JUNIT_XML_ENV: &str = "RSDEBSTRAP_JUNIT_XML";
+
+/// Prints each architecture's install status of `$1`, one per line, and succeeds if
+/// any of them is `installed`.
+const PACKAGE_INSTALLED_SCRIPT: &str = "dpkg-query --show --showformat='${db:Status-Status}\\n' \
+     \"$1\" 2>/dev/null | grep -qx installed";
+
+/// A verify phase assertion, discriminated by the `type` field.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum VerifyTask {
+    /// Passes if the absolute `path` exists in the rootfs (symlinks are followed
+    /// inside the chroot).
+    FileExists {
+        /// Absolute path inside the rootfs.
+        #[serde(deserialize_with = "crate::de::path")]
+        #[cfg_attr(feature = "schema", schemars(with = "String"))]
+        path: Utf8PathBuf,
+    },
+    /// Passes if `command`, run with `/bin/sh -c`, exits with status 0. The command
+    /// should only inspect the rootfs, like the built-in checks.
+    CommandSucceeds {
+        /// Shell command to run inside the rootfs.
+        #[serde(deserialize_with = "crate::de::string")]
+        command: String,
+    },
+    /// Passes if dpkg reports `package` (optionally `package:arch`) as installed.
+    PackageInstalled {
+        /// Debian package name.
+        #[serde(deserialize_with = "crate::de::string")]
+        package: String,
+    },
+    /// Passes if `systemctl is-enabled` accepts `service` (enabled, static, alias, ...).
+    ServiceEnabled {
+        /// systemd unit name; `.service` is implied when no suffix is given.
+        #[serde(deserialize_with = "crate::de::string")]
+        service: String,
+    },
+    /// Passes if the host executable `suite`, copied into the rootfs and run there
+    /// with `args`, exits with status 0 and writes a JUnit XML report to
+    /// `$RSDEBSTRAP_JUNIT_XML` that records no failures or errors. The report is
+    /// copied to `results` on the host.
+    TestSuite {
+        /// Host path of the test suite executable (relative to the profile).
+        #[serde(deserialize_with = "crate::de::path")]
+        #[cfg_attr(feature = "schema", schemars(with = "String"))]
+        suite: Utf8PathBuf,
+        /// Arguments passed to the test suite.
+        #[serde(
+            default,
+            deserialize_with = "crate::de::string_list",
+            skip_serializing_if = "Vec::is_empty"
+        )]
+        #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<String>>"))]
+        args: Vec<String>,
+        /// Host path the JUnit XML report is copied to, relative to `dir`
+        /// (default: `<dir>/<suite file name>.junit.xml`).
+        #[serde(
+            default,
+            deserialize_with = "crate::de::opt_path",
+            skip_serializing_if = "Option::is_none"
+        )]
+        #[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+        results: Option<Utf8PathBuf>,
+    },
+    /// Passes if a disk image boots in qemu far enough to print a console string or
+    /// answer SSH.
+    Boot(BootCheck),
+}
+
+impl VerifyTask {
+    /// Returns the check's display name, e.g. `package_installed:openssh-server`.
+    pub fn name(&self) -> String {
+        match self {
+            Self::FileExists { path } => format!("file_exists:{}", path),
+            Self::CommandSucceeds { command } => format!("command_succeeds:{}", command),
+            Self::PackageInstalled { package } => format!("package_installed:{}", package),
+            Self::ServiceEnabled { service } => format!("service_enabled:{}", service),
+            Self::TestSuite { suite, .. } => {
+                format!("test_suite:{}", suite.file_name().unwrap_or(suite.as_str()))
+            }
+            Self::Boot(check) => format!("boot:{}", check.name()),
+        }
+    }
+
+    /// Resolves `suite` against the profile file's directory, and `results` against
+    /// the output directory `dir`, defaulting it to `<dir>/<suite file name>.junit.xml`.
+    pub(crate) fn resolve_paths(&mut self, profile_dir: &Utf8Path, dir: &Utf8Path) {
+        if let Self::Boot(check) = self {
+            check.resolve_paths(profile_dir, dir);
+        }
+        if let Self::TestSuite { suite, results, .. } = self {
+            if suite.is_relative() {
+                *suite = profile_dir.join(&*suite);
+            }
+            let results = results.get_or_insert_with(|| {
+                format!("{}.junit.xml", suite.file_name().unwrap_or("test-suite")).into()
+            });
+            if results.is_relative() {
+                *results = dir.join(&*results);
+            }
+        }
+    }
+
+    /// Validates the check's configuration.
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        match self {
+            Self::FileExists { path } => {
+                if !path.is_absolute() || path.as_str().contains(['\0', '\n', '\r']) {
+                    return Err(RsdebstrapError::Validation(format!(
+                        "verify file_exists: path {:?} must be absolute without null or \
+                        newline characters",
+                        path
+                    )));
+                }
+                validate_no_parent_dirs(path, "verify file_exists")
+            }
+            Self::CommandSucceeds { command } => {
+                if command.trim().is_empty() || command.contains('\0') {
+                    return Err(RsdebstrapError::Validation(
+                        "verify command_succeeds: command must not be empty or contain null \
+                        characters"
+                            .to_string(),
+                    ));
+                }
+                Ok(())
+            }
+            Self::PackageInstalled { package } => {
+                if !is_package_name(package) {
+                    return Err(RsdebstrapError::Validation(format!(
+                        "verify package_installed: {:?} is not a Debian package name \
+                        (optionally followed by ':<arch>')",
+                        package
+                    )));
+                }
+                Ok(())
+            }
+            Self::ServiceEnabled { service } => {
+                if service.is_empty()
+                    || service.starts_with('-')
+                    || !service
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || ":_.@\\-".contains(c))
+                {
+                    return Err(RsdebstrapError::Validation(format!(
+                        "verify service_enabled: {:?} is not a systemd unit name",
+                        service
+                    )));
+                }
+                Ok(())
+            }
+            Self::TestSuite {
+                suite,
+                args,
+                results,
+            } => {
+                validate_no_parent_dirs(suite, "verify test_suite")?;
+                validate_host_file_exists(suite, "verify test_suite")?;
+                if args.iter().any(|arg| arg.contains('\0')) {
+                    return Err(RsdebstrapError::Validation(
+                        "verify test_suite: args must not contain null characters".to_string(),
+                    ));
+                }
+                if results
+                    .as_ref()
+                    .is_some_and(|results| results.file_name().is_none())
+                {
+                    return Err(RsdebstrapError::Validation(format!(
+                        "verify test_suite: results {:?} must name a file",
+                        results
+                    )));
+                }
+                Ok(())
+            }
+            Self::Boot(check) => check.validate(),
+        }
+    }
+
+    /// Returns the command that performs the check inside the rootfs, or `None` for
+    /// a `test_suite` check, whose command depends on where the suite is staged (see
+    /// [`test_suite_command`]), and a `boot` check, which runs on the host.
+    pub fn command(&self) -> Option<Vec<String>> {
+        let command: Vec<&str> = match self {
+            Self::FileExists { path } => vec!["test", "-e", path.as_str()],
+            Self::CommandSucceeds { command } => vec!["/bin/sh", "-c", command],
+            Self::PackageInstalled { package } => {
+                vec!["/bin/sh", "-c", PACKAGE_INSTALLED_SCRIPT, "sh", package]
+            }
+            Self::ServiceEnabled { service } => {
+                vec!["systemctl", "is-enabled", "--quiet", service]
+            }
+            Self::TestSuite { .. } | Self::Boot(_) => return None,
+        };
+        Some(command.into_iter().map(String::from).collect())
+    }
+
+    /// Executes the check; an error means the check failed (or could not run).
+    pub fn execute(
+        &self,
+        ctx: &dyn IsolationContext,
+        privilege: Option<PrivilegeMethod>,
+    ) -> Result<()> {
+        if let Self::TestSuite {
+            suite,
+            args,
+            results,
+        } = self
+        {
+            return run_test_suite(ctx, privilege, suite, args, results.as_deref());
+        }
+        if let Self::Boot(check) = self {
+            return check.execute(ctx.dry_run());
+        }
+        let command = self
+            .command()
+            .expect("every check but test_suite and boot has a single command");
+        let result = execute_in_context(ctx, &command, "verify check", privilege)?;
+        check_execution_result(&result, &command, ctx.name(), ctx.dry_run())
+    }
+}
+
+/// Returns the command running the staged test suite `suite` with `args`, telling it
+/// to write its JUnit XML report to `xml` (both paths inside the isolation).
+pub fn test_suite_command(suite: &str, xml: &str, args: &[String]) -> Vec<String> {
+    let mut command = vec![
+        "env".to_string(),
+        format!("{}={}", JUNIT_XML_ENV, xml),
+        suite.to_string(),
+    ];
+    command.extend(args.iter().cloned());
+    command
+}
+
+/// Stages `suite` into the rootfs, runs it, copies the JUnit XML report it writes to
+/// `results`, and fails unless it exited with status 0 and the report records no
+/// failures or errors.
+fn run_test_suite(
+    ctx: &dyn IsolationContext,
+    privilege: Option<PrivilegeMethod>,
+    suite: &Utf8Path,
+    args: &[String],
+    results: Option<&Utf8Path>,
+) -> Result<()> {
+    let dry_run = ctx.dry_run();
+    if !dry_run {
+        crate::phase::validate_staging_directory(ctx.rootfs(), ctx.staging_dir())
+            .context("rootfs validation failed")?;
+    }
+
+    let staged_name = format!("test-suite-{}", uuid::Uuid::new_v4());
+    let (target, in_isolation) = crate::phase::staged_file_paths(ctx, &staged_name);
+    let (xml_target, xml_in_isolation) =
+        crate::phase::staged_file_paths(ctx, &format!("{}.xml", staged_name));
+    let _suite_guard = TempFileGuard::new(target.clone(), dry_run);
+    let _xml_guard = TempFileGuard::new(xml_target.clone(), dry_run);
+    crate::phase::prepare_files_with_toctou_check(ctx, || {
+        let source = ScriptSource::Script(suite.to_path_buf());
+        crate::phase::prepare_source_file(&source, &target, 0o700, "test suite")?;
+        // Created by rsdebstrap, so the report stays readable on the host when the
+        // suite truncates and rewrites it as root.
+        fs::write(&xml_target, "")
+            .with_context(|| format!("failed to create JUnit report file {}", xml_target))?;
+        #[cfg(unix)]
+        crate::phase::set_file_mode(&xml_target, 0o644)?;
+        Ok(())
+    })?;
+    ctx.hand_over(&[in_isolation.clone(), xml_in_isolation.clone()], privilege)?;
+
+    let command = test_suite_command(&in_isolation, &xml_in_isolation, args);
+    let result = execute_in_context(ctx, &command, "verify test suite", privilege)?;
+    if dry_run {
+        return check_execution_result(&result, &command, ctx.name(), dry_run);
+    }
+
+    let xml = read_report(&xml_target)?;
+    if let (Some(results), false) = (results, xml.is_empty()) {
+        if let Some(parent) = results.parent().filter(|p| !p.as_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create directory {}", parent))?;
+        }
+        fs::write(results, &xml)
+            .with_context(|| format!("failed to write JUnit report to {}", results))?;
+        info!("test suite report written to {}", results);
+    }
+    check_execution_result(&result, &command, ctx.name(), dry_run)?;
+
+    let failed = |status: String| -> anyhow::Error {
+        RsdebstrapError::execution_in_isolation(&command, ctx.name(), status).into()
+    };
+    if xml.is_empty() {
+        return Err(failed(format!("wrote no JUnit report to ${}", JUNIT_XML_ENV)));
+    }
+    let summary = JunitSummary::parse(&xml)
+        .map_err(|e| failed(format!("wrote an invalid JUnit report: {}", e)))?;
+    info!(
+        "test suite ran {} test case(s): {} failed, {} skipped",
+        summary.tests,
+        summary.failures + summary.errors,
+        summary.skipped
+    );
+    if summary.failures + summary.errors > 0 {
+        return Err(failed(format!(
+            "{} of {} test case(s) failed",
+            summary.failures + summary.errors,
+            summary.tests
+        )));
+    }
+    Ok(())
+}
+
+/// Reads the JUnit report at `path` without following a symlink the suite may have
+/// put in its place.
+fn read_report(path: &Utf8Path) -> Result<String> {
+    use rustix::fs::{Mode, OFlags};
+
+    let fd = match rustix::fs::open(
+        path.as_std_path(),
+        OFlags::NOFOLLOW | OFlags::RDONLY | OFlags::CLOEXEC | OFlags::NONBLOCK,
+        Mode::empty(),
+    ) {
+        Ok(fd) => fd,
+        Err(rustix::io::Errno::NOENT) => return Ok(String::new()),
+        Err(e) => {
+            return Err(RsdebstrapError::io(
+                format!("failed to open JUnit report {}", path),
+                e.into(),
+            )
+            .into());
+        }
+    };
+    let mut file = fs::File::from(fd);
+    if !file.metadata()?.is_file() {
+        return Err(RsdebstrapError::Validation(format!(
+            "JUnit report {} is not a regular file",
+            path
+        ))
+        .into());
+    }
+    let mut xml = String::new();
+    file.read_to_string(&mut xml)
+        .with_context(|| format!("failed to read JUnit report {}", path))?;
+    Ok(xml)
+}
+
+/// Test case counts of a JUnit XML report.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct JunitSummary {
+    /// `<testcase>` elements.
+    pub tests: usize,
+    /// `<failure>` elements.
+    pub failures: usize,
+    /// `<error>` elements.
+    pub errors: usize,
+    /// `<skipped>` elements.
+    pub skipped: usize,
+}
+
+impl JunitSummary {
+    /// Counts the elements of a JUnit XML report, skipping comments, CDATA sections
+    /// and processing instructions. Fails unless the report has a `<testsuite>` or
+    /// `<testsuites>` element.
+    pub fn parse(xml: &str) -> Result<Self, RsdebstrapError> {
+        let mut summary = Self::default();
+        let mut has_suite = false;
+        let mut rest = xml;
+        while let Some(start) = rest.find('<') {
+            rest = &rest[start + 1..];
+            let skip_to = if rest.starts_with("!--") {
+                Some("-->")
+            } else if rest.starts_with("![CDATA[") {
+                Some("]]>")
+            } else if rest.starts_with('?') {
+                Some("?>")
+            } else {
+                None
+            };
+            if let Some(end) = skip_to {
+                let Some(at) = rest.find(end) else {
+                    return Err(RsdebstrapError::Validation(format!(
+                        "unterminated markup, expected '{}'",
+                        end
+                    )));
+                };
+                rest = &rest[at + end.len()..];
+                continue;
+            }
+            let name_len = rest
+                .find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+                .unwrap_or(rest.len());
+            match &rest[..name_len] {
+                "testsuite" | "testsuites" => has_suite = true,
+                "testcase" => summary.tests += 1,
+                "failure" => summary.failures += 1,
+                "error" => summary.errors += 1,
+                "skipped" => summary.skipped += 1,
+                _ => {}
+            }
+        }
+        if !has_suite {
+            return Err(RsdebstrapError::Validation(
+                "no <testsuite> or <testsuites> element".to_string(),
+            ));
+        }
+        Ok(summary)
+    }
+}
+
+/// Returns `true` if `name` is a Debian package name, optionally qualified with an
+/// architecture (`libc6:amd64`).
+fn is_package_name(name: &str) -> bool {
+    let (package, arch) = match name.split_once(':') {
+        Some((package, arch)) => (package, Some(arch)),
+        None => (name, None),
+    };
+    let package_ok = package.len() >= 2
+        && package.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && package
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "+-.".contains(c));
+    let arch_ok = arch.is_none_or(|a| {
+        !a.is_empty()
+            && a.chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    });
+    package_ok && arch_ok
+}
+
+/// A [`VerifyTask`] bound to the chroot and privilege method it runs with, as the
+/// pipeline runs it.
+///
+/// Checks run in a plain chroot as root without network access — a default
+/// `IsolationConfig`, not `defaults.isolation` — using `defaults.privilege`.
+#[derive(Debug)]
+pub(crate) struct VerifyItem<'a> {
+    task: &'a VerifyTask,
+    isolation: IsolationConfig,
+    privilege: Option<PrivilegeMethod>,
+}
+
+impl<'a> VerifyItem<'a> {
+    pub(crate) fn new(task: &'a VerifyTask, privilege: Option<PrivilegeMethod>) -> Self {
+        Self {
+            task,
+            isolation: IsolationConfig::default(),
+            privilege,
+        }
+    }
+}
+
+impl PhaseItem for VerifyItem<'_> {
+    fn name(&self) -> Cow<'_, str> {
+        Cow::Owned(self.task.name())
+    }
+
+    fn validate(&self) -> Result<(), RsdebstrapError> {
+        self.task.validate()
+    }
+
+    fn execute(&self, ctx: &dyn IsolationContext) -> Result<()> {
+        self.task.execute(ctx, self.privilege)
+    }
+
+    fn resolved_isolation_config(&self) -> Option<&IsolationConfig> {
+        Some(&self.isolation)
+    }
+
+    fn network_enabled(&self) -> bool {
+        false
+    }
+
+    fn isolation_privilege(&self) -> Option<PrivilegeMethod> {
+        self.privilege
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(yaml: &str) -> VerifyTask {
+        yaml_serde::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn deserialize_each_check() {
+        for (yaml, name) in [
+            ("type: file_exists\npath: /etc/hostname\n", "file_exists:/etc/hostname"),
+            ("type: command_succeeds\ncommand: sshd -t\n", "command_succeeds:sshd -t"),
+            (
+                "type: package_installed\npackage: openssh-server\n",
+                "package_installed:openssh-server",
+            ),
+            ("type: service_enabled\nservice: ssh\n", "service_enabled:ssh"),
+            ("type: test_suite\nsuite: /bin/true\nargs:\n- --junit\n", "test_suite:true"),
+            ("type: boot\nimage: disk.img\nwait_for:\n  ssh: 22\n", "boot:disk.img"),
+        ] {
+            let task = task(yaml);
+            assert_eq!(task.name(), name);
+            task.validate().unwrap();
+            assert_eq!(yaml_serde::to_string(&task).unwrap(), yaml);
+        }
+        for yaml in [
+            "type: file_exists\n",
+            "type: file_exists\npath: /a\ncommand: b\n",
+            "type: port_open\nport: 22\n",
+        ] {
+            assert!(yaml_serde::from_str::<VerifyTask>(yaml).is_err(), "{yaml}");
+        }
+    }
+
+    #[test]
+    fn validate_rejects_bad_checks() {
+        for yaml in [
+            "type: file_exists\npath: etc/hostname\n",
+            "type: file_exists\npath: /etc/../root\n",
+            "type: command_succeeds\ncommand: '  '\n",
+            "type: package_installed\npackage: OpenSSH\n",
+            "type: package_installed\npackage: 'a; rm -rf /'\n",
+            "type: package_installed\npackage: 'libc6:'\n",
+            "type: service_enabled\nservice: '--root=/'\n",
+            "type: service_enabled\nservice: 'ssh service'\n",
+        ] {
+            let err = task(yaml).validate().unwrap_err();
+            assert!(matches!(err, RsdebstrapError::Validation(_)), "{yaml}: {err}");
+        }
+        task("type: package_installed\npackage: libc6:amd64\n")
+            .validate()
+            .unwrap();
+        task("type: service_enabled\nservice: getty@tty1.service\n")
+            .validate()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_suite_validation_and_paths() {
+        for yaml in [
+            "type: test_suite\nsuite: /nonexistent/suite\n",
+            "type: test_suite\nsuite: /bin/../bin/true\n",
+            "type: test_suite\nsuite: /bin/true\nresults: /\n",
+        ] {
+            assert!(task(yaml).validate().is_err(), "{yaml}");
+        }
+
+        let mut check = task("type: test_suite\nsuite: suites/smoke.sh\n");
+        check.resolve_paths(Utf8Path::new("/profiles"), Utf8Path::new("/out"));
+        assert_eq!(
+            check,
+            task(
+                "type: test_suite\nsuite: /profiles/suites/smoke.sh\n\
+                 results: /out/smoke.sh.junit.xml\n"
+            )
+        );
+        assert_eq!(check.command(), None);
+        assert_eq!(
+            test_suite_command("/tmp/s", "/tmp/s.xml", &["-v".to_string()]),
+            ["env", "RSDEBSTRAP_JUNIT_XML=/tmp/s.xml", "/tmp/s", "-v"]
+        );
+    }
+
+    #[test]
+    fn junit_summary_counts_test_cases() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuites>
+  <testsuite name="image" tests="4" failures="1" errors="1">
+    <!-- <testcase name="commented out"><failure/></testcase> -->
+    <testcase name="ssh"/>
+    <testcase name="dns"><failure message="no resolver">boom</failure></testcase>
+    <testcase name="ntp"><error><![CDATA[<failure>]]></error></testcase>
+    <testcase name="gpu"><skipped/></testcase>
+  </testsuite>
+</testsuites>
+"#;
+        assert_eq!(
+            JunitSummary::parse(xml).unwrap(),
+            JunitSummary {
+                tests: 4,
+                failures: 1,
+                errors: 1,
+                skipped: 1,
+            }
+        );
+        assert_eq!(
+            JunitSummary::parse("<testsuite tests=\"0\"/>").unwrap(),
+            JunitSummary::default()
+        );
+        for xml in ["not xml", "<testcase/>", "<testsuite><!-- unterminated"] {
+            assert!(JunitSummary::parse(xml).is_err(), "{xml}");
+        }
+    }
+
+    #[test]
+    fn read_report_refuses_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        assert_eq!(read_report(&dir.join("missing.xml")).unwrap(), "");
+        fs::write(dir.join("report.xml"), "<testsuite/>").unwrap();
+        assert_eq!(read_report(&dir.join("report.xml")).unwrap(), "<testsuite/>");
+        std::os::unix::fs::symlink(dir.join("report.xml"), dir.join("link.xml")).unwrap();
+        assert!(read_report(&dir.join("link.xml")).is_err());
+        assert!(read_report(dir).is_err());
+    }
+
+    #[test]
+    fn commands_pass_values_as_arguments() {
+        assert_eq!(
+            task("type: file_exists\npath: /etc/hostname\n")
+                .command()
+                .unwrap(),
+            ["test", "-e", "/etc/hostname"]
+        );
+        assert_eq!(
+            task("type: package_installed\npackage: g++\n")
+                .command()
+                .unwrap(),
+            ["/bin/sh", "-c", PACKAGE_INSTALLED_SCRIPT, "sh", "g++"]
+        );
+        assert_eq!(
+            task("type: service_enabled\nservice: ssh\n")
+                .command()
+                .unwrap(),
+            ["systemctl", "is-enabled", "--quiet", "ssh"]
+        );
+    }
+
+    #[test]
+    fn package_installed_script_matches_any_installed_arch() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let fake = dir.path().join("dpkg-query");
+        std::fs::write(
+            &fake,
+            "#!/bin/sh\ncase \"$3\" in\n  libc6) printf 'installed\\nnot-installed\\n' ;;\n  \
+             vim) printf 'config-files\\n' ;;\n  *) exit 1 ;;\nesac\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&fake, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let path = format!("{}:{}", dir.path().display(), std::env::var("PATH").unwrap());
+        let run = |package: &str| {
+            std::process::Command::new("/bin/sh")
+                .args(["-c", PACKAGE_INSTALLED_SCRIPT, "sh", package])
+                .env("PATH", &path)
+                .status()
+                .unwrap()
+                .success()
+        };
+        assert!(run("libc6"));
+        assert!(!run("vim"));
+        assert!(!run("missing"));
+    }
+}
@@ -1,29 +1,152 @@
 //! Pipeline orchestrator for executing tasks in phases.
 //!
 //! The pipeline manages per-task isolation contexts and executes
-//! tasks in three ordered phases:
+//! tasks in four ordered phases:
 //!
 //! 1. **Prepare** — preparation tasks before main provisioning
 //! 2. **Provision** — main configuration tasks (e.g., package installation, config)
 //! 3. **Assemble** — finalization tasks (e.g., cleanup scripts, image creation)
+//! 4. **Verify** — checks of the result, all run before the phase fails
 //!
 //! Each task gets its own isolation context based on its resolved isolation setting.
 
 use anyhow::{Context, Result};
-use camino::Utf8Path;
+use camino::{Utf8Path, Utf8PathBuf};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
-use crate::error::RsdebstrapError;
-use crate::executor::CommandExecutor;
+use crate::config::IsolationConfig;
+use crate::error::{RsdebstrapError, Stage};
+use crate::executor::{CommandExecutor, OfflineExecutor, OutputPrefix};
+use crate::isolation::mount::RootfsMounts;
+use crate::isolation::staging::{StagedContext, TMP_STAGING_DIR};
 use crate::isolation::{DirectProvider, IsolationProvider};
-use crate::phase::{AssembleConfig, PhaseItem, PrepareConfig, ProvisionTask};
+use crate::layer_cache::{LayerCache, Layers};
+use crate::phase::verify::VerifyItem;
+use crate::phase::{AssembleConfig, PhaseItem, PrepareConfig, ProvisionTask, VerifyTask};
+use crate::privilege::PrivilegeMethod;
+use crate::progress::{Progress, ProgressEvent};
+use crate::run_dir::RunId;
+use crate::sandbox::Sandbox;
+use crate::secrets::{Secret, SecretContext, Secrets};
 
 // Phase name constants to avoid duplication between validate(),
 // run_prepare_and_provision(), and run_assemble()
 const PHASE_PREPARE: &str = "prepare";
 const PHASE_PROVISION: &str = "provision";
 const PHASE_ASSEMBLE: &str = "assemble";
+const PHASE_VERIFY: &str = "verify";
+
+/// Which pipeline stages a run executes.
+///
+/// `provision` covers the prepare and provision phases, which always run
+/// together; `assemble` and `verify` cover one phase each. Used by `apply --only` /
+/// `--skip` to re-run part of a pipeline against an existing rootfs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhaseSelection {
+    /// Run the prepare and provision phases.
+    pub provision: bool,
+    /// Run the assemble phase.
+    pub assemble: bool,
+    /// Run the verify phase.
+    pub verify: bool,
+}
+
+impl PhaseSelection {
+    /// Runs every phase.
+    pub const ALL: Self = Self {
+        provision: true,
+        assemble: true,
+        verify: true,
+    };
+
+    /// Returns true if no pipeline stage is selected.
+    pub fn is_none(&self) -> bool {
+        !self.provision && !self.assemble && !self.verify
+    }
+}
+
+impl Default for PhaseSelection {
+    fn default() -> Self {
+        Self::ALL
+    }
+}
+
+/// What a phase does when one of its tasks fails.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum FailurePolicy {
+    /// Stop at the first failed task (default).
+    #[default]
+    FailFast,
+    /// Run the remaining tasks, then fail with every task failure.
+    RunAllThenFail,
+}
+
+impl FailurePolicy {
+    /// Returns true for the default policy, so it is left out of serialized profiles.
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Failure policy of each phase that runs a list of tasks (`failure_policy`).
+///
+/// The prepare phase always stops at the first failure, since the tasks after it
+/// depend on its mounts; the verify phase always runs every check.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct FailurePolicies {
+    /// Policy of the provision phase (default: fail_fast).
+    #[serde(default, skip_serializing_if = "FailurePolicy::is_default")]
+    pub provision: FailurePolicy,
+    /// Policy of the assemble phase (default: fail_fast).
+    #[serde(default, skip_serializing_if = "FailurePolicy::is_default")]
+    pub assemble: FailurePolicy,
+}
+
+impl FailurePolicies {
+    /// Returns true if every phase uses the default policy.
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Selects provision tasks by their `tags`, like `apply --tags`/`--skip-tags`.
+///
+/// With `include` tags, only tasks carrying at least one of them run (untagged
+/// tasks are skipped); a task carrying any `exclude` tag never runs. The default
+/// filter runs every task.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagFilter {
+    /// Tags of which a task must carry at least one (empty: no requirement).
+    pub include: Vec<String>,
+    /// Tags that skip a task carrying any of them.
+    pub exclude: Vec<String>,
+}
+
+impl TagFilter {
+    /// Returns true if a task with `tags` passes the filter.
+    pub fn matches(&self, tags: &[String]) -> bool {
+        let included = self.include.is_empty() || tags.iter().any(|t| self.include.contains(t));
+        included && !tags.iter().any(|t| self.exclude.contains(t))
+    }
+
+    /// Returns the filter's tags that none of `tasks` carries, in filter order.
+    pub fn unused_tags<'f>(&'f self, tasks: &[ProvisionTask]) -> Vec<&'f str> {
+        self.include
+            .iter()
+            .chain(&self.exclude)
+            .filter(|tag| !tasks.iter().any(|t| t.tags().contains(tag)))
+            .map(String::as_str)
+            .collect()
+    }
+}
 
 /// Pipeline orchestrator for executing tasks in phases.
 ///
@@ -36,6 +159,29 @@ pub struct Pipeline<'a> {
     prepare: &'a PrepareConfig,
     provision: &'a [ProvisionTask],
     assemble: &'a AssembleConfig,
+    verify: &'a [VerifyTask],
+    /// Privilege method the verify checks run with (`defaults.privilege`).
+    verify_privilege: Option<PrivilegeMethod>,
+    /// Where tasks stage their files inside the rootfs (default: `/tmp`).
+    staging_dir: Utf8PathBuf,
+    /// Stages to run (default: all).
+    selection: PhaseSelection,
+    /// What the provision and assemble phases do when a task fails.
+    failure_policy: FailurePolicies,
+    /// Provision tasks to run by tag (default: all).
+    tag_filter: TagFilter,
+    /// `name` of the provision task to start at, skipping those before it.
+    start_at_task: Option<String>,
+    /// Observer told as each task starts and finishes.
+    progress: Option<&'a dyn Progress>,
+    /// Cache the provision tasks' layers are replayed from and captured into.
+    layer_cache: Option<&'a LayerCache>,
+    /// Resolved `secrets`, handed to the provision tasks.
+    secrets: Option<&'a Secrets>,
+    /// ID of the run, which names the files tasks stage (default: random names).
+    run_id: Option<&'a RunId>,
+    /// Restrictions on the commands of tasks run with `isolation: false`.
+    sandbox: Option<Sandbox>,
 }
 
 impl<'a> Pipeline<'a> {
@@ -49,17 +195,165 @@ impl<'a> Pipeline<'a> {
             prepare,
             provision,
             assemble,
+            verify: &[],
+            verify_privilege: None,
+            staging_dir: Utf8PathBuf::from(TMP_STAGING_DIR),
+            selection: PhaseSelection::ALL,
+            failure_policy: FailurePolicies::default(),
+            tag_filter: TagFilter::default(),
+            start_at_task: None,
+            progress: None,
+            layer_cache: None,
+            secrets: None,
+            run_id: None,
+            sandbox: None,
         }
     }
 
-    /// Returns true if the pipeline has no tasks to execute.
+    /// Adds the verify phase: `tasks` run after the assemble phase, in a plain chroot
+    /// with `privilege`.
+    pub fn with_verify(
+        mut self,
+        tasks: &'a [VerifyTask],
+        privilege: Option<PrivilegeMethod>,
+    ) -> Self {
+        self.verify = tasks;
+        self.verify_privilege = privilege;
+        self
+    }
+
+    /// Sets the directory, as seen inside the rootfs, where tasks stage their files.
+    pub fn with_staging_dir(mut self, staging_dir: &Utf8Path) -> Self {
+        self.staging_dir = staging_dir.to_owned();
+        self
+    }
+
+    /// Names the files tasks stage after `run_id` and the task,
+    /// `<kind>-<run-id>-<phase>-<number>`, instead of random UUIDs.
+    pub fn with_run_id(mut self, run_id: Option<&'a RunId>) -> Self {
+        self.run_id = run_id;
+        self
+    }
+
+    /// Returns the run ID set by [`with_run_id`](Self::with_run_id).
+    pub fn run_id(&self) -> Option<&'a RunId> {
+        self.run_id
+    }
+
+    /// Restricts the run to the selected stages. Validation still covers every phase.
+    pub fn with_selection(mut self, selection: PhaseSelection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    /// Sets what the provision and assemble phases do when a task fails.
+    pub fn with_failure_policy(mut self, policy: FailurePolicies) -> Self {
+        self.failure_policy = policy;
+        self
+    }
+
+    /// Restricts the provision phase to tasks passing `filter`. Validation still
+    /// covers every task.
+    pub fn with_tag_filter(mut self, filter: TagFilter) -> Self {
+        self.tag_filter = filter;
+        self
+    }
+
+    /// Skips the provision tasks before the one whose `name` is `name`; if no task
+    /// has that name, no provision task runs. Validation still covers every task.
+    pub fn with_start_at_task(mut self, name: Option<&str>) -> Self {
+        self.start_at_task = name.map(str::to_string);
+        self
+    }
+
+    /// Reports each task's start and finish to `progress`.
+    pub fn with_progress(mut self, progress: Option<&'a dyn Progress>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Replays the leading provision tasks from `cache` and captures the layers of
+    /// the tasks that run (`apply --layer-cache`).
+    pub fn with_layer_cache(mut self, cache: Option<&'a LayerCache>) -> Self {
+        self.layer_cache = cache;
+        self
+    }
+
+    /// Hands `secrets` to the provision tasks that receive them.
+    pub fn with_secrets(mut self, secrets: Option<&'a Secrets>) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    /// Runs the commands of tasks with `isolation: false` in `sandbox`.
+    pub fn with_sandbox(mut self, sandbox: Option<Sandbox>) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    /// Returns true if the selected phases have no tasks to execute.
     pub fn is_empty(&self) -> bool {
-        self.prepare.is_empty() && self.provision.is_empty() && self.assemble.is_empty()
+        self.total_tasks() == 0
     }
 
-    /// Returns the total number of tasks across all phases.
+    /// Returns the total number of tasks across the selected phases.
     pub fn total_tasks(&self) -> usize {
-        self.prepare.len() + self.provision.len() + self.assemble.len()
+        let provision = if self.selection.provision {
+            self.prepare.len() + self.selected_provision_items().len()
+        } else {
+            0
+        };
+        let assemble = if self.selection.assemble {
+            self.assemble.len()
+        } else {
+            0
+        };
+        let verify = if self.selection.verify {
+            self.verify.len()
+        } else {
+            0
+        };
+        provision + assemble + verify
+    }
+
+    /// Returns the index of the first provision task to consider under `--start-at-task`.
+    fn start_index(&self) -> usize {
+        match &self.start_at_task {
+            Some(name) => self
+                .provision
+                .iter()
+                .position(|t| t.configured_name() == Some(name.as_str()))
+                .unwrap_or(self.provision.len()),
+            None => 0,
+        }
+    }
+
+    /// Returns the selected stages.
+    pub(crate) fn selection(&self) -> PhaseSelection {
+        self.selection
+    }
+
+    /// Returns the provision tasks from the start task on that pass the tag filter,
+    /// in profile order, numbered by their position in the profile.
+    pub(crate) fn selected_provision_items(&self) -> Vec<(usize, &dyn PhaseItem)> {
+        numbered(provision_items(self.provision))
+            .into_iter()
+            .skip(self.start_index())
+            .filter(|(number, _)| self.tag_filter.matches(self.provision[number - 1].tags()))
+            .collect()
+    }
+
+    /// Returns the assemble tasks, numbered by their position in the phase.
+    pub(crate) fn selected_assemble_items(&self) -> Vec<(usize, &dyn PhaseItem)> {
+        numbered(self.assemble.items())
+    }
+
+    /// Returns the verify checks bound to the chroot they run in, in profile order.
+    pub(crate) fn verify_items(&self) -> Vec<VerifyItem<'a>> {
+        self.verify
+            .iter()
+            .map(|task| VerifyItem::new(task, self.verify_privilege))
+            .collect()
     }
 
     /// Validates all tasks in the pipeline.
@@ -67,7 +361,15 @@ impl<'a> Pipeline<'a> {
         validate_phase_items(PHASE_PREPARE, &self.prepare.items())?;
         validate_phase_items(PHASE_PROVISION, &provision_items(self.provision))?;
         validate_phase_items(PHASE_ASSEMBLE, &self.assemble.items())?;
-        Ok(())
+        let verify = self.verify_items();
+        validate_phase_items(
+            PHASE_VERIFY,
+            &verify
+                .iter()
+                .map(|i| i as &dyn PhaseItem)
+                .collect::<Vec<_>>(),
+        )?;
+        validate_unique_task_names(self.provision)
     }
 
     /// Executes all phases of the pipeline with per-task isolation contexts.
@@ -87,8 +389,8 @@ impl<'a> Pipeline<'a> {
     }
 
     /// Executes the prepare and provision phases (the first pipeline stage)
-    /// and emits the "starting pipeline" banner (counting tasks across all
-    /// three phases).
+    /// and emits the "starting pipeline" banner (counting tasks across the
+    /// selected phases). The phases are skipped if the selection excludes them.
     ///
     /// Callers that need work between provisioning and assembly — e.g.
     /// `run_pipeline_phase()` restoring the temporary resolv.conf — call
@@ -105,21 +407,41 @@ impl<'a> Pipeline<'a> {
         }
 
         info!("starting pipeline with {} task(s)", self.total_tasks());
-        run_phase_items(PHASE_PREPARE, &self.prepare.items(), rootfs, executor, dry_run)?;
-        run_phase_items(
-            PHASE_PROVISION,
-            &provision_items(self.provision),
+        if !self.selection.provision {
+            debug!("skipping {} and {} phases", PHASE_PREPARE, PHASE_PROVISION);
+            return Ok(());
+        }
+        self.run_phase_items(
+            PHASE_PREPARE,
+            &numbered(self.prepare.items()),
             rootfs,
             executor,
             dry_run,
-        )
+            None,
+        )?;
+        let provision = self.selected_provision_items();
+        let start = self.start_index();
+        if start > 0 {
+            info!("skipping {} {} task(s) before the start task", start, PHASE_PROVISION);
+        }
+        let untagged = self.provision.len().saturating_sub(start) - provision.len();
+        if untagged > 0 {
+            info!("skipping {} {} task(s) not selected by tags", untagged, PHASE_PROVISION);
+        }
+        // Dry runs change nothing, so there is nothing to capture or replay.
+        let layers = self
+            .layer_cache
+            .filter(|_| !dry_run)
+            .map(LayerCache::layers);
+        self.run_phase_items(PHASE_PROVISION, &provision, rootfs, executor, dry_run, layers)
     }
 
-    /// Executes the assemble phase (the second pipeline stage) and logs
-    /// pipeline completion.
+    /// Executes the assemble and verify phases (the second pipeline stage) and
+    /// logs pipeline completion.
     ///
     /// Call only after a successful [`Self::run_prepare_and_provision`].
-    /// Returns immediately if the pipeline has no tasks.
+    /// Returns immediately if the pipeline has no tasks. Each phase is skipped
+    /// if the selection excludes it.
     pub fn run_assemble(
         &self,
         rootfs: &Utf8Path,
@@ -130,10 +452,231 @@ impl<'a> Pipeline<'a> {
             return Ok(());
         }
 
-        run_phase_items(PHASE_ASSEMBLE, &self.assemble.items(), rootfs, executor, dry_run)?;
+        if self.selection.assemble {
+            self.run_phase_items(
+                PHASE_ASSEMBLE,
+                &self.selected_assemble_items(),
+                rootfs,
+                executor,
+                dry_run,
+                None,
+            )?;
+        } else {
+            debug!("skipping {} phase", PHASE_ASSEMBLE);
+        }
+        if self.selection.verify {
+            self.run_verify(rootfs, executor, dry_run)?;
+        } else {
+            debug!("skipping {} phase", PHASE_VERIFY);
+        }
         info!("pipeline completed successfully");
         Ok(())
     }
+
+    /// Runs every verify check, logs a pass/fail report, and fails with
+    /// [`RsdebstrapError::Verification`] if any check failed.
+    ///
+    /// A failing check does not stop the phase, so one run reports every problem; an
+    /// interrupted one does.
+    fn run_verify(
+        &self,
+        rootfs: &Utf8Path,
+        executor: &Arc<dyn CommandExecutor>,
+        dry_run: bool,
+    ) -> Result<()> {
+        let checks = self.verify_items();
+        if checks.is_empty() {
+            debug!("skipping empty {} phase", PHASE_VERIFY);
+            return Ok(());
+        }
+
+        info!("running {} phase ({} check(s))", PHASE_VERIFY, checks.len());
+        if let Some(progress) = self.progress {
+            progress.report(&ProgressEvent::PhaseStarted {
+                phase: PHASE_VERIFY,
+                tasks: checks.len(),
+            });
+        }
+        let mut failed = Vec::new();
+        for (number, check) in (1..).zip(&checks) {
+            let _prefix = OutputPrefix::enter(format!("{}/{}", PHASE_VERIFY, check.name()));
+            if let Some(progress) = self.progress {
+                progress.report(&ProgressEvent::TaskStarted {
+                    phase: PHASE_VERIFY,
+                    number,
+                    name: check.name().into_owned(),
+                });
+            }
+            let staging = self.task_staging(PHASE_VERIFY, number);
+            match run_task_item(check, rootfs, executor, dry_run, &staging, None, &[]) {
+                Ok(()) => info!("PASS {} {}: {}", PHASE_VERIFY, number, check.name()),
+                // Unlike a failed check, an interrupt stops the remaining checks.
+                Err(e) if crate::error::is_interrupted(&e) => {
+                    return Err(e.context(Stage::Pipeline.context(format!(
+                        "{} phase interrupted at {} {} ({})",
+                        PHASE_VERIFY,
+                        PHASE_VERIFY,
+                        number,
+                        check.name()
+                    ))));
+                }
+                Err(e) => {
+                    warn!("FAIL {} {}: {}: {:#}", PHASE_VERIFY, number, check.name(), e);
+                    failed.push(check.name().into_owned());
+                }
+            }
+            if let Some(progress) = self.progress {
+                progress.report(&ProgressEvent::TaskFinished {
+                    phase: PHASE_VERIFY,
+                    number,
+                    name: check.name().into_owned(),
+                });
+            }
+        }
+        info!(
+            "{} report: {} passed, {} failed",
+            PHASE_VERIFY,
+            checks.len() - failed.len(),
+            failed.len()
+        );
+        if !failed.is_empty() {
+            return Err(RsdebstrapError::Verification {
+                failed,
+                total: checks.len(),
+            })
+            .context(Stage::Pipeline.context(format!("{} phase failed", PHASE_VERIFY)));
+        }
+        if let Some(progress) = self.progress {
+            progress.report(&ProgressEvent::PhaseFinished {
+                phase: PHASE_VERIFY,
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns the staging of the task `number` of `phase`.
+    fn task_staging(&self, phase: &str, number: usize) -> TaskStaging<'_> {
+        TaskStaging {
+            dir: &self.staging_dir,
+            name_suffix: self
+                .run_id
+                .map(|run_id| format!("{}-{}-{}", run_id, phase, number)),
+        }
+    }
+
+    /// Runs `tasks` of one phase in order.
+    ///
+    /// A failed task with `ignore_errors` is logged and counts as done. Any other
+    /// failure stops the phase under [`FailurePolicy::FailFast`] (always the policy of
+    /// the prepare phase); under [`FailurePolicy::RunAllThenFail`] the remaining tasks
+    /// still run and the phase then fails with [`RsdebstrapError::Multiple`]
+    /// holding every task's error. An interrupted task
+    /// ([`RsdebstrapError::Interrupted`]) always stops the phase.
+    ///
+    /// With `layers`, each task goes through the layer cache: replayed from its cached
+    /// layer while the chain of layers before it is cached, otherwise run and captured.
+    fn run_phase_items(
+        &self,
+        phase_name: &'static str,
+        tasks: &[(usize, &dyn PhaseItem)],
+        rootfs: &Utf8Path,
+        executor: &Arc<dyn CommandExecutor>,
+        dry_run: bool,
+        mut layers: Option<Layers<'_>>,
+    ) -> Result<()> {
+        if tasks.is_empty() {
+            debug!("skipping empty {} phase", phase_name);
+            return Ok(());
+        }
+
+        info!("running {} phase ({} task(s))", phase_name, tasks.len());
+        if let Some(progress) = self.progress {
+            progress.report(&ProgressEvent::PhaseStarted {
+                phase: phase_name,
+                tasks: tasks.len(),
+            });
+        }
+
+        let policy = match phase_name {
+            PHASE_PROVISION => self.failure_policy.provision,
+            PHASE_ASSEMBLE => self.failure_policy.assemble,
+            _ => FailurePolicy::FailFast,
+        };
+        let mut failed = Vec::new();
+        for (index, &(number, task)) in tasks.iter().enumerate() {
+            info!("running {} {}/{}: {}", phase_name, index + 1, tasks.len(), task.name());
+            // Tasks run one after another on this thread, so each task's output lines
+            // carry its own label and never mix with the next task's.
+            let _prefix = OutputPrefix::enter(format!("{}/{}", phase_name, task.name()));
+            if let Some(progress) = self.progress {
+                progress.report(&ProgressEvent::TaskStarted {
+                    phase: phase_name,
+                    number,
+                    name: task.name().into_owned(),
+                });
+            }
+            let secrets = match self.secrets {
+                Some(secrets) if phase_name == PHASE_PROVISION => {
+                    secrets.for_task(task.configured_name())
+                }
+                _ => Vec::new(),
+            };
+            let staging = self.task_staging(phase_name, number);
+            let sandbox = self.sandbox.as_ref();
+            let run =
+                || run_task_item(task, rootfs, executor, dry_run, &staging, sandbox, &secrets);
+            let result = match layers.as_mut() {
+                Some(layers) => layers.apply(number, rootfs, &self.staging_dir, run),
+                None => run(),
+            };
+            if let Err(e) = result {
+                let label = task_label(phase_name, number, task);
+                if crate::error::is_interrupted(&e) {
+                    // The user asked to stop: neither `ignore_errors` nor the failure
+                    // policy may run the remaining tasks.
+                    return Err(e.context(
+                        Stage::Pipeline
+                            .context(format!("{} phase interrupted at {}", phase_name, label)),
+                    ));
+                }
+                if task.ignore_errors() {
+                    warn!("ignoring failure of {}: {:#}", label, e);
+                } else {
+                    let e = e.context(Stage::Pipeline.context(format!("failed to run {}", label)));
+                    if policy == FailurePolicy::FailFast {
+                        return Err(e);
+                    }
+                    warn!("{:#}; running the remaining tasks", e);
+                    failed.push(e);
+                }
+            }
+            if let Some(progress) = self.progress {
+                progress.report(&ProgressEvent::TaskFinished {
+                    phase: phase_name,
+                    number,
+                    name: task.name().into_owned(),
+                });
+            }
+        }
+        if !failed.is_empty() {
+            let message = format!("{} of {} task(s) failed", failed.len(), tasks.len());
+            return Err(RsdebstrapError::multiple(message, failed))
+                .context(Stage::Pipeline.context(format!("{} phase failed", phase_name)));
+        }
+        if let Some(progress) = self.progress {
+            progress.report(&ProgressEvent::PhaseFinished { phase: phase_name });
+        }
+
+        Ok(())
+    }
+}
+
+/// Where, and under which names, a task stages its files.
+struct TaskStaging<'s> {
+    /// Staging directory as seen inside the rootfs.
+    dir: &'s Utf8Path,
+    /// `<run-id>-<phase>-<number>`; without it, staged names are random.
+    name_suffix: Option<String>,
 }
 
 /// Borrows the provision tasks as `PhaseItem` trait objects for uniform handling
@@ -142,88 +685,150 @@ fn provision_items(tasks: &[ProvisionTask]) -> Vec<&dyn PhaseItem> {
     tasks.iter().map(|t| t as &dyn PhaseItem).collect()
 }
 
-fn run_phase_items(
-    phase_name: &str,
-    tasks: &[&dyn PhaseItem],
-    rootfs: &Utf8Path,
-    executor: &Arc<dyn CommandExecutor>,
-    dry_run: bool,
-) -> Result<()> {
-    if tasks.is_empty() {
-        debug!("skipping empty {} phase", phase_name);
-        return Ok(());
-    }
-
-    info!("running {} phase ({} task(s))", phase_name, tasks.len());
+/// Pairs each task with its 1-based position in its phase, which labels it in logs
+/// and errors even when tasks before it are skipped.
+fn numbered(items: Vec<&dyn PhaseItem>) -> Vec<(usize, &dyn PhaseItem)> {
+    (1..).zip(items).collect()
+}
 
-    for (index, task) in tasks.iter().enumerate() {
-        info!("running {} {}/{}: {}", phase_name, index + 1, tasks.len(), task.name());
-        run_task_item(*task, rootfs, executor, dry_run)
-            .with_context(|| format!("failed to run {} {}", phase_name, index + 1))?;
+/// Labels a task in errors: its phase and number, followed by its `name` if given.
+pub(crate) fn task_label(phase_name: &str, number: usize, task: &dyn PhaseItem) -> String {
+    match task.configured_name() {
+        Some(name) => format!("{} {} ({})", phase_name, number, name),
+        None => format!("{} {}", phase_name, number),
     }
-
-    Ok(())
 }
 
 /// Runs a single task with its own isolation context.
 ///
-/// Creates the appropriate provider based on the task's resolved isolation
-/// config, sets up the context, executes the task, and ensures teardown.
+/// Mounts the task's own `mounts`, creates the appropriate provider based on the
+/// task's resolved isolation config (running in `sandbox` without isolation), sets up
+/// the context, executes the task, and ensures teardown and unmounting.
 fn run_task_item(
     task: &dyn PhaseItem,
     rootfs: &Utf8Path,
     executor: &Arc<dyn CommandExecutor>,
     dry_run: bool,
+    staging: &TaskStaging<'_>,
+    sandbox: Option<&Sandbox>,
+    secrets: &[&Secret],
 ) -> Result<()> {
+    // Mounted before the isolation context exists and unmounted after it is gone, so
+    // they bracket the task the way `prepare.mount` brackets the whole pipeline. A
+    // failed setup below unmounts them through `RootfsMounts`' Drop.
+    let mut task_mounts = RootfsMounts::new(
+        rootfs,
+        task.task_mounts().to_vec(),
+        executor.clone(),
+        task.isolation_privilege(),
+        dry_run,
+    )
+    .with_unmount_policy(
+        task.resolved_isolation_config()
+            .map(IsolationConfig::unmount_policy)
+            .unwrap_or_default(),
+    );
+    task_mounts
+        .mount()
+        .context("failed to mount task filesystems")?;
+
     let provider: Box<dyn IsolationProvider> = match task.resolved_isolation_config() {
-        Some(config) => config.as_provider(),
-        None => Box::new(DirectProvider),
+        Some(config) => config.as_provider(task.isolation_privilege()),
+        None => Box::new(DirectProvider::new(sandbox.cloned())),
+    };
+
+    // Every command the context issues (including the file staging around the task)
+    // then runs without network access.
+    let executor: Arc<dyn CommandExecutor> = if task.network_enabled() {
+        executor.clone()
+    } else {
+        Arc::new(OfflineExecutor::new(executor.clone()))
     };
 
     let mut ctx = provider
-        .setup(rootfs, executor.clone(), dry_run)
+        .setup(rootfs, executor, dry_run)
         .context("failed to setup isolation context")?;
+    if staging.dir != TMP_STAGING_DIR || staging.name_suffix.is_some() {
+        ctx = Box::new(
+            StagedContext::new(ctx, staging.dir).with_name_suffix(staging.name_suffix.clone()),
+        );
+    }
+    if !secrets.is_empty() {
+        ctx = Box::new(
+            SecretContext::setup(ctx, secrets, task.isolation_privilege())
+                .context("failed to hand secrets to the task")?,
+        );
+    }
 
     let run_result = task.execute(ctx.as_ref());
     let teardown_result = ctx.teardown();
+    drop(ctx);
+    let unmount_result = task_mounts.unmount();
 
-    match (run_result, teardown_result) {
+    let result = match (run_result, teardown_result) {
         (Ok(()), Ok(())) => Ok(()),
         (Err(e), Ok(())) => Err(e),
         (Ok(()), Err(e)) => Err(e).context("failed to teardown isolation context"),
         (Err(run_err), Err(tear_err)) => {
             Err(run_err.context(format!("additionally, teardown failed: {:#}", tear_err)))
         }
+    };
+    match (result, unmount_result) {
+        (result, Ok(())) => result,
+        (Ok(()), Err(e)) => Err(e).context("failed to unmount task filesystems"),
+        (Err(err), Err(unmount_err)) => {
+            Err(err.context(format!("additionally, unmount failed: {:#}", unmount_err)))
+        }
     }
 }
 
 /// Validates all tasks in a single phase, enriching errors with phase context.
 ///
-/// For `Validation` errors, prepends the phase name and task index to the message.
+/// For `Validation` errors, prepends the phase name, task index and task `name`
+/// (if given) to the message.
 /// For `Io` errors, prepends the phase context to the `context` field while
 /// preserving the `source` for programmatic inspection.
 /// Other error variants are wrapped in `Validation` with phase context for
 /// forward-compatibility, ensuring no future variant loses phase information.
 fn validate_phase_items(phase_name: &str, tasks: &[&dyn PhaseItem]) -> Result<(), RsdebstrapError> {
-    for (index, task) in tasks.iter().enumerate() {
+    for (number, task) in numbered(tasks.to_vec()) {
+        let label = || task_label(phase_name, number, task);
         task.validate().map_err(|e| match e {
-            RsdebstrapError::Validation(msg) => RsdebstrapError::Validation(format!(
-                "{} {} validation failed: {}",
-                phase_name,
-                index + 1,
-                msg
-            )),
+            RsdebstrapError::Validation(msg) => {
+                RsdebstrapError::Validation(format!("{} validation failed: {}", label(), msg))
+            }
             RsdebstrapError::Io { context, source } => RsdebstrapError::Io {
-                context: format!("{} {} validation failed: {}", phase_name, index + 1, context),
+                context: format!("{} validation failed: {}", label(), context),
                 source,
             },
-            other => RsdebstrapError::Validation(format!(
-                "{} {} validation failed: {}",
-                phase_name,
-                index + 1,
-                other
-            )),
+            other => {
+                RsdebstrapError::Validation(format!("{} validation failed: {}", label(), other))
+            }
         })?;
     }
     Ok(())
 }
+
+/// Checks that no two provision tasks share a `name`, so `--start-at-task` and
+/// error messages identify exactly one task.
+fn validate_unique_task_names(tasks: &[ProvisionTask]) -> Result<(), RsdebstrapError> {
+    for (index, task) in tasks.iter().enumerate() {
+        let Some(name) = task.configured_name() else {
+            continue;
+        };
+        if let Some(first) = tasks[..index]
+            .iter()
+            .position(|t| t.configured_name() == Some(name))
+        {
+            return Err(RsdebstrapError::Validation(format!(
+                "{} {} has the same name {:?} as {} {}",
+                PHASE_PROVISION,
+                index + 1,
+                name,
+                PHASE_PROVISION,
+                first + 1
+            )));
+        }
+    }
+    Ok(())
+}
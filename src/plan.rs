@@ -0,0 +1,355 @@
+//! Execution plan for `apply --dry-run --plan`.
+//!
+//! [`build_plan`] walks the same configuration `apply` would act on — the bootstrap
+//! command, the pipeline's mounts and resolv.conf handling, and each selected task
+//! with its resolved isolation and privilege — and returns it as an ordered list of
+//! [`PlanStep`]s, so a profile change can be reviewed before anything runs.
+
+use std::fmt;
+
+use anyhow::Result;
+
+use crate::bootstrap::{self, RootfsOutput};
+use crate::checksums::ChecksumConfig;
+use crate::config::{IsolationConfig, MountEntry, Profile};
+use crate::disk_space::ByteSize;
+use crate::isolation::staging::Staging;
+use crate::output_dir::OutputPermissions;
+use crate::phase::PhaseItem;
+use crate::pipeline::Pipeline;
+use crate::privilege::PrivilegeMethod;
+use crate::upload::UploadConfig;
+
+/// One step of an execution plan: a one-line summary and indented details.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanStep {
+    /// What the step does.
+    pub summary: String,
+    /// Settings that shape the step, one per line.
+    pub details: Vec<String>,
+}
+
+impl PlanStep {
+    fn new(summary: impl Into<String>) -> Self {
+        Self {
+            summary: summary.into(),
+            details: Vec::new(),
+        }
+    }
+
+    fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.details.push(detail.into());
+        self
+    }
+}
+
+/// The ordered steps `apply` would take. Displays as a numbered list.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Plan {
+    /// Steps in execution order.
+    pub steps: Vec<PlanStep>,
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Execution plan ({} step(s)):", self.steps.len())?;
+        for (index, step) in self.steps.iter().enumerate() {
+            writeln!(f, "{:>3}. {}", index + 1, step.summary)?;
+            for detail in &step.details {
+                writeln!(f, "       {}", detail)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Builds the plan for `profile`, running the bootstrap if `run_bootstrap` and the
+/// tasks `pipeline` selects.
+///
+/// URL credentials in the bootstrap arguments are masked.
+pub fn build_plan(profile: &Profile, run_bootstrap: bool, pipeline: &Pipeline<'_>) -> Result<Plan> {
+    let mut plan = Plan::default();
+    if run_bootstrap {
+        plan.steps.push(bootstrap_step(profile)?);
+    }
+    if !pipeline.is_empty() {
+        let RootfsOutput::Directory(rootfs) =
+            profile.bootstrap.as_backend().rootfs_output(&profile.dir)?
+        else {
+            // Rejected by profile validation; nothing more to plan.
+            return Ok(plan);
+        };
+        pipeline_steps(&mut plan, profile, pipeline, rootfs.as_str());
+    }
+    if let Some(checksums) = &profile.checksums
+        && pipeline.selection().assemble
+    {
+        plan.steps.push(checksums_step(profile, checksums));
+    }
+    if let Some(upload) = &profile.upload
+        && pipeline.selection().assemble
+    {
+        plan.steps.push(upload_step(profile, upload));
+    }
+    if let Some(permissions) = &profile.output_permissions {
+        plan.steps.push(permissions_step(profile, permissions));
+    }
+    Ok(plan)
+}
+
+fn permissions_step(profile: &Profile, permissions: &OutputPermissions) -> PlanStep {
+    let rootfs = match profile.bootstrap.as_backend().rootfs_output(&profile.dir) {
+        Ok(RootfsOutput::Directory(rootfs)) => Some(rootfs),
+        _ => None,
+    };
+    let mut step = PlanStep::new(format!("output permissions: {}", profile.dir)).detail(format!(
+        "privilege: {}",
+        privilege(profile.defaults.privilege.as_ref().map(|d| d.method))
+    ));
+    for (command, args) in permissions.commands(&profile.dir, rootfs.as_deref()) {
+        step = step.detail(format!("{} {}", command, args.join(" ")));
+    }
+    step
+}
+
+fn upload_step(profile: &Profile, upload: &UploadConfig) -> PlanStep {
+    let mut step =
+        PlanStep::new(format!("upload: artifacts in {} to {}", profile.dir, upload.provider));
+    for artifact in
+        upload.artifacts(profile.default_checksum_artifact(), profile.checksums.as_ref())
+    {
+        step = step.detail(format!("{} -> {}", artifact, upload.location(&artifact)));
+    }
+    step.detail(format!(
+        "parts of {}, {} retries per request",
+        ByteSize(upload.chunk_size()),
+        upload.retries()
+    ))
+}
+
+fn checksums_step(profile: &Profile, checksums: &ChecksumConfig) -> PlanStep {
+    let files = checksums
+        .algorithms
+        .iter()
+        .map(|a| a.sums_file())
+        .collect::<Vec<_>>();
+    let mut step =
+        PlanStep::new(format!("checksums: write {} in {}", files.join(", "), profile.dir));
+    for artifact in checksums.artifacts(profile.default_checksum_artifact()) {
+        step = step.detail(format!("artifact: {}", artifact));
+    }
+    if let Some(sign) = &checksums.sign {
+        let key = sign.key.as_deref().unwrap_or("default key");
+        step = step.detail(format!("sign with {} ({})", sign.method, key));
+    }
+    step
+}
+
+fn bootstrap_step(profile: &Profile) -> Result<PlanStep> {
+    let backend = profile.bootstrap.as_backend();
+    let command = backend.command_name();
+    let args = backend
+        .build_args(&profile.dir)?
+        .iter()
+        .map(|arg| bootstrap::sanitize_credential(arg))
+        .collect::<Vec<_>>();
+
+    let mut step = PlanStep::new(format!("bootstrap: {} {}", command, args.join(" "))).detail(
+        format!("privilege: {}", privilege(profile.bootstrap.command_privilege_method())),
+    );
+    for command in profile.bootstrap.stream_to() {
+        step = step.detail(format!("stream to: {} (in {})", command.join(" "), profile.dir));
+    }
+    let fallbacks = profile.bootstrap.fallback_mirrors();
+    if !fallbacks.is_empty() {
+        step = step.detail(format!(
+            "mirror: first healthy of the primary and {} fallback(s)",
+            fallbacks.len()
+        ));
+    }
+    for keyring in &profile.keyrings {
+        let source = match (&keyring.path, &keyring.url) {
+            (Some(path), _) => path.to_string(),
+            (None, Some(url)) => bootstrap::sanitize_credential(url),
+            (None, None) => continue,
+        };
+        step = step.detail(format!("keyring: {}", source));
+    }
+    if profile.apt_cache.is_some() {
+        step = step.detail("apt downloads go through the apt cache proxy");
+    }
+    Ok(step)
+}
+
+fn pipeline_steps(plan: &mut Plan, profile: &Profile, pipeline: &Pipeline<'_>, rootfs: &str) {
+    let selection = pipeline.selection();
+    let mounts = profile.pipeline_mounts();
+    if !mounts.is_empty() {
+        let step = mounts.iter().fold(
+            PlanStep::new(format!("mount {} filesystem(s) in {}", mounts.len(), rootfs)),
+            |step, entry| step.detail(mount(entry)),
+        );
+        plan.steps.push(step);
+    }
+    // A tmpfs staging area is listed with the mounts.
+    if profile.defaults.staging == Staging::Private {
+        plan.steps
+            .push(PlanStep::new("create a private task staging directory under /tmp"));
+    }
+
+    let resolv_conf = profile.prepare.resolv_conf.as_ref();
+    if let Some(rc) = resolv_conf {
+        let summary = if rc.copy {
+            "resolv.conf: copy the host's /etc/resolv.conf into the rootfs".to_string()
+        } else {
+            let servers = rc
+                .name_servers
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            let mut summary = format!("resolv.conf: write name servers {}", servers.join(", "));
+            if !rc.search.is_empty() {
+                summary.push_str(&format!(" (search {})", rc.search.join(" ")));
+            }
+            summary
+        };
+        plan.steps.push(PlanStep::new(summary));
+    }
+    let network_files = profile
+        .defaults
+        .isolation
+        .network_files()
+        .iter()
+        .map(|f| f.path())
+        .collect::<Vec<_>>()
+        .join(", ");
+    if !network_files.is_empty() {
+        plan.steps.push(PlanStep::new(format!(
+            "network files: copy the host's {} into the rootfs",
+            network_files
+        )));
+    }
+    if profile.apt_cache.is_some() {
+        plan.steps
+            .push(PlanStep::new("apt: point apt in the rootfs at the apt cache proxy"));
+    }
+
+    if selection.provision {
+        for (number, task) in pipeline.selected_provision_items() {
+            plan.steps.push(task_step("provision", number, task));
+        }
+    }
+    if resolv_conf.is_some() {
+        plan.steps
+            .push(PlanStep::new("resolv.conf: restore the rootfs's original file"));
+    }
+    if !network_files.is_empty() {
+        plan.steps.push(PlanStep::new(format!(
+            "network files: restore the rootfs's original {}",
+            network_files
+        )));
+    }
+    if profile.apt_cache.is_some() {
+        plan.steps
+            .push(PlanStep::new("apt: remove the apt cache proxy configuration"));
+    }
+    if selection.assemble {
+        for (number, task) in pipeline.selected_assemble_items() {
+            plan.steps.push(task_step("assemble", number, task));
+        }
+    }
+    if selection.verify {
+        for (number, check) in (1..).zip(pipeline.verify_items()) {
+            plan.steps.push(task_step("verify", number, &check));
+        }
+    }
+    if profile.defaults.staging == Staging::Private {
+        plan.steps
+            .push(PlanStep::new("remove the task staging directory"));
+    }
+    if !mounts.is_empty() {
+        plan.steps
+            .push(PlanStep::new(format!("unmount {} filesystem(s)", mounts.len())));
+    }
+}
+
+fn task_step(phase: &str, number: usize, task: &dyn PhaseItem) -> PlanStep {
+    let mut step = PlanStep::new(format!("{} {}: {}", phase, number, task.name()))
+        .detail(format!("isolation: {}", isolation(task.resolved_isolation_config())))
+        .detail(format!("privilege: {}", privilege(task.isolation_privilege())));
+    if !task.network_enabled() {
+        step = step.detail("network: disabled");
+    }
+    if task.ignore_errors() {
+        step = step.detail("failure: ignored");
+    }
+    for entry in task.task_mounts() {
+        step = step.detail(format!("mount: {}", mount(entry)));
+    }
+    step
+}
+
+fn isolation(config: Option<&IsolationConfig>) -> String {
+    let Some(config) = config else {
+        return "none (runs on the host)".to_string();
+    };
+    let chroot = config.options();
+    let mut parts = vec![config.name().to_string()];
+    if let Some(workdir) = &chroot.workdir {
+        parts.push(format!("workdir {}", workdir));
+    }
+    if let Some(user) = &chroot.user {
+        parts.push(format!("user {}", user));
+    }
+    if chroot.clean_env {
+        parts.push("clean env".to_string());
+    }
+    if let Some(path) = &chroot.path {
+        parts.push(format!("PATH {}", path));
+    }
+    // Values may be secrets; list the names only.
+    if !chroot.env.is_empty() {
+        let names = chroot.env.keys().cloned().collect::<Vec<_>>();
+        parts.push(format!("env {}", names.join(" ")));
+    }
+    for bind in &chroot.binds {
+        let ro = if bind.ro { " (ro)" } else { "" };
+        parts.push(format!("bind {} -> {}{}", bind.source, bind.target, ro));
+    }
+    parts.join(", ")
+}
+
+fn privilege(method: Option<PrivilegeMethod>) -> String {
+    method.map_or_else(|| "none".to_string(), |m| m.to_string())
+}
+
+fn mount(entry: &MountEntry) -> String {
+    if entry.options.is_empty() {
+        format!("{} -> {}", entry.source, entry.target)
+    } else {
+        format!("{} -> {} ({})", entry.source, entry.target, entry.options.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_numbers_steps_and_indents_details() {
+        let plan = Plan {
+            steps: vec![
+                PlanStep::new("bootstrap: mmdebstrap trixie").detail("privilege: sudo"),
+                PlanStep::new("unmount 1 filesystem(s)"),
+            ],
+        };
+
+        assert_eq!(
+            plan.to_string(),
+            "Execution plan (2 step(s)):\n  \
+             1. bootstrap: mmdebstrap trixie\n       \
+             privilege: sudo\n  \
+             2. unmount 1 filesystem(s)\n"
+        );
+    }
+}
@@ -0,0 +1,291 @@
+//! Privilege pre-flight checks for `apply`.
+//!
+//! A build can run for many minutes before its first privileged pipeline task.
+//! If the escalation tool only then asks for a password (or is not configured for
+//! the user at all), the build fails late and leaves a half-built rootfs behind.
+//! [`check_privilege`] runs `<method> true` once per method up front instead, which
+//! surfaces configuration problems as [`RsdebstrapError::Privilege`] and lets an
+//! interactive user answer any password prompt before the long work starts.
+//!
+//! `sudo` credentials expire after its `timestamp_timeout`, so
+//! [`CredentialKeepalive`] refreshes them with `sudo -n -v` for the rest of the
+//! run. The other methods have no refresh command: `doas` caching is controlled by
+//! `persist` in doas.conf, and `run0`/`pkexec` by the polkit rules.
+
+use std::sync::Arc;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use tracing::{debug, info, warn};
+
+use crate::error::RsdebstrapError;
+use crate::executor::{self, CommandExecutor, CommandSpec};
+use crate::privilege::PrivilegeMethod;
+
+/// How often [`CredentialKeepalive`] refreshes cached `sudo` credentials.
+///
+/// Well below sudo's default 5–15 minute `timestamp_timeout`.
+pub const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Verifies that each privilege method can run a command.
+///
+/// Runs `true` through every method in `methods` via `executor`. Any interactive
+/// prompt happens here, before the bootstrap starts.
+///
+/// # Errors
+///
+/// Returns [`RsdebstrapError::Privilege`] for the first method whose check cannot
+/// be spawned or exits non-zero.
+pub fn check_privilege(
+    methods: &[PrivilegeMethod],
+    executor: &dyn CommandExecutor,
+) -> Result<(), RsdebstrapError> {
+    for &method in methods {
+        info!("checking privilege escalation via {}", method);
+        let spec = CommandSpec::new("true", Vec::new()).with_privilege(Some(method));
+        let result = executor
+            .execute(&spec)
+            .map_err(|e| RsdebstrapError::Privilege {
+                method,
+                message: format!("{:#}", e),
+            })?;
+        if let Some(status) = result.status.filter(|s| !s.success()) {
+            return Err(RsdebstrapError::Privilege {
+                method,
+                message: format!(
+                    "`{} true` exited with {}; check that {} is configured for this user",
+                    method, status, method
+                ),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Keeps cached `sudo` credentials fresh for the lifetime of the guard.
+///
+/// Spawns a background thread that runs `sudo -n -v` every [`KEEPALIVE_INTERVAL`].
+/// `-n` makes a refresh fail instead of prompting in the middle of the build; the
+/// thread then logs a warning and stops. Dropping the guard stops the thread. A
+/// signal arriving during a refresh stops the thread too, but is left for the
+/// build's command to take.
+pub struct CredentialKeepalive {
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl CredentialKeepalive {
+    /// Starts refreshing credentials if `methods` contains `sudo`.
+    ///
+    /// Returns an inert guard otherwise.
+    pub fn start(methods: &[PrivilegeMethod], executor: Arc<dyn CommandExecutor>) -> Self {
+        Self::start_with_interval(methods, executor, KEEPALIVE_INTERVAL)
+    }
+
+    fn start_with_interval(
+        methods: &[PrivilegeMethod],
+        executor: Arc<dyn CommandExecutor>,
+        interval: Duration,
+    ) -> Self {
+        if !methods.contains(&PrivilegeMethod::Sudo) {
+            return Self {
+                stop: None,
+                handle: None,
+            };
+        }
+
+        let (stop, stopped) = mpsc::channel::<()>();
+        let spawned = thread::Builder::new()
+            .name("sudo-keepalive".to_string())
+            .spawn(move || {
+                executor::run_in_background();
+                let spec = CommandSpec::new("sudo", vec!["-n".to_string(), "-v".to_string()]);
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    match executor.execute_checked(&spec) {
+                        Ok(()) => debug!("refreshed cached sudo credentials"),
+                        Err(e) if crate::error::is_interrupted(&e) => {
+                            debug!("sudo credential keepalive interrupted: {:#}", e);
+                            return;
+                        }
+                        Err(e) => {
+                            warn!(
+                                "failed to refresh cached sudo credentials, later privileged \
+                                commands may prompt for a password: {:#}",
+                                e
+                            );
+                            return;
+                        }
+                    }
+                }
+            });
+
+        match spawned {
+            Ok(handle) => Self {
+                stop: Some(stop),
+                handle: Some(handle),
+            },
+            Err(e) => {
+                warn!("failed to start sudo credential keepalive: {}", e);
+                Self {
+                    stop: None,
+                    handle: None,
+                }
+            }
+        }
+    }
+
+    /// Returns whether a refresh thread was started.
+    pub fn is_active(&self) -> bool {
+        self.handle.is_some()
+    }
+}
+
+impl Drop for CredentialKeepalive {
+    fn drop(&mut self) {
+        // Dropping the sender disconnects the channel and wakes the thread.
+        drop(self.stop.take());
+        if let Some(handle) = self.handle.take()
+            && handle.join().is_err()
+        {
+            warn!("sudo credential keepalive thread panicked");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::ExecutionResult;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+    use std::sync::Mutex;
+
+    /// Records each spec and answers with the configured exit code.
+    struct ScriptedExecutor {
+        calls: Mutex<Vec<CommandSpec>>,
+        exit_code: i32,
+    }
+
+    impl ScriptedExecutor {
+        fn new(exit_code: i32) -> Self {
+            Self {
+                calls: Mutex::new(Vec::new()),
+                exit_code,
+            }
+        }
+    }
+
+    impl CommandExecutor for ScriptedExecutor {
+        fn execute(&self, spec: &CommandSpec) -> anyhow::Result<ExecutionResult> {
+            self.calls.lock().unwrap().push(spec.clone());
+            Ok(ExecutionResult {
+                status: Some(ExitStatus::from_raw(self.exit_code << 8)),
+                details: None,
+            })
+        }
+    }
+
+    #[test]
+    fn check_runs_true_through_each_method() {
+        let executor = ScriptedExecutor::new(0);
+        check_privilege(&[PrivilegeMethod::Sudo, PrivilegeMethod::Pkexec], &executor).unwrap();
+
+        let calls = executor.calls.lock().unwrap();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].command, "true");
+        assert!(calls[0].args.is_empty());
+        assert_eq!(calls[0].privilege, Some(PrivilegeMethod::Sudo));
+        assert_eq!(calls[1].privilege, Some(PrivilegeMethod::Pkexec));
+    }
+
+    #[test]
+    fn check_with_no_methods_runs_nothing() {
+        let executor = ScriptedExecutor::new(0);
+        check_privilege(&[], &executor).unwrap();
+        assert!(executor.calls.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn check_non_zero_exit_is_privilege_error() {
+        let executor = ScriptedExecutor::new(1);
+        let err = check_privilege(&[PrivilegeMethod::Doas], &executor).unwrap_err();
+        match err {
+            RsdebstrapError::Privilege { method, message } => {
+                assert_eq!(method, PrivilegeMethod::Doas);
+                assert!(message.contains("`doas true` exited with"), "{message}");
+            }
+            other => panic!("expected Privilege error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn check_spawn_failure_is_privilege_error() {
+        struct Unavailable;
+        impl CommandExecutor for Unavailable {
+            fn execute(&self, _spec: &CommandSpec) -> anyhow::Result<ExecutionResult> {
+                Err(RsdebstrapError::command_not_found("run0", "privilege escalation command")
+                    .into())
+            }
+        }
+
+        let err = check_privilege(&[PrivilegeMethod::Run0], &Unavailable).unwrap_err();
+        assert!(matches!(
+            err,
+            RsdebstrapError::Privilege {
+                method: PrivilegeMethod::Run0,
+                ..
+            }
+        ));
+        assert!(err.to_string().contains("'run0' not found in PATH"), "{err}");
+    }
+
+    #[test]
+    fn keepalive_is_inert_without_sudo() {
+        let executor = Arc::new(ScriptedExecutor::new(0));
+        let keepalive = CredentialKeepalive::start(&[PrivilegeMethod::Doas], executor.clone());
+        assert!(!keepalive.is_active());
+    }
+
+    #[test]
+    fn keepalive_refreshes_sudo_until_dropped() {
+        let executor = Arc::new(ScriptedExecutor::new(0));
+        let keepalive = CredentialKeepalive::start_with_interval(
+            &[PrivilegeMethod::Sudo],
+            executor.clone(),
+            Duration::from_millis(5),
+        );
+        assert!(keepalive.is_active());
+        while executor.calls.lock().unwrap().len() < 2 {
+            thread::sleep(Duration::from_millis(5));
+        }
+        drop(keepalive);
+
+        let calls = executor.calls.lock().unwrap();
+        let count = calls.len();
+        assert!(
+            calls
+                .iter()
+                .all(|c| c.command == "sudo" && c.args == ["-n", "-v"])
+        );
+        drop(calls);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(executor.calls.lock().unwrap().len(), count, "thread must stop on drop");
+    }
+
+    #[test]
+    fn keepalive_stops_after_failed_refresh() {
+        let executor = Arc::new(ScriptedExecutor::new(1));
+        let keepalive = CredentialKeepalive::start_with_interval(
+            &[PrivilegeMethod::Sudo],
+            executor.clone(),
+            Duration::from_millis(5),
+        );
+        while executor.calls.lock().unwrap().is_empty() {
+            thread::sleep(Duration::from_millis(5));
+        }
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(executor.calls.lock().unwrap().len(), 1);
+        drop(keepalive);
+    }
+}
@@ -1,6 +1,7 @@
 //! Privilege escalation configuration.
 //!
-//! This module provides types for configuring privilege escalation (`sudo`, `doas`)
+//! This module provides types for configuring privilege escalation (`sudo`, `doas`,
+//! `run0`, `pkexec`, or a rootless user namespace)
 //! on a per-command basis. Tasks and bootstrap backends can declare their own
 //! privilege settings, inheriting from profile-level defaults when unspecified.
 
@@ -14,7 +15,7 @@ use serde::{Deserialize, Serialize};
 use crate::error::RsdebstrapError;
 
 /// Privilege escalation method.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
 #[cfg_attr(feature = "schema", derive(JsonSchema))]
 #[serde(rename_all = "lowercase")]
 pub enum PrivilegeMethod {
@@ -22,21 +23,45 @@ pub enum PrivilegeMethod {
     Sudo,
     /// Use `doas` for privilege escalation.
     Doas,
+    /// Use systemd's `run0` for privilege escalation (polkit-authorized).
+    Run0,
+    /// Use polkit's `pkexec` for privilege escalation.
+    Pkexec,
+    /// Run rootless inside a user namespace (`unshare --map-root-user --map-auto`).
+    ///
+    /// The command sees itself as root, with the caller's `/etc/subuid` and
+    /// `/etc/subgid` ranges mapped in; no sudo/doas is needed. An mmdebstrap
+    /// bootstrap runs in its own `unshare` mode instead of being wrapped.
+    Userns,
 }
 
 impl PrivilegeMethod {
     /// Returns the command name for this privilege method.
+    ///
+    /// This is the program that wraps the command, which differs from the YAML
+    /// name only for `userns` (`unshare`).
     pub fn command_name(&self) -> &'static str {
         match self {
             Self::Sudo => "sudo",
             Self::Doas => "doas",
+            Self::Run0 => "run0",
+            Self::Pkexec => "pkexec",
+            Self::Userns => "unshare",
+        }
+    }
+
+    /// Returns the YAML name of this privilege method (e.g. `sudo`, `userns`).
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Userns => "userns",
+            _ => self.command_name(),
         }
     }
 }
 
 impl std::fmt::Display for PrivilegeMethod {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(self.command_name())
+        f.write_str(self.as_str())
     }
 }
 
@@ -70,6 +95,11 @@ pub enum Privilege {
 }
 
 impl Privilege {
+    /// Returns true if the setting is `Inherit` (the field was not specified).
+    pub fn is_inherit(&self) -> bool {
+        matches!(self, Self::Inherit)
+    }
+
     /// Returns the resolved privilege method.
     ///
     /// Should only be called after [`resolve()`](Self::resolve) or
@@ -291,12 +321,18 @@ mod tests {
     fn privilege_method_command_name() {
         assert_eq!(PrivilegeMethod::Sudo.command_name(), "sudo");
         assert_eq!(PrivilegeMethod::Doas.command_name(), "doas");
+        assert_eq!(PrivilegeMethod::Run0.command_name(), "run0");
+        assert_eq!(PrivilegeMethod::Pkexec.command_name(), "pkexec");
+        assert_eq!(PrivilegeMethod::Userns.command_name(), "unshare");
     }
 
     #[test]
     fn privilege_method_display() {
         assert_eq!(PrivilegeMethod::Sudo.to_string(), "sudo");
         assert_eq!(PrivilegeMethod::Doas.to_string(), "doas");
+        assert_eq!(PrivilegeMethod::Run0.to_string(), "run0");
+        assert_eq!(PrivilegeMethod::Pkexec.to_string(), "pkexec");
+        assert_eq!(PrivilegeMethod::Userns.to_string(), "userns");
     }
 
     #[test]
@@ -306,6 +342,15 @@ mod tests {
 
         let doas: PrivilegeMethod = yaml_serde::from_str("doas").unwrap();
         assert_eq!(doas, PrivilegeMethod::Doas);
+
+        let run0: PrivilegeMethod = yaml_serde::from_str("run0").unwrap();
+        assert_eq!(run0, PrivilegeMethod::Run0);
+
+        let pkexec: PrivilegeMethod = yaml_serde::from_str("pkexec").unwrap();
+        assert_eq!(pkexec, PrivilegeMethod::Pkexec);
+
+        let userns: PrivilegeMethod = yaml_serde::from_str("userns").unwrap();
+        assert_eq!(userns, PrivilegeMethod::Userns);
     }
 
     // =========================================================================
@@ -475,8 +520,8 @@ mod tests {
 
     #[test]
     fn privilege_method_rejects_invalid_value() {
-        let result: Result<PrivilegeMethod, _> = yaml_serde::from_str("pkexec");
-        assert!(result.is_err(), "pkexec should not be a valid PrivilegeMethod");
+        let result: Result<PrivilegeMethod, _> = yaml_serde::from_str("su");
+        assert!(result.is_err(), "su should not be a valid PrivilegeMethod");
     }
 
     #[test]
@@ -493,8 +538,8 @@ mod tests {
 
     #[test]
     fn privilege_rejects_invalid_method_in_map() {
-        let result: Result<Privilege, _> = yaml_serde::from_str("method: pkexec");
-        assert!(result.is_err(), "pkexec should not be valid in privilege map");
+        let result: Result<Privilege, _> = yaml_serde::from_str("method: su");
+        assert!(result.is_err(), "su should not be valid in privilege map");
     }
 
     // =========================================================================
@@ -569,7 +614,10 @@ mod tests {
                 json!(false),
                 json!({"method": "sudo"}),
                 json!({"method": "doas"}),
+                json!({"method": "run0"}),
                 json!({"method": "pkexec"}),
+                json!({"method": "userns"}),
+                json!({"method": "su"}),
                 json!({"methd": "sudo"}),
                 json!({"method": "sudo", "extra": 1}),
                 json!({}),
@@ -0,0 +1,64 @@
+//! Progress reporting for embedders of [`Runner`](crate::Runner).
+//!
+//! A [`Progress`] observer receives a [`ProgressEvent`] as each step of a build
+//! starts and finishes. Failures are not reported here: they end the run and are
+//! returned as its error.
+
+/// A step of a build, reported to a [`Progress`] observer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProgressEvent {
+    /// The bootstrap backend is about to run.
+    BootstrapStarted {
+        /// Backend command name (e.g. `mmdebstrap`).
+        command: String,
+    },
+    /// The bootstrap backend finished.
+    BootstrapFinished,
+    /// A pipeline phase is about to run its tasks. Phases with no task to run are
+    /// not reported.
+    PhaseStarted {
+        /// Phase name: `prepare`, `provision`, `assemble` or `verify`.
+        phase: &'static str,
+        /// Number of tasks the phase runs.
+        tasks: usize,
+    },
+    /// A pipeline phase ran all its tasks.
+    PhaseFinished {
+        /// Phase name: `prepare`, `provision`, `assemble` or `verify`.
+        phase: &'static str,
+    },
+    /// A pipeline task is about to run.
+    TaskStarted {
+        /// Phase name: `prepare`, `provision`, `assemble` or `verify`.
+        phase: &'static str,
+        /// 1-based position of the task in its phase.
+        number: usize,
+        /// Display name of the task.
+        name: String,
+    },
+    /// A pipeline task finished.
+    TaskFinished {
+        /// Phase name: `prepare`, `provision`, `assemble` or `verify`.
+        phase: &'static str,
+        /// 1-based position of the task in its phase.
+        number: usize,
+        /// Display name of the task.
+        name: String,
+    },
+}
+
+/// Observer of [`ProgressEvent`]s. Implemented for `Fn(&ProgressEvent)` closures.
+pub trait Progress: Send + Sync {
+    /// Called for each event, on the thread running the build.
+    fn report(&self, event: &ProgressEvent);
+}
+
+impl<F> Progress for F
+where
+    F: Fn(&ProgressEvent) + Send + Sync,
+{
+    fn report(&self, event: &ProgressEvent) {
+        self(event)
+    }
+}
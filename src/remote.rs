@@ -0,0 +1,323 @@
+//! Remote builds over SSH (`apply --remote`).
+//!
+//! This module provides:
+//! - [`SshExecutor`]: a [`CommandExecutor`] that runs each command on a remote host
+//!   through `ssh`
+//! - [`RemoteBuild`]: syncs a profile's directory to a builder and runs
+//!   `rsdebstrap apply` there
+//!
+//! The build itself runs on the builder, by the `rsdebstrap` installed there: the
+//! pipeline works on the rootfs through the filesystem as well as through commands, so
+//! it has to run on the host holding the rootfs. The profile and the scripts, recipes
+//! and other files it references live locally and are copied to the builder with
+//! `rsync` before every run, so files a profile uses must be inside its directory
+//! (or exist at the same absolute path on the builder).
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use tracing::info;
+
+use crate::cli::ApplyArgs;
+use crate::config;
+use crate::error::{RsdebstrapError, Stage};
+use crate::executor::{CommandExecutor, CommandSpec, ExecutionResult, shell_command_line};
+
+/// Options passed to every `ssh` call: fail instead of prompting for a password or
+/// host key confirmation, which would hang a build with no terminal.
+const SSH_OPTIONS: [&str; 2] = ["-o", "BatchMode=yes"];
+
+/// Layer cache of remote builds given `--layer-cache`, relative to the remote build
+/// directory. The local cache's path means nothing on the builder, and `rsync` leaves
+/// this directory alone, so the builder keeps its cache between runs.
+pub const REMOTE_LAYER_CACHE: &str = ".rsdebstrap-layer-cache";
+
+/// Returns true if `dir` is a usable `--remote-dir`: a relative path to a directory
+/// below the remote user's home.
+///
+/// `rsync --delete` makes the directory a copy of the profile's, so anything that
+/// could name the home directory itself, the root or another directory (`.`, `..`, an
+/// absolute path, `~` or a shell expansion) is rejected.
+pub fn is_remote_dir(dir: &Utf8Path) -> bool {
+    let mut components = dir.components().peekable();
+    components.peek().is_some()
+        && components.all(|component| match component {
+            camino::Utf8Component::Normal(name) => {
+                !name.starts_with(['-', '~'])
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "._+-@,".contains(c))
+            }
+            _ => false,
+        })
+}
+
+/// Returns true if `target` is a usable `[user@]host` ssh destination.
+///
+/// Rejects anything `ssh` or `rsync` would read as an option or a path, so the target
+/// never changes the meaning of their command lines.
+pub fn is_ssh_target(target: &str) -> bool {
+    let host = target.rsplit_once('@').map_or(target, |(_, host)| host);
+    !target.is_empty()
+        && !target.starts_with('-')
+        && !host.is_empty()
+        && !target.contains(['/', ':', ' ', '\t', '\n'])
+}
+
+/// Command executor that runs commands on a remote host with `ssh`.
+///
+/// Each [`CommandSpec`] becomes one `ssh <target> -- <command line>` run through the
+/// inner executor. The privilege escalation, environment and working directory are
+/// applied on the remote host (`<method> env -C <cwd> KEY=VALUE... <command>`);
+/// standard input and the timeout apply to the `ssh` process.
+pub struct SshExecutor {
+    target: String,
+    inner: Arc<dyn CommandExecutor>,
+}
+
+impl SshExecutor {
+    /// Creates an executor running commands on `target` (`[user@]host`) through `inner`.
+    pub fn new(target: &str, inner: Arc<dyn CommandExecutor>) -> Result<Self, RsdebstrapError> {
+        if !is_ssh_target(target) {
+            return Err(RsdebstrapError::Validation(format!(
+                "--remote {:?} is not a [user@]host ssh destination",
+                target
+            )));
+        }
+        Ok(Self {
+            target: target.to_string(),
+            inner,
+        })
+    }
+
+    /// Returns the remote host.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// Returns the shell command line that runs `spec` on the remote host.
+    pub fn remote_command_line(spec: &CommandSpec) -> String {
+        shell_command_line(spec)
+    }
+
+    /// Returns the local `ssh` command that runs `spec` on the remote host.
+    pub fn ssh_spec(&self, spec: &CommandSpec) -> CommandSpec {
+        let mut args: Vec<String> = SSH_OPTIONS.iter().map(|o| o.to_string()).collect();
+        args.push(self.target.clone());
+        args.push("--".to_string());
+        args.push(Self::remote_command_line(spec));
+        let mut ssh = CommandSpec::new("ssh", args);
+        ssh.timeout = spec.timeout;
+        ssh.stdin = spec.stdin.clone();
+        ssh
+    }
+}
+
+impl CommandExecutor for SshExecutor {
+    fn execute(&self, spec: &CommandSpec) -> Result<ExecutionResult> {
+        self.inner.execute(&self.ssh_spec(spec))
+    }
+}
+
+/// A build of a local profile on a remote builder.
+///
+/// [`apply()`](Self::apply) copies the profile's directory to `dir` on the builder (a
+/// path relative to the remote user's home directory), then runs `rsdebstrap apply`
+/// there with the same options.
+pub struct RemoteBuild {
+    ssh: SshExecutor,
+    dir: Utf8PathBuf,
+    local: Arc<dyn CommandExecutor>,
+}
+
+impl RemoteBuild {
+    /// Creates a build on `target` in the remote directory `dir`, running the local
+    /// `ssh`/`rsync` commands through `executor`.
+    pub fn new(
+        target: &str,
+        dir: &Utf8Path,
+        executor: Arc<dyn CommandExecutor>,
+    ) -> Result<Self, RsdebstrapError> {
+        if !is_remote_dir(dir) {
+            return Err(RsdebstrapError::Validation(format!(
+                "--remote-dir {:?} must be a subdirectory of the remote home, given as a \
+                relative path without '.', '..', '~' or shell characters",
+                dir
+            )));
+        }
+        Ok(Self {
+            ssh: SshExecutor::new(target, executor.clone())?,
+            dir: dir.to_owned(),
+            local: executor,
+        })
+    }
+
+    /// Returns the `rsync` command that copies `profile_dir` to the builder, leaving out
+    /// `exclude` (the local build output, relative to `profile_dir`) if given.
+    ///
+    /// `--delete` keeps the remote copy identical to the local directory; excluded
+    /// paths are neither sent nor deleted, so the builder keeps its own output and its
+    /// [`REMOTE_LAYER_CACHE`].
+    pub fn sync_spec(&self, profile_dir: &Utf8Path, exclude: Option<&Utf8Path>) -> CommandSpec {
+        let mut args = vec![
+            "--archive".to_string(),
+            "--delete".to_string(),
+            "--rsh".to_string(),
+            format!("ssh {}", SSH_OPTIONS.join(" ")),
+            format!("--exclude=/{}/", REMOTE_LAYER_CACHE),
+        ];
+        if let Some(exclude) = exclude {
+            args.push(format!("--exclude=/{}/", exclude));
+        }
+        args.push(format!("{}/", profile_dir));
+        args.push(format!("{}:{}/", self.ssh.target(), self.dir));
+        CommandSpec::new("rsync", args)
+    }
+
+    /// Syncs the profile in `opts` to the builder and runs `apply` there.
+    ///
+    /// The profile is loaded and validated locally first, so a broken profile fails
+    /// before anything is copied. In dry-run mode the `ssh` and `rsync` commands are
+    /// only logged.
+    pub fn apply(&self, opts: &ApplyArgs) -> Result<()> {
+        let file = &opts.common.file;
+        let profile = config::load_profile_for(
+            file,
+            opts.strictness.unknown_fields(),
+            opts.target.arch.as_deref(),
+        )
+        .with_context(|| Stage::Profile.context(format!("failed to load profile from {}", file)))?;
+        profile.validate().context("profile validation failed")?;
+
+        let canonical = file
+            .canonicalize_utf8()
+            .map_err(|e| RsdebstrapError::io(format!("failed to resolve {}", file), e))?;
+        let (Some(profile_dir), Some(file_name)) = (canonical.parent(), canonical.file_name())
+        else {
+            return Err(RsdebstrapError::Config(format!(
+                "could not determine the directory of profile {}",
+                canonical
+            ))
+            .into());
+        };
+        let output = profile
+            .dir
+            .strip_prefix(profile_dir)
+            .ok()
+            .filter(|p| !p.as_str().is_empty());
+
+        info!("syncing {} to {}:{}", profile_dir, self.ssh.target(), self.dir);
+        self.ssh
+            .execute_checked(&CommandSpec::new(
+                "mkdir",
+                vec!["-p".to_string(), self.dir.to_string()],
+            ))
+            .context(Stage::Bootstrap.context("failed to create the remote build directory"))?;
+        self.local
+            .execute_checked(&self.sync_spec(profile_dir, output))
+            .context(Stage::Bootstrap.context("failed to sync the profile to the builder"))?;
+
+        if opts.layer_cache.is_some() {
+            info!(
+                "using {}/{} on {} as the layer cache",
+                self.dir,
+                REMOTE_LAYER_CACHE,
+                self.ssh.target()
+            );
+        }
+        info!("running apply on {}", self.ssh.target());
+        let apply =
+            CommandSpec::new("rsdebstrap", opts.remote_args(file_name)).with_cwd(self.dir.clone());
+        self.ssh
+            .execute_checked(&apply)
+            .with_context(|| format!("remote apply on {} failed", self.ssh.target()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::privilege::PrivilegeMethod;
+
+    #[test]
+    fn ssh_targets() {
+        for target in ["builder", "ci@arm64.example.org", "user@10.0.0.2"] {
+            assert!(is_ssh_target(target), "{target}");
+        }
+        for target in ["", "-oProxyCommand=x", "user@", "host:/srv", "a b", "../x"] {
+            assert!(!is_ssh_target(target), "{target}");
+        }
+    }
+
+    #[test]
+    fn remote_command_line_applies_privilege_env_and_cwd_remotely() {
+        let spec = CommandSpec::new("mmdebstrap", vec!["trixie".to_string(), "it's".to_string()])
+            .with_privilege(Some(PrivilegeMethod::Sudo))
+            .with_cwd(Utf8PathBuf::from("/srv/build"))
+            .with_env("LANG", "C");
+        assert_eq!(
+            SshExecutor::remote_command_line(&spec),
+            "'sudo' 'env' '-C' '/srv/build' 'LANG=C' 'mmdebstrap' 'trixie' 'it'\\''s'"
+        );
+
+        let plain = CommandSpec::new("true", vec![]);
+        assert_eq!(SshExecutor::remote_command_line(&plain), "'true'");
+    }
+
+    #[test]
+    fn remote_dirs() {
+        for dir in ["rsdebstrap-remote", "builds/web", "ci.builds/v1.2"] {
+            assert!(is_remote_dir(Utf8Path::new(dir)), "{dir}");
+        }
+        for dir in [
+            "",
+            ".",
+            "./",
+            "..",
+            "builds/..",
+            "../x",
+            "/",
+            "/srv/build",
+            "~",
+            "~ci/x",
+            "$HOME",
+            "a b",
+            "-rf",
+            "x/-y",
+            "a;b",
+            "`x`",
+        ] {
+            assert!(!is_remote_dir(Utf8Path::new(dir)), "{dir}");
+        }
+
+        let executor: Arc<dyn CommandExecutor> =
+            Arc::new(crate::executor::RealCommandExecutor { dry_run: true });
+        let err = RemoteBuild::new("ci@builder", Utf8Path::new("."), executor)
+            .err()
+            .unwrap();
+        assert!(matches!(err, RsdebstrapError::Validation(_)), "{err:?}");
+    }
+
+    #[test]
+    fn sync_spec_excludes_local_output() {
+        let executor: Arc<dyn CommandExecutor> =
+            Arc::new(crate::executor::RealCommandExecutor { dry_run: true });
+        let build = RemoteBuild::new("ci@builder", Utf8Path::new("builds/web"), executor).unwrap();
+        let spec = build.sync_spec(Utf8Path::new("/home/me/web"), Some(Utf8Path::new("output")));
+        assert_eq!(spec.command, "rsync");
+        assert_eq!(
+            spec.args,
+            [
+                "--archive",
+                "--delete",
+                "--rsh",
+                "ssh -o BatchMode=yes",
+                "--exclude=/.rsdebstrap-layer-cache/",
+                "--exclude=/output/",
+                "/home/me/web/",
+                "ci@builder:builds/web/",
+            ]
+        );
+    }
+}
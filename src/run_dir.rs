@@ -0,0 +1,255 @@
+//! Per-run ID and working directory.
+//!
+//! Every [`Runner`](crate::Runner) run gets a [`RunId`]: its start time in UTC
+//! followed by a random suffix (`20261018T093512Z-3f9c2a1b`), so IDs sort by start
+//! time and two builds started in the same second still differ. It appears in the log,
+//! in the `run_started` event and in the names of the files tasks stage in the rootfs
+//! (`task-<run-id>-provision-2.sh`), so a leftover file names the run and the task
+//! that left it.
+//!
+//! A real run keeps its own files in [`RunDir`], `<dir>/.rsdebstrap/<run-id>/`: the
+//! downloaded task binaries and `state.json`, the run's status. A successful run
+//! removes its binaries; a failed one leaves everything for inspection. Only the
+//! newest [`KEEP_RUNS`] run directories are kept.
+
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Serialize;
+use tracing::{debug, warn};
+
+use crate::error::RsdebstrapError;
+use crate::phase::assemble::release::format_utc;
+
+/// Directory under the output directory holding the run directories.
+pub const RUNS_DIR: &str = ".rsdebstrap";
+
+/// Name of the status file in a run directory.
+pub const STATE_FILE: &str = "state.json";
+
+/// Name of the directory of downloaded task binaries in a run directory.
+pub const BINARIES_DIR: &str = "binaries";
+
+/// Number of run directories kept in [`RUNS_DIR`], this run's included.
+pub const KEEP_RUNS: usize = 10;
+
+/// Identifier of one run. See the [module documentation](self).
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RunId(String);
+
+impl RunId {
+    /// Returns a new ID for a run starting now.
+    pub fn generate() -> Self {
+        let suffix = uuid::Uuid::new_v4().simple().to_string();
+        Self::new(now(), &suffix[..8])
+    }
+
+    /// Returns the ID of a run started `secs` after the Unix epoch, made unique by
+    /// `suffix`.
+    fn new(secs: u64, suffix: &str) -> Self {
+        let stamp: String = format_utc(secs)
+            .chars()
+            .filter(|c| !matches!(c, '-' | ':'))
+            .collect();
+        Self(format!("{}-{}", stamp, suffix))
+    }
+
+    /// Returns the ID as a string.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for RunId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Returns the run directory of `run_id` for the output directory `dir`, whether or not
+/// it exists.
+pub fn run_dir_path(dir: &Utf8Path, run_id: &RunId) -> Utf8PathBuf {
+    dir.join(RUNS_DIR).join(run_id.as_str())
+}
+
+/// Status of a run, as recorded in [`STATE_FILE`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    /// The run has not finished (or the process died).
+    Running,
+    /// The build succeeded.
+    Succeeded,
+    /// The build failed.
+    Failed,
+}
+
+/// Contents of [`STATE_FILE`].
+#[derive(Debug, Serialize)]
+struct RunState<'a> {
+    run_id: &'a str,
+    started: &'a str,
+    status: RunStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    finished: Option<String>,
+}
+
+/// The working directory of a run. See the [module documentation](self).
+#[derive(Debug)]
+pub struct RunDir {
+    path: Utf8PathBuf,
+    run_id: RunId,
+    started: String,
+}
+
+impl RunDir {
+    /// Creates the run directory of `run_id` in the output directory `dir`, records
+    /// the run as running, and removes the oldest run directories beyond
+    /// [`KEEP_RUNS`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RsdebstrapError::Io`] if the directory or its state file cannot be
+    /// written.
+    pub fn create(dir: &Utf8Path, run_id: &RunId) -> Result<Self, RsdebstrapError> {
+        let path = run_dir_path(dir, run_id);
+        fs::create_dir_all(&path).map_err(|e| {
+            RsdebstrapError::io(format!("failed to create run directory {}", path), e)
+        })?;
+        let run_dir = Self {
+            path,
+            run_id: run_id.clone(),
+            started: format_utc(now()),
+        };
+        run_dir.write_state(RunStatus::Running)?;
+        prune(&dir.join(RUNS_DIR), run_id);
+        Ok(run_dir)
+    }
+
+    /// Returns the path of the run directory.
+    pub fn path(&self) -> &Utf8Path {
+        &self.path
+    }
+
+    /// Returns the directory downloaded task binaries are kept in.
+    pub fn binaries_dir(&self) -> Utf8PathBuf {
+        self.path.join(BINARIES_DIR)
+    }
+
+    /// Records how the run ended. A successful run also removes its task binaries.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RsdebstrapError::Io`] if the state file cannot be written or the
+    /// binaries cannot be removed.
+    pub fn finish(&self, success: bool) -> Result<(), RsdebstrapError> {
+        let status = if success {
+            RunStatus::Succeeded
+        } else {
+            RunStatus::Failed
+        };
+        self.write_state(status)?;
+        let binaries = self.binaries_dir();
+        if success && binaries.exists() {
+            fs::remove_dir_all(&binaries).map_err(|e| {
+                RsdebstrapError::io(format!("failed to remove task binaries {}", binaries), e)
+            })?;
+        }
+        Ok(())
+    }
+
+    fn write_state(&self, status: RunStatus) -> Result<(), RsdebstrapError> {
+        let state = RunState {
+            run_id: self.run_id.as_str(),
+            started: &self.started,
+            status,
+            finished: (status != RunStatus::Running).then(|| format_utc(now())),
+        };
+        let path = self.path.join(STATE_FILE);
+        let mut json = serde_json::to_string_pretty(&state).map_err(|e| {
+            RsdebstrapError::Config(format!("failed to serialize the run state: {}", e))
+        })?;
+        json.push('\n');
+        fs::write(&path, json)
+            .map_err(|e| RsdebstrapError::io(format!("failed to write {}", path), e))
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Removes the oldest run directories in `runs` beyond [`KEEP_RUNS`], never `current`.
+/// Failures are only warned about; stale run directories do not affect the build.
+fn prune(runs: &Utf8Path, current: &RunId) {
+    let Ok(entries) = runs.read_dir_utf8() else {
+        return;
+    };
+    let mut names: Vec<String> = entries
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|entry| entry.file_name().to_string())
+        .filter(|name| name != current.as_str())
+        .collect();
+    // Run IDs start with their UTC start time, so names sort oldest first.
+    names.sort();
+    let excess = (names.len() + 1).saturating_sub(KEEP_RUNS);
+    for name in names.into_iter().take(excess) {
+        let path = runs.join(&name);
+        match fs::remove_dir_all(&path) {
+            Ok(()) => debug!("removed old run directory {}", path),
+            Err(e) => warn!("failed to remove old run directory {}: {}", path, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_ids_sort_by_start_time() {
+        let id = RunId::new(1_767_323_045, "3f9c2a1b");
+        assert_eq!(id.as_str(), "20260102T030405Z-3f9c2a1b");
+        assert!(RunId::new(1_767_323_044, "ffffffff") < id);
+        assert_eq!(RunId::generate().as_str().len(), id.as_str().len());
+    }
+
+    #[test]
+    fn run_dir_records_the_outcome_and_keeps_the_newest_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        for n in 0..KEEP_RUNS + 2 {
+            fs::create_dir_all(dir.join(RUNS_DIR).join(format!("20250101T0000{:02}Z-0", n)))
+                .unwrap();
+        }
+
+        let id = RunId::new(1_767_323_045, "3f9c2a1b");
+        let run_dir = RunDir::create(dir, &id).unwrap();
+        assert_eq!(run_dir.path(), dir.join(".rsdebstrap/20260102T030405Z-3f9c2a1b"));
+        let state = fs::read_to_string(run_dir.path().join(STATE_FILE)).unwrap();
+        assert!(state.contains("\"status\": \"running\""), "{state}");
+        let mut kept: Vec<_> = dir
+            .join(RUNS_DIR)
+            .read_dir_utf8()
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string())
+            .collect();
+        kept.sort();
+        assert_eq!(kept.len(), KEEP_RUNS);
+        assert_eq!(kept[0], "20250101T000003Z-0");
+
+        fs::create_dir(run_dir.binaries_dir()).unwrap();
+        run_dir.finish(false).unwrap();
+        assert!(run_dir.binaries_dir().exists());
+        run_dir.finish(true).unwrap();
+        assert!(!run_dir.binaries_dir().exists());
+        let state = fs::read_to_string(run_dir.path().join(STATE_FILE)).unwrap();
+        assert!(state.contains("\"status\": \"succeeded\""), "{state}");
+        assert!(state.contains("\"finished\": \""), "{state}");
+    }
+}
@@ -0,0 +1,2119 @@
+//! Library entry point for building a rootfs from a profile.
+//!
+//! [`Runner`] runs what `rsdebstrap apply` runs — the bootstrap backend, then the
+//! prepare, provision and assemble phases — without going through the CLI argument
+//! types. Each choice the `apply` flags make has a `with_*` method, and the command
+//! executor and a [`Progress`] observer can be injected:
+//!
+//! ```no_run
+//! # fn main() -> anyhow::Result<()> {
+//! use camino::Utf8Path;
+//! use rsdebstrap::Runner;
+//! use rsdebstrap::progress::ProgressEvent;
+//!
+//! Runner::load(Utf8Path::new("profile.yml"))?
+//!     .with_dry_run(true)
+//!     .with_progress(|event: &ProgressEvent| eprintln!("{:?}", event))
+//!     .run()
+//! # }
+//! ```
+//!
+//! Unlike the CLI, a `Runner` does not install the Ctrl-C/SIGTERM handler; call
+//! [`crate::executor::install_interrupt_handler`] first for the same interrupt handling.
+
+use std::fs;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+use tracing::{info, warn};
+
+use crate::audit::{AUDIT_FILE, AuditExecutor, AuditLog};
+use crate::bootstrap::{ToolVersion, suite, version};
+use crate::config::{ProfileLayers, UnknownFields};
+use crate::disk_space::disk_usage;
+use crate::error::Stage;
+use crate::events::{Event, EventExecutor, EventStream};
+use crate::executor::{CommandExecutor, CommandSpec, RealCommandExecutor};
+use crate::isolation::apt_proxy::RootfsAptProxy;
+use crate::isolation::mount::{RootfsMounts, find_stale_mounts};
+use crate::isolation::namespace::PipelineMountNamespace;
+use crate::isolation::network_files::RootfsNetworkFiles;
+use crate::isolation::overlay::RootfsOverlay;
+use crate::isolation::resolv_conf::RootfsResolvConf;
+use crate::isolation::services::RootfsServiceBlock;
+use crate::isolation::staging::RootfsStaging;
+use crate::layer_cache::LayerCache;
+use crate::lock::{FileLock, dir_lock_path};
+use crate::metrics::{BuildMetrics, MeteredExecutor, MetricsFormat};
+use crate::phase::ProvisionTask;
+use crate::phase::assemble::relabel::host_selinux_enforcing;
+use crate::phase::assemble::release::{self, BuildInfo, is_git_commit};
+use crate::pipeline::{PhaseSelection, Pipeline, TagFilter};
+use crate::plan::{self, Plan};
+use crate::progress::{Progress, ProgressEvent};
+use crate::run_dir::{self, RunDir, RunId};
+use crate::sandbox::Sandbox;
+use crate::secrets::Secrets;
+use crate::summary::{self, RootfsUsage};
+use crate::task_record::TaskRecord;
+use crate::umask::UmaskGuard;
+use crate::upload::{self, Credentials};
+use crate::{
+    RsdebstrapError, bootstrap, config, disk_space, keyring, output_dir, preflight, privilege,
+    shellcheck, snapshot,
+};
+
+/// Builds the rootfs a profile describes: the library form of `rsdebstrap apply`.
+///
+/// By default every phase runs for real through [`RealCommandExecutor`]. The
+/// `with_*` methods narrow the run the way the `apply` flags do.
+pub struct Runner {
+    profile: config::Profile,
+    executor: Option<Arc<dyn CommandExecutor>>,
+    progress: Option<Arc<dyn Progress>>,
+    dry_run: bool,
+    bootstrap: bool,
+    selection: PhaseSelection,
+    tag_filter: TagFilter,
+    start_at_task: Option<String>,
+    clean_stale_mounts: bool,
+    allow_existing: bool,
+    wait_for_lock: bool,
+    source_commit: Option<String>,
+    overlay: bool,
+    layer_cache: Option<Utf8PathBuf>,
+    metrics: Vec<MetricsFormat>,
+    summary: Option<Utf8PathBuf>,
+    /// The metrics of the running build, set by [`run`](Self::run).
+    recorder: Option<Arc<BuildMetrics>>,
+    events: Option<Arc<EventStream>>,
+    run_id: RunId,
+    /// The run's working directory, created by a real build.
+    run_dir: Option<RunDir>,
+    /// Audit log of the privileged commands of a real build, kept in `run_dir`.
+    audit: Option<Arc<AuditLog>>,
+}
+
+impl Runner {
+    /// Creates a runner for `profile`, as returned by [`config::load_profile`].
+    pub fn new(profile: config::Profile) -> Self {
+        Self {
+            profile,
+            executor: None,
+            progress: None,
+            dry_run: false,
+            bootstrap: true,
+            selection: PhaseSelection::ALL,
+            tag_filter: TagFilter::default(),
+            start_at_task: None,
+            clean_stale_mounts: false,
+            allow_existing: false,
+            wait_for_lock: false,
+            source_commit: None,
+            overlay: false,
+            layer_cache: None,
+            metrics: Vec::new(),
+            summary: None,
+            recorder: None,
+            events: None,
+            run_id: RunId::generate(),
+            run_dir: None,
+            audit: None,
+        }
+    }
+
+    /// Loads the profile at `path` and creates a runner for it.
+    pub fn load(path: &Utf8Path) -> Result<Self> {
+        Self::load_with(path, UnknownFields::Deny)
+    }
+
+    /// Like [`Runner::load`], treating unknown profile fields as `unknown_fields` says.
+    pub fn load_with(path: &Utf8Path, unknown_fields: UnknownFields) -> Result<Self> {
+        Self::load_for(path, unknown_fields, None)
+    }
+
+    /// Like [`Runner::load_with`], for the profile's `targets` entry `arch` (`--arch`).
+    pub fn load_for(
+        path: &Utf8Path,
+        unknown_fields: UnknownFields,
+        arch: Option<&str>,
+    ) -> Result<Self> {
+        Self::load_layered(path, unknown_fields, arch, &ProfileLayers::default())
+    }
+
+    /// Like [`Runner::load_for`], with the profile between `layers` (see
+    /// [`config::load_profile_layered`]).
+    pub fn load_layered(
+        path: &Utf8Path,
+        unknown_fields: UnknownFields,
+        arch: Option<&str>,
+        layers: &ProfileLayers,
+    ) -> Result<Self> {
+        let profile = config::load_profile_layered(path, unknown_fields, arch, layers)
+            .with_context(|| {
+                Stage::Profile.context(format!("failed to load profile from {}", path))
+            })?;
+        Ok(Self::new(profile))
+    }
+
+    /// Runs commands through `executor` instead of a [`RealCommandExecutor`].
+    pub fn with_executor(mut self, executor: Arc<dyn CommandExecutor>) -> Self {
+        self.executor = Some(executor);
+        self
+    }
+
+    /// Reports the bootstrap and each pipeline task to `progress`.
+    pub fn with_progress(mut self, progress: impl Progress + 'static) -> Self {
+        self.progress = Some(Arc::new(progress));
+        self
+    }
+
+    /// Logs commands instead of running them and changes nothing on disk (`--dry-run`).
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Whether to run the bootstrap; without it the rootfs from an earlier run is
+    /// reused (`--skip-bootstrap`).
+    pub fn with_bootstrap(mut self, bootstrap: bool) -> Self {
+        self.bootstrap = bootstrap;
+        self
+    }
+
+    /// Restricts the pipeline to the selected stages (`--only`/`--skip`).
+    pub fn with_selection(mut self, selection: PhaseSelection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    /// Restricts the provision phase to tasks passing `filter` (`--tags`/`--skip-tags`).
+    pub fn with_tag_filter(mut self, filter: TagFilter) -> Self {
+        self.tag_filter = filter;
+        self
+    }
+
+    /// Skips the provision tasks before the one named `name` (`--start-at-task`).
+    pub fn with_start_at_task(mut self, name: Option<&str>) -> Self {
+        self.start_at_task = name.map(str::to_string);
+        self
+    }
+
+    /// Unmounts filesystems a crashed run left mounted in the rootfs before mounting
+    /// anything (`--clean-stale-mounts`); otherwise they are only warned about.
+    pub fn with_clean_stale_mounts(mut self, clean: bool) -> Self {
+        self.clean_stale_mounts = clean;
+        self
+    }
+
+    /// Builds into an existing non-empty `dir` not created by rsdebstrap even under
+    /// `dir_policy: owned` (`--allow-existing`).
+    pub fn with_allow_existing(mut self, allow: bool) -> Self {
+        self.allow_existing = allow;
+        self
+    }
+
+    /// Waits for another build holding the output directory's lock instead of failing
+    /// (`--wait-for-lock`).
+    pub fn with_wait_for_lock(mut self, wait: bool) -> Self {
+        self.wait_for_lock = wait;
+        self
+    }
+
+    /// Records `commit`, the profile repository's git commit, in the `assemble.release`
+    /// provenance file (`--source-commit`).
+    pub fn with_source_commit(mut self, commit: Option<&str>) -> Self {
+        self.source_commit = commit.map(str::to_string);
+        self
+    }
+
+    /// Runs the pipeline against an overlayfs over the rootfs (`--overlay`), leaving the
+    /// rootfs unchanged; the changes stay in `<rootfs>.overlay/upper`.
+    pub fn with_overlay(mut self, overlay: bool) -> Self {
+        self.overlay = overlay;
+        self
+    }
+
+    /// Replays unchanged leading provision tasks from the layer cache in `dir` and
+    /// caches the changes of the tasks that run (`--layer-cache`).
+    pub fn with_layer_cache(mut self, dir: Option<&Utf8Path>) -> Self {
+        self.layer_cache = dir.map(Utf8Path::to_owned);
+        self
+    }
+
+    /// Writes the build's metrics to the profile's `dir` in each of `formats`, whether
+    /// the build succeeds or fails (`--metrics`).
+    pub fn with_metrics(mut self, formats: &[MetricsFormat]) -> Self {
+        self.metrics = formats.to_vec();
+        self
+    }
+
+    /// Also writes the end-of-run [summary](crate::summary) to `path` as JSON, whether
+    /// the build succeeds or fails (`--summary`).
+    pub fn with_summary(mut self, path: Option<&Utf8Path>) -> Self {
+        self.summary = path.map(Utf8Path::to_owned);
+        self
+    }
+
+    /// Writes the run's steps, commands and outcome to `events` (`--events-fd`,
+    /// `--events-file`).
+    pub fn with_events(mut self, events: Option<Arc<EventStream>>) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Returns the profile this runner builds.
+    pub fn profile(&self) -> &config::Profile {
+        &self.profile
+    }
+
+    /// Returns the ID of the run, which names its working directory
+    /// (`<dir>/.rsdebstrap/<run-id>/`) and the files its tasks stage.
+    pub fn run_id(&self) -> &RunId {
+        &self.run_id
+    }
+
+    /// Validates the profile and the selection, then returns the steps [`run`](Self::run)
+    /// would take, without running anything.
+    pub fn plan(&self) -> Result<Plan> {
+        self.check()?;
+        plan::build_plan(&self.profile, self.bootstrap, &self.pipeline(&self.profile))
+    }
+
+    /// Validates the profile and the selection, then builds the rootfs.
+    pub fn run(mut self) -> Result<()> {
+        let Some(events) = self.events.clone() else {
+            return self.run_checked();
+        };
+        events.emit(&Event::RunStarted {
+            run_id: self.run_id.to_string(),
+            dry_run: self.dry_run,
+        });
+        self.executor = Some(Arc::new(EventExecutor::new(self.executor(), Arc::clone(&events))));
+        self.observe(events.clone());
+
+        let result = self.run_checked();
+        events.emit(&Event::RunFinished {
+            success: result.is_ok(),
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+        });
+        result
+    }
+
+    fn run_checked(&mut self) -> Result<()> {
+        if self.dry_run {
+            warn!("DRY-RUN MODE: No changes will be made");
+        }
+        info!("run ID: {}", self.run_id);
+        self.check()?;
+
+        // Every privileged command of a real build, the pre-flight check's included.
+        if !self.dry_run {
+            let audit = Arc::new(AuditLog::new());
+            self.executor = Some(Arc::new(AuditExecutor::new(self.executor(), Arc::clone(&audit))));
+            self.audit = Some(audit);
+        }
+
+        // Recorded for the end-of-run summary even without `--metrics`.
+        let metrics = Arc::new(BuildMetrics::new());
+        self.executor = Some(Arc::new(MeteredExecutor::new(self.executor(), Arc::clone(&metrics))));
+        self.observe(metrics.clone());
+        self.recorder = Some(Arc::clone(&metrics));
+        let rootfs_before = self.rootfs_dir().map(|rootfs| disk_usage(&rootfs));
+
+        let result = self.build();
+        if let Some(audit) = &self.audit
+            && audit.pending() > 0
+        {
+            warn!(
+                "{} privileged command(s) not written to the audit log: the build failed \
+                before creating the run directory",
+                audit.pending()
+            );
+        }
+        if let Some(run_dir) = &self.run_dir
+            && let Err(e) = run_dir.finish(result.is_ok())
+        {
+            warn!("failed to record the run's outcome: {}", e);
+        }
+        metrics.finish(result.is_ok());
+        self.summarize(&metrics, rootfs_before);
+        if !self.metrics.is_empty() {
+            self.write_metrics(&metrics);
+        }
+        result
+    }
+
+    /// Returns the rootfs directory if the bootstrap writes one and it exists, outside
+    /// dry-run mode.
+    fn rootfs_dir(&self) -> Option<Utf8PathBuf> {
+        if self.dry_run {
+            return None;
+        }
+        self.rootfs_output().filter(|rootfs| rootfs.is_dir())
+    }
+
+    /// Returns the rootfs directory the bootstrap writes, unless it writes an archive
+    /// or image.
+    fn rootfs_output(&self) -> Option<Utf8PathBuf> {
+        match self
+            .profile
+            .bootstrap
+            .as_backend()
+            .rootfs_output(&self.profile.dir)
+        {
+            Ok(bootstrap::RootfsOutput::Directory(rootfs)) => Some(rootfs),
+            _ => None,
+        }
+    }
+
+    /// Logs the end-of-run summary and writes it to `summary` if set. `rootfs_before`
+    /// is the rootfs's disk usage when the build started. A failure to write is only
+    /// warned about, so it never hides the outcome of the build.
+    fn summarize(&self, metrics: &BuildMetrics, rootfs_before: Option<u64>) {
+        let mut report = metrics.summary();
+        report.rootfs = self.rootfs_dir().map(|path| {
+            let bytes = disk_usage(&path);
+            RootfsUsage {
+                path,
+                bytes,
+                added_bytes: bytes.saturating_sub(rootfs_before.unwrap_or(0)),
+            }
+        });
+        if !self.dry_run {
+            report.artifacts = summary::artifacts(&self.profile.dir);
+        }
+        for line in report.lines() {
+            info!("{}", line);
+        }
+
+        let Some(path) = &self.summary else {
+            return;
+        };
+        if self.dry_run {
+            info!("would write the build summary to {}", path);
+            return;
+        }
+        match report.write(path) {
+            Ok(()) => info!("wrote the build summary to {}", path),
+            Err(e) => warn!("failed to write the build summary: {}", e),
+        }
+    }
+
+    /// Reports progress to `observer` as well as to the progress observer set so far.
+    fn observe(&mut self, observer: Arc<dyn Progress>) {
+        let progress = self.progress.take();
+        self.progress = Some(Arc::new(move |event: &ProgressEvent| {
+            if let Some(progress) = &progress {
+                progress.report(event);
+            }
+            observer.report(event);
+        }));
+    }
+
+    fn executor(&self) -> Arc<dyn CommandExecutor> {
+        self.executor.clone().unwrap_or_else(|| {
+            Arc::new(RealCommandExecutor {
+                dry_run: self.dry_run,
+            })
+        })
+    }
+
+    /// Writes `metrics` next to the artifacts. A failure is only warned about, so it
+    /// never hides the outcome of the build.
+    fn write_metrics(&self, metrics: &BuildMetrics) {
+        let dir = &self.profile.dir;
+        if self.dry_run {
+            for format in &self.metrics {
+                info!("would write metrics to {}", dir.join(format.file_name()));
+            }
+            return;
+        }
+        if !dir.is_dir() {
+            warn!("not writing metrics: {} does not exist", dir);
+            return;
+        }
+        match metrics.write(dir, &self.metrics) {
+            Ok(paths) => {
+                for path in paths {
+                    info!("wrote metrics to {}", path);
+                }
+            }
+            Err(e) => warn!("failed to write metrics: {}", e),
+        }
+    }
+
+    fn build(&mut self) -> Result<()> {
+        let executor = self.executor();
+        let dry_run = self.dry_run;
+        // Files the build creates, and those of every command it runs, get the
+        // profile's umask; a build setting one runs alone.
+        let _umask = UmaskGuard::enter(self.profile.defaults.umask);
+
+        // Another build writing to the same directory would corrupt this one, its mounts
+        // included; lock before anything else so a concurrent apply fails (or waits).
+        let _dir_lock = if dry_run {
+            None
+        } else {
+            Some(self.lock_output_dir()?)
+        };
+
+        // Fail fast (and take any password prompt) before the bootstrap starts, then keep
+        // sudo's cached credentials alive until the pipeline finishes.
+        let _keepalive = if dry_run {
+            None
+        } else {
+            let methods = self.profile.privilege_methods();
+            preflight::check_privilege(&methods, executor.as_ref())
+                .context("privilege pre-flight check failed")?;
+            Some(preflight::CredentialKeepalive::start(&methods, executor.clone()))
+        };
+
+        let result = self.build_locked(&executor);
+        // Hand the output over even after a failure, which leaves it to be removed.
+        let Some(permissions) = &self.profile.output_permissions else {
+            return result;
+        };
+        let applied = permissions
+            .apply(
+                &self.profile.dir,
+                self.rootfs_output().as_deref(),
+                executor.as_ref(),
+                self.profile.defaults.privilege.as_ref().map(|d| d.method),
+                dry_run,
+            )
+            .context(Stage::Pipeline.context("failed to set the output permissions"));
+        match (result, applied) {
+            (Ok(()), applied) => applied,
+            (Err(e), Ok(())) => Err(e),
+            (Err(e), Err(applied)) => {
+                warn!("{:#}", applied);
+                Err(e)
+            }
+        }
+    }
+
+    /// Runs the build once `dir` is locked and privilege is available.
+    fn build_locked(&mut self, executor: &Arc<dyn CommandExecutor>) -> Result<()> {
+        let executor = executor.clone();
+        let dry_run = self.dry_run;
+
+        // Key the layers before the run adjusts the profile (mirror selection, task
+        // binary downloads).
+        let layer_cache = match &self.layer_cache {
+            Some(dir) if dry_run => {
+                info!("not using the layer cache in {} in dry-run mode", dir);
+                None
+            }
+            Some(dir) => Some(
+                LayerCache::open(
+                    dir,
+                    &self.profile,
+                    executor.clone(),
+                    self.profile.defaults.privilege.as_ref().map(|d| d.method),
+                )
+                .context(Stage::Pipeline.context("failed to open the layer cache"))?,
+            ),
+            None => None,
+        };
+
+        // Older backends reject newer flags; know the release before building arguments.
+        let backend_version = if self.bootstrap {
+            let command = self.profile.bootstrap.as_backend().command_name();
+            let found = version::detect(command);
+            version::check_min_version(
+                command,
+                found.as_ref(),
+                self.profile.bootstrap.min_version(),
+                dry_run,
+            )
+            .context(Stage::Bootstrap.context("bootstrap tool version check failed"))?;
+            found
+        } else {
+            None
+        };
+
+        let profile = &mut self.profile;
+        // Hash the profile before the run adjusts it (mirror selection, keyrings).
+        if profile.assemble.release.is_some() {
+            let build = BuildInfo::for_profile(
+                profile,
+                backend_version.as_ref(),
+                self.source_commit.as_deref(),
+            )?;
+            if let Some(release) = profile.assemble.release.as_mut() {
+                release.build = Some(build);
+            }
+        }
+        // Read the credentials now rather than fail after the whole build.
+        let upload = match &profile.upload {
+            Some(config) if self.selection.assemble => {
+                let metadata = upload::object_metadata(profile, &release::profile_sha256(profile)?);
+                let credentials = match Credentials::from_env(config.provider) {
+                    Ok(credentials) => Some(credentials),
+                    Err(e) if dry_run => {
+                        warn!("{}", e);
+                        None
+                    }
+                    Err(e) => {
+                        return Err(e).context(
+                            Stage::Pipeline.context("failed to read the upload credentials"),
+                        );
+                    }
+                };
+                Some((metadata, credentials))
+            }
+            _ => None,
+        };
+        let claim_dir =
+            output_dir::check_policy(&profile.dir, profile.dir_policy, self.allow_existing)
+                .context(Stage::Bootstrap.context("output directory check failed"))?;
+        if !dry_run && !profile.dir.exists() {
+            fs::create_dir_all(&profile.dir).with_context(|| {
+                Stage::Bootstrap.context(format!("failed to create directory: {}", profile.dir))
+            })?;
+        }
+        if claim_dir && !dry_run {
+            output_dir::write_marker(&profile.dir)
+                .context(Stage::Bootstrap.context("failed to mark the output directory"))?;
+        }
+        if !dry_run {
+            let run_dir = RunDir::create(&profile.dir, &self.run_id)
+                .context(Stage::Bootstrap.context("failed to create the run directory"))?;
+            info!("run directory: {}", run_dir.path());
+            if let Some(audit) = &self.audit {
+                audit
+                    .attach(&run_dir.path().join(AUDIT_FILE))
+                    .context(Stage::Bootstrap.context("failed to open the audit log"))?;
+            }
+            self.run_dir = Some(run_dir);
+        }
+
+        // Fail before the bootstrap rather than with ENOSPC halfway through it.
+        if self.bootstrap {
+            disk_space::check_free_space(profile, dry_run)
+                .context(Stage::Bootstrap.context("free space pre-check failed"))?;
+        }
+
+        // Read before the mirror health check, which needs the mirrors' real URLs.
+        let secrets = Secrets::resolve(&profile.secrets)
+            .context(Stage::Bootstrap.context("failed to resolve secrets"))?;
+        profile.bootstrap.substitute_secrets(&secrets);
+
+        if self.bootstrap
+            && let Some(snapshot) = &profile.snapshot
+        {
+            let stamp =
+                snapshot::stamp(snapshot).context(Stage::Bootstrap.context("invalid snapshot"))?;
+            info!("building from {} as of {}", snapshot::SNAPSHOT_HOST, stamp);
+            for mirror in profile.bootstrap.apply_snapshot(&stamp) {
+                warn!(
+                    "mirror {} is not a Debian archive and is not pinned to the snapshot",
+                    bootstrap::sanitize_credential(&mirror)
+                );
+            }
+        }
+
+        // Downloaded keyrings live in a temporary directory that must outlive the bootstrap.
+        let _keyrings = if self.bootstrap {
+            profile
+                .bootstrap
+                .select_mirror(executor.as_ref())
+                .context("failed to select a bootstrap mirror")?;
+            let keyrings = keyring::prepare_keyrings(&profile.keyrings, executor.as_ref(), dry_run)
+                .context(Stage::Bootstrap.context("failed to prepare keyrings"))?;
+            profile.bootstrap.add_keyrings(&keyrings.paths);
+            Some(keyrings)
+        } else {
+            info!("skipping bootstrap phase, reusing the existing rootfs");
+            None
+        };
+
+        // Fetch task binaries and prepare downloads before the bootstrap so a bad
+        // download fails fast; the download task then copies from its cache.
+        if self.selection.provision
+            && let Some(download) = &profile.prepare.download
+        {
+            download
+                .fetch(executor.as_ref(), dry_run)
+                .context(Stage::Pipeline.context("failed to fetch prepare downloads"))?;
+        }
+        if self.selection.provision {
+            let binaries =
+                run_dir::run_dir_path(&profile.dir, &self.run_id).join(run_dir::BINARIES_DIR);
+            download_task_binaries(
+                profile,
+                &self.tag_filter,
+                self.start_at_task.as_deref(),
+                &binaries,
+                executor.as_ref(),
+                dry_run,
+            )?;
+        }
+
+        let profile = &self.profile;
+        // Keep the cache running until the pipeline finishes; the built-in proxy stops
+        // when this is dropped.
+        let apt_cache = profile
+            .apt_cache
+            .as_ref()
+            .map(|c| c.start(profile.bootstrap.suite()))
+            .transpose()
+            .context(Stage::Bootstrap.context("failed to start apt cache"))?;
+        let proxy_url = apt_cache.as_ref().map(|c| c.url());
+        if let (Some(recorder), Some(stats)) =
+            (&self.recorder, apt_cache.as_ref().and_then(|c| c.stats()))
+        {
+            recorder.watch_apt_cache(stats);
+        }
+
+        if self.bootstrap {
+            let command = profile.bootstrap.as_backend().command_name().to_string();
+            self.report(ProgressEvent::BootstrapStarted { command });
+            run_bootstrap_phase(
+                profile,
+                &executor,
+                backend_version.as_ref(),
+                proxy_url.as_deref(),
+            )?;
+            if profile.snapshot.is_some()
+                && let config::Bootstrap::Debootstrap(_) = &profile.bootstrap
+                && let bootstrap::RootfsOutput::Directory(rootfs) =
+                    profile.bootstrap.as_backend().rootfs_output(&profile.dir)?
+            {
+                snapshot::write_apt_conf(
+                    &rootfs,
+                    &executor,
+                    profile.bootstrap.command_privilege_method(),
+                    dry_run,
+                )
+                .context(Stage::Bootstrap.context("failed to configure apt for the snapshot"))?;
+            }
+            self.report(ProgressEvent::BootstrapFinished);
+        }
+        if !self.selection.is_none() {
+            run_pipeline_phase(
+                profile,
+                self.pipeline(profile)
+                    .with_layer_cache(layer_cache.as_ref())
+                    .with_secrets(Some(&secrets)),
+                executor.clone(),
+                proxy_url.as_deref(),
+                self.clean_stale_mounts,
+                dry_run,
+                self.overlay,
+            )?;
+            if self.overlay {
+                info!("not recording provision tasks: the overlay left the rootfs unchanged");
+            } else if self.selection.provision && !dry_run {
+                self.record_task_runs()?;
+            }
+        }
+        if self.selection.assemble
+            && let Some(checksums) = &profile.checksums
+        {
+            checksums
+                .write(
+                    &profile.dir,
+                    profile.default_checksum_artifact(),
+                    executor.as_ref(),
+                    dry_run,
+                )
+                .context(Stage::Pipeline.context("failed to write artifact checksums"))?;
+        }
+        if let (Some(config), Some((metadata, credentials))) = (&profile.upload, &upload) {
+            let artifacts =
+                config.artifacts(profile.default_checksum_artifact(), profile.checksums.as_ref());
+            config
+                .upload(
+                    &profile.dir,
+                    &artifacts,
+                    metadata,
+                    credentials.as_ref(),
+                    executor.as_ref(),
+                    dry_run,
+                )
+                .context(Stage::Pipeline.context("failed to upload artifacts"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Takes the output directory's lock, failing if another build holds it unless
+    /// `wait_for_lock` is set. Creates the directory for the lock file.
+    fn lock_output_dir(&self) -> Result<FileLock> {
+        let dir = &self.profile.dir;
+        if !dir.exists() {
+            fs::create_dir_all(dir).with_context(|| {
+                Stage::Bootstrap.context(format!("failed to create directory: {}", dir))
+            })?;
+        }
+        let path = dir_lock_path(dir);
+        let lock = if self.wait_for_lock {
+            FileLock::exclusive
+        } else {
+            FileLock::try_exclusive
+        };
+        lock(&path, "the output directory")
+            .context(Stage::Bootstrap.context("failed to lock the output directory"))
+    }
+
+    fn report(&self, event: ProgressEvent) {
+        if let Some(progress) = &self.progress {
+            progress.report(&event);
+        }
+    }
+
+    /// Returns `profile`'s pipeline narrowed to the phases and tasks this run selects.
+    ///
+    /// Takes the profile separately so the pipeline can borrow it while `self` is not.
+    fn pipeline<'a>(&'a self, profile: &'a config::Profile) -> Pipeline<'a> {
+        profile
+            .pipeline()
+            .with_selection(self.selection)
+            .with_tag_filter(self.tag_filter.clone())
+            .with_start_at_task(self.start_at_task.as_deref())
+            .with_progress(self.progress.as_deref())
+            .with_run_id(Some(&self.run_id))
+    }
+
+    /// Validates the profile, then checks that the selection makes a runnable build.
+    ///
+    /// `--start-at-task` must name a provision task, in a run that provisions. At least
+    /// one phase must remain, and a pipeline run without the bootstrap needs
+    /// the rootfs from an earlier run (not checked in dry-run mode, which creates nothing).
+    fn check(&self) -> Result<()> {
+        let profile = &self.profile;
+        profile.validate().context("profile validation failed")?;
+        shellcheck::check_profile(profile).context("shell task linting failed")?;
+        for tag in self.tag_filter.unused_tags(&profile.provision) {
+            warn!("no provision task is tagged {:?}", tag);
+        }
+
+        if let Some(name) = self.start_at_task.as_deref() {
+            if !self.selection.provision {
+                return Err(RsdebstrapError::Validation(
+                    "--start-at-task needs the provision phase".to_string(),
+                )
+                .into());
+            }
+            if !profile
+                .provision
+                .iter()
+                .any(|t| t.configured_name() == Some(name))
+            {
+                return Err(RsdebstrapError::Validation(format!(
+                    "--start-at-task: no provision task is named {:?}",
+                    name
+                ))
+                .into());
+            }
+        }
+        if let Some(commit) = self.source_commit.as_deref() {
+            if !is_git_commit(commit) {
+                return Err(RsdebstrapError::Validation(format!(
+                    "--source-commit {:?} is not a git commit (4 to 64 hex digits)",
+                    commit
+                ))
+                .into());
+            }
+            if profile.assemble.release.is_none() {
+                warn!("--source-commit is only recorded by an assemble.release task");
+            }
+        }
+        if self.overlay && self.selection.is_none() {
+            warn!("--overlay has no effect without a pipeline phase");
+        }
+        if self.bootstrap {
+            let name = profile.bootstrap.suite();
+            if let Some(release) = suite::resolve(name)
+                && release.codename != name
+                && profile.bootstrap.preset().is_none()
+            {
+                info!(
+                    "suite {} is Debian {} (as of {})",
+                    name,
+                    release.codename,
+                    suite::TABLE_DATE
+                );
+            }
+            for warning in profile.bootstrap.suite_warnings(suite::Date::today()) {
+                warn!("{}", warning);
+            }
+        }
+        if profile.assemble.relabel.is_none() && host_selinux_enforcing() {
+            warn!(
+                "the host enforces SELinux: files in the rootfs get the host's labels; \
+                add an assemble.relabel task to apply the image's own policy"
+            );
+        }
+        if self.layer_cache.is_some() {
+            if self.overlay {
+                return Err(RsdebstrapError::Validation(
+                    "--layer-cache cannot be combined with --overlay".to_string(),
+                )
+                .into());
+            }
+            if !self.bootstrap {
+                return Err(RsdebstrapError::Validation(
+                    "--layer-cache needs the bootstrap phase: cached layers apply to a \
+                    freshly bootstrapped rootfs"
+                        .to_string(),
+                )
+                .into());
+            }
+            if !self.selection.provision {
+                warn!("--layer-cache has no effect without the provision phase");
+            }
+        }
+        if !self.bootstrap && self.selection.is_none() {
+            return Err(RsdebstrapError::Validation(
+                "--only/--skip leave no phase to run".to_string(),
+            )
+            .into());
+        }
+        if self.bootstrap || self.selection.is_none() {
+            return Ok(());
+        }
+        if self.pipeline(profile).is_empty() {
+            warn!("the selected phases have no tasks; nothing to do");
+            return Ok(());
+        }
+        let backend = profile.bootstrap.as_backend();
+        if let bootstrap::RootfsOutput::Directory(rootfs) = backend.rootfs_output(&profile.dir)?
+            && !self.dry_run
+            && !rootfs.is_dir()
+        {
+            return Err(RsdebstrapError::Validation(format!(
+                "rootfs {} does not exist; run the bootstrap phase before skipping it",
+                rootfs
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Records the digests of the provision tasks this run ran, for `diff`.
+    ///
+    /// Tasks skipped by the selection keep their earlier entries; entries past the end
+    /// of the profile's provision list are dropped.
+    fn record_task_runs(&self) -> Result<()> {
+        let profile = &self.profile;
+        let bootstrap::RootfsOutput::Directory(rootfs) =
+            profile.bootstrap.as_backend().rootfs_output(&profile.dir)?
+        else {
+            return Ok(());
+        };
+        let mut record = TaskRecord::load(&rootfs)?;
+        for (number, _) in self.pipeline(profile).selected_provision_items() {
+            record.insert(number, &profile.provision[number - 1])?;
+        }
+        record.truncate(profile.provision.len());
+        record
+            .save(&rootfs)
+            .context(Stage::Pipeline.context("failed to record the provision tasks that ran"))
+    }
+}
+
+/// Downloads the binaries of provision tasks that give a `url` instead of a path,
+/// for the tasks selected by `tags` from `start_at_task` on.
+///
+/// The downloads go to `dir`, the run directory's `binaries`, which a successful run
+/// removes when it finishes.
+fn download_task_binaries(
+    profile: &mut config::Profile,
+    tags: &TagFilter,
+    start_at_task: Option<&str>,
+    dir: &Utf8Path,
+    executor: &dyn CommandExecutor,
+    dry_run: bool,
+) -> Result<()> {
+    let start = start_at_task
+        .and_then(|name| {
+            profile
+                .provision
+                .iter()
+                .position(|t| t.configured_name() == Some(name))
+        })
+        .unwrap_or(0);
+    let needs_download = |index: usize, t: &ProvisionTask| {
+        index >= start && t.binary_url().is_some() && tags.matches(t.tags())
+    };
+    if !profile
+        .provision
+        .iter()
+        .enumerate()
+        .any(|(index, t)| needs_download(index, t))
+    {
+        return Ok(());
+    }
+
+    if !dry_run {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("failed to create task binary download directory {}", dir))?;
+    }
+    for (index, task) in profile.provision.iter_mut().enumerate() {
+        if !needs_download(index, task) {
+            continue;
+        }
+        let dest = dir.join(format!("{}-binary", index));
+        task.download_binary(&dest, executor, dry_run)
+            .with_context(|| {
+                Stage::Pipeline
+                    .context(format!("failed to fetch binary for provision task {}", task.name()))
+            })?;
+    }
+    Ok(())
+}
+
+/// Executes the bootstrap phase using the configured backend.
+///
+/// With a `proxy_url`, the backend runs under `env http_proxy=<url>`: both
+/// mmdebstrap (through apt) and debootstrap (through wget) honor the variable, and
+/// setting it with `env` survives privilege escalation, which would otherwise reset
+/// the environment. Unlike an apt option, it leaves no trace in the rootfs.
+///
+/// With a known `version` of the backend, the arguments are adapted to it.
+fn run_bootstrap_phase(
+    profile: &config::Profile,
+    executor: &Arc<dyn CommandExecutor>,
+    version: Option<&ToolVersion>,
+    proxy_url: Option<&str>,
+) -> Result<()> {
+    let backend = profile.bootstrap.as_backend();
+    let command_name = backend.command_name();
+
+    let mut args = backend.build_args(&profile.dir).with_context(|| {
+        Stage::Bootstrap.context(format!("failed to build arguments for {}", command_name))
+    })?;
+    if let Some(version) = version {
+        args = backend.adapt_args(args, version).with_context(|| {
+            Stage::Bootstrap
+                .context(format!("{} {} does not support the profile", command_name, version))
+        })?;
+    }
+
+    let privilege = profile.bootstrap.command_privilege_method();
+    let spec = match proxy_url {
+        Some(url) => {
+            let mut env_args = vec![format!("http_proxy={}", url), command_name.to_string()];
+            env_args.extend(args);
+            CommandSpec::new("env", env_args)
+        }
+        None => CommandSpec::new(command_name, args),
+    }
+    .with_privilege(privilege);
+    let stream_to = profile.bootstrap.stream_to();
+    if stream_to.is_empty() {
+        executor.execute_checked(&spec)
+    } else {
+        // The archive flows from the backend straight into the stream_to commands.
+        let mut stages = vec![spec];
+        stages.extend(stream_to.iter().map(|command| {
+            CommandSpec::new(&command[0], command[1..].to_vec()).with_cwd(profile.dir.clone())
+        }));
+        executor.execute_pipeline(&stages)
+    }
+    .with_context(|| Stage::Bootstrap.context(format!("failed to execute {}", command_name)))?;
+
+    Ok(())
+}
+
+/// Reports filesystems a previous run left mounted under `rootfs` and, with `clean`,
+/// unmounts them.
+///
+/// Left in place, they would end up nested under the new mounts and be missed by
+/// their teardown.
+fn handle_stale_mounts(
+    rootfs: &Utf8Path,
+    executor: &Arc<dyn CommandExecutor>,
+    privilege: Option<privilege::PrivilegeMethod>,
+    unmount_policy: config::UnmountPolicy,
+    clean: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let stale = find_stale_mounts(rootfs)?;
+    if stale.is_empty() {
+        return Ok(());
+    }
+    let listed = stale
+        .iter()
+        .map(|m| m.mount_point.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    if !clean {
+        warn!(
+            "{} filesystem(s) still mounted under {} from a previous run: {}. \
+            Re-run with --clean-stale-mounts to unmount them first",
+            stale.len(),
+            rootfs,
+            listed
+        );
+        return Ok(());
+    }
+
+    warn!("unmounting {} stale filesystem(s) under {}: {}", stale.len(), rootfs, listed);
+    let mut mounts =
+        RootfsMounts::adopt_stale(rootfs, &stale, executor.clone(), privilege, dry_run)
+            .with_unmount_policy(unmount_policy);
+    mounts
+        .unmount()
+        .context(Stage::Pipeline.context("failed to unmount stale filesystems"))
+}
+
+/// Executes the pipeline phase (prepare, provision, assemble).
+///
+/// `pipeline` is `profile.pipeline()`, narrowed to the tasks this run selects. With
+/// `overlay`, it runs against an overlayfs over the rootfs and leaves the rootfs itself
+/// untouched.
+fn run_pipeline_phase(
+    profile: &config::Profile,
+    pipeline: Pipeline<'_>,
+    executor: Arc<dyn CommandExecutor>,
+    proxy_url: Option<&str>,
+    clean_stale_mounts: bool,
+    dry_run: bool,
+    overlay: bool,
+) -> Result<()> {
+    if pipeline.is_empty() {
+        return Ok(());
+    }
+
+    // Get rootfs directory (validation ensures it's a directory if tasks exist)
+    let backend = profile.bootstrap.as_backend();
+    let bootstrap::RootfsOutput::Directory(rootfs) = backend.rootfs_output(&profile.dir)? else {
+        return Err(RsdebstrapError::Validation(
+            "pipeline tasks require directory output but bootstrap is configured for \
+            non-directory format. Please set bootstrap format to 'directory' or remove \
+            pipeline tasks."
+                .to_string(),
+        )
+        .into());
+    };
+
+    let privilege = profile.defaults.privilege.as_ref().map(|d| d.method);
+
+    // With `--overlay`, everything below runs against the overlay's merged view. The
+    // overlay is unmounted last, after the pipeline's own mounts (its Drop guard covers
+    // the error paths).
+    let mut overlay =
+        overlay.then(|| RootfsOverlay::new(&rootfs, executor.clone(), privilege, dry_run));
+    let rootfs = match overlay.as_mut() {
+        Some(overlay) => {
+            overlay
+                .mount()
+                .context(Stage::Pipeline.context("failed to mount the overlay over the rootfs"))?;
+            overlay.merged()
+        }
+        None => rootfs,
+    };
+
+    let unmount_policy = profile.defaults.isolation.unmount_policy();
+    handle_stale_mounts(
+        &rootfs,
+        &executor,
+        privilege,
+        unmount_policy,
+        clean_stale_mounts,
+        dry_run,
+    )?;
+
+    // Enter a private mount namespace once stale mounts (which live in the host's) are
+    // dealt with, so every mount below stays out of the host's mount table. Left after
+    // everything below is torn down, before the overlay is unmounted.
+    let _mount_namespace = if profile.defaults.isolation.mount_namespace() {
+        let required = profile.defaults.isolation.requires_mount_namespace();
+        PipelineMountNamespace::enter(privilege, required, dry_run)
+            .context(Stage::Pipeline.context("failed to enter a private mount namespace"))?
+    } else {
+        None
+    };
+
+    // Set up filesystem mounts (prepare phase mounts and the shared context directory)
+    let mount_entries = profile.pipeline_mounts();
+    let mut mounts =
+        RootfsMounts::new(&rootfs, mount_entries, executor.clone(), privilege, dry_run)
+            .with_unmount_policy(unmount_policy);
+    mounts
+        .mount()
+        .context(Stage::Pipeline.context("failed to mount filesystems in rootfs"))?;
+
+    // Create the per-run staging directory (if configured) after the mounts, so it
+    // lands on a `/tmp` mounted by `prepare.mount` rather than under it.
+    let mut staging = RootfsStaging::new(&rootfs, profile.defaults.staging, dry_run)
+        .with_run_id(pipeline.run_id());
+    staging
+        .setup()
+        .context(Stage::Pipeline.context("failed to create task staging directory in rootfs"))?;
+    let pipeline = pipeline.with_staging_dir(staging.dir());
+
+    // Tasks without isolation may write to the rootfs and the run directory only.
+    let sandbox = profile.defaults.sandbox.as_ref().map(|config| {
+        let run_dir = pipeline
+            .run_id()
+            .map(|run_id| run_dir::run_dir_path(&profile.dir, run_id));
+        Sandbox::new(config, std::iter::once(rootfs.clone()).chain(run_dir))
+    });
+    let pipeline = pipeline.with_sandbox(sandbox);
+
+    // Set up resolv.conf (if configured in prepare phase)
+    // setup failure is handled by Drop guards for mounts cleanup
+    let resolv_conf_config = profile.prepare.resolv_conf.as_ref().map(|rc| rc.config());
+    let mut resolv_conf = RootfsResolvConf::new(
+        &rootfs,
+        resolv_conf_config,
+        Utf8Path::new("/etc/resolv.conf"),
+        executor.clone(),
+        privilege,
+        dry_run,
+    );
+    resolv_conf
+        .setup()
+        .context(Stage::Pipeline.context("failed to set up resolv.conf in rootfs"))?;
+
+    // Copy the host's name-resolution files (if `defaults.isolation.network_files` is
+    // set); restored together with resolv.conf, before assemble.
+    let mut network_files = RootfsNetworkFiles::new(
+        &rootfs,
+        profile.defaults.isolation.network_files().to_vec(),
+        Utf8Path::new("/"),
+        executor.clone(),
+        privilege,
+        dry_run,
+    );
+    network_files
+        .setup()
+        .context(Stage::Pipeline.context("failed to copy host network files into rootfs"))?;
+
+    // Point apt inside the rootfs at the caching proxy (if configured); removed
+    // together with the temporary resolv.conf, before assemble.
+    let mut apt_proxy = RootfsAptProxy::new(
+        &rootfs,
+        proxy_url.map(str::to_string),
+        executor.clone(),
+        privilege,
+        dry_run,
+    );
+    apt_proxy
+        .setup()
+        .context(Stage::Pipeline.context("failed to configure apt proxy in rootfs"))?;
+
+    // Keep maintainer scripts from starting daemons (if
+    // `defaults.isolation.block_services` is set); unblocked before assemble.
+    let mut services = RootfsServiceBlock::new(
+        &rootfs,
+        profile.defaults.isolation.block_services(),
+        executor.clone(),
+        privilege,
+        dry_run,
+    );
+    services
+        .setup()
+        .context(Stage::Pipeline.context("failed to block service starts in rootfs"))?;
+
+    // Run prepare + provision, then restore the original resolv.conf BEFORE
+    // the assemble phase: an assemble resolv_conf task writes the permanent
+    // /etc/resolv.conf, which teardown's `rm -f` + backup restore would
+    // otherwise destroy. Assemble is gated on both prior stages succeeding:
+    // after a failed teardown the guard's Drop backstop retries the restore
+    // at scope end and would clobber assemble's output. The assemble task
+    // itself replaces /etc/resolv.conf atomically (staged sibling + rename),
+    // so a mid-assemble failure cannot leave the rootfs without a resolv.conf
+    // even though the guard is already disarmed. Unmount always runs
+    // last (mounts bracket all three phases).
+    // Error priority: prepare/provision > resolv_conf restore > network files restore >
+    // apt proxy removal > service unblock > assemble > unmount.
+    let run_result = pipeline.run_prepare_and_provision(&rootfs, &executor, dry_run);
+    let resolv_result = resolv_conf.teardown();
+    let network_files_result = network_files.teardown();
+    let apt_proxy_result = apt_proxy.teardown();
+    let services_result = services.teardown();
+    let assemble_result = if run_result.is_ok()
+        && resolv_result.is_ok()
+        && network_files_result.is_ok()
+        && apt_proxy_result.is_ok()
+        && services_result.is_ok()
+    {
+        pipeline.run_assemble(&rootfs, &executor, dry_run)
+    } else {
+        Ok(())
+    };
+    // Removed before unmounting, while a `/tmp` mount holding it is still there. A
+    // leftover staging directory does not affect the image's contents, so a failed
+    // removal is only reported.
+    if let Err(e) = staging.teardown() {
+        warn!("failed to remove task staging directory: {:#}", e);
+    }
+    let unmount_result = mounts.unmount();
+
+    if let Err(e) = run_result {
+        if let Err(r) = resolv_result {
+            tracing::error!("resolv.conf restore also failed: {:#}", r);
+        }
+        if let Err(n) = network_files_result {
+            tracing::error!("network files restore also failed: {:#}", n);
+        }
+        if let Err(a) = apt_proxy_result {
+            tracing::error!("apt proxy removal also failed: {:#}", a);
+        }
+        if let Err(b) = services_result {
+            tracing::error!("service unblock also failed: {:#}", b);
+        }
+        if let Err(u) = unmount_result {
+            tracing::error!(
+                "unmount also failed after pipeline error: {:#}. \
+                Drop guard will attempt cleanup.",
+                u
+            );
+        }
+        return Err(e);
+    }
+
+    if let Err(e) = resolv_result {
+        if let Err(u) = unmount_result {
+            tracing::error!(
+                "unmount also failed after resolv.conf restore error: {:#}. \
+                Drop guard will attempt cleanup.",
+                u
+            );
+        }
+        return Err(e).context(Stage::Teardown.context(
+            "failed to restore resolv.conf after provisioning; any assemble tasks were skipped",
+        ));
+    }
+
+    if let Err(e) = network_files_result {
+        if let Err(u) = unmount_result {
+            tracing::error!(
+                "unmount also failed after network files restore error: {:#}. \
+                Drop guard will attempt cleanup.",
+                u
+            );
+        }
+        return Err(e).context(Stage::Teardown.context(
+            "failed to restore network files after provisioning; any assemble tasks were skipped",
+        ));
+    }
+
+    if let Err(e) = apt_proxy_result {
+        if let Err(u) = unmount_result {
+            tracing::error!(
+                "unmount also failed after apt proxy removal error: {:#}. \
+                Drop guard will attempt cleanup.",
+                u
+            );
+        }
+        return Err(e).context(Stage::Teardown.context(
+            "failed to remove apt proxy configuration after provisioning; \
+            any assemble tasks were skipped",
+        ));
+    }
+
+    if let Err(e) = services_result {
+        if let Err(u) = unmount_result {
+            tracing::error!(
+                "unmount also failed after service unblock error: {:#}. \
+                Drop guard will attempt cleanup.",
+                u
+            );
+        }
+        return Err(e).context(Stage::Teardown.context(
+            "failed to unblock service starts after provisioning; any assemble tasks were skipped",
+        ));
+    }
+
+    if let Err(e) = assemble_result {
+        if let Err(u) = unmount_result {
+            tracing::error!(
+                "unmount also failed after assemble error: {:#}. \
+                Drop guard will attempt cleanup.",
+                u
+            );
+        }
+        return Err(e);
+    }
+
+    unmount_result.context(
+        Stage::Teardown
+            .context("failed to unmount filesystems after pipeline completed successfully"),
+    )?;
+    if let Some(overlay) = overlay.as_mut() {
+        overlay
+            .unmount()
+            .context(Stage::Teardown.context("failed to unmount the overlay"))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    //! Sequencing tests for `run_pipeline_phase()`: the temporary prepare
+    //! resolv.conf must be restored after provision and before assemble, so an
+    //! assemble resolv_conf task's permanent file/symlink survives; the
+    //! assemble phase must be gated on prepare/provision and the restore both
+    //! succeeding; and an assemble failure must propagate while leaving the
+    //! restored original in place.
+
+    use super::*;
+    use crate::executor::ExecutionResult;
+    use camino::Utf8PathBuf;
+    use std::io::Write as _;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+    use std::sync::Mutex;
+
+    /// How a configured `RecordingExecutor` failure matches a command's args.
+    enum ArgMatch {
+        /// Any invocation of the command fails.
+        Any,
+        /// Fails if any argument contains the fragment.
+        Contains(String),
+        /// Fails only if the *first* argument contains the fragment. Targets one
+        /// of several same-named commands whose args differ by position — e.g.
+        /// the teardown restore `mv <backup> <resolv>` (backup first) vs the
+        /// setup backup `mv <resolv> <backup>` (backup second).
+        FirstArgContains(String),
+    }
+
+    /// Records commands and really executes them so tests can assert both the
+    /// command order and the actual filesystem effects on a temp rootfs.
+    /// `fail_on_command` short-circuits a matching command with exit 1 without
+    /// executing it; `fail_on_command_with_arg` / `fail_on_command_with_first_arg`
+    /// additionally require an argument to contain the given fragment (anywhere,
+    /// or in first position), so one occurrence of a repeated command can be
+    /// targeted.
+    struct RecordingExecutor {
+        commands: Mutex<Vec<(String, Vec<String>)>>,
+        fail_on: Mutex<Option<(String, ArgMatch)>>,
+    }
+
+    impl RecordingExecutor {
+        fn new() -> Arc<Self> {
+            Arc::new(Self {
+                commands: Mutex::new(Vec::new()),
+                fail_on: Mutex::new(None),
+            })
+        }
+
+        fn fail_on_command(&self, command: &str) {
+            *self.fail_on.lock().unwrap() = Some((command.to_string(), ArgMatch::Any));
+        }
+
+        fn fail_on_command_with_arg(&self, command: &str, arg_fragment: &str) {
+            *self.fail_on.lock().unwrap() =
+                Some((command.to_string(), ArgMatch::Contains(arg_fragment.to_string())));
+        }
+
+        fn fail_on_command_with_first_arg(&self, command: &str, arg_fragment: &str) {
+            *self.fail_on.lock().unwrap() =
+                Some((command.to_string(), ArgMatch::FirstArgContains(arg_fragment.to_string())));
+        }
+
+        fn command_names(&self) -> Vec<String> {
+            self.commands
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(command, _)| command.clone())
+                .collect()
+        }
+    }
+
+    impl CommandExecutor for RecordingExecutor {
+        fn execute(&self, spec: &CommandSpec) -> Result<ExecutionResult> {
+            self.commands
+                .lock()
+                .unwrap()
+                .push((spec.command.clone(), spec.args.clone()));
+
+            let should_fail =
+                self.fail_on
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .is_some_and(|(command, arg_match)| {
+                        command == &spec.command
+                            && match arg_match {
+                                ArgMatch::Any => true,
+                                ArgMatch::Contains(fragment) => {
+                                    spec.args.iter().any(|a| a.contains(fragment))
+                                }
+                                ArgMatch::FirstArgContains(fragment) => {
+                                    spec.args.first().is_some_and(|a| a.contains(fragment))
+                                }
+                            }
+                    });
+            if should_fail {
+                return Ok(ExecutionResult {
+                    status: Some(ExitStatus::from_raw(1 << 8)),
+                    details: None,
+                });
+            }
+
+            let status = std::process::Command::new(&spec.command)
+                .args(&spec.args)
+                .status()?;
+            Ok(ExecutionResult {
+                status: Some(status),
+                details: None,
+            })
+        }
+    }
+
+    const LINK_ASSEMBLE: &str =
+        "assemble:\n  resolv_conf:\n    link: ../run/systemd/resolve/stub-resolv.conf\n";
+    const GENERATE_ASSEMBLE: &str = "assemble:\n  resolv_conf:\n    name_servers: [198.51.100.1]\n";
+
+    /// Minimal profile with a link-mode assemble task when `assemble` is set;
+    /// delegates to [`profile_yaml_with_assemble`].
+    fn profile_yaml(
+        dir: &Utf8Path,
+        prepare: bool,
+        provision: Option<&str>,
+        assemble: bool,
+    ) -> String {
+        profile_yaml_with_assemble(dir, prepare, provision, assemble.then_some(LINK_ASSEMBLE))
+    }
+
+    /// Minimal profile: directory bootstrap output, no mounts, no privilege
+    /// defaults (commands run unprivileged so the executor can really run
+    /// them). `provision` adds one shell task with the given inline content,
+    /// running directly on the host (`isolation: false`). `assemble`, if given,
+    /// is the raw YAML for the assemble section (e.g. [`LINK_ASSEMBLE`] or
+    /// [`GENERATE_ASSEMBLE`]).
+    fn profile_yaml_with_assemble(
+        dir: &Utf8Path,
+        prepare: bool,
+        provision: Option<&str>,
+        assemble: Option<&str>,
+    ) -> String {
+        let mut yaml = format!(
+            "dir: {dir}\nbootstrap:\n  type: mmdebstrap\n  suite: trixie\n  target: rootfs\n"
+        );
+        if prepare {
+            yaml.push_str("prepare:\n  resolv_conf:\n    name_servers: [192.0.2.1]\n");
+        }
+        if let Some(content) = provision {
+            // The content must stay quoted in the YAML: a bare `true` would
+            // parse as a boolean, not a script string.
+            yaml.push_str(&format!(
+                "provision:\n  - type: shell\n    content: \"{content}\"\n    isolation: false\n"
+            ));
+        }
+        if let Some(assemble_yaml) = assemble {
+            yaml.push_str(assemble_yaml);
+        }
+        yaml
+    }
+
+    fn load_profile_from(yaml: &str) -> config::Profile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(yaml.as_bytes()).unwrap();
+        file.flush().unwrap();
+        let profile = config::load_profile(Utf8Path::from_path(file.path()).unwrap()).unwrap();
+        // load_profile does not validate; mirror run_apply, which validates next.
+        profile.validate().unwrap();
+        profile
+    }
+
+    fn seed_rootfs(dir: &Utf8Path) -> Utf8PathBuf {
+        let rootfs = dir.join("rootfs");
+        fs::create_dir_all(rootfs.join("etc")).unwrap();
+        fs::write(rootfs.join("etc/resolv.conf"), "# original\n").unwrap();
+        // For shell provision tasks (DirectProvider): a real /tmp for the
+        // staged script, and a /bin/sh resolving to the host shell so the
+        // recording executor can really run it.
+        fs::create_dir_all(rootfs.join("tmp")).unwrap();
+        fs::create_dir_all(rootfs.join("bin")).unwrap();
+        std::os::unix::fs::symlink("/bin/sh", rootfs.join("bin/sh")).unwrap();
+        rootfs
+    }
+
+    const LINK_TARGET: &str = "../run/systemd/resolve/stub-resolv.conf";
+
+    #[test]
+    fn both_configured_assemble_output_survives() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(tmp.path()).unwrap();
+        let rootfs = seed_rootfs(dir);
+        let profile = load_profile_from(&profile_yaml(dir, true, None, true));
+        let executor = RecordingExecutor::new();
+
+        run_pipeline_phase(
+            &profile,
+            profile.pipeline(),
+            executor.clone(),
+            None,
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+
+        // setup (mv, cp, chmod) → teardown restore (rm, mv) → assemble
+        // stage-and-rename (ln, mv): the restore happens between provision and
+        // assemble, and assemble atomically renames its staged symlink over
+        // the just-restored original — the permanent config replaces it.
+        assert_eq!(executor.command_names(), ["mv", "cp", "chmod", "rm", "mv", "ln", "mv"]);
+        let resolv = rootfs.join("etc/resolv.conf");
+        assert!(
+            fs::symlink_metadata(&resolv)
+                .unwrap()
+                .file_type()
+                .is_symlink()
+        );
+        assert_eq!(fs::read_link(&resolv).unwrap(), std::path::Path::new(LINK_TARGET));
+        assert!(!rootfs.join("etc/resolv.conf.rsdebstrap-orig").exists());
+        // The staging entry was consumed by the promoting rename.
+        assert!(fs::symlink_metadata(rootfs.join("etc/resolv.conf.rsdebstrap-tmp")).is_err());
+    }
+
+    #[test]
+    fn prepare_only_restores_original() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(tmp.path()).unwrap();
+        let rootfs = seed_rootfs(dir);
+        let profile = load_profile_from(&profile_yaml(dir, true, None, false));
+        let executor = RecordingExecutor::new();
+
+        run_pipeline_phase(
+            &profile,
+            profile.pipeline(),
+            executor.clone(),
+            None,
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(executor.command_names(), ["mv", "cp", "chmod", "rm", "mv"]);
+        let resolv = rootfs.join("etc/resolv.conf");
+        assert!(fs::symlink_metadata(&resolv).unwrap().file_type().is_file());
+        assert_eq!(fs::read_to_string(&resolv).unwrap(), "# original\n");
+        assert!(!rootfs.join("etc/resolv.conf.rsdebstrap-orig").exists());
+    }
+
+    #[test]
+    fn assemble_only_writes_symlink() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(tmp.path()).unwrap();
+        let rootfs = seed_rootfs(dir);
+        let profile = load_profile_from(&profile_yaml(dir, false, None, true));
+        let executor = RecordingExecutor::new();
+
+        run_pipeline_phase(
+            &profile,
+            profile.pipeline(),
+            executor.clone(),
+            None,
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+
+        // No backup mv: the prepare guard never activates. The only commands
+        // are assemble's stage (ln) and atomic promote (mv).
+        assert_eq!(executor.command_names(), ["ln", "mv"]);
+        let resolv = rootfs.join("etc/resolv.conf");
+        assert!(
+            fs::symlink_metadata(&resolv)
+                .unwrap()
+                .file_type()
+                .is_symlink()
+        );
+        assert_eq!(fs::read_link(&resolv).unwrap(), std::path::Path::new(LINK_TARGET));
+        assert!(!rootfs.join("etc/resolv.conf.rsdebstrap-orig").exists());
+    }
+
+    #[test]
+    fn empty_pipeline_is_noop() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(tmp.path()).unwrap();
+        let rootfs = seed_rootfs(dir);
+        let profile = load_profile_from(&profile_yaml(dir, false, None, false));
+        let executor = RecordingExecutor::new();
+
+        run_pipeline_phase(
+            &profile,
+            profile.pipeline(),
+            executor.clone(),
+            None,
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+
+        assert!(executor.command_names().is_empty());
+        let resolv = rootfs.join("etc/resolv.conf");
+        assert_eq!(fs::read_to_string(&resolv).unwrap(), "# original\n");
+    }
+
+    #[test]
+    fn teardown_failure_gates_assemble() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(tmp.path()).unwrap();
+        let rootfs = seed_rootfs(dir);
+        let profile = load_profile_from(&profile_yaml(dir, true, None, true));
+        let executor = RecordingExecutor::new();
+        executor.fail_on_command("rm");
+
+        let err = run_pipeline_phase(
+            &profile,
+            profile.pipeline(),
+            executor.clone(),
+            None,
+            false,
+            false,
+            false,
+        )
+        .unwrap_err();
+
+        assert!(
+            format!("{:#}", err).contains("failed to restore resolv.conf after provisioning"),
+            "unexpected error: {err:#}"
+        );
+        // setup (mv, cp, chmod) → teardown rm fails → assemble is gated off
+        // (no ln) → the guard's Drop backstop retries the teardown once more
+        // (the second failing rm).
+        assert_eq!(executor.command_names(), ["mv", "cp", "chmod", "rm", "rm"]);
+        // The restore genuinely never happened: the temporary file and the
+        // backup are still in place, and assemble never touched anything.
+        let resolv = rootfs.join("etc/resolv.conf");
+        assert_eq!(
+            fs::read_to_string(&resolv).unwrap(),
+            "# Generated by rsdebstrap\nnameserver 192.0.2.1\n"
+        );
+        assert!(rootfs.join("etc/resolv.conf.rsdebstrap-orig").exists());
+    }
+
+    #[test]
+    fn setup_cp_failure_rolls_back_without_running_pipeline() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(tmp.path()).unwrap();
+        let rootfs = seed_rootfs(dir);
+        let profile = load_profile_from(&profile_yaml(dir, true, None, true));
+        let executor = RecordingExecutor::new();
+        executor.fail_on_command("cp");
+
+        let err = run_pipeline_phase(
+            &profile,
+            profile.pipeline(),
+            executor.clone(),
+            None,
+            false,
+            false,
+            false,
+        )
+        .unwrap_err();
+
+        assert!(
+            format!("{:#}", err).contains("failed to set up resolv.conf in rootfs"),
+            "unexpected error: {err:#}"
+        );
+        // Backup mv, failed cp, rollback mv — the guard never activates, so
+        // there is no Drop retry and neither pipeline stage runs.
+        assert_eq!(executor.command_names(), ["mv", "cp", "mv"]);
+        let resolv = rootfs.join("etc/resolv.conf");
+        assert_eq!(fs::read_to_string(&resolv).unwrap(), "# original\n");
+        assert!(!rootfs.join("etc/resolv.conf.rsdebstrap-orig").exists());
+    }
+
+    #[test]
+    fn restore_runs_after_provision_and_before_assemble() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(tmp.path()).unwrap();
+        let rootfs = seed_rootfs(dir);
+        let profile = load_profile_from(&profile_yaml(dir, true, Some("true"), true));
+        let executor = RecordingExecutor::new();
+
+        run_pipeline_phase(
+            &profile,
+            profile.pipeline(),
+            executor.clone(),
+            None,
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+
+        // setup (mv, cp, chmod) → provision shell → restore (rm, mv) →
+        // assemble stage-and-rename (ln, mv): the provision task runs while
+        // the temporary resolv.conf is in place; the restore strictly follows.
+        let sh = rootfs.join("bin/sh");
+        assert_eq!(
+            executor.command_names(),
+            ["mv", "cp", "chmod", sh.as_str(), "rm", "mv", "ln", "mv"]
+        );
+        let resolv = rootfs.join("etc/resolv.conf");
+        assert!(
+            fs::symlink_metadata(&resolv)
+                .unwrap()
+                .file_type()
+                .is_symlink()
+        );
+        assert_eq!(fs::read_link(&resolv).unwrap(), std::path::Path::new(LINK_TARGET));
+        assert!(!rootfs.join("etc/resolv.conf.rsdebstrap-orig").exists());
+    }
+
+    #[test]
+    fn provision_failure_skips_assemble_and_restores_original() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(tmp.path()).unwrap();
+        let rootfs = seed_rootfs(dir);
+        let profile = load_profile_from(&profile_yaml(dir, true, Some("exit 1"), true));
+        let executor = RecordingExecutor::new();
+
+        let err = run_pipeline_phase(
+            &profile,
+            profile.pipeline(),
+            executor.clone(),
+            None,
+            false,
+            false,
+            false,
+        )
+        .unwrap_err();
+
+        assert!(
+            format!("{:#}", err).contains("failed to run provision"),
+            "unexpected error: {err:#}"
+        );
+        // The failed provision gates assemble off (no ln/mv after the
+        // restore), but the teardown still restores the original.
+        let sh = rootfs.join("bin/sh");
+        assert_eq!(executor.command_names(), ["mv", "cp", "chmod", sh.as_str(), "rm", "mv"]);
+        let resolv = rootfs.join("etc/resolv.conf");
+        assert!(fs::symlink_metadata(&resolv).unwrap().file_type().is_file());
+        assert_eq!(fs::read_to_string(&resolv).unwrap(), "# original\n");
+        assert!(!rootfs.join("etc/resolv.conf.rsdebstrap-orig").exists());
+    }
+
+    #[test]
+    fn assemble_failure_propagates_and_preserves_restored_original() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(tmp.path()).unwrap();
+        let rootfs = seed_rootfs(dir);
+        let profile = load_profile_from(&profile_yaml(dir, true, None, true));
+        let executor = RecordingExecutor::new();
+        // Fail only assemble's promote mv: the setup/teardown mvs never have
+        // the staging path among their arguments and run for real.
+        executor.fail_on_command_with_arg("mv", "rsdebstrap-tmp");
+
+        let err = run_pipeline_phase(
+            &profile,
+            profile.pipeline(),
+            executor.clone(),
+            None,
+            false,
+            false,
+            false,
+        )
+        .unwrap_err();
+
+        assert!(
+            format!("{:#}", err).contains("failed to run assemble"),
+            "unexpected error: {err:#}"
+        );
+        // setup (mv, cp, chmod) → restore (rm, mv) → assemble stages its
+        // symlink (ln) and the promote mv fails.
+        assert_eq!(executor.command_names(), ["mv", "cp", "chmod", "rm", "mv", "ln", "mv"]);
+        // Atomicity invariant at pipeline level: the restored original
+        // survives the failed assemble; only the staging symlink remains.
+        let resolv = rootfs.join("etc/resolv.conf");
+        assert!(fs::symlink_metadata(&resolv).unwrap().file_type().is_file());
+        assert_eq!(fs::read_to_string(&resolv).unwrap(), "# original\n");
+        assert!(!rootfs.join("etc/resolv.conf.rsdebstrap-orig").exists());
+        let staging = rootfs.join("etc/resolv.conf.rsdebstrap-tmp");
+        assert!(
+            fs::symlink_metadata(&staging)
+                .unwrap()
+                .file_type()
+                .is_symlink()
+        );
+    }
+
+    #[test]
+    fn restore_mv_failure_gates_assemble_and_strands_backup() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(tmp.path()).unwrap();
+        let rootfs = seed_rootfs(dir);
+        let profile = load_profile_from(&profile_yaml(dir, true, None, true));
+        let executor = RecordingExecutor::new();
+        // Fail only the teardown restore `mv <backup> <resolv>` (backup is its
+        // first arg); the setup backup `mv <resolv> <backup>` has the backup
+        // second and runs for real.
+        executor.fail_on_command_with_first_arg("mv", "rsdebstrap-orig");
+
+        let err = run_pipeline_phase(
+            &profile,
+            profile.pipeline(),
+            executor.clone(),
+            None,
+            false,
+            false,
+            false,
+        )
+        .unwrap_err();
+
+        assert!(
+            format!("{:#}", err).contains("failed to restore resolv.conf after provisioning"),
+            "unexpected error: {err:#}"
+        );
+        // setup (mv, cp, chmod) → teardown rm ok, restore mv fails → assemble
+        // gated off (no ln) → the guard's Drop backstop retries the teardown
+        // (rm, mv), which fails again.
+        assert_eq!(executor.command_names(), ["mv", "cp", "chmod", "rm", "mv", "rm", "mv"]);
+        // The failure the gate exists to catch: the temporary resolv.conf was
+        // already removed and the restore never landed, so the final path is
+        // empty and the original is stranded in the backup.
+        assert!(fs::symlink_metadata(rootfs.join("etc/resolv.conf")).is_err());
+        let backup = rootfs.join("etc/resolv.conf.rsdebstrap-orig");
+        assert!(backup.exists());
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "# original\n");
+    }
+
+    #[test]
+    fn both_configured_generate_assemble_output_survives() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(tmp.path()).unwrap();
+        let rootfs = seed_rootfs(dir);
+        let profile = load_profile_from(&profile_yaml_with_assemble(
+            dir,
+            true,
+            None,
+            Some(GENERATE_ASSEMBLE),
+        ));
+        let executor = RecordingExecutor::new();
+
+        run_pipeline_phase(
+            &profile,
+            profile.pipeline(),
+            executor.clone(),
+            None,
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+
+        // setup (mv, cp, chmod) → teardown restore (rm, mv) → assemble generate
+        // (rm, cp, chmod, mv): the generated file replaces the just-restored
+        // original, and the generate path clears any stale staging entry first.
+        assert_eq!(
+            executor.command_names(),
+            ["mv", "cp", "chmod", "rm", "mv", "rm", "cp", "chmod", "mv"]
+        );
+        let resolv = rootfs.join("etc/resolv.conf");
+        assert!(fs::symlink_metadata(&resolv).unwrap().file_type().is_file());
+        assert!(
+            fs::read_to_string(&resolv)
+                .unwrap()
+                .contains("nameserver 198.51.100.1")
+        );
+        assert!(!rootfs.join("etc/resolv.conf.rsdebstrap-orig").exists());
+        assert!(fs::symlink_metadata(rootfs.join("etc/resolv.conf.rsdebstrap-tmp")).is_err());
+    }
+
+    #[test]
+    fn generate_assemble_only_writes_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(tmp.path()).unwrap();
+        let rootfs = seed_rootfs(dir);
+        let profile = load_profile_from(&profile_yaml_with_assemble(
+            dir,
+            false,
+            None,
+            Some(GENERATE_ASSEMBLE),
+        ));
+        let executor = RecordingExecutor::new();
+
+        run_pipeline_phase(
+            &profile,
+            profile.pipeline(),
+            executor.clone(),
+            None,
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+
+        // No prepare guard: only assemble's generate sequence — clear the
+        // staging entry, copy, chmod, promote.
+        assert_eq!(executor.command_names(), ["rm", "cp", "chmod", "mv"]);
+        let resolv = rootfs.join("etc/resolv.conf");
+        assert!(fs::symlink_metadata(&resolv).unwrap().file_type().is_file());
+        assert!(
+            fs::read_to_string(&resolv)
+                .unwrap()
+                .contains("nameserver 198.51.100.1")
+        );
+        assert!(fs::symlink_metadata(rootfs.join("etc/resolv.conf.rsdebstrap-tmp")).is_err());
+    }
+
+    /// Debian's default `/etc/resolv.conf` is a *symlink*, not a regular file,
+    /// yet every other pipeline-level test seeds a regular file. The prepare
+    /// guard must back the symlink up and restore it faithfully as a symlink
+    /// (the backup `mv` moves the link itself; the restore `mv` moves it back),
+    /// not flatten it into a regular file. Seed a *live* symlink whose relative
+    /// target sits in the same `/etc` directory so it still resolves after the
+    /// backup `mv`.
+    #[test]
+    fn prepare_only_restores_symlink_original() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(tmp.path()).unwrap();
+        let rootfs = seed_rootfs(dir);
+        let resolv = rootfs.join("etc/resolv.conf");
+        // Replace the seeded regular file with a symlink to a sibling entry.
+        fs::write(rootfs.join("etc/upstream-resolv.conf"), "# upstream\n").unwrap();
+        fs::remove_file(&resolv).unwrap();
+        std::os::unix::fs::symlink("upstream-resolv.conf", &resolv).unwrap();
+
+        let profile = load_profile_from(&profile_yaml(dir, true, None, false));
+        let executor = RecordingExecutor::new();
+
+        run_pipeline_phase(
+            &profile,
+            profile.pipeline(),
+            executor.clone(),
+            None,
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+
+        // Same command shape as prepare_only_restores_original — setup
+        // (mv backup, cp temp, chmod) → teardown (rm temp, mv restore) — but
+        // here the backed-up and restored entry is a symlink.
+        assert_eq!(executor.command_names(), ["mv", "cp", "chmod", "rm", "mv"]);
+        // The original symlink is restored byte-for-byte (same link target),
+        // not replaced by a regular file.
+        assert!(
+            fs::symlink_metadata(&resolv)
+                .unwrap()
+                .file_type()
+                .is_symlink()
+        );
+        assert_eq!(fs::read_link(&resolv).unwrap(), std::path::Path::new("upstream-resolv.conf"));
+        assert!(!rootfs.join("etc/resolv.conf.rsdebstrap-orig").exists());
+    }
+
+    /// A fresh systemd rootfs commonly ships `/etc/resolv.conf` as a *dangling*
+    /// symlink into `/run` (systemd-resolved not running yet) — exactly the
+    /// prepare+assemble scenario this PR targets. The prepare guard must detect
+    /// it with `symlink_metadata()` (which sees the link itself), not
+    /// `metadata()` (which follows the link and errors on the missing target):
+    /// detecting it as absent would skip the backup `mv` and then `cp` the
+    /// temporary file *through* the dangling link, failing setup. With the
+    /// guard correct, provisioning runs against a real temporary resolv.conf
+    /// and the assemble task's permanent symlink still lands.
+    #[test]
+    fn both_configured_dangling_symlink_original_survives() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(tmp.path()).unwrap();
+        let rootfs = seed_rootfs(dir);
+        let resolv = rootfs.join("etc/resolv.conf");
+        // Dangling: the /run target does not exist in the seeded rootfs.
+        fs::remove_file(&resolv).unwrap();
+        std::os::unix::fs::symlink(LINK_TARGET, &resolv).unwrap();
+
+        let profile = load_profile_from(&profile_yaml(dir, true, None, true));
+        let executor = RecordingExecutor::new();
+
+        run_pipeline_phase(
+            &profile,
+            profile.pipeline(),
+            executor.clone(),
+            None,
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+
+        // setup (mv backup, cp temp, chmod) → teardown (rm temp; the restore mv
+        // is *skipped* because try_exists() follows the dangling backup link and
+        // reports it absent, leaving the backup stranded — pre-existing
+        // behavior) → assemble stage-and-rename (ln, mv). The permanent assemble
+        // symlink is the final state.
+        assert_eq!(executor.command_names(), ["mv", "cp", "chmod", "rm", "ln", "mv"]);
+        assert!(
+            fs::symlink_metadata(&resolv)
+                .unwrap()
+                .file_type()
+                .is_symlink()
+        );
+        assert_eq!(fs::read_link(&resolv).unwrap(), std::path::Path::new(LINK_TARGET));
+    }
+
+    #[test]
+    fn apt_proxy_is_configured_for_provision_and_removed_before_assemble() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(tmp.path()).unwrap();
+        let rootfs = seed_rootfs(dir);
+        fs::create_dir_all(rootfs.join("etc/apt/apt.conf.d")).unwrap();
+        let snippet = rootfs.join("etc/apt/apt.conf.d/00rsdebstrap-proxy");
+        // The provision task copies the snippet aside, proving it was in place.
+        let seen = dir.join("seen-proxy.conf");
+        let provision = format!("cp {snippet} {seen}");
+        let profile = load_profile_from(&profile_yaml(dir, false, Some(&provision), true));
+        let executor = RecordingExecutor::new();
+
+        run_pipeline_phase(
+            &profile,
+            profile.pipeline(),
+            executor.clone(),
+            Some("http://127.0.0.1:3142"),
+            false,
+            false,
+            false,
+        )
+        .unwrap();
+
+        // setup (cp, chmod) → provision shell → removal (rm) → assemble (ln, mv).
+        let sh = rootfs.join("bin/sh");
+        assert_eq!(executor.command_names(), ["cp", "chmod", sh.as_str(), "rm", "ln", "mv"]);
+        assert!(
+            fs::read_to_string(&seen)
+                .unwrap()
+                .contains("Acquire::http::Proxy \"http://127.0.0.1:3142\";")
+        );
+        assert!(!snippet.exists());
+    }
+
+    #[test]
+    fn apt_proxy_removal_failure_gates_assemble() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(tmp.path()).unwrap();
+        let rootfs = seed_rootfs(dir);
+        fs::create_dir_all(rootfs.join("etc/apt/apt.conf.d")).unwrap();
+        let profile = load_profile_from(&profile_yaml(dir, false, None, true));
+        let executor = RecordingExecutor::new();
+        executor.fail_on_command_with_arg("rm", "00rsdebstrap-proxy");
+
+        let err = run_pipeline_phase(
+            &profile,
+            profile.pipeline(),
+            executor.clone(),
+            Some("http://127.0.0.1:3142"),
+            false,
+            false,
+            false,
+        )
+        .unwrap_err();
+
+        assert!(
+            format!("{:#}", err).contains("failed to remove apt proxy configuration"),
+            "unexpected error: {err:#}"
+        );
+        // No assemble commands ran; the guard's Drop backstop retried the removal.
+        assert_eq!(executor.command_names(), ["cp", "chmod", "rm", "rm"]);
+    }
+}
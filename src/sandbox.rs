@@ -0,0 +1,490 @@
+//! Landlock and seccomp restrictions for tasks run without isolation.
+//!
+//! A task with `isolation: false` runs its commands directly on the host. With
+//! `defaults.sandbox` set, [`DirectContext`](crate::isolation::DirectContext) runs them
+//! through the hidden `rsdebstrap sandbox-exec` subcommand instead: [`Sandbox::wrap`]
+//! prefixes each command with it, and [`exec`] restricts that process before executing
+//! the command, which inherits the restrictions along with every process it starts.
+//! The helper runs after `sudo` (or whichever privilege method the task uses), since a
+//! restricted process can no longer gain privileges.
+//!
+//! - Landlock allows writes only beneath the rootfs, the run directory, `/dev/null` and
+//!   `defaults.sandbox.writable`. Reads are not restricted.
+//! - The seccomp filter fails the syscalls that change the host as a whole (mounts,
+//!   module loading, `kexec`, reboot, swap, the clock, `ptrace`, `bpf`, keyrings) with
+//!   `EPERM`, as well as every syscall of a foreign ABI.
+//!
+//! A kernel without Landlock (before 5.13, or with it left out of the LSM list) fails
+//! the command rather than running it unrestricted.
+
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::cli::SandboxExecArgs;
+use crate::config::SandboxConfig;
+use crate::error::RsdebstrapError;
+use crate::executor::current_exe;
+
+/// Name of the hidden subcommand that runs a command in a sandbox.
+pub const SANDBOX_EXEC: &str = "sandbox-exec";
+
+/// Written by most commands, so always writable.
+const DEV_NULL: &str = "/dev/null";
+
+/// Restrictions applied to the commands of a task run with `isolation: false`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sandbox {
+    landlock: bool,
+    seccomp: bool,
+    writable: Vec<Utf8PathBuf>,
+}
+
+impl Sandbox {
+    /// Returns the sandbox `config` describes, in which `writable` (the rootfs and the
+    /// run directory) can be written as well.
+    pub fn new(config: &SandboxConfig, writable: impl IntoIterator<Item = Utf8PathBuf>) -> Self {
+        let writable = if config.landlock() {
+            writable
+                .into_iter()
+                .chain([Utf8PathBuf::from(DEV_NULL)])
+                .chain(config.writable.iter().cloned())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Self {
+            landlock: config.landlock(),
+            seccomp: config.seccomp(),
+            writable,
+        }
+    }
+
+    /// Returns the paths beneath which the sandbox allows writes.
+    pub fn writable(&self) -> &[Utf8PathBuf] {
+        &self.writable
+    }
+
+    /// Returns `command` prefixed with the `sandbox-exec` invocation of the running
+    /// executable that applies this sandbox.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the running executable cannot be located.
+    pub fn wrap(&self, command: &[String]) -> Result<Vec<String>, RsdebstrapError> {
+        let mut args = vec![current_exe()?.into_string(), SANDBOX_EXEC.to_string()];
+        for path in &self.writable {
+            args.push("--write".to_string());
+            args.push(path.to_string());
+        }
+        if !self.landlock {
+            args.push("--no-landlock".to_string());
+        }
+        if !self.seccomp {
+            args.push("--no-seccomp".to_string());
+        }
+        args.push("--".to_string());
+        args.extend(command.iter().cloned());
+        Ok(args)
+    }
+
+    /// Restricts the calling process and every process it starts from now on.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a restriction cannot be applied.
+    pub fn enter(&self) -> Result<(), RsdebstrapError> {
+        set_no_new_privs()?;
+        if self.landlock {
+            landlock::restrict(&self.writable)?;
+        }
+        if self.seccomp {
+            seccomp::install()?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs the `sandbox-exec` subcommand: enters the sandbox `args` describe and executes
+/// its command in place of rsdebstrap. Returns only on failure.
+///
+/// # Errors
+///
+/// Returns an error if the sandbox cannot be entered or the command cannot be executed.
+pub fn exec(args: &SandboxExecArgs) -> Result<()> {
+    let sandbox = Sandbox {
+        landlock: !args.no_landlock,
+        seccomp: !args.no_seccomp,
+        writable: args.write.clone(),
+    };
+    let Some((program, rest)) = args.command.split_first() else {
+        return Err(
+            RsdebstrapError::Validation(format!("{}: no command given", SANDBOX_EXEC)).into()
+        );
+    };
+    sandbox.enter()?;
+    let error = std::process::Command::new(program).args(rest).exec();
+    Err(RsdebstrapError::io(format!("failed to execute {}", program), error).into())
+}
+
+/// Keeps the process and its children from gaining privileges, which both Landlock
+/// and seccomp require of an unprivileged caller.
+fn set_no_new_privs() -> Result<(), RsdebstrapError> {
+    // SAFETY: PR_SET_NO_NEW_PRIVS takes only integer arguments.
+    let ret = unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) };
+    if ret != 0 {
+        return Err(RsdebstrapError::io(
+            "failed to set no_new_privs for the sandbox",
+            io::Error::last_os_error(),
+        ));
+    }
+    Ok(())
+}
+
+/// The Landlock write restriction.
+mod landlock {
+    use super::*;
+
+    const CREATE_RULESET_VERSION: libc::c_uint = 1;
+    const RULE_PATH_BENEATH: libc::c_uint = 1;
+
+    const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+    const ACCESS_FS_REMOVE_DIR: u64 = 1 << 4;
+    const ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+    const ACCESS_FS_MAKE_CHAR: u64 = 1 << 6;
+    const ACCESS_FS_MAKE_DIR: u64 = 1 << 7;
+    const ACCESS_FS_MAKE_REG: u64 = 1 << 8;
+    const ACCESS_FS_MAKE_SOCK: u64 = 1 << 9;
+    const ACCESS_FS_MAKE_FIFO: u64 = 1 << 10;
+    const ACCESS_FS_MAKE_BLOCK: u64 = 1 << 11;
+    const ACCESS_FS_MAKE_SYM: u64 = 1 << 12;
+    /// Landlock ABI 2.
+    const ACCESS_FS_REFER: u64 = 1 << 13;
+    /// Landlock ABI 3.
+    const ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+
+    /// The accesses that can be granted on a file rather than a directory.
+    const FILE_ACCESS: u64 = ACCESS_FS_WRITE_FILE | ACCESS_FS_TRUNCATE;
+
+    /// `struct landlock_ruleset_attr`, up to the fields of ABI 1; the kernel accepts
+    /// the shorter struct.
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    /// `struct landlock_path_beneath_attr`, packed in the kernel's UAPI.
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    /// Returns the write accesses the running kernel's Landlock `abi` can restrict.
+    fn write_access(abi: libc::c_long) -> u64 {
+        let mut access = ACCESS_FS_WRITE_FILE
+            | ACCESS_FS_REMOVE_DIR
+            | ACCESS_FS_REMOVE_FILE
+            | ACCESS_FS_MAKE_CHAR
+            | ACCESS_FS_MAKE_DIR
+            | ACCESS_FS_MAKE_REG
+            | ACCESS_FS_MAKE_SOCK
+            | ACCESS_FS_MAKE_FIFO
+            | ACCESS_FS_MAKE_BLOCK
+            | ACCESS_FS_MAKE_SYM;
+        if abi >= 2 {
+            access |= ACCESS_FS_REFER;
+        }
+        if abi >= 3 {
+            access |= ACCESS_FS_TRUNCATE;
+        }
+        access
+    }
+
+    /// Allows the process writes only beneath `writable`.
+    pub(super) fn restrict(writable: &[Utf8PathBuf]) -> Result<(), RsdebstrapError> {
+        // SAFETY: a null attribute of size 0 with the version flag only queries the ABI.
+        let abi = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                std::ptr::null::<RulesetAttr>(),
+                0usize,
+                CREATE_RULESET_VERSION,
+            )
+        };
+        if abi < 1 {
+            return Err(RsdebstrapError::io(
+                "Landlock is not available in this kernel; remove defaults.sandbox or set \
+                landlock: false",
+                io::Error::last_os_error(),
+            ));
+        }
+        let handled = write_access(abi);
+        let attr = RulesetAttr {
+            handled_access_fs: handled,
+        };
+        // SAFETY: `attr` is a valid ruleset attribute of the size passed.
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                &raw const attr,
+                size_of::<RulesetAttr>(),
+                0 as libc::c_uint,
+            )
+        };
+        if fd < 0 {
+            return Err(RsdebstrapError::io(
+                "failed to create the Landlock ruleset",
+                io::Error::last_os_error(),
+            ));
+        }
+        // SAFETY: the kernel returned a new file descriptor that nothing else owns.
+        let ruleset = unsafe { OwnedFd::from_raw_fd(fd as libc::c_int) };
+        for path in writable {
+            add_rule(&ruleset, path, handled)?;
+        }
+        // SAFETY: `ruleset` is an open Landlock ruleset.
+        let ret = unsafe {
+            libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0 as libc::c_uint)
+        };
+        if ret != 0 {
+            return Err(RsdebstrapError::io(
+                "failed to enforce the Landlock ruleset",
+                io::Error::last_os_error(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Allows the `handled` accesses beneath `path`, or those applying to a file if it
+    /// is not a directory.
+    fn add_rule(ruleset: &OwnedFd, path: &Utf8Path, handled: u64) -> Result<(), RsdebstrapError> {
+        let context = || format!("failed to allow writes to {} in the sandbox", path);
+        let metadata = std::fs::metadata(path).map_err(|e| RsdebstrapError::io(context(), e))?;
+        let file = rustix::fs::open(
+            path.as_std_path(),
+            rustix::fs::OFlags::PATH | rustix::fs::OFlags::CLOEXEC,
+            rustix::fs::Mode::empty(),
+        )
+        .map_err(|e| RsdebstrapError::io(context(), e.into()))?;
+        let rule = PathBeneathAttr {
+            allowed_access: if metadata.is_dir() {
+                handled
+            } else {
+                handled & FILE_ACCESS
+            },
+            parent_fd: file.as_raw_fd(),
+        };
+        // SAFETY: `rule` is a valid path-beneath attribute whose descriptor stays open
+        // for the call.
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_add_rule,
+                ruleset.as_raw_fd(),
+                RULE_PATH_BENEATH,
+                &raw const rule,
+                0 as libc::c_uint,
+            )
+        };
+        if ret != 0 {
+            return Err(RsdebstrapError::io(context(), io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+}
+
+/// The seccomp syscall filter.
+mod seccomp {
+    use super::*;
+
+    /// `AUDIT_ARCH_*` of the native syscall ABI.
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xC000_003E);
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: Option<u32> = Some(0xC000_00B7);
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    const AUDIT_ARCH: Option<u32> = None;
+
+    /// Offsets of `nr` and `arch` in `struct seccomp_data`.
+    const DATA_NR: u32 = 0;
+    const DATA_ARCH: u32 = 4;
+
+    /// Syscalls failed with `EPERM`.
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    const BLOCKED: &[libc::c_long] = &[
+        libc::SYS_mount,
+        libc::SYS_umount2,
+        libc::SYS_pivot_root,
+        libc::SYS_move_mount,
+        libc::SYS_open_tree,
+        libc::SYS_fsopen,
+        libc::SYS_fsconfig,
+        libc::SYS_fsmount,
+        libc::SYS_fspick,
+        libc::SYS_swapon,
+        libc::SYS_swapoff,
+        libc::SYS_reboot,
+        libc::SYS_kexec_load,
+        libc::SYS_kexec_file_load,
+        libc::SYS_init_module,
+        libc::SYS_finit_module,
+        libc::SYS_delete_module,
+        libc::SYS_settimeofday,
+        libc::SYS_clock_settime,
+        libc::SYS_clock_adjtime,
+        libc::SYS_adjtimex,
+        libc::SYS_acct,
+        libc::SYS_quotactl,
+        libc::SYS_ptrace,
+        libc::SYS_process_vm_writev,
+        libc::SYS_open_by_handle_at,
+        libc::SYS_bpf,
+        libc::SYS_perf_event_open,
+        libc::SYS_add_key,
+        libc::SYS_request_key,
+        libc::SYS_keyctl,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_iopl,
+        #[cfg(target_arch = "x86_64")]
+        libc::SYS_ioperm,
+    ];
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    const BLOCKED: &[libc::c_long] = &[];
+
+    /// Syscall numbers at or above this are the x32 ABI, which shares `AUDIT_ARCH`
+    /// with x86_64.
+    #[cfg(target_arch = "x86_64")]
+    const X32_SYSCALL_BIT: Option<u32> = Some(0x4000_0000);
+    #[cfg(not(target_arch = "x86_64"))]
+    const X32_SYSCALL_BIT: Option<u32> = None;
+
+    fn stmt(code: u32, k: u32) -> libc::sock_filter {
+        libc::sock_filter {
+            code: code as u16,
+            jt: 0,
+            jf: 0,
+            k,
+        }
+    }
+
+    fn jump_if(code: u32, k: u32, jt: usize) -> libc::sock_filter {
+        libc::sock_filter {
+            code: (libc::BPF_JMP | code | libc::BPF_K) as u16,
+            jt: u8::try_from(jt).expect("the seccomp filter fits in a jump offset"),
+            jf: 0,
+            k,
+        }
+    }
+
+    /// Returns the filter for the native ABI `arch`.
+    fn program(arch: u32) -> Vec<libc::sock_filter> {
+        let deny = libc::SECCOMP_RET_ERRNO | libc::EPERM as u32;
+        let load = libc::BPF_LD | libc::BPF_W | libc::BPF_ABS;
+        let checks: Vec<(u32, u32)> = X32_SYSCALL_BIT
+            .map(|bit| (libc::BPF_JGE, bit))
+            .into_iter()
+            .chain(BLOCKED.iter().map(|&nr| (libc::BPF_JEQ, nr as u32)))
+            .collect();
+        let mut program = vec![
+            stmt(load, DATA_ARCH),
+            jump_if(libc::BPF_JEQ, arch, 1),
+            stmt(libc::BPF_RET | libc::BPF_K, deny),
+            stmt(load, DATA_NR),
+        ];
+        // Each match jumps over the remaining checks and the allow to the final deny.
+        let count = checks.len();
+        for (index, (op, k)) in checks.into_iter().enumerate() {
+            program.push(jump_if(op, k, count - index));
+        }
+        program.push(stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW));
+        program.push(stmt(libc::BPF_RET | libc::BPF_K, deny));
+        program
+    }
+
+    /// Installs the filter on the process.
+    pub(super) fn install() -> Result<(), RsdebstrapError> {
+        let Some(arch) = AUDIT_ARCH else {
+            return Err(RsdebstrapError::Isolation(format!(
+                "seccomp filtering is not supported on {}; set defaults.sandbox.seccomp \
+                to false",
+                std::env::consts::ARCH
+            )));
+        };
+        let mut filter = program(arch);
+        let prog = libc::sock_fprog {
+            len: u16::try_from(filter.len()).expect("the seccomp filter fits in a program"),
+            filter: filter.as_mut_ptr(),
+        };
+        // SAFETY: `prog` points at `filter`, which outlives the call; the kernel copies
+        // the program.
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_seccomp,
+                libc::SECCOMP_SET_MODE_FILTER,
+                0 as libc::c_uint,
+                &raw const prog,
+            )
+        };
+        if ret != 0 {
+            return Err(RsdebstrapError::io(
+                "failed to install the seccomp filter",
+                io::Error::last_os_error(),
+            ));
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn matches_jump_to_the_final_deny() {
+            let program = program(0xC000_003E);
+            let deny = program.len() - 1;
+            for (index, instruction) in program.iter().enumerate().skip(4) {
+                if instruction.code == (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16
+                    || instruction.code == (libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K) as u16
+                {
+                    assert_eq!(index + 1 + usize::from(instruction.jt), deny);
+                }
+            }
+            assert_eq!(program[deny - 1].k, libc::SECCOMP_RET_ALLOW);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_passes_the_sandbox_to_sandbox_exec() {
+        let config = SandboxConfig {
+            seccomp: Some(false),
+            writable: vec![Utf8PathBuf::from("/var/cache/build")],
+            ..SandboxConfig::default()
+        };
+        let sandbox = Sandbox::new(&config, [Utf8PathBuf::from("/srv/rootfs")]);
+        let args = sandbox.wrap(&["/srv/rootfs/bin/sh".to_string()]).unwrap();
+        assert_eq!(
+            args[1..],
+            [
+                "sandbox-exec",
+                "--write",
+                "/srv/rootfs",
+                "--write",
+                "/dev/null",
+                "--write",
+                "/var/cache/build",
+                "--no-seccomp",
+                "--",
+                "/srv/rootfs/bin/sh",
+            ]
+        );
+        assert_eq!(Utf8Path::new(&args[0]), std::env::current_exe().unwrap());
+    }
+}
@@ -72,3 +72,54 @@ impl JsonSchema for IpAddrSchema {
         })
     }
 }
+
+/// Schema proxy for [`crate::output_dir::FileMode`].
+///
+/// A mode is an octal string (`"0755"`) or a number whose digits are read as octal;
+/// the parser reports malformed ones.
+pub(crate) struct FileModeSchema;
+
+impl JsonSchema for FileModeSchema {
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn schema_name() -> Cow<'static, str> {
+        "FileMode".into()
+    }
+
+    fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "anyOf": [
+                { "type": "integer", "minimum": 0 },
+                { "type": "string" }
+            ]
+        })
+    }
+}
+
+/// Schema proxy for [`crate::disk_space::ByteSize`].
+///
+/// A size is a number of bytes or a string with a unit suffix (`2G`, `512M`). The
+/// string form is left unconstrained so the schema never rejects a size the parser
+/// accepts; the parser reports malformed ones.
+pub(crate) struct ByteSizeSchema;
+
+impl JsonSchema for ByteSizeSchema {
+    fn inline_schema() -> bool {
+        true
+    }
+
+    fn schema_name() -> Cow<'static, str> {
+        "ByteSize".into()
+    }
+
+    fn json_schema(_generator: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "anyOf": [
+                { "type": "integer", "minimum": 0 },
+                { "type": "string" }
+            ]
+        })
+    }
+}
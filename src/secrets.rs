@@ -0,0 +1,725 @@
+//! Secrets for the bootstrap and the provision tasks.
+//!
+//! The profile's `secrets` section names values that must not be written into the
+//! profile itself, such as mirror credentials and API tokens. Each secret is read
+//! right before the build from its `from` source: an environment variable of the
+//! rsdebstrap process, a file on the host, or the output of a host command such as
+//! `sops -d` or `vault kv get`. [`Secrets::resolve`] registers every value with
+//! [`mask`], which [`redact`](crate::redact::redact) applies to everything rsdebstrap
+//! prints.
+//!
+//! Bootstrap mirrors refer to a secret as `${secret:NAME}`. Provision tasks receive
+//! theirs from [`SecretContext`]: as environment variables, sourced from a file in the
+//! staging directory so they never appear on a command line, and as files at the
+//! configured paths in the rootfs. Both are shredded when the task finishes.
+
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::process::{Command, Stdio};
+use std::sync::{PoisonError, RwLock};
+
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::error::RsdebstrapError;
+use crate::executor::{CommandExecutor, ExecutionResult};
+use crate::isolation::IsolationContext;
+use crate::phase::{
+    sh_quote, staged_file_paths, validate_host_file_exists, validate_no_parent_dirs,
+    validate_staging_directory,
+};
+use crate::privilege::PrivilegeMethod;
+
+/// Replacement for a secret value in logs.
+pub(crate) const MASK: &str = "***";
+
+/// Lines of a multi-line secret at least this long are also masked on their own, so
+/// that a key printed line by line does not leak.
+const MIN_MASKED_LINE_LEN: usize = 8;
+
+/// Permissions of the files secrets are written to in the rootfs.
+const SECRET_FILE_MODE: u32 = 0o400;
+
+/// Shell snippet that exports the variables in the env file (`$0`) and runs the
+/// task's command (`$@`) in their place.
+const ENV_WRAPPER: &str = r#". "$0" && exec "$@""#;
+
+/// Values [`mask`] replaces, longest first.
+static MASKED: RwLock<Vec<String>> = RwLock::new(Vec::new());
+
+/// Where a secret's value is read from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "SourceFields", into = "SourceFields")]
+pub enum SecretSource {
+    /// Environment variable of the rsdebstrap process.
+    Env(String),
+    /// File on the host (relative paths are resolved against the profile's directory).
+    File(Utf8PathBuf),
+    /// Command run on the host, whose standard output is the value.
+    Command(Vec<String>),
+}
+
+/// `from` as written in the profile, with exactly one key set.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+struct SourceFields {
+    /// Environment variable of the rsdebstrap process.
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    env: Option<String>,
+    /// File on the host (relative paths are resolved against the profile's directory).
+    /// A trailing newline is removed.
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_path",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<crate::schema::Utf8PathSchema>")
+    )]
+    file: Option<Utf8PathBuf>,
+    /// Command run on the host, e.g. `[sops, -d, token.enc]`, whose standard output is
+    /// the value. A trailing newline is removed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    command: Option<Vec<String>>,
+}
+
+impl TryFrom<SourceFields> for SecretSource {
+    type Error = String;
+
+    fn try_from(fields: SourceFields) -> Result<Self, Self::Error> {
+        match (fields.env, fields.file, fields.command) {
+            (Some(env), None, None) => Ok(Self::Env(env)),
+            (None, Some(file), None) => Ok(Self::File(file)),
+            (None, None, Some(command)) => Ok(Self::Command(command)),
+            _ => Err("from must set exactly one of env, file and command".to_string()),
+        }
+    }
+}
+
+impl From<SecretSource> for SourceFields {
+    fn from(source: SecretSource) -> Self {
+        match source {
+            SecretSource::Env(env) => Self {
+                env: Some(env),
+                ..Self::default()
+            },
+            SecretSource::File(file) => Self {
+                file: Some(file),
+                ..Self::default()
+            },
+            SecretSource::Command(command) => Self {
+                command: Some(command),
+                ..Self::default()
+            },
+        }
+    }
+}
+
+/// A secret of the profile's `secrets` section.
+///
+/// A secret without `env` or `file` is only available to bootstrap mirrors, as
+/// `${secret:NAME}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct SecretConfig {
+    /// Where the value is read from: exactly one of `env`, `file` and `command`.
+    #[cfg_attr(feature = "schema", schemars(with = "SourceFields"))]
+    pub from: SecretSource,
+    /// Environment variable provision tasks receive the value in (optional).
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub env: Option<String>,
+    /// Absolute path in the rootfs the value is written to (mode 0400) while a
+    /// provision task runs (optional). Its directory must exist in the rootfs.
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_path",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[cfg_attr(
+        feature = "schema",
+        schemars(with = "Option<crate::schema::Utf8PathSchema>")
+    )]
+    pub file: Option<Utf8PathBuf>,
+    /// `name`s of the provision tasks that receive the secret (default: all).
+    #[serde(
+        default,
+        deserialize_with = "crate::de::string_list",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub tasks: Vec<String>,
+}
+
+impl SecretConfig {
+    /// Creates a secret read from `from`, not yet given to any task.
+    pub fn new(from: SecretSource) -> Self {
+        Self {
+            from,
+            env: None,
+            file: None,
+            tasks: Vec::new(),
+        }
+    }
+
+    /// Gives the secret to provision tasks as the environment variable `env`.
+    pub fn with_env(mut self, env: impl Into<String>) -> Self {
+        self.env = Some(env.into());
+        self
+    }
+
+    /// Writes the secret to `file` in the rootfs while provision tasks run.
+    pub fn with_file(mut self, file: impl Into<Utf8PathBuf>) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+
+    /// Restricts the secret to the provision tasks named `tasks`.
+    pub fn with_tasks<I, S>(mut self, tasks: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.tasks = tasks.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Resolves a relative `file` source against the profile's directory.
+    pub(crate) fn resolve_paths(&mut self, profile_dir: &Utf8Path) {
+        if let SecretSource::File(path) = &mut self.from
+            && path.is_relative()
+        {
+            *path = profile_dir.join(&*path);
+        }
+    }
+
+    /// Validates the secret `name`; `task_names` are the `name`s of the provision tasks.
+    pub(crate) fn validate(&self, name: &str, task_names: &[&str]) -> Result<(), RsdebstrapError> {
+        let invalid =
+            |msg: String| RsdebstrapError::Validation(format!("secrets.{}: {}", name, msg));
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(RsdebstrapError::Validation(format!(
+                "secret name '{}' must consist of ASCII letters, digits, '_' and '-'",
+                name
+            )));
+        }
+
+        match &self.from {
+            SecretSource::Env(var) if !is_env_name(var) => {
+                return Err(invalid(format!("'{}' is not a valid environment variable name", var)));
+            }
+            SecretSource::File(path) => {
+                validate_host_file_exists(path, "secret file")
+                    .map_err(|e| invalid(e.to_string()))?;
+            }
+            SecretSource::Command(command) if command.is_empty() => {
+                return Err(invalid("command must not be empty".to_string()));
+            }
+            _ => {}
+        }
+
+        if let Some(env) = &self.env
+            && !is_env_name(env)
+        {
+            return Err(invalid(format!("env '{}' is not a valid environment variable name", env)));
+        }
+        if let Some(file) = &self.file {
+            if !file.is_absolute() || file.file_name().is_none() {
+                return Err(invalid(format!("file must be an absolute file path: {}", file)));
+            }
+            validate_no_parent_dirs(file, "secret").map_err(|e| invalid(e.to_string()))?;
+        }
+        if !self.tasks.is_empty() && self.env.is_none() && self.file.is_none() {
+            return Err(invalid("tasks requires env or file".to_string()));
+        }
+        for task in &self.tasks {
+            if !task_names.contains(&task.as_str()) {
+                return Err(invalid(format!("no provision task is named '{}'", task)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the value from the source.
+    fn read(&self, name: &str) -> Result<String, RsdebstrapError> {
+        let value = match &self.from {
+            SecretSource::Env(var) => std::env::var(var).map_err(|_| {
+                RsdebstrapError::Validation(format!(
+                    "secret '{}': environment variable {} is not set or not UTF-8",
+                    name, var
+                ))
+            })?,
+            SecretSource::File(path) => trim_newline(fs::read_to_string(path).map_err(|e| {
+                RsdebstrapError::io(format!("failed to read secret '{}' from {}", name, path), e)
+            })?),
+            SecretSource::Command(command) => run_source_command(name, command)?,
+        };
+        if value.is_empty() {
+            return Err(RsdebstrapError::Validation(format!("secret '{}' is empty", name)));
+        }
+        Ok(value)
+    }
+}
+
+/// Returns whether `name` is a portable environment variable name.
+fn is_env_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Removes one trailing newline, as left by `echo` or a text editor.
+fn trim_newline(mut value: String) -> String {
+    if value.ends_with('\n') {
+        value.pop();
+        if value.ends_with('\r') {
+            value.pop();
+        }
+    }
+    value
+}
+
+/// Runs a `command` source on the host and returns its standard output.
+///
+/// Standard error is passed through, so tools like `sops` can report problems; the
+/// output itself never appears in errors.
+fn run_source_command(name: &str, command: &[String]) -> Result<String, RsdebstrapError> {
+    let display = command.join(" ");
+    let output = Command::new(&command[0])
+        .args(&command[1..])
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| RsdebstrapError::Execution {
+            command: display.clone(),
+            status: format!("failed to run the command of secret '{}': {}", name, e),
+            details: None,
+        })?;
+    if !output.status.success() {
+        return Err(RsdebstrapError::Execution {
+            command: display,
+            status: format!("{} (reading secret '{}')", output.status, name),
+            details: None,
+        });
+    }
+    String::from_utf8(output.stdout)
+        .map(trim_newline)
+        .map_err(|_| {
+            RsdebstrapError::Validation(format!("secret '{}': command output is not UTF-8", name))
+        })
+}
+
+/// A resolved secret.
+#[derive(Clone)]
+pub struct Secret {
+    name: String,
+    value: String,
+    env: Option<String>,
+    file: Option<Utf8PathBuf>,
+    tasks: Vec<String>,
+}
+
+impl Secret {
+    /// Returns the secret's name in the profile.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns whether the provision task named `task` receives the secret.
+    fn applies_to(&self, task: Option<&str>) -> bool {
+        (self.env.is_some() || self.file.is_some())
+            && (self.tasks.is_empty() || task.is_some_and(|t| self.tasks.iter().any(|n| n == t)))
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Secret")
+            .field("name", &self.name)
+            .field("value", &MASK)
+            .field("env", &self.env)
+            .field("file", &self.file)
+            .field("tasks", &self.tasks)
+            .finish()
+    }
+}
+
+/// The resolved values of the profile's `secrets`.
+#[derive(Debug, Clone, Default)]
+pub struct Secrets {
+    secrets: Vec<Secret>,
+}
+
+impl Secrets {
+    /// Reads every secret from its source and registers the values with [`mask`].
+    ///
+    /// Runs in dry-run mode too: reading a secret changes nothing, and the mirror
+    /// health check needs the real mirror URLs.
+    pub fn resolve(config: &BTreeMap<String, SecretConfig>) -> Result<Self, RsdebstrapError> {
+        let mut secrets = Vec::with_capacity(config.len());
+        for (name, secret) in config {
+            let value = secret.read(name)?;
+            register(&value);
+            info!("resolved secret {}", name);
+            secrets.push(Secret {
+                name: name.clone(),
+                value,
+                env: secret.env.clone(),
+                file: secret.file.clone(),
+                tasks: secret.tasks.clone(),
+            });
+        }
+        Ok(Self { secrets })
+    }
+
+    /// Returns true if the profile has no secrets.
+    pub fn is_empty(&self) -> bool {
+        self.secrets.is_empty()
+    }
+
+    /// Returns the secrets the provision task with the `name` `task` receives.
+    pub fn for_task(&self, task: Option<&str>) -> Vec<&Secret> {
+        self.secrets.iter().filter(|s| s.applies_to(task)).collect()
+    }
+
+    /// Replaces each `${secret:NAME}` in `text` with the value of secret `NAME`.
+    ///
+    /// [`Profile::validate`](crate::config::Profile::validate) ensures each referenced
+    /// secret exists.
+    pub fn substitute(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for secret in &self.secrets {
+            text = text.replace(&placeholder(&secret.name), &secret.value);
+        }
+        text
+    }
+}
+
+/// Returns the placeholder referring to the secret `name`.
+fn placeholder(name: &str) -> String {
+    format!("${{secret:{}}}", name)
+}
+
+/// Returns the names of the secrets `${secret:NAME}` placeholders in `text` refer to.
+pub(crate) fn placeholder_names(text: &str) -> Vec<&str> {
+    text.split("${secret:")
+        .skip(1)
+        .filter_map(|rest| rest.split_once('}').map(|(name, _)| name))
+        .collect()
+}
+
+/// Registers a secret value so that [`mask`] hides it.
+pub(crate) fn register(value: &str) {
+    let mut masked = MASKED.write().unwrap_or_else(PoisonError::into_inner);
+    let lines = value
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.len() >= MIN_MASKED_LINE_LEN);
+    for value in std::iter::once(value).chain(lines) {
+        if !masked.iter().any(|v| v == value) {
+            masked.push(value.to_string());
+        }
+    }
+    masked.sort_by_key(|v| Reverse(v.len()));
+}
+
+/// Replaces every resolved secret value in `text` with `***`.
+///
+/// Part of [`redact`](crate::redact::redact), which also masks URL passwords and
+/// token assignments.
+pub fn mask(text: &str) -> Cow<'_, str> {
+    let masked = MASKED.read().unwrap_or_else(PoisonError::into_inner);
+    if !masked.iter().any(|v| text.contains(v.as_str())) {
+        return Cow::Borrowed(text);
+    }
+    let mut text = text.to_string();
+    for value in masked.iter() {
+        text = text.replace(value.as_str(), MASK);
+    }
+    Cow::Owned(text)
+}
+
+/// Isolation context wrapper that hands secrets to a provision task.
+///
+/// [`setup()`](Self::setup) writes each `file` secret into the rootfs and the `env`
+/// secrets into an env file in the staging directory; every command then runs
+/// through `/bin/sh`, which sources the env file first. The files are overwritten
+/// with zeros and removed on teardown, or on drop if teardown was skipped.
+pub struct SecretContext {
+    inner: Box<dyn IsolationContext>,
+    /// The env file as seen inside the isolation, if any secret is passed in `env`.
+    env_file: Option<String>,
+    /// Host paths of the files written for the task.
+    written: Vec<Utf8PathBuf>,
+}
+
+impl SecretContext {
+    /// Wraps `inner` and writes the files for `secrets`, handing them to the task's
+    /// user with `privilege`. In dry-run mode nothing is written.
+    pub fn setup(
+        inner: Box<dyn IsolationContext>,
+        secrets: &[&Secret],
+        privilege: Option<PrivilegeMethod>,
+    ) -> Result<Self> {
+        let mut ctx = Self {
+            inner,
+            env_file: None,
+            written: Vec::new(),
+        };
+        let rootfs = ctx.inner.rootfs().to_owned();
+        let mut handed_over = Vec::new();
+
+        for secret in secrets {
+            let Some(file) = &secret.file else {
+                continue;
+            };
+            if ctx.inner.dry_run() {
+                info!("would write secret {} to {}", secret.name, file);
+                continue;
+            }
+            let parent = file.parent().unwrap_or(Utf8Path::new("/"));
+            validate_staging_directory(&rootfs, parent)
+                .with_context(|| format!("cannot write secret {} to {}", secret.name, file))?;
+            let host = rootfs.join(file.strip_prefix("/").unwrap_or(file));
+            ctx.write(&host, secret.value.as_bytes())?;
+            handed_over.push(file.to_string());
+        }
+
+        let env: Vec<(&str, &str)> = secrets
+            .iter()
+            .filter_map(|s| s.env.as_deref().map(|env| (env, s.value.as_str())))
+            .collect();
+        if !env.is_empty() {
+            if ctx.inner.dry_run() {
+                let names: Vec<&str> = env.iter().map(|(name, _)| *name).collect();
+                info!("would pass secrets in {}", names.join(", "));
+            } else {
+                let (host, in_isolation) = staged_file_paths(
+                    ctx.inner.as_ref(),
+                    &format!("{}.env", ctx.inner.staging_name("rsdebstrap-secrets")),
+                );
+                validate_staging_directory(&rootfs, ctx.inner.staging_dir())
+                    .context("cannot write the secrets env file")?;
+                let content: String = env
+                    .iter()
+                    .map(|(name, value)| format!("export {}={}\n", name, sh_quote(value)))
+                    .collect();
+                ctx.write(&host, content.as_bytes())?;
+                handed_over.push(in_isolation.clone());
+                ctx.env_file = Some(in_isolation);
+            }
+        }
+
+        ctx.inner.hand_over(&handed_over, privilege)?;
+        Ok(ctx)
+    }
+
+    /// Creates `host` with mode 0400 and writes `content`, refusing to follow a
+    /// symlink or to replace an existing file.
+    fn write(&mut self, host: &Utf8Path, content: &[u8]) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(SECRET_FILE_MODE)
+            .custom_flags(nofollow())
+            .open(host)
+            .map_err(|e| {
+                RsdebstrapError::io(format!("failed to create secret file {}", host), e)
+            })?;
+        self.written.push(host.to_owned());
+        file.write_all(content)
+            .map_err(|e| RsdebstrapError::io(format!("failed to write secret file {}", host), e))?;
+        Ok(())
+    }
+
+    /// Returns `command` wrapped so that it runs with the env file's variables.
+    fn wrap(&self, command: &[String]) -> Vec<String> {
+        match &self.env_file {
+            Some(env_file) => ["/bin/sh", "-c", ENV_WRAPPER, env_file]
+                .into_iter()
+                .map(str::to_string)
+                .chain(command.iter().cloned())
+                .collect(),
+            None => command.to_vec(),
+        }
+    }
+
+    /// Overwrites the written files with zeros and removes them.
+    fn shred(&mut self) -> Result<()> {
+        let mut first_error = None;
+        for path in std::mem::take(&mut self.written) {
+            if let Err(e) = shred_file(&path) {
+                first_error.get_or_insert(e);
+            }
+        }
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+/// `O_NOFOLLOW`, which `std` does not export.
+fn nofollow() -> i32 {
+    rustix::fs::OFlags::NOFOLLOW.bits() as i32
+}
+
+/// Overwrites the file at `path` with zeros, syncs it and removes it.
+///
+/// A file the task's user took over may no longer be writable; it is still removed.
+fn shred_file(path: &Utf8Path) -> Result<()> {
+    if let Ok(mut file) = OpenOptions::new()
+        .write(true)
+        .custom_flags(nofollow())
+        .open(path)
+    {
+        let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+        let zeros = vec![0u8; usize::try_from(len).unwrap_or(0)];
+        let _ = file.write_all(&zeros).and_then(|()| file.sync_all());
+    }
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => {
+            Err(RsdebstrapError::io(format!("failed to remove secret file {}", path), e).into())
+        }
+    }
+}
+
+impl IsolationContext for SecretContext {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn rootfs(&self) -> &Utf8Path {
+        self.inner.rootfs()
+    }
+
+    fn dry_run(&self) -> bool {
+        self.inner.dry_run()
+    }
+
+    fn staging_dir(&self) -> &Utf8Path {
+        self.inner.staging_dir()
+    }
+
+    fn staging_name(&self, kind: &str) -> String {
+        self.inner.staging_name(kind)
+    }
+
+    fn execute(
+        &self,
+        command: &[String],
+        privilege: Option<PrivilegeMethod>,
+    ) -> Result<ExecutionResult> {
+        self.inner.execute(&self.wrap(command), privilege)
+    }
+
+    fn execute_with_stdin(
+        &self,
+        command: &[String],
+        privilege: Option<PrivilegeMethod>,
+        stdin: &[u8],
+    ) -> Result<ExecutionResult> {
+        self.inner
+            .execute_with_stdin(&self.wrap(command), privilege, stdin)
+    }
+
+    fn hand_over(&self, paths: &[String], privilege: Option<PrivilegeMethod>) -> Result<()> {
+        self.inner.hand_over(paths, privilege)
+    }
+
+    fn executor(&self) -> &dyn CommandExecutor {
+        self.inner.executor()
+    }
+
+    fn teardown(&mut self) -> Result<()> {
+        let shred_result = self.shred();
+        let teardown_result = self.inner.teardown();
+        shred_result.context("failed to shred secret files")?;
+        teardown_result
+    }
+}
+
+impl Drop for SecretContext {
+    fn drop(&mut self) {
+        if let Err(e) = self.shred() {
+            tracing::error!("failed to shred secret files during cleanup: {:#}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholder_names_in_text() {
+        assert_eq!(
+            placeholder_names("https://u:${secret:pw}@host/${secret:path}/x"),
+            ["pw", "path"]
+        );
+        assert!(placeholder_names("https://host/debian").is_empty());
+    }
+
+    #[test]
+    fn mask_hides_values_and_long_lines() {
+        register("s3cr3t-mask-test");
+        register("first-line-of-key\nsecond-line-of-key");
+        assert_eq!(mask("token=s3cr3t-mask-test!"), "token=***!");
+        assert_eq!(mask("got second-line-of-key"), "got ***");
+        assert!(matches!(mask("nothing here"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn env_names() {
+        assert!(is_env_name("API_TOKEN"));
+        assert!(is_env_name("_x1"));
+        assert!(!is_env_name("1X"));
+        assert!(!is_env_name("A-B"));
+        assert!(!is_env_name(""));
+    }
+
+    #[test]
+    fn debug_does_not_show_the_value() {
+        let secret = Secret {
+            name: "token".to_string(),
+            value: "hunter2".to_string(),
+            env: None,
+            file: None,
+            tasks: Vec::new(),
+        };
+        assert!(!format!("{:?}", secret).contains("hunter2"));
+    }
+
+    #[test]
+    fn trailing_newline_is_trimmed_once() {
+        assert_eq!(trim_newline("a\n".to_string()), "a");
+        assert_eq!(trim_newline("a\r\n".to_string()), "a");
+        assert_eq!(trim_newline("a\n\n".to_string()), "a\n");
+    }
+}
@@ -0,0 +1,788 @@
+//! Build server mode (`rsdebstrap serve`).
+//!
+//! The server listens on a Unix socket and speaks JSON-RPC 2.0, one request per line
+//! and one response per line, so a build farm can submit builds and follow them
+//! without shelling out. Methods:
+//!
+//! - `validate {"profile": path, "lint"?, "no_strict"?, "arch"?}` — loads and
+//!   validates a profile:
+//!   `{"valid": bool, "error"?: string, "warnings"?: [{code, location, message}]}`,
+//!   with `warnings` from [`crate::lint`] when `lint` is set
+//! - `apply {"profile": path, "dry_run"?, "skip_bootstrap"?, "tags"?, "skip_tags"?,
+//!   "start_at_task"?, "metrics"?, "no_strict"?, "arch"?}` — queues a build: `{"job": id}`
+//! - `status {"job"?: id}` — one job's state, or every job's without `job`
+//! - `logs {"job": id, "from"?: n}` — the job's log lines from line `n` on, the index
+//!   to ask for next, and whether the job is done; poll it to stream the log
+//! - `cancel {"job": id}` — drops a queued job, or interrupts a running one like
+//!   Ctrl-C would, so it unwinds through its cleanup
+//!
+//! Profile paths are paths on the server. Jobs start in submission order on a pool
+//! of `--max-builds` worker threads, each running one build at a time through
+//! [`Runner`]; they are kept in memory until the server exits. Builds that share an
+//! output directory or a layer cache take turns through the locks in
+//! [`crate::lock`]. The socket is created with mode 0600, so only its owner
+//! (and root) can submit builds.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::thread;
+
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tracing::{info, warn};
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::config::{self, Profile, UnknownFields};
+use crate::error::RsdebstrapError;
+use crate::executor::{self, CommandExecutor, RealCommandExecutor};
+use crate::lint;
+use crate::metrics::MetricsFormat;
+use crate::pipeline::TagFilter;
+use crate::progress::ProgressEvent;
+use crate::runner::Runner;
+use crate::shellcheck;
+
+/// JSON-RPC error codes (see the JSON-RPC 2.0 specification).
+mod codes {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    /// An unknown job id.
+    pub const UNKNOWN_JOB: i64 = -32001;
+}
+
+/// Parameters of the `apply` method, mirroring the `apply` flags of the same names.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApplyParams {
+    /// Path of the profile on the server.
+    pub profile: Utf8PathBuf,
+    /// Log what would be done instead of doing it.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Reuse the existing rootfs instead of bootstrapping it.
+    #[serde(default)]
+    pub skip_bootstrap: bool,
+    /// Run only provision tasks carrying one of these tags.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Skip provision tasks carrying any of these tags.
+    #[serde(default)]
+    pub skip_tags: Vec<String>,
+    /// Start the provision phase at the task with this `name`.
+    #[serde(default)]
+    pub start_at_task: Option<String>,
+    /// Write build metrics next to the artifacts in these formats.
+    #[serde(default)]
+    pub metrics: Vec<MetricsFormat>,
+    /// Warn about unknown profile fields instead of rejecting the profile.
+    #[serde(default)]
+    pub no_strict: bool,
+    /// Build the profile's `targets` entry for this architecture.
+    #[serde(default)]
+    pub arch: Option<String>,
+}
+
+/// The state of a job.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// Waiting for the jobs before it.
+    Queued,
+    /// Building.
+    Running,
+    /// The build finished successfully.
+    Succeeded,
+    /// The build failed; the job's `error` says why.
+    Failed,
+    /// Cancelled before or while it ran.
+    Cancelled,
+}
+
+impl JobState {
+    /// Returns true once the job will not change any more.
+    pub fn is_done(self) -> bool {
+        matches!(self, Self::Succeeded | Self::Failed | Self::Cancelled)
+    }
+}
+
+/// A job's state as reported by `status`.
+#[derive(Debug, Clone, Serialize)]
+struct JobStatus {
+    job: u64,
+    profile: Utf8PathBuf,
+    state: JobState,
+    /// The step running now, e.g. `provision 2: shell:setup.sh`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    step: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+struct Job {
+    id: u64,
+    params: ApplyParams,
+    status: Mutex<JobStatus>,
+    log: Mutex<Vec<String>>,
+    cancel_requested: AtomicBool,
+    /// Watched by the commands of the job's build (see [`executor::watch_cancel`]).
+    interrupt: Arc<AtomicBool>,
+}
+
+impl Job {
+    fn status(&self) -> JobStatus {
+        lock(&self.status).clone()
+    }
+
+    fn set_step(&self, step: Option<String>) {
+        lock(&self.status).step = step;
+    }
+}
+
+/// Locks `mutex`, recovering the data if a panicking thread poisoned it: job state is
+/// only ever replaced whole, so it stays consistent.
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+thread_local! {
+    /// The job running on this worker thread, whose log receives its log lines.
+    static CURRENT_JOB: RefCell<Option<Arc<Job>>> = const { RefCell::new(None) };
+}
+
+#[derive(Default)]
+struct Queue {
+    pending: VecDeque<Arc<Job>>,
+}
+
+/// The job table and queue of a build server.
+///
+/// [`start()`](Self::start) spawns the worker threads that run the queued jobs;
+/// [`handle()`](Self::handle) answers one JSON-RPC request.
+pub struct Server {
+    jobs: Mutex<Vec<Arc<Job>>>,
+    queue: Mutex<Queue>,
+    wakeup: Condvar,
+    /// Runs the commands of every job instead of a [`RealCommandExecutor`].
+    executor: Option<Arc<dyn CommandExecutor>>,
+}
+
+impl Server {
+    /// Creates a server and starts `max_builds` worker threads (at least one). Jobs run
+    /// their commands through `executor` if given, otherwise through a
+    /// [`RealCommandExecutor`].
+    pub fn start(executor: Option<Arc<dyn CommandExecutor>>, max_builds: usize) -> Arc<Self> {
+        let server = Self::new(executor);
+        for _ in 0..max_builds.max(1) {
+            let worker = Arc::clone(&server);
+            thread::spawn(move || worker.work());
+        }
+        server
+    }
+
+    fn new(executor: Option<Arc<dyn CommandExecutor>>) -> Arc<Self> {
+        Arc::new(Self {
+            jobs: Mutex::new(Vec::new()),
+            queue: Mutex::new(Queue::default()),
+            wakeup: Condvar::new(),
+            executor,
+        })
+    }
+
+    fn work(&self) {
+        loop {
+            let job = {
+                let mut queue = lock(&self.queue);
+                loop {
+                    if let Some(job) = queue.pending.pop_front() {
+                        break job;
+                    }
+                    queue = self
+                        .wakeup
+                        .wait(queue)
+                        .unwrap_or_else(PoisonError::into_inner);
+                }
+            };
+            self.run_job(&job);
+        }
+    }
+
+    fn run_job(&self, job: &Arc<Job>) {
+        {
+            let mut status = lock(&job.status);
+            if status.state != JobState::Queued {
+                return;
+            }
+            status.state = JobState::Running;
+        }
+        executor::watch_cancel(Some(Arc::clone(&job.interrupt)));
+        CURRENT_JOB.set(Some(Arc::clone(job)));
+        info!("building {}", job.params.profile);
+
+        let result = self.build(job);
+
+        CURRENT_JOB.set(None);
+        executor::watch_cancel(None);
+        let mut status = lock(&job.status);
+        status.step = None;
+        status.state = match result {
+            Ok(()) => JobState::Succeeded,
+            Err(_) if job.cancel_requested.load(Ordering::SeqCst) => JobState::Cancelled,
+            Err(e) => {
+                status.error = Some(format!("{:#}", e));
+                JobState::Failed
+            }
+        };
+        info!("job {}: {:?}", job.id, status.state);
+    }
+
+    fn build(&self, job: &Arc<Job>) -> Result<()> {
+        let params = &job.params;
+        let executor = self.executor.clone().unwrap_or_else(|| {
+            Arc::new(RealCommandExecutor {
+                dry_run: params.dry_run,
+            })
+        });
+        let progress_job = Arc::clone(job);
+        Runner::load_for(&params.profile, unknown_fields(params.no_strict), params.arch.as_deref())?
+            .with_executor(executor)
+            .with_dry_run(params.dry_run)
+            // Queued builds sharing a `dir` take turns rather than failing.
+            .with_wait_for_lock(true)
+            .with_bootstrap(!params.skip_bootstrap)
+            .with_tag_filter(TagFilter {
+                include: params.tags.clone(),
+                exclude: params.skip_tags.clone(),
+            })
+            .with_start_at_task(params.start_at_task.as_deref())
+            .with_metrics(&params.metrics)
+            .with_progress(move |event: &ProgressEvent| {
+                progress_job.set_step(match event {
+                    ProgressEvent::BootstrapStarted { command } => {
+                        Some(format!("bootstrap: {}", command))
+                    }
+                    ProgressEvent::TaskStarted {
+                        phase,
+                        number,
+                        name,
+                    } => Some(format!("{} {}: {}", phase, number, name)),
+                    _ => None,
+                })
+            })
+            .run()
+    }
+
+    fn job(&self, id: u64) -> Option<Arc<Job>> {
+        lock(&self.jobs).iter().find(|j| j.id == id).cloned()
+    }
+
+    fn submit(&self, params: ApplyParams) -> u64 {
+        let mut jobs = lock(&self.jobs);
+        let id = jobs.len() as u64 + 1;
+        let job = Arc::new(Job {
+            id,
+            status: Mutex::new(JobStatus {
+                job: id,
+                profile: params.profile.clone(),
+                state: JobState::Queued,
+                step: None,
+                error: None,
+            }),
+            params,
+            log: Mutex::new(Vec::new()),
+            cancel_requested: AtomicBool::new(false),
+            interrupt: Arc::new(AtomicBool::new(false)),
+        });
+        jobs.push(Arc::clone(&job));
+        lock(&self.queue).pending.push_back(job);
+        self.wakeup.notify_one();
+        id
+    }
+
+    /// Answers one JSON-RPC request line. Returns `None` for a notification (a request
+    /// without an `id`), which gets no response.
+    pub fn handle(&self, line: &str) -> Option<String> {
+        let response = match serde_json::from_str::<Value>(line) {
+            Err(e) => {
+                Some(error_response(Value::Null, codes::PARSE_ERROR, format!("parse error: {}", e)))
+            }
+            Ok(request) => self.handle_request(request),
+        };
+        response.map(|r| r.to_string())
+    }
+
+    fn handle_request(&self, request: Value) -> Option<Value> {
+        let id = request.get("id").cloned();
+        let method = request.get("method").and_then(Value::as_str);
+        let (Some("2.0"), Some(method)) = (request.get("jsonrpc").and_then(Value::as_str), method)
+        else {
+            return Some(error_response(
+                id.unwrap_or(Value::Null),
+                codes::INVALID_REQUEST,
+                "invalid request: expected a JSON-RPC 2.0 request object".to_string(),
+            ));
+        };
+        let params = request.get("params").cloned().unwrap_or(json!({}));
+        let result = self.call(method, params);
+        let id = id?;
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => error_response(id, code, message),
+        })
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<Value, (i64, String)> {
+        match method {
+            "validate" => {
+                #[derive(Deserialize)]
+                #[serde(deny_unknown_fields)]
+                struct Params {
+                    profile: Utf8PathBuf,
+                    #[serde(default)]
+                    lint: bool,
+                    #[serde(default)]
+                    no_strict: bool,
+                    #[serde(default)]
+                    arch: Option<String>,
+                }
+                let params: Params = parse_params(params)?;
+                let unknown = unknown_fields(params.no_strict);
+                Ok(match validate_profile(&params.profile, unknown, params.arch.as_deref()) {
+                    Ok(profile) if params.lint => {
+                        json!({ "valid": true, "warnings": lint::lint_profile(&profile) })
+                    }
+                    Ok(_) => json!({ "valid": true }),
+                    Err(e) => json!({ "valid": false, "error": format!("{:#}", e) }),
+                })
+            }
+            "apply" => {
+                let params: ApplyParams = parse_params(params)?;
+                Ok(json!({ "job": self.submit(params) }))
+            }
+            "status" => {
+                #[derive(Deserialize)]
+                #[serde(deny_unknown_fields)]
+                struct Params {
+                    job: Option<u64>,
+                }
+                let params: Params = parse_params(params)?;
+                match params.job {
+                    Some(id) => to_value(self.known_job(id)?.status()),
+                    None => {
+                        let jobs: Vec<JobStatus> =
+                            lock(&self.jobs).iter().map(|j| j.status()).collect();
+                        Ok(json!({ "jobs": to_value(jobs)? }))
+                    }
+                }
+            }
+            "logs" => {
+                #[derive(Deserialize)]
+                #[serde(deny_unknown_fields)]
+                struct Params {
+                    job: u64,
+                    #[serde(default)]
+                    from: usize,
+                }
+                let params: Params = parse_params(params)?;
+                let job = self.known_job(params.job)?;
+                // Read the state first: a job reported done has no lines still to come.
+                let done = job.status().state.is_done();
+                let log = lock(&job.log);
+                let lines = log.get(params.from..).unwrap_or_default();
+                Ok(json!({ "lines": lines, "next": params.from.max(log.len()), "done": done }))
+            }
+            "cancel" => {
+                #[derive(Deserialize)]
+                #[serde(deny_unknown_fields)]
+                struct Params {
+                    job: u64,
+                }
+                let params: Params = parse_params(params)?;
+                let job = self.known_job(params.job)?;
+                let mut status = lock(&job.status);
+                match status.state {
+                    JobState::Queued => status.state = JobState::Cancelled,
+                    JobState::Running => {
+                        job.cancel_requested.store(true, Ordering::SeqCst);
+                        job.interrupt.store(true, Ordering::SeqCst);
+                        warn!("job {}: cancelling", job.id);
+                    }
+                    _ => {}
+                }
+                Ok(json!({ "state": status.state }))
+            }
+            _ => Err((codes::METHOD_NOT_FOUND, format!("unknown method {:?}", method))),
+        }
+    }
+
+    fn known_job(&self, id: u64) -> Result<Arc<Job>, (i64, String)> {
+        self.job(id)
+            .ok_or_else(|| (codes::UNKNOWN_JOB, format!("no job {}", id)))
+    }
+}
+
+fn validate_profile(
+    path: &Utf8Path,
+    unknown_fields: UnknownFields,
+    arch: Option<&str>,
+) -> Result<Profile, RsdebstrapError> {
+    let profile = config::load_profile_for(path, unknown_fields, arch)?;
+    profile.validate()?;
+    shellcheck::check_profile(&profile)?;
+    Ok(profile)
+}
+
+/// Maps the `no_strict` param to the profile loader's policy.
+fn unknown_fields(no_strict: bool) -> UnknownFields {
+    if no_strict {
+        UnknownFields::Warn
+    } else {
+        UnknownFields::Deny
+    }
+}
+
+fn parse_params<T: serde::de::DeserializeOwned>(params: Value) -> Result<T, (i64, String)> {
+    serde_json::from_value(params)
+        .map_err(|e| (codes::INVALID_PARAMS, format!("invalid params: {}", e)))
+}
+
+fn to_value(value: impl Serialize) -> Result<Value, (i64, String)> {
+    serde_json::to_value(value).map_err(|e| (codes::INVALID_REQUEST, e.to_string()))
+}
+
+fn error_response(id: Value, code: i64, message: String) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+/// Tracing writer for a server: prints each event to stderr and also appends it to
+/// the log of the job running on the emitting thread. A job's lines are prefixed with
+/// `[job N]` on stderr, since several jobs may run at once.
+#[derive(Debug, Default)]
+pub struct JobLogWriter;
+
+/// One formatted event, printed and recorded when dropped.
+pub struct JobLogLine {
+    buffer: Vec<u8>,
+}
+
+impl<'a> MakeWriter<'a> for JobLogWriter {
+    type Writer = JobLogLine;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        JobLogLine { buffer: Vec::new() }
+    }
+}
+
+impl Write for JobLogLine {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for JobLogLine {
+    fn drop(&mut self) {
+        CURRENT_JOB.with_borrow(|job| match job {
+            Some(job) => {
+                let text = String::from_utf8_lossy(&self.buffer);
+                let text = crate::redact::redact(&text);
+                let mut stderr = io::stderr().lock();
+                for line in text.lines() {
+                    let _ = writeln!(stderr, "[job {}] {}", job.id, line);
+                }
+                lock(&job.log).extend(text.lines().map(str::to_string));
+            }
+            None => {
+                let text = String::from_utf8_lossy(&self.buffer);
+                let _ = io::stderr().write_all(crate::redact::redact(&text).as_bytes());
+            }
+        });
+    }
+}
+
+/// Serves `server` on the Unix socket at `path` until the process exits.
+///
+/// A socket file left by a server that is no longer running is replaced; one a running
+/// server still accepts connections on is an error.
+pub fn listen(path: &Utf8Path, server: Arc<Server>) -> Result<()> {
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(RsdebstrapError::Validation(format!(
+                "{} exists and is not a socket",
+                path
+            ))
+            .into());
+        }
+        if UnixStream::connect(path).is_ok() {
+            return Err(RsdebstrapError::Validation(format!(
+                "another server is listening on {}",
+                path
+            ))
+            .into());
+        }
+        fs::remove_file(path)
+            .map_err(|e| RsdebstrapError::io(format!("failed to remove stale {}", path), e))?;
+    }
+    let listener = UnixListener::bind(path)
+        .map_err(|e| RsdebstrapError::io(format!("failed to listen on {}", path), e))?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+        .map_err(|e| RsdebstrapError::io(format!("failed to restrict {}", path), e))?;
+    info!("listening on {}", path);
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let server = Arc::clone(&server);
+                thread::spawn(move || {
+                    if let Err(e) = serve_connection(stream, &server) {
+                        warn!("connection failed: {}", e);
+                    }
+                });
+            }
+            Err(e) => warn!("failed to accept a connection: {}", e),
+        }
+    }
+    Ok(())
+}
+
+fn serve_connection(stream: UnixStream, server: &Server) -> io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(response) = server.handle(&line) {
+            writer.write_all(response.as_bytes())?;
+            writer.write_all(b"\n")?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::{CommandSpec, ExecutionResult};
+    use std::time::{Duration, Instant};
+
+    /// Records command names and succeeds without running anything.
+    #[derive(Default)]
+    struct RecordingExecutor {
+        commands: Mutex<Vec<String>>,
+    }
+
+    impl CommandExecutor for RecordingExecutor {
+        fn execute(&self, spec: &CommandSpec) -> Result<ExecutionResult> {
+            lock(&self.commands).push(spec.command.clone());
+            Ok(ExecutionResult {
+                status: None,
+                details: None,
+            })
+        }
+    }
+
+    fn call(server: &Server, method: &str, params: Value) -> Value {
+        let request = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+        let response = server.handle(&request.to_string()).unwrap();
+        serde_json::from_str(&response).unwrap()
+    }
+
+    fn write_profile(dir: &Utf8Path) -> Utf8PathBuf {
+        let path = dir.join("profile.yml");
+        fs::write(
+            &path,
+            format!(
+                "dir: {}\nbootstrap:\n  type: mmdebstrap\n  suite: trixie\n  target: rootfs\n",
+                dir.join("out")
+            ),
+        )
+        .unwrap();
+        path
+    }
+
+    fn wait_until_done(server: &Server, job: u64) -> Value {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let status = call(server, "status", json!({ "job": job }))["result"].clone();
+            if status["state"] != "queued" && status["state"] != "running" {
+                return status;
+            }
+            assert!(Instant::now() < deadline, "job {job} did not finish");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn validate_reports_invalid_profiles() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        let server = Server::start(Some(Arc::new(RecordingExecutor::default())), 1);
+
+        let profile = write_profile(dir);
+        let response = call(&server, "validate", json!({ "profile": profile }));
+        assert_eq!(response["result"], json!({ "valid": true }));
+
+        let response = call(&server, "validate", json!({ "profile": dir.join("missing.yml") }));
+        assert_eq!(response["result"]["valid"], false);
+        assert!(
+            response["result"]["error"]
+                .as_str()
+                .unwrap()
+                .contains("missing.yml")
+        );
+
+        fs::write(
+            &profile,
+            format!(
+                "dir: {}\nbootstrap:\n  type: mmdebstrap\n  suite: trixie\n  target: rootfs\n\
+                provision:\n- type: shell\n  content: echo hi\n",
+                dir.join("out")
+            ),
+        )
+        .unwrap();
+        let response = call(&server, "validate", json!({ "profile": profile, "lint": true }));
+        assert_eq!(response["result"]["valid"], true);
+        assert_eq!(
+            response["result"]["warnings"],
+            json!([{
+                "code": "unnamed-task",
+                "location": "provision 1",
+                "message": "task shell:<inline> has no name; set `name` to label it in logs",
+            }])
+        );
+    }
+
+    #[test]
+    fn apply_runs_queued_jobs_in_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        let executor = Arc::new(RecordingExecutor::default());
+        let server = Server::start(Some(executor.clone()), 1);
+        let profile = write_profile(dir);
+
+        let first = call(&server, "apply", json!({ "profile": profile, "dry_run": true }));
+        assert_eq!(first["result"]["job"], 1);
+        let second = call(&server, "apply", json!({ "profile": dir.join("missing.yml") }));
+        assert_eq!(second["result"]["job"], 2);
+
+        assert_eq!(wait_until_done(&server, 1)["state"], "succeeded");
+        let failed = wait_until_done(&server, 2);
+        assert_eq!(failed["state"], "failed");
+        assert!(failed["error"].as_str().unwrap().contains("missing.yml"));
+        assert_eq!(*lock(&executor.commands), ["mmdebstrap"]);
+
+        let all = call(&server, "status", json!({}));
+        assert_eq!(all["result"]["jobs"].as_array().unwrap().len(), 2);
+        let logs = call(&server, "logs", json!({ "job": 1, "from": 1000 }));
+        assert_eq!(logs["result"]["lines"], json!([]));
+        assert_eq!(logs["result"]["done"], true);
+    }
+
+    /// Holds each command until two are running at once, recording the most seen.
+    #[derive(Default)]
+    struct OverlapExecutor {
+        running: Mutex<usize>,
+        most: Mutex<usize>,
+        changed: Condvar,
+    }
+
+    impl CommandExecutor for OverlapExecutor {
+        fn execute(&self, _spec: &CommandSpec) -> Result<ExecutionResult> {
+            let mut running = lock(&self.running);
+            *running += 1;
+            let mut most = lock(&self.most);
+            *most = (*most).max(*running);
+            drop(most);
+            self.changed.notify_all();
+            let (mut running, _) = self
+                .changed
+                .wait_timeout_while(running, Duration::from_secs(5), |running| *running < 2)
+                .unwrap();
+            *running -= 1;
+            Ok(ExecutionResult {
+                status: None,
+                details: None,
+            })
+        }
+    }
+
+    #[test]
+    fn max_builds_runs_jobs_concurrently() {
+        let executor = Arc::new(OverlapExecutor::default());
+        let server = Server::start(Some(executor.clone()), 2);
+        let dirs = [tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap()];
+        let jobs: Vec<u64> = dirs
+            .iter()
+            .map(|dir| {
+                let profile = write_profile(Utf8Path::from_path(dir.path()).unwrap());
+                let params = json!({ "profile": profile, "dry_run": true });
+                call(&server, "apply", params)["result"]["job"]
+                    .as_u64()
+                    .unwrap()
+            })
+            .collect();
+
+        for job in jobs {
+            assert_eq!(wait_until_done(&server, job)["state"], "succeeded");
+        }
+        assert_eq!(*lock(&executor.most), 2);
+    }
+
+    #[test]
+    fn cancel_drops_queued_job() {
+        let executor = Arc::new(RecordingExecutor::default());
+        // No worker thread: the job stays queued until run by hand.
+        let server = Server::new(Some(executor.clone()));
+        let dir = tempfile::tempdir().unwrap();
+        let profile = write_profile(Utf8Path::from_path(dir.path()).unwrap());
+        let job = call(&server, "apply", json!({ "profile": profile }))["result"]["job"]
+            .as_u64()
+            .unwrap();
+
+        let response = call(&server, "cancel", json!({ "job": job }));
+        assert_eq!(response["result"]["state"], "cancelled");
+
+        let queued = lock(&server.queue).pending.pop_front().unwrap();
+        server.run_job(&queued);
+        assert!(lock(&executor.commands).is_empty());
+        let status = call(&server, "status", json!({ "job": job }));
+        assert_eq!(status["result"]["state"], "cancelled");
+    }
+
+    #[test]
+    fn errors_follow_json_rpc() {
+        let server = Server::start(Some(Arc::new(RecordingExecutor::default())), 1);
+
+        let parse: Value = serde_json::from_str(&server.handle("{").unwrap()).unwrap();
+        assert_eq!(parse["error"]["code"], codes::PARSE_ERROR);
+        let invalid: Value = serde_json::from_str(&server.handle("{\"id\":3}").unwrap()).unwrap();
+        assert_eq!(invalid["error"]["code"], codes::INVALID_REQUEST);
+        assert_eq!(invalid["id"], 3);
+        assert_eq!(call(&server, "build", json!({}))["error"]["code"], codes::METHOD_NOT_FOUND);
+        assert_eq!(
+            call(&server, "apply", json!({ "profile": 1 }))["error"]["code"],
+            codes::INVALID_PARAMS
+        );
+        assert_eq!(
+            call(&server, "status", json!({ "job": 9 }))["error"]["code"],
+            codes::UNKNOWN_JOB
+        );
+
+        let notification = json!({ "jsonrpc": "2.0", "method": "status" });
+        assert_eq!(server.handle(&notification.to_string()), None);
+    }
+}
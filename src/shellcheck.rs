@@ -0,0 +1,663 @@
+//! Linting of shell task sources, as selected by `defaults.shellcheck`.
+//!
+//! [`check_profile`] runs every shell task's script (inline `content` or `script`
+//! file) through `shellcheck` before anything is built, so broken quoting fails
+//! `validate` and `apply` up front instead of deep into a build. Without `shellcheck`
+//! on the `PATH`, a built-in scanner reports what breaks a script outright:
+//! unterminated quotes, backticks, `$(`/`${` and here-documents. Findings at or above
+//! `fail_on` fail the check; the rest are logged as warnings. Tasks whose `shell` is
+//! not a POSIX-family shell (`sh`, `dash`, `bash`, `ksh`) are skipped.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use camino::Utf8Path;
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::config::Profile;
+use crate::error::RsdebstrapError;
+use crate::phase::{ProvisionTask, ScriptSource};
+use crate::pipeline::task_label;
+
+/// Program run to lint scripts.
+const SHELLCHECK: &str = "shellcheck";
+
+/// Shells `shellcheck` understands, by executable name.
+const SHELLS: &[&str] = &["sh", "dash", "bash", "ksh"];
+
+/// Severity of a finding, in `shellcheck`'s terms, from least to most severe.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum ShellcheckSeverity {
+    /// Stylistic suggestions.
+    Style,
+    /// Notes on code that is probably fine.
+    Info,
+    /// Code that likely does not do what was meant.
+    #[default]
+    Warning,
+    /// Code that does not parse or cannot work.
+    Error,
+}
+
+impl ShellcheckSeverity {
+    /// Returns the severity's name, as `shellcheck --severity` takes it.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Style => "style",
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for ShellcheckSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Linting of shell task sources before the build (`defaults.shellcheck`).
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct ShellcheckConfig {
+    /// Least severe findings reported (default: warning).
+    #[serde(default)]
+    pub severity: ShellcheckSeverity,
+    /// Findings at or above this severity fail validation; less severe ones are
+    /// logged as warnings (default: error).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fail_on: Option<ShellcheckSeverity>,
+}
+
+impl ShellcheckConfig {
+    /// Returns the severity at which findings fail validation.
+    pub fn fail_on(&self) -> ShellcheckSeverity {
+        self.fail_on.unwrap_or(ShellcheckSeverity::Error)
+    }
+
+    /// Validates that `fail_on` is not below `severity`, which would never report
+    /// the findings it names.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RsdebstrapError::Validation`] otherwise.
+    pub fn validate(&self) -> Result<(), RsdebstrapError> {
+        if self.fail_on() < self.severity {
+            return Err(RsdebstrapError::Validation(format!(
+                "defaults.shellcheck.fail_on ({}) must not be below severity ({})",
+                self.fail_on(),
+                self.severity
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// One problem found in a shell task's script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// How serious the problem is.
+    pub severity: ShellcheckSeverity,
+    /// The `shellcheck` code (`SC<code>`), or `None` from the built-in scanner.
+    pub code: Option<u32>,
+    /// The task, as a task label (`provision 2 (setup)`).
+    pub location: String,
+    /// Line in the script, from 1.
+    pub line: usize,
+    /// Column in the line, from 1.
+    pub column: usize,
+    /// What is wrong.
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.severity)?;
+        if let Some(code) = self.code {
+            write!(f, "[SC{}]", code)?;
+        }
+        write!(f, " {} line {}:{}: {}", self.location, self.line, self.column, self.message)
+    }
+}
+
+/// Lints the scripts of `profile`'s shell tasks as `defaults.shellcheck` says.
+///
+/// Every finding is logged; nothing is checked when `defaults.shellcheck` is unset.
+///
+/// # Errors
+///
+/// Returns [`RsdebstrapError::Validation`] if any finding is at or above `fail_on`,
+/// and an I/O error if a script cannot be read or `shellcheck` fails to lint it.
+pub fn check_profile(profile: &Profile) -> Result<(), RsdebstrapError> {
+    let Some(config) = &profile.defaults.shellcheck else {
+        return Ok(());
+    };
+    let mut findings = Vec::new();
+    for (index, task) in profile.provision.iter().enumerate() {
+        let ProvisionTask::Shell(shell_task) = task else {
+            continue;
+        };
+        let location = task_label("provision", index + 1, task);
+        let Some(shell) = shell_name(shell_task.shell()) else {
+            debug!("not linting {}: shellcheck does not know {}", location, shell_task.shell());
+            continue;
+        };
+        let problems = match run_shellcheck(SHELLCHECK, shell, shell_task.source(), config.severity)
+        {
+            Ok(problems) => problems,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                debug!("{} not found; using the built-in scanner", SHELLCHECK);
+                let content = match shell_task.source() {
+                    ScriptSource::Content(content) => content.clone(),
+                    ScriptSource::Script(path) => std::fs::read_to_string(path)
+                        .map_err(|e| RsdebstrapError::io(format!("failed to read {}", path), e))?,
+                };
+                scan(&content)
+            }
+            Err(e) => {
+                return Err(RsdebstrapError::io(
+                    format!("failed to lint {} with {}", location, SHELLCHECK),
+                    e,
+                ));
+            }
+        };
+        findings.extend(
+            problems
+                .into_iter()
+                .filter(|problem| problem.severity >= config.severity)
+                .map(|problem| problem.at(&location)),
+        );
+    }
+
+    for finding in &findings {
+        warn!("{}", finding);
+    }
+    let fail_on = config.fail_on();
+    let mut failing = findings.iter().filter(|f| f.severity >= fail_on);
+    match failing.next() {
+        None => {
+            info!("shell task sources: {} finding(s)", findings.len());
+            Ok(())
+        }
+        Some(first) => Err(RsdebstrapError::Validation(format!(
+            "{} shell task finding(s) at or above {} severity, first: {}",
+            1 + failing.count(),
+            fail_on,
+            first
+        ))),
+    }
+}
+
+/// Returns the `shellcheck --shell` name of `shell`, or `None` if it does not lint it.
+fn shell_name(shell: &str) -> Option<&str> {
+    let name = Utf8Path::new(shell).file_name()?;
+    SHELLS.contains(&name).then_some(name)
+}
+
+/// A finding before it is tied to a task.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Problem {
+    severity: ShellcheckSeverity,
+    code: Option<u32>,
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+impl Problem {
+    fn at(self, location: &str) -> Finding {
+        Finding {
+            severity: self.severity,
+            code: self.code,
+            location: location.to_string(),
+            line: self.line,
+            column: self.column,
+            message: self.message,
+        }
+    }
+}
+
+/// `shellcheck --format=json1` output.
+#[derive(Deserialize)]
+struct Report {
+    comments: Vec<Comment>,
+}
+
+#[derive(Deserialize)]
+struct Comment {
+    line: usize,
+    column: usize,
+    level: ShellcheckSeverity,
+    code: u32,
+    message: String,
+}
+
+/// Runs `program` (`shellcheck`) over `source` as a `shell` script.
+///
+/// Returns a [`io::ErrorKind::NotFound`] error if `program` is not installed.
+fn run_shellcheck(
+    program: &str,
+    shell: &str,
+    source: &ScriptSource,
+    severity: ShellcheckSeverity,
+) -> io::Result<Vec<Problem>> {
+    let mut command = Command::new(program);
+    command
+        .arg("--format=json1")
+        .arg(format!("--shell={}", shell))
+        .arg(format!("--severity={}", severity))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let output = match source {
+        ScriptSource::Script(path) => command.arg(path).stdin(Stdio::null()).output()?,
+        ScriptSource::Content(content) => {
+            let mut child = command.arg("-").stdin(Stdio::piped()).spawn()?;
+            // A write error means shellcheck exited early; its status tells why.
+            if let Some(mut stdin) = child.stdin.take() {
+                let _ = stdin.write_all(content.as_bytes());
+            }
+            child.wait_with_output()?
+        }
+    };
+    // 0: no findings, 1: findings; anything else is shellcheck's own failure.
+    if !matches!(output.status.code(), Some(0 | 1)) {
+        return Err(io::Error::other(format!(
+            "{} exited with {}: {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let report: Report = serde_json::from_slice(&output.stdout)
+        .map_err(|e| io::Error::other(format!("unreadable {} output: {}", program, e)))?;
+    Ok(report
+        .comments
+        .into_iter()
+        .map(|comment| Problem {
+            severity: comment.level,
+            code: Some(comment.code),
+            line: comment.line,
+            column: comment.column,
+            message: comment.message,
+        })
+        .collect())
+}
+
+/// What the built-in scanner is inside of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Open {
+    /// `'...'`
+    Single,
+    /// `$'...'`
+    AnsiC,
+    /// `"..."`
+    Double,
+    /// `` `...` ``
+    Backtick,
+    /// `$(...)`, or `$((...))` with the inner parenthesis as a [`Open::Paren`]
+    Subst,
+    /// `${...}`
+    Brace,
+    /// `(...)`
+    Paren,
+}
+
+impl Open {
+    fn describe(self) -> &'static str {
+        match self {
+            Self::Single => "single-quoted string is never closed",
+            Self::AnsiC => "`$'` string is never closed",
+            Self::Double => "double-quoted string is never closed",
+            Self::Backtick => "backtick command substitution is never closed",
+            Self::Subst => "`$(` is never closed",
+            Self::Brace => "`${` is never closed",
+            Self::Paren => "`(` is never closed",
+        }
+    }
+}
+
+/// Scans a script for constructs that are never closed: quotes, backticks, `$(`,
+/// `${`, `(` and here-documents. Reports the outermost one, where the breakage
+/// starts.
+fn scan(source: &str) -> Vec<Problem> {
+    let chars: Vec<char> = source.chars().collect();
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(
+            chars
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| **c == '\n')
+                .map(|(i, _)| i + 1),
+        )
+        .collect();
+    let position = |i: usize| {
+        let line = line_starts.partition_point(|&start| start <= i);
+        (line, i - line_starts[line - 1] + 1)
+    };
+    let problem = |i: usize, message: String| {
+        let (line, column) = position(i);
+        Problem {
+            severity: ShellcheckSeverity::Error,
+            code: None,
+            line,
+            column,
+            message,
+        }
+    };
+
+    let mut stack: Vec<(Open, usize)> = Vec::new();
+    // Here-documents whose bodies start after the current line: (delimiter, `<<-`, start).
+    let mut heredocs: Vec<(String, bool, usize)> = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let top = stack.last().map(|(open, _)| *open);
+        match top {
+            Some(Open::Single) => {
+                if c == '\'' {
+                    stack.pop();
+                }
+            }
+            Some(Open::AnsiC) => match c {
+                '\\' => i += 1,
+                '\'' => {
+                    stack.pop();
+                }
+                _ => {}
+            },
+            Some(Open::Double) => match (c, next) {
+                ('\\', _) => i += 1,
+                ('"', _) => {
+                    stack.pop();
+                }
+                ('`', _) => stack.push((Open::Backtick, i)),
+                ('$', Some('(')) => {
+                    stack.push((Open::Subst, i));
+                    i += 1;
+                }
+                ('$', Some('{')) => {
+                    stack.push((Open::Brace, i));
+                    i += 1;
+                }
+                _ => {}
+            },
+            Some(Open::Brace) => match (c, next) {
+                ('\\', _) => i += 1,
+                ('}', _) => {
+                    stack.pop();
+                }
+                ('"', _) => stack.push((Open::Double, i)),
+                // Within double quotes, `${x:-can't}` keeps its apostrophe.
+                ('\'', _) if !stack.iter().any(|(open, _)| *open == Open::Double) => {
+                    stack.push((Open::Single, i))
+                }
+                ('`', _) => stack.push((Open::Backtick, i)),
+                ('$', Some('(')) => {
+                    stack.push((Open::Subst, i));
+                    i += 1;
+                }
+                ('$', Some('{')) => {
+                    stack.push((Open::Brace, i));
+                    i += 1;
+                }
+                _ => {}
+            },
+            Some(Open::Backtick) => match (c, next) {
+                ('\\', _) => i += 1,
+                ('`', _) => {
+                    stack.pop();
+                }
+                ('\'', _) => stack.push((Open::Single, i)),
+                ('"', _) => stack.push((Open::Double, i)),
+                ('$', Some('(')) => {
+                    stack.push((Open::Subst, i));
+                    i += 1;
+                }
+                _ => {}
+            },
+            None | Some(Open::Subst) | Some(Open::Paren) => match (c, next) {
+                ('\\', _) => i += 1,
+                ('\'', _) => stack.push((Open::Single, i)),
+                ('"', _) => stack.push((Open::Double, i)),
+                ('`', _) => stack.push((Open::Backtick, i)),
+                ('$', Some('\'')) => {
+                    stack.push((Open::AnsiC, i));
+                    i += 1;
+                }
+                ('$', Some('(')) => {
+                    stack.push((Open::Subst, i));
+                    i += 1;
+                }
+                ('$', Some('{')) => {
+                    stack.push((Open::Brace, i));
+                    i += 1;
+                }
+                ('(', _) => stack.push((Open::Paren, i)),
+                // An unmatched `)` at the top level ends a `case` pattern.
+                (')', _) => {
+                    if top.is_some() {
+                        stack.pop();
+                    }
+                }
+                ('#', _) if i == 0 || is_word_break(chars[i - 1]) => {
+                    while i + 1 < chars.len() && chars[i + 1] != '\n' {
+                        i += 1;
+                    }
+                }
+                // A here-string (`<<<`) has no body.
+                ('<', Some('<')) if chars.get(i + 2) == Some(&'<') => i += 2,
+                ('<', Some('<')) => {
+                    let start = i;
+                    i += 2;
+                    let strip_tabs = chars.get(i) == Some(&'-');
+                    if strip_tabs {
+                        i += 1;
+                    }
+                    while matches!(chars.get(i), Some(' ' | '\t')) {
+                        i += 1;
+                    }
+                    let mut delimiter = String::new();
+                    while let Some(&c) = chars.get(i) {
+                        if c.is_whitespace() || ";&|<>()".contains(c) {
+                            break;
+                        }
+                        if !matches!(c, '\'' | '"' | '\\') {
+                            delimiter.push(c);
+                        }
+                        i += 1;
+                    }
+                    if !delimiter.is_empty() {
+                        heredocs.push((delimiter, strip_tabs, start));
+                    }
+                    continue;
+                }
+                ('\n', _) if !heredocs.is_empty() => {
+                    for (delimiter, strip_tabs, start) in heredocs.drain(..) {
+                        loop {
+                            if i + 1 >= chars.len() {
+                                return vec![problem(
+                                    start,
+                                    format!("here-document is never ended by `{}`", delimiter),
+                                )];
+                            }
+                            let end = chars[i + 1..]
+                                .iter()
+                                .position(|&c| c == '\n')
+                                .map_or(chars.len(), |n| i + 1 + n);
+                            let line: String = chars[i + 1..end].iter().collect();
+                            i = end;
+                            let line = if strip_tabs {
+                                line.trim_start_matches('\t')
+                            } else {
+                                &line
+                            };
+                            if line == delimiter {
+                                break;
+                            }
+                        }
+                    }
+                    continue;
+                }
+                _ => {}
+            },
+        }
+        i += 1;
+    }
+
+    match stack.first() {
+        Some(&(open, start)) => vec![problem(start, open.describe().to_string())],
+        None => Vec::new(),
+    }
+}
+
+/// Returns whether a `#` after `c` starts a comment.
+fn is_word_break(c: char) -> bool {
+    c.is_whitespace() || ";&|()".contains(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn messages(source: &str) -> Vec<(usize, usize, String)> {
+        scan(source)
+            .into_iter()
+            .map(|p| (p.line, p.column, p.message))
+            .collect()
+    }
+
+    #[test]
+    fn scan_accepts_well_formed_scripts() {
+        let script = r#"#!/bin/sh
+set -eu
+# it's only a comment
+echo "it's $(printf '%s' "nested \"quotes\"") and ${HOME:-can't}"
+echo $'ANSI \'quoted\'' `date` $((1 + (2 * 3)))
+case "$1" in
+  a) echo 'a';;
+  (b) echo b;;
+esac
+cat <<'EOF' > /etc/motd
+Don't "worry"
+EOF
+cat <<-END
+	it's tab-indented
+	END
+echo ${#1} done # trailing comment
+read -r a b <<<"$1 it's"
+"#;
+        assert_eq!(messages(script), []);
+    }
+
+    #[test]
+    fn scan_reports_where_the_unclosed_construct_starts() {
+        assert_eq!(
+            messages("echo ok\necho \"unterminated\necho 'more'\n"),
+            [(2, 6, "double-quoted string is never closed".to_string())]
+        );
+        assert_eq!(
+            messages("echo 'it\\'s'\n"),
+            [(1, 12, "single-quoted string is never closed".to_string())]
+        );
+        assert_eq!(messages("x=$(date\n"), [(1, 3, "`$(` is never closed".to_string())]);
+        assert_eq!(
+            messages("cat <<EOF\nbody\nEOF \n"),
+            [(1, 5, "here-document is never ended by `EOF`".to_string())]
+        );
+    }
+
+    #[test]
+    fn findings_show_severity_code_and_position() {
+        let finding = Problem {
+            severity: ShellcheckSeverity::Warning,
+            code: Some(2086),
+            line: 3,
+            column: 6,
+            message: "Double quote to prevent globbing and word splitting.".to_string(),
+        }
+        .at("provision 2 (setup)");
+        assert_eq!(
+            finding.to_string(),
+            "warning[SC2086] provision 2 (setup) line 3:6: Double quote to prevent \
+            globbing and word splitting."
+        );
+    }
+
+    #[test]
+    fn only_posix_family_shells_are_linted() {
+        assert_eq!(shell_name("/bin/sh"), Some("sh"));
+        assert_eq!(shell_name("/usr/bin/bash"), Some("bash"));
+        assert_eq!(shell_name("/usr/bin/python3"), None);
+    }
+
+    #[test]
+    fn fail_on_must_not_be_below_severity() {
+        let config = ShellcheckConfig {
+            severity: ShellcheckSeverity::Warning,
+            fail_on: Some(ShellcheckSeverity::Info),
+        };
+        assert!(config.validate().is_err());
+        assert!(ShellcheckConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn runs_shellcheck_with_the_script_on_stdin() {
+        let dir = tempfile::tempdir().unwrap();
+        let program = dir.path().join("shellcheck");
+        let args = dir.path().join("args");
+        std::fs::write(
+            &program,
+            format!(
+                "#!/bin/sh\necho \"$@\" > {}\ncat > /dev/null\n\
+                echo '{{\"comments\":[{{\"file\":\"-\",\"line\":2,\"endLine\":2,\
+                \"column\":6,\"endColumn\":8,\"level\":\"info\",\"code\":2086,\
+                \"message\":\"Double quote\",\"fix\":null}}]}}'\nexit 1\n",
+                args.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let problems = run_shellcheck(
+            program.to_str().unwrap(),
+            "bash",
+            &ScriptSource::Content("echo $1\n".to_string()),
+            ShellcheckSeverity::Style,
+        )
+        .unwrap();
+        assert_eq!(
+            problems,
+            [Problem {
+                severity: ShellcheckSeverity::Info,
+                code: Some(2086),
+                line: 2,
+                column: 6,
+                message: "Double quote".to_string(),
+            }]
+        );
+        assert_eq!(
+            std::fs::read_to_string(args).unwrap(),
+            "--format=json1 --shell=bash --severity=style -\n"
+        );
+
+        let missing = dir.path().join("missing");
+        let err = run_shellcheck(
+            missing.to_str().unwrap(),
+            "sh",
+            &ScriptSource::Content("true\n".to_string()),
+            ShellcheckSeverity::Style,
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,252 @@
+//! Point-in-time builds from snapshot.debian.org.
+//!
+//! A profile's `snapshot` timestamp pins the bootstrap to the Debian archive as it was
+//! at that moment: before the bootstrap, [`Bootstrap::apply_snapshot`] rewrites each
+//! Debian archive mirror (`deb.debian.org`, `ftp.<cc>.debian.org`,
+//! `security.debian.org`, …) to its `https://snapshot.debian.org/archive/<archive>/<stamp>/`
+//! counterpart, or uses the snapshot of the main archive when no mirror is set. The
+//! backends write these mirrors to the rootfs's `sources.list`, so apt inside the
+//! rootfs installs from the same snapshot.
+//!
+//! Snapshot `Release` files are past their `Valid-Until`, so apt's check is turned off:
+//! through mmdebstrap's `--aptopt`, which mmdebstrap also keeps in the rootfs, and for
+//! debootstrap, which does not check it itself, by [`write_apt_conf`] after the
+//! bootstrap.
+//!
+//! [`Bootstrap::apply_snapshot`]: crate::config::Bootstrap::apply_snapshot
+
+use std::fs;
+use std::sync::Arc;
+
+use camino::Utf8Path;
+use tracing::info;
+use url::Url;
+
+use crate::error::RsdebstrapError;
+use crate::executor::{CommandExecutor, CommandSpec};
+use crate::phase::assemble::dir_exists;
+use crate::privilege::PrivilegeMethod;
+
+/// Host serving the snapshots.
+pub const SNAPSHOT_HOST: &str = "snapshot.debian.org";
+
+/// apt option disabling the `Valid-Until` check of `Release` files.
+pub const CHECK_VALID_UNTIL: &str = "Acquire::Check-Valid-Until \"false\";";
+
+/// apt configuration file [`write_apt_conf`] writes, relative to the rootfs.
+pub const APT_CONF: &str = "etc/apt/apt.conf.d/80rsdebstrap-snapshot";
+
+/// Oldest snapshot: the service's archive starts in March 2005.
+const FIRST_YEAR: u32 = 2005;
+
+/// Returns the snapshot.debian.org timestamp (`20240601T000000Z`) of `snapshot`.
+///
+/// Accepts a UTC time as `2024-06-01T00:00:00Z` or `20240601T000000Z`, or a date
+/// (`2024-06-01`, taken as midnight UTC).
+///
+/// # Errors
+///
+/// Returns [`RsdebstrapError::Validation`] for anything else.
+pub fn stamp(snapshot: &str) -> Result<String, RsdebstrapError> {
+    let invalid = || {
+        RsdebstrapError::Validation(format!(
+            "snapshot '{}' must be a UTC time such as 2024-06-01T00:00:00Z or a date \
+            such as 2024-06-01",
+            snapshot
+        ))
+    };
+    // Each accepted form, with `#` for a digit.
+    let digits: String = if matches_pattern(snapshot, "####-##-##T##:##:##Z")
+        || matches_pattern(snapshot, "########T######Z")
+    {
+        snapshot.to_string()
+    } else if matches_pattern(snapshot, "####-##-##") {
+        format!("{}T000000Z", snapshot)
+    } else {
+        return Err(invalid());
+    }
+    .chars()
+    .filter(char::is_ascii_digit)
+    .collect();
+    let number = |range: std::ops::Range<usize>| digits[range].parse::<u32>().unwrap_or(0);
+    let (year, month, day) = (number(0..4), number(4..6), number(6..8));
+    let (hour, minute, second) = (number(8..10), number(10..12), number(12..14));
+    if year < FIRST_YEAR
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return Err(invalid());
+    }
+    let (date, time) = digits.split_at(8);
+    Ok(format!("{}T{}Z", date, time))
+}
+
+/// Returns whether `text` has a digit wherever `pattern` has `#`, and the same
+/// character everywhere else.
+fn matches_pattern(text: &str, pattern: &str) -> bool {
+    text.len() == pattern.len()
+        && text.bytes().zip(pattern.bytes()).all(|(t, p)| match p {
+            b'#' => t.is_ascii_digit(),
+            _ => t == p,
+        })
+}
+
+/// Returns the URL of `archive` (`debian`, `debian-security`, …) at `stamp`.
+pub fn archive_url(scheme: &str, archive: &str, stamp: &str) -> String {
+    format!("{}://{}/archive/{}/{}/", scheme, SNAPSHOT_HOST, archive, stamp)
+}
+
+/// Returns the snapshot of the Debian archive `url` points to, keeping its scheme, or
+/// `None` for any other repository.
+fn rewrite_url(url: &str, stamp: &str) -> Option<String> {
+    let parsed = Url::parse(url).ok()?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return None;
+    }
+    let host = parsed.host_str()?;
+    let path = parsed.path().trim_matches('/');
+    let archive = match (host, path) {
+        ("security.debian.org", "" | "debian-security") => "debian-security",
+        (_, "debian" | "debian-security" | "debian-ports" | "debian-debug")
+            if is_debian_mirror(host) =>
+        {
+            path
+        }
+        _ => return None,
+    };
+    Some(archive_url(parsed.scheme(), archive, stamp))
+}
+
+/// Returns whether `host` is one of the Debian archive's own mirrors.
+fn is_debian_mirror(host: &str) -> bool {
+    matches!(host, "deb.debian.org" | "ftp.debian.org" | "httpredir.debian.org")
+        || host
+            .strip_prefix("ftp.")
+            .and_then(|rest| rest.strip_suffix(".debian.org"))
+            .is_some_and(|cc| cc.len() == 2 && cc.bytes().all(|b| b.is_ascii_alphabetic()))
+}
+
+/// Rewrites the Debian archive URLs in a mirror entry (a URL or an mmdebstrap
+/// `sources.list` line) to their snapshot at `stamp`. Returns `None` when the entry
+/// names no Debian archive and stays as it is.
+pub fn rewrite_mirror(entry: &str, stamp: &str) -> Option<String> {
+    let mut rewritten = false;
+    let words: Vec<String> = entry
+        .split(' ')
+        .map(|word| match rewrite_url(word, stamp) {
+            Some(url) => {
+                rewritten = true;
+                url
+            }
+            None => word.to_string(),
+        })
+        .collect();
+    rewritten.then(|| words.join(" "))
+}
+
+/// Writes [`CHECK_VALID_UNTIL`] to [`APT_CONF`] in `rootfs`, for apt inside a rootfs
+/// whose backend had no apt options to keep.
+///
+/// Goes through a host temporary file copied with `privilege`, since the rootfs
+/// belongs to root.
+///
+/// # Errors
+///
+/// Returns an error if `etc/apt/apt.conf.d` is a symlink, or the file cannot be
+/// written.
+pub(crate) fn write_apt_conf(
+    rootfs: &Utf8Path,
+    executor: &Arc<dyn CommandExecutor>,
+    privilege: Option<PrivilegeMethod>,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let path = rootfs.join(APT_CONF);
+    if dry_run {
+        info!("would write {}", path);
+        return Ok(());
+    }
+    let conf_dir = Utf8Path::new(APT_CONF)
+        .parent()
+        .unwrap_or(Utf8Path::new(""));
+    if !dir_exists(rootfs, conf_dir)? {
+        return Err(RsdebstrapError::Validation(format!(
+            "{} has no /{}; cannot disable apt's Valid-Until check for the snapshot",
+            rootfs, conf_dir
+        ))
+        .into());
+    }
+    let temp = tempfile::NamedTempFile::new().map_err(|e| {
+        RsdebstrapError::io("failed to create temporary file for the apt configuration", e)
+    })?;
+    fs::write(temp.path(), format!("{}\n", CHECK_VALID_UNTIL)).map_err(|e| {
+        RsdebstrapError::io(
+            format!("failed to write temporary apt configuration: {}", temp.path().display()),
+            e,
+        )
+    })?;
+    let spec = CommandSpec::new(
+        "install",
+        vec![
+            "-m".to_string(),
+            "0644".to_string(),
+            temp.path().to_string_lossy().to_string(),
+            path.to_string(),
+        ],
+    )
+    .with_privilege(privilege);
+    executor.execute_checked(&spec)?;
+    info!("disabled apt's Valid-Until check in {}", path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stamp_accepts_times_and_dates() {
+        assert_eq!(stamp("2024-06-01T00:00:00Z").unwrap(), "20240601T000000Z");
+        assert_eq!(stamp("20240601T123456Z").unwrap(), "20240601T123456Z");
+        assert_eq!(stamp("2024-06-01").unwrap(), "20240601T000000Z");
+        for bad in [
+            "2024-06-01T00:00:00",
+            "2024-06-01T00:00:00+02:00",
+            "2024-13-01",
+            "2004-06-01",
+            "2024-6-1",
+            "yesterday",
+            "",
+        ] {
+            assert!(stamp(bad).is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn rewrite_mirror_pins_debian_archives_only() {
+        let stamp = "20240601T000000Z";
+        assert_eq!(
+            rewrite_mirror("https://deb.debian.org/debian", stamp).as_deref(),
+            Some("https://snapshot.debian.org/archive/debian/20240601T000000Z/")
+        );
+        assert_eq!(
+            rewrite_mirror("http://ftp.jp.debian.org/debian/", stamp).as_deref(),
+            Some("http://snapshot.debian.org/archive/debian/20240601T000000Z/")
+        );
+        assert_eq!(
+            rewrite_mirror(
+                "deb http://security.debian.org/debian-security trixie-security main",
+                stamp
+            )
+            .as_deref(),
+            Some(
+                "deb http://snapshot.debian.org/archive/debian-security/20240601T000000Z/ \
+                trixie-security main"
+            )
+        );
+        assert_eq!(rewrite_mirror("https://apt.example.com/debian", stamp), None);
+        assert_eq!(rewrite_mirror("https://deb.debian.org/other", stamp), None);
+    }
+}
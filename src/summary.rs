@@ -0,0 +1,282 @@
+//! End-of-run summary of `rsdebstrap apply`.
+//!
+//! When a build ends, successfully or not, the [`Runner`](crate::Runner) logs a
+//! [`BuildSummary`]: how long the build, the bootstrap, each phase and each task took
+//! (as recorded by [`BuildMetrics`](crate::metrics::BuildMetrics)), the disk space the
+//! rootfs uses and how much of it the run added, and the size of each artifact in the
+//! profile's `dir`. `apply --summary <path>` also writes it as JSON.
+
+use std::fs;
+use std::time::Duration;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Serialize;
+
+use crate::disk_space::format_bytes;
+use crate::error::RsdebstrapError;
+
+/// Summary of one build. See the [module documentation](self).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BuildSummary {
+    /// Whether the build succeeded.
+    pub success: bool,
+    /// Duration of the whole build, in seconds.
+    pub duration_secs: f64,
+    /// Each phase that ran, in order, the bootstrap included.
+    pub phases: Vec<PhaseTiming>,
+    /// The bootstrap and each pipeline task that ran, in order.
+    pub tasks: Vec<TaskTiming>,
+    /// Disk usage of the rootfs directory, unless the bootstrap writes an archive or
+    /// image, or the run was a dry run.
+    pub rootfs: Option<RootfsUsage>,
+    /// The files in the profile's `dir` when the build ended.
+    pub artifacts: Vec<Artifact>,
+}
+
+/// Duration of a phase: from the start of its first task to the end of its last one.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PhaseTiming {
+    /// `bootstrap`, `prepare`, `provision`, `assemble` or `verify`.
+    pub phase: &'static str,
+    pub duration_secs: f64,
+    /// False if the build failed during the phase.
+    pub success: bool,
+}
+
+/// Duration of the bootstrap or of a pipeline task.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TaskTiming {
+    /// `bootstrap`, `prepare`, `provision`, `assemble` or `verify`.
+    pub phase: &'static str,
+    /// 1-based position in the phase (0 for the bootstrap).
+    pub number: usize,
+    pub name: String,
+    pub duration_secs: f64,
+    /// False if the build failed while the task ran.
+    pub success: bool,
+}
+
+/// Disk space used by the rootfs directory.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RootfsUsage {
+    pub path: Utf8PathBuf,
+    /// Disk space used when the build ended.
+    pub bytes: u64,
+    /// Disk space the build added: the growth since it started (0 if it shrank).
+    pub added_bytes: u64,
+}
+
+/// A file in the profile's `dir`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Artifact {
+    /// Path relative to `dir`.
+    pub path: Utf8PathBuf,
+    pub bytes: u64,
+}
+
+impl BuildSummary {
+    /// Renders the summary as the lines logged at the end of the build.
+    pub fn lines(&self) -> Vec<String> {
+        let outcome = if self.success { "succeeded" } else { "failed" };
+        let mut lines = vec![format!(
+            "build {} in {}",
+            outcome,
+            format_duration(self.duration_secs)
+        )];
+        for phase in &self.phases {
+            let failed = if phase.success { "" } else { " (failed)" };
+            lines.push(format!(
+                "  {}: {}{}",
+                phase.phase,
+                format_duration(phase.duration_secs),
+                failed
+            ));
+            for task in self.tasks.iter().filter(|t| t.phase == phase.phase) {
+                let failed = if task.success { "" } else { " (failed)" };
+                let name = if task.number == 0 {
+                    task.name.clone()
+                } else {
+                    format!("{}. {}", task.number, task.name)
+                };
+                lines.push(format!(
+                    "    {}: {}{}",
+                    name,
+                    format_duration(task.duration_secs),
+                    failed
+                ));
+            }
+        }
+        if let Some(rootfs) = &self.rootfs {
+            lines.push(format!(
+                "  rootfs {}: {} ({} added)",
+                rootfs.path,
+                format_bytes(rootfs.bytes),
+                format_bytes(rootfs.added_bytes)
+            ));
+        }
+        for artifact in &self.artifacts {
+            lines.push(format!("  artifact {}: {}", artifact.path, format_bytes(artifact.bytes)));
+        }
+        lines
+    }
+
+    /// Writes the summary to `path` as JSON.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RsdebstrapError::Io`] if the file cannot be written.
+    pub fn write(&self, path: &Utf8Path) -> Result<(), RsdebstrapError> {
+        let json = serde_json::to_string_pretty(self).expect("the summary serializes");
+        fs::write(path, format!("{}\n", json))
+            .map_err(|e| RsdebstrapError::io(format!("failed to write {}", path), e))
+    }
+}
+
+/// Returns the regular files directly in `dir` (hidden ones excepted), sorted by name.
+/// An unreadable `dir` has none.
+pub fn artifacts(dir: &Utf8Path) -> Vec<Artifact> {
+    let Ok(entries) = dir.read_dir_utf8() else {
+        return Vec::new();
+    };
+    let mut artifacts: Vec<Artifact> = entries
+        .flatten()
+        .filter(|entry| !entry.file_name().starts_with('.'))
+        .filter_map(|entry| {
+            let meta = entry.path().symlink_metadata().ok()?;
+            meta.is_file().then(|| Artifact {
+                path: Utf8PathBuf::from(entry.file_name()),
+                bytes: meta.len(),
+            })
+        })
+        .collect();
+    artifacts.sort_by(|a, b| a.path.cmp(&b.path));
+    artifacts
+}
+
+/// Formats seconds for the summary, e.g. `4.2s` or `3m 07.5s`.
+fn format_duration(secs: f64) -> String {
+    let duration = Duration::from_secs_f64(secs.max(0.0));
+    let minutes = duration.as_secs() / 60;
+    let secs = duration.as_secs_f64() - (minutes * 60) as f64;
+    if minutes == 0 {
+        format!("{:.1}s", secs)
+    } else if minutes < 60 {
+        format!("{}m {:04.1}s", minutes, secs)
+    } else {
+        format!("{}h {:02}m {:04.1}s", minutes / 60, minutes % 60, secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary() -> BuildSummary {
+        BuildSummary {
+            success: false,
+            duration_secs: 187.5,
+            phases: vec![
+                PhaseTiming {
+                    phase: "bootstrap",
+                    duration_secs: 90.0,
+                    success: true,
+                },
+                PhaseTiming {
+                    phase: "provision",
+                    duration_secs: 4.3,
+                    success: false,
+                },
+            ],
+            tasks: vec![
+                TaskTiming {
+                    phase: "bootstrap",
+                    number: 0,
+                    name: "mmdebstrap".to_string(),
+                    duration_secs: 90.0,
+                    success: true,
+                },
+                TaskTiming {
+                    phase: "provision",
+                    number: 1,
+                    name: "shell:setup.sh".to_string(),
+                    duration_secs: 4.3,
+                    success: false,
+                },
+            ],
+            rootfs: Some(RootfsUsage {
+                path: Utf8PathBuf::from("/out/rootfs"),
+                bytes: 3 << 20,
+                added_bytes: 1 << 20,
+            }),
+            artifacts: vec![Artifact {
+                path: Utf8PathBuf::from("SHA256SUMS"),
+                bytes: 100,
+            }],
+        }
+    }
+
+    #[test]
+    fn lines_nest_tasks_under_phases() {
+        assert_eq!(
+            summary().lines(),
+            [
+                "build failed in 3m 07.5s",
+                "  bootstrap: 1m 30.0s",
+                "    mmdebstrap: 1m 30.0s",
+                "  provision: 4.3s (failed)",
+                "    1. shell:setup.sh: 4.3s (failed)",
+                "  rootfs /out/rootfs: 3.0 MiB (1.0 MiB added)",
+                "  artifact SHA256SUMS: 100 B",
+            ]
+        );
+    }
+
+    #[test]
+    fn write_saves_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8Path::from_path(dir.path())
+            .unwrap()
+            .join("summary.json");
+        summary().write(&path).unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(json["success"], false);
+        assert_eq!(json["tasks"][1]["name"], "shell:setup.sh");
+        assert_eq!(json["rootfs"]["added_bytes"], 1 << 20);
+        assert_eq!(json["artifacts"][0]["bytes"], 100);
+    }
+
+    #[test]
+    fn artifacts_lists_visible_files_in_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        fs::write(dir.join("rootfs.tar.zst"), "tarball").unwrap();
+        fs::write(dir.join("SHA256SUMS"), "sums").unwrap();
+        fs::write(dir.join(".rsdebstrap-dir"), "").unwrap();
+        fs::create_dir(dir.join("rootfs")).unwrap();
+
+        let artifacts = artifacts(dir);
+
+        assert_eq!(
+            artifacts,
+            [
+                Artifact {
+                    path: Utf8PathBuf::from("SHA256SUMS"),
+                    bytes: 4,
+                },
+                Artifact {
+                    path: Utf8PathBuf::from("rootfs.tar.zst"),
+                    bytes: 7,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn format_duration_adds_minutes_and_hours() {
+        assert_eq!(format_duration(0.04), "0.0s");
+        assert_eq!(format_duration(61.0), "1m 01.0s");
+        assert_eq!(format_duration(3723.5), "1h 02m 03.5s");
+    }
+}
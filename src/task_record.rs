@@ -0,0 +1,365 @@
+//! Record of the provision task scripts an `apply` ran.
+//!
+//! After a successful pipeline run, `apply` stores the SHA-256 digest of each
+//! provision task's script or recipe it ran in a file next to the rootfs
+//! (`<rootfs>.rsdebstrap-tasks`), keyed by the task's position in the profile.
+//! `diff` compares the profile's current scripts against it to find tasks changed
+//! since then. The file lives outside the rootfs so it never ends up in the image.
+//!
+//! Each line is `<number>\t<sha256>\t<name>`; the name is informational only.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::download;
+use crate::error::RsdebstrapError;
+use crate::phase::{
+    CookbookTask, OverlayTask, PluginTask, ProvisionTask, PuppetTask, ScriptSource, ShellTask,
+    WasmTask, walk_host_tree,
+};
+
+/// Suffix appended to the rootfs path to name its record file.
+const RECORD_SUFFIX: &str = ".rsdebstrap-tasks";
+
+/// A task as recorded by the last run that ran it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedTask {
+    /// SHA-256 digest of the task's script or recipe.
+    pub digest: String,
+    /// The task's display name when it ran.
+    pub name: String,
+}
+
+/// Recorded provision tasks, keyed by their 1-based position in the profile.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TaskRecord {
+    /// Recorded tasks by position.
+    pub tasks: BTreeMap<usize, RecordedTask>,
+}
+
+impl TaskRecord {
+    /// Returns the record file path for `rootfs`.
+    pub fn path(rootfs: &Utf8Path) -> Utf8PathBuf {
+        let mut path = rootfs.as_str().trim_end_matches('/').to_string();
+        path.push_str(RECORD_SUFFIX);
+        Utf8PathBuf::from(path)
+    }
+
+    /// Loads the record for `rootfs`; a missing file is an empty record.
+    ///
+    /// Malformed lines are warned about and skipped.
+    pub fn load(rootfs: &Utf8Path) -> Result<Self, RsdebstrapError> {
+        let path = Self::path(rootfs);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(RsdebstrapError::io(format!("failed to read {}", path), e)),
+        };
+        let mut record = Self::default();
+        for line in content.lines().filter(|l| !l.trim().is_empty()) {
+            let mut fields = line.splitn(3, '\t');
+            match (fields.next().map(str::parse::<usize>), fields.next(), fields.next()) {
+                (Some(Ok(number)), Some(digest), Some(name)) if number > 0 => {
+                    record.tasks.insert(
+                        number,
+                        RecordedTask {
+                            digest: digest.to_string(),
+                            name: name.to_string(),
+                        },
+                    );
+                }
+                _ => warn!("ignoring malformed line in {}: {:?}", path, line),
+            }
+        }
+        Ok(record)
+    }
+
+    /// Records `task` as run at position `number`.
+    pub fn insert(&mut self, number: usize, task: &ProvisionTask) -> Result<(), RsdebstrapError> {
+        self.tasks.insert(
+            number,
+            RecordedTask {
+                digest: task_digest(task)?,
+                name: task.name().into_owned(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Drops recorded tasks past the end of a provision list of `len` tasks.
+    pub fn truncate(&mut self, len: usize) {
+        self.tasks.retain(|&number, _| number <= len);
+    }
+
+    /// Writes the record for `rootfs`, replacing the file atomically.
+    pub fn save(&self, rootfs: &Utf8Path) -> Result<(), RsdebstrapError> {
+        let path = Self::path(rootfs);
+        let dir = path.parent().unwrap_or(Utf8Path::new("."));
+        let mut content = String::new();
+        for (number, task) in &self.tasks {
+            content.push_str(&format!("{}\t{}\t{}\n", number, task.digest, task.name));
+        }
+        let mut file = tempfile::NamedTempFile::new_in(dir)
+            .map_err(|e| RsdebstrapError::io(format!("failed to create a file in {}", dir), e))?;
+        file.write_all(content.as_bytes())
+            .map_err(|e| RsdebstrapError::io(format!("failed to write {}", path), e))?;
+        file.persist(&path)
+            .map_err(|e| RsdebstrapError::io(format!("failed to write {}", path), e.error))?;
+        Ok(())
+    }
+}
+
+/// Returns the lowercase hexadecimal SHA-256 digest identifying what `task` runs.
+///
+/// This is the [`script_digest`] of its source, except for a mitamae task with further
+/// `recipes` or `attributes`: its digest then covers every recipe and the node JSON.
+/// Likewise a shell task's digest covers its `shell_args`, `args` and `stdin`, if any.
+/// Cookbook and puppet task digests cover their settings and every file in their directory,
+/// a debconf task's digest covers the selections it feeds to `debconf-set-selections`, and
+/// ssh and kernel task digests cover the script they generate. An overlay task's digest
+/// covers its settings and its tarball or every file in its directory; an rsync task's
+/// covers its settings only, since rsync itself finds the changed files. A plugin task's digest
+/// covers its config and the plugin executable, a wasm task's its config and module.
+pub fn task_digest(task: &ProvisionTask) -> Result<String, RsdebstrapError> {
+    let mitamae = match task {
+        ProvisionTask::Shell(shell) => return shell_digest(shell),
+        ProvisionTask::Mitamae(mitamae) => mitamae,
+        ProvisionTask::Cookbook(cookbook) => return cookbook_digest(cookbook),
+        ProvisionTask::Puppet(puppet) => return puppet_digest(puppet),
+        ProvisionTask::Debconf(debconf) => {
+            return Ok(format!("{:x}", Sha256::digest(debconf.input()?)));
+        }
+        ProvisionTask::Ssh(ssh) => return Ok(format!("{:x}", Sha256::digest(ssh.script()))),
+        ProvisionTask::Kernel(kernel) => {
+            return Ok(format!("{:x}", Sha256::digest(kernel.script())));
+        }
+        ProvisionTask::Overlay(overlay) => return overlay_digest(overlay),
+        ProvisionTask::Rsync(rsync) => {
+            let settings = format!(
+                "{}\n{}\n{:?}\n{}\n{:?}\n",
+                rsync.host(),
+                rsync.path(),
+                rsync.direction(),
+                rsync.delete(),
+                rsync.exclude()
+            );
+            return Ok(format!("{:x}", Sha256::digest(settings)));
+        }
+        ProvisionTask::Plugin(plugin) => return plugin_digest(plugin),
+        ProvisionTask::Wasm(wasm) => return wasm_digest(wasm),
+    };
+    let digest = script_digest(mitamae.source())?;
+    if mitamae.recipes().is_empty() && mitamae.attributes().is_empty() {
+        return Ok(digest);
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}\n", digest));
+    for recipe in mitamae.recipes() {
+        hasher.update(format!("{}\n", download::sha256_file(recipe)?));
+    }
+    hasher.update(mitamae.node_json());
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Returns the [`script_digest`] of a shell task's script, covering its `shell_args`,
+/// `args` and `stdin` as well when it has any.
+fn shell_digest(task: &ShellTask) -> Result<String, RsdebstrapError> {
+    let digest = script_digest(task.source())?;
+    if task.shell_args().is_empty() && task.args().is_empty() && task.stdin().is_none() {
+        return Ok(digest);
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(format!(
+        "{}\n{:?}\n{:?}\n{:?}\n",
+        digest,
+        task.shell_args(),
+        task.args(),
+        task.stdin()
+    ));
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Returns the digest of a cookbook task's runner, run list, Bundler settings and tree.
+fn cookbook_digest(task: &CookbookTask) -> Result<String, RsdebstrapError> {
+    let settings = format!("{:?}\n{:?}\n{:?}\n", task.runner(), task.run_list(), task.bundler());
+    tree_digest(&settings, task.dir(), "cookbook")
+}
+
+/// Returns the digest of a puppet task's settings and tree.
+fn puppet_digest(task: &PuppetTask) -> Result<String, RsdebstrapError> {
+    let settings = format!(
+        "{}\n{:?}\n{:?}\n{}\n",
+        task.manifest(),
+        task.modulepath(),
+        task.hiera_config(),
+        task.puppet()
+    );
+    tree_digest(&settings, task.dir(), "puppet")
+}
+
+/// Returns the digest of an overlay task's settings and tarball or tree.
+fn overlay_digest(task: &OverlayTask) -> Result<String, RsdebstrapError> {
+    let settings =
+        format!("{}\n{}\n{:?}\n", task.destination(), task.preserve_ownership(), task.allow());
+    if !task.is_archive() {
+        return tree_digest(&settings, task.source(), "overlay");
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(settings);
+    hasher.update(download::sha256_file(task.source())?);
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Returns the digest of a plugin task's name, config and executable.
+fn plugin_digest(task: &PluginTask) -> Result<String, RsdebstrapError> {
+    let mut hasher = Sha256::new();
+    hasher.update(format!(
+        "{}\n{}\n{}\n",
+        task.plugin(),
+        serde_json::Value::Object(task.config().clone()),
+        download::sha256_file(&task.executable()?)?
+    ));
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Returns the digest of a wasm task's config and module.
+fn wasm_digest(task: &WasmTask) -> Result<String, RsdebstrapError> {
+    let mut hasher = Sha256::new();
+    hasher.update(format!(
+        "{}\n{}\n",
+        serde_json::Value::Object(task.config().clone()),
+        download::sha256_file(task.module())?
+    ));
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Returns the digest of `settings` followed by every path and file digest below `dir`.
+fn tree_digest(settings: &str, dir: &Utf8Path, label: &str) -> Result<String, RsdebstrapError> {
+    let mut hasher = Sha256::new();
+    hasher.update(settings);
+    for relative in walk_host_tree(dir, label)? {
+        let path = dir.join(&relative);
+        if path.is_file() {
+            hasher.update(format!("{}\t{}\n", relative, download::sha256_file(&path)?));
+        } else {
+            hasher.update(format!("{}/\n", relative));
+        }
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Returns the lowercase hexadecimal SHA-256 digest of a script source's contents.
+pub fn script_digest(source: &ScriptSource) -> Result<String, RsdebstrapError> {
+    match source {
+        ScriptSource::Script(path) => download::sha256_file(path),
+        ScriptSource::Content(content) => Ok(format!("{:x}", Sha256::digest(content.as_bytes()))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::phase::provision::{DebconfSelection, DebconfType};
+    use crate::phase::{DebconfTask, MitamaeTask, ShellTask};
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let rootfs = Utf8Path::from_path(dir.path()).unwrap().join("rootfs");
+        let task = ProvisionTask::Shell(ShellTask::new(ScriptSource::Content("echo hi".into())));
+
+        let mut record = TaskRecord::default();
+        record.insert(2, &task).unwrap();
+        record.save(&rootfs).unwrap();
+
+        assert!(dir.path().join("rootfs.rsdebstrap-tasks").is_file());
+        let loaded = TaskRecord::load(&rootfs).unwrap();
+        assert_eq!(loaded, record);
+        assert_eq!(loaded.tasks[&2].name, "shell:<inline>");
+        assert_eq!(
+            loaded.tasks[&2].digest,
+            script_digest(&ScriptSource::Content("echo hi".into())).unwrap()
+        );
+    }
+
+    #[test]
+    fn load_skips_malformed_lines_and_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let rootfs = Utf8Path::from_path(dir.path()).unwrap().join("rootfs");
+        assert_eq!(TaskRecord::load(&rootfs).unwrap(), TaskRecord::default());
+
+        fs::write(TaskRecord::path(&rootfs), "garbage\n0\tabc\tzero\n1\tabc\tsetup\n").unwrap();
+        let loaded = TaskRecord::load(&rootfs).unwrap();
+        assert_eq!(loaded.tasks.len(), 1);
+        assert_eq!(loaded.tasks[&1].name, "setup");
+    }
+
+    #[test]
+    fn mitamae_digest_covers_recipes_and_attributes() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        fs::write(dir.join("base.rb"), "package 'vim'").unwrap();
+        fs::write(dir.join("web.rb"), "package 'nginx'").unwrap();
+        let single = MitamaeTask::new(ScriptSource::Script(dir.join("base.rb")), "mitamae".into());
+        let multi = single.clone().with_recipes([dir.join("web.rb")]);
+        let digest = |task: &MitamaeTask| task_digest(&ProvisionTask::Mitamae(task.clone()));
+
+        assert_eq!(digest(&single).unwrap(), script_digest(single.source()).unwrap());
+        let before = digest(&multi).unwrap();
+        assert_ne!(before, digest(&single).unwrap());
+
+        fs::write(dir.join("web.rb"), "package 'apache2'").unwrap();
+        assert_ne!(digest(&multi).unwrap(), before);
+
+        let attributes = [("port".to_string(), serde_json::json!(80))]
+            .into_iter()
+            .collect();
+        assert_ne!(
+            digest(&multi.clone().with_attributes(attributes)).unwrap(),
+            digest(&multi).unwrap()
+        );
+    }
+
+    #[test]
+    fn shell_digest_covers_args_and_stdin() {
+        let task = ShellTask::new(ScriptSource::Content("echo \"$1\"".into()));
+        let digest = |task: &ShellTask| task_digest(&ProvisionTask::Shell(task.clone())).unwrap();
+
+        assert_eq!(digest(&task), script_digest(task.source()).unwrap());
+        let with_args = task.clone().with_args(["a"]);
+        assert_ne!(digest(&with_args), digest(&task));
+        assert_ne!(digest(&with_args), digest(&task.clone().with_shell_args(["a"])));
+        let with_stdin = task.clone().with_stdin("a\n");
+        assert_ne!(digest(&with_stdin), digest(&task));
+        assert_ne!(digest(&with_stdin), digest(&task.clone().with_stdin("b\n")));
+    }
+
+    #[test]
+    fn debconf_digest_covers_selections() {
+        let digest = |value: &str| {
+            let selection = DebconfSelection::new(DebconfType::Select, value);
+            let task = DebconfTask::new().with_selection("tzdata/Areas", selection);
+            task_digest(&ProvisionTask::Debconf(task)).unwrap()
+        };
+
+        assert_eq!(digest("Etc"), digest("Etc"));
+        assert_ne!(digest("Etc"), digest("Europe"));
+    }
+
+    #[test]
+    fn truncate_drops_tasks_past_the_end() {
+        let task = ProvisionTask::Shell(ShellTask::new(ScriptSource::Content("true".into())));
+        let mut record = TaskRecord::default();
+        record.insert(1, &task).unwrap();
+        record.insert(3, &task).unwrap();
+
+        record.truncate(2);
+
+        assert_eq!(record.tasks.keys().copied().collect::<Vec<_>>(), [1]);
+    }
+}
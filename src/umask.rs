@@ -0,0 +1,138 @@
+//! The profile's `defaults.umask`.
+//!
+//! While a build runs, [`UmaskGuard`] sets the process umask, which every command
+//! rsdebstrap runs inherits (sudo keeps the stricter of its own and the caller's), so
+//! files the bootstrap, the tasks and the assemble steps create are masked with it.
+//! Files rsdebstrap stages with an explicit mode (scripts, recipes, binaries) are
+//! masked through [`mask`] as well, since `chmod` ignores the umask.
+//!
+//! The umask belongs to the whole process, so a build that sets one runs alone:
+//! under `serve --max-builds`, it waits for the builds already running and the others
+//! wait for it.
+
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use rustix::fs::Mode;
+use tracing::info;
+
+use crate::output_dir::FileMode;
+
+/// No umask configured: [`mask`] leaves modes unchanged.
+const UNSET: u32 = u32::MAX;
+
+/// The umask the running build set, or [`UNSET`].
+static CURRENT: AtomicU32 = AtomicU32::new(UNSET);
+
+/// Held shared by builds without a umask and exclusively by a build setting one.
+static BUILDS: RwLock<()> = RwLock::new(());
+
+/// Keeps a build's umask, if it sets one, until dropped, then restores the previous
+/// one.
+#[derive(Debug)]
+pub(crate) struct UmaskGuard {
+    /// The umask to restore; `None` if the build sets none.
+    previous: Option<Mode>,
+    /// [`BUILDS`] held by a build without a umask.
+    _shared: Option<RwLockReadGuard<'static, ()>>,
+    /// [`BUILDS`] held by a build with one, released after the umask is restored.
+    _exclusive: Option<RwLockWriteGuard<'static, ()>>,
+}
+
+impl UmaskGuard {
+    /// Sets the process umask to `umask`, once no other build runs, or only registers
+    /// a build that leaves it alone.
+    pub(crate) fn enter(umask: Option<FileMode>) -> Self {
+        let Some(umask) = umask else {
+            let lock = BUILDS.read().unwrap_or_else(PoisonError::into_inner);
+            return Self {
+                previous: None,
+                _shared: Some(lock),
+                _exclusive: None,
+            };
+        };
+        let lock = BUILDS.try_write().or_else(|_| {
+            info!("waiting for the other builds to finish to set defaults.umask {}", umask);
+            BUILDS.write()
+        });
+        let lock = lock.unwrap_or_else(PoisonError::into_inner);
+        let previous = rustix::process::umask(Mode::from_raw_mode(umask.0));
+        CURRENT.store(umask.0, Ordering::Relaxed);
+        Self {
+            previous: Some(previous),
+            _shared: None,
+            _exclusive: Some(lock),
+        }
+    }
+}
+
+impl Drop for UmaskGuard {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous {
+            rustix::process::umask(previous);
+            CURRENT.store(UNSET, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Returns `mode` with the running build's umask bits cleared.
+pub(crate) fn mask(mode: u32) -> u32 {
+    masked(mode, CURRENT.load(Ordering::Relaxed))
+}
+
+fn masked(mode: u32, umask: u32) -> u32 {
+    if umask == UNSET { mode } else { mode & !umask }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    /// Reads the process umask without changing it, even for a moment.
+    fn process_umask() -> u32 {
+        let status = std::fs::read_to_string("/proc/self/status").unwrap();
+        let line = status
+            .lines()
+            .find_map(|l| l.strip_prefix("Umask:"))
+            .unwrap();
+        u32::from_str_radix(line.trim(), 8).unwrap()
+    }
+
+    #[test]
+    fn masked_clears_umask_bits_unless_unset() {
+        assert_eq!(masked(0o755, 0o077), 0o700);
+        assert_eq!(masked(0o644, 0o027), 0o640);
+        assert_eq!(masked(0o755, UNSET), 0o755);
+    }
+
+    // The guards set the umask the test process already has, so the other tests
+    // running alongside are not affected.
+    #[test]
+    fn overlapping_guards_take_turns() {
+        let umask = process_umask();
+        let first = UmaskGuard::enter(Some(FileMode(umask)));
+
+        let (sender, receiver) = mpsc::channel();
+        let second = std::thread::spawn(move || {
+            let shared = UmaskGuard::enter(None);
+            sender.send("shared").unwrap();
+            drop(shared);
+            let _exclusive = UmaskGuard::enter(Some(FileMode(umask)));
+            sender.send("exclusive").unwrap();
+        });
+        assert!(
+            receiver.recv_timeout(Duration::from_millis(200)).is_err(),
+            "a build must not start while another one sets the umask"
+        );
+        assert_eq!(mask(0o777), 0o777 & !umask);
+
+        drop(first);
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(10)), Ok("shared"));
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(10)), Ok("exclusive"));
+        second.join().unwrap();
+        assert_eq!(process_umask(), umask);
+        assert_eq!(mask(0o777), 0o777);
+    }
+}
@@ -0,0 +1,1015 @@
+//! Upload of the build's artifacts to object storage.
+//!
+//! A profile's `upload` section makes `apply` finish — after `checksums`, so the sums
+//! files and signatures go along — by uploading the artifacts to an S3-compatible
+//! bucket: Amazon S3, Google Cloud Storage through its XML API with HMAC keys, or a
+//! self-hosted store such as MinIO (`endpoint`). Requests are signed by `curl`
+//! (`--aws-sigv4`) run through the [`CommandExecutor`], like every other external
+//! command.
+//!
+//! A file larger than `chunk_size` is sent as a multipart upload, one part at a time,
+//! so only one chunk is staged on disk. Every request is retried `retries` times with
+//! exponential backoff, and a multipart upload that still fails is aborted so the
+//! store does not keep its parts. Each object is tagged with `x-amz-meta-*` metadata:
+//! the profile hash, suite and architectures ([`object_metadata`]) plus the profile's
+//! own `metadata`.
+//!
+//! Credentials come from the environment ([`Credentials::from_env`]) and reach `curl`
+//! on its standard input, never on its command line.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use camino::{Utf8Path, Utf8PathBuf};
+#[cfg(feature = "schema")]
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::checksums::{self, ChecksumConfig};
+use crate::config::Profile;
+use crate::disk_space::{ByteSize, human};
+use crate::download::validate_url;
+use crate::error::RsdebstrapError;
+use crate::executor::{self, CommandExecutor, CommandSpec};
+use crate::secrets;
+
+/// Environment variable holding the access key ID.
+pub const ACCESS_KEY_ENV: &str = "AWS_ACCESS_KEY_ID";
+/// Environment variable holding the secret access key.
+pub const SECRET_KEY_ENV: &str = "AWS_SECRET_ACCESS_KEY";
+/// Environment variable holding an optional session token.
+pub const SESSION_TOKEN_ENV: &str = "AWS_SESSION_TOKEN";
+
+/// Default size of a multipart upload part.
+pub const DEFAULT_CHUNK_SIZE: ByteSize = ByteSize(64 << 20);
+/// Smallest part size S3 accepts (except for the last part).
+pub const MIN_CHUNK_SIZE: ByteSize = ByteSize(5 << 20);
+/// Largest part size S3 accepts.
+pub const MAX_CHUNK_SIZE: ByteSize = ByteSize(5 << 30);
+/// Most parts a multipart upload may have.
+const MAX_PARTS: u64 = 10_000;
+
+/// Default number of retries of a failed request.
+pub const DEFAULT_RETRIES: u32 = 3;
+
+/// Delay before the first retry; doubled for each further one.
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Prefix of the metadata keys rsdebstrap sets itself.
+const RESERVED_METADATA_PREFIX: &str = "rsdebstrap-";
+
+/// Object storage service the bucket lives in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum StorageProvider {
+    /// Amazon S3 or an S3-compatible store (default).
+    #[default]
+    S3,
+    /// Google Cloud Storage, through its S3-compatible XML API with HMAC keys.
+    Gcs,
+}
+
+impl StorageProvider {
+    /// Returns the region signed requests name when the profile sets none.
+    fn default_region(self) -> &'static str {
+        match self {
+            Self::S3 => "us-east-1",
+            Self::Gcs => "auto",
+        }
+    }
+
+    /// Returns the service endpoint for `region` when the profile sets none.
+    fn default_endpoint(self, region: &str) -> String {
+        match self {
+            Self::S3 => format!("https://s3.{}.amazonaws.com", region),
+            Self::Gcs => "https://storage.googleapis.com".to_string(),
+        }
+    }
+
+    /// Returns the URL scheme objects are shown with in logs (`s3://`, `gs://`).
+    fn scheme(self) -> &'static str {
+        match self {
+            Self::S3 => "s3",
+            Self::Gcs => "gs",
+        }
+    }
+}
+
+impl fmt::Display for StorageProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::S3 => "s3",
+            Self::Gcs => "gcs",
+        })
+    }
+}
+
+/// Configuration for uploading the build's artifacts to object storage.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "schema", derive(JsonSchema))]
+#[serde(deny_unknown_fields)]
+pub struct UploadConfig {
+    /// Object storage service (default: `s3`).
+    #[serde(default)]
+    pub provider: StorageProvider,
+    /// Bucket the artifacts are uploaded to.
+    #[serde(deserialize_with = "crate::de::string")]
+    pub bucket: String,
+    /// Key prefix of the uploaded objects, e.g. `debian/trixie` (optional).
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub prefix: Option<String>,
+    /// Service endpoint, for S3-compatible stores (default: the provider's).
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub endpoint: Option<String>,
+    /// Region requests are signed for (default: `us-east-1` for s3, `auto` for gcs).
+    #[serde(
+        default,
+        deserialize_with = "crate::de::opt_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub region: Option<String>,
+    /// Artifact paths relative to the profile's `dir` (default: the bootstrap output
+    /// and, if `checksums` is set, the sums files and their signatures).
+    #[serde(
+        default,
+        deserialize_with = "crate::de::path_list",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<Vec<String>>"))]
+    pub artifacts: Vec<Utf8PathBuf>,
+    /// Part size of multipart uploads; smaller files are uploaded in one request
+    /// (default: `64M`, between `5M` and `5G`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "schema", schemars(with = "Option<String>"))]
+    pub chunk_size: Option<ByteSize>,
+    /// Retries of each failed request (default: 3).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+    /// Additional object metadata, sent as `x-amz-meta-<key>` headers (optional).
+    #[serde(
+        default,
+        deserialize_with = "crate::de::null_to_default",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl UploadConfig {
+    /// Creates an upload to `bucket` with the default settings.
+    pub fn new(bucket: impl Into<String>) -> Self {
+        Self {
+            provider: StorageProvider::default(),
+            bucket: bucket.into(),
+            prefix: None,
+            endpoint: None,
+            region: None,
+            artifacts: Vec::new(),
+            chunk_size: None,
+            retries: None,
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    /// Validates the upload configuration.
+    ///
+    /// `default_artifact` is the bootstrap output relative to `dir` when it is not a
+    /// directory; without it, `artifacts` must be listed.
+    pub fn validate(&self, default_artifact: Option<&Utf8Path>) -> Result<(), RsdebstrapError> {
+        let bucket_chars = self
+            .bucket
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'.');
+        if !(3..=63).contains(&self.bucket.len()) || !bucket_chars {
+            return Err(RsdebstrapError::Validation(format!(
+                "upload.bucket '{}' must be 3 to 63 lowercase letters, digits, '-' or '.'",
+                self.bucket
+            )));
+        }
+        if let Some(prefix) = &self.prefix {
+            let trimmed = prefix.trim_matches('/');
+            if trimmed.is_empty()
+                || prefix.contains('\n')
+                || trimmed.split('/').any(|s| matches!(s, "" | "." | ".."))
+            {
+                return Err(RsdebstrapError::Validation(format!(
+                    "upload.prefix {:?} must be a key prefix such as debian/trixie \
+                    (no empty, '.' or '..' segments or newline)",
+                    prefix
+                )));
+            }
+        }
+        if let Some(endpoint) = &self.endpoint {
+            validate_url(endpoint, "upload endpoint")?;
+        }
+        if let Some(region) = &self.region
+            && (region.is_empty() || region.contains(|c: char| c.is_whitespace() || c == ':'))
+        {
+            return Err(RsdebstrapError::Validation(format!(
+                "upload.region {:?} must be a region name such as us-east-1",
+                region
+            )));
+        }
+        if let Some(size) = self.chunk_size
+            && !(MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&size)
+        {
+            return Err(RsdebstrapError::Validation(format!(
+                "upload.chunk_size {} must be between {} and {}",
+                size, MIN_CHUNK_SIZE, MAX_CHUNK_SIZE
+            )));
+        }
+        if self.artifacts.is_empty() && default_artifact.is_none() {
+            return Err(RsdebstrapError::Validation(
+                "upload.artifacts must be set when the bootstrap output is a directory".to_string(),
+            ));
+        }
+        for artifact in &self.artifacts {
+            checksums::validate_artifact_path(artifact, "upload")?;
+        }
+        for (key, value) in &self.metadata {
+            let plain = key
+                .bytes()
+                .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-');
+            if key.is_empty() || !plain || key.starts_with(RESERVED_METADATA_PREFIX) {
+                return Err(RsdebstrapError::Validation(format!(
+                    "upload.metadata key '{}' must be lowercase letters, digits or '-' \
+                    and not start with '{}'",
+                    key, RESERVED_METADATA_PREFIX
+                )));
+            }
+            if value.chars().any(|c| !c.is_ascii() || c.is_ascii_control()) {
+                return Err(RsdebstrapError::Validation(format!(
+                    "upload.metadata value of '{}' must be printable ASCII",
+                    key
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the artifacts to upload, relative to `dir`: the listed ones, or the
+    /// bootstrap output followed by the files `checksums` writes.
+    pub fn artifacts(
+        &self,
+        default_artifact: Option<&Utf8Path>,
+        checksums: Option<&ChecksumConfig>,
+    ) -> Vec<Utf8PathBuf> {
+        if !self.artifacts.is_empty() {
+            return self.artifacts.clone();
+        }
+        let mut artifacts: Vec<Utf8PathBuf> = default_artifact
+            .into_iter()
+            .map(Utf8Path::to_path_buf)
+            .collect();
+        for algorithm in checksums.iter().flat_map(|c| &c.algorithms) {
+            let sums = Utf8PathBuf::from(algorithm.sums_file());
+            if let Some(sign) = checksums.and_then(|c| c.sign.as_ref()) {
+                artifacts.push(sums.clone());
+                artifacts.push(sign.signature_path(&sums));
+            } else {
+                artifacts.push(sums);
+            }
+        }
+        artifacts
+    }
+
+    /// Returns the part size of multipart uploads.
+    pub fn chunk_size(&self) -> u64 {
+        self.chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE).0
+    }
+
+    /// Returns the number of retries of a failed request.
+    pub fn retries(&self) -> u32 {
+        self.retries.unwrap_or(DEFAULT_RETRIES)
+    }
+
+    /// Returns the region requests are signed for.
+    fn region(&self) -> &str {
+        self.region
+            .as_deref()
+            .unwrap_or(self.provider.default_region())
+    }
+
+    /// Returns the key of the object `artifact` is uploaded to.
+    pub fn object_key(&self, artifact: &Utf8Path) -> String {
+        match self.prefix.as_deref().map(|p| p.trim_matches('/')) {
+            Some(prefix) => format!("{}/{}", prefix, artifact),
+            None => artifact.to_string(),
+        }
+    }
+
+    /// Returns the object `artifact` is uploaded to as shown in logs, e.g.
+    /// `s3://images/debian/trixie/rootfs.tar.zst`.
+    pub fn location(&self, artifact: &Utf8Path) -> String {
+        format!("{}://{}/{}", self.provider.scheme(), self.bucket, self.object_key(artifact))
+    }
+
+    /// Returns the path-style URL of the object `key`.
+    fn object_url(&self, key: &str) -> String {
+        let endpoint = match &self.endpoint {
+            Some(endpoint) => endpoint.trim_end_matches('/').to_string(),
+            None => self.provider.default_endpoint(self.region()),
+        };
+        let key = key
+            .split('/')
+            .map(percent_encode)
+            .collect::<Vec<_>>()
+            .join("/");
+        format!("{}/{}/{}", endpoint, self.bucket, key)
+    }
+
+    /// Uploads `artifacts`, relative to `dir`, tagged with `metadata`.
+    ///
+    /// In dry-run mode nothing is read or sent, and `credentials` may be missing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an artifact cannot be read, or a request still fails after
+    /// its retries.
+    pub fn upload(
+        &self,
+        dir: &Utf8Path,
+        artifacts: &[Utf8PathBuf],
+        metadata: &BTreeMap<String, String>,
+        credentials: Option<&Credentials>,
+        executor: &dyn CommandExecutor,
+        dry_run: bool,
+    ) -> Result<()> {
+        if dry_run {
+            for artifact in artifacts {
+                info!("would upload {} to {}", dir.join(artifact), self.location(artifact));
+            }
+            return Ok(());
+        }
+        let credentials = credentials.ok_or_else(|| missing_credentials(self.provider))?;
+        let staging = tempfile::Builder::new()
+            .prefix("rsdebstrap-upload-")
+            .tempdir()
+            .map_err(|e| RsdebstrapError::io("failed to create a staging directory", e))?;
+        let session = Session {
+            config: self,
+            credentials,
+            metadata,
+            executor,
+            staging: Utf8Path::from_path(staging.path())
+                .context("staging directory path is not UTF-8")?,
+            retry_delay: RETRY_DELAY,
+        };
+        for artifact in artifacts {
+            session.upload_file(&dir.join(artifact), artifact)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the metadata every uploaded object is tagged with: the profile hash
+/// (`profile_sha256`, see [`crate::phase::assemble::release::profile_sha256`]), the
+/// suite and the target architectures, followed by the profile's `upload.metadata`.
+pub fn object_metadata(profile: &Profile, profile_sha256: &str) -> BTreeMap<String, String> {
+    let mut architectures = profile.bootstrap.architectures();
+    if architectures.is_empty() {
+        architectures.push(crate::doctor::debian_arch(std::env::consts::ARCH));
+    }
+    let mut metadata = BTreeMap::from([
+        ("rsdebstrap-profile-sha256".to_string(), profile_sha256.to_string()),
+        ("rsdebstrap-suite".to_string(), profile.bootstrap.suite().to_string()),
+        ("rsdebstrap-arch".to_string(), architectures.join(",")),
+    ]);
+    if let Some(upload) = &profile.upload {
+        metadata.extend(upload.metadata.clone());
+    }
+    metadata
+}
+
+/// Returns the error for credentials missing from the environment.
+fn missing_credentials(provider: StorageProvider) -> RsdebstrapError {
+    RsdebstrapError::Validation(format!(
+        "upload to {} needs credentials in {} and {}",
+        provider, ACCESS_KEY_ENV, SECRET_KEY_ENV
+    ))
+}
+
+/// Object storage credentials: an access key pair and an optional session token.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Credentials {
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("access_key", &self.access_key)
+            .field("secret_key", &secrets::MASK)
+            .field("session_token", &self.session_token.as_ref().map(|_| secrets::MASK))
+            .finish()
+    }
+}
+
+impl Credentials {
+    /// Creates credentials from an access key pair.
+    pub fn new(access_key: impl Into<String>, secret_key: impl Into<String>) -> Self {
+        Self {
+            access_key: access_key.into(),
+            secret_key: secret_key.into(),
+            session_token: None,
+        }
+    }
+
+    /// Adds a session token, for temporary credentials.
+    pub fn with_session_token(mut self, token: impl Into<String>) -> Self {
+        self.session_token = Some(token.into());
+        self
+    }
+
+    /// Reads the credentials from [`ACCESS_KEY_ENV`], [`SECRET_KEY_ENV`] and the
+    /// optional [`SESSION_TOKEN_ENV`] (for GCS, an HMAC key), and registers the secret
+    /// parts with [`secrets::mask`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RsdebstrapError::Validation`] if the key pair is not set.
+    pub fn from_env(provider: StorageProvider) -> Result<Self, RsdebstrapError> {
+        let var = |name| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let (Some(access_key), Some(secret_key)) = (var(ACCESS_KEY_ENV), var(SECRET_KEY_ENV))
+        else {
+            return Err(missing_credentials(provider));
+        };
+        let mut credentials = Self::new(access_key, secret_key);
+        if let Some(token) = var(SESSION_TOKEN_ENV) {
+            credentials = credentials.with_session_token(token);
+        }
+        secrets::register(&credentials.secret_key);
+        if let Some(token) = &credentials.session_token {
+            secrets::register(token);
+        }
+        Ok(credentials)
+    }
+
+    /// Returns the `curl` config (read with `--config -`) passing the credentials.
+    fn curl_config(&self) -> String {
+        let mut config =
+            format!("user = {}\n", curl_quote(&format!("{}:{}", self.access_key, self.secret_key)));
+        if let Some(token) = &self.session_token {
+            config.push_str(&format!(
+                "header = {}\n",
+                curl_quote(&format!("x-amz-security-token: {}", token))
+            ));
+        }
+        config
+    }
+}
+
+/// Quotes `value` for a `curl` config file.
+fn curl_quote(value: &str) -> String {
+    let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("\"{}\"", escaped)
+}
+
+/// Percent-encodes a key segment, keeping only the characters that need no encoding
+/// in signed requests.
+fn percent_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Returns the text of the first `<name>` element in `xml`.
+fn xml_element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let len = xml[start..].find(&format!("</{}>", name))?;
+    Some(&xml[start..start + len])
+}
+
+/// Returns the value of the last `ETag` header in a `curl --dump-header` file.
+fn etag_header(headers: &str) -> Option<&str> {
+    headers
+        .lines()
+        .filter_map(|line| line.split_once(':'))
+        .filter(|(name, _)| name.trim().eq_ignore_ascii_case("etag"))
+        .map(|(_, value)| value.trim())
+        .next_back()
+}
+
+/// Reads a response `curl` wrote into the staging directory.
+fn read_response(path: &Utf8Path) -> Result<String, RsdebstrapError> {
+    fs::read_to_string(path)
+        .map_err(|e| RsdebstrapError::io(format!("failed to read the response {}", path), e))
+}
+
+/// One run of [`UploadConfig::upload`].
+struct Session<'a> {
+    config: &'a UploadConfig,
+    credentials: &'a Credentials,
+    metadata: &'a BTreeMap<String, String>,
+    executor: &'a dyn CommandExecutor,
+    /// Directory for the staged part and the responses.
+    staging: &'a Utf8Path,
+    retry_delay: Duration,
+}
+
+impl Session<'_> {
+    /// Returns a signed `curl` request with `args` (method, body, output) to `url`.
+    fn request(&self, args: &[&str], url: &str) -> CommandSpec {
+        let mut all = vec![
+            "--fail".to_string(),
+            "--silent".to_string(),
+            "--show-error".to_string(),
+            "--aws-sigv4".to_string(),
+            format!("aws:amz:{}:s3", self.config.region()),
+            "--config".to_string(),
+            "-".to_string(),
+        ];
+        all.extend(args.iter().map(|a| a.to_string()));
+        all.push(url.to_string());
+        CommandSpec::new("curl", all).with_stdin(self.credentials.curl_config())
+    }
+
+    /// Returns `--header` arguments tagging the object with the metadata.
+    fn metadata_headers(&self) -> Vec<String> {
+        self.metadata
+            .iter()
+            .flat_map(|(key, value)| {
+                [
+                    "--header".to_string(),
+                    format!("x-amz-meta-{}: {}", key, value),
+                ]
+            })
+            .collect()
+    }
+
+    /// Runs `attempt` until it succeeds or has been retried `retries` times. An
+    /// interrupted attempt is not retried, and an interrupt cuts the wait short.
+    fn retry<T>(&self, what: &str, mut attempt: impl FnMut() -> Result<T>) -> Result<T> {
+        let retries = self.config.retries();
+        let mut delay = self.retry_delay;
+        for tries in 1.. {
+            match attempt() {
+                Ok(value) => return Ok(value),
+                Err(e) if tries <= retries && !crate::error::is_interrupted(&e) => {
+                    warn!("{} failed (attempt {} of {}): {:#}", what, tries, retries + 1, e);
+                    executor::sleep_unless_interrupted(delay);
+                    delay *= 2;
+                }
+                Err(e) => return Err(e.context(format!("{} failed", what))),
+            }
+        }
+        unreachable!("the retry loop returns")
+    }
+
+    /// Uploads the file at `path` as the object for `artifact`.
+    fn upload_file(&self, path: &Utf8Path, artifact: &Utf8Path) -> Result<()> {
+        let size = fs::metadata(path)
+            .map_err(|e| RsdebstrapError::io(format!("failed to read artifact {}", path), e))?
+            .len();
+        let key = self.config.object_key(artifact);
+        let url = self.config.object_url(&key);
+        let location = self.config.location(artifact);
+        let chunk_size = self.config.chunk_size();
+        if size <= chunk_size {
+            let mut args = vec!["--request", "PUT", "--upload-file", path.as_str()];
+            let headers = self.metadata_headers();
+            args.extend(headers.iter().map(String::as_str));
+            self.retry(&format!("upload of {}", location), || {
+                self.executor.execute_checked(&self.request(&args, &url))
+            })?;
+            info!("uploaded {} ({}) to {}", path, human(size), location);
+            return Ok(());
+        }
+        let parts = size.div_ceil(chunk_size);
+        if parts > MAX_PARTS {
+            return Err(RsdebstrapError::Validation(format!(
+                "{} needs {} parts of {}, more than the {} a multipart upload allows; \
+                raise upload.chunk_size",
+                path,
+                parts,
+                ByteSize(chunk_size),
+                MAX_PARTS
+            ))
+            .into());
+        }
+        let upload_id = self.initiate(&url, &location)?;
+        let result = self.upload_parts(path, &url, &upload_id, parts, &location);
+        if let Err(e) = result {
+            let abort =
+                self.request(&["--request", "DELETE"], &format!("{}?uploadId={}", url, upload_id));
+            if let Err(abort_error) = self.executor.execute_checked(&abort) {
+                warn!("failed to abort the multipart upload to {}: {:#}", location, abort_error);
+            }
+            return Err(e);
+        }
+        info!("uploaded {} ({}, {} parts) to {}", path, human(size), parts, location);
+        Ok(())
+    }
+
+    /// Starts a multipart upload to `url` and returns its upload ID.
+    fn initiate(&self, url: &str, location: &str) -> Result<String> {
+        let response = self.staging.join("initiate.xml");
+        let mut args = vec!["--request", "POST", "--output", response.as_str()];
+        let headers = self.metadata_headers();
+        args.extend(headers.iter().map(String::as_str));
+        self.retry(&format!("start of the multipart upload to {}", location), || {
+            self.executor
+                .execute_checked(&self.request(&args, &format!("{}?uploads", url)))?;
+            let xml = read_response(&response)?;
+            xml_element(&xml, "UploadId")
+                .filter(|id| !id.is_empty())
+                .map(percent_encode)
+                .ok_or_else(|| {
+                    RsdebstrapError::Validation(format!("no UploadId in the response: {}", xml))
+                        .into()
+                })
+        })
+    }
+
+    /// Uploads the `parts` chunks of the file at `path` and completes the upload.
+    fn upload_parts(
+        &self,
+        path: &Utf8Path,
+        url: &str,
+        upload_id: &str,
+        parts: u64,
+        location: &str,
+    ) -> Result<()> {
+        let chunk_size = self.config.chunk_size();
+        let mut file = File::open(path)
+            .map_err(|e| RsdebstrapError::io(format!("failed to open artifact {}", path), e))?;
+        let part_path = self.staging.join("part");
+        let headers_path = self.staging.join("part.headers");
+        let mut etags = Vec::with_capacity(parts as usize);
+        for number in 1..=parts {
+            stage_chunk(&mut file, (number - 1) * chunk_size, chunk_size, &part_path)
+                .map_err(|e| RsdebstrapError::io(format!("failed to read artifact {}", path), e))?;
+            let part_url = format!("{}?partNumber={}&uploadId={}", url, number, upload_id);
+            let args = [
+                "--request",
+                "PUT",
+                "--upload-file",
+                part_path.as_str(),
+                "--dump-header",
+                headers_path.as_str(),
+            ];
+            let etag =
+                self.retry(&format!("part {} of {} to {}", number, parts, location), || {
+                    self.executor
+                        .execute_checked(&self.request(&args, &part_url))?;
+                    let headers = read_response(&headers_path)?;
+                    etag_header(&headers).map(str::to_string).ok_or_else(|| {
+                        RsdebstrapError::Validation("no ETag in the part response".to_string())
+                            .into()
+                    })
+                })?;
+            info!("uploaded part {} of {} to {}", number, parts, location);
+            etags.push(etag);
+        }
+
+        let body_path = self.staging.join("complete.xml");
+        let mut body = String::from("<CompleteMultipartUpload>");
+        for (index, etag) in etags.iter().enumerate() {
+            body.push_str(&format!(
+                "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                index + 1,
+                etag
+            ));
+        }
+        body.push_str("</CompleteMultipartUpload>");
+        fs::write(&body_path, body)
+            .map_err(|e| RsdebstrapError::io(format!("failed to write {}", body_path), e))?;
+        let response = self.staging.join("complete-response.xml");
+        let data = format!("@{}", body_path);
+        let args = [
+            "--request",
+            "POST",
+            "--header",
+            "Content-Type: application/xml",
+            "--data-binary",
+            data.as_str(),
+            "--output",
+            response.as_str(),
+        ];
+        self.retry(&format!("completion of the multipart upload to {}", location), || {
+            self.executor.execute_checked(
+                &self.request(&args, &format!("{}?uploadId={}", url, upload_id)),
+            )?;
+            // S3 can report a failed completion in a 200 response.
+            let xml = read_response(&response)?;
+            match xml_element(&xml, "Message").filter(|_| xml.contains("<Error>")) {
+                Some(message) => Err(RsdebstrapError::Validation(format!(
+                    "the store rejected the completed upload: {}",
+                    message
+                ))
+                .into()),
+                None => Ok(()),
+            }
+        })
+    }
+}
+
+/// Copies `len` bytes of `file` from `offset` (fewer at its end) to `dest`.
+fn stage_chunk(file: &mut File, offset: u64, len: u64, dest: &Utf8Path) -> io::Result<()> {
+    file.seek(SeekFrom::Start(offset))?;
+    let mut part = File::create(dest)?;
+    io::copy(&mut file.by_ref().take(len), &mut part)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::ExecutionResult;
+    use std::sync::Mutex;
+
+    /// `curl` stand-in playing an object store: records each request and writes the
+    /// responses `--output` and `--dump-header` ask for. Fails the first `failures`
+    /// requests, and every request whose URL contains `failing`. Interrupts every
+    /// request if `interrupted`.
+    #[derive(Default)]
+    struct FakeStore {
+        requests: Mutex<Vec<CommandSpec>>,
+        parts: Mutex<Vec<Vec<u8>>>,
+        failures: Mutex<usize>,
+        failing: Option<&'static str>,
+        interrupted: bool,
+    }
+
+    impl FakeStore {
+        fn urls(&self) -> Vec<String> {
+            let requests = self.requests.lock().unwrap();
+            requests
+                .iter()
+                .map(|r| {
+                    let method = r.args.iter().position(|a| a == "--request").unwrap();
+                    format!("{} {}", r.args[method + 1], r.args.last().unwrap())
+                })
+                .collect()
+        }
+    }
+
+    impl CommandExecutor for FakeStore {
+        fn execute(&self, spec: &CommandSpec) -> Result<ExecutionResult> {
+            self.requests.lock().unwrap().push(spec.clone());
+            if self.interrupted {
+                return Err(RsdebstrapError::interrupted(spec, "interrupted").into());
+            }
+            let url = spec.args.last().unwrap();
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 || self.failing.is_some_and(|f| url.contains(f)) {
+                *failures = failures.saturating_sub(1);
+                anyhow::bail!("connection reset");
+            }
+            let arg = |name: &str| {
+                let index = spec.args.iter().position(|a| a == name)?;
+                spec.args.get(index + 1)
+            };
+            if url.ends_with("?uploads") {
+                fs::write(arg("--output").unwrap(), "<Result><UploadId>u+1</UploadId></Result>")?;
+            } else if url.contains("partNumber=") {
+                let body = fs::read(arg("--upload-file").unwrap())?;
+                let mut parts = self.parts.lock().unwrap();
+                parts.push(body);
+                fs::write(
+                    arg("--dump-header").unwrap(),
+                    format!("HTTP/1.1 200 OK\r\nETag: \"etag{}\"\r\n", parts.len()),
+                )?;
+            } else if let Some(output) = arg("--output") {
+                fs::write(output, "<CompleteMultipartUploadResult/>")?;
+            }
+            Ok(ExecutionResult {
+                status: None,
+                details: None,
+            })
+        }
+    }
+
+    fn config(yaml: &str) -> UploadConfig {
+        yaml_serde::from_str(yaml).unwrap()
+    }
+
+    fn session<'a>(
+        config: &'a UploadConfig,
+        credentials: &'a Credentials,
+        metadata: &'a BTreeMap<String, String>,
+        store: &'a FakeStore,
+        staging: &'a Utf8Path,
+    ) -> Session<'a> {
+        Session {
+            config,
+            credentials,
+            metadata,
+            executor: store,
+            staging,
+            retry_delay: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn validate_rejects_bad_settings() {
+        for (yaml, expected) in [
+            ("{bucket: Images}", "3 to 63 lowercase"),
+            ("{bucket: images, prefix: a/../b}", "key prefix"),
+            ("{bucket: images, endpoint: minio:9000}", "http:// or https://"),
+            ("{bucket: images, region: 'us east'}", "region name"),
+            ("{bucket: images, chunk_size: 1M}", "between 5M and 5G"),
+            ("{bucket: images, artifacts: [../x.img]}", "relative path inside dir"),
+            ("{bucket: images, metadata: {Team: os}}", "lowercase letters"),
+            ("{bucket: images, metadata: {rsdebstrap-suite: x}}", "not start with"),
+            ("{bucket: images, metadata: {team: \"a\\nb\"}}", "printable ASCII"),
+        ] {
+            let err = config(yaml)
+                .validate(Some(Utf8Path::new("rootfs.tar")))
+                .unwrap_err();
+            assert!(err.to_string().contains(expected), "{yaml}: {err}");
+        }
+        let err = config("{bucket: images}").validate(None).unwrap_err();
+        assert!(err.to_string().contains("must be set"), "{err}");
+        config("{bucket: images, prefix: debian/trixie/, chunk_size: 8M}")
+            .validate(Some(Utf8Path::new("rootfs.tar")))
+            .unwrap();
+    }
+
+    #[test]
+    fn default_artifacts_follow_the_checksums() {
+        let checksums: ChecksumConfig =
+            yaml_serde::from_str("{algorithms: [sha256], sign: {method: gpg}}").unwrap();
+        let upload = config("{bucket: images}");
+        assert_eq!(
+            upload.artifacts(Some(Utf8Path::new("rootfs.tar.zst")), Some(&checksums)),
+            ["rootfs.tar.zst", "SHA256SUMS", "SHA256SUMS.asc"]
+        );
+        assert_eq!(
+            config("{bucket: images, artifacts: [disk.img]}").artifacts(None, Some(&checksums)),
+            ["disk.img"]
+        );
+    }
+
+    #[test]
+    fn object_urls_are_path_style_and_encoded() {
+        let upload = config("{bucket: images, prefix: /debian/trixie/}");
+        let key = upload.object_key(Utf8Path::new("out/root fs+1.tar"));
+        assert_eq!(key, "debian/trixie/out/root fs+1.tar");
+        assert_eq!(
+            upload.object_url(&key),
+            "https://s3.us-east-1.amazonaws.com/images/debian/trixie/out/root%20fs%2B1.tar"
+        );
+        let gcs = config("{provider: gcs, bucket: images}");
+        assert_eq!(gcs.object_url("a.tar"), "https://storage.googleapis.com/images/a.tar");
+        assert_eq!(gcs.location(Utf8Path::new("a.tar")), "gs://images/a.tar");
+        let minio = config("{bucket: images, endpoint: 'http://minio:9000/'}");
+        assert_eq!(minio.object_url("a.tar"), "http://minio:9000/images/a.tar");
+    }
+
+    #[test]
+    fn credentials_reach_curl_on_stdin_only() {
+        let credentials = Credentials::new("AKID", "se\"cret").with_session_token("tok");
+        assert_eq!(
+            credentials.curl_config(),
+            "user = \"AKID:se\\\"cret\"\nheader = \"x-amz-security-token: tok\"\n"
+        );
+        let debug = format!("{:?}", Credentials::new("AKID", "hunter2"));
+        assert!(!debug.contains("hunter2"), "{debug}");
+    }
+
+    #[test]
+    fn small_files_are_uploaded_in_one_tagged_request() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(temp.path()).unwrap();
+        fs::write(dir.join("rootfs.tar"), "abc").unwrap();
+        let upload = config("{bucket: images, prefix: builds}");
+        let credentials = Credentials::new("AKID", "secret");
+        let metadata = BTreeMap::from([("rsdebstrap-suite".to_string(), "trixie".to_string())]);
+        let store = FakeStore::default();
+
+        session(&upload, &credentials, &metadata, &store, dir)
+            .upload_file(&dir.join("rootfs.tar"), Utf8Path::new("rootfs.tar"))
+            .unwrap();
+
+        let requests = store.requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        let args = requests[0].args.join(" ");
+        assert!(args.contains("--aws-sigv4 aws:amz:us-east-1:s3 --config -"), "{args}");
+        assert!(args.contains("--header x-amz-meta-rsdebstrap-suite: trixie"), "{args}");
+        assert!(!args.contains("secret"), "{args}");
+        assert!(args.ends_with("/images/builds/rootfs.tar"), "{args}");
+        assert_eq!(requests[0].stdin.as_deref(), Some(&b"user = \"AKID:secret\"\n"[..]));
+    }
+
+    #[test]
+    fn large_files_are_uploaded_in_parts_with_retries() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(temp.path()).unwrap();
+        let staging = dir.join("staging");
+        fs::create_dir(&staging).unwrap();
+        let content: Vec<u8> = (0..MIN_CHUNK_SIZE.0 * 2 + 10).map(|i| i as u8).collect();
+        fs::write(dir.join("disk.img"), &content).unwrap();
+        let upload = config("{bucket: images, chunk_size: 5M, retries: 1}");
+        let credentials = Credentials::new("AKID", "secret");
+        let metadata = BTreeMap::new();
+        let store = FakeStore {
+            failures: Mutex::new(1),
+            ..FakeStore::default()
+        };
+
+        session(&upload, &credentials, &metadata, &store, &staging)
+            .upload_file(&dir.join("disk.img"), Utf8Path::new("disk.img"))
+            .unwrap();
+
+        let url = "https://s3.us-east-1.amazonaws.com/images/disk.img";
+        assert_eq!(
+            store.urls(),
+            [
+                format!("POST {url}?uploads"),
+                format!("POST {url}?uploads"),
+                format!("PUT {url}?partNumber=1&uploadId=u%2B1"),
+                format!("PUT {url}?partNumber=2&uploadId=u%2B1"),
+                format!("PUT {url}?partNumber=3&uploadId=u%2B1"),
+                format!("POST {url}?uploadId=u%2B1"),
+            ]
+        );
+        assert_eq!(store.parts.lock().unwrap().concat(), content);
+        let body = fs::read_to_string(staging.join("complete.xml")).unwrap();
+        assert!(
+            body.contains("<Part><PartNumber>3</PartNumber><ETag>\"etag3\"</ETag></Part>"),
+            "{body}"
+        );
+    }
+
+    #[test]
+    fn a_failed_multipart_upload_is_aborted() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(temp.path()).unwrap();
+        fs::write(dir.join("disk.img"), vec![0; MIN_CHUNK_SIZE.0 as usize + 1]).unwrap();
+        let upload = config("{bucket: images, chunk_size: 5M, retries: 0}");
+        let credentials = Credentials::new("AKID", "secret");
+        let metadata = BTreeMap::new();
+        let store = FakeStore {
+            failing: Some("partNumber="),
+            ..FakeStore::default()
+        };
+
+        let err = session(&upload, &credentials, &metadata, &store, dir)
+            .upload_file(&dir.join("disk.img"), Utf8Path::new("disk.img"))
+            .unwrap_err();
+
+        assert!(format!("{err:#}").contains("connection reset"), "{err:#}");
+        let url = "https://s3.us-east-1.amazonaws.com/images/disk.img";
+        assert_eq!(
+            store.urls(),
+            [
+                format!("POST {url}?uploads"),
+                format!("PUT {url}?partNumber=1&uploadId=u%2B1"),
+                format!("DELETE {url}?uploadId=u%2B1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn an_interrupted_upload_is_not_retried() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(temp.path()).unwrap();
+        fs::write(dir.join("rootfs.tar"), "abc").unwrap();
+        let upload = config("{bucket: images, retries: 3}");
+        let credentials = Credentials::new("AKID", "secret");
+        let metadata = BTreeMap::new();
+        let store = FakeStore {
+            interrupted: true,
+            ..FakeStore::default()
+        };
+
+        let err = session(&upload, &credentials, &metadata, &store, dir)
+            .upload_file(&dir.join("rootfs.tar"), Utf8Path::new("rootfs.tar"))
+            .unwrap_err();
+
+        assert!(crate::error::is_interrupted(&err), "{err:#}");
+        assert_eq!(store.requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn responses_are_parsed() {
+        assert_eq!(xml_element("<a><UploadId>x</UploadId></a>", "UploadId"), Some("x"));
+        assert_eq!(xml_element("<a/>", "UploadId"), None);
+        assert_eq!(
+            etag_header("HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\netag: \"e1\"\r\n"),
+            Some("\"e1\"")
+        );
+    }
+}
@@ -0,0 +1,196 @@
+//! Per-user defaults merged beneath every profile.
+//!
+//! `$XDG_CONFIG_HOME/rsdebstrap/config.yaml` (`~/.config/rsdebstrap/config.yaml`
+//! without it) holds the settings that belong to the machine rather than to the
+//! image: the privilege method, a nearby mirror, where downloads are cached, and how
+//! log lines look. Loading a profile with [`UserConfig::apply`] fills in each of them
+//! only where the profile leaves it unset, so a profile stays the same everywhere and
+//! always wins.
+//!
+//! The CLI reads the file unless `--no-user-config` is given; library callers opt in
+//! with [`ProfileLayers`](crate::config::ProfileLayers). `serve` does not read it.
+
+use std::fs;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Deserialize;
+
+use crate::bootstrap::distribution::Distribution;
+use crate::config::{Bootstrap, Profile};
+use crate::error::RsdebstrapError;
+use crate::privilege::PrivilegeDefaults;
+
+/// Name of the user configuration file in [`config_dir`].
+pub const FILE_NAME: &str = "config.yaml";
+
+/// Format of log lines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines (default)
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// Per-user defaults. See the [module documentation](self).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserConfig {
+    /// Download cache of a `prepare.download` task without a `cache_dir` (relative
+    /// paths are resolved against the file's directory).
+    #[serde(default, deserialize_with = "crate::de::opt_path")]
+    pub cache_dir: Option<Utf8PathBuf>,
+    /// `defaults.privilege` of a profile without one.
+    #[serde(default)]
+    pub privilege: Option<PrivilegeDefaults>,
+    /// Bootstrap mirrors of a Debian profile without mirrors or a preset; debootstrap
+    /// takes the first.
+    #[serde(default)]
+    pub mirrors: Vec<String>,
+    /// Format of log lines (default: text).
+    #[serde(default)]
+    pub log_format: LogFormat,
+}
+
+/// Returns rsdebstrap's directory of user configuration: `$XDG_CONFIG_HOME/rsdebstrap`,
+/// or `~/.config/rsdebstrap` without it.
+pub fn config_dir() -> Option<Utf8PathBuf> {
+    let var = |name| std::env::var(name).ok().filter(|v| !v.is_empty());
+    let base = match (var("XDG_CONFIG_HOME"), var("HOME")) {
+        (Some(config), _) => Utf8PathBuf::from(config),
+        (None, Some(home)) => Utf8PathBuf::from(home).join(".config"),
+        (None, None) => return None,
+    };
+    Some(base.join("rsdebstrap"))
+}
+
+impl UserConfig {
+    /// Loads [`FILE_NAME`] from [`config_dir`], or returns `None` when there is none.
+    ///
+    /// # Errors
+    ///
+    /// As [`UserConfig::load`].
+    pub fn load_default() -> Result<Option<Self>, RsdebstrapError> {
+        match config_dir().map(|dir| dir.join(FILE_NAME)) {
+            Some(path) if path.exists() => Self::load(&path).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Loads the user configuration file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`RsdebstrapError::Io`] if the file cannot be read,
+    /// [`RsdebstrapError::Config`] if it is not a valid configuration, and
+    /// [`RsdebstrapError::Validation`] for an empty `cache_dir` or mirror.
+    pub fn load(path: &Utf8Path) -> Result<Self, RsdebstrapError> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| RsdebstrapError::io(format!("failed to read {}", path), e))?;
+        // An empty file is an empty configuration, not a YAML `null`.
+        let mut config: Self = if text.trim().is_empty() {
+            Self::default()
+        } else {
+            yaml_serde::from_str(&text).map_err(|e| {
+                RsdebstrapError::Config(format!("invalid user config {}: {}", path, e))
+            })?
+        };
+        if config
+            .cache_dir
+            .as_ref()
+            .is_some_and(|dir| dir.as_str().is_empty())
+        {
+            return Err(RsdebstrapError::Validation(format!(
+                "{}: cache_dir must not be empty",
+                path
+            )));
+        }
+        if config.mirrors.iter().any(|mirror| mirror.trim().is_empty()) {
+            return Err(RsdebstrapError::Validation(format!(
+                "{}: mirrors must not be empty strings",
+                path
+            )));
+        }
+        if let Some(dir) = config.cache_dir.as_mut()
+            && dir.is_relative()
+        {
+            *dir = path.parent().unwrap_or(Utf8Path::new("")).join(&*dir);
+        }
+        Ok(config)
+    }
+
+    /// Fills in the settings `profile` leaves unset: `defaults.privilege`, the
+    /// bootstrap mirrors (Debian without a preset only, since the mirror serves one
+    /// distribution) and the `prepare.download` cache.
+    ///
+    /// Runs before the preset and distribution defaults and before task privileges are
+    /// resolved, so `privilege: true` tasks pick up the method from here.
+    pub fn apply(&self, profile: &mut Profile) {
+        if profile.defaults.privilege.is_none() {
+            profile.defaults.privilege = self.privilege.clone();
+        }
+        if let Some(download) = profile.prepare.download.as_mut()
+            && download.cache_dir.is_none()
+        {
+            download.cache_dir = self.cache_dir.clone();
+        }
+        let bootstrap = &mut profile.bootstrap;
+        if bootstrap.distribution() != Distribution::Debian
+            || bootstrap.preset().is_some()
+            || bootstrap.mirror_urls().next().is_some()
+        {
+            return;
+        }
+        match bootstrap {
+            Bootstrap::Mmdebstrap(cfg) => cfg.mirrors = self.mirrors.clone(),
+            Bootstrap::Debootstrap(cfg) => cfg.mirror = self.mirrors.first().cloned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::privilege::PrivilegeMethod;
+
+    #[test]
+    fn load_resolves_the_cache_dir_against_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        let path = dir.join(FILE_NAME);
+        fs::write(
+            &path,
+            "cache_dir: cache\nprivilege:\n  method: doas\nmirrors: [http://mirror.lan/debian]\n\
+            log_format: json\n",
+        )
+        .unwrap();
+
+        let config = UserConfig::load(&path).unwrap();
+        assert_eq!(config.cache_dir, Some(dir.join("cache")));
+        assert_eq!(config.privilege.map(|p| p.method), Some(PrivilegeMethod::Doas));
+        assert_eq!(config.mirrors, ["http://mirror.lan/debian"]);
+        assert_eq!(config.log_format, LogFormat::Json);
+
+        fs::write(&path, "").unwrap();
+        assert_eq!(UserConfig::load(&path).unwrap(), UserConfig::default());
+    }
+
+    #[test]
+    fn load_rejects_unknown_fields_and_empty_values() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8Path::from_path(dir.path()).unwrap();
+        let path = dir.join(FILE_NAME);
+        for (text, expected) in [
+            ("mirror: http://mirror.lan/debian\n", "unknown field `mirror`"),
+            ("cache_dir: ''\n", "cache_dir must not be empty"),
+            ("mirrors: ['']\n", "mirrors must not be empty"),
+            ("log_format: xml\n", "unknown variant `xml`"),
+        ] {
+            fs::write(&path, text).unwrap();
+            let err = UserConfig::load(&path).unwrap_err();
+            assert!(err.to_string().contains(expected), "{text:?}: {err}");
+        }
+    }
+}
@@ -0,0 +1,411 @@
+//! Locating profile parse errors.
+//!
+//! `yaml_serde` names where deserialization failed with a dotted path at the start of
+//! its message (`provision[0].type: unknown variant ...`) and a location. Inside
+//! internally tagged enums, whose content serde buffers before deserializing it, the
+//! location is that of the buffered value's end or the document start instead, and the
+//! path loses its sequence index: a typo under `bootstrap:` is reported at line 1.
+//!
+//! [`profile_parse_error`] turns the path into a JSON-pointer-like one
+//! (`/provision/0/isolaton`), looks it up in the document text for the real line and
+//! column, and suggests the closest expected name for a misspelled field or variant.
+
+use std::fmt;
+
+use camino::Utf8Path;
+use serde::de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+
+use crate::error::RsdebstrapError;
+
+/// One step of a path into a YAML document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+/// Parses a `yaml_serde` path (`provision[1].isolation`, `.` for the root).
+///
+/// Returns `None` for text that is not a path, such as a message containing spaces.
+pub(crate) fn parse_path(path: &str) -> Option<Vec<Segment>> {
+    if path == "." {
+        return Some(Vec::new());
+    }
+    let mut segments = Vec::new();
+    for part in path.split('.') {
+        let (key, indices) = part.split_once('[').map_or((part, ""), |(k, i)| (k, i));
+        if key.is_empty() && indices.is_empty() || key.contains(char::is_whitespace) {
+            return None;
+        }
+        if !key.is_empty() {
+            segments.push(Segment::Key(key.to_string()));
+        }
+        for index in indices.split('[').filter(|i| !i.is_empty()) {
+            segments.push(Segment::Index(index.strip_suffix(']')?.parse().ok()?));
+        }
+    }
+    Some(segments)
+}
+
+/// Renders `segments` as a JSON pointer (`/provision/0/content`).
+fn pointer(segments: &[Segment]) -> String {
+    segments
+        .iter()
+        .map(|segment| match segment {
+            Segment::Key(key) => format!("/{}", key.replace('~', "~0").replace('/', "~1")),
+            Segment::Index(index) => format!("/{}", index),
+        })
+        .collect()
+}
+
+/// Builds the [`RsdebstrapError::ConfigParse`] error for `err`, raised while
+/// deserializing the profile `text` read from `file_path`.
+///
+/// `unknown_field` is the exact place of an unknown field when the caller found it
+/// (`provision[0].isolation` and the field name), which the error's own path lacks
+/// inside tagged enums.
+pub(crate) fn profile_parse_error(
+    err: &yaml_serde::Error,
+    text: &str,
+    unknown_field: Option<(String, String)>,
+    file_path: &Utf8Path,
+) -> RsdebstrapError {
+    let mut message = err.to_string();
+    if let Some(loc) = err.location() {
+        let suffix = format!(" at line {} column {}", loc.line(), loc.column());
+        if let Some(stripped) = message.strip_suffix(&suffix) {
+            message.truncate(stripped.len());
+        }
+    }
+
+    let mut segments = None;
+    if let Some((prefix, rest)) = message.split_once(": ")
+        && let Some(path) = parse_path(prefix)
+        && locate(text, &path).is_some()
+    {
+        segments = Some(path);
+        message = rest.to_string();
+    }
+    if let Some((at, field)) = unknown_field
+        && let Some(mut path) = parse_path(&at)
+    {
+        path.push(Segment::Key(field));
+        segments = Some(path);
+    }
+
+    let position = segments
+        .as_deref()
+        .and_then(|path| locate(text, path))
+        .or_else(|| err.location().map(|loc| (loc.line(), loc.column())));
+    RsdebstrapError::ConfigParse {
+        file: file_path.to_string(),
+        line: position.and_then(|(line, _)| u32::try_from(line).ok()),
+        column: position.and_then(|(_, column)| u32::try_from(column).ok()),
+        path: segments
+            .filter(|path| !path.is_empty())
+            .map(|path| pointer(&path)),
+        suggestion: suggestion(&message),
+        message,
+    }
+}
+
+/// Returns the expected name closest to the one an "unknown field" or "unknown
+/// variant" message rejects, if one is close enough to be a likely typo.
+fn suggestion(message: &str) -> Option<String> {
+    let rest = message
+        .strip_prefix("unknown field `")
+        .or_else(|| message.strip_prefix("unknown variant `"))?;
+    let (name, rest) = rest.split_once('`')?;
+    let (_, expected) = rest.split_once("expected")?;
+    expected
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, candidate)| distance <= (name.len().max(candidate.len()) / 3).max(1))
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+/// Levenshtein distance between `a` and `b`, counted in characters.
+pub(crate) fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Returns the 1-based line and column of the node `path` leads to in `text`: the key
+/// for a mapping entry, the item for a sequence index.
+///
+/// The document is walked with [`Find`], which fails on reaching the node so that
+/// `yaml_serde` records the node's position in the error.
+pub(crate) fn locate(text: &str, path: &[Segment]) -> Option<(usize, usize)> {
+    if path.is_empty() {
+        return None;
+    }
+    let err = Find(path)
+        .deserialize(yaml_serde::Deserializer::from_str(text))
+        .err()?;
+    if err.to_string().contains(FOUND) {
+        err.location().map(|loc| (loc.line(), loc.column()))
+    } else {
+        None
+    }
+}
+
+/// Message of the error [`Find`] raises at the node it was looking for.
+const FOUND: &str = "rsdebstrap: located node";
+
+/// Seed walking a document along a path, failing at the node the path leads to.
+struct Find<'a>(&'a [Segment]);
+
+impl<'de> DeserializeSeed<'de> for Find<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Find<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a mapping or sequence")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let Some((Segment::Key(name), rest)) = self.0.split_first() else {
+            return Ok(());
+        };
+        let key = FindKey {
+            name,
+            last: rest.is_empty(),
+        };
+        while let Some(matched) = map.next_key_seed(key)? {
+            if matched {
+                map.next_value_seed(Find(rest))?;
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let Some((&Segment::Index(target), rest)) = self.0.split_first() else {
+            return Ok(());
+        };
+        let mut index = 0;
+        loop {
+            let more = if index != target {
+                seq.next_element::<IgnoredAny>()?.is_some()
+            } else if rest.is_empty() {
+                seq.next_element_seed(Found)?.is_some()
+            } else {
+                seq.next_element_seed(Find(rest))?.is_some()
+            };
+            if !more {
+                return Ok(());
+            }
+            index += 1;
+        }
+    }
+
+    // Scalars end the walk: the path leads nowhere in this document.
+    fn visit_bool<E: de::Error>(self, _: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E: de::Error>(self, _: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E: de::Error>(self, _: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E: de::Error>(self, _: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E: de::Error>(self, _: &str) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<(), E> {
+        Ok(())
+    }
+}
+
+/// Seed for a mapping key: reports whether it is `name`, and fails on it if it is the
+/// `last` segment of the path.
+#[derive(Clone, Copy)]
+struct FindKey<'a> {
+    name: &'a str,
+    last: bool,
+}
+
+impl<'de> DeserializeSeed<'de> for FindKey<'_> {
+    type Value = bool;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<bool, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for FindKey<'_> {
+    type Value = bool;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a mapping key")
+    }
+
+    fn visit_str<E: de::Error>(self, key: &str) -> Result<bool, E> {
+        match (key == self.name, self.last) {
+            (true, true) => Err(E::custom(FOUND)),
+            (matched, _) => Ok(matched),
+        }
+    }
+
+    fn visit_bool<E: de::Error>(self, _: bool) -> Result<bool, E> {
+        Ok(false)
+    }
+
+    fn visit_i64<E: de::Error>(self, _: i64) -> Result<bool, E> {
+        Ok(false)
+    }
+
+    fn visit_u64<E: de::Error>(self, _: u64) -> Result<bool, E> {
+        Ok(false)
+    }
+
+    fn visit_f64<E: de::Error>(self, _: f64) -> Result<bool, E> {
+        Ok(false)
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<bool, E> {
+        Ok(false)
+    }
+}
+
+/// Seed that fails on whatever node it is given.
+struct Found;
+
+impl<'de> DeserializeSeed<'de> for Found {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Found {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("any node")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, _: A) -> Result<(), A::Error> {
+        Err(de::Error::custom(FOUND))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, _: A) -> Result<(), A::Error> {
+        Err(de::Error::custom(FOUND))
+    }
+
+    fn visit_str<E: de::Error>(self, _: &str) -> Result<(), E> {
+        Err(E::custom(FOUND))
+    }
+
+    fn visit_bool<E: de::Error>(self, _: bool) -> Result<(), E> {
+        Err(E::custom(FOUND))
+    }
+
+    fn visit_i64<E: de::Error>(self, _: i64) -> Result<(), E> {
+        Err(E::custom(FOUND))
+    }
+
+    fn visit_u64<E: de::Error>(self, _: u64) -> Result<(), E> {
+        Err(E::custom(FOUND))
+    }
+
+    fn visit_f64<E: de::Error>(self, _: f64) -> Result<(), E> {
+        Err(E::custom(FOUND))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<(), E> {
+        Err(E::custom(FOUND))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROFILE: &str = "\
+dir: /tmp/rootfs
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  mirors: [https://deb.debian.org/debian]
+provision:
+- type: shell
+  content: echo hi
+- type: shell
+  isolaton: false
+";
+
+    #[test]
+    fn parse_path_reads_yaml_serde_paths() {
+        use Segment::{Index, Key};
+        assert_eq!(parse_path("."), Some(vec![]));
+        assert_eq!(
+            parse_path("provision[1].isolation"),
+            Some(vec![Key("provision".into()), Index(1), Key("isolation".into())])
+        );
+        assert_eq!(parse_path("invalid type"), None);
+        assert_eq!(parse_path("a[x]"), None);
+    }
+
+    #[test]
+    fn locate_finds_keys_and_items() {
+        let path = |p: &str| parse_path(p).unwrap();
+        assert_eq!(locate(PROFILE, &path("bootstrap.mirors")), Some((5, 3)));
+        assert_eq!(locate(PROFILE, &path("provision[1]")), Some((9, 3)));
+        assert_eq!(locate(PROFILE, &path("provision[1].isolaton")), Some((10, 3)));
+        assert_eq!(locate(PROFILE, &path("provision[2]")), None);
+        assert_eq!(locate(PROFILE, &path("dir.nested")), None);
+        assert_eq!(locate("a: 1\n", &path("b")), None);
+    }
+
+    #[test]
+    fn suggestion_picks_the_closest_expected_name() {
+        let suggest = |m: &str| suggestion(m);
+        assert_eq!(
+            suggest("unknown field `provisionres`, expected one of `dir`, `provision`, `verify`")
+                .as_deref(),
+            Some("provision")
+        );
+        assert_eq!(
+            suggest("unknown variant `shel`, expected one of `shell`, `ssh`").as_deref(),
+            Some("shell")
+        );
+        assert_eq!(suggest("unknown field `colour`, expected `dir`"), None);
+        assert_eq!(suggest("invalid type: integer `5`, expected a string"), None);
+        assert_eq!(edit_distance("mirors", "mirrors"), 1);
+    }
+}
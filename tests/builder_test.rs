@@ -2,8 +2,8 @@ mod helpers;
 
 use anyhow::Result;
 use camino::Utf8PathBuf;
-use rsdebstrap::bootstrap::BootstrapBackend;
-use rsdebstrap::bootstrap::mmdebstrap::MmdebstrapConfig;
+use rsdebstrap::bootstrap::mmdebstrap::{Format, MmdebstrapConfig};
+use rsdebstrap::bootstrap::{BootstrapBackend, ToolVersion};
 use rsdebstrap::executor::{CommandExecutor, CommandSpec, RealCommandExecutor};
 
 #[test]
@@ -147,6 +147,22 @@ fn test_build_mmdebstrap_args_with_non_default_values() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_build_mmdebstrap_args_userns_selects_unshare_mode() -> Result<()> {
+    use rsdebstrap::privilege::{Privilege, PrivilegeMethod};
+
+    let config = helpers::MmdebstrapConfigBuilder::new("trixie", "rootfs")
+        .privilege(Privilege::Method(PrivilegeMethod::Userns))
+        .build();
+    let dir = Utf8PathBuf::from("/tmp/test");
+
+    let args = config.build_args(&dir)?;
+
+    assert_eq!(args, ["--mode", "unshare", "trixie", "/tmp/test/rootfs"]);
+
+    Ok(())
+}
+
 #[test]
 fn test_build_debootstrap_args_with_non_default_variant() -> Result<()> {
     use rsdebstrap::bootstrap::debootstrap::Variant;
@@ -203,6 +219,30 @@ fn test_build_debootstrap_args_with_all_non_default_flags() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_build_debootstrap_args_with_keyring() -> Result<()> {
+    let config = helpers::DebootstrapConfigBuilder::new("trixie", "rootfs")
+        .exclude(["systemd"])
+        .keyring("/usr/share/keyrings/derivative-archive-keyring.gpg")
+        .foreign(true)
+        .build();
+    let dir = Utf8PathBuf::from("/tmp/test-debootstrap");
+
+    let args = config.build_args(&dir)?;
+
+    let expected = vec![
+        "--exclude=systemd",
+        "--keyring=/usr/share/keyrings/derivative-archive-keyring.gpg",
+        "--foreign",
+        "trixie",
+        "/tmp/test-debootstrap/rootfs",
+    ];
+
+    assert_eq!(args, expected, "keyring should be emitted as --keyring=PATH");
+
+    Ok(())
+}
+
 #[test]
 fn test_build_debootstrap_args_filters_empty_mirror() -> Result<()> {
     let config = helpers::DebootstrapConfigBuilder::new("bookworm", "rootfs")
@@ -222,3 +262,53 @@ fn test_build_debootstrap_args_filters_empty_mirror() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_adapt_args_follows_the_backend_version() -> Result<()> {
+    let dir = Utf8PathBuf::from("/tmp/test-versions");
+    let version = |s: &str| s.parse::<ToolVersion>().unwrap();
+
+    // Releases before --no-merged-usr never merge /usr, so the flag is dropped.
+    let config = helpers::DebootstrapConfigBuilder::new("trixie", "rootfs")
+        .merged_usr(false)
+        .build();
+    let args = config.build_args(&dir)?;
+    assert!(args.contains(&"--no-merged-usr".to_string()));
+    let old = config.adapt_args(args.clone(), &version("1.0.81"))?;
+    assert!(!old.contains(&"--no-merged-usr".to_string()), "{old:?}");
+    assert_eq!(config.adapt_args(args.clone(), &version("1.0.134"))?, args);
+
+    // --merged-usr cannot be dropped without changing the rootfs.
+    let config = helpers::DebootstrapConfigBuilder::new("trixie", "rootfs")
+        .merged_usr(true)
+        .build();
+    let args = config.build_args(&dir)?;
+    let err = config.adapt_args(args, &version("1.0.81")).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("needs debootstrap 1.0.83 or newer"),
+        "{err}"
+    );
+
+    let config = helpers::MmdebstrapConfigBuilder::new("trixie", "rootfs.ext2")
+        .format(Format::Ext2)
+        .customize_hook(["copy-in /etc/hosts /etc"])
+        .build();
+    let args = config.build_args(&dir)?;
+    assert_eq!(config.adapt_args(args.clone(), &version("1.4.3"))?, args);
+    let err = config
+        .adapt_args(args.clone(), &version("0.7.5"))
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("format ext2 needs mmdebstrap 0.8.0"),
+        "{err}"
+    );
+    let config = MmdebstrapConfig {
+        format: Format::Tar,
+        ..config
+    };
+    let err = config.adapt_args(args, &version("0.5.1")).unwrap_err();
+    assert!(err.to_string().contains("the special hook 'copy-in"), "{err}");
+    Ok(())
+}
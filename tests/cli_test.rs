@@ -1,7 +1,11 @@
 use anyhow::Result;
 use camino::Utf8PathBuf;
 use clap::Parser;
-use rsdebstrap::cli::{Cli, Commands, LogLevel};
+use rsdebstrap::cli::{ApplyPhase, Cli, Commands, InitBackend, LogLevel};
+use rsdebstrap::config::UnknownFields;
+use rsdebstrap::metrics::MetricsFormat;
+use rsdebstrap::pipeline::{PhaseSelection, TagFilter};
+use rsdebstrap::privilege::PrivilegeMethod;
 
 #[test]
 fn test_parse_apply_command() -> Result<()> {
@@ -11,7 +15,17 @@ fn test_parse_apply_command() -> Result<()> {
         Commands::Apply(opts) => {
             assert_eq!(opts.common.file, Utf8PathBuf::from("test.yml"));
             assert_eq!(opts.common.log_level, LogLevel::Info);
+            assert!(!opts.common.no_user_config);
             assert!(!opts.dry_run);
+            assert!(!opts.plan);
+            assert!(!opts.clean_stale_mounts);
+            assert!(opts.only.is_empty());
+            assert!(opts.skip.is_empty());
+            assert!(!opts.skip_bootstrap);
+            assert_eq!(opts.tag_filter(), TagFilter::default());
+            assert_eq!(opts.start_at_task, None);
+            assert!(opts.runs(ApplyPhase::Bootstrap));
+            assert_eq!(opts.pipeline_selection(), PhaseSelection::ALL);
         }
         _ => panic!("Expected Apply command"),
     }
@@ -43,6 +57,266 @@ fn test_parse_apply_command_with_flags() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_parse_apply_command_with_clean_stale_mounts() -> Result<()> {
+    let args = Cli::parse_from([
+        "rsdebstrap",
+        "apply",
+        "--file",
+        "test.yml",
+        "--clean-stale-mounts",
+    ]);
+
+    match args.command {
+        Commands::Apply(opts) => assert!(opts.clean_stale_mounts),
+        _ => panic!("Expected Apply command"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_apply_command_with_only_phases() -> Result<()> {
+    let args = Cli::parse_from(["rsdebstrap", "apply", "--only", "bootstrap,assemble"]);
+
+    match args.command {
+        Commands::Apply(opts) => {
+            assert_eq!(opts.only, [ApplyPhase::Bootstrap, ApplyPhase::Assemble]);
+            assert!(opts.runs(ApplyPhase::Bootstrap));
+            assert!(!opts.runs(ApplyPhase::Provision));
+            assert!(opts.runs(ApplyPhase::Assemble));
+        }
+        _ => panic!("Expected Apply command"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_apply_command_with_skipped_phases() -> Result<()> {
+    let args = Cli::parse_from([
+        "rsdebstrap",
+        "apply",
+        "--skip-bootstrap",
+        "--skip",
+        "assemble",
+    ]);
+
+    match args.command {
+        Commands::Apply(opts) => {
+            assert!(!opts.runs(ApplyPhase::Bootstrap));
+            assert_eq!(
+                opts.pipeline_selection(),
+                PhaseSelection {
+                    provision: true,
+                    assemble: false,
+                    verify: true,
+                }
+            );
+        }
+        _ => panic!("Expected Apply command"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_apply_command_rejects_only_with_skip() {
+    for skip in [
+        ["--skip", "assemble"].as_slice(),
+        ["--skip-bootstrap"].as_slice(),
+    ] {
+        let mut argv = vec!["rsdebstrap", "apply", "--only", "provision"];
+        argv.extend_from_slice(skip);
+        assert!(Cli::try_parse_from(argv).is_err(), "{skip:?} should conflict with --only");
+    }
+}
+
+#[test]
+fn test_parse_apply_command_rejects_unknown_phase() {
+    assert!(Cli::try_parse_from(["rsdebstrap", "apply", "--only", "prepare"]).is_err());
+}
+
+#[test]
+fn test_parse_apply_command_with_tags() -> Result<()> {
+    let args = Cli::parse_from([
+        "rsdebstrap",
+        "apply",
+        "--tags",
+        "base,net",
+        "--skip-tags",
+        "debug",
+        "--skip-tags",
+        "slow",
+    ]);
+
+    match args.command {
+        Commands::Apply(opts) => {
+            let filter = opts.tag_filter();
+            assert_eq!(filter.include, ["base", "net"]);
+            assert_eq!(filter.exclude, ["debug", "slow"]);
+        }
+        _ => panic!("Expected Apply command"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_apply_command_with_start_at_task() -> Result<()> {
+    let args = Cli::parse_from([
+        "rsdebstrap",
+        "apply",
+        "--skip-bootstrap",
+        "--start-at-task",
+        "configure users",
+    ]);
+
+    match args.command {
+        Commands::Apply(opts) => {
+            assert_eq!(opts.start_at_task.as_deref(), Some("configure users"));
+        }
+        _ => panic!("Expected Apply command"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_remote_args_reproduce_the_apply_options() {
+    let args = Cli::parse_from([
+        "rsdebstrap",
+        "apply",
+        "--file",
+        "profiles/web.yml",
+        "--log-level",
+        "debug",
+        "--dry-run",
+        "--skip",
+        "assemble",
+        "--tags",
+        "base,web",
+        "--start-at-task",
+        "configure users",
+        "--layer-cache",
+        "cache",
+        "--metrics",
+        "prometheus",
+        "--metrics",
+        "otel",
+        "--no-strict",
+        "--no-user-config",
+        "--arch",
+        "arm64",
+        "--set",
+        "bootstrap.suite=trixie",
+        "--set",
+        "bootstrap.include=[vim, curl]",
+        "--remote",
+        "ci@arm64-builder",
+    ]);
+    let Commands::Apply(opts) = args.command else {
+        panic!("Expected Apply command");
+    };
+    assert_eq!(opts.remote.as_deref(), Some("ci@arm64-builder"));
+    assert_eq!(opts.remote_dir, Utf8PathBuf::from("rsdebstrap-remote"));
+
+    let remote_args = opts.remote_args("web.yml");
+    let reparsed = Cli::parse_from(std::iter::once("rsdebstrap".to_string()).chain(remote_args));
+    let Commands::Apply(remote) = reparsed.command else {
+        panic!("Expected Apply command");
+    };
+    assert_eq!(remote.common.file, Utf8PathBuf::from("web.yml"));
+    assert_eq!(remote.common.log_level, LogLevel::Debug);
+    assert!(remote.dry_run);
+    assert_eq!(remote.skip, [ApplyPhase::Assemble]);
+    assert_eq!(remote.tags, ["base", "web"]);
+    assert_eq!(remote.start_at_task.as_deref(), Some("configure users"));
+    assert_eq!(remote.layer_cache, Some(Utf8PathBuf::from(".rsdebstrap-layer-cache")));
+    assert_eq!(remote.metrics, [MetricsFormat::Prometheus, MetricsFormat::Otel]);
+    assert_eq!(remote.strictness.unknown_fields(), UnknownFields::Warn);
+    assert!(remote.common.no_user_config);
+    assert_eq!(remote.target.arch.as_deref(), Some("arm64"));
+    assert_eq!(remote.target.set, opts.target.set);
+    assert_eq!(remote.target.set[1].value, "[vim, curl]");
+    assert_eq!(remote.remote, None);
+}
+
+#[test]
+fn test_parse_arch_selects_a_target() {
+    let args = Cli::parse_from(["rsdebstrap", "validate", "--arch", "arm64"]);
+    let Commands::Validate(opts) = args.command else {
+        panic!("Expected Validate command");
+    };
+    assert_eq!(opts.target.arch.as_deref(), Some("arm64"));
+    assert!(Cli::try_parse_from(["rsdebstrap", "doctor", "--arch", "arm64"]).is_err());
+}
+
+#[test]
+fn test_parse_apply_remote_dir_requires_remote() {
+    assert!(Cli::try_parse_from(["rsdebstrap", "apply", "--remote-dir", "builds"]).is_err());
+}
+
+#[test]
+fn test_parse_apply_events() {
+    let args = Cli::parse_from(["rsdebstrap", "apply", "--events-fd", "3"]);
+    match args.command {
+        Commands::Apply(opts) => {
+            assert_eq!(opts.events_fd, Some(3));
+            assert_eq!(opts.events_file, None);
+        }
+        _ => panic!("Expected Apply command"),
+    }
+
+    let args = Cli::parse_from(["rsdebstrap", "apply", "--events-file", "events.jsonl"]);
+    match args.command {
+        Commands::Apply(opts) => {
+            assert_eq!(opts.events_file, Some(Utf8PathBuf::from("events.jsonl")));
+        }
+        _ => panic!("Expected Apply command"),
+    }
+
+    for argv in [
+        &[
+            "rsdebstrap",
+            "apply",
+            "--events-fd",
+            "3",
+            "--events-file",
+            "e.jsonl",
+        ][..],
+        &[
+            "rsdebstrap",
+            "apply",
+            "--events-fd",
+            "3",
+            "--remote",
+            "builder",
+        ],
+        &[
+            "rsdebstrap",
+            "apply",
+            "--events-file",
+            "e.jsonl",
+            "--remote",
+            "builder",
+        ],
+    ] {
+        assert!(Cli::try_parse_from(argv).is_err(), "{argv:?} should be rejected");
+    }
+}
+
+#[test]
+fn test_parse_apply_command_plan_requires_dry_run() {
+    assert!(Cli::try_parse_from(["rsdebstrap", "apply", "--plan"]).is_err());
+
+    let args = Cli::parse_from(["rsdebstrap", "apply", "--dry-run", "--plan"]);
+    match args.command {
+        Commands::Apply(opts) => assert!(opts.plan),
+        _ => panic!("Expected Apply command"),
+    }
+}
+
 #[test]
 fn test_parse_validate_command() -> Result<()> {
     let args = Cli::parse_from(["rsdebstrap", "validate", "--file", "test.yml"]);
@@ -50,9 +324,181 @@ fn test_parse_validate_command() -> Result<()> {
     match args.command {
         Commands::Validate(opts) => {
             assert_eq!(opts.common.file, Utf8PathBuf::from("test.yml"));
+            assert!(!opts.resolved);
         }
         _ => panic!("Expected Validate command"),
     }
 
     Ok(())
 }
+
+#[test]
+fn test_parse_validate_resolved() -> Result<()> {
+    let args = Cli::parse_from(["rsdebstrap", "validate", "--file", "test.yml", "--resolved"]);
+
+    match args.command {
+        Commands::Validate(opts) => assert!(opts.resolved),
+        _ => panic!("Expected Validate command"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_strictness() {
+    let unknown_fields = |argv: &[&str]| match Cli::parse_from(argv).command {
+        Commands::Validate(opts) => opts.strictness.unknown_fields(),
+        _ => panic!("Expected Validate command"),
+    };
+    assert_eq!(unknown_fields(&["rsdebstrap", "validate"]), UnknownFields::Deny);
+    assert_eq!(unknown_fields(&["rsdebstrap", "validate", "--no-strict"]), UnknownFields::Warn);
+    assert_eq!(
+        unknown_fields(&["rsdebstrap", "validate", "--no-strict", "--strict"]),
+        UnknownFields::Deny
+    );
+    assert_eq!(
+        unknown_fields(&["rsdebstrap", "validate", "--strict", "--no-strict"]),
+        UnknownFields::Warn
+    );
+}
+
+#[test]
+fn test_parse_validate_lint() {
+    let args = Cli::parse_from(["rsdebstrap", "validate", "--file", "test.yml", "--lint"]);
+    match args.command {
+        Commands::Validate(opts) => assert!(opts.lint),
+        _ => panic!("Expected Validate command"),
+    }
+
+    assert!(Cli::try_parse_from(["rsdebstrap", "validate", "--lint", "--resolved"]).is_err());
+}
+
+#[test]
+fn test_parse_doctor_command() {
+    let args = Cli::parse_from(["rsdebstrap", "doctor"]);
+    match args.command {
+        Commands::Doctor(opts) => assert_eq!(opts.file, None),
+        _ => panic!("Expected Doctor command"),
+    }
+
+    let args = Cli::parse_from(["rsdebstrap", "doctor", "-f", "test.yml", "--no-strict"]);
+    match args.command {
+        Commands::Doctor(opts) => {
+            assert_eq!(opts.file, Some(Utf8PathBuf::from("test.yml")));
+            assert!(opts.strictness.no_strict);
+        }
+        _ => panic!("Expected Doctor command"),
+    }
+}
+
+#[test]
+fn test_parse_diff_command() -> Result<()> {
+    let args = Cli::parse_from(["rsdebstrap", "diff", "--file", "test.yml"]);
+
+    match args.command {
+        Commands::Diff(opts) => {
+            assert_eq!(opts.common.file, Utf8PathBuf::from("test.yml"));
+        }
+        _ => panic!("Expected Diff command"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_serve_command() -> Result<()> {
+    let args = Cli::parse_from(["rsdebstrap", "serve"]);
+    match args.command {
+        Commands::Serve(opts) => {
+            assert_eq!(opts.socket, Utf8PathBuf::from("rsdebstrap.sock"));
+            assert_eq!(opts.max_builds, 1);
+        }
+        _ => panic!("Expected Serve command"),
+    }
+
+    let args = Cli::parse_from([
+        "rsdebstrap",
+        "serve",
+        "--socket",
+        "/run/rsdebstrap.sock",
+        "--max-builds",
+        "8",
+    ]);
+    match args.command {
+        Commands::Serve(opts) => {
+            assert_eq!(opts.socket, Utf8PathBuf::from("/run/rsdebstrap.sock"));
+            assert_eq!(opts.max_builds, 8);
+        }
+        _ => panic!("Expected Serve command"),
+    }
+
+    assert!(Cli::try_parse_from(["rsdebstrap", "serve", "--max-builds", "0"]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_init_command_defaults() -> Result<()> {
+    let args = Cli::parse_from(["rsdebstrap", "init"]);
+
+    match args.command {
+        Commands::Init(opts) => {
+            assert_eq!(opts.common.file, Utf8PathBuf::from("profile.yml"));
+            assert_eq!(opts.backend, InitBackend::Mmdebstrap);
+            assert_eq!(opts.suite, "trixie");
+            assert!(opts.mirror.is_empty());
+            assert_eq!(opts.dir, Utf8PathBuf::from("output"));
+            assert_eq!(opts.target, "rootfs");
+            assert_eq!(opts.privilege, None);
+            assert!(!opts.no_example_task);
+            assert!(!opts.interactive);
+            assert!(!opts.force);
+        }
+        _ => panic!("Expected Init command"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_parse_init_command_with_flags() -> Result<()> {
+    let args = Cli::parse_from([
+        "rsdebstrap",
+        "init",
+        "-f",
+        "bookworm.yml",
+        "--backend",
+        "debootstrap",
+        "--suite",
+        "bookworm",
+        "--mirror",
+        "http://ftp.jp.debian.org/debian",
+        "--dir",
+        "/srv/images",
+        "--target",
+        "root",
+        "--privilege",
+        "doas",
+        "--no-example-task",
+        "--interactive",
+        "--force",
+    ]);
+
+    match args.command {
+        Commands::Init(opts) => {
+            assert_eq!(opts.common.file, Utf8PathBuf::from("bookworm.yml"));
+            assert_eq!(opts.backend, InitBackend::Debootstrap);
+            assert_eq!(opts.suite, "bookworm");
+            assert_eq!(opts.mirror, vec!["http://ftp.jp.debian.org/debian"]);
+            assert_eq!(opts.dir, Utf8PathBuf::from("/srv/images"));
+            assert_eq!(opts.target, "root");
+            assert_eq!(opts.privilege, Some(PrivilegeMethod::Doas));
+            assert!(opts.no_example_task);
+            assert!(opts.interactive);
+            assert!(opts.force);
+        }
+        _ => panic!("Expected Init command"),
+    }
+
+    Ok(())
+}
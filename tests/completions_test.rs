@@ -1,9 +1,10 @@
-//! Tests for shell completion functionality.
+//! Tests for shell completion and man page functionality.
 //!
-//! This module tests the completions subcommand, ensuring that:
+//! This module tests the completions and man subcommands, ensuring that:
 //! - Completions can be parsed for all supported shells
 //! - Generation produces valid output without panicking
 //! - The CLI correctly handles completion requests
+//! - The man page is rendered from the CLI definitions
 
 use anyhow::Result;
 use clap::{Parser, ValueEnum};
@@ -141,3 +142,43 @@ fn test_invalid_shell_rejected() {
     let result = Cli::try_parse_from(["rsdebstrap", "completions", "invalid-shell"]);
     assert!(result.is_err(), "Expected parsing to fail for invalid shell");
 }
+
+/// Test parsing the man command.
+#[test]
+fn test_man_command_parsing() {
+    let args = Cli::parse_from(["rsdebstrap", "man"]);
+    assert!(matches!(args.command, Commands::Man), "Expected Man command");
+}
+
+/// Test that the rendered man page documents the CLI definitions.
+#[test]
+fn test_man_page_contents() -> Result<()> {
+    let page = String::from_utf8(rsdebstrap::render_man_page()?)?;
+
+    for pattern in [
+        ".TH rsdebstrap 1",
+        "apply",
+        "validate",
+        "completions",
+        "man",
+    ] {
+        assert!(page.contains(pattern), "Pattern '{}' not found in man page", pattern);
+    }
+
+    Ok(())
+}
+
+/// Integration test: Test actual CLI invocation for the man page.
+#[test]
+fn test_cli_man_output() -> Result<()> {
+    let output = std::process::Command::new("cargo")
+        .args(["run", "--quiet", "--", "man"])
+        .output()?;
+
+    assert!(output.status.success(), "Command failed to execute");
+
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains(".TH rsdebstrap 1"));
+
+    Ok(())
+}
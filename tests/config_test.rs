@@ -4,8 +4,17 @@ use anyhow::Result;
 use camino::{Utf8Path, Utf8PathBuf};
 use rsdebstrap::RsdebstrapError;
 use rsdebstrap::bootstrap::mmdebstrap::{self, Format};
-use rsdebstrap::config::load_profile;
-use rsdebstrap::phase::ProvisionTask;
+use rsdebstrap::bootstrap::suite;
+use rsdebstrap::config::{
+    Bootstrap, EnvOverrides, ProfileLayers, UnknownFields, load_profile, load_profile_layered,
+};
+use rsdebstrap::isolation::staging::Staging;
+use rsdebstrap::output_dir::{FileMode, Owner};
+use rsdebstrap::phase::{ProvisionTask, VerifyTask};
+use rsdebstrap::pipeline::FailurePolicy;
+use rsdebstrap::privilege::{PrivilegeDefaults, PrivilegeMethod};
+use rsdebstrap::shellcheck::ShellcheckSeverity;
+use rsdebstrap::user_config::UserConfig;
 use tempfile::tempdir;
 
 #[test]
@@ -1095,8 +1104,8 @@ invalid: yaml
     // editorconfig-checker-enable
     let err = result.unwrap_err();
     assert!(
-        matches!(err, RsdebstrapError::Config(_)),
-        "Expected RsdebstrapError::Config, got: {:?}",
+        matches!(err, RsdebstrapError::ConfigParse { line: Some(_), .. }),
+        "Expected RsdebstrapError::ConfigParse with a line, got: {:?}",
         err
     );
 
@@ -1368,6 +1377,160 @@ provision:
     Ok(())
 }
 
+#[test]
+fn test_load_profile_mitamae_defaults_binary_follows_target_arch() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let profile_path = temp_dir.path().join("profile.yml");
+    let binary_path = temp_dir.path().join("mitamae-aarch64");
+    std::fs::write(&binary_path, "fake mitamae binary")?;
+
+    // editorconfig-checker-disable
+    std::fs::write(
+        &profile_path,
+        crate::yaml!(
+            r#"---
+dir: /tmp/test
+defaults:
+  mitamae:
+    binary:
+      aarch64: mitamae-aarch64
+bootstrap:
+  type: mmdebstrap
+  suite: bookworm
+  target: rootfs
+  format: directory
+  architectures: [arm64]
+provision:
+  - type: mitamae
+    content: "package 'vim'"
+"#
+        ),
+    )?;
+    // editorconfig-checker-enable
+
+    let path = Utf8Path::from_path(&profile_path).unwrap();
+    let profile = load_profile(path)?;
+
+    match profile.provision.as_slice() {
+        [ProvisionTask::Mitamae(mitamae)] => {
+            assert_eq!(
+                mitamae.binary().unwrap().canonicalize_utf8()?,
+                Utf8PathBuf::from_path_buf(binary_path.canonicalize()?).unwrap(),
+                "binary should be selected by the target architecture, not the host's"
+            );
+        }
+        _ => panic!("expected one mitamae task"),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_load_profile_mitamae_defaults_version_selects_release_for_target_arch() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let profile_path = temp_dir.path().join("profile.yml");
+    let digest = "a".repeat(64);
+
+    // editorconfig-checker-disable
+    std::fs::write(
+        &profile_path,
+        format!(
+            r#"---
+dir: /tmp/test
+defaults:
+  mitamae:
+    version: 1.14.1
+    sha256:
+      aarch64: {}
+bootstrap:
+  type: mmdebstrap
+  suite: bookworm
+  target: rootfs
+  format: directory
+  architectures: [arm64]
+provision:
+  - type: mitamae
+    content: "package 'vim'"
+"#,
+            digest
+        ),
+    )?;
+    // editorconfig-checker-enable
+
+    let path = Utf8Path::from_path(&profile_path).unwrap();
+    let profile = load_profile(path)?;
+
+    match profile.provision.as_slice() {
+        [ProvisionTask::Mitamae(mitamae)] => {
+            assert_eq!(mitamae.binary(), None, "release binary is set once downloaded");
+            assert_eq!(
+                mitamae.download_url(),
+                Some(
+                    "https://github.com/itamae-kitchen/mitamae/releases/download/v1.14.1/\
+                    mitamae-aarch64-linux.tar.gz"
+                )
+            );
+        }
+        _ => panic!("expected one mitamae task"),
+    }
+    profile.defaults.mitamae.validate()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_mitamae_defaults_version_validation() -> Result<()> {
+    let cases = [
+        ("version: latest", "defaults.mitamae.version"),
+        ("sha256:\n      aarch64: {digest}", "defaults.mitamae.version"),
+        ("version: 1.14.1\n    sha256:\n      aarch64: nothex", "defaults.mitamae.sha256"),
+        (
+            "version: 1.14.1\n    sha256:\n      x86_64: {digest}",
+            "defaults.mitamae.sha256.aarch64",
+        ),
+    ];
+    for (mitamae, expected) in cases {
+        let temp_dir = tempdir()?;
+        let profile_path = temp_dir.path().join("profile.yml");
+        let mitamae = mitamae.replace("{digest}", &"a".repeat(64));
+        // editorconfig-checker-disable
+        std::fs::write(
+            &profile_path,
+            format!(
+                r#"---
+dir: /tmp/test
+defaults:
+  mitamae:
+    {}
+bootstrap:
+  type: mmdebstrap
+  suite: bookworm
+  target: rootfs
+  format: directory
+  architectures: [arm64]
+provision:
+  - type: mitamae
+    content: "package 'vim'"
+"#,
+                mitamae
+            ),
+        )?;
+        // editorconfig-checker-enable
+
+        let path = Utf8Path::from_path(&profile_path).unwrap();
+        let err = load_profile(path)?.validate().unwrap_err();
+        assert!(
+            matches!(err, RsdebstrapError::Validation(_)),
+            "Expected Validation error for {:?}, got: {:?}",
+            mitamae,
+            err
+        );
+        assert!(err.to_string().contains(expected), "for {:?}, got: {}", mitamae, err);
+    }
+
+    Ok(())
+}
+
 // =============================================================================
 // Task-level isolation tests
 // =============================================================================
@@ -1604,12 +1767,8 @@ provision:
     Ok(())
 }
 
-// =============================================================================
-// Mount configuration tests
-// =============================================================================
-
 #[test]
-fn test_load_profile_with_mount_preset() -> Result<()> {
+fn test_load_profile_chroot_isolation_options() -> Result<()> {
     // editorconfig-checker-disable
     let profile = helpers::load_profile_from_yaml(crate::yaml!(
         r#"---
@@ -1622,58 +1781,321 @@ bootstrap:
   suite: bookworm
   target: rootfs
   format: directory
-prepare:
-  mount:
-    preset: recommends
+provision:
+  - type: shell
+    content: make install
+    isolation:
+      type: chroot
+      workdir: /srv/app
+      user: builder:staff
+      binds:
+        - source: /tmp
+          target: /srv/app/cache
+        - source: /tmp
+          target: /run/secrets
+          ro: true
 "#
     ))?;
     // editorconfig-checker-enable
 
-    let mount_task = profile.prepare.mount.as_ref().expect("Expected mount task");
-    assert!(mount_task.preset.is_some(), "Expected preset to be set");
+    use rsdebstrap::config::{ChrootBind, ChrootIsolation, IsolationConfig};
 
-    let mounts = mount_task.resolved_mounts();
-    assert_eq!(mounts.len(), 6, "Recommends preset should have 6 entries");
+    let ProvisionTask::Shell(task) = &profile.provision[0] else {
+        panic!("Expected Shell task, got: {:?}", profile.provision[0]);
+    };
+    assert_eq!(
+        task.resolved_isolation_config(),
+        Some(&IsolationConfig::Chroot(ChrootIsolation {
+            network: None,
+            workdir: Some("/srv/app".into()),
+            user: Some("builder:staff".to_string()),
+            clean_env: false,
+            path: None,
+            env: Default::default(),
+            binds: vec![
+                ChrootBind {
+                    source: "/tmp".into(),
+                    target: "/srv/app/cache".into(),
+                    ro: false,
+                },
+                ChrootBind {
+                    source: "/tmp".into(),
+                    target: "/run/secrets".into(),
+                    ro: true,
+                },
+            ],
+            unmount_policy: None,
+            network_files: vec![],
+            block_services: false,
+            mount_namespace: None,
+        }))
+    );
+    profile.validate()?;
 
     Ok(())
 }
 
 #[test]
-fn test_load_profile_with_custom_mounts() -> Result<()> {
+fn test_profile_loads_chroot_environment_options() -> Result<()> {
+    let options = "      clean_env: true\n      path: /usr/sbin:/usr/bin\n      \
+                   env:\n        DEBIAN_FRONTEND: noninteractive\n";
+    let profile = helpers::load_profile_from_yaml(chroot_options_profile("", options))?;
+
+    let ProvisionTask::Shell(task) = &profile.provision[0] else {
+        panic!("Expected Shell task, got: {:?}", profile.provision[0]);
+    };
+    let Some(rsdebstrap::config::IsolationConfig::Chroot(chroot)) =
+        task.resolved_isolation_config()
+    else {
+        panic!("Expected chroot isolation");
+    };
+    assert_eq!(
+        chroot.env_args(None),
+        [
+            "-i",
+            "PATH=/usr/sbin:/usr/bin",
+            "HOME=/root",
+            "DEBIAN_FRONTEND=noninteractive"
+        ]
+    );
+    profile.validate()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_loads_unmount_policy() -> Result<()> {
+    use rsdebstrap::config::{DEFAULT_UNMOUNT_RETRY_DELAY_MS, UnmountMode, UnmountPolicy};
+    use std::time::Duration;
+
+    let defaults = "defaults:\n  privilege:\n    method: sudo\n  isolation:\n    type: chroot\n    \
+                    unmount_policy:\n      retries: 2\n      lazy: true\n";
+    let options = "      unmount_policy:\n        retries: 1\n        retry_delay_ms: 0\n        \
+                   force: true\n";
+    let profile = helpers::load_profile_from_yaml(chroot_options_profile(defaults, options))?;
+
+    let policy = profile.defaults.isolation.unmount_policy();
+    assert_eq!(policy.retries, 2);
+    assert_eq!(policy.retry_delay(), Duration::from_millis(DEFAULT_UNMOUNT_RETRY_DELAY_MS));
+    assert_eq!(policy.escalation().collect::<Vec<_>>(), [UnmountMode::Lazy]);
+
+    // A task-level isolation map replaces the defaults as a whole.
+    let ProvisionTask::Shell(task) = &profile.provision[0] else {
+        panic!("Expected Shell task, got: {:?}", profile.provision[0]);
+    };
+    let policy = task
+        .resolved_isolation_config()
+        .expect("task isolation")
+        .unmount_policy();
+    assert_eq!(
+        policy,
+        UnmountPolicy {
+            retries: 1,
+            retry_delay_ms: Some(0),
+            lazy: false,
+            force: true,
+        }
+    );
+    assert_eq!(policy.escalation().collect::<Vec<_>>(), [UnmountMode::Force]);
+    profile.validate()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_unmount_policy_defaults_to_single_attempt() -> Result<()> {
+    use rsdebstrap::config::UnmountPolicy;
+
+    let profile = helpers::load_profile_from_yaml(chroot_options_profile(SUDO_DEFAULTS, ""))?;
+    let policy = profile.defaults.isolation.unmount_policy();
+    assert_eq!(policy, UnmountPolicy::default());
+    assert_eq!(policy.retries, 0);
+    assert_eq!(policy.escalation().count(), 0);
+
+    let err = helpers::load_profile_from_yaml(chroot_options_profile(
+        SUDO_DEFAULTS,
+        "      unmount_policy:\n        detach: true\n",
+    ))
+    .unwrap_err();
+    assert!(format!("{err:#}").contains("unknown field"), "{err:#}");
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_loads_network_files() -> Result<()> {
+    use rsdebstrap::config::NetworkFile;
+
+    let defaults = "defaults:\n  isolation:\n    type: chroot\n    \
+                    network_files: [hosts, nsswitch]\n";
+    let profile = helpers::load_profile_from_yaml(chroot_options_profile(defaults, ""))?;
+
+    assert_eq!(
+        profile.defaults.isolation.network_files(),
+        [NetworkFile::Hosts, NetworkFile::Nsswitch]
+    );
+    assert_eq!(NetworkFile::Nsswitch.path(), "/etc/nsswitch.conf");
+    profile.validate()?;
+
+    let err = helpers::load_profile_from_yaml(chroot_options_profile(
+        "defaults:\n  isolation:\n    type: chroot\n    network_files: [resolv]\n",
+        "",
+    ))
+    .unwrap_err();
+    assert!(format!("{err:#}").contains("unknown variant"), "{err:#}");
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_loads_block_services() -> Result<()> {
+    let defaults = "defaults:\n  isolation:\n    type: chroot\n    block_services: true\n";
+    let profile = helpers::load_profile_from_yaml(chroot_options_profile(defaults, ""))?;
+    assert!(profile.defaults.isolation.block_services());
+
+    let profile = helpers::load_profile_from_yaml(chroot_options_profile("", ""))?;
+    assert!(!profile.defaults.isolation.block_services());
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_loads_mount_namespace() -> Result<()> {
+    let profile = helpers::load_profile_from_yaml(chroot_options_profile("", ""))?;
+    assert!(profile.defaults.isolation.mount_namespace());
+    assert!(!profile.defaults.isolation.requires_mount_namespace());
+
+    let defaults = "defaults:\n  isolation:\n    type: chroot\n    mount_namespace: false\n";
+    let profile = helpers::load_profile_from_yaml(chroot_options_profile(defaults, ""))?;
+    assert!(!profile.defaults.isolation.mount_namespace());
+    assert!(!profile.defaults.isolation.requires_mount_namespace());
+
+    let defaults = "defaults:\n  isolation:\n    type: chroot\n    mount_namespace: true\n";
+    let profile = helpers::load_profile_from_yaml(chroot_options_profile(defaults, ""))?;
+    assert!(profile.defaults.isolation.requires_mount_namespace());
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_validation_rejects_duplicate_network_files() -> Result<()> {
+    let defaults = "defaults:\n  isolation:\n    type: chroot\n    \
+                    network_files: [hosts, hosts]\n";
+    let profile = helpers::load_profile_from_yaml(chroot_options_profile(defaults, ""))?;
+
+    let err = profile.validate().unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("network_files lists /etc/hosts more than once"),
+        "{err}"
+    );
+
+    Ok(())
+}
+
+/// Builds a profile whose single shell task uses the given chroot isolation
+/// options (indented YAML mapping lines) and `defaults` block.
+fn chroot_options_profile(defaults: &str, options: &str) -> String {
+    format!(
+        "dir: /tmp/test\n\
+         {defaults}\
+         bootstrap:\n  type: mmdebstrap\n  suite: bookworm\n  target: rootfs\n  \
+         format: directory\n\
+         provision:\n  - type: shell\n    content: make\n    isolation:\n      \
+         type: chroot\n{options}"
+    )
+}
+
+const SUDO_DEFAULTS: &str = "defaults:\n  privilege:\n    method: sudo\n";
+
+#[test]
+fn test_profile_validation_rejects_invalid_chroot_options() -> Result<()> {
+    for (options, expected) in [
+        ("      workdir: srv/app\n", "must be an absolute path"),
+        ("      workdir: /srv/../etc\n", ".."),
+        ("      user: \"-root\"\n", "must be 'user' or 'user:group'"),
+        ("      user: \"builder:\"\n", "must be 'user' or 'user:group'"),
+        ("      user: \"a b\"\n", "must be 'user' or 'user:group'"),
+        ("      path: /usr/bin:bin\n", "':'-separated absolute directories"),
+        ("      path: \"\"\n", "':'-separated absolute directories"),
+        ("      env:\n        1X: y\n", "must be letters, digits and '_'"),
+        ("      env:\n        PATH: /bin\n", "use 'path' instead"),
+        (
+            "      binds:\n        - source: /tmp\n          target: /\n",
+            "mount target '/' is not allowed",
+        ),
+        (
+            "      binds:\n        - source: /nonexistent/rsdebstrap\n          target: /mnt\n",
+            "chroot bind source not found",
+        ),
+    ] {
+        let profile =
+            helpers::load_profile_from_yaml(chroot_options_profile(SUDO_DEFAULTS, options))?;
+        let err = profile.validate().unwrap_err();
+        assert!(
+            matches!(err, RsdebstrapError::Validation(_)),
+            "Expected Validation error, got: {:?}",
+            err
+        );
+        let message = err.to_string();
+        assert!(message.contains("provision 1 isolation"), "{options}: {message}");
+        assert!(message.contains(expected), "{options}: {message}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_validation_chroot_binds_require_privilege() -> Result<()> {
+    let binds = "      binds:\n        - source: /tmp\n          target: /mnt\n";
+
+    let profile = helpers::load_profile_from_yaml(chroot_options_profile("", binds))?;
+    let err = profile.validate().unwrap_err();
+    assert!(err.to_string().contains("binds require a privilege method"), "{err}");
+
+    let userns = "defaults:\n  privilege:\n    method: userns\n";
+    let profile = helpers::load_profile_from_yaml(chroot_options_profile(userns, binds))?;
+    let err = profile.validate().unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("not supported with privilege method userns"),
+        "{err}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_validation_rejects_invalid_default_chroot_options() -> Result<()> {
     // editorconfig-checker-disable
     let profile = helpers::load_profile_from_yaml(crate::yaml!(
         r#"---
 dir: /tmp/test
 defaults:
-  privilege:
-    method: sudo
+  isolation:
+    type: chroot
+    workdir: relative
 bootstrap:
   type: mmdebstrap
   suite: bookworm
   target: rootfs
   format: directory
-prepare:
-  mount:
-    mounts:
-      - source: proc
-        target: /proc
-      - source: sysfs
-        target: /sys
 "#
     ))?;
     // editorconfig-checker-enable
 
-    let mount_task = profile.prepare.mount.as_ref().expect("Expected mount task");
-    let mounts = mount_task.resolved_mounts();
-    assert_eq!(mounts.len(), 2);
-    assert_eq!(mounts[0].source, "proc");
-    assert_eq!(mounts[1].source, "sysfs");
+    let err = profile.validate().unwrap_err();
+    assert!(
+        err.to_string()
+            .starts_with("validation error: defaults.isolation: "),
+        "{err}"
+    );
 
     Ok(())
 }
 
 #[test]
-fn test_load_profile_with_preset_and_custom_mounts() -> Result<()> {
+fn test_load_profile_task_mounts() -> Result<()> {
     // editorconfig-checker-disable
     let profile = helpers::load_profile_from_yaml(crate::yaml!(
         r#"---
@@ -1688,80 +2110,166 @@ bootstrap:
   format: directory
 prepare:
   mount:
-    preset: recommends
     mounts:
-      - source: /dev
-        target: /dev
-        options:
-          - bind
+      - source: proc
+        target: /proc
+provision:
+  - type: shell
+    content: make artifacts
+    mounts:
+      - source: /tmp
+        target: /artifacts
+        options: [bind]
+      - source: devpts
+        target: /dev/pts
+  - type: shell
+    content: echo no mounts
 "#
     ))?;
     // editorconfig-checker-enable
 
-    let mount_task = profile.prepare.mount.as_ref().expect("Expected mount task");
-    let mounts = mount_task.resolved_mounts();
-    // Recommends has 6, custom replaces /dev entry => 6
-    assert_eq!(mounts.len(), 6);
-
-    // The /dev entry should be the custom bind mount
-    let dev_entry = mounts.iter().find(|m| m.target.as_str() == "/dev").unwrap();
-    assert_eq!(dev_entry.source, "/dev");
-    assert!(dev_entry.options.contains(&"bind".to_string()), "Expected bind option");
+    let mounts = profile.provision[0].mounts();
+    assert_eq!(mounts.len(), 2);
+    assert_eq!(mounts[0].source, "/tmp");
+    assert_eq!(mounts[0].target.as_str(), "/artifacts");
+    assert!(mounts[0].is_bind_mount());
+    assert!(mounts[1].is_pseudo_fs());
+    assert!(profile.provision[1].mounts().is_empty());
+    profile.validate()?;
 
     Ok(())
 }
 
 #[test]
-fn test_profile_validation_mounts_require_privilege() -> Result<()> {
+fn test_load_profile_task_tags() -> Result<()> {
     // editorconfig-checker-disable
     let profile = helpers::load_profile_from_yaml(crate::yaml!(
         r#"---
 dir: /tmp/test
-defaults:
-  isolation:
-    type: chroot
 bootstrap:
   type: mmdebstrap
   suite: bookworm
   target: rootfs
   format: directory
-prepare:
-  mount:
-    preset: recommends
+provision:
+  - type: shell
+    content: echo debug
+    tags: [debug, verbose]
+  - type: shell
+    content: echo untagged
 "#
     ))?;
     // editorconfig-checker-enable
 
-    let err = profile.validate().unwrap_err();
-    assert!(
-        matches!(err, RsdebstrapError::Validation(_)),
-        "Expected Validation error, got: {:?}",
-        err
-    );
+    assert_eq!(profile.provision[0].tags(), ["debug", "verbose"]);
+    assert!(profile.provision[1].tags().is_empty());
+    profile.validate()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_load_profile_task_names() -> Result<()> {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+bootstrap:
+  type: mmdebstrap
+  suite: bookworm
+  target: rootfs
+  format: directory
+provision:
+  - type: shell
+    name: install packages
+    content: apt-get install -y vim
+  - type: shell
+    content: echo unnamed
+"#
+    ))?;
+    // editorconfig-checker-enable
+
+    assert_eq!(profile.provision[0].configured_name(), Some("install packages"));
+    assert_eq!(profile.provision[0].name(), "install packages");
+    assert_eq!(profile.provision[1].configured_name(), None);
+    assert_eq!(profile.provision[1].name(), "shell:<inline>");
+    profile.validate()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_validation_rejects_duplicate_task_names() -> Result<()> {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+bootstrap:
+  type: mmdebstrap
+  suite: bookworm
+  target: rootfs
+  format: directory
+provision:
+  - type: shell
+    name: setup
+    content: echo one
+  - type: shell
+    content: echo two
+  - type: shell
+    name: setup
+    content: echo three
+"#
+    ))?;
+    // editorconfig-checker-enable
+
+    let err = profile.validate().unwrap_err();
     assert!(
-        err.to_string().contains("privilege"),
-        "Expected error about privilege, got: {}",
-        err
+        err.to_string()
+            .contains("provision 3 has the same name \"setup\" as provision 1"),
+        "{err}"
     );
-    // Pin the exact guard branch, not merely any message mentioning privilege.
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_validation_task_mounts_require_privilege() -> Result<()> {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+bootstrap:
+  type: mmdebstrap
+  suite: bookworm
+  target: rootfs
+  format: directory
+provision:
+  - type: shell
+    content: make artifacts
+    mounts:
+      - source: proc
+        target: /proc
+"#
+    ))?;
+    // editorconfig-checker-enable
+
+    let err = profile.validate().unwrap_err();
     assert!(
-        err.to_string().contains("must be configured"),
-        "Expected the 'defaults.privilege must be configured' guard, got: {}",
-        err
+        err.to_string()
+            .contains("provision 1 mounts: mounts require a privilege method"),
+        "{err}"
     );
 
     Ok(())
 }
 
 #[test]
-fn test_profile_validation_mount_order_error() -> Result<()> {
+fn test_profile_validation_task_mounts_must_not_cover_prepare_mounts() -> Result<()> {
     // editorconfig-checker-disable
     let profile = helpers::load_profile_from_yaml(crate::yaml!(
         r#"---
 dir: /tmp/test
 defaults:
-  isolation:
-    type: chroot
   privilege:
     method: sudo
 bootstrap:
@@ -1771,39 +2279,73 @@ bootstrap:
   format: directory
 prepare:
   mount:
+    preset: recommends
+provision:
+  - type: shell
+    content: make artifacts
     mounts:
-      - source: devpts
-        target: /dev/pts
-      - source: devtmpfs
+      - source: /tmp
         target: /dev
+        options: [bind]
 "#
     ))?;
     // editorconfig-checker-enable
 
     let err = profile.validate().unwrap_err();
     assert!(
-        matches!(err, RsdebstrapError::Validation(_)),
-        "Expected Validation error, got: {:?}",
-        err
-    );
-    assert!(
-        err.to_string().contains("mount order"),
-        "Expected error about mount order, got: {}",
-        err
+        err.to_string()
+            .contains("'/dev' would mount over pipeline mount target '/dev'"),
+        "{err}"
     );
 
     Ok(())
 }
 
 #[test]
-fn test_profile_validation_accepts_mounts_with_chroot_and_privilege() -> Result<()> {
+fn test_profile_validation_task_mounts_checks_entries_and_order() -> Result<()> {
+    for (mounts, expected) in [
+        (
+            "      - source: devpts\n        target: /dev/pts\n      \
+             - source: devtmpfs\n        target: /dev\n",
+            "mount order error",
+        ),
+        (
+            "      - source: tmp\n        target: /scratch\n",
+            "not a recognized pseudo-filesystem",
+        ),
+    ] {
+        let yaml = format!(
+            "dir: /tmp/test\n\
+             defaults:\n  privilege:\n    method: sudo\n\
+             bootstrap:\n  type: mmdebstrap\n  suite: bookworm\n  target: rootfs\n  \
+             format: directory\n\
+             provision:\n  - type: shell\n    content: make\n    mounts:\n{mounts}"
+        );
+        let profile = helpers::load_profile_from_yaml(yaml)?;
+        let err = profile.validate().unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("provision 1 validation failed"), "{message}");
+        assert!(message.contains(expected), "{message}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_load_profile_context_is_resolved_and_mounted_read_only() -> Result<()> {
+    let temp_dir = tempdir()?;
+    let profile_path = temp_dir.path().join("profile.yml");
+    let context_dir = temp_dir.path().join("context");
+    std::fs::create_dir(&context_dir)?;
+
     // editorconfig-checker-disable
-    let profile = helpers::load_profile_from_yaml(crate::yaml!(
-        r#"---
+    std::fs::write(
+        &profile_path,
+        crate::yaml!(
+            r#"---
 dir: /tmp/test
+context: context
 defaults:
-  isolation:
-    type: chroot
   privilege:
     method: sudo
 bootstrap:
@@ -1817,23 +2359,56 @@ prepare:
       - source: proc
         target: /proc
 "#
-    ))?;
+        ),
+    )?;
     // editorconfig-checker-enable
 
-    assert!(profile.validate().is_ok());
+    let profile = load_profile(Utf8Path::from_path(&profile_path).unwrap())?;
+    let context = profile.context.clone().expect("context should be set");
+    assert_eq!(context.as_std_path(), context_dir);
+    profile.validate()?;
+
+    let mounts = profile.pipeline_mounts();
+    assert_eq!(mounts.len(), 2);
+    let last = mounts.last().unwrap();
+    assert_eq!(last.source, context.as_str());
+    assert_eq!(last.target.as_str(), rsdebstrap::config::CONTEXT_MOUNT_POINT);
+    assert_eq!(last.options, ["bind", "ro"]);
 
     Ok(())
 }
 
 #[test]
-fn test_profile_validation_rejects_pseudo_fs_with_bind_mount() -> Result<()> {
+fn test_profile_validation_rejects_invalid_context() -> Result<()> {
+    let base = "bootstrap:\n  type: mmdebstrap\n  suite: bookworm\n  target: rootfs\n";
+    for (extra, expected) in [
+        (
+            "context: /nonexistent/rsdebstrap-context\n\
+             defaults:\n  privilege:\n    method: sudo\n",
+            "context must be an existing directory",
+        ),
+        ("context: /tmp\n", "defaults.privilege must be configured when context"),
+        (
+            "context: /tmp\ndefaults:\n  privilege:\n    method: userns\n",
+            "context is not supported with privilege method userns",
+        ),
+    ] {
+        let profile = helpers::load_profile_from_yaml(format!("dir: /tmp/test\n{extra}{base}"))?;
+        let err = profile.validate().unwrap_err();
+        assert!(err.to_string().contains(expected), "{extra}: {err}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_validation_task_mounts_must_not_cover_context() -> Result<()> {
     // editorconfig-checker-disable
     let profile = helpers::load_profile_from_yaml(crate::yaml!(
         r#"---
 dir: /tmp/test
+context: /tmp
 defaults:
-  isolation:
-    type: chroot
   privilege:
     method: sudo
 bootstrap:
@@ -1841,139 +2416,150 @@ bootstrap:
   suite: bookworm
   target: rootfs
   format: directory
-prepare:
-  mount:
+provision:
+  - type: shell
+    content: make
     mounts:
-      - source: proc
-        target: /proc
-        options:
-          - bind
+      - source: tmpfs
+        target: /run
 "#
     ))?;
     // editorconfig-checker-enable
 
     let err = profile.validate().unwrap_err();
     assert!(
-        matches!(err, RsdebstrapError::Validation(_)),
-        "Expected Validation error, got: {:?}",
-        err
-    );
-    assert!(
-        err.to_string().contains("pseudo-filesystem"),
-        "Expected error about pseudo-filesystem, got: {}",
-        err
+        err.to_string()
+            .contains("would mount over pipeline mount target '/run/rsdebstrap/context'"),
+        "{err}"
     );
 
     Ok(())
 }
 
 // =============================================================================
-// resolv_conf integration tests
+// Mount configuration tests
 // =============================================================================
 
 #[test]
-fn test_load_profile_with_resolv_conf_copy() -> Result<()> {
+fn test_load_profile_with_mount_preset() -> Result<()> {
     // editorconfig-checker-disable
     let profile = helpers::load_profile_from_yaml(crate::yaml!(
         r#"---
 dir: /tmp/test
+defaults:
+  privilege:
+    method: sudo
 bootstrap:
   type: mmdebstrap
   suite: bookworm
   target: rootfs
   format: directory
 prepare:
-  resolv_conf:
-    copy: true
+  mount:
+    preset: recommends
 "#
     ))?;
     // editorconfig-checker-enable
 
-    let rc_task = profile
-        .prepare
-        .resolv_conf
-        .as_ref()
-        .expect("Expected resolv_conf task");
-    assert!(rc_task.copy);
-    assert!(rc_task.name_servers.is_empty());
-    assert!(rc_task.search.is_empty());
+    let mount_task = profile.prepare.mount.as_ref().expect("Expected mount task");
+    assert!(mount_task.preset.is_some(), "Expected preset to be set");
+
+    let mounts = mount_task.resolved_mounts();
+    assert_eq!(mounts.len(), 6, "Recommends preset should have 6 entries");
 
     Ok(())
 }
 
 #[test]
-fn test_load_profile_with_resolv_conf_name_servers() -> Result<()> {
+fn test_load_profile_with_custom_mounts() -> Result<()> {
     // editorconfig-checker-disable
     let profile = helpers::load_profile_from_yaml(crate::yaml!(
         r#"---
 dir: /tmp/test
+defaults:
+  privilege:
+    method: sudo
 bootstrap:
   type: mmdebstrap
   suite: bookworm
   target: rootfs
   format: directory
 prepare:
-  resolv_conf:
-    name_servers:
-      - 8.8.8.8
-      - 8.8.4.4
-    search:
-      - example.com
+  mount:
+    mounts:
+      - source: proc
+        target: /proc
+      - source: sysfs
+        target: /sys
 "#
     ))?;
     // editorconfig-checker-enable
 
-    let rc_task = profile
-        .prepare
-        .resolv_conf
-        .as_ref()
-        .expect("Expected resolv_conf task");
-    assert!(!rc_task.copy);
-    assert_eq!(rc_task.name_servers.len(), 2);
-    assert_eq!(rc_task.search, vec!["example.com"]);
+    let mount_task = profile.prepare.mount.as_ref().expect("Expected mount task");
+    let mounts = mount_task.resolved_mounts();
+    assert_eq!(mounts.len(), 2);
+    assert_eq!(mounts[0].source, "proc");
+    assert_eq!(mounts[1].source, "sysfs");
 
     Ok(())
 }
 
 #[test]
-fn test_load_profile_without_resolv_conf() -> Result<()> {
+fn test_load_profile_with_preset_and_custom_mounts() -> Result<()> {
     // editorconfig-checker-disable
     let profile = helpers::load_profile_from_yaml(crate::yaml!(
         r#"---
 dir: /tmp/test
 defaults:
-  isolation:
-    type: chroot
+  privilege:
+    method: sudo
 bootstrap:
   type: mmdebstrap
   suite: bookworm
   target: rootfs
   format: directory
+prepare:
+  mount:
+    preset: recommends
+    mounts:
+      - source: /dev
+        target: /dev
+        options:
+          - bind
 "#
     ))?;
     // editorconfig-checker-enable
 
-    assert!(profile.prepare.resolv_conf.is_none());
+    let mount_task = profile.prepare.mount.as_ref().expect("Expected mount task");
+    let mounts = mount_task.resolved_mounts();
+    // Recommends has 6, custom replaces /dev entry => 6
+    assert_eq!(mounts.len(), 6);
+
+    // The /dev entry should be the custom bind mount
+    let dev_entry = mounts.iter().find(|m| m.target.as_str() == "/dev").unwrap();
+    assert_eq!(dev_entry.source, "/dev");
+    assert!(dev_entry.options.contains(&"bind".to_string()), "Expected bind option");
 
     Ok(())
 }
 
 #[test]
-fn test_profile_validation_rejects_resolv_conf_copy_with_name_servers() -> Result<()> {
+fn test_profile_validation_mounts_require_privilege() -> Result<()> {
     // editorconfig-checker-disable
     let profile = helpers::load_profile_from_yaml(crate::yaml!(
         r#"---
 dir: /tmp/test
+defaults:
+  isolation:
+    type: chroot
 bootstrap:
   type: mmdebstrap
   suite: bookworm
   target: rootfs
   format: directory
 prepare:
-  resolv_conf:
-    copy: true
-    name_servers:
-      - 8.8.8.8
+  mount:
+    preset: recommends
 "#
     ))?;
     // editorconfig-checker-enable
@@ -1985,8 +2571,14 @@ prepare:
         err
     );
     assert!(
-        err.to_string().contains("mutually exclusive"),
-        "Expected error about mutual exclusivity, got: {}",
+        err.to_string().contains("privilege"),
+        "Expected error about privilege, got: {}",
+        err
+    );
+    // Pin the exact guard branch, not merely any message mentioning privilege.
+    assert!(
+        err.to_string().contains("must be configured"),
+        "Expected the 'defaults.privilege must be configured' guard, got: {}",
         err
     );
 
@@ -1994,15 +2586,255 @@ prepare:
 }
 
 #[test]
-fn test_profile_validation_rejects_resolv_conf_empty_config() -> Result<()> {
+fn test_profile_validation_mount_order_error() -> Result<()> {
     // editorconfig-checker-disable
     let profile = helpers::load_profile_from_yaml(crate::yaml!(
         r#"---
 dir: /tmp/test
-bootstrap:
-  type: mmdebstrap
-  suite: bookworm
-  target: rootfs
+defaults:
+  isolation:
+    type: chroot
+  privilege:
+    method: sudo
+bootstrap:
+  type: mmdebstrap
+  suite: bookworm
+  target: rootfs
+  format: directory
+prepare:
+  mount:
+    mounts:
+      - source: devpts
+        target: /dev/pts
+      - source: devtmpfs
+        target: /dev
+"#
+    ))?;
+    // editorconfig-checker-enable
+
+    let err = profile.validate().unwrap_err();
+    assert!(
+        matches!(err, RsdebstrapError::Validation(_)),
+        "Expected Validation error, got: {:?}",
+        err
+    );
+    assert!(
+        err.to_string().contains("mount order"),
+        "Expected error about mount order, got: {}",
+        err
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_validation_accepts_mounts_with_chroot_and_privilege() -> Result<()> {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+defaults:
+  isolation:
+    type: chroot
+  privilege:
+    method: sudo
+bootstrap:
+  type: mmdebstrap
+  suite: bookworm
+  target: rootfs
+  format: directory
+prepare:
+  mount:
+    mounts:
+      - source: proc
+        target: /proc
+"#
+    ))?;
+    // editorconfig-checker-enable
+
+    assert!(profile.validate().is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_validation_rejects_pseudo_fs_with_bind_mount() -> Result<()> {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+defaults:
+  isolation:
+    type: chroot
+  privilege:
+    method: sudo
+bootstrap:
+  type: mmdebstrap
+  suite: bookworm
+  target: rootfs
+  format: directory
+prepare:
+  mount:
+    mounts:
+      - source: proc
+        target: /proc
+        options:
+          - bind
+"#
+    ))?;
+    // editorconfig-checker-enable
+
+    let err = profile.validate().unwrap_err();
+    assert!(
+        matches!(err, RsdebstrapError::Validation(_)),
+        "Expected Validation error, got: {:?}",
+        err
+    );
+    assert!(
+        err.to_string().contains("pseudo-filesystem"),
+        "Expected error about pseudo-filesystem, got: {}",
+        err
+    );
+
+    Ok(())
+}
+
+// =============================================================================
+// resolv_conf integration tests
+// =============================================================================
+
+#[test]
+fn test_load_profile_with_resolv_conf_copy() -> Result<()> {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+bootstrap:
+  type: mmdebstrap
+  suite: bookworm
+  target: rootfs
+  format: directory
+prepare:
+  resolv_conf:
+    copy: true
+"#
+    ))?;
+    // editorconfig-checker-enable
+
+    let rc_task = profile
+        .prepare
+        .resolv_conf
+        .as_ref()
+        .expect("Expected resolv_conf task");
+    assert!(rc_task.copy);
+    assert!(rc_task.name_servers.is_empty());
+    assert!(rc_task.search.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_load_profile_with_resolv_conf_name_servers() -> Result<()> {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+bootstrap:
+  type: mmdebstrap
+  suite: bookworm
+  target: rootfs
+  format: directory
+prepare:
+  resolv_conf:
+    name_servers:
+      - 8.8.8.8
+      - 8.8.4.4
+    search:
+      - example.com
+"#
+    ))?;
+    // editorconfig-checker-enable
+
+    let rc_task = profile
+        .prepare
+        .resolv_conf
+        .as_ref()
+        .expect("Expected resolv_conf task");
+    assert!(!rc_task.copy);
+    assert_eq!(rc_task.name_servers.len(), 2);
+    assert_eq!(rc_task.search, vec!["example.com"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_load_profile_without_resolv_conf() -> Result<()> {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+defaults:
+  isolation:
+    type: chroot
+bootstrap:
+  type: mmdebstrap
+  suite: bookworm
+  target: rootfs
+  format: directory
+"#
+    ))?;
+    // editorconfig-checker-enable
+
+    assert!(profile.prepare.resolv_conf.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_validation_rejects_resolv_conf_copy_with_name_servers() -> Result<()> {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+bootstrap:
+  type: mmdebstrap
+  suite: bookworm
+  target: rootfs
+  format: directory
+prepare:
+  resolv_conf:
+    copy: true
+    name_servers:
+      - 8.8.8.8
+"#
+    ))?;
+    // editorconfig-checker-enable
+
+    let err = profile.validate().unwrap_err();
+    assert!(
+        matches!(err, RsdebstrapError::Validation(_)),
+        "Expected Validation error, got: {:?}",
+        err
+    );
+    assert!(
+        err.to_string().contains("mutually exclusive"),
+        "Expected error about mutual exclusivity, got: {}",
+        err
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_validation_rejects_resolv_conf_empty_config() -> Result<()> {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+bootstrap:
+  type: mmdebstrap
+  suite: bookworm
+  target: rootfs
   format: directory
 prepare:
   resolv_conf:
@@ -2464,3 +3296,1433 @@ bootstrap:
         err_msg
     );
 }
+
+#[test]
+fn test_task_network_defaults_to_enabled() -> Result<()> {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+provision:
+- type: shell
+  content: echo hi
+"#
+    ))?;
+    // editorconfig-checker-enable
+
+    assert!(!profile.offline);
+    assert!(profile.provision[0].network_enabled());
+
+    Ok(())
+}
+
+#[test]
+fn test_task_network_inherits_from_isolation_and_task_overrides() -> Result<()> {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+defaults:
+  isolation:
+    type: chroot
+    network: false
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+provision:
+- type: shell
+  content: echo inherited
+- type: shell
+  content: echo overridden
+  network: true
+"#
+    ))?;
+    // editorconfig-checker-enable
+
+    assert_eq!(profile.defaults.isolation.network(), Some(false));
+    assert!(!profile.provision[0].network_enabled());
+    assert!(profile.provision[1].network_enabled());
+
+    Ok(())
+}
+
+#[test]
+fn test_offline_disables_network_for_all_tasks() -> Result<()> {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+offline: true
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+provision:
+- type: shell
+  content: echo hi
+- type: shell
+  content: echo direct
+  isolation: false
+"#
+    ))?;
+    // editorconfig-checker-enable
+
+    assert!(profile.offline);
+    assert!(profile.provision.iter().all(|t| !t.network_enabled()));
+
+    Ok(())
+}
+
+#[test]
+fn test_offline_rejects_task_enabling_network() {
+    // editorconfig-checker-disable
+    let result = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+offline: true
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+provision:
+- type: shell
+  content: apt-get install -y vim
+  network: true
+"#
+    ));
+    // editorconfig-checker-enable
+
+    let err = result.expect_err("offline profile must reject network: true");
+    assert!(
+        format!("{err:#}").contains("network: true is not allowed"),
+        "unexpected error: {err:#}"
+    );
+}
+
+#[test]
+fn test_offline_rejects_isolation_enabling_network() {
+    // editorconfig-checker-disable
+    let result = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+offline: true
+defaults:
+  isolation:
+    type: chroot
+    network: true
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+provision:
+- type: shell
+  content: echo hi
+"#
+    ));
+    // editorconfig-checker-enable
+
+    assert!(result.is_err(), "offline profile must reject isolation network: true");
+}
+
+#[test]
+fn test_offline_rejects_downloads_and_uploads() -> Result<()> {
+    let bootstrap = "dir: /tmp/test\noffline: true\nbootstrap:\n  type: mmdebstrap\n  \
+        suite: trixie\n  target: rootfs\n  format: directory\n  architectures: [arm64]\n";
+    let digest = "a".repeat(64);
+    for (rest, expected) in [
+        (
+            format!(
+                "prepare:\n  download:\n    files:\n    - url: https://example.org/tool\n      \
+                sha256: {}\n      path: /usr/local/bin/tool\n",
+                digest
+            ),
+            "prepare.download is not allowed",
+        ),
+        (
+            format!(
+                "provision:\n- type: mitamae\n  content: \"package 'vim'\"\n  \
+                url: https://example.org/mitamae\n  sha256: {}\n",
+                digest
+            ),
+            "provision 1 downloading its binary from https://example.org/mitamae",
+        ),
+        (
+            format!(
+                "defaults:\n  mitamae:\n    version: 1.14.1\n    sha256:\n      \
+                aarch64: {}\nprovision:\n- type: mitamae\n  content: \"package 'vim'\"\n",
+                digest
+            ),
+            "provision 1 downloading its binary from https://github.com/",
+        ),
+        (
+            "upload:\n  bucket: images\n  artifacts: [rootfs]\n".to_string(),
+            "upload is not allowed",
+        ),
+    ] {
+        let profile = helpers::load_profile_from_yaml(format!("{}{}", bootstrap, rest))?;
+        let err = profile.validate().unwrap_err();
+        assert!(err.to_string().contains(expected), "{rest}: {err}");
+        assert!(err.to_string().contains("offline: true"), "{rest}: {err}");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_apt_cache_dir_resolves_relative_to_profile() -> Result<()> {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+apt_cache:
+  dir: cache
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+"#
+    ))?;
+    // editorconfig-checker-enable
+
+    let apt_cache = profile.apt_cache.as_ref().expect("apt_cache should be set");
+    let dir = apt_cache.dir.as_ref().expect("dir should be set");
+    assert!(dir.is_absolute(), "dir should be resolved: {dir}");
+    assert!(dir.ends_with("cache"));
+    assert_eq!(apt_cache.proxy, None);
+    profile.validate()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_apt_cache_rejects_proxy_and_dir_together() -> Result<()> {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+apt_cache:
+  proxy: http://127.0.0.1:3142
+  dir: /var/cache/rsdebstrap
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+"#
+    ))?;
+    // editorconfig-checker-enable
+
+    let err = profile
+        .validate()
+        .expect_err("proxy and dir must be exclusive");
+    assert!(err.to_string().contains("mutually exclusive"), "unexpected error: {err}");
+
+    Ok(())
+}
+
+#[test]
+fn test_apt_cache_rejects_unknown_fields() {
+    // editorconfig-checker-disable
+    let result = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+apt_cache:
+  url: http://127.0.0.1:3142
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+"#
+    ));
+    // editorconfig-checker-enable
+
+    assert!(result.is_err(), "unknown apt_cache field must be rejected");
+}
+
+#[test]
+fn test_upload_needs_artifacts_for_directory_output() -> Result<()> {
+    let profile = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\nbootstrap:\n  type: mmdebstrap\n  suite: trixie\n  target: rootfs\n\
+        upload:\n  bucket: images\n",
+    )?;
+    let err = profile.validate().unwrap_err();
+    assert!(err.to_string().contains("upload.artifacts must be set"), "{err}");
+
+    let profile = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\nbootstrap:\n  type: mmdebstrap\n  suite: trixie\n  \
+        target: rootfs.tar.zst\nupload:\n  bucket: images\n  prefix: debian/trixie\n  \
+        metadata:\n    team: os\n",
+    )?;
+    profile.validate()?;
+    let upload = profile.upload.as_ref().unwrap();
+    assert_eq!(upload.chunk_size(), 64 << 20);
+    assert_eq!(
+        upload.location(camino::Utf8Path::new("rootfs.tar.zst")),
+        "s3://images/debian/trixie/rootfs.tar.zst"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_stream_to_requires_stdout_target() -> Result<()> {
+    for (bootstrap, expected) in [
+        ("  target: \"-\"\n", "needs stream_to"),
+        ("  target: rootfs.tar\n  stream_to:\n  - [cat]\n", "needs target \"-\""),
+        (
+            "  target: \"-\"\n  format: directory\n  stream_to:\n  - [cat]\n",
+            "cannot be streamed",
+        ),
+        ("  target: \"-\"\n  stream_to:\n  - []\n", "stream_to[0] must name a command"),
+    ] {
+        let profile = helpers::load_profile_from_yaml(format!(
+            "dir: /tmp/test\nbootstrap:\n  type: mmdebstrap\n  suite: trixie\n{}",
+            bootstrap
+        ))?;
+        let err = profile.validate().expect_err(bootstrap);
+        assert!(err.to_string().contains(expected), "{bootstrap}: {err}");
+    }
+
+    let profile = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\nbootstrap:\n  type: mmdebstrap\n  suite: trixie\n  target: \"-\"\n  \
+        stream_to:\n  - [cat]\n",
+    )?;
+    profile.validate()?;
+    assert_eq!(profile.default_checksum_artifact(), None);
+    Ok(())
+}
+
+#[test]
+fn test_fallback_mirrors_require_primary_mirror() -> Result<()> {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+  fallback_mirrors:
+  - http://ftp.jp.debian.org/debian
+"#
+    ))?;
+    // editorconfig-checker-enable
+
+    let err = profile
+        .validate()
+        .expect_err("fallbacks without mirrors must be rejected");
+    assert!(err.to_string().contains("requires 'mirrors'"), "unexpected error: {err}");
+
+    Ok(())
+}
+
+#[test]
+fn test_fallback_mirrors_reject_sources_line_primary() -> Result<()> {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+  mirrors:
+  - deb http://deb.debian.org/debian trixie main
+  fallback_mirrors:
+  - http://ftp.jp.debian.org/debian
+"#
+    ))?;
+    // editorconfig-checker-enable
+
+    let err = profile
+        .validate()
+        .expect_err("sources line cannot be health-checked");
+    assert!(
+        err.to_string()
+            .contains("must be an http:// or https:// URL"),
+        "unexpected error: {err}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_keyrings_accept_local_keyring_with_matching_sha256() -> Result<()> {
+    let dir = tempdir()?;
+    let keyring = dir.path().join("archive.gpg");
+    std::fs::write(&keyring, b"\x99")?;
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(format!(
+        r#"---
+dir: /tmp/test
+keyrings:
+- path: {}
+  sha256: fd9528b920d6d3956e9e16114523e1889c751e8c1e040182116d4c906b43f558
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+"#,
+        keyring.display()
+    ))?;
+    // editorconfig-checker-enable
+
+    assert_eq!(profile.keyrings.len(), 1);
+    profile.validate()?;
+
+    Ok(())
+}
+
+#[test]
+fn test_keyrings_reject_local_keyring_with_wrong_sha256() -> Result<()> {
+    let dir = tempdir()?;
+    let keyring = dir.path().join("archive.gpg");
+    std::fs::write(&keyring, b"\x99")?;
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(format!(
+        r#"---
+dir: /tmp/test
+keyrings:
+- path: {}
+  sha256: {}
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+"#,
+        keyring.display(),
+        "0".repeat(64)
+    ))?;
+    // editorconfig-checker-enable
+
+    let err = profile
+        .validate()
+        .expect_err("tampered keyring must be rejected");
+    assert!(
+        matches!(err, RsdebstrapError::ChecksumMismatch { .. }),
+        "unexpected error: {err}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_keyrings_url_requires_sha256() -> Result<()> {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+keyrings:
+- url: https://example.org/archive.gpg
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+"#
+    ))?;
+    // editorconfig-checker-enable
+
+    let err = profile
+        .validate()
+        .expect_err("unpinned download must be rejected");
+    assert!(
+        err.to_string().contains(
+            "keyrings[0]: keyring url 'https://example.org/archive.gpg' requires 'sha256'"
+        ),
+        "unexpected error: {err}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_keyrings_reject_second_keyring_for_debootstrap() -> Result<()> {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+keyrings:
+- url: https://example.org/archive.gpg
+  sha256: fd9528b920d6d3956e9e16114523e1889c751e8c1e040182116d4c906b43f558
+bootstrap:
+  type: debootstrap
+  suite: trixie
+  target: rootfs
+  keyring: /usr/share/keyrings/debian-archive-keyring.gpg
+"#
+    ))?;
+    // editorconfig-checker-enable
+
+    let err = profile
+        .validate()
+        .expect_err("debootstrap takes a single keyring");
+    assert!(err.to_string().contains("single keyring"), "unexpected error: {err}");
+
+    Ok(())
+}
+
+#[test]
+fn test_staging_defaults_to_tmp() -> Result<()> {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+"#
+    ))?;
+    // editorconfig-checker-enable
+
+    assert_eq!(profile.defaults.staging, Staging::Tmp);
+    assert!(profile.pipeline_mounts().is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_staging_tmpfs_adds_pipeline_mount() -> Result<()> {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+defaults:
+  staging: tmpfs
+  privilege:
+    method: sudo
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+"#
+    ))?;
+    // editorconfig-checker-enable
+
+    assert_eq!(profile.defaults.staging, Staging::Tmpfs);
+    let mounts = profile.pipeline_mounts();
+    assert_eq!(mounts.len(), 1);
+    assert_eq!(mounts[0].source, "tmpfs");
+    assert_eq!(mounts[0].target, "/run/rsdebstrap/staging");
+    assert!(profile.validate().is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn test_staging_tmpfs_requires_privilege() -> Result<()> {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+defaults:
+  staging: tmpfs
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+"#
+    ))?;
+    // editorconfig-checker-enable
+
+    let err = profile
+        .validate()
+        .expect_err("tmpfs staging needs privilege");
+    assert!(
+        err.to_string()
+            .contains("defaults.privilege must be configured"),
+        "{err}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_staging_tmpfs_rejects_userns() -> Result<()> {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+defaults:
+  staging: tmpfs
+  privilege:
+    method: userns
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+"#
+    ))?;
+    // editorconfig-checker-enable
+
+    let err = profile
+        .validate()
+        .expect_err("tmpfs staging cannot persist in a user namespace");
+    assert!(
+        err.to_string()
+            .contains("not supported with privilege method userns"),
+        "{err}"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_staging_rejects_unknown_mode() {
+    // editorconfig-checker-disable
+    let result = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+dir: /tmp/test
+defaults:
+  staging: ramdisk
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+"#
+    ));
+    // editorconfig-checker-enable
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn test_profile_checksums_default_to_an_archive_output() -> Result<()> {
+    let yaml = "dir: /tmp/test\n\
+                bootstrap:\n  type: mmdebstrap\n  suite: trixie\n  target: rootfs.tar.zst\n\
+                checksums:\n  algorithms: [sha256, sha512]\n";
+    let profile = helpers::load_profile_from_yaml(yaml)?;
+
+    profile.validate()?;
+    let checksums = profile.checksums.as_ref().expect("checksums should be set");
+    assert_eq!(
+        checksums.artifacts(profile.default_checksum_artifact()),
+        [Utf8Path::new("rootfs.tar.zst")]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_validation_requires_checksum_artifacts_for_directory_output() -> Result<()> {
+    let yaml = "dir: /tmp/test\n\
+                bootstrap:\n  type: debootstrap\n  suite: trixie\n  target: rootfs\n\
+                checksums: {}\n";
+    let profile = helpers::load_profile_from_yaml(yaml)?;
+
+    let err = profile.validate().unwrap_err();
+    assert!(err.to_string().contains("checksums.artifacts must be set"), "{err}");
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_merges_task_user_into_chroot_isolation() -> Result<()> {
+    let yaml = "dir: /tmp/test\n\
+                bootstrap:\n  type: debootstrap\n  suite: trixie\n  target: rootfs\n\
+                provision:\n  - type: shell\n    content: make\n    user: builder\n    \
+                group: staff\n";
+    let profile = helpers::load_profile_from_yaml(yaml)?;
+
+    let ProvisionTask::Shell(task) = &profile.provision[0] else {
+        panic!("Expected Shell task, got: {:?}", profile.provision[0]);
+    };
+    let Some(rsdebstrap::config::IsolationConfig::Chroot(chroot)) =
+        task.resolved_isolation_config()
+    else {
+        panic!("Expected chroot isolation");
+    };
+    assert_eq!(chroot.user.as_deref(), Some("builder:staff"));
+    let reloaded = helpers::load_profile_from_yaml(profile.to_yaml()?)?;
+    assert_eq!(reloaded, profile);
+
+    Ok(())
+}
+
+#[test]
+fn test_pivot_root_isolation_takes_the_chroot_options() -> Result<()> {
+    let yaml = "dir: /tmp/test\n\
+                defaults:\n  isolation:\n    type: pivot_root\n    network: false\n\
+                bootstrap:\n  type: debootstrap\n  suite: trixie\n  target: rootfs\n\
+                provision:\n  - type: shell\n    content: make\n    user: builder\n";
+    let profile = helpers::load_profile_from_yaml(yaml)?;
+
+    let ProvisionTask::Shell(task) = &profile.provision[0] else {
+        panic!("Expected Shell task, got: {:?}", profile.provision[0]);
+    };
+    let Some(config @ rsdebstrap::config::IsolationConfig::PivotRoot(options)) =
+        task.resolved_isolation_config()
+    else {
+        panic!("Expected pivot_root isolation");
+    };
+    assert_eq!(config.name(), "pivot_root");
+    assert_eq!(options.network, Some(false));
+    assert_eq!(options.user.as_deref(), Some("builder"));
+    assert_eq!(config.as_provider(None).name(), "pivot_root");
+    let reloaded = helpers::load_profile_from_yaml(profile.to_yaml()?)?;
+    assert_eq!(reloaded, profile);
+
+    Ok(())
+}
+
+#[test]
+fn test_profile_rejects_invalid_task_user() {
+    for (task, expected) in [
+        ("    group: staff\n", "task 'group' requires 'user'"),
+        (
+            "    user: builder\n    isolation: false\n",
+            "task user 'builder' requires chroot isolation",
+        ),
+        (
+            "    user: builder\n    isolation:\n      type: chroot\n      user: other\n",
+            "conflicts with isolation user 'other'",
+        ),
+    ] {
+        let yaml = format!(
+            "dir: /tmp/test\n\
+             bootstrap:\n  type: debootstrap\n  suite: trixie\n  target: rootfs\n\
+             provision:\n  - type: shell\n    content: make\n{task}"
+        );
+        let err = helpers::load_profile_from_yaml(yaml).unwrap_err();
+        assert!(format!("{err:#}").contains(expected), "{task}: {err:#}");
+    }
+}
+
+#[test]
+fn test_prepare_download_resolves_cache_dir_and_privilege() -> Result<()> {
+    let profile = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\ndefaults:\n  privilege:\n    method: sudo\n\
+        bootstrap:\n  type: mmdebstrap\n  suite: trixie\n  target: rootfs\n  format: directory\n\
+        prepare:\n  download:\n    cache_dir: cache\n    files:\n    \
+        - url: https://example.org/tool\n      \
+        sha256: 5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03\n      \
+        path: /usr/local/bin/tool\n      executable: true\n",
+    )?;
+    let download = profile.prepare.download.as_ref().unwrap();
+    assert!(download.cache_dir()?.is_absolute());
+    assert!(download.cache_dir()?.ends_with("cache"));
+    assert_eq!(
+        download.resolved_privilege_method(),
+        Some(rsdebstrap::privilege::PrivilegeMethod::Sudo)
+    );
+    assert!(download.files[0].executable);
+    Ok(())
+}
+
+#[test]
+fn test_assemble_extract_resolves_output_and_privilege() -> Result<()> {
+    let profile = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\ndefaults:\n  privilege:\n    method: sudo\n\
+        bootstrap:\n  type: mmdebstrap\n  suite: trixie\n  target: rootfs\n  format: directory\n\
+        assemble:\n  extract:\n    output: artifacts\n    paths: [/var/log/build/*.log]\n",
+    )?;
+    let extract = profile.assemble.extract.as_ref().unwrap();
+    assert!(extract.output.is_absolute());
+    assert!(extract.output.ends_with("artifacts"));
+    assert_eq!(
+        extract.resolved_privilege_method(),
+        Some(rsdebstrap::privilege::PrivilegeMethod::Sudo)
+    );
+    assert_eq!(profile.assemble.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_assemble_relabel_resolves_privilege_and_validates_policy() -> Result<()> {
+    let profile = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\ndefaults:\n  privilege:\n    method: sudo\n\
+        bootstrap:\n  type: mmdebstrap\n  suite: trixie\n  target: rootfs\n  format: directory\n\
+        assemble:\n  relabel:\n    policy: mls\n  clean: {}\n",
+    )?;
+    profile.validate()?;
+    let relabel = profile.assemble.relabel.as_ref().unwrap();
+    assert_eq!(
+        relabel.resolved_privilege_method(),
+        Some(rsdebstrap::privilege::PrivilegeMethod::Sudo)
+    );
+    assert_eq!(profile.assemble.len(), 2);
+
+    let err = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\nbootstrap:\n  type: mmdebstrap\n  suite: trixie\n  target: rootfs\n\
+        assemble:\n  relabel:\n    policy: ../targeted\n",
+    )?
+    .validate()
+    .unwrap_err();
+    assert!(err.to_string().contains("invalid policy '../targeted'"), "{err}");
+    Ok(())
+}
+
+#[test]
+fn test_verify_checks_use_default_privilege() -> Result<()> {
+    let profile = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\ndefaults:\n  privilege:\n    method: doas\n\
+        bootstrap:\n  type: mmdebstrap\n  suite: trixie\n  target: rootfs\n  format: directory\n\
+        verify:\n- type: package_installed\n  package: openssh-server\n",
+    )?;
+    assert_eq!(profile.verify.len(), 1);
+    assert_eq!(profile.privilege_methods(), [rsdebstrap::privilege::PrivilegeMethod::Doas]);
+    assert_eq!(profile.pipeline().total_tasks(), 1);
+
+    let err = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\nbootstrap:\n  type: mmdebstrap\n  suite: trixie\n  target: rootfs.tar\n\
+        verify:\n- type: file_exists\n  path: /etc/hostname\n",
+    )?
+    .validate()
+    .unwrap_err();
+    assert!(err.to_string().contains("require directory output"), "{err}");
+    Ok(())
+}
+
+#[test]
+fn test_verify_test_suite_resolves_suite_and_results() -> Result<()> {
+    let profile = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\nbootstrap:\n  type: mmdebstrap\n  suite: trixie\n  target: rootfs\n  \
+        format: directory\nverify:\n- type: test_suite\n  suite: tests/smoke\n\
+        - type: test_suite\n  suite: /opt/suite.py\n  args: [-v]\n  results: reports/py.xml\n",
+    )?;
+    let [
+        VerifyTask::TestSuite {
+            suite: smoke,
+            results: smoke_results,
+            ..
+        },
+        VerifyTask::TestSuite {
+            suite: py,
+            args,
+            results: py_results,
+        },
+    ] = profile.verify.as_slice()
+    else {
+        panic!("expected two test_suite checks: {:?}", profile.verify);
+    };
+    assert!(smoke.is_absolute() && smoke.ends_with("tests/smoke"), "{smoke}");
+    assert_eq!(smoke_results.as_deref(), Some(Utf8Path::new("/tmp/test/smoke.junit.xml")));
+    assert_eq!(py, "/opt/suite.py");
+    assert_eq!(args, &["-v"]);
+    assert_eq!(py_results.as_deref(), Some(Utf8Path::new("/tmp/test/reports/py.xml")));
+    Ok(())
+}
+
+#[test]
+fn test_verify_boot_resolves_image_and_requires_qemu() -> Result<()> {
+    let profile = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\nbootstrap:\n  type: mmdebstrap\n  suite: trixie\n  target: rootfs\n  \
+        format: directory\nverify:\n- type: boot\n  image: images/disk.img\n  \
+        qemu: rsdebstrap-missing-qemu\n",
+    )?;
+    let [VerifyTask::Boot(boot)] = profile.verify.as_slice() else {
+        panic!("expected one boot check: {:?}", profile.verify);
+    };
+    assert_eq!(boot.image(), "/tmp/test/images/disk.img");
+    assert_eq!(profile.verify[0].name(), "boot:disk.img");
+
+    let err = profile.validate().unwrap_err();
+    assert!(matches!(err, RsdebstrapError::CommandNotFound { .. }), "{err}");
+    assert!(err.to_string().contains("rsdebstrap-missing-qemu"), "{err}");
+    Ok(())
+}
+
+#[test]
+fn test_failure_policy_and_ignore_errors_parse() -> Result<()> {
+    let profile = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\nbootstrap:\n  type: mmdebstrap\n  suite: trixie\n  target: rootfs\n  \
+        format: directory\nfailure_policy:\n  assemble: run_all_then_fail\nassemble:\n  \
+        clean:\n    ignore_errors: true\n",
+    )?;
+    assert_eq!(profile.failure_policy.provision, FailurePolicy::FailFast);
+    assert_eq!(profile.failure_policy.assemble, FailurePolicy::RunAllThenFail);
+    let clean = profile.assemble.clean.as_ref().expect("clean task");
+    assert!(clean.ignore_errors);
+
+    let yaml = yaml_serde::to_string(&profile.failure_policy)?;
+    assert_eq!(yaml.trim(), "assemble: run_all_then_fail");
+
+    let err = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\nbootstrap:\n  type: mmdebstrap\n  suite: trixie\n  target: rootfs\n\
+        failure_policy:\n  verify: run_all_then_fail\n",
+    )
+    .unwrap_err();
+    assert!(format!("{err:#}").contains("verify"), "{err:#}");
+    Ok(())
+}
+
+#[test]
+fn test_output_permissions_parse_and_round_trip() -> Result<()> {
+    let profile = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\nbootstrap:\n  type: mmdebstrap\n  suite: trixie\n  target: rootfs\n\
+        output_permissions:\n  dir:\n    owner: invoking\n    mode: 0755\n  rootfs:\n    \
+        owner: \"1000:1000\"\n",
+    )?;
+    let permissions = profile
+        .output_permissions
+        .as_ref()
+        .expect("output_permissions");
+    let dir = permissions.dir.as_ref().expect("dir");
+    assert_eq!(dir.owner, Some(Owner::Invoking));
+    assert_eq!(dir.mode, Some(FileMode(0o755)));
+    let rootfs = permissions.rootfs.as_ref().expect("rootfs");
+    assert_eq!(rootfs.owner, Some(Owner::Named("1000:1000".to_string())));
+    assert_eq!(helpers::load_profile_from_yaml(profile.to_yaml()?)?, profile);
+
+    let err = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\nbootstrap:\n  type: mmdebstrap\n  suite: trixie\n  target: rootfs\n\
+        output_permissions:\n  dir:\n    owner: \"-R root\"\n",
+    )
+    .unwrap_err();
+    assert!(format!("{err:#}").contains("invalid owner"), "{err:#}");
+    Ok(())
+}
+
+#[test]
+fn test_defaults_umask_must_leave_owner_permissions() -> Result<()> {
+    let yaml = |umask: &str| {
+        format!(
+            "dir: /tmp/test\ndefaults:\n  umask: {}\nbootstrap:\n  type: mmdebstrap\n  \
+            suite: trixie\n  target: rootfs\n",
+            umask
+        )
+    };
+    let profile = helpers::load_profile_from_yaml(yaml("\"0027\""))?;
+    assert_eq!(profile.defaults.umask, Some(FileMode(0o027)));
+    assert_eq!(helpers::load_profile_from_yaml(profile.to_yaml()?)?, profile);
+
+    profile.validate()?;
+
+    let err = helpers::load_profile_from_yaml(yaml("0277"))?
+        .validate()
+        .unwrap_err();
+    assert!(err.to_string().contains("owner's permissions"), "{err}");
+    Ok(())
+}
+
+#[test]
+fn test_defaults_shellcheck_fails_on_errors_by_default() -> Result<()> {
+    let yaml = |shellcheck: &str| {
+        format!(
+            "dir: /tmp/test\ndefaults:\n  shellcheck: {}\nbootstrap:\n  type: mmdebstrap\n  \
+            suite: trixie\n  target: rootfs\n",
+            shellcheck
+        )
+    };
+    let profile = helpers::load_profile_from_yaml(yaml("{severity: style}"))?;
+    let shellcheck = profile.defaults.shellcheck.as_ref().unwrap();
+    assert_eq!(shellcheck.severity, ShellcheckSeverity::Style);
+    assert_eq!(shellcheck.fail_on(), ShellcheckSeverity::Error);
+    assert_eq!(helpers::load_profile_from_yaml(profile.to_yaml()?)?, profile);
+    profile.validate()?;
+
+    let err = helpers::load_profile_from_yaml(yaml("{severity: warning, fail_on: info}"))?
+        .validate()
+        .unwrap_err();
+    assert!(err.to_string().contains("must not be below severity"), "{err}");
+    Ok(())
+}
+
+#[test]
+fn test_defaults_sandbox_defaults_to_landlock_and_seccomp() -> Result<()> {
+    let yaml = |sandbox: &str| {
+        format!(
+            "dir: /tmp/test\ndefaults:\n  sandbox: {}\nbootstrap:\n  type: mmdebstrap\n  \
+            suite: trixie\n  target: rootfs\n",
+            sandbox
+        )
+    };
+    let profile = helpers::load_profile_from_yaml(yaml("{writable: [/var/cache/build]}"))?;
+    let sandbox = profile.defaults.sandbox.as_ref().unwrap();
+    assert!(sandbox.landlock() && sandbox.seccomp());
+    assert_eq!(helpers::load_profile_from_yaml(profile.to_yaml()?)?, profile);
+    profile.validate()?;
+
+    for (sandbox, message) in [
+        ("{landlock: false, seccomp: false}", "remove it instead"),
+        ("{writable: [cache]}", "must be an absolute path"),
+        ("{landlock: false, writable: [/cache]}", "needs landlock"),
+    ] {
+        let err = helpers::load_profile_from_yaml(yaml(sandbox))?
+            .validate()
+            .unwrap_err();
+        assert!(err.to_string().contains(message), "{sandbox}: {err}");
+    }
+    Ok(())
+}
+
+const TARGETS_YAML: &str = "\
+dir: out/amd64
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+  architectures: [amd64]
+  mirrors: [https://deb.debian.org/debian]
+  include: [grub-efi-amd64]
+targets:
+  amd64: {}
+  arm64:
+    dir: out/arm64
+    mirrors: [https://ports.example.org/debian]
+    include: [grub-efi-arm64]
+    mitamae_binary: bin/mitamae-aarch64
+provision:
+- type: mitamae
+  content: \"package 'vim'\"
+";
+
+#[test]
+fn test_targets_select_the_architecture_overrides() -> Result<()> {
+    let temp = tempdir()?;
+    let dir = Utf8Path::from_path(temp.path()).unwrap();
+    let path = dir.join("profile.yml");
+    std::fs::write(&path, TARGETS_YAML)?;
+    std::fs::create_dir(dir.join("bin"))?;
+    std::fs::write(dir.join("bin/mitamae-aarch64"), "")?;
+    std::fs::set_permissions(
+        dir.join("bin/mitamae-aarch64"),
+        std::os::unix::fs::PermissionsExt::from_mode(0o755),
+    )?;
+    let load = |arch| {
+        rsdebstrap::config::load_profile_for(&path, rsdebstrap::config::UnknownFields::Deny, arch)
+    };
+
+    let profile = load(Some("arm64"))?;
+    profile.validate()?;
+    assert!(profile.targets.is_empty());
+    assert_eq!(profile.dir.file_name(), Some("arm64"));
+    assert!(profile.dir.is_absolute());
+    let cfg = helpers::get_mmdebstrap_config(&profile).unwrap();
+    assert_eq!(cfg.architectures, ["arm64"]);
+    assert_eq!(cfg.mirrors, ["https://ports.example.org/debian"]);
+    assert_eq!(cfg.include, ["grub-efi-arm64"]);
+    let ProvisionTask::Mitamae(mitamae) = &profile.provision[0] else {
+        panic!("expected a mitamae task");
+    };
+    assert!(
+        mitamae
+            .binary()
+            .is_some_and(|binary| binary.is_absolute() && binary.ends_with("bin/mitamae-aarch64")),
+        "{:?}",
+        mitamae.binary()
+    );
+
+    // An empty entry only selects the architecture; no --arch keeps the shared settings.
+    let amd64 = load(Some("amd64"))?;
+    assert_eq!(amd64.dir.file_name(), Some("amd64"));
+    assert_eq!(helpers::get_mmdebstrap_config(&amd64).unwrap().include, ["grub-efi-amd64"]);
+    let shared = load(None)?;
+    assert_eq!(shared.targets.len(), 2);
+    assert_eq!(shared.bootstrap.architectures(), ["amd64"]);
+
+    let err = load(Some("riscv64")).unwrap_err();
+    assert!(err.to_string().contains("available: amd64, arm64"), "{err}");
+    Ok(())
+}
+
+#[test]
+fn test_targets_validate_names_and_debootstrap_mirrors() -> Result<()> {
+    let err = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\nbootstrap:\n  type: mmdebstrap\n  suite: trixie\n  target: rootfs\n\
+        targets:\n  ARM64: {}\n",
+    )?
+    .validate()
+    .unwrap_err();
+    assert!(err.to_string().contains("not a Debian architecture name"), "{err}");
+
+    let mut profile = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\nbootstrap:\n  type: debootstrap\n  suite: trixie\n  target: rootfs\n\
+        targets:\n  arm64:\n    mirrors: [http://a.example/debian, http://b.example/debian]\n",
+    )?;
+    let err = profile.select_target("arm64").unwrap_err();
+    assert!(err.to_string().contains("single mirror"), "{err}");
+    Ok(())
+}
+
+#[test]
+fn test_suite_typos_and_unbuilt_architectures_are_rejected() -> Result<()> {
+    let profile = |suite: &str, extra: &str| {
+        helpers::load_profile_from_yaml(format!(
+            "dir: /tmp/test\nbootstrap:\n  type: mmdebstrap\n  suite: {}\n  target: rootfs\n{}",
+            suite, extra
+        ))
+    };
+
+    let err = profile("trixy", "")?.validate().unwrap_err();
+    assert!(err.to_string().contains("did you mean 'trixie'?"), "{err}");
+
+    let err = profile("stable", "  architectures: [mips64el]\n")?
+        .validate()
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("Debian trixie is not built for architecture 'mips64el'"),
+        "{err}"
+    );
+
+    // Aliases, custom mirrors and suites outside the table are accepted.
+    profile("testing", "  architectures: [arm64]\n")?.validate()?;
+    profile(
+        "trixie",
+        "  architectures: [loong64]\n  mirrors: [http://deb.debian.org/debian-ports]\n",
+    )?
+    .validate()?;
+    profile("noble", "  mirrors: [http://archive.ubuntu.com/ubuntu]\n")?.validate()?;
+    Ok(())
+}
+
+#[test]
+fn test_snapshot_pins_debian_mirrors() -> Result<()> {
+    let mut profile = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\nsnapshot: 2024-06-01T00:00:00Z\nbootstrap:\n  type: mmdebstrap\n  \
+        suite: bookworm\n  target: rootfs\n  mirrors:\n    - http://deb.debian.org/debian\n    \
+        - deb https://apt.example.com/repo bookworm main\n",
+    )?;
+    profile.validate()?;
+    let unchanged = profile.bootstrap.apply_snapshot("20240601T000000Z");
+    assert_eq!(unchanged, ["deb https://apt.example.com/repo bookworm main"]);
+    let Bootstrap::Mmdebstrap(cfg) = &profile.bootstrap else {
+        panic!("expected mmdebstrap");
+    };
+    assert_eq!(cfg.mirrors[0], "http://snapshot.debian.org/archive/debian/20240601T000000Z/");
+    assert_eq!(cfg.aptopt, ["Acquire::Check-Valid-Until \"false\";"]);
+
+    let mut profile = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\nsnapshot: 2024-06-01\nbootstrap:\n  type: debootstrap\n  \
+        suite: bookworm\n  target: rootfs\n",
+    )?;
+    assert!(
+        profile
+            .bootstrap
+            .apply_snapshot("20240601T000000Z")
+            .is_empty()
+    );
+    let Bootstrap::Debootstrap(cfg) = &profile.bootstrap else {
+        panic!("expected debootstrap");
+    };
+    assert_eq!(
+        cfg.mirror.as_deref(),
+        Some("https://snapshot.debian.org/archive/debian/20240601T000000Z/")
+    );
+    Ok(())
+}
+
+#[test]
+fn test_snapshot_validation() -> Result<()> {
+    let err = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\nsnapshot: last week\nbootstrap:\n  type: mmdebstrap\n  \
+        suite: bookworm\n  target: rootfs\n",
+    )?
+    .validate()
+    .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("snapshot 'last week' must be a UTC time"),
+        "{err}"
+    );
+
+    let err = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\nsnapshot: 2024-06-01\nbootstrap:\n  type: mmdebstrap\n  \
+        suite: bookworm\n  target: rootfs\n  mirrors: [http://deb.debian.org/debian]\n  \
+        fallback_mirrors: [http://ftp.jp.debian.org/debian]\n",
+    )?
+    .validate()
+    .unwrap_err();
+    assert!(err.to_string().contains("cannot be combined"), "{err}");
+    Ok(())
+}
+
+#[test]
+fn test_ubuntu_distribution_fills_in_components_mirror_and_keyring() -> Result<()> {
+    let profile = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\nbootstrap:\n  type: mmdebstrap\n  distribution: ubuntu\n  \
+        suite: noble\n  target: rootfs\n  architectures: [arm64]\n",
+    )?;
+    let cfg = helpers::get_mmdebstrap_config(&profile).unwrap();
+    assert_eq!(cfg.components, ["main", "universe"]);
+    assert_eq!(cfg.mirrors, ["https://ports.ubuntu.com/ubuntu-ports"]);
+    assert_eq!(cfg.keyring, ["/usr/share/keyrings/ubuntu-archive-keyring.gpg"]);
+
+    let profile = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\nbootstrap:\n  type: debootstrap\n  distribution: ubuntu\n  \
+        suite: noble\n  target: rootfs\n  arch: amd64\n  components: [main]\n  \
+        keyring: /etc/apt/keyrings/ubuntu.gpg\n",
+    )?;
+    profile.validate()?;
+    let cfg = helpers::get_debootstrap_config(&profile).unwrap();
+    assert_eq!(cfg.components, ["main"]);
+    assert_eq!(cfg.mirror.as_deref(), Some("https://archive.ubuntu.com/ubuntu"));
+    assert_eq!(cfg.keyring.as_deref(), Some("/etc/apt/keyrings/ubuntu.gpg"));
+
+    // Written back out, the filled-in settings load unchanged.
+    assert_eq!(helpers::load_profile_from_yaml(profile.to_yaml()?)?, profile);
+    Ok(())
+}
+
+#[test]
+fn test_user_config_fills_in_what_the_profile_leaves_unset() -> Result<()> {
+    let dir = tempdir()?;
+    let dir = Utf8Path::from_path(dir.path()).unwrap();
+    let layers = ProfileLayers {
+        user_config: Some(UserConfig {
+            cache_dir: Some(Utf8PathBuf::from("/var/cache/rsdebstrap")),
+            privilege: Some(PrivilegeDefaults {
+                method: PrivilegeMethod::Doas,
+            }),
+            mirrors: vec!["http://mirror.lan/debian".to_string()],
+            ..UserConfig::default()
+        }),
+        ..ProfileLayers::default()
+    };
+    let load = |yaml: &str| -> Result<_> {
+        let path = dir.join("profile.yml");
+        std::fs::write(&path, yaml)?;
+        Ok(load_profile_layered(&path, UnknownFields::Deny, None, &layers)?)
+    };
+
+    let profile = load(
+        "dir: rootfs\nbootstrap:\n  type: debootstrap\n  suite: trixie\n  target: rootfs\n  \
+        privilege: true\nprepare:\n  download:\n    files:\n      - url: https://example.com/a\n        \
+        sha256: 0000000000000000000000000000000000000000000000000000000000000000\n        \
+        path: /opt/a\n",
+    )?;
+    let cfg = helpers::get_debootstrap_config(&profile).unwrap();
+    assert_eq!(cfg.mirror.as_deref(), Some("http://mirror.lan/debian"));
+    assert_eq!(cfg.privilege.resolved_method(), Some(PrivilegeMethod::Doas));
+    let download = profile.prepare.download.as_ref().unwrap();
+    assert_eq!(download.cache_dir.as_deref(), Some(Utf8Path::new("/var/cache/rsdebstrap")));
+
+    // The profile's own settings win.
+    let profile = load(
+        "dir: rootfs\ndefaults:\n  privilege:\n    method: sudo\nbootstrap:\n  \
+        type: mmdebstrap\n  suite: trixie\n  target: rootfs\n  \
+        mirrors: [https://deb.debian.org/debian]\n",
+    )?;
+    let cfg = helpers::get_mmdebstrap_config(&profile).unwrap();
+    assert_eq!(cfg.mirrors, ["https://deb.debian.org/debian"]);
+    assert_eq!(profile.defaults.privilege.map(|p| p.method), Some(PrivilegeMethod::Sudo));
+
+    // A mirror serves one distribution, so Ubuntu profiles keep their defaults.
+    let profile = load(
+        "dir: rootfs\nbootstrap:\n  type: mmdebstrap\n  distribution: ubuntu\n  \
+        suite: noble\n  target: rootfs\n  architectures: [amd64]\n",
+    )?;
+    let cfg = helpers::get_mmdebstrap_config(&profile).unwrap();
+    assert_eq!(cfg.mirrors, ["https://archive.ubuntu.com/ubuntu"]);
+    Ok(())
+}
+
+#[test]
+fn test_env_overrides_sit_between_the_user_config_and_the_flags() -> Result<()> {
+    let dir = tempdir()?;
+    let dir = Utf8Path::from_path(dir.path()).unwrap();
+    let path = dir.join("profile.yml");
+    std::fs::write(
+        &path,
+        "dir: rootfs\ndefaults:\n  privilege:\n    method: sudo\nbootstrap:\n  \
+        type: mmdebstrap\n  suite: trixie\n  target: rootfs\n  privilege: true\n\
+        targets:\n  arm64:\n    dir: rootfs-arm64\n",
+    )?;
+    let layers = ProfileLayers {
+        user_config: Some(UserConfig {
+            privilege: Some(PrivilegeDefaults {
+                method: PrivilegeMethod::Run0,
+            }),
+            ..UserConfig::default()
+        }),
+        env: EnvOverrides::from_vars(
+            |name| match name {
+                "RSDEBSTRAP_DIR" => Some("out/rootfs".to_string()),
+                "RSDEBSTRAP_PRIVILEGE_METHOD" => Some("doas".to_string()),
+                _ => None,
+            },
+            Utf8Path::new("/ci/build"),
+        )?,
+        sets: Vec::new(),
+    };
+
+    // The environment replaces the profile's values, the `targets` entry's included.
+    let profile = load_profile_layered(&path, UnknownFields::Deny, Some("arm64"), &layers)?;
+    assert_eq!(profile.dir, "/ci/build/out/rootfs");
+    let cfg = helpers::get_mmdebstrap_config(&profile).unwrap();
+    assert_eq!(cfg.privilege.resolved_method(), Some(PrivilegeMethod::Doas));
+
+    // Without it, the profile's values beat the user configuration.
+    let layers = ProfileLayers {
+        env: EnvOverrides::default(),
+        ..layers
+    };
+    let profile = load_profile_layered(&path, UnknownFields::Deny, None, &layers)?;
+    assert_eq!(profile.dir, dir.canonicalize_utf8()?.join("rootfs"));
+    let cfg = helpers::get_mmdebstrap_config(&profile).unwrap();
+    assert_eq!(cfg.privilege.resolved_method(), Some(PrivilegeMethod::Sudo));
+    Ok(())
+}
+
+#[test]
+fn test_set_overrides_beat_the_environment_and_the_targets_entry() -> Result<()> {
+    let dir = tempdir()?;
+    let dir = Utf8Path::from_path(dir.path()).unwrap();
+    let path = dir.join("profile.yml");
+    std::fs::write(
+        &path,
+        "dir: rootfs\nbootstrap:\n  type: mmdebstrap\n  suite: bookworm\n  \
+        target: rootfs\n  include: [curl]\ntargets:\n  arm64:\n    dir: rootfs-arm64\n",
+    )?;
+    let layers = ProfileLayers {
+        env: EnvOverrides {
+            dir: Some("/env/rootfs".into()),
+            ..EnvOverrides::default()
+        },
+        sets: vec![
+            "bootstrap.suite=trixie".parse()?,
+            "dir=/set/rootfs".parse()?,
+            "bootstrap.include=[vim, git]".parse()?,
+            "provision[0]={type: shell, content: 'echo hi'}".parse()?,
+        ],
+        ..ProfileLayers::default()
+    };
+
+    let profile = load_profile_layered(&path, UnknownFields::Deny, Some("arm64"), &layers)?;
+    assert_eq!(profile.dir, "/set/rootfs");
+    let cfg = helpers::get_mmdebstrap_config(&profile).unwrap();
+    assert_eq!(cfg.suite, "trixie");
+    assert_eq!(cfg.include, ["vim", "git"]);
+    assert_eq!(profile.provision.len(), 1);
+    profile.validate()?;
+
+    // Everything the sets leave alone survives the round trip unchanged.
+    let plain = load_profile_layered(&path, UnknownFields::Deny, None, &ProfileLayers::default())?;
+    let layers = ProfileLayers {
+        sets: vec![format!("dir={}", plain.dir).parse()?],
+        ..ProfileLayers::default()
+    };
+    assert_eq!(load_profile_layered(&path, UnknownFields::Deny, None, &layers)?, plain);
+
+    // The result must still be a valid profile.
+    for (set, expected) in [
+        ("bootstrap.sutie=trixie", "unknown field `sutie`"),
+        ("bootstrap.include=vim", "invalid type"),
+        ("dir.name=rootfs", "'name' is set on a value that is not a mapping"),
+    ] {
+        let layers = ProfileLayers {
+            sets: vec![set.parse()?],
+            ..ProfileLayers::default()
+        };
+        let err = load_profile_layered(&path, UnknownFields::Deny, None, &layers).unwrap_err();
+        assert!(err.to_string().contains(&format!("--set {}: ", set)), "{err}");
+        assert!(err.to_string().contains(expected), "{set}: {err}");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_preset_fills_in_derivative_settings() -> Result<()> {
+    let profile = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\nbootstrap:\n  type: mmdebstrap\n  preset: raspbian\n  \
+        suite: bookworm\n  target: rootfs\n  include: [openssh-server]\n",
+    )?;
+    let cfg = helpers::get_mmdebstrap_config(&profile).unwrap();
+    assert_eq!(cfg.mirrors, ["http://raspbian.raspberrypi.com/raspbian"]);
+    assert_eq!(cfg.keyring, ["/usr/share/keyrings/raspbian-archive-keyring.gpg"]);
+    assert_eq!(cfg.components, ["main", "contrib", "non-free", "rpi"]);
+    assert_eq!(cfg.architectures, ["armhf"]);
+    assert_eq!(cfg.include, ["openssh-server", "raspbian-archive-keyring"]);
+
+    let profile = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\nbootstrap:\n  type: debootstrap\n  preset: devuan\n  \
+        suite: daedalus\n  target: rootfs\n  arch: arm64\n  \
+        mirror: http://mirror.example.com/merged\n",
+    )?;
+    let cfg = helpers::get_debootstrap_config(&profile).unwrap();
+    assert_eq!(cfg.mirror.as_deref(), Some("http://mirror.example.com/merged"));
+    assert_eq!(cfg.arch.as_deref(), Some("arm64"));
+    assert_eq!(cfg.components, ["main"]);
+    assert_eq!(cfg.include, ["devuan-keyring"]);
+
+    // Written back out, the filled-in settings load unchanged.
+    assert_eq!(helpers::load_profile_from_yaml(profile.to_yaml()?)?, profile);
+    Ok(())
+}
+
+#[test]
+fn test_preset_validation() -> Result<()> {
+    let kali = |suite: &str, extra: &str| {
+        helpers::load_profile_from_yaml(format!(
+            "dir: /tmp/test\nbootstrap:\n  type: mmdebstrap\n  preset: kali\n  \
+            suite: {}\n  target: rootfs\n  keyring: [/etc/apt/keyrings/kali.gpg]\n{}",
+            suite, extra
+        ))
+    };
+
+    let err = kali("kali-roling", "").unwrap_err();
+    assert!(err.to_string().contains("did you mean 'kali-rolling'?"), "{err}");
+
+    let err = kali("kali-rolling", "  architectures: [riscv64]\n").unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("preset kali is not built for architecture 'riscv64'"),
+        "{err}"
+    );
+
+    let err = kali("kali-rolling", "snapshot: 2024-06-01\n")?
+        .validate()
+        .unwrap_err();
+    assert!(err.to_string().contains("builds a derivative"), "{err}");
+
+    let err = kali("kali-rolling", "  distribution: ubuntu\n")?
+        .validate()
+        .unwrap_err();
+    assert!(err.to_string().contains("but distribution is ubuntu"), "{err}");
+
+    let err = helpers::load_profile_from_yaml(
+        "dir: /tmp/test\nbootstrap:\n  type: mmdebstrap\n  preset: mint\n  \
+        suite: wilma\n  target: rootfs\n",
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("unknown preset 'mint'"), "{err}");
+
+    // Debian's release table does not apply to a derivative's suites.
+    assert!(
+        kali("kali-rolling", "")?
+            .bootstrap
+            .suite_warnings(suite::Date::today())
+            .is_empty()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_ubuntu_distribution_validation() -> Result<()> {
+    let ubuntu = |suite: &str, extra: &str| {
+        helpers::load_profile_from_yaml(format!(
+            "dir: /tmp/test\nbootstrap:\n  type: mmdebstrap\n  distribution: ubuntu\n  \
+            suite: {}\n  target: rootfs\n  keyring: [/etc/apt/keyrings/ubuntu.gpg]\n{}",
+            suite, extra
+        ))
+    };
+
+    let err = ubuntu("trixie", "")?.validate().unwrap_err();
+    assert!(err.to_string().contains("is a Debian release"), "{err}");
+
+    let err = ubuntu("noble", "  architectures: [loong64]\n")?
+        .validate()
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("Ubuntu is not built for architecture 'loong64'"),
+        "{err}"
+    );
+
+    let err = ubuntu("noble", "  mirrors: [http://deb.debian.org/debian]\n")?
+        .validate()
+        .unwrap_err();
+    assert!(err.to_string().contains("is a Debian archive"), "{err}");
+
+    let err = ubuntu("noble", "  architectures: [amd64, arm64]\n").unwrap_err();
+    assert!(err.to_string().contains("different Ubuntu archives"), "{err}");
+    ubuntu(
+        "noble",
+        "  architectures: [amd64, arm64]\n  mirrors: [http://mirror.example.com/ubuntu]\n",
+    )?
+    .validate()?;
+    Ok(())
+}
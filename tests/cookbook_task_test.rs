@@ -0,0 +1,308 @@
+//! Deserialization, validation and execution tests for CookbookTask.
+
+mod helpers;
+
+use std::cell::RefCell;
+
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+use rsdebstrap::RsdebstrapError;
+use rsdebstrap::config::IsolationConfig;
+use rsdebstrap::executor::{CommandExecutor, ExecutionResult};
+use rsdebstrap::isolation::IsolationContext;
+use rsdebstrap::phase::provision::{BundlerConfig, CookbookRunner};
+use rsdebstrap::phase::{CookbookTask, ProvisionTask};
+use rsdebstrap::privilege::PrivilegeMethod;
+use tempfile::tempdir;
+
+use crate::helpers::MockContext;
+
+/// Mock context that also lists the staged cookbook directory at each command.
+struct SnapshotContext {
+    inner: MockContext,
+    snapshots: RefCell<Vec<Vec<String>>>,
+}
+
+impl SnapshotContext {
+    fn new(rootfs: &Utf8Path) -> Self {
+        Self {
+            inner: MockContext::new(rootfs),
+            snapshots: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+impl IsolationContext for SnapshotContext {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn rootfs(&self) -> &Utf8Path {
+        self.inner.rootfs()
+    }
+
+    fn dry_run(&self) -> bool {
+        self.inner.dry_run()
+    }
+
+    fn staging_dir(&self) -> &Utf8Path {
+        self.inner.staging_dir()
+    }
+
+    fn executor(&self) -> &dyn CommandExecutor {
+        self.inner.executor()
+    }
+
+    fn execute(
+        &self,
+        command: &[String],
+        privilege: Option<PrivilegeMethod>,
+    ) -> Result<ExecutionResult> {
+        let tmp = self.rootfs().join("tmp");
+        let staged = std::fs::read_dir(&tmp)?
+            .next()
+            .expect("cookbook should be staged")?;
+        let mut files = Vec::new();
+        let mut pending = vec![staged.path()];
+        while let Some(dir) = pending.pop() {
+            for entry in std::fs::read_dir(&dir)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    pending.push(path);
+                } else {
+                    let relative = path.strip_prefix(staged.path())?;
+                    files.push(relative.to_string_lossy().into_owned());
+                }
+            }
+        }
+        files.sort();
+        self.snapshots.borrow_mut().push(files);
+        self.inner.execute(command, privilege)
+    }
+
+    fn teardown(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Creates a rootfs with /tmp and a cookbook directory with a Gemfile and two recipes.
+fn setup(temp_dir: &tempfile::TempDir) -> (Utf8PathBuf, Utf8PathBuf) {
+    let base = Utf8Path::from_path(temp_dir.path()).expect("path should be valid UTF-8");
+    let rootfs = base.join("rootfs");
+    std::fs::create_dir_all(rootfs.join("tmp")).expect("failed to create tmp dir");
+    let cookbook = base.join("cookbook");
+    std::fs::create_dir_all(cookbook.join("recipes")).expect("failed to create recipes dir");
+    std::fs::create_dir_all(cookbook.join("cookbooks/base")).expect("failed to create cookbooks");
+    std::fs::write(cookbook.join("Gemfile"), "gem 'itamae'\n").expect("failed to write Gemfile");
+    std::fs::write(cookbook.join("recipes/base.rb"), "package 'vim'\n").expect("write failed");
+    std::fs::write(cookbook.join("recipes/web.rb"), "package 'nginx'\n").expect("write failed");
+    (rootfs, cookbook)
+}
+
+fn resolved(mut task: CookbookTask) -> CookbookTask {
+    task.resolve_privilege(None).unwrap();
+    task.resolve_isolation(&IsolationConfig::default());
+    task
+}
+
+#[test]
+fn test_deserialize_cookbook_task() {
+    // editorconfig-checker-disable
+    let yaml = r#"type: cookbook
+runner: cinc
+dir: ./chef
+run_list: ["recipe[base]", "role[web]"]
+bundler:
+  install: false
+  without: [development, test]
+"#;
+    // editorconfig-checker-enable
+    let task: ProvisionTask = yaml_serde::from_str(yaml).expect("should parse cookbook task");
+    let ProvisionTask::Cookbook(cookbook) = &task else {
+        panic!("Expected Cookbook task, got: {:?}", task);
+    };
+    assert_eq!(cookbook.runner(), CookbookRunner::Cinc);
+    assert_eq!(cookbook.dir(), "./chef");
+    assert_eq!(cookbook.run_list(), ["recipe[base]", "role[web]"]);
+    let bundler = cookbook.bundler().expect("bundler should be set");
+    assert!(!bundler.install);
+    assert_eq!(bundler.without, ["development", "test"]);
+    assert_eq!(task.name(), "cookbook:./chef");
+
+    let yaml = yaml_serde::to_string(&task).unwrap();
+    assert_eq!(yaml_serde::from_str::<ProvisionTask>(&yaml).unwrap(), task);
+}
+
+#[test]
+fn test_deserialize_rejects_unknown_runner_and_fields() {
+    for yaml in [
+        "type: cookbook\nrunner: chef\ndir: .\nrun_list: [a]\n",
+        "type: cookbook\nrunner: itamae\ndir: .\nrun_list: [a]\nrecipe: a.rb\n",
+    ] {
+        assert!(yaml_serde::from_str::<ProvisionTask>(yaml).is_err(), "{yaml}");
+    }
+}
+
+#[test]
+fn test_validate_itamae_run_list() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (_, cookbook) = setup(&temp_dir);
+
+    let task = CookbookTask::new(CookbookRunner::Itamae, &cookbook, ["recipes/base.rb"]);
+    assert!(task.validate().is_ok());
+
+    for entry in [
+        "recipes/missing.rb",
+        "/etc/passwd",
+        "../cookbook/recipes/base.rb",
+    ] {
+        let task = CookbookTask::new(CookbookRunner::Itamae, &cookbook, [entry]);
+        assert!(task.validate().is_err(), "{entry} should be rejected");
+    }
+
+    let task = CookbookTask::new(CookbookRunner::Itamae, &cookbook, Vec::<String>::new());
+    let err = task.validate().unwrap_err();
+    assert!(err.to_string().contains("non-empty 'run_list'"), "{err}");
+}
+
+#[test]
+fn test_validate_cinc_run_list_and_bundler() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (_, cookbook) = setup(&temp_dir);
+
+    let task = CookbookTask::new(CookbookRunner::Cinc, &cookbook, ["recipe[base]"]);
+    assert!(task.validate().is_ok());
+
+    let task = CookbookTask::new(CookbookRunner::Cinc, &cookbook, ["recipe[a], recipe[b]"]);
+    assert!(matches!(task.validate(), Err(RsdebstrapError::Validation(_))));
+
+    let bundler = BundlerConfig {
+        install: true,
+        without: vec!["dev test".to_string()],
+    };
+    let task =
+        CookbookTask::new(CookbookRunner::Cinc, &cookbook, ["recipe[base]"]).with_bundler(bundler);
+    assert!(matches!(task.validate(), Err(RsdebstrapError::Validation(_))));
+
+    std::fs::remove_dir(cookbook.join("cookbooks/base")).unwrap();
+    std::fs::remove_dir(cookbook.join("cookbooks")).unwrap();
+    let task = CookbookTask::new(CookbookRunner::Cinc, &cookbook, ["recipe[base]"]);
+    let err = task.validate().unwrap_err();
+    assert!(err.to_string().contains("no 'cookbooks' directory"), "{err}");
+}
+
+#[test]
+fn test_validate_rejects_symlinks_and_missing_gemfile() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (_, cookbook) = setup(&temp_dir);
+
+    std::fs::remove_file(cookbook.join("Gemfile")).unwrap();
+    let task = CookbookTask::new(CookbookRunner::Itamae, &cookbook, ["recipes/base.rb"])
+        .with_bundler(BundlerConfig::default());
+    assert!(task.validate().is_err(), "bundler without a Gemfile should be rejected");
+
+    std::os::unix::fs::symlink("/etc/passwd", cookbook.join("recipes/passwd")).unwrap();
+    let task = CookbookTask::new(CookbookRunner::Itamae, &cookbook, ["recipes/base.rb"]);
+    let err = task.validate().unwrap_err();
+    assert!(err.to_string().contains("symlinks are not allowed"), "{err}");
+}
+
+#[test]
+fn test_execute_itamae_with_bundler() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (rootfs, cookbook) = setup(&temp_dir);
+
+    let bundler = BundlerConfig {
+        install: true,
+        without: vec!["development".to_string(), "test".to_string()],
+    };
+    let task = resolved(
+        CookbookTask::new(CookbookRunner::Itamae, &cookbook, ["recipes/base.rb", "recipes/web.rb"])
+            .with_bundler(bundler),
+    );
+
+    let context = SnapshotContext::new(&rootfs);
+    task.execute(&context).expect("execute should succeed");
+
+    let commands = context.inner.executed_commands();
+    assert_eq!(commands.len(), 2, "{:?}", commands);
+    let staged = commands[0][1]
+        .strip_prefix("BUNDLE_GEMFILE=")
+        .and_then(|gemfile| gemfile.strip_suffix("/Gemfile"))
+        .expect("install should set BUNDLE_GEMFILE");
+    assert!(staged.starts_with("/tmp/cookbook-"), "{staged}");
+    assert_eq!(commands[0][2..], ["BUNDLE_WITHOUT=development:test", "bundle", "install"]);
+    assert_eq!(
+        commands[1][4..],
+        [
+            "exec".to_string(),
+            "itamae".to_string(),
+            "local".to_string(),
+            format!("{}/recipes/base.rb", staged),
+            format!("{}/recipes/web.rb", staged),
+        ]
+    );
+
+    let expected = ["Gemfile", "recipes/base.rb", "recipes/web.rb"];
+    assert_eq!(context.snapshots.borrow()[0], expected);
+    assert_eq!(
+        std::fs::read_dir(rootfs.join("tmp")).unwrap().count(),
+        0,
+        "staged cookbook should be removed"
+    );
+}
+
+#[test]
+fn test_execute_cinc_without_bundler() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (rootfs, cookbook) = setup(&temp_dir);
+
+    let task = resolved(CookbookTask::new(
+        CookbookRunner::Cinc,
+        &cookbook,
+        ["recipe[base]", "role[web]"],
+    ));
+
+    let context = MockContext::new(&rootfs);
+    task.execute(&context).expect("execute should succeed");
+
+    let commands = context.executed_commands();
+    assert_eq!(commands.len(), 1);
+    let cmd = &commands[0];
+    assert_eq!(cmd[0], "cinc-solo");
+    assert_eq!(cmd[1], "--config-option");
+    assert!(
+        cmd[2].starts_with("cookbook_path=/tmp/cookbook-") && cmd[2].ends_with("/cookbooks"),
+        "{}",
+        cmd[2]
+    );
+    assert_eq!(cmd[3..], ["--override-runlist", "recipe[base],role[web]"]);
+}
+
+#[test]
+fn test_execute_failure_stops_before_runner() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (rootfs, cookbook) = setup(&temp_dir);
+
+    let task = resolved(
+        CookbookTask::new(CookbookRunner::Itamae, &cookbook, ["recipes/base.rb"])
+            .with_bundler(BundlerConfig::default()),
+    );
+
+    let context = MockContext::with_failure(&rootfs, 1);
+    assert!(task.execute(&context).is_err());
+    assert_eq!(context.executed_commands().len(), 1, "only bundle install should run");
+    assert_eq!(std::fs::read_dir(rootfs.join("tmp")).unwrap().count(), 0);
+}
+
+#[test]
+fn test_execute_dry_run_skips_copy() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (_, cookbook) = setup(&temp_dir);
+
+    let task = resolved(CookbookTask::new(CookbookRunner::Itamae, &cookbook, ["recipes/base.rb"]));
+
+    let context = MockContext::new_dry_run(Utf8Path::new("/nonexistent/rootfs"));
+    task.execute(&context).expect("dry run should succeed");
+    assert_eq!(context.executed_commands().len(), 1);
+}
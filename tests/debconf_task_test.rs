@@ -0,0 +1,153 @@
+//! Deserialization, validation and execution tests for DebconfTask.
+
+mod helpers;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use rsdebstrap::RsdebstrapError;
+use rsdebstrap::config::IsolationConfig;
+use rsdebstrap::isolation::TaskIsolation;
+use rsdebstrap::phase::provision::{DebconfSelection, DebconfType};
+use rsdebstrap::phase::{DebconfTask, ProvisionTask};
+use tempfile::tempdir;
+
+use crate::helpers::MockContext;
+
+/// Writes a preseed file without a trailing newline and returns the rootfs and its path.
+fn setup(temp_dir: &tempfile::TempDir) -> (Utf8PathBuf, Utf8PathBuf) {
+    let base = Utf8Path::from_path(temp_dir.path()).expect("path should be valid UTF-8");
+    let rootfs = base.join("rootfs");
+    std::fs::create_dir_all(&rootfs).expect("failed to create rootfs");
+    let preseed = base.join("preseed.cfg");
+    std::fs::write(
+        &preseed,
+        "# keyboard\nkeyboard-configuration keyboard-configuration/layoutcode string us",
+    )
+    .expect("failed to write preseed file");
+    (rootfs, preseed)
+}
+
+fn resolved(mut task: DebconfTask) -> DebconfTask {
+    task.resolve_privilege(None).unwrap();
+    task.resolve_isolation(&IsolationConfig::default());
+    task
+}
+
+#[test]
+fn test_deserialize_debconf_task() {
+    // editorconfig-checker-disable
+    let yaml = r#"type: debconf
+file: ./preseed.cfg
+selections:
+  tzdata/Areas:
+    type: select
+    value: Etc
+  libc6/restart-without-asking:
+    owner: libc6:amd64
+    type: boolean
+    value: true
+"#;
+    // editorconfig-checker-enable
+    let task: ProvisionTask = yaml_serde::from_str(yaml).expect("should parse debconf task");
+    let ProvisionTask::Debconf(debconf) = &task else {
+        panic!("Expected Debconf task, got: {:?}", task);
+    };
+    assert_eq!(debconf.file(), Some(Utf8Path::new("./preseed.cfg")));
+    let restart = &debconf.selections()["libc6/restart-without-asking"];
+    assert_eq!(restart.owner.as_deref(), Some("libc6:amd64"));
+    assert_eq!(restart.kind, DebconfType::Boolean);
+    assert_eq!(restart.value, "true");
+    assert_eq!(task.name(), "debconf:./preseed.cfg");
+
+    let yaml = yaml_serde::to_string(&task).unwrap();
+    assert_eq!(yaml_serde::from_str::<ProvisionTask>(&yaml).unwrap(), task);
+}
+
+#[test]
+fn test_deserialize_rejects_unknown_type_and_fields() {
+    for yaml in [
+        "type: debconf\nselections:\n  a/b: {type: radio, value: x}\n",
+        "type: debconf\nselections:\n  a/b: {type: string, answer: x}\n",
+        "type: debconf\nselections: {}\nuser: nobody\n",
+    ] {
+        assert!(yaml_serde::from_str::<ProvisionTask>(yaml).is_err(), "{yaml}");
+    }
+}
+
+#[test]
+fn test_input_joins_file_and_selections() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (_, preseed) = setup(&temp_dir);
+
+    let task = DebconfTask::new()
+        .with_file(&preseed)
+        .with_selection("tzdata/Zones/Etc", DebconfSelection::new(DebconfType::Select, "UTC"))
+        .with_selection("tzdata/Areas", DebconfSelection::new(DebconfType::Select, "Etc"));
+    assert!(task.validate().is_ok());
+    assert_eq!(
+        task.input().unwrap(),
+        "# keyboard\n\
+         keyboard-configuration keyboard-configuration/layoutcode string us\n\
+         tzdata tzdata/Areas select Etc\n\
+         tzdata tzdata/Zones/Etc select UTC\n"
+    );
+}
+
+#[test]
+fn test_validate_rejects_invalid_tasks() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (_, preseed) = setup(&temp_dir);
+    let select = || DebconfSelection::new(DebconfType::Select, "Etc");
+
+    let cases = [
+        DebconfTask::new(),
+        DebconfTask::new().with_selection("noprefix", select()),
+        DebconfTask::new().with_selection("tz data/Areas", select()),
+        DebconfTask::new().with_selection(
+            "tzdata/Areas",
+            DebconfSelection::new(DebconfType::Select, "Etc\ntzdata tzdata/Zones/Etc select UTC"),
+        ),
+        DebconfTask::new()
+            .with_file(&preseed)
+            .with_isolation(TaskIsolation::Disabled),
+        DebconfTask::new().with_file(preseed.with_file_name("missing.cfg")),
+    ];
+    for task in cases {
+        assert!(task.validate().is_err(), "{:?} should be rejected", task);
+    }
+
+    let mut owned = select();
+    owned.owner = Some("tzdata".to_string());
+    assert!(
+        DebconfTask::new()
+            .with_selection("noprefix", owned)
+            .validate()
+            .is_ok()
+    );
+
+    let err = DebconfTask::new().validate().unwrap_err();
+    assert!(matches!(err, RsdebstrapError::Validation(_)), "{err}");
+}
+
+#[test]
+fn test_execute_pipes_selections_into_debconf_set_selections() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (rootfs, preseed) = setup(&temp_dir);
+
+    let task = resolved(DebconfTask::new().with_file(&preseed));
+    let context = MockContext::new(&rootfs);
+    task.execute(&context).expect("execute should succeed");
+
+    assert_eq!(context.executed_commands(), [["debconf-set-selections"]]);
+    assert_eq!(context.executed_stdins(), [Some(task.input().unwrap().into_bytes())]);
+}
+
+#[test]
+fn test_execute_failure_returns_error() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (rootfs, preseed) = setup(&temp_dir);
+
+    let task = resolved(DebconfTask::new().with_file(&preseed));
+    let context = MockContext::with_failure(&rootfs, 1);
+    let err = task.execute(&context).unwrap_err();
+    assert!(format!("{:#}", err).contains("debconf-set-selections"), "{err:#}");
+}
@@ -0,0 +1,187 @@
+mod helpers;
+
+use std::fs;
+
+use anyhow::Result;
+use camino::{Utf8Path, Utf8PathBuf};
+use rsdebstrap::RsdebstrapError;
+use rsdebstrap::diff::{Drift, diff_rootfs};
+use rsdebstrap::phase::ScriptSource;
+use rsdebstrap::task_record::{TaskRecord, script_digest};
+use tempfile::TempDir;
+
+fn diff_profile_yaml(dir: &Utf8Path) -> String {
+    // editorconfig-checker-disable
+    format!(
+        r#"---
+dir: {dir}
+defaults:
+  privilege:
+    method: sudo
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+  include: [vim, "curl=8.14.1-2", "libc6:amd64"]
+prepare:
+  mount:
+    mounts:
+    - source: proc
+      target: /proc
+    - source: tmpfs
+      target: /tmp
+provision:
+- type: shell
+  name: install-tools
+  content: apt-get install -y vim
+- type: shell
+  content: echo cleanup
+assemble:
+  resolv_conf:
+    name_servers: [192.0.2.53]
+"#
+    )
+    // editorconfig-checker-enable
+}
+
+/// Creates a rootfs that matches [`diff_profile_yaml`], with every task recorded.
+fn matching_rootfs() -> Result<(TempDir, Utf8PathBuf)> {
+    let dir = tempfile::tempdir()?;
+    let root = Utf8Path::from_path(dir.path()).expect("temp path should be valid UTF-8");
+    let rootfs = root.join("rootfs");
+    fs::create_dir_all(rootfs.join("var/lib/dpkg"))?;
+    fs::write(
+        rootfs.join("var/lib/dpkg/status"),
+        "Package: vim\nStatus: install ok installed\n\n\
+         Package: curl\nStatus: install ok installed\n\n\
+         Package: libc6\nStatus: install ok installed\n",
+    )?;
+    fs::create_dir_all(rootfs.join("etc"))?;
+    fs::write(
+        rootfs.join("etc/resolv.conf"),
+        "# Generated by rsdebstrap\nnameserver 192.0.2.53\n",
+    )?;
+    fs::create_dir_all(rootfs.join("proc"))?;
+    fs::create_dir_all(rootfs.join("tmp"))?;
+
+    let profile = helpers::load_profile_from_yaml(diff_profile_yaml(root))?;
+    let mut record = TaskRecord::default();
+    for (index, task) in profile.provision.iter().enumerate() {
+        record.insert(index + 1, task)?;
+    }
+    record.save(&rootfs)?;
+    Ok((dir, rootfs))
+}
+
+#[test]
+fn diff_reports_no_drift_for_a_matching_rootfs() -> Result<()> {
+    let (dir, _rootfs) = matching_rootfs()?;
+    let root = Utf8Path::from_path(dir.path()).unwrap();
+    let profile = helpers::load_profile_from_yaml(diff_profile_yaml(root))?;
+
+    let report = diff_rootfs(&profile)?;
+
+    assert!(report.is_empty(), "{report}");
+    Ok(())
+}
+
+#[test]
+fn diff_reports_each_kind_of_drift() -> Result<()> {
+    let (dir, rootfs) = matching_rootfs()?;
+    let root = Utf8Path::from_path(dir.path()).unwrap();
+    fs::write(
+        rootfs.join("var/lib/dpkg/status"),
+        "Package: vim\nStatus: deinstall ok config-files\n\n\
+         Package: curl\nStatus: install ok installed\n\n\
+         Package: libc6\nStatus: install ok installed\n",
+    )?;
+    fs::write(rootfs.join("etc/resolv.conf"), "nameserver 198.51.100.1\n")?;
+    fs::remove_dir(rootfs.join("tmp"))?;
+    let yaml = diff_profile_yaml(root).replace("echo cleanup", "echo cleanup again");
+    let profile = helpers::load_profile_from_yaml(yaml)?;
+
+    let report = diff_rootfs(&profile)?;
+
+    assert_eq!(
+        report.drifts,
+        [
+            Drift::MissingPackage("vim".to_string()),
+            Drift::ResolvConf("differs from the configured name servers".to_string()),
+            Drift::MissingMountPoint("/tmp".into()),
+            Drift::TaskChanged("provision 2".to_string()),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn diff_reports_unrecorded_and_removed_tasks() -> Result<()> {
+    let (dir, rootfs) = matching_rootfs()?;
+    let root = Utf8Path::from_path(dir.path()).unwrap();
+    let mut record = TaskRecord::load(&rootfs)?;
+    record.tasks.remove(&1);
+    record.tasks.insert(
+        3,
+        rsdebstrap::task_record::RecordedTask {
+            digest: script_digest(&ScriptSource::Content("echo old".to_string()))?,
+            name: "old-task".to_string(),
+        },
+    );
+    record.save(&rootfs)?;
+    let profile = helpers::load_profile_from_yaml(diff_profile_yaml(root))?;
+
+    let report = diff_rootfs(&profile)?;
+
+    assert_eq!(
+        report.drifts,
+        [
+            Drift::TaskNotRecorded("provision 1 (install-tools)".to_string()),
+            Drift::TaskRemoved("provision 3 (old-task)".to_string()),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn diff_checks_a_resolv_conf_link() -> Result<()> {
+    let (dir, rootfs) = matching_rootfs()?;
+    let root = Utf8Path::from_path(dir.path()).unwrap();
+    let yaml = diff_profile_yaml(root)
+        .replace("name_servers: [192.0.2.53]", "link: ../run/systemd/resolve/stub-resolv.conf");
+    let profile = helpers::load_profile_from_yaml(yaml)?;
+
+    let report = diff_rootfs(&profile)?;
+    assert_eq!(
+        report.drifts,
+        [Drift::ResolvConf(
+            "is not a symlink to ../run/systemd/resolve/stub-resolv.conf".to_string()
+        )]
+    );
+
+    fs::remove_file(rootfs.join("etc/resolv.conf"))?;
+    std::os::unix::fs::symlink("../run/resolv.conf", rootfs.join("etc/resolv.conf"))?;
+    let report = diff_rootfs(&profile)?;
+    assert_eq!(
+        report.drifts,
+        [Drift::ResolvConf(
+            "points at ../run/resolv.conf instead of ../run/systemd/resolve/stub-resolv.conf"
+                .to_string()
+        )]
+    );
+    Ok(())
+}
+
+#[test]
+fn diff_requires_an_existing_rootfs() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let root = Utf8Path::from_path(dir.path()).unwrap();
+    let profile = helpers::load_profile_from_yaml(diff_profile_yaml(root))?;
+
+    let err = diff_rootfs(&profile).unwrap_err();
+
+    assert!(
+        matches!(err.downcast_ref(), Some(RsdebstrapError::Validation(msg)) if msg.contains("does not exist")),
+        "{err:#}"
+    );
+    Ok(())
+}
@@ -0,0 +1,221 @@
+//! Tests for the `doctor` host checks against a fake host.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use rsdebstrap::config;
+use rsdebstrap::doctor::{Host, LOW_FREE_BYTES, Status, diagnose};
+use tempfile::TempDir;
+
+/// A host with the given commands, files and free space, running as a normal user on
+/// amd64.
+#[derive(Default)]
+struct FakeHost {
+    commands: BTreeMap<&'static str, &'static str>,
+    files: BTreeMap<String, String>,
+    paths: BTreeSet<String>,
+    free: Option<u64>,
+}
+
+impl FakeHost {
+    /// A host that passes every check without a profile.
+    fn complete() -> Self {
+        let mut host = Self {
+            free: Some(LOW_FREE_BYTES * 4),
+            ..Self::default()
+        };
+        for (name, version) in [
+            ("mmdebstrap", "mmdebstrap 1.5.4"),
+            ("debootstrap", "debootstrap 1.0.141"),
+            ("sudo", "Sudo version 1.9.16p2"),
+            ("tar", "tar (GNU tar) 1.35"),
+        ] {
+            host.commands.insert(name, version);
+        }
+        host.files
+            .insert("/proc/filesystems".into(), "nodev\tproc\nnodev\toverlay\n".into());
+        host
+    }
+}
+
+impl Host for FakeHost {
+    fn find_command(&self, name: &str) -> Option<Utf8PathBuf> {
+        self.commands
+            .contains_key(name)
+            .then(|| Utf8PathBuf::from(format!("/usr/bin/{}", name)))
+    }
+
+    fn command_version(&self, command: &Utf8Path) -> Option<String> {
+        self.commands
+            .get(command.file_name()?)
+            .map(|v| v.to_string())
+    }
+
+    fn read_file(&self, path: &Utf8Path) -> Option<String> {
+        self.files.get(path.as_str()).cloned()
+    }
+
+    fn exists(&self, path: &Utf8Path) -> bool {
+        self.paths.contains(path.as_str()) || self.files.contains_key(path.as_str())
+    }
+
+    fn available_space(&self, _path: &Utf8Path) -> Option<u64> {
+        self.free
+    }
+
+    fn is_root(&self) -> bool {
+        false
+    }
+
+    fn arch(&self) -> String {
+        "amd64".to_string()
+    }
+}
+
+fn load(yaml: &str) -> (TempDir, config::Profile) {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = Utf8Path::from_path(temp_dir.path())
+        .unwrap()
+        .join("profile.yml");
+    fs::write(&path, yaml).unwrap();
+    let profile = config::load_profile(&path).unwrap();
+    (temp_dir, profile)
+}
+
+#[test]
+fn test_complete_host_passes_without_profile() {
+    let report = diagnose(&FakeHost::complete(), None);
+    assert_eq!(report.count(Status::Fail), 0, "{report}");
+    assert_eq!(report.count(Status::Warn), 0, "{report}");
+    assert_eq!(report.get("mmdebstrap").unwrap().detail, "1.5.4 at /usr/bin/mmdebstrap");
+    assert!(report.get("disk space").is_none());
+    assert!(report.to_string().ends_with("0 warning(s), 0 failed\n"), "{report}");
+}
+
+#[test]
+fn test_missing_tools_warn_or_fail() {
+    let mut host = FakeHost::complete();
+    host.commands.remove("debootstrap");
+    host.commands.remove("tar");
+    host.commands.insert("mmdebstrap", "mmdebstrap 0.8.4");
+    host.files.clear();
+    host.files
+        .insert("/proc/sys/kernel/unprivileged_userns_clone".into(), "0\n".into());
+    let report = diagnose(&host, None);
+    for name in [
+        "debootstrap",
+        "tar",
+        "mmdebstrap",
+        "overlayfs",
+        "user namespaces",
+    ] {
+        assert_eq!(report.get(name).unwrap().status, Status::Warn, "{name}: {report}");
+    }
+    assert_eq!(report.count(Status::Fail), 0, "{report}");
+
+    host.commands.remove("mmdebstrap");
+    host.commands.remove("sudo");
+    let report = diagnose(&host, None);
+    assert_eq!(report.get("mmdebstrap").unwrap().status, Status::Fail);
+    assert!(
+        report
+            .get("debootstrap")
+            .unwrap()
+            .detail
+            .contains("no bootstrap backend")
+    );
+    assert_eq!(report.get("privilege").unwrap().status, Status::Warn);
+}
+
+#[test]
+fn test_profile_decides_required_checks() {
+    // editorconfig-checker-disable
+    let (_temp_dir, profile) = load(
+        r#"dir: /nonexistent/rsdebstrap-doctor/out
+defaults:
+  privilege:
+    method: doas
+bootstrap:
+  type: debootstrap
+  suite: trixie
+  target: rootfs
+  arch: arm64
+"#,
+    );
+    // editorconfig-checker-enable
+    let mut host = FakeHost::complete();
+    host.free = Some(LOW_FREE_BYTES / 8);
+    let report = diagnose(&host, Some(&profile));
+
+    let failed: Vec<_> = report
+        .checks
+        .iter()
+        .filter(|c| c.status == Status::Fail)
+        .map(|c| c.name.as_str())
+        .collect();
+    assert_eq!(
+        failed,
+        [
+            "debootstrap suite trixie",
+            "privilege doas",
+            "qemu arm64",
+            "disk space"
+        ],
+        "{report}"
+    );
+    assert!(
+        report
+            .get("qemu arm64")
+            .unwrap()
+            .detail
+            .contains("qemu-aarch64")
+    );
+    assert!(
+        report
+            .get("disk space")
+            .unwrap()
+            .detail
+            .contains("the build needs about 1.0 GiB")
+    );
+    // Only the profile's backend is required.
+    host.commands.remove("mmdebstrap");
+    let report = diagnose(&host, Some(&profile));
+    assert_eq!(report.get("mmdebstrap").unwrap().status, Status::Warn);
+
+    host.commands.insert("doas", "doas");
+    host.files.insert(
+        "/proc/sys/fs/binfmt_misc/qemu-aarch64".into(),
+        "enabled\ninterpreter /usr/libexec/qemu-binfmt/aarch64-binfmt-P\n".into(),
+    );
+    host.paths
+        .insert("/usr/share/debootstrap/scripts/trixie".into());
+    host.free = Some(LOW_FREE_BYTES * 2);
+    let report = diagnose(&host, Some(&profile));
+    assert_eq!(report.count(Status::Fail), 0, "{report}");
+    assert!(report.get("privilege").is_none());
+}
+
+#[test]
+fn test_min_version_of_the_profile_backend_fails() {
+    // editorconfig-checker-disable
+    let (_temp_dir, profile) = load(
+        r#"dir: /nonexistent/rsdebstrap-doctor/out
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+  min_version: "1.6"
+"#,
+    );
+    // editorconfig-checker-enable
+    let mut host = FakeHost::complete();
+    let report = diagnose(&host, Some(&profile));
+    let check = report.get("mmdebstrap").unwrap();
+    assert_eq!(check.status, Status::Fail, "{report}");
+    assert!(check.detail.contains("the profile needs 1.6 or newer"), "{report}");
+
+    host.commands.insert("mmdebstrap", "mmdebstrap 1.6.0");
+    let report = diagnose(&host, Some(&profile));
+    assert_eq!(report.get("mmdebstrap").unwrap().status, Status::Pass, "{report}");
+}
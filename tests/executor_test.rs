@@ -1,5 +1,7 @@
+use std::time::{Duration, Instant};
+
 use camino::Utf8Path;
-use rsdebstrap::executor::{CommandExecutor, CommandSpec, RealCommandExecutor};
+use rsdebstrap::executor::{CommandExecutor, CommandSpec, RealCommandExecutor, pipeline_spec};
 
 #[test]
 fn dry_run_skips_command_lookup() {
@@ -63,6 +65,38 @@ fn execute_checked_returns_error_for_non_zero_exit() {
     );
 }
 
+#[test]
+fn execution_error_records_how_the_command_ran() {
+    let executor = RealCommandExecutor { dry_run: false };
+    let dir = tempfile::tempdir().unwrap();
+    let cwd = Utf8Path::from_path(dir.path()).unwrap().to_owned();
+    let script = "for i in $(seq 1 30); do echo \"E: line $i\" >&2; done; exit 100";
+    let spec = CommandSpec::new("sh", vec!["-c".into(), script.into()])
+        .with_cwd(cwd.clone())
+        .with_env("DEBIAN_FRONTEND", "noninteractive")
+        .with_env("API_TOKEN", "hunter2");
+
+    let err = executor.execute_checked(&spec).unwrap_err();
+    let Some(rsdebstrap::RsdebstrapError::Execution {
+        details: Some(details),
+        ..
+    }) = err.downcast_ref::<rsdebstrap::RsdebstrapError>()
+    else {
+        panic!("Expected Execution error with details, got: {err:?}");
+    };
+    assert_eq!(details.argv, ["sh", "-c", script]);
+    assert_eq!(details.cwd.as_deref(), Some(cwd.as_str()));
+    assert_eq!(details.env, ["DEBIAN_FRONTEND=noninteractive", "API_TOKEN=***"]);
+    assert!(details.elapsed.is_some());
+    assert_eq!(details.stderr_tail.len(), rsdebstrap::error::STDERR_TAIL_LINES);
+    assert_eq!(details.stderr_tail.last().unwrap(), "E: line 30");
+
+    let message = err.to_string();
+    assert!(message.contains("exit status: 100 (cwd: "), "{message}");
+    assert!(message.contains("\n  E: line 30"), "{message}");
+    assert!(!message.contains("hunter2"), "{message}");
+}
+
 #[test]
 fn cwd_is_applied_to_child() {
     let executor = RealCommandExecutor { dry_run: false };
@@ -119,3 +153,95 @@ fn env_is_applied_to_child() {
         .expect("execute should spawn");
     assert_ne!(result_no_env.code(), Some(0), "without the env var the test should fail");
 }
+
+#[test]
+fn timeout_kills_command() {
+    let executor = RealCommandExecutor { dry_run: false };
+    let spec =
+        CommandSpec::new("sleep", vec!["30".into()]).with_timeout(Duration::from_millis(200));
+    let started = Instant::now();
+    let err = executor
+        .execute(&spec)
+        .expect_err("command should time out");
+    assert!(err.to_string().contains("timed out after 200ms"), "unexpected error: {err:#}");
+    assert!(started.elapsed() < Duration::from_secs(10), "command was not killed");
+}
+
+#[test]
+fn command_finishing_within_timeout_succeeds() {
+    let executor = RealCommandExecutor { dry_run: false };
+    let spec = CommandSpec::new("true", Vec::new()).with_timeout(Duration::from_secs(30));
+    executor
+        .execute_checked(&spec)
+        .expect("command should finish in time");
+}
+
+#[test]
+fn large_output_is_streamed_without_blocking() {
+    // More than a pipe buffer on both streams: the command only exits if both are
+    // read while it runs.
+    let executor = RealCommandExecutor { dry_run: false };
+    let spec = CommandSpec::new(
+        "sh",
+        vec![
+            "-c".into(),
+            "i=0; while [ $i -lt 5000 ]; do echo out $i; echo err $i >&2; i=$((i+1)); done".into(),
+        ],
+    )
+    .with_timeout(Duration::from_secs(60));
+    executor
+        .execute_checked(&spec)
+        .expect("command should not block on full pipes");
+}
+
+#[test]
+fn pipeline_connects_stdout_to_the_next_stdin() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let dir = Utf8Path::from_path(temp_dir.path()).unwrap();
+    let executor = RealCommandExecutor { dry_run: false };
+    // More than a pipe buffer, so the stages must run concurrently.
+    let stages = [
+        CommandSpec::new("sh", vec!["-c".into(), "seq 100000".into()]),
+        CommandSpec::new("sort", vec!["-n".into()]),
+        CommandSpec::new("sh", vec!["-c".into(), "tail -n 1 > last".into()])
+            .with_cwd(dir.to_owned()),
+    ];
+    executor
+        .execute_pipeline(&stages)
+        .expect("pipeline should succeed");
+    assert_eq!(std::fs::read_to_string(dir.join("last")).unwrap(), "100000\n");
+
+    // The shell form the other executors fall back on behaves the same.
+    std::fs::remove_file(dir.join("last")).unwrap();
+    executor
+        .execute_checked(&pipeline_spec(&stages))
+        .expect("shell pipeline should succeed");
+    assert_eq!(std::fs::read_to_string(dir.join("last")).unwrap(), "100000\n");
+}
+
+#[test]
+fn pipeline_reports_the_failed_stage() {
+    let executor = RealCommandExecutor { dry_run: false };
+    let stages = [
+        CommandSpec::new("sh", vec!["-c".into(), "echo data; exit 3".into()]),
+        CommandSpec::new("cat", Vec::new()),
+    ];
+    let err = executor.execute_pipeline(&stages).unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref::<rsdebstrap::RsdebstrapError>(),
+            Some(rsdebstrap::RsdebstrapError::Execution { command, .. }) if command.starts_with("sh ")
+        ),
+        "{err:#}"
+    );
+    assert!(err.to_string().contains("exit status: 3"), "{err:#}");
+
+    let stages = [
+        CommandSpec::new("true", Vec::new()),
+        CommandSpec::new("this-command-should-not-exist", Vec::new()),
+    ];
+    assert!(executor.execute_pipeline(&stages).is_err());
+    RealCommandExecutor { dry_run: true }
+        .execute_pipeline(&stages)
+        .expect("dry run should not run the pipeline");
+}
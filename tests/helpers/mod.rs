@@ -9,12 +9,13 @@ use std::sync::{LazyLock, Mutex};
 use anyhow::{Context, Result};
 use camino::{Utf8Path, Utf8PathBuf};
 use rsdebstrap::RsdebstrapError;
-use rsdebstrap::bootstrap::debootstrap::{self, DebootstrapConfig};
-use rsdebstrap::bootstrap::mmdebstrap::{self, MmdebstrapConfig};
+use rsdebstrap::bootstrap::debootstrap::DebootstrapConfig;
+pub use rsdebstrap::bootstrap::debootstrap::DebootstrapConfigBuilder;
+use rsdebstrap::bootstrap::mmdebstrap::MmdebstrapConfig;
+pub use rsdebstrap::bootstrap::mmdebstrap::MmdebstrapConfigBuilder;
 use rsdebstrap::config::{Bootstrap, Profile, load_profile};
 use rsdebstrap::executor::ExecutionResult;
 use rsdebstrap::isolation::IsolationContext;
-use rsdebstrap::privilege::Privilege;
 use tempfile::NamedTempFile;
 use tracing::warn;
 
@@ -66,195 +67,6 @@ pub fn dedent(input: &str) -> String {
     out
 }
 
-/// Builder for constructing `MmdebstrapConfig` in tests.
-///
-/// Provides a fluent API to set only the fields that differ from defaults,
-/// reducing boilerplate in test code.
-pub struct MmdebstrapConfigBuilder {
-    suite: String,
-    target: String,
-    mode: mmdebstrap::Mode,
-    format: mmdebstrap::Format,
-    variant: mmdebstrap::Variant,
-    architectures: Vec<String>,
-    components: Vec<String>,
-    include: Vec<String>,
-    keyring: Vec<String>,
-    aptopt: Vec<String>,
-    dpkgopt: Vec<String>,
-    setup_hook: Vec<String>,
-    extract_hook: Vec<String>,
-    essential_hook: Vec<String>,
-    customize_hook: Vec<String>,
-    mirrors: Vec<String>,
-    privilege: Privilege,
-}
-
-impl MmdebstrapConfigBuilder {
-    pub fn new(suite: impl Into<String>, target: impl Into<String>) -> Self {
-        Self {
-            suite: suite.into(),
-            target: target.into(),
-            mode: Default::default(),
-            format: Default::default(),
-            variant: Default::default(),
-            architectures: Default::default(),
-            components: Default::default(),
-            include: Default::default(),
-            keyring: Default::default(),
-            aptopt: Default::default(),
-            dpkgopt: Default::default(),
-            setup_hook: Default::default(),
-            extract_hook: Default::default(),
-            essential_hook: Default::default(),
-            customize_hook: Default::default(),
-            mirrors: Default::default(),
-            privilege: Default::default(),
-        }
-    }
-
-    pub fn mode(mut self, mode: mmdebstrap::Mode) -> Self {
-        self.mode = mode;
-        self
-    }
-
-    pub fn format(mut self, format: mmdebstrap::Format) -> Self {
-        self.format = format;
-        self
-    }
-
-    pub fn variant(mut self, variant: mmdebstrap::Variant) -> Self {
-        self.variant = variant;
-        self
-    }
-
-    pub fn architectures<I, S>(mut self, architectures: I) -> Self
-    where
-        I: IntoIterator<Item = S>,
-        S: Into<String>,
-    {
-        self.architectures = architectures.into_iter().map(Into::into).collect();
-        self
-    }
-
-    pub fn components<I, S>(mut self, components: I) -> Self
-    where
-        I: IntoIterator<Item = S>,
-        S: Into<String>,
-    {
-        self.components = components.into_iter().map(Into::into).collect();
-        self
-    }
-
-    pub fn include<I, S>(mut self, include: I) -> Self
-    where
-        I: IntoIterator<Item = S>,
-        S: Into<String>,
-    {
-        self.include = include.into_iter().map(Into::into).collect();
-        self
-    }
-
-    pub fn keyring<I, S>(mut self, keyring: I) -> Self
-    where
-        I: IntoIterator<Item = S>,
-        S: Into<String>,
-    {
-        self.keyring = keyring.into_iter().map(Into::into).collect();
-        self
-    }
-
-    pub fn aptopt<I, S>(mut self, aptopt: I) -> Self
-    where
-        I: IntoIterator<Item = S>,
-        S: Into<String>,
-    {
-        self.aptopt = aptopt.into_iter().map(Into::into).collect();
-        self
-    }
-
-    pub fn dpkgopt<I, S>(mut self, dpkgopt: I) -> Self
-    where
-        I: IntoIterator<Item = S>,
-        S: Into<String>,
-    {
-        self.dpkgopt = dpkgopt.into_iter().map(Into::into).collect();
-        self
-    }
-
-    pub fn setup_hook<I, S>(mut self, setup_hook: I) -> Self
-    where
-        I: IntoIterator<Item = S>,
-        S: Into<String>,
-    {
-        self.setup_hook = setup_hook.into_iter().map(Into::into).collect();
-        self
-    }
-
-    pub fn extract_hook<I, S>(mut self, extract_hook: I) -> Self
-    where
-        I: IntoIterator<Item = S>,
-        S: Into<String>,
-    {
-        self.extract_hook = extract_hook.into_iter().map(Into::into).collect();
-        self
-    }
-
-    pub fn essential_hook<I, S>(mut self, essential_hook: I) -> Self
-    where
-        I: IntoIterator<Item = S>,
-        S: Into<String>,
-    {
-        self.essential_hook = essential_hook.into_iter().map(Into::into).collect();
-        self
-    }
-
-    pub fn customize_hook<I, S>(mut self, customize_hook: I) -> Self
-    where
-        I: IntoIterator<Item = S>,
-        S: Into<String>,
-    {
-        self.customize_hook = customize_hook.into_iter().map(Into::into).collect();
-        self
-    }
-
-    pub fn mirrors<I, S>(mut self, mirrors: I) -> Self
-    where
-        I: IntoIterator<Item = S>,
-        S: Into<String>,
-    {
-        self.mirrors = mirrors.into_iter().map(Into::into).collect();
-        self
-    }
-
-    pub fn privilege(mut self, privilege: Privilege) -> Self {
-        self.privilege = privilege;
-        self
-    }
-
-    pub fn build(self) -> MmdebstrapConfig {
-        MmdebstrapConfig {
-            suite: self.suite,
-            target: self.target,
-            mode: self.mode,
-            format: self.format,
-            variant: self.variant,
-            architectures: self.architectures,
-            components: self.components,
-            include: self.include,
-            keyring: self.keyring,
-            aptopt: self.aptopt,
-            dpkgopt: self.dpkgopt,
-            setup_hook: self.setup_hook,
-            extract_hook: self.extract_hook,
-            essential_hook: self.essential_hook,
-            customize_hook: self.customize_hook,
-            mirrors: self.mirrors,
-            privilege: self.privilege,
-        }
-    }
-}
-
 /// Test helper to create a MmdebstrapConfig with minimal required fields.
 ///
 /// All optional fields are initialized with their default values.
@@ -262,139 +74,6 @@ pub fn create_mmdebstrap(suite: impl Into<String>, target: impl Into<String>) ->
     MmdebstrapConfigBuilder::new(suite, target).build()
 }
 
-/// Builder for constructing `DebootstrapConfig` in tests.
-///
-/// Provides a fluent API to set only the fields that differ from defaults,
-/// reducing boilerplate in test code.
-pub struct DebootstrapConfigBuilder {
-    suite: String,
-    target: String,
-    variant: debootstrap::Variant,
-    arch: Option<String>,
-    components: Vec<String>,
-    include: Vec<String>,
-    exclude: Vec<String>,
-    mirror: Option<String>,
-    foreign: bool,
-    merged_usr: Option<bool>,
-    no_resolve_deps: bool,
-    verbose: bool,
-    print_debs: bool,
-    privilege: Privilege,
-}
-
-impl DebootstrapConfigBuilder {
-    pub fn new(suite: impl Into<String>, target: impl Into<String>) -> Self {
-        Self {
-            suite: suite.into(),
-            target: target.into(),
-            variant: Default::default(),
-            arch: Default::default(),
-            components: Default::default(),
-            include: Default::default(),
-            exclude: Default::default(),
-            mirror: Default::default(),
-            foreign: Default::default(),
-            merged_usr: Default::default(),
-            no_resolve_deps: Default::default(),
-            verbose: Default::default(),
-            print_debs: Default::default(),
-            privilege: Default::default(),
-        }
-    }
-
-    pub fn variant(mut self, variant: debootstrap::Variant) -> Self {
-        self.variant = variant;
-        self
-    }
-
-    pub fn arch(mut self, arch: impl Into<String>) -> Self {
-        self.arch = Some(arch.into());
-        self
-    }
-
-    pub fn components<I, S>(mut self, components: I) -> Self
-    where
-        I: IntoIterator<Item = S>,
-        S: Into<String>,
-    {
-        self.components = components.into_iter().map(Into::into).collect();
-        self
-    }
-
-    pub fn include<I, S>(mut self, include: I) -> Self
-    where
-        I: IntoIterator<Item = S>,
-        S: Into<String>,
-    {
-        self.include = include.into_iter().map(Into::into).collect();
-        self
-    }
-
-    pub fn exclude<I, S>(mut self, exclude: I) -> Self
-    where
-        I: IntoIterator<Item = S>,
-        S: Into<String>,
-    {
-        self.exclude = exclude.into_iter().map(Into::into).collect();
-        self
-    }
-
-    pub fn mirror(mut self, mirror: impl Into<String>) -> Self {
-        self.mirror = Some(mirror.into());
-        self
-    }
-
-    pub fn foreign(mut self, foreign: bool) -> Self {
-        self.foreign = foreign;
-        self
-    }
-
-    pub fn merged_usr(mut self, merged_usr: bool) -> Self {
-        self.merged_usr = Some(merged_usr);
-        self
-    }
-
-    pub fn no_resolve_deps(mut self, no_resolve_deps: bool) -> Self {
-        self.no_resolve_deps = no_resolve_deps;
-        self
-    }
-
-    pub fn verbose(mut self, verbose: bool) -> Self {
-        self.verbose = verbose;
-        self
-    }
-
-    pub fn print_debs(mut self, print_debs: bool) -> Self {
-        self.print_debs = print_debs;
-        self
-    }
-
-    pub fn privilege(mut self, privilege: Privilege) -> Self {
-        self.privilege = privilege;
-        self
-    }
-
-    pub fn build(self) -> DebootstrapConfig {
-        DebootstrapConfig {
-            suite: self.suite,
-            target: self.target,
-            variant: self.variant,
-            arch: self.arch,
-            components: self.components,
-            include: self.include,
-            exclude: self.exclude,
-            mirror: self.mirror,
-            foreign: self.foreign,
-            merged_usr: self.merged_usr,
-            no_resolve_deps: self.no_resolve_deps,
-            verbose: self.verbose,
-            print_debs: self.print_debs,
-            privilege: self.privilege,
-        }
-    }
-}
-
 /// Test helper to create a DebootstrapConfig with minimal required fields.
 ///
 /// All optional fields are initialized with their default values.
@@ -502,7 +181,9 @@ pub struct MockContext {
     error_message: Option<String>,
     executed_commands: RefCell<Vec<Vec<String>>>,
     executed_privileges: RefCell<Vec<Option<rsdebstrap::privilege::PrivilegeMethod>>>,
+    executed_stdins: RefCell<Vec<Option<Vec<u8>>>>,
     return_no_status: bool,
+    staging_dir: Option<Utf8PathBuf>,
 }
 
 impl MockContext {
@@ -516,7 +197,9 @@ impl MockContext {
             error_message: None,
             executed_commands: RefCell::new(Vec::new()),
             executed_privileges: RefCell::new(Vec::new()),
+            executed_stdins: RefCell::new(Vec::new()),
             return_no_status: false,
+            staging_dir: None,
         }
     }
 
@@ -530,7 +213,9 @@ impl MockContext {
             error_message: None,
             executed_commands: RefCell::new(Vec::new()),
             executed_privileges: RefCell::new(Vec::new()),
+            executed_stdins: RefCell::new(Vec::new()),
             return_no_status: false,
+            staging_dir: None,
         }
     }
 
@@ -544,7 +229,9 @@ impl MockContext {
             error_message: None,
             executed_commands: RefCell::new(Vec::new()),
             executed_privileges: RefCell::new(Vec::new()),
+            executed_stdins: RefCell::new(Vec::new()),
             return_no_status: false,
+            staging_dir: None,
         }
     }
 
@@ -558,7 +245,9 @@ impl MockContext {
             error_message: Some(message.to_string()),
             executed_commands: RefCell::new(Vec::new()),
             executed_privileges: RefCell::new(Vec::new()),
+            executed_stdins: RefCell::new(Vec::new()),
             return_no_status: false,
+            staging_dir: None,
         }
     }
 
@@ -572,10 +261,18 @@ impl MockContext {
             error_message: None,
             executed_commands: RefCell::new(Vec::new()),
             executed_privileges: RefCell::new(Vec::new()),
+            executed_stdins: RefCell::new(Vec::new()),
             return_no_status: true,
+            staging_dir: None,
         }
     }
 
+    /// Stages task files in `dir` instead of `/tmp`.
+    pub fn with_staging_dir(mut self, dir: &str) -> Self {
+        self.staging_dir = Some(Utf8PathBuf::from(dir));
+        self
+    }
+
     pub fn executed_commands(&self) -> Vec<Vec<String>> {
         self.executed_commands.borrow().clone()
     }
@@ -583,6 +280,11 @@ impl MockContext {
     pub fn executed_privileges(&self) -> Vec<Option<rsdebstrap::privilege::PrivilegeMethod>> {
         self.executed_privileges.borrow().clone()
     }
+
+    /// Returns the input written to each executed command's stdin, if any.
+    pub fn executed_stdins(&self) -> Vec<Option<Vec<u8>>> {
+        self.executed_stdins.borrow().clone()
+    }
 }
 
 impl IsolationContext for MockContext {
@@ -598,6 +300,12 @@ impl IsolationContext for MockContext {
         self.dry_run
     }
 
+    fn staging_dir(&self) -> &Utf8Path {
+        self.staging_dir
+            .as_deref()
+            .unwrap_or_else(|| Utf8Path::new("/tmp"))
+    }
+
     fn executor(&self) -> &dyn rsdebstrap::executor::CommandExecutor {
         unimplemented!("MockContext does not provide a real executor")
     }
@@ -609,23 +317,44 @@ impl IsolationContext for MockContext {
     ) -> Result<ExecutionResult> {
         self.executed_commands.borrow_mut().push(command.to_vec());
         self.executed_privileges.borrow_mut().push(privilege);
+        self.executed_stdins.borrow_mut().push(None);
 
         if self.should_error {
             anyhow::bail!("{}", self.error_message.as_deref().unwrap_or("mock error"));
         }
 
         if self.return_no_status {
-            Ok(ExecutionResult { status: None })
+            Ok(ExecutionResult {
+                status: None,
+                details: None,
+            })
         } else if self.should_fail {
             let status = Some(ExitStatus::from_raw(self.exit_code.unwrap_or(1) << 8));
-            Ok(ExecutionResult { status })
+            Ok(ExecutionResult {
+                status,
+                details: None,
+            })
         } else {
             Ok(ExecutionResult {
                 status: Some(ExitStatus::from_raw(0)),
+                details: None,
             })
         }
     }
 
+    fn execute_with_stdin(
+        &self,
+        command: &[String],
+        privilege: Option<rsdebstrap::privilege::PrivilegeMethod>,
+        stdin: &[u8],
+    ) -> Result<ExecutionResult> {
+        let result = self.execute(command, privilege);
+        if let Some(last) = self.executed_stdins.borrow_mut().last_mut() {
+            *last = Some(stdin.to_vec());
+        }
+        result
+    }
+
     fn teardown(&mut self) -> Result<()> {
         Ok(())
     }
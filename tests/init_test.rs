@@ -0,0 +1,114 @@
+//! Integration tests for `run_init()`: the generated profile must load and
+//! validate through the normal `load_profile` path, and existing files are only
+//! replaced with `--force`.
+
+use anyhow::Result;
+use camino::Utf8PathBuf;
+use clap::Parser;
+use rsdebstrap::cli::{Cli, Commands, InitArgs};
+use rsdebstrap::config::{self, Bootstrap};
+use rsdebstrap::run_init;
+
+fn init_args(args: &[&str]) -> InitArgs {
+    let argv = ["rsdebstrap", "init"].iter().chain(args).copied();
+    match Cli::parse_from(argv).command {
+        Commands::Init(opts) => opts,
+        _ => panic!("Expected Init command"),
+    }
+}
+
+fn temp_profile_path() -> Result<(tempfile::TempDir, Utf8PathBuf)> {
+    let dir = tempfile::tempdir()?;
+    let path = Utf8PathBuf::from_path_buf(dir.path().join("profile.yml"))
+        .map_err(|p| anyhow::anyhow!("non-UTF-8 temp path: {}", p.display()))?;
+    Ok((dir, path))
+}
+
+#[test]
+fn test_init_writes_valid_mmdebstrap_profile() -> Result<()> {
+    let (dir, path) = temp_profile_path()?;
+    run_init(&init_args(&[
+        "-f",
+        path.as_str(),
+        "--mirror",
+        "http://a/debian",
+        "--mirror",
+        "http://b/debian",
+    ]))?;
+
+    let profile = config::load_profile(&path)?;
+    profile.validate()?;
+    assert_eq!(profile.dir, Utf8PathBuf::from_path_buf(dir.path().join("output")).unwrap());
+    match &profile.bootstrap {
+        Bootstrap::Mmdebstrap(cfg) => {
+            assert_eq!(cfg.suite, "trixie");
+            assert_eq!(cfg.target, "rootfs");
+            assert_eq!(cfg.mirrors, vec!["http://a/debian", "http://b/debian"]);
+        }
+        other => panic!("Expected mmdebstrap bootstrap, got {:?}", other),
+    }
+    assert_eq!(profile.provision.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_init_writes_valid_debootstrap_profile() -> Result<()> {
+    let (_dir, path) = temp_profile_path()?;
+    run_init(&init_args(&[
+        "-f",
+        path.as_str(),
+        "--backend",
+        "debootstrap",
+        "--suite",
+        "bookworm",
+        "--privilege",
+        "sudo",
+        "--no-example-task",
+    ]))?;
+
+    let profile = config::load_profile(&path)?;
+    profile.validate()?;
+    match &profile.bootstrap {
+        Bootstrap::Debootstrap(cfg) => {
+            assert_eq!(cfg.suite, "bookworm");
+            assert_eq!(cfg.mirror.as_deref(), Some("https://deb.debian.org/debian"));
+        }
+        other => panic!("Expected debootstrap bootstrap, got {:?}", other),
+    }
+    assert!(profile.defaults.privilege.is_some());
+    assert!(profile.provision.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_init_refuses_existing_file_without_force() -> Result<()> {
+    let (_dir, path) = temp_profile_path()?;
+    std::fs::write(&path, "keep me\n")?;
+
+    let err = run_init(&init_args(&["-f", path.as_str()])).unwrap_err();
+    assert!(err.to_string().contains("already exists"), "unexpected error: {err:#}");
+    assert_eq!(std::fs::read_to_string(&path)?, "keep me\n");
+
+    run_init(&init_args(&["-f", path.as_str(), "--force"]))?;
+    config::load_profile(&path)?.validate()?;
+    Ok(())
+}
+
+#[test]
+fn test_init_rejects_multiple_debootstrap_mirrors() -> Result<()> {
+    let (_dir, path) = temp_profile_path()?;
+    let err = run_init(&init_args(&[
+        "-f",
+        path.as_str(),
+        "--backend",
+        "debootstrap",
+        "--mirror",
+        "http://a/debian",
+        "--mirror",
+        "http://b/debian",
+    ]))
+    .unwrap_err();
+    assert!(err.to_string().contains("single mirror"), "unexpected error: {err:#}");
+    assert!(!path.exists());
+    Ok(())
+}
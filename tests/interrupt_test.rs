@@ -0,0 +1,39 @@
+//! Signal handling of `RealCommandExecutor`.
+//!
+//! The handlers are process-wide, so this file holds a single test and runs as
+//! its own test binary.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rsdebstrap::executor::{
+    CommandExecutor, CommandSpec, RealCommandExecutor, install_interrupt_handler, received_signal,
+};
+use rustix::process::{Signal, getpid, kill_process};
+
+#[test]
+fn sigint_cancels_running_command_and_lets_cleanup_run() {
+    install_interrupt_handler().expect("handlers should install");
+    assert_eq!(received_signal(), None);
+
+    let executor = RealCommandExecutor { dry_run: false };
+    let signaller = thread::spawn(|| {
+        thread::sleep(Duration::from_millis(300));
+        kill_process(getpid(), Signal::INT).expect("failed to signal self");
+    });
+
+    let started = Instant::now();
+    let err = executor
+        .execute(&CommandSpec::new("sleep", vec!["30".into()]))
+        .expect_err("the running command should be cancelled");
+    signaller.join().unwrap();
+
+    assert!(err.to_string().contains("interrupted"), "unexpected error: {err:#}");
+    assert!(started.elapsed() < Duration::from_secs(10), "command was not killed");
+    assert_eq!(received_signal(), Some(2));
+
+    // The interrupt is consumed by the cancelled command, so cleanup commands run.
+    executor
+        .execute_checked(&CommandSpec::new("true", Vec::new()))
+        .expect("cleanup command should run after the interrupt");
+}
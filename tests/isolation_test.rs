@@ -1,9 +1,11 @@
 use std::sync::{Arc, Mutex};
 
 use rsdebstrap::RsdebstrapError;
+use rsdebstrap::config::{ChrootBind, ChrootIsolation, SandboxConfig};
 use rsdebstrap::executor::{CommandExecutor, CommandSpec, ExecutionResult};
 use rsdebstrap::isolation::{ChrootProvider, DirectProvider, IsolationProvider};
 use rsdebstrap::privilege::PrivilegeMethod;
+use rsdebstrap::sandbox::Sandbox;
 
 type CommandCalls = Arc<Mutex<Vec<(String, Vec<String>, Option<PrivilegeMethod>)>>>;
 
@@ -18,7 +20,10 @@ impl CommandExecutor for RecordingExecutor {
             .lock()
             .unwrap()
             .push((spec.command.clone(), spec.args.clone(), spec.privilege));
-        Ok(ExecutionResult { status: None })
+        Ok(ExecutionResult {
+            status: None,
+            details: None,
+        })
     }
 }
 
@@ -28,13 +33,13 @@ impl CommandExecutor for RecordingExecutor {
 
 #[test]
 fn test_chroot_provider_name() {
-    let provider = ChrootProvider;
+    let provider = ChrootProvider::default();
     assert_eq!(provider.name(), "chroot");
 }
 
 #[test]
 fn test_chroot_provider_setup_creates_context() {
-    let provider = ChrootProvider;
+    let provider = ChrootProvider::default();
     let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor::default());
     let rootfs = camino::Utf8Path::new("/tmp/rootfs");
 
@@ -52,7 +57,7 @@ fn test_chroot_provider_setup_creates_context() {
 
 #[test]
 fn test_chroot_context_execute_builds_correct_args() {
-    let provider = ChrootProvider;
+    let provider = ChrootProvider::default();
     let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
     let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
         calls: Arc::clone(&calls),
@@ -77,7 +82,7 @@ fn test_chroot_context_execute_builds_correct_args() {
 
 #[test]
 fn test_chroot_context_execute_empty_command() {
-    let provider = ChrootProvider;
+    let provider = ChrootProvider::default();
     let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
     let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
         calls: Arc::clone(&calls),
@@ -99,7 +104,7 @@ fn test_chroot_context_execute_empty_command() {
 
 #[test]
 fn test_chroot_context_teardown_is_idempotent() {
-    let provider = ChrootProvider;
+    let provider = ChrootProvider::default();
     let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor::default());
     let rootfs = camino::Utf8Path::new("/tmp/rootfs");
 
@@ -114,7 +119,7 @@ fn test_chroot_context_teardown_is_idempotent() {
 
 #[test]
 fn test_chroot_context_multiple_executions() {
-    let provider = ChrootProvider;
+    let provider = ChrootProvider::default();
     let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
     let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
         calls: Arc::clone(&calls),
@@ -146,7 +151,7 @@ fn test_chroot_context_multiple_executions() {
 
 #[test]
 fn test_chroot_context_execute_after_teardown_returns_isolation_error() {
-    let provider = ChrootProvider;
+    let provider = ChrootProvider::default();
     let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor::default());
     let rootfs = camino::Utf8Path::new("/tmp/rootfs");
 
@@ -170,7 +175,7 @@ fn test_chroot_context_execute_after_teardown_returns_isolation_error() {
 
 #[test]
 fn test_chroot_context_propagates_sudo_privilege() {
-    let provider = ChrootProvider;
+    let provider = ChrootProvider::default();
     let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
     let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
         calls: Arc::clone(&calls),
@@ -194,7 +199,7 @@ fn test_chroot_context_propagates_sudo_privilege() {
 
 #[test]
 fn test_chroot_context_propagates_doas_privilege() {
-    let provider = ChrootProvider;
+    let provider = ChrootProvider::default();
     let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
     let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
         calls: Arc::clone(&calls),
@@ -214,7 +219,7 @@ fn test_chroot_context_propagates_doas_privilege() {
 
 #[test]
 fn test_chroot_context_propagates_none_privilege() {
-    let provider = ChrootProvider;
+    let provider = ChrootProvider::default();
     let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
     let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
         calls: Arc::clone(&calls),
@@ -232,19 +237,333 @@ fn test_chroot_context_propagates_none_privilege() {
     assert_eq!(*privilege, None);
 }
 
+// =============================================================================
+// Chroot options tests
+// =============================================================================
+
+/// Creates a rootfs whose account database has a `builder` user and a `staff` group.
+fn rootfs_with_builder() -> (tempfile::TempDir, camino::Utf8PathBuf) {
+    let temp = tempfile::tempdir().unwrap();
+    let rootfs = camino::Utf8PathBuf::from_path_buf(temp.path().to_path_buf()).unwrap();
+    std::fs::create_dir(rootfs.join("etc")).unwrap();
+    std::fs::write(
+        rootfs.join("etc/passwd"),
+        "root:x:0:0:root:/root:/bin/bash\nbuilder:x:1000:1000::/home/builder:/bin/sh\n",
+    )
+    .unwrap();
+    std::fs::write(rootfs.join("etc/group"), "root:x:0:\nstaff:x:50:\n").unwrap();
+    (temp, rootfs)
+}
+
+#[test]
+fn test_chroot_context_execute_applies_user_and_workdir() {
+    let provider = ChrootProvider::new(
+        ChrootIsolation {
+            workdir: Some("/srv/app".into()),
+            user: Some("builder:staff".to_string()),
+            ..Default::default()
+        },
+        None,
+    );
+    let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
+    let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
+        calls: Arc::clone(&calls),
+    });
+    let (_temp, rootfs) = rootfs_with_builder();
+    let command: Vec<String> = vec!["/bin/sh".to_string(), "/tmp/script.sh".to_string()];
+
+    let context = provider.setup(&rootfs, executor, false).unwrap();
+    context
+        .execute(&command, Some(PrivilegeMethod::Sudo))
+        .unwrap();
+
+    let calls = calls.lock().unwrap();
+    assert_eq!(calls.len(), 1);
+    let (cmd, args, privilege) = &calls[0];
+    assert_eq!(cmd, "chroot");
+    assert_eq!(
+        args,
+        &[
+            "--userspec=builder:staff",
+            rootfs.as_str(),
+            "/usr/bin/env",
+            "--chdir=/srv/app",
+            "HOME=/home/builder",
+            "/bin/sh",
+            "/tmp/script.sh",
+        ]
+    );
+    assert_eq!(*privilege, Some(PrivilegeMethod::Sudo));
+}
+
+#[test]
+fn test_pivot_root_context_runs_commands_through_pivot_exec() {
+    let provider = ChrootProvider::pivot_root(
+        ChrootIsolation {
+            workdir: Some("/srv/app".into()),
+            user: Some("builder:staff".to_string()),
+            ..Default::default()
+        },
+        None,
+    );
+    assert_eq!(provider.name(), "pivot_root");
+    let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
+    let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
+        calls: Arc::clone(&calls),
+    });
+    let (_temp, rootfs) = rootfs_with_builder();
+    let command: Vec<String> = vec!["/bin/sh".to_string(), "/tmp/script.sh".to_string()];
+
+    let context = provider.setup(&rootfs, executor, false).unwrap();
+    context
+        .execute(&command, Some(PrivilegeMethod::Sudo))
+        .unwrap();
+
+    let calls = calls.lock().unwrap();
+    let (cmd, args, privilege) = &calls[0];
+    assert_eq!(camino::Utf8Path::new(cmd), std::env::current_exe().unwrap());
+    assert_eq!(
+        args,
+        &[
+            "pivot-exec",
+            "--userspec=builder:staff",
+            rootfs.as_str(),
+            "--",
+            "/usr/bin/env",
+            "--chdir=/srv/app",
+            "HOME=/home/builder",
+            "/bin/sh",
+            "/tmp/script.sh",
+        ]
+    );
+    assert_eq!(*privilege, Some(PrivilegeMethod::Sudo));
+}
+
+#[test]
+fn test_pivot_exec_makes_the_rootfs_the_root_of_a_private_namespace() {
+    if !rustix::process::geteuid().is_root() {
+        eprintln!("skipping: pivot-exec needs root");
+        return;
+    }
+    let temp = tempfile::tempdir().unwrap();
+    let rootfs = camino::Utf8Path::from_path(temp.path()).unwrap();
+    // A rootfs holding just /bin/sh and the libraries it loads.
+    let copy = std::process::Command::new("sh")
+        .args([
+            "-c",
+            "for f in /bin/sh $(ldd /bin/sh | grep -o '/[^ ]*'); do \
+             mkdir -p \"$0$(dirname $f)\" && cp -L $f \"$0$f\" || exit 1; done",
+            rootfs.as_str(),
+        ])
+        .status()
+        .unwrap();
+    assert!(copy.success());
+    std::fs::create_dir(rootfs.join("marker")).unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_rsdebstrap"))
+        .args(["pivot-exec", rootfs.as_str(), "--", "/bin/sh", "-c"])
+        .arg("cd /marker && cd /.. && [ -d /marker ] && [ ! -e /root ] && echo inside")
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.contains("failed to create a mount namespace") {
+        eprintln!("skipping: mount namespaces are not available");
+        return;
+    }
+    assert!(output.status.success(), "{stderr}");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "inside\n");
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").unwrap();
+    assert!(!mountinfo.contains(rootfs.as_str()), "{mountinfo}");
+}
+
+#[test]
+fn test_chroot_context_execute_applies_clean_environment() {
+    let provider = ChrootProvider::new(
+        ChrootIsolation {
+            workdir: Some("/srv/app".into()),
+            clean_env: true,
+            env: [("LANG".to_string(), "C.UTF-8".to_string())].into(),
+            ..Default::default()
+        },
+        None,
+    );
+    let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
+    let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
+        calls: Arc::clone(&calls),
+    });
+    let rootfs = camino::Utf8Path::new("/tmp/rootfs");
+    let command: Vec<String> = vec!["/bin/sh".to_string(), "/tmp/script.sh".to_string()];
+
+    let context = provider.setup(rootfs, executor, false).unwrap();
+    context.execute(&command, None).unwrap();
+
+    let calls = calls.lock().unwrap();
+    let (_, args, _) = &calls[0];
+    assert_eq!(
+        args,
+        &[
+            "/tmp/rootfs",
+            "/usr/bin/env",
+            "-i",
+            "--chdir=/srv/app",
+            "PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin",
+            "HOME=/root",
+            "LANG=C.UTF-8",
+            "/bin/sh",
+            "/tmp/script.sh",
+        ]
+    );
+}
+
+#[test]
+fn test_chroot_context_hand_over_chowns_inside_rootfs() {
+    let provider = ChrootProvider::new(
+        ChrootIsolation {
+            user: Some("builder".to_string()),
+            ..Default::default()
+        },
+        None,
+    );
+    let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
+    let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
+        calls: Arc::clone(&calls),
+    });
+    let (_temp, rootfs) = rootfs_with_builder();
+
+    let context = provider.setup(&rootfs, executor, false).unwrap();
+    context
+        .hand_over(&["/tmp/task-1/script.sh".to_string()], Some(PrivilegeMethod::Sudo))
+        .unwrap();
+
+    let calls = calls.lock().unwrap();
+    assert_eq!(calls.len(), 1);
+    let (cmd, args, privilege) = &calls[0];
+    assert_eq!(cmd, "chroot");
+    assert_eq!(args, &[rootfs.as_str(), "chown", "builder", "/tmp/task-1/script.sh"]);
+    assert_eq!(*privilege, Some(PrivilegeMethod::Sudo));
+}
+
+#[test]
+fn test_chroot_provider_setup_rejects_unknown_accounts() {
+    let (_temp, rootfs) = rootfs_with_builder();
+    for (user, expected) in [
+        ("nobody", "chroot user 'nobody' does not exist"),
+        ("builder:wheel", "chroot group 'wheel' does not exist"),
+    ] {
+        let provider = ChrootProvider::new(
+            ChrootIsolation {
+                user: Some(user.to_string()),
+                ..Default::default()
+            },
+            None,
+        );
+        let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor::default());
+
+        let err = provider.setup(&rootfs, executor, false).err().unwrap();
+
+        assert!(err.to_string().contains(expected), "{user}: {err}");
+    }
+}
+
+#[test]
+fn test_chroot_provider_setup_skips_account_check_in_dry_run_and_for_ids() {
+    let (_temp, rootfs) = rootfs_with_builder();
+    for (user, dry_run) in [("nobody", true), ("2000:2000", false)] {
+        let provider = ChrootProvider::new(
+            ChrootIsolation {
+                user: Some(user.to_string()),
+                ..Default::default()
+            },
+            None,
+        );
+        let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor::default());
+
+        assert!(provider.setup(&rootfs, executor, dry_run).is_ok(), "{user}");
+    }
+}
+
+#[test]
+fn test_chroot_context_hand_over_without_user_is_noop() {
+    let provider = ChrootProvider::default();
+    let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
+    let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
+        calls: Arc::clone(&calls),
+    });
+    let rootfs = camino::Utf8Path::new("/tmp/rootfs");
+
+    let context = provider.setup(rootfs, executor, false).unwrap();
+    context
+        .hand_over(&["/tmp/task-1/script.sh".to_string()], None)
+        .unwrap();
+
+    assert!(calls.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_chroot_binds_are_mounted_on_setup_and_unmounted_on_teardown() {
+    let provider = ChrootProvider::new(
+        ChrootIsolation {
+            binds: vec![
+                ChrootBind {
+                    source: "/srv/cache".into(),
+                    target: "/var/cache/build".into(),
+                    ro: false,
+                },
+                ChrootBind {
+                    source: "/srv/secrets".into(),
+                    target: "/run/secrets".into(),
+                    ro: true,
+                },
+            ],
+            ..Default::default()
+        },
+        Some(PrivilegeMethod::Sudo),
+    );
+    let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
+    let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
+        calls: Arc::clone(&calls),
+    });
+    let rootfs = camino::Utf8Path::new("/tmp/rootfs");
+
+    let mut context = provider.setup(rootfs, executor, true).unwrap();
+    {
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].0, "mount");
+        assert_eq!(calls[0].1, ["-o", "bind", "/srv/cache", "/tmp/rootfs/var/cache/build"]);
+        assert_eq!(calls[1].0, "mount");
+        assert_eq!(calls[1].1, ["-o", "bind,ro", "/srv/secrets", "/tmp/rootfs/run/secrets"]);
+        assert!(
+            calls
+                .iter()
+                .all(|(_, _, privilege)| *privilege == Some(PrivilegeMethod::Sudo))
+        );
+    }
+
+    context.teardown().unwrap();
+    context.teardown().unwrap();
+
+    let calls = calls.lock().unwrap();
+    assert_eq!(calls.len(), 4);
+    assert_eq!(calls[2].0, "umount");
+    assert_eq!(calls[2].1, ["/tmp/rootfs/run/secrets"]);
+    assert_eq!(calls[3].0, "umount");
+    assert_eq!(calls[3].1, ["/tmp/rootfs/var/cache/build"]);
+}
+
 // =============================================================================
 // DirectProvider tests
 // =============================================================================
 
 #[test]
 fn test_direct_provider_name() {
-    let provider = DirectProvider;
+    let provider = DirectProvider::default();
     assert_eq!(provider.name(), "direct");
 }
 
 #[test]
 fn test_direct_provider_setup_creates_context() {
-    let provider = DirectProvider;
+    let provider = DirectProvider::default();
     let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor::default());
     let rootfs = camino::Utf8Path::new("/tmp/rootfs");
 
@@ -262,7 +581,7 @@ fn test_direct_provider_setup_creates_context() {
 
 #[test]
 fn test_direct_context_execute_translates_absolute_paths() {
-    let provider = DirectProvider;
+    let provider = DirectProvider::default();
     let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
     let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
         calls: Arc::clone(&calls),
@@ -284,7 +603,7 @@ fn test_direct_context_execute_translates_absolute_paths() {
 
 #[test]
 fn test_direct_context_execute_preserves_relative_paths() {
-    let provider = DirectProvider;
+    let provider = DirectProvider::default();
     let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
     let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
         calls: Arc::clone(&calls),
@@ -305,7 +624,7 @@ fn test_direct_context_execute_preserves_relative_paths() {
 
 #[test]
 fn test_direct_context_execute_empty_command_returns_error() {
-    let provider = DirectProvider;
+    let provider = DirectProvider::default();
     let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor::default());
     let rootfs = camino::Utf8Path::new("/tmp/rootfs");
     let command: Vec<String> = vec![];
@@ -330,7 +649,7 @@ fn test_direct_context_execute_empty_command_returns_error() {
 
 #[test]
 fn test_direct_context_teardown_is_idempotent() {
-    let provider = DirectProvider;
+    let provider = DirectProvider::default();
     let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor::default());
     let rootfs = camino::Utf8Path::new("/tmp/rootfs");
 
@@ -345,7 +664,7 @@ fn test_direct_context_teardown_is_idempotent() {
 
 #[test]
 fn test_direct_context_multiple_executions() {
-    let provider = DirectProvider;
+    let provider = DirectProvider::default();
     let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
     let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
         calls: Arc::clone(&calls),
@@ -373,7 +692,7 @@ fn test_direct_context_multiple_executions() {
 
 #[test]
 fn test_direct_context_execute_after_teardown_returns_isolation_error() {
-    let provider = DirectProvider;
+    let provider = DirectProvider::default();
     let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor::default());
     let rootfs = camino::Utf8Path::new("/tmp/rootfs");
 
@@ -397,7 +716,7 @@ fn test_direct_context_execute_after_teardown_returns_isolation_error() {
 
 #[test]
 fn test_direct_context_propagates_sudo_privilege() {
-    let provider = DirectProvider;
+    let provider = DirectProvider::default();
     let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
     let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
         calls: Arc::clone(&calls),
@@ -419,7 +738,7 @@ fn test_direct_context_propagates_sudo_privilege() {
 
 #[test]
 fn test_direct_context_propagates_doas_privilege() {
-    let provider = DirectProvider;
+    let provider = DirectProvider::default();
     let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
     let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
         calls: Arc::clone(&calls),
@@ -439,7 +758,7 @@ fn test_direct_context_propagates_doas_privilege() {
 
 #[test]
 fn test_direct_context_propagates_none_privilege() {
-    let provider = DirectProvider;
+    let provider = DirectProvider::default();
     let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
     let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
         calls: Arc::clone(&calls),
@@ -456,3 +775,117 @@ fn test_direct_context_propagates_none_privilege() {
     let (_, _, privilege) = &calls[0];
     assert_eq!(*privilege, None);
 }
+
+// =============================================================================
+// DirectContext sandbox tests
+// =============================================================================
+
+#[test]
+fn test_direct_context_runs_commands_through_the_sandbox() {
+    let sandbox =
+        Sandbox::new(&SandboxConfig::default(), [camino::Utf8PathBuf::from("/tmp/rootfs")]);
+    let provider = DirectProvider::new(Some(sandbox));
+    let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
+    let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
+        calls: Arc::clone(&calls),
+    });
+    let rootfs = camino::Utf8Path::new("/tmp/rootfs");
+    let command: Vec<String> = vec!["/bin/sh".to_string(), "/tmp/script.sh".to_string()];
+
+    let context = provider.setup(rootfs, executor, false).unwrap();
+    context
+        .execute(&command, Some(PrivilegeMethod::Sudo))
+        .unwrap();
+
+    let calls = calls.lock().unwrap();
+    let (cmd, args, privilege) = &calls[0];
+    assert_eq!(camino::Utf8Path::new(cmd), std::env::current_exe().unwrap());
+    assert_eq!(
+        args,
+        &[
+            "sandbox-exec",
+            "--write",
+            "/tmp/rootfs",
+            "--write",
+            "/dev/null",
+            "--",
+            "/tmp/rootfs/bin/sh",
+            "/tmp/rootfs/tmp/script.sh",
+        ]
+    );
+    // The helper runs after privilege escalation, which the sandbox would prevent.
+    assert_eq!(*privilege, Some(PrivilegeMethod::Sudo));
+}
+
+#[test]
+fn test_sandbox_exec_restricts_writes_and_syscalls() {
+    let dir = tempfile::tempdir().unwrap();
+    let writable = dir.path().join("writable");
+    std::fs::create_dir(&writable).unwrap();
+    let script = format!(
+        "touch {0}/allowed; touch {1}/denied; grep -E '^(Seccomp|NoNewPrivs):' /proc/self/status",
+        writable.display(),
+        dir.path().display()
+    );
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_rsdebstrap"))
+        .args(["sandbox-exec", "--write"])
+        .arg(&writable)
+        .args(["--write", "/dev/null", "--", "sh", "-c", &script])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if stderr.contains("Landlock is not available") {
+        eprintln!("skipping: the kernel has no Landlock");
+        return;
+    }
+    assert!(output.status.success(), "{stderr}");
+    assert!(writable.join("allowed").exists());
+    assert!(!dir.path().join("denied").exists());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Seccomp:\t2"), "{stdout}");
+    assert!(stdout.contains("NoNewPrivs:\t1"), "{stdout}");
+}
+
+// =============================================================================
+// stdin tests
+// =============================================================================
+
+/// Executor that records the stdin of each command.
+#[derive(Default)]
+struct StdinExecutor {
+    stdins: Arc<Mutex<Vec<Option<Vec<u8>>>>>,
+}
+
+impl CommandExecutor for StdinExecutor {
+    fn execute(&self, spec: &CommandSpec) -> anyhow::Result<ExecutionResult> {
+        self.stdins.lock().unwrap().push(spec.stdin.clone());
+        Ok(ExecutionResult {
+            status: None,
+            details: None,
+        })
+    }
+}
+
+#[test]
+fn test_contexts_pass_stdin_to_executor() {
+    let providers: [Box<dyn IsolationProvider>; 2] = [
+        Box::new(ChrootProvider::default()),
+        Box::new(DirectProvider::default()),
+    ];
+    for provider in providers {
+        let executor = Arc::new(StdinExecutor::default());
+        let stdins = Arc::clone(&executor.stdins);
+        let rootfs = camino::Utf8Path::new("/tmp/rootfs");
+        let command = vec!["/bin/sh".to_string()];
+
+        let context = provider.setup(rootfs, executor, false).unwrap();
+        context
+            .execute_with_stdin(&command, None, b"echo hi\n")
+            .unwrap();
+        context.execute(&command, None).unwrap();
+
+        let stdins = stdins.lock().unwrap();
+        assert_eq!(stdins[0].as_deref(), Some(&b"echo hi\n"[..]), "{}", provider.name());
+        assert_eq!(stdins[1], None, "{}", provider.name());
+    }
+}
@@ -0,0 +1,150 @@
+//! Deserialization, validation and execution tests for KernelTask.
+
+mod helpers;
+
+use camino::Utf8Path;
+use rsdebstrap::RsdebstrapError;
+use rsdebstrap::config::IsolationConfig;
+use rsdebstrap::isolation::TaskIsolation;
+use rsdebstrap::phase::provision::kernel::is_sysctl_key;
+use rsdebstrap::phase::{KernelTask, ProvisionTask};
+use tempfile::tempdir;
+
+use crate::helpers::MockContext;
+
+fn resolved(mut task: KernelTask) -> KernelTask {
+    task.resolve_privilege(None).unwrap();
+    task.resolve_isolation(&IsolationConfig::default());
+    task
+}
+
+#[test]
+fn test_deserialize_kernel_task() {
+    // editorconfig-checker-disable
+    let yaml = r#"type: kernel
+modules: [br_netfilter, overlay]
+sysctl:
+  net.ipv4.ip_forward: 1
+  net.bridge.bridge-nf-call-iptables: true
+  kernel.core_pattern: "|/bin/false"
+file_name: 60-containers
+"#;
+    // editorconfig-checker-enable
+    let task: ProvisionTask = yaml_serde::from_str(yaml).expect("should parse kernel task");
+    let ProvisionTask::Kernel(kernel) = &task else {
+        panic!("Expected Kernel task, got: {:?}", task);
+    };
+    assert_eq!(kernel.modules(), ["br_netfilter", "overlay"]);
+    assert_eq!(kernel.sysctl()["net.ipv4.ip_forward"], "1");
+    assert_eq!(kernel.sysctl()["net.bridge.bridge-nf-call-iptables"], "true");
+    assert_eq!(kernel.file_name(), "60-containers");
+    assert_eq!(task.name(), "kernel:60-containers.conf");
+
+    let yaml = yaml_serde::to_string(&task).unwrap();
+    assert_eq!(yaml_serde::from_str::<ProvisionTask>(&yaml).unwrap(), task);
+}
+
+#[test]
+fn test_deserialize_rejects_invalid_values_and_fields() {
+    for yaml in [
+        "type: kernel\nmodules: [1]\n",
+        "type: kernel\nsysctl:\n  vm.swappiness: [10]\n",
+        "type: kernel\nsysctl:\n  vm.swappiness: null\n",
+        "type: kernel\nmodules: [loop]\nfile: x.conf\n",
+    ] {
+        assert!(yaml_serde::from_str::<ProvisionTask>(yaml).is_err(), "{yaml}");
+    }
+}
+
+#[test]
+fn test_sysctl_key_syntax() {
+    for key in [
+        "net.ipv4.ip_forward",
+        "net/ipv4/conf/eth0.100/rp_filter",
+        "-net.ipv6.conf.all.disable_ipv6",
+        "net.ipv4.conf.*.rp_filter",
+    ] {
+        assert!(is_sysctl_key(key), "{key}");
+    }
+    for key in [
+        "",
+        "-",
+        "net..ipv4",
+        ".net",
+        "net.ipv4 .ip_forward",
+        "net.ipv4=1",
+    ] {
+        assert!(!is_sysctl_key(key), "{key}");
+    }
+}
+
+#[test]
+fn test_validate_rejects_invalid_tasks() {
+    let cases = [
+        KernelTask::new(),
+        KernelTask::new()
+            .with_modules(["loop"])
+            .with_isolation(TaskIsolation::Disabled),
+        KernelTask::new().with_modules(["loop", "loop"]),
+        KernelTask::new().with_modules(["loop max_loop=8"]),
+        KernelTask::new().with_sysctl("vm swappiness", "10"),
+        KernelTask::new().with_sysctl("vm.swappiness", ""),
+        KernelTask::new().with_sysctl("vm.swappiness", "10\nkernel.panic = 0"),
+        KernelTask::new()
+            .with_modules(["loop"])
+            .with_file_name("../sysctl"),
+    ];
+    for task in cases {
+        assert!(task.validate().is_err(), "{:?} should be rejected", task);
+    }
+
+    let err = KernelTask::new().validate().unwrap_err();
+    assert!(matches!(err, RsdebstrapError::Validation(_)), "{err}");
+}
+
+#[test]
+fn test_script_writes_only_configured_files() {
+    let task = KernelTask::new()
+        .with_sysctl("vm.swappiness", "10")
+        .with_sysctl("kernel.core_pattern", "|/bin/it's");
+    assert!(task.validate().is_ok());
+    assert_eq!(task.modules_load_conf(), None);
+    assert_eq!(
+        task.sysctl_conf().unwrap(),
+        "# Generated by rsdebstrap\nkernel.core_pattern = |/bin/it's\nvm.swappiness = 10\n"
+    );
+
+    let script = task.script();
+    assert!(!script.contains("modules-load.d"), "{script}");
+    assert!(
+        script.ends_with(
+            "mkdir -p /etc/sysctl.d\n\
+             printf '%s' '# Generated by rsdebstrap\n\
+             kernel.core_pattern = |/bin/it'\\''s\nvm.swappiness = 10\n' > \
+             '/etc/sysctl.d/90-rsdebstrap.conf'\n\
+             chmod 644 '/etc/sysctl.d/90-rsdebstrap.conf'\n"
+        ),
+        "{script}"
+    );
+}
+
+#[test]
+fn test_execute_pipes_script_into_shell() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let rootfs = Utf8Path::from_path(temp_dir.path()).expect("path should be valid UTF-8");
+
+    let task = resolved(KernelTask::new().with_modules(["overlay"]));
+    let context = MockContext::new(rootfs);
+    task.execute(&context).expect("execute should succeed");
+
+    assert_eq!(context.executed_commands(), [["/bin/sh", "-s"]]);
+    assert_eq!(context.executed_stdins(), [Some(task.script().into_bytes())]);
+    assert!(
+        task.script()
+            .contains("/etc/modules-load.d/90-rsdebstrap.conf")
+    );
+
+    let context = MockContext::with_failure(rootfs, 1);
+    let err = task.execute(&context).unwrap_err();
+    assert!(format!("{:#}", err).contains("/bin/sh"), "{err:#}");
+}
@@ -0,0 +1,80 @@
+//! Integration tests for `run_migrate()`: a legacy profile that `load_profile`
+//! refuses loads after `migrate --in-place`, with its comments and permissions kept.
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+use anyhow::Result;
+use camino::Utf8PathBuf;
+use clap::Parser;
+use rsdebstrap::cli::{Cli, Commands, MigrateArgs};
+use rsdebstrap::config;
+use rsdebstrap::run_migrate;
+
+fn migrate_args(args: &[&str]) -> MigrateArgs {
+    let argv = ["rsdebstrap", "migrate"].iter().chain(args).copied();
+    match Cli::parse_from(argv).command {
+        Commands::Migrate(opts) => opts,
+        _ => panic!("Expected Migrate command"),
+    }
+}
+
+// editorconfig-checker-disable
+const LEGACY: &str = r#"---
+dir: /tmp/migrate-test
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+# Generate the resolver config
+pre_processors:
+- type: resolv_conf
+  name_servers: [9.9.9.9]
+provisioners:
+- type: shell
+  name: hello
+  content: echo hello
+"#;
+// editorconfig-checker-enable
+
+#[test]
+fn test_migrate_in_place_converts_legacy_profile() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = Utf8PathBuf::from_path_buf(dir.path().join("legacy.yml"))
+        .map_err(|p| anyhow::anyhow!("non-UTF-8 temp path: {}", p.display()))?;
+    fs::write(&path, LEGACY)?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o640))?;
+
+    let err = config::load_profile(&path).unwrap_err();
+    assert!(err.to_string().contains("rsdebstrap migrate"), "{}", err);
+
+    let opts = migrate_args(&["--file", path.as_str(), "--in-place"]);
+    assert!(opts.in_place);
+    run_migrate(&opts)?;
+
+    let migrated = fs::read_to_string(&path)?;
+    assert!(migrated.contains("# Generate the resolver config\nprepare:\n"), "{}", migrated);
+    assert_eq!(fs::metadata(&path)?.permissions().mode() & 0o777, 0o640);
+    let profile = config::load_profile(&path)?;
+    assert_eq!(profile.schema_version, Some(1));
+    assert!(profile.prepare.resolv_conf.is_some());
+    assert_eq!(profile.provision[0].configured_name(), Some("hello"));
+
+    // A second run has nothing left to do.
+    run_migrate(&opts)?;
+    assert_eq!(fs::read_to_string(&path)?, migrated);
+    Ok(())
+}
+
+#[test]
+fn test_migrate_without_in_place_leaves_the_file() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("legacy.yml");
+    fs::write(&path, LEGACY)?;
+
+    let opts = migrate_args(&["--file", path.to_str().unwrap()]);
+    assert!(!opts.in_place);
+    run_migrate(&opts)?;
+    assert_eq!(fs::read_to_string(&path)?, LEGACY);
+    Ok(())
+}
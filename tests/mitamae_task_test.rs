@@ -4,6 +4,8 @@ mod helpers;
 
 use rsdebstrap::RsdebstrapError;
 use rsdebstrap::config::IsolationConfig;
+use rsdebstrap::executor::{CommandExecutor, CommandSpec, ExecutionResult, RealCommandExecutor};
+use rsdebstrap::phase::provision::mitamae::{self, MitamaeRelease};
 use rsdebstrap::phase::{MitamaeTask, ScriptSource};
 use tempfile::tempdir;
 
@@ -315,3 +317,195 @@ fn test_execute_without_tmp_directory() {
         err_msg
     );
 }
+
+/// SHA-256 of the bytes `fake mitamae binary`.
+const FAKE_BINARY_SHA256: &str = "0fac5dd00f9f11d48dbe91e66426253841929025cd58d235a8c64a75cfcaea98";
+
+/// `curl` stand-in that writes a fake mitamae binary to the `--output` path.
+struct FakeCurl;
+
+impl CommandExecutor for FakeCurl {
+    fn execute(&self, spec: &CommandSpec) -> anyhow::Result<ExecutionResult> {
+        let output = spec.args.iter().position(|a| a == "--output").unwrap() + 1;
+        std::fs::write(&spec.args[output], "fake mitamae binary")?;
+        Ok(ExecutionResult {
+            status: Some(std::os::unix::process::ExitStatusExt::from_raw(0)),
+            details: None,
+        })
+    }
+}
+
+#[test]
+fn test_download_binary_then_execute() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let rootfs = camino::Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf())
+        .expect("path should be valid UTF-8");
+    setup_rootfs_with_tmp(&temp_dir);
+    let downloads = tempdir().expect("failed to create temp dir");
+    let dest = camino::Utf8PathBuf::from_path_buf(downloads.path().join("mitamae"))
+        .expect("path should be valid UTF-8");
+
+    let mut task = MitamaeTask::with_url(
+        ScriptSource::Content("package 'vim'".to_string()),
+        "https://example.org/mitamae-x86_64-linux",
+        FAKE_BINARY_SHA256,
+    );
+    task.resolve_privilege(None).unwrap();
+    task.resolve_isolation(&IsolationConfig::default());
+
+    task.download_binary(&dest, &FakeCurl, false)
+        .expect("download should succeed");
+    assert_eq!(task.binary(), Some(dest.as_path()));
+
+    let context = MockContext::new(&rootfs);
+    task.execute(&context).expect("execute should succeed");
+    assert_eq!(context.executed_commands().len(), 1);
+}
+
+#[test]
+fn test_download_binary_rejects_checksum_mismatch() {
+    let downloads = tempdir().expect("failed to create temp dir");
+    let dest = camino::Utf8PathBuf::from_path_buf(downloads.path().join("mitamae"))
+        .expect("path should be valid UTF-8");
+
+    let mut task = MitamaeTask::with_url(
+        ScriptSource::Content("package 'vim'".to_string()),
+        "https://example.org/mitamae-x86_64-linux",
+        "0".repeat(64),
+    );
+
+    let err = task.download_binary(&dest, &FakeCurl, false).unwrap_err();
+
+    assert!(
+        matches!(
+            err.downcast_ref::<RsdebstrapError>(),
+            Some(RsdebstrapError::ChecksumMismatch { .. })
+        ),
+        "Expected ChecksumMismatch, got: {:#}",
+        err
+    );
+    assert_eq!(task.binary(), None, "a rejected download must not be used");
+}
+
+#[test]
+fn test_release_arch_maps_rust_architectures_to_release_assets() {
+    assert_eq!(mitamae::release_arch("x86_64"), Some("x86_64"));
+    assert_eq!(mitamae::release_arch("aarch64"), Some("aarch64"));
+    assert_eq!(mitamae::release_arch("arm"), Some("armhf"));
+    assert_eq!(mitamae::release_arch("x86"), Some("i386"));
+    assert_eq!(mitamae::release_arch("riscv64"), None);
+}
+
+#[test]
+fn test_release_url_names_the_versioned_asset() {
+    let release = MitamaeRelease::new("1.14.1", "aarch64", "0".repeat(64));
+    assert_eq!(
+        release.url(),
+        "https://github.com/itamae-kitchen/mitamae/releases/download/v1.14.1/\
+        mitamae-aarch64-linux.tar.gz"
+    );
+}
+
+#[test]
+fn test_release_fetch_extracts_binary_from_cached_tarball() {
+    let staging = tempdir().expect("failed to create temp dir");
+    std::fs::write(staging.path().join("mitamae-x86_64-linux"), "fake mitamae binary")
+        .expect("failed to write binary");
+    let cache = tempdir().expect("failed to create temp dir");
+    let cache_dir = camino::Utf8PathBuf::from_path_buf(cache.path().to_path_buf())
+        .expect("path should be valid UTF-8");
+    let tarball = cache_dir.join("release.tar.gz");
+    let executor = RealCommandExecutor { dry_run: false };
+    executor
+        .execute_checked(&CommandSpec::new(
+            "tar",
+            vec![
+                "-czf".to_string(),
+                tarball.to_string(),
+                "-C".to_string(),
+                staging.path().to_str().unwrap().to_string(),
+                "mitamae-x86_64-linux".to_string(),
+            ],
+        ))
+        .expect("failed to create tarball");
+    let sha256 = rsdebstrap::download::sha256_file(&tarball).unwrap();
+    // Pre-placing the tarball under its digest keeps the cache from downloading it.
+    std::fs::rename(&tarball, cache_dir.join(&sha256)).unwrap();
+
+    let release = MitamaeRelease::new("1.14.1", "x86_64", &sha256);
+    let binary = release
+        .fetch(&cache_dir, &executor, false)
+        .expect("fetch should succeed");
+
+    assert_eq!(binary, cache_dir.join(format!("{}-mitamae-x86_64-linux", sha256)));
+    assert_eq!(std::fs::read_to_string(&binary).unwrap(), "fake mitamae binary");
+}
+
+#[test]
+fn test_execute_rejects_binary_modified_after_download() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let rootfs = camino::Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf())
+        .expect("path should be valid UTF-8");
+    setup_rootfs_with_tmp(&temp_dir);
+    let downloads = tempdir().expect("failed to create temp dir");
+    let dest = camino::Utf8PathBuf::from_path_buf(downloads.path().join("mitamae"))
+        .expect("path should be valid UTF-8");
+
+    let mut task = MitamaeTask::with_url(
+        ScriptSource::Content("package 'vim'".to_string()),
+        "https://example.org/mitamae-x86_64-linux",
+        FAKE_BINARY_SHA256,
+    );
+    task.resolve_privilege(None).unwrap();
+    task.resolve_isolation(&IsolationConfig::default());
+    task.download_binary(&dest, &FakeCurl, false)
+        .expect("download should succeed");
+    std::fs::write(&dest, "tampered").expect("failed to overwrite binary");
+
+    let context = MockContext::new(&rootfs);
+    let err = task.execute(&context).unwrap_err();
+
+    assert!(format!("{:#}", err).contains("checksum mismatch"), "got: {:#}", err);
+    assert!(context.executed_commands().is_empty(), "tampered binary must not run");
+}
+
+#[test]
+fn test_execute_recipes_and_attributes_in_one_invocation() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let rootfs = camino::Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf())
+        .expect("path should be valid UTF-8");
+
+    setup_rootfs_with_tmp(&temp_dir);
+    let binary = create_fake_binary(&temp_dir);
+    let base = rootfs.join("base.rb");
+    let web = rootfs.join("web.rb");
+    std::fs::write(&base, "package 'vim'\n").expect("failed to write recipe");
+    std::fs::write(&web, "package 'nginx'\n").expect("failed to write recipe");
+
+    let attributes = [("port".to_string(), serde_json::json!(8080))]
+        .into_iter()
+        .collect();
+    let mut task = MitamaeTask::new(ScriptSource::Script(base), binary)
+        .with_recipes([web])
+        .with_attributes(attributes);
+    task.resolve_privilege(None).unwrap();
+    task.resolve_isolation(&IsolationConfig::default());
+
+    let context = MockContext::new(&rootfs);
+    task.execute(&context).expect("execute should succeed");
+
+    let commands = context.executed_commands();
+    assert_eq!(commands.len(), 1, "Expected a single mitamae invocation");
+    let cmd = &commands[0];
+    assert_eq!(cmd.len(), 6, "{:?}", cmd);
+    assert_eq!(cmd[1], "local");
+    assert_eq!(cmd[2], "--node-json");
+    assert!(cmd[3].starts_with("/tmp/node-") && cmd[3].ends_with(".json"), "{}", cmd[3]);
+    assert!(cmd[4].starts_with("/tmp/recipe-") && cmd[4].ends_with(".rb"), "{}", cmd[4]);
+    assert!(cmd[5].starts_with("/tmp/recipe-") && cmd[5].ends_with("-1.rb"), "{}", cmd[5]);
+
+    let remaining = std::fs::read_dir(temp_dir.path().join("tmp"))
+        .expect("failed to read tmp dir")
+        .count();
+    assert_eq!(remaining, 0, "Expected staged files to be cleaned up");
+}
@@ -4,8 +4,10 @@ use std::sync::{Arc, Mutex};
 
 use camino::Utf8Path;
 use rsdebstrap::{
-    cli,
+    RsdebstrapError, cli,
+    error::{exit_code, exit_codes},
     executor::{CommandExecutor, CommandSpec, ExecutionResult},
+    privilege::PrivilegeMethod,
     run_apply, run_validate,
 };
 use tempfile::NamedTempFile;
@@ -23,7 +25,10 @@ impl CommandExecutor for RecordingExecutor {
             .lock()
             .unwrap()
             .push((spec.command.clone(), spec.args.clone()));
-        Ok(ExecutionResult { status: None })
+        Ok(ExecutionResult {
+            status: None,
+            details: None,
+        })
     }
 }
 
@@ -111,8 +116,30 @@ fn run_apply_uses_executor_with_built_args() {
         common: cli::CommonArgs {
             file: path.to_owned(),
             log_level: cli::LogLevel::Error,
+            no_user_config: true,
         },
+        strictness: cli::StrictArgs::default(),
+        target: cli::TargetArgs::default(),
         dry_run: true,
+        plan: false,
+        clean_stale_mounts: false,
+        allow_existing: false,
+        wait_for_lock: false,
+        only: vec![],
+        skip: vec![],
+        skip_bootstrap: false,
+        tags: vec![],
+        skip_tags: vec![],
+        start_at_task: None,
+        source_commit: None,
+        overlay: false,
+        layer_cache: None,
+        remote: None,
+        remote_dir: "rsdebstrap-remote".into(),
+        metrics: vec![],
+        summary: None,
+        events_fd: None,
+        events_file: None,
     };
     let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
     let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
@@ -136,8 +163,30 @@ fn run_apply_uses_executor_with_debootstrap_args() {
         common: cli::CommonArgs {
             file: path.to_owned(),
             log_level: cli::LogLevel::Error,
+            no_user_config: true,
         },
+        strictness: cli::StrictArgs::default(),
+        target: cli::TargetArgs::default(),
         dry_run: true,
+        plan: false,
+        clean_stale_mounts: false,
+        allow_existing: false,
+        wait_for_lock: false,
+        only: vec![],
+        skip: vec![],
+        skip_bootstrap: false,
+        tags: vec![],
+        skip_tags: vec![],
+        start_at_task: None,
+        source_commit: None,
+        overlay: false,
+        layer_cache: None,
+        remote: None,
+        remote_dir: "rsdebstrap-remote".into(),
+        metrics: vec![],
+        summary: None,
+        events_fd: None,
+        events_file: None,
     };
     let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
     let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
@@ -170,12 +219,43 @@ fn run_validate_succeeds_on_valid_profile() {
         common: cli::CommonArgs {
             file: path.to_owned(),
             log_level: cli::LogLevel::Error,
+            no_user_config: true,
         },
+        strictness: cli::StrictArgs::default(),
+        target: cli::TargetArgs::default(),
+        resolved: false,
+        lint: false,
     };
 
     run_validate(&opts).expect("run_validate should succeed for sample profile");
 }
 
+#[test]
+fn run_validate_no_strict_ignores_unknown_fields() {
+    let yaml =
+        bootstrap_only_yaml().replace("  variant: apt\n", "  variant: apt\n  varient: apt\n");
+    let file = write_yaml_tempfile(&yaml);
+    let path = Utf8Path::from_path(file.path()).expect("temp path should be valid UTF-8");
+    let mut opts = cli::ValidateArgs {
+        common: cli::CommonArgs {
+            file: path.to_owned(),
+            log_level: cli::LogLevel::Error,
+            no_user_config: true,
+        },
+        strictness: cli::StrictArgs::default(),
+        target: cli::TargetArgs::default(),
+        resolved: false,
+        lint: false,
+    };
+
+    let err = run_validate(&opts).expect_err("strict mode should reject the typo");
+    assert_eq!(exit_code(&err), exit_codes::CONFIG);
+    assert!(format!("{:#}", err).contains("unknown field `varient`"), "{:#}", err);
+
+    opts.strictness.no_strict = true;
+    run_validate(&opts).expect("--no-strict should ignore the typo");
+}
+
 #[test]
 fn run_apply_with_pipeline_tasks_uses_isolation() {
     let file = write_yaml_tempfile(provisioner_yaml());
@@ -184,8 +264,30 @@ fn run_apply_with_pipeline_tasks_uses_isolation() {
         common: cli::CommonArgs {
             file: path.to_owned(),
             log_level: cli::LogLevel::Error,
+            no_user_config: true,
         },
+        strictness: cli::StrictArgs::default(),
+        target: cli::TargetArgs::default(),
         dry_run: true,
+        plan: false,
+        clean_stale_mounts: false,
+        allow_existing: false,
+        wait_for_lock: false,
+        only: vec![],
+        skip: vec![],
+        skip_bootstrap: false,
+        tags: vec![],
+        skip_tags: vec![],
+        start_at_task: None,
+        source_commit: None,
+        overlay: false,
+        layer_cache: None,
+        remote: None,
+        remote_dir: "rsdebstrap-remote".into(),
+        metrics: vec![],
+        summary: None,
+        events_fd: None,
+        events_file: None,
     };
     let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
     let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
@@ -211,6 +313,254 @@ fn run_apply_with_pipeline_tasks_uses_isolation() {
     assert_eq!(args[1], "/bin/sh");
 }
 
+/// Apply options for `path` with the given phase filters, in dry-run mode.
+fn filtered_apply_args(
+    path: &Utf8Path,
+    only: Vec<cli::ApplyPhase>,
+    skip: Vec<cli::ApplyPhase>,
+    skip_bootstrap: bool,
+) -> cli::ApplyArgs {
+    cli::ApplyArgs {
+        common: cli::CommonArgs {
+            file: path.to_owned(),
+            log_level: cli::LogLevel::Error,
+            no_user_config: true,
+        },
+        strictness: cli::StrictArgs::default(),
+        target: cli::TargetArgs::default(),
+        dry_run: true,
+        plan: false,
+        clean_stale_mounts: false,
+        allow_existing: false,
+        wait_for_lock: false,
+        only,
+        skip,
+        skip_bootstrap,
+        tags: vec![],
+        skip_tags: vec![],
+        start_at_task: None,
+        source_commit: None,
+        overlay: false,
+        layer_cache: None,
+        remote: None,
+        remote_dir: "rsdebstrap-remote".into(),
+        metrics: vec![],
+        summary: None,
+        events_fd: None,
+        events_file: None,
+    }
+}
+
+fn recorded_commands(opts: &cli::ApplyArgs) -> Vec<String> {
+    let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
+    let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
+        calls: Arc::clone(&calls),
+    });
+    run_apply(opts, executor).expect("run_apply should succeed");
+    let calls = calls.lock().unwrap();
+    calls.iter().map(|(command, _)| command.clone()).collect()
+}
+
+#[test]
+fn run_apply_skip_bootstrap_runs_only_the_pipeline() {
+    let file = write_yaml_tempfile(provisioner_yaml());
+    let path = Utf8Path::from_path(file.path()).expect("temp path should be valid UTF-8");
+
+    let opts = filtered_apply_args(path, vec![], vec![], true);
+    assert_eq!(recorded_commands(&opts), ["chroot"]);
+
+    let opts = filtered_apply_args(path, vec![], vec![cli::ApplyPhase::Bootstrap], false);
+    assert_eq!(recorded_commands(&opts), ["chroot"]);
+}
+
+#[test]
+fn run_apply_skip_tags_skips_tagged_tasks() {
+    let yaml = provisioner_yaml()
+        .replace("    echo \"provisioning\"\n", "    echo \"provisioning\"\n  tags: [debug]\n");
+    let file = write_yaml_tempfile(&yaml);
+    let path = Utf8Path::from_path(file.path()).expect("temp path should be valid UTF-8");
+
+    let mut opts = filtered_apply_args(path, vec![], vec![], false);
+    assert_eq!(recorded_commands(&opts), ["mmdebstrap", "chroot"]);
+
+    opts.skip_tags = vec!["debug".to_string()];
+    assert_eq!(recorded_commands(&opts), ["mmdebstrap"]);
+}
+
+#[test]
+fn run_apply_start_at_task_must_name_a_task() {
+    let yaml = provisioner_yaml().replace("- type: shell\n", "- type: shell\n  name: setup\n");
+    let file = write_yaml_tempfile(&yaml);
+    let path = Utf8Path::from_path(file.path()).expect("temp path should be valid UTF-8");
+
+    let mut opts = filtered_apply_args(path, vec![], vec![], true);
+    opts.start_at_task = Some("setup".to_string());
+    assert_eq!(recorded_commands(&opts), ["chroot"]);
+
+    opts.start_at_task = Some("missing".to_string());
+    let err = run_apply(&opts, Arc::new(RecordingExecutor::default())).expect_err("should fail");
+    assert!(format!("{err:#}").contains("no provision task is named \"missing\""), "{err:#}");
+    assert_eq!(exit_code(&err), exit_codes::VALIDATION);
+
+    let mut opts = filtered_apply_args(path, vec![cli::ApplyPhase::Assemble], vec![], false);
+    opts.start_at_task = Some("setup".to_string());
+    let err = run_apply(&opts, Arc::new(RecordingExecutor::default())).expect_err("should fail");
+    assert!(format!("{err:#}").contains("needs the provision phase"), "{err:#}");
+}
+
+#[test]
+fn run_apply_layer_cache_needs_a_fresh_bootstrap() {
+    let file = write_yaml_tempfile(provisioner_yaml());
+    let path = Utf8Path::from_path(file.path()).expect("temp path should be valid UTF-8");
+    let cache = tempfile::tempdir().expect("failed to create temp dir");
+    let cache = Utf8Path::from_path(cache.path()).expect("temp path should be valid UTF-8");
+
+    let mut opts = filtered_apply_args(path, vec![], vec![], true);
+    opts.layer_cache = Some(cache.to_owned());
+    let err = run_apply(&opts, Arc::new(RecordingExecutor::default())).expect_err("should fail");
+    assert!(
+        format!("{err:#}").contains("--layer-cache needs the bootstrap phase"),
+        "{err:#}"
+    );
+    assert_eq!(exit_code(&err), exit_codes::VALIDATION);
+
+    let mut opts = filtered_apply_args(path, vec![], vec![], false);
+    opts.layer_cache = Some(cache.to_owned());
+    opts.overlay = true;
+    let err = run_apply(&opts, Arc::new(RecordingExecutor::default())).expect_err("should fail");
+    assert!(format!("{err:#}").contains("cannot be combined with --overlay"), "{err:#}");
+}
+
+#[test]
+fn run_apply_remote_syncs_profile_and_runs_apply_on_builder() {
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    let base = Utf8Path::from_path(dir.path()).expect("temp path should be valid UTF-8");
+    let base = base.canonicalize_utf8().expect("temp dir should resolve");
+    let profile = base.join("web.yml");
+    std::fs::write(
+        &profile,
+        provisioner_yaml().replace("/tmp/orchestration-test-provisioner", "output"),
+    )
+    .expect("failed to write profile");
+
+    let mut opts = filtered_apply_args(&profile, vec![], vec![], false);
+    opts.remote = Some("ci@builder".to_string());
+    let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
+    let executor = Arc::new(RecordingExecutor {
+        calls: Arc::clone(&calls),
+    });
+    run_apply(&opts, executor).expect("run_apply should succeed");
+
+    let calls = calls.lock().unwrap();
+    let commands: Vec<&str> = calls.iter().map(|(command, _)| command.as_str()).collect();
+    assert_eq!(commands, ["ssh", "rsync", "ssh"]);
+    assert_eq!(
+        calls[0].1,
+        [
+            "-o",
+            "BatchMode=yes",
+            "ci@builder",
+            "--",
+            "'mkdir' '-p' 'rsdebstrap-remote'"
+        ]
+    );
+    assert_eq!(calls[1].1[4], "--exclude=/.rsdebstrap-layer-cache/");
+    assert_eq!(calls[1].1[5], "--exclude=/output/");
+    assert_eq!(calls[1].1[6], format!("{}/", base));
+    assert_eq!(calls[1].1[7], "ci@builder:rsdebstrap-remote/");
+    assert!(
+        calls[2].1[4]
+            .starts_with("'env' '-C' 'rsdebstrap-remote' 'rsdebstrap' 'apply' '--file' 'web.yml'"),
+        "{:?}",
+        calls[2].1
+    );
+
+    opts.remote_dir = "~".into();
+    let recorder = Arc::new(RecordingExecutor::default());
+    let err = run_apply(&opts, recorder.clone()).expect_err("should fail");
+    assert_eq!(exit_code(&err), exit_codes::VALIDATION);
+    assert!(recorder.calls.lock().unwrap().is_empty(), "nothing may be synced");
+
+    opts.remote_dir = "rsdebstrap-remote".into();
+    opts.remote = Some("-oProxyCommand=x".to_string());
+    let err = run_apply(&opts, Arc::new(RecordingExecutor::default())).expect_err("should fail");
+    assert_eq!(exit_code(&err), exit_codes::VALIDATION);
+}
+
+#[test]
+fn run_apply_plan_runs_nothing() {
+    let file = write_yaml_tempfile(provisioner_yaml());
+    let path = Utf8Path::from_path(file.path()).expect("temp path should be valid UTF-8");
+    let mut opts = filtered_apply_args(path, vec![], vec![], false);
+    opts.plan = true;
+
+    assert!(recorded_commands(&opts).is_empty());
+}
+
+#[test]
+fn run_apply_only_bootstrap_skips_the_pipeline() {
+    let file = write_yaml_tempfile(provisioner_yaml());
+    let path = Utf8Path::from_path(file.path()).expect("temp path should be valid UTF-8");
+
+    let opts = filtered_apply_args(path, vec![cli::ApplyPhase::Bootstrap], vec![], false);
+    assert_eq!(recorded_commands(&opts), ["mmdebstrap"]);
+}
+
+#[test]
+fn run_apply_only_assemble_skips_provision_tasks() {
+    let file = write_yaml_tempfile(provisioner_yaml());
+    let path = Utf8Path::from_path(file.path()).expect("temp path should be valid UTF-8");
+
+    // The profile has no assemble tasks, so nothing runs at all.
+    let opts = filtered_apply_args(path, vec![cli::ApplyPhase::Assemble], vec![], false);
+    assert!(recorded_commands(&opts).is_empty());
+}
+
+#[test]
+fn run_apply_rejects_skipping_every_phase() {
+    let file = write_yaml_tempfile(provisioner_yaml());
+    let path = Utf8Path::from_path(file.path()).expect("temp path should be valid UTF-8");
+    let opts = filtered_apply_args(
+        path,
+        vec![],
+        vec![
+            cli::ApplyPhase::Provision,
+            cli::ApplyPhase::Assemble,
+            cli::ApplyPhase::Verify,
+        ],
+        true,
+    );
+
+    let err = run_apply(&opts, Arc::new(RecordingExecutor::default())).expect_err("should fail");
+
+    assert!(format!("{err:#}").contains("no phase to run"), "{err:#}");
+    assert_eq!(exit_code(&err), exit_codes::VALIDATION);
+}
+
+#[test]
+fn run_apply_skip_bootstrap_requires_existing_rootfs() {
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    let yaml = provisioner_yaml().replace(
+        "dir: /tmp/orchestration-test-provisioner",
+        &format!("dir: {}", dir.path().display()),
+    );
+    let file = write_yaml_tempfile(&yaml);
+    let path = Utf8Path::from_path(file.path()).expect("temp path should be valid UTF-8");
+    let mut opts = filtered_apply_args(path, vec![], vec![], true);
+    opts.dry_run = false;
+    let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
+    let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
+        calls: Arc::clone(&calls),
+    });
+
+    let err = run_apply(&opts, executor).expect_err("missing rootfs should fail");
+
+    assert!(format!("{err:#}").contains("does not exist"), "{err:#}");
+    assert_eq!(exit_code(&err), exit_codes::VALIDATION);
+    // Rejected before the privilege pre-flight check or anything else ran.
+    assert!(calls.lock().unwrap().is_empty());
+}
+
 /// An executor that fails on the Nth call (1-indexed).
 /// Used to simulate failures at specific points in the execution flow.
 struct FailingExecutor {
@@ -240,7 +590,10 @@ impl CommandExecutor for FailingExecutor {
         if current >= self.fail_on_call {
             anyhow::bail!("simulated failure on call {}", current)
         }
-        Ok(ExecutionResult { status: None })
+        Ok(ExecutionResult {
+            status: None,
+            details: None,
+        })
     }
 }
 
@@ -257,8 +610,30 @@ fn test_run_apply_pipeline_and_teardown_both_fail() {
         common: cli::CommonArgs {
             file: path.to_owned(),
             log_level: cli::LogLevel::Error,
+            no_user_config: true,
         },
+        strictness: cli::StrictArgs::default(),
+        target: cli::TargetArgs::default(),
         dry_run: true,
+        plan: false,
+        clean_stale_mounts: false,
+        allow_existing: false,
+        wait_for_lock: false,
+        only: vec![],
+        skip: vec![],
+        skip_bootstrap: false,
+        tags: vec![],
+        skip_tags: vec![],
+        start_at_task: None,
+        source_commit: None,
+        overlay: false,
+        layer_cache: None,
+        remote: None,
+        remote_dir: "rsdebstrap-remote".into(),
+        metrics: vec![],
+        summary: None,
+        events_fd: None,
+        events_file: None,
     };
 
     // Fail starting from the 2nd call (pipeline task execution)
@@ -282,4 +657,516 @@ fn test_run_apply_pipeline_and_teardown_both_fail() {
         "Expected provisioner error, got: {}",
         err_string
     );
+    assert_eq!(exit_code(&err), exit_codes::PIPELINE);
+}
+
+#[test]
+fn run_apply_bootstrap_failure_has_bootstrap_exit_code() {
+    let file = write_yaml_tempfile(bootstrap_only_yaml());
+    let path = Utf8Path::from_path(file.path()).expect("temp path should be valid UTF-8");
+    let opts = cli::ApplyArgs {
+        common: cli::CommonArgs {
+            file: path.to_owned(),
+            log_level: cli::LogLevel::Error,
+            no_user_config: true,
+        },
+        strictness: cli::StrictArgs::default(),
+        target: cli::TargetArgs::default(),
+        dry_run: true,
+        plan: false,
+        clean_stale_mounts: false,
+        allow_existing: false,
+        wait_for_lock: false,
+        only: vec![],
+        skip: vec![],
+        skip_bootstrap: false,
+        tags: vec![],
+        skip_tags: vec![],
+        start_at_task: None,
+        source_commit: None,
+        overlay: false,
+        layer_cache: None,
+        remote: None,
+        remote_dir: "rsdebstrap-remote".into(),
+        metrics: vec![],
+        summary: None,
+        events_fd: None,
+        events_file: None,
+    };
+
+    let err = run_apply(&opts, Arc::new(FailingExecutor::new(1))).expect_err("should fail");
+
+    assert!(format!("{err:#}").contains("failed to execute mmdebstrap"), "{err:#}");
+    assert_eq!(exit_code(&err), exit_codes::BOOTSTRAP);
+}
+
+#[test]
+fn run_validate_missing_profile_has_config_exit_code() {
+    let opts = cli::ValidateArgs {
+        common: cli::CommonArgs {
+            file: "/nonexistent/profile.yml".into(),
+            log_level: cli::LogLevel::Error,
+            no_user_config: true,
+        },
+        strictness: cli::StrictArgs::default(),
+        target: cli::TargetArgs::default(),
+        resolved: false,
+        lint: false,
+    };
+
+    let err = run_validate(&opts).expect_err("missing profile should fail");
+
+    assert_eq!(exit_code(&err), exit_codes::CONFIG);
+}
+
+#[test]
+fn run_apply_fails_fast_when_privilege_preflight_fails() {
+    let file = write_yaml_tempfile(provisioner_yaml());
+    let path = Utf8Path::from_path(file.path()).expect("temp path should be valid UTF-8");
+    let opts = cli::ApplyArgs {
+        common: cli::CommonArgs {
+            file: path.to_owned(),
+            log_level: cli::LogLevel::Error,
+            no_user_config: true,
+        },
+        strictness: cli::StrictArgs::default(),
+        target: cli::TargetArgs::default(),
+        dry_run: false,
+        plan: false,
+        clean_stale_mounts: false,
+        allow_existing: false,
+        wait_for_lock: false,
+        only: vec![],
+        skip: vec![],
+        skip_bootstrap: false,
+        tags: vec![],
+        skip_tags: vec![],
+        start_at_task: None,
+        source_commit: None,
+        overlay: false,
+        layer_cache: None,
+        remote: None,
+        remote_dir: "rsdebstrap-remote".into(),
+        metrics: vec![],
+        summary: None,
+        events_fd: None,
+        events_file: None,
+    };
+
+    // The first call is the pre-flight `sudo true`; failing it must stop the run
+    // before the bootstrap backend is invoked.
+    let executor = Arc::new(FailingExecutor::new(1));
+    let calls = Arc::clone(&executor.calls);
+    let err = run_apply(&opts, executor).expect_err("run_apply should fail");
+
+    let typed = err
+        .downcast_ref::<RsdebstrapError>()
+        .expect("error should be an RsdebstrapError");
+    assert!(
+        matches!(
+            typed,
+            RsdebstrapError::Privilege {
+                method: PrivilegeMethod::Sudo,
+                ..
+            }
+        ),
+        "unexpected error: {typed:?}"
+    );
+    assert_eq!(exit_code(&err), exit_codes::PRIVILEGE);
+    let calls = calls.lock().unwrap();
+    assert_eq!(calls.as_slice(), [("true".to_string(), Vec::<String>::new())]);
+}
+
+#[test]
+fn run_apply_dry_run_skips_privilege_preflight() {
+    let file = write_yaml_tempfile(provisioner_yaml());
+    let path = Utf8Path::from_path(file.path()).expect("temp path should be valid UTF-8");
+    let opts = cli::ApplyArgs {
+        common: cli::CommonArgs {
+            file: path.to_owned(),
+            log_level: cli::LogLevel::Error,
+            no_user_config: true,
+        },
+        strictness: cli::StrictArgs::default(),
+        target: cli::TargetArgs::default(),
+        dry_run: true,
+        plan: false,
+        clean_stale_mounts: false,
+        allow_existing: false,
+        wait_for_lock: false,
+        only: vec![],
+        skip: vec![],
+        skip_bootstrap: false,
+        tags: vec![],
+        skip_tags: vec![],
+        start_at_task: None,
+        source_commit: None,
+        overlay: false,
+        layer_cache: None,
+        remote: None,
+        remote_dir: "rsdebstrap-remote".into(),
+        metrics: vec![],
+        summary: None,
+        events_fd: None,
+        events_file: None,
+    };
+    let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
+    let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
+        calls: Arc::clone(&calls),
+    });
+
+    run_apply(&opts, executor).expect("run_apply should succeed");
+
+    assert!(
+        calls
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|(command, _)| command != "true")
+    );
+}
+
+#[test]
+fn run_apply_runs_network_disabled_task_under_unshare() {
+    // editorconfig-checker-disable
+    let yaml = r#"---
+dir: /tmp/orchestration-test-offline
+offline: true
+defaults:
+  privilege:
+    method: sudo
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+provision:
+- type: shell
+  content: echo offline
+"#;
+    // editorconfig-checker-enable
+    let file = write_yaml_tempfile(yaml);
+    let path = Utf8Path::from_path(file.path()).expect("temp path should be valid UTF-8");
+    let opts = cli::ApplyArgs {
+        common: cli::CommonArgs {
+            file: path.to_owned(),
+            log_level: cli::LogLevel::Error,
+            no_user_config: true,
+        },
+        strictness: cli::StrictArgs::default(),
+        target: cli::TargetArgs::default(),
+        dry_run: true,
+        plan: false,
+        clean_stale_mounts: false,
+        allow_existing: false,
+        wait_for_lock: false,
+        only: vec![],
+        skip: vec![],
+        skip_bootstrap: false,
+        tags: vec![],
+        skip_tags: vec![],
+        start_at_task: None,
+        source_commit: None,
+        overlay: false,
+        layer_cache: None,
+        remote: None,
+        remote_dir: "rsdebstrap-remote".into(),
+        metrics: vec![],
+        summary: None,
+        events_fd: None,
+        events_file: None,
+    };
+    let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
+    let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
+        calls: Arc::clone(&calls),
+    });
+
+    run_apply(&opts, executor).expect("run_apply should succeed");
+
+    let calls = calls.lock().unwrap();
+    assert_eq!(calls.len(), 2);
+    // The bootstrap still has network access.
+    assert_eq!(calls[0].0, "mmdebstrap");
+    // The provision task's chroot runs inside a new network namespace.
+    let (command, args) = &calls[1];
+    assert_eq!(command, "unshare");
+    assert_eq!(&args[..3], ["--net", "--", "chroot"]);
+}
+
+#[test]
+fn run_apply_shares_context_directory_read_only_with_tasks() {
+    let context = tempfile::tempdir().unwrap();
+    let context = Utf8Path::from_path(context.path()).unwrap().to_owned();
+    // editorconfig-checker-disable
+    let yaml = format!(
+        r#"---
+dir: /tmp/orchestration-test-context
+context: {context}
+defaults:
+  privilege:
+    method: sudo
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+provision:
+- type: shell
+  content: cat /run/rsdebstrap/context/motd
+"#
+    );
+    // editorconfig-checker-enable
+    let file = write_yaml_tempfile(&yaml);
+    let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
+    let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
+        calls: Arc::clone(&calls),
+    });
+
+    run_apply(&mirror_failover_opts(&file), executor).expect("run_apply should succeed");
+
+    let calls = calls.lock().unwrap();
+    let commands: Vec<&str> = calls.iter().map(|(c, _)| c.as_str()).collect();
+    assert_eq!(commands, ["mmdebstrap", "mount", "chroot", "umount"]);
+    let mount_point = "/tmp/orchestration-test-context/rootfs/run/rsdebstrap/context";
+    assert_eq!(calls[1].1, ["-o", "bind,ro", context.as_str(), mount_point]);
+    assert_eq!(calls[3].1, [mount_point]);
+}
+
+#[test]
+fn run_apply_routes_bootstrap_through_apt_cache_proxy() {
+    // editorconfig-checker-disable
+    let yaml = r#"---
+dir: /tmp/orchestration-test-apt-cache
+apt_cache:
+  proxy: http://127.0.0.1:3142
+bootstrap:
+  type: debootstrap
+  suite: trixie
+  target: rootfs
+"#;
+    // editorconfig-checker-enable
+    let file = write_yaml_tempfile(yaml);
+    let path = Utf8Path::from_path(file.path()).expect("temp path should be valid UTF-8");
+    let opts = cli::ApplyArgs {
+        common: cli::CommonArgs {
+            file: path.to_owned(),
+            log_level: cli::LogLevel::Error,
+            no_user_config: true,
+        },
+        strictness: cli::StrictArgs::default(),
+        target: cli::TargetArgs::default(),
+        dry_run: true,
+        plan: false,
+        clean_stale_mounts: false,
+        allow_existing: false,
+        wait_for_lock: false,
+        only: vec![],
+        skip: vec![],
+        skip_bootstrap: false,
+        tags: vec![],
+        skip_tags: vec![],
+        start_at_task: None,
+        source_commit: None,
+        overlay: false,
+        layer_cache: None,
+        remote: None,
+        remote_dir: "rsdebstrap-remote".into(),
+        metrics: vec![],
+        summary: None,
+        events_fd: None,
+        events_file: None,
+    };
+    let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
+    let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
+        calls: Arc::clone(&calls),
+    });
+
+    run_apply(&opts, executor).expect("run_apply should succeed");
+
+    let calls = calls.lock().unwrap();
+    assert_eq!(calls.len(), 1);
+    let (command, args) = &calls[0];
+    assert_eq!(command, "env");
+    assert_eq!(&args[..2], ["http_proxy=http://127.0.0.1:3142", "debootstrap"]);
+}
+
+/// Fails the `curl` health check for URLs containing `down`; records every command.
+struct FlakyMirrorExecutor {
+    down: &'static str,
+    calls: CommandCalls,
+}
+
+impl CommandExecutor for FlakyMirrorExecutor {
+    fn execute(&self, spec: &CommandSpec) -> anyhow::Result<ExecutionResult> {
+        use std::os::unix::process::ExitStatusExt;
+
+        self.calls
+            .lock()
+            .unwrap()
+            .push((spec.command.clone(), spec.args.clone()));
+        let failed = spec.command == "curl" && spec.args.iter().any(|a| a.contains(self.down));
+        let code = if failed { 22 } else { 0 };
+        Ok(ExecutionResult {
+            status: Some(std::process::ExitStatus::from_raw(code << 8)),
+            details: None,
+        })
+    }
+}
+
+fn mirror_failover_opts(file: &NamedTempFile) -> cli::ApplyArgs {
+    let path = Utf8Path::from_path(file.path()).expect("temp path should be valid UTF-8");
+    cli::ApplyArgs {
+        common: cli::CommonArgs {
+            file: path.to_owned(),
+            log_level: cli::LogLevel::Error,
+            no_user_config: true,
+        },
+        strictness: cli::StrictArgs::default(),
+        target: cli::TargetArgs::default(),
+        dry_run: true,
+        plan: false,
+        clean_stale_mounts: false,
+        allow_existing: false,
+        wait_for_lock: false,
+        only: vec![],
+        skip: vec![],
+        skip_bootstrap: false,
+        tags: vec![],
+        skip_tags: vec![],
+        start_at_task: None,
+        source_commit: None,
+        overlay: false,
+        layer_cache: None,
+        remote: None,
+        remote_dir: "rsdebstrap-remote".into(),
+        metrics: vec![],
+        summary: None,
+        events_fd: None,
+        events_file: None,
+    }
+}
+
+// editorconfig-checker-disable
+const MIRROR_FAILOVER_YAML: &str = r#"---
+dir: /tmp/orchestration-test-mirror-failover
+bootstrap:
+  type: debootstrap
+  suite: trixie
+  target: rootfs
+  mirror: http://deb.debian.org/debian
+  fallback_mirrors:
+  - http://ftp.jp.debian.org/debian
+  - http://ftp.us.debian.org/debian
+"#;
+// editorconfig-checker-enable
+
+#[test]
+fn run_apply_bootstraps_from_first_healthy_fallback_mirror() {
+    let file = write_yaml_tempfile(MIRROR_FAILOVER_YAML);
+    let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
+    let executor: Arc<dyn CommandExecutor> = Arc::new(FlakyMirrorExecutor {
+        down: "deb.debian.org",
+        calls: Arc::clone(&calls),
+    });
+
+    run_apply(&mirror_failover_opts(&file), executor).expect("run_apply should succeed");
+
+    let calls = calls.lock().unwrap();
+    let commands: Vec<&str> = calls.iter().map(|(c, _)| c.as_str()).collect();
+    assert_eq!(commands, ["curl", "curl", "debootstrap"]);
+    assert_eq!(
+        calls[1].1.last().unwrap(),
+        "http://ftp.jp.debian.org/debian/dists/trixie/Release"
+    );
+    assert_eq!(calls[2].1.last().unwrap(), "http://ftp.jp.debian.org/debian");
+}
+
+#[test]
+fn run_apply_fails_before_bootstrap_when_no_mirror_is_healthy() {
+    let file = write_yaml_tempfile(MIRROR_FAILOVER_YAML);
+    let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
+    let executor: Arc<dyn CommandExecutor> = Arc::new(FlakyMirrorExecutor {
+        down: "debian.org",
+        calls: Arc::clone(&calls),
+    });
+
+    let err = run_apply(&mirror_failover_opts(&file), executor).expect_err("should fail");
+
+    assert!(
+        err.chain().any(|e| matches!(
+            e.downcast_ref::<RsdebstrapError>(),
+            Some(RsdebstrapError::Mirror(_))
+        )),
+        "unexpected error: {err:#}"
+    );
+    let calls = calls.lock().unwrap();
+    assert!(calls.iter().all(|(c, _)| c == "curl"), "bootstrap must not run");
+    assert_eq!(calls.len(), 3);
+}
+
+#[test]
+fn run_apply_downloads_keyring_and_passes_it_to_backend() {
+    // editorconfig-checker-disable
+    let yaml = r#"---
+dir: /tmp/orchestration-test-keyrings
+keyrings:
+- url: https://example.org/keys/derivative-archive-keyring.gpg
+  sha256: fd9528b920d6d3956e9e16114523e1889c751e8c1e040182116d4c906b43f558
+bootstrap:
+  type: debootstrap
+  suite: trixie
+  target: rootfs
+"#;
+    // editorconfig-checker-enable
+    let file = write_yaml_tempfile(yaml);
+    let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
+    let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
+        calls: Arc::clone(&calls),
+    });
+
+    run_apply(&mirror_failover_opts(&file), executor).expect("run_apply should succeed");
+
+    let calls = calls.lock().unwrap();
+    let commands: Vec<&str> = calls.iter().map(|(c, _)| c.as_str()).collect();
+    assert_eq!(commands, ["curl", "debootstrap"]);
+    let (_, curl_args) = &calls[0];
+    assert_eq!(
+        curl_args.last().unwrap(),
+        "https://example.org/keys/derivative-archive-keyring.gpg"
+    );
+    let output = &curl_args[curl_args.iter().position(|a| a == "--output").unwrap() + 1];
+    assert!(output.ends_with("/0-derivative-archive-keyring.gpg"), "{output}");
+    let (_, debootstrap_args) = &calls[1];
+    assert!(
+        debootstrap_args.contains(&format!("--keyring={}", output)),
+        "{debootstrap_args:?}"
+    );
+}
+
+#[test]
+fn run_apply_downloads_mitamae_binary_before_bootstrap() {
+    // editorconfig-checker-disable
+    let yaml = r#"---
+dir: /tmp/orchestration-test-mitamae-url
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+provision:
+- type: mitamae
+  url: https://example.org/mitamae-x86_64-linux
+  sha256: 0fac5dd00f9f11d48dbe91e66426253841929025cd58d235a8c64a75cfcaea98
+  content: package 'vim'
+"#;
+    // editorconfig-checker-enable
+    let file = write_yaml_tempfile(yaml);
+    let calls: CommandCalls = Arc::new(Mutex::new(Vec::new()));
+    let executor: Arc<dyn CommandExecutor> = Arc::new(RecordingExecutor {
+        calls: Arc::clone(&calls),
+    });
+
+    run_apply(&mirror_failover_opts(&file), executor).expect("run_apply should succeed");
+
+    let calls = calls.lock().unwrap();
+    let commands: Vec<&str> = calls.iter().map(|(c, _)| c.as_str()).collect();
+    assert_eq!(commands[..2], ["curl", "mmdebstrap"], "{commands:?}");
+    assert_eq!(calls[0].1.last().unwrap(), "https://example.org/mitamae-x86_64-linux");
 }
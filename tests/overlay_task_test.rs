@@ -0,0 +1,301 @@
+//! Deserialization, validation and execution tests for OverlayTask.
+
+mod helpers;
+
+use std::process::Command;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use rsdebstrap::RsdebstrapError;
+use rsdebstrap::config::IsolationConfig;
+use rsdebstrap::isolation::TaskIsolation;
+use rsdebstrap::phase::provision::overlay::{OverlayEntry, archive_entries};
+use rsdebstrap::phase::{OverlayTask, ProvisionTask};
+use tempfile::{TempDir, tempdir};
+
+use crate::helpers::MockContext;
+
+fn resolved(mut task: OverlayTask) -> OverlayTask {
+    task.resolve_privilege(None).unwrap();
+    task.resolve_isolation(&IsolationConfig::default());
+    task
+}
+
+/// Creates a rootfs with `/tmp` and an overlay directory holding `etc/skel/.bashrc`.
+fn setup(temp_dir: &TempDir) -> (Utf8PathBuf, Utf8PathBuf) {
+    let base = Utf8Path::from_path(temp_dir.path()).expect("path should be valid UTF-8");
+    let rootfs = base.join("rootfs");
+    std::fs::create_dir_all(rootfs.join("tmp")).unwrap();
+    let overlay = base.join("overlay");
+    std::fs::create_dir_all(overlay.join("etc/skel")).unwrap();
+    std::fs::write(overlay.join("etc/skel/.bashrc"), "alias ll='ls -l'\n").unwrap();
+    (rootfs, overlay)
+}
+
+/// Packs `dir` into the tarball `archive` with the host's `tar`.
+fn pack(dir: &Utf8Path, archive: &Utf8Path, members: &[&str]) {
+    let status = Command::new("tar")
+        .args([
+            "--create",
+            "--file",
+            archive.as_str(),
+            "--directory",
+            dir.as_str(),
+        ])
+        .args(members)
+        .status()
+        .expect("tar should run");
+    assert!(status.success());
+}
+
+#[test]
+fn test_deserialize_overlay_task() {
+    // editorconfig-checker-disable
+    let yaml = r#"type: overlay
+source: overlay.tar.gz
+destination: /etc/skel
+preserve_ownership: true
+xattrs: false
+allow: [/etc/skel]
+"#;
+    // editorconfig-checker-enable
+    let task: ProvisionTask = yaml_serde::from_str(yaml).expect("should parse overlay task");
+    let ProvisionTask::Overlay(overlay) = &task else {
+        panic!("Expected Overlay task, got: {:?}", task);
+    };
+    assert_eq!(overlay.source(), "overlay.tar.gz");
+    assert_eq!(overlay.destination(), "/etc/skel");
+    assert!(overlay.preserve_ownership());
+    assert!(!overlay.xattrs());
+    assert_eq!(overlay.allow(), [Utf8PathBuf::from("/etc/skel")]);
+    assert_eq!(task.name(), "overlay:overlay.tar.gz");
+
+    let yaml = yaml_serde::to_string(&task).unwrap();
+    assert_eq!(yaml_serde::from_str::<ProvisionTask>(&yaml).unwrap(), task);
+
+    let task: ProvisionTask = yaml_serde::from_str("type: overlay\nsource: files\n").unwrap();
+    let ProvisionTask::Overlay(overlay) = &task else {
+        panic!("Expected Overlay task, got: {:?}", task);
+    };
+    assert_eq!(overlay.destination(), "/");
+    assert!(overlay.xattrs());
+    assert!(
+        !yaml_serde::to_string(&task)
+            .unwrap()
+            .contains("destination")
+    );
+}
+
+#[test]
+fn test_validate_rejects_invalid_tasks() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (_rootfs, overlay) = setup(&temp_dir);
+
+    let cases = [
+        OverlayTask::new(&overlay).with_isolation(TaskIsolation::Disabled),
+        OverlayTask::new(&overlay).with_destination("etc"),
+        OverlayTask::new(&overlay).with_destination("/usr/../etc"),
+        OverlayTask::new(&overlay).with_allow(["etc/skel"]),
+        OverlayTask::new(&overlay).with_allow(["/etc/ssh"]),
+        OverlayTask::new(overlay.join("missing.tar")),
+    ];
+    for task in cases {
+        assert!(task.validate().is_err(), "{:?} should be rejected", task);
+    }
+
+    let err = OverlayTask::new(&overlay)
+        .with_allow(["/etc/ssh"])
+        .validate()
+        .unwrap_err();
+    assert!(matches!(err, RsdebstrapError::Validation(_)), "{err}");
+    assert!(err.to_string().contains("writes /etc/skel,"), "{err}");
+
+    std::os::unix::fs::symlink("/etc/shadow", overlay.join("etc/shadow")).unwrap();
+    let err = OverlayTask::new(&overlay).validate().unwrap_err();
+    assert!(err.to_string().contains("symlinks are not allowed"), "{err}");
+}
+
+#[test]
+fn test_allow_list_admits_parent_directories() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (_rootfs, overlay) = setup(&temp_dir);
+
+    OverlayTask::new(&overlay)
+        .with_allow(["/etc/skel"])
+        .validate()
+        .expect("etc and etc/skel lead to the allowed path");
+    OverlayTask::new(overlay.join("etc"))
+        .with_destination("/etc")
+        .with_allow(["/etc/skel/.bashrc"])
+        .validate()
+        .expect("the allowed file itself");
+
+    let task = OverlayTask::new(&overlay).with_allow(["/etc/skel"]);
+    let file_named_like_a_parent = [OverlayEntry {
+        path: "etc".into(),
+        is_dir: false,
+    }];
+    assert!(task.check_allowed(&file_named_like_a_parent).is_err());
+}
+
+#[test]
+fn test_archive_entries_are_listed_and_checked() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (_rootfs, overlay) = setup(&temp_dir);
+    let archive = overlay.with_extension("tar");
+    pack(&overlay, &archive, &["."]);
+
+    let entries = archive_entries(&archive).expect("archive should be listed");
+    assert_eq!(
+        entries,
+        [
+            OverlayEntry {
+                path: "etc".into(),
+                is_dir: true
+            },
+            OverlayEntry {
+                path: "etc/skel".into(),
+                is_dir: true
+            },
+            OverlayEntry {
+                path: "etc/skel/.bashrc".into(),
+                is_dir: false
+            },
+        ]
+    );
+    OverlayTask::new(&archive)
+        .with_allow(["/etc/skel"])
+        .validate()
+        .unwrap();
+    let err = OverlayTask::new(&archive)
+        .with_allow(["/opt"])
+        .validate()
+        .unwrap_err();
+    assert!(err.to_string().contains("not below any path in 'allow'"), "{err}");
+
+    std::fs::write(&archive, "not a tarball").unwrap();
+    let err = archive_entries(&archive).unwrap_err();
+    assert!(err.to_string().contains("failed to list overlay archive"), "{err}");
+}
+
+#[test]
+fn test_script_extracts_archives_inside_the_rootfs() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (_rootfs, overlay) = setup(&temp_dir);
+    let archive = overlay.with_extension("tar");
+    pack(&overlay, &archive, &["etc"]);
+
+    let script = OverlayTask::new(&archive)
+        .with_xattrs(false)
+        .script("/tmp/overlay-1")
+        .unwrap();
+    assert_eq!(
+        script,
+        "set -eu\nmkdir -p '/'\n\
+         tar --extract --file '/tmp/overlay-1' --directory '/' --preserve-permissions \
+         --no-same-owner --keep-directory-symlink\n"
+    );
+    let script = OverlayTask::new(&archive).script("/tmp/overlay-1").unwrap();
+    assert!(
+        script.contains(" --no-same-owner --xattrs --xattrs-include='*' --acls "),
+        "{script}"
+    );
+    let script = OverlayTask::new(&archive)
+        .with_preserve_ownership(true)
+        .script("/tmp/overlay-1")
+        .unwrap();
+    assert!(script.contains(" --same-owner "), "{script}");
+}
+
+#[test]
+fn test_script_copies_directories() {
+    use std::os::unix::fs::MetadataExt;
+
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (_rootfs, overlay) = setup(&temp_dir);
+    std::fs::write(overlay.join("motd"), "hello\n").unwrap();
+
+    let task = OverlayTask::new(&overlay)
+        .with_destination("/srv/it's")
+        .with_xattrs(false);
+    let script = task.script("/tmp/overlay-1").unwrap();
+    assert_eq!(
+        script,
+        "set -eu\nmkdir -p '/srv/it'\\''s'\n\
+         cp -R --preserve=mode,timestamps '/tmp/overlay-1/etc' '/tmp/overlay-1/motd' \
+         '/srv/it'\\''s'/\n"
+    );
+
+    let metadata = std::fs::metadata(overlay.join("motd")).unwrap();
+    let script = task
+        .with_preserve_ownership(true)
+        .script("/tmp/overlay-1")
+        .unwrap();
+    assert!(
+        script.contains(&format!(
+            "chown -h {}:{} '/srv/it'\\''s/motd'\n",
+            metadata.uid(),
+            metadata.gid()
+        )),
+        "{script}"
+    );
+    assert_eq!(script.matches("chown -h").count(), 4, "{script}");
+}
+
+#[test]
+fn test_script_extracts_directories_packed_with_xattrs() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (_rootfs, overlay) = setup(&temp_dir);
+
+    let task = OverlayTask::new(&overlay).with_preserve_ownership(true);
+    let script = task.script("/tmp/overlay-1").unwrap();
+    assert_eq!(
+        script,
+        "set -eu\nmkdir -p '/'\n\
+         tar --extract --file '/tmp/overlay-1' --directory '/' --preserve-permissions \
+         --same-owner --numeric-owner --xattrs --xattrs-include='*' --acls \
+         --keep-directory-symlink\n"
+    );
+    assert!(!script.contains("cp -R"), "{script}");
+}
+
+#[test]
+fn test_execute_stages_and_removes_the_overlay() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (rootfs, overlay) = setup(&temp_dir);
+    let archive = overlay.with_extension("tar");
+    pack(&overlay, &archive, &["etc"]);
+
+    for source in [&overlay, &archive] {
+        let task = resolved(OverlayTask::new(source));
+        let context = MockContext::new(&rootfs);
+        task.execute(&context).expect("execute should succeed");
+
+        assert_eq!(context.executed_commands(), [["/bin/sh", "-s"]]);
+        let stdin = context.executed_stdins()[0].clone().unwrap();
+        let script = String::from_utf8(stdin).unwrap();
+        assert!(script.contains("'/tmp/overlay-"), "{script}");
+        assert_eq!(
+            std::fs::read_dir(rootfs.join("tmp")).unwrap().count(),
+            0,
+            "staged overlay should be removed"
+        );
+    }
+
+    let task = resolved(OverlayTask::new(&overlay));
+    let context = MockContext::with_failure(&rootfs, 1);
+    let err = task.execute(&context).unwrap_err();
+    assert!(format!("{:#}", err).contains("/bin/sh"), "{err:#}");
+    assert_eq!(std::fs::read_dir(rootfs.join("tmp")).unwrap().count(), 0);
+}
+
+#[test]
+fn test_execute_dry_run_writes_nothing() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (rootfs, overlay) = setup(&temp_dir);
+
+    let task = resolved(OverlayTask::new(&overlay));
+    let context = MockContext::new_dry_run(&rootfs);
+    task.execute(&context).expect("dry run should succeed");
+    assert_eq!(context.executed_commands(), [["/bin/sh", "-s"]]);
+    assert_eq!(std::fs::read_dir(rootfs.join("tmp")).unwrap().count(), 0);
+}
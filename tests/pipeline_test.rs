@@ -7,15 +7,26 @@ use camino::Utf8Path;
 use rsdebstrap::RsdebstrapError;
 use rsdebstrap::config::IsolationConfig;
 use rsdebstrap::executor::{CommandExecutor, CommandSpec, ExecutionResult};
-use rsdebstrap::phase::{AssembleConfig, PrepareConfig, ProvisionTask, ScriptSource, ShellTask};
-use rsdebstrap::pipeline::Pipeline;
+use rsdebstrap::phase::{
+    AssembleConfig, PrepareConfig, ProvisionTask, ScriptSource, ShellTask, VerifyTask,
+};
+use rsdebstrap::pipeline::{FailurePolicies, FailurePolicy, PhaseSelection, Pipeline, TagFilter};
+use rsdebstrap::privilege::{PrivilegeDefaults, PrivilegeMethod};
 
 /// Empty prepare/assemble phases shared by the provision-focused pipeline tests.
 static EMPTY_PREPARE: PrepareConfig = PrepareConfig {
     mount: None,
     resolv_conf: None,
+    download: None,
+};
+static EMPTY_ASSEMBLE: AssembleConfig = AssembleConfig {
+    extract: None,
+    resolv_conf: None,
+    sanitize: None,
+    clean: None,
+    release: None,
+    relabel: None,
 };
-static EMPTY_ASSEMBLE: AssembleConfig = AssembleConfig { resolv_conf: None };
 
 /// Builds a pipeline with only provision tasks (empty prepare/assemble phases).
 fn provision_pipeline(tasks: &[ProvisionTask]) -> Pipeline<'_> {
@@ -31,6 +42,8 @@ struct MockExecutor {
     calls: Mutex<Vec<Vec<String>>>,
     /// If set, the Nth call (0-indexed) will return an error.
     fail_on_call: Option<usize>,
+    /// If set, the Nth call (0-indexed) will report an interrupt, as on Ctrl-C.
+    interrupt_on_call: Option<usize>,
 }
 
 impl MockExecutor {
@@ -38,13 +51,21 @@ impl MockExecutor {
         Self {
             calls: Mutex::new(Vec::new()),
             fail_on_call: None,
+            interrupt_on_call: None,
         }
     }
 
     fn failing_on(call_index: usize) -> Self {
         Self {
-            calls: Mutex::new(Vec::new()),
             fail_on_call: Some(call_index),
+            ..Self::new()
+        }
+    }
+
+    fn interrupted_on(call_index: usize) -> Self {
+        Self {
+            interrupt_on_call: Some(call_index),
+            ..Self::new()
         }
     }
 
@@ -69,7 +90,18 @@ impl CommandExecutor for MockExecutor {
         if self.fail_on_call == Some(index) {
             anyhow::bail!("simulated failure on call {}", index);
         }
-        Ok(ExecutionResult { status: None })
+        if self.interrupt_on_call == Some(index) {
+            return Err(RsdebstrapError::Interrupted {
+                command: spec.command.clone(),
+                status: "interrupted".to_string(),
+                details: None,
+            }
+            .into());
+        }
+        Ok(ExecutionResult {
+            status: None,
+            details: None,
+        })
     }
 }
 
@@ -124,6 +156,19 @@ fn test_pipeline_total_tasks_counts_all_phases() {
     assert_eq!(pipeline.total_tasks(), 6);
 }
 
+#[test]
+fn test_pipeline_total_tasks_counts_only_selected_phases() {
+    let tasks = [inline_task("echo 1"), inline_task("echo 2")];
+    let selection = PhaseSelection {
+        provision: false,
+        assemble: true,
+        verify: false,
+    };
+    let pipeline = provision_pipeline(&tasks).with_selection(selection);
+    assert!(pipeline.is_empty());
+    assert_eq!(pipeline.total_tasks(), 0);
+}
+
 // =============================================================================
 // validate() tests
 // =============================================================================
@@ -344,6 +389,348 @@ fn test_pipeline_run_error_stops_remaining_tasks() {
     assert_eq!(mock_executor.call_count(), 2);
 }
 
+/// Parses a resolved inline shell task with `ignore_errors: true`.
+fn ignored_task(content: &str) -> ProvisionTask {
+    let yaml = format!("content: \"{}\"\nignore_errors: true\n", content);
+    let mut task: ShellTask = yaml_serde::from_str(&yaml).unwrap();
+    assert!(task.ignore_errors());
+    task.resolve_privilege(None).unwrap();
+    task.resolve_isolation(&IsolationConfig::default());
+    ProvisionTask::Shell(task)
+}
+
+#[test]
+fn test_pipeline_run_continues_past_ignored_failure() {
+    let tasks = [
+        inline_task("echo 1"),
+        ignored_task("echo 2"),
+        inline_task("echo 3"),
+    ];
+    let pipeline = provision_pipeline(&tasks);
+
+    let mock_executor = Arc::new(MockExecutor::failing_on(1));
+    let executor: Arc<dyn CommandExecutor> = Arc::clone(&mock_executor) as Arc<dyn CommandExecutor>;
+
+    pipeline
+        .run(Utf8Path::new("/tmp/rootfs"), executor, true)
+        .expect("an ignored failure must not fail the run");
+    assert_eq!(mock_executor.call_count(), 3);
+}
+
+#[test]
+fn test_pipeline_run_all_then_fail_aggregates_failures() {
+    let tasks = [
+        inline_task("echo 1"),
+        inline_task("echo 2"),
+        inline_task("echo 3"),
+    ];
+    let pipeline = provision_pipeline(&tasks).with_failure_policy(FailurePolicies {
+        provision: FailurePolicy::RunAllThenFail,
+        ..Default::default()
+    });
+
+    let mock_executor = Arc::new(MockExecutor::failing_on(1));
+    let executor: Arc<dyn CommandExecutor> = Arc::clone(&mock_executor) as Arc<dyn CommandExecutor>;
+    let err = pipeline
+        .run(Utf8Path::new("/tmp/rootfs"), executor, true)
+        .unwrap_err();
+
+    assert_eq!(mock_executor.call_count(), 3, "the remaining tasks must still run");
+    assert_eq!(err.to_string(), "provision phase failed");
+    match err.downcast_ref::<RsdebstrapError>() {
+        Some(error @ RsdebstrapError::Multiple { message, errors }) => {
+            assert_eq!(message, "1 of 3 task(s) failed");
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].to_string(), "failed to run provision 2");
+            assert_eq!(
+                errors[0].root_cause().to_string(),
+                "simulated failure on call 1",
+                "each task's error must be kept whole"
+            );
+            assert_eq!(error.code(), "RSD0012");
+        }
+        other => panic!("Expected RsdebstrapError::Multiple, got: {:?}", other),
+    }
+}
+
+#[test]
+fn test_pipeline_interrupt_stops_phase_despite_ignore_errors_and_policy() {
+    let tasks = [
+        inline_task("echo 1"),
+        ignored_task("echo 2"),
+        inline_task("echo 3"),
+    ];
+    let pipeline = provision_pipeline(&tasks).with_failure_policy(FailurePolicies {
+        provision: FailurePolicy::RunAllThenFail,
+        ..Default::default()
+    });
+
+    let mock_executor = Arc::new(MockExecutor::interrupted_on(1));
+    let executor: Arc<dyn CommandExecutor> = Arc::clone(&mock_executor) as Arc<dyn CommandExecutor>;
+    let err = pipeline
+        .run(Utf8Path::new("/tmp/rootfs"), executor, true)
+        .unwrap_err();
+
+    assert_eq!(mock_executor.call_count(), 2, "no task may run after an interrupt");
+    assert!(rsdebstrap::error::is_interrupted(&err), "{err:#}");
+    assert_eq!(err.to_string(), "provision phase interrupted at provision 2");
+}
+
+// =============================================================================
+// tag filter tests
+// =============================================================================
+
+/// Parses a resolved inline shell task with the given `tags`.
+fn tagged_task(content: &str, tags: &[&str]) -> ProvisionTask {
+    let yaml = format!("content: \"{}\"\ntags: [{}]\n", content, tags.join(", "));
+    let mut task: ShellTask = yaml_serde::from_str(&yaml).unwrap();
+    task.resolve_privilege(None).unwrap();
+    task.resolve_isolation(&IsolationConfig::default());
+    ProvisionTask::Shell(task)
+}
+
+fn tag_filter(include: &[&str], exclude: &[&str]) -> TagFilter {
+    TagFilter {
+        include: include.iter().map(|t| t.to_string()).collect(),
+        exclude: exclude.iter().map(|t| t.to_string()).collect(),
+    }
+}
+
+#[test]
+fn test_tag_filter_matches() {
+    let tags = ["debug".to_string(), "net".to_string()];
+
+    assert!(TagFilter::default().matches(&[]));
+    assert!(TagFilter::default().matches(&tags));
+    assert!(tag_filter(&["net"], &[]).matches(&tags));
+    assert!(!tag_filter(&["net"], &[]).matches(&[]));
+    assert!(!tag_filter(&[], &["debug"]).matches(&tags));
+    assert!(tag_filter(&[], &["debug"]).matches(&[]));
+    // Exclusion wins over inclusion.
+    assert!(!tag_filter(&["net"], &["debug"]).matches(&tags));
+}
+
+#[test]
+fn test_tag_filter_unused_tags() {
+    let tasks = [tagged_task("echo 1", &["debug"]), inline_task("echo 2")];
+
+    let filter = tag_filter(&["debug", "release"], &["nightly"]);
+    assert_eq!(filter.unused_tags(&tasks), ["release", "nightly"]);
+}
+
+#[test]
+fn test_pipeline_run_skips_tasks_excluded_by_tags() {
+    let tasks = [
+        tagged_task("echo 1", &["debug"]),
+        inline_task("echo 2"),
+        tagged_task("echo 3", &["release", "debug"]),
+    ];
+    let pipeline = provision_pipeline(&tasks).with_tag_filter(tag_filter(&[], &["debug"]));
+    assert_eq!(pipeline.total_tasks(), 1);
+
+    let mock_executor = Arc::new(MockExecutor::new());
+    let executor: Arc<dyn CommandExecutor> = Arc::clone(&mock_executor) as Arc<dyn CommandExecutor>;
+
+    pipeline
+        .run(Utf8Path::new("/tmp/rootfs"), executor, true)
+        .expect("pipeline should run");
+    assert_eq!(mock_executor.call_count(), 1);
+}
+
+#[test]
+fn test_pipeline_run_only_runs_included_tags() {
+    let tasks = [
+        tagged_task("echo 1", &["debug"]),
+        inline_task("echo 2"),
+        tagged_task("echo 3", &["release"]),
+    ];
+    let pipeline = provision_pipeline(&tasks).with_tag_filter(tag_filter(&["release"], &[]));
+
+    let mock_executor = Arc::new(MockExecutor::new());
+    let executor: Arc<dyn CommandExecutor> = Arc::clone(&mock_executor) as Arc<dyn CommandExecutor>;
+
+    pipeline
+        .run(Utf8Path::new("/tmp/rootfs"), executor, true)
+        .expect("pipeline should run");
+    assert_eq!(mock_executor.call_count(), 1);
+}
+
+#[test]
+fn test_pipeline_validate_rejects_invalid_tag() {
+    let tasks = [tagged_task("echo 1", &["\"two words\""])];
+    let err = provision_pipeline(&tasks).validate().unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("provision 1 validation failed: invalid tag"),
+        "{err}"
+    );
+}
+
+// =============================================================================
+// task name tests
+// =============================================================================
+
+/// Parses a resolved inline shell task with the given `name`.
+fn named_task(content: &str, name: &str) -> ProvisionTask {
+    let yaml = format!("content: \"{}\"\nname: \"{}\"\n", content, name);
+    let mut task: ShellTask = yaml_serde::from_str(&yaml).unwrap();
+    task.resolve_privilege(None).unwrap();
+    task.resolve_isolation(&IsolationConfig::default());
+    ProvisionTask::Shell(task)
+}
+
+#[test]
+fn test_pipeline_run_error_names_the_task() {
+    let tasks = [
+        inline_task("echo 1"),
+        named_task("echo 2", "configure users"),
+    ];
+    let pipeline = provision_pipeline(&tasks);
+
+    let mock_executor = Arc::new(MockExecutor::failing_on(1));
+    let executor: Arc<dyn CommandExecutor> = Arc::clone(&mock_executor) as Arc<dyn CommandExecutor>;
+
+    let err = pipeline
+        .run(Utf8Path::new("/tmp/rootfs"), executor, true)
+        .unwrap_err();
+    assert!(
+        format!("{err:#}").contains("failed to run provision 2 (configure users)"),
+        "{err:#}"
+    );
+}
+
+#[test]
+fn test_pipeline_validate_error_names_the_task() {
+    let tasks = [named_task(" ", "empty script")];
+    let err = provision_pipeline(&tasks).validate().unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("provision 1 (empty script) validation failed"),
+        "{err}"
+    );
+}
+
+#[test]
+fn test_pipeline_validate_rejects_blank_name() {
+    let tasks = [named_task("echo 1", "  ")];
+    let err = provision_pipeline(&tasks).validate().unwrap_err();
+    assert!(err.to_string().contains("task name must not be blank"), "{err}");
+}
+
+#[test]
+fn test_pipeline_run_starts_at_named_task() {
+    let tasks = [
+        named_task("echo 1", "first"),
+        inline_task("echo 2"),
+        named_task("echo 3", "third"),
+        inline_task("echo 4"),
+    ];
+    let pipeline = provision_pipeline(&tasks).with_start_at_task(Some("third"));
+    assert_eq!(pipeline.total_tasks(), 2);
+
+    // Failing the first command run shows the numbering still follows the profile.
+    let mock_executor = Arc::new(MockExecutor::failing_on(0));
+    let executor: Arc<dyn CommandExecutor> = Arc::clone(&mock_executor) as Arc<dyn CommandExecutor>;
+
+    let err = pipeline
+        .run(Utf8Path::new("/tmp/rootfs"), executor, true)
+        .unwrap_err();
+    assert!(format!("{err:#}").contains("failed to run provision 3 (third)"), "{err:#}");
+    assert_eq!(mock_executor.call_count(), 1);
+}
+
+#[test]
+fn test_pipeline_start_at_unknown_task_runs_no_provision_task() {
+    let tasks = [named_task("echo 1", "first")];
+    let pipeline = provision_pipeline(&tasks).with_start_at_task(Some("missing"));
+    assert!(pipeline.is_empty());
+}
+
+// =============================================================================
+// per-task mounts tests
+// =============================================================================
+
+/// Parses a resolved shell task with `mounts`, escalated with sudo.
+fn task_with_mounts() -> ProvisionTask {
+    let mut task: ShellTask = yaml_serde::from_str(
+        "content: make artifacts\n\
+         mounts:\n\
+         \x20 - source: proc\n    target: /proc\n\
+         \x20 - source: /srv/artifacts\n    target: /artifacts\n    options: [bind]\n",
+    )
+    .unwrap();
+    task.resolve_privilege(Some(&PrivilegeDefaults {
+        method: PrivilegeMethod::Sudo,
+    }))
+    .unwrap();
+    task.resolve_isolation(&IsolationConfig::default());
+    ProvisionTask::Shell(task)
+}
+
+#[test]
+fn test_pipeline_run_task_mounts_bracket_only_their_task() {
+    let tasks = [task_with_mounts(), inline_task("echo next")];
+    let pipeline = provision_pipeline(&tasks);
+
+    let mock_executor = Arc::new(MockExecutor::new());
+    let executor: Arc<dyn CommandExecutor> = Arc::clone(&mock_executor) as Arc<dyn CommandExecutor>;
+
+    let result = pipeline.run(Utf8Path::new("/tmp/rootfs"), executor, true);
+    assert!(result.is_ok(), "pipeline run failed: {:?}", result);
+
+    let calls = mock_executor.calls();
+    let commands: Vec<&str> = calls.iter().map(|c| c[0].as_str()).collect();
+    assert_eq!(commands, ["mount", "mount", "chroot", "umount", "umount", "chroot"]);
+    assert_eq!(calls[0], ["mount", "-t", "proc", "proc", "/tmp/rootfs/proc"]);
+    assert_eq!(
+        calls[1],
+        [
+            "mount",
+            "-o",
+            "bind",
+            "/srv/artifacts",
+            "/tmp/rootfs/artifacts"
+        ]
+    );
+    assert_eq!(calls[3], ["umount", "/tmp/rootfs/artifacts"]);
+    assert_eq!(calls[4], ["umount", "/tmp/rootfs/proc"]);
+}
+
+#[test]
+fn test_pipeline_run_task_mounts_unmounted_after_task_error() {
+    let tasks = [task_with_mounts()];
+    let pipeline = provision_pipeline(&tasks);
+
+    // Calls: mount, mount, chroot (fails), umount, umount
+    let mock_executor = Arc::new(MockExecutor::failing_on(2));
+    let executor: Arc<dyn CommandExecutor> = Arc::clone(&mock_executor) as Arc<dyn CommandExecutor>;
+
+    let result = pipeline.run(Utf8Path::new("/tmp/rootfs"), executor, true);
+    assert!(result.is_err());
+
+    let calls = mock_executor.calls();
+    let commands: Vec<&str> = calls.iter().map(|c| c[0].as_str()).collect();
+    assert_eq!(commands, ["mount", "mount", "chroot", "umount", "umount"]);
+}
+
+#[test]
+fn test_pipeline_run_task_mount_failure_skips_task() {
+    let tasks = [task_with_mounts()];
+    let pipeline = provision_pipeline(&tasks);
+
+    // Calls: mount, mount (fails), umount of the first mount
+    let mock_executor = Arc::new(MockExecutor::failing_on(1));
+    let executor: Arc<dyn CommandExecutor> = Arc::clone(&mock_executor) as Arc<dyn CommandExecutor>;
+
+    let result = pipeline.run(Utf8Path::new("/tmp/rootfs"), executor, true);
+    let err_msg = format!("{:#}", result.unwrap_err());
+    assert!(err_msg.contains("failed to mount task filesystems"), "{}", err_msg);
+
+    let calls = mock_executor.calls();
+    let commands: Vec<&str> = calls.iter().map(|c| c[0].as_str()).collect();
+    assert_eq!(commands, ["mount", "mount", "umount"]);
+}
+
 // =============================================================================
 // per-task isolation tests
 // =============================================================================
@@ -506,3 +893,97 @@ fn test_pipeline_validate_preserves_io_variant() {
         ),
     }
 }
+
+// =============================================================================
+// verify phase tests
+// =============================================================================
+
+fn verify_tasks() -> Vec<VerifyTask> {
+    yaml_serde::from_str(
+        "- type: file_exists\n  path: /etc/hostname\n\
+         - type: package_installed\n  package: openssh-server\n",
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_pipeline_verify_runs_every_check_in_a_chroot() {
+    let checks = verify_tasks();
+    let pipeline = provision_pipeline(&[]).with_verify(&checks, Some(PrivilegeMethod::Sudo));
+    assert_eq!(pipeline.total_tasks(), 2);
+
+    let mock_executor = Arc::new(MockExecutor::new());
+    let executor: Arc<dyn CommandExecutor> = Arc::clone(&mock_executor) as Arc<dyn CommandExecutor>;
+    pipeline
+        .run(Utf8Path::new("/tmp/rootfs"), executor, true)
+        .unwrap();
+
+    let calls = mock_executor.calls();
+    assert_eq!(calls.len(), 2, "{calls:?}");
+    for call in &calls {
+        assert!(call.iter().any(|arg| arg == "chroot"), "{call:?}");
+    }
+    assert!(calls[0].ends_with(&["test", "-e", "/etc/hostname"].map(String::from)));
+}
+
+#[test]
+fn test_pipeline_verify_reports_every_failure() {
+    let checks = verify_tasks();
+    let pipeline = provision_pipeline(&[]).with_verify(&checks, None);
+
+    let mock_executor = Arc::new(MockExecutor::failing_on(0));
+    let executor: Arc<dyn CommandExecutor> = Arc::clone(&mock_executor) as Arc<dyn CommandExecutor>;
+    let err = pipeline
+        .run(Utf8Path::new("/tmp/rootfs"), executor, true)
+        .unwrap_err();
+
+    assert_eq!(mock_executor.call_count(), 2, "a failed check must not stop the phase");
+    match err.downcast_ref::<RsdebstrapError>() {
+        Some(RsdebstrapError::Verification { failed, total }) => {
+            assert_eq!(failed, &["file_exists:/etc/hostname"]);
+            assert_eq!(*total, 2);
+        }
+        other => panic!("Expected RsdebstrapError::Verification, got: {:?}", other),
+    }
+}
+
+#[test]
+fn test_pipeline_verify_stops_on_interrupt() {
+    let checks = verify_tasks();
+    let pipeline = provision_pipeline(&[]).with_verify(&checks, None);
+
+    let mock_executor = Arc::new(MockExecutor::interrupted_on(0));
+    let executor: Arc<dyn CommandExecutor> = Arc::clone(&mock_executor) as Arc<dyn CommandExecutor>;
+    let err = pipeline
+        .run(Utf8Path::new("/tmp/rootfs"), executor, true)
+        .unwrap_err();
+
+    assert_eq!(mock_executor.call_count(), 1, "no check may run after an interrupt");
+    assert!(rsdebstrap::error::is_interrupted(&err), "{err:#}");
+    assert_eq!(
+        err.to_string(),
+        "verify phase interrupted at verify 1 (file_exists:/etc/hostname)"
+    );
+}
+
+#[test]
+fn test_pipeline_verify_follows_selection_and_validates() {
+    let checks = verify_tasks();
+    let selection = PhaseSelection {
+        provision: true,
+        assemble: true,
+        verify: false,
+    };
+    let pipeline = provision_pipeline(&[])
+        .with_verify(&checks, None)
+        .with_selection(selection);
+    assert!(pipeline.is_empty());
+
+    let bad: Vec<VerifyTask> =
+        yaml_serde::from_str("- type: service_enabled\n  service: '--root=/'\n").unwrap();
+    let err = provision_pipeline(&[])
+        .with_verify(&bad, None)
+        .validate()
+        .unwrap_err();
+    assert!(err.to_string().contains("verify 1 validation failed"), "{err}");
+}
@@ -0,0 +1,183 @@
+//! Deserialization, validation and execution tests for PluginTask, against a fake
+//! plugin executable speaking the JSON protocol.
+
+mod helpers;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use rsdebstrap::RsdebstrapError;
+use rsdebstrap::config::IsolationConfig;
+use rsdebstrap::phase::{PluginTask, ProvisionTask};
+use rsdebstrap::task_record::task_digest;
+use serde_json::json;
+use tempfile::tempdir;
+
+use crate::helpers::MockContext;
+
+// editorconfig-checker-disable
+/// Saves each request next to itself and rejects a config with `fail: true`.
+const PLUGIN: &str = r#"#!/bin/sh
+dir=$(dirname "$0")
+cat > "$dir/$1.json"
+case "$1" in
+validate)
+  if grep -q '"fail":true' "$dir/validate.json"; then
+    echo '{"errors": ["fail is set", "really"]}'
+  else
+    echo '{"errors": []}'
+  fi ;;
+plan) printf '%s\n' '{"command": ["/bin/sh", "-s"], "stdin": "echo hello\n"}' ;;
+*) exit 2 ;;
+esac
+"#;
+// editorconfig-checker-enable
+
+fn write_plugin(dir: &Utf8Path, content: &str) -> Utf8PathBuf {
+    let path = dir.join("rsdebstrap-plugin-hello");
+    fs::write(&path, content).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+fn utf8_tempdir() -> (tempfile::TempDir, Utf8PathBuf) {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let path = Utf8Path::from_path(temp_dir.path())
+        .expect("path should be valid UTF-8")
+        .to_path_buf();
+    (temp_dir, path)
+}
+
+fn request(dir: &Utf8Path, action: &str) -> serde_json::Value {
+    serde_json::from_str(&fs::read_to_string(dir.join(format!("{}.json", action))).unwrap())
+        .unwrap()
+}
+
+#[test]
+fn test_deserialize_plugin_task() {
+    // editorconfig-checker-disable
+    let yaml = r#"type: plugin
+plugin: hello
+binary: plugins/rsdebstrap-plugin-hello
+config:
+  greeting: hi
+  times: 2
+  targets: [a, b]
+"#;
+    // editorconfig-checker-enable
+    let mut task: ProvisionTask = yaml_serde::from_str(yaml).expect("should parse plugin task");
+    let ProvisionTask::Plugin(plugin) = &task else {
+        panic!("Expected Plugin task, got: {:?}", task);
+    };
+    assert_eq!(plugin.plugin(), "hello");
+    assert_eq!(plugin.config()["times"], json!(2));
+    assert_eq!(plugin.config()["targets"], json!(["a", "b"]));
+    assert_eq!(task.name(), "plugin:hello");
+
+    let yaml = yaml_serde::to_string(&task).unwrap();
+    assert_eq!(yaml_serde::from_str::<ProvisionTask>(&yaml).unwrap(), task);
+
+    task.resolve_paths(Utf8Path::new("/profiles"));
+    let ProvisionTask::Plugin(plugin) = &task else {
+        unreachable!();
+    };
+    assert_eq!(
+        plugin.binary(),
+        Some(Utf8Path::new("/profiles/plugins/rsdebstrap-plugin-hello"))
+    );
+
+    for yaml in [
+        "type: plugin\n",
+        "type: plugin\nplugin: 1\n",
+        "type: plugin\nplugin: hello\nconfig: [a]\n",
+        "type: plugin\nplugin: hello\nargs: [a]\n",
+    ] {
+        assert!(yaml_serde::from_str::<ProvisionTask>(yaml).is_err(), "{yaml}");
+    }
+}
+
+#[test]
+fn test_validate_asks_the_plugin() {
+    let (_temp_dir, dir) = utf8_tempdir();
+    let binary = write_plugin(&dir, PLUGIN);
+
+    let mut task = PluginTask::new("hello")
+        .with_binary(&binary)
+        .with_config("greeting", json!("hi"));
+    task.resolve_paths(&dir);
+    task.validate().expect("plugin should accept the config");
+    let sent = request(&dir, "validate");
+    assert_eq!(sent["protocol"], json!(1));
+    assert_eq!(sent["action"], json!("validate"));
+    assert_eq!(sent["config"], json!({"greeting": "hi"}));
+    assert_eq!(sent["base_dir"], json!(dir.as_str()));
+
+    let err = task
+        .clone()
+        .with_config("fail", json!(true))
+        .validate()
+        .unwrap_err();
+    assert!(matches!(err, RsdebstrapError::Validation(_)), "{err}");
+    assert!(
+        err.to_string()
+            .contains("plugin 'hello' rejected its config: fail is set; really"),
+        "{err}"
+    );
+
+    let err = PluginTask::new("Hello").validate().unwrap_err();
+    assert!(matches!(err, RsdebstrapError::Validation(_)), "{err}");
+    let err = PluginTask::new("no-such-plugin-here")
+        .validate()
+        .unwrap_err();
+    assert!(
+        matches!(err, RsdebstrapError::CommandNotFound { ref command, .. }
+            if command == "rsdebstrap-plugin-no-such-plugin-here"),
+        "{err}"
+    );
+
+    let failing = write_plugin(&dir, "#!/bin/sh\nexit 3\n");
+    let err = PluginTask::new("hello")
+        .with_binary(failing)
+        .validate()
+        .unwrap_err();
+    assert!(matches!(err, RsdebstrapError::Execution { .. }), "{err}");
+}
+
+#[test]
+fn test_execute_runs_the_planned_command() {
+    let (_temp_dir, dir) = utf8_tempdir();
+    let binary = write_plugin(&dir, PLUGIN);
+    let rootfs = dir.join("rootfs");
+
+    let mut task = PluginTask::new("hello").with_binary(&binary);
+    task.resolve_privilege(None).unwrap();
+    task.resolve_isolation(&IsolationConfig::default());
+    let context = MockContext::new(&rootfs);
+    task.execute(&context).expect("execute should succeed");
+
+    assert_eq!(context.executed_commands(), [["/bin/sh", "-s"]]);
+    assert_eq!(context.executed_stdins(), [Some(b"echo hello\n".to_vec())]);
+    let sent = request(&dir, "plan");
+    assert_eq!(sent["rootfs"], json!(rootfs.as_str()));
+    assert_eq!(sent["dry_run"], json!(false));
+
+    write_plugin(&dir, "#!/bin/sh\necho '{\"command\": []}'\n");
+    let err = task.execute(&MockContext::new(&rootfs)).unwrap_err();
+    assert!(err.to_string().contains("planned an empty command"), "{err}");
+}
+
+#[test]
+fn test_digest_covers_config_and_executable() {
+    let (_temp_dir, dir) = utf8_tempdir();
+    let binary = write_plugin(&dir, PLUGIN);
+    let digest = |task: &PluginTask| task_digest(&ProvisionTask::Plugin(task.clone())).unwrap();
+
+    let task = PluginTask::new("hello").with_binary(&binary);
+    let base = digest(&task);
+    assert_eq!(base, digest(&task));
+    assert_ne!(base, digest(&task.clone().with_config("greeting", json!("hi"))));
+
+    write_plugin(&dir, &format!("{}\n", PLUGIN));
+    assert_ne!(base, digest(&task));
+}
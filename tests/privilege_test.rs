@@ -242,6 +242,221 @@ fn test_default_privilege_doas_inherited() {
     }
 }
 
+#[test]
+fn test_privilege_methods_lists_distinct_methods_in_first_use_order() {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+        dir: /tmp/test
+        defaults:
+          privilege:
+            method: sudo
+        bootstrap:
+          type: mmdebstrap
+          suite: trixie
+          target: rootfs
+          privilege: false
+        provision:
+          - type: shell
+            content: echo "one"
+            privilege:
+              method: pkexec
+          - type: shell
+            content: echo "two"
+            privilege: true
+          - type: shell
+            content: echo "three"
+            privilege:
+              method: pkexec
+        "#
+    ))
+    .expect("profile should load");
+    // editorconfig-checker-enable
+
+    assert_eq!(
+        profile.privilege_methods(),
+        vec![PrivilegeMethod::Pkexec, PrivilegeMethod::Sudo]
+    );
+}
+
+#[test]
+fn test_assemble_task_privilege_resolves_and_is_listed() {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+        dir: /tmp/test
+        defaults:
+          privilege:
+            method: sudo
+        bootstrap:
+          type: mmdebstrap
+          suite: trixie
+          target: rootfs
+          privilege: false
+        assemble:
+          sanitize:
+            paths: [/var/log/installer]
+          clean:
+            privilege:
+              method: pkexec
+          release:
+            privilege:
+              method: doas
+        "#
+    ))
+    .expect("profile should load");
+    // editorconfig-checker-enable
+
+    let sanitize = profile.assemble.sanitize.as_ref().unwrap();
+    assert_eq!(sanitize.resolved_privilege_method(), Some(PrivilegeMethod::Sudo));
+    let clean = profile.assemble.clean.as_ref().unwrap();
+    assert_eq!(clean.resolved_privilege_method(), Some(PrivilegeMethod::Pkexec));
+    let release = profile.assemble.release.as_ref().unwrap();
+    assert_eq!(release.resolved_privilege_method(), Some(PrivilegeMethod::Doas));
+    assert_eq!(
+        profile.privilege_methods(),
+        vec![
+            PrivilegeMethod::Sudo,
+            PrivilegeMethod::Pkexec,
+            PrivilegeMethod::Doas
+        ]
+    );
+    profile
+        .validate()
+        .expect("assemble profile should validate");
+}
+
+#[test]
+fn test_privilege_methods_empty_without_privilege() {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+        dir: /tmp/test
+        bootstrap:
+          type: debootstrap
+          suite: trixie
+          target: rootfs
+        provision:
+          - type: shell
+            content: echo "hello"
+        "#
+    ))
+    .expect("profile should load");
+    // editorconfig-checker-enable
+
+    assert!(profile.privilege_methods().is_empty());
+}
+
+#[test]
+fn test_userns_bootstrap_runs_mmdebstrap_unwrapped() {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+        dir: /tmp/test
+        defaults:
+          privilege:
+            method: userns
+        bootstrap:
+          type: mmdebstrap
+          suite: trixie
+          target: rootfs
+        provision:
+          - type: shell
+            content: echo "hello"
+        "#
+    ))
+    .expect("profile should load");
+    // editorconfig-checker-enable
+
+    profile.validate().expect("userns profile should validate");
+    assert_eq!(profile.bootstrap.resolved_privilege_method(), Some(PrivilegeMethod::Userns));
+    assert_eq!(profile.bootstrap.command_privilege_method(), None);
+    assert_eq!(profile.privilege_methods(), vec![PrivilegeMethod::Userns]);
+}
+
+#[test]
+fn test_userns_rejected_for_debootstrap() {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+        dir: /tmp/test
+        bootstrap:
+          type: debootstrap
+          suite: trixie
+          target: rootfs
+          privilege:
+            method: userns
+        "#
+    ))
+    .expect("profile should load");
+    // editorconfig-checker-enable
+
+    let err = profile
+        .validate()
+        .expect_err("debootstrap cannot run rootless");
+    assert!(matches!(err, RsdebstrapError::Validation(_)));
+    assert!(err.to_string().contains("not supported by debootstrap"), "{err}");
+}
+
+#[test]
+fn test_userns_rejected_for_non_unshare_mmdebstrap_mode() {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+        dir: /tmp/test
+        bootstrap:
+          type: mmdebstrap
+          suite: trixie
+          target: rootfs
+          mode: root
+          privilege:
+            method: userns
+        "#
+    ))
+    .expect("profile should load");
+    // editorconfig-checker-enable
+
+    let err = profile
+        .validate()
+        .expect_err("mode root cannot run rootless");
+    assert!(err.to_string().contains("requires mmdebstrap mode unshare"), "{err}");
+}
+
+#[test]
+fn test_userns_rejected_with_mounts() {
+    // editorconfig-checker-disable
+    let profile = helpers::load_profile_from_yaml(crate::yaml!(
+        r#"---
+        dir: /tmp/test
+        defaults:
+          privilege:
+            method: userns
+        bootstrap:
+          type: mmdebstrap
+          suite: trixie
+          target: rootfs
+          format: directory
+        prepare:
+          mount:
+            preset: recommends
+        provision:
+          - type: shell
+            content: echo "hello"
+        "#
+    ))
+    .expect("profile should load");
+    // editorconfig-checker-enable
+
+    let err = profile
+        .validate()
+        .expect_err("mounts cannot persist across namespaces");
+    assert!(
+        err.to_string()
+            .contains("mounts are not supported with privilege method userns"),
+        "{err}"
+    );
+}
+
 // =============================================================================
 // MockContext-based privilege propagation tests
 // =============================================================================
@@ -0,0 +1,142 @@
+mod helpers;
+
+use anyhow::Result;
+use camino::Utf8Path;
+use rsdebstrap::RsdebstrapError;
+use rsdebstrap::bootstrap::debootstrap::DebootstrapConfigBuilder;
+use rsdebstrap::bootstrap::mmdebstrap::{MmdebstrapConfigBuilder, Variant};
+use rsdebstrap::config::{ProfileBuilder, load_profile};
+use rsdebstrap::phase::{MitamaeTask, ScriptSource, ShellTask};
+use rsdebstrap::privilege::{Privilege, PrivilegeMethod};
+
+#[test]
+fn builder_matches_the_equivalent_yaml() -> Result<()> {
+    // editorconfig-checker-disable
+    let yaml = crate::yaml!(
+        r#"---
+dir: /tmp/builder-test
+defaults:
+  privilege:
+    method: sudo
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+  variant: minbase
+  include: [vim]
+provision:
+- type: shell
+  name: setup
+  content: echo setup
+  tags: [base]
+- type: shell
+  content: echo unprivileged
+  privilege: false
+"#
+    );
+    // editorconfig-checker-enable
+    let loaded = helpers::load_profile_from_yaml(yaml)?;
+
+    let built = ProfileBuilder::new(
+        "/tmp/builder-test",
+        MmdebstrapConfigBuilder::new("trixie", "rootfs")
+            .variant(Variant::Minbase)
+            .include(["vim"])
+            .build(),
+    )
+    .privilege(PrivilegeMethod::Sudo)
+    .provision(
+        ShellTask::new(ScriptSource::Content("echo setup".into()))
+            .with_name("setup")
+            .with_tags(["base"]),
+    )
+    .provision(
+        ShellTask::new(ScriptSource::Content("echo unprivileged".into()))
+            .with_privilege(Privilege::Disabled),
+    )
+    .build()?;
+
+    assert_eq!(built, loaded);
+    Ok(())
+}
+
+#[test]
+fn builder_resolves_relative_paths_against_base_dir() -> Result<()> {
+    let profile =
+        ProfileBuilder::new("out", DebootstrapConfigBuilder::new("trixie", "rootfs").build())
+            .provision(MitamaeTask::new(
+                ScriptSource::Script("recipes/default.rb".into()),
+                "bin/mitamae".into(),
+            ))
+            .context("assets")
+            .base_dir("/srv/profiles")
+            .build()?;
+
+    assert_eq!(profile.dir, "/srv/profiles/out");
+    assert_eq!(profile.context.as_deref(), Some(Utf8Path::new("/srv/profiles/assets")));
+    assert_eq!(
+        profile.provision[0].script_path(),
+        Some(Utf8Path::new("/srv/profiles/recipes/default.rb"))
+    );
+    Ok(())
+}
+
+#[test]
+fn builder_rejects_an_empty_dir() {
+    let err = ProfileBuilder::new("", MmdebstrapConfigBuilder::new("trixie", "rootfs").build())
+        .build()
+        .unwrap_err();
+
+    assert!(
+        matches!(err, RsdebstrapError::Validation(msg) if msg.contains("dir must not be empty"))
+    );
+}
+
+#[test]
+fn to_yaml_round_trips_the_example_profile() -> Result<()> {
+    let profile = load_profile(Utf8Path::new("examples/debian_trixie_mmdebstrap.yml"))?;
+
+    let reloaded = helpers::load_profile_from_yaml(profile.to_yaml()?)?;
+
+    assert_eq!(reloaded, profile);
+    Ok(())
+}
+
+#[test]
+fn to_yaml_round_trips_a_built_profile() -> Result<()> {
+    let profile = ProfileBuilder::new(
+        "/tmp/builder-test",
+        DebootstrapConfigBuilder::new("trixie", "rootfs")
+            .arch("arm64")
+            .mirror("https://deb.debian.org/debian")
+            .build(),
+    )
+    .offline(true)
+    .provision(
+        MitamaeTask::new_without_binary(ScriptSource::Content("package 'vim'".into()))
+            .with_name("recipe"),
+    )
+    .build()?;
+
+    let yaml = profile.to_yaml()?;
+    let reloaded = helpers::load_profile_from_yaml(&yaml)?;
+
+    assert_eq!(reloaded, profile, "{yaml}");
+    assert!(yaml.contains("offline: true"), "{yaml}");
+    Ok(())
+}
+
+#[test]
+fn validate_rejects_the_root_as_dir() {
+    for dir in ["/", "/tmp/..", "/usr"] {
+        let profile =
+            ProfileBuilder::new(dir, MmdebstrapConfigBuilder::new("trixie", "rootfs").build())
+                .build()
+                .unwrap();
+        let err = profile.validate().unwrap_err();
+        assert!(
+            matches!(err, RsdebstrapError::Validation(ref msg) if msg.contains("dir must not be")),
+            "{dir}: {err}"
+        );
+    }
+}
@@ -0,0 +1,156 @@
+//! Deserialization, validation and execution tests for PuppetTask.
+
+mod helpers;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use rsdebstrap::RsdebstrapError;
+use rsdebstrap::config::IsolationConfig;
+use rsdebstrap::phase::{ProvisionTask, PuppetTask};
+use tempfile::tempdir;
+
+use crate::helpers::MockContext;
+
+/// Creates a rootfs with /tmp and a Puppet tree with a site manifest, a module and Hiera data.
+fn setup(temp_dir: &tempfile::TempDir) -> (Utf8PathBuf, Utf8PathBuf) {
+    let base = Utf8Path::from_path(temp_dir.path()).expect("path should be valid UTF-8");
+    let rootfs = base.join("rootfs");
+    std::fs::create_dir_all(rootfs.join("tmp")).expect("failed to create tmp dir");
+    let tree = base.join("puppet");
+    for dir in ["manifests", "modules/base/manifests", "site", "data"] {
+        std::fs::create_dir_all(tree.join(dir)).expect("failed to create dir");
+    }
+    std::fs::write(tree.join("manifests/site.pp"), "include base\n").expect("write failed");
+    std::fs::write(tree.join("modules/base/manifests/init.pp"), "class base {}\n")
+        .expect("write failed");
+    std::fs::write(tree.join("hiera.yaml"), "version: 5\n").expect("write failed");
+    std::fs::write(tree.join("data/common.yaml"), "---\n").expect("write failed");
+    (rootfs, tree)
+}
+
+#[test]
+fn test_deserialize_puppet_task() {
+    // editorconfig-checker-disable
+    let yaml = r#"type: puppet
+dir: ./puppet
+manifest: manifests/web.pp
+modulepath: [modules, site]
+hiera_config: hiera.yaml
+puppet: /opt/puppetlabs/bin/puppet
+"#;
+    // editorconfig-checker-enable
+    let task: ProvisionTask = yaml_serde::from_str(yaml).expect("should parse puppet task");
+    let ProvisionTask::Puppet(puppet) = &task else {
+        panic!("Expected Puppet task, got: {:?}", task);
+    };
+    assert_eq!(puppet.dir(), "./puppet");
+    assert_eq!(puppet.manifest(), "manifests/web.pp");
+    assert_eq!(puppet.modulepath(), [Utf8PathBuf::from("modules"), Utf8PathBuf::from("site")]);
+    assert_eq!(puppet.hiera_config(), Some(Utf8Path::new("hiera.yaml")));
+    assert_eq!(puppet.puppet(), "/opt/puppetlabs/bin/puppet");
+    assert_eq!(task.name(), "puppet:./puppet");
+
+    let yaml = yaml_serde::to_string(&task).unwrap();
+    assert_eq!(yaml_serde::from_str::<ProvisionTask>(&yaml).unwrap(), task);
+}
+
+#[test]
+fn test_deserialize_defaults() {
+    let task: ProvisionTask = yaml_serde::from_str("type: puppet\ndir: .\n").unwrap();
+    let ProvisionTask::Puppet(puppet) = &task else {
+        panic!("Expected Puppet task, got: {:?}", task);
+    };
+    assert_eq!(puppet.manifest(), "manifests/site.pp");
+    assert!(puppet.modulepath().is_empty());
+    assert_eq!(puppet.puppet(), "puppet");
+
+    let yaml = yaml_serde::to_string(&task).unwrap();
+    assert!(!yaml.contains("manifest"), "{yaml}");
+}
+
+#[test]
+fn test_validate_paths_within_tree() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (_, tree) = setup(&temp_dir);
+
+    let task = PuppetTask::new(&tree)
+        .with_modulepath(["modules", "site"])
+        .with_hiera_config("hiera.yaml");
+    assert!(task.validate().is_ok());
+    assert!(
+        PuppetTask::new(&tree)
+            .with_manifest("manifests")
+            .validate()
+            .is_ok()
+    );
+
+    let cases = [
+        PuppetTask::new(&tree).with_manifest("manifests/missing.pp"),
+        PuppetTask::new(&tree).with_manifest("/etc/puppet/site.pp"),
+        PuppetTask::new(&tree).with_modulepath(["../modules"]),
+        PuppetTask::new(&tree).with_modulepath(["hiera.yaml"]),
+        PuppetTask::new(&tree).with_hiera_config("data"),
+        PuppetTask::new(&tree).with_puppet(""),
+        PuppetTask::new(tree.join("missing")),
+    ];
+    for task in cases {
+        assert!(task.validate().is_err(), "{:?} should be rejected", task);
+    }
+}
+
+#[test]
+fn test_validate_rejects_symlink_in_tree() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (_, tree) = setup(&temp_dir);
+    std::os::unix::fs::symlink("/etc/shadow", tree.join("data/shadow")).unwrap();
+
+    let err = PuppetTask::new(&tree).validate().unwrap_err();
+    assert!(matches!(err, RsdebstrapError::Validation(_)), "{err}");
+    assert!(err.to_string().contains("symlinks are not allowed"), "{err}");
+}
+
+#[test]
+fn test_execute_puppet_apply() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (rootfs, tree) = setup(&temp_dir);
+
+    let mut task = PuppetTask::new(&tree)
+        .with_modulepath(["modules", "site"])
+        .with_hiera_config("hiera.yaml");
+    task.resolve_privilege(None).unwrap();
+    task.resolve_isolation(&IsolationConfig::default());
+
+    let context = MockContext::new(&rootfs);
+    task.execute(&context).expect("execute should succeed");
+
+    let commands = context.executed_commands();
+    assert_eq!(commands.len(), 1);
+    let cmd = &commands[0];
+    assert_eq!(cmd[..2], ["puppet", "apply"]);
+    let staged = cmd[4]
+        .strip_suffix("/manifests/site.pp")
+        .expect("manifest should be last");
+    assert!(staged.starts_with("/tmp/puppet-"), "{staged}");
+    assert_eq!(cmd[2], format!("--modulepath={0}/modules:{0}/site", staged));
+    assert_eq!(cmd[3], format!("--hiera_config={}/hiera.yaml", staged));
+
+    assert_eq!(
+        std::fs::read_dir(rootfs.join("tmp")).unwrap().count(),
+        0,
+        "staged tree should be removed"
+    );
+}
+
+#[test]
+fn test_execute_failure_returns_error() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (rootfs, tree) = setup(&temp_dir);
+
+    let mut task = PuppetTask::new(&tree);
+    task.resolve_privilege(None).unwrap();
+    task.resolve_isolation(&IsolationConfig::default());
+
+    let context = MockContext::with_failure(&rootfs, 1);
+    let err = task.execute(&context).unwrap_err();
+    assert!(format!("{:#}", err).contains("puppet"), "{err:#}");
+    assert_eq!(std::fs::read_dir(rootfs.join("tmp")).unwrap().count(), 0);
+}
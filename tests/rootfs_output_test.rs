@@ -118,3 +118,26 @@ fn test_debootstrap_rootfs_output_directory() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_mmdebstrap_stdout_target_is_streamed() -> Result<()> {
+    let config = MmdebstrapConfig {
+        stream_to: vec![vec![
+            "zstd".to_string(),
+            "-o".to_string(),
+            "rootfs.tar.zst".to_string(),
+        ]],
+        ..helpers::create_mmdebstrap("trixie", "-")
+    };
+    let output_dir = Utf8PathBuf::from("/tmp/rootfs-output");
+
+    let output = config.rootfs_output(&output_dir)?;
+    assert!(
+        matches!(&output, RootfsOutput::NonDirectory { reason } if reason.contains("streamed")),
+        "expected streamed output, got {output:?}"
+    );
+    let args = config.build_args(&output_dir)?;
+    assert_eq!(args.last().map(String::as_str), Some("-"), "{args:?}");
+
+    Ok(())
+}
@@ -0,0 +1,72 @@
+//! Deserialization and validation tests for RsyncTask.
+
+mod helpers;
+
+use camino::Utf8Path;
+use rsdebstrap::RsdebstrapError;
+use rsdebstrap::phase::provision::RsyncDirection;
+use rsdebstrap::phase::{ProvisionTask, RsyncTask};
+use tempfile::tempdir;
+
+#[test]
+fn test_deserialize_rsync_task() {
+    // editorconfig-checker-disable
+    let yaml = r#"type: rsync
+host: build/boot
+path: /boot
+direction: out
+delete: true
+exclude: ["*.old", grub/]
+"#;
+    // editorconfig-checker-enable
+    let task: ProvisionTask = yaml_serde::from_str(yaml).expect("should parse rsync task");
+    let ProvisionTask::Rsync(rsync) = &task else {
+        panic!("Expected Rsync task, got: {:?}", task);
+    };
+    assert_eq!(rsync.host(), "build/boot");
+    assert_eq!(rsync.path(), "/boot");
+    assert_eq!(rsync.direction(), RsyncDirection::Out);
+    assert!(rsync.delete());
+    assert_eq!(rsync.exclude(), ["*.old", "grub/"]);
+    assert_eq!(task.name(), "rsync:/boot -> build/boot");
+    assert!(task.resolved_isolation_config().is_none());
+
+    let yaml = yaml_serde::to_string(&task).unwrap();
+    assert_eq!(yaml_serde::from_str::<ProvisionTask>(&yaml).unwrap(), task);
+
+    for yaml in [
+        "type: rsync\nhost: a\n",
+        "type: rsync\nhost: a\npath: /a\ndirection: both\n",
+        "type: rsync\nhost: a\npath: /a\nisolation: false\n",
+    ] {
+        assert!(yaml_serde::from_str::<ProvisionTask>(yaml).is_err(), "{yaml}");
+    }
+}
+
+#[test]
+fn test_validate_rejects_invalid_tasks() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let host = Utf8Path::from_path(temp_dir.path()).expect("path should be valid UTF-8");
+
+    let cases = [
+        RsyncTask::new("", "/srv"),
+        RsyncTask::new(host.join("missing"), "/srv"),
+        RsyncTask::new(host, "srv"),
+        RsyncTask::new(host, "/srv/../etc"),
+        RsyncTask::new(host, "/srv").with_exclude([""]),
+        RsyncTask::new(host, "/srv").with_exclude(["a\nb"]),
+    ];
+    for task in cases {
+        let err = task.validate().expect_err("should be rejected");
+        assert!(matches!(err, RsdebstrapError::Validation(_)), "{err}");
+    }
+
+    RsyncTask::new(host, "/srv/assets")
+        .with_exclude(["*.tmp"])
+        .validate()
+        .unwrap();
+    RsyncTask::new(host.join("not-yet"), "/boot")
+        .with_direction(RsyncDirection::Out)
+        .validate()
+        .expect("the host directory is created when syncing out");
+}
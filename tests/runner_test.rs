@@ -0,0 +1,435 @@
+mod helpers;
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use rsdebstrap::Runner;
+use rsdebstrap::audit;
+use rsdebstrap::error::RsdebstrapError;
+use rsdebstrap::events::EventStream;
+use rsdebstrap::executor::{CommandExecutor, CommandSpec, ExecutionResult};
+use rsdebstrap::lock::{FileLock, dir_lock_path};
+use rsdebstrap::pipeline::{PhaseSelection, TagFilter};
+use rsdebstrap::progress::ProgressEvent;
+use rsdebstrap::run_dir::{self, RunId};
+use rsdebstrap::{disk_space, output_dir};
+
+#[derive(Default)]
+struct RecordingExecutor {
+    commands: Mutex<Vec<String>>,
+}
+
+impl CommandExecutor for RecordingExecutor {
+    fn execute(&self, spec: &CommandSpec) -> Result<ExecutionResult> {
+        self.commands.lock().unwrap().push(spec.command.clone());
+        Ok(ExecutionResult {
+            status: None,
+            details: None,
+        })
+    }
+}
+
+fn runner_profile_yaml() -> String {
+    // editorconfig-checker-disable
+    crate::yaml!(
+        r#"---
+dir: /tmp/runner-test
+defaults:
+  privilege:
+    method: sudo
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+  mirrors:
+  - https://deb.debian.org/debian
+provision:
+- type: shell
+  name: install-tools
+  content: apt-get install -y vim
+- type: shell
+  content: echo debug
+  tags: [debug]
+"#
+    )
+    // editorconfig-checker-enable
+}
+
+#[test]
+fn runner_reports_progress_through_the_injected_executor() -> Result<()> {
+    let profile = helpers::load_profile_from_yaml(runner_profile_yaml())?;
+    let executor = Arc::new(RecordingExecutor::default());
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&events);
+
+    Runner::new(profile)
+        .with_executor(executor.clone())
+        .with_dry_run(true)
+        .with_progress(move |event: &ProgressEvent| recorded.lock().unwrap().push(event.clone()))
+        .run()?;
+
+    assert_eq!(*executor.commands.lock().unwrap(), ["mmdebstrap", "chroot", "chroot"]);
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            ProgressEvent::BootstrapStarted {
+                command: "mmdebstrap".to_string()
+            },
+            ProgressEvent::BootstrapFinished,
+            ProgressEvent::PhaseStarted {
+                phase: "provision",
+                tasks: 2
+            },
+            ProgressEvent::TaskStarted {
+                phase: "provision",
+                number: 1,
+                name: "install-tools".to_string()
+            },
+            ProgressEvent::TaskFinished {
+                phase: "provision",
+                number: 1,
+                name: "install-tools".to_string()
+            },
+            ProgressEvent::TaskStarted {
+                phase: "provision",
+                number: 2,
+                name: "shell:<inline>".to_string()
+            },
+            ProgressEvent::TaskFinished {
+                phase: "provision",
+                number: 2,
+                name: "shell:<inline>".to_string()
+            },
+            ProgressEvent::PhaseFinished { phase: "provision" },
+        ]
+    );
+    Ok(())
+}
+
+/// A `Write` into a shared buffer.
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn runner_streams_events() -> Result<()> {
+    let profile = helpers::load_profile_from_yaml(runner_profile_yaml())?;
+    let buffer = Buffer::default();
+
+    let runner = Runner::new(profile)
+        .with_executor(Arc::new(RecordingExecutor::default()))
+        .with_dry_run(true)
+        .with_events(Some(EventStream::new(buffer.clone())));
+    let run_id = runner.run_id().to_string();
+    runner.run()?;
+
+    let output = String::from_utf8(buffer.0.lock().unwrap().clone())?;
+    let events = output
+        .lines()
+        .map(serde_json::from_str::<serde_json::Value>)
+        .collect::<Result<Vec<_>, _>>()?;
+    let kinds: Vec<&str> = events
+        .iter()
+        .map(|e| e["event"].as_str().unwrap())
+        .filter(|kind| !matches!(*kind, "warning" | "error"))
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            "run_started",
+            "bootstrap_started",
+            "command",
+            "bootstrap_finished",
+            "phase_started",
+            "task_started",
+            "command",
+            "task_finished",
+            "task_started",
+            "command",
+            "task_finished",
+            "phase_finished",
+            "run_finished",
+        ]
+    );
+    assert_eq!(events[0]["dry_run"], true);
+    assert_eq!(events[0]["run_id"], run_id);
+    assert_eq!(events.last().unwrap()["success"], true);
+    Ok(())
+}
+
+#[test]
+fn runner_applies_the_selection() -> Result<()> {
+    let profile = helpers::load_profile_from_yaml(runner_profile_yaml())?;
+    let executor = Arc::new(RecordingExecutor::default());
+
+    Runner::new(profile)
+        .with_executor(executor.clone())
+        .with_dry_run(true)
+        .with_bootstrap(false)
+        .with_selection(PhaseSelection {
+            provision: true,
+            assemble: false,
+            verify: false,
+        })
+        .with_tag_filter(TagFilter {
+            include: vec![],
+            exclude: vec!["debug".to_string()],
+        })
+        .run()?;
+
+    assert_eq!(*executor.commands.lock().unwrap(), ["chroot"]);
+    Ok(())
+}
+
+#[test]
+fn runner_plan_runs_nothing() -> Result<()> {
+    let profile = helpers::load_profile_from_yaml(runner_profile_yaml())?;
+    let executor = Arc::new(RecordingExecutor::default());
+
+    let plan = Runner::new(profile)
+        .with_executor(executor.clone())
+        .with_start_at_task(Some("install-tools"))
+        .plan()?;
+
+    assert_eq!(plan.steps.len(), 3, "{plan}");
+    assert!(executor.commands.lock().unwrap().is_empty());
+    Ok(())
+}
+
+#[test]
+fn runner_lints_shell_tasks_before_running_anything() -> Result<()> {
+    let yaml = format!(
+        "{}- type: shell\n  name: broken\n  content: 'echo \"unterminated'\n",
+        runner_profile_yaml().replace("defaults:\n", "defaults:\n  shellcheck: {}\n")
+    );
+    let profile = helpers::load_profile_from_yaml(yaml)?;
+    let executor = Arc::new(RecordingExecutor::default());
+
+    let err = Runner::new(profile)
+        .with_executor(executor.clone())
+        .with_dry_run(true)
+        .run()
+        .unwrap_err();
+
+    assert!(format!("{err:#}").contains("provision 3 (broken) line 1"), "{err:#}");
+    assert!(executor.commands.lock().unwrap().is_empty());
+    Ok(())
+}
+
+#[test]
+fn runner_rejects_a_source_commit_that_is_not_a_commit() -> Result<()> {
+    let yaml = format!("{}assemble:\n  release: {{}}\n", runner_profile_yaml());
+    let profile = helpers::load_profile_from_yaml(yaml)?;
+
+    let err = Runner::new(profile)
+        .with_executor(Arc::new(RecordingExecutor::default()))
+        .with_dry_run(true)
+        .with_source_commit(Some("main"))
+        .run()
+        .unwrap_err();
+
+    assert!(err.to_string().contains("is not a git commit"), "{err:#}");
+    Ok(())
+}
+
+#[test]
+fn runner_sets_the_release_task_build_info() -> Result<()> {
+    let yaml = format!("{}assemble:\n  release: {{}}\n", runner_profile_yaml());
+    let profile = helpers::load_profile_from_yaml(yaml)?;
+    let executor = Arc::new(RecordingExecutor::default());
+
+    Runner::new(profile)
+        .with_executor(executor.clone())
+        .with_dry_run(true)
+        .with_source_commit(Some("0123abcd"))
+        .run()?;
+
+    // A dry run logs the write instead of running commands, but a release task without
+    // build info would have failed the run.
+    assert_eq!(*executor.commands.lock().unwrap(), ["mmdebstrap", "chroot", "chroot"]);
+    Ok(())
+}
+
+#[test]
+fn runner_fails_before_bootstrap_without_free_space() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let dir = temp_dir.path().join("out");
+    let yaml = runner_profile_yaml().replace(
+        "dir: /tmp/runner-test",
+        &format!("dir: {}\nestimated_size: 1000T", dir.display()),
+    );
+    let profile = helpers::load_profile_from_yaml(&yaml)?;
+    let executor = Arc::new(RecordingExecutor::default());
+
+    let err = Runner::new(profile.clone())
+        .with_executor(executor.clone())
+        .run()
+        .unwrap_err();
+    assert!(format!("{:#}", err).contains("not enough free space"), "{err:#}");
+    assert_eq!(rsdebstrap::error::exit_code(&err), rsdebstrap::error::exit_codes::VALIDATION);
+    assert!(
+        !executor
+            .commands
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|c| c == "mmdebstrap")
+    );
+
+    // Dry runs only warn, and `estimated_size: 0` skips the check.
+    Runner::new(profile)
+        .with_executor(executor.clone())
+        .with_dry_run(true)
+        .run()?;
+    let profile = helpers::load_profile_from_yaml(yaml.replace("1000T", "0"))?;
+    assert_eq!(disk_space::required_space(&profile), None);
+    Ok(())
+}
+
+#[test]
+fn runner_fails_fast_while_another_build_holds_the_dir() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let dir = camino::Utf8PathBuf::from_path_buf(temp_dir.path().join("out")).unwrap();
+    let yaml = runner_profile_yaml().replace("dir: /tmp/runner-test", &format!("dir: {}", dir));
+    let profile = helpers::load_profile_from_yaml(&yaml)?;
+    let executor = Arc::new(RecordingExecutor::default());
+    std::fs::create_dir(&dir)?;
+    let _held = FileLock::exclusive(&dir_lock_path(&dir), "the output directory")?;
+
+    let err = Runner::new(profile)
+        .with_executor(executor.clone())
+        .run()
+        .unwrap_err();
+    assert!(
+        matches!(err.downcast_ref::<RsdebstrapError>(), Some(RsdebstrapError::Locked { .. })),
+        "{err:#}"
+    );
+    assert!(executor.commands.lock().unwrap().is_empty());
+    assert_eq!(std::fs::read_dir(&dir)?.count(), 1, "only the lock file is in dir");
+    Ok(())
+}
+
+#[test]
+fn runner_writes_the_summary_when_the_build_fails() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let dir = camino::Utf8PathBuf::from_path_buf(temp_dir.path().join("out")).unwrap();
+    std::fs::create_dir(&dir)?;
+    std::fs::write(dir.join("rootfs.tar"), "tarball")?;
+    let summary = dir.with_extension("json");
+    let yaml = runner_profile_yaml().replace("dir: /tmp/runner-test", &format!("dir: {}", dir));
+    let profile = helpers::load_profile_from_yaml(&yaml)?;
+    let _held = FileLock::exclusive(&dir_lock_path(&dir), "the output directory")?;
+
+    Runner::new(profile)
+        .with_executor(Arc::new(RecordingExecutor::default()))
+        .with_summary(Some(&summary))
+        .run()
+        .unwrap_err();
+
+    let summary: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&summary)?)?;
+    assert_eq!(summary["success"], false);
+    assert_eq!(summary["tasks"], serde_json::json!([]));
+    assert_eq!(summary["rootfs"], serde_json::Value::Null);
+    assert_eq!(summary["artifacts"], serde_json::json!([{ "path": "rootfs.tar", "bytes": 7 }]));
+    Ok(())
+}
+
+#[test]
+fn runner_sets_the_output_permissions_last() -> Result<()> {
+    let yaml = format!(
+        "{}output_permissions:\n  dir:\n    owner: ci:ci\n    mode: \"0755\"\n",
+        runner_profile_yaml()
+    );
+    let profile = helpers::load_profile_from_yaml(yaml)?;
+    let executor = Arc::new(RecordingExecutor::default());
+
+    Runner::new(profile)
+        .with_executor(executor.clone())
+        .with_dry_run(true)
+        .run()?;
+
+    assert_eq!(
+        *executor.commands.lock().unwrap(),
+        ["mmdebstrap", "chroot", "chroot", "find", "chmod"]
+    );
+    Ok(())
+}
+
+#[test]
+fn runner_respects_the_dir_policy() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let dir = temp_dir.path().join("out");
+    let yaml = runner_profile_yaml().replace(
+        "dir: /tmp/runner-test",
+        &format!("dir: {}\ndir_policy: owned\nestimated_size: 1000T", dir.display()),
+    );
+    let profile = helpers::load_profile_from_yaml(&yaml)?;
+    let executor = Arc::new(RecordingExecutor::default());
+
+    // A missing directory is created and marked; the run then stops at the free
+    // space check.
+    let err = Runner::new(profile.clone())
+        .with_executor(executor.clone())
+        .run()
+        .unwrap_err();
+    assert!(format!("{:#}", err).contains("not enough free space"), "{err:#}");
+    assert!(dir.join(output_dir::MARKER).is_file());
+
+    // An unmarked, non-empty directory is refused unless --allow-existing is given.
+    std::fs::remove_file(dir.join(output_dir::MARKER))?;
+    std::fs::write(dir.join("precious.txt"), "keep me")?;
+    let err = Runner::new(profile.clone())
+        .with_executor(executor.clone())
+        .with_dry_run(true)
+        .run()
+        .unwrap_err();
+    assert!(format!("{:#}", err).contains("not created by rsdebstrap"), "{err:#}");
+    Runner::new(profile)
+        .with_executor(executor)
+        .with_dry_run(true)
+        .with_allow_existing(true)
+        .run()?;
+    assert!(!dir.join(output_dir::MARKER).exists());
+    Ok(())
+}
+
+#[test]
+fn runner_records_a_failed_run_in_its_run_directory() -> Result<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let dir = camino::Utf8PathBuf::from_path_buf(temp_dir.path().join("out")).unwrap();
+    let yaml = runner_profile_yaml()
+        .replace("dir: /tmp/runner-test", &format!("dir: {}\nestimated_size: 1000T", dir));
+    let profile = helpers::load_profile_from_yaml(&yaml)?;
+
+    let runner = Runner::new(profile).with_executor(Arc::new(RecordingExecutor::default()));
+    let run_id: RunId = runner.run_id().clone();
+    assert_ne!(run_id, Runner::new(runner.profile().clone()).run_id().clone());
+    runner.run().unwrap_err();
+
+    let run_dir = run_dir::run_dir_path(&dir, &run_id);
+    assert_eq!(run_dir, dir.join(".rsdebstrap").join(run_id.as_str()));
+    let state: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(run_dir.join(run_dir::STATE_FILE))?)?;
+    assert_eq!(state["run_id"], run_id.as_str());
+    assert_eq!(state["status"], "failed");
+
+    // The privilege pre-flight check ran before the run directory existed.
+    let audit = std::fs::read_to_string(run_dir.join(audit::AUDIT_FILE))?;
+    let entries = audit
+        .lines()
+        .map(serde_json::from_str::<serde_json::Value>)
+        .collect::<Result<Vec<_>, _>>()?;
+    assert!(!entries.is_empty(), "{audit}");
+    assert!(entries.iter().all(|e| e["privilege"] == "sudo"), "{audit}");
+    Ok(())
+}
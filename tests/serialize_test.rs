@@ -0,0 +1,208 @@
+mod helpers;
+
+use anyhow::Result;
+use rsdebstrap::config::Profile;
+
+/// A profile touching every serializable type: both task kinds, every phase role,
+/// chroot options, apt cache, keyrings, context and checksums.
+fn full_profile_yaml() -> String {
+    // editorconfig-checker-disable
+    crate::yaml!(
+        r#"---
+dir: /tmp/serialize-test
+defaults:
+  isolation:
+    type: chroot
+    workdir: /root
+    unmount_policy:
+      retries: 2
+      lazy: true
+  privilege:
+    method: sudo
+  mitamae:
+    binary:
+      x86_64: /usr/local/bin/mitamae-x86_64
+      aarch64: /usr/local/bin/mitamae-aarch64
+  staging: tmpfs
+bootstrap:
+  type: debootstrap
+  suite: trixie
+  target: rootfs
+  mirror: http://deb.debian.org/debian
+  fallback_mirrors: [http://ftp.debian.org/debian]
+  include: [vim]
+  merged_usr: true
+  privilege: true
+prepare:
+  mount:
+    preset: recommends
+    mounts:
+    - source: tmpfs
+      target: /var/cache
+      options: [size=64m]
+  resolv_conf:
+    name_servers: [192.0.2.53]
+    search: [example.org]
+provision:
+- type: shell
+  name: setup
+  shell: /bin/bash
+  content: echo setup
+  tags: [base, net]
+  network: false
+  mounts:
+  - source: /srv/cache
+    target: /var/cache/apt
+    options: [bind, ro]
+- type: shell
+  script: scripts/cleanup.sh
+  privilege: false
+  isolation: false
+- type: mitamae
+  content: package 'curl'
+  isolation:
+    type: chroot
+    user: nobody
+    clean_env: true
+    path: /usr/bin:/bin
+    env:
+      LANG: C.UTF-8
+    binds:
+    - source: /srv/assets
+      target: /mnt/assets
+      ro: true
+- type: mitamae
+  script: recipes/default.rb
+  url: https://example.org/mitamae
+  sha256: 0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef
+- type: mitamae
+  recipes: [recipes/base.rb, recipes/web.rb]
+  attributes:
+    web:
+      port: 8080
+      hosts: [a, b]
+- type: cookbook
+  runner: itamae
+  dir: cookbook
+  run_list: [recipes/default.rb]
+  bundler:
+    without: [test]
+- type: puppet
+  dir: puppet
+  modulepath: [modules]
+  hiera_config: hiera.yaml
+- type: debconf
+  file: preseed.cfg
+  selections:
+    tzdata/Areas: {type: select, value: Etc}
+    libc6/restart-without-asking: {owner: libc6:amd64, type: boolean, value: true}
+- type: ssh
+  config:
+    10-hardening: "PasswordAuthentication no\n"
+  authorized_keys:
+    root: [ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIHJzZGVic3RyYXA admin@example.org]
+  host_keys: regenerate
+- type: kernel
+  modules: [overlay]
+  sysctl:
+    net.ipv4.ip_forward: 1
+  file_name: 60-containers
+- type: overlay
+  source: overlay.tar.gz
+  destination: /etc/skel
+  preserve_ownership: true
+  allow: [/etc/skel]
+- type: rsync
+  host: assets
+  path: /srv/assets
+  delete: true
+  exclude: ["*.tmp"]
+assemble:
+  resolv_conf:
+    name_servers: [198.51.100.1]
+verify:
+- type: file_exists
+  path: /etc/hostname
+- type: service_enabled
+  service: ssh
+apt_cache:
+  dir: cache
+keyrings:
+- path: keys/archive.gpg
+- url: https://example.org/archive.gpg
+  sha256: 0123456789abcdef0123456789abcdef0123456789abcdef0123456789abcdef
+context: assets
+checksums:
+  algorithms: [sha256, sha512]
+  artifacts: [rootfs.img]
+  sign:
+    method: cosign
+    key: cosign.key
+"#
+    )
+    // editorconfig-checker-enable
+}
+
+#[test]
+fn every_profile_type_round_trips_through_yaml() -> Result<()> {
+    let profile = helpers::load_profile_from_yaml(full_profile_yaml())?;
+
+    let yaml = profile.to_yaml()?;
+    let reloaded = helpers::load_profile_from_yaml(&yaml)?;
+
+    assert_eq!(reloaded, profile, "{yaml}");
+    Ok(())
+}
+
+#[test]
+fn serialized_profile_shows_resolved_settings() -> Result<()> {
+    let profile = helpers::load_profile_from_yaml(full_profile_yaml())?;
+
+    let value: yaml_serde::Value = yaml_serde::from_str(&profile.to_yaml()?)?;
+
+    let bootstrap = &value["bootstrap"];
+    assert_eq!(bootstrap["type"], "debootstrap");
+    assert_eq!(bootstrap["privilege"]["method"], "sudo");
+    let setup = &value["provision"][0];
+    assert_eq!(setup["privilege"]["method"], "sudo");
+    assert_eq!(setup["isolation"]["workdir"], "/root");
+    assert_eq!(setup["network"], false);
+    let cleanup = &value["provision"][1];
+    assert_eq!(cleanup["privilege"], false);
+    assert_eq!(cleanup["isolation"], false);
+    assert!(
+        cleanup["script"]
+            .as_str()
+            .is_some_and(|s| s.starts_with('/') && s.ends_with("/scripts/cleanup.sh")),
+        "{:?}",
+        cleanup["script"]
+    );
+    Ok(())
+}
+
+#[test]
+fn minimal_profile_omits_unset_fields() -> Result<()> {
+    // editorconfig-checker-disable
+    let yaml = crate::yaml!(
+        r#"---
+dir: /tmp/serialize-test
+bootstrap:
+  type: mmdebstrap
+  suite: trixie
+  target: rootfs
+"#
+    );
+    // editorconfig-checker-enable
+    let profile: Profile = helpers::load_profile_from_yaml(yaml)?;
+
+    let value: yaml_serde::Value = yaml_serde::from_str(&profile.to_yaml()?)?;
+
+    let keys = value
+        .as_mapping()
+        .expect("profile should serialize to a mapping")
+        .keys()
+        .filter_map(|k| k.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(keys, ["dir", "defaults", "bootstrap"]);
+    Ok(())
+}
@@ -479,6 +479,7 @@ fn test_execute_inline_script_verifies_file_written() {
             }
             Ok(ExecutionResult {
                 status: Some(ExitStatus::from_raw(0)),
+                details: None,
             })
         }
         fn teardown(&mut self) -> Result<()> {
@@ -564,6 +565,7 @@ fn test_execute_external_script_verifies_file_copied() {
             }
             Ok(ExecutionResult {
                 status: Some(ExitStatus::from_raw(0)),
+                details: None,
             })
         }
         fn teardown(&mut self) -> Result<()> {
@@ -712,7 +714,10 @@ fn test_execute_nonzero_exit_returns_execution_error() {
         downcast.unwrap(),
     );
     // Verify the command field contains isolation backend info
-    if let RsdebstrapError::Execution { command, status } = downcast.unwrap() {
+    if let RsdebstrapError::Execution {
+        command, status, ..
+    } = downcast.unwrap()
+    {
         assert!(
             command.contains("isolation: mock"),
             "Expected command to contain isolation backend name, got: {}",
@@ -725,3 +730,165 @@ fn test_execute_nonzero_exit_returns_execution_error() {
         );
     }
 }
+
+#[test]
+fn test_execute_stages_script_in_context_staging_dir() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let rootfs = camino::Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf())
+        .expect("path should be valid UTF-8");
+
+    setup_valid_rootfs(&temp_dir);
+    std::fs::create_dir(rootfs.join("tmp/rsdebstrap-test")).expect("failed to create staging dir");
+
+    let mut task = ShellTask::new(ScriptSource::Content("echo hello".to_string()));
+    task.resolve_privilege(None).unwrap();
+    task.resolve_isolation(&IsolationConfig::default());
+
+    let context = MockContext::new(&rootfs).with_staging_dir("/tmp/rsdebstrap-test");
+    task.execute(&context).expect("staged script should run");
+
+    let commands = context.executed_commands();
+    assert_eq!(commands.len(), 1);
+    assert!(
+        commands[0][1].starts_with("/tmp/rsdebstrap-test/task-"),
+        "Expected script path in the staging dir, got: {}",
+        commands[0][1]
+    );
+    assert_eq!(
+        std::fs::read_dir(rootfs.join("tmp/rsdebstrap-test"))
+            .expect("failed to read staging dir")
+            .count(),
+        0,
+        "Expected script to be cleaned up"
+    );
+}
+
+#[test]
+fn test_run_fails_when_staging_dir_missing() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let rootfs = camino::Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf())
+        .expect("path should be valid UTF-8");
+
+    setup_valid_rootfs(&temp_dir);
+
+    let mut task = ShellTask::new(ScriptSource::Content("echo hello".to_string()));
+    task.resolve_privilege(None).unwrap();
+    task.resolve_isolation(&IsolationConfig::default());
+
+    let context = MockContext::new(&rootfs).with_staging_dir("/run/rsdebstrap/staging");
+    let err = task
+        .execute(&context)
+        .expect_err("missing staging dir must be rejected");
+    assert!(context.executed_commands().is_empty());
+    assert!(format!("{err:#}").contains("/run"), "unexpected error: {err:#}");
+}
+
+#[test]
+fn test_execute_passes_shell_args_and_script_args() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let rootfs = camino::Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf())
+        .expect("path should be valid UTF-8");
+
+    setup_valid_rootfs(&temp_dir);
+
+    let mut task =
+        ShellTask::with_shell(ScriptSource::Content("echo \"$1\"".to_string()), "/bin/sh")
+            .with_shell_args(["-e", "-o", "pipefail"])
+            .with_args(["hello world", "--verbose"]);
+    task.resolve_privilege(None).unwrap();
+    task.resolve_isolation(&IsolationConfig::default());
+
+    let context = MockContext::new(&rootfs);
+    task.execute(&context).expect("execute should succeed");
+
+    let commands = context.executed_commands();
+    assert_eq!(commands.len(), 1);
+    let cmd = &commands[0];
+    assert_eq!(cmd[..4], ["/bin/sh", "-e", "-o", "pipefail"]);
+    assert!(cmd[4].starts_with("/tmp/task-"), "{}", cmd[4]);
+    assert_eq!(cmd[5..], ["hello world", "--verbose"]);
+}
+
+#[test]
+fn test_validate_rejects_nul_in_args() {
+    let source = ScriptSource::Content("echo test".to_string());
+    for task in [
+        ShellTask::new(source.clone()).with_args(["a\0b"]),
+        ShellTask::new(source.clone()).with_shell_args(["-\0e"]),
+    ] {
+        let err = task.validate().unwrap_err();
+        assert!(matches!(err, RsdebstrapError::Validation(_)), "{err}");
+        assert!(err.to_string().contains("NUL"), "{err}");
+    }
+    assert!(ShellTask::new(source).with_args([""]).validate().is_ok());
+}
+
+#[test]
+fn test_args_round_trip_through_yaml() {
+    // editorconfig-checker-disable
+    let yaml = r#"content: echo "$@"
+shell: /bin/bash
+shell_args: [-e, -u, -o, pipefail]
+args: [one, "two words"]
+"#;
+    // editorconfig-checker-enable
+    let task: ShellTask = yaml_serde::from_str(yaml).expect("should parse shell args");
+    assert_eq!(task.shell_args(), ["-e", "-u", "-o", "pipefail"]);
+    assert_eq!(task.args(), ["one", "two words"]);
+
+    let yaml = yaml_serde::to_string(&task).unwrap();
+    assert_eq!(yaml_serde::from_str::<ShellTask>(&yaml).unwrap(), task);
+
+    let plain: ShellTask = yaml_serde::from_str("content: echo\nargs: null\n").unwrap();
+    assert!(plain.args().is_empty());
+    assert!(!yaml_serde::to_string(&plain).unwrap().contains("args"));
+}
+
+#[test]
+fn test_execute_feeds_stdin() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let rootfs = camino::Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf())
+        .expect("path should be valid UTF-8");
+
+    setup_valid_rootfs(&temp_dir);
+
+    // editorconfig-checker-disable
+    let yaml = r#"content: debconf-set-selections
+stdin: |
+  tzdata tzdata/Areas select Etc
+  tzdata tzdata/Zones/Etc select UTC
+"#;
+    // editorconfig-checker-enable
+    let mut task: ShellTask = yaml_serde::from_str(yaml).expect("should parse stdin");
+    task.resolve_privilege(None).unwrap();
+    task.resolve_isolation(&IsolationConfig::default());
+
+    let context = MockContext::new(&rootfs);
+    task.execute(&context).expect("execute should succeed");
+
+    let expected = "tzdata tzdata/Areas select Etc\ntzdata tzdata/Zones/Etc select UTC\n";
+    assert_eq!(context.executed_stdins(), [Some(expected.as_bytes().to_vec())]);
+    assert_eq!(
+        yaml_serde::from_str::<ShellTask>(&yaml_serde::to_string(&task).unwrap())
+            .unwrap()
+            .stdin(),
+        Some(expected)
+    );
+}
+
+#[test]
+fn test_execute_without_stdin_leaves_it_inherited() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let rootfs = camino::Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf())
+        .expect("path should be valid UTF-8");
+
+    setup_valid_rootfs(&temp_dir);
+
+    let mut task = ShellTask::new(ScriptSource::Content("true".to_string()));
+    task.resolve_privilege(None).unwrap();
+    task.resolve_isolation(&IsolationConfig::default());
+
+    let context = MockContext::new(&rootfs);
+    task.execute(&context).expect("execute should succeed");
+    assert_eq!(context.executed_stdins(), [None]);
+}
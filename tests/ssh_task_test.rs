@@ -0,0 +1,162 @@
+//! Deserialization, validation and execution tests for SshTask.
+
+mod helpers;
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use camino::Utf8Path;
+use rsdebstrap::RsdebstrapError;
+use rsdebstrap::config::IsolationConfig;
+use rsdebstrap::isolation::TaskIsolation;
+use rsdebstrap::phase::provision::SshHostKeys;
+use rsdebstrap::phase::{ProvisionTask, SshTask};
+use tempfile::tempdir;
+
+use crate::helpers::MockContext;
+
+const KEY: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIHJzZGVic3RyYXA admin@example.org";
+
+fn resolved(mut task: SshTask) -> SshTask {
+    task.resolve_privilege(None).unwrap();
+    task.resolve_isolation(&IsolationConfig::default());
+    task
+}
+
+#[test]
+fn test_deserialize_ssh_task() {
+    // editorconfig-checker-disable
+    let yaml = r#"type: ssh
+config:
+  10-hardening: |
+    PasswordAuthentication no
+    PermitRootLogin prohibit-password
+authorized_keys:
+  root:
+    - ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIHJzZGVic3RyYXA admin@example.org
+host_keys: remove
+"#;
+    // editorconfig-checker-enable
+    let task: ProvisionTask = yaml_serde::from_str(yaml).expect("should parse ssh task");
+    let ProvisionTask::Ssh(ssh) = &task else {
+        panic!("Expected Ssh task, got: {:?}", task);
+    };
+    assert_eq!(
+        ssh.config()["10-hardening"],
+        "PasswordAuthentication no\nPermitRootLogin prohibit-password\n"
+    );
+    assert_eq!(ssh.authorized_keys()["root"], [KEY]);
+    assert_eq!(ssh.host_keys(), SshHostKeys::Remove);
+    assert_eq!(task.name(), "ssh:<config 10-hardening; keys root; remove host keys>");
+
+    let yaml = yaml_serde::to_string(&task).unwrap();
+    assert_eq!(yaml_serde::from_str::<ProvisionTask>(&yaml).unwrap(), task);
+}
+
+#[test]
+fn test_deserialize_rejects_unknown_values_and_fields() {
+    for yaml in [
+        "type: ssh\nhost_keys: rotate\n",
+        "type: ssh\nauthorized_keys:\n  root: ssh-ed25519 AAAA\n",
+        "type: ssh\nhost_keys: remove\nuser: root\n",
+    ] {
+        assert!(yaml_serde::from_str::<ProvisionTask>(yaml).is_err(), "{yaml}");
+    }
+}
+
+#[test]
+fn test_validate_rejects_invalid_tasks() {
+    let cases = [
+        SshTask::new(),
+        SshTask::new()
+            .with_host_keys(SshHostKeys::Regenerate)
+            .with_isolation(TaskIsolation::Disabled),
+        SshTask::new().with_config("../sshd_config", "Port 22\n"),
+        SshTask::new().with_config(".hidden", "Port 22\n"),
+        SshTask::new()
+            .with_config("port", "Port 22\n")
+            .with_config("port.conf", "Port 2222\n"),
+        SshTask::new().with_authorized_keys("-root", [KEY]),
+        SshTask::new().with_authorized_keys("root", Vec::<String>::new()),
+        SshTask::new().with_authorized_keys("root", ["AAAAC3NzaC1lZDI1NTE5 admin"]),
+        SshTask::new().with_authorized_keys("root", [format!("{KEY}\n{KEY}")]),
+    ];
+    for task in cases {
+        assert!(task.validate().is_err(), "{:?} should be rejected", task);
+    }
+
+    let options = format!("no-port-forwarding,from=\"10.0.0.0/8\" {KEY}");
+    assert!(
+        SshTask::new()
+            .with_config("10-hardening.conf", "PasswordAuthentication no")
+            .with_authorized_keys("builder", [options])
+            .validate()
+            .is_ok()
+    );
+
+    let err = SshTask::new().validate().unwrap_err();
+    assert!(matches!(err, RsdebstrapError::Validation(_)), "{err}");
+}
+
+#[test]
+fn test_script_writes_config_keys_and_host_keys() {
+    let task = SshTask::new()
+        .with_config("10-hardening", "PasswordAuthentication no")
+        .with_authorized_keys("o'brien", [KEY, "sk-ssh-ed25519@openssh.com AAAA it's me"])
+        .with_host_keys(SshHostKeys::Regenerate);
+    let script = task.script();
+
+    assert!(script.starts_with("set -eu\n"), "{script}");
+    assert!(
+        script.contains(
+            "printf '%s' 'PasswordAuthentication no\n' > \
+             '/etc/ssh/sshd_config.d/10-hardening.conf'\n"
+        ),
+        "{script}"
+    );
+    assert!(script.contains("getent passwd 'o'\\''brien'"), "{script}");
+    assert!(script.contains("'sk-ssh-ed25519@openssh.com AAAA it'\\''s me'"), "{script}");
+    assert!(script.contains("chmod 600 \"$home/.ssh/authorized_keys\"\n"), "{script}");
+    assert!(
+        script.ends_with(
+            "rm -f /etc/ssh/ssh_host_*_key /etc/ssh/ssh_host_*_key.pub\nssh-keygen -A\n"
+        )
+    );
+
+    // The quoting must leave a syntactically valid script.
+    let mut sh = Command::new("sh")
+        .arg("-n")
+        .stdin(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn sh");
+    sh.stdin
+        .take()
+        .unwrap()
+        .write_all(script.as_bytes())
+        .unwrap();
+    assert!(sh.wait().unwrap().success(), "{script}");
+}
+
+#[test]
+fn test_execute_pipes_script_into_shell() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let rootfs = Utf8Path::from_path(temp_dir.path()).expect("path should be valid UTF-8");
+
+    let task = resolved(SshTask::new().with_host_keys(SshHostKeys::Remove));
+    let context = MockContext::new(rootfs);
+    task.execute(&context).expect("execute should succeed");
+
+    assert_eq!(context.executed_commands(), [["/bin/sh", "-s"]]);
+    assert_eq!(context.executed_stdins(), [Some(task.script().into_bytes())]);
+}
+
+#[test]
+fn test_execute_failure_returns_error() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let rootfs = Utf8Path::from_path(temp_dir.path()).expect("path should be valid UTF-8");
+
+    let task = resolved(SshTask::new().with_authorized_keys("root", [KEY]));
+    let context = MockContext::with_failure(rootfs, 1);
+    let err = task.execute(&context).unwrap_err();
+    assert!(format!("{:#}", err).contains("/bin/sh"), "{err:#}");
+}
@@ -1,4 +1,4 @@
-use camino::Utf8Path;
+use camino::{Utf8Path, Utf8PathBuf};
 use rsdebstrap::RsdebstrapError;
 use rsdebstrap::phase::{MitamaeTask, ProvisionTask, ScriptSource, ShellTask};
 use tempfile::tempdir;
@@ -520,12 +520,104 @@ binary: /usr/local/bin/mitamae
     assert!(result.is_err());
     let err_msg = result.unwrap_err().to_string();
     assert!(
-        err_msg.contains("either 'script' or 'content' must be specified"),
-        "Expected 'either script or content' error, got: {}",
+        err_msg.contains("either 'script', 'content' or 'recipes' must be specified"),
+        "Expected 'either script, content or recipes' error, got: {}",
         err_msg
     );
 }
 
+#[test]
+fn test_task_definition_deserialize_mitamae_with_url() {
+    // editorconfig-checker-disable
+    let yaml = r#"type: mitamae
+url: https://example.org/mitamae-x86_64-linux
+sha256: 17a815baf7efd5341b39e803d557cea4b127e125af8a5f92f0edd6322a0c38e5
+content: echo test
+"#;
+    // editorconfig-checker-enable
+    let task: ProvisionTask = yaml_serde::from_str(yaml).expect("should parse mitamae with url");
+    assert_eq!(task.binary_url(), Some("https://example.org/mitamae-x86_64-linux"));
+    match &task {
+        ProvisionTask::Mitamae(m) => {
+            assert_eq!(m.binary(), None);
+            assert_eq!(
+                m.sha256(),
+                Some("17a815baf7efd5341b39e803d557cea4b127e125af8a5f92f0edd6322a0c38e5")
+            );
+            m.validate()
+                .expect("url task should validate without a host binary");
+        }
+        other => panic!("Expected Mitamae task, got: {:?}", other),
+    }
+}
+
+#[test]
+fn test_task_definition_deserialize_mitamae_rejects_both_binary_and_url() {
+    // editorconfig-checker-disable
+    let yaml = r#"type: mitamae
+binary: /usr/local/bin/mitamae
+url: https://example.org/mitamae-x86_64-linux
+sha256: 17a815baf7efd5341b39e803d557cea4b127e125af8a5f92f0edd6322a0c38e5
+content: echo test
+"#;
+    // editorconfig-checker-enable
+    let result: std::result::Result<ProvisionTask, _> = yaml_serde::from_str(yaml);
+    let err_msg = result.unwrap_err().to_string();
+    assert!(
+        err_msg.contains("'binary' and 'url' are mutually exclusive"),
+        "Expected mutual exclusion error, got: {}",
+        err_msg
+    );
+}
+
+#[test]
+fn test_task_definition_deserialize_mitamae_with_recipes_and_attributes() {
+    // editorconfig-checker-disable
+    let yaml = r#"type: mitamae
+binary: /usr/local/bin/mitamae
+recipes:
+  - base.rb
+  - web.rb
+  - db.rb
+attributes:
+  web:
+    port: 8080
+  debug: true
+"#;
+    // editorconfig-checker-enable
+    let task: ProvisionTask = yaml_serde::from_str(yaml).expect("should parse mitamae recipes");
+    let ProvisionTask::Mitamae(m) = &task else {
+        panic!("Expected Mitamae task, got: {:?}", task);
+    };
+    assert_eq!(m.script_path(), Some(Utf8Path::new("base.rb")));
+    assert_eq!(m.recipes(), [Utf8PathBuf::from("web.rb"), Utf8PathBuf::from("db.rb")]);
+    assert_eq!(m.attributes()["web"], serde_json::json!({ "port": 8080 }));
+    assert_eq!(
+        m.node_json(),
+        "{\n  \"debug\": true,\n  \"web\": {\n    \"port\": 8080\n  }\n}\n"
+    );
+
+    let yaml = yaml_serde::to_string(&task).unwrap();
+    assert!(yaml.contains("recipes:"), "{yaml}");
+    assert!(!yaml.contains("script:"), "{yaml}");
+    assert_eq!(yaml_serde::from_str::<ProvisionTask>(&yaml).unwrap(), task);
+}
+
+#[test]
+fn test_task_definition_deserialize_mitamae_rejects_recipes_with_script() {
+    for source in ["script: ./recipe.rb", "content: package 'vim'"] {
+        let yaml = format!("type: mitamae\nrecipes: [base.rb]\n{source}\n");
+        let err_msg = yaml_serde::from_str::<ProvisionTask>(&yaml)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err_msg.contains("'recipes' is mutually exclusive with 'script' and 'content'"),
+            "Expected mutual exclusion error, got: {}",
+            err_msg
+        );
+    }
+}
+
 // =============================================================================
 // MitamaeTask validation and path tests
 // =============================================================================
@@ -666,6 +758,55 @@ fn test_mitamae_resolve_paths_resolves_binary_and_recipe() {
     assert_eq!(task.script_path().unwrap().as_str(), "/home/user/project/recipes/default.rb");
 }
 
+#[test]
+fn test_mitamae_resolve_paths_resolves_recipes() {
+    let mut task =
+        MitamaeTask::new(ScriptSource::Script("base.rb".into()), "/usr/local/bin/mitamae".into())
+            .with_recipes(["web.rb", "/abs/db.rb"]);
+    task.resolve_paths(Utf8Path::new("/home/user/project"));
+    assert_eq!(task.script_path().unwrap().as_str(), "/home/user/project/base.rb");
+    assert_eq!(
+        task.recipes(),
+        [
+            Utf8PathBuf::from("/home/user/project/web.rb"),
+            Utf8PathBuf::from("/abs/db.rb")
+        ]
+    );
+}
+
+#[test]
+fn test_mitamae_validate_recipes() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let dir = Utf8Path::from_path(temp_dir.path()).expect("path should be valid UTF-8");
+    std::fs::write(dir.join("mitamae"), "fake binary").expect("failed to write binary");
+    std::fs::write(dir.join("base.rb"), "package 'vim'").expect("failed to write recipe");
+
+    let task = MitamaeTask::new(ScriptSource::Script(dir.join("base.rb")), dir.join("mitamae"));
+    assert!(
+        task.clone()
+            .with_recipes([dir.join("base.rb")])
+            .validate()
+            .is_ok()
+    );
+
+    let err = task
+        .clone()
+        .with_recipes([dir.join("missing.rb")])
+        .validate()
+        .unwrap_err();
+    assert!(err.to_string().contains("missing.rb"), "{err}");
+
+    let err = MitamaeTask::new(ScriptSource::Content("package 'vim'".into()), dir.join("mitamae"))
+        .with_recipes([dir.join("base.rb")])
+        .validate()
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("cannot follow inline recipe content"),
+        "{err}"
+    );
+}
+
 #[test]
 fn test_mitamae_resolve_paths_preserves_absolute() {
     let mut task = MitamaeTask::new(
@@ -970,3 +1111,63 @@ fn test_task_definition_resolve_isolation_dispatches_to_mitamae() {
     task.resolve_isolation(&IsolationConfig::chroot());
     assert_eq!(task.resolved_isolation_config(), Some(&IsolationConfig::chroot()));
 }
+
+#[test]
+fn test_mitamae_validate_rejects_url_without_sha256() {
+    // editorconfig-checker-disable
+    let yaml = r#"type: mitamae
+url: https://example.org/mitamae-x86_64-linux
+content: echo test
+"#;
+    // editorconfig-checker-enable
+    let task: ProvisionTask = yaml_serde::from_str(yaml).expect("should parse");
+    let ProvisionTask::Mitamae(task) = task else {
+        panic!("Expected Mitamae task");
+    };
+    let err = task.validate().unwrap_err();
+    assert!(
+        err.to_string().contains("requires 'sha256'"),
+        "Expected missing sha256 error, got: {}",
+        err
+    );
+}
+
+#[test]
+fn test_mitamae_validate_rejects_binary_with_wrong_sha256() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let binary_path = temp_dir.path().join("mitamae");
+    std::fs::write(&binary_path, "fake binary").expect("failed to write binary");
+
+    // editorconfig-checker-disable
+    let yaml = format!(
+        r#"type: mitamae
+binary: {}
+sha256: {}
+content: echo test
+"#,
+        binary_path.display(),
+        "0".repeat(64)
+    );
+    // editorconfig-checker-enable
+    let task: ProvisionTask = yaml_serde::from_str(&yaml).expect("should parse");
+    let ProvisionTask::Mitamae(task) = task else {
+        panic!("Expected Mitamae task");
+    };
+    let err = task.validate().unwrap_err();
+    assert!(
+        matches!(err, RsdebstrapError::ChecksumMismatch { .. }),
+        "Expected ChecksumMismatch, got: {:?}",
+        err
+    );
+}
+
+#[test]
+fn test_mitamae_set_binary_if_absent_ignored_for_url_task() {
+    let mut task = MitamaeTask::with_url(
+        ScriptSource::Content("package 'vim'".to_string()),
+        "https://example.org/mitamae-x86_64-linux",
+        "17a815baf7efd5341b39e803d557cea4b127e125af8a5f92f0edd6322a0c38e5",
+    );
+    task.set_binary_if_absent(Utf8Path::new("/usr/local/bin/mitamae"));
+    assert_eq!(task.binary(), None, "defaults must not replace a url binary");
+}
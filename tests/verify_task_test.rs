@@ -0,0 +1,79 @@
+//! Execution tests for the `test_suite` verify check.
+
+mod helpers;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use rsdebstrap::phase::VerifyTask;
+use tempfile::{TempDir, tempdir};
+
+use crate::helpers::MockContext;
+
+/// Creates a rootfs with `/tmp` and a test suite script; returns both.
+fn setup(temp_dir: &TempDir) -> (Utf8PathBuf, Utf8PathBuf) {
+    let base = Utf8Path::from_path(temp_dir.path()).expect("path should be valid UTF-8");
+    let rootfs = base.join("rootfs");
+    std::fs::create_dir_all(rootfs.join("tmp")).unwrap();
+    let suite = base.join("smoke.sh");
+    std::fs::write(&suite, "#!/bin/sh\nexit 0\n").unwrap();
+    (rootfs, suite)
+}
+
+fn test_suite(suite: &Utf8Path, results: &Utf8Path) -> VerifyTask {
+    VerifyTask::TestSuite {
+        suite: suite.to_path_buf(),
+        args: vec!["--fast".to_string()],
+        results: Some(results.to_path_buf()),
+    }
+}
+
+#[test]
+fn test_execute_stages_suite_and_requires_a_report() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (rootfs, suite) = setup(&temp_dir);
+    let results = suite.with_file_name("out/smoke.junit.xml");
+
+    // The mock runs nothing, so the suite never writes its report.
+    let context = MockContext::new(&rootfs);
+    let err = test_suite(&suite, &results)
+        .execute(&context, None)
+        .unwrap_err();
+    assert!(err.to_string().contains("wrote no JUnit report"), "{err:#}");
+    assert!(!results.exists(), "an empty report is not copied");
+
+    let commands = context.executed_commands();
+    assert_eq!(commands.len(), 1);
+    let [env, xml, staged, arg] = commands[0].as_slice() else {
+        panic!("unexpected command: {:?}", commands[0]);
+    };
+    assert_eq!(env, "env");
+    assert!(staged.starts_with("/tmp/test-suite-"), "{staged}");
+    assert_eq!(xml, &format!("RSDEBSTRAP_JUNIT_XML={}.xml", staged));
+    assert_eq!(arg, "--fast");
+    assert_eq!(
+        std::fs::read_dir(rootfs.join("tmp")).unwrap().count(),
+        0,
+        "staged suite and report should be removed"
+    );
+
+    let context = MockContext::with_failure(&rootfs, 1);
+    let err = test_suite(&suite, &results)
+        .execute(&context, None)
+        .unwrap_err();
+    assert!(err.to_string().contains("exit status: 1"), "{err:#}");
+    assert_eq!(std::fs::read_dir(rootfs.join("tmp")).unwrap().count(), 0);
+}
+
+#[test]
+fn test_execute_dry_run_writes_nothing() {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let (rootfs, suite) = setup(&temp_dir);
+    let results = suite.with_file_name("smoke.junit.xml");
+
+    let context = MockContext::new_dry_run(&rootfs);
+    test_suite(&suite, &results)
+        .execute(&context, None)
+        .expect("dry run should succeed");
+    assert_eq!(context.executed_commands().len(), 1);
+    assert_eq!(std::fs::read_dir(rootfs.join("tmp")).unwrap().count(), 0);
+    assert!(!results.exists());
+}
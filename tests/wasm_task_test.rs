@@ -0,0 +1,196 @@
+//! Deserialization, validation and execution tests for WasmTask. The sandbox tests
+//! need the `wasm` feature (`cargo test --features wasm`) and build their modules
+//! from WebAssembly text.
+
+mod helpers;
+
+use std::fs;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use rsdebstrap::RsdebstrapError;
+use rsdebstrap::phase::{ProvisionTask, WasmTask};
+use rsdebstrap::task_record::task_digest;
+use serde_json::json;
+use tempfile::tempdir;
+
+fn utf8_tempdir() -> (tempfile::TempDir, Utf8PathBuf) {
+    let temp_dir = tempdir().expect("failed to create temp dir");
+    let path = Utf8Path::from_path(temp_dir.path())
+        .expect("path should be valid UTF-8")
+        .to_path_buf();
+    (temp_dir, path)
+}
+
+/// Writes a module importing the whole host API, with `data` at offset 0 and the
+/// given function definitions.
+fn write_module(dir: &Utf8Path, data: &str, funcs: &str) -> Utf8PathBuf {
+    let path = dir.join("task.wat");
+    let wat = format!(
+        r#"(module
+  (import "rsdebstrap" "input_len" (func $input_len (result i32)))
+  (import "rsdebstrap" "input_read" (func $input_read (param i32)))
+  (import "rsdebstrap" "execute" (func $execute (param i32 i32) (result i32)))
+  (import "rsdebstrap" "error" (func $error (param i32 i32)))
+  (import "rsdebstrap" "log" (func $log (param i32 i32)))
+  (memory (export "memory") 1)
+  (data (i32.const 0) "{}")
+  {})"#,
+        data.replace('\\', "\\\\").replace('"', "\\\""),
+        funcs
+    );
+    fs::write(&path, wat).unwrap();
+    path
+}
+
+#[test]
+fn test_deserialize_wasm_task() {
+    // editorconfig-checker-disable
+    let yaml = r#"type: wasm
+module: tasks/motd.wasm
+config:
+  text: hello
+"#;
+    // editorconfig-checker-enable
+    let mut task: ProvisionTask = yaml_serde::from_str(yaml).expect("should parse wasm task");
+    let ProvisionTask::Wasm(wasm) = &task else {
+        panic!("Expected Wasm task, got: {:?}", task);
+    };
+    assert_eq!(wasm.config()["text"], json!("hello"));
+    assert_eq!(task.name(), "wasm:motd.wasm");
+
+    let yaml = yaml_serde::to_string(&task).unwrap();
+    assert_eq!(yaml_serde::from_str::<ProvisionTask>(&yaml).unwrap(), task);
+
+    task.resolve_paths(Utf8Path::new("/profiles"));
+    let ProvisionTask::Wasm(wasm) = &task else {
+        unreachable!();
+    };
+    assert_eq!(wasm.module(), "/profiles/tasks/motd.wasm");
+
+    for yaml in [
+        "type: wasm\n",
+        "type: wasm\nmodule: [a]\n",
+        "type: wasm\nmodule: a.wasm\nwasi: true\n",
+    ] {
+        assert!(yaml_serde::from_str::<ProvisionTask>(yaml).is_err(), "{yaml}");
+    }
+}
+
+#[test]
+fn test_validate_requires_module_file() {
+    let err = WasmTask::new("/nonexistent/task.wasm")
+        .validate()
+        .unwrap_err();
+    assert!(err.to_string().contains("wasm module"), "{err}");
+}
+
+#[test]
+fn test_digest_covers_config_and_module() {
+    let (_temp_dir, dir) = utf8_tempdir();
+    let module = write_module(&dir, "", "");
+    let digest = |task: &WasmTask| task_digest(&ProvisionTask::Wasm(task.clone())).unwrap();
+
+    let task = WasmTask::new(&module);
+    let base = digest(&task);
+    assert_ne!(base, digest(&task.clone().with_config("text", json!("hi"))));
+    write_module(&dir, "changed", "");
+    assert_ne!(base, digest(&task));
+}
+
+#[cfg(not(feature = "wasm"))]
+#[test]
+fn test_validate_without_wasm_feature() {
+    let (_temp_dir, dir) = utf8_tempdir();
+    let module = write_module(&dir, "", "");
+    let err = WasmTask::new(module).validate().unwrap_err();
+    assert!(matches!(err, RsdebstrapError::Validation(_)), "{err}");
+    assert!(err.to_string().contains("`wasm` feature"), "{err}");
+}
+
+#[cfg(feature = "wasm")]
+mod sandbox {
+    use super::*;
+    use crate::helpers::MockContext;
+    use rsdebstrap::config::IsolationConfig;
+
+    const COMMAND: &str = r#"{"command": ["/bin/sh", "-s"], "stdin": "echo hi"}"#;
+
+    fn resolved(mut task: WasmTask) -> WasmTask {
+        task.resolve_privilege(None).unwrap();
+        task.resolve_isolation(&IsolationConfig::default());
+        task
+    }
+
+    fn run_module(dir: &Utf8Path) -> Utf8PathBuf {
+        write_module(
+            dir,
+            COMMAND,
+            &format!(
+                r#"(func (export "run") (result i32)
+    (call $input_read (i32.const 1024))
+    (call $log (i32.const 1024) (call $input_len))
+    (call $execute (i32.const 0) (i32.const {})))"#,
+                COMMAND.len()
+            ),
+        )
+    }
+
+    #[test]
+    fn test_execute_runs_requested_commands() {
+        let (_temp_dir, dir) = utf8_tempdir();
+        let task = resolved(WasmTask::new(run_module(&dir)).with_config("text", json!("hi")));
+        task.validate().expect("module should validate");
+
+        let rootfs = dir.join("rootfs");
+        let context = MockContext::new(&rootfs);
+        task.execute(&context).expect("execute should succeed");
+        assert_eq!(context.executed_commands(), [["/bin/sh", "-s"]]);
+        assert_eq!(context.executed_stdins(), [Some(b"echo hi".to_vec())]);
+
+        let context = MockContext::with_failure(&rootfs, 3);
+        let err = task.execute(&context).unwrap_err();
+        assert!(format!("{:#}", err).contains("run returned 3"), "{err:#}");
+    }
+
+    #[test]
+    fn test_validate_reports_module_errors() {
+        let (_temp_dir, dir) = utf8_tempdir();
+        let message = "text is required";
+        let module = write_module(
+            &dir,
+            message,
+            &format!(
+                r#"(func (export "validate") (result i32)
+    (call $error (i32.const 0) (i32.const {}))
+    (i32.const 1))
+  (func (export "run") (result i32) (i32.const 0))"#,
+                message.len()
+            ),
+        );
+        let err = WasmTask::new(module).validate().unwrap_err();
+        assert!(matches!(err, RsdebstrapError::Validation(_)), "{err}");
+        assert!(
+            err.to_string()
+                .contains("rejected its config: text is required"),
+            "{err}"
+        );
+
+        // Commands are only allowed during `run`.
+        let module = write_module(
+            &dir,
+            COMMAND,
+            &format!(
+                r#"(func (export "validate") (result i32)
+    (call $execute (i32.const 0) (i32.const {})))
+  (func (export "run") (result i32) (i32.const 0))"#,
+                COMMAND.len()
+            ),
+        );
+        let err = WasmTask::new(module).validate().unwrap_err();
+        assert!(err.to_string().contains("only allowed during `run`"), "{err}");
+
+        let module = write_module(&dir, "", "");
+        let err = WasmTask::new(module).validate().unwrap_err();
+        assert!(err.to_string().contains("does not export `run`"), "{err}");
+    }
+}